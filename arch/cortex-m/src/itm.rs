@@ -0,0 +1,306 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! ARM Instrumentation Trace Macrocell
+//!
+//! The ITM has 32 stimulus ports. Each write to a stimulus port is sent as a
+//! trace packet through the [TPIU](crate::tpiu), usually on the SWO pin, to a
+//! debug probe. Writing to a stimulus port is much faster than writing to a
+//! UART, and does not need any pin besides the debug ones.
+//!
+//! [`Itm`] writes to one stimulus port. It implements the UART HIL, so that it
+//! can be the backend of the kernel debug writer, and [`IoWrite`] for the
+//! panic output. By convention, port 0 carries text and the other ports carry
+//! binary trace events, written with [`write_u32`].
+//!
+//! Stimulus ports are only written while the ITM and the port are enabled, so
+//! nothing blocks if no debug probe enabled the trace output.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! cortexm4::itm::enable(1, 0b11);
+//! cortexm4::tpiu::configure_swo(16_000_000, 2_000_000, cortexm4::tpiu::SwoProtocol::Nrz)
+//!     .unwrap();
+//! let itm = static_init!(cortexm4::itm::Itm, cortexm4::itm::Itm::new(0));
+//! kernel::deferred_call::DeferredCallClient::register(itm);
+//! components::debug_writer::DebugWriterNoMuxComponent::new(itm)
+//!     .finalize(components::debug_writer_no_mux_component_static!());
+//! ```
+//!
+//! <https://developer.arm.com/documentation/ddi0403/latest>
+//! Implementation matches `ARM DDI 0403E.e`
+
+use core::cell::Cell;
+
+use super::dcb;
+use kernel::debug::IoWrite;
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::registers::interfaces::{Readable, Writeable};
+use kernel::utilities::registers::{
+    register_bitfields, register_structs, ReadOnly, ReadWrite, WriteOnly,
+};
+use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
+
+/// Number of stimulus ports.
+pub const NUM_PORTS: usize = 32;
+
+/// Key to write to the lock access register to allow writes to the other
+/// registers.
+const UNLOCK_KEY: u32 = 0xC5ACCE55;
+
+register_structs! {
+    ItmRegisters {
+        /// Stimulus Port Registers
+        (0x000 => stim: [ReadWrite<u32, Stimulus::Register>; NUM_PORTS]),
+
+        (0x080 => _reserved0),
+
+        /// Trace Enable Register
+        (0xE00 => ter: ReadWrite<u32>),
+
+        (0xE04 => _reserved1),
+
+        /// Trace Privilege Register
+        (0xE40 => tpr: ReadWrite<u32>),
+
+        (0xE44 => _reserved2),
+
+        /// Trace Control Register
+        (0xE80 => tcr: ReadWrite<u32, TraceControl::Register>),
+
+        (0xE84 => _reserved3),
+
+        /// Lock Access Register
+        (0xFB0 => lar: WriteOnly<u32>),
+
+        /// Lock Status Register
+        (0xFB4 => lsr: ReadOnly<u32>),
+
+        (0xFB8 => @END),
+    }
+}
+
+register_bitfields![u32,
+    Stimulus [
+        /// Reads as 1 when the port can accept a write.
+        FIFOREADY       OFFSET(0)   NUMBITS(1),
+    ],
+    TraceControl [
+        /// Is 1 while the ITM is sending a packet.
+        /// RO.
+        BUSY            OFFSET(23)  NUMBITS(1),
+
+        /// Identifier of the ITM packets on the trace bus.
+        TRACEBUSID      OFFSET(16)  NUMBITS(7),
+
+        /// Local timestamp prescaler.
+        TSPRESCALE      OFFSET(8)   NUMBITS(2),
+
+        /// Use the SWO clock for the timestamp counter.
+        SWOENA          OFFSET(4)   NUMBITS(1),
+
+        /// Forward the packets of the DWT to the TPIU.
+        TXENA           OFFSET(3)   NUMBITS(1),
+
+        /// Send synchronization packets.
+        SYNCENA         OFFSET(2)   NUMBITS(1),
+
+        /// Send local timestamp packets.
+        TSENA           OFFSET(1)   NUMBITS(1),
+
+        /// Enable the ITM.
+        ITMENA          OFFSET(0)   NUMBITS(1),
+    ],
+];
+
+const ITM_BASE: usize = 0xE0000000;
+
+const ITM: StaticRef<ItmRegisters> = unsafe { StaticRef::new(ITM_BASE as *const ItmRegisters) };
+
+/// Enable the ITM and the stimulus ports set in `ports`, with `trace_bus_id`
+/// as the identifier of the ITM on the trace bus.
+///
+/// The stimulus ports can then only be written by the kernel. The TPIU must
+/// also be configured to output the packets, for example with
+/// [`tpiu::configure_swo`](crate::tpiu::configure_swo).
+pub fn enable(trace_bus_id: u8, ports: u32) {
+    dcb::enable_debug_and_trace();
+
+    ITM.lar.set(UNLOCK_KEY);
+    ITM.tcr.write(
+        TraceControl::TRACEBUSID.val(trace_bus_id as u32)
+            + TraceControl::SYNCENA::SET
+            + TraceControl::ITMENA::SET,
+    );
+    // Each bit of the privilege register covers eight stimulus ports.
+    ITM.tpr.set(0b1111);
+    ITM.ter.set(ports);
+}
+
+/// Disable the ITM, once the last packet has been sent.
+pub fn disable() {
+    while ITM.tcr.is_set(TraceControl::BUSY) {}
+    ITM.tcr.set(0);
+}
+
+/// Whether the ITM and stimulus port `port` are enabled, which can be done
+/// with [`enable`] or by the debug probe.
+pub fn port_enabled(port: usize) -> bool {
+    port < NUM_PORTS && ITM.tcr.is_set(TraceControl::ITMENA) && (ITM.ter.get() & (1 << port)) != 0
+}
+
+fn wait_until_ready(port: usize) {
+    while !ITM.stim[port].is_set(Stimulus::FIFOREADY) {}
+}
+
+/// Write `bytes` to stimulus port `port`, one byte per packet. Nothing is
+/// written if the port is not enabled.
+pub fn write_bytes(port: usize, bytes: &[u8]) {
+    if !port_enabled(port) {
+        return;
+    }
+    let stim = (ITM_BASE + 4 * port) as *mut u8;
+    for byte in bytes {
+        wait_until_ready(port);
+        // The size of the write sets the size of the packet, so the port must
+        // be written with a byte access.
+        unsafe {
+            core::ptr::write_volatile(stim, *byte);
+        }
+    }
+}
+
+/// Write `value` to stimulus port `port`, in a single packet. Nothing is
+/// written if the port is not enabled.
+///
+/// This is the hook for trace events: a single packet per event keeps the
+/// overhead low, and the decoder on the host knows from the port what the
+/// value means.
+pub fn write_u32(port: usize, value: u32) {
+    if !port_enabled(port) {
+        return;
+    }
+    wait_until_ready(port);
+    ITM.stim[port].set(value);
+}
+
+/// Text output through an ITM stimulus port.
+pub struct Itm<'a> {
+    port: usize,
+    deferred_call: DeferredCall,
+    tx_client: OptionalCell<&'a dyn hil::uart::TransmitClient>,
+    tx_buffer: TakeCell<'static, [u8]>,
+    tx_len: Cell<usize>,
+    tx_word: Cell<bool>,
+}
+
+impl Itm<'_> {
+    pub fn new(port: usize) -> Self {
+        Self {
+            port,
+            deferred_call: DeferredCall::new(),
+            tx_client: OptionalCell::empty(),
+            tx_buffer: TakeCell::empty(),
+            tx_len: Cell::new(0),
+            tx_word: Cell::new(false),
+        }
+    }
+}
+
+impl IoWrite for Itm<'_> {
+    fn write(&mut self, buf: &[u8]) -> usize {
+        write_bytes(self.port, buf);
+        buf.len()
+    }
+}
+
+impl hil::uart::Configure for Itm<'_> {
+    fn configure(&self, _params: hil::uart::Parameters) -> Result<(), ErrorCode> {
+        // The baud rate of the trace output is set in the TPIU.
+        Ok(())
+    }
+}
+
+impl<'a> hil::uart::Transmit<'a> for Itm<'a> {
+    fn set_transmit_client(&self, client: &'a dyn hil::uart::TransmitClient) {
+        self.tx_client.set(client);
+    }
+
+    fn transmit_buffer(
+        &self,
+        tx_buffer: &'static mut [u8],
+        tx_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if tx_len == 0 || tx_len > tx_buffer.len() {
+            Err((ErrorCode::SIZE, tx_buffer))
+        } else if self.tx_buffer.is_some() || self.tx_word.get() {
+            Err((ErrorCode::BUSY, tx_buffer))
+        } else {
+            // The stimulus port drains at the trace clock rate, so the whole
+            // buffer is written immediately.
+            write_bytes(self.port, &tx_buffer[..tx_len]);
+            self.tx_len.set(tx_len);
+            self.tx_buffer.replace(tx_buffer);
+            self.deferred_call.set();
+            Ok(())
+        }
+    }
+
+    fn transmit_word(&self, word: u32) -> Result<(), ErrorCode> {
+        if self.tx_buffer.is_some() || self.tx_word.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        write_u32(self.port, word);
+        self.tx_word.set(true);
+        self.deferred_call.set();
+        Ok(())
+    }
+
+    fn transmit_abort(&self) -> Result<(), ErrorCode> {
+        // Transmissions complete immediately, only the callback is pending.
+        Err(ErrorCode::FAIL)
+    }
+}
+
+impl<'a> hil::uart::Receive<'a> for Itm<'a> {
+    fn set_receive_client(&self, _client: &'a dyn hil::uart::ReceiveClient) {}
+
+    fn receive_buffer(
+        &self,
+        rx_buffer: &'static mut [u8],
+        _rx_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        Err((ErrorCode::NOSUPPORT, rx_buffer))
+    }
+
+    fn receive_word(&self) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
+
+    fn receive_abort(&self) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
+}
+
+impl DeferredCallClient for Itm<'_> {
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+
+    fn handle_deferred_call(&self) {
+        if self.tx_word.take() {
+            self.tx_client.map(|client| client.transmitted_word(Ok(())));
+        }
+        if let Some(buffer) = self.tx_buffer.take() {
+            self.tx_client.map(|client| {
+                client.transmitted_buffer(buffer, self.tx_len.get(), Ok(()));
+            });
+        }
+    }
+}
@@ -12,12 +12,14 @@ use core::fmt::Write;
 
 pub mod dcb;
 pub mod dwt;
+pub mod itm;
 pub mod mpu;
 pub mod nvic;
 pub mod scb;
 pub mod support;
 pub mod syscall;
 pub mod systick;
+pub mod tpiu;
 
 // These constants are defined in the linker script.
 extern "C" {
@@ -136,6 +136,12 @@ const MPU_BASE_ADDRESS: StaticRef<MpuRegisters> =
 ///
 /// There should only be one instantiation of this object as it represents
 /// real hardware.
+///
+/// To measure how long configuring the MPU takes on a context switch, give
+/// the [`Dwt`](crate::dwt::Dwt) cycle counter to
+/// [`kernel::Kernel::set_cycle_counter`], and divide the `mpu_setup_cycles` by
+/// the `context_switches` of [`kernel::Kernel::loop_counters`].
+/// The `stats` command of the process console prints this average.
 pub struct MPU<const NUM_REGIONS: usize, const MIN_REGION_SIZE: usize> {
     /// MMIO reference to MPU registers.
     registers: StaticRef<MpuRegisters>,
@@ -146,6 +152,10 @@ pub struct MPU<const NUM_REGIONS: usize, const MIN_REGION_SIZE: usize> {
     /// is currently configured for so that the MPU can skip updating when the
     /// kernel returns to the same app.
     hardware_is_configured_for: OptionalCell<NonZeroUsize>,
+    /// Values last written to the RBAR and RASR registers of each region, so
+    /// that regions which are already configured as needed are not written
+    /// again.
+    hardware_regions: [Cell<Option<(u32, u32)>>; NUM_REGIONS],
 }
 
 impl<const NUM_REGIONS: usize, const MIN_REGION_SIZE: usize> MPU<NUM_REGIONS, MIN_REGION_SIZE> {
@@ -154,6 +164,7 @@ impl<const NUM_REGIONS: usize, const MIN_REGION_SIZE: usize> MPU<NUM_REGIONS, MI
             registers: MPU_BASE_ADDRESS,
             config_count: Cell::new(NonZeroUsize::MIN),
             hardware_is_configured_for: OptionalCell::empty(),
+            hardware_regions: [const { Cell::new(None) }; NUM_REGIONS],
         }
     }
 
@@ -166,26 +177,20 @@ impl<const NUM_REGIONS: usize, const MIN_REGION_SIZE: usize> MPU<NUM_REGIONS, MI
 
 /// Per-process struct storing MPU configuration for cortex-m MPUs.
 ///
-/// The cortex-m MPU has eight regions, all of which must be configured (though
-/// unused regions may be configured as disabled). This struct caches the result
-/// of region configuration calculation.
+/// The cortex-m MPU has eight or sixteen regions, all of which must be
+/// configured (though unused regions may be configured as disabled). This
+/// struct caches the result of region configuration calculation.
 pub struct CortexMConfig<const NUM_REGIONS: usize> {
     /// Unique ID for this configuration, assigned from a
     /// monotonically increasing counter in the MPU struct.
     id: NonZeroUsize,
     /// The computed region configuration for this process.
     regions: [CortexMRegion; NUM_REGIONS],
-    /// Has the configuration changed since the last time the this process
-    /// configuration was written to hardware?
-    is_dirty: Cell<bool>,
+    /// Regions that changed since the last time this process configuration
+    /// was written to hardware, one bit per region.
+    dirty_regions: Cell<u32>,
 }
 
-/// Records the index of the last region used for application RAM memory.
-/// Regions 0-APP_MEMORY_REGION_MAX_NUM are used for application RAM. Regions
-/// with indices above APP_MEMORY_REGION_MAX_NUM can be used for other MPU
-/// needs.
-const APP_MEMORY_REGION_MAX_NUM: usize = 1;
-
 impl<const NUM_REGIONS: usize> fmt::Display for CortexMConfig<NUM_REGIONS> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "\r\n Cortex-M MPU")?;
@@ -215,16 +220,21 @@ impl<const NUM_REGIONS: usize> fmt::Display for CortexMConfig<NUM_REGIONS> {
                     access_str,
                     access_bits,
                 )?;
+                // The sub-regions are those of the physical region, which can
+                // be larger than the logical one.
                 let subregion_bits = region.attributes().read(RegionAttributes::SRD);
-                let subregion_size = location.1 / 8;
+                let region_start =
+                    (region.base_address().read(RegionBaseAddress::ADDR) << 5) as usize;
+                let subregion_size =
+                    (1 << (region.attributes().read(RegionAttributes::SIZE) + 1)) / 8;
                 for j in 0..8 {
                     write!(
                         f,
                         "\
                          \r\n    Sub-region {}: [{:#010X}:{:#010X}], {}",
                         j,
-                        start + j * subregion_size,
-                        start + (j + 1) * subregion_size,
+                        region_start + j * subregion_size,
+                        region_start + (j + 1) * subregion_size,
                         if (subregion_bits >> j) & 1 == 0 {
                             "Enabled"
                         } else {
@@ -241,9 +251,26 @@ impl<const NUM_REGIONS: usize> fmt::Display for CortexMConfig<NUM_REGIONS> {
 }
 
 impl<const NUM_REGIONS: usize> CortexMConfig<NUM_REGIONS> {
+    /// Mask of `dirty_regions` with all regions set.
+    const ALL_REGIONS: u32 = ((1u64 << NUM_REGIONS) - 1) as u32;
+
+    fn mark_dirty(&self, region_num: usize) {
+        self.dirty_regions
+            .set(self.dirty_regions.get() | (1 << region_num));
+    }
+
+    /// Number of regions used for application RAM memory. Regions
+    /// `0..APP_MEMORY_REGIONS` are used for application RAM, the other regions
+    /// can be used for other MPU needs.
+    ///
+    /// MPUs with sixteen regions use four regions for application RAM, which
+    /// halves the size of the regions compared to two, and thus their alignment
+    /// and the granularity of the app memory break.
+    const APP_MEMORY_REGIONS: usize = if NUM_REGIONS >= 16 { 4 } else { 2 };
+
     fn unused_region_number(&self) -> Option<usize> {
         for (number, region) in self.regions.iter().enumerate() {
-            if number <= APP_MEMORY_REGION_MAX_NUM {
+            if number < Self::APP_MEMORY_REGIONS {
                 continue;
             }
             if let None = region.location() {
@@ -252,6 +279,58 @@ impl<const NUM_REGIONS: usize> CortexMConfig<NUM_REGIONS> {
         }
         None
     }
+
+    /// Cover the app-owned memory with the application RAM regions of
+    /// `region_size` bytes placed back to back from `region_start`, enabling
+    /// their first `num_enabled_subregions` subregions.
+    ///
+    /// The regions are only updated if all of them can be configured.
+    fn set_app_memory_regions(
+        &mut self,
+        region_start: usize,
+        region_size: usize,
+        num_enabled_subregions: usize,
+        permissions: mpu::Permissions,
+    ) -> Option<()> {
+        // The first region is always used, as we cannot have an app memory
+        // region without subregions.
+        if num_enabled_subregions == 0 || num_enabled_subregions > 8 * Self::APP_MEMORY_REGIONS {
+            return None;
+        }
+
+        let subregion_size = region_size / 8;
+        let mut regions = self.regions;
+        for (i, region) in regions
+            .iter_mut()
+            .enumerate()
+            .take(Self::APP_MEMORY_REGIONS)
+        {
+            let num_enabled = cmp::min(num_enabled_subregions.saturating_sub(i * 8), 8);
+            *region = if num_enabled == 0 {
+                // We cannot have a completely unused MPU region
+                CortexMRegion::empty(i)
+            } else {
+                // The logical region only spans the enabled subregions, so
+                // that it does not overlap with memory past the app break.
+                let start = region_start + i * region_size;
+                CortexMRegion::new(
+                    start as *const u8,
+                    num_enabled * subregion_size,
+                    start as *const u8,
+                    region_size,
+                    i,
+                    Some((0, num_enabled - 1)),
+                    permissions,
+                )?
+            };
+        }
+
+        self.regions = regions;
+        for i in 0..Self::APP_MEMORY_REGIONS {
+            self.mark_dirty(i);
+        }
+        Some(())
+    }
 }
 
 /// Struct storing configuration for a Cortex-M MPU region.
@@ -384,6 +463,13 @@ impl<const NUM_REGIONS: usize, const MIN_REGION_SIZE: usize> mpu::MPU
 {
     type MpuConfig = CortexMConfig<NUM_REGIONS>;
 
+    // App memory blocks are covered by `APP_MEMORY_REGIONS` regions of the
+    // same power-of-two size, each aligned to its size.
+    const APP_MEMORY_ALIGNMENT: mpu::AppMemoryAlignment = mpu::AppMemoryAlignment {
+        min_alignment: 256,
+        regions: CortexMConfig::<NUM_REGIONS>::APP_MEMORY_REGIONS,
+    };
+
     fn enable_app_mpu(&self) {
         // Enable the MPU, disable it during HardFault/NMI handlers, and allow
         // privileged code access to all unprotected memory.
@@ -411,7 +497,7 @@ impl<const NUM_REGIONS: usize, const MIN_REGION_SIZE: usize> mpu::MPU
         let mut ret = CortexMConfig {
             id,
             regions: [CortexMRegion::empty(0); NUM_REGIONS],
-            is_dirty: Cell::new(true),
+            dirty_regions: Cell::new(CortexMConfig::<NUM_REGIONS>::ALL_REGIONS),
         };
 
         self.reset_config(&mut ret);
@@ -424,7 +510,7 @@ impl<const NUM_REGIONS: usize, const MIN_REGION_SIZE: usize> mpu::MPU
             config.regions[i] = CortexMRegion::empty(i);
         }
 
-        config.is_dirty.set(true);
+        config.dirty_regions.set(Self::MpuConfig::ALL_REGIONS);
     }
 
     fn allocate_region(
@@ -549,7 +635,7 @@ impl<const NUM_REGIONS: usize, const MIN_REGION_SIZE: usize> mpu::MPU
         )?;
 
         config.regions[region_num] = region;
-        config.is_dirty.set(true);
+        config.mark_dirty(region_num);
 
         Some(mpu::Region::new(start as *const u8, size))
     }
@@ -566,19 +652,27 @@ impl<const NUM_REGIONS: usize, const MIN_REGION_SIZE: usize> mpu::MPU
             .find(|(_idx, r)| **r == region)
             .ok_or(())?;
 
-        if idx <= APP_MEMORY_REGION_MAX_NUM {
+        if idx < Self::MpuConfig::APP_MEMORY_REGIONS {
             return Err(());
         }
 
         config.regions[idx] = CortexMRegion::empty(idx);
-        config.is_dirty.set(true);
+        config.mark_dirty(idx);
 
         Ok(())
     }
 
-    // When allocating memory for apps, we use two regions, each a power of two
-    // in size. By using two regions we halve their size, and also halve their
-    // alignment restrictions.
+    // When allocating memory for apps, we use two regions (four on MPUs with
+    // sixteen regions) of the same power-of-two size, placed back to back. By
+    // using several regions we reduce their size, and thus their alignment
+    // restrictions.
+    //
+    // The app-owned memory is covered by enabling the subregions of these
+    // regions one after the other, and the kernel-owned memory at the end of
+    // the process memory block is never covered. The block thus only needs to
+    // be a multiple of the subregion size, rather than a power of two, which
+    // avoids wasting up to half of the block for memory layouts that are not a
+    // power of two.
     fn allocate_app_memory_region(
         &self,
         unallocated_memory_start: *const u8,
@@ -597,116 +691,59 @@ impl<const NUM_REGIONS: usize, const MIN_REGION_SIZE: usize> mpu::MPU
             }
         }
 
-        // Make sure there is enough memory for app memory and kernel memory.
-        let memory_size = cmp::max(
-            min_memory_size,
-            initial_app_memory_size + initial_kernel_memory_size,
-        );
-
-        // Size must be a power of two, so:
-        // https://www.youtube.com/watch?v=ovo6zwv6DX4.
-        let mut memory_size_po2 = math::closest_power_of_two(memory_size as u32) as usize;
-        let exponent = math::log_base_two(memory_size_po2 as u32);
-
-        // Check for compliance with the constraints of the MPU.
-        if exponent < 9 {
-            // Region sizes must be 256 bytes or larger to support subregions.
-            // Since we are using two regions, and each must be at least 256
-            // bytes, we need the entire memory region to be at least 512 bytes.
-            memory_size_po2 = 512;
-        } else if exponent > 32 {
-            // Region sizes must be 4GB or smaller.
-            return None;
-        }
+        let app_memory_regions = Self::MpuConfig::APP_MEMORY_REGIONS;
+
+        // Find the smallest region size for which the regions hold both the
+        // app-owned memory, rounded up to subregions, and the kernel-owned
+        // memory. Region sizes must be 256 bytes or larger to support
+        // subregions.
+        let mut region_size: usize = 256;
+        let (memory_size, num_enabled_subregions) = loop {
+            let subregion_size = region_size / 8;
+
+            // Want `round_up(app_memory_size / subregion_size)`, with at least
+            // one subregion.
+            let num_enabled_subregions = initial_app_memory_size / subregion_size + 1;
+            let memory_size = cmp::max(
+                min_memory_size,
+                num_enabled_subregions * subregion_size + initial_kernel_memory_size,
+            )
+            .next_multiple_of(subregion_size);
 
-        // Region size is the actual size the MPU region will be set to, and is
-        // half of the total power of two size we are allocating to the app.
-        let mut region_size = memory_size_po2 / 2;
+            if memory_size <= app_memory_regions * region_size {
+                break (memory_size, num_enabled_subregions);
+            }
+
+            // Region sizes must be 4GB or smaller.
+            if region_size >= (1 << 31) {
+                return None;
+            }
+            region_size *= 2;
+        };
 
         // The region should start as close as possible to the start of the
-        // unallocated memory.
+        // unallocated memory. If the start and length don't align, move region
+        // up until it does.
         let mut region_start = unallocated_memory_start as usize;
-
-        // If the start and length don't align, move region up until it does.
         if region_start % region_size != 0 {
             region_start += region_size - (region_start % region_size);
         }
 
-        // We allocate two MPU regions exactly over the process memory block,
-        // and we disable subregions at the end of this region to disallow
-        // access to the memory past the app break. As the app break later
-        // increases, we will be able to linearly grow the logical region
-        // covering app-owned memory by enabling more and more subregions. The
-        // Cortex-M MPU supports 8 subregions per region, so the size of this
-        // logical region is always a multiple of a sixteenth of the MPU region
-        // length.
-
-        // Determine the number of subregions to enable.
-        // Want `round_up(app_memory_size / subregion_size)`.
-        let mut num_enabled_subregions = initial_app_memory_size * 8 / region_size + 1;
-
-        let subregion_size = region_size / 8;
-
-        // Calculates the end address of the enabled subregions and the initial
-        // kernel memory break.
-        let subregions_enabled_end = region_start + num_enabled_subregions * subregion_size;
-        let kernel_memory_break = region_start + memory_size_po2 - initial_kernel_memory_size;
-
-        // If the last subregion covering app-owned memory overlaps the start of
-        // kernel-owned memory, we make the entire process memory block twice as
-        // big so there is plenty of space between app-owned and kernel-owned
-        // memory.
-        if subregions_enabled_end > kernel_memory_break {
-            region_size *= 2;
-
-            if region_start % region_size != 0 {
-                region_start += region_size - (region_start % region_size);
-            }
-
-            num_enabled_subregions = initial_app_memory_size * 8 / region_size + 1;
-        }
-
-        // Make sure the region fits in the unallocated memory.
-        if region_start + memory_size_po2
+        // Make sure the memory block fits in the unallocated memory.
+        if region_start + memory_size
             > (unallocated_memory_start as usize) + unallocated_memory_size
         {
             return None;
         }
 
-        // Get the number of subregions enabled in each of the two MPU regions.
-        let num_enabled_subregions0 = cmp::min(num_enabled_subregions, 8);
-        let num_enabled_subregions1 = num_enabled_subregions.saturating_sub(8);
-
-        let region0 = CortexMRegion::new(
-            region_start as *const u8,
+        config.set_app_memory_regions(
+            region_start,
             region_size,
-            region_start as *const u8,
-            region_size,
-            0,
-            Some((0, num_enabled_subregions0 - 1)),
+            num_enabled_subregions,
             permissions,
         )?;
 
-        // We cannot have a completely unused MPU region
-        let region1 = if num_enabled_subregions1 == 0 {
-            CortexMRegion::empty(1)
-        } else {
-            CortexMRegion::new(
-                (region_start + region_size) as *const u8,
-                region_size,
-                (region_start + region_size) as *const u8,
-                region_size,
-                1,
-                Some((0, num_enabled_subregions1 - 1)),
-                permissions,
-            )?
-        };
-
-        config.regions[0] = region0;
-        config.regions[1] = region1;
-        config.is_dirty.set(true);
-
-        Some((region_start as *const u8, memory_size_po2))
+        Some((region_start as *const u8, memory_size))
     }
 
     fn update_app_memory_region(
@@ -718,8 +755,9 @@ impl<const NUM_REGIONS: usize, const MIN_REGION_SIZE: usize> mpu::MPU
     ) -> Result<(), ()> {
         // Get first region, or error if the process tried to update app memory
         // MPU region before it was created.
-        let (region_start_ptr, region_size) = config.regions[0].location().ok_or(())?;
+        let (region_start_ptr, _) = config.regions[0].location().ok_or(())?;
         let region_start = region_start_ptr as usize;
+        let region_size = 1 << (config.regions[0].attributes().read(RegionAttributes::SIZE) + 1);
 
         let app_memory_break = app_memory_break as usize;
         let kernel_memory_break = kernel_memory_break as usize;
@@ -747,54 +785,43 @@ impl<const NUM_REGIONS: usize, const MIN_REGION_SIZE: usize> mpu::MPU
             return Err(());
         }
 
-        // Get the number of subregions enabled in each of the two MPU regions.
-        let num_enabled_subregions0 = cmp::min(num_enabled_subregions, 8);
-        let num_enabled_subregions1 = num_enabled_subregions.saturating_sub(8);
-
-        let region0 = CortexMRegion::new(
-            region_start as *const u8,
-            region_size,
-            region_start as *const u8,
-            region_size,
-            0,
-            Some((0, num_enabled_subregions0 - 1)),
-            permissions,
-        )
-        .ok_or(())?;
-
-        let region1 = if num_enabled_subregions1 == 0 {
-            CortexMRegion::empty(1)
-        } else {
-            CortexMRegion::new(
-                (region_start + region_size) as *const u8,
-                region_size,
-                (region_start + region_size) as *const u8,
+        config
+            .set_app_memory_regions(
+                region_start,
                 region_size,
-                1,
-                Some((0, num_enabled_subregions1 - 1)),
+                num_enabled_subregions,
                 permissions,
             )
-            .ok_or(())?
-        };
-
-        config.regions[0] = region0;
-        config.regions[1] = region1;
-        config.is_dirty.set(true);
-
-        Ok(())
+            .ok_or(())
     }
 
     fn configure_mpu(&self, config: &Self::MpuConfig) {
-        // If the hardware is already configured for this app and the app's MPU
-        // configuration has not changed, then skip the hardware update.
-        if !self.hardware_is_configured_for.contains(&config.id) || config.is_dirty.get() {
-            // Set MPU regions
-            for region in config.regions.iter() {
+        // If the hardware is already configured for this app, only the regions
+        // that changed since need to be written, and none if the app's MPU
+        // configuration has not changed.
+        let regions = if self.hardware_is_configured_for.contains(&config.id) {
+            config.dirty_regions.get()
+        } else {
+            Self::MpuConfig::ALL_REGIONS
+        };
+        if regions == 0 {
+            return;
+        }
+
+        for (i, region) in config.regions.iter().enumerate() {
+            if regions & (1 << i) == 0 {
+                continue;
+            }
+            // Regions that are configured the same way as for the previous
+            // app, such as unused regions, are not written either.
+            let values = (region.base_address().value, region.attributes().value);
+            if self.hardware_regions[i].get() != Some(values) {
                 self.registers.rbar.write(region.base_address());
                 self.registers.rasr.write(region.attributes());
+                self.hardware_regions[i].set(Some(values));
             }
-            self.hardware_is_configured_for.set(config.id);
-            config.is_dirty.set(false);
         }
+        self.hardware_is_configured_for.set(config.id);
+        config.dirty_regions.set(0);
     }
 }
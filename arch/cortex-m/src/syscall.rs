@@ -448,4 +448,9 @@ impl<A: CortexMVariant> kernel::syscall::UserspaceKernelBoundary for SysCall<A>
             Err(ErrorCode::SIZE)
         }
     }
+
+    fn load_context(&self, state: &mut CortexMStoredState, input: &[u8]) -> Result<(), ErrorCode> {
+        *state = CortexMStoredState::try_from(input).or(Err(ErrorCode::INVAL))?;
+        Ok(())
+    }
 }
@@ -0,0 +1,125 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! ARM Trace Port Interface Unit
+//!
+//! The TPIU outputs the trace packets of the ITM (and of the ETM, if the chip
+//! has one) on the trace pins. This module only configures the Serial Wire
+//! Output (SWO) pin, which most debug probes can capture.
+//!
+//! The chip must also route the SWO pin to the TPIU, which is chip specific
+//! (for example with the `DBGMCU_CR` register and the alternate function of
+//! the pin on STM32 chips).
+//!
+//! <https://developer.arm.com/documentation/ddi0403/latest>
+//! Implementation matches `ARM DDI 0403E.e`
+
+use kernel::utilities::registers::interfaces::{Readable, Writeable};
+use kernel::utilities::registers::{register_bitfields, register_structs, ReadOnly, ReadWrite};
+use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
+
+register_structs! {
+    TpiuRegisters {
+        /// Supported Parallel Port Size Register
+        (0x000 => sspsr: ReadOnly<u32>),
+
+        /// Current Parallel Port Size Register
+        (0x004 => cspsr: ReadWrite<u32>),
+
+        (0x008 => _reserved0),
+
+        /// Asynchronous Clock Prescaler Register
+        (0x010 => acpr: ReadWrite<u32, AsynchronousClockPrescaler::Register>),
+
+        (0x014 => _reserved1),
+
+        /// Selected Pin Protocol Register
+        (0x0F0 => sppr: ReadWrite<u32, SelectedPinProtocol::Register>),
+
+        (0x0F4 => _reserved2),
+
+        /// Formatter and Flush Status Register
+        (0x300 => ffsr: ReadOnly<u32>),
+
+        /// Formatter and Flush Control Register
+        (0x304 => ffcr: ReadWrite<u32, FormatterAndFlushControl::Register>),
+
+        (0x308 => @END),
+    }
+}
+
+register_bitfields![u32,
+    AsynchronousClockPrescaler [
+        /// The baud rate of the SWO pin is the trace clock divided by
+        /// `SWOSCALER + 1`.
+        SWOSCALER       OFFSET(0)   NUMBITS(16),
+    ],
+    SelectedPinProtocol [
+        TXMODE          OFFSET(0)   NUMBITS(2) [
+            ParallelTracePort = 0,
+            SwoManchester = 1,
+            SwoNrz = 2,
+        ],
+    ],
+    FormatterAndFlushControl [
+        /// Indicates that triggers are inserted in the trace stream. Reads as
+        /// one on most implementations.
+        TRIGIN          OFFSET(8)   NUMBITS(1),
+
+        /// Continuous formatting. The formatter is not needed for the SWO
+        /// output, which only carries the trace stream of the ITM.
+        ENFCONT         OFFSET(1)   NUMBITS(1),
+    ],
+];
+
+const TPIU: StaticRef<TpiuRegisters> =
+    unsafe { StaticRef::new(0xE0040000 as *const TpiuRegisters) };
+
+/// Encoding of the SWO pin.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SwoProtocol {
+    /// Manchester encoding.
+    Manchester,
+    /// NRZ encoding, like a UART. Most debug probes expect this encoding.
+    Nrz,
+}
+
+/// Output the trace stream on the SWO pin at `baud_rate`, from the trace
+/// clock at `trace_clock_hz`, which is usually the core clock.
+///
+/// The debug and trace unit must be enabled first, with
+/// [`dcb::enable_debug_and_trace`](crate::dcb::enable_debug_and_trace) (it
+/// is enabled by [`itm::enable`](crate::itm::enable)). Returns `INVAL` if the
+/// baud rate cannot be derived from the trace clock.
+pub fn configure_swo(
+    trace_clock_hz: u32,
+    baud_rate: u32,
+    protocol: SwoProtocol,
+) -> Result<(), ErrorCode> {
+    if baud_rate == 0 || baud_rate > trace_clock_hz {
+        return Err(ErrorCode::INVAL);
+    }
+    let prescaler = (trace_clock_hz / baud_rate) - 1;
+    if prescaler > 0xFFFF {
+        return Err(ErrorCode::INVAL);
+    }
+
+    // The SWO output has a single pin.
+    TPIU.cspsr.set(1);
+    TPIU.acpr
+        .write(AsynchronousClockPrescaler::SWOSCALER.val(prescaler));
+    TPIU.sppr.write(match protocol {
+        SwoProtocol::Manchester => SelectedPinProtocol::TXMODE::SwoManchester,
+        SwoProtocol::Nrz => SelectedPinProtocol::TXMODE::SwoNrz,
+    });
+    TPIU.ffcr.write(FormatterAndFlushControl::TRIGIN::SET);
+    Ok(())
+}
+
+/// Supported sizes of the parallel trace port, as a bitmask where bit `n`
+/// set means that a port of `n + 1` pins is supported.
+pub fn supported_port_sizes() -> u32 {
+    TPIU.sspsr.get()
+}
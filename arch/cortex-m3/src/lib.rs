@@ -16,10 +16,12 @@ pub mod mpu {
 
 pub use cortexm::initialize_ram_jump_to_main;
 pub use cortexm::interrupt_mask;
+pub use cortexm::itm;
 pub use cortexm::nvic;
 pub use cortexm::scb;
 pub use cortexm::support;
 pub use cortexm::systick;
+pub use cortexm::tpiu;
 pub use cortexm::unhandled_interrupt;
 pub use cortexm::CortexMVariant;
 
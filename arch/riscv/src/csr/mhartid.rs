@@ -0,0 +1,11 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+use kernel::utilities::registers::register_bitfields;
+
+register_bitfields![usize,
+    pub mhartid [
+        hartid OFFSET(0) NUMBITS(crate::XLEN) []
+    ]
+];
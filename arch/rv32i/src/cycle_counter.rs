@@ -0,0 +1,42 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Cycle counter based on the `mcycle` CSR.
+
+use core::cell::Cell;
+
+use kernel::hil;
+
+use crate::csr::CSR;
+
+/// The `mcycle` counter as a [`hil::hw_debug::CycleCounter`].
+///
+/// Not all cores can stop `mcycle`, so it always runs: `start()` and `stop()`
+/// do nothing, and `reset()` only moves the point counts are measured from.
+pub struct McycleCounter {
+    /// Value of `mcycle` at the last reset.
+    offset: Cell<u64>,
+}
+
+impl McycleCounter {
+    pub const fn new() -> Self {
+        Self {
+            offset: Cell::new(0),
+        }
+    }
+}
+
+impl hil::hw_debug::CycleCounter for McycleCounter {
+    fn start(&self) {}
+
+    fn stop(&self) {}
+
+    fn count(&self) -> u64 {
+        CSR.read_cycle_counter().wrapping_sub(self.offset.get())
+    }
+
+    fn reset(&self) {
+        self.offset.set(CSR.read_cycle_counter());
+    }
+}
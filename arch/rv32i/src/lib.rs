@@ -13,8 +13,10 @@ use core::fmt::Write;
 use kernel::utilities::registers::interfaces::{Readable, Writeable};
 
 pub mod clic;
+pub mod cycle_counter;
 pub mod machine_timer;
 pub mod pmp;
+pub mod smp;
 pub mod support;
 pub mod syscall;
 
@@ -56,6 +58,9 @@ extern "C" {
     ///    any Rust code runs. See <https://github.com/tock/tock/issues/2222> for more
     ///    information.
     /// 3. Finally it calls `main()`, the main entry point for Tock boards.
+    ///
+    /// Only hart 0 does this. The other harts wait until they are started
+    /// with [`smp::start_secondary_harts`].
     pub fn _start();
 }
 
@@ -85,6 +90,11 @@ core::arch::global_asm!("
             // Re-enable linker relaxations.
             .option pop
 
+            // Only hart 0 initializes memory and runs `main()`. The other
+            // harts, if any, wait until the board starts them.
+            csrr t0, 0xF14              // t0 = mhartid
+            bnez t0, 300f               // If t0 != 0, go wait.
+
             // Initialize the stack pointer register. This comes directly from
             // the linker script.
             la sp, {estack}             // Set the initial stack pointer.
@@ -133,6 +143,41 @@ core::arch::global_asm!("
             // With that initial setup out of the way, we now branch to the main
             // code, likely defined in a board's main.rs.
             j main
+
+          300: // secondary_hart_wait
+            // Wait until `smp::start_secondary_harts()` publishes the entry
+            // point and the stacks of the secondary harts. This does not use
+            // any memory other than the boot parameters, as hart 0 may still
+            // be initializing it.
+            la t1, {smp_boot}           // t1 = &SECONDARY_HART_BOOT
+            lw t2, 0(t1)                // t2 = magic
+            li t3, {smp_magic}
+            bne t2, t3, 300b            // Wait until magic is set.
+            fence r, rw                 // Read the parameters after magic.
+
+            lw t2, 16(t1)               // t2 = last_hart
+            bgtu t0, t2, 302f           // Park harts that have no stack.
+
+            // The stack of hart n ends at stack_base + n * stack_size.
+            lw t2, 8(t1)                // t2 = stack_base
+            lw t3, 12(t1)               // t3 = stack_size
+            mv t4, t0                   // t4 = mhartid
+          301:
+            add t2, t2, t3              // t2 += stack_size
+            addi t4, t4, -1
+            bnez t4, 301b               // Repeat mhartid times.
+            mv sp, t2                   // sp = end of the hart stack
+            add s0, sp, zero            // s0 = sp
+
+            csrw 0x340, zero            // mscratch = 0, we are in the kernel.
+
+            lw t2, 4(t1)                // t2 = entry
+            mv a0, t0                   // a0 = mhartid
+            jr t2                       // entry(mhartid)
+
+          302: // secondary_hart_park
+            wfi
+            j 302b
         ",
 gp = sym __global_pointer,
 estack = sym _estack,
@@ -141,6 +186,8 @@ ebss = sym _ezero,
 sdata = sym _srelocate,
 edata = sym _erelocate,
 etext = sym _etext,
+smp_boot = sym smp::SECONDARY_HART_BOOT,
+smp_magic = const smp::SECONDARY_HART_BOOT_MAGIC,
 );
 
 /// The various privilege levels in RISC-V.
@@ -242,6 +242,54 @@ fn region_overlaps(
             || other_range.contains(&(region_range.end - 1)))
 }
 
+/// Lay out the enabled regions of a [`PMPUserMPUConfig`] in consecutive PMP
+/// TOR entries, starting at entry 0.
+///
+/// A TOR entry matches the addresses between the address of the previous
+/// entry and its own. The regions are thus sorted by address, and a disabled
+/// entry holding the start address of a region is only inserted when the
+/// previous entry does not already hold that address. Adjacent regions then
+/// take a single entry, instead of two.
+///
+/// `emit` is called for each entry in order, with the index of the entry, its
+/// configuration and its `pmpaddrX` value. Returns the number of entries of
+/// the layout.
+pub fn pack_tor_regions<const MAX_REGIONS: usize>(
+    regions: &[(TORUserPMPCFG, *const u8, *const u8); MAX_REGIONS],
+    mut emit: impl FnMut(usize, TORUserPMPCFG, usize),
+) -> usize {
+    // Indices of the enabled regions, sorted by start address:
+    let mut order = [0; MAX_REGIONS];
+    let mut enabled = 0;
+    for (i, region) in regions.iter().enumerate() {
+        if region.0 != TORUserPMPCFG::OFF {
+            order[enabled] = i;
+            enabled += 1;
+        }
+    }
+    let order = &mut order[..enabled];
+    order.sort_unstable_by_key(|i| regions[*i].1 as usize);
+
+    // The first PMP entry is bounded by address 0.
+    let mut previous = 0;
+    let mut entries = 0;
+    for i in order.iter() {
+        let (pmpcfg, start, end) = regions[*i];
+        let start = (start as usize).overflowing_shr(2).0;
+        let end = (end as usize).overflowing_shr(2).0;
+
+        if start != previous {
+            emit(entries, TORUserPMPCFG::OFF, start);
+            entries += 1;
+        }
+        emit(entries, pmpcfg, end);
+        entries += 1;
+        previous = end;
+    }
+
+    entries
+}
+
 /// Print a table of the configured PMP regions, read from  the HW CSRs.
 ///
 /// # Safety
@@ -413,6 +461,18 @@ pub trait TORUserPMP<const MAX_REGIONS: usize> {
     /// disabled through [`TORUserPMP::disable_user_pmp`]).
     fn available_regions(&self) -> usize;
 
+    /// The number of PMP entries available for userspace memory protection,
+    /// if the implementation lays out the regions with [`pack_tor_regions`].
+    ///
+    /// As adjacent regions share PMP entries when packed, `MAX_REGIONS` may
+    /// then exceed half of the number of entries. The [`PMPUserMPU`] only
+    /// allocates or grows a region if the packed regions still fit in the
+    /// entries. Implementations which place each region in two fixed entries
+    /// return `None`.
+    fn packed_entries(&self) -> Option<usize> {
+        None
+    }
+
     /// Configure the user-mode memory protection.
     ///
     /// This method configures the user-mode memory protection, to be enforced
@@ -482,9 +542,10 @@ pub trait TORUserPMP<const MAX_REGIONS: usize> {
 pub struct PMPUserMPUConfig<const MAX_REGIONS: usize> {
     /// PMP config identifier, as generated by the issuing PMP implementation.
     id: NonZeroUsize,
-    /// Indicates if the configuration has changed since the last time it was
-    /// written to hardware.
-    is_dirty: Cell<bool>,
+    /// Incremented every time the configuration changes. On chips with
+    /// several harts, each hart has its own PMP, which compares the generation
+    /// to the one it last wrote to hardware to know whether it is stale.
+    generation: Cell<usize>,
     /// Array of MPU regions. Each region requires two physical PMP entries.
     regions: [(TORUserPMPCFG, *const u8, *const u8); MAX_REGIONS],
     /// Which region index (into the `regions` array above) is used
@@ -492,6 +553,14 @@ pub struct PMPUserMPUConfig<const MAX_REGIONS: usize> {
     app_memory_region: OptionalCell<usize>,
 }
 
+impl<const MAX_REGIONS: usize> PMPUserMPUConfig<MAX_REGIONS> {
+    /// Record that the configuration changed and must be written to hardware
+    /// again.
+    fn mark_changed(&self) {
+        self.generation.set(self.generation.get().wrapping_add(1));
+    }
+}
+
 impl<const MAX_REGIONS: usize> fmt::Display for PMPUserMPUConfig<MAX_REGIONS> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         // Ternary operator shortcut function, to avoid bulky formatting...
@@ -505,9 +574,9 @@ impl<const MAX_REGIONS: usize> fmt::Display for PMPUserMPUConfig<MAX_REGIONS> {
 
         write!(
             f,
-            " PMPUserMPUConfig {{\r\n  id: {},\r\n  is_dirty: {},\r\n  app_memory_region: {:?},\r\n  regions:\r\n",
+            " PMPUserMPUConfig {{\r\n  id: {},\r\n  generation: {},\r\n  app_memory_region: {:?},\r\n  regions:\r\n",
             self.id,
-            self.is_dirty.get(),
+            self.generation.get(),
             self.app_memory_region.get()
         )?;
 
@@ -538,10 +607,10 @@ pub struct PMPUserMPU<const MAX_REGIONS: usize, P: TORUserPMP<MAX_REGIONS> + 'st
     /// Monotonically increasing counter for allocated configurations, used to
     /// assign unique IDs to `PMPUserMPUConfig` instances.
     config_count: Cell<NonZeroUsize>,
-    /// The configuration that the PMP was last configured for. Used (along with
-    /// the `is_dirty` flag) to determine if PMP can skip writing the
+    /// The configuration, and its generation, that the PMP was last
+    /// configured for. Used to determine if PMP can skip writing the
     /// configuration to hardware.
-    last_configured_for: OptionalCell<NonZeroUsize>,
+    last_configured_for: OptionalCell<(NonZeroUsize, usize)>,
     /// Underlying hardware PMP implementation, exposing a number (up to
     /// `P::MAX_REGIONS`) of memory protection regions with a 4-byte enforcement
     /// granularity.
@@ -562,6 +631,14 @@ impl<const MAX_REGIONS: usize, P: TORUserPMP<MAX_REGIONS> + 'static> PMPUserMPU<
             pmp,
         }
     }
+
+    /// Whether the regions of `config` fit in the PMP entries, for PMP
+    /// implementations which pack the regions.
+    fn regions_fit(&self, config: &PMPUserMPUConfig<MAX_REGIONS>) -> bool {
+        self.pmp
+            .packed_entries()
+            .is_none_or(|entries| pack_tor_regions(&config.regions, |_, _, _| ()) <= entries)
+    }
 }
 
 impl<const MAX_REGIONS: usize, P: TORUserPMP<MAX_REGIONS> + 'static> kernel::platform::mpu::MPU
@@ -569,6 +646,13 @@ impl<const MAX_REGIONS: usize, P: TORUserPMP<MAX_REGIONS> + 'static> kernel::pla
 {
     type MpuConfig = PMPUserMPUConfig<MAX_REGIONS>;
 
+    // TOR regions only need to be aligned to 4 bytes, whatever their size.
+    const APP_MEMORY_ALIGNMENT: kernel::platform::mpu::AppMemoryAlignment =
+        kernel::platform::mpu::AppMemoryAlignment {
+            min_alignment: 4,
+            regions: 0,
+        };
+
     fn enable_app_mpu(&self) {
         // TODO: This operation may fail when the PMP is not exclusively used
         // for userspace. Instead of panicing, we should handle this case more
@@ -598,7 +682,7 @@ impl<const MAX_REGIONS: usize, P: TORUserPMP<MAX_REGIONS> + 'static> kernel::pla
                 core::ptr::null::<u8>(),
                 core::ptr::null::<u8>(),
             ); MAX_REGIONS],
-            is_dirty: Cell::new(true),
+            generation: Cell::new(0),
             app_memory_region: OptionalCell::empty(),
         })
     }
@@ -612,7 +696,7 @@ impl<const MAX_REGIONS: usize, P: TORUserPMP<MAX_REGIONS> + 'static> kernel::pla
             )
         });
         config.app_memory_region.clear();
-        config.is_dirty.set(true);
+        config.mark_changed();
     }
 
     fn allocate_region(
@@ -700,13 +784,20 @@ impl<const MAX_REGIONS: usize, P: TORUserPMP<MAX_REGIONS> + 'static> kernel::pla
             }
         }
 
-        // All checks passed, store region allocation and mark config as dirty:
+        // Store the region allocation, and make sure that the regions still
+        // fit in the PMP entries:
         config.regions[region_num] = (
             permissions.into(),
             start as *const u8,
             (start + size) as *const u8,
         );
-        config.is_dirty.set(true);
+        if !self.regions_fit(config) {
+            config.regions[region_num].0 = TORUserPMPCFG::OFF;
+            return None;
+        }
+
+        // All checks passed, mark config as dirty:
+        config.mark_changed();
 
         Some(mpu::Region::new(start as *const u8, size))
     }
@@ -730,7 +821,7 @@ impl<const MAX_REGIONS: usize, P: TORUserPMP<MAX_REGIONS> + 'static> kernel::pla
             .ok_or(())?;
 
         config.regions[index].0 = TORUserPMPCFG::OFF;
-        config.is_dirty.set(true);
+        config.mark_changed();
 
         Ok(())
     }
@@ -818,14 +909,21 @@ impl<const MAX_REGIONS: usize, P: TORUserPMP<MAX_REGIONS> + 'static> kernel::pla
             }
         }
 
-        // All checks passed, store region allocation, indicate the
-        // app_memory_region, and mark config as dirty:
+        // Store the region allocation, and make sure that the regions still
+        // fit in the PMP entries:
         config.regions[region_num] = (
             permissions.into(),
             start as *const u8,
             (start + pmp_region_size) as *const u8,
         );
-        config.is_dirty.set(true);
+        if !self.regions_fit(config) {
+            config.regions[region_num].0 = TORUserPMPCFG::OFF;
+            return None;
+        }
+
+        // All checks passed, indicate the app_memory_region, and mark config
+        // as dirty:
+        config.mark_changed();
         config.app_memory_region.replace(region_num);
 
         Some((start as *const u8, memory_block_size))
@@ -856,19 +954,26 @@ impl<const MAX_REGIONS: usize, P: TORUserPMP<MAX_REGIONS> + 'static> kernel::pla
         }
 
         // If we're not out of memory, update the region configuration
-        // accordingly:
+        // accordingly. Moving the end of the region away from the start of
+        // another region can require one more PMP entry, in which case the
+        // update fails:
+        let previous_region = config.regions[region_num];
         config.regions[region_num].0 = permissions.into();
         config.regions[region_num].2 = app_memory_break as *const u8;
-        config.is_dirty.set(true);
+        if !self.regions_fit(config) {
+            config.regions[region_num] = previous_region;
+            return Err(());
+        }
+        config.mark_changed();
 
         Ok(())
     }
 
     fn configure_mpu(&self, config: &Self::MpuConfig) {
-        if !self.last_configured_for.contains(&config.id) || config.is_dirty.get() {
+        let current = (config.id, config.generation.get());
+        if !self.last_configured_for.contains(&current) {
             self.pmp.configure_pmp(&config.regions).unwrap();
-            config.is_dirty.set(false);
-            self.last_configured_for.set(config.id);
+            self.last_configured_for.set(current);
         }
     }
 }
@@ -904,6 +1009,37 @@ pub mod test {
         fn disable_user_pmp(&self) {}
     }
 
+    /// A mock PMP which packs the regions into four PMP entries.
+    struct MockPackingTORUserPMP;
+    impl<const MPU_REGIONS: usize> TORUserPMP<MPU_REGIONS> for MockPackingTORUserPMP {
+        const CONST_ASSERT_CHECK: () = ();
+
+        fn available_regions(&self) -> usize {
+            MPU_REGIONS
+        }
+
+        fn packed_entries(&self) -> Option<usize> {
+            Some(4)
+        }
+
+        fn configure_pmp(
+            &self,
+            regions: &[(TORUserPMPCFG, *const u8, *const u8); MPU_REGIONS],
+        ) -> Result<(), ()> {
+            if super::pack_tor_regions(regions, |_, _, _| ()) > 4 {
+                Err(())
+            } else {
+                Ok(())
+            }
+        }
+
+        fn enable_user_pmp(&self) -> Result<(), ()> {
+            Ok(())
+        }
+
+        fn disable_user_pmp(&self) {}
+    }
+
     // TODO: implement more test cases, such as:
     //
     // - Try to update the app memory break with an invalid pointer below its
@@ -1117,12 +1253,213 @@ pub mod test {
             )
             .is_none());
     }
+
+    #[test]
+    fn test_pack_tor_regions() {
+        use super::pack_tor_regions;
+        use kernel::platform::mpu::Permissions;
+
+        let rw: TORUserPMPCFG = Permissions::ReadWriteOnly.into();
+        let rx: TORUserPMPCFG = Permissions::ReadExecuteOnly.into();
+
+        // Unsorted regions, of which the last two are adjacent, with a
+        // disabled region in between:
+        let regions = [
+            (rw, 0x3000 as *const u8, 0x4000 as *const u8),
+            (
+                TORUserPMPCFG::OFF,
+                core::ptr::null::<u8>(),
+                core::ptr::null::<u8>(),
+            ),
+            (rx, 0x1000 as *const u8, 0x2000 as *const u8),
+            (rw, 0x4000 as *const u8, 0x5000 as *const u8),
+        ];
+
+        let mut entries = [(TORUserPMPCFG::OFF, 0); 8];
+        let count = pack_tor_regions(&regions, |i, cfg, addr| entries[i] = (cfg, addr));
+        assert_eq!(count, 5);
+        assert!(entries[0] == (TORUserPMPCFG::OFF, 0x1000 >> 2));
+        assert!(entries[1] == (rx, 0x2000 >> 2));
+        assert!(entries[2] == (TORUserPMPCFG::OFF, 0x3000 >> 2));
+        assert!(entries[3] == (rw, 0x4000 >> 2));
+        assert!(entries[4] == (rw, 0x5000 >> 2));
+
+        // A region starting at address 0 is bounded by the start of the PMP:
+        let regions = [(rw, core::ptr::null::<u8>(), 0x1000 as *const u8)];
+        assert_eq!(pack_tor_regions(&regions, |_, _, _| ()), 1);
+    }
+
+    #[test]
+    fn test_mpu_packed_regions() {
+        use crate::pmp::PMPUserMPU;
+        use kernel::platform::mpu::{Permissions, MPU};
+
+        // More regions than half of the (four) PMP entries:
+        let mpu: PMPUserMPU<4, MockPackingTORUserPMP> = PMPUserMPU::new(MockPackingTORUserPMP);
+        let mut config = mpu
+            .new_config()
+            .expect("Failed to allocate the first MPU config");
+
+        // Two adjacent regions only take three entries:
+        let (app_start, _) = mpu
+            .allocate_app_memory_region(
+                0x80000000 as *const u8,
+                0x2000,
+                0x2000,
+                0x1000,
+                0x800,
+                Permissions::ReadWriteOnly,
+                &mut config,
+            )
+            .expect("Failed to allocate the app memory region");
+        assert!(app_start == 0x80000000 as *const u8);
+        mpu.allocate_region(
+            0x80001000 as *const u8,
+            0x1000,
+            0x1000,
+            Permissions::ReadOnly,
+            &mut config,
+        )
+        .expect("Failed to allocate a region adjacent to the app memory region");
+
+        // A third region which is adjacent to the second one fits in the last
+        // entry:
+        let region = mpu
+            .allocate_region(
+                0x80002000 as *const u8,
+                0x1000,
+                0x1000,
+                Permissions::ReadOnly,
+                &mut config,
+            )
+            .expect("Failed to allocate a third adjacent region");
+
+        // Another region with a gap before it would need two more entries:
+        mpu.remove_memory_region(region, &mut config)
+            .expect("Failed to remove valid MPU region allocation");
+        assert!(mpu
+            .allocate_region(
+                0x80004000 as *const u8,
+                0x1000,
+                0x1000,
+                Permissions::ReadOnly,
+                &mut config,
+            )
+            .is_none());
+        let region = mpu
+            .allocate_region(
+                0x80002000 as *const u8,
+                0x1000,
+                0x1000,
+                Permissions::ReadOnly,
+                &mut config,
+            )
+            .expect("Failed to allocate a third adjacent region");
+
+        // Shrinking the app memory region would open a gap before the second
+        // region, which does not fit:
+        assert!(mpu
+            .update_app_memory_region(
+                0x80000800 as *const u8,
+                0x80001800 as *const u8,
+                Permissions::ReadWriteOnly,
+                &mut config,
+            )
+            .is_err());
+
+        // Without the third region, it does:
+        mpu.remove_memory_region(region, &mut config)
+            .expect("Failed to remove valid MPU region allocation");
+        mpu.update_app_memory_region(
+            0x80000800 as *const u8,
+            0x80001800 as *const u8,
+            Permissions::ReadWriteOnly,
+            &mut config,
+        )
+        .expect("Failed to shrink the app memory region");
+        mpu.configure_mpu(&config);
+    }
+
+    /// A mock PMP which counts how many times it is configured.
+    struct MockCountingTORUserPMP(core::cell::Cell<usize>);
+    impl<const MPU_REGIONS: usize> TORUserPMP<MPU_REGIONS> for MockCountingTORUserPMP {
+        const CONST_ASSERT_CHECK: () = ();
+
+        fn available_regions(&self) -> usize {
+            MPU_REGIONS
+        }
+
+        fn configure_pmp(
+            &self,
+            _regions: &[(TORUserPMPCFG, *const u8, *const u8); MPU_REGIONS],
+        ) -> Result<(), ()> {
+            self.0.set(self.0.get() + 1);
+            Ok(())
+        }
+
+        fn enable_user_pmp(&self) -> Result<(), ()> {
+            Ok(())
+        }
+
+        fn disable_user_pmp(&self) {}
+    }
+
+    #[test]
+    fn test_mpu_config_changed_on_other_hart() {
+        use crate::pmp::PMPUserMPU;
+        use core::cell::Cell;
+        use kernel::platform::mpu::{Permissions, MPU};
+
+        // The PMPs of two harts, which run the same process in turn:
+        let hart0: PMPUserMPU<4, MockCountingTORUserPMP> =
+            PMPUserMPU::new(MockCountingTORUserPMP(Cell::new(0)));
+        let hart1: PMPUserMPU<4, MockCountingTORUserPMP> =
+            PMPUserMPU::new(MockCountingTORUserPMP(Cell::new(0)));
+        let mut config = hart0
+            .new_config()
+            .expect("Failed to allocate the first MPU config");
+        hart0
+            .allocate_app_memory_region(
+                0x80000000 as *const u8,
+                0x2000,
+                0x2000,
+                0x1000,
+                0x800,
+                Permissions::ReadWriteOnly,
+                &mut config,
+            )
+            .expect("Failed to allocate the app memory region");
+
+        hart0.configure_mpu(&config);
+        hart1.configure_mpu(&config);
+        assert_eq!((hart0.pmp.0.get(), hart1.pmp.0.get()), (1, 1));
+
+        // Hart 0 moves the app memory break and writes the new configuration.
+        // Hart 1 must write it too, the next time it runs the process:
+        hart0
+            .update_app_memory_region(
+                0x80001000 as *const u8,
+                0x80001800 as *const u8,
+                Permissions::ReadWriteOnly,
+                &mut config,
+            )
+            .expect("Failed to grow the app memory region");
+        hart0.configure_mpu(&config);
+        hart1.configure_mpu(&config);
+        assert_eq!((hart0.pmp.0.get(), hart1.pmp.0.get()), (2, 2));
+
+        // Unchanged configurations are not written again:
+        hart0.configure_mpu(&config);
+        hart1.configure_mpu(&config);
+        assert_eq!((hart0.pmp.0.get(), hart1.pmp.0.get()), (2, 2));
+    }
 }
 
 pub mod simple {
     use super::{pmpcfg_octet, TORUserPMP, TORUserPMPCFG};
     use crate::csr;
-    use core::fmt;
+    use core::cell::Cell;
+    use core::{cmp, fmt};
     use kernel::utilities::registers::{FieldValue, LocalRegisterCopy};
 
     /// A "simple" RISC-V PMP implementation.
@@ -1133,17 +1470,33 @@ pub mod simple {
     /// expected to be set to the number of available entries.
     ///
     /// [`SimplePMP`] implements [`TORUserPMP`] to expose all of its regions as
-    /// "top of range" (TOR) regions (each taking up two physical PMP entires)
-    /// for use as a user-mode memory protection mechanism.
+    /// "top of range" (TOR) regions for use as a user-mode memory protection
+    /// mechanism. The regions are packed into the physical PMP entries with
+    /// [`pack_tor_regions`](super::pack_tor_regions) each time the PMP is
+    /// configured: a region takes up two entries, or a single one if it
+    /// starts where the previous region ends.
     ///
     /// Notably, [`SimplePMP`] implements `TORUserPMP<MPU_REGIONS>` over a
-    /// generic `MPU_REGIONS` where `MPU_REGIONS <= (AVAILABLE_ENTRIES / 2)`. As
-    /// PMP re-configuration can have a significiant runtime overhead, users are
-    /// free to specify a small `MPU_REGIONS` const-generic parameter to reduce
-    /// the runtime overhead induced through PMP configuration, at the cost of
-    /// having less PMP regions available to use for userspace memory
+    /// generic `MPU_REGIONS` where `MPU_REGIONS <= AVAILABLE_ENTRIES`. With
+    /// more than `AVAILABLE_ENTRIES / 2` regions, the [`PMPUserMPU`] refuses
+    /// allocations for which the packed regions would not fit in the entries.
+    /// As PMP re-configuration can have a significiant runtime overhead, users
+    /// are free to specify a small `MPU_REGIONS` const-generic parameter to
+    /// reduce the runtime overhead induced through PMP configuration, at the
+    /// cost of having less PMP regions available to use for userspace memory
     /// protection.
-    pub struct SimplePMP<const AVAILABLE_ENTRIES: usize>;
+    ///
+    /// The [`SimplePMP`] remembers the values it last wrote to the pmpaddrX
+    /// and pmpcfgX CSRs, and only writes the CSRs whose value changes.
+    ///
+    /// [`PMPUserMPU`]: super::PMPUserMPU
+    pub struct SimplePMP<const AVAILABLE_ENTRIES: usize> {
+        /// Value last written to each pmpaddrX CSR.
+        pmpaddr: [Cell<Option<usize>>; AVAILABLE_ENTRIES],
+        /// Value last written to each pmpcfgX CSR. Only the first
+        /// `AVAILABLE_ENTRIES / 4` (rounded up) are used.
+        pmpcfg: [Cell<Option<usize>>; AVAILABLE_ENTRIES],
+    }
 
     impl<const AVAILABLE_ENTRIES: usize> SimplePMP<AVAILABLE_ENTRIES> {
         pub unsafe fn new() -> Result<Self, ()> {
@@ -1198,16 +1551,19 @@ pub mod simple {
 
             // Hardware PMP is verified to be in a compatible mode / state, and
             // has at least `AVAILABLE_ENTRIES` entries.
-            Ok(SimplePMP)
+            Ok(SimplePMP {
+                pmpaddr: [const { Cell::new(None) }; AVAILABLE_ENTRIES],
+                pmpcfg: [const { Cell::new(None) }; AVAILABLE_ENTRIES],
+            })
         }
     }
 
     impl<const AVAILABLE_ENTRIES: usize, const MPU_REGIONS: usize> TORUserPMP<MPU_REGIONS>
         for SimplePMP<AVAILABLE_ENTRIES>
     {
-        // Ensure that the MPU_REGIONS (starting at entry, and occupying two
-        // entries per region) don't overflow the available entires.
-        const CONST_ASSERT_CHECK: () = assert!(MPU_REGIONS <= (AVAILABLE_ENTRIES / 2));
+        // Ensure that the MPU_REGIONS (occupying at least one entry per
+        // region once packed) don't overflow the available entires.
+        const CONST_ASSERT_CHECK: () = assert!(MPU_REGIONS <= AVAILABLE_ENTRIES);
 
         fn available_regions(&self) -> usize {
             // Always assume to have `MPU_REGIONS` usable TOR regions. We don't
@@ -1215,78 +1571,65 @@ pub mod simple {
             MPU_REGIONS
         }
 
+        fn packed_entries(&self) -> Option<usize> {
+            Some(AVAILABLE_ENTRIES)
+        }
+
         // This implementation is specific for 32-bit systems. We use
-        // `u32::from_be_bytes` and then cast to usize, as it manages to compile
+        // `u32::from_le_bytes` and then cast to usize, as it manages to compile
         // on 64-bit systems as well. However, this implementation will not work
         // on RV64I systems, due to the changed pmpcfgX CSR layout.
+        //
+        // The regions are sorted by address and packed, and then each pmpaddrX
+        // and pmpcfgX CSR is written if it does not already hold the value
+        // needed.
         fn configure_pmp(
             &self,
             regions: &[(TORUserPMPCFG, *const u8, *const u8); MPU_REGIONS],
         ) -> Result<(), ()> {
-            // Could use `iter_array_chunks` once that's stable.
-            let mut regions_iter = regions.iter();
-            let mut i = 0;
-
-            while let Some(even_region) = regions_iter.next() {
-                let odd_region_opt = regions_iter.next();
-
-                if let Some(odd_region) = odd_region_opt {
-                    // We can configure two regions at once which, given that we
-                    // start at index 0 (an even offset), translates to a single
-                    // CSR write for the pmpcfgX register:
-                    csr::CSR.pmpconfig_set(
-                        i / 2,
-                        u32::from_be_bytes([
-                            odd_region.0.get(),
-                            TORUserPMPCFG::OFF.get(),
-                            even_region.0.get(),
-                            TORUserPMPCFG::OFF.get(),
-                        ]) as usize,
-                    );
+            let mut pmpcfg = [TORUserPMPCFG::OFF.get(); AVAILABLE_ENTRIES];
+            let mut pmpaddr = [0; AVAILABLE_ENTRIES];
+            let entries = super::pack_tor_regions(regions, |i, cfg, addr| {
+                if i < AVAILABLE_ENTRIES {
+                    pmpcfg[i] = cfg.get();
+                    pmpaddr[i] = addr;
+                }
+            });
+            if entries > AVAILABLE_ENTRIES {
+                return Err(());
+            }
 
-                    // Now, set the addresses of the respective regions, if they
-                    // are enabled, respectively:
-                    if even_region.0 != TORUserPMPCFG::OFF {
-                        csr::CSR
-                            .pmpaddr_set(i * 2 + 0, (even_region.1 as usize).overflowing_shr(2).0);
-                        csr::CSR
-                            .pmpaddr_set(i * 2 + 1, (even_region.2 as usize).overflowing_shr(2).0);
-                    }
+            for (i, addr) in pmpaddr.iter().enumerate().take(entries) {
+                if self.pmpaddr[i].get() != Some(*addr) {
+                    csr::CSR.pmpaddr_set(i, *addr);
+                    self.pmpaddr[i].set(Some(*addr));
+                }
+            }
 
-                    if odd_region.0 != TORUserPMPCFG::OFF {
-                        csr::CSR
-                            .pmpaddr_set(i * 2 + 2, (odd_region.1 as usize).overflowing_shr(2).0);
-                        csr::CSR
-                            .pmpaddr_set(i * 2 + 3, (odd_region.2 as usize).overflowing_shr(2).0);
-                    }
+            // The regions can never use more than two entries each, so the
+            // pmpcfgX octets of the entries above are left turned off.
+            let usable_entries = cmp::min(AVAILABLE_ENTRIES, 2 * MPU_REGIONS);
+            for (i, octets) in pmpcfg[..usable_entries].chunks(4).enumerate() {
+                let mut bytes = [TORUserPMPCFG::OFF.get(); 4];
+                bytes[..octets.len()].copy_from_slice(octets);
+                let value = u32::from_le_bytes(bytes) as usize;
+                if self.pmpcfg[i].get() == Some(value) {
+                    continue;
+                }
+                self.pmpcfg[i].set(Some(value));
 
-                    i += 2;
+                if octets.len() == 4 {
+                    csr::CSR.pmpconfig_set(i, value);
                 } else {
-                    // TODO: check overhead of code
-                    // Modify the first two pmpcfgX octets for this region:
+                    // Only modify the octets of the usable entries:
                     csr::CSR.pmpconfig_modify(
-                        i / 2,
+                        i,
                         FieldValue::<usize, csr::pmpconfig::pmpcfg::Register>::new(
-                            0x0000FFFF,
+                            (1 << (octets.len() * 8)) - 1,
                             0,
-                            u32::from_be_bytes([
-                                0,
-                                0,
-                                even_region.0.get(),
-                                TORUserPMPCFG::OFF.get(),
-                            ]) as usize,
+                            value,
                         ),
                     );
-
-                    // Set the addresses if the region is enabled:
-                    if even_region.0 != TORUserPMPCFG::OFF {
-                        csr::CSR
-                            .pmpaddr_set(i * 2 + 0, (even_region.1 as usize).overflowing_shr(2).0);
-                        csr::CSR
-                            .pmpaddr_set(i * 2 + 1, (even_region.2 as usize).overflowing_shr(2).0);
-                    }
-
-                    i += 1;
                 }
             }
 
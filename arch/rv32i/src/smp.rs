@@ -0,0 +1,72 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Boot of the secondary harts of multi-core chips.
+//!
+//! All harts execute `_start` on reset. Hart 0 then initializes memory and
+//! runs `main()`, while the other harts (the secondary harts) wait. Once the
+//! board is ready, it starts them with [`start_secondary_harts`]. Each of them
+//! then calls the entry function, on its own stack.
+//!
+//! The secondary harts must configure their own trap handler and PMP, since
+//! these are per-hart CSRs.
+
+use core::ptr::addr_of_mut;
+use core::sync::atomic::{fence, Ordering};
+
+/// Value of `SecondaryHartBoot::magic` once the other fields are valid. The
+/// parameters are read before memory is initialized, so they must not be
+/// trusted on a zero value only.
+pub(crate) const SECONDARY_HART_BOOT_MAGIC: u32 = 0x534d_5021;
+
+/// Boot parameters of the secondary harts. The offsets of the fields are
+/// used by `_start`. The stack of hart `n` ends at `stack_base + n *
+/// stack_size`.
+#[repr(C)]
+pub(crate) struct SecondaryHartBoot {
+    magic: u32,
+    entry: usize,
+    stack_base: usize,
+    stack_size: usize,
+    /// Harts above this one have no stack, and stay parked.
+    last_hart: usize,
+}
+
+pub(crate) static mut SECONDARY_HART_BOOT: SecondaryHartBoot = SecondaryHartBoot {
+    magic: 0,
+    entry: 0,
+    stack_base: 0,
+    stack_size: 0,
+    last_hart: 0,
+};
+
+/// Start the secondary harts: each of them calls `entry` with its hart ID.
+///
+/// `stacks` holds one stack of `stack_size` bytes for each secondary hart, in
+/// order: hart 1 uses the first one. Both `stacks` and `stack_size` must be
+/// 16-byte aligned. Harts that do not have a stack in `stacks` are not
+/// started.
+///
+/// Everything `entry` uses must be initialized before this is called.
+pub unsafe fn start_secondary_harts(
+    entry: unsafe extern "C" fn(hart: usize) -> !,
+    stacks: &'static mut [u8],
+    stack_size: usize,
+) {
+    let boot = addr_of_mut!(SECONDARY_HART_BOOT);
+    core::ptr::write_volatile(addr_of_mut!((*boot).entry), entry as usize);
+    core::ptr::write_volatile(
+        addr_of_mut!((*boot).stack_base),
+        stacks.as_mut_ptr() as usize,
+    );
+    core::ptr::write_volatile(addr_of_mut!((*boot).stack_size), stack_size);
+    core::ptr::write_volatile(
+        addr_of_mut!((*boot).last_hart),
+        stacks.len() / stack_size.max(1),
+    );
+    // Make the parameters, and anything that `entry` uses, visible to the
+    // other harts before they see `magic`.
+    fence(Ordering::SeqCst);
+    core::ptr::write_volatile(addr_of_mut!((*boot).magic), SECONDARY_HART_BOOT_MAGIC);
+}
@@ -15,7 +15,7 @@
 use core::ptr::addr_of;
 use core::ptr::addr_of_mut;
 
-use capsules_core::virtualizers::virtual_aes_ccm::MuxAES128CCM;
+use capsules_system::retained_state::{RetainedLayout, RetainedState};
 
 use kernel::capabilities;
 use kernel::component::Component;
@@ -23,11 +23,10 @@ use kernel::hil;
 use kernel::hil::buzzer::Buzzer;
 use kernel::hil::i2c::I2CMaster;
 use kernel::hil::led::LedHigh;
-use kernel::hil::symmetric_encryption::AES128;
 use kernel::hil::time::Alarm;
-use kernel::hil::time::Counter;
 use kernel::hil::usb::Client;
 use kernel::platform::chip::Chip;
+use kernel::platform::peripherals::{Peripheral, PeripheralKind};
 use kernel::platform::{KernelResources, SyscallDriverLookup};
 use kernel::scheduler::round_robin::RoundRobinSched;
 #[allow(unused_imports)]
@@ -125,6 +124,12 @@ static mut CDC_REF_FOR_PANIC: Option<
 > = None;
 static mut NRF52_POWER: Option<&'static nrf52840::power::Power> = None;
 
+// GPREGRET holds the value telling the bootloader to stay in its update mode.
+const RETAINED_LAYOUT: RetainedLayout = RetainedLayout {
+    bootloader_handshake: Some(0),
+    ..RetainedLayout::NONE
+};
+
 /// Dummy buffer that causes the linker to reserve enough space for the stack.
 #[no_mangle]
 #[link_section = ".stack_buffer"]
@@ -135,9 +140,11 @@ fn baud_rate_reset_bootloader_enter() {
     unsafe {
         // 0x4e is the magic value the Adafruit nRF52 Bootloader expects
         // as defined by https://github.com/adafruit/Adafruit_nRF52_Bootloader/blob/master/src/main.c
-        NRF52_POWER.unwrap().set_gpregret(0x90);
+        let _ = RetainedState::new(NRF52_POWER.unwrap(), RETAINED_LAYOUT)
+            .set_bootloader_handshake(0x90);
         // uncomment to use with Adafruit nRF52 Bootloader
-        // NRF52_POWER.unwrap().set_gpregret(0x4e);
+        // let _ = RetainedState::new(NRF52_POWER.unwrap(), RETAINED_LAYOUT)
+        //     .set_bootloader_handshake(0x4e);
         cortexm4::scb::reset();
     }
 }
@@ -148,24 +155,28 @@ type SHT3xSensor = components::sht3x::SHT3xComponentType<
 >;
 type TemperatureDriver = components::temperature::TemperatureComponentType<SHT3xSensor>;
 type HumidityDriver = components::humidity::HumidityComponentType<SHT3xSensor>;
-type RngDriver = components::rng::RngComponentType<nrf52840::trng::Trng<'static>>;
 
-type Ieee802154Driver = components::ieee802154::Ieee802154ComponentType<
-    nrf52840::ieee802154_radio::Radio<'static>,
-    nrf52840::aes::AesECB<'static>,
->;
+/// Optional subsystems of the board, for portable applications.
+static PERIPHERALS: [Peripheral; 4] = [
+    Peripheral::screen(capsules_extra::screen::DRIVER_NUM, 240, 240),
+    Peripheral::new(
+        PeripheralKind::Buzzer,
+        capsules_extra::buzzer_driver::DRIVER_NUM,
+    ),
+    Peripheral::new(
+        PeripheralKind::BleRadio,
+        capsules_extra::ble_advertising_driver::DRIVER_NUM,
+    ),
+    Peripheral::new(
+        PeripheralKind::Ieee802154Radio,
+        capsules_extra::ieee802154::DRIVER_NUM,
+    ),
+];
 
 /// Supported drivers by the platform
 pub struct Platform {
-    ble_radio: &'static capsules_extra::ble_advertising_driver::BLE<
-        'static,
-        nrf52::ble_radio::Radio<'static>,
-        capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<
-            'static,
-            nrf52::rtc::Rtc<'static>,
-        >,
-    >,
-    ieee802154_radio: &'static Ieee802154Driver,
+    ble_radio: &'static nrf52840_platform::radio::BleDriver,
+    ieee802154_radio: &'static nrf52840_platform::radio::Ieee802154Driver,
     console: &'static capsules_core::console::Console<'static>,
     proximity: &'static capsules_extra::proximity::ProximitySensor<'static>,
     gpio: &'static capsules_core::gpio::GPIO<'static, nrf52::gpio::GPIOPin<'static>>,
@@ -176,15 +187,9 @@ pub struct Platform {
     >,
     button: &'static capsules_core::button::Button<'static, nrf52::gpio::GPIOPin<'static>>,
     screen: &'static capsules_extra::screen::Screen<'static>,
-    rng: &'static RngDriver,
+    rng: &'static nrf52840_platform::crypto::RngDriver,
     ipc: kernel::ipc::IPC<{ NUM_PROCS as u8 }>,
-    alarm: &'static capsules_core::alarm::AlarmDriver<
-        'static,
-        capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<
-            'static,
-            nrf52::rtc::Rtc<'static>,
-        >,
-    >,
+    alarm: &'static nrf52840_platform::alarm::AlarmDriver,
     buzzer: &'static capsules_extra::buzzer_driver::Buzzer<
         'static,
         capsules_extra::buzzer_pwm::PwmBuzzer<
@@ -199,6 +204,7 @@ pub struct Platform {
     adc: &'static capsules_core::adc::AdcVirtualized<'static>,
     temperature: &'static TemperatureDriver,
     humidity: &'static HumidityDriver,
+    peripherals: &'static components::peripherals::PeripheralsComponentType,
     scheduler: &'static RoundRobinSched<'static>,
     systick: cortexm4::systick::SysTick,
 }
@@ -224,6 +230,7 @@ impl SyscallDriverLookup for Platform {
             kernel::ipc::DRIVER_NUM => f(Some(&self.ipc)),
             capsules_extra::temperature::DRIVER_NUM => f(Some(self.temperature)),
             capsules_extra::humidity::DRIVER_NUM => f(Some(self.humidity)),
+            capsules_extra::peripherals::DRIVER_NUM => f(Some(self.peripherals)),
             _ => f(None),
         }
     }
@@ -392,17 +399,8 @@ unsafe fn start() -> (
     // ALARM & TIMER
     //--------------------------------------------------------------------------
 
-    let rtc = &base_peripherals.rtc;
-    let _ = rtc.start();
-
-    let mux_alarm = components::alarm::AlarmMuxComponent::new(rtc)
-        .finalize(components::alarm_mux_component_static!(nrf52::rtc::Rtc));
-    let alarm = components::alarm::AlarmDriverComponent::new(
-        board_kernel,
-        capsules_core::alarm::DRIVER_NUM,
-        mux_alarm,
-    )
-    .finalize(components::alarm_component_static!(nrf52::rtc::Rtc));
+    let nrf52840_platform::alarm::Alarm { mux_alarm, alarm } =
+        nrf52840_platform::alarm::setup(board_kernel, &base_peripherals.rtc);
 
     //--------------------------------------------------------------------------
     // PWM & BUZZER
@@ -505,31 +503,19 @@ unsafe fn start() -> (
     ));
     CDC_REF_FOR_PANIC = Some(cdc); //for use by panic handler
 
-    // Create a shared UART channel for the console and for kernel debug.
-    let uart_mux = components::console::UartMuxComponent::new(cdc, 115200)
-        .finalize(components::uart_mux_component_static!());
-
-    // Setup the console.
-    let console = components::console::ConsoleComponent::new(
-        board_kernel,
-        capsules_core::console::DRIVER_NUM,
-        uart_mux,
-    )
-    .finalize(components::console_component_static!());
-    // Create the debugger object that handles calls to `debug!()`.
-    components::debug_writer::DebugWriterComponent::new(uart_mux)
-        .finalize(components::debug_writer_component_static!());
+    // Share the CDC-ACM channel between the console and kernel debug.
+    let console = nrf52840_platform::console::setup(board_kernel, cdc, 115200);
+    PROCESS_PRINTER = Some(console.process_printer);
 
     //--------------------------------------------------------------------------
-    // RANDOM NUMBERS
+    // RANDOM NUMBERS & AES
     //--------------------------------------------------------------------------
 
-    let rng = components::rng::RngComponent::new(
+    let crypto = nrf52840_platform::crypto::setup(
         board_kernel,
-        capsules_core::rng::DRIVER_NUM,
         &base_peripherals.trng,
-    )
-    .finalize(components::rng_component_static!(nrf52840::trng::Trng));
+        &base_peripherals.ecb,
+    );
 
     //--------------------------------------------------------------------------
     // ADC
@@ -698,56 +684,20 @@ unsafe fn start() -> (
     // WIRELESS
     //--------------------------------------------------------------------------
 
-    let ble_radio = components::ble::BLEComponent::new(
+    let radio = nrf52840_platform::radio::setup(
         board_kernel,
-        capsules_extra::ble_advertising_driver::DRIVER_NUM,
         &base_peripherals.ble_radio,
+        &nrf52840_peripherals.ieee802154_radio,
+        crypto.aes_mux,
         mux_alarm,
-    )
-    .finalize(components::ble_component_static!(
-        nrf52840::rtc::Rtc,
-        nrf52840::ble_radio::Radio
-    ));
-
-    let aes_mux = static_init!(
-        MuxAES128CCM<'static, nrf52840::aes::AesECB>,
-        MuxAES128CCM::new(&base_peripherals.ecb,)
+        nrf52840_platform::radio::Ieee802154Addresses::from_device_id(PAN_ID),
     );
-    kernel::deferred_call::DeferredCallClient::register(aes_mux);
-    base_peripherals.ecb.set_client(aes_mux);
-
-    let device_id = (*addr_of!(nrf52840::ficr::FICR_INSTANCE)).id();
 
-    let device_id_bottom_16 = u16::from_le_bytes([device_id[0], device_id[1]]);
+    let peripherals = components::peripherals::PeripheralsComponent::new(&PERIPHERALS)
+        .finalize(components::peripherals_component_static!());
 
-    let (ieee802154_radio, _mux_mac) = components::ieee802154::Ieee802154Component::new(
-        board_kernel,
-        capsules_extra::ieee802154::DRIVER_NUM,
-        &nrf52840_peripherals.ieee802154_radio,
-        aes_mux,
-        PAN_ID,
-        device_id_bottom_16,
-        device_id,
-    )
-    .finalize(components::ieee802154_component_static!(
-        nrf52840::ieee802154_radio::Radio,
-        nrf52840::aes::AesECB<'static>
-    ));
-
-    let process_printer = components::process_printer::ProcessPrinterTextComponent::new()
-        .finalize(components::process_printer_text_component_static!());
-    PROCESS_PRINTER = Some(process_printer);
-
-    let pconsole = components::process_console::ProcessConsoleComponent::new(
-        board_kernel,
-        uart_mux,
-        mux_alarm,
-        process_printer,
-        Some(cortexm4::support::reset),
-    )
-    .finalize(components::process_console_component_static!(
-        nrf52840::rtc::Rtc
-    ));
+    let pconsole =
+        nrf52840_platform::console::setup_process_console(board_kernel, &console, mux_alarm);
     let _ = pconsole.start();
 
     //--------------------------------------------------------------------------
@@ -762,16 +712,16 @@ unsafe fn start() -> (
         .finalize(components::round_robin_component_static!(NUM_PROCS));
 
     let platform = Platform {
-        ble_radio,
-        ieee802154_radio,
-        console,
+        ble_radio: radio.ble,
+        ieee802154_radio: radio.ieee802154,
+        console: console.console,
         proximity,
         led,
         gpio,
         adc: adc_syscall,
         screen,
         button,
-        rng,
+        rng: crypto.rng,
         buzzer,
         alarm,
         ipc: kernel::ipc::IPC::new(
@@ -781,6 +731,7 @@ unsafe fn start() -> (
         ),
         temperature,
         humidity,
+        peripherals,
         scheduler,
         systick: cortexm4::systick::SysTick::new_with_calibration(64000000),
     };
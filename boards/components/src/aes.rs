@@ -23,6 +23,14 @@
 //!     >
 //! ));
 //! ```
+//!
+//! On chips without an AES peripheral, the software implementation can be
+//! used instead:
+//!
+//! ```rust
+//! let aes = components::aes::Aes128SoftwareComponent::new()
+//!     .finalize(components::aes128_software_component_static!());
+//! ```
 
 use core::mem::MaybeUninit;
 use kernel::capabilities;
@@ -158,3 +166,37 @@ impl<
         aes_driver
     }
 }
+
+#[macro_export]
+macro_rules! aes128_software_component_static {
+    ($(,)?) => {{
+        kernel::static_buf!(
+            capsules_extra::symmetric_encryption::aes_software::Aes128Software<'static>
+        )
+    };};
+}
+
+pub type Aes128SoftwareComponentType =
+    capsules_extra::symmetric_encryption::aes_software::Aes128Software<'static>;
+
+pub struct Aes128SoftwareComponent {}
+
+impl Aes128SoftwareComponent {
+    pub fn new() -> Aes128SoftwareComponent {
+        Aes128SoftwareComponent {}
+    }
+}
+
+impl Component for Aes128SoftwareComponent {
+    type StaticInput = &'static mut MaybeUninit<Aes128SoftwareComponentType>;
+    type Output = &'static Aes128SoftwareComponentType;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let aes =
+            s.write(capsules_extra::symmetric_encryption::aes_software::Aes128Software::new());
+
+        kernel::deferred_call::DeferredCallClient::register(aes);
+
+        aes
+    }
+}
@@ -0,0 +1,82 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the app loader syscall driver.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let app_loader = components::app_loader::AppLoaderComponent::new(
+//!     board_kernel,
+//!     capsules_extra::app_loader::DRIVER_NUM,
+//!     dynamic_loader,
+//!     policy,
+//! )
+//! .finalize(components::app_loader_component_static!());
+//! ```
+
+use capsules_extra::app_loader::{AppLoader, InstallPolicy};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::process::DynamicProcessLoading;
+
+#[macro_export]
+macro_rules! app_loader_component_static {
+    () => {{
+        let buffer = kernel::static_buf!([u8; capsules_extra::app_loader::BUF_LEN]);
+        let app_loader = kernel::static_buf!(capsules_extra::app_loader::AppLoader<'static>);
+        (buffer, app_loader)
+    };};
+}
+
+pub type AppLoaderComponentType = AppLoader<'static>;
+
+pub struct AppLoaderComponent {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    loader: &'static dyn DynamicProcessLoading<'static>,
+    policy: &'static dyn InstallPolicy,
+}
+
+impl AppLoaderComponent {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        loader: &'static dyn DynamicProcessLoading<'static>,
+        policy: &'static dyn InstallPolicy,
+    ) -> AppLoaderComponent {
+        AppLoaderComponent {
+            board_kernel,
+            driver_num,
+            loader,
+            policy,
+        }
+    }
+}
+
+impl Component for AppLoaderComponent {
+    type StaticInput = (
+        &'static mut MaybeUninit<[u8; capsules_extra::app_loader::BUF_LEN]>,
+        &'static mut MaybeUninit<AppLoader<'static>>,
+    );
+    type Output = &'static AppLoader<'static>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let buffer = s.0.write([0; capsules_extra::app_loader::BUF_LEN]);
+
+        let app_loader = s.1.write(AppLoader::new(
+            self.loader,
+            self.policy,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+            buffer,
+        ));
+        self.loader.set_client(app_loader);
+
+        app_loader
+    }
+}
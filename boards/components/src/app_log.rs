@@ -0,0 +1,73 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the AppLog driver, which writes leveled log records from
+//! processes to the kernel debug output.
+//!
+//! The kernel debug writer must be set up before the records are printed.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let app_log = components::app_log::AppLogComponent::new(
+//!     board_kernel,
+//!     capsules_extra::app_log::DRIVER_NUM,
+//!     capsules_extra::app_log::Level::Info,
+//! )
+//! .finalize(components::app_log_component_static!());
+//! ```
+
+use capsules_extra::app_log::{AppLog, Level};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+
+#[macro_export]
+macro_rules! app_log_component_static {
+    () => {{
+        kernel::static_buf!(capsules_extra::app_log::AppLog<$crate::app_log::Capability>)
+    };};
+}
+
+pub type AppLogComponentType = AppLog<Capability>;
+
+pub struct Capability;
+unsafe impl capabilities::ProcessManagementCapability for Capability {}
+
+pub struct AppLogComponent {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    default_level: Level,
+}
+
+impl AppLogComponent {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        default_level: Level,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            default_level,
+        }
+    }
+}
+
+impl Component for AppLogComponent {
+    type StaticInput = &'static mut MaybeUninit<AppLog<Capability>>;
+    type Output = &'static AppLog<Capability>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        s.write(AppLog::new(
+            self.board_kernel,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+            self.default_level,
+            Capability,
+        ))
+    }
+}
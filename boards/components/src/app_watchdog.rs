@@ -0,0 +1,95 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the watchdog of processes.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let app_watchdog = components::app_watchdog::AppWatchdogComponent::new(
+//!     board_kernel,
+//!     capsules_extra::app_watchdog::DRIVER_NUM,
+//!     mux_alarm,
+//! )
+//! .finalize(components::app_watchdog_component_static!(
+//!     nrf52840::rtc::Rtc<'static>
+//! ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::app_watchdog::AppWatchdog;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::time::Alarm;
+
+#[macro_export]
+macro_rules! app_watchdog_component_static {
+    ($A:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let app_watchdog = kernel::static_buf!(
+            capsules_extra::app_watchdog::AppWatchdog<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                $crate::app_watchdog::Capability,
+            >
+        );
+
+        (alarm, app_watchdog)
+    };};
+}
+
+pub struct Capability;
+unsafe impl capabilities::ProcessManagementCapability for Capability {}
+
+pub type AppWatchdogComponentType<A> =
+    AppWatchdog<'static, VirtualMuxAlarm<'static, A>, Capability>;
+
+pub struct AppWatchdogComponent<A: 'static + Alarm<'static>> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+}
+
+impl<A: 'static + Alarm<'static>> AppWatchdogComponent<A> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            alarm_mux,
+        }
+    }
+}
+
+impl<A: 'static + Alarm<'static>> Component for AppWatchdogComponent<A> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<AppWatchdog<'static, VirtualMuxAlarm<'static, A>, Capability>>,
+    );
+    type Output = &'static AppWatchdog<'static, VirtualMuxAlarm<'static, A>, Capability>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let app_watchdog = static_buffer.1.write(AppWatchdog::new(
+            alarm,
+            self.board_kernel,
+            Capability,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        alarm.set_alarm_client(app_watchdog);
+
+        app_watchdog
+    }
+}
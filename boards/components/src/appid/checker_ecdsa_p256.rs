@@ -0,0 +1,101 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the ECDSA P-256 credential checker, with software SHA-256
+//! and signature verification.
+//!
+//! The public key is the `x` and `y` coordinates of the public point, each 32
+//! bytes big endian, and is usually a constant in the board crate. Boards with
+//! a hardware SHA-256 engine or signature verifier can use
+//! [`AppCheckerSignatureComponent`](crate::appid::checker_signature::AppCheckerSignatureComponent)
+//! with the `EcdsaNistP256` credential type instead.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let checking_policy =
+//!     components::appid::checker_ecdsa_p256::AppCheckerEcdsaP256SoftwareComponent::new(
+//!         &PUBLIC_KEY,
+//!     )
+//!     .finalize(components::app_checker_ecdsa_p256_software_component_static!());
+//! ```
+
+use capsules_extra::public_key_crypto::ecdsa_p256::EcdsaP256SoftwareVerifier;
+use capsules_extra::sha256::Sha256Software;
+use capsules_system::process_checker::ecdsa_p256::{AppCheckerEcdsaP256, HASH_LEN, SIGNATURE_LEN};
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::deferred_call::DeferredCallClient;
+use kernel::hil::{digest, public_key_crypto};
+
+#[macro_export]
+macro_rules! app_checker_ecdsa_p256_software_component_static {
+    () => {{
+        let sha = kernel::static_buf!(capsules_extra::sha256::Sha256Software<'static>);
+        let verifier = kernel::static_buf!(
+            capsules_extra::public_key_crypto::ecdsa_p256::EcdsaP256SoftwareVerifier<'static>
+        );
+        let hash_buffer =
+            kernel::static_buf!([u8; capsules_system::process_checker::ecdsa_p256::HASH_LEN]);
+        let signature_buffer =
+            kernel::static_buf!([u8; capsules_system::process_checker::ecdsa_p256::SIGNATURE_LEN]);
+        let checker = kernel::static_buf!(
+            capsules_system::process_checker::ecdsa_p256::AppCheckerEcdsaP256<
+                'static,
+                capsules_extra::public_key_crypto::ecdsa_p256::EcdsaP256SoftwareVerifier<'static>,
+                capsules_extra::sha256::Sha256Software<'static>,
+            >
+        );
+
+        (sha, verifier, hash_buffer, signature_buffer, checker)
+    };};
+}
+
+pub type AppCheckerEcdsaP256SoftwareComponentType =
+    AppCheckerEcdsaP256<'static, EcdsaP256SoftwareVerifier<'static>, Sha256Software<'static>>;
+
+pub struct AppCheckerEcdsaP256SoftwareComponent {
+    public_key: &'static [u8; 64],
+}
+
+impl AppCheckerEcdsaP256SoftwareComponent {
+    pub fn new(public_key: &'static [u8; 64]) -> Self {
+        Self { public_key }
+    }
+}
+
+impl Component for AppCheckerEcdsaP256SoftwareComponent {
+    type StaticInput = (
+        &'static mut MaybeUninit<Sha256Software<'static>>,
+        &'static mut MaybeUninit<EcdsaP256SoftwareVerifier<'static>>,
+        &'static mut MaybeUninit<[u8; HASH_LEN]>,
+        &'static mut MaybeUninit<[u8; SIGNATURE_LEN]>,
+        &'static mut MaybeUninit<AppCheckerEcdsaP256SoftwareComponentType>,
+    );
+
+    type Output = &'static AppCheckerEcdsaP256SoftwareComponentType;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let sha = s.0.write(Sha256Software::new());
+        sha.register();
+
+        let verifier = s.1.write(EcdsaP256SoftwareVerifier::new(self.public_key));
+        verifier.register();
+
+        let hash_buffer = s.2.write([0; HASH_LEN]);
+        let signature_buffer = s.3.write([0; SIGNATURE_LEN]);
+
+        let checker = s.4.write(AppCheckerEcdsaP256::new_ecdsa_p256(
+            sha,
+            verifier,
+            hash_buffer,
+            signature_buffer,
+        ));
+
+        digest::Digest::set_client(sha, checker);
+        public_key_crypto::signature::SignatureVerify::set_verify_client(verifier, checker);
+
+        checker
+    }
+}
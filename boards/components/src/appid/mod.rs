@@ -5,6 +5,7 @@
 pub mod assigner_name;
 pub mod assigner_tbf;
 pub mod checker;
+pub mod checker_ecdsa_p256;
 pub mod checker_null;
 pub mod checker_sha;
 pub mod checker_signature;
@@ -0,0 +1,134 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the attestation report driver.
+//!
+//! The report buffer is sized for the `MAX_APPS` argument of the static
+//! macro; a report for more applications fails with `SIZE`. The digest engine must not be shared with
+//! another user, such as the kernel integrity check.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! // Without a crypto accelerator, the signer can be in software.
+//! let signer = components::ecdsa_p256_signer::EcdsaP256SoftwareSignerComponent::new(
+//!     &PRIVATE_KEY,
+//! )
+//! .finalize(components::ecdsa_p256_software_signer_component_static!());
+//! let attestation = components::attestation::AttestationComponent::new(
+//!     board_kernel,
+//!     capsules_extra::attestation::DRIVER_NUM,
+//!     sha,
+//!     signer,
+//!     integrity,
+//!     retained.count_boot().unwrap_or(0),
+//! )
+//! .finalize(components::attestation_component_static!(
+//!     capsules_extra::sha256::Sha256Software<'static>,
+//!     capsules_extra::public_key_crypto::ecdsa_p256::EcdsaP256SoftwareSigner<'static>,
+//!     4
+//! ));
+//! ```
+
+use capsules_extra::attestation::{Attestation, HASH_LEN, SIGNATURE_LEN};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::digest::{DigestDataHash, Sha256};
+use kernel::hil::public_key_crypto::signature::SignatureSign;
+use kernel::platform::self_test::KernelIntegrity;
+
+#[macro_export]
+macro_rules! attestation_component_static {
+    ($D:ty, $S:ty, $MAX_APPS:expr $(,)?) => {{
+        use capsules_extra::attestation::{report_len, Attestation, HASH_LEN, SIGNATURE_LEN};
+        let attestation =
+            kernel::static_buf!(Attestation<'static, $D, $S, $crate::attestation::Capability>);
+        let report = kernel::static_buf!([u8; report_len($MAX_APPS)]);
+        let hash = kernel::static_buf!([u8; HASH_LEN]);
+        let signature = kernel::static_buf!([u8; SIGNATURE_LEN]);
+
+        (attestation, report, hash, signature)
+    };};
+}
+
+pub struct Capability;
+unsafe impl capabilities::ProcessManagementCapability for Capability {}
+
+pub type AttestationComponentType<D, S> = Attestation<'static, D, S, Capability>;
+
+pub struct AttestationComponent<
+    D: 'static + DigestDataHash<'static, HASH_LEN> + Sha256,
+    S: 'static + SignatureSign<'static, HASH_LEN, SIGNATURE_LEN>,
+    const REPORT_LEN: usize,
+> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    digest: &'static D,
+    signer: &'static S,
+    integrity: &'static dyn KernelIntegrity,
+    boot_count: u32,
+}
+
+impl<
+        D: 'static + DigestDataHash<'static, HASH_LEN> + Sha256,
+        S: 'static + SignatureSign<'static, HASH_LEN, SIGNATURE_LEN>,
+        const REPORT_LEN: usize,
+    > AttestationComponent<D, S, REPORT_LEN>
+{
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        digest: &'static D,
+        signer: &'static S,
+        integrity: &'static dyn KernelIntegrity,
+        boot_count: u32,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            digest,
+            signer,
+            integrity,
+            boot_count,
+        }
+    }
+}
+
+impl<
+        D: 'static + DigestDataHash<'static, HASH_LEN> + Sha256,
+        S: 'static + SignatureSign<'static, HASH_LEN, SIGNATURE_LEN>,
+        const REPORT_LEN: usize,
+    > Component for AttestationComponent<D, S, REPORT_LEN>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<Attestation<'static, D, S, Capability>>,
+        &'static mut MaybeUninit<[u8; REPORT_LEN]>,
+        &'static mut MaybeUninit<[u8; HASH_LEN]>,
+        &'static mut MaybeUninit<[u8; SIGNATURE_LEN]>,
+    );
+    type Output = &'static Attestation<'static, D, S, Capability>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let attestation = s.0.write(Attestation::new(
+            self.board_kernel,
+            Capability,
+            self.digest,
+            self.signer,
+            self.integrity,
+            self.boot_count,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+            s.1.write([0; REPORT_LEN]),
+            s.2.write([0; HASH_LEN]),
+            s.3.write([0; SIGNATURE_LEN]),
+        ));
+        DigestDataHash::set_client(self.digest, attestation);
+        self.signer.set_sign_client(attestation);
+
+        attestation
+    }
+}
@@ -0,0 +1,100 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the kernel benchmarks.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let cycle_counter = static_init!(
+//!     rv32i::cycle_counter::McycleCounter,
+//!     rv32i::cycle_counter::McycleCounter::new()
+//! );
+//! let benchmark = components::benchmark::BenchmarkComponent::new(
+//!     board_kernel,
+//!     capsules_extra::benchmark::DRIVER_NUM,
+//!     mux_alarm,
+//!     cycle_counter,
+//! )
+//! .finalize(components::benchmark_component_static!(
+//!     qemu_rv32_virt_chip::chip::QemuRv32VirtClint,
+//!     rv32i::cycle_counter::McycleCounter
+//! ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::benchmark::Benchmark;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::hw_debug::CycleCounter;
+use kernel::hil::time::Alarm;
+
+#[macro_export]
+macro_rules! benchmark_component_static {
+    ($A:ty, $C:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let benchmark = kernel::static_buf!(
+            capsules_extra::benchmark::Benchmark<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                $C,
+            >
+        );
+
+        (alarm, benchmark)
+    };};
+}
+
+pub struct BenchmarkComponent<A: 'static + Alarm<'static>, C: 'static + CycleCounter> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    counter: &'static C,
+}
+
+impl<A: 'static + Alarm<'static>, C: 'static + CycleCounter> BenchmarkComponent<A, C> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        counter: &'static C,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            alarm_mux,
+            counter,
+        }
+    }
+}
+
+impl<A: 'static + Alarm<'static>, C: 'static + CycleCounter> Component
+    for BenchmarkComponent<A, C>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<Benchmark<'static, VirtualMuxAlarm<'static, A>, C>>,
+    );
+    type Output = &'static Benchmark<'static, VirtualMuxAlarm<'static, A>, C>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let benchmark = static_buffer.1.write(Benchmark::new(
+            alarm,
+            self.counter,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        alarm.set_alarm_client(benchmark);
+
+        benchmark
+    }
+}
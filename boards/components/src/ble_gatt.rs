@@ -0,0 +1,109 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for a Bluetooth Low Energy peripheral with a GATT server.
+//!
+//! The peripheral needs the radio for itself, so it cannot be used with the
+//! BLE advertising driver.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let ble_peripheral = components::ble_gatt::BlePeripheralComponent::new(
+//!     &base_peripherals.ble_radio,
+//!     mux_alarm,
+//!     [0x01, 0x02, 0x03, 0x04, 0x05, 0xc6],
+//!     b"Tock",
+//!     &ATTRIBUTES,
+//! )
+//! .finalize(components::ble_peripheral_component_static!(
+//!     nrf52840::ble_radio::Radio,
+//!     nrf52840::rtc::Rtc<'static>
+//! ));
+//! ble_peripheral.start();
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::ble_gatt::{Attribute, BlePeripheral, BUFFER_LEN};
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::ble_connection::BleConnectionRadio;
+use kernel::hil::time::Alarm;
+
+#[macro_export]
+macro_rules! ble_peripheral_component_static {
+    ($R:ty, $A:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let peripheral = kernel::static_buf!(
+            capsules_extra::ble_gatt::BlePeripheral<
+                'static,
+                $R,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+        let buffer = kernel::static_buf!([u8; capsules_extra::ble_gatt::BUFFER_LEN]);
+        (alarm, peripheral, buffer)
+    };};
+}
+
+pub type BlePeripheralComponentType<R, A> = BlePeripheral<'static, R, VirtualMuxAlarm<'static, A>>;
+
+pub struct BlePeripheralComponent<
+    R: BleConnectionRadio<'static> + 'static,
+    A: Alarm<'static> + 'static,
+> {
+    radio: &'static R,
+    mux_alarm: &'static MuxAlarm<'static, A>,
+    address: [u8; 6],
+    name: &'static [u8],
+    attributes: &'static [Attribute<'static>],
+}
+
+impl<R: BleConnectionRadio<'static>, A: Alarm<'static>> BlePeripheralComponent<R, A> {
+    pub fn new(
+        radio: &'static R,
+        mux_alarm: &'static MuxAlarm<'static, A>,
+        address: [u8; 6],
+        name: &'static [u8],
+        attributes: &'static [Attribute<'static>],
+    ) -> Self {
+        Self {
+            radio,
+            mux_alarm,
+            address,
+            name,
+            attributes,
+        }
+    }
+}
+
+impl<R: BleConnectionRadio<'static>, A: Alarm<'static>> Component for BlePeripheralComponent<R, A> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<BlePeripheral<'static, R, VirtualMuxAlarm<'static, A>>>,
+        &'static mut MaybeUninit<[u8; BUFFER_LEN]>,
+    );
+    type Output = &'static BlePeripheral<'static, R, VirtualMuxAlarm<'static, A>>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.mux_alarm));
+        alarm.setup();
+        let buffer = static_buffer.2.write([0; BUFFER_LEN]);
+
+        let peripheral = static_buffer.1.write(BlePeripheral::new(
+            self.radio,
+            alarm,
+            self.address,
+            self.name,
+            self.attributes,
+            buffer,
+        ));
+        self.radio.set_connection_client(peripheral);
+        alarm.set_alarm_client(peripheral);
+
+        peripheral
+    }
+}
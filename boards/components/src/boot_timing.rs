@@ -0,0 +1,54 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for measuring the boot time of a board.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let dwt = static_init!(cortexm4::dwt::Dwt, cortexm4::dwt::Dwt::new());
+//! let boot = components::boot_timing::BootTimerComponent::new(dwt, 64_000_000, None)
+//!     .finalize(components::boot_timer_component_static!(cortexm4::dwt::Dwt, 16));
+//! ```
+
+use capsules_system::boot_timing::BootTimer;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::hw_debug::CycleCounter;
+
+#[macro_export]
+macro_rules! boot_timer_component_static {
+    ($C:ty, $N:expr $(,)?) => {{
+        kernel::static_buf!(capsules_system::boot_timing::BootTimer<'static, $C, $N>)
+    };};
+}
+
+pub struct BootTimerComponent<C: 'static + CycleCounter, const N: usize> {
+    counter: &'static C,
+    frequency_hz: u32,
+    budget_us: Option<u32>,
+}
+
+impl<C: 'static + CycleCounter, const N: usize> BootTimerComponent<C, N> {
+    pub fn new(counter: &'static C, frequency_hz: u32, budget_us: Option<u32>) -> Self {
+        Self {
+            counter,
+            frequency_hz,
+            budget_us,
+        }
+    }
+}
+
+impl<C: 'static + CycleCounter, const N: usize> Component for BootTimerComponent<C, N> {
+    type StaticInput = &'static mut MaybeUninit<BootTimer<'static, C, N>>;
+    type Output = &'static BootTimer<'static, C, N>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        static_buffer.write(BootTimer::new(
+            self.counter,
+            self.frequency_hz,
+            self.budget_us,
+        ))
+    }
+}
@@ -0,0 +1,169 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for an IPv6 border router between a 6LoWPAN mesh and Ethernet.
+//!
+//! The border router is a user of the virtual MAC with its own 6LoWPAN
+//! state, next to the UDP stack.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let border_router = components::border_router::BorderRouterComponent::new(
+//!     mux_mac,
+//!     mux_alarm,
+//!     src_mac_from_serial_num,
+//!     ethernet,
+//!     [0x02, 0x00, 0x00, 0x00, 0x00, 0x01],
+//!     [0xfd, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01],
+//! )
+//! .finalize(components::border_router_component_static!(
+//!     nrf52840::rtc::Rtc<'static>,
+//!     capsules_extra::ieee802154::framer::Framer<'static, AwakeMac, AesCcm>,
+//! ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::ieee802154::device::MacDevice;
+use capsules_extra::ieee802154::virtual_mac::{MacUser, MuxMac};
+use capsules_extra::net::ieee802154::MacAddress;
+use capsules_extra::net::ipv6::border_router::BorderRouter;
+use capsules_extra::net::sixlowpan::sixlowpan_compression;
+use capsules_extra::net::sixlowpan::sixlowpan_state::{
+    RxState, Sixlowpan, SixlowpanState, TxState,
+};
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::ethernet::EthernetAdapterDatapath;
+use kernel::hil::radio;
+use kernel::hil::time::Alarm;
+
+/// Largest IPv6 datagram forwarded.
+pub const DATAGRAM_LEN: usize = 1280;
+/// Ethernet header and the largest IPv6 datagram forwarded.
+pub const ETHERNET_FRAME_LEN: usize = 14 + DATAGRAM_LEN;
+
+#[macro_export]
+macro_rules! border_router_component_static {
+    ($A:ty, $M:ty $(,)?) => {{
+        use capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm;
+        use capsules_extra::net::sixlowpan::{sixlowpan_compression, sixlowpan_state};
+        use components::border_router::{DATAGRAM_LEN, ETHERNET_FRAME_LEN};
+
+        let alarm = kernel::static_buf!(VirtualMuxAlarm<'static, $A>);
+        let mac_user =
+            kernel::static_buf!(capsules_extra::ieee802154::virtual_mac::MacUser<'static, $M>);
+        let sixlowpan = kernel::static_buf!(
+            sixlowpan_state::Sixlowpan<
+                'static,
+                VirtualMuxAlarm<'static, $A>,
+                sixlowpan_compression::Context,
+            >
+        );
+        let rx_state = kernel::static_buf!(sixlowpan_state::RxState<'static>);
+        let border_router =
+            kernel::static_buf!(capsules_extra::net::ipv6::border_router::BorderRouter<'static>);
+        let rx_buf = kernel::static_buf!([u8; DATAGRAM_LEN]);
+        let datagram = kernel::static_buf!([u8; DATAGRAM_LEN]);
+        let radio_buf = kernel::static_buf!([u8; kernel::hil::radio::MAX_BUF_SIZE]);
+        let ethernet_buf = kernel::static_buf!([u8; ETHERNET_FRAME_LEN]);
+
+        (
+            alarm,
+            mac_user,
+            sixlowpan,
+            rx_state,
+            border_router,
+            rx_buf,
+            datagram,
+            radio_buf,
+            ethernet_buf,
+        )
+    };};
+}
+
+pub struct BorderRouterComponent<A: Alarm<'static> + 'static, M: MacDevice<'static> + 'static> {
+    mux_mac: &'static MuxMac<'static, M>,
+    mux_alarm: &'static MuxAlarm<'static, A>,
+    src_mac_addr: MacAddress,
+    ethernet: &'static dyn EthernetAdapterDatapath<'static>,
+    ethernet_mac: [u8; 6],
+    prefix: [u8; 8],
+}
+
+impl<A: Alarm<'static>, M: MacDevice<'static>> BorderRouterComponent<A, M> {
+    pub fn new(
+        mux_mac: &'static MuxMac<'static, M>,
+        mux_alarm: &'static MuxAlarm<'static, A>,
+        src_mac_addr: MacAddress,
+        ethernet: &'static dyn EthernetAdapterDatapath<'static>,
+        ethernet_mac: [u8; 6],
+        prefix: [u8; 8],
+    ) -> Self {
+        Self {
+            mux_mac,
+            mux_alarm,
+            src_mac_addr,
+            ethernet,
+            ethernet_mac,
+            prefix,
+        }
+    }
+}
+
+impl<A: Alarm<'static>, M: MacDevice<'static>> Component for BorderRouterComponent<A, M> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<MacUser<'static, M>>,
+        &'static mut MaybeUninit<
+            Sixlowpan<'static, VirtualMuxAlarm<'static, A>, sixlowpan_compression::Context>,
+        >,
+        &'static mut MaybeUninit<RxState<'static>>,
+        &'static mut MaybeUninit<BorderRouter<'static>>,
+        &'static mut MaybeUninit<[u8; DATAGRAM_LEN]>,
+        &'static mut MaybeUninit<[u8; DATAGRAM_LEN]>,
+        &'static mut MaybeUninit<[u8; radio::MAX_BUF_SIZE]>,
+        &'static mut MaybeUninit<[u8; ETHERNET_FRAME_LEN]>,
+    );
+    type Output = &'static BorderRouter<'static>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let alarm = s.0.write(VirtualMuxAlarm::new(self.mux_alarm));
+        alarm.setup();
+
+        let mac_user = s.1.write(MacUser::new(self.mux_mac));
+        self.mux_mac.add_user(mac_user);
+
+        let sixlowpan = s.2.write(Sixlowpan::new(
+            sixlowpan_compression::Context {
+                prefix: [0; 16],
+                prefix_len: 0,
+                id: 0,
+                compress: false,
+            },
+            alarm, // Only used to get the time, not to set alarms.
+        ));
+        let rx_state = s.3.write(RxState::new(s.5.write([0; DATAGRAM_LEN])));
+        sixlowpan.add_rx_state(rx_state);
+        mac_user.set_receive_client(sixlowpan);
+
+        let border_router = s.4.write(BorderRouter::new(
+            mac_user,
+            TxState::new(sixlowpan),
+            self.src_mac_addr,
+            self.ethernet,
+            self.ethernet_mac,
+            self.prefix,
+            s.6.write([0; DATAGRAM_LEN]),
+            s.7.write([0; radio::MAX_BUF_SIZE]),
+            s.8.write([0; ETHERNET_FRAME_LEN]),
+        ));
+        mac_user.set_transmit_client(border_router);
+        sixlowpan.set_rx_client(border_router);
+        self.ethernet.set_client(border_router);
+        self.ethernet.enable_receive();
+
+        border_router
+    }
+}
@@ -0,0 +1,93 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the configuration service.
+//!
+//! The service needs its own `KVPermissions` user, typically a virtual user
+//! of the KV permissions mux.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let config_kv = components::kv::VirtualKVPermissionsComponent::new(mux_kv).finalize(
+//!     components::virtual_kv_permissions_component_static!(
+//!         capsules_extra::kv_store_permissions::KVStorePermissions<
+//!             capsules_extra::tickv_kv_store::TicKVKVStore<...>,
+//!         >
+//!     ),
+//! );
+//! let config = components::config_service::ConfigServiceComponent::new(
+//!     config_kv,
+//!     board_kernel,
+//!     capsules_extra::config_service::DRIVER_NUM,
+//! )
+//! .finalize(components::config_service_component_static!(
+//!     capsules_extra::virtual_kv::VirtualKVPermissions<...>
+//! ));
+//! ```
+
+use capsules_extra::config_service::{ConfigService, KEY_LEN};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil;
+
+/// Length of the value buffer, which bounds the length of the records.
+pub const VALUE_BUFFER_LEN: usize = 256;
+
+#[macro_export]
+macro_rules! config_service_component_static {
+    ($V:ty $(,)?) => {{
+        let config =
+            kernel::static_buf!(capsules_extra::config_service::ConfigService<'static, $V>);
+        let key_buffer = kernel::static_buf!([u8; capsules_extra::config_service::KEY_LEN]);
+        let value_buffer = kernel::static_buf!([u8; $crate::config_service::VALUE_BUFFER_LEN]);
+
+        (config, key_buffer, value_buffer)
+    };};
+}
+
+pub type ConfigServiceComponentType<V> = ConfigService<'static, V>;
+
+pub struct ConfigServiceComponent<V: hil::kv::KVPermissions<'static> + 'static> {
+    kv: &'static V,
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+}
+
+impl<V: hil::kv::KVPermissions<'static>> ConfigServiceComponent<V> {
+    pub fn new(kv: &'static V, board_kernel: &'static kernel::Kernel, driver_num: usize) -> Self {
+        Self {
+            kv,
+            board_kernel,
+            driver_num,
+        }
+    }
+}
+
+impl<V: hil::kv::KVPermissions<'static>> Component for ConfigServiceComponent<V> {
+    type StaticInput = (
+        &'static mut MaybeUninit<ConfigService<'static, V>>,
+        &'static mut MaybeUninit<[u8; KEY_LEN]>,
+        &'static mut MaybeUninit<[u8; VALUE_BUFFER_LEN]>,
+    );
+    type Output = &'static ConfigService<'static, V>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let key_buffer = static_buffer.1.write([0; KEY_LEN]);
+        let value_buffer = static_buffer.2.write([0; VALUE_BUFFER_LEN]);
+
+        let config = static_buffer.0.write(ConfigService::new(
+            self.kv,
+            key_buffer,
+            value_buffer,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        self.kv.set_client(config);
+        config
+    }
+}
@@ -32,6 +32,34 @@
 //! let console = ConsoleComponent::new(board_kernel, uart_mux)
 //!    .finalize(console_component_static!());
 //! ```
+//!
+//! A board with consoles over several transports creates a `ConsoleComponent`
+//! for each, with the driver numbers in
+//! `capsules_core::console::ADDITIONAL_DRIVER_NUMS` for the consoles after
+//! the first one:
+//!
+//! ```rust
+//! let cdc_mux = UartMuxComponent::new(cdc, 115200)
+//!     .finalize(components::uart_mux_component_static!());
+//! let host_console = ConsoleComponent::new(
+//!     board_kernel,
+//!     capsules_core::console::ADDITIONAL_DRIVER_NUMS[0],
+//!     cdc_mux,
+//! )
+//! .finalize(console_component_static!());
+//! ```
+//!
+//! Kernel debug output can then be routed to one of the transports at runtime
+//! with a `DebugRouter` (see the `debug_router` component).
+//!
+//! One application can be allowed to reconfigure the UART of a console, for
+//! example to change its baud rate:
+//!
+//! ```rust
+//! let console = ConsoleComponent::new(board_kernel, capsules_core::console::DRIVER_NUM, uart_mux)
+//!     .with_configure_owner(ShortId::Fixed(core::num::NonZeroU32::new(0x1234).unwrap()))
+//!     .finalize(console_component_static!());
+//! ```
 // Author: Philip Levis <pal@cs.stanford.edu>
 // Last modified: 1/08/2023
 
@@ -47,6 +75,7 @@ use kernel::create_capability;
 use kernel::hil;
 use kernel::hil::time::{self, Alarm};
 use kernel::hil::uart;
+use kernel::process::ShortId;
 
 use capsules_core::console::DEFAULT_BUF_SIZE;
 
@@ -128,6 +157,7 @@ pub struct ConsoleComponent<const RX_BUF_LEN: usize, const TX_BUF_LEN: usize> {
     board_kernel: &'static kernel::Kernel,
     driver_num: usize,
     uart_mux: &'static MuxUart<'static>,
+    configure_owner: Option<ShortId>,
 }
 
 impl<const RX_BUF_LEN: usize, const TX_BUF_LEN: usize> ConsoleComponent<RX_BUF_LEN, TX_BUF_LEN> {
@@ -140,8 +170,15 @@ impl<const RX_BUF_LEN: usize, const TX_BUF_LEN: usize> ConsoleComponent<RX_BUF_L
             board_kernel,
             driver_num,
             uart_mux,
+            configure_owner: None,
         }
     }
+
+    /// Let the application with `ShortId` `owner` reconfigure the UART.
+    pub fn with_configure_owner(mut self, owner: ShortId) -> Self {
+        self.configure_owner = Some(owner);
+        self
+    }
 }
 
 impl<const RX_BUF_LEN: usize, const TX_BUF_LEN: usize> Component
@@ -173,6 +210,9 @@ impl<const RX_BUF_LEN: usize, const TX_BUF_LEN: usize> Component
         ));
         hil::uart::Transmit::set_transmit_client(console_uart, console);
         hil::uart::Receive::set_receive_client(console_uart, console);
+        if let Some(owner) = self.configure_owner {
+            console.set_configure(console_uart, owner);
+        }
 
         console
     }
@@ -0,0 +1,119 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for date and time kept in software.
+//!
+//! The software date time needs its own `KVPermissions` user, typically a
+//! virtual user of the KV permissions mux. It is given to processes with the
+//! `DateTimeComponent`.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let software_date_time = components::date_time_software::SoftwareDateTimeComponent::new(
+//!     mux_alarm,
+//!     date_time_kv,
+//! )
+//! .finalize(components::date_time_software_component_static!(
+//!     nrf52840::rtc::Rtc<'static>,
+//!     capsules_extra::virtual_kv::VirtualKVPermissions<...>
+//! ));
+//! let date_time = components::date_time::DateTimeComponent::new(
+//!     board_kernel,
+//!     capsules_extra::date_time::DRIVER_NUM,
+//!     software_date_time,
+//! )
+//! .finalize(components::date_time_component_static!(
+//!     components::date_time_software::SoftwareDateTimeComponentType<...>
+//! ));
+//! let _ = software_date_time.load();
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::date_time_software::{SoftwareDateTime, KEY_LEN, VALUE_LEN};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::deferred_call::DeferredCallClient;
+use kernel::hil;
+use kernel::hil::time::Alarm;
+use kernel::storage_permissions::StoragePermissions;
+
+/// Length of the value buffer, with room for the KV header.
+pub const VALUE_BUFFER_LEN: usize = VALUE_LEN + 16;
+
+#[macro_export]
+macro_rules! date_time_software_component_static {
+    ($A:ty, $V:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let date_time = kernel::static_buf!(
+            capsules_extra::date_time_software::SoftwareDateTime<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                $V,
+            >
+        );
+        let key_buffer = kernel::static_buf!([u8; capsules_extra::date_time_software::KEY_LEN]);
+        let value_buffer = kernel::static_buf!([u8; $crate::date_time_software::VALUE_BUFFER_LEN]);
+
+        (alarm, date_time, key_buffer, value_buffer)
+    };};
+}
+
+pub type SoftwareDateTimeComponentType<A, V> =
+    SoftwareDateTime<'static, VirtualMuxAlarm<'static, A>, V>;
+
+pub struct SoftwareDateTimeComponent<
+    A: 'static + Alarm<'static>,
+    V: hil::kv::KVPermissions<'static> + 'static,
+> {
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    kv: &'static V,
+}
+
+impl<A: 'static + Alarm<'static>, V: hil::kv::KVPermissions<'static>>
+    SoftwareDateTimeComponent<A, V>
+{
+    pub fn new(alarm_mux: &'static MuxAlarm<'static, A>, kv: &'static V) -> Self {
+        Self { alarm_mux, kv }
+    }
+}
+
+impl<A: 'static + Alarm<'static>, V: hil::kv::KVPermissions<'static>> Component
+    for SoftwareDateTimeComponent<A, V>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<SoftwareDateTime<'static, VirtualMuxAlarm<'static, A>, V>>,
+        &'static mut MaybeUninit<[u8; KEY_LEN]>,
+        &'static mut MaybeUninit<[u8; VALUE_BUFFER_LEN]>,
+    );
+    type Output = &'static SoftwareDateTime<'static, VirtualMuxAlarm<'static, A>, V>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let storage_cap = create_capability!(capabilities::KerneluserStorageCapability);
+
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let key_buffer = static_buffer.2.write([0; KEY_LEN]);
+        let value_buffer = static_buffer.3.write([0; VALUE_BUFFER_LEN]);
+
+        let date_time = static_buffer.1.write(SoftwareDateTime::new(
+            alarm,
+            self.kv,
+            StoragePermissions::new_kernel(&storage_cap),
+            key_buffer,
+            value_buffer,
+        ));
+        alarm.set_alarm_client(date_time);
+        self.kv.set_client(date_time);
+        date_time.register();
+
+        date_time
+    }
+}
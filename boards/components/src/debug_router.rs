@@ -0,0 +1,76 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for DebugRouter, which sends kernel debug output to one of
+//! several UART muxes.
+//!
+//! Each mux gets a transmit-only virtual UART for the debug output. Use the
+//! router with `DebugWriterTransmitComponent`.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let router = components::debug_router::DebugRouterComponent::new([uart_mux, cdc_mux])
+//!     .finalize(components::debug_router_component_static!(2));
+//! components::debug_writer::DebugWriterTransmitComponent::new(router)
+//!     .finalize(components::debug_writer_transmit_component_static!());
+//! ```
+
+use capsules_core::virtualizers::virtual_uart::{MuxUart, UartDevice};
+use capsules_extra::debug_router::DebugRouter;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil;
+use kernel::hil::uart;
+
+#[macro_export]
+macro_rules! debug_router_component_static {
+    ($N:expr $(,)?) => {{
+        let uarts = kernel::static_buf!(
+            [capsules_core::virtualizers::virtual_uart::UartDevice<'static>; $N]
+        );
+        let outputs = kernel::static_buf!([&'static dyn kernel::hil::uart::Transmit<'static>; $N]);
+        let router = kernel::static_buf!(capsules_extra::debug_router::DebugRouter<'static>);
+
+        (uarts, outputs, router)
+    };};
+}
+
+pub struct DebugRouterComponent<const N: usize> {
+    uart_muxes: [&'static MuxUart<'static>; N],
+}
+
+impl<const N: usize> DebugRouterComponent<N> {
+    pub fn new(uart_muxes: [&'static MuxUart<'static>; N]) -> Self {
+        Self { uart_muxes }
+    }
+}
+
+impl<const N: usize> Component for DebugRouterComponent<N> {
+    type StaticInput = (
+        &'static mut MaybeUninit<[UartDevice<'static>; N]>,
+        &'static mut MaybeUninit<[&'static dyn uart::Transmit<'static>; N]>,
+        &'static mut MaybeUninit<DebugRouter<'static>>,
+    );
+    type Output = &'static DebugRouter<'static>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        // Create a transmit-only virtual device on each mux.
+        let uarts: &'static [UartDevice<'static>; N] = s.0.write(core::array::from_fn(|i| {
+            UartDevice::new(self.uart_muxes[i], false)
+        }));
+        for uart in uarts.iter() {
+            uart.setup();
+        }
+        let outputs = s.1.write(core::array::from_fn(|i| {
+            &uarts[i] as &'static dyn uart::Transmit<'static>
+        }));
+
+        let router = s.2.write(DebugRouter::new(outputs));
+        for uart in uarts.iter() {
+            hil::uart::Transmit::set_transmit_client(uart, router);
+        }
+        router
+    }
+}
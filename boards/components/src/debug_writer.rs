@@ -6,7 +6,9 @@
 //!
 //! This provides components for attaching the kernel debug output (for panic!,
 //! print!, debug!, etc.) to the output. `DebugWriterComponent` uses a UART mux,
-//! and `DebugWriterNoMuxComponent` just uses a UART interface directly.
+//! `DebugWriterNoMuxComponent` just uses a UART interface directly, and
+//! `DebugWriterTransmitComponent` uses any transmitter, such as a
+//! `DebugRouter` choosing between several consoles.
 //!
 //! Usage
 //! -----
@@ -17,6 +19,9 @@
 //!     &nrf52::uart::UARTE0,
 //! )
 //! .finalize(());
+//!
+//! components::debug_writer::DebugWriterTransmitComponent::new(debug_router)
+//!     .finalize(components::debug_writer_transmit_component_static!());
 //! ```
 
 // Author: Brad Campbell <bradjc@virginia.edu>
@@ -83,6 +88,19 @@ macro_rules! debug_writer_no_mux_component_static {
     };};
 }
 
+/// The optional argument to this macro allows boards to specify the size of the in-RAM
+/// buffer used for storing debug messages.
+#[macro_export]
+macro_rules! debug_writer_transmit_component_static {
+    ($BUF_SIZE_KB:expr) => {{
+        $crate::debug_writer_no_mux_component_static!($BUF_SIZE_KB)
+    };};
+    () => {{
+        use $crate::debug_writer::DEFAULT_DEBUG_BUFFER_KBYTE;
+        $crate::debug_writer_no_mux_component_static!(DEFAULT_DEBUG_BUFFER_KBYTE)
+    };};
+}
+
 pub struct DebugWriterComponent<const BUF_SIZE_BYTES: usize> {
     uart_mux: &'static MuxUart<'static>,
     marker: core::marker::PhantomData<[u8; BUF_SIZE_BYTES]>,
@@ -190,3 +208,45 @@ impl<U: uart::Uart<'static> + uart::Transmit<'static> + 'static, const BUF_SIZE_
         });
     }
 }
+
+pub struct DebugWriterTransmitComponent<const BUF_SIZE_BYTES: usize> {
+    transmit: &'static dyn uart::Transmit<'static>,
+    marker: core::marker::PhantomData<[u8; BUF_SIZE_BYTES]>,
+}
+
+impl<const BUF_SIZE_BYTES: usize> DebugWriterTransmitComponent<BUF_SIZE_BYTES> {
+    pub fn new(transmit: &'static dyn uart::Transmit<'static>) -> Self {
+        Self {
+            transmit,
+            marker: core::marker::PhantomData,
+        }
+    }
+}
+
+impl<const BUF_SIZE_BYTES: usize> Component for DebugWriterTransmitComponent<BUF_SIZE_BYTES> {
+    type StaticInput = (
+        &'static mut MaybeUninit<RingBuffer<'static, u8>>,
+        &'static mut MaybeUninit<[u8; BUF_SIZE_BYTES]>,
+        &'static mut MaybeUninit<kernel::debug::DebugWriter>,
+        &'static mut MaybeUninit<kernel::debug::DebugWriterWrapper>,
+    );
+    type Output = ();
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let buf = s.1.write([0; BUF_SIZE_BYTES]);
+        let (output_buf, internal_buf) = buf.split_at_mut(DEBUG_BUFFER_SPLIT);
+
+        let ring_buffer = s.0.write(RingBuffer::new(internal_buf));
+        let debugger = s.2.write(kernel::debug::DebugWriter::new(
+            self.transmit,
+            output_buf,
+            ring_buffer,
+        ));
+        self.transmit.set_transmit_client(debugger);
+
+        let debug_wrapper = s.3.write(kernel::debug::DebugWriterWrapper::new(debugger));
+        unsafe {
+            kernel::debug::set_debug_writer_wrapper(debug_wrapper);
+        }
+    }
+}
@@ -0,0 +1,81 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for running initialization after the kernel loop starts.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let deferred_init = components::deferred_init::DeferredInitComponent::new(
+//!     mux_alarm,
+//!     deferred_init_tasks,
+//!     10,
+//! )
+//! .finalize(components::deferred_init_component_static!(nrf52840::rtc::Rtc));
+//! deferred_init.start();
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_system::deferred_init::{DeferredInit, DeferredInitClient};
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::time::Alarm;
+
+#[macro_export]
+macro_rules! deferred_init_component_static {
+    ($A:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let deferred_init = kernel::static_buf!(
+            capsules_system::deferred_init::DeferredInit<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+
+        (alarm, deferred_init)
+    };};
+}
+
+pub struct DeferredInitComponent<A: 'static + Alarm<'static>> {
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    tasks: &'static [&'static dyn DeferredInitClient],
+    interval_ms: u32,
+}
+
+impl<A: 'static + Alarm<'static>> DeferredInitComponent<A> {
+    pub fn new(
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        tasks: &'static [&'static dyn DeferredInitClient],
+        interval_ms: u32,
+    ) -> Self {
+        Self {
+            alarm_mux,
+            tasks,
+            interval_ms,
+        }
+    }
+}
+
+impl<A: 'static + Alarm<'static>> Component for DeferredInitComponent<A> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<DeferredInit<'static, VirtualMuxAlarm<'static, A>>>,
+    );
+    type Output = &'static DeferredInit<'static, VirtualMuxAlarm<'static, A>>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let deferred_init =
+            static_buffer
+                .1
+                .write(DeferredInit::new(alarm, self.tasks, self.interval_ms));
+        alarm.set_alarm_client(deferred_init);
+
+        deferred_init
+    }
+}
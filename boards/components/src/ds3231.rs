@@ -0,0 +1,92 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the DS3231 real time clock.
+//!
+//! The RTC is both a `DateTime` and a `DateTimeAlarm`.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let ds3231 = components::ds3231::Ds3231Component::new(mux_i2c, 0x68, rtc_int_pin)
+//!     .finalize(components::ds3231_component_static!(nrf52840::i2c::TWI));
+//! let date_time = components::date_time::DateTimeComponent::new(
+//!     board_kernel,
+//!     capsules_extra::date_time::DRIVER_NUM,
+//!     ds3231,
+//! )
+//! .finalize(components::date_time_component_static!(
+//!     components::ds3231::Ds3231ComponentType<nrf52840::i2c::TWI>
+//! ));
+//! ```
+
+use capsules_core::virtualizers::virtual_i2c::{I2CDevice, MuxI2C};
+use capsules_extra::ds3231::{Ds3231, BUFFER_LEN};
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::gpio;
+use kernel::hil::i2c;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! ds3231_component_static {
+    ($I:ty $(,)?) => {{
+        let i2c_device =
+            kernel::static_buf!(capsules_core::virtualizers::virtual_i2c::I2CDevice<$I>);
+        let buffer = kernel::static_buf!([u8; capsules_extra::ds3231::BUFFER_LEN]);
+        let ds3231 = kernel::static_buf!(
+            capsules_extra::ds3231::Ds3231<
+                'static,
+                capsules_core::virtualizers::virtual_i2c::I2CDevice<$I>,
+            >
+        );
+
+        (i2c_device, buffer, ds3231)
+    };};
+}
+
+pub type Ds3231ComponentType<I> = Ds3231<'static, I2CDevice<'static, I>>;
+
+pub struct Ds3231Component<I: 'static + i2c::I2CMaster<'static>> {
+    i2c_mux: &'static MuxI2C<'static, I>,
+    i2c_address: u8,
+    interrupt_pin: &'static dyn gpio::InterruptPin<'static>,
+}
+
+impl<I: 'static + i2c::I2CMaster<'static>> Ds3231Component<I> {
+    pub fn new(
+        i2c_mux: &'static MuxI2C<'static, I>,
+        i2c_address: u8,
+        interrupt_pin: &'static dyn gpio::InterruptPin<'static>,
+    ) -> Self {
+        Ds3231Component {
+            i2c_mux,
+            i2c_address,
+            interrupt_pin,
+        }
+    }
+}
+
+impl<I: 'static + i2c::I2CMaster<'static>> Component for Ds3231Component<I> {
+    type StaticInput = (
+        &'static mut MaybeUninit<I2CDevice<'static, I>>,
+        &'static mut MaybeUninit<[u8; BUFFER_LEN]>,
+        &'static mut MaybeUninit<Ds3231<'static, I2CDevice<'static, I>>>,
+    );
+    type Output = &'static Ds3231<'static, I2CDevice<'static, I>>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let ds3231_i2c = static_buffer
+            .0
+            .write(I2CDevice::new(self.i2c_mux, self.i2c_address));
+        let buffer = static_buffer.1.write([0; BUFFER_LEN]);
+        let ds3231 = static_buffer
+            .2
+            .write(Ds3231::new(ds3231_i2c, self.interrupt_pin, buffer));
+
+        ds3231_i2c.set_client(ds3231);
+        self.interrupt_pin.set_client(ds3231);
+        ds3231
+    }
+}
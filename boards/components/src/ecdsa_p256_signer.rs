@@ -0,0 +1,60 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the software ECDSA P-256 signer.
+//!
+//! The private key is the 32 bytes big endian scalar, usually provisioned in a
+//! flash region outside of the kernel.
+//!
+//! Signing runs in constant time on cores with constant time multiplication,
+//! so the signer can be given to capsules that userspace can make sign, such
+//! as the attestation driver. On cores where the time of a multiplication
+//! depends on its operands, like the Cortex-M3, the time to sign can leak
+//! information about the nonce and the key.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let signer = components::ecdsa_p256_signer::EcdsaP256SoftwareSignerComponent::new(
+//!     &PRIVATE_KEY,
+//! )
+//! .finalize(components::ecdsa_p256_software_signer_component_static!());
+//! ```
+
+use capsules_extra::public_key_crypto::ecdsa_p256::EcdsaP256SoftwareSigner;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::deferred_call::DeferredCallClient;
+
+#[macro_export]
+macro_rules! ecdsa_p256_software_signer_component_static {
+    () => {{
+        kernel::static_buf!(
+            capsules_extra::public_key_crypto::ecdsa_p256::EcdsaP256SoftwareSigner<'static>
+        )
+    };};
+}
+
+pub struct EcdsaP256SoftwareSignerComponent {
+    private_key: &'static [u8; 32],
+}
+
+impl EcdsaP256SoftwareSignerComponent {
+    pub fn new(private_key: &'static [u8; 32]) -> Self {
+        Self { private_key }
+    }
+}
+
+impl Component for EcdsaP256SoftwareSignerComponent {
+    type StaticInput = &'static mut MaybeUninit<EcdsaP256SoftwareSigner<'static>>;
+
+    type Output = &'static EcdsaP256SoftwareSigner<'static>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let signer = s.write(EcdsaP256SoftwareSigner::new(self.private_key));
+        signer.register();
+
+        signer
+    }
+}
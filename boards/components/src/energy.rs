@@ -0,0 +1,102 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for per process energy accounting.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let energy = components::energy::EnergyAccountantComponent::new(
+//!     board_kernel,
+//!     capsules_extra::energy::DRIVER_NUM,
+//!     mux_alarm,
+//!     &ENERGY_MODEL,
+//!     None,
+//! )
+//! .finalize(components::energy_accountant_component_static!(
+//!     nrf52840::rtc::Rtc<'static>
+//! ));
+//! pconsole.set_energy(energy);
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::energy::{EnergyAccountant, EnergyModel};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::time::Alarm;
+
+#[macro_export]
+macro_rules! energy_accountant_component_static {
+    ($A:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let accountant = kernel::static_buf!(
+            capsules_extra::energy::EnergyAccountant<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+
+        (alarm, accountant)
+    };};
+}
+
+pub type EnergyAccountantComponentType<A> = EnergyAccountant<'static, VirtualMuxAlarm<'static, A>>;
+
+pub struct EnergyAccountantComponent<A: 'static + Alarm<'static>> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    model: &'static EnergyModel,
+    budget_uj: Option<u64>,
+}
+
+impl<A: 'static + Alarm<'static>> EnergyAccountantComponent<A> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        model: &'static EnergyModel,
+        budget_uj: Option<u64>,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            alarm_mux,
+            model,
+            budget_uj,
+        }
+    }
+}
+
+impl<A: 'static + Alarm<'static>> Component for EnergyAccountantComponent<A> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<EnergyAccountant<'static, VirtualMuxAlarm<'static, A>>>,
+    );
+    type Output = &'static EnergyAccountant<'static, VirtualMuxAlarm<'static, A>>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+        let process_management_cap = create_capability!(capabilities::ProcessManagementCapability);
+
+        let energy_alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        energy_alarm.setup();
+
+        let accountant = static_buffer.1.write(EnergyAccountant::new(
+            energy_alarm,
+            self.model,
+            self.budget_uj,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        energy_alarm.set_alarm_client(accountant);
+        self.board_kernel
+            .set_energy_monitor(accountant, &process_management_cap);
+
+        accountant
+    }
+}
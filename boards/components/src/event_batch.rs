@@ -0,0 +1,115 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for batching input events.
+//!
+//! The queue of events must be placed in the `.retained` section by the board,
+//! so that it keeps its events across resets. Comparators are connected by the
+//! board, by setting the event batch as their client.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! #[link_section = ".retained"]
+//! static mut HMI_EVENTS: RetainedEvents<32> = RetainedEvents::new();
+//!
+//! let buttons = static_init!(
+//!     [(&'static dyn InterruptValuePin<'static>, ActivationMode); 1],
+//!     [(button_pin, ActivationMode::ActiveLow)]
+//! );
+//! let event_batch = components::event_batch::EventBatchComponent::new(
+//!     board_kernel,
+//!     capsules_extra::event_batch::DRIVER_NUM,
+//!     mux_alarm,
+//!     buttons,
+//!     unsafe { &mut *addr_of_mut!(HMI_EVENTS) },
+//! )
+//! .finalize(components::event_batch_component_static!(nrf52840::rtc::Rtc<'static>, 32));
+//! AnalogComparator::set_client(&base_peripherals.lpcomp, event_batch);
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::event_batch::{EventBatch, RetainedEvents};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::gpio::{ActivationMode, InterruptValuePin};
+use kernel::hil::time::Alarm;
+
+#[macro_export]
+macro_rules! event_batch_component_static {
+    ($A:ty, $N:expr $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let event_batch = kernel::static_buf!(
+            capsules_extra::event_batch::EventBatch<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                $N,
+            >
+        );
+
+        (alarm, event_batch)
+    };};
+}
+
+pub type EventBatchComponentType<A, const N: usize> =
+    EventBatch<'static, VirtualMuxAlarm<'static, A>, N>;
+
+pub struct EventBatchComponent<A: 'static + Alarm<'static>, const N: usize> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    buttons: &'static [(&'static dyn InterruptValuePin<'static>, ActivationMode)],
+    events: &'static mut RetainedEvents<N>,
+}
+
+impl<A: 'static + Alarm<'static>, const N: usize> EventBatchComponent<A, N> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        buttons: &'static [(&'static dyn InterruptValuePin<'static>, ActivationMode)],
+        events: &'static mut RetainedEvents<N>,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            alarm_mux,
+            buttons,
+            events,
+        }
+    }
+}
+
+impl<A: 'static + Alarm<'static>, const N: usize> Component for EventBatchComponent<A, N> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<EventBatch<'static, VirtualMuxAlarm<'static, A>, N>>,
+    );
+    type Output = &'static EventBatch<'static, VirtualMuxAlarm<'static, A>, N>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let event_batch = static_buffer.1.write(EventBatch::new(
+            alarm,
+            self.buttons,
+            self.events,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        alarm.set_alarm_client(event_batch);
+        for &(pin, _) in self.buttons.iter() {
+            pin.set_client(event_batch);
+        }
+        event_batch.start();
+
+        event_batch
+    }
+}
@@ -0,0 +1,131 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Components for UARTs reserved for applications.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let gps = components::exclusive_uart::ExclusiveUartPortComponent::new(
+//!     &base_peripherals.uarte0,
+//!     kernel::process::ShortId::Fixed(core::num::NonZeroU32::new(0x1234).unwrap()),
+//!     gps_parameters,
+//! )
+//! .finalize(components::exclusive_uart_port_component_static!());
+//! let ports = static_init!([&'static ExclusiveUartPort<'static>; 1], [gps]);
+//! let exclusive_uart = components::exclusive_uart::ExclusiveUartComponent::new(
+//!     board_kernel,
+//!     capsules_extra::exclusive_uart::DRIVER_NUM,
+//!     ports,
+//! )
+//! .finalize(components::exclusive_uart_component_static!());
+//! ```
+
+use capsules_extra::exclusive_uart::{ExclusiveUart, ExclusiveUartPort, DEFAULT_BUF_SIZE};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::uart;
+use kernel::process::ShortId;
+
+#[macro_export]
+macro_rules! exclusive_uart_port_component_static {
+    () => {{
+        let port = kernel::static_buf!(capsules_extra::exclusive_uart::ExclusiveUartPort<'static>);
+        let tx_buffer = kernel::static_buf!([u8; capsules_extra::exclusive_uart::DEFAULT_BUF_SIZE]);
+        let rx_buffer = kernel::static_buf!([u8; capsules_extra::exclusive_uart::DEFAULT_BUF_SIZE]);
+
+        (port, tx_buffer, rx_buffer)
+    };};
+}
+
+#[macro_export]
+macro_rules! exclusive_uart_component_static {
+    () => {{
+        kernel::static_buf!(capsules_extra::exclusive_uart::ExclusiveUart<'static>)
+    };};
+}
+
+pub struct ExclusiveUartPortComponent {
+    uart: &'static dyn uart::Uart<'static>,
+    owner: ShortId,
+    parameters: uart::Parameters,
+}
+
+impl ExclusiveUartPortComponent {
+    pub fn new(
+        uart: &'static dyn uart::Uart<'static>,
+        owner: ShortId,
+        parameters: uart::Parameters,
+    ) -> Self {
+        Self {
+            uart,
+            owner,
+            parameters,
+        }
+    }
+}
+
+impl Component for ExclusiveUartPortComponent {
+    type StaticInput = (
+        &'static mut MaybeUninit<ExclusiveUartPort<'static>>,
+        &'static mut MaybeUninit<[u8; DEFAULT_BUF_SIZE]>,
+        &'static mut MaybeUninit<[u8; DEFAULT_BUF_SIZE]>,
+    );
+    type Output = &'static ExclusiveUartPort<'static>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let tx_buffer = static_buffer.1.write([0; DEFAULT_BUF_SIZE]);
+        let rx_buffer = static_buffer.2.write([0; DEFAULT_BUF_SIZE]);
+
+        let port = static_buffer.0.write(ExclusiveUartPort::new(
+            self.uart,
+            self.owner,
+            self.parameters,
+            tx_buffer,
+            rx_buffer,
+        ));
+        self.uart.set_transmit_client(port);
+        self.uart.set_receive_client(port);
+        let _ = port.initialize();
+        port
+    }
+}
+
+pub struct ExclusiveUartComponent {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    ports: &'static [&'static ExclusiveUartPort<'static>],
+}
+
+impl ExclusiveUartComponent {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        ports: &'static [&'static ExclusiveUartPort<'static>],
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            ports,
+        }
+    }
+}
+
+impl Component for ExclusiveUartComponent {
+    type StaticInput = &'static mut MaybeUninit<ExclusiveUart<'static>>;
+    type Output = &'static ExclusiveUart<'static>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let exclusive_uart = static_buffer.write(ExclusiveUart::new(
+            self.ports,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        exclusive_uart.initialize();
+        exclusive_uart
+    }
+}
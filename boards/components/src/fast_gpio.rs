@@ -0,0 +1,62 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the low-latency output pin driver.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let fast_gpio = components::fast_gpio::FastGpioComponent::new(components::fast_gpio_pins!(
+//!     nrf52840::gpio::GPIOPin,
+//!     &nrf52840_peripherals.gpio_port[Pin::P1_01],
+//!     &nrf52840_peripherals.gpio_port[Pin::P1_02],
+//! ))
+//! .finalize(components::fast_gpio_component_static!(
+//!     nrf52840::gpio::GPIOPin
+//! ));
+//! ```
+
+use capsules_extra::fast_gpio::FastGpio;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::gpio;
+
+/// Create the static list of pins driven by the fast GPIO driver.
+#[macro_export]
+macro_rules! fast_gpio_pins {
+    ($P:ty, $($pin:expr),+ $(,)?) => {{
+        use kernel::count_expressions;
+        const NUM_PINS: usize = count_expressions!($($pin),+);
+        let pins = kernel::static_init!([&'static $P; NUM_PINS], [$($pin,)*]);
+        &*pins
+    };};
+}
+
+#[macro_export]
+macro_rules! fast_gpio_component_static {
+    ($P:ty $(,)?) => {{
+        kernel::static_buf!(capsules_extra::fast_gpio::FastGpio<'static, $P>)
+    };};
+}
+
+pub type FastGpioComponentType<P> = FastGpio<'static, P>;
+
+pub struct FastGpioComponent<P: 'static + gpio::Output + gpio::Configure> {
+    pins: &'static [&'static P],
+}
+
+impl<P: 'static + gpio::Output + gpio::Configure> FastGpioComponent<P> {
+    pub fn new(pins: &'static [&'static P]) -> Self {
+        Self { pins }
+    }
+}
+
+impl<P: 'static + gpio::Output + gpio::Configure> Component for FastGpioComponent<P> {
+    type StaticInput = &'static mut MaybeUninit<FastGpio<'static, P>>;
+    type Output = &'static FastGpio<'static, P>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        s.write(FastGpio::new(self.pins))
+    }
+}
@@ -0,0 +1,115 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the FAT filesystem on an SD card.
+//!
+//! This provides one Component, FatComponent. This component creates a FAT
+//! filesystem on top of the given SD card, through its block storage
+//! interface, and a userspace driver that gives apps access to its files.
+//! The block storage interface becomes the client of the SD card, so the SD
+//! card cannot be used by another capsule at the same time.
+//!
+//! Usage
+//! -----
+//! ```rust
+//!    let fat = components::fat::FatComponent::new(
+//!        board_kernel,
+//!        capsules_extra::fat::driver::DRIVER_NUM,
+//!        sdcard,
+//!    )
+//!    .finalize(components::fat_component_static!(
+//!        VirtualMuxAlarm<'static, nrf52840::rtc::Rtc>
+//!    ));
+//! ```
+
+use capsules_extra::fat::driver::{FatDriver, KERNEL_BUFFER_LENGTH};
+use capsules_extra::fat::{FatFs, SECTOR_SIZE};
+use capsules_extra::sdcard::{SDCard, SDCardBlockStorage};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::deferred_call::DeferredCallClient;
+use kernel::hil::block_storage::BlockStorage;
+use kernel::hil::time::Alarm;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! fat_component_static {
+    ($A:ty $(,)?) => {{
+        let storage = kernel::static_buf!(capsules_extra::sdcard::SDCardBlockStorage<'static, $A>);
+        let fat = kernel::static_buf!(
+            capsules_extra::fat::FatFs<
+                'static,
+                capsules_extra::sdcard::SDCardBlockStorage<'static, $A>,
+            >
+        );
+        let fat_driver = kernel::static_buf!(
+            capsules_extra::fat::driver::FatDriver<
+                'static,
+                capsules_extra::sdcard::SDCardBlockStorage<'static, $A>,
+            >
+        );
+        let sector_buffer = kernel::static_buf!([u8; capsules_extra::fat::SECTOR_SIZE]);
+        let data_buffer =
+            kernel::static_buf!([u8; capsules_extra::fat::driver::KERNEL_BUFFER_LENGTH]);
+
+        (storage, fat, fat_driver, sector_buffer, data_buffer)
+    };};
+}
+
+pub type FatComponentType<A> = FatDriver<'static, SDCardBlockStorage<'static, A>>;
+
+pub struct FatComponent<A: Alarm<'static> + 'static> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    sdcard: &'static SDCard<'static, A>,
+}
+
+impl<A: Alarm<'static>> FatComponent<A> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        sdcard: &'static SDCard<'static, A>,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            sdcard,
+        }
+    }
+}
+
+impl<A: Alarm<'static>> Component for FatComponent<A> {
+    type StaticInput = (
+        &'static mut MaybeUninit<SDCardBlockStorage<'static, A>>,
+        &'static mut MaybeUninit<FatFs<'static, SDCardBlockStorage<'static, A>>>,
+        &'static mut MaybeUninit<FatDriver<'static, SDCardBlockStorage<'static, A>>>,
+        &'static mut MaybeUninit<[u8; SECTOR_SIZE]>,
+        &'static mut MaybeUninit<[u8; KERNEL_BUFFER_LENGTH]>,
+    );
+    type Output = &'static FatDriver<'static, SDCardBlockStorage<'static, A>>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let storage = s.0.write(SDCardBlockStorage::new(self.sdcard));
+        self.sdcard.set_client(storage);
+
+        let sector_buffer = s.3.write([0; SECTOR_SIZE]);
+        let fat = s.1.write(FatFs::new(storage, sector_buffer));
+        storage.set_client(fat);
+        fat.register();
+
+        let data_buffer = s.4.write([0; KERNEL_BUFFER_LENGTH]);
+        let fat_driver = s.2.write(FatDriver::new(
+            fat,
+            data_buffer,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        fat.set_client(fat_driver);
+
+        fat_driver
+    }
+}
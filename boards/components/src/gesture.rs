@@ -0,0 +1,109 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for touch gesture recognition.
+//!
+//! The gesture recognizer sits between the touch panel and the touch driver,
+//! and can be the gesture source of the touch driver.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let gesture = components::gesture::GestureComponent::new(
+//!     board_kernel,
+//!     capsules_extra::gesture::DRIVER_NUM,
+//!     touch_panel,
+//!     mux_alarm,
+//!     40,
+//! )
+//! .finalize(components::gesture_component_static!(nrf52840::rtc::Rtc<'static>));
+//! let touch = components::touch::TouchComponent::new(
+//!     board_kernel,
+//!     capsules_extra::touch::DRIVER_NUM,
+//!     gesture,
+//!     Some(gesture),
+//!     Some(screen),
+//! )
+//! .finalize(components::touch_component_static!());
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::gesture::GestureRecognizer;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil;
+use kernel::hil::time::Alarm;
+
+#[macro_export]
+macro_rules! gesture_component_static {
+    ($A:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let gesture = kernel::static_buf!(
+            capsules_extra::gesture::GestureRecognizer<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+
+        (alarm, gesture)
+    };};
+}
+
+pub type GestureComponentType<A> = GestureRecognizer<'static, VirtualMuxAlarm<'static, A>>;
+
+pub struct GestureComponent<A: 'static + Alarm<'static>> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    touch: &'static dyn hil::touch::Touch<'static>,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    swipe_distance: u16,
+}
+
+impl<A: 'static + Alarm<'static>> GestureComponent<A> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        touch: &'static dyn hil::touch::Touch<'static>,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        swipe_distance: u16,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            touch,
+            alarm_mux,
+            swipe_distance,
+        }
+    }
+}
+
+impl<A: 'static + Alarm<'static>> Component for GestureComponent<A> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<GestureRecognizer<'static, VirtualMuxAlarm<'static, A>>>,
+    );
+    type Output = &'static GestureRecognizer<'static, VirtualMuxAlarm<'static, A>>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let gesture = static_buffer.1.write(GestureRecognizer::new(
+            self.touch,
+            alarm,
+            self.swipe_distance,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        alarm.set_alarm_client(gesture);
+        self.touch.set_client(gesture);
+
+        gesture
+    }
+}
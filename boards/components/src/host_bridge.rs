@@ -0,0 +1,80 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the host bridge.
+//!
+//! The bridge uses its own virtual UART device on a UART mux, so the channel
+//! can be shared with the console.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let bridge = components::host_bridge::HostBridgeComponent::new(uart_mux)
+//!     .finalize(components::host_bridge_component_static!());
+//!
+//! let pin = static_init!(HostBridgePin<'static>, HostBridgePin::new(bridge, 0));
+//! bridge.add_device(pin);
+//! let _ = bridge.start();
+//! ```
+
+use capsules_core::virtualizers::virtual_uart::{MuxUart, UartDevice};
+use capsules_extra::host_bridge::{HostBridge, FRAME_BUF_LEN, TX_BUF_LEN};
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil;
+
+#[macro_export]
+macro_rules! host_bridge_component_static {
+    () => {{
+        let uart =
+            kernel::static_buf!(capsules_core::virtualizers::virtual_uart::UartDevice<'static>);
+        let tx_buffer = kernel::static_buf!([u8; capsules_extra::host_bridge::TX_BUF_LEN]);
+        let rx_buffer = kernel::static_buf!([u8; 1]);
+        let frame_buffer = kernel::static_buf!([u8; capsules_extra::host_bridge::FRAME_BUF_LEN]);
+        let bridge = kernel::static_buf!(capsules_extra::host_bridge::HostBridge<'static>);
+
+        (uart, tx_buffer, rx_buffer, frame_buffer, bridge)
+    };};
+}
+
+pub struct HostBridgeComponent {
+    uart_mux: &'static MuxUart<'static>,
+}
+
+impl HostBridgeComponent {
+    pub fn new(uart_mux: &'static MuxUart) -> HostBridgeComponent {
+        HostBridgeComponent { uart_mux }
+    }
+}
+
+impl Component for HostBridgeComponent {
+    type StaticInput = (
+        &'static mut MaybeUninit<UartDevice<'static>>,
+        &'static mut MaybeUninit<[u8; TX_BUF_LEN]>,
+        &'static mut MaybeUninit<[u8; 1]>,
+        &'static mut MaybeUninit<[u8; FRAME_BUF_LEN]>,
+        &'static mut MaybeUninit<HostBridge<'static>>,
+    );
+    type Output = &'static HostBridge<'static>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let bridge_uart = s.0.write(UartDevice::new(self.uart_mux, true));
+        bridge_uart.setup();
+
+        let tx_buffer = s.1.write([0; TX_BUF_LEN]);
+        let rx_buffer = s.2.write([0; 1]);
+        let frame_buffer = s.3.write([0; FRAME_BUF_LEN]);
+
+        let bridge = s.4.write(HostBridge::new(
+            bridge_uart,
+            tx_buffer,
+            rx_buffer,
+            frame_buffer,
+        ));
+        hil::uart::Transmit::set_transmit_client(bridge_uart, bridge);
+        hil::uart::Receive::set_receive_client(bridge_uart, bridge);
+
+        bridge
+    }
+}
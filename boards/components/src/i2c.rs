@@ -4,12 +4,18 @@
 
 //! Components for I2C.
 //!
-//! This provides two components.
+//! This provides four components.
 //!
 //! 1. `I2CMuxComponent` provides a virtualization layer for a I2C bus.
 //!
 //! 2. `I2CComponent` provides a virtualized client to the I2C bus.
 //!
+//! 3. `I2CSlaveMuxComponent` provides a virtualization layer for the slave
+//!    mode of an I2C peripheral.
+//!
+//! 4. `I2CSlaveComponent` provides a virtualized slave, which responds on its
+//!    own address.
+//!
 //! Usage
 //! -----
 //! ```rust
@@ -17,11 +23,17 @@
 //!     .finalize(components::i2c_mux_component_static!());
 //! let client_i2c = components::i2c::I2CComponent::new(mux_i2c, 0x19)
 //!     .finalize(components::i2c_component_static!());
+//!
+//! let mux_i2c_slave = components::i2c::I2CSlaveMuxComponent::new(&base_peripherals.twi1)
+//!     .finalize(components::i2c_slave_mux_component_static!(nrf52840::i2c::TWI));
+//! let i2c_slave = components::i2c::I2CSlaveComponent::new(mux_i2c_slave)
+//!     .finalize(components::i2c_slave_component_static!(nrf52840::i2c::TWI));
 //! ```
 
 // Author: Alexandru Radovici <msg4alex@gmail.com>
 
 use capsules_core::virtualizers::virtual_i2c::{I2CDevice, MuxI2C};
+use capsules_core::virtualizers::virtual_i2c_slave::{I2CSlaveDevice, MuxI2CSlave};
 use core::mem::MaybeUninit;
 use kernel::capabilities;
 use kernel::component::Component;
@@ -46,6 +58,24 @@ macro_rules! i2c_component_static {
     };};
 }
 
+#[macro_export]
+macro_rules! i2c_slave_mux_component_static {
+    ($S:ty $(,)?) => {{
+        kernel::static_buf!(
+            capsules_core::virtualizers::virtual_i2c_slave::MuxI2CSlave<'static, $S>
+        )
+    };};
+}
+
+#[macro_export]
+macro_rules! i2c_slave_component_static {
+    ($S:ty $(,)?) => {{
+        kernel::static_buf!(
+            capsules_core::virtualizers::virtual_i2c_slave::I2CSlaveDevice<'static, $S>
+        )
+    };};
+}
+
 #[macro_export]
 macro_rules! i2c_master_slave_component_static {
     ($I:ty $(,)?) => {{
@@ -123,6 +153,51 @@ impl<I: 'static + i2c::I2CMaster<'static>> Component for I2CComponent<I> {
     }
 }
 
+pub struct I2CSlaveMuxComponent<S: 'static + i2c::I2CSlaveMultiAddress<'static>> {
+    i2c: &'static S,
+}
+
+impl<S: 'static + i2c::I2CSlaveMultiAddress<'static>> I2CSlaveMuxComponent<S> {
+    pub fn new(i2c: &'static S) -> Self {
+        I2CSlaveMuxComponent { i2c }
+    }
+}
+
+impl<S: 'static + i2c::I2CSlaveMultiAddress<'static>> Component for I2CSlaveMuxComponent<S> {
+    type StaticInput = &'static mut MaybeUninit<MuxI2CSlave<'static, S>>;
+    type Output = &'static MuxI2CSlave<'static, S>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let mux_i2c_slave = static_buffer.write(MuxI2CSlave::new(self.i2c));
+
+        self.i2c.set_slave_client(mux_i2c_slave);
+
+        mux_i2c_slave
+    }
+}
+
+pub struct I2CSlaveComponent<S: 'static + i2c::I2CSlaveMultiAddress<'static>> {
+    mux: &'static MuxI2CSlave<'static, S>,
+}
+
+impl<S: 'static + i2c::I2CSlaveMultiAddress<'static>> I2CSlaveComponent<S> {
+    pub fn new(mux: &'static MuxI2CSlave<'static, S>) -> Self {
+        I2CSlaveComponent { mux }
+    }
+}
+
+impl<S: 'static + i2c::I2CSlaveMultiAddress<'static>> Component for I2CSlaveComponent<S> {
+    type StaticInput = &'static mut MaybeUninit<I2CSlaveDevice<'static, S>>;
+    type Output = &'static I2CSlaveDevice<'static, S>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let i2c_slave = static_buffer.write(I2CSlaveDevice::new(self.mux));
+        i2c_slave.setup();
+
+        i2c_slave
+    }
+}
+
 pub struct I2CMasterSlaveDriverComponent<I: 'static + i2c::I2CMasterSlave<'static>> {
     board_kernel: &'static kernel::Kernel,
     driver_num: usize,
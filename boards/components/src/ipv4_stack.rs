@@ -0,0 +1,100 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component to initialize an IPv4 interface on an Ethernet adapter.
+//!
+//! This provides one Component, Ipv4StackComponent. This component creates
+//! an Ipv4Stack on the given adapter, with its own virtual alarm, and starts
+//! it. The interface gets its address from a DHCP server, unless the board
+//! gives it a static configuration before it is started.
+//!
+//! Usage
+//! -----
+//! ```rust
+//!    let ipv4_stack = Ipv4StackComponent::new(
+//!        virtio_net,
+//!        mux_alarm,
+//!        [0x52, 0x54, 0x00, 0x12, 0x34, 0x56],
+//!    )
+//!    .finalize(components::ipv4_stack_component_static!(
+//!        qemu_rv32_virt_chip::chip::QemuRv32VirtClint
+//!    ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::net::ipv4::stack::{EthernetAddr, Ipv4Stack, FRAME_BUF_LEN};
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::ethernet::EthernetAdapterDatapath;
+use kernel::hil::time::Alarm;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! ipv4_stack_component_static {
+    ($A:ty $(,)?) => {{
+        use capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm;
+        use capsules_extra::net::ipv4::stack::FRAME_BUF_LEN;
+
+        let alarm = kernel::static_buf!(VirtualMuxAlarm<'static, $A>);
+        let ipv4_stack = kernel::static_buf!(
+            capsules_extra::net::ipv4::stack::Ipv4Stack<'static, VirtualMuxAlarm<'static, $A>>
+        );
+        let control_buffer = kernel::static_buf!([u8; FRAME_BUF_LEN]);
+        let data_buffer = kernel::static_buf!([u8; FRAME_BUF_LEN]);
+
+        (alarm, ipv4_stack, control_buffer, data_buffer)
+    };};
+}
+
+pub type Ipv4StackComponentType<A> = Ipv4Stack<'static, VirtualMuxAlarm<'static, A>>;
+
+pub struct Ipv4StackComponent<A: Alarm<'static> + 'static> {
+    adapter: &'static dyn EthernetAdapterDatapath<'static>,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    mac: EthernetAddr,
+}
+
+impl<A: Alarm<'static>> Ipv4StackComponent<A> {
+    pub fn new(
+        adapter: &'static dyn EthernetAdapterDatapath<'static>,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        mac: EthernetAddr,
+    ) -> Self {
+        Self {
+            adapter,
+            alarm_mux,
+            mac,
+        }
+    }
+}
+
+impl<A: Alarm<'static>> Component for Ipv4StackComponent<A> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<Ipv4Stack<'static, VirtualMuxAlarm<'static, A>>>,
+        &'static mut MaybeUninit<[u8; FRAME_BUF_LEN]>,
+        &'static mut MaybeUninit<[u8; FRAME_BUF_LEN]>,
+    );
+    type Output = &'static Ipv4Stack<'static, VirtualMuxAlarm<'static, A>>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let alarm = s.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let control_buffer = s.2.write([0; FRAME_BUF_LEN]);
+        let data_buffer = s.3.write([0; FRAME_BUF_LEN]);
+        let ipv4_stack = s.1.write(Ipv4Stack::new(
+            self.adapter,
+            alarm,
+            self.mac,
+            control_buffer,
+            data_buffer,
+        ));
+        self.adapter.set_client(ipv4_stack);
+        alarm.set_alarm_client(ipv4_stack);
+        ipv4_stack.start();
+
+        ipv4_stack
+    }
+}
@@ -0,0 +1,99 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component to initialize the userland UDP over IPv4 driver.
+//!
+//! This provides one Component, Ipv4UdpDriverComponent. This component
+//! creates `NUM_SOCKETS` UDP sockets on the given Ipv4Stack, and a userspace
+//! driver that gives apps access to them.
+//!
+//! Usage
+//! -----
+//! ```rust
+//!    let ipv4_udp_driver = Ipv4UdpDriverComponent::new(
+//!        board_kernel,
+//!        capsules_extra::net::ipv4::DRIVER_NUM,
+//!        ipv4_stack,
+//!    )
+//!    .finalize(components::ipv4_udp_driver_component_static!(
+//!        qemu_rv32_virt_chip::chip::QemuRv32VirtClint,
+//!        2
+//!    ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm;
+use capsules_extra::net::ipv4::stack::Ipv4Stack;
+use capsules_extra::net::ipv4::udp::UdpSocket;
+use capsules_extra::net::ipv4::Ipv4UdpDriver;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::time::Alarm;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! ipv4_udp_driver_component_static {
+    ($A:ty, $N:expr $(,)?) => {{
+        use capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm;
+
+        let sockets = kernel::static_buf!([capsules_extra::net::ipv4::udp::UdpSocket<'static>; $N]);
+        let ipv4_udp_driver = kernel::static_buf!(
+            capsules_extra::net::ipv4::Ipv4UdpDriver<'static, VirtualMuxAlarm<'static, $A>, $N>
+        );
+
+        (sockets, ipv4_udp_driver)
+    };};
+}
+
+pub type Ipv4UdpDriverComponentType<A, const NUM_SOCKETS: usize> =
+    Ipv4UdpDriver<'static, VirtualMuxAlarm<'static, A>, NUM_SOCKETS>;
+
+pub struct Ipv4UdpDriverComponent<A: Alarm<'static> + 'static, const NUM_SOCKETS: usize> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    ipv4_stack: &'static Ipv4Stack<'static, VirtualMuxAlarm<'static, A>>,
+}
+
+impl<A: Alarm<'static>, const NUM_SOCKETS: usize> Ipv4UdpDriverComponent<A, NUM_SOCKETS> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        ipv4_stack: &'static Ipv4Stack<'static, VirtualMuxAlarm<'static, A>>,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            ipv4_stack,
+        }
+    }
+}
+
+impl<A: Alarm<'static>, const NUM_SOCKETS: usize> Component
+    for Ipv4UdpDriverComponent<A, NUM_SOCKETS>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<[UdpSocket<'static>; NUM_SOCKETS]>,
+        &'static mut MaybeUninit<Ipv4UdpDriver<'static, VirtualMuxAlarm<'static, A>, NUM_SOCKETS>>,
+    );
+    type Output = &'static Ipv4UdpDriver<'static, VirtualMuxAlarm<'static, A>, NUM_SOCKETS>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let sockets = s.0.write(core::array::from_fn(UdpSocket::new));
+
+        let ipv4_udp_driver = s.1.write(Ipv4UdpDriver::new(
+            self.ipv4_stack,
+            sockets,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        for socket in sockets.iter() {
+            self.ipv4_stack.add_socket(socket);
+            socket.set_client(ipv4_udp_driver);
+        }
+
+        ipv4_udp_driver
+    }
+}
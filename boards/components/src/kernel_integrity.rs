@@ -0,0 +1,100 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the boot-time integrity check of the kernel image.
+//!
+//! The check hashes the kernel code and read-only data (from `_stext` to
+//! `_etext`) and compares the hash with the one the flashing tool stored in
+//! the kernel attributes. The board starts it once the digest engine is
+//! ready, usually right before the kernel loop.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let sha = components::sha::ShaSoftware256Component::new()
+//!     .finalize(components::sha_software_256_component_static!());
+//! let integrity = components::kernel_integrity::KernelIntegrityComponent::new(sha)
+//!     .finalize(components::kernel_integrity_component_static!(
+//!         capsules_extra::sha256::Sha256Software<'static>
+//!     ));
+//! integrity.set_log(&kernel::platform::self_test::DebugSelfTestLog);
+//! let _ = integrity.start();
+//! ```
+
+use capsules_system::kernel_integrity::KernelIntegrityChecker;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::digest::{DigestDataVerify, Sha256};
+
+#[macro_export]
+macro_rules! kernel_integrity_component_static {
+    ($D:ty $(,)?) => {{
+        let checker = kernel::static_buf!(
+            capsules_system::kernel_integrity::KernelIntegrityChecker<'static, $D>
+        );
+        let compare = kernel::static_buf!([u8; 32]);
+
+        (checker, compare)
+    };};
+}
+
+pub type KernelIntegrityComponentType<D> = KernelIntegrityChecker<'static, D>;
+
+// These symbols are defined in the linker script.
+extern "C" {
+    static _stext: u8;
+    static _etext: u8;
+    static _sattributes: u8;
+    static _eattributes: u8;
+}
+
+pub struct KernelIntegrityComponent<D: 'static + DigestDataVerify<'static, 32> + Sha256> {
+    digest: &'static D,
+}
+
+impl<D: 'static + DigestDataVerify<'static, 32> + Sha256> KernelIntegrityComponent<D> {
+    pub fn new(digest: &'static D) -> Self {
+        Self { digest }
+    }
+}
+
+impl<D: 'static + DigestDataVerify<'static, 32> + Sha256> Component
+    for KernelIntegrityComponent<D>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<KernelIntegrityChecker<'static, D>>,
+        &'static mut MaybeUninit<[u8; 32]>,
+    );
+    type Output = &'static KernelIntegrityChecker<'static, D>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        // SAFETY: the linker script defines these symbols around the kernel
+        // code and the kernel attributes in flash, which are not written
+        // while the kernel runs.
+        let (image, attributes) = unsafe {
+            (
+                core::slice::from_raw_parts(
+                    core::ptr::addr_of!(_stext),
+                    core::ptr::addr_of!(_etext) as usize - core::ptr::addr_of!(_stext) as usize,
+                ),
+                core::slice::from_raw_parts(
+                    core::ptr::addr_of!(_sattributes),
+                    core::ptr::addr_of!(_eattributes) as usize
+                        - core::ptr::addr_of!(_sattributes) as usize,
+                ),
+            )
+        };
+
+        let compare = s.1.write([0; 32]);
+        let checker = s.0.write(KernelIntegrityChecker::new(
+            self.digest,
+            image,
+            attributes,
+            compare,
+        ));
+        self.digest.set_client(checker);
+
+        checker
+    }
+}
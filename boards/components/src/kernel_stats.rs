@@ -0,0 +1,104 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for collecting kernel runtime statistics.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let kernel_stats = components::kernel_stats::KernelStatsComponent::new(
+//!     board_kernel,
+//!     mux_alarm,
+//!     Some(interrupt_counter),
+//! )
+//! .finalize(components::kernel_stats_component_static!(
+//!     nrf52840::rtc::Rtc<'static>,
+//!     48
+//! ));
+//! pconsole.set_statistics(kernel_stats);
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_system::kernel_stats::KernelStats;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::hil::time::Alarm;
+use kernel::platform::stats::InterruptCounts;
+
+#[macro_export]
+macro_rules! kernel_stats_component_static {
+    ($A:ty, $NUM_INTERRUPTS:expr $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let stats = kernel::static_buf!(
+            capsules_system::kernel_stats::KernelStats<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                $crate::kernel_stats::Capability,
+                $NUM_INTERRUPTS,
+            >
+        );
+
+        (alarm, stats)
+    };};
+}
+
+pub type KernelStatsComponentType<A, const NUM_INTERRUPTS: usize> =
+    KernelStats<'static, VirtualMuxAlarm<'static, A>, Capability, NUM_INTERRUPTS>;
+
+pub struct Capability;
+unsafe impl capabilities::ProcessManagementCapability for Capability {}
+
+pub struct KernelStatsComponent<A: 'static + Alarm<'static>, const NUM_INTERRUPTS: usize> {
+    board_kernel: &'static kernel::Kernel,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    interrupts: Option<&'static dyn InterruptCounts>,
+}
+
+impl<A: 'static + Alarm<'static>, const NUM_INTERRUPTS: usize>
+    KernelStatsComponent<A, NUM_INTERRUPTS>
+{
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        interrupts: Option<&'static dyn InterruptCounts>,
+    ) -> Self {
+        Self {
+            board_kernel,
+            alarm_mux,
+            interrupts,
+        }
+    }
+}
+
+impl<A: 'static + Alarm<'static>, const NUM_INTERRUPTS: usize> Component
+    for KernelStatsComponent<A, NUM_INTERRUPTS>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<
+            KernelStats<'static, VirtualMuxAlarm<'static, A>, Capability, NUM_INTERRUPTS>,
+        >,
+    );
+    type Output =
+        &'static KernelStats<'static, VirtualMuxAlarm<'static, A>, Capability, NUM_INTERRUPTS>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let stats_alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        stats_alarm.setup();
+
+        let stats = static_buffer.1.write(KernelStats::new(
+            self.board_kernel,
+            stats_alarm,
+            self.interrupts,
+            Capability,
+        ));
+        kernel::hil::time::Alarm::set_alarm_client(stats_alarm, stats);
+        stats.start();
+
+        stats
+    }
+}
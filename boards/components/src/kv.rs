@@ -87,7 +87,7 @@ macro_rules! kv_permissions_mux_component_static {
 }
 
 pub type KVPermissionsMuxComponentType<V> =
-    capsules_extra::kv_store_permissions::KVStorePermissions<'static, V>;
+    capsules_extra::virtual_kv::MuxKVPermissions<'static, V>;
 
 pub struct KVPermissionsMuxComponent<V: hil::kv::KVPermissions<'static> + 'static> {
     kv: &'static V,
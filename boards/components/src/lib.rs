@@ -12,40 +12,65 @@ pub mod alarm;
 pub mod analog_comparator;
 pub mod apds9960;
 pub mod app_flash_driver;
+pub mod app_loader;
+pub mod app_log;
+pub mod app_watchdog;
 pub mod appid;
 pub mod atecc508a;
+pub mod attestation;
+pub mod benchmark;
 pub mod ble;
+pub mod ble_gatt;
 pub mod bme280;
 pub mod bmm150;
 pub mod bmp280;
+pub mod boot_timing;
+pub mod border_router;
 pub mod bus;
 pub mod button;
 pub mod can;
 pub mod ccs811;
 pub mod cdc;
 pub mod chirp_i2c_moisture;
+pub mod config_service;
 pub mod console;
 pub mod crc;
 pub mod ctap;
 pub mod dac;
 pub mod date_time;
+pub mod date_time_software;
 pub mod debug_queue;
+pub mod debug_router;
 pub mod debug_writer;
+pub mod deferred_init;
 pub mod dfrobot_rainfall_sensor;
+pub mod ds3231;
+pub mod ecdsa_p256_signer;
+pub mod energy;
 pub mod eui64;
+pub mod event_batch;
+pub mod exclusive_uart;
+pub mod fast_gpio;
+pub mod fat;
 pub mod flash;
 pub mod fm25cl;
 pub mod ft6x06;
 pub mod fxos8700;
+pub mod gesture;
 pub mod gpio;
 pub mod hd44780;
 pub mod hmac;
+pub mod host_bridge;
 pub mod hs3003;
 pub mod hts221;
 pub mod humidity;
 pub mod i2c;
 pub mod ieee802154;
+pub mod ipv4_stack;
+pub mod ipv4_udp_driver;
 pub mod isl29035;
+pub mod kernel_integrity;
+pub mod kernel_stats;
 pub mod keyboard_hid;
 pub mod kv;
 pub mod l3gd20;
@@ -60,14 +85,25 @@ pub mod lsm303agr;
 pub mod lsm303dlhc;
 pub mod lsm6dsox;
 pub mod ltc294x;
+pub mod mass_storage;
 pub mod mlx90614;
 pub mod moisture;
 pub mod mx25r6435f;
+pub mod neighbor_table;
+pub mod network_stack;
 pub mod ninedof;
 pub mod nonvolatile_storage;
 pub mod nrf51822;
+pub mod packet_capture;
 pub mod panic_button;
+pub mod pcf8523;
+pub mod pcm_audio;
+pub mod peripherals;
+pub mod persistent_counters;
+pub mod pipe;
+pub mod power_rail;
 pub mod pressure;
+pub mod process_checkpoint;
 pub mod process_console;
 pub mod process_printer;
 pub mod proximity;
@@ -75,6 +111,7 @@ pub mod pwm;
 pub mod rainfall;
 pub mod rf233;
 pub mod rng;
+pub mod rollback_protection;
 pub mod sched;
 pub mod screen;
 pub mod segger_rtt;
@@ -90,14 +127,20 @@ pub mod spi;
 pub mod ssd1306;
 pub mod st77xx;
 pub mod storage_permissions;
+pub mod system_events;
+pub mod tcp_driver;
+pub mod tcp_mux;
 pub mod temperature;
 pub mod temperature_rp2040;
 pub mod temperature_stm;
 pub mod test;
 pub mod text_screen;
+pub mod thermal;
 pub mod thread_network;
 pub mod tickv;
 pub mod touch;
+pub mod touch_calibration;
 pub mod udp_driver;
 pub mod udp_mux;
 pub mod usb;
+pub mod wear_leveling;
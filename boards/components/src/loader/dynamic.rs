@@ -0,0 +1,132 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for creating a dynamic process loader.
+//!
+//! `DynamicLoaderComponent` installs new process binaries into the app flash
+//! region defined by the standard Tock linker script, and loads them with an
+//! existing `SequentialProcessLoaderMachine`.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let dynamic_loader = components::loader::dynamic::DynamicLoaderComponent::new(
+//!     loader,
+//!     &base_peripherals.nvmc,
+//! )
+//! .finalize(components::dynamic_loader_component_static!(
+//!     nrf52840::chip::NRF52<Nrf52840DefaultPeripherals>,
+//!     kernel::process::ProcessStandardDebugFull,
+//!     nrf52840::nvmc::Nvmc,
+//! ));
+//! ```
+
+use capsules_extra::nonvolatile_to_pages::NonvolatileToPages;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil;
+use kernel::platform::chip::Chip;
+use kernel::process::{
+    DynamicLoader, ProcessLoadingAsync, ProcessStandardDebug, SequentialProcessLoaderMachine,
+};
+
+/// Size of the buffer used to write new binaries to flash.
+pub const BUF_LEN: usize = 512;
+
+#[macro_export]
+macro_rules! dynamic_loader_component_static {
+    ($C:ty, $D:ty, $F:ty $(,)?) => {{
+        let page = kernel::static_buf!(<$F as kernel::hil::flash::Flash>::Page);
+        let ntp = kernel::static_buf!(
+            capsules_extra::nonvolatile_to_pages::NonvolatileToPages<'static, $F>
+        );
+        let buffer = kernel::static_buf!([u8; $crate::loader::dynamic::BUF_LEN]);
+        let loader = kernel::static_buf!(kernel::process::DynamicLoader<'static, $C, $D>);
+
+        (page, ntp, buffer, loader)
+    };};
+}
+
+pub type DynamicLoaderComponentType<C, D> = DynamicLoader<'static, C, D>;
+
+pub struct DynamicLoaderComponent<
+    C: Chip + 'static,
+    D: ProcessStandardDebug + 'static,
+    F: 'static + hil::flash::Flash + hil::flash::HasClient<'static, NonvolatileToPages<'static, F>>,
+> {
+    loader: &'static SequentialProcessLoaderMachine<'static, C, D>,
+    flash: &'static F,
+}
+
+impl<
+        C: Chip,
+        D: ProcessStandardDebug,
+        F: 'static
+            + hil::flash::Flash
+            + hil::flash::HasClient<'static, NonvolatileToPages<'static, F>>,
+    > DynamicLoaderComponent<C, D, F>
+{
+    pub fn new(
+        loader: &'static SequentialProcessLoaderMachine<'static, C, D>,
+        flash: &'static F,
+    ) -> Self {
+        Self { loader, flash }
+    }
+}
+
+impl<
+        C: Chip,
+        D: ProcessStandardDebug,
+        F: 'static
+            + hil::flash::Flash
+            + hil::flash::HasClient<'static, NonvolatileToPages<'static, F>>,
+    > Component for DynamicLoaderComponent<C, D, F>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<<F as hil::flash::Flash>::Page>,
+        &'static mut MaybeUninit<NonvolatileToPages<'static, F>>,
+        &'static mut MaybeUninit<[u8; BUF_LEN]>,
+        &'static mut MaybeUninit<DynamicLoader<'static, C, D>>,
+    );
+
+    type Output = &'static DynamicLoader<'static, C, D>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let proc_manage_cap =
+            kernel::create_capability!(kernel::capabilities::ProcessManagementCapability);
+
+        // These symbols are defined in the standard Tock linker script.
+        extern "C" {
+            /// Beginning of the ROM region containing app images.
+            static _sapps: u8;
+            /// End of the ROM region containing app images.
+            static _eapps: u8;
+        }
+
+        let flash_pagebuffer = s.0.write(<F as hil::flash::Flash>::Page::default());
+        let nv_to_page =
+            s.1.write(NonvolatileToPages::new(self.flash, flash_pagebuffer));
+        hil::flash::HasClient::set_client(self.flash, nv_to_page);
+
+        let buffer = s.2.write([0; BUF_LEN]);
+
+        let dynamic_loader = s.3.write(DynamicLoader::new(
+            self.loader,
+            nv_to_page,
+            unsafe {
+                core::slice::from_raw_parts(
+                    core::ptr::addr_of!(_sapps),
+                    core::ptr::addr_of!(_eapps) as usize - core::ptr::addr_of!(_sapps) as usize,
+                )
+            },
+            buffer,
+            &proc_manage_cap,
+        ));
+        hil::nonvolatile_storage::NonvolatileStorage::set_client(nv_to_page, dynamic_loader);
+        self.loader.set_client(dynamic_loader);
+
+        dynamic_loader
+    }
+}
@@ -0,0 +1,116 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for installing process binaries from external storage.
+//!
+//! `ProcessInstallerComponent` installs the binaries stored in the
+//! `storage_len` bytes starting at `storage_start` of a flash, typically an
+//! external flash chip, with a dynamic loader. Other users of the dynamic
+//! loader, like the `app_loader` capsule, must use the installer instead.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let installer = components::loader::install::ProcessInstallerComponent::new(
+//!     dynamic_loader,
+//!     flash_user,
+//!     0x20000,
+//!     0x7E0000,
+//! )
+//! .finalize(components::process_installer_component_static!(
+//!     capsules_core::virtualizers::virtual_flash::FlashUser<'static, Mx25r6435f>,
+//! ));
+//! pconsole.set_process_install(installer);
+//! ```
+
+use capsules_extra::nonvolatile_to_pages::NonvolatileToPages;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil;
+use kernel::process::{DynamicProcessLoading, ProcessInstaller};
+
+/// Size of the buffer used to copy binaries to app flash.
+pub const BUF_LEN: usize = 512;
+
+#[macro_export]
+macro_rules! process_installer_component_static {
+    ($F:ty $(,)?) => {{
+        let page = kernel::static_buf!(<$F as kernel::hil::flash::Flash>::Page);
+        let ntp = kernel::static_buf!(
+            capsules_extra::nonvolatile_to_pages::NonvolatileToPages<'static, $F>
+        );
+        let buffer = kernel::static_buf!([u8; $crate::loader::install::BUF_LEN]);
+        let installer = kernel::static_buf!(kernel::process::ProcessInstaller<'static>);
+
+        (page, ntp, buffer, installer)
+    };};
+}
+
+pub struct ProcessInstallerComponent<
+    F: 'static + hil::flash::Flash + hil::flash::HasClient<'static, NonvolatileToPages<'static, F>>,
+> {
+    loader: &'static dyn DynamicProcessLoading<'static>,
+    flash: &'static F,
+    storage_start: usize,
+    storage_len: usize,
+}
+
+impl<
+        F: 'static
+            + hil::flash::Flash
+            + hil::flash::HasClient<'static, NonvolatileToPages<'static, F>>,
+    > ProcessInstallerComponent<F>
+{
+    pub fn new(
+        loader: &'static dyn DynamicProcessLoading<'static>,
+        flash: &'static F,
+        storage_start: usize,
+        storage_len: usize,
+    ) -> Self {
+        Self {
+            loader,
+            flash,
+            storage_start,
+            storage_len,
+        }
+    }
+}
+
+impl<
+        F: 'static
+            + hil::flash::Flash
+            + hil::flash::HasClient<'static, NonvolatileToPages<'static, F>>,
+    > Component for ProcessInstallerComponent<F>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<<F as hil::flash::Flash>::Page>,
+        &'static mut MaybeUninit<NonvolatileToPages<'static, F>>,
+        &'static mut MaybeUninit<[u8; BUF_LEN]>,
+        &'static mut MaybeUninit<ProcessInstaller<'static>>,
+    );
+
+    type Output = &'static ProcessInstaller<'static>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let flash_pagebuffer = s.0.write(<F as hil::flash::Flash>::Page::default());
+        let nv_to_page =
+            s.1.write(NonvolatileToPages::new(self.flash, flash_pagebuffer));
+        hil::flash::HasClient::set_client(self.flash, nv_to_page);
+
+        let buffer = s.2.write([0; BUF_LEN]);
+
+        let installer = s.3.write(ProcessInstaller::new(
+            self.loader,
+            nv_to_page,
+            self.storage_start,
+            self.storage_len,
+            buffer,
+        ));
+        hil::nonvolatile_storage::NonvolatileStorage::set_client(nv_to_page, installer);
+        self.loader.set_client(installer);
+
+        installer
+    }
+}
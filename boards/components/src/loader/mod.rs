@@ -2,4 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 // Copyright Tock Contributors 2024.
 
+pub mod dynamic;
+pub mod install;
 pub mod sequential;
+pub mod swap;
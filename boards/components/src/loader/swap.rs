@@ -0,0 +1,159 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for swapping processes to flash (experimental).
+//!
+//! `ProcessSwapComponent` swaps processes out to `NUM_SLOTS` slots of
+//! `slot_size` bytes in the flash, starting at `swap_start`. The region
+//! must be reserved for swapping: outside of the kernel and app flash, and
+//! aligned to flash pages. Boards with a dynamic loader over the same flash
+//! cannot use this component, as both need to be the client of the flash.
+//! The flash needs a driver for writing it, like `sifive::spi_flash` for the
+//! flash of the HiFive1.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let process_swap = components::loader::swap::ProcessSwapComponent::new(
+//!     board_kernel,
+//!     loader,
+//!     &base_peripherals.nvmc,
+//!     mux_alarm,
+//!     0xF0000,
+//!     0x4000,
+//! )
+//! .finalize(components::process_swap_component_static!(
+//!     nrf52840::nvmc::Nvmc,
+//!     nrf52840::rtc::Rtc<'static>,
+//!     4,
+//! ));
+//! ipc.set_swap_in(process_swap);
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::nonvolatile_to_pages::NonvolatileToPages;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil;
+use kernel::hil::time::Alarm;
+use kernel::process::{ProcessSwap, ProcessSwapping};
+
+/// Size of the buffer used to copy process memory to and from flash.
+pub const BUF_LEN: usize = 512;
+
+#[macro_export]
+macro_rules! process_swap_component_static {
+    ($F:ty, $A:ty, $NUM_SLOTS:expr $(,)?) => {{
+        let page = kernel::static_buf!(<$F as kernel::hil::flash::Flash>::Page);
+        let ntp = kernel::static_buf!(
+            capsules_extra::nonvolatile_to_pages::NonvolatileToPages<'static, $F>
+        );
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let buffer = kernel::static_buf!([u8; $crate::loader::swap::BUF_LEN]);
+        let swap = kernel::static_buf!(
+            kernel::process::ProcessSwap<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                $NUM_SLOTS,
+            >
+        );
+
+        (page, ntp, alarm, buffer, swap)
+    };};
+}
+
+pub type ProcessSwapComponentType<A, const NUM_SLOTS: usize> =
+    ProcessSwap<'static, VirtualMuxAlarm<'static, A>, NUM_SLOTS>;
+
+pub struct ProcessSwapComponent<
+    F: 'static + hil::flash::Flash + hil::flash::HasClient<'static, NonvolatileToPages<'static, F>>,
+    A: 'static + Alarm<'static>,
+    const NUM_SLOTS: usize,
+> {
+    board_kernel: &'static kernel::Kernel,
+    loader: &'static dyn ProcessSwapping,
+    flash: &'static F,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    swap_start: usize,
+    slot_size: usize,
+}
+
+impl<
+        F: 'static
+            + hil::flash::Flash
+            + hil::flash::HasClient<'static, NonvolatileToPages<'static, F>>,
+        A: 'static + Alarm<'static>,
+        const NUM_SLOTS: usize,
+    > ProcessSwapComponent<F, A, NUM_SLOTS>
+{
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        loader: &'static dyn ProcessSwapping,
+        flash: &'static F,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        swap_start: usize,
+        slot_size: usize,
+    ) -> Self {
+        Self {
+            board_kernel,
+            loader,
+            flash,
+            alarm_mux,
+            swap_start,
+            slot_size,
+        }
+    }
+}
+
+impl<
+        F: 'static
+            + hil::flash::Flash
+            + hil::flash::HasClient<'static, NonvolatileToPages<'static, F>>,
+        A: 'static + Alarm<'static>,
+        const NUM_SLOTS: usize,
+    > Component for ProcessSwapComponent<F, A, NUM_SLOTS>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<<F as hil::flash::Flash>::Page>,
+        &'static mut MaybeUninit<NonvolatileToPages<'static, F>>,
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<[u8; BUF_LEN]>,
+        &'static mut MaybeUninit<ProcessSwap<'static, VirtualMuxAlarm<'static, A>, NUM_SLOTS>>,
+    );
+
+    type Output = &'static ProcessSwap<'static, VirtualMuxAlarm<'static, A>, NUM_SLOTS>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let proc_manage_cap =
+            kernel::create_capability!(kernel::capabilities::ProcessManagementCapability);
+
+        let flash_pagebuffer = s.0.write(<F as hil::flash::Flash>::Page::default());
+        let nv_to_page =
+            s.1.write(NonvolatileToPages::new(self.flash, flash_pagebuffer));
+        hil::flash::HasClient::set_client(self.flash, nv_to_page);
+
+        let alarm = s.2.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let buffer = s.3.write([0; BUF_LEN]);
+
+        let process_swap = s.4.write(ProcessSwap::new(
+            self.board_kernel,
+            self.loader,
+            nv_to_page,
+            alarm,
+            buffer,
+            self.swap_start,
+            self.slot_size,
+            &proc_manage_cap,
+        ));
+        hil::nonvolatile_storage::NonvolatileStorage::set_client(nv_to_page, process_swap);
+        alarm.set_alarm_client(process_swap);
+
+        process_swap
+    }
+}
@@ -0,0 +1,133 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for USB mass storage support.
+//!
+//! Exposes a region of any `hil::flash::Flash` to the host as a removable
+//! drive.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let strings = static_init!(
+//!     [&str; 3],
+//!     [
+//!         "Nordic Semiconductor", // Manufacturer
+//!         "nRF52840dk - TockOS",  // Product
+//!         "serial0001",           // Serial number
+//!     ]
+//! );
+//!
+//! let mass_storage = components::mass_storage::MassStorageComponent::new(
+//!     &nrf52840_peripherals.usbd,
+//!     capsules_extra::usb::cdc::MAX_CTRL_PACKET_SIZE_NRF52840,
+//!     0x1915, // Nordic Semiconductor
+//!     0x503a,
+//!     strings,
+//!     mx25r6435f,
+//!     0,    // First page
+//!     2048, // Number of pages (8 MB)
+//! )
+//! .finalize(components::mass_storage_component_static!(
+//!     nrf52840::usbd::Usbd,
+//!     Mx25r6435f
+//! ));
+//!
+//! mass_storage.enable();
+//! mass_storage.attach();
+//! ```
+
+use capsules_extra::usb::mass_storage::MassStorage;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! mass_storage_component_static {
+    ($U:ty, $F:ty $(,)?) => {{
+        let mass_storage =
+            kernel::static_buf!(capsules_extra::usb::mass_storage::MassStorage<'static, $U, $F>);
+        let page_buffer = kernel::static_buf!(<$F as kernel::hil::flash::Flash>::Page);
+
+        (mass_storage, page_buffer)
+    };};
+}
+
+pub type MassStorageComponentType<U, F> = MassStorage<'static, U, F>;
+
+pub struct MassStorageComponent<
+    U: 'static + hil::usb::UsbController<'static>,
+    F: 'static + hil::flash::Flash + hil::flash::HasClient<'static, MassStorage<'static, U, F>>,
+> {
+    usb: &'static U,
+    max_ctrl_packet_size: u8,
+    vendor_id: u16,
+    product_id: u16,
+    strings: &'static [&'static str; 3],
+    flash: &'static F,
+    first_page: usize,
+    num_pages: usize,
+}
+
+impl<
+        U: 'static + hil::usb::UsbController<'static>,
+        F: 'static + hil::flash::Flash + hil::flash::HasClient<'static, MassStorage<'static, U, F>>,
+    > MassStorageComponent<U, F>
+{
+    pub fn new(
+        usb: &'static U,
+        max_ctrl_packet_size: u8,
+        vendor_id: u16,
+        product_id: u16,
+        strings: &'static [&'static str; 3],
+        flash: &'static F,
+        first_page: usize,
+        num_pages: usize,
+    ) -> Self {
+        Self {
+            usb,
+            max_ctrl_packet_size,
+            vendor_id,
+            product_id,
+            strings,
+            flash,
+            first_page,
+            num_pages,
+        }
+    }
+}
+
+impl<
+        U: 'static + hil::usb::UsbController<'static>,
+        F: 'static + hil::flash::Flash + hil::flash::HasClient<'static, MassStorage<'static, U, F>>,
+    > Component for MassStorageComponent<U, F>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<MassStorage<'static, U, F>>,
+        &'static mut MaybeUninit<F::Page>,
+    );
+    type Output = &'static MassStorage<'static, U, F>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let page_buffer = s.1.write(F::Page::default());
+
+        let mass_storage = s.0.write(MassStorage::new(
+            self.usb,
+            self.max_ctrl_packet_size,
+            self.vendor_id,
+            self.product_id,
+            self.strings,
+            self.flash,
+            page_buffer,
+            self.first_page,
+            self.num_pages,
+        ));
+        self.usb.set_client(mass_storage);
+        hil::flash::HasClient::set_client(self.flash, mass_storage);
+
+        mass_storage
+    }
+}
@@ -0,0 +1,97 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the table of radio neighbors.
+//!
+//! Radio stacks are connected by the board, by setting the table as their
+//! link observer.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let neighbor_table = components::neighbor_table::NeighborTableComponent::new(
+//!     board_kernel,
+//!     capsules_extra::neighbor_table::DRIVER_NUM,
+//!     mux_alarm,
+//!     300,
+//! )
+//! .finalize(components::neighbor_table_component_static!(nrf52840::rtc::Rtc<'static>, 16));
+//! mux_mac.set_link_observer(neighbor_table);
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::neighbor_table::NeighborTableService;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::time::Alarm;
+
+#[macro_export]
+macro_rules! neighbor_table_component_static {
+    ($A:ty, $N:expr $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let neighbor_table = kernel::static_buf!(
+            capsules_extra::neighbor_table::NeighborTableService<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                $N,
+            >
+        );
+
+        (alarm, neighbor_table)
+    };};
+}
+
+pub type NeighborTableComponentType<A, const N: usize> =
+    NeighborTableService<'static, VirtualMuxAlarm<'static, A>, N>;
+
+pub struct NeighborTableComponent<A: 'static + Alarm<'static>, const N: usize> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    max_age_s: u32,
+}
+
+impl<A: 'static + Alarm<'static>, const N: usize> NeighborTableComponent<A, N> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        max_age_s: u32,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            alarm_mux,
+            max_age_s,
+        }
+    }
+}
+
+impl<A: 'static + Alarm<'static>, const N: usize> Component for NeighborTableComponent<A, N> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<NeighborTableService<'static, VirtualMuxAlarm<'static, A>, N>>,
+    );
+    type Output = &'static NeighborTableService<'static, VirtualMuxAlarm<'static, A>, N>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let neighbor_table = static_buffer.1.write(NeighborTableService::new(
+            alarm,
+            self.max_age_s,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        alarm.set_alarm_client(neighbor_table);
+
+        neighbor_table
+    }
+}
@@ -0,0 +1,227 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the in-kernel IEEE 802.15.4 and UDP network stack.
+//!
+//! `NetworkStackComponent` creates the 802.15.4 stack (see
+//! [`ieee802154`](crate::ieee802154)), the 6LoWPAN and UDP layers on top of it
+//! (see [`udp_mux`](crate::udp_mux)) and the userspace UDP driver (see
+//! [`udp_driver`](crate::udp_driver)) from a single [`NetworkStackConfig`], so
+//! that boards set up the stack consistently.
+//!
+//! The node has three IPv6 addresses: the link local addresses derived from
+//! its long and short MAC addresses, and `ip_address` from the configuration.
+//! Packets are sent from the first one by default.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! const NETWORK: components::network_stack::NetworkStackConfig =
+//!     components::network_stack::NetworkStackConfig {
+//!         pan_id: 0xABCD,
+//!         short_addr: 0x1540,
+//!         long_addr: [0x15; 8],
+//!         ..components::network_stack::NetworkStackConfig::DEFAULT
+//!     };
+//!
+//! let (ieee802154, udp, mux_mac) = components::network_stack::NetworkStackComponent::new(
+//!     board_kernel,
+//!     &nrf52840_peripherals.ieee802154_radio,
+//!     aes_mux,
+//!     mux_alarm,
+//!     NETWORK,
+//! )
+//! .finalize(components::network_stack_component_static!(
+//!     nrf52840::ieee802154_radio::Radio,
+//!     nrf52840::aes::AesECB<'static>,
+//!     nrf52840::rtc::Rtc
+//! ));
+//! ```
+
+use crate::ieee802154::{Ieee802154Component, Ieee802154ComponentMacDeviceType};
+use crate::udp_driver::UDPDriverComponent;
+use crate::udp_mux::{UDPMuxComponent, MAX_PAYLOAD_LEN};
+use capsules_core::virtualizers::virtual_aes_ccm::MuxAES128CCM;
+use capsules_core::virtualizers::virtual_alarm::MuxAlarm;
+use capsules_extra::ieee802154::virtual_mac::MuxMac;
+use capsules_extra::net::ieee802154::{KeyId, MacAddress, PanID, SecurityLevel};
+use capsules_extra::net::ipv6::ip_utils::IPAddr;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::radio::Radio;
+use kernel::hil::symmetric_encryption::{AES128Ctr, AES128, AES128CBC, AES128ECB};
+use kernel::hil::time::Alarm;
+
+/// A link layer key that the board provisions in the 802.15.4 driver.
+#[derive(Clone, Copy)]
+pub struct NetworkKey {
+    pub level: SecurityLevel,
+    pub key_id: KeyId,
+    pub key: [u8; 16],
+}
+
+/// Configuration of the network stack.
+#[derive(Clone, Copy)]
+pub struct NetworkStackConfig {
+    pub pan_id: PanID,
+    pub short_addr: u16,
+    pub long_addr: [u8; 8],
+    /// MAC address of the next hop, to which all packets are sent.
+    pub dst_mac_addr: MacAddress,
+    /// Context used for 6LoWPAN header compression.
+    pub ctx_prefix: [u8; 16],
+    pub ctx_prefix_len: u8,
+    /// Routable address of the node.
+    pub ip_address: IPAddr,
+    /// Keys provisioned in the 802.15.4 driver.
+    pub keys: &'static [NetworkKey],
+    /// Largest UDP payload that userspace can send, at most
+    /// [`MAX_PAYLOAD_LEN`].
+    pub max_payload_len: usize,
+}
+
+impl NetworkStackConfig {
+    /// The configuration the Tock tutorials and test networks use. Boards
+    /// usually only set their addresses.
+    pub const DEFAULT: NetworkStackConfig = NetworkStackConfig {
+        pan_id: 0xABCD,
+        short_addr: 0,
+        long_addr: [0; 8],
+        dst_mac_addr: MacAddress::Short(49138),
+        ctx_prefix: [0; 16],
+        ctx_prefix_len: 8,
+        ip_address: IPAddr([
+            0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d,
+            0x1e, 0x1f,
+        ]),
+        keys: &[],
+        max_payload_len: MAX_PAYLOAD_LEN,
+    };
+}
+
+#[macro_export]
+macro_rules! network_stack_component_static {
+    ($R:ty, $A:ty, $T:ty $(,)?) => {{
+        let ieee802154 = components::ieee802154_component_static!($R, $A);
+        let udp_mux = components::udp_mux_component_static!(
+            $T,
+            components::ieee802154::Ieee802154ComponentMacDeviceType<$R, $A>
+        );
+        let udp_driver = components::udp_driver_component_static!($T);
+        let interfaces =
+            kernel::static_buf!([capsules_extra::net::ipv6::ip_utils::IPAddr; 3]);
+
+        (ieee802154, udp_mux, udp_driver, interfaces)
+    };};
+}
+
+pub type NetworkStackComponentMuxMacType<R, A> =
+    MuxMac<'static, Ieee802154ComponentMacDeviceType<R, A>>;
+
+pub struct NetworkStackComponent<
+    R: 'static + Radio<'static>,
+    A: 'static + AES128<'static> + AES128Ctr + AES128CBC + AES128ECB,
+    T: 'static + Alarm<'static>,
+> {
+    board_kernel: &'static kernel::Kernel,
+    radio: &'static R,
+    aes_mux: &'static MuxAES128CCM<'static, A>,
+    alarm_mux: &'static MuxAlarm<'static, T>,
+    config: NetworkStackConfig,
+}
+
+impl<
+        R: 'static + Radio<'static>,
+        A: 'static + AES128<'static> + AES128Ctr + AES128CBC + AES128ECB,
+        T: 'static + Alarm<'static>,
+    > NetworkStackComponent<R, A, T>
+{
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        radio: &'static R,
+        aes_mux: &'static MuxAES128CCM<'static, A>,
+        alarm_mux: &'static MuxAlarm<'static, T>,
+        config: NetworkStackConfig,
+    ) -> Self {
+        Self {
+            board_kernel,
+            radio,
+            aes_mux,
+            alarm_mux,
+            config,
+        }
+    }
+}
+
+impl<
+        R: 'static + Radio<'static>,
+        A: 'static + AES128<'static> + AES128Ctr + AES128CBC + AES128ECB,
+        T: 'static + Alarm<'static>,
+    > Component for NetworkStackComponent<R, A, T>
+{
+    type StaticInput = (
+        <Ieee802154Component<R, A> as Component>::StaticInput,
+        <UDPMuxComponent<T, Ieee802154ComponentMacDeviceType<R, A>> as Component>::StaticInput,
+        <UDPDriverComponent<T> as Component>::StaticInput,
+        &'static mut MaybeUninit<[IPAddr; 3]>,
+    );
+    type Output = (
+        &'static crate::ieee802154::Ieee802154ComponentType<R, A>,
+        &'static capsules_extra::net::udp::UDPDriver<'static>,
+        &'static NetworkStackComponentMuxMacType<R, A>,
+    );
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let config = self.config;
+
+        let (ieee802154_driver, mux_mac) = Ieee802154Component::new(
+            self.board_kernel,
+            capsules_extra::ieee802154::DRIVER_NUM,
+            self.radio,
+            self.aes_mux,
+            config.pan_id,
+            config.short_addr,
+            config.long_addr,
+        )
+        .finalize(s.0);
+        for key in config.keys {
+            if ieee802154_driver
+                .provision_key(key.level, key.key_id, key.key)
+                .is_err()
+            {
+                kernel::debug!("network_stack: no space left for key {:?}", key.key_id);
+            }
+        }
+
+        let interfaces = s.3.write([
+            IPAddr::generate_from_mac(MacAddress::Long(config.long_addr)),
+            config.ip_address,
+            IPAddr::generate_from_mac(MacAddress::Short(config.short_addr)),
+        ]);
+
+        let (udp_send_mux, udp_recv_mux, udp_port_table) = UDPMuxComponent::new(
+            mux_mac,
+            config.ctx_prefix_len,
+            config.ctx_prefix,
+            config.dst_mac_addr,
+            MacAddress::Long(config.long_addr),
+            interfaces,
+            self.alarm_mux,
+        )
+        .finalize(s.1);
+
+        let udp_driver = UDPDriverComponent::new(
+            self.board_kernel,
+            capsules_extra::net::udp::driver::DRIVER_NUM,
+            udp_send_mux,
+            udp_recv_mux,
+            udp_port_table,
+            interfaces,
+        )
+        .with_max_payload_len(config.max_payload_len)
+        .finalize(s.2);
+
+        (ieee802154_driver, udp_driver, mux_mac)
+    }
+}
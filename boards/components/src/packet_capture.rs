@@ -0,0 +1,200 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Components for capturing the frames of a network stack.
+//!
+//! `PacketCaptureComponent` creates the capture and its syscall driver. A
+//! board feeds it from the 802.15.4 MAC mux, or from an Ethernet adapter
+//! with `EthernetCaptureComponent`, and can stream it in the pcap format
+//! over a UART with `PcapUartComponent`. The pcap stream is binary, so it
+//! needs a UART where nothing else is written, such as a second UART or the
+//! console of a board that prints nothing.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let packet_capture = components::packet_capture::PacketCaptureComponent::new(
+//!     board_kernel,
+//!     capsules_extra::packet_capture::DRIVER_NUM,
+//!     &base_peripherals.rtc,
+//!     capsules_extra::packet_capture::LinkType::Ieee802154,
+//!     128,
+//! )
+//! .finalize(components::packet_capture_component_static!(
+//!     nrf52840::rtc::Rtc<'static>,
+//!     2048
+//! ));
+//! mux_mac.set_packet_observer(packet_capture);
+//!
+//! // Stream the capture to the host, and start it right away.
+//! components::packet_capture::PcapUartComponent::new(packet_capture, uart_mux)
+//!     .finalize(components::pcap_uart_component_static!(144));
+//! packet_capture.start();
+//! ```
+//!
+//! For Ethernet, the capture is interposed between the adapter and the
+//! stack:
+//!
+//! ```rust
+//! let ethernet_capture =
+//!     components::packet_capture::EthernetCaptureComponent::new(virtio_net, packet_capture)
+//!         .finalize(components::ethernet_capture_component_static!());
+//! let ipv4_stack = components::ipv4_stack::Ipv4StackComponent::new(
+//!     ethernet_capture,
+//!     mux_alarm,
+//!     MAC_ADDR,
+//! )
+//! .finalize(components::ipv4_stack_component_static!(Timer));
+//! ```
+
+use capsules_core::virtualizers::virtual_uart::{MuxUart, UartDevice};
+use capsules_extra::packet_capture::{EthernetCapture, LinkType, PacketCapture, PacketObserver};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::ethernet::EthernetAdapterDatapath;
+use kernel::hil::time::Time;
+use kernel::hil::uart::Transmit;
+
+#[macro_export]
+macro_rules! packet_capture_component_static {
+    ($T:ty, $RING_LEN:expr $(,)?) => {{
+        let packet_capture =
+            kernel::static_buf!(capsules_extra::packet_capture::PacketCapture<'static, $T>);
+        let ring = kernel::static_buf!([u8; $RING_LEN]);
+
+        (packet_capture, ring)
+    };};
+}
+
+pub type PacketCaptureComponentType<T> = PacketCapture<'static, T>;
+
+pub struct PacketCaptureComponent<T: 'static + Time, const RING_LEN: usize> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    time: &'static T,
+    link_type: LinkType,
+    snap_len: u16,
+}
+
+impl<T: 'static + Time, const RING_LEN: usize> PacketCaptureComponent<T, RING_LEN> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        time: &'static T,
+        link_type: LinkType,
+        snap_len: u16,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            time,
+            link_type,
+            snap_len,
+        }
+    }
+}
+
+impl<T: 'static + Time, const RING_LEN: usize> Component for PacketCaptureComponent<T, RING_LEN> {
+    type StaticInput = (
+        &'static mut MaybeUninit<PacketCapture<'static, T>>,
+        &'static mut MaybeUninit<[u8; RING_LEN]>,
+    );
+    type Output = &'static PacketCapture<'static, T>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let ring = static_buffer.1.write([0; RING_LEN]);
+
+        static_buffer.0.write(PacketCapture::new(
+            self.time,
+            self.link_type,
+            self.snap_len,
+            ring,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ))
+    }
+}
+
+#[macro_export]
+macro_rules! pcap_uart_component_static {
+    ($BUF_LEN:expr $(,)?) => {{
+        let uart = kernel::static_buf!(capsules_core::virtualizers::virtual_uart::UartDevice);
+        let buffer = kernel::static_buf!([u8; $BUF_LEN]);
+
+        (uart, buffer)
+    };};
+}
+
+pub struct PcapUartComponent<T: 'static + Time, const BUF_LEN: usize> {
+    packet_capture: &'static PacketCapture<'static, T>,
+    uart_mux: &'static MuxUart<'static>,
+}
+
+impl<T: 'static + Time, const BUF_LEN: usize> PcapUartComponent<T, BUF_LEN> {
+    pub fn new(
+        packet_capture: &'static PacketCapture<'static, T>,
+        uart_mux: &'static MuxUart<'static>,
+    ) -> Self {
+        Self {
+            packet_capture,
+            uart_mux,
+        }
+    }
+}
+
+impl<T: 'static + Time, const BUF_LEN: usize> Component for PcapUartComponent<T, BUF_LEN> {
+    type StaticInput = (
+        &'static mut MaybeUninit<UartDevice<'static>>,
+        &'static mut MaybeUninit<[u8; BUF_LEN]>,
+    );
+    type Output = &'static UartDevice<'static>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let uart = static_buffer.0.write(UartDevice::new(self.uart_mux, false));
+        uart.setup();
+        let buffer = static_buffer.1.write([0; BUF_LEN]);
+
+        uart.set_transmit_client(self.packet_capture);
+        self.packet_capture.set_pcap_uart(uart, buffer);
+
+        uart
+    }
+}
+
+#[macro_export]
+macro_rules! ethernet_capture_component_static {
+    () => {{
+        kernel::static_buf!(capsules_extra::packet_capture::EthernetCapture<'static>)
+    };};
+}
+
+pub struct EthernetCaptureComponent {
+    adapter: &'static dyn EthernetAdapterDatapath<'static>,
+    observer: &'static dyn PacketObserver,
+}
+
+impl EthernetCaptureComponent {
+    pub fn new(
+        adapter: &'static dyn EthernetAdapterDatapath<'static>,
+        observer: &'static dyn PacketObserver,
+    ) -> Self {
+        Self { adapter, observer }
+    }
+}
+
+impl Component for EthernetCaptureComponent {
+    type StaticInput = &'static mut MaybeUninit<EthernetCapture<'static>>;
+    type Output = &'static EthernetCapture<'static>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let ethernet_capture =
+            static_buffer.write(EthernetCapture::new(self.adapter, self.observer));
+        self.adapter.set_client(ethernet_capture);
+
+        ethernet_capture
+    }
+}
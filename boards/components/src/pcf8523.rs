@@ -0,0 +1,92 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the PCF8523 real time clock.
+//!
+//! The RTC is both a `DateTime` and a `DateTimeAlarm`.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let pcf8523 = components::pcf8523::Pcf8523Component::new(mux_i2c, 0x68, rtc_int_pin)
+//!     .finalize(components::pcf8523_component_static!(nrf52840::i2c::TWI));
+//! let date_time = components::date_time::DateTimeComponent::new(
+//!     board_kernel,
+//!     capsules_extra::date_time::DRIVER_NUM,
+//!     pcf8523,
+//! )
+//! .finalize(components::date_time_component_static!(
+//!     components::pcf8523::Pcf8523ComponentType<nrf52840::i2c::TWI>
+//! ));
+//! ```
+
+use capsules_core::virtualizers::virtual_i2c::{I2CDevice, MuxI2C};
+use capsules_extra::pcf8523::{Pcf8523, BUFFER_LEN};
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::gpio;
+use kernel::hil::i2c;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! pcf8523_component_static {
+    ($I:ty $(,)?) => {{
+        let i2c_device =
+            kernel::static_buf!(capsules_core::virtualizers::virtual_i2c::I2CDevice<$I>);
+        let buffer = kernel::static_buf!([u8; capsules_extra::pcf8523::BUFFER_LEN]);
+        let pcf8523 = kernel::static_buf!(
+            capsules_extra::pcf8523::Pcf8523<
+                'static,
+                capsules_core::virtualizers::virtual_i2c::I2CDevice<$I>,
+            >
+        );
+
+        (i2c_device, buffer, pcf8523)
+    };};
+}
+
+pub type Pcf8523ComponentType<I> = Pcf8523<'static, I2CDevice<'static, I>>;
+
+pub struct Pcf8523Component<I: 'static + i2c::I2CMaster<'static>> {
+    i2c_mux: &'static MuxI2C<'static, I>,
+    i2c_address: u8,
+    interrupt_pin: &'static dyn gpio::InterruptPin<'static>,
+}
+
+impl<I: 'static + i2c::I2CMaster<'static>> Pcf8523Component<I> {
+    pub fn new(
+        i2c_mux: &'static MuxI2C<'static, I>,
+        i2c_address: u8,
+        interrupt_pin: &'static dyn gpio::InterruptPin<'static>,
+    ) -> Self {
+        Pcf8523Component {
+            i2c_mux,
+            i2c_address,
+            interrupt_pin,
+        }
+    }
+}
+
+impl<I: 'static + i2c::I2CMaster<'static>> Component for Pcf8523Component<I> {
+    type StaticInput = (
+        &'static mut MaybeUninit<I2CDevice<'static, I>>,
+        &'static mut MaybeUninit<[u8; BUFFER_LEN]>,
+        &'static mut MaybeUninit<Pcf8523<'static, I2CDevice<'static, I>>>,
+    );
+    type Output = &'static Pcf8523<'static, I2CDevice<'static, I>>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let pcf8523_i2c = static_buffer
+            .0
+            .write(I2CDevice::new(self.i2c_mux, self.i2c_address));
+        let buffer = static_buffer.1.write([0; BUFFER_LEN]);
+        let pcf8523 = static_buffer
+            .2
+            .write(Pcf8523::new(pcf8523_i2c, self.interrupt_pin, buffer));
+
+        pcf8523_i2c.set_client(pcf8523);
+        self.interrupt_pin.set_client(pcf8523);
+        pcf8523
+    }
+}
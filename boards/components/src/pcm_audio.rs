@@ -0,0 +1,96 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for PCM audio playback through a PWM pin or a DAC.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let audio = components::pcm_audio::PcmAudioComponent::new(
+//!     board_kernel,
+//!     capsules_extra::pcm_audio::DRIVER_NUM,
+//!     mux_alarm,
+//!     capsules_extra::pcm_audio::AudioOutput::Pwm {
+//!         pin: audio_pwm_pin,
+//!         carrier_hz: 62_500,
+//!     },
+//! )
+//! .finalize(components::pcm_audio_component_static!(
+//!     nrf52840::rtc::Rtc<'static>
+//! ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::pcm_audio::{AudioOutput, PcmAudio};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::time::Alarm;
+
+#[macro_export]
+macro_rules! pcm_audio_component_static {
+    ($A:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let audio = kernel::static_buf!(
+            capsules_extra::pcm_audio::PcmAudio<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+
+        (alarm, audio)
+    };};
+}
+
+pub type PcmAudioComponentType<A> = PcmAudio<'static, VirtualMuxAlarm<'static, A>>;
+
+pub struct PcmAudioComponent<A: 'static + Alarm<'static>> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    output: AudioOutput<'static>,
+}
+
+impl<A: 'static + Alarm<'static>> PcmAudioComponent<A> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        output: AudioOutput<'static>,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            alarm_mux,
+            output,
+        }
+    }
+}
+
+impl<A: 'static + Alarm<'static>> Component for PcmAudioComponent<A> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<PcmAudio<'static, VirtualMuxAlarm<'static, A>>>,
+    );
+    type Output = &'static PcmAudio<'static, VirtualMuxAlarm<'static, A>>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let audio_alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        audio_alarm.setup();
+
+        let audio = static_buffer.1.write(PcmAudio::new(
+            audio_alarm,
+            self.output,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        audio_alarm.set_alarm_client(audio);
+
+        audio
+    }
+}
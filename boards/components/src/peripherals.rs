@@ -0,0 +1,47 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the driver that lists the optional subsystems of the board.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! static PERIPHERALS: [Peripheral; 1] =
+//!     [Peripheral::screen(capsules_extra::screen::DRIVER_NUM, 240, 240)];
+//! let peripherals = components::peripherals::PeripheralsComponent::new(&PERIPHERALS)
+//!     .finalize(components::peripherals_component_static!());
+//! ```
+
+use capsules_extra::peripherals::PeripheralsDriver;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::platform::peripherals::{Peripheral, Peripherals};
+
+#[macro_export]
+macro_rules! peripherals_component_static {
+    () => {{
+        kernel::static_buf!(capsules_extra::peripherals::PeripheralsDriver)
+    };};
+}
+
+pub type PeripheralsComponentType = capsules_extra::peripherals::PeripheralsDriver;
+
+pub struct PeripheralsComponent {
+    peripherals: &'static [Peripheral],
+}
+
+impl PeripheralsComponent {
+    pub fn new(peripherals: &'static [Peripheral]) -> Self {
+        Self { peripherals }
+    }
+}
+
+impl Component for PeripheralsComponent {
+    type StaticInput = &'static mut MaybeUninit<PeripheralsDriver>;
+    type Output = &'static PeripheralsDriver;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        s.write(PeripheralsDriver::new(Peripherals::new(self.peripherals)))
+    }
+}
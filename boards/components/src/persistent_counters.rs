@@ -0,0 +1,109 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the boot and panic counters kept in the KV store.
+//!
+//! The counters need their own `KVPermissions` user, typically a virtual
+//! user of the KV permissions mux, and a retained register for the panic
+//! breadcrumb. The component makes them the panic recorder of the kernel.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let boot_counters = components::persistent_counters::BootCountersComponent::new(
+//!     counters_kv,
+//!     &base_peripherals.pwr_clk,
+//!     RetainedLayout {
+//!         panic_breadcrumb: Some(1),
+//!         ..RetainedLayout::NONE
+//!     },
+//! )
+//! .finalize(components::boot_counters_component_static!(
+//!     capsules_extra::virtual_kv::VirtualKVPermissions<...>,
+//!     nrf52840::power::Power<'static>,
+//! ));
+//! let _ = boot_counters.start();
+//! ```
+
+use capsules_system::persistent_counters::{BootCounters, KEY_LEN, VALUE_LEN};
+use capsules_system::retained_state::RetainedLayout;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil;
+use kernel::hil::retained::RetainedRegisters;
+use kernel::storage_permissions::StoragePermissions;
+
+/// Length of the value buffer, with room for the KV header.
+pub const VALUE_BUFFER_LEN: usize = VALUE_LEN + 16;
+
+#[macro_export]
+macro_rules! boot_counters_component_static {
+    ($V:ty, $R:ty $(,)?) => {{
+        let boot_counters = kernel::static_buf!(
+            capsules_system::persistent_counters::BootCounters<'static, $V, $R>
+        );
+        let key_buffer = kernel::static_buf!([u8; capsules_system::persistent_counters::KEY_LEN]);
+        let value_buffer = kernel::static_buf!([u8; $crate::persistent_counters::VALUE_BUFFER_LEN]);
+
+        (boot_counters, key_buffer, value_buffer)
+    };};
+}
+
+pub type BootCountersComponentType<V, R> = BootCounters<'static, V, R>;
+
+pub struct BootCountersComponent<
+    V: hil::kv::KVPermissions<'static> + 'static,
+    R: RetainedRegisters + 'static,
+> {
+    kv: &'static V,
+    retained: &'static R,
+    layout: RetainedLayout,
+}
+
+impl<V: hil::kv::KVPermissions<'static>, R: RetainedRegisters> BootCountersComponent<V, R> {
+    pub fn new(kv: &'static V, retained: &'static R, layout: RetainedLayout) -> Self {
+        Self {
+            kv,
+            retained,
+            layout,
+        }
+    }
+}
+
+impl<V: hil::kv::KVPermissions<'static>, R: RetainedRegisters> Component
+    for BootCountersComponent<V, R>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<BootCounters<'static, V, R>>,
+        &'static mut MaybeUninit<[u8; KEY_LEN]>,
+        &'static mut MaybeUninit<[u8; VALUE_BUFFER_LEN]>,
+    );
+    type Output = &'static BootCounters<'static, V, R>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let storage_cap = create_capability!(capabilities::KerneluserStorageCapability);
+
+        let key_buffer = static_buffer.1.write([0; KEY_LEN]);
+        let value_buffer = static_buffer.2.write([0; VALUE_BUFFER_LEN]);
+
+        let boot_counters = static_buffer.0.write(BootCounters::new(
+            self.kv,
+            StoragePermissions::new_kernel(&storage_cap),
+            self.retained,
+            self.layout,
+            key_buffer,
+            value_buffer,
+        ));
+        self.kv.set_client(boot_counters);
+        // SAFETY: the counters are static and the recorder is only used in
+        // the panic routines.
+        unsafe {
+            kernel::debug::set_panic_recorder(boot_counters);
+        }
+
+        boot_counters
+    }
+}
@@ -0,0 +1,79 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for named pipes between processes.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let pipes = components::pipe::PipeComponent::new(
+//!     board_kernel,
+//!     capsules_extra::pipe::DRIVER_NUM,
+//!     ["sensor", "log"],
+//! )
+//! .finalize(components::pipe_component_static!(2, 128));
+//! ```
+
+use capsules_extra::pipe::{Pipe, PipeDriver};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+
+/// Create the buffers of `$N` pipes that each hold `$LEN - 1` bytes.
+#[macro_export]
+macro_rules! pipe_component_static {
+    ($N:expr, $LEN:expr $(,)?) => {{
+        use capsules_extra::pipe::{Pipe, PipeDriver};
+        use kernel::static_buf;
+        let buffers = static_buf!([[u8; $LEN]; $N]);
+        let pipes = static_buf!([Pipe<'static>; $N]);
+        let driver = static_buf!(PipeDriver<'static>);
+        (buffers, pipes, driver)
+    };};
+}
+
+pub struct PipeComponent<const N: usize, const LEN: usize> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    names: [&'static str; N],
+}
+
+impl<const N: usize, const LEN: usize> PipeComponent<N, LEN> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        names: [&'static str; N],
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            names,
+        }
+    }
+}
+
+impl<const N: usize, const LEN: usize> Component for PipeComponent<N, LEN> {
+    type StaticInput = (
+        &'static mut MaybeUninit<[[u8; LEN]; N]>,
+        &'static mut MaybeUninit<[Pipe<'static>; N]>,
+        &'static mut MaybeUninit<PipeDriver<'static>>,
+    );
+    type Output = &'static PipeDriver<'static>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let mut buffers = s.0.write([[0; LEN]; N]).iter_mut();
+        let pipes = s.1.write(core::array::from_fn(|i| {
+            // `buffers` has exactly `N` elements.
+            Pipe::new(self.names[i], buffers.next().unwrap())
+        }));
+
+        s.2.write(PipeDriver::new(
+            pipes,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ))
+    }
+}
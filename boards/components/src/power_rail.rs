@@ -0,0 +1,234 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Components for power rails.
+//!
+//! This provides four components.
+//!
+//! 1. `GpioPowerRailComponent` provides a power rail switched by a GPIO pin.
+//!
+//! 2. `PowerRailMuxComponent` shares a power rail between its users.
+//!
+//! 3. `PowerRailComponent` provides a user of a shared power rail.
+//!
+//! 4. `PowerGatedSensorComponent` powers a temperature and/or humidity sensor
+//!    only around its measurements.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let sensor_rail = components::power_rail::GpioPowerRailComponent::new(
+//!     mux_alarm,
+//!     "sensors",
+//!     &nrf52840_peripherals.gpio_port[SENSORS_ENABLE],
+//!     kernel::hil::gpio::ActivationMode::ActiveHigh,
+//!     5,
+//! )
+//! .finalize(components::gpio_power_rail_component_static!(nrf52840::rtc::Rtc));
+//! let mux_rail = components::power_rail::PowerRailMuxComponent::new(sensor_rail)
+//!     .finalize(components::power_rail_mux_component_static!());
+//! let hs3003_rail = components::power_rail::PowerRailComponent::new(mux_rail)
+//!     .finalize(components::power_rail_component_static!());
+//! let gated_hs3003 = components::power_rail::PowerGatedSensorComponent::new(hs3003_rail)
+//!     .with_temperature(hs3003)
+//!     .with_humidity(hs3003)
+//!     .finalize(components::power_gated_sensor_component_static!());
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_core::virtualizers::virtual_power_rail::{MuxPowerRail, VirtualPowerRail};
+use capsules_extra::gpio_power_rail::GpioPowerRail;
+use capsules_extra::power_gated_sensor::PowerGatedSensor;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::deferred_call::DeferredCallClient;
+use kernel::hil::gpio::{ActivationMode, Pin};
+use kernel::hil::power_rail::PowerRail;
+use kernel::hil::sensors::{HumidityDriver, TemperatureDriver};
+use kernel::hil::time::{self, Alarm};
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! gpio_power_rail_component_static {
+    ($A:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let rail = kernel::static_buf!(
+            capsules_extra::gpio_power_rail::GpioPowerRail<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+
+        (alarm, rail)
+    };};
+}
+
+#[macro_export]
+macro_rules! power_rail_mux_component_static {
+    () => {{
+        kernel::static_buf!(capsules_core::virtualizers::virtual_power_rail::MuxPowerRail<'static>)
+    };};
+}
+
+#[macro_export]
+macro_rules! power_rail_component_static {
+    () => {{
+        kernel::static_buf!(
+            capsules_core::virtualizers::virtual_power_rail::VirtualPowerRail<'static>
+        )
+    };};
+}
+
+#[macro_export]
+macro_rules! power_gated_sensor_component_static {
+    () => {{
+        kernel::static_buf!(capsules_extra::power_gated_sensor::PowerGatedSensor<'static>)
+    };};
+}
+
+pub type GpioPowerRailComponentType<A> = GpioPowerRail<'static, VirtualMuxAlarm<'static, A>>;
+
+pub struct GpioPowerRailComponent<A: 'static + Alarm<'static>> {
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    name: &'static str,
+    pin: &'static dyn Pin,
+    mode: ActivationMode,
+    settle_ms: u32,
+}
+
+impl<A: 'static + Alarm<'static>> GpioPowerRailComponent<A> {
+    pub fn new(
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        name: &'static str,
+        pin: &'static dyn Pin,
+        mode: ActivationMode,
+        settle_ms: u32,
+    ) -> Self {
+        Self {
+            alarm_mux,
+            name,
+            pin,
+            mode,
+            settle_ms,
+        }
+    }
+}
+
+impl<A: 'static + Alarm<'static>> Component for GpioPowerRailComponent<A> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<GpioPowerRail<'static, VirtualMuxAlarm<'static, A>>>,
+    );
+    type Output = &'static GpioPowerRail<'static, VirtualMuxAlarm<'static, A>>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let alarm = s.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let rail = s.1.write(GpioPowerRail::new(
+            self.name,
+            self.pin,
+            self.mode,
+            alarm,
+            self.settle_ms,
+        ));
+        time::Alarm::set_alarm_client(alarm, rail);
+        rail.register();
+
+        rail
+    }
+}
+
+pub struct PowerRailMuxComponent {
+    rail: &'static dyn PowerRail<'static>,
+}
+
+impl PowerRailMuxComponent {
+    pub fn new(rail: &'static dyn PowerRail<'static>) -> Self {
+        Self { rail }
+    }
+}
+
+impl Component for PowerRailMuxComponent {
+    type StaticInput = &'static mut MaybeUninit<MuxPowerRail<'static>>;
+    type Output = &'static MuxPowerRail<'static>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let mux_rail = static_buffer.write(MuxPowerRail::new(self.rail));
+        self.rail.set_client(mux_rail);
+        mux_rail.register();
+
+        mux_rail
+    }
+}
+
+pub struct PowerRailComponent {
+    mux_rail: &'static MuxPowerRail<'static>,
+}
+
+impl PowerRailComponent {
+    pub fn new(mux_rail: &'static MuxPowerRail<'static>) -> Self {
+        Self { mux_rail }
+    }
+}
+
+impl Component for PowerRailComponent {
+    type StaticInput = &'static mut MaybeUninit<VirtualPowerRail<'static>>;
+    type Output = &'static VirtualPowerRail<'static>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let rail = static_buffer.write(VirtualPowerRail::new(self.mux_rail));
+        rail.setup();
+
+        rail
+    }
+}
+
+pub struct PowerGatedSensorComponent {
+    rail: &'static dyn PowerRail<'static>,
+    temperature: Option<&'static dyn TemperatureDriver<'static>>,
+    humidity: Option<&'static dyn HumidityDriver<'static>>,
+}
+
+impl PowerGatedSensorComponent {
+    pub fn new(rail: &'static dyn PowerRail<'static>) -> Self {
+        Self {
+            rail,
+            temperature: None,
+            humidity: None,
+        }
+    }
+
+    pub fn with_temperature(mut self, sensor: &'static dyn TemperatureDriver<'static>) -> Self {
+        self.temperature = Some(sensor);
+        self
+    }
+
+    pub fn with_humidity(mut self, sensor: &'static dyn HumidityDriver<'static>) -> Self {
+        self.humidity = Some(sensor);
+        self
+    }
+}
+
+impl Component for PowerGatedSensorComponent {
+    type StaticInput = &'static mut MaybeUninit<PowerGatedSensor<'static>>;
+    type Output = &'static PowerGatedSensor<'static>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let gated = static_buffer.write(PowerGatedSensor::new(self.rail));
+        self.rail.set_client(gated);
+        if let Some(sensor) = self.temperature {
+            gated.set_temperature_sensor(sensor);
+            sensor.set_client(gated);
+        }
+        if let Some(sensor) = self.humidity {
+            gated.set_humidity_sensor(sensor);
+            sensor.set_client(gated);
+        }
+
+        gated
+    }
+}
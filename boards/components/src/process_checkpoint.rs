@@ -0,0 +1,127 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for checkpointing processes to flash.
+//!
+//! `ProcessCheckpointerComponent` writes checkpoints of processes to
+//! `num_slots` slots of `slot_size` bytes, starting at `storage_start` of a
+//! flash, typically an external flash chip. The process in slot `i` of the
+//! processes array uses slot `i`.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let checkpointer = components::process_checkpoint::ProcessCheckpointerComponent::new(
+//!     board_kernel,
+//!     flash_user,
+//!     0x780000,
+//!     0x10000,
+//!     NUM_PROCS,
+//! )
+//! .finalize(components::process_checkpointer_component_static!(
+//!     capsules_core::virtualizers::virtual_flash::FlashUser<'static, Mx25r6435f>,
+//! ));
+//! pconsole.set_process_checkpoint(checkpointer);
+//! kernel::process::ProcessCheckpoint::set_client(checkpointer, pconsole);
+//! ```
+
+use capsules_extra::nonvolatile_to_pages::NonvolatileToPages;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil;
+use kernel::process::ProcessCheckpointer;
+
+/// Size of the buffer used to copy process memory to and from flash.
+pub const BUF_LEN: usize = 512;
+
+#[macro_export]
+macro_rules! process_checkpointer_component_static {
+    ($F:ty $(,)?) => {{
+        let page = kernel::static_buf!(<$F as kernel::hil::flash::Flash>::Page);
+        let ntp = kernel::static_buf!(
+            capsules_extra::nonvolatile_to_pages::NonvolatileToPages<'static, $F>
+        );
+        let buffer = kernel::static_buf!([u8; $crate::process_checkpoint::BUF_LEN]);
+        let checkpointer = kernel::static_buf!(kernel::process::ProcessCheckpointer<'static>);
+
+        (page, ntp, buffer, checkpointer)
+    };};
+}
+
+pub struct ProcessCheckpointerComponent<
+    F: 'static + hil::flash::Flash + hil::flash::HasClient<'static, NonvolatileToPages<'static, F>>,
+> {
+    board_kernel: &'static kernel::Kernel,
+    flash: &'static F,
+    storage_start: usize,
+    slot_size: usize,
+    num_slots: usize,
+}
+
+impl<
+        F: 'static
+            + hil::flash::Flash
+            + hil::flash::HasClient<'static, NonvolatileToPages<'static, F>>,
+    > ProcessCheckpointerComponent<F>
+{
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        flash: &'static F,
+        storage_start: usize,
+        slot_size: usize,
+        num_slots: usize,
+    ) -> Self {
+        Self {
+            board_kernel,
+            flash,
+            storage_start,
+            slot_size,
+            num_slots,
+        }
+    }
+}
+
+impl<
+        F: 'static
+            + hil::flash::Flash
+            + hil::flash::HasClient<'static, NonvolatileToPages<'static, F>>,
+    > Component for ProcessCheckpointerComponent<F>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<<F as hil::flash::Flash>::Page>,
+        &'static mut MaybeUninit<NonvolatileToPages<'static, F>>,
+        &'static mut MaybeUninit<[u8; BUF_LEN]>,
+        &'static mut MaybeUninit<ProcessCheckpointer<'static>>,
+    );
+
+    type Output = &'static ProcessCheckpointer<'static>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let process_management_capability =
+            create_capability!(capabilities::ProcessManagementCapability);
+
+        let flash_pagebuffer = s.0.write(<F as hil::flash::Flash>::Page::default());
+        let nv_to_page =
+            s.1.write(NonvolatileToPages::new(self.flash, flash_pagebuffer));
+        hil::flash::HasClient::set_client(self.flash, nv_to_page);
+
+        let buffer = s.2.write([0; BUF_LEN]);
+
+        let checkpointer = s.3.write(ProcessCheckpointer::new(
+            self.board_kernel,
+            nv_to_page,
+            buffer,
+            self.storage_start,
+            self.slot_size,
+            self.num_slots,
+            &process_management_capability,
+        ));
+        hil::nonvolatile_storage::NonvolatileStorage::set_client(nv_to_page, checkpointer);
+
+        checkpointer
+    }
+}
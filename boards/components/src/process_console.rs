@@ -105,6 +105,8 @@ extern "C" {
     static _erelocate: u8;
     static _szero: u8;
     static _ezero: u8;
+    static _sattributes: u8;
+    static _eattributes: u8;
 }
 
 pub struct Capability;
@@ -150,6 +152,15 @@ impl<const COMMAND_HISTORY_LEN: usize, A: 'static + Alarm<'static>> Component
             relocations_end: core::ptr::addr_of!(_erelocate),
             bss_start: core::ptr::addr_of!(_szero),
             bss_end: core::ptr::addr_of!(_ezero),
+            // SAFETY: The attributes region is in the kernel flash, which is
+            // not written while the kernel runs.
+            attributes: unsafe {
+                core::slice::from_raw_parts(
+                    core::ptr::addr_of!(_sattributes),
+                    core::ptr::addr_of!(_eattributes) as usize
+                        - core::ptr::addr_of!(_sattributes) as usize,
+                )
+            },
         };
 
         let console_alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
@@ -188,6 +199,7 @@ impl<const COMMAND_HISTORY_LEN: usize, A: 'static + Alarm<'static>> Component
         hil::uart::Transmit::set_transmit_client(console_uart, console);
         hil::uart::Receive::set_receive_client(console_uart, console);
         console_alarm.set_alarm_client(console);
+        console.set_uart_configure(console_uart);
 
         console
     }
@@ -46,11 +46,17 @@ macro_rules! pwm_driver_component_helper {
 
 pub struct PwmMuxComponent<P: 'static + pwm::Pwm> {
     pwm: &'static P,
+    channels: usize,
 }
 
 impl<P: 'static + pwm::Pwm> PwmMuxComponent<P> {
     pub fn new(pwm: &'static P) -> Self {
-        PwmMuxComponent { pwm }
+        PwmMuxComponent { pwm, channels: 1 }
+    }
+
+    /// Share a PWM that drives up to `channels` pins at the same time.
+    pub fn new_with_channels(pwm: &'static P, channels: usize) -> Self {
+        PwmMuxComponent { pwm, channels }
     }
 }
 
@@ -59,7 +65,7 @@ impl<P: 'static + pwm::Pwm> Component for PwmMuxComponent<P> {
     type Output = &'static MuxPwm<'static, P>;
 
     fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
-        let pwm_mux = static_buffer.write(MuxPwm::new(self.pwm));
+        let pwm_mux = static_buffer.write(MuxPwm::new_with_channels(self.pwm, self.channels));
 
         pwm_mux
     }
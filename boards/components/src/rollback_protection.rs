@@ -0,0 +1,87 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for rollback protection with the minimum application versions
+//! in nonvolatile storage.
+//!
+//! The storage should not be shared with other users, since a failed write
+//! disables the write back of later updates.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let rollback = components::rollback_protection::RollbackProtectionComponent::new(
+//!     nv_to_page,
+//!     ROLLBACK_TABLE_ADDRESS,
+//!     // SAFETY: the table is in flash that is only written through
+//!     // `nv_to_page`.
+//!     unsafe { core::slice::from_raw_parts(ROLLBACK_TABLE_ADDRESS as *const u8, 64) },
+//! )
+//! .finalize(components::rollback_protection_component_static!(8));
+//! loader.set_rollback_protection(rollback);
+//! ```
+
+use capsules_system::rollback_protection::{NonvolatileRollbackProtection, ENTRY_LEN};
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::nonvolatile_storage::NonvolatileStorage;
+
+/// Create the static state for `$N` applications.
+#[macro_export]
+macro_rules! rollback_protection_component_static {
+    ($N:expr $(,)?) => {{
+        let rollback = kernel::static_buf!(
+            capsules_system::rollback_protection::NonvolatileRollbackProtection<'static, $N>
+        );
+        let buffer =
+            kernel::static_buf!([[u8; capsules_system::rollback_protection::ENTRY_LEN]; $N]);
+
+        (rollback, buffer)
+    };};
+}
+
+pub type RollbackProtectionComponentType<const N: usize> =
+    NonvolatileRollbackProtection<'static, N>;
+
+pub struct RollbackProtectionComponent<const N: usize> {
+    storage: &'static dyn NonvolatileStorage<'static>,
+    address: usize,
+    stored: &'static [u8],
+}
+
+impl<const N: usize> RollbackProtectionComponent<N> {
+    /// `stored` is the memory-mapped content of the table at `address` in
+    /// `storage`.
+    pub fn new(
+        storage: &'static dyn NonvolatileStorage<'static>,
+        address: usize,
+        stored: &'static [u8],
+    ) -> Self {
+        Self {
+            storage,
+            address,
+            stored,
+        }
+    }
+}
+
+impl<const N: usize> Component for RollbackProtectionComponent<N> {
+    type StaticInput = (
+        &'static mut MaybeUninit<NonvolatileRollbackProtection<'static, N>>,
+        &'static mut MaybeUninit<[[u8; ENTRY_LEN]; N]>,
+    );
+    type Output = &'static NonvolatileRollbackProtection<'static, N>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let buffer = s.1.write([[0; ENTRY_LEN]; N]).as_flattened_mut();
+        let rollback = s.0.write(NonvolatileRollbackProtection::new(
+            self.storage,
+            self.address,
+            self.stored,
+            buffer,
+        ));
+        self.storage.set_client(rollback);
+        rollback
+    }
+}
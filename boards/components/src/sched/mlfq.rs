@@ -4,7 +4,26 @@
 
 //! Component for a multi-level feedback queue scheduler.
 //!
-//! This provides one Component, MLFQComponent.
+//! This provides one Component, MLFQComponent. By default, the scheduler has
+//! three queues; `MLFQComponent::new_with_queues` configures the number of
+//! queues, their timeslices and how often processes are restored to the
+//! highest priority.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let scheduler = components::sched::mlfq::MLFQComponent::new_with_queues(
+//!     mux_alarm,
+//!     &*addr_of!(PROCESSES),
+//!     [5000, 10000, 20000, 40000],
+//!     2000,
+//! )
+//! .finalize(components::mlfq_component_static!(
+//!     nrf52840::rtc::Rtc,
+//!     NUM_PROCS,
+//!     4
+//! ));
+//! ```
 
 // Author: Hudson Ayers <hayers@stanford.edu>
 // Last modified: 03/31/2020
@@ -20,6 +39,9 @@ use kernel::scheduler::mlfq::{MLFQProcessNode, MLFQSched};
 #[macro_export]
 macro_rules! mlfq_component_static {
     ($A:ty, $N:expr $(,)?) => {{
+        $crate::mlfq_component_static!($A, $N, 3)
+    };};
+    ($A:ty, $N:expr, $Q:expr $(,)?) => {{
         let alarm = kernel::static_buf!(
             capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
         );
@@ -27,6 +49,7 @@ macro_rules! mlfq_component_static {
             kernel::scheduler::mlfq::MLFQSched<
                 'static,
                 capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                $Q,
             >
         );
         let mlfq_node = kernel::static_buf!(
@@ -37,9 +60,18 @@ macro_rules! mlfq_component_static {
     };};
 }
 
-pub struct MLFQComponent<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize> {
+pub type MLFQComponentType<A, const NUM_QUEUES: usize = 3> =
+    MLFQSched<'static, VirtualMuxAlarm<'static, A>, NUM_QUEUES>;
+
+pub struct MLFQComponent<
+    A: 'static + time::Alarm<'static>,
+    const NUM_PROCS: usize,
+    const NUM_QUEUES: usize = 3,
+> {
     alarm_mux: &'static MuxAlarm<'static, A>,
     processes: &'static [Option<&'static dyn Process>],
+    timeslices_us: [u32; NUM_QUEUES],
+    priority_refresh_period_ms: u32,
 }
 
 impl<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize> MLFQComponent<A, NUM_PROCS> {
@@ -50,26 +82,52 @@ impl<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize> MLFQComponent<A,
         MLFQComponent {
             alarm_mux,
             processes,
+            timeslices_us: MLFQSched::<VirtualMuxAlarm<'static, A>>::TIMESLICES_US,
+            priority_refresh_period_ms:
+                MLFQSched::<VirtualMuxAlarm<'static, A>>::PRIORITY_REFRESH_PERIOD_MS,
+        }
+    }
+}
+
+impl<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize, const NUM_QUEUES: usize>
+    MLFQComponent<A, NUM_PROCS, NUM_QUEUES>
+{
+    /// Create a scheduler with one queue for each of `timeslices_us`, see
+    /// `MLFQSched::new_with_queues`.
+    pub fn new_with_queues(
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        processes: &'static [Option<&'static dyn Process>],
+        timeslices_us: [u32; NUM_QUEUES],
+        priority_refresh_period_ms: u32,
+    ) -> MLFQComponent<A, NUM_PROCS, NUM_QUEUES> {
+        MLFQComponent {
+            alarm_mux,
+            processes,
+            timeslices_us,
+            priority_refresh_period_ms,
         }
     }
 }
 
-impl<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize> Component
-    for MLFQComponent<A, NUM_PROCS>
+impl<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize, const NUM_QUEUES: usize> Component
+    for MLFQComponent<A, NUM_PROCS, NUM_QUEUES>
 {
     type StaticInput = (
         &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
-        &'static mut MaybeUninit<MLFQSched<'static, VirtualMuxAlarm<'static, A>>>,
+        &'static mut MaybeUninit<MLFQSched<'static, VirtualMuxAlarm<'static, A>, NUM_QUEUES>>,
         &'static mut MaybeUninit<[MaybeUninit<MLFQProcessNode<'static>>; NUM_PROCS]>,
     );
-    type Output = &'static mut MLFQSched<'static, VirtualMuxAlarm<'static, A>>;
+    type Output = &'static mut MLFQSched<'static, VirtualMuxAlarm<'static, A>, NUM_QUEUES>;
 
     fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
         let scheduler_alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
         scheduler_alarm.setup();
 
-        let scheduler = static_buffer.1.write(MLFQSched::new(scheduler_alarm));
-
+        let scheduler = static_buffer.1.write(MLFQSched::new_with_queues(
+            scheduler_alarm,
+            self.timeslices_us,
+            self.priority_refresh_period_ms,
+        ));
         const UNINIT: MaybeUninit<MLFQProcessNode<'static>> = MaybeUninit::uninit();
         let nodes = static_buffer.2.write([UNINIT; NUM_PROCS]);
 
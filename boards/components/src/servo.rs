@@ -7,13 +7,17 @@
 //! Usage
 //! -----
 //! ```rust
-//! let servo = components::servo::ServosComponent::new().finalize(components::servo_component_static!(
-//!     servo1, servo2,
-//! ));
+//! let servo = components::servo::ServosComponent::new(
+//!     board_kernel,
+//!     capsules_extra::servo::DRIVER_NUM,
+//! )
+//! .finalize(components::servo_component_static!(servo1, servo2,));
 //! ```
 use capsules_extra::servo::Servo as ServoDriver;
 use core::mem::MaybeUninit;
+use kernel::capabilities;
 use kernel::component::Component;
+use kernel::create_capability;
 
 #[macro_export]
 macro_rules! servo_component_static {
@@ -39,11 +43,17 @@ macro_rules! servo_component_static {
 
 pub type ServosComponentType<const SERVO_COUNT: usize> = ServoDriver<'static, SERVO_COUNT>;
 
-pub struct ServosComponent<const SERVO_COUNT: usize> {}
+pub struct ServosComponent<const SERVO_COUNT: usize> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+}
 
 impl<const SERVO_COUNT: usize> ServosComponent<SERVO_COUNT> {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(board_kernel: &'static kernel::Kernel, driver_num: usize) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+        }
     }
 }
 
@@ -55,6 +65,10 @@ impl<const SERVO_COUNT: usize> Component for ServosComponent<SERVO_COUNT> {
     type Output = &'static ServoDriver<'static, SERVO_COUNT>;
 
     fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
-        static_buffer.0.write(ServoDriver::new(static_buffer.1))
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+        static_buffer.0.write(ServoDriver::new(
+            static_buffer.1,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ))
     }
 }
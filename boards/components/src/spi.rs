@@ -247,6 +247,8 @@ impl<S: 'static + spi::SpiSlave<'static>> Component for SpiSyscallPComponent<S>
 
         spi_syscallsp.config_buffers(spi_read_buf, spi_write_buf);
         syscallp_spi_device.set_client(spi_syscallsp);
+        self.spi_slave.set_client(Some(syscallp_spi_device));
+        let _ = self.spi_slave.init();
 
         spi_syscallsp
     }
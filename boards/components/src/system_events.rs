@@ -0,0 +1,56 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the SystemEvents driver, which delivers lifecycle and
+//! system events to processes.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let system_events = components::system_events::SystemEventsComponent::new(
+//!     board_kernel,
+//!     capsules_extra::system_events::DRIVER_NUM,
+//! )
+//! .finalize(components::system_events_component_static!());
+//! ```
+
+use capsules_extra::system_events::SystemEvents;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+
+#[macro_export]
+macro_rules! system_events_component_static {
+    () => {{
+        kernel::static_buf!(capsules_extra::system_events::SystemEvents<'static>)
+    };};
+}
+
+pub struct SystemEventsComponent {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+}
+
+impl SystemEventsComponent {
+    pub fn new(board_kernel: &'static kernel::Kernel, driver_num: usize) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+        }
+    }
+}
+
+impl Component for SystemEventsComponent {
+    type StaticInput = &'static mut MaybeUninit<SystemEvents<'static>>;
+    type Output = &'static SystemEvents<'static>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        s.write(SystemEvents::new(
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ))
+    }
+}
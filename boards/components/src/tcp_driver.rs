@@ -0,0 +1,112 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component to initialize the userland TCP driver.
+//!
+//! This provides one Component, TcpDriverComponent. This component creates
+//! `NUM_SOCKETS` TCP sockets on the given MuxTcp, and a userspace driver that
+//! gives apps access to them.
+//!
+//! Usage
+//! -----
+//! ```rust
+//!    let tcp_driver = TcpDriverComponent::new(
+//!        board_kernel,
+//!        capsules_extra::net::tcp::DRIVER_NUM,
+//!        tcp_mux,
+//!    )
+//!    .finalize(components::tcp_driver_component_static!(nrf52840::rtc::Rtc, 2));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm;
+use capsules_extra::net::tcp::tcp_socket::{MuxTcp, TcpSocket};
+use capsules_extra::net::tcp::TcpDriver;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::time::Alarm;
+
+/// Size of the transmit and receive buffers of each socket.
+pub const TCP_SOCKET_BUF_LEN: usize = 512;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! tcp_driver_component_static {
+    ($A:ty, $N:expr $(,)?) => {{
+        use capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm;
+        use components::tcp_driver::TCP_SOCKET_BUF_LEN;
+
+        let sockets = kernel::static_buf!(
+            [capsules_extra::net::tcp::tcp_socket::TcpSocket<'static, VirtualMuxAlarm<'static, $A>>;
+                $N]
+        );
+        let tx_buffers = kernel::static_buf!([[u8; TCP_SOCKET_BUF_LEN]; $N]);
+        let rx_buffers = kernel::static_buf!([[u8; TCP_SOCKET_BUF_LEN]; $N]);
+        let tcp_driver = kernel::static_buf!(
+            capsules_extra::net::tcp::TcpDriver<'static, VirtualMuxAlarm<'static, $A>, $N>
+        );
+
+        (sockets, tx_buffers, rx_buffers, tcp_driver)
+    };};
+}
+
+pub type TcpDriverComponentType<A, const NUM_SOCKETS: usize> =
+    TcpDriver<'static, VirtualMuxAlarm<'static, A>, NUM_SOCKETS>;
+
+pub struct TcpDriverComponent<A: Alarm<'static> + 'static, const NUM_SOCKETS: usize> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    tcp_mux: &'static MuxTcp<'static, VirtualMuxAlarm<'static, A>>,
+}
+
+impl<A: Alarm<'static>, const NUM_SOCKETS: usize> TcpDriverComponent<A, NUM_SOCKETS> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        tcp_mux: &'static MuxTcp<'static, VirtualMuxAlarm<'static, A>>,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            tcp_mux,
+        }
+    }
+}
+
+impl<A: Alarm<'static>, const NUM_SOCKETS: usize> Component for TcpDriverComponent<A, NUM_SOCKETS> {
+    type StaticInput = (
+        &'static mut MaybeUninit<[TcpSocket<'static, VirtualMuxAlarm<'static, A>>; NUM_SOCKETS]>,
+        &'static mut MaybeUninit<[[u8; TCP_SOCKET_BUF_LEN]; NUM_SOCKETS]>,
+        &'static mut MaybeUninit<[[u8; TCP_SOCKET_BUF_LEN]; NUM_SOCKETS]>,
+        &'static mut MaybeUninit<TcpDriver<'static, VirtualMuxAlarm<'static, A>, NUM_SOCKETS>>,
+    );
+    type Output = &'static TcpDriver<'static, VirtualMuxAlarm<'static, A>, NUM_SOCKETS>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let mut tx_buffers = s.1.write([[0; TCP_SOCKET_BUF_LEN]; NUM_SOCKETS]).iter_mut();
+        let mut rx_buffers = s.2.write([[0; TCP_SOCKET_BUF_LEN]; NUM_SOCKETS]).iter_mut();
+        let sockets = s.0.write(core::array::from_fn(|id| {
+            TcpSocket::new(
+                id,
+                self.tcp_mux,
+                tx_buffers.next().unwrap(),
+                rx_buffers.next().unwrap(),
+            )
+        }));
+
+        let tcp_driver = s.3.write(TcpDriver::new(
+            sockets,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        for socket in sockets.iter() {
+            self.tcp_mux.add_socket(socket);
+            socket.set_client(tcp_driver);
+        }
+
+        tcp_driver
+    }
+}
@@ -0,0 +1,272 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component to initialize the tcp/6lowpan interface.
+//!
+//! This provides one Component, TCPMuxComponent. This component creates a
+//! MuxTcp, with its own IPv6 sender and receiver on top of the given MAC
+//! mux, that TCP sockets can be added to.
+//!
+//! Usage
+//! -----
+//! ```rust
+//!    let tcp_mux = TCPMuxComponent::new(
+//!        mux_mac,
+//!        DEFAULT_CTX_PREFIX_LEN,
+//!        DEFAULT_CTX_PREFIX,
+//!        DST_MAC_ADDR,
+//!        src_mac_from_serial_num,
+//!        local_ip_ifaces,
+//!        mux_alarm,
+//!    )
+//!    .finalize(components::tcp_mux_component_static!(
+//!        nrf52840::rtc::Rtc,
+//!        nrf52840::ieee802154_radio::Radio
+//!    ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::ieee802154::device::MacDevice;
+use capsules_extra::net::ieee802154::MacAddress;
+use capsules_extra::net::ipv6::ip_utils::IPAddr;
+use capsules_extra::net::ipv6::ipv6_recv::IP6Receiver;
+use capsules_extra::net::ipv6::ipv6_recv::IP6RecvStruct;
+use capsules_extra::net::ipv6::ipv6_send::IP6SendStruct;
+use capsules_extra::net::ipv6::ipv6_send::IP6Sender;
+use capsules_extra::net::ipv6::{IP6Packet, IPPayload, TransportHeader};
+use capsules_extra::net::network_capabilities::{
+    AddrRange, IpVisibilityCapability, NetworkCapability, PortRange,
+};
+use capsules_extra::net::sixlowpan::{sixlowpan_compression, sixlowpan_state};
+use capsules_extra::net::tcp::tcp_port_table::{TcpPortManager, MAX_NUM_BOUND_PORTS};
+use capsules_extra::net::tcp::tcp_socket::MuxTcp;
+use capsules_extra::net::tcp::TCPHeader;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::radio;
+use kernel::hil::time::Alarm;
+
+/// The max size of the payload of a TCP segment.
+pub const MAX_SEGMENT_LEN: usize = 200;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! tcp_mux_component_static {
+    ($A:ty, $M:ty $(,)?) => {{
+        use capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm;
+        use capsules_extra::net::sixlowpan::{sixlowpan_compression, sixlowpan_state};
+        use components::tcp_mux::MAX_SEGMENT_LEN;
+
+        let ip_alarm = kernel::static_buf!(VirtualMuxAlarm<'static, $A>);
+        let tcp_alarm = kernel::static_buf!(VirtualMuxAlarm<'static, $A>);
+        let mac_user =
+            kernel::static_buf!(capsules_extra::ieee802154::virtual_mac::MacUser<'static, $M>);
+        let sixlowpan = kernel::static_buf!(
+            sixlowpan_state::Sixlowpan<
+                'static,
+                VirtualMuxAlarm<'static, $A>,
+                sixlowpan_compression::Context,
+            >
+        );
+        let rx_state = kernel::static_buf!(sixlowpan_state::RxState<'static>);
+        let ip6_send = kernel::static_buf!(
+            capsules_extra::net::ipv6::ipv6_send::IP6SendStruct<
+                'static,
+                VirtualMuxAlarm<'static, $A>,
+            >
+        );
+        let ip6_receive =
+            kernel::static_buf!(capsules_extra::net::ipv6::ipv6_recv::IP6RecvStruct<'static>);
+        let ip6_packet = kernel::static_buf!(capsules_extra::net::ipv6::IP6Packet<'static>);
+        let tcp_mux = kernel::static_buf!(
+            capsules_extra::net::tcp::tcp_socket::MuxTcp<'static, VirtualMuxAlarm<'static, $A>>
+        );
+        let tcp_port_manager =
+            kernel::static_buf!(capsules_extra::net::tcp::tcp_port_table::TcpPortManager);
+        let used_ports = kernel::static_buf!(
+            [Option<u16>; capsules_extra::net::tcp::tcp_port_table::MAX_NUM_BOUND_PORTS]
+        );
+
+        let radio_buf = kernel::static_buf!([u8; kernel::hil::radio::MAX_BUF_SIZE]);
+        let sixlowpan_rx = kernel::static_buf!([u8; 1280]);
+        let ip6_payload = kernel::static_buf!([u8; MAX_SEGMENT_LEN]);
+        let tcp_tx = kernel::static_buf!([u8; MAX_SEGMENT_LEN]);
+
+        let ip_vis_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::IpVisibilityCapability);
+        let net_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::NetworkCapability);
+
+        (
+            ip_alarm,
+            tcp_alarm,
+            mac_user,
+            sixlowpan,
+            rx_state,
+            ip6_send,
+            ip6_receive,
+            ip6_packet,
+            tcp_mux,
+            tcp_port_manager,
+            used_ports,
+            radio_buf,
+            sixlowpan_rx,
+            ip6_payload,
+            tcp_tx,
+            ip_vis_cap,
+            net_cap,
+        )
+    };};
+}
+
+pub type TCPMuxComponentType<A> = MuxTcp<'static, VirtualMuxAlarm<'static, A>>;
+
+pub struct TCPMuxComponent<A: Alarm<'static> + 'static, M: MacDevice<'static> + 'static> {
+    mux_mac: &'static capsules_extra::ieee802154::virtual_mac::MuxMac<'static, M>,
+    ctx_pfix_len: u8,
+    ctx_pfix: [u8; 16],
+    dst_mac_addr: MacAddress,
+    src_mac_addr: MacAddress,
+    interface_list: &'static [IPAddr],
+    alarm_mux: &'static MuxAlarm<'static, A>,
+}
+
+impl<A: Alarm<'static> + 'static, M: MacDevice<'static>> TCPMuxComponent<A, M> {
+    pub fn new(
+        mux_mac: &'static capsules_extra::ieee802154::virtual_mac::MuxMac<'static, M>,
+        ctx_pfix_len: u8,
+        ctx_pfix: [u8; 16],
+        dst_mac_addr: MacAddress,
+        src_mac_addr: MacAddress,
+        interface_list: &'static [IPAddr],
+        alarm_mux: &'static MuxAlarm<'static, A>,
+    ) -> Self {
+        Self {
+            mux_mac,
+            ctx_pfix_len,
+            ctx_pfix,
+            dst_mac_addr,
+            src_mac_addr,
+            interface_list,
+            alarm_mux,
+        }
+    }
+}
+
+impl<A: Alarm<'static> + 'static, M: MacDevice<'static>> Component for TCPMuxComponent<A, M> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<capsules_extra::ieee802154::virtual_mac::MacUser<'static, M>>,
+        &'static mut MaybeUninit<
+            sixlowpan_state::Sixlowpan<
+                'static,
+                VirtualMuxAlarm<'static, A>,
+                sixlowpan_compression::Context,
+            >,
+        >,
+        &'static mut MaybeUninit<sixlowpan_state::RxState<'static>>,
+        &'static mut MaybeUninit<IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>>,
+        &'static mut MaybeUninit<IP6RecvStruct<'static>>,
+        &'static mut MaybeUninit<IP6Packet<'static>>,
+        &'static mut MaybeUninit<MuxTcp<'static, VirtualMuxAlarm<'static, A>>>,
+        &'static mut MaybeUninit<TcpPortManager>,
+        &'static mut MaybeUninit<[Option<u16>; MAX_NUM_BOUND_PORTS]>,
+        &'static mut MaybeUninit<[u8; radio::MAX_BUF_SIZE]>,
+        &'static mut MaybeUninit<[u8; 1280]>,
+        &'static mut MaybeUninit<[u8; MAX_SEGMENT_LEN]>,
+        &'static mut MaybeUninit<[u8; MAX_SEGMENT_LEN]>,
+        &'static mut MaybeUninit<IpVisibilityCapability>,
+        &'static mut MaybeUninit<NetworkCapability>,
+    );
+    type Output = &'static MuxTcp<'static, VirtualMuxAlarm<'static, A>>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let ipsender_virtual_alarm = s.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        ipsender_virtual_alarm.setup();
+        let tcp_virtual_alarm = s.1.write(VirtualMuxAlarm::new(self.alarm_mux));
+        tcp_virtual_alarm.setup();
+
+        let tcp_mac =
+            s.2.write(capsules_extra::ieee802154::virtual_mac::MacUser::new(
+                self.mux_mac,
+            ));
+        self.mux_mac.add_user(tcp_mac);
+        let create_cap = create_capability!(capabilities::NetworkCapabilityCreationCapability);
+        let ip_vis = s.15.write(IpVisibilityCapability::new(&create_cap));
+        let net_cap = s.16.write(NetworkCapability::new(
+            AddrRange::Any,
+            PortRange::Any,
+            PortRange::Any,
+            &create_cap,
+        ));
+
+        let sixlowpan = s.3.write(sixlowpan_state::Sixlowpan::new(
+            sixlowpan_compression::Context {
+                prefix: self.ctx_pfix,
+                prefix_len: self.ctx_pfix_len,
+                id: 0,
+                compress: false,
+            },
+            ipsender_virtual_alarm, // OK to reuse bc only used to get time, not set alarms
+        ));
+
+        let sixlowpan_rx_buffer = s.12.write([0; 1280]);
+        let sixlowpan_state = sixlowpan as &dyn sixlowpan_state::SixlowpanState;
+        let sixlowpan_tx = sixlowpan_state::TxState::new(sixlowpan_state);
+        let default_rx_state =
+            s.4.write(sixlowpan_state::RxState::new(sixlowpan_rx_buffer));
+        sixlowpan_state.add_rx_state(default_rx_state);
+        tcp_mac.set_receive_client(sixlowpan);
+
+        let ip6_payload = s.13.write([0; MAX_SEGMENT_LEN]);
+        let ip_pyld: IPPayload = IPPayload {
+            header: TransportHeader::TCP(TCPHeader::new()),
+            payload: ip6_payload,
+        };
+        let ip6_dg = s.7.write(IP6Packet::new(ip_pyld));
+
+        let radio_buf = s.11.write([0; radio::MAX_BUF_SIZE]);
+
+        // As for UDP, the IP sender holds the destination MAC address, so all
+        // segments are sent through the same gateway.
+        let ip_send = s.5.write(IP6SendStruct::new(
+            ip6_dg,
+            ipsender_virtual_alarm,
+            radio_buf,
+            sixlowpan_tx,
+            tcp_mac,
+            self.dst_mac_addr,
+            self.src_mac_addr,
+            ip_vis,
+        ));
+        ipsender_virtual_alarm.set_alarm_client(ip_send);
+        ip_send.set_addr(self.interface_list[0]);
+        tcp_mac.set_transmit_client(ip_send);
+
+        let ip_receive = s.6.write(IP6RecvStruct::new());
+        sixlowpan_state.set_rx_client(ip_receive);
+
+        let used_ports = s.10.write([None; MAX_NUM_BOUND_PORTS]);
+        let create_table_cap = create_capability!(capabilities::CreatePortTableCapability);
+        let port_table =
+            s.9.write(TcpPortManager::new(&create_table_cap, used_ports));
+
+        let tcp_tx_buffer = s.14.write([0; MAX_SEGMENT_LEN]);
+        let tcp_mux = s.8.write(MuxTcp::new(
+            ip_send,
+            tcp_virtual_alarm,
+            port_table,
+            tcp_tx_buffer,
+            net_cap,
+        ));
+        ip_send.set_client(tcp_mux);
+        ip_receive.set_client(tcp_mux);
+        tcp_virtual_alarm.set_alarm_client(tcp_mux);
+
+        tcp_mux
+    }
+}
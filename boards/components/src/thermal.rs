@@ -0,0 +1,192 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for thermal monitoring.
+//!
+//! The temperature driver of processes can still use the sensor, through the
+//! thermal manager.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let thermal_policy = components::thermal::PauseProcessesPolicyComponent::new(
+//!     board_kernel,
+//!     capsules_extra::thermal::ThermalLevel::Critical,
+//!     None,
+//! )
+//! .finalize(components::pause_processes_policy_component_static!(NUM_PROCS));
+//! let thermal = components::thermal::ThermalManagerComponent::new(
+//!     board_kernel,
+//!     capsules_extra::thermal::DRIVER_NUM,
+//!     mux_alarm,
+//!     &base_peripherals.temp,
+//!     THERMAL_THRESHOLDS,
+//!     1000,
+//!     Some(thermal_policy),
+//! )
+//! .finalize(components::thermal_manager_component_static!(
+//!     nrf52840::rtc::Rtc<'static>,
+//!     nrf52840::temperature::Temp<'static>
+//! ));
+//! let temp = components::temperature::TemperatureComponent::new(
+//!     board_kernel,
+//!     capsules_extra::temperature::DRIVER_NUM,
+//!     thermal,
+//! )
+//! .finalize(components::temperature_component_static!(
+//!     components::thermal::ThermalManagerComponentType<
+//!         nrf52840::rtc::Rtc<'static>,
+//!         nrf52840::temperature::Temp<'static>,
+//!     >
+//! ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::thermal::{
+    PauseProcessesPolicy, ThermalLevel, ThermalManager, ThermalPolicy, ThermalThresholds,
+};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::sensors::TemperatureDriver;
+use kernel::hil::time::Alarm;
+
+#[macro_export]
+macro_rules! thermal_manager_component_static {
+    ($A:ty, $T:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let manager = kernel::static_buf!(
+            capsules_extra::thermal::ThermalManager<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                $T,
+            >
+        );
+
+        (alarm, manager)
+    };};
+}
+
+#[macro_export]
+macro_rules! pause_processes_policy_component_static {
+    ($N:expr $(,)?) => {{
+        kernel::static_buf!(
+            capsules_extra::thermal::PauseProcessesPolicy<$crate::thermal::Capability, $N>
+        )
+    };};
+}
+
+pub type ThermalManagerComponentType<A, T> =
+    ThermalManager<'static, VirtualMuxAlarm<'static, A>, T>;
+
+pub struct ThermalManagerComponent<
+    A: 'static + Alarm<'static>,
+    T: 'static + TemperatureDriver<'static>,
+> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    sensor: &'static T,
+    thresholds: ThermalThresholds,
+    interval_ms: u32,
+    policy: Option<&'static dyn ThermalPolicy>,
+}
+
+impl<A: 'static + Alarm<'static>, T: 'static + TemperatureDriver<'static>>
+    ThermalManagerComponent<A, T>
+{
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        sensor: &'static T,
+        thresholds: ThermalThresholds,
+        interval_ms: u32,
+        policy: Option<&'static dyn ThermalPolicy>,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            alarm_mux,
+            sensor,
+            thresholds,
+            interval_ms,
+            policy,
+        }
+    }
+}
+
+impl<A: 'static + Alarm<'static>, T: 'static + TemperatureDriver<'static>> Component
+    for ThermalManagerComponent<A, T>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<ThermalManager<'static, VirtualMuxAlarm<'static, A>, T>>,
+    );
+    type Output = &'static ThermalManager<'static, VirtualMuxAlarm<'static, A>, T>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let thermal_alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        thermal_alarm.setup();
+
+        let manager = static_buffer.1.write(ThermalManager::new(
+            thermal_alarm,
+            self.sensor,
+            self.thresholds,
+            self.interval_ms,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        thermal_alarm.set_alarm_client(manager);
+        self.sensor.set_client(manager);
+        if let Some(policy) = self.policy {
+            manager.set_policy(policy);
+        }
+        manager.start();
+
+        manager
+    }
+}
+
+pub struct Capability;
+unsafe impl capabilities::ProcessManagementCapability for Capability {}
+
+pub struct PauseProcessesPolicyComponent<const N: usize> {
+    board_kernel: &'static kernel::Kernel,
+    level: ThermalLevel,
+    names: Option<&'static [&'static str]>,
+}
+
+impl<const N: usize> PauseProcessesPolicyComponent<N> {
+    /// Stop the processes in `names`, or all of them, at `level` and above.
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        level: ThermalLevel,
+        names: Option<&'static [&'static str]>,
+    ) -> Self {
+        Self {
+            board_kernel,
+            level,
+            names,
+        }
+    }
+}
+
+impl<const N: usize> Component for PauseProcessesPolicyComponent<N> {
+    type StaticInput = &'static mut MaybeUninit<PauseProcessesPolicy<Capability, N>>;
+    type Output = &'static PauseProcessesPolicy<Capability, N>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        s.write(PauseProcessesPolicy::new(
+            self.board_kernel,
+            Capability,
+            self.level,
+            self.names,
+        ))
+    }
+}
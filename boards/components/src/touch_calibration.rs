@@ -0,0 +1,114 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for touch panel calibration.
+//!
+//! The calibration sits between the touch panel and the touch driver, and
+//! needs its own `KVPermissions` user, typically a virtual user of the KV
+//! permissions mux.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let calibration = components::touch_calibration::TouchCalibrationComponent::new(
+//!     board_kernel,
+//!     capsules_extra::touch_calibration::DRIVER_NUM,
+//!     touch_panel,
+//!     Some(screen),
+//!     calibration_kv,
+//! )
+//! .finalize(components::touch_calibration_component_static!(
+//!     capsules_extra::virtual_kv::VirtualKVPermissions<...>
+//! ));
+//! let touch = components::touch::TouchComponent::new(
+//!     board_kernel,
+//!     capsules_extra::touch::DRIVER_NUM,
+//!     calibration,
+//!     None,
+//!     Some(screen),
+//! )
+//! .finalize(components::touch_component_static!());
+//! let _ = calibration.load();
+//! ```
+
+use capsules_extra::touch_calibration::{TouchCalibration, CALIBRATION_LEN, KEY_LEN};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil;
+use kernel::storage_permissions::StoragePermissions;
+
+/// Length of the value buffer, with room for the KV header.
+pub const VALUE_BUFFER_LEN: usize = CALIBRATION_LEN + 16;
+
+#[macro_export]
+macro_rules! touch_calibration_component_static {
+    ($V:ty $(,)?) => {{
+        let calibration =
+            kernel::static_buf!(capsules_extra::touch_calibration::TouchCalibration<'static, $V>);
+        let key_buffer = kernel::static_buf!([u8; capsules_extra::touch_calibration::KEY_LEN]);
+        let value_buffer = kernel::static_buf!([u8; $crate::touch_calibration::VALUE_BUFFER_LEN]);
+
+        (calibration, key_buffer, value_buffer)
+    };};
+}
+
+pub type TouchCalibrationComponentType<V> = TouchCalibration<'static, V>;
+
+pub struct TouchCalibrationComponent<V: hil::kv::KVPermissions<'static> + 'static> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    touch: &'static dyn hil::touch::Touch<'static>,
+    screen: Option<&'static dyn hil::screen::Screen<'static>>,
+    kv: &'static V,
+}
+
+impl<V: hil::kv::KVPermissions<'static>> TouchCalibrationComponent<V> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        touch: &'static dyn hil::touch::Touch<'static>,
+        screen: Option<&'static dyn hil::screen::Screen<'static>>,
+        kv: &'static V,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            touch,
+            screen,
+            kv,
+        }
+    }
+}
+
+impl<V: hil::kv::KVPermissions<'static>> Component for TouchCalibrationComponent<V> {
+    type StaticInput = (
+        &'static mut MaybeUninit<TouchCalibration<'static, V>>,
+        &'static mut MaybeUninit<[u8; KEY_LEN]>,
+        &'static mut MaybeUninit<[u8; VALUE_BUFFER_LEN]>,
+    );
+    type Output = &'static TouchCalibration<'static, V>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+        let storage_cap = create_capability!(capabilities::KerneluserStorageCapability);
+
+        let key_buffer = static_buffer.1.write([0; KEY_LEN]);
+        let value_buffer = static_buffer.2.write([0; VALUE_BUFFER_LEN]);
+
+        let calibration = static_buffer.0.write(TouchCalibration::new(
+            self.touch,
+            self.screen,
+            self.kv,
+            StoragePermissions::new_kernel(&storage_cap),
+            key_buffer,
+            value_buffer,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        self.touch.set_client(calibration);
+        self.kv.set_client(calibration);
+        calibration
+    }
+}
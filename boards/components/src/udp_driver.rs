@@ -76,6 +76,7 @@ pub struct UDPDriverComponent<A: Alarm<'static> + 'static> {
     udp_recv_mux: &'static MuxUdpReceiver<'static>,
     port_table: &'static UdpPortManager,
     interface_list: &'static [IPAddr],
+    max_payload_len: usize,
 }
 
 impl<A: Alarm<'static>> UDPDriverComponent<A> {
@@ -97,8 +98,16 @@ impl<A: Alarm<'static>> UDPDriverComponent<A> {
             udp_recv_mux,
             port_table,
             interface_list,
+            max_payload_len: MAX_PAYLOAD_LEN,
         }
     }
+
+    /// Limit the UDP payloads userspace can send to `max_payload_len` bytes,
+    /// at most `MAX_PAYLOAD_LEN`.
+    pub fn with_max_payload_len(mut self, max_payload_len: usize) -> Self {
+        self.max_payload_len = max_payload_len.min(MAX_PAYLOAD_LEN);
+        self
+    }
 }
 
 impl<A: Alarm<'static>> Component for UDPDriverComponent<A> {
@@ -148,7 +157,7 @@ impl<A: Alarm<'static>> Component for UDPDriverComponent<A> {
             udp_send,
             self.board_kernel.create_grant(self.driver_num, &grant_cap),
             self.interface_list,
-            MAX_PAYLOAD_LEN,
+            self.max_payload_len,
             self.port_table,
             kernel::utilities::leasable_buffer::SubSliceMut::new(buffer),
             &DRIVER_CAP,
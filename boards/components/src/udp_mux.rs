@@ -124,6 +124,10 @@ macro_rules! udp_mux_component_static {
                 capsules_extra::net::udp::udp_port_table::MAX_NUM_BOUND_PORTS]
         );
 
+        let bound_ports = kernel::static_buf!(
+            [Option<(u16, usize)>; capsules_extra::net::udp::udp_port_table::MAX_NUM_BOUND_PORTS]
+        );
+
         let radio_buf = kernel::static_buf!([u8; kernel::hil::radio::MAX_BUF_SIZE]);
         let sixlowpan_rx = kernel::static_buf!([u8; 1280]);
         let udp_dgram = kernel::static_buf!([u8; MAX_PAYLOAD_LEN]);
@@ -150,6 +154,7 @@ macro_rules! udp_mux_component_static {
             udp_dgram,
             udp_vis_cap,
             ip_vis_cap,
+            bound_ports,
         )
     };};
 }
@@ -223,6 +228,7 @@ impl<A: Alarm<'static> + 'static, M: MacDevice<'static>> Component for UDPMuxCom
         &'static mut MaybeUninit<[u8; MAX_PAYLOAD_LEN]>,
         &'static mut MaybeUninit<UdpVisibilityCapability>,
         &'static mut MaybeUninit<IpVisibilityCapability>,
+        &'static mut MaybeUninit<[Option<(u16, usize)>; MAX_NUM_BOUND_PORTS]>,
     );
     type Output = (
         &'static MuxUdpSender<'static, IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>>,
@@ -308,10 +314,12 @@ impl<A: Alarm<'static> + 'static, M: MacDevice<'static>> Component for UDPMuxCom
         ip_send.set_client(udp_send_mux);
 
         let kernel_ports = s.10.write([None; MAX_NUM_BOUND_PORTS]);
+        let bound_ports = s.16.write([None; MAX_NUM_BOUND_PORTS]);
         let create_table_cap = create_capability!(capabilities::CreatePortTableCapability);
         let udp_port_table = s.7.write(UdpPortManager::new(
             &create_table_cap,
             kernel_ports,
+            bound_ports,
             udp_vis,
         ));
 
@@ -0,0 +1,79 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for wear leveling on a flash.
+//!
+//! Usage
+//! -----
+//! ```rust
+//!    let wear_leveling = components::wear_leveling::WearLevelingFlashComponent::new(
+//!        flash,
+//!        0, // the region starts at the first page of the flash
+//!    )
+//!    .finalize(components::wear_leveling_flash_component_static!(
+//!        capsules_extra::mx25r6435f::MX25R6435F<...>,
+//!        30,
+//!    ));
+//! ```
+
+use capsules_extra::wear_leveling::WearLevelingFlash;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::flash::{Flash, HasClient};
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! wear_leveling_flash_component_static {
+    ($F:ty, $PAGES:expr $(,)?) => {{
+        let wear_leveling = kernel::static_buf!(
+            capsules_extra::wear_leveling::WearLevelingFlash<'static, $F, $PAGES>
+        );
+        let buffer = kernel::static_buf!(<$F as kernel::hil::flash::Flash>::Page);
+
+        (wear_leveling, buffer)
+    };};
+}
+
+pub type WearLevelingFlashComponentType<F, const PAGES: usize> =
+    WearLevelingFlash<'static, F, PAGES>;
+
+pub struct WearLevelingFlashComponent<
+    F: 'static + Flash + HasClient<'static, WearLevelingFlash<'static, F, PAGES>>,
+    const PAGES: usize,
+> {
+    flash: &'static F,
+    start_page: usize,
+}
+
+impl<
+        F: 'static + Flash + HasClient<'static, WearLevelingFlash<'static, F, PAGES>>,
+        const PAGES: usize,
+    > WearLevelingFlashComponent<F, PAGES>
+{
+    pub fn new(flash: &'static F, start_page: usize) -> Self {
+        Self { flash, start_page }
+    }
+}
+
+impl<
+        F: 'static + Flash + HasClient<'static, WearLevelingFlash<'static, F, PAGES>>,
+        const PAGES: usize,
+    > Component for WearLevelingFlashComponent<F, PAGES>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<WearLevelingFlash<'static, F, PAGES>>,
+        &'static mut MaybeUninit<F::Page>,
+    );
+    type Output = &'static WearLevelingFlash<'static, F, PAGES>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let buffer = s.1.write(F::Page::default());
+        let wear_leveling =
+            s.0.write(WearLevelingFlash::new(self.flash, self.start_page, buffer));
+        HasClient::set_client(self.flash, wear_leveling);
+        let _ = wear_leveling.init();
+
+        wear_leveling
+    }
+}
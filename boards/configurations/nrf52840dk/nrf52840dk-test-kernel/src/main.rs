@@ -96,6 +96,10 @@ impl TestLauncher {
             3 => unsafe { test::aes_test::run_aes128_ctr(&self.peripherals.ecb, self) },
             4 => unsafe { test::aes_test::run_aes128_cbc(&self.peripherals.ecb, self) },
             5 => unsafe { test::aes_test::run_aes128_ecb(&self.peripherals.ecb, self) },
+            6 => test::rtc_scheduler_timer_test::run_rtc_scheduler_timer(
+                &self.peripherals.rtc2,
+                self,
+            ),
             _ => kernel::debug!("All tests finished."),
         }
     }
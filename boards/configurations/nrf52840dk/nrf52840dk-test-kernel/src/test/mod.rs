@@ -4,5 +4,6 @@
 
 pub(crate) mod aes_test;
 pub(crate) mod hmac_sha256_test;
+pub(crate) mod rtc_scheduler_timer_test;
 pub(crate) mod sha256_test;
 pub(crate) mod siphash24_test;
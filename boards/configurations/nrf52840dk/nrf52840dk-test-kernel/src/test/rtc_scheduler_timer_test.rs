@@ -0,0 +1,85 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! This tests that timeslices of the RTC2 scheduler timer expire after their
+//! length, measured with the DWT cycle counter. To run this test, add this
+//! line to the boot sequence:
+//! ```
+//! test::rtc_scheduler_timer_test::run_rtc_scheduler_timer(&base_peripherals.rtc2, client);
+//! ```
+//!
+//! The low frequency clock must already be running.
+
+use core::num::NonZeroU32;
+
+use capsules_core::test::capsule_test::{CapsuleTestClient, CapsuleTestError};
+use cortexm4::dwt::Dwt;
+use kernel::debug;
+use kernel::hil::hw_debug::CycleCounter;
+use kernel::platform::scheduler_timer::SchedulerTimer;
+use nrf52840::rtc::RtcSchedulerTimer;
+
+/// Frequency of the CPU and so of the DWT cycle counter.
+const CPU_FREQUENCY: u64 = 64_000_000;
+const RTC_FREQUENCY: u64 = 32768;
+
+pub fn run_rtc_scheduler_timer(timer: &RtcSchedulerTimer, client: &'static dyn CapsuleTestClient) {
+    let dwt = Dwt::new();
+    dwt.start();
+
+    // The first timeslice starts the counter, the second one runs on the
+    // counter that is already running.
+    let result =
+        test_timeslice(timer, &dwt, 10_000).and_then(|()| test_timeslice(timer, &dwt, 1_000));
+
+    timer.reset();
+    if result.is_ok() {
+        debug!("RTC scheduler timer test passed.");
+    }
+    client.done(result);
+}
+
+fn test_timeslice(timer: &RtcSchedulerTimer, dwt: &Dwt, us: u32) -> Result<(), CapsuleTestError> {
+    // The timeslice lasts a whole number of ticks, and starts within the
+    // tick in progress.
+    let ticks = us as u64 * RTC_FREQUENCY / 1_000_000;
+    let min_cycles = (ticks - 1) * CPU_FREQUENCY / RTC_FREQUENCY;
+    let max_cycles = (ticks + 1) * CPU_FREQUENCY / RTC_FREQUENCY;
+
+    timer.reset();
+    let start = dwt.count() as u32;
+    timer.start(NonZeroU32::new(us).unwrap());
+    timer.arm();
+
+    let mut remaining = us;
+    let elapsed = loop {
+        let elapsed = (dwt.count() as u32).wrapping_sub(start) as u64;
+        match timer.get_remaining_us() {
+            Some(now) if now.get() <= remaining => remaining = now.get(),
+            Some(now) => {
+                debug!(
+                    "RTC scheduler timer: {}us remaining after {}us remaining",
+                    now.get(),
+                    remaining
+                );
+                return Err(CapsuleTestError::IncorrectResult);
+            }
+            None => break elapsed,
+        }
+        if elapsed > 2 * max_cycles {
+            debug!("RTC scheduler timer: {}us timeslice did not expire", us);
+            return Err(CapsuleTestError::IncorrectResult);
+        }
+    };
+    timer.disarm();
+
+    if elapsed < min_cycles || elapsed > max_cycles {
+        debug!(
+            "RTC scheduler timer: {}us timeslice expired after {} cycles, expected {} to {}",
+            us, elapsed, min_cycles, max_cycles
+        );
+        return Err(CapsuleTestError::IncorrectResult);
+    }
+    Ok(())
+}
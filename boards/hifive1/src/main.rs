@@ -21,12 +21,10 @@ use kernel::capabilities;
 use kernel::component::Component;
 use kernel::hil;
 use kernel::hil::led::LedLow;
-use kernel::platform::chip::Chip;
 use kernel::platform::scheduler_timer::VirtualSchedulerTimer;
 use kernel::platform::{KernelResources, SyscallDriverLookup};
 use kernel::scheduler::cooperative::CooperativeSched;
 use kernel::utilities::registers::interfaces::ReadWriteable;
-use kernel::Kernel;
 use kernel::{create_capability, debug, static_init};
 use rv32i::csr;
 
@@ -45,6 +43,17 @@ static mut CHIP: Option<&'static e310_g002::chip::E310x<E310G002DefaultPeriphera
 static mut PROCESS_PRINTER: Option<&'static capsules_system::process_printer::ProcessPrinterText> =
     None;
 
+/// Flash offset of the slots that processes are swapped out to, after the
+/// apps (see `layout.ld`).
+const SWAP_START: usize = 0x30_0000;
+/// Size of a swap slot. Processes with more memory cannot be swapped out.
+const SWAP_SLOT_SIZE: usize = 0x2000;
+const NUM_SWAP_SLOTS: usize = 4;
+/// Size of the flash of the HiFive1 Rev B.
+const FLASH_SIZE: usize = 0x40_0000;
+
+type Chip = e310_g002::chip::E310x<'static, E310G002DefaultPeripherals<'static>>;
+
 // How should the kernel respond when a process faults.
 const FAULT_RESPONSE: capsules_system::process_policies::PanicFaultPolicy =
     capsules_system::process_policies::PanicFaultPolicy {};
@@ -75,6 +84,7 @@ struct HiFive1 {
     scheduler_timer: &'static VirtualSchedulerTimer<
         VirtualMuxAlarm<'static, e310_g002::chip::E310xClint<'static>>,
     >,
+    ipc: kernel::ipc::IPC<{ NUM_PROCS as u8 }>,
 }
 
 /// Mapping of integer syscalls to objects that implement syscalls.
@@ -88,6 +98,7 @@ impl SyscallDriverLookup for HiFive1 {
             capsules_core::console::DRIVER_NUM => f(Some(self.console)),
             capsules_core::alarm::DRIVER_NUM => f(Some(self.alarm)),
             capsules_core::low_level_debug::DRIVER_NUM => f(Some(self.lldb)),
+            kernel::ipc::DRIVER_NUM => f(Some(&self.ipc)),
             _ => f(None),
         }
     }
@@ -128,53 +139,6 @@ impl KernelResources<e310_g002::chip::E310x<'static, E310G002DefaultPeripherals<
     }
 }
 
-/// For the HiFive1, if load_process is inlined, it leads to really large stack utilization in
-/// main. By wrapping it in a non-inlined function, this reduces the stack utilization once
-/// processes are running.
-#[inline(never)]
-fn load_processes_not_inlined<C: Chip>(board_kernel: &'static Kernel, chip: &'static C) {
-    // These symbols are defined in the linker script.
-    extern "C" {
-        /// Beginning of the ROM region containing app images.
-        static _sapps: u8;
-        /// End of the ROM region containing app images.
-        static _eapps: u8;
-        /// Beginning of the RAM region for app memory.
-        static mut _sappmem: u8;
-        /// End of the RAM region for app memory.
-        static _eappmem: u8;
-    }
-
-    let app_flash = unsafe {
-        core::slice::from_raw_parts(
-            core::ptr::addr_of!(_sapps),
-            core::ptr::addr_of!(_eapps) as usize - core::ptr::addr_of!(_sapps) as usize,
-        )
-    };
-
-    let app_memory = unsafe {
-        core::slice::from_raw_parts_mut(
-            core::ptr::addr_of_mut!(_sappmem),
-            core::ptr::addr_of!(_eappmem) as usize - core::ptr::addr_of!(_sappmem) as usize,
-        )
-    };
-
-    let process_mgmt_cap = create_capability!(capabilities::ProcessManagementCapability);
-    kernel::process::load_processes(
-        board_kernel,
-        chip,
-        app_flash,
-        app_memory,
-        unsafe { &mut *addr_of_mut!(PROCESSES) },
-        &FAULT_RESPONSE,
-        &process_mgmt_cap,
-    )
-    .unwrap_or_else(|err| {
-        debug!("Error loading processes!");
-        debug!("{:?}", err);
-    });
-}
-
 /// This is in a separate, inline(never) function so that its stack frame is
 /// removed when this function returns. Otherwise, the stack space used for
 /// these static_inits is wasted.
@@ -336,6 +300,64 @@ unsafe fn start() -> (
         VirtualSchedulerTimer::new(systick_virtual_alarm)
     );
 
+    // Credential checking is not used, but the asynchronous process loader is
+    // needed to swap processes.
+    let checking_policy = components::appid::checker_null::AppCheckerNullComponent::new()
+        .finalize(components::app_checker_null_component_static!());
+    let assigner = components::appid::assigner_name::AppIdAssignerNamesComponent::new()
+        .finalize(components::appid_assigner_names_component_static!());
+    let checker = components::appid::checker::ProcessCheckerMachineComponent::new(checking_policy)
+        .finalize(components::process_checker_machine_component_static!());
+
+    let storage_permissions_policy =
+        components::storage_permissions::null::StoragePermissionsNullComponent::new().finalize(
+            components::storage_permissions_null_component_static!(
+                Chip,
+                kernel::process::ProcessStandardDebugFull,
+            ),
+        );
+
+    let loader = components::loader::sequential::ProcessLoaderSequentialComponent::new(
+        checker,
+        &mut *addr_of_mut!(PROCESSES),
+        board_kernel,
+        chip,
+        &FAULT_RESPONSE,
+        assigner,
+        storage_permissions_policy,
+    )
+    .finalize(components::process_loader_sequential_component_static!(
+        Chip,
+        kernel::process::ProcessStandardDebugFull,
+        NUM_PROCS
+    ));
+
+    // Swap idle processes to the flash, to run more processes than fit in the
+    // 16 KiB of RAM. Processes that are discovered or notified over IPC are
+    // swapped back in.
+    let spi_flash = static_init!(
+        sifive::spi_flash::SpiFlash,
+        sifive::spi_flash::SpiFlash::new(
+            e310_g002::spi_flash::SPI_FLASH_BASE,
+            e310_g002::spi_flash::FLASH_MAPPED_ADDRESS,
+            FLASH_SIZE,
+        )
+    );
+    kernel::deferred_call::DeferredCallClient::register(spi_flash);
+    let process_swap = components::loader::swap::ProcessSwapComponent::new(
+        board_kernel,
+        loader,
+        spi_flash,
+        mux_alarm,
+        SWAP_START,
+        SWAP_SLOT_SIZE,
+    )
+    .finalize(components::process_swap_component_static!(
+        sifive::spi_flash::SpiFlash,
+        e310_g002::chip::E310xClint,
+        NUM_SWAP_SLOTS,
+    ));
+
     let hifive1 = HiFive1 {
         led,
         console,
@@ -343,9 +365,13 @@ unsafe fn start() -> (
         alarm,
         scheduler,
         scheduler_timer,
+        ipc: kernel::ipc::IPC::new(
+            board_kernel,
+            kernel::ipc::DRIVER_NUM,
+            &memory_allocation_cap,
+        ),
     };
-
-    load_processes_not_inlined(board_kernel, chip);
+    hifive1.ipc.set_swap_in(process_swap);
 
     (board_kernel, hifive1, chip)
 }
@@ -356,10 +382,5 @@ pub unsafe fn main() {
     let main_loop_capability = create_capability!(capabilities::MainLoopCapability);
 
     let (board_kernel, board, chip) = start();
-    board_kernel.kernel_loop(
-        &board,
-        chip,
-        None::<&kernel::ipc::IPC<0>>,
-        &main_loop_capability,
-    );
+    board_kernel.kernel_loop(&board, chip, Some(&board.ipc), &main_loop_capability);
 }
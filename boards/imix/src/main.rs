@@ -78,6 +78,10 @@ mod multi_timer_test;
 
 const NUM_PROCS: usize = 4;
 
+/// Timeout of the watchdog during board setup, before the kernel takes it
+/// over with a shorter period.
+const EARLY_WATCHDOG_TIMEOUT_MS: usize = 10_000;
+
 // Constants related to the configuration of the 15.4 network stack
 // TODO: Notably, the radio MAC addresses can be configured from userland at the moment
 // We probably want to change this from a security perspective (multiple apps being
@@ -171,6 +175,7 @@ struct Imix {
         &'static capsules_extra::nonvolatile_storage_driver::NonvolatileStorage<'static>,
     scheduler: &'static RoundRobinSched<'static>,
     systick: cortexm4::systick::SysTick,
+    watchdog: &'static sam4l::wdt::Wdt,
 }
 
 impl SyscallDriverLookup for Imix {
@@ -211,7 +216,7 @@ impl KernelResources<sam4l::chip::Sam4l<Sam4lDefaultPeripherals>> for Imix {
     type ProcessFault = ();
     type Scheduler = RoundRobinSched<'static>;
     type SchedulerTimer = cortexm4::systick::SysTick;
-    type WatchDog = ();
+    type WatchDog = sam4l::wdt::Wdt;
     type ContextSwitchCallback = ();
 
     fn syscall_driver_lookup(&self) -> &Self::SyscallDriverLookup {
@@ -230,7 +235,7 @@ impl KernelResources<sam4l::chip::Sam4l<Sam4lDefaultPeripherals>> for Imix {
         &self.systick
     }
     fn watchdog(&self) -> &Self::WatchDog {
-        &()
+        self.watchdog
     }
     fn context_switch_callback(&self) -> &Self::ContextSwitchCallback {
         &()
@@ -316,6 +321,10 @@ unsafe fn start() -> (
     Imix,
     &'static sam4l::chip::Sam4l<Sam4lDefaultPeripherals>,
 ) {
+    // Catch hangs during setup, until the kernel loop takes the watchdog over.
+    let watchdog = static_init!(sam4l::wdt::Wdt, sam4l::wdt::Wdt::new());
+    watchdog.arm_early(EARLY_WATCHDOG_TIMEOUT_MS);
+
     sam4l::init();
     let pm = static_init!(sam4l::pm::PowerManager, sam4l::pm::PowerManager::new());
     let peripherals = static_init!(Sam4lDefaultPeripherals, Sam4lDefaultPeripherals::new(pm));
@@ -764,6 +773,7 @@ unsafe fn start() -> (
         nonvolatile_storage,
         scheduler,
         systick: cortexm4::systick::SysTick::new(),
+        watchdog,
     };
 
     // Need to initialize the UART for the nRF51 serialization.
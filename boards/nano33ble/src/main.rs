@@ -15,12 +15,12 @@
 use core::ptr::addr_of;
 use core::ptr::addr_of_mut;
 
+use capsules_system::retained_state::{RetainedLayout, RetainedState};
 use kernel::capabilities;
 use kernel::component::Component;
 use kernel::hil::gpio::Configure;
 use kernel::hil::gpio::Output;
 use kernel::hil::led::LedLow;
-use kernel::hil::time::Counter;
 use kernel::hil::usb::Client;
 use kernel::platform::chip::Chip;
 use kernel::platform::{KernelResources, SyscallDriverLookup};
@@ -104,6 +104,12 @@ static mut CDC_REF_FOR_PANIC: Option<
 > = None;
 static mut NRF52_POWER: Option<&'static nrf52840::power::Power> = None;
 
+// GPREGRET holds the value telling the bootloader to stay in its update mode.
+const RETAINED_LAYOUT: RetainedLayout = RetainedLayout {
+    bootloader_handshake: Some(0),
+    ..RetainedLayout::NONE
+};
+
 /// Dummy buffer that causes the linker to reserve enough space for the stack.
 #[no_mangle]
 #[link_section = ".stack_buffer"]
@@ -113,7 +119,8 @@ pub static mut STACK_MEMORY: [u8; 0x1000] = [0; 0x1000];
 fn baud_rate_reset_bootloader_enter() {
     unsafe {
         // 0x90 is the magic value the bootloader expects
-        NRF52_POWER.unwrap().set_gpregret(0x90);
+        let _ = RetainedState::new(NRF52_POWER.unwrap(), RETAINED_LAYOUT)
+            .set_bootloader_handshake(0x90);
         cortexm4::scb::reset();
     }
 }
@@ -123,37 +130,13 @@ type HTS221Sensor = components::hts221::Hts221ComponentType<
 >;
 type TemperatureDriver = components::temperature::TemperatureComponentType<HTS221Sensor>;
 type HumidityDriver = components::humidity::HumidityComponentType<HTS221Sensor>;
-type Ieee802154MacDevice = components::ieee802154::Ieee802154ComponentMacDeviceType<
-    nrf52840::ieee802154_radio::Radio<'static>,
-    nrf52840::aes::AesECB<'static>,
->;
-type Ieee802154Driver = components::ieee802154::Ieee802154ComponentType<
-    nrf52840::ieee802154_radio::Radio<'static>,
-    nrf52840::aes::AesECB<'static>,
->;
-type RngDriver = components::rng::RngComponentType<nrf52840::trng::Trng<'static>>;
 
 /// Supported drivers by the platform
 pub struct Platform {
-    ble_radio: &'static capsules_extra::ble_advertising_driver::BLE<
-        'static,
-        nrf52::ble_radio::Radio<'static>,
-        capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<
-            'static,
-            nrf52::rtc::Rtc<'static>,
-        >,
-    >,
-    ieee802154_radio: &'static Ieee802154Driver,
+    ble_radio: &'static nrf52840_platform::radio::BleDriver,
+    ieee802154_radio: &'static nrf52840_platform::radio::Ieee802154Driver,
     console: &'static capsules_core::console::Console<'static>,
-    pconsole: &'static capsules_core::process_console::ProcessConsole<
-        'static,
-        { capsules_core::process_console::DEFAULT_COMMAND_HISTORY_LEN },
-        capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<
-            'static,
-            nrf52::rtc::Rtc<'static>,
-        >,
-        components::process_console::Capability,
-    >,
+    pconsole: &'static nrf52840_platform::console::ProcessConsole,
     proximity: &'static capsules_extra::proximity::ProximitySensor<'static>,
     temperature: &'static TemperatureDriver,
     humidity: &'static HumidityDriver,
@@ -164,16 +147,10 @@ pub struct Platform {
         3,
     >,
     adc: &'static capsules_core::adc::AdcVirtualized<'static>,
-    rng: &'static RngDriver,
+    rng: &'static nrf52840_platform::crypto::RngDriver,
     ipc: kernel::ipc::IPC<{ NUM_PROCS as u8 }>,
-    alarm: &'static capsules_core::alarm::AlarmDriver<
-        'static,
-        capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<
-            'static,
-            nrf52::rtc::Rtc<'static>,
-        >,
-    >,
-    udp_driver: &'static capsules_extra::net::udp::UDPDriver<'static>,
+    alarm: &'static nrf52840_platform::alarm::AlarmDriver,
+    udp_driver: &'static nrf52840_platform::radio::UdpDriver,
     scheduler: &'static RoundRobinSched<'static>,
     systick: cortexm4::systick::SysTick,
 }
@@ -328,17 +305,8 @@ pub unsafe fn start() -> (
     // ALARM & TIMER
     //--------------------------------------------------------------------------
 
-    let rtc = &base_peripherals.rtc;
-    let _ = rtc.start();
-
-    let mux_alarm = components::alarm::AlarmMuxComponent::new(rtc)
-        .finalize(components::alarm_mux_component_static!(nrf52::rtc::Rtc));
-    let alarm = components::alarm::AlarmDriverComponent::new(
-        board_kernel,
-        capsules_core::alarm::DRIVER_NUM,
-        mux_alarm,
-    )
-    .finalize(components::alarm_component_static!(nrf52::rtc::Rtc));
+    let nrf52840_platform::alarm::Alarm { mux_alarm, alarm } =
+        nrf52840_platform::alarm::setup(board_kernel, &base_peripherals.rtc);
 
     //--------------------------------------------------------------------------
     // UART & CONSOLE & DEBUG
@@ -376,47 +344,21 @@ pub unsafe fn start() -> (
     ));
     CDC_REF_FOR_PANIC = Some(cdc); //for use by panic handler
 
-    // Process Printer for displaying process information.
-    let process_printer = components::process_printer::ProcessPrinterTextComponent::new()
-        .finalize(components::process_printer_text_component_static!());
-    PROCESS_PRINTER = Some(process_printer);
-
-    // Create a shared UART channel for the console and for kernel debug.
-    let uart_mux = components::console::UartMuxComponent::new(cdc, 115200)
-        .finalize(components::uart_mux_component_static!());
-
-    let pconsole = components::process_console::ProcessConsoleComponent::new(
-        board_kernel,
-        uart_mux,
-        mux_alarm,
-        process_printer,
-        Some(cortexm4::support::reset),
-    )
-    .finalize(components::process_console_component_static!(
-        nrf52::rtc::Rtc<'static>
-    ));
-
-    // Setup the console.
-    let console = components::console::ConsoleComponent::new(
-        board_kernel,
-        capsules_core::console::DRIVER_NUM,
-        uart_mux,
-    )
-    .finalize(components::console_component_static!());
-    // Create the debugger object that handles calls to `debug!()`.
-    components::debug_writer::DebugWriterComponent::new(uart_mux)
-        .finalize(components::debug_writer_component_static!());
+    // Share the CDC-ACM channel between the console and kernel debug.
+    let console = nrf52840_platform::console::setup(board_kernel, cdc, 115200);
+    PROCESS_PRINTER = Some(console.process_printer);
+    let pconsole =
+        nrf52840_platform::console::setup_process_console(board_kernel, &console, mux_alarm);
 
     //--------------------------------------------------------------------------
-    // RANDOM NUMBERS
+    // RANDOM NUMBERS & AES
     //--------------------------------------------------------------------------
 
-    let rng = components::rng::RngComponent::new(
+    let crypto = nrf52840_platform::crypto::setup(
         board_kernel,
-        capsules_core::rng::DRIVER_NUM,
         &base_peripherals.trng,
-    )
-    .finalize(components::rng_component_static!(nrf52840::trng::Trng));
+        &base_peripherals.ecb,
+    );
 
     //--------------------------------------------------------------------------
     // ADC
@@ -525,82 +467,25 @@ pub unsafe fn start() -> (
     // WIRELESS
     //--------------------------------------------------------------------------
 
-    let ble_radio = components::ble::BLEComponent::new(
+    let addresses = nrf52840_platform::radio::Ieee802154Addresses::from_device_id(PAN_ID);
+    let radio = nrf52840_platform::radio::setup(
         board_kernel,
-        capsules_extra::ble_advertising_driver::DRIVER_NUM,
         &base_peripherals.ble_radio,
-        mux_alarm,
-    )
-    .finalize(components::ble_component_static!(
-        nrf52840::rtc::Rtc,
-        nrf52840::ble_radio::Radio
-    ));
-
-    use capsules_extra::net::ieee802154::MacAddress;
-
-    let aes_mux = components::ieee802154::MuxAes128ccmComponent::new(&base_peripherals.ecb)
-        .finalize(components::mux_aes128ccm_component_static!(
-            nrf52840::aes::AesECB
-        ));
-
-    let device_id = (*addr_of!(nrf52840::ficr::FICR_INSTANCE)).id();
-    let device_id_bottom_16 = u16::from_le_bytes([device_id[0], device_id[1]]);
-    let (ieee802154_radio, mux_mac) = components::ieee802154::Ieee802154Component::new(
-        board_kernel,
-        capsules_extra::ieee802154::DRIVER_NUM,
         &nrf52840_peripherals.ieee802154_radio,
-        aes_mux,
-        PAN_ID,
-        device_id_bottom_16,
-        device_id,
-    )
-    .finalize(components::ieee802154_component_static!(
-        nrf52840::ieee802154_radio::Radio,
-        nrf52840::aes::AesECB<'static>
-    ));
-    use capsules_extra::net::ipv6::ip_utils::IPAddr;
-
-    let local_ip_ifaces = static_init!(
-        [IPAddr; 3],
-        [
-            IPAddr([
-                0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d,
-                0x0e, 0x0f,
-            ]),
-            IPAddr([
-                0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d,
-                0x1e, 0x1f,
-            ]),
-            IPAddr::generate_from_mac(capsules_extra::net::ieee802154::MacAddress::Short(
-                device_id_bottom_16
-            )),
-        ]
+        crypto.aes_mux,
+        mux_alarm,
+        addresses,
     );
 
-    let (udp_send_mux, udp_recv_mux, udp_port_table) = components::udp_mux::UDPMuxComponent::new(
-        mux_mac,
+    let udp_driver = nrf52840_platform::radio::setup_udp(
+        board_kernel,
+        &radio,
+        mux_alarm,
+        addresses,
+        DST_MAC_ADDR,
         DEFAULT_CTX_PREFIX_LEN,
         DEFAULT_CTX_PREFIX,
-        DST_MAC_ADDR,
-        MacAddress::Short(device_id_bottom_16),
-        local_ip_ifaces,
-        mux_alarm,
-    )
-    .finalize(components::udp_mux_component_static!(
-        nrf52840::rtc::Rtc,
-        Ieee802154MacDevice
-    ));
-
-    // UDP driver initialization happens here
-    let udp_driver = components::udp_driver::UDPDriverComponent::new(
-        board_kernel,
-        capsules_extra::net::udp::DRIVER_NUM,
-        udp_send_mux,
-        udp_recv_mux,
-        udp_port_table,
-        local_ip_ifaces,
-    )
-    .finalize(components::udp_driver_component_static!(nrf52840::rtc::Rtc));
+    );
 
     //--------------------------------------------------------------------------
     // FINAL SETUP AND BOARD BOOT
@@ -614,9 +499,9 @@ pub unsafe fn start() -> (
         .finalize(components::round_robin_component_static!(NUM_PROCS));
 
     let platform = Platform {
-        ble_radio,
-        ieee802154_radio,
-        console,
+        ble_radio: radio.ble,
+        ieee802154_radio: radio.ieee802154,
+        console: console.console,
         pconsole,
         proximity,
         temperature,
@@ -624,7 +509,7 @@ pub unsafe fn start() -> (
         adc: adc_syscall,
         led,
         gpio,
-        rng,
+        rng: crypto.rng,
         alarm,
         udp_driver,
         ipc: kernel::ipc::IPC::new(
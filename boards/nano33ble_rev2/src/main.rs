@@ -15,6 +15,7 @@
 use core::ptr::addr_of;
 use core::ptr::addr_of_mut;
 
+use capsules_system::retained_state::{RetainedLayout, RetainedState};
 use kernel::capabilities;
 use kernel::component::Component;
 use kernel::hil::gpio::Configure;
@@ -101,6 +102,12 @@ static mut CDC_REF_FOR_PANIC: Option<
 > = None;
 static mut NRF52_POWER: Option<&'static nrf52840::power::Power> = None;
 
+// GPREGRET holds the value telling the bootloader to stay in its update mode.
+const RETAINED_LAYOUT: RetainedLayout = RetainedLayout {
+    bootloader_handshake: Some(0),
+    ..RetainedLayout::NONE
+};
+
 /// Dummy buffer that causes the linker to reserve enough space for the stack.
 #[no_mangle]
 #[link_section = ".stack_buffer"]
@@ -110,7 +117,8 @@ pub static mut STACK_MEMORY: [u8; 0x1000] = [0; 0x1000];
 fn baud_rate_reset_bootloader_enter() {
     unsafe {
         // 0x90 is the magic value the bootloader expects
-        NRF52_POWER.unwrap().set_gpregret(0x90);
+        let _ = RetainedState::new(NRF52_POWER.unwrap(), RETAINED_LAYOUT)
+            .set_bootloader_handshake(0x90);
         cortexm4::scb::reset();
     }
 }
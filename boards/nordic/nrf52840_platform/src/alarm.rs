@@ -0,0 +1,41 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Alarms on the RTC, shared by the kernel and processes.
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use kernel::component::Component;
+use kernel::hil::time::Counter;
+use nrf52840::rtc::Rtc;
+
+pub type AlarmMux = MuxAlarm<'static, Rtc<'static>>;
+pub type VirtualAlarm = VirtualMuxAlarm<'static, Rtc<'static>>;
+pub type AlarmDriver = components::alarm::AlarmDriverComponentType<Rtc<'static>>;
+
+pub struct Alarm {
+    /// Mux for the other capsules that need an alarm.
+    pub mux_alarm: &'static AlarmMux,
+    /// Alarm driver for processes.
+    pub alarm: &'static AlarmDriver,
+}
+
+/// Start the RTC and share it between the kernel and processes.
+///
+/// # Safety
+///
+/// Must be called at most once.
+pub unsafe fn setup(board_kernel: &'static kernel::Kernel, rtc: &'static Rtc<'static>) -> Alarm {
+    let _ = rtc.start();
+
+    let mux_alarm = components::alarm::AlarmMuxComponent::new(rtc)
+        .finalize(components::alarm_mux_component_static!(Rtc));
+    let alarm = components::alarm::AlarmDriverComponent::new(
+        board_kernel,
+        capsules_core::alarm::DRIVER_NUM,
+        mux_alarm,
+    )
+    .finalize(components::alarm_component_static!(Rtc));
+
+    Alarm { mux_alarm, alarm }
+}
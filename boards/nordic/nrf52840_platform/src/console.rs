@@ -0,0 +1,89 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Console for processes, kernel debug output and the process console, all
+//! sharing one UART.
+//!
+//! The UART can be any UART of the board, for example a UARTE peripheral from
+//! `nrf52_components::UartChannelComponent`, or CDC-ACM over USB.
+
+use capsules_core::console::Console as ConsoleDriver;
+use capsules_core::process_console::DEFAULT_COMMAND_HISTORY_LEN;
+use capsules_core::virtualizers::virtual_uart::MuxUart;
+use capsules_system::process_printer::ProcessPrinterText;
+use kernel::component::Component;
+use kernel::hil::uart::Uart;
+use nrf52840::rtc::Rtc;
+
+use crate::alarm::{AlarmMux, VirtualAlarm};
+
+pub type ProcessConsole = capsules_core::process_console::ProcessConsole<
+    'static,
+    { DEFAULT_COMMAND_HISTORY_LEN },
+    VirtualAlarm,
+    components::process_console::Capability,
+>;
+
+pub struct Console {
+    /// Mux for the other users of the UART.
+    pub uart_mux: &'static MuxUart<'static>,
+    /// Console driver for processes.
+    pub console: &'static ConsoleDriver<'static>,
+    /// Prints process information, for the process console and panics.
+    pub process_printer: &'static ProcessPrinterText,
+}
+
+/// Share `uart` between the console driver for processes and `debug!()`.
+///
+/// # Safety
+///
+/// Must be called at most once.
+pub unsafe fn setup(
+    board_kernel: &'static kernel::Kernel,
+    uart: &'static dyn Uart<'static>,
+    baud_rate: u32,
+) -> Console {
+    let process_printer = components::process_printer::ProcessPrinterTextComponent::new()
+        .finalize(components::process_printer_text_component_static!());
+
+    let uart_mux = components::console::UartMuxComponent::new(uart, baud_rate)
+        .finalize(components::uart_mux_component_static!());
+
+    let console = components::console::ConsoleComponent::new(
+        board_kernel,
+        capsules_core::console::DRIVER_NUM,
+        uart_mux,
+    )
+    .finalize(components::console_component_static!());
+    // Create the debugger object that handles calls to `debug!()`.
+    components::debug_writer::DebugWriterComponent::new(uart_mux)
+        .finalize(components::debug_writer_component_static!());
+
+    Console {
+        uart_mux,
+        console,
+        process_printer,
+    }
+}
+
+/// Add the process console to the UART of `console`. The board starts it
+/// once it is set up.
+///
+/// # Safety
+///
+/// Must be called at most once.
+pub unsafe fn setup_process_console(
+    board_kernel: &'static kernel::Kernel,
+    console: &Console,
+    mux_alarm: &'static AlarmMux,
+) -> &'static ProcessConsole {
+    components::process_console::ProcessConsoleComponent::new(
+        board_kernel,
+        console.uart_mux,
+        mux_alarm,
+        console.process_printer,
+        Some(cortexm4::support::reset),
+    )
+    .finalize(components::process_console_component_static!(Rtc<'static>))
+}
@@ -0,0 +1,38 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Random numbers from the TRNG, and AES-CCM on the ECB peripheral.
+
+use capsules_core::virtualizers::virtual_aes_ccm::MuxAES128CCM;
+use kernel::component::Component;
+use nrf52840::aes::AesECB;
+use nrf52840::trng::Trng;
+
+pub type RngDriver = components::rng::RngComponentType<Trng<'static>>;
+pub type AesMux = MuxAES128CCM<'static, AesECB<'static>>;
+
+pub struct Crypto {
+    /// Random number driver for processes.
+    pub rng: &'static RngDriver,
+    /// Mux for the users of AES-CCM, for example the IEEE 802.15.4 stack.
+    pub aes_mux: &'static AesMux,
+}
+
+/// # Safety
+///
+/// Must be called at most once.
+pub unsafe fn setup(
+    board_kernel: &'static kernel::Kernel,
+    trng: &'static Trng<'static>,
+    ecb: &'static AesECB<'static>,
+) -> Crypto {
+    let rng =
+        components::rng::RngComponent::new(board_kernel, capsules_core::rng::DRIVER_NUM, trng)
+            .finalize(components::rng_component_static!(Trng));
+
+    let aes_mux = components::ieee802154::MuxAes128ccmComponent::new(ecb)
+        .finalize(components::mux_aes128ccm_component_static!(AesECB));
+
+    Crypto { rng, aes_mux }
+}
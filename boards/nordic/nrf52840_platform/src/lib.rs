@@ -0,0 +1,21 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Kernel services shared by the nRF52840 boards.
+//!
+//! Most nRF52840 boards set up the same services from the same peripherals of
+//! the chip, and only differ in which pins and addresses they use. Each module
+//! sets up one service and returns the capsules it created, so that a board
+//! can pick the services it needs and add its own drivers next to them.
+//!
+//! Each setup function allocates its capsules with `static_init!`, and so must
+//! be called at most once.
+
+#![no_std]
+
+pub mod alarm;
+pub mod console;
+pub mod crypto;
+pub mod radio;
+pub mod storage;
@@ -0,0 +1,160 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! BLE advertising and IEEE 802.15.4 on the radio, and UDP over 6LoWPAN on
+//! top of IEEE 802.15.4.
+
+use core::ptr::addr_of;
+
+use capsules_extra::net::ieee802154::{MacAddress, PanID};
+use capsules_extra::net::ipv6::ip_utils::IPAddr;
+use kernel::component::Component;
+use kernel::static_init;
+use nrf52840::aes::AesECB;
+use nrf52840::ble_radio::Radio as BleRadio;
+use nrf52840::ieee802154_radio::Radio as Ieee802154Radio;
+use nrf52840::rtc::Rtc;
+
+use crate::alarm::{AlarmMux, VirtualAlarm};
+use crate::crypto::AesMux;
+
+pub type BleDriver =
+    capsules_extra::ble_advertising_driver::BLE<'static, BleRadio<'static>, VirtualAlarm>;
+pub type Ieee802154Driver =
+    components::ieee802154::Ieee802154ComponentType<Ieee802154Radio<'static>, AesECB<'static>>;
+pub type Ieee802154MacDevice = components::ieee802154::Ieee802154ComponentMacDeviceType<
+    Ieee802154Radio<'static>,
+    AesECB<'static>,
+>;
+pub type MuxMac = capsules_extra::ieee802154::virtual_mac::MuxMac<'static, Ieee802154MacDevice>;
+pub type UdpDriver = capsules_extra::net::udp::UDPDriver<'static>;
+
+/// Addresses of the board on the IEEE 802.15.4 network.
+#[derive(Clone, Copy)]
+pub struct Ieee802154Addresses {
+    pub pan_id: PanID,
+    pub short_addr: u16,
+    pub long_addr: [u8; 8],
+}
+
+impl Ieee802154Addresses {
+    /// Addresses derived from the device id in the FICR, which is unique to
+    /// each chip.
+    pub fn from_device_id(pan_id: PanID) -> Self {
+        // SAFETY: the FICR is only read.
+        let device_id = unsafe { (*addr_of!(nrf52840::ficr::FICR_INSTANCE)).id() };
+        Self {
+            pan_id,
+            short_addr: u16::from_le_bytes([device_id[0], device_id[1]]),
+            long_addr: device_id,
+        }
+    }
+}
+
+pub struct Radio {
+    /// BLE advertising driver for processes.
+    pub ble: &'static BleDriver,
+    /// IEEE 802.15.4 driver for processes.
+    pub ieee802154: &'static Ieee802154Driver,
+    /// Mux for the other users of the IEEE 802.15.4 MAC, for example UDP.
+    pub mux_mac: &'static MuxMac,
+}
+
+/// # Safety
+///
+/// Must be called at most once.
+pub unsafe fn setup(
+    board_kernel: &'static kernel::Kernel,
+    ble_radio: &'static BleRadio<'static>,
+    ieee802154_radio: &'static Ieee802154Radio<'static>,
+    aes_mux: &'static AesMux,
+    mux_alarm: &'static AlarmMux,
+    addresses: Ieee802154Addresses,
+) -> Radio {
+    let ble = components::ble::BLEComponent::new(
+        board_kernel,
+        capsules_extra::ble_advertising_driver::DRIVER_NUM,
+        ble_radio,
+        mux_alarm,
+    )
+    .finalize(components::ble_component_static!(Rtc, BleRadio));
+
+    let (ieee802154, mux_mac) = components::ieee802154::Ieee802154Component::new(
+        board_kernel,
+        capsules_extra::ieee802154::DRIVER_NUM,
+        ieee802154_radio,
+        aes_mux,
+        addresses.pan_id,
+        addresses.short_addr,
+        addresses.long_addr,
+    )
+    .finalize(components::ieee802154_component_static!(
+        Ieee802154Radio,
+        AesECB<'static>
+    ));
+
+    Radio {
+        ble,
+        ieee802154,
+        mux_mac,
+    }
+}
+
+/// Add the UDP driver for processes on the IEEE 802.15.4 MAC of `radio`.
+///
+/// Packets are sent to the next hop `dst_mac_addr`, and the 6LoWPAN header
+/// compression uses the first `ctx_prefix_len` bits of `ctx_prefix` as
+/// context.
+///
+/// # Safety
+///
+/// Must be called at most once.
+pub unsafe fn setup_udp(
+    board_kernel: &'static kernel::Kernel,
+    radio: &Radio,
+    mux_alarm: &'static AlarmMux,
+    addresses: Ieee802154Addresses,
+    dst_mac_addr: MacAddress,
+    ctx_prefix_len: u8,
+    ctx_prefix: [u8; 16],
+) -> &'static UdpDriver {
+    let local_ip_ifaces = static_init!(
+        [IPAddr; 3],
+        [
+            IPAddr([
+                0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d,
+                0x0e, 0x0f,
+            ]),
+            IPAddr([
+                0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d,
+                0x1e, 0x1f,
+            ]),
+            IPAddr::generate_from_mac(MacAddress::Short(addresses.short_addr)),
+        ]
+    );
+
+    let (udp_send_mux, udp_recv_mux, udp_port_table) = components::udp_mux::UDPMuxComponent::new(
+        radio.mux_mac,
+        ctx_prefix_len,
+        ctx_prefix,
+        dst_mac_addr,
+        MacAddress::Short(addresses.short_addr),
+        local_ip_ifaces,
+        mux_alarm,
+    )
+    .finalize(components::udp_mux_component_static!(
+        Rtc,
+        Ieee802154MacDevice
+    ));
+
+    components::udp_driver::UDPDriverComponent::new(
+        board_kernel,
+        capsules_extra::net::udp::DRIVER_NUM,
+        udp_send_mux,
+        udp_recv_mux,
+        udp_port_table,
+        local_ip_ifaces,
+    )
+    .finalize(components::udp_driver_component_static!(Rtc))
+}
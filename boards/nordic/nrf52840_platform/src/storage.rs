@@ -0,0 +1,130 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Key-value storage with TicKV on an external MX25R6435F flash.
+//!
+//! The flash is shared through a [`MuxFlash`], so that the rest of it can be
+//! used for other purposes. TicKV erases some of its pages much more often
+//! than others, so it is placed on a [`WearLevelingFlash`] that spreads these
+//! erases over the region.
+//!
+//! [`MuxFlash`]: capsules_core::virtualizers::virtual_flash::MuxFlash
+//! [`WearLevelingFlash`]: capsules_extra::wear_leveling::WearLevelingFlash
+
+use kernel::component::Component;
+use kernel::static_init;
+
+pub type Mx25r6435f = components::mx25r6435f::Mx25r6435fComponentType<
+    nrf52840::spi::SPIM<'static>,
+    nrf52840::gpio::GPIOPin<'static>,
+    nrf52840::rtc::Rtc<'static>,
+>;
+/// User of the shared external flash.
+pub type Mx25r6435fUser =
+    capsules_core::virtualizers::virtual_flash::FlashUser<'static, Mx25r6435f>;
+pub const TICKV_PAGE_SIZE: usize =
+    core::mem::size_of::<<Mx25r6435f as kernel::hil::flash::Flash>::Page>();
+/// Number of data pages of the wear leveling region. One of them is spare, so
+/// TicKV has one page less.
+pub const WEAR_LEVELING_PAGES: usize = 30;
+/// Length of the region at the start of the flash that holds the key-value
+/// store.
+pub const REGION_LEN: usize =
+    (WEAR_LEVELING_PAGES + capsules_extra::wear_leveling::METADATA_PAGES) * TICKV_PAGE_SIZE;
+pub type TicKVFlash =
+    components::wear_leveling::WearLevelingFlashComponentType<Mx25r6435fUser, WEAR_LEVELING_PAGES>;
+pub type Siphasher24 = components::siphash::Siphasher24ComponentType;
+pub type TicKVDedicatedFlash =
+    components::tickv::TicKVDedicatedFlashComponentType<TicKVFlash, Siphasher24, TICKV_PAGE_SIZE>;
+pub type TicKVKVStore = components::kv::TicKVKVStoreComponentType<
+    TicKVDedicatedFlash,
+    capsules_extra::tickv::TicKVKeyType,
+>;
+pub type KVStorePermissions = components::kv::KVStorePermissionsComponentType<TicKVKVStore>;
+pub type MuxKV = components::kv::KVPermissionsMuxComponentType<KVStorePermissions>;
+pub type VirtualKVPermissions =
+    components::kv::VirtualKVPermissionsComponentType<KVStorePermissions>;
+pub type KVDriver = components::kv::KVDriverComponentType<VirtualKVPermissions>;
+
+pub struct Storage {
+    /// Mux for the other users of the KV stack, which each take a
+    /// `components::kv::VirtualKVPermissionsComponent` on it.
+    pub mux_kv: &'static MuxKV,
+    /// KV driver for processes.
+    pub kv_driver: &'static KVDriver,
+}
+
+/// Store key-value pairs in the first `REGION_LEN` bytes of `flash`.
+///
+/// # Safety
+///
+/// Must be called at most once.
+pub unsafe fn setup(
+    board_kernel: &'static kernel::Kernel,
+    flash: &'static Mx25r6435fUser,
+) -> Storage {
+    let wear_leveling =
+        components::wear_leveling::WearLevelingFlashComponent::new(flash, 0).finalize(
+            components::wear_leveling_flash_component_static!(Mx25r6435fUser, WEAR_LEVELING_PAGES,),
+        );
+
+    // Static buffer to use when reading/writing flash for TicKV.
+    let page_buffer = static_init!(
+        <Mx25r6435f as kernel::hil::flash::Flash>::Page,
+        <Mx25r6435f as kernel::hil::flash::Flash>::Page::default()
+    );
+
+    // SipHash for creating TicKV hashed keys.
+    let sip_hash = components::siphash::Siphasher24Component::new()
+        .finalize(components::siphasher24_component_static!());
+
+    // TicKV with Tock wrapper/interface.
+    let tickv = components::tickv::TicKVDedicatedFlashComponent::new(
+        sip_hash,
+        wear_leveling,
+        0,
+        wear_leveling.num_pages() * TICKV_PAGE_SIZE,
+        page_buffer,
+    )
+    .finalize(components::tickv_dedicated_flash_component_static!(
+        TicKVFlash,
+        Siphasher24,
+        TICKV_PAGE_SIZE,
+    ));
+
+    // KVSystem interface to KV (built on TicKV).
+    let tickv_kv_store = components::kv::TicKVKVStoreComponent::new(tickv).finalize(
+        components::tickv_kv_store_component_static!(
+            TicKVDedicatedFlash,
+            capsules_extra::tickv::TicKVKeyType,
+        ),
+    );
+
+    let kv_store_permissions = components::kv::KVStorePermissionsComponent::new(tickv_kv_store)
+        .finalize(components::kv_store_permissions_component_static!(
+            TicKVKVStore
+        ));
+
+    // Share the KV stack with a mux.
+    let mux_kv = components::kv::KVPermissionsMuxComponent::new(kv_store_permissions).finalize(
+        components::kv_permissions_mux_component_static!(KVStorePermissions),
+    );
+
+    // Create a virtual component for the userspace driver.
+    let virtual_kv_driver = components::kv::VirtualKVPermissionsComponent::new(mux_kv).finalize(
+        components::virtual_kv_permissions_component_static!(KVStorePermissions),
+    );
+
+    // Userspace driver for KV.
+    let kv_driver = components::kv::KVDriverComponent::new(
+        virtual_kv_driver,
+        board_kernel,
+        capsules_extra::kv_driver::DRIVER_NUM,
+    )
+    .finalize(components::kv_driver_component_static!(
+        VirtualKVPermissions
+    ));
+
+    Storage { mux_kv, kv_driver }
+}
@@ -81,6 +81,7 @@ use kernel::hil::time::Counter;
 #[allow(unused_imports)]
 use kernel::hil::usb::Client;
 use kernel::platform::{KernelResources, SyscallDriverLookup};
+#[cfg(not(feature = "mlfq"))]
 use kernel::scheduler::round_robin::RoundRobinSched;
 #[allow(unused_imports)]
 use kernel::{capabilities, create_capability, debug, debug_gpio, debug_verbose, static_init};
@@ -115,16 +116,26 @@ const SPI_MX25R6435F_CHIP_SELECT: Pin = Pin::P0_17;
 const SPI_MX25R6435F_WRITE_PROTECT_PIN: Pin = Pin::P0_22;
 const SPI_MX25R6435F_HOLD_PIN: Pin = Pin::P0_23;
 
+/// Start of the region of the external flash that holds process binaries,
+/// after the TicKV region.
+pub const APP_IMAGES_START: usize = nrf52840_platform::storage::REGION_LEN;
+/// Length of the region of the external flash that holds process binaries.
+pub const APP_IMAGES_LEN: usize = CHECKPOINTS_START - APP_IMAGES_START;
+
+/// Size of the slot for the checkpoint of one process.
+pub const CHECKPOINT_SLOT_SIZE: usize = 0x10000;
+/// Start of the region at the end of the external flash that holds process
+/// checkpoints, one slot per process.
+pub const CHECKPOINTS_START: usize = 0x800000 - CHECKPOINT_SLOT_SIZE * NUM_PROCS;
+
 /// I2C pins
 const I2C_SDA_PIN: Pin = Pin::P0_26;
 const I2C_SCL_PIN: Pin = Pin::P0_27;
 
-// Constants related to the configuration of the 15.4 network stack
-const PAN_ID: u16 = 0xABCD;
-const DST_MAC_ADDR: capsules_extra::net::ieee802154::MacAddress =
-    capsules_extra::net::ieee802154::MacAddress::Short(49138);
-const DEFAULT_CTX_PREFIX_LEN: u8 = 8; //Length of context for 6LoWPAN compression
-const DEFAULT_CTX_PREFIX: [u8; 16] = [0x0_u8; 16]; //Context for 6LoWPAN Compression
+// Configuration of the 15.4 network stack. The addresses are derived from the
+// device ID when the stack is created.
+const NETWORK_STACK_CONFIG: components::network_stack::NetworkStackConfig =
+    components::network_stack::NetworkStackConfig::DEFAULT;
 
 /// Debug Writer
 pub mod io;
@@ -134,8 +145,41 @@ pub mod io;
 // - Set to true to use Segger RTT over USB.
 const USB_DEBUGGING: bool = false;
 
+/// Number of interrupt sources on the nRF52840.
+const NUM_INTERRUPTS: usize = 48;
+
+/// Power model used to estimate the energy of processes, from the typical
+/// currents in the nRF52840 datasheet at 3 V.
+static ENERGY_MODEL: capsules_extra::energy::EnergyModel = capsules_extra::energy::EnergyModel {
+    cpu_active_uw: 9_900,
+    peripherals: &[capsules_extra::energy::PeripheralPower {
+        driver_num: capsules_extra::temperature::DRIVER_NUM,
+        power_uw: 3_300,
+    }],
+};
+
+/// Die temperatures of the thermal levels, below the 85 °C maximum operating
+/// temperature of the nRF52840, in hundredths of degrees Celsius.
+const THERMAL_THRESHOLDS: capsules_extra::thermal::ThermalThresholds =
+    capsules_extra::thermal::ThermalThresholds {
+        warm: 70_00,
+        hot: 80_00,
+        critical: 85_00,
+        hysteresis: 5_00,
+    };
+
+/// How often the die temperature is sampled.
+const THERMAL_SAMPLE_INTERVAL_MS: u32 = 5_000;
+
+/// Interrupt service of this platform, which counts interrupts per source.
+type InterruptService = kernel::platform::stats::InterruptCounter<
+    'static,
+    Nrf52840DefaultPeripherals<'static>,
+    NUM_INTERRUPTS,
+>;
+
 /// This platform's chip type:
-pub type Chip = nrf52840::chip::NRF52<'static, Nrf52840DefaultPeripherals<'static>>;
+pub type Chip = nrf52840::chip::NRF52<'static, InterruptService>;
 
 /// Number of concurrent processes this platform supports.
 pub const NUM_PROCS: usize = 8;
@@ -144,7 +188,7 @@ pub const NUM_PROCS: usize = 8;
 pub static mut PROCESSES: [Option<&'static dyn kernel::process::Process>; NUM_PROCS] =
     [None; NUM_PROCS];
 
-static mut CHIP: Option<&'static nrf52840::chip::NRF52<Nrf52840DefaultPeripherals>> = None;
+static mut CHIP: Option<&'static Chip> = None;
 static mut PROCESS_PRINTER: Option<&'static capsules_system::process_printer::ProcessPrinterText> =
     None;
 
@@ -161,43 +205,80 @@ type AlarmDriver = components::alarm::AlarmDriverComponentType<nrf52840::rtc::Rt
 type RngDriver = components::rng::RngComponentType<nrf52840::trng::Trng<'static>>;
 
 // TicKV
-type Mx25r6435f = components::mx25r6435f::Mx25r6435fComponentType<
-    nrf52840::spi::SPIM<'static>,
-    nrf52840::gpio::GPIOPin<'static>,
+use nrf52840_platform::storage::{KVDriver, KVStorePermissions, VirtualKVPermissions};
+type ConfigService = components::config_service::ConfigServiceComponentType<VirtualKVPermissions>;
+
+// Date and time
+type SoftwareDateTime = components::date_time_software::SoftwareDateTimeComponentType<
     nrf52840::rtc::Rtc<'static>,
+    VirtualKVPermissions,
 >;
-const TICKV_PAGE_SIZE: usize =
-    core::mem::size_of::<<Mx25r6435f as kernel::hil::flash::Flash>::Page>();
-type Siphasher24 = components::siphash::Siphasher24ComponentType;
-type TicKVDedicatedFlash =
-    components::tickv::TicKVDedicatedFlashComponentType<Mx25r6435f, Siphasher24, TICKV_PAGE_SIZE>;
-type TicKVKVStore = components::kv::TicKVKVStoreComponentType<
-    TicKVDedicatedFlash,
-    capsules_extra::tickv::TicKVKeyType,
->;
-type KVStorePermissions = components::kv::KVStorePermissionsComponentType<TicKVKVStore>;
-type VirtualKVPermissions = components::kv::VirtualKVPermissionsComponentType<KVStorePermissions>;
-type KVDriver = components::kv::KVDriverComponentType<VirtualKVPermissions>;
+type DateTimeDriver = capsules_extra::date_time::DateTimeCapsule<'static, SoftwareDateTime>;
 
 // Temperature
-type TemperatureDriver =
-    components::temperature::TemperatureComponentType<nrf52840::temperature::Temp<'static>>;
+type ThermalDriver = components::thermal::ThermalManagerComponentType<
+    nrf52840::rtc::Rtc<'static>,
+    nrf52840::temperature::Temp<'static>,
+>;
+type TemperatureDriver = components::temperature::TemperatureComponentType<ThermalDriver>;
 
 // IEEE 802.15.4
-type Ieee802154MacDevice = components::ieee802154::Ieee802154ComponentMacDeviceType<
+/// Userspace 802.15.4 driver with in-kernel packet framing and MAC layer.
+pub type Ieee802154Driver = components::ieee802154::Ieee802154ComponentType<
     nrf52840::ieee802154_radio::Radio<'static>,
     nrf52840::aes::AesECB<'static>,
 >;
-/// Userspace 802.15.4 driver with in-kernel packet framing and MAC layer.
-pub type Ieee802154Driver = components::ieee802154::Ieee802154ComponentType<
+/// Multiplexer of the 802.15.4 MAC, shared by the UDP and TCP stacks.
+pub type MuxMac = components::network_stack::NetworkStackComponentMuxMacType<
     nrf52840::ieee802154_radio::Radio<'static>,
     nrf52840::aes::AesECB<'static>,
 >;
 
+// TCP
+/// Userspace TCP driver, with two sockets.
+pub type TcpDriver = components::tcp_driver::TcpDriverComponentType<nrf52840::rtc::Rtc<'static>, 2>;
+
 // EUI64
 /// Userspace EUI64 driver.
 pub type Eui64Driver = components::eui64::Eui64ComponentType;
 
+// I2C target
+/// Target mode of TWI1, shared by address between the I2C driver and capsules.
+pub type MuxI2CSlave = capsules_core::virtualizers::virtual_i2c_slave::MuxI2CSlave<
+    'static,
+    nrf52840::i2c::TWI<'static>,
+>;
+type I2CSlave = capsules_core::virtualizers::virtual_i2c_slave::I2CSlaveDevice<
+    'static,
+    nrf52840::i2c::TWI<'static>,
+>;
+type I2CMasterSlave = capsules_core::i2c_master_slave_combo::I2CMasterSlaveCombo<
+    'static,
+    nrf52840::i2c::TWI<'static>,
+    I2CSlave,
+>;
+
+// Energy accounting
+type EnergyDriver = components::energy::EnergyAccountantComponentType<nrf52840::rtc::Rtc<'static>>;
+
+// Capsules that can be disabled at runtime from the process console, on top
+// of the drivers each application is allowed to use.
+type AppDriverPermissions = capsules_system::app_driver_permissions::AppDriverPermissions<
+    'static,
+    kernel::platform::TbfHeaderFilterDefaultAllow,
+>;
+type SuspendableDrivers =
+    capsules_system::suspendable_drivers::SuspendableDrivers<'static, AppDriverPermissions>;
+
+/// Scheduler of the platform. The `mlfq` feature replaces the default round
+/// robin scheduler with a multilevel feedback queue scheduler.
+#[cfg(not(feature = "mlfq"))]
+pub type SchedulerInUse = RoundRobinSched<'static>;
+/// Scheduler of the platform. The `mlfq` feature replaces the default round
+/// robin scheduler with a multilevel feedback queue scheduler.
+#[cfg(feature = "mlfq")]
+pub type SchedulerInUse = components::sched::mlfq::MLFQComponentType<nrf52840::rtc::Rtc<'static>>;
+
 /// Supported drivers by the platform
 pub struct Platform {
     ble_radio: &'static capsules_extra::ble_advertising_driver::BLE<
@@ -206,7 +287,8 @@ pub struct Platform {
         VirtualMuxAlarm<'static, nrf52840::rtc::Rtc<'static>>,
     >,
     button: &'static capsules_core::button::Button<'static, nrf52840::gpio::GPIOPin<'static>>,
-    pconsole: &'static capsules_core::process_console::ProcessConsole<
+    /// The process console.
+    pub pconsole: &'static capsules_core::process_console::ProcessConsole<
         'static,
         { capsules_core::process_console::DEFAULT_COMMAND_HISTORY_LEN },
         VirtualMuxAlarm<'static, nrf52840::rtc::Rtc<'static>>,
@@ -222,8 +304,19 @@ pub struct Platform {
     rng: &'static RngDriver,
     adc: &'static capsules_core::adc::AdcDedicated<'static, nrf52840::adc::Adc<'static>>,
     temp: &'static TemperatureDriver,
+    thermal: &'static ThermalDriver,
+    energy: &'static EnergyDriver,
     /// The IPC driver.
     pub ipc: kernel::ipc::IPC<{ NUM_PROCS as u8 }>,
+    /// The external flash, which holds process binaries to install from
+    /// [`APP_IMAGES_START`].
+    pub image_flash: &'static nrf52840_platform::storage::Mx25r6435fUser,
+    /// The external flash, which holds process checkpoints from
+    /// [`CHECKPOINTS_START`].
+    pub checkpoint_flash: &'static nrf52840_platform::storage::Mx25r6435fUser,
+    /// The target mode of TWI1, on which capsules can respond on addresses
+    /// other than the one of the I2C driver.
+    pub mux_i2c_slave: &'static MuxI2CSlave,
     analog_comparator: &'static capsules_extra::analog_comparator::AnalogComparator<
         'static,
         nrf52840::acomp::Comparator<'static>,
@@ -231,7 +324,7 @@ pub struct Platform {
     alarm: &'static AlarmDriver,
     i2c_master_slave: &'static capsules_core::i2c_master_slave_driver::I2CMasterSlaveDriver<
         'static,
-        nrf52840::i2c::TWI<'static>,
+        I2CMasterSlave,
     >,
     spi_controller: &'static capsules_core::spi_controller::Spi<
         'static,
@@ -241,8 +334,11 @@ pub struct Platform {
         >,
     >,
     kv_driver: &'static KVDriver,
-    scheduler: &'static RoundRobinSched<'static>,
-    systick: cortexm4::systick::SysTick,
+    date_time: &'static DateTimeDriver,
+    config_service: &'static ConfigService,
+    suspendable_drivers: &'static SuspendableDrivers,
+    scheduler: &'static SchedulerInUse,
+    scheduler_timer: &'static nrf52840::rtc::RtcSchedulerTimer,
 }
 
 impl SyscallDriverLookup for Platform {
@@ -260,11 +356,15 @@ impl SyscallDriverLookup for Platform {
             capsules_core::adc::DRIVER_NUM => f(Some(self.adc)),
             capsules_extra::ble_advertising_driver::DRIVER_NUM => f(Some(self.ble_radio)),
             capsules_extra::temperature::DRIVER_NUM => f(Some(self.temp)),
+            capsules_extra::thermal::DRIVER_NUM => f(Some(self.thermal)),
+            capsules_extra::energy::DRIVER_NUM => f(Some(self.energy)),
             capsules_extra::analog_comparator::DRIVER_NUM => f(Some(self.analog_comparator)),
             kernel::ipc::DRIVER_NUM => f(Some(&self.ipc)),
             capsules_core::i2c_master_slave_driver::DRIVER_NUM => f(Some(self.i2c_master_slave)),
             capsules_core::spi_controller::DRIVER_NUM => f(Some(self.spi_controller)),
             capsules_extra::kv_driver::DRIVER_NUM => f(Some(self.kv_driver)),
+            capsules_extra::date_time::DRIVER_NUM => f(Some(self.date_time)),
+            capsules_extra::config_service::DRIVER_NUM => f(Some(self.config_service)),
             _ => f(None),
         }
     }
@@ -272,10 +372,10 @@ impl SyscallDriverLookup for Platform {
 
 impl KernelResources<Chip> for Platform {
     type SyscallDriverLookup = Self;
-    type SyscallFilter = ();
+    type SyscallFilter = SuspendableDrivers;
     type ProcessFault = ();
-    type Scheduler = RoundRobinSched<'static>;
-    type SchedulerTimer = cortexm4::systick::SysTick;
+    type Scheduler = SchedulerInUse;
+    type SchedulerTimer = nrf52840::rtc::RtcSchedulerTimer;
     type WatchDog = ();
     type ContextSwitchCallback = ();
 
@@ -283,7 +383,7 @@ impl KernelResources<Chip> for Platform {
         self
     }
     fn syscall_filter(&self) -> &Self::SyscallFilter {
-        &()
+        self.suspendable_drivers
     }
     fn process_fault(&self) -> &Self::ProcessFault {
         &()
@@ -292,7 +392,7 @@ impl KernelResources<Chip> for Platform {
         self.scheduler
     }
     fn scheduler_timer(&self) -> &Self::SchedulerTimer {
-        &self.systick
+        self.scheduler_timer
     }
     fn watchdog(&self) -> &Self::WatchDog {
         &()
@@ -311,6 +411,7 @@ pub unsafe fn ieee802154_udp(
     &'static Eui64Driver,
     &'static Ieee802154Driver,
     &'static capsules_extra::net::udp::UDPDriver<'static>,
+    &'static MuxMac,
 ) {
     //--------------------------------------------------------------------------
     // AES
@@ -323,7 +424,7 @@ pub unsafe fn ieee802154_udp(
             ));
 
     //--------------------------------------------------------------------------
-    // 802.15.4
+    // 802.15.4 AND UDP
     //--------------------------------------------------------------------------
 
     let device_id = (*addr_of!(nrf52840::ficr::FICR_INSTANCE)).id();
@@ -332,64 +433,72 @@ pub unsafe fn ieee802154_udp(
     let eui64_driver = components::eui64::Eui64Component::new(u64::from_le_bytes(device_id))
         .finalize(components::eui64_component_static!());
 
-    let (ieee802154_driver, mux_mac) = components::ieee802154::Ieee802154Component::new(
-        board_kernel,
-        capsules_extra::ieee802154::DRIVER_NUM,
-        &nrf52840_peripherals.ieee802154_radio,
-        aes_mux,
-        PAN_ID,
-        device_id_bottom_16,
-        device_id,
-    )
-    .finalize(components::ieee802154_component_static!(
-        nrf52840::ieee802154_radio::Radio,
-        nrf52840::aes::AesECB<'static>
-    ));
+    let (ieee802154_driver, udp_driver, mux_mac) =
+        components::network_stack::NetworkStackComponent::new(
+            board_kernel,
+            &nrf52840_peripherals.ieee802154_radio,
+            aes_mux,
+            mux_alarm,
+            components::network_stack::NetworkStackConfig {
+                short_addr: device_id_bottom_16,
+                long_addr: device_id,
+                ..NETWORK_STACK_CONFIG
+            },
+        )
+        .finalize(components::network_stack_component_static!(
+            nrf52840::ieee802154_radio::Radio,
+            nrf52840::aes::AesECB<'static>,
+            nrf52840::rtc::Rtc
+        ));
 
-    //--------------------------------------------------------------------------
-    // UDP
-    //--------------------------------------------------------------------------
+    (eui64_driver, ieee802154_driver, udp_driver, mux_mac)
+}
+
+/// Create the in-kernel TCP stack on top of the 15.4 stack created by
+/// [`ieee802154_udp`], with the same addresses.
+pub unsafe fn ieee802154_tcp(
+    board_kernel: &'static kernel::Kernel,
+    mux_mac: &'static MuxMac,
+    mux_alarm: &'static MuxAlarm<nrf52840::rtc::Rtc>,
+) -> &'static TcpDriver {
+    let device_id = (*addr_of!(nrf52840::ficr::FICR_INSTANCE)).id();
+    let device_id_bottom_16: u16 = u16::from_le_bytes([device_id[0], device_id[1]]);
 
     let local_ip_ifaces = static_init!(
         [IPAddr; 3],
         [
-            IPAddr::generate_from_mac(capsules_extra::net::ieee802154::MacAddress::Long(device_id)),
-            IPAddr([
-                0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d,
-                0x1e, 0x1f,
-            ]),
-            IPAddr::generate_from_mac(capsules_extra::net::ieee802154::MacAddress::Short(
-                device_id_bottom_16
-            )),
+            IPAddr::generate_from_mac(MacAddress::Long(device_id)),
+            NETWORK_STACK_CONFIG.ip_address,
+            IPAddr::generate_from_mac(MacAddress::Short(device_id_bottom_16)),
         ]
     );
 
-    let (udp_send_mux, udp_recv_mux, udp_port_table) = components::udp_mux::UDPMuxComponent::new(
+    let tcp_mux = components::tcp_mux::TCPMuxComponent::new(
         mux_mac,
-        DEFAULT_CTX_PREFIX_LEN,
-        DEFAULT_CTX_PREFIX,
-        DST_MAC_ADDR,
+        NETWORK_STACK_CONFIG.ctx_prefix_len,
+        NETWORK_STACK_CONFIG.ctx_prefix,
+        NETWORK_STACK_CONFIG.dst_mac_addr,
         MacAddress::Long(device_id),
         local_ip_ifaces,
         mux_alarm,
     )
-    .finalize(components::udp_mux_component_static!(
+    .finalize(components::tcp_mux_component_static!(
         nrf52840::rtc::Rtc,
-        Ieee802154MacDevice
+        components::ieee802154::Ieee802154ComponentMacDeviceType<
+            nrf52840::ieee802154_radio::Radio,
+            nrf52840::aes::AesECB<'static>,
+        >
     ));
 
-    // UDP driver initialization happens here
-    let udp_driver = components::udp_driver::UDPDriverComponent::new(
+    components::tcp_driver::TcpDriverComponent::new(
         board_kernel,
-        capsules_extra::net::udp::driver::DRIVER_NUM,
-        udp_send_mux,
-        udp_recv_mux,
-        udp_port_table,
-        local_ip_ifaces,
+        capsules_extra::net::tcp::DRIVER_NUM,
+        tcp_mux,
     )
-    .finalize(components::udp_driver_component_static!(nrf52840::rtc::Rtc));
-
-    (eui64_driver, ieee802154_driver, udp_driver)
+    .finalize(components::tcp_driver_component_static!(
+        nrf52840::rtc::Rtc,
+        2
+    ))
 }
 
 /// This is in a separate, inline(never) function so that its stack frame is
@@ -410,6 +519,13 @@ pub unsafe fn start() -> (
     // Apply errata fixes and enable interrupts.
     nrf52840::init();
 
+    // Measure how long each stage of the boot takes with the cycle counter.
+    let dwt = static_init!(cortexm4::dwt::Dwt, cortexm4::dwt::Dwt::new());
+    let boot_timer =
+        components::boot_timing::BootTimerComponent::new(dwt, 64_000_000, None).finalize(
+            components::boot_timer_component_static!(cortexm4::dwt::Dwt, 8),
+        );
+
     // Set up peripheral drivers. Called in separate function to reduce stack
     // usage.
     let ieee802154_ack_buf = static_init!(
@@ -433,6 +549,26 @@ pub unsafe fn start() -> (
         Some(&nrf52840_peripherals.gpio_port[LED3_PIN]),
     );
 
+    // Pins the `debug-gpio` process console command can assign to the debug
+    // GPIOs at runtime.
+    let debug_gpio_pins = static_init!(
+        [(&'static str, &'static dyn kernel::hil::gpio::Pin); 8],
+        [
+            ("LED1", &nrf52840_peripherals.gpio_port[LED1_PIN]),
+            ("LED2", &nrf52840_peripherals.gpio_port[LED2_PIN]),
+            ("LED3", &nrf52840_peripherals.gpio_port[LED3_PIN]),
+            ("LED4", &nrf52840_peripherals.gpio_port[LED4_PIN]),
+            ("P1.12", &nrf52840_peripherals.gpio_port[Pin::P1_12]),
+            ("P1.13", &nrf52840_peripherals.gpio_port[Pin::P1_13]),
+            ("P1.14", &nrf52840_peripherals.gpio_port[Pin::P1_14]),
+            ("P1.15", &nrf52840_peripherals.gpio_port[Pin::P1_15]),
+        ]
+    );
+    let debug_gpios = static_init!(
+        kernel::debug::DebugGpioRegistry,
+        kernel::debug::DebugGpioRegistry::new(debug_gpio_pins)
+    );
+
     // Choose the channel for serial output. This board can be configured to use
     // either the Segger RTT channel or via UART with traditional TX/RX GPIO
     // pins.
@@ -458,7 +594,11 @@ pub unsafe fn start() -> (
 
     // Create (and save for panic debugging) a chip object to setup low-level
     // resources (e.g. MPU, systick).
-    let chip = static_init!(Chip, nrf52840::chip::NRF52::new(nrf52840_peripherals));
+    let interrupt_service = static_init!(
+        InterruptService,
+        kernel::platform::stats::InterruptCounter::new(nrf52840_peripherals)
+    );
+    let chip = static_init!(Chip, nrf52840::chip::NRF52::new(interrupt_service));
     CHIP = Some(chip);
 
     // Do nRF configuration and setup. This is shared code with other nRF-based
@@ -480,6 +620,8 @@ pub unsafe fn start() -> (
     let memory_allocation_capability = create_capability!(capabilities::MemoryAllocationCapability);
     let gpio_port = &nrf52840_peripherals.gpio_port;
 
+    boot_timer.mark("setup");
+
     //--------------------------------------------------------------------------
     // GPIO
     //--------------------------------------------------------------------------
@@ -560,6 +702,8 @@ pub unsafe fn start() -> (
         LedLow::new(&nrf52840_peripherals.gpio_port[LED4_PIN]),
     ));
 
+    boot_timer.mark("gpio, buttons and leds");
+
     //--------------------------------------------------------------------------
     // TIMER
     //--------------------------------------------------------------------------
@@ -609,6 +753,7 @@ pub unsafe fn start() -> (
     .finalize(components::process_console_component_static!(
         nrf52840::rtc::Rtc<'static>
     ));
+    pconsole.set_debug_gpios(debug_gpios);
 
     // Setup the serial console for userspace.
     let console = components::console::ConsoleComponent::new(
@@ -622,6 +767,8 @@ pub unsafe fn start() -> (
     components::debug_writer::DebugWriterComponent::new(uart_mux)
         .finalize(components::debug_writer_component_static!());
 
+    boot_timer.mark("alarm and console");
+
     //--------------------------------------------------------------------------
     // BLE
     //--------------------------------------------------------------------------
@@ -641,15 +788,38 @@ pub unsafe fn start() -> (
     // TEMPERATURE (internal)
     //--------------------------------------------------------------------------
 
-    let temp = components::temperature::TemperatureComponent::new(
+    // The die temperature is sampled for thermal monitoring, and processes
+    // read it through the thermal manager. All processes are paused while the
+    // chip is critically hot.
+    let thermal_policy = components::thermal::PauseProcessesPolicyComponent::new(
         board_kernel,
-        capsules_extra::temperature::DRIVER_NUM,
+        capsules_extra::thermal::ThermalLevel::Critical,
+        None,
+    )
+    .finalize(components::pause_processes_policy_component_static!(
+        NUM_PROCS
+    ));
+    let thermal = components::thermal::ThermalManagerComponent::new(
+        board_kernel,
+        capsules_extra::thermal::DRIVER_NUM,
+        mux_alarm,
         &base_peripherals.temp,
+        THERMAL_THRESHOLDS,
+        THERMAL_SAMPLE_INTERVAL_MS,
+        Some(thermal_policy),
     )
-    .finalize(components::temperature_component_static!(
-        nrf52840::temperature::Temp
+    .finalize(components::thermal_manager_component_static!(
+        nrf52840::rtc::Rtc<'static>,
+        nrf52840::temperature::Temp<'static>
     ));
 
+    let temp = components::temperature::TemperatureComponent::new(
+        board_kernel,
+        capsules_extra::temperature::DRIVER_NUM,
+        thermal,
+    )
+    .finalize(components::temperature_component_static!(ThermalDriver));
+
     //--------------------------------------------------------------------------
     // RANDOM NUMBER GENERATOR
     //--------------------------------------------------------------------------
@@ -686,6 +856,8 @@ pub unsafe fn start() -> (
         nrf52840::adc::Adc
     ));
 
+    boot_timer.mark("ble, rng and adc");
+
     //--------------------------------------------------------------------------
     // SPI
     //--------------------------------------------------------------------------
@@ -729,78 +901,117 @@ pub unsafe fn start() -> (
         nrf52840::rtc::Rtc
     ));
 
+    // Share the external flash between TicKV, the binaries that can be
+    // installed with the `install` command of the process console, and
+    // process checkpoints.
+    let mux_flash = components::flash::FlashMuxComponent::new(mx25r6435f).finalize(
+        components::flash_mux_component_static!(nrf52840_platform::storage::Mx25r6435f),
+    );
+    let tickv_flash = components::flash::FlashUserComponent::new(mux_flash).finalize(
+        components::flash_user_component_static!(nrf52840_platform::storage::Mx25r6435f),
+    );
+    let image_flash = components::flash::FlashUserComponent::new(mux_flash).finalize(
+        components::flash_user_component_static!(nrf52840_platform::storage::Mx25r6435f),
+    );
+    let checkpoint_flash = components::flash::FlashUserComponent::new(mux_flash).finalize(
+        components::flash_user_component_static!(nrf52840_platform::storage::Mx25r6435f),
+    );
+    // Process state is written before the KV store, and both before the bulk
+    // writes of installed binaries, so they are not kept waiting behind them.
+    checkpoint_flash.set_priority(2);
+    tickv_flash.set_priority(1);
+
+    boot_timer.mark("spi and external flash");
+
     //--------------------------------------------------------------------------
     // TICKV
     //--------------------------------------------------------------------------
 
-    // Static buffer to use when reading/writing flash for TicKV.
-    let page_buffer = static_init!(
-        <Mx25r6435f as kernel::hil::flash::Flash>::Page,
-        <Mx25r6435f as kernel::hil::flash::Flash>::Page::default()
-    );
+    // KV stack on TicKV, with a userspace driver.
+    let nrf52840_platform::storage::Storage { mux_kv, kv_driver } =
+        nrf52840_platform::storage::setup(board_kernel, tickv_flash);
 
-    // SipHash for creating TicKV hashed keys.
-    let sip_hash = components::siphash::Siphasher24Component::new()
-        .finalize(components::siphasher24_component_static!());
-
-    // TicKV with Tock wrapper/interface.
-    let tickv = components::tickv::TicKVDedicatedFlashComponent::new(
-        sip_hash,
-        mx25r6435f,
-        0, // start at the beginning of the flash chip
-        (capsules_extra::mx25r6435f::SECTOR_SIZE as usize) * 32, // arbitrary size of 32 pages
-        page_buffer,
+    // Configuration records, on their own user of the KV stack.
+    let virtual_kv_config = components::kv::VirtualKVPermissionsComponent::new(mux_kv).finalize(
+        components::virtual_kv_permissions_component_static!(KVStorePermissions),
+    );
+    let config_service = components::config_service::ConfigServiceComponent::new(
+        virtual_kv_config,
+        board_kernel,
+        capsules_extra::config_service::DRIVER_NUM,
     )
-    .finalize(components::tickv_dedicated_flash_component_static!(
-        Mx25r6435f,
-        Siphasher24,
-        TICKV_PAGE_SIZE,
+    .finalize(components::config_service_component_static!(
+        VirtualKVPermissions
     ));
 
-    // KVSystem interface to KV (built on TicKV).
-    let tickv_kv_store = components::kv::TicKVKVStoreComponent::new(tickv).finalize(
-        components::tickv_kv_store_component_static!(
-            TicKVDedicatedFlash,
-            capsules_extra::tickv::TicKVKeyType,
-        ),
-    );
-
-    let kv_store_permissions = components::kv::KVStorePermissionsComponent::new(tickv_kv_store)
-        .finalize(components::kv_store_permissions_component_static!(
-            TicKVKVStore
-        ));
-
-    // Share the KV stack with a mux.
-    let mux_kv = components::kv::KVPermissionsMuxComponent::new(kv_store_permissions).finalize(
-        components::kv_permissions_mux_component_static!(KVStorePermissions),
+    // The board has no RTC, so the date and time are kept in software, and
+    // stored on their own user of the KV stack.
+    let virtual_kv_date_time = components::kv::VirtualKVPermissionsComponent::new(mux_kv).finalize(
+        components::virtual_kv_permissions_component_static!(KVStorePermissions),
     );
+    let software_date_time = components::date_time_software::SoftwareDateTimeComponent::new(
+        mux_alarm,
+        virtual_kv_date_time,
+    )
+    .finalize(components::date_time_software_component_static!(
+        nrf52840::rtc::Rtc<'static>,
+        VirtualKVPermissions
+    ));
+    let date_time = components::date_time::DateTimeComponent::new(
+        board_kernel,
+        capsules_extra::date_time::DRIVER_NUM,
+        software_date_time,
+    )
+    .finalize(components::date_time_component_static!(SoftwareDateTime));
+    let _ = software_date_time.load();
 
-    // Create a virtual component for the userspace driver.
-    let virtual_kv_driver = components::kv::VirtualKVPermissionsComponent::new(mux_kv).finalize(
+    // Boot and panic counters, printed by the `status` command of the process
+    // console. The reason of a panic is kept in GPREGRET2 until the next boot.
+    let virtual_kv_counters = components::kv::VirtualKVPermissionsComponent::new(mux_kv).finalize(
         components::virtual_kv_permissions_component_static!(KVStorePermissions),
     );
-
-    // Userspace driver for KV.
-    let kv_driver = components::kv::KVDriverComponent::new(
-        virtual_kv_driver,
-        board_kernel,
-        capsules_extra::kv_driver::DRIVER_NUM,
+    let boot_counters = components::persistent_counters::BootCountersComponent::new(
+        virtual_kv_counters,
+        &base_peripherals.pwr_clk,
+        capsules_system::retained_state::RetainedLayout {
+            panic_breadcrumb: Some(1),
+            ..capsules_system::retained_state::RetainedLayout::NONE
+        },
     )
-    .finalize(components::kv_driver_component_static!(
-        VirtualKVPermissions
+    .finalize(components::boot_counters_component_static!(
+        VirtualKVPermissions,
+        nrf52840::power::Power<'static>,
     ));
+    let _ = boot_counters.start();
+    pconsole.set_persistent_counters(boot_counters);
 
     //--------------------------------------------------------------------------
     // I2C CONTROLLER/TARGET
     //--------------------------------------------------------------------------
 
+    // The driver responds on its address through the mux, so capsules can
+    // respond on the second address of TWI1.
+    let mux_i2c_slave = components::i2c::I2CSlaveMuxComponent::new(&base_peripherals.twi1)
+        .finalize(components::i2c_slave_mux_component_static!(
+            nrf52840::i2c::TWI
+        ));
+    let i2c_slave = components::i2c::I2CSlaveComponent::new(mux_i2c_slave)
+        .finalize(components::i2c_slave_component_static!(nrf52840::i2c::TWI));
+    let i2c_master_slave_combo = static_init!(
+        I2CMasterSlave,
+        capsules_core::i2c_master_slave_combo::I2CMasterSlaveCombo::new(
+            &base_peripherals.twi1,
+            i2c_slave
+        )
+    );
+
     let i2c_master_slave = components::i2c::I2CMasterSlaveDriverComponent::new(
         board_kernel,
         capsules_core::i2c_master_slave_driver::DRIVER_NUM,
-        &base_peripherals.twi1,
+        i2c_master_slave_combo,
     )
     .finalize(components::i2c_master_slave_component_static!(
-        nrf52840::i2c::TWI
+        I2CMasterSlave
     ));
 
     base_peripherals.twi1.configure(
@@ -809,6 +1020,8 @@ pub unsafe fn start() -> (
     );
     base_peripherals.twi1.set_speed(nrf52840::i2c::Speed::K400);
 
+    boot_timer.mark("kv store");
+
     //--------------------------------------------------------------------------
     // ANALOG COMPARATOR
     //--------------------------------------------------------------------------
@@ -881,12 +1094,117 @@ pub unsafe fn start() -> (
     // keyboard_hid.enable();
     // keyboard_hid.attach();
 
+    // // Mass Storage Example
+    // //
+    // // Exposes the external flash chip as a removable drive. The flash can
+    // // only have one client, so this replaces the TicKV storage above.
+    // let mass_storage = components::mass_storage::MassStorageComponent::new(
+    //     &nrf52840_peripherals.usbd,
+    //     capsules_extra::usb::cdc::MAX_CTRL_PACKET_SIZE_NRF52840,
+    //     0x1915, // Nordic Semiconductor
+    //     0x503a,
+    //     strings,
+    //     mx25r6435f,
+    //     0,    // First page
+    //     2048, // Number of pages (8 MB)
+    // )
+    // .finalize(components::mass_storage_component_static!(
+    //     nrf52840::usbd::Usbd,
+    //     nrf52840_platform::storage::Mx25r6435f
+    // ));
+
+    // mass_storage.enable();
+    // mass_storage.attach();
+
     //--------------------------------------------------------------------------
     // PLATFORM SETUP, SCHEDULER, AND START KERNEL LOOP
     //--------------------------------------------------------------------------
 
+    //--------------------------------------------------------------------------
+    // SUSPENDABLE DRIVERS
+    //--------------------------------------------------------------------------
+
+    // Capsules listed here can be suspended and resumed from the process
+    // console with the `suspend` and `resume` commands.
+    // Applications with a `ShortId` listed here may only use the drivers
+    // granted to them, which can be changed at runtime with
+    // `suspendable_drivers.filter().set_granted()`. The permissions in the TBF
+    // headers of applications apply as well.
+    let app_driver_permission_list = static_init!(
+        [capsules_system::app_driver_permissions::AppDriverPermission; 0],
+        []
+    );
+    let app_driver_permissions = AppDriverPermissions::new(
+        app_driver_permission_list,
+        kernel::platform::TbfHeaderFilterDefaultAllow {},
+    );
+
+    let suspendable_driver_list = static_init!(
+        [capsules_system::suspendable_drivers::SuspendableDriver<'static>; 4],
+        [
+            capsules_system::suspendable_drivers::SuspendableDriver::new(
+                "button",
+                capsules_core::button::DRIVER_NUM,
+                Some(button),
+            ),
+            capsules_system::suspendable_drivers::SuspendableDriver::new(
+                "led",
+                capsules_core::led::DRIVER_NUM,
+                None,
+            ),
+            capsules_system::suspendable_drivers::SuspendableDriver::new(
+                "gpio",
+                capsules_core::gpio::DRIVER_NUM,
+                None,
+            ),
+            capsules_system::suspendable_drivers::SuspendableDriver::new(
+                "ble",
+                capsules_extra::ble_advertising_driver::DRIVER_NUM,
+                None,
+            ),
+        ]
+    );
+    let suspendable_drivers = static_init!(
+        SuspendableDrivers,
+        SuspendableDrivers::new(suspendable_driver_list, app_driver_permissions)
+    );
+    pconsole.set_suspend_control(suspendable_drivers);
+
+    // Collect kernel statistics for the `stats` process console command.
+    let kernel_stats = components::kernel_stats::KernelStatsComponent::new(
+        board_kernel,
+        mux_alarm,
+        Some(interrupt_service),
+    )
+    .finalize(components::kernel_stats_component_static!(
+        nrf52840::rtc::Rtc<'static>,
+        NUM_INTERRUPTS
+    ));
+    pconsole.set_statistics(kernel_stats);
+
+    // Account the energy of processes for the `energy` process console
+    // command and the energy driver.
+    let energy = components::energy::EnergyAccountantComponent::new(
+        board_kernel,
+        capsules_extra::energy::DRIVER_NUM,
+        mux_alarm,
+        &ENERGY_MODEL,
+        None,
+    )
+    .finalize(components::energy_accountant_component_static!(
+        nrf52840::rtc::Rtc<'static>
+    ));
+    temp.set_energy_accounting(energy);
+    pconsole.set_energy(energy);
+
+    #[cfg(not(feature = "mlfq"))]
     let scheduler = components::sched::round_robin::RoundRobinComponent::new(&*addr_of!(PROCESSES))
         .finalize(components::round_robin_component_static!(NUM_PROCS));
+    #[cfg(feature = "mlfq")]
+    let scheduler =
+        components::sched::mlfq::MLFQComponent::new(mux_alarm, &*addr_of!(PROCESSES)).finalize(
+            components::mlfq_component_static!(nrf52840::rtc::Rtc<'static>, NUM_PROCS),
+        );
 
     let platform = Platform {
         button,
@@ -898,6 +1216,8 @@ pub unsafe fn start() -> (
         rng,
         adc,
         temp,
+        thermal,
+        energy,
         alarm,
         analog_comparator,
         ipc: kernel::ipc::IPC::new(
@@ -905,16 +1225,55 @@ pub unsafe fn start() -> (
             kernel::ipc::DRIVER_NUM,
             &memory_allocation_capability,
         ),
+        image_flash,
+        checkpoint_flash,
+        mux_i2c_slave,
         i2c_master_slave,
         spi_controller,
         kv_driver,
+        date_time,
+        config_service,
+        suspendable_drivers,
         scheduler,
-        systick: cortexm4::systick::SysTick::new_with_calibration(64000000),
+        // RTC2 keeps counting timeslices while the CPU sleeps.
+        scheduler_timer: &base_peripherals.rtc2,
     };
 
     let _ = platform.pconsole.start();
     base_peripherals.adc.calibrate();
 
+    boot_timer.mark("other");
+    boot_timer.report();
+
+    // Measure the CPU time of each process, which the `stats` command of the
+    // process console prints.
+    let process_management_capability =
+        create_capability!(capabilities::ProcessManagementCapability);
+    board_kernel.set_cycle_counter(dwt, &process_management_capability);
+
+    // Stop the HFXO while the chip sleeps and no alarm expires within 5 ms,
+    // unless a radio is on.
+    let power_manager = static_init!(
+        nrf52840::power_manager::PowerManager<'static, nrf52840::rtc::Rtc<'static>>,
+        nrf52840::power_manager::PowerManager::new(
+            &base_peripherals.pwr_clk,
+            &base_peripherals.clock,
+            rtc,
+            5000,
+        )
+    );
+    let _ = kernel::platform::power::PowerManager::register(
+        power_manager,
+        &nrf52840_peripherals.ieee802154_radio,
+    );
+    let _ =
+        kernel::platform::power::PowerManager::register(power_manager, &base_peripherals.ble_radio);
+    board_kernel.set_power_manager(
+        power_manager,
+        &create_capability!(capabilities::MainLoopCapability),
+    );
+    kernel::platform::errata::log_errata(&nrf52840::errata::ERRATA);
+
     debug!("Initialization complete. Entering main loop\r");
     debug!("{}", &*addr_of!(nrf52840::ficr::FICR_INSTANCE));
 
@@ -12,21 +12,46 @@
 
 use core::ptr::addr_of_mut;
 
-use kernel::debug;
+use kernel::component::Component;
 use kernel::platform::{KernelResources, SyscallDriverLookup};
-use kernel::{capabilities, create_capability};
-use nrf52840dk_lib::{self, PROCESSES};
+use kernel::{capabilities, create_capability, static_init};
+use nrf52840dk_lib::{self, NUM_PROCS, PROCESSES};
+
+// Board name and kernel version, for host-side tools.
+kernel::kernel_attributes!(board: "nrf52840dk");
+
+// Syscall interfaces of the drivers, for generating userspace bindings.
+kernel::export_driver_apis!(
+    capsules_core::alarm::DRIVER_API,
+    capsules_core::button::DRIVER_API,
+    capsules_core::console::DRIVER_API,
+    capsules_core::led::DRIVER_API,
+    capsules_extra::thermal::DRIVER_API,
+);
 
 // State for loading and holding applications.
 // How should the kernel respond when a process faults.
 const FAULT_RESPONSE: capsules_system::process_policies::PanicFaultPolicy =
     capsules_system::process_policies::PanicFaultPolicy {};
 
+/// Time between the start of the kernel loop and each deferred initialization
+/// task.
+const DEFERRED_INIT_INTERVAL_MS: u32 = 10;
+
+/// `ShortId` of the application allowed to install new applications ("APLD").
+const APP_LOADER_SHORT_ID: u32 = 0x4150_4c44;
+
+/// RAM set aside for the power-on self-test.
+static mut SELF_TEST_RAM: [u32; 256] = [0; 256];
+
 struct Platform {
     base: nrf52840dk_lib::Platform,
     eui64_driver: &'static nrf52840dk_lib::Eui64Driver,
     ieee802154_driver: &'static nrf52840dk_lib::Ieee802154Driver,
     udp_driver: &'static capsules_extra::net::udp::UDPDriver<'static>,
+    tcp_driver: &'static nrf52840dk_lib::TcpDriver,
+    packet_capture: &'static PacketCapture,
+    app_loader: &'static components::app_loader::AppLoaderComponentType,
 }
 
 impl SyscallDriverLookup for Platform {
@@ -37,7 +62,10 @@ impl SyscallDriverLookup for Platform {
         match driver_num {
             capsules_extra::eui64::DRIVER_NUM => f(Some(self.eui64_driver)),
             capsules_extra::net::udp::DRIVER_NUM => f(Some(self.udp_driver)),
+            capsules_extra::net::tcp::DRIVER_NUM => f(Some(self.tcp_driver)),
             capsules_extra::ieee802154::DRIVER_NUM => f(Some(self.ieee802154_driver)),
+            capsules_extra::packet_capture::DRIVER_NUM => f(Some(self.packet_capture)),
+            capsules_extra::app_loader::DRIVER_NUM => f(Some(self.app_loader)),
             _ => self.base.with_driver(driver_num, f),
         }
     }
@@ -45,6 +73,9 @@ impl SyscallDriverLookup for Platform {
 
 type Chip = nrf52840dk_lib::Chip;
 
+type PacketCapture =
+    components::packet_capture::PacketCaptureComponentType<nrf52840::rtc::Rtc<'static>>;
+
 impl KernelResources<Chip> for Platform {
     type SyscallDriverLookup = Self;
     type SyscallFilter = <nrf52840dk_lib::Platform as KernelResources<Chip>>::SyscallFilter;
@@ -85,52 +116,193 @@ pub unsafe fn main() {
         nrf52840dk_lib::start();
 
     //--------------------------------------------------------------------------
-    // IEEE 802.15.4 and UDP
+    // IEEE 802.15.4, UDP and TCP
     //--------------------------------------------------------------------------
 
-    let (eui64_driver, ieee802154_driver, udp_driver) =
+    let (eui64_driver, ieee802154_driver, udp_driver, mux_mac) =
         nrf52840dk_lib::ieee802154_udp(board_kernel, default_peripherals, mux_alarm);
+    let tcp_driver = nrf52840dk_lib::ieee802154_tcp(board_kernel, mux_mac, mux_alarm);
+
+    // Capture of the 802.15.4 frames, which processes start and read.
+    let packet_capture = components::packet_capture::PacketCaptureComponent::new(
+        board_kernel,
+        capsules_extra::packet_capture::DRIVER_NUM,
+        &default_peripherals.nrf52.rtc,
+        capsules_extra::packet_capture::LinkType::Ieee802154,
+        kernel::hil::radio::MAX_FRAME_SIZE as u16,
+    )
+    .finalize(components::packet_capture_component_static!(
+        nrf52840::rtc::Rtc<'static>,
+        2048
+    ));
+    mux_mac.set_packet_observer(packet_capture);
+
+    //--------------------------------------------------------------------------
+    // POWER-ON SELF-TEST
+    //--------------------------------------------------------------------------
+
+    let ram_test =
+        kernel::platform::self_test::RamPatternTest::new("ram", &mut *addr_of_mut!(SELF_TEST_RAM));
+    let self_test_report = kernel::platform::self_test::run_self_tests(
+        &[kernel::platform::self_test::SelfTestEntry::new(
+            &ram_test,
+            kernel::platform::self_test::SelfTestPolicy::Halt,
+        )],
+        &kernel::platform::self_test::DebugSelfTestLog,
+    );
+    if self_test_report.must_halt() {
+        panic!("Power-on self-test failed");
+    }
+
+    //--------------------------------------------------------------------------
+    // PROCESS LOADING
+    //--------------------------------------------------------------------------
+
+    // Credential checking is not used, but the asynchronous process loader
+    // is needed to install new applications at runtime.
+    let checking_policy = components::appid::checker_null::AppCheckerNullComponent::new()
+        .finalize(components::app_checker_null_component_static!());
+    let assigner = components::appid::assigner_tbf::AppIdAssignerTbfHeaderComponent::new()
+        .finalize(components::appid_assigner_tbf_header_component_static!());
+    let checker = components::appid::checker::ProcessCheckerMachineComponent::new(checking_policy)
+        .finalize(components::process_checker_machine_component_static!());
+
+    let storage_permissions_policy =
+        components::storage_permissions::null::StoragePermissionsNullComponent::new().finalize(
+            components::storage_permissions_null_component_static!(
+                Chip,
+                kernel::process::ProcessStandardDebugFull,
+            ),
+        );
+
+    let loader = components::loader::sequential::ProcessLoaderSequentialComponent::new(
+        checker,
+        &mut *addr_of_mut!(PROCESSES),
+        board_kernel,
+        chip,
+        &FAULT_RESPONSE,
+        assigner,
+        storage_permissions_policy,
+    )
+    .finalize(components::process_loader_sequential_component_static!(
+        Chip,
+        kernel::process::ProcessStandardDebugFull,
+        NUM_PROCS
+    ));
+    base_platform.pconsole.set_process_reload(loader);
+
+    //--------------------------------------------------------------------------
+    // DYNAMIC APP LOADING
+    //--------------------------------------------------------------------------
+
+    let dynamic_loader = components::loader::dynamic::DynamicLoaderComponent::new(
+        loader,
+        &default_peripherals.nrf52.nvmc,
+    )
+    .finalize(components::dynamic_loader_component_static!(
+        Chip,
+        kernel::process::ProcessStandardDebugFull,
+        nrf52840::nvmc::Nvmc,
+    ));
+
+    // Install binaries from the external flash with the `install` command of
+    // the process console.
+    let installer = components::loader::install::ProcessInstallerComponent::new(
+        dynamic_loader,
+        base_platform.image_flash,
+        nrf52840dk_lib::APP_IMAGES_START,
+        nrf52840dk_lib::APP_IMAGES_LEN,
+    )
+    .finalize(components::process_installer_component_static!(
+        nrf52840_platform::storage::Mx25r6435fUser
+    ));
+    kernel::process::ProcessInstall::set_client(installer, base_platform.pconsole);
+    base_platform.pconsole.set_process_install(installer);
+
+    // Save and restore stopped processes with the `checkpoint` and `restore`
+    // commands of the process console.
+    let checkpointer = components::process_checkpoint::ProcessCheckpointerComponent::new(
+        board_kernel,
+        base_platform.checkpoint_flash,
+        nrf52840dk_lib::CHECKPOINTS_START,
+        nrf52840dk_lib::CHECKPOINT_SLOT_SIZE,
+        nrf52840dk_lib::NUM_PROCS,
+    )
+    .finalize(components::process_checkpointer_component_static!(
+        nrf52840_platform::storage::Mx25r6435fUser
+    ));
+    kernel::process::ProcessCheckpoint::set_client(checkpointer, base_platform.pconsole);
+    base_platform.pconsole.set_process_checkpoint(checkpointer);
+
+    // Only the application with this `ShortId` in its TBF header may install
+    // new applications with the app loader driver.
+    let app_loader_ids = static_init!(
+        [kernel::process::ShortId; 1],
+        [kernel::process::ShortId::Fixed(
+            core::num::NonZeroU32::new(APP_LOADER_SHORT_ID).unwrap()
+        )]
+    );
+    let app_loader_policy = static_init!(
+        capsules_extra::app_loader::ShortIdInstallPolicy,
+        capsules_extra::app_loader::ShortIdInstallPolicy::new(app_loader_ids)
+    );
+    let app_loader = components::app_loader::AppLoaderComponent::new(
+        board_kernel,
+        capsules_extra::app_loader::DRIVER_NUM,
+        installer,
+        app_loader_policy,
+    )
+    .finalize(components::app_loader_component_static!());
+
+    // Install binaries sent over the console with the `receive` command of
+    // the process console.
+    kernel::process::DynamicProcessLoading::set_client(app_loader, base_platform.pconsole);
+    base_platform.pconsole.set_dynamic_loader(app_loader);
 
     let platform = Platform {
         base: base_platform,
         eui64_driver,
         ieee802154_driver,
         udp_driver,
+        tcp_driver,
+        packet_capture,
+        app_loader,
     };
 
-    // These symbols are defined in the linker script.
-    extern "C" {
-        /// Beginning of the ROM region containing app images.
-        static _sapps: u8;
-        /// End of the ROM region containing app images.
-        static _eapps: u8;
-        /// Beginning of the RAM region for app memory.
-        static mut _sappmem: u8;
-        /// End of the RAM region for app memory.
-        static _eappmem: u8;
-    }
+    //--------------------------------------------------------------------------
+    // KERNEL INTEGRITY
+    //--------------------------------------------------------------------------
 
-    let process_management_capability =
-        create_capability!(capabilities::ProcessManagementCapability);
-    kernel::process::load_processes(
-        board_kernel,
-        chip,
-        core::slice::from_raw_parts(
-            core::ptr::addr_of!(_sapps),
-            core::ptr::addr_of!(_eapps) as usize - core::ptr::addr_of!(_sapps) as usize,
-        ),
-        core::slice::from_raw_parts_mut(
-            core::ptr::addr_of_mut!(_sappmem),
-            core::ptr::addr_of!(_eappmem) as usize - core::ptr::addr_of!(_sappmem) as usize,
+    let sha = components::sha::ShaSoftware256Component::new()
+        .finalize(components::sha_software_256_component_static!());
+    let integrity = components::kernel_integrity::KernelIntegrityComponent::new(sha).finalize(
+        components::kernel_integrity_component_static!(
+            capsules_extra::sha256::Sha256Software<'static>
         ),
-        &mut *addr_of_mut!(PROCESSES),
-        &FAULT_RESPONSE,
-        &process_management_capability,
+    );
+    integrity.set_log(&kernel::platform::self_test::DebugSelfTestLog);
+    platform.base.pconsole.set_kernel_integrity(integrity);
+
+    //--------------------------------------------------------------------------
+    // DEFERRED INITIALIZATION
+    //--------------------------------------------------------------------------
+
+    // Hashing the kernel is slow, so it starts after the kernel loop, once
+    // processes had a chance to run. The check is skipped if no hash was
+    // written when flashing the kernel.
+    let deferred_init_tasks = static_init!(
+        [&'static dyn capsules_system::deferred_init::DeferredInitClient; 1],
+        [integrity]
+    );
+    let deferred_init = components::deferred_init::DeferredInitComponent::new(
+        mux_alarm,
+        deferred_init_tasks,
+        DEFERRED_INIT_INTERVAL_MS,
     )
-    .unwrap_or_else(|err| {
-        debug!("Error loading processes!");
-        debug!("{:?}", err);
-    });
+    .finalize(components::deferred_init_component_static!(
+        nrf52840::rtc::Rtc<'static>
+    ));
+    deferred_init.start();
 
     let main_loop_capability = create_capability!(capabilities::MainLoopCapability);
     board_kernel.kernel_loop(
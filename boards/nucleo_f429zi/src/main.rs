@@ -43,6 +43,15 @@ static mut CHIP: Option<&'static stm32f429zi::chip::Stm32f4xx<Stm32f429ziDefault
 static mut PROCESS_PRINTER: Option<&'static capsules_system::process_printer::ProcessPrinterText> =
     None;
 
+// Alignment constraints of app memory, for tools placing apps at fixed RAM
+// addresses.
+kernel::app_memory_alignment_attribute!(
+    <stm32f429zi::chip::Stm32f4xx<Stm32f429ziDefaultPeripherals> as kernel::platform::chip::Chip>::MPU
+);
+
+// Board name and kernel version, for host-side tools.
+kernel::kernel_attributes!(board: "nucleo_f429zi");
+
 // How should the kernel respond when a process faults.
 const FAULT_RESPONSE: capsules_system::process_policies::PanicFaultPolicy =
     capsules_system::process_policies::PanicFaultPolicy {};
@@ -287,7 +296,6 @@ unsafe fn set_pin_primary_functions(
 /// Helper function for miscellaneous peripheral functions
 unsafe fn setup_peripherals(
     tim2: &stm32f429zi::tim2::Tim2,
-    trng: &stm32f429zi::trng::Trng,
     can1: &'static stm32f429zi::can::Can,
     rtc: &'static stm32f429zi::rtc::Rtc,
 ) {
@@ -299,9 +307,6 @@ unsafe fn setup_peripherals(
     tim2.start();
     cortexm4::nvic::Nvic::new(stm32f429zi::nvic::TIM2).enable();
 
-    // RNG
-    trng.enable_clock();
-
     // CAN
     can1.enable_clock();
 
@@ -345,12 +350,7 @@ unsafe fn start() -> (
     peripherals.init();
     let base_peripherals = &peripherals.stm32f4;
 
-    setup_peripherals(
-        &base_peripherals.tim2,
-        &peripherals.trng,
-        &peripherals.can1,
-        &peripherals.rtc,
-    );
+    setup_peripherals(&base_peripherals.tim2, &peripherals.can1, &peripherals.rtc);
 
     set_pin_primary_functions(syscfg, &base_peripherals.gpio_ports);
 
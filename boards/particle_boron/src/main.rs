@@ -17,16 +17,11 @@ use core::ptr::addr_of;
 use core::ptr::addr_of_mut;
 
 use capsules_core::i2c_master_slave_driver::I2CMasterSlaveDriver;
-use capsules_core::virtualizers::virtual_aes_ccm::MuxAES128CCM;
-use capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm;
 use kernel::component::Component;
-use kernel::deferred_call::DeferredCallClient;
 use kernel::hil::gpio::Configure;
 use kernel::hil::gpio::FloatingState;
 use kernel::hil::i2c::{I2CMaster, I2CSlave};
 use kernel::hil::led::LedLow;
-use kernel::hil::symmetric_encryption::AES128;
-use kernel::hil::time::Counter;
 use kernel::platform::{KernelResources, SyscallDriverLookup};
 use kernel::scheduler::round_robin::RoundRobinSched;
 #[allow(unused_imports)]
@@ -95,21 +90,11 @@ pub static mut STACK_MEMORY: [u8; 0x1000] = [0; 0x1000];
 
 type TemperatureDriver =
     components::temperature::TemperatureComponentType<nrf52840::temperature::Temp<'static>>;
-type RngDriver = components::rng::RngComponentType<nrf52840::trng::Trng<'static>>;
-
-type Ieee802154Driver = components::ieee802154::Ieee802154ComponentType<
-    nrf52840::ieee802154_radio::Radio<'static>,
-    nrf52840::aes::AesECB<'static>,
->;
 
 /// Supported drivers by the platform
 pub struct Platform {
-    ble_radio: &'static capsules_extra::ble_advertising_driver::BLE<
-        'static,
-        nrf52840::ble_radio::Radio<'static>,
-        VirtualMuxAlarm<'static, nrf52840::rtc::Rtc<'static>>,
-    >,
-    ieee802154_radio: &'static Ieee802154Driver,
+    ble_radio: &'static nrf52840_platform::radio::BleDriver,
+    ieee802154_radio: &'static nrf52840_platform::radio::Ieee802154Driver,
     button: &'static capsules_core::button::Button<'static, nrf52840::gpio::GPIOPin<'static>>,
     console: &'static capsules_core::console::Console<'static>,
     gpio: &'static capsules_core::gpio::GPIO<'static, nrf52840::gpio::GPIOPin<'static>>,
@@ -119,20 +104,14 @@ pub struct Platform {
         4,
     >,
     adc: &'static capsules_core::adc::AdcVirtualized<'static>,
-    rng: &'static RngDriver,
+    rng: &'static nrf52840_platform::crypto::RngDriver,
     temp: &'static TemperatureDriver,
     ipc: kernel::ipc::IPC<{ NUM_PROCS as u8 }>,
     i2c_master_slave: &'static capsules_core::i2c_master_slave_driver::I2CMasterSlaveDriver<
         'static,
         nrf52840::i2c::TWI<'static>,
     >,
-    alarm: &'static capsules_core::alarm::AlarmDriver<
-        'static,
-        capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<
-            'static,
-            nrf52840::rtc::Rtc<'static>,
-        >,
-    >,
+    alarm: &'static nrf52840_platform::alarm::AlarmDriver,
     scheduler: &'static RoundRobinSched<'static>,
     systick: cortexm4::systick::SysTick,
 }
@@ -356,16 +335,8 @@ pub unsafe fn start_particle_boron() -> (
     // ALARM & TIMER
     //--------------------------------------------------------------------------
 
-    let rtc = &base_peripherals.rtc;
-    let _ = rtc.start();
-    let mux_alarm = components::alarm::AlarmMuxComponent::new(rtc)
-        .finalize(components::alarm_mux_component_static!(nrf52840::rtc::Rtc));
-    let alarm = components::alarm::AlarmDriverComponent::new(
-        board_kernel,
-        capsules_core::alarm::DRIVER_NUM,
-        mux_alarm,
-    )
-    .finalize(components::alarm_component_static!(nrf52840::rtc::Rtc));
+    let nrf52840_platform::alarm::Alarm { mux_alarm, alarm } =
+        nrf52840_platform::alarm::setup(board_kernel, &base_peripherals.rtc);
 
     //--------------------------------------------------------------------------
     // UART & CONSOLE & DEBUG
@@ -380,61 +351,32 @@ pub unsafe fn start_particle_boron() -> (
         nrf52840::rtc::Rtc
     ));
 
-    // Process Printer for displaying process information.
-    let process_printer = components::process_printer::ProcessPrinterTextComponent::new()
-        .finalize(components::process_printer_text_component_static!());
-    PROCESS_PRINTER = Some(process_printer);
-
-    // Create a shared UART channel for the console and for kernel debug.
-    let uart_mux = components::console::UartMuxComponent::new(uart_channel, 115200)
-        .finalize(components::uart_mux_component_static!(132));
-
-    // Setup the console.
-    let console = components::console::ConsoleComponent::new(
-        board_kernel,
-        capsules_core::console::DRIVER_NUM,
-        uart_mux,
-    )
-    .finalize(components::console_component_static!(132, 132));
-    // Create the debugger object that handles calls to `debug!()`.
-    components::debug_writer::DebugWriterComponent::new(uart_mux)
-        .finalize(components::debug_writer_component_static!());
+    // Share the UART channel between the console and kernel debug.
+    let console = nrf52840_platform::console::setup(board_kernel, uart_channel, 115200);
+    PROCESS_PRINTER = Some(console.process_printer);
 
     //--------------------------------------------------------------------------
     // WIRELESS
     //--------------------------------------------------------------------------
 
-    let ble_radio = components::ble::BLEComponent::new(
+    let crypto = nrf52840_platform::crypto::setup(
         board_kernel,
-        capsules_extra::ble_advertising_driver::DRIVER_NUM,
-        &base_peripherals.ble_radio,
-        mux_alarm,
-    )
-    .finalize(components::ble_component_static!(
-        nrf52840::rtc::Rtc,
-        nrf52840::ble_radio::Radio
-    ));
-
-    let aes_mux = static_init!(
-        MuxAES128CCM<'static, nrf52840::aes::AesECB>,
-        MuxAES128CCM::new(&base_peripherals.ecb,)
+        &base_peripherals.trng,
+        &base_peripherals.ecb,
     );
-    base_peripherals.ecb.set_client(aes_mux);
-    aes_mux.register();
 
-    let (ieee802154_radio, _mux_mac) = components::ieee802154::Ieee802154Component::new(
+    let radio = nrf52840_platform::radio::setup(
         board_kernel,
-        capsules_extra::ieee802154::DRIVER_NUM,
+        &base_peripherals.ble_radio,
         &nrf52840_peripherals.ieee802154_radio,
-        aes_mux,
-        PAN_ID,
-        SRC_MAC,
-        DEFAULT_EXT_SRC_MAC,
-    )
-    .finalize(components::ieee802154_component_static!(
-        nrf52840::ieee802154_radio::Radio,
-        nrf52840::aes::AesECB<'static>
-    ));
+        crypto.aes_mux,
+        mux_alarm,
+        nrf52840_platform::radio::Ieee802154Addresses {
+            pan_id: PAN_ID,
+            short_addr: SRC_MAC,
+            long_addr: DEFAULT_EXT_SRC_MAC,
+        },
+    );
 
     //--------------------------------------------------------------------------
     // Sensor
@@ -449,17 +391,6 @@ pub unsafe fn start_particle_boron() -> (
         nrf52840::temperature::Temp
     ));
 
-    //--------------------------------------------------------------------------
-    // RANDOM NUMBERS
-    //--------------------------------------------------------------------------
-
-    let rng = components::rng::RngComponent::new(
-        board_kernel,
-        capsules_core::rng::DRIVER_NUM,
-        &base_peripherals.trng,
-    )
-    .finalize(components::rng_component_static!(nrf52840::trng::Trng));
-
     //--------------------------------------------------------------------------
     // ADC
     //--------------------------------------------------------------------------
@@ -559,13 +490,13 @@ pub unsafe fn start_particle_boron() -> (
 
     let platform = Platform {
         button,
-        ble_radio,
-        ieee802154_radio,
-        console,
+        ble_radio: radio.ble,
+        ieee802154_radio: radio.ieee802154,
+        console: console.console,
         led,
         gpio,
         adc: adc_syscall,
-        rng,
+        rng: crypto.rng,
         temp,
         alarm,
         ipc: kernel::ipc::IPC::new(
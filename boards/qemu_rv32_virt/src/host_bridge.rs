@@ -0,0 +1,125 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Virtual peripherals forwarded to a process on the host.
+//!
+//! The `virt` machine has no LEDs, buttons, sensors or screens. With the
+//! `host_bridge` feature enabled, this board instead creates virtual versions
+//! of these peripherals which are forwarded to the host over the console UART
+//! (see `capsules_extra::host_bridge` for the protocol). The host side has to
+//! pick the frames, which start with `0x02`, out of the console output.
+//!
+//! Device identifiers:
+//!
+//! - 0 and 1: LEDs
+//! - 2: button
+//! - 3: temperature and humidity sensor
+//! - 4: 128x64 monochrome screen
+
+use capsules_core::virtualizers::virtual_uart::MuxUart;
+use capsules_extra::host_bridge::pin::HostBridgePin;
+use capsules_extra::host_bridge::screen::HostBridgeScreen;
+use capsules_extra::host_bridge::sensor::HostBridgeSensor;
+use kernel::component::Component;
+use kernel::hil::gpio;
+use kernel::hil::led::LedHigh;
+use kernel::hil::screen::ScreenPixelFormat;
+use kernel::static_init;
+
+const SCREEN_RESOLUTION: (usize, usize) = (128, 64);
+
+type LedDriver = components::led::LedsComponentType<LedHigh<'static, HostBridgePin<'static>>, 2>;
+type ButtonDriver = components::button::ButtonComponentType<HostBridgePin<'static>>;
+type TemperatureDriver =
+    components::temperature::TemperatureComponentType<HostBridgeSensor<'static>>;
+type HumidityDriver = components::humidity::HumidityComponentType<HostBridgeSensor<'static>>;
+
+/// Syscall drivers for the virtual peripherals.
+pub struct HostBridgeDrivers {
+    pub led: &'static LedDriver,
+    pub button: &'static ButtonDriver,
+    pub temperature: &'static TemperatureDriver,
+    pub humidity: &'static HumidityDriver,
+    pub screen: &'static components::screen::ScreenComponentType,
+}
+
+pub unsafe fn setup(
+    board_kernel: &'static kernel::Kernel,
+    uart_mux: &'static MuxUart<'static>,
+) -> HostBridgeDrivers {
+    let bridge = components::host_bridge::HostBridgeComponent::new(uart_mux)
+        .finalize(components::host_bridge_component_static!());
+
+    let led_pins = static_init!(
+        [HostBridgePin<'static>; 2],
+        [HostBridgePin::new(bridge, 0), HostBridgePin::new(bridge, 1)]
+    );
+    let button_pin = static_init!(HostBridgePin<'static>, HostBridgePin::new(bridge, 2));
+    let sensor = static_init!(HostBridgeSensor<'static>, HostBridgeSensor::new(bridge, 3));
+    let screen = static_init!(
+        HostBridgeScreen<'static>,
+        HostBridgeScreen::new(bridge, 4, SCREEN_RESOLUTION, ScreenPixelFormat::Mono)
+    );
+    for pin in led_pins.iter() {
+        bridge.add_device(pin);
+    }
+    bridge.add_device(button_pin);
+    bridge.add_device(sensor);
+    bridge.add_device(screen);
+
+    let led = components::led::LedsComponent::new().finalize(components::led_component_static!(
+        LedHigh<'static, HostBridgePin<'static>>,
+        LedHigh::new(&led_pins[0]),
+        LedHigh::new(&led_pins[1]),
+    ));
+
+    let button = components::button::ButtonComponent::new(
+        board_kernel,
+        capsules_core::button::DRIVER_NUM,
+        components::button_component_helper!(
+            HostBridgePin<'static>,
+            (
+                button_pin,
+                gpio::ActivationMode::ActiveHigh,
+                gpio::FloatingState::PullNone
+            )
+        ),
+    )
+    .finalize(components::button_component_static!(HostBridgePin<'static>));
+
+    let temperature = components::temperature::TemperatureComponent::new(
+        board_kernel,
+        capsules_extra::temperature::DRIVER_NUM,
+        sensor,
+    )
+    .finalize(components::temperature_component_static!(
+        HostBridgeSensor<'static>
+    ));
+    let humidity = components::humidity::HumidityComponent::new(
+        board_kernel,
+        capsules_extra::humidity::DRIVER_NUM,
+        sensor,
+    )
+    .finalize(components::humidity_component_static!(
+        HostBridgeSensor<'static>
+    ));
+
+    let screen = components::screen::ScreenComponent::new(
+        board_kernel,
+        capsules_extra::screen::DRIVER_NUM,
+        screen,
+        None,
+    )
+    .finalize(components::screen_component_static!(1024));
+
+    let _ = bridge.start();
+
+    HostBridgeDrivers {
+        led,
+        button,
+        temperature,
+        humidity,
+        screen,
+    }
+}
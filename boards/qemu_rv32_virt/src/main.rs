@@ -25,6 +25,8 @@ use kernel::{create_capability, debug, static_init};
 use qemu_rv32_virt_chip::chip::{QemuRv32VirtChip, QemuRv32VirtDefaultPeripherals};
 use rv32i::csr;
 
+#[cfg(feature = "host_bridge")]
+mod host_bridge;
 pub mod io;
 
 pub const NUM_PROCS: usize = 4;
@@ -41,6 +43,15 @@ static mut CHIP: Option<&'static QemuRv32VirtChip<QemuRv32VirtDefaultPeripherals
 static mut PROCESS_PRINTER: Option<&'static capsules_system::process_printer::ProcessPrinterText> =
     None;
 
+// Alignment constraints of app memory, for tools placing apps at fixed RAM
+// addresses.
+kernel::app_memory_alignment_attribute!(
+    <QemuRv32VirtChip<QemuRv32VirtDefaultPeripherals> as kernel::platform::chip::Chip>::MPU
+);
+
+// Board name and kernel version, for host-side tools.
+kernel::kernel_attributes!(board: "qemu_rv32_virt");
+
 // How should the kernel respond when a process faults.
 const FAULT_RESPONSE: capsules_system::process_policies::PanicFaultPolicy =
     capsules_system::process_policies::PanicFaultPolicy {};
@@ -50,6 +61,22 @@ const FAULT_RESPONSE: capsules_system::process_policies::PanicFaultPolicy =
 #[link_section = ".stack_buffer"]
 pub static mut STACK_MEMORY: [u8; 0x8000] = [0; 0x8000];
 
+/// Number of harts the kernel runs on, if QEMU emulates them (`-smp`).
+const NUM_HARTS: usize = 2;
+
+/// Size of the stack of each hart other than hart 0.
+const HART_STACK_SIZE: usize = 0x2000;
+
+#[repr(align(16))]
+struct HartStacks([u8; HART_STACK_SIZE * (NUM_HARTS - 1)]);
+
+/// Stacks of the harts other than hart 0.
+static mut HART_STACKS: HartStacks = HartStacks([0; HART_STACK_SIZE * (NUM_HARTS - 1)]);
+
+// References to the kernel and the platform for the other harts.
+static mut BOARD_KERNEL: Option<&'static kernel::Kernel> = None;
+static mut PLATFORM: Option<&'static QemuRv32VirtPlatform> = None;
+
 /// A structure representing this platform that holds references to all
 /// capsules for this platform. We've included an alarm and console.
 struct QemuRv32VirtPlatform {
@@ -67,6 +94,7 @@ struct QemuRv32VirtPlatform {
         'static,
         capsules_core::virtualizers::virtual_uart::UartDevice<'static>,
     >,
+    app_log: &'static components::app_log::AppLogComponentType,
     alarm: &'static capsules_core::alarm::AlarmDriver<
         'static,
         VirtualMuxAlarm<'static, qemu_rv32_virt_chip::chip::QemuRv32VirtClint<'static>>,
@@ -82,6 +110,20 @@ struct QemuRv32VirtPlatform {
             qemu_rv32_virt_chip::virtio::devices::virtio_rng::VirtIORng<'static, 'static>,
         >,
     >,
+    ipv4_udp: Option<
+        &'static components::ipv4_udp_driver::Ipv4UdpDriverComponentType<
+            qemu_rv32_virt_chip::chip::QemuRv32VirtClint<'static>,
+            2,
+        >,
+    >,
+    #[cfg(feature = "host_bridge")]
+    host_bridge: host_bridge::HostBridgeDrivers,
+    #[cfg(feature = "benchmark")]
+    benchmark: &'static capsules_extra::benchmark::Benchmark<
+        'static,
+        VirtualMuxAlarm<'static, qemu_rv32_virt_chip::chip::QemuRv32VirtClint<'static>>,
+        rv32i::cycle_counter::McycleCounter,
+    >,
 }
 
 /// Mapping of integer syscalls to objects that implement syscalls.
@@ -94,6 +136,7 @@ impl SyscallDriverLookup for QemuRv32VirtPlatform {
             capsules_core::console::DRIVER_NUM => f(Some(self.console)),
             capsules_core::alarm::DRIVER_NUM => f(Some(self.alarm)),
             capsules_core::low_level_debug::DRIVER_NUM => f(Some(self.lldb)),
+            capsules_extra::app_log::DRIVER_NUM => f(Some(self.app_log)),
             capsules_core::rng::DRIVER_NUM => {
                 if let Some(rng_driver) = self.virtio_rng {
                     f(Some(rng_driver))
@@ -101,7 +144,26 @@ impl SyscallDriverLookup for QemuRv32VirtPlatform {
                     f(None)
                 }
             }
+            capsules_extra::net::ipv4::DRIVER_NUM => {
+                if let Some(ipv4_udp) = self.ipv4_udp {
+                    f(Some(ipv4_udp))
+                } else {
+                    f(None)
+                }
+            }
             kernel::ipc::DRIVER_NUM => f(Some(&self.ipc)),
+            #[cfg(feature = "host_bridge")]
+            capsules_core::led::DRIVER_NUM => f(Some(self.host_bridge.led)),
+            #[cfg(feature = "host_bridge")]
+            capsules_core::button::DRIVER_NUM => f(Some(self.host_bridge.button)),
+            #[cfg(feature = "host_bridge")]
+            capsules_extra::temperature::DRIVER_NUM => f(Some(self.host_bridge.temperature)),
+            #[cfg(feature = "host_bridge")]
+            capsules_extra::humidity::DRIVER_NUM => f(Some(self.host_bridge.humidity)),
+            #[cfg(feature = "host_bridge")]
+            capsules_extra::screen::DRIVER_NUM => f(Some(self.host_bridge.screen)),
+            #[cfg(feature = "benchmark")]
+            capsules_extra::benchmark::DRIVER_NUM => f(Some(self.benchmark)),
             _ => f(None),
         }
     }
@@ -148,28 +210,11 @@ impl
     }
 }
 
-/// This is in a separate, inline(never) function so that its stack frame is
-/// removed when this function returns. Otherwise, the stack space used for
-/// these static_inits is wasted.
-#[inline(never)]
-unsafe fn start() -> (
-    &'static kernel::Kernel,
-    QemuRv32VirtPlatform,
-    &'static qemu_rv32_virt_chip::chip::QemuRv32VirtChip<
-        'static,
-        QemuRv32VirtDefaultPeripherals<'static>,
-    >,
-) {
+/// Kernel memory protection with the ePMP of the current hart.
+unsafe fn kernel_protection(
+) -> rv32i::pmp::kernel_protection_mml_epmp::KernelProtectionMMLEPMP<16, 5> {
     // These symbols are defined in the linker script.
     extern "C" {
-        /// Beginning of the ROM region containing app images.
-        static _sapps: u8;
-        /// End of the ROM region containing app images.
-        static _eapps: u8;
-        /// Beginning of the RAM region for app memory.
-        static mut _sappmem: u8;
-        /// End of the RAM region for app memory.
-        static _eappmem: u8;
         /// The start of the kernel text (Included only for kernel PMP)
         static _stext: u8;
         /// The end of the kernel text (Included only for kernel PMP)
@@ -183,16 +228,7 @@ unsafe fn start() -> (
         /// The end of the kernel / app RAM (Included only for kernel PMP)
         static _esram: u8;
     }
-
-    // ---------- BASIC INITIALIZATION -----------
-
-    // Basic setup of the RISC-V IMAC platform
-    rv32i::configure_trap_handler();
-
-    // Set up memory protection immediately after setting the trap handler, to
-    // ensure that much of the board initialization routine runs with ePMP
-    // protection.
-    let epmp = rv32i::pmp::kernel_protection_mml_epmp::KernelProtectionMMLEPMP::new(
+    rv32i::pmp::kernel_protection_mml_epmp::KernelProtectionMMLEPMP::new(
         rv32i::pmp::kernel_protection_mml_epmp::FlashRegion(
             rv32i::pmp::NAPOTRegionSpec::new(
                 core::ptr::addr_of!(_sflash),
@@ -222,7 +258,42 @@ unsafe fn start() -> (
             .unwrap(),
         ),
     )
-    .unwrap();
+    .unwrap()
+}
+
+/// This is in a separate, inline(never) function so that its stack frame is
+/// removed when this function returns. Otherwise, the stack space used for
+/// these static_inits is wasted.
+#[inline(never)]
+unsafe fn start() -> (
+    &'static kernel::Kernel,
+    QemuRv32VirtPlatform,
+    &'static qemu_rv32_virt_chip::chip::QemuRv32VirtChip<
+        'static,
+        QemuRv32VirtDefaultPeripherals<'static>,
+    >,
+) {
+    // These symbols are defined in the linker script.
+    extern "C" {
+        /// Beginning of the ROM region containing app images.
+        static _sapps: u8;
+        /// End of the ROM region containing app images.
+        static _eapps: u8;
+        /// Beginning of the RAM region for app memory.
+        static mut _sappmem: u8;
+        /// End of the RAM region for app memory.
+        static _eappmem: u8;
+    }
+
+    // ---------- BASIC INITIALIZATION -----------
+
+    // Basic setup of the RISC-V IMAC platform
+    rv32i::configure_trap_handler();
+
+    // Set up memory protection immediately after setting the trap handler, to
+    // ensure that much of the board initialization routine runs with ePMP
+    // protection.
+    let epmp = kernel_protection();
 
     // Acquire required capabilities
     let process_mgmt_cap = create_capability!(capabilities::ProcessManagementCapability);
@@ -238,12 +309,6 @@ unsafe fn start() -> (
         QemuRv32VirtDefaultPeripherals::new(),
     );
 
-    // Create a shared UART channel for the console and for kernel
-    // debug over the provided memory-mapped 16550-compatible
-    // UART.
-    let uart_mux = components::console::UartMuxComponent::new(&peripherals.uart0, 115200)
-        .finalize(components::uart_mux_component_static!());
-
     // Use the RISC-V machine timer timesource
     let hardware_timer = static_init!(
         qemu_rv32_virt_chip::chip::QemuRv32VirtClint,
@@ -258,6 +323,47 @@ unsafe fn start() -> (
     );
     hil::time::Alarm::set_alarm_client(hardware_timer, mux_alarm);
 
+    // With the `fault_injection` feature, the console UART goes through a
+    // fault injection wrapper, configured with the `inject` process console
+    // command.
+    #[cfg(feature = "fault_injection")]
+    let (console_uart, fault_sites): (
+        &'static dyn hil::uart::Uart<'static>,
+        &'static [&'static kernel::utilities::fault_injection::FaultSite],
+    ) = {
+        let uart_fault_alarm = static_init!(
+            VirtualMuxAlarm<'static, qemu_rv32_virt_chip::chip::QemuRv32VirtClint>,
+            VirtualMuxAlarm::new(mux_alarm)
+        );
+        uart_fault_alarm.setup();
+        let uart_faults = static_init!(
+            capsules_extra::fault_injection::uart::UartFaults<
+                'static,
+                qemu_rv32_virt_chip::uart::Uart16550,
+                VirtualMuxAlarm<'static, qemu_rv32_virt_chip::chip::QemuRv32VirtClint>,
+            >,
+            capsules_extra::fault_injection::uart::UartFaults::new(
+                &peripherals.uart0,
+                uart_fault_alarm,
+                "uart0"
+            )
+        );
+        uart_faults.setup();
+        let fault_sites = static_init!(
+            [&'static kernel::utilities::fault_injection::FaultSite; 1],
+            [uart_faults.fault_site()]
+        );
+        (uart_faults, fault_sites)
+    };
+    #[cfg(not(feature = "fault_injection"))]
+    let console_uart: &'static dyn hil::uart::Uart<'static> = &peripherals.uart0;
+
+    // Create a shared UART channel for the console and for kernel
+    // debug over the provided memory-mapped 16550-compatible
+    // UART.
+    let uart_mux = components::console::UartMuxComponent::new(console_uart, 115200)
+        .finalize(components::uart_mux_component_static!());
+
     // Virtual alarm for the scheduler
     let systick_virtual_alarm = static_init!(
         VirtualMuxAlarm<'static, qemu_rv32_virt_chip::chip::QemuRv32VirtClint>,
@@ -365,14 +471,14 @@ unsafe fn start() -> (
         None
     };
 
-    // If there is a VirtIO NetworkCard present, use the appropriate VirtIONet
-    // driver. Currently this is not used, as work on the userspace network
-    // driver and kernel network stack is in progress.
-    //
-    // A template dummy driver is provided to verify basic functionality of this
-    // interface.
-    let _virtio_net_if: Option<
-        &'static qemu_rv32_virt_chip::virtio::devices::virtio_net::VirtIONet<'static>,
+    // If there is a VirtIO NetworkCard present, run an IPv4 interface on it
+    // and give apps UDP sockets on that interface. The interface gets its
+    // address from a DHCP server, such as the one of QEMU's SLIRP network.
+    let ipv4_udp_driver: Option<
+        &'static components::ipv4_udp_driver::Ipv4UdpDriverComponentType<
+            qemu_rv32_virt_chip::chip::QemuRv32VirtClint<'static>,
+            2,
+        >,
     > = if let Some(net_idx) = virtio_net_idx {
         use qemu_rv32_virt_chip::virtio::devices::virtio_net::VirtIONet;
         use qemu_rv32_virt_chip::virtio::queues::split_queue::{
@@ -442,14 +548,27 @@ unsafe fn start() -> (
             .initialize(virtio_net, mmio_queues)
             .unwrap();
 
-        // Don't forget to enable RX once when integrating this into a
-        // proper Ethernet stack:
-        // virtio_net.enable_rx();
+        let ipv4_stack = components::ipv4_stack::Ipv4StackComponent::new(
+            virtio_net,
+            mux_alarm,
+            // QEMU's default MAC address, already known to its DHCP server
+            [0x52, 0x54, 0x00, 0x12, 0x34, 0x56],
+        )
+        .finalize(components::ipv4_stack_component_static!(
+            qemu_rv32_virt_chip::chip::QemuRv32VirtClint
+        ));
 
-        // TODO: When we have a proper Ethernet driver available for userspace,
-        // return that. For now, just return a reference to the raw VirtIONet
-        // driver:
-        Some(virtio_net as &'static VirtIONet)
+        let ipv4_udp_driver = components::ipv4_udp_driver::Ipv4UdpDriverComponent::new(
+            board_kernel,
+            capsules_extra::net::ipv4::DRIVER_NUM,
+            ipv4_stack,
+        )
+        .finalize(components::ipv4_udp_driver_component_static!(
+            qemu_rv32_virt_chip::chip::QemuRv32VirtClint,
+            2
+        ));
+
+        Some(ipv4_udp_driver)
     } else {
         // No VirtIO NetworkCard discovered
         None
@@ -490,6 +609,8 @@ unsafe fn start() -> (
     .finalize(components::process_console_component_static!(
         qemu_rv32_virt_chip::chip::QemuRv32VirtClint
     ));
+    #[cfg(feature = "fault_injection")]
+    pconsole.set_fault_sites(fault_sites);
 
     // Setup the console.
     let console = components::console::ConsoleComponent::new(
@@ -509,6 +630,33 @@ unsafe fn start() -> (
     )
     .finalize(components::low_level_debug_component_static!());
 
+    let app_log = components::app_log::AppLogComponent::new(
+        board_kernel,
+        capsules_extra::app_log::DRIVER_NUM,
+        capsules_extra::app_log::Level::Info,
+    )
+    .finalize(components::app_log_component_static!());
+
+    // Virtual peripherals forwarded to the host over the console UART.
+    #[cfg(feature = "host_bridge")]
+    let host_bridge = host_bridge::setup(board_kernel, uart_mux);
+
+    // Kernel benchmarks, see `make run-benchmark`.
+    #[cfg(feature = "benchmark")]
+    let benchmark = components::benchmark::BenchmarkComponent::new(
+        board_kernel,
+        capsules_extra::benchmark::DRIVER_NUM,
+        mux_alarm,
+        static_init!(
+            rv32i::cycle_counter::McycleCounter,
+            rv32i::cycle_counter::McycleCounter::new()
+        ),
+    )
+    .finalize(components::benchmark_component_static!(
+        qemu_rv32_virt_chip::chip::QemuRv32VirtClint,
+        rv32i::cycle_counter::McycleCounter
+    ));
+
     let scheduler =
         components::sched::cooperative::CooperativeComponent::new(&*addr_of!(PROCESSES))
             .finalize(components::cooperative_component_static!(NUM_PROCS));
@@ -525,9 +673,15 @@ unsafe fn start() -> (
         console,
         alarm,
         lldb,
+        app_log,
         scheduler,
         scheduler_timer,
         virtio_rng: virtio_rng_driver,
+        ipv4_udp: ipv4_udp_driver,
+        #[cfg(feature = "host_bridge")]
+        host_bridge,
+        #[cfg(feature = "benchmark")]
+        benchmark,
         ipc: kernel::ipc::IPC::new(
             board_kernel,
             kernel::ipc::DRIVER_NUM,
@@ -538,6 +692,9 @@ unsafe fn start() -> (
     // Start the process console:
     let _ = platform.pconsole.start();
 
+    #[cfg(feature = "benchmark")]
+    let _ = platform.benchmark.start_alarm_jitter(100, 10);
+
     debug!("QEMU RISC-V 32-bit \"virt\" machine, initialization complete.");
     debug!("Entering main loop.");
 
@@ -566,11 +723,35 @@ unsafe fn start() -> (
     (board_kernel, platform, chip)
 }
 
+/// Entry point of the harts other than hart 0.
+unsafe extern "C" fn secondary_hart_main(_hart: usize) -> ! {
+    let main_loop_capability = create_capability!(capabilities::MainLoopCapability);
+
+    rv32i::configure_trap_handler();
+    let chip = (*addr_of!(CHIP)).unwrap();
+    chip.start_secondary_hart(kernel_protection());
+
+    let board_kernel = (*addr_of!(BOARD_KERNEL)).unwrap();
+    let platform = (*addr_of!(PLATFORM)).unwrap();
+    board_kernel.smp_kernel_loop(platform, chip, Some(&platform.ipc), &main_loop_capability)
+}
+
 /// Main function called after RAM initialized.
 #[no_mangle]
 pub unsafe fn main() {
     let main_loop_capability = create_capability!(capabilities::MainLoopCapability);
 
     let (board_kernel, platform, chip) = start();
-    board_kernel.kernel_loop(&platform, chip, Some(&platform.ipc), &main_loop_capability);
+    let platform = static_init!(QemuRv32VirtPlatform, platform);
+    BOARD_KERNEL = Some(board_kernel);
+    PLATFORM = Some(platform);
+
+    // Run the kernel on the other harts too. Harts that QEMU does not
+    // emulate are never started.
+    rv32i::smp::start_secondary_harts(
+        secondary_hart_main,
+        &mut (*addr_of_mut!(HART_STACKS)).0,
+        HART_STACK_SIZE,
+    );
+    board_kernel.smp_kernel_loop(platform, chip, Some(&platform.ipc), &main_loop_capability);
 }
@@ -78,6 +78,16 @@ type TemperatureRp2040Sensor = components::temperature_rp2040::TemperatureRp2040
 >;
 type TemperatureDriver = components::temperature::TemperatureComponentType<TemperatureRp2040Sensor>;
 
+/// AES-GCM on top of the software AES implementation, as the RP2040 has no AES
+/// peripheral.
+type AesGcm = capsules_aes_gcm::aes_gcm::Aes128Gcm<
+    'static,
+    capsules_core::virtualizers::virtual_aes_ccm::VirtualAES128CCM<
+        'static,
+        components::aes::Aes128SoftwareComponentType,
+    >,
+>;
+
 /// Supported drivers by the platform
 pub struct RaspberryPiPico {
     ipc: kernel::ipc::IPC<{ NUM_PROCS as u8 }>,
@@ -91,9 +101,14 @@ pub struct RaspberryPiPico {
     adc: &'static capsules_core::adc::AdcVirtualized<'static>,
     temperature: &'static TemperatureDriver,
     i2c: &'static capsules_core::i2c_master::I2CMasterDriver<'static, I2c<'static, 'static>>,
+    i2c_master_slave: &'static capsules_core::i2c_master_slave_driver::I2CMasterSlaveDriver<
+        'static,
+        I2c<'static, 'static>,
+    >,
 
     date_time:
         &'static capsules_extra::date_time::DateTimeCapsule<'static, rp2040::rtc::Rtc<'static>>,
+    aes: &'static capsules_extra::symmetric_encryption::aes::AesDriver<'static, AesGcm>,
     scheduler: &'static RoundRobinSched<'static>,
     systick: cortexm0p::systick::SysTick,
 }
@@ -112,7 +127,9 @@ impl SyscallDriverLookup for RaspberryPiPico {
             capsules_core::adc::DRIVER_NUM => f(Some(self.adc)),
             capsules_extra::temperature::DRIVER_NUM => f(Some(self.temperature)),
             capsules_core::i2c_master::DRIVER_NUM => f(Some(self.i2c)),
+            capsules_core::i2c_master_slave_driver::DRIVER_NUM => f(Some(self.i2c_master_slave)),
             capsules_extra::date_time::DRIVER_NUM => f(Some(self.date_time)),
+            capsules_extra::symmetric_encryption::aes::DRIVER_NUM => f(Some(self.aes)),
             _ => f(None),
         }
     }
@@ -397,8 +414,9 @@ pub unsafe fn start() -> (
             // Used for i2c. Comment them in if you don't use i2c.
             // 4 => peripherals.pins.get_pin(RPGpio::GPIO4),
             // 5 => peripherals.pins.get_pin(RPGpio::GPIO5),
-            6 => peripherals.pins.get_pin(RPGpio::GPIO6),
-            7 => peripherals.pins.get_pin(RPGpio::GPIO7),
+            // Used for the second i2c. Comment them in if you don't use it.
+            // 6 => peripherals.pins.get_pin(RPGpio::GPIO6),
+            // 7 => peripherals.pins.get_pin(RPGpio::GPIO7),
             8 => peripherals.pins.get_pin(RPGpio::GPIO8),
             9 => peripherals.pins.get_pin(RPGpio::GPIO9),
             10 => peripherals.pins.get_pin(RPGpio::GPIO10),
@@ -536,6 +554,52 @@ pub unsafe fn start() -> (
     i2c0.init(10 * 1000);
     i2c0.set_master_client(i2c);
 
+    // I2C1 can also act as a slave, for userspace to emulate I2C devices.
+    let sda1_pin = peripherals.pins.get_pin(RPGpio::GPIO6);
+    let scl1_pin = peripherals.pins.get_pin(RPGpio::GPIO7);
+
+    sda1_pin.set_function(GpioFunction::I2C);
+    scl1_pin.set_function(GpioFunction::I2C);
+
+    sda1_pin.set_floating_state(FloatingState::PullUp);
+    scl1_pin.set_floating_state(FloatingState::PullUp);
+
+    let i2c1 = &peripherals.i2c1;
+    i2c1.init(100 * 1000);
+    let i2c_master_slave = components::i2c::I2CMasterSlaveDriverComponent::new(
+        board_kernel,
+        capsules_core::i2c_master_slave_driver::DRIVER_NUM,
+        i2c1,
+    )
+    .finalize(components::i2c_master_slave_component_static!(
+        I2c<'static, 'static>
+    ));
+
+    let aes = components::aes::Aes128SoftwareComponent::new()
+        .finalize(components::aes128_software_component_static!());
+    let aes_mux = components::ieee802154::MuxAes128ccmComponent::new(aes).finalize(
+        components::mux_aes128ccm_component_static!(components::aes::Aes128SoftwareComponentType),
+    );
+    let ccm_client = components::aes::AesVirtualComponent::new(aes_mux).finalize(
+        components::aes_virtual_component_static!(components::aes::Aes128SoftwareComponentType),
+    );
+
+    const CRYPT_SIZE: usize = 7 * kernel::hil::symmetric_encryption::AES128_BLOCK_SIZE;
+    let crypt_buf = static_init!([u8; CRYPT_SIZE], [0x00; CRYPT_SIZE]);
+    let gcm_client = static_init!(
+        AesGcm,
+        capsules_aes_gcm::aes_gcm::Aes128Gcm::new(ccm_client, crypt_buf)
+    );
+    kernel::hil::symmetric_encryption::AES128CCM::set_client(ccm_client, gcm_client);
+    kernel::hil::symmetric_encryption::AES128::set_client(ccm_client, gcm_client);
+
+    let aes = components::aes::AesDriverComponent::new(
+        board_kernel,
+        capsules_extra::symmetric_encryption::aes::DRIVER_NUM,
+        gcm_client,
+    )
+    .finalize(components::aes_driver_component_static!(AesGcm));
+
     let scheduler = components::sched::round_robin::RoundRobinComponent::new(&*addr_of!(PROCESSES))
         .finalize(components::round_robin_component_static!(NUM_PROCS));
 
@@ -552,7 +616,9 @@ pub unsafe fn start() -> (
         adc: adc_syscall,
         temperature: temp,
         i2c,
+        i2c_master_slave,
         date_time,
+        aes,
 
         scheduler,
         systick: cortexm0p::systick::SysTick::new_with_calibration(125_000_000),
@@ -270,7 +270,6 @@ unsafe fn set_pin_primary_functions(
         pin.set_alternate_function(AlternateFunction::AF4);
     });
 
-    i2c1.enable_clock();
     i2c1.set_speed(
         stm32f412g::i2c::I2CSpeed::Speed400k,
         peripheral_clock_frequency,
@@ -364,11 +363,7 @@ unsafe fn set_pin_primary_functions(
 }
 
 /// Helper function for miscellaneous peripheral functions
-unsafe fn setup_peripherals(
-    tim2: &stm32f412g::tim2::Tim2,
-    fsmc: &stm32f412g::fsmc::Fsmc,
-    trng: &stm32f412g::trng::Trng,
-) {
+unsafe fn setup_peripherals(tim2: &stm32f412g::tim2::Tim2, fsmc: &stm32f412g::fsmc::Fsmc) {
     // USART2 IRQn is 38
     cortexm4::nvic::Nvic::new(stm32f412g::nvic::USART2).enable();
 
@@ -379,9 +374,6 @@ unsafe fn setup_peripherals(
 
     // FSMC
     fsmc.enable();
-
-    // RNG
-    trng.enable_clock();
 }
 
 /// Main function.
@@ -428,11 +420,7 @@ unsafe fn start() -> (
     let _ = clocks.set_sys_clock_source(stm32f412g::rcc::SysClockSource::PLL);
 
     let base_peripherals = &peripherals.stm32f4;
-    setup_peripherals(
-        &base_peripherals.tim2,
-        &base_peripherals.fsmc,
-        &peripherals.trng,
-    );
+    setup_peripherals(&base_peripherals.tim2, &base_peripherals.fsmc);
 
     set_pin_primary_functions(
         syscfg,
@@ -0,0 +1,221 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Versioning of the command interface of syscall drivers.
+//!
+//! Changing the command numbers of a driver breaks every application that was
+//! compiled against the old numbers. To avoid this, a driver can keep serving
+//! its old interfaces next to the current one. Each interface is an ABI
+//! version of the driver, and each process picks the version it was written
+//! for with the version command ([`VERSION_COMMAND`]):
+//!
+//! - `arg1 == 0` queries the versions. Returns the version the process
+//!   currently uses, the oldest supported version, and the newest version.
+//! - Any other `arg1` selects that version for the process, and fails with
+//!   `NOSUPPORT` if the driver does not support it.
+//!
+//! Applications that never issue the version command get the default version
+//! of the driver, which should be the version that existing applications were
+//! compiled against.
+//!
+//! The driver only implements its newest interface. For each older version it
+//! provides a [`CommandTable`] that maps the commands of that version to
+//! current commands, and [`AbiVersions::translate`] applies the table before
+//! the driver handles the command. The version a process selected is kept in
+//! a [`ProcessAbi`] in the grant of the driver.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! /// Version 1 returned the number of buttons from command 0, which is
+//! /// command 4 now, and had no command 4.
+//! const ABI_VERSIONS: AbiVersions =
+//!     AbiVersions::new(2, 1, &[CommandTable::new(1, &[(0, Some(4)), (4, None)])]);
+//!
+//! fn command(&self, command_num: usize, data: usize, _: usize, processid: ProcessId)
+//!     -> CommandReturn
+//! {
+//!     let command_num = match self
+//!         .apps
+//!         .enter(processid, |app, _| ABI_VERSIONS.translate(&mut app.abi, command_num, data))
+//!         .unwrap_or_else(|err| Err(CommandReturn::failure(err.into())))
+//!     {
+//!         Ok(command_num) => command_num,
+//!         Err(ret) => return ret,
+//!     };
+//!     ...
+//! }
+//! ```
+
+use kernel::syscall::CommandReturn;
+use kernel::ErrorCode;
+
+/// Command number of the version command of versioned drivers.
+pub const VERSION_COMMAND: usize = 0xFFFF_FFFF;
+
+/// Commands of an old ABI version of a driver.
+pub struct CommandTable {
+    version: u32,
+    /// Commands of this version that are numbered differently in the newest
+    /// version, with their current number, or `None` if the command no
+    /// longer exists. Commands that are not listed are unchanged.
+    commands: &'static [(usize, Option<usize>)],
+}
+
+impl CommandTable {
+    pub const fn new(version: u32, commands: &'static [(usize, Option<usize>)]) -> CommandTable {
+        CommandTable { version, commands }
+    }
+
+    fn translate(&self, command_num: usize) -> Option<usize> {
+        self.commands
+            .iter()
+            .find(|(old, _)| *old == command_num)
+            .map_or(Some(command_num), |(_, new)| *new)
+    }
+}
+
+/// ABI version a process selected for a driver.
+#[derive(Copy, Clone, Default)]
+pub struct ProcessAbi {
+    /// `None` until the process selects a version.
+    version: Option<u32>,
+}
+
+/// The ABI versions a driver supports.
+pub struct AbiVersions {
+    newest: u32,
+    default: u32,
+    /// Tables of all supported versions older than `newest`.
+    tables: &'static [CommandTable],
+}
+
+impl AbiVersions {
+    /// `default` is the version of processes that do not select one, and
+    /// must be `newest` or have a table in `tables`.
+    pub const fn new(newest: u32, default: u32, tables: &'static [CommandTable]) -> AbiVersions {
+        AbiVersions {
+            newest,
+            default,
+            tables,
+        }
+    }
+
+    fn oldest(&self) -> u32 {
+        self.tables
+            .iter()
+            .map(|table| table.version)
+            .fold(self.newest, u32::min)
+    }
+
+    fn is_supported(&self, version: u32) -> bool {
+        version == self.newest || self.tables.iter().any(|table| table.version == version)
+    }
+
+    /// Version that `abi` currently uses.
+    pub fn version(&self, abi: &ProcessAbi) -> u32 {
+        abi.version.unwrap_or(self.default)
+    }
+
+    /// Translate a command of the version `abi` uses into a command of the
+    /// newest version.
+    ///
+    /// Handles the version command, and commands that do not exist in the
+    /// version of the process, by returning `Err` with the value to return
+    /// to the process.
+    pub fn translate(
+        &self,
+        abi: &mut ProcessAbi,
+        command_num: usize,
+        arg1: usize,
+    ) -> Result<usize, CommandReturn> {
+        if command_num == VERSION_COMMAND {
+            return Err(match arg1 {
+                0 => CommandReturn::success_u32_u32_u32(
+                    self.version(abi),
+                    self.oldest(),
+                    self.newest,
+                ),
+                version => match u32::try_from(version) {
+                    Ok(version) if self.is_supported(version) => {
+                        abi.version = Some(version);
+                        CommandReturn::success()
+                    }
+                    _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+                },
+            });
+        }
+
+        let version = self.version(abi);
+        if version == self.newest {
+            return Ok(command_num);
+        }
+        self.tables
+            .iter()
+            .find(|table| table.version == version)
+            .and_then(|table| table.translate(command_num))
+            .ok_or(CommandReturn::failure(ErrorCode::NOSUPPORT))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AbiVersions, CommandTable, ProcessAbi, VERSION_COMMAND};
+    use kernel::ErrorCode;
+
+    const VERSIONS: AbiVersions = AbiVersions::new(
+        3,
+        1,
+        &[
+            CommandTable::new(1, &[(0, Some(4)), (5, None)]),
+            CommandTable::new(2, &[(5, None)]),
+        ],
+    );
+
+    #[test]
+    fn default_version() {
+        let mut abi = ProcessAbi::default();
+        assert_eq!(VERSIONS.translate(&mut abi, 0, 0).ok(), Some(4));
+        assert_eq!(VERSIONS.translate(&mut abi, 1, 0).ok(), Some(1));
+        assert_eq!(
+            VERSIONS
+                .translate(&mut abi, 5, 0)
+                .err()
+                .unwrap()
+                .get_failure(),
+            Some(ErrorCode::NOSUPPORT)
+        );
+    }
+
+    #[test]
+    fn query() {
+        let mut abi = ProcessAbi::default();
+        let ret = VERSIONS
+            .translate(&mut abi, VERSION_COMMAND, 0)
+            .err()
+            .unwrap();
+        assert_eq!(ret.get_success_3_u32(), Some((1, 1, 3)));
+    }
+
+    #[test]
+    fn select() {
+        let mut abi = ProcessAbi::default();
+        let ret = VERSIONS
+            .translate(&mut abi, VERSION_COMMAND, 3)
+            .err()
+            .unwrap();
+        assert!(ret.is_success());
+        assert_eq!(VERSIONS.version(&abi), 3);
+        assert_eq!(VERSIONS.translate(&mut abi, 0, 0).ok(), Some(0));
+        assert_eq!(VERSIONS.translate(&mut abi, 5, 0).ok(), Some(5));
+
+        let ret = VERSIONS
+            .translate(&mut abi, VERSION_COMMAND, 4)
+            .err()
+            .unwrap();
+        assert_eq!(ret.get_failure(), Some(ErrorCode::NOSUPPORT));
+        assert_eq!(VERSIONS.version(&abi), 3);
+    }
+}
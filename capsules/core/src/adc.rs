@@ -9,8 +9,9 @@
 //! The first, called AdcDedicated, assumes that it has complete (dedicated)
 //! control of the kernel ADC. This capsule provides userspace with
 //! the ability to perform single, continuous, and high speed samples.
-//! However, using this capsule means that no other
-//! capsule or kernel service can use the ADC. It also allows only
+//! It also streams samples with the common sensor stream interface (see
+//! [`kernel::utilities::sensor_stream`]). However, using this capsule means
+//! that no other capsule or kernel service can use the ADC. It also allows only
 //! a single process to use the ADC: other processes will receive
 //! NOMEM errors.
 //!
@@ -60,6 +61,7 @@ use kernel::hil;
 use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
 use kernel::syscall::{CommandReturn, SyscallDriver};
 use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::sensor_stream::{self, SensorStream};
 use kernel::{ErrorCode, ProcessId};
 
 /// Syscall driver number.
@@ -92,7 +94,7 @@ pub struct AdcDedicated<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> {
     mode: Cell<AdcMode>,
 
     // App state
-    apps: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<2>>,
+    apps: Grant<App, UpcallCount<2>, AllowRoCount<0>, AllowRwCount<3>>,
     processid: OptionalCell<ProcessId>,
     channel: Cell<usize>,
 
@@ -111,6 +113,7 @@ pub(crate) enum AdcMode {
     ContinuousSample = 1,
     SingleBuffer = 2,
     ContinuousBuffer = 3,
+    Stream = 4,
 }
 
 // Datas passed by the application to us
@@ -127,6 +130,8 @@ pub struct App {
     samples_outstanding: Cell<usize>,
     next_samples_outstanding: Cell<usize>,
     using_app_buf0: Cell<bool>,
+    /// Samples are streamed into allow buffer 2, with upcall 1.
+    stream: SensorStream<2, 1>,
 }
 
 impl Default for App {
@@ -137,6 +142,7 @@ impl Default for App {
             samples_outstanding: Cell::new(0),
             next_samples_outstanding: Cell::new(0),
             using_app_buf0: Cell::new(true),
+            stream: SensorStream::default(),
         }
     }
 }
@@ -166,7 +172,7 @@ impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> AdcDedicated<'a, A>
     /// - `adc_buf2` - second buffer used when continuously sampling ADC
     pub fn new(
         adc: &'a A,
-        grant: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<2>>,
+        grant: Grant<App, UpcallCount<2>, AllowRoCount<0>, AllowRwCount<3>>,
         channels: &'a [<A as hil::adc::Adc<'a>>::Channel],
         adc_buf1: &'static mut [u16; 128],
         adc_buf2: &'static mut [u16; 128],
@@ -577,6 +583,70 @@ impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> AdcDedicated<'a, A>
         ret
     }
 
+    /// Stream samples of a channel to the application.
+    ///
+    /// Samples are taken continuously into the internal buffers, and each
+    /// full internal buffer is appended to the stream buffer of the
+    /// application as one record.
+    ///
+    /// - `channel` - index into `channels` array, which channel to sample
+    /// - `frequency` - number of samples per second to collect
+    fn start_stream(&self, channel: usize, frequency: u32) -> Result<(), ErrorCode> {
+        // only one sample at a time
+        if self.active.get() {
+            return Err(ErrorCode::BUSY);
+        }
+
+        // convert channel index
+        if channel >= self.channels.len() {
+            return Err(ErrorCode::INVAL);
+        }
+        let chan = &self.channels[channel];
+
+        let ret = self.processid.map_or(Err(ErrorCode::NOMEM), |id| {
+            self.apps
+                .enter(id, |app, _| {
+                    self.adc_buf1.take().map_or(Err(ErrorCode::BUSY), |buf1| {
+                        let Some(buf2) = self.adc_buf2.take() else {
+                            self.replace_buffer(buf1);
+                            return Err(ErrorCode::BUSY);
+                        };
+                        let len1 = buf1.len();
+                        let len2 = buf2.len();
+                        self.adc
+                            .sample_highspeed(chan, frequency, buf1, len1, buf2, len2)
+                            .map_or_else(
+                                |(ecode, buf1, buf2)| {
+                                    // store buffers again
+                                    self.replace_buffer(buf1);
+                                    self.replace_buffer(buf2);
+                                    Err(ecode)
+                                },
+                                |()| {
+                                    app.stream.start();
+                                    Ok(())
+                                },
+                            )
+                    })
+                })
+                .map_err(|err| {
+                    if err == kernel::process::Error::NoSuchApp
+                        || err == kernel::process::Error::InactiveApp
+                    {
+                        self.processid.clear();
+                    }
+                })
+                .unwrap_or(Err(ErrorCode::NOMEM))
+        });
+        if ret == Ok(()) {
+            // save state for callback
+            self.active.set(true);
+            self.mode.set(AdcMode::Stream);
+            self.channel.set(channel);
+        }
+        ret
+    }
+
     /// Stops sampling the ADC.
     ///
     /// Any active operation by the ADC is canceled. No additional callbacks
@@ -594,6 +664,7 @@ impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> AdcDedicated<'a, A>
                     self.active.set(false);
                     self.mode.set(AdcMode::NoMode);
                     app.app_buf_offset.set(0);
+                    app.stream.stop();
 
                     // actually cancel the operation
                     let rc = self.adc.stop_sampling();
@@ -836,7 +907,42 @@ impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> hil::adc::HighSpeedC
         let buffer_with_samples = self.replace_buffer(buf);
 
         // do we expect a buffer?
-        if self.active.get()
+        if self.active.get() && self.mode.get() == AdcMode::Stream {
+            // keep sampling into another buffer while we copy the samples
+            self.take_and_map_buffer(|adc_buf| {
+                let request_len = adc_buf.len();
+                let _ = self
+                    .adc
+                    .provide_buffer(adc_buf, request_len)
+                    .map_err(|(_, buf)| {
+                        self.replace_buffer(buf);
+                    });
+            });
+
+            let streamed = self.processid.map_or(false, |id| {
+                self.apps
+                    .enter(id, |app, kernel_data| {
+                        buffer_with_samples.map(|adc_buf| {
+                            app.stream.push(
+                                kernel_data,
+                                length as u16,
+                                adc_buf[..length]
+                                    .iter()
+                                    .flat_map(|sample| sample.to_le_bytes()),
+                            );
+                        });
+                    })
+                    .map_err(|err| {
+                        if err == kernel::process::Error::NoSuchApp
+                            || err == kernel::process::Error::InactiveApp
+                        {
+                            self.processid.clear();
+                        }
+                    })
+                    .is_ok()
+            });
+            unexpected_state = !streamed;
+        } else if self.active.get()
             && (self.mode.get() == AdcMode::SingleBuffer
                 || self.mode.get() == AdcMode::ContinuousBuffer)
         {
@@ -1244,6 +1350,22 @@ impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> SyscallDriver for Ad
                 }),
             },
 
+            // Sensor stream interface
+            sensor_stream::command::START => self.start_stream(channel, frequency as u32).into(),
+            sensor_stream::command::STOP => self.stop_sampling().into(),
+            sensor_stream::command::FLUSH => self
+                .apps
+                .enter(processid, |app, kernel_data| {
+                    if app.stream.is_running() {
+                        app.stream.flush(kernel_data);
+                        Ok(())
+                    } else {
+                        Err(ErrorCode::OFF)
+                    }
+                })
+                .unwrap_or_else(|err| Err(err.into()))
+                .into(),
+
             // Get resolution bits
             101 => CommandReturn::success_u32(self.get_resolution_bits() as u32),
             // Get voltage reference mV
@@ -1254,12 +1376,24 @@ impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> SyscallDriver for Ad
                     CommandReturn::failure(ErrorCode::NOSUPPORT)
                 }
             }
+            // Get the alignment of the buffers of a read-write allow number
+            103 => CommandReturn::success_u32(self.allow_readwrite_alignment(channel) as u32),
 
             // default
             _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
         }
     }
 
+    /// The buffers of allow 0 and 1 are filled with 16 bit samples, which the
+    /// process reads through the pointer of the callback, so they must be
+    /// aligned for 16 bit accesses.
+    fn allow_readwrite_alignment(&self, allow_num: usize) -> usize {
+        match allow_num {
+            0 | 1 => core::mem::align_of::<u16>(),
+            _ => 1,
+        }
+    }
+
     fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
         self.apps.enter(processid, |_, _| {})
     }
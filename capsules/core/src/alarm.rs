@@ -14,6 +14,21 @@ use kernel::{ErrorCode, ProcessId};
 use crate::driver;
 pub const DRIVER_NUM: usize = driver::NUM::Alarm as usize;
 
+kernel::driver_api!(
+    DRIVER_API,
+    DRIVER_NUM,
+    name: "alarm",
+    commands: [
+        0 => "exists()",
+        1 => "frequency() -> u32",
+        2 => "now() -> u32",
+        3 => "stop()",
+        5 => "set_relative(dt) -> u32",
+        6 => "set_absolute(reference, dt) -> u32",
+    ],
+    subscribes: [0 => "fired(now, expiration)"],
+);
+
 #[derive(Copy, Clone, Debug)]
 struct Expiration<T: Ticks> {
     reference: T,
@@ -534,6 +549,12 @@ impl<'a, A: Alarm<'a>> SyscallDriver for AlarmDriver<'a, A> {
             )
     }
 
+    // Reading the time and frequency and setting the alarm are short
+    // commands that processes make together.
+    fn allow_command_batch(&self) -> bool {
+        true
+    }
+
     fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
         self.app_alarms.enter(processid, |_, _| {})
     }
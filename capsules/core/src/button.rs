@@ -34,14 +34,20 @@
 //!
 //! Enable or disable button interrupts and read the current button state.
 //!
+//! The driver is versioned (see [`crate::abi_version`]). Processes use
+//! version 1 unless they select version 2. Version 1 is the same as version
+//! 2, except that command `0` returns the number of buttons, and command `4`
+//! does not exist.
+//!
 //! #### `command_num`
 //!
-//! - `0`: Driver existence check and get number of buttons on the board.
+//! - `0`: Driver existence check.
 //! - `1`: Enable interrupts for a given button. This will enable both press
 //!   and depress events.
 //! - `2`: Disable interrupts for a button. No affect or reliance on
 //!   registered callback.
 //! - `3`: Read the current state of the button.
+//! - `4`: Get the number of buttons on the board.
 //!
 //! ### Subscribe
 //!
@@ -60,13 +66,36 @@ use core::cell::Cell;
 use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
 use kernel::hil::gpio;
 use kernel::hil::gpio::{Configure, Input, InterruptWithValue};
+use kernel::platform::suspend::Suspendable;
 use kernel::syscall::{CommandReturn, SyscallDriver};
 use kernel::{ErrorCode, ProcessId};
 
+use crate::abi_version::{AbiVersions, CommandTable, ProcessAbi};
+
 /// Syscall driver number.
 use crate::driver;
 pub const DRIVER_NUM: usize = driver::NUM::Button as usize;
 
+kernel::driver_api!(
+    DRIVER_API,
+    DRIVER_NUM,
+    name: "button",
+    commands: [
+        0 => "exists()",
+        1 => "enable_interrupt(index)",
+        2 => "disable_interrupt(index)",
+        3 => "read(index) -> u32",
+        4 => "count() -> u32",
+    ],
+    subscribes: [0 => "changed(index, pressed)"],
+);
+
+/// Version 1 returns the number of buttons from the existence check, which
+/// version 2 moved to command 4 to follow TRD104. Command 4 does not exist in
+/// version 1.
+const ABI_VERSIONS: AbiVersions =
+    AbiVersions::new(2, 1, &[CommandTable::new(1, &[(0, Some(4)), (4, None)])]);
+
 /// Keeps track which buttons each app has a registered interrupt for.
 ///
 /// `SubscribeMap` is a bit array where bits are set to one if
@@ -81,6 +110,7 @@ pub type SubscribeMap = u32;
 #[derive(Default)]
 pub struct App {
     subscribe_map: u32,
+    abi: ProcessAbi,
 }
 
 /// Manages the list of GPIO pins that are connected to buttons and which apps
@@ -138,12 +168,16 @@ impl<'a, P: gpio::InterruptPin<'a>> SyscallDriver for Button<'a, P> {
     ///
     /// ### `command_num`
     ///
-    /// - `0`: Driver existence check and get number of buttons on the board.
+    /// Numbers of version 2. Commands of version 1 are translated to these
+    /// before they are handled.
+    ///
+    /// - `0`: Driver existence check.
     /// - `1`: Enable interrupts for a given button. This will enable both press
     ///   and depress events.
     /// - `2`: Disable interrupts for a button. No affect or reliance on
     ///   registered callback.
     /// - `3`: Read the current state of the button.
+    /// - `4`: Get the number of buttons on the board.
     fn command(
         &self,
         command_num: usize,
@@ -151,13 +185,22 @@ impl<'a, P: gpio::InterruptPin<'a>> SyscallDriver for Button<'a, P> {
         _: usize,
         processid: ProcessId,
     ) -> CommandReturn {
+        let command_num = match self
+            .apps
+            .enter(processid, |cntr, _| {
+                ABI_VERSIONS.translate(&mut cntr.abi, command_num, data)
+            })
+            .unwrap_or_else(|_| {
+                // Without a grant the process cannot have selected a version.
+                ABI_VERSIONS.translate(&mut ProcessAbi::default(), command_num, data)
+            }) {
+            Ok(command_num) => command_num,
+            Err(ret) => return ret,
+        };
+
         let pins = self.pins;
         match command_num {
-            // return button count
-            // TODO(Tock 3.0): TRD104 specifies that Command 0 should return Success, not SuccessU32,
-            // but this driver is unchanged since it has been stabilized. It will be brought into
-            // compliance as part of the next major release of Tock. See #3375.
-            0 => CommandReturn::success_u32(pins.len() as u32),
+            0 => CommandReturn::success(),
 
             // enable interrupts for a button
             1 => {
@@ -216,6 +259,9 @@ impl<'a, P: gpio::InterruptPin<'a>> SyscallDriver for Button<'a, P> {
                 }
             }
 
+            // return button count
+            4 => CommandReturn::success_u32(pins.len() as u32),
+
             // default
             _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
         }
@@ -250,3 +296,25 @@ impl<'a, P: gpio::InterruptPin<'a>> gpio::ClientWithValue for Button<'a, P> {
         }
     }
 }
+
+impl<'a, P: gpio::InterruptPin<'a>> Suspendable for Button<'a, P> {
+    fn suspend(&self) {
+        for pin in self.pins.iter() {
+            pin.0.disable_interrupts();
+        }
+    }
+
+    fn resume(&self) {
+        // Only re-enable interrupts for buttons that some process is still
+        // listening to.
+        let mut subscribed: SubscribeMap = 0;
+        self.apps.each(|_, cntr, _| {
+            subscribed |= cntr.subscribe_map;
+        });
+        for (i, pin) in self.pins.iter().enumerate() {
+            if subscribed & (1 << i) != 0 {
+                let _ = pin.0.enable_interrupts(gpio::InterruptEdge::EitherEdge);
+            }
+        }
+    }
+}
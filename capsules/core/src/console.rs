@@ -40,9 +40,26 @@
 //! When the buffer has been written successfully, the buffer is released from
 //! the driver. Successive writes must call `allow` each time a buffer is to be
 //! written.
+//!
+//! Configuration
+//! -------------
+//!
+//! A board can let one application reconfigure the UART of the console at
+//! runtime, for example to raise its baud rate, with
+//! [`Console::set_configure`]. With a virtual UART, this reconfigures the
+//! UART for all its users once the transmission in flight is finished.
+//!
+//! Multiple consoles
+//! -----------------
+//!
+//! A board can have several consoles over different transports, for example
+//! a UART for people and USB CDC for host tooling. Each console is a separate
+//! `Console` with its own grant, created with one of the driver numbers in
+//! [`ADDITIONAL_DRIVER_NUMS`].
 
 use kernel::grant::{AllowRoCount, AllowRwCount, Grant, GrantKernelData, UpcallCount};
 use kernel::hil::uart;
+use kernel::process::ShortId;
 use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
 use kernel::syscall::{CommandReturn, SyscallDriver};
 use kernel::utilities::cells::{OptionalCell, TakeCell};
@@ -51,6 +68,27 @@ use kernel::{ErrorCode, ProcessId};
 /// Syscall driver number.
 use crate::driver;
 pub const DRIVER_NUM: usize = driver::NUM::Console as usize;
+/// Syscall driver numbers of the consoles after the first one.
+pub const ADDITIONAL_DRIVER_NUMS: [usize; 2] = [
+    driver::NUM::Console1 as usize,
+    driver::NUM::Console2 as usize,
+];
+
+kernel::driver_api!(
+    DRIVER_API,
+    DRIVER_NUM,
+    name: "console",
+    commands: [
+        0 => "exists()",
+        1 => "write(len)",
+        2 => "read(len)",
+        3 => "abort_read()",
+        4 => "configure(baud_rate, format)",
+    ],
+    subscribes: [1 => "write_done(len)", 2 => "read_done(status, len)"],
+    allow_ro: [1 => "write"],
+    allow_rw: [1 => "read"],
+);
 
 /// Default size for the read and write buffers used by the console.
 /// Boards may pass different-size buffers if needed.
@@ -111,6 +149,8 @@ pub struct Console<'a> {
     tx_buffer: TakeCell<'static, [u8]>,
     rx_in_progress: OptionalCell<ProcessId>,
     rx_buffer: TakeCell<'static, [u8]>,
+    /// UART configuration, and the application allowed to change it.
+    configure: OptionalCell<(&'a dyn uart::Configure, ShortId)>,
 }
 
 impl<'a> Console<'a> {
@@ -132,9 +172,41 @@ impl<'a> Console<'a> {
             tx_buffer: TakeCell::new(tx_buffer),
             rx_in_progress: OptionalCell::empty(),
             rx_buffer: TakeCell::new(rx_buffer),
+            configure: OptionalCell::empty(),
         }
     }
 
+    /// Let the application with `ShortId` `owner` reconfigure the UART of the
+    /// console through `configure`.
+    pub fn set_configure(&self, configure: &'a dyn uart::Configure, owner: ShortId) {
+        self.configure.set((configure, owner));
+    }
+
+    /// Reconfigure the UART for `processid`, if its application may.
+    fn configure(
+        &self,
+        processid: ProcessId,
+        baud_rate: usize,
+        format: usize,
+    ) -> Result<(), ErrorCode> {
+        let (configure, owner) = self.configure.get().ok_or(ErrorCode::NOSUPPORT)?;
+        if processid.short_app_id() != owner {
+            return Err(ErrorCode::NOSUPPORT);
+        }
+        let baud_rate = u32::try_from(baud_rate).map_err(|_| ErrorCode::INVAL)?;
+        let parameters = decode_word_format(
+            uart::Parameters {
+                baud_rate,
+                width: uart::Width::Eight,
+                parity: uart::Parity::None,
+                stop_bits: uart::StopBits::One,
+                hw_flow_control: false,
+            },
+            format,
+        )?;
+        configure.configure(parameters)
+    }
+
     /// Internal helper function for setting up a new send transaction
     fn send_new(
         &self,
@@ -283,11 +355,14 @@ impl SyscallDriver for Console<'_> {
     ///        passed in `arg1`
     /// - `3`: Cancel any in progress receives and return (via callback)
     ///        what has been received so far.
+    /// - `4`: Reconfigure the UART with the baud rate in `arg1` and the word
+    ///        format in `arg2` (see [`decode_word_format`]). Only the
+    ///        application set with [`Console::set_configure`] may.
     fn command(
         &self,
         cmd_num: usize,
         arg1: usize,
-        _: usize,
+        arg2: usize,
         processid: ProcessId,
     ) -> CommandReturn {
         let res = self
@@ -310,6 +385,7 @@ impl SyscallDriver for Console<'_> {
                         let _ = self.uart.receive_abort();
                         Ok(())
                     }
+                    4 => self.configure(processid, arg1, arg2),
                     _ => Err(ErrorCode::NOSUPPORT),
                 }
             })
@@ -326,6 +402,41 @@ impl SyscallDriver for Console<'_> {
     }
 }
 
+/// Decode a word format, keeping the baud rate of `parameters`.
+///
+/// Bits 0 to 3 are the width in bits, bits 4 and 5 the parity (0 none, 1 odd,
+/// 2 even), bit 6 selects two stop bits and bit 7 enables hardware flow
+/// control.
+pub fn decode_word_format(
+    parameters: uart::Parameters,
+    format: usize,
+) -> Result<uart::Parameters, ErrorCode> {
+    let width = match format & 0xf {
+        6 => uart::Width::Six,
+        7 => uart::Width::Seven,
+        8 => uart::Width::Eight,
+        _ => return Err(ErrorCode::INVAL),
+    };
+    let parity = match (format >> 4) & 0x3 {
+        0 => uart::Parity::None,
+        1 => uart::Parity::Odd,
+        2 => uart::Parity::Even,
+        _ => return Err(ErrorCode::INVAL),
+    };
+    let stop_bits = if format & (1 << 6) == 0 {
+        uart::StopBits::One
+    } else {
+        uart::StopBits::Two
+    };
+    Ok(uart::Parameters {
+        width,
+        parity,
+        stop_bits,
+        hw_flow_control: format & (1 << 7) != 0,
+        ..parameters
+    })
+}
+
 impl uart::TransmitClient for Console<'_> {
     fn transmitted_buffer(
         &self,
@@ -22,12 +22,24 @@ pub enum NUM {
     AnalogComparator      = 0x00007,
     LowLevelDebug         = 0x00008,
     ReadOnlyState         = 0x00009,
+    AppLog                = 0x0000A,
+    FastGpio              = 0x0000B,
+    Console1              = 0x0000C,
+    Console2              = 0x0000D,
     Pwm                   = 0x00010,
 
     // Kernel
     Ipc                   = 0x10000,
+    AppLoader             = 0x10001,
+    Energy                = 0x10002,
+    Peripherals           = 0x10003,
+    Pipe                  = 0x10004,
+    SystemEvents          = 0x10005,
+    Thermal               = 0x10006,
+    AppWatchdog           = 0x10007,
 
     // HW Buses
+    Uart                  = 0x20000,
     Spi                   = 0x20001,
     SpiPeripheral         = 0x20002,
     I2cMaster             = 0x20003,
@@ -43,6 +55,10 @@ pub enum NUM {
     LoRaPhyGPIO           = 0x30004,
     Thread                = 0x30005,
     Eui64                 = 0x30006,
+    Tcp                   = 0x30007,
+    NeighborTable         = 0x30008,
+    Ipv4Udp               = 0x30009,
+    PacketCapture         = 0x3000A,
 
     // Cryptography
     Rng                   = 0x40001,
@@ -51,12 +67,15 @@ pub enum NUM {
     CtapHid               = 0x40004,
     Sha                   = 0x40005,
     Aes                   = 0x40006,
+    Attestation           = 0x40007,
 
     // Storage
     AppFlash              = 0x50000,
     NvmStorage            = 0x50001,
     SdCard                = 0x50002,
     Kv                    = 0x50003,
+    FatFs                 = 0x50004,
+    ConfigService         = 0x50005,
 
     // Sensors
     Temperature           = 0x60000,
@@ -97,5 +116,10 @@ pub enum NUM {
     DateTime              = 0x90007,
     CycleCount            = 0x90008,
     Servo                 = 0x90009,
+    Audio                 = 0x9000A,
+    TouchCalibration      = 0x9000B,
+    Benchmark             = 0x9000C,
+    EventBatch            = 0x9000D,
+    Gesture               = 0x9000E,
 }
 }
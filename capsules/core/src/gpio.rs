@@ -337,6 +337,12 @@ impl<'a, IP: gpio::InterruptPin<'a>> SyscallDriver for GPIO<'a, IP> {
         }
     }
 
+    // Setting, clearing and reading several pins are short commands that
+    // processes often make back to back.
+    fn allow_command_batch(&self) -> bool {
+        true
+    }
+
     fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
         self.apps.enter(processid, |_, _| {})
     }
@@ -62,6 +62,18 @@ use kernel::{ErrorCode, ProcessId};
 use crate::driver;
 pub const DRIVER_NUM: usize = driver::NUM::Led as usize;
 
+kernel::driver_api!(
+    DRIVER_API,
+    DRIVER_NUM,
+    name: "led",
+    commands: [
+        0 => "count() -> u32",
+        1 => "on(index)",
+        2 => "off(index)",
+        3 => "toggle(index)",
+    ],
+);
+
 /// Holds the array of LEDs and implements a `Driver` interface to
 /// control them.
 pub struct LedDriver<'a, L: led::Led, const NUM_LEDS: usize> {
@@ -136,6 +148,11 @@ impl<L: led::Led, const NUM_LEDS: usize> SyscallDriver for LedDriver<'_, L, NUM_
         }
     }
 
+    // Processes commonly change several LEDs at once.
+    fn allow_command_batch(&self) -> bool {
+        true
+    }
+
     fn allocate_grant(&self, _processid: ProcessId) -> Result<(), kernel::process::Error> {
         Ok(())
     }
@@ -10,6 +10,7 @@ pub mod test;
 #[macro_use]
 pub mod stream;
 
+pub mod abi_version;
 pub mod adc;
 pub mod alarm;
 pub mod button;
@@ -6,6 +6,33 @@
 //! a terminal to inspect and control userspace processes.
 //!
 //! For a more in-depth documentation check /doc/Process_Console.md
+//!
+//! Startup script
+//! --------------
+//!
+//! A board can give the console a [`StartupScript`]: text, usually kept in a
+//! flash region outside of the kernel and the apps, so that it can be changed
+//! with a flashing tool and without rebuilding the kernel. Each line is either
+//! a command, which the console runs when it starts, before it listens to the
+//! UART, or an alias definition:
+//!
+//! ```text
+//! # Diagnostics for field units.
+//! alias ps list
+//! alias top watch processes 5s
+//! stop app2
+//! watch stats 10s
+//! ```
+//!
+//! Typing an alias runs its command, followed by any arguments given to the
+//! alias. Aliases cannot refer to other aliases. Empty lines and lines starting
+//! with `#` are ignored, and the script ends at the first NUL or erased (0xFF)
+//! byte. The `alias` command lists the aliases.
+//!
+//! ```rust,ignore
+//! let script = unsafe { core::slice::from_raw_parts(0xFE000 as *const u8, 4096) };
+//! pconsole.set_startup_script(StartupScript::new(script));
+//! ```
 use core::cell::Cell;
 use core::cmp;
 use core::fmt;
@@ -13,17 +40,31 @@ use core::fmt::write;
 use core::str;
 use kernel::capabilities::ProcessManagementCapability;
 use kernel::capabilities::ProcessStartCapability;
+use kernel::energy::EnergyStatistics;
+use kernel::hil::link_quality::NeighborTable;
 use kernel::hil::time::ConvertTicks;
+use kernel::platform::attributes;
+use kernel::platform::persistent_counters::PersistentCounters;
+use kernel::platform::self_test::KernelIntegrity;
+use kernel::platform::stats::{KernelStatistics, Metrics};
+use kernel::platform::suspend::SuspendControl;
+use kernel::process::{
+    DynamicProcessLoading, DynamicProcessLoadingClient, ProcessCheckpoint, ProcessCheckpointClient,
+    ProcessInstall, ProcessInstallClient, ProcessLoadError, ProcessReload,
+};
 use kernel::utilities::cells::MapCell;
+use kernel::utilities::cells::OptionalCell;
 use kernel::utilities::cells::TakeCell;
 use kernel::ProcessId;
 
 use kernel::debug;
+use kernel::debug::{DebugGpioControl, NUM_DEBUG_GPIOS};
 use kernel::hil::time::{Alarm, AlarmClient};
 use kernel::hil::uart;
 use kernel::introspection::KernelInfo;
-use kernel::process::{ProcessPrinter, ProcessPrinterContext, State};
+use kernel::process::{Process, ProcessPrinter, ProcessPrinterContext, State};
 use kernel::utilities::binary_write::BinaryWrite;
+use kernel::utilities::fault_injection::{self, Fault, FaultSite};
 use kernel::ErrorCode;
 use kernel::Kernel;
 
@@ -44,7 +85,13 @@ pub const DEFAULT_COMMAND_HISTORY_LEN: usize = 10;
 /// List of valid commands for printing help. Consolidated as these are
 /// displayed in a few different cases.
 const VALID_COMMANDS_STR: &[u8] =
-    b"help status list stop start fault boot terminate process kernel reset panic console-start console-stop\r\n";
+    b"help status list stop start fault boot terminate process kernel attributes reset reload install receive checkpoint restore panic console-start console-stop drivers suspend resume stats cputime energy watch debug-gpio inject alias neighbors uart\r\n";
+
+/// Interval of the `watch` command if none is given.
+const WATCH_DEFAULT_INTERVAL_MS: u32 = 1000;
+/// Shortest interval of the `watch` command, so the output stays readable
+/// and the console can still be used.
+const WATCH_MIN_INTERVAL_MS: u32 = 100;
 
 /// Escape character for ANSI escape sequences.
 const ESC: u8 = b'\x1B';
@@ -206,6 +253,136 @@ pub struct KernelAddresses {
     pub relocations_end: *const u8,
     pub bss_start: *const u8,
     pub bss_end: *const u8,
+    /// The kernel attributes region, read by the `attributes` command.
+    pub attributes: &'static [u8],
+}
+
+/// What the `watch` command prints.
+#[derive(Clone, Copy, PartialEq)]
+enum WatchExpression {
+    /// The state of each process.
+    Processes,
+    /// The load average and loop counters of the kernel.
+    Stats,
+    /// The registered metric with this index.
+    Metric(usize),
+}
+
+/// Commands and aliases run by the process console, see the [module
+/// documentation](self).
+#[derive(Clone, Copy)]
+pub struct StartupScript<'a> {
+    text: &'a [u8],
+}
+
+impl<'a> StartupScript<'a> {
+    /// Read the script from `region`, which may be longer than the script.
+    pub fn new(region: &'a [u8]) -> Self {
+        let len = region
+            .iter()
+            .position(|&byte| byte == EOL || byte == 0xFF)
+            .unwrap_or(region.len());
+        Self {
+            text: &region[..len],
+        }
+    }
+
+    /// The first line at or after byte `offset` that is not empty or a
+    /// comment, trimmed, with the offset of the line after it. Lines that are
+    /// not valid UTF-8 are skipped.
+    fn line_at(&self, mut offset: usize) -> Option<(&'a str, usize)> {
+        while offset < self.text.len() {
+            let rest = &self.text[offset..];
+            let len = rest
+                .iter()
+                .position(|&byte| byte == NLINE)
+                .unwrap_or(rest.len());
+            offset += len + 1;
+            if let Ok(line) = str::from_utf8(&rest[..len]) {
+                let line = line.trim();
+                if !line.is_empty() && !line.starts_with('#') {
+                    return Some((line, offset));
+                }
+            }
+        }
+        None
+    }
+
+    /// The first command at or after byte `offset`, with the offset of the
+    /// line after it.
+    fn command_at(&self, mut offset: usize) -> Option<(&'a str, usize)> {
+        while let Some((line, next)) = self.line_at(offset) {
+            if line.split_whitespace().next() != Some("alias") {
+                return Some((line, next));
+            }
+            offset = next;
+        }
+        None
+    }
+
+    /// The alias at or after byte `offset`, as its name and command, with the
+    /// offset of the line after it.
+    fn alias_at(&self, mut offset: usize) -> Option<(&'a str, &'a str, usize)> {
+        while let Some((line, next)) = self.line_at(offset) {
+            let mut words = line.splitn(3, char::is_whitespace);
+            if let (Some("alias"), Some(name), Some(command)) =
+                (words.next(), words.next(), words.next())
+            {
+                if !name.is_empty() && !command.trim().is_empty() {
+                    return Some((name, command.trim(), next));
+                }
+            }
+            offset = next;
+        }
+        None
+    }
+
+    /// The command of the alias `name`. If it is defined more than once, the
+    /// first definition is used.
+    fn alias(&self, name: &str) -> Option<&'a str> {
+        let mut offset = 0;
+        while let Some((alias, command, next)) = self.alias_at(offset) {
+            if alias == name {
+                return Some(command);
+            }
+            offset = next;
+        }
+        None
+    }
+}
+
+/// Parse an interval in milliseconds, optionally suffixed with `ms`, or in
+/// seconds suffixed with `s`.
+fn parse_interval_ms(interval: &str) -> Option<u32> {
+    if let Some(ms) = interval.strip_suffix("ms") {
+        ms.parse().ok()
+    } else if let Some(s) = interval.strip_suffix('s') {
+        s.parse::<u32>().ok()?.checked_mul(1000)
+    } else {
+        interval.parse().ok()
+    }
+}
+
+/// Progress of the `receive` command.
+#[derive(Clone, Copy, PartialEq)]
+enum BinaryReceive {
+    /// Receiving the chunk at `offset` in the binary of `length` bytes, of
+    /// which `received` bytes arrived.
+    Receiving {
+        length: usize,
+        offset: usize,
+        received: usize,
+    },
+    /// Writing the `received` bytes of the chunk at `offset`, of which
+    /// `written` bytes are written.
+    Writing {
+        length: usize,
+        offset: usize,
+        received: usize,
+        written: usize,
+    },
+    /// The loader checks and loads the binary.
+    Loading,
 }
 
 /// Track the operational state of the process console.
@@ -270,6 +447,65 @@ pub struct ProcessConsole<
     /// Function used to reset the device in bootloader mode
     reset_function: Option<fn() -> !>,
 
+    /// Optional registry of capsules that can be suspended at runtime.
+    suspend_control: OptionalCell<&'a dyn SuspendControl>,
+
+    /// Optional source of kernel runtime statistics.
+    statistics: OptionalCell<&'a dyn KernelStatistics>,
+
+    /// Optional source of per process energy accounting.
+    energy: OptionalCell<&'a dyn EnergyStatistics>,
+
+    /// Optional process loader used to reload processes from flash.
+    reload: OptionalCell<&'a dyn ProcessReload>,
+
+    /// Optional installer of process binaries from external storage.
+    installer: OptionalCell<&'a dyn ProcessInstall<'a>>,
+
+    /// Optional loader of process binaries received with `receive`.
+    loader: OptionalCell<&'a dyn DynamicProcessLoading<'a>>,
+
+    /// Progress of the binary being received, while `receive` runs.
+    binary: OptionalCell<BinaryReceive>,
+
+    /// Optional store of process checkpoints in nonvolatile storage.
+    checkpointer: OptionalCell<&'a dyn ProcessCheckpoint<'a>>,
+
+    /// Optional result of the kernel image integrity check.
+    integrity: OptionalCell<&'a dyn KernelIntegrity>,
+
+    /// Optional named metrics that can be printed with `watch`.
+    metrics: OptionalCell<&'a dyn Metrics>,
+
+    /// Optional boot and panic counters printed by the `status` command.
+    counters: OptionalCell<&'a dyn PersistentCounters>,
+
+    /// Optional pins the `debug-gpio` command can assign to the debug GPIOs.
+    debug_gpios: OptionalCell<&'a dyn DebugGpioControl>,
+
+    /// Optional configuration of the UART of the console, changed with the
+    /// `uart` command.
+    uart_configure: OptionalCell<&'a dyn uart::Configure>,
+
+    /// Optional fault sites the `inject` command can configure.
+    fault_sites: OptionalCell<&'a [&'a FaultSite]>,
+
+    /// What the `watch` command prints, and how often, if it is running.
+    watch: OptionalCell<(WatchExpression, u32)>,
+
+    /// Time since the `watch` command started, in milliseconds.
+    watch_elapsed_ms: Cell<u32>,
+
+    /// Optional table of radio neighbors printed by the `neighbors` command.
+    neighbors: OptionalCell<&'a dyn NeighborTable>,
+
+    /// Optional startup script and aliases.
+    script: OptionalCell<StartupScript<'a>>,
+
+    /// Offset of the next line of the startup script to run, while it is
+    /// running.
+    script_offset: OptionalCell<usize>,
+
     /// This capsule needs to use potentially dangerous APIs related to
     /// processes, and requires a capability to access those APIs.
     capability: C,
@@ -476,10 +712,110 @@ impl<
             kernel,
             kernel_addresses,
             reset_function,
+            suspend_control: OptionalCell::empty(),
+            statistics: OptionalCell::empty(),
+            energy: OptionalCell::empty(),
+            reload: OptionalCell::empty(),
+            installer: OptionalCell::empty(),
+            loader: OptionalCell::empty(),
+            binary: OptionalCell::empty(),
+            checkpointer: OptionalCell::empty(),
+            integrity: OptionalCell::empty(),
+            metrics: OptionalCell::empty(),
+            counters: OptionalCell::empty(),
+            debug_gpios: OptionalCell::empty(),
+            uart_configure: OptionalCell::empty(),
+            fault_sites: OptionalCell::empty(),
+            watch: OptionalCell::empty(),
+            watch_elapsed_ms: Cell::new(0),
+            neighbors: OptionalCell::empty(),
+            script: OptionalCell::empty(),
+            script_offset: OptionalCell::empty(),
             capability,
         }
     }
 
+    /// Provide the registry of suspendable capsules used by the `drivers`,
+    /// `suspend`, and `resume` commands.
+    pub fn set_suspend_control(&self, suspend_control: &'a dyn SuspendControl) {
+        self.suspend_control.set(suspend_control);
+    }
+
+    /// Provide the kernel statistics displayed by the `stats` command.
+    pub fn set_statistics(&self, statistics: &'a dyn KernelStatistics) {
+        self.statistics.set(statistics);
+    }
+
+    /// Provide the energy accounting displayed by the `energy` command.
+    pub fn set_energy(&self, energy: &'a dyn EnergyStatistics) {
+        self.energy.set(energy);
+    }
+
+    /// Provide the process loader used by the `reload` command.
+    pub fn set_process_reload(&self, reload: &'a dyn ProcessReload) {
+        self.reload.set(reload);
+    }
+
+    /// Provide the installer used by the `install` command. The console must
+    /// be set as its client, to print the outcome of installations.
+    pub fn set_process_install(&self, installer: &'a dyn ProcessInstall<'a>) {
+        self.installer.set(installer);
+    }
+
+    /// Provide the loader used by the `receive` command. The console must be
+    /// set as its client.
+    pub fn set_dynamic_loader(&self, loader: &'a dyn DynamicProcessLoading<'a>) {
+        self.loader.set(loader);
+    }
+
+    /// Provide the checkpointer used by the `checkpoint` and `restore`
+    /// commands. The console must be set as its client, to print the outcome.
+    pub fn set_process_checkpoint(&self, checkpointer: &'a dyn ProcessCheckpoint<'a>) {
+        self.checkpointer.set(checkpointer);
+    }
+
+    /// Provide the kernel integrity check displayed by the `kernel` command.
+    pub fn set_kernel_integrity(&self, integrity: &'a dyn KernelIntegrity) {
+        self.integrity.set(integrity);
+    }
+
+    /// Provide the metrics that can be printed with the `watch` command.
+    pub fn set_metrics(&self, metrics: &'a dyn Metrics) {
+        self.metrics.set(metrics);
+    }
+
+    /// Provide the boot and panic counters printed by the `status` command.
+    pub fn set_persistent_counters(&self, counters: &'a dyn PersistentCounters) {
+        self.counters.set(counters);
+    }
+
+    /// Provide the pins the `debug-gpio` command can assign.
+    pub fn set_debug_gpios(&self, debug_gpios: &'a dyn DebugGpioControl) {
+        self.debug_gpios.set(debug_gpios);
+    }
+
+    /// Provide the configuration of the UART changed by the `uart` command.
+    pub fn set_uart_configure(&self, uart_configure: &'a dyn uart::Configure) {
+        self.uart_configure.set(uart_configure);
+    }
+
+    /// Provide the fault sites the `inject` command can configure.
+    pub fn set_fault_sites(&self, fault_sites: &'a [&'a FaultSite]) {
+        self.fault_sites.set(fault_sites);
+    }
+
+    /// Provide the radio neighbors printed by the `neighbors` command.
+    pub fn set_neighbor_table(&self, neighbors: &'a dyn NeighborTable) {
+        self.neighbors.set(neighbors);
+    }
+
+    /// Provide the aliases, and the commands to run when the console starts.
+    /// This must be called before the console is started.
+    pub fn set_startup_script(&self, script: StartupScript<'a>) {
+        self.script.set(script);
+        self.script_offset.set(0);
+    }
+
     /// Start the process console listening for user commands.
     pub fn start(&self) -> Result<(), ErrorCode> {
         if self.mode.get() == ProcessConsoleState::Off {
@@ -515,17 +851,12 @@ impl<
         }
 
         // Display pconsole info.
-        let mut console_writer = ConsoleWriter::new();
-        let _ = write(
-            &mut console_writer,
-            format_args!(
-                "Kernel version: {}.{} (build {})\r\n",
-                kernel::KERNEL_MAJOR_VERSION,
-                kernel::KERNEL_MINOR_VERSION,
-                option_env!("TOCK_KERNEL_VERSION").unwrap_or("unknown"),
-            ),
-        );
-        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+        self.write_args(format_args!(
+            "Kernel version: {}.{} (build {})\r\n",
+            kernel::KERNEL_MAJOR_VERSION,
+            kernel::KERNEL_MINOR_VERSION,
+            option_env!("TOCK_KERNEL_VERSION").unwrap_or("unknown"),
+        ));
 
         let _ = self.write_bytes(b"Welcome to the process console.\r\n");
         let _ = self.write_bytes(b"Valid commands are: ");
@@ -766,7 +1097,8 @@ impl<
 
                 match cmd_str {
                     Ok(s) => {
-                        let clean_str = s.trim();
+                        let mut expanded = [EOL; COMMAND_BUF_LEN];
+                        let clean_str = self.expand_alias(s.trim(), &mut expanded);
 
                         // Check if the command history is enabled by the user
                         // and check if the command is not full of whitespaces
@@ -792,104 +1124,32 @@ impl<
                             let _ = self.write_bytes(b"Disabling the process console.\r\n");
                             let _ = self.write_bytes(b"Run console-start to reactivate.\r\n");
                             self.mode.set(ProcessConsoleState::Hibernating);
+                            self.watch.clear();
                         } else if clean_str.starts_with("start") {
-                            let argument = clean_str.split_whitespace().nth(1);
-                            argument.map(|name| {
-                                self.kernel
-                                    .process_each_capability(&self.capability, |proc| {
-                                        let proc_name = proc.get_process_name();
-                                        if proc_name == name {
-                                            proc.resume();
-                                            let mut console_writer = ConsoleWriter::new();
-                                            let _ = write(
-                                                &mut console_writer,
-                                                format_args!("Process {} resumed.\r\n", name),
-                                            );
-
-                                            let _ = self.write_bytes(
-                                                &(console_writer.buf)[..console_writer.size],
-                                            );
-                                        }
-                                    });
+                            self.each_named_process(clean_str, &mut |proc, name| {
+                                proc.resume();
+                                self.write_process_event(name, "resumed.");
                             });
                         } else if clean_str.starts_with("stop") {
-                            let argument = clean_str.split_whitespace().nth(1);
-                            argument.map(|name| {
-                                self.kernel
-                                    .process_each_capability(&self.capability, |proc| {
-                                        let proc_name = proc.get_process_name();
-                                        if proc_name == name {
-                                            proc.stop();
-                                            let mut console_writer = ConsoleWriter::new();
-                                            let _ = write(
-                                                &mut console_writer,
-                                                format_args!("Process {} stopped\r\n", proc_name),
-                                            );
-
-                                            let _ = self.write_bytes(
-                                                &(console_writer.buf)[..console_writer.size],
-                                            );
-                                        }
-                                    });
+                            self.each_named_process(clean_str, &mut |proc, name| {
+                                proc.stop();
+                                self.write_process_event(name, "stopped");
                             });
                         } else if clean_str.starts_with("fault") {
-                            let argument = clean_str.split_whitespace().nth(1);
-                            argument.map(|name| {
-                                self.kernel
-                                    .process_each_capability(&self.capability, |proc| {
-                                        let proc_name = proc.get_process_name();
-                                        if proc_name == name {
-                                            proc.set_fault_state();
-                                            let mut console_writer = ConsoleWriter::new();
-                                            let _ = write(
-                                                &mut console_writer,
-                                                format_args!(
-                                                    "Process {} now faulted\r\n",
-                                                    proc_name
-                                                ),
-                                            );
-
-                                            let _ = self.write_bytes(
-                                                &(console_writer.buf)[..console_writer.size],
-                                            );
-                                        }
-                                    });
+                            self.each_named_process(clean_str, &mut |proc, name| {
+                                proc.set_fault_state();
+                                self.write_process_event(name, "now faulted");
                             });
                         } else if clean_str.starts_with("terminate") {
-                            let argument = clean_str.split_whitespace().nth(1);
-                            argument.map(|name| {
-                                self.kernel
-                                    .process_each_capability(&self.capability, |proc| {
-                                        let proc_name = proc.get_process_name();
-                                        if proc_name == name {
-                                            proc.terminate(None);
-                                            let mut console_writer = ConsoleWriter::new();
-                                            let _ = write(
-                                                &mut console_writer,
-                                                format_args!(
-                                                    "Process {} terminated\r\n",
-                                                    proc_name
-                                                ),
-                                            );
-
-                                            let _ = self.write_bytes(
-                                                &(console_writer.buf)[..console_writer.size],
-                                            );
-                                        }
-                                    });
+                            self.each_named_process(clean_str, &mut |proc, name| {
+                                proc.terminate(None);
+                                self.write_process_event(name, "terminated");
                             });
                         } else if clean_str.starts_with("boot") {
-                            let argument = clean_str.split_whitespace().nth(1);
-                            argument.map(|name| {
-                                self.kernel
-                                    .process_each_capability(&self.capability, |proc| {
-                                        let proc_name = proc.get_process_name();
-                                        if proc_name == name
-                                            && proc.get_state() == State::Terminated
-                                        {
-                                            proc.start(&self.capability);
-                                        }
-                                    });
+                            self.each_named_process(clean_str, &mut |proc, _| {
+                                if proc.get_state() == State::Terminated {
+                                    proc.start(&self.capability);
+                                }
                             });
                         } else if clean_str.starts_with("list") {
                             let _ = self
@@ -911,46 +1171,44 @@ impl<
                             }
                         } else if clean_str.starts_with("status") {
                             let info: KernelInfo = KernelInfo::new(self.kernel);
-                            let mut console_writer = ConsoleWriter::new();
-                            let _ = write(
-                                &mut console_writer,
-                                format_args!(
+                            self.write_args(format_args!(
                                     "Total processes: {}\r\n",
                                     info.number_loaded_processes(&self.capability)
-                                ),
-                            );
-                            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
-                            console_writer.clear();
-                            let _ = write(
-                                &mut console_writer,
-                                format_args!(
+                                ));
+ self.write_args(format_args!(
                                     "Active processes: {}\r\n",
                                     info.number_active_processes(&self.capability)
-                                ),
-                            );
-                            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
-                            console_writer.clear();
-                            let _ = write(
-                                &mut console_writer,
-                                format_args!(
+                                ));
+ self.write_args(format_args!(
                                     "Timeslice expirations: {}\r\n",
                                     info.timeslice_expirations(&self.capability)
-                                ),
-                            );
-                            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
-                        } else if clean_str.starts_with("process") {
-                            let argument = clean_str.split_whitespace().nth(1);
-                            argument.map(|name| {
-                                // If two processes have the same name, only
-                                // print the first one we find.
-                                let mut found = false;
-                                self.kernel
-                                    .process_each_capability(&self.capability, |proc| {
-                                        if found {
-                                            return;
+                                ));
+                            self.counters.map(|counters| match counters.counts() {
+                                Some(counts) => {
+                                    self.write_args(format_args!(
+                                        "Boot count: {}\r\nPanic count: {}\r\n",
+                                        counts.boot_count, counts.panic_count
+                                    ));
+                                    match counts.last_panic {
+                                        Some(reason) => self.write_args(format_args!(
+                                            "Last panic: {:#x}\r\n",
+                                            reason
+                                        )),
+                                        None => {
+                                            let _ = self.write_bytes(b"Last panic: none\r\n");
                                         }
-                                        let proc_name = proc.get_process_name();
-                                        if proc_name == name {
+                                    }
+                                }
+                                None => {
+                                    let _ = self.write_bytes(b"Boot counters: not loaded\r\n");
+                                }
+                            });
+                        } else if clean_str.starts_with("process") {
+                            // If two processes have the same name, only print
+                            // the first one we find.
+                            let mut found = false;
+                            self.each_named_process(clean_str, &mut |proc, _| {
+                                        if !found {
                                             let mut console_writer = ConsoleWriter::new();
                                             let mut context: Option<ProcessPrinterContext> = None;
                                             context = self.process_printer.print_overview(
@@ -974,34 +1232,270 @@ impl<
 
                                             found = true;
                                         }
-                                    });
                             });
                         } else if clean_str.starts_with("kernel") {
-                            let mut console_writer = ConsoleWriter::new();
-                            let _ = write(
-                                &mut console_writer,
-                                format_args!(
+                            self.write_args(format_args!(
                                     "Kernel version: {}.{} (build {})\r\n",
                                     kernel::KERNEL_MAJOR_VERSION,
                                     kernel::KERNEL_MINOR_VERSION,
                                     option_env!("TOCK_KERNEL_VERSION").unwrap_or("unknown")
-                                ),
-                            );
-                            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
-                            console_writer.clear();
+                                ));
+
+                            self.integrity.map(|integrity| {
+                                self.write_args(format_args!(
+                                    "Kernel integrity: {:?}\r\n",
+                                    integrity.integrity_status()
+                                ));
+                            });
 
                             // Prints kernel memory by moving the writer to the
                             // start state.
                             self.writer_state.replace(WriterState::KernelStart);
+                        } else if clean_str.starts_with("attributes") {
+                            match attributes::Attributes::new(self.kernel_addresses.attributes) {
+                                Some(attributes) => {
+                                    for attribute in attributes {
+                                        self.write_args(format_args!(" {}\r\n", attribute));
+                                    }
+                                }
+                                None => {
+                                    let _ = self.write_bytes(b"No kernel attributes.\r\n");
+                                }
+                            }
                         } else if clean_str.starts_with("reset") {
-                            self.reset_function.map_or_else(
+                            let argument = clean_str.split_whitespace().nth(1);
+                            argument.map_or_else(
+                                || {
+                                    self.reset_function.map_or_else(
+                                        || {
+                                            let _ = self
+                                                .write_bytes(b"Reset function is not implemented");
+                                        },
+                                        |f| {
+                                            f();
+                                        },
+                                    );
+                                },
+                                |_| {
+                                    // Restart the process from the beginning.
+                                    // Its grant state is freed, while data in
+                                    // nonvolatile storage is kept.
+                                    self.each_named_process(clean_str, &mut |proc, name| {
+                                        if proc.get_state() == State::Terminated {
+                                            proc.start(&self.capability);
+                                        } else {
+                                            proc.try_restart(None);
+                                        }
+                                        self.write_process_event(name, "restarted");
+                                    });
+                                },
+                            );
+                        } else if clean_str.starts_with("reload") {
+                            let argument = clean_str.split_whitespace().nth(1);
+                            argument.map(|name| {
+                                // Find the process first, reloading it removes
+                                // it from the processes array.
+                                let mut process_id = None;
+                                self.each_named_process(clean_str, &mut |proc, _| {
+                                    process_id = Some(proc.processid());
+                                });
+                                let result = match (self.reload.get(), process_id) {
+                                    (None, _) => Err(ErrorCode::NOSUPPORT),
+                                    (_, None) => Err(ErrorCode::INVAL),
+                                    (Some(reload), Some(process_id)) => {
+                                        reload.reload_process(process_id)
+                                    }
+                                };
+
+                                match result { Ok(()) => self.write_args(format_args!("Reloading process {}\r\n", name)), Err(e) => self.write_args(format_args!("Failed to reload {}: {:?}\r\n", name, e)), }
+                            });
+                        } else if clean_str.starts_with("install") {
+                            self.install_command(clean_str);
+                        } else if clean_str.starts_with("receive") {
+                            self.receive_command(clean_str);
+                        } else if clean_str.starts_with("checkpoint") {
+                            self.checkpoint_command(clean_str, false);
+                        } else if clean_str.starts_with("restore") {
+                            self.checkpoint_command(clean_str, true);
+                        } else if clean_str.starts_with("drivers") {
+                            self.suspend_control.map_or_else(
+                                || {
+                                    let _ = self.write_bytes(b"No suspendable drivers.\r\n");
+                                },
+                                |control| {
+                                    let _ =
+                                        self.write_bytes(b" Name            Driver    State\r\n");
+                                    for i in 0..control.count() {
+                                        self.write_args(format_args!(
+                                                " {:<16}{:#08x}  {}\r\n",
+                                                control.name(i).unwrap_or(""),
+                                                control.driver_num(i).unwrap_or(0),
+                                                if control.is_suspended(i).unwrap_or(false) {
+                                                    "suspended"
+                                                } else {
+                                                    "active"
+                                                }
+                                            ));
+                                    }
+                                },
+                            );
+                        } else if clean_str.starts_with("suspend")
+                            || clean_str.starts_with("resume")
+                        {
+                            let suspend = clean_str.starts_with("suspend");
+                            let argument = clean_str.split_whitespace().nth(1);
+                            argument.map(|name| {
+                                let result = self.suspend_control.map_or(
+                                    Err(ErrorCode::NOSUPPORT),
+                                    |control| {
+                                        if suspend {
+                                            control.suspend(name)
+                                        } else {
+                                            control.resume(name)
+                                        }
+                                    },
+                                );
+                                match result { Ok(()) => self.write_args(format_args!(
+                                            "Driver {} {}\r\n",
+                                            name,
+                                            if suspend { "suspended" } else { "resumed" }
+                                        )), Err(e) => self.write_args(format_args!("Driver {}: {:?}\r\n", name, e)), }
+                            });
+                        } else if clean_str.starts_with("stats") {
+                            self.statistics.map_or_else(
                                 || {
-                                    let _ = self.write_bytes(b"Reset function is not implemented");
+                                    let _ = self.write_bytes(b"No kernel statistics.\r\n");
                                 },
-                                |f| {
-                                    f();
+                                |stats| {
+                                    let uptime = stats.uptime_seconds();
+                                    let counters = stats.loop_counters();
+                                    let cs = stats.context_switch_rate_averages();
+                                    let load = stats.load_averages();
+                                    self.write_args(format_args!(
+                                            "Uptime: {}d {:02}:{:02}:{:02}\r\n\
+                                             Load average: {}.{:02} {}.{:02} {}.{:02}\r\n\
+                                             Context switches: {} ({}/s, avg {}.{:02} {}.{:02} {}.{:02})\r\n\
+                                             Kernel work: {}, sleeps: {}\r\n",
+                                            uptime / 86400,
+                                            uptime / 3600 % 24,
+                                            uptime / 60 % 60,
+                                            uptime % 60,
+                                            load[0] / 100,
+                                            load[0] % 100,
+                                            load[1] / 100,
+                                            load[1] % 100,
+                                            load[2] / 100,
+                                            load[2] % 100,
+                                            counters.context_switches,
+                                            stats.context_switch_rate(),
+                                            cs[0] / 100,
+                                            cs[0] % 100,
+                                            cs[1] / 100,
+                                            cs[1] % 100,
+                                            cs[2] / 100,
+                                            cs[2] % 100,
+                                            counters.kernel_work,
+                                            counters.sleeps,
+                                        ));
+
+                                    // Only measured if the kernel has a cycle
+                                    // counter.
+                                    if counters.mpu_setup_cycles > 0 && counters.context_switches > 0 {
+                                        self.write_args(format_args!(
+                                            "MPU setup: {} cycles per context switch\r\n",
+                                            counters.mpu_setup_cycles / counters.context_switches,
+                                        ));
+                                    }
+
+                                    if stats.interrupt_sources() > 0 {
+                                        // Only list interrupts that have fired to
+                                        // keep the output short.
+                                        let mut console_writer = ConsoleWriter::new();
+                                        let _ = write(
+                                            &mut console_writer,
+                                            format_args!("Interrupts (total/last s):"),
+                                        );
+                                        for source in 0..stats.interrupt_sources() {
+                                            if let Some((total, rate)) =
+                                                stats.interrupt_count(source)
+                                            {
+                                                if total > 0 {
+                                                    let _ = write(
+                                                        &mut console_writer,
+                                                        format_args!(
+                                                            " {}:{}/{}",
+                                                            source, total, rate
+                                                        ),
+                                                    );
+                                                }
+                                            }
+                                        }
+                                        let _ = write(&mut console_writer, format_args!("\r\n"));
+                                        let _ = self.write_bytes(
+                                            &(console_writer.buf)[..console_writer.size],
+                                        );
+                                    }
                                 },
                             );
+                            self.print_process_cpu();
+                        } else if clean_str.starts_with("cputime") {
+                            self.print_process_cpu();
+                        } else if clean_str.starts_with("energy") {
+                            self.energy.map_or_else(
+                                || {
+                                    let _ = self.write_bytes(b"No energy accounting.\r\n");
+                                },
+                                |energy| {
+                                    let _ = self.write_bytes(
+                                        b" Process              CPU (ms) Energy (uJ) Budget left (uJ)\r\n",
+                                    );
+                                    self.kernel
+                                        .process_each_capability(&self.capability, |process| {
+                                            if let Some(usage) =
+                                                energy.process_energy(process.processid())
+                                            {
+                                                let mut console_writer = ConsoleWriter::new();
+                                                let _ = write(
+                                                    &mut console_writer,
+                                                    format_args!(
+                                                        " {:<20}{:9}{:12}",
+                                                        process.get_process_name(),
+                                                        usage.cpu_time_us / 1000,
+                                                        usage.energy_uj(),
+                                                    ),
+                                                );
+                                                let _ = match usage.remaining_budget_uj() {
+                                                    Some(left) => write(
+                                                        &mut console_writer,
+                                                        format_args!("{:17}\r\n", left),
+                                                    ),
+                                                    None => write(
+                                                        &mut console_writer,
+                                                        format_args!("{:>17}\r\n", "-"),
+                                                    ),
+                                                };
+                                                let _ = self.write_bytes(
+                                                    &(console_writer.buf)[..console_writer.size],
+                                                );
+                                            }
+                                        });
+                                    if let Some(measured) = energy.measured_energy_uj() {
+                                        self.write_args(format_args!("Measured system energy: {} uJ\r\n", measured));
+                                    }
+                                },
+                            );
+                        } else if clean_str.starts_with("watch") {
+                            self.watch_command(clean_str);
+                        } else if clean_str.starts_with("debug-gpio") {
+                            self.debug_gpio_command(clean_str);
+                        } else if clean_str.starts_with("inject") {
+                            self.inject_command(clean_str);
+                        } else if clean_str.starts_with("alias") {
+                            self.alias_command();
+                        } else if clean_str.starts_with("neighbors") {
+                            self.neighbors_command();
+                        } else if clean_str.starts_with("uart") {
+                            self.uart_command(clean_str);
                         } else if clean_str.starts_with("panic") {
                             panic!("Process Console forced a kernel panic.");
                         } else {
@@ -1010,12 +1504,7 @@ impl<
                         }
                     }
                     Err(_e) => {
-                        let mut console_writer = ConsoleWriter::new();
-                        let _ = write(
-                            &mut console_writer,
-                            format_args!("Invalid command: {:?}", command),
-                        );
-                        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                        self.write_args(format_args!("Invalid command: {:?}", command));
                     }
                 }
             }
@@ -1029,6 +1518,576 @@ impl<
         }
     }
 
+    /// If the first word of `command` is an alias, write its command followed
+    /// by the rest of `command` to `expanded`, and return it. Otherwise return
+    /// `command`.
+    fn expand_alias<'b>(&self, command: &'b str, expanded: &'b mut [u8]) -> &'b str {
+        let (name, arguments) = command
+            .split_once(char::is_whitespace)
+            .unwrap_or((command, ""));
+        let alias = match self.script.and_then(|script| script.alias(name)) {
+            Some(alias) => alias,
+            None => return command,
+        };
+        let mut len = 0;
+        for part in [alias.as_bytes(), b" ", arguments.as_bytes()] {
+            let copied = cmp::min(part.len(), expanded.len() - len);
+            expanded[len..len + copied].copy_from_slice(&part[..copied]);
+            len += copied;
+        }
+        // Truncation may split a character, so keep the valid part.
+        match str::from_utf8(&expanded[..len]) {
+            Ok(command) => command.trim(),
+            Err(e) => str::from_utf8(&expanded[..e.valid_up_to()])
+                .unwrap_or("")
+                .trim(),
+        }
+    }
+
+    /// Run `alias`, which lists the aliases of the startup script.
+    fn alias_command(&self) {
+        let mut any = false;
+        self.script.map(|script| {
+            let mut offset = 0;
+            while let Some((name, command, next)) = script.alias_at(offset) {
+                // Only the first definition of a name is used.
+                if script.alias(name) == Some(command) {
+                    let _ = self.write_bytes(name.as_bytes());
+                    let _ = self.write_bytes(b" = ");
+                    let _ = self.write_bytes(command.as_bytes());
+                    let _ = self.write_bytes(b"\r\n");
+                    any = true;
+                }
+                offset = next;
+            }
+        });
+        if !any {
+            let _ = self.write_bytes(b"No aliases.\r\n");
+        }
+    }
+
+    /// Print the CPU time of each process that ran, as measured by the
+    /// scheduler timer, its share of the time all processes ran, and the
+    /// cycles it took if the kernel has a cycle counter.
+    fn print_process_cpu(&self) {
+        let mut total_us: u64 = 0;
+        self.kernel
+            .process_each_capability(&self.capability, |proc| {
+                total_us = total_us.wrapping_add(proc.get_stats().cpu_time_us);
+            });
+        let _ = self.write_bytes(b"Process CPU time:\r\n");
+        self.kernel
+            .process_each_capability(&self.capability, |proc| {
+                let stats = proc.get_stats();
+                if stats.runs == 0 {
+                    return;
+                }
+                let permille = stats.cpu_time_us.saturating_mul(1000) / total_us.max(1);
+                self.write_args(format_args!(
+                    "  {:<20} {:>8} ms {:>12} cycles {:>3}.{}% {} runs\r\n",
+                    proc.get_process_name(),
+                    stats.cpu_time_us / 1000,
+                    stats.cpu_cycles,
+                    permille / 10,
+                    permille % 10,
+                    stats.runs,
+                ));
+            });
+    }
+
+    /// Run `neighbors`, which prints the link quality of each radio neighbor.
+    fn neighbors_command(&self) {
+        let neighbors = match self.neighbors.get() {
+            Some(neighbors) => neighbors,
+            None => {
+                let _ = self.write_bytes(b"No neighbor table.\r\n");
+                return;
+            }
+        };
+        if neighbors.num_neighbors() == 0 {
+            let _ = self.write_bytes(b"No neighbors.\r\n");
+        }
+        for neighbor in (0..neighbors.num_neighbors()).filter_map(|index| neighbors.neighbor(index))
+        {
+            let mut console_writer = ConsoleWriter::new();
+            let _ = write(
+                &mut console_writer,
+                format_args!(
+                    "{} frames {} age {}s",
+                    neighbor.address, neighbor.frames, neighbor.age_s
+                ),
+            );
+            if let Some(rssi) = neighbor.rssi {
+                let _ = write(
+                    &mut console_writer,
+                    format_args!(
+                        " rssi {} (avg {} min {} max {})",
+                        rssi.last, rssi.average, rssi.min, rssi.max
+                    ),
+                );
+            }
+            if let Some(lqi) = neighbor.lqi {
+                let _ = write(
+                    &mut console_writer,
+                    format_args!(
+                        " lqi {} (avg {} min {} max {})",
+                        lqi.last, lqi.average, lqi.min, lqi.max
+                    ),
+                );
+            }
+            let _ = write(&mut console_writer, format_args!("\r\n"));
+            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+        }
+    }
+
+    /// Run `uart <baud> [none|odd|even] [rtscts]`, which reconfigures the UART
+    /// of the console with 8 data bits and one stop bit. The new configuration
+    /// applies once the UART is idle, so the reply is sent with it.
+    fn uart_command(&self, command: &str) {
+        let Some(uart_configure) = self.uart_configure.get() else {
+            let _ = self.write_bytes(b"No UART configuration.\r\n");
+            return;
+        };
+        let mut arguments = command.split_whitespace().skip(1);
+        let Some(baud_rate) = arguments.next().and_then(|b| b.parse::<u32>().ok()) else {
+            let _ = self.write_bytes(b"Usage: uart <baud> [none|odd|even] [rtscts]\r\n");
+            return;
+        };
+        let mut parameters = uart::Parameters {
+            baud_rate,
+            width: uart::Width::Eight,
+            parity: uart::Parity::None,
+            stop_bits: uart::StopBits::One,
+            hw_flow_control: false,
+        };
+        for argument in arguments {
+            match argument {
+                "none" => parameters.parity = uart::Parity::None,
+                "odd" => parameters.parity = uart::Parity::Odd,
+                "even" => parameters.parity = uart::Parity::Even,
+                "rtscts" => parameters.hw_flow_control = true,
+                _ => {
+                    let _ = self.write_bytes(b"Usage: uart <baud> [none|odd|even] [rtscts]\r\n");
+                    return;
+                }
+            }
+        }
+        match uart_configure.configure(parameters) {
+            Ok(()) => self.write_args(format_args!("UART at {} baud.\r\n", baud_rate)),
+            Err(e) => self.write_args(format_args!("Failed to configure UART: {:?}\r\n", e)),
+        }
+    }
+
+    /// Run the next commands of the startup script, each once the output of
+    /// the previous one has been sent, then listen for user commands.
+    fn run_script(&self) {
+        while let Some(offset) = self.script_offset.get() {
+            match self.script.and_then(|script| script.command_at(offset)) {
+                Some((command, next)) => {
+                    self.script_offset.set(next);
+                    if self.mode.get() == ProcessConsoleState::Active {
+                        // Echo the command, as if it had been typed.
+                        let _ = self.write_bytes(command.as_bytes());
+                        let _ = self.write_bytes(b"\r\n");
+                    }
+                    self.command_buffer.map(|buffer| {
+                        let len = cmp::min(command.len(), buffer.len() - 1);
+                        buffer[..len].copy_from_slice(&command.as_bytes()[..len]);
+                        buffer[len] = EOL;
+                    });
+                    self.read_command();
+                    if self.tx_in_progress.get() || self.writer_state.get() != WriterState::Empty {
+                        // Continue when the output has been sent.
+                        return;
+                    }
+                }
+                None => {
+                    self.script_offset.clear();
+                    self.rx_buffer.take().map(|buffer| {
+                        let _ = self.uart.receive_buffer(buffer, 1);
+                    });
+                }
+            }
+        }
+    }
+
+    /// Run `watch <expression> [interval]` or `watch stop`.
+    fn watch_command(&self, command: &str) {
+        let mut arguments = command.split_whitespace().skip(1);
+        let expression = match arguments.next() {
+            None => {
+                let _ = self.write_bytes(
+                    b"Usage: watch <processes|stats|metric> [interval ms|s], watch stop\r\n",
+                );
+                self.metrics.map(|metrics| {
+                    let _ = self.write_bytes(b"Metrics:");
+                    for index in 0..metrics.num_metrics() {
+                        if let Some((name, _)) = metrics.metric(index) {
+                            let _ = self.write_bytes(b" ");
+                            let _ = self.write_bytes(name.as_bytes());
+                        }
+                    }
+                    let _ = self.write_bytes(b"\r\n");
+                });
+                return;
+            }
+            Some("stop") => {
+                if self.watch.take().is_some() {
+                    let _ = self.write_bytes(b"Watch stopped.\r\n");
+                }
+                return;
+            }
+            Some("processes") => WatchExpression::Processes,
+            Some("stats") => WatchExpression::Stats,
+            Some(name) => match self.metrics.and_then(|metrics| metrics.find_metric(name)) {
+                Some(index) => WatchExpression::Metric(index),
+                None => {
+                    let _ = self.write_bytes(b"Unknown metric, run watch to list them.\r\n");
+                    return;
+                }
+            },
+        };
+        if expression == WatchExpression::Stats && self.statistics.is_none() {
+            let _ = self.write_bytes(b"No kernel statistics.\r\n");
+            return;
+        }
+        let interval_ms = match arguments.next().map(parse_interval_ms) {
+            None => WATCH_DEFAULT_INTERVAL_MS,
+            Some(Some(interval_ms)) if interval_ms >= WATCH_MIN_INTERVAL_MS => interval_ms,
+            Some(_) => {
+                self.write_args(format_args!(
+                    "Interval must be at least {} ms.\r\n",
+                    WATCH_MIN_INTERVAL_MS
+                ));
+                return;
+            }
+        };
+
+        // If a watch is already running its alarm is armed and picks up the
+        // new expression and interval when it fires.
+        let running = self.watch.is_some();
+        self.watch.set((expression, interval_ms));
+        self.watch_elapsed_ms.set(0);
+        if !running {
+            self.alarm
+                .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(interval_ms));
+        }
+        let _ = self.write_bytes(b"Run watch stop to stop.\r\n");
+    }
+
+    /// Run `debug-gpio`, which lists the debug GPIOs and the pins they can be
+    /// assigned, or `debug-gpio <0-2> <pin|none>`.
+    fn debug_gpio_command(&self, command: &str) {
+        let Some(debug_gpios) = self.debug_gpios.get() else {
+            let _ = self.write_bytes(b"No reassignable debug GPIOs.\r\n");
+            return;
+        };
+        let mut arguments = command.split_whitespace().skip(1);
+        match (arguments.next(), arguments.next()) {
+            (None, _) => {
+                for slot in 0..NUM_DEBUG_GPIOS {
+                    self.write_args(format_args!(
+                        "Debug GPIO {}: {}\r\n",
+                        slot,
+                        debug_gpios.assigned(slot).unwrap_or("none")
+                    ));
+                }
+                let _ = self.write_bytes(b"Pins:");
+                let mut index = 0;
+                while let Some(name) = debug_gpios.pin_name(index) {
+                    let _ = self.write_bytes(b" ");
+                    let _ = self.write_bytes(name.as_bytes());
+                    index += 1;
+                }
+                let _ = self.write_bytes(b"\r\n");
+            }
+            (Some(slot), Some(pin)) => {
+                let pin = if pin == "none" { None } else { Some(pin) };
+                let result = slot
+                    .parse::<usize>()
+                    .map_err(|_| ErrorCode::INVAL)
+                    .and_then(|slot| debug_gpios.assign(slot, pin));
+                if result.is_err() {
+                    let _ = self.write_bytes(b"Unknown debug GPIO or pin.\r\n");
+                }
+            }
+            (Some(_), None) => {
+                let _ = self.write_bytes(b"Usage: debug-gpio [<0-2> <pin|none>]\r\n");
+            }
+        }
+    }
+
+    /// Call `f` with each process whose name is the first argument of
+    /// `command`, and its name.
+    fn each_named_process(&self, command: &str, f: &mut dyn FnMut(&dyn Process, &str)) {
+        if let Some(name) = command.split_whitespace().nth(1) {
+            self.kernel
+                .process_each_capability(&self.capability, |proc| {
+                    let proc_name = proc.get_process_name();
+                    if proc_name == name {
+                        f(proc, proc_name);
+                    }
+                });
+        }
+    }
+
+    /// Print that `event` happened to the process `name`.
+    fn write_process_event(&self, name: &str, event: &str) {
+        self.write_args(format_args!("Process {} {}\r\n", name, event));
+    }
+
+    /// Run `install <offset>`, which installs the process binary at `offset`
+    /// (in decimal) in the image storage of the installer.
+    fn install_command(&self, command: &str) {
+        let offset = command.split_whitespace().nth(1);
+        let result = match (self.installer.get(), offset.and_then(|o| o.parse().ok())) {
+            (None, _) => Err(ErrorCode::NOSUPPORT),
+            (_, None) => Err(ErrorCode::INVAL),
+            (Some(installer), Some(offset)) => installer.install(offset),
+        };
+        match result {
+            Ok(()) => {
+                let _ = self.write_bytes(b"Installing binary\r\n");
+            }
+            Err(e) => {
+                self.write_args(format_args!("Failed to install binary: {:?}\r\n", e));
+            }
+        }
+    }
+
+    /// Run `receive <length>`, which installs a process binary of `length`
+    /// bytes (in decimal) sent over the console.
+    ///
+    /// The binary is sent in chunks of `COMMAND_BUF_LEN` bytes, or less for
+    /// the last one. The console does not buffer more than a chunk, so the
+    /// sender must wait for the console to print that it received a chunk
+    /// before it sends the next one.
+    fn receive_command(&self, command: &str) {
+        let length = command.split_whitespace().nth(1);
+        let result = match (self.loader.get(), length.and_then(|l| l.parse().ok())) {
+            (None, _) => Err(ErrorCode::NOSUPPORT),
+            (_, None) => Err(ErrorCode::INVAL),
+            (Some(loader), Some(length)) => loader.setup(length).map(|address| (length, address)),
+        };
+        match result {
+            Ok((length, address)) => {
+                self.binary.set(BinaryReceive::Receiving {
+                    length,
+                    offset: 0,
+                    received: 0,
+                });
+                self.write_args(format_args!(
+                    "Receiving {} bytes at {:#x}\r\n",
+                    length, address
+                ));
+            }
+            Err(e) => {
+                self.write_args(format_args!("Failed to receive binary: {:?}\r\n", e));
+            }
+        }
+    }
+
+    /// Add `byte` to the chunk of the binary being received, and write the
+    /// chunk to flash once it is complete.
+    fn receive_binary_byte(&self, byte: u8) {
+        // Bytes sent while a chunk is written or the binary loaded are
+        // dropped.
+        if let Some(BinaryReceive::Receiving {
+            length,
+            offset,
+            received,
+        }) = self.binary.get()
+        {
+            self.command_buffer.map(|command| {
+                command[received] = byte;
+            });
+            let received = received + 1;
+            if received < cmp::min(COMMAND_BUF_LEN, length - offset) {
+                self.binary.set(BinaryReceive::Receiving {
+                    length,
+                    offset,
+                    received,
+                });
+            } else {
+                self.write_binary_chunk(length, offset, received, 0);
+            }
+        }
+    }
+
+    /// Write the rest of the chunk of `received` bytes at `offset` in the
+    /// binary, of which `written` bytes are written.
+    fn write_binary_chunk(&self, length: usize, offset: usize, received: usize, written: usize) {
+        self.binary.set(BinaryReceive::Writing {
+            length,
+            offset,
+            received,
+            written,
+        });
+        let result = self.loader.map_or(Err(ErrorCode::FAIL), |loader| {
+            self.command_buffer.map_or(Err(ErrorCode::FAIL), |command| {
+                loader.write(&command[written..received], offset + written)
+            })
+        });
+        if let Err(e) = result {
+            self.finish_receive(Err(e));
+        }
+    }
+
+    /// End the `receive` command. Unless the binary was loaded, this
+    /// releases the space reserved for it.
+    fn finish_receive(&self, result: Result<(), ErrorCode>) {
+        if result.is_err() {
+            self.loader.map(|loader| loader.abort());
+        }
+        self.binary.clear();
+        self.command_buffer.map(|command| {
+            command[0] = EOL;
+        });
+        self.command_index.set(0);
+        match result {
+            Ok(()) => self.write_args(format_args!("Installed binary\r\n")),
+            Err(e) => self.write_args(format_args!("Failed to receive binary: {:?}\r\n", e)),
+        }
+    }
+
+    /// Run `checkpoint <name>` or, if `restore` is set, `restore <name>`,
+    /// which save or restore the memory of the stopped process `name`.
+    fn checkpoint_command(&self, command: &str, restore: bool) {
+        let mut process_id = None;
+        self.each_named_process(command, &mut |proc, _| {
+            process_id = Some(proc.processid());
+        });
+        let result = match (self.checkpointer.get(), process_id) {
+            (None, _) => Err(ErrorCode::NOSUPPORT),
+            (_, None) => Err(ErrorCode::INVAL),
+            (Some(checkpointer), Some(id)) if restore => checkpointer.restore(id),
+            (Some(checkpointer), Some(id)) => checkpointer.checkpoint(id),
+        };
+        if result.is_err() {
+            self.write_checkpoint_result(restore, result);
+        }
+    }
+
+    /// Print the outcome of a `checkpoint` or `restore` command.
+    fn write_checkpoint_result(&self, restore: bool, result: Result<(), ErrorCode>) {
+        let operation = if restore { "Restore" } else { "Checkpoint" };
+        self.write_args(format_args!("{}: {:?}\r\n", operation, result));
+    }
+
+    /// Run `inject`, which lists the fault sites and their faults, or
+    /// `inject <site> <off|drop|error <code>|delay <ms>> [every]`.
+    fn inject_command(&self, command: &str) {
+        let Some(fault_sites) = self.fault_sites.get() else {
+            let _ = self.write_bytes(b"No fault sites.\r\n");
+            return;
+        };
+        let mut arguments = command.split_whitespace().skip(1);
+        let Some(name) = arguments.next() else {
+            if !fault_injection::enabled() {
+                let _ = self.write_bytes(b"Fault injection is disabled in this kernel.\r\n");
+            }
+            for site in fault_sites.iter() {
+                let mut console_writer = ConsoleWriter::new();
+                let _ = match site.fault() {
+                    None => write(&mut console_writer, format_args!("{}: off", site.name())),
+                    Some((fault, every)) => write(
+                        &mut console_writer,
+                        format_args!("{}: {:?} every {}", site.name(), fault, every),
+                    ),
+                };
+                let _ = write(
+                    &mut console_writer,
+                    format_args!(", {} injected\r\n", site.injected()),
+                );
+                let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+            }
+            return;
+        };
+        let Some(site) = fault_sites.iter().find(|site| site.name() == name) else {
+            let _ = self.write_bytes(b"Unknown fault site.\r\n");
+            return;
+        };
+        let fault = match arguments.next() {
+            Some("off") => {
+                site.clear();
+                return;
+            }
+            Some("drop") => Some(Fault::Drop),
+            Some("error") => arguments.next().and_then(|code| {
+                fault_injection::ERRORS
+                    .iter()
+                    .find(|(error_name, _)| code.eq_ignore_ascii_case(error_name))
+                    .map(|(_, error)| Fault::Error(*error))
+            }),
+            Some("delay") => arguments
+                .next()
+                .and_then(|ms| ms.parse().ok())
+                .map(Fault::Delay),
+            _ => None,
+        };
+        let every = arguments.next().map_or(Some(1), |every| every.parse().ok());
+        match (fault, every) {
+            (Some(fault), Some(every)) => site.inject(fault, every),
+            _ => {
+                let _ = self.write_bytes(
+                    b"Usage: inject [<site> <off|drop|error <code>|delay <ms>> [every]]\r\n",
+                );
+            }
+        }
+    }
+
+    /// Print one sample of a running `watch` command.
+    fn print_watch(&self, expression: WatchExpression) {
+        let elapsed_ms = self.watch_elapsed_ms.get();
+        let mut console_writer = ConsoleWriter::new();
+        let _ = write(
+            &mut console_writer,
+            format_args!("[{:5}.{:03}]", elapsed_ms / 1000, elapsed_ms % 1000),
+        );
+        match expression {
+            WatchExpression::Processes => {
+                // One line per process so that long names cannot overflow
+                // the writer.
+                let _ = write(&mut console_writer, format_args!("\r\n"));
+                let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                self.kernel
+                    .process_each_capability(&self.capability, |process| {
+                        self.write_args(format_args!(
+                            "  {:<20}{:?}\r\n",
+                            process.get_process_name(),
+                            process.get_state()
+                        ));
+                    });
+                return;
+            }
+            WatchExpression::Stats => {
+                self.statistics.map(|stats| {
+                    let counters = stats.loop_counters();
+                    let load = stats.load_averages();
+                    let _ = write(
+                        &mut console_writer,
+                        format_args!(
+                            " load {}.{:02} context switches {}/s kernel work {} sleeps {}",
+                            load[0] / 100,
+                            load[0] % 100,
+                            stats.context_switch_rate(),
+                            counters.kernel_work,
+                            counters.sleeps,
+                        ),
+                    );
+                });
+            }
+            WatchExpression::Metric(index) => {
+                if let Some((name, value)) = self.metrics.and_then(|metrics| metrics.metric(index))
+                {
+                    let _ = write(&mut console_writer, format_args!(" {} = {}", name, value));
+                }
+            }
+        }
+        let _ = write(&mut console_writer, format_args!("\r\n"));
+        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+    }
+
     fn prompt(&self) {
         // Only display the prompt in active mode.
         match self.mode.get() {
@@ -1063,6 +2122,13 @@ impl<
         }
     }
 
+    /// Format `args` and write the result to the console.
+    fn write_args(&self, args: fmt::Arguments) {
+        let mut console_writer = ConsoleWriter::new();
+        let _ = write(&mut console_writer, args);
+        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+    }
+
     fn write_bytes(&self, bytes: &[u8]) -> Result<(), ErrorCode> {
         if self.tx_in_progress.get() {
             self.queue_buffer.map(|buf| {
@@ -1138,13 +2204,65 @@ impl<
     > AlarmClient for ProcessConsole<'a, COMMAND_HISTORY_LEN, A, C>
 {
     fn alarm(&self) {
+        // After start, the alarm is only used by the `watch` command.
+        if let Some((expression, interval_ms)) = self.watch.get() {
+            self.watch_elapsed_ms
+                .set(self.watch_elapsed_ms.get().wrapping_add(interval_ms));
+            // Skip samples while the console is in the middle of printing
+            // the output of a command so lines do not interleave.
+            if self.writer_state.get() == WriterState::Empty {
+                self.print_watch(expression);
+            }
+            // Count from the last expiration so samples do not drift.
+            self.alarm.set_alarm(
+                self.alarm.get_alarm(),
+                self.alarm.ticks_from_ms(interval_ms),
+            );
+            return;
+        }
         self.prompt();
+        if self.script_offset.is_some() {
+            // Listen for user commands once the script has run.
+            self.run_script();
+            return;
+        }
         self.rx_buffer.take().map(|buffer| {
             let _ = self.uart.receive_buffer(buffer, 1);
         });
     }
 }
 
+impl<
+        'a,
+        const COMMAND_HISTORY_LEN: usize,
+        A: Alarm<'a>,
+        C: ProcessManagementCapability + ProcessStartCapability,
+    > ProcessInstallClient for ProcessConsole<'a, COMMAND_HISTORY_LEN, A, C>
+{
+    fn install_done(&self, result: Result<(), ErrorCode>) {
+        match result {
+            Ok(()) => self.write_args(format_args!("Installed binary\r\n")),
+            Err(e) => self.write_args(format_args!("Failed to install binary: {:?}\r\n", e)),
+        }
+    }
+}
+
+impl<
+        'a,
+        const COMMAND_HISTORY_LEN: usize,
+        A: Alarm<'a>,
+        C: ProcessManagementCapability + ProcessStartCapability,
+    > ProcessCheckpointClient for ProcessConsole<'a, COMMAND_HISTORY_LEN, A, C>
+{
+    fn checkpoint_done(&self, _process_id: ProcessId, result: Result<(), ErrorCode>) {
+        self.write_checkpoint_result(false, result);
+    }
+
+    fn restore_done(&self, _process_id: ProcessId, result: Result<(), ErrorCode>) {
+        self.write_checkpoint_result(true, result);
+    }
+}
+
 impl<
         'a,
         const COMMAND_HISTORY_LEN: usize,
@@ -1180,9 +2298,69 @@ impl<
             if self.execute.get() {
                 self.execute.set(false);
                 self.read_command();
+            } else if self.script_offset.is_some() {
+                self.run_script();
+            }
+        }
+    }
+}
+
+impl<
+        'a,
+        const COMMAND_HISTORY_LEN: usize,
+        A: Alarm<'a>,
+        C: ProcessManagementCapability + ProcessStartCapability,
+    > DynamicProcessLoadingClient for ProcessConsole<'a, COMMAND_HISTORY_LEN, A, C>
+{
+    fn write_done(&self, result: Result<(), ErrorCode>, length: usize) {
+        if let Some(BinaryReceive::Writing {
+            length: binary_length,
+            offset,
+            received,
+            written,
+        }) = self.binary.get()
+        {
+            let written = written + length;
+            match result {
+                Err(e) => self.finish_receive(Err(e)),
+                Ok(()) if written < received => {
+                    self.write_binary_chunk(binary_length, offset, received, written);
+                }
+                Ok(()) if offset + received < binary_length => {
+                    self.binary.set(BinaryReceive::Receiving {
+                        length: binary_length,
+                        offset: offset + received,
+                        received: 0,
+                    });
+                    self.write_args(format_args!(
+                        "Received {}/{} bytes\r\n",
+                        offset + received,
+                        binary_length
+                    ));
+                }
+                Ok(()) => {
+                    self.binary.set(BinaryReceive::Loading);
+                    let result = self
+                        .loader
+                        .map_or(Err(ErrorCode::FAIL), |loader| loader.load());
+                    if let Err(e) = result {
+                        self.finish_receive(Err(e));
+                    }
+                }
             }
         }
     }
+
+    fn load_done(&self, result: Result<(), ProcessLoadError>) {
+        if self.binary.get() == Some(BinaryReceive::Loading) {
+            self.finish_receive(result.map_err(|e| match e {
+                ProcessLoadError::NotEnoughMemory => ErrorCode::NOMEM,
+                ProcessLoadError::NoProcessSlot => ErrorCode::NOMEM,
+                ProcessLoadError::NotLoaded => ErrorCode::ALREADY,
+                _ => ErrorCode::INVAL,
+            }));
+        }
+    }
 }
 
 impl<
@@ -1202,6 +2380,7 @@ impl<
         if error == uart::Error::None {
             match rx_len {
                 0 => debug!("ProcessConsole had read of 0 bytes"),
+                1 if self.binary.is_some() => self.receive_binary_byte(read_buf[0]),
                 1 => {
                     self.command_buffer.map(|command| {
                         let esc_state = self.esc_state.get().next_state(read_buf[0]);
@@ -1434,3 +2613,38 @@ impl<
         let _ = self.uart.receive_buffer(read_buf, 1);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_interval_ms, StartupScript};
+
+    const SCRIPT: &[u8] = b"# Diagnostics\n\nalias ps list\n  stop app2  \r\nalias top watch processes 5s\nalias ps status\nwatch stats 10s\n\xFF\xFFstart app2\n";
+
+    #[test]
+    fn commands_skip_comments_and_aliases() {
+        let script = StartupScript::new(SCRIPT);
+        let (first, next) = script.command_at(0).unwrap();
+        assert_eq!(first, "stop app2");
+        let (second, next) = script.command_at(next).unwrap();
+        assert_eq!(second, "watch stats 10s");
+        // The script ends at erased flash.
+        assert!(script.command_at(next).is_none());
+    }
+
+    #[test]
+    fn first_alias_definition_is_used() {
+        let script = StartupScript::new(SCRIPT);
+        assert_eq!(script.alias("ps"), Some("list"));
+        assert_eq!(script.alias("top"), Some("watch processes 5s"));
+        assert_eq!(script.alias("stop"), None);
+    }
+
+    #[test]
+    fn intervals() {
+        assert_eq!(parse_interval_ms("250"), Some(250));
+        assert_eq!(parse_interval_ms("250ms"), Some(250));
+        assert_eq!(parse_interval_ms("10s"), Some(10000));
+        assert_eq!(parse_interval_ms("s"), None);
+        assert_eq!(parse_interval_ms("5000000s"), None);
+    }
+}
@@ -4,6 +4,15 @@
 
 //! Provides userspace applications with the ability to communicate over the SPI
 //! bus as a peripheral. Only supports chip select 0.
+//!
+//! The application shares a buffer to transmit and, optionally, a buffer to
+//! receive into, then asks for a transfer of up to `len` bytes. The transfer
+//! happens when the external controller selects the chip and clocks the bus.
+//! Transfers longer than the kernel buffers are split into several hardware
+//! transactions. The transfer completes with the `transfer_done` upcall once
+//! `len` bytes were exchanged, or early if the controller ended a transaction
+//! before the kernel buffers were full; the upcall then reports the number of
+//! bytes actually exchanged.
 
 use core::cell::Cell;
 use core::cmp;
@@ -21,6 +30,34 @@ use kernel::{ErrorCode, ProcessId};
 use crate::driver;
 pub const DRIVER_NUM: usize = driver::NUM::SpiPeripheral as usize;
 
+kernel::driver_api!(
+    DRIVER_API,
+    DRIVER_NUM,
+    name: "spi_peripheral",
+    commands: [
+        0 => "exists()",
+        1 => "read_write(len)",
+        2 => "get_chip_select() -> u32",
+        3 => "set_phase(trailing)",
+        4 => "get_phase() -> u32",
+        5 => "set_polarity(idle_high)",
+        6 => "get_polarity() -> u32",
+    ],
+    subscribes: [0 => "transfer_done(len)", 1 => "chip_selected(len)"],
+    allow_ro: [0 => "write"],
+    allow_rw: [0 => "read"],
+);
+
+/// Ids for subscribed upcalls.
+mod upcall {
+    /// A transfer completed.
+    pub const TRANSFER_DONE: usize = 0;
+    /// The controller selected the chip.
+    pub const CHIP_SELECTED: usize = 1;
+    /// The number of upcalls the kernel stores for this grant
+    pub const COUNT: u8 = 2;
+}
+
 /// Ids for read-only allow buffers
 mod ro_allow {
     pub const WRITE: usize = 0;
@@ -56,7 +93,7 @@ pub struct SpiPeripheral<'a, S: SpiSlaveDevice<'a>> {
     kernel_len: Cell<usize>,
     grants: Grant<
         PeripheralApp,
-        UpcallCount<2>,
+        UpcallCount<{ upcall::COUNT }>,
         AllowRoCount<{ ro_allow::COUNT }>,
         AllowRwCount<{ rw_allow::COUNT }>,
     >,
@@ -68,7 +105,7 @@ impl<'a, S: SpiSlaveDevice<'a>> SpiPeripheral<'a, S> {
         spi_slave: &'a S,
         grants: Grant<
             PeripheralApp,
-            UpcallCount<2>,
+            UpcallCount<{ upcall::COUNT }>,
             AllowRoCount<{ ro_allow::COUNT }>,
             AllowRwCount<{ rw_allow::COUNT }>,
         >,
@@ -91,17 +128,22 @@ impl<'a, S: SpiSlaveDevice<'a>> SpiPeripheral<'a, S> {
         self.kernel_write.replace(write);
     }
 
+    /// Length of the next hardware transaction of the current transfer.
+    fn chunk_len(&self, app: &PeripheralApp) -> usize {
+        cmp::min(app.len - app.index, self.kernel_len.get())
+    }
+
     // Assumes checks for busy/etc. already done
-    // Updates app.index to be index + length of op
-    fn do_next_read_write(&self, app: &mut PeripheralApp, kernel_data: &GrantKernelData) {
+    // app.index stays at the start of the transaction until it is done
+    fn do_next_read_write(&self, app: &PeripheralApp, kernel_data: &GrantKernelData) {
+        let chunk_len = self.chunk_len(app);
         let write_len = self.kernel_write.map_or(0, |kwbuf| {
             let mut start = app.index;
-            let tmp_len = kernel_data
+            kernel_data
                 .get_readonly_processbuffer(ro_allow::WRITE)
                 .and_then(|write| {
                     write.enter(|src| {
-                        let len = cmp::min(app.len - start, self.kernel_len.get());
-                        let end = cmp::min(start + len, src.len());
+                        let end = cmp::min(start + chunk_len, src.len());
                         start = cmp::min(start, end);
 
                         for (i, c) in src[start..end].iter().enumerate() {
@@ -110,9 +152,7 @@ impl<'a, S: SpiSlaveDevice<'a>> SpiPeripheral<'a, S> {
                         end - start
                     })
                 })
-                .unwrap_or(0);
-            app.index = start + tmp_len;
-            tmp_len
+                .unwrap_or(0)
         });
         // TODO verify SPI return value
         let _ = self.spi_slave.read_write_bytes(
@@ -273,8 +313,8 @@ impl<'a, S: SpiSlaveDevice<'a>> SpiSlaveClient for SpiPeripheral<'a, S> {
     ) {
         self.current_process.map(|process_id| {
             let _ = self.grants.enter(process_id, move |app, kernel_data| {
+                let index = app.index;
                 let rbuf = readbuf.inspect(|src| {
-                    let index = app.index;
                     let _ = kernel_data
                         .get_readwrite_processbuffer(rw_allow::READ)
                         .and_then(|read| {
@@ -286,8 +326,8 @@ impl<'a, S: SpiSlaveDevice<'a>> SpiSlaveClient for SpiPeripheral<'a, S> {
                                 // If app_read is shorter than before, and shorter
                                 // than what we have read would require, then truncate.
                                 // -pal 12/9/20
-                                let end = index;
-                                let start = index - length;
+                                let start = index;
+                                let end = index + length;
                                 let end = cmp::min(end, cmp::min(src.len(), dest.len()));
 
                                 // If the new endpoint is earlier than our expected
@@ -308,12 +348,18 @@ impl<'a, S: SpiSlaveDevice<'a>> SpiSlaveClient for SpiPeripheral<'a, S> {
                 self.kernel_read.put(rbuf);
                 self.kernel_write.put(writebuf);
 
-                if app.index == app.len {
+                // The controller may end the transaction before the kernel
+                // buffers are full, which ends the transfer.
+                let ended_early = length < self.chunk_len(app);
+                app.index = cmp::min(index + length, app.len);
+                if app.index == app.len || ended_early {
                     self.busy.set(false);
-                    let len = app.len;
+                    let len = app.index;
                     app.len = 0;
                     app.index = 0;
-                    kernel_data.schedule_upcall(0, (len, 0, 0)).ok();
+                    kernel_data
+                        .schedule_upcall(upcall::TRANSFER_DONE, (len, 0, 0))
+                        .ok();
                 } else {
                     self.do_next_read_write(app, kernel_data);
                 }
@@ -326,7 +372,9 @@ impl<'a, S: SpiSlaveDevice<'a>> SpiSlaveClient for SpiPeripheral<'a, S> {
         self.current_process.map(|process_id| {
             let _ = self.grants.enter(process_id, move |app, kernel_data| {
                 let len = app.len;
-                kernel_data.schedule_upcall(1, (len, 0, 0)).ok();
+                kernel_data
+                    .schedule_upcall(upcall::CHIP_SELECTED, (len, 0, 0))
+                    .ok();
             });
         });
     }
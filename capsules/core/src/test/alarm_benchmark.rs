@@ -0,0 +1,133 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Benchmark of an alarm multiplexer with many alarms armed. Depends on a
+//! working UART and debug! macro, and a cycle counter.
+//!
+//! All the alarms are armed to expire at the same time, and the benchmark
+//! prints the cycles it took to arm each alarm, and the cycles between the
+//! calls of the clients of the alarms as they expire. The latter is the cost
+//! of the multiplexer finding and firing the next expired alarm with the
+//! others still armed.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let dwt = static_init!(cortexm4::dwt::Dwt, cortexm4::dwt::Dwt::new());
+//! let benchmark = static_init!(
+//!     AlarmBenchmark<'static, cortexm4::dwt::Dwt>,
+//!     AlarmBenchmark::new(dwt)
+//! );
+//! let virtual_alarms = static_init!(
+//!     [VirtualMuxAlarm<'static, nrf52840::rtc::Rtc<'static>>; 32],
+//!     core::array::from_fn(|_| VirtualMuxAlarm::new(mux_alarm))
+//! );
+//! let alarms = static_init!(
+//!     [BenchmarkAlarm<
+//!         'static,
+//!         VirtualMuxAlarm<'static, nrf52840::rtc::Rtc<'static>>,
+//!         cortexm4::dwt::Dwt,
+//!     >; 32],
+//!     core::array::from_fn(|i| BenchmarkAlarm::new(&virtual_alarms[i], benchmark))
+//! );
+//! for (virtual_alarm, alarm) in virtual_alarms.iter().zip(alarms.iter()) {
+//!     virtual_alarm.setup();
+//!     virtual_alarm.set_alarm_client(alarm);
+//! }
+//! benchmark.run(alarms);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::debug;
+use kernel::hil::hw_debug::CycleCounter;
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks};
+
+/// Measurements shared by the alarms of a benchmark.
+pub struct AlarmBenchmark<'a, C: CycleCounter> {
+    counter: &'a C,
+    alarms: Cell<usize>,
+    /// Alarms that have not expired yet.
+    remaining: Cell<usize>,
+    arm_cycles: Cell<u64>,
+    /// Count of the cycle counter when the last alarm expired.
+    last_expiration: Cell<u64>,
+    expiration_cycles: Cell<u64>,
+}
+
+impl<'a, C: CycleCounter> AlarmBenchmark<'a, C> {
+    pub fn new(counter: &'a C) -> Self {
+        Self {
+            counter,
+            alarms: Cell::new(0),
+            remaining: Cell::new(0),
+            arm_cycles: Cell::new(0),
+            last_expiration: Cell::new(0),
+            expiration_cycles: Cell::new(0),
+        }
+    }
+
+    /// Arm all `alarms` to expire in a second.
+    pub fn run<A: Alarm<'a>>(&self, alarms: &[BenchmarkAlarm<'a, A, C>]) {
+        let Some(first) = alarms.first() else {
+            return;
+        };
+        self.alarms.set(alarms.len());
+        self.remaining.set(alarms.len());
+        self.expiration_cycles.set(0);
+
+        let now = first.alarm.now();
+        let dt = first.alarm.ticks_from_seconds(1);
+        self.counter.start();
+        let start = self.counter.count();
+        for alarm in alarms {
+            alarm.alarm.set_alarm(now, dt);
+        }
+        self.arm_cycles.set(self.counter.count() - start);
+    }
+
+    fn expired(&self) {
+        let now = self.counter.count();
+        let remaining = self.remaining.get();
+        if remaining == 0 {
+            return;
+        }
+        // The time until the first alarm expires is not part of the firing.
+        if remaining != self.alarms.get() {
+            self.expiration_cycles
+                .set(self.expiration_cycles.get() + now - self.last_expiration.get());
+        }
+        self.remaining.set(remaining - 1);
+        if remaining == 1 {
+            let alarms = self.alarms.get() as u64;
+            debug!(
+                "Alarm benchmark: {} alarms, {} cycles to arm each, {} cycles between expirations",
+                alarms,
+                self.arm_cycles.get() / alarms,
+                self.expiration_cycles.get() / (alarms - 1).max(1),
+            );
+        }
+        // Exclude the time to print from the next expiration.
+        self.last_expiration.set(self.counter.count());
+    }
+}
+
+/// Client of one of the alarms of a benchmark.
+pub struct BenchmarkAlarm<'a, A: Alarm<'a>, C: CycleCounter> {
+    alarm: &'a A,
+    benchmark: &'a AlarmBenchmark<'a, C>,
+}
+
+impl<'a, A: Alarm<'a>, C: CycleCounter> BenchmarkAlarm<'a, A, C> {
+    pub fn new(alarm: &'a A, benchmark: &'a AlarmBenchmark<'a, C>) -> Self {
+        Self { alarm, benchmark }
+    }
+}
+
+impl<'a, A: Alarm<'a>, C: CycleCounter> AlarmClient for BenchmarkAlarm<'a, A, C> {
+    fn alarm(&self) {
+        self.benchmark.expired();
+    }
+}
@@ -3,6 +3,7 @@
 // Copyright Tock Contributors 2022.
 
 pub mod alarm;
+pub mod alarm_benchmark;
 pub mod alarm_edge_cases;
 pub mod capsule_test;
 pub mod double_grant_entry;
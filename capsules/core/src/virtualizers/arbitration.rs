@@ -0,0 +1,179 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Priority arbitration between the devices of a shared bus.
+//!
+//! Each device of a bus virtualizer has a priority, 0 by default. When the bus
+//! is free, the pending device with the highest priority is served next. To
+//! bound starvation, a pending device gains one level of priority each time
+//! another device is served before it, until it is served. With equal
+//! priorities, the bus is therefore shared in turn.
+//!
+//! Each device also counts how often it was passed over, which helps finding
+//! which device starves the others during debugging.
+
+use core::cell::Cell;
+
+use kernel::utilities::counters::{Gauge, SaturatingCounter};
+
+/// How often a device had to wait for other devices.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StarvationStats {
+    /// Number of times another device was served while this one was pending.
+    pub passed_over: u32,
+    /// Largest number of devices served in a row while this one was pending.
+    pub max_passed_over: u32,
+}
+
+/// Arbitration state of one device of a shared bus.
+pub struct Arbitration {
+    priority: Cell<u8>,
+    /// Devices served in a row while this one was pending, with the highest
+    /// such number.
+    waiting: Gauge,
+    passed_over: SaturatingCounter,
+}
+
+impl Arbitration {
+    pub const fn new() -> Self {
+        Self {
+            priority: Cell::new(0),
+            waiting: Gauge::new(),
+            passed_over: SaturatingCounter::new(),
+        }
+    }
+
+    pub fn priority(&self) -> u8 {
+        self.priority.get()
+    }
+
+    /// Set the priority of the device. Higher values are served first.
+    pub fn set_priority(&self, priority: u8) {
+        self.priority.set(priority);
+    }
+
+    pub fn starvation_stats(&self) -> StarvationStats {
+        StarvationStats {
+            passed_over: self.passed_over.get(),
+            max_passed_over: self.waiting.max(),
+        }
+    }
+
+    pub fn reset_starvation_stats(&self) {
+        self.passed_over.take();
+        self.waiting.reset_max();
+    }
+
+    fn urgency(&self) -> u32 {
+        (self.priority.get() as u32).saturating_add(self.waiting.get())
+    }
+
+    fn passed_over(&self) {
+        self.waiting.increment();
+        self.passed_over.increment();
+    }
+}
+
+impl Default for Arbitration {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Select the pending device to serve next among the ones returned by
+/// `devices`, and update the arbitration state of all pending devices.
+///
+/// `devices` must return the same devices each time it is called.
+pub(crate) fn select<'a, T: 'a, I: Iterator<Item = &'a T>>(
+    devices: impl Fn() -> I,
+    pending: impl Fn(&T) -> bool,
+    arbitration: impl Fn(&T) -> &Arbitration,
+) -> Option<&'a T> {
+    // Ties are broken by the order of the devices.
+    let mut selected: Option<&'a T> = None;
+    for device in devices().filter(|device| pending(device)) {
+        if selected.is_none_or(|s| arbitration(device).urgency() > arbitration(s).urgency()) {
+            selected = Some(device);
+        }
+    }
+
+    selected.inspect(|selected| {
+        for device in devices().filter(|device| pending(device)) {
+            if core::ptr::eq(device, *selected) {
+                arbitration(device).waiting.set(0);
+            } else {
+                arbitration(device).passed_over();
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Device {
+        pending: Cell<bool>,
+        arbitration: Arbitration,
+    }
+
+    fn device(priority: u8) -> Device {
+        let arbitration = Arbitration::new();
+        arbitration.set_priority(priority);
+        Device {
+            pending: Cell::new(true),
+            arbitration,
+        }
+    }
+
+    fn serve(devices: &[Device]) -> Option<usize> {
+        select(
+            || devices.iter(),
+            |device| device.pending.get(),
+            |device| &device.arbitration,
+        )
+        .map(|selected| {
+            // The device becomes pending again with its next operation.
+            devices
+                .iter()
+                .position(|device| core::ptr::eq(device, selected))
+                .unwrap()
+        })
+    }
+
+    #[test]
+    fn test_equal_priorities_take_turns() {
+        let devices = [device(0), device(0), device(0)];
+        let order: [Option<usize>; 6] = core::array::from_fn(|_| serve(&devices));
+        assert_eq!(
+            order,
+            [Some(0), Some(1), Some(2), Some(0), Some(1), Some(2)]
+        );
+    }
+
+    #[test]
+    fn test_priority_is_served_first_without_starvation() {
+        let devices = [device(0), device(2)];
+        let order: [Option<usize>; 4] = core::array::from_fn(|_| serve(&devices));
+        // The low priority device is served once it was passed over twice.
+        assert_eq!(order, [Some(1), Some(1), Some(0), Some(1)]);
+        assert_eq!(
+            devices[0].arbitration.starvation_stats(),
+            StarvationStats {
+                passed_over: 3,
+                max_passed_over: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn test_only_pending_devices_are_served() {
+        let devices = [device(3), device(0)];
+        devices[0].pending.set(false);
+        assert_eq!(serve(&devices), Some(1));
+        devices[1].pending.set(false);
+        assert_eq!(serve(&devices), None);
+        assert_eq!(devices[0].arbitration.starvation_stats().passed_over, 0);
+    }
+}
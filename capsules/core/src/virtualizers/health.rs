@@ -0,0 +1,127 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Health tracking of the devices of a shared bus.
+//!
+//! When an external device is unplugged or broken, every transaction with it
+//! fails, usually after the bus waited for the device to answer. After a
+//! number of consecutive failures, the failure threshold, the device is marked
+//! offline. Requests to an offline device fail immediately without using the
+//! bus, so that the device does not slow down the other devices of the bus.
+//!
+//! To bring the device back when it is plugged in again, one in every
+//! [`PROBE_INTERVAL`] requests to an offline device is still sent to the bus
+//! as a probe. The device is online again as soon as a transaction succeeds.
+//!
+//! Only failures caused by the device count towards the threshold; what
+//! counts as such depends on the bus.
+
+use core::cell::Cell;
+
+/// Number of consecutive failures after which a device is offline, unless the
+/// board sets another threshold.
+pub const DEFAULT_FAILURE_THRESHOLD: u8 = 3;
+
+/// While a device is offline, one in this many requests is sent to the bus to
+/// probe whether the device is back.
+pub const PROBE_INTERVAL: u8 = 8;
+
+/// Health state of one device of a shared bus.
+pub struct DeviceHealth {
+    threshold: Cell<u8>,
+    /// Consecutive failed transactions.
+    failures: Cell<u8>,
+    /// Requests rejected since the last probe.
+    rejected: Cell<u8>,
+}
+
+impl DeviceHealth {
+    pub const fn new() -> Self {
+        Self {
+            threshold: Cell::new(DEFAULT_FAILURE_THRESHOLD),
+            failures: Cell::new(0),
+            rejected: Cell::new(0),
+        }
+    }
+
+    /// Set the number of consecutive failures after which the device is
+    /// offline. A threshold of 0 keeps the device online.
+    pub fn set_failure_threshold(&self, threshold: u8) {
+        self.threshold.set(threshold);
+    }
+
+    pub fn is_online(&self) -> bool {
+        self.threshold.get() == 0 || self.failures.get() < self.threshold.get()
+    }
+
+    /// Whether a new request to the device should be sent to the bus.
+    pub fn admit(&self) -> bool {
+        if self.is_online() {
+            return true;
+        }
+        let rejected = self.rejected.get() + 1;
+        if rejected >= PROBE_INTERVAL {
+            self.rejected.set(0);
+            true
+        } else {
+            self.rejected.set(rejected);
+            false
+        }
+    }
+
+    /// A transaction with the device succeeded.
+    pub fn succeeded(&self) {
+        self.failures.set(0);
+        self.rejected.set(0);
+    }
+
+    /// A transaction failed because of the device.
+    pub fn failed(&self) {
+        self.failures.set(self.failures.get().saturating_add(1));
+    }
+}
+
+impl Default for DeviceHealth {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_offline_after_threshold_and_probe() {
+        let health = DeviceHealth::new();
+        for _ in 0..DEFAULT_FAILURE_THRESHOLD {
+            assert!(health.admit());
+            health.failed();
+        }
+        assert!(!health.is_online());
+
+        // Requests are rejected until the next probe.
+        for _ in 1..PROBE_INTERVAL {
+            assert!(!health.admit());
+        }
+        assert!(health.admit());
+        health.failed();
+        assert!(!health.admit());
+
+        health.succeeded();
+        assert!(health.is_online());
+        assert!(health.admit());
+    }
+
+    #[test]
+    fn test_zero_threshold_stays_online() {
+        let health = DeviceHealth::new();
+        health.set_failure_threshold(0);
+        for _ in 0..10 {
+            health.failed();
+        }
+        assert!(health.is_online());
+        assert!(health.admit());
+    }
+}
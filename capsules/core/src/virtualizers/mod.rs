@@ -2,11 +2,15 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 // Copyright Tock Contributors 2023.
 
+pub mod arbitration;
+pub mod health;
 pub mod virtual_adc;
 pub mod virtual_aes_ccm;
 pub mod virtual_alarm;
 pub mod virtual_flash;
 pub mod virtual_i2c;
+pub mod virtual_i2c_slave;
+pub mod virtual_power_rail;
 pub mod virtual_pwm;
 pub mod virtual_rng;
 pub mod virtual_spi;
@@ -7,7 +7,6 @@
 
 use core::cell::Cell;
 
-use kernel::collections::list::{List, ListLink, ListNode};
 use kernel::hil::time::{self, Alarm, Ticks, Time};
 use kernel::utilities::cells::OptionalCell;
 use kernel::ErrorCode;
@@ -32,28 +31,40 @@ impl<T: Ticks> TickDtReference<T> {
     }
 }
 
+/// Where a virtual alarm is in the mux.
+#[derive(Copy, Clone, PartialEq)]
+enum State {
+    /// Not armed.
+    Idle,
+    /// Armed and in the mux's heap of armed alarms.
+    Armed,
+    /// Expired and waiting for the mux to call its client.
+    Firing,
+}
+
 /// An object to multiplex multiple "virtual" alarms over a single underlying alarm. A
-/// `VirtualMuxAlarm` is a node in a linked list of alarms that share the same underlying alarm.
+/// `VirtualMuxAlarm` is a node in the heap of armed alarms that share the same underlying alarm.
 pub struct VirtualMuxAlarm<'a, A: Alarm<'a>> {
     /// Underlying alarm which multiplexes all these virtual alarm.
     mux: &'a MuxAlarm<'a, A>,
     /// Reference and dt point when this alarm was setup.
     dt_reference: Cell<TickDtReference<A::Ticks>>,
-    /// Whether this alarm is currently armed, i.e. whether it should fire when the time has
-    /// elapsed.
-    armed: Cell<bool>,
-    /// Next alarm in the list.
-    next: ListLink<'a, VirtualMuxAlarm<'a, A>>,
+    /// Whether this alarm is armed, and where it is in the mux.
+    state: Cell<State>,
+    /// This alarm, set by `setup()`, so the mux can link it while it is armed.
+    this: OptionalCell<&'a VirtualMuxAlarm<'a, A>>,
+    /// First child in the heap of armed alarms.
+    child: Cell<Option<&'a VirtualMuxAlarm<'a, A>>>,
+    /// Next sibling in the heap of armed alarms.
+    sibling: Cell<Option<&'a VirtualMuxAlarm<'a, A>>>,
+    /// Parent in the heap if this is the first child, previous sibling otherwise.
+    prev: Cell<Option<&'a VirtualMuxAlarm<'a, A>>>,
+    /// Next alarm to fire while the mux fires expired alarms.
+    next_firing: Cell<Option<&'a VirtualMuxAlarm<'a, A>>>,
     /// Alarm client for this node in the list.
     client: OptionalCell<&'a dyn time::AlarmClient>,
 }
 
-impl<'a, A: Alarm<'a>> ListNode<'a, VirtualMuxAlarm<'a, A>> for VirtualMuxAlarm<'a, A> {
-    fn next(&self) -> &'a ListLink<VirtualMuxAlarm<'a, A>> {
-        &self.next
-    }
-}
-
 impl<'a, A: Alarm<'a>> VirtualMuxAlarm<'a, A> {
     /// After calling new, always call setup()
     pub fn new(mux_alarm: &'a MuxAlarm<'a, A>) -> VirtualMuxAlarm<'a, A> {
@@ -65,8 +76,12 @@ impl<'a, A: Alarm<'a>> VirtualMuxAlarm<'a, A> {
                 dt: zero,
                 extended: false,
             }),
-            armed: Cell::new(false),
-            next: ListLink::empty(),
+            state: Cell::new(State::Idle),
+            this: OptionalCell::empty(),
+            child: Cell::new(None),
+            sibling: Cell::new(None),
+            prev: Cell::new(None),
+            next_firing: Cell::new(None),
             client: OptionalCell::empty(),
         }
     }
@@ -74,7 +89,21 @@ impl<'a, A: Alarm<'a>> VirtualMuxAlarm<'a, A> {
     /// Call this method immediately after new() to link this to the mux, otherwise alarms won't
     /// fire
     pub fn setup(&'a self) {
-        self.mux.virtual_alarms.push_head(self);
+        self.this.set(self);
+    }
+
+    /// Ticks until this alarm expires, 0 if it has expired.
+    ///
+    /// This orders armed alarms consistently as time passes: an alarm that
+    /// expires sooner than another one keeps doing so until both expired.
+    fn remaining(&self, now: A::Ticks) -> A::Ticks {
+        let dt_ref = self.dt_reference.get();
+        let expiration = dt_ref.reference_plus_dt();
+        if now.within_range(dt_ref.reference, expiration) {
+            expiration.wrapping_sub(now)
+        } else {
+            A::Ticks::from(0u32)
+        }
     }
 }
 
@@ -93,29 +122,28 @@ impl<'a, A: Alarm<'a>> Alarm<'a> for VirtualMuxAlarm<'a, A> {
     }
 
     fn disarm(&self) -> Result<(), ErrorCode> {
-        if !self.armed.get() {
-            return Ok(());
-        }
-
-        self.armed.set(false);
-
-        let enabled = self.mux.enabled.get() - 1;
-        self.mux.enabled.set(enabled);
-
-        // If there are not more enabled alarms, disable the underlying alarm
-        // completely.
-        if enabled == 0 {
-            let _ = self.mux.alarm.disarm();
+        match self.state.replace(State::Idle) {
+            State::Idle => {}
+            // The mux skips the alarm when it gets to it.
+            State::Firing => {}
+            State::Armed => {
+                self.this.map(|this| {
+                    let first = self.mux.first();
+                    self.mux.remove(this, self.mux.alarm.now());
+                    if first.is_some_and(|first| core::ptr::eq(first, this)) {
+                        self.mux.schedule();
+                    }
+                });
+            }
         }
         Ok(())
     }
 
     fn is_armed(&self) -> bool {
-        self.armed.get()
+        self.state.get() != State::Idle
     }
 
     fn set_alarm(&self, reference: Self::Ticks, dt: Self::Ticks) {
-        let enabled = self.mux.enabled.get();
         let half_max = Self::Ticks::half_max_value();
         // If the dt is more than half of the available time resolution, then we need to break
         // up the alarm into two internal alarms. This ensures that our internal comparisons of
@@ -134,46 +162,32 @@ impl<'a, A: Alarm<'a>> Alarm<'a> for VirtualMuxAlarm<'a, A> {
                 extended: false,
             }
         };
-        self.dt_reference.set(dt_reference);
-        // Ensure local variable has correct value when used below
-        let dt = dt_reference.dt;
 
-        if !self.armed.get() {
-            self.mux.enabled.set(enabled + 1);
-            self.armed.set(true);
-        }
+        let Some(this) = self.this.get() else {
+            // Not set up, the mux cannot fire this alarm.
+            self.dt_reference.set(dt_reference);
+            return;
+        };
 
-        // First alarm, so set it
-        if enabled == 0 {
-            //debug!("virtual_alarm: first alarm: set it.");
-            self.mux.set_alarm(reference, dt);
-        } else if !self.mux.firing.get() {
-            // If firing is true, the mux will scan all the alarms after
-            // firing and pick the soonest one so do not need to modify the
-            // mux. Otherwise, this is an alarm
-            // started in a separate code path (e.g., another event).
-            // This new alarm fires sooner if two things are both true:
-            //    1. The current earliest alarm expiration doesn't fall
-            //    in the range of [reference, reference+dt): this means
-            //    it is either in the past (before reference) or the future
-            //    (reference + dt), AND
-            //    2. now falls in the [reference, reference+dt)
-            //    window of the current earliest alarm. This means the
-            //    current earliest alarm hasn't fired yet (it is in the future).
-            // -pal
-            let cur_alarm = self.mux.alarm.get_alarm();
-            let now = self.mux.alarm.now();
-            let expiration = reference.wrapping_add(dt);
-            if !cur_alarm.within_range(reference, expiration) {
-                let next = self.mux.next_tick_vals.get();
-                if next.map_or(true, |(next_reference, next_dt)| {
-                    now.within_range(next_reference, next_reference.wrapping_add(next_dt))
-                }) {
-                    self.mux.set_alarm(reference, dt);
-                }
-            } else {
-                // current alarm will fire earlier, keep it
-            }
+        let now = self.mux.alarm.now();
+        let first = self.mux.first();
+        if self.state.get() == State::Armed {
+            self.mux.remove(this, now);
+        }
+        // If the alarm is firing it is still in the mux's list of alarms to
+        // fire, which skips it now that it is armed again.
+        self.dt_reference.set(dt_reference);
+        self.state.set(State::Armed);
+        self.mux.insert(this, now);
+
+        // Only the first alarm to expire sets the underlying alarm.
+        let new_first = self.mux.first();
+        let first_changed = match (first, new_first) {
+            (Some(first), Some(new_first)) => !core::ptr::eq(first, new_first),
+            _ => true,
+        };
+        if first_changed || new_first.is_some_and(|new_first| core::ptr::eq(new_first, this)) {
+            self.mux.schedule();
         }
     }
 
@@ -199,106 +213,222 @@ impl<'a, A: Alarm<'a>> time::AlarmClient for VirtualMuxAlarm<'a, A> {
 }
 
 /// Structure to control a set of virtual alarms multiplexed together on top of a single alarm.
+///
+/// Armed alarms are kept in a pairing heap ordered by expiration, linked
+/// through the alarms themselves. Arming an alarm takes constant time,
+/// finding the next alarm to expire takes constant time, and removing an
+/// alarm, because it fired or was disarmed, takes amortized logarithmic time.
+/// Alarms that are not armed cost nothing when the underlying alarm fires.
+///
+/// The mux previously scanned every virtual alarm on each operation. To
+/// compare the two on an nRF52840DK, run
+/// [`crate::test::alarm_benchmark`] with the DWT of the Cortex-M4 as the
+/// cycle counter, once on this mux and once on the linear mux (the parent of
+/// the commit that introduced the heap), with the same number of alarms. It
+/// prints the cycles to arm each alarm and the cycles between expirations.
+/// No measurements have been recorded here yet.
 pub struct MuxAlarm<'a, A: Alarm<'a>> {
-    /// Head of the linked list of virtual alarms multiplexed together.
-    virtual_alarms: List<'a, VirtualMuxAlarm<'a, A>>,
-    /// Number of virtual alarms that are currently enabled.
-    enabled: Cell<usize>,
+    /// Root of the heap of armed virtual alarms, the one that expires first.
+    armed: Cell<Option<&'a VirtualMuxAlarm<'a, A>>>,
     /// Underlying alarm, over which the virtual alarms are multiplexed.
     alarm: &'a A,
-    /// Whether we are firing; used to delay restarted alarms
+    /// Whether we are firing; the underlying alarm is set once all expired
+    /// alarms have fired.
     firing: Cell<bool>,
-    /// Reference to next alarm
-    next_tick_vals: Cell<Option<(A::Ticks, A::Ticks)>>,
 }
 
 impl<'a, A: Alarm<'a>> MuxAlarm<'a, A> {
     pub const fn new(alarm: &'a A) -> MuxAlarm<'a, A> {
         MuxAlarm {
-            virtual_alarms: List::new(),
-            enabled: Cell::new(0),
+            armed: Cell::new(None),
             alarm,
             firing: Cell::new(false),
-            next_tick_vals: Cell::new(None),
         }
     }
 
     pub fn set_alarm(&self, reference: A::Ticks, dt: A::Ticks) {
-        self.next_tick_vals.set(Some((reference, dt)));
         self.alarm.set_alarm(reference, dt);
     }
 
     pub fn disarm(&self) {
-        self.next_tick_vals.set(None);
         let _ = self.alarm.disarm();
     }
+
+    /// The armed alarm that expires first.
+    fn first(&self) -> Option<&'a VirtualMuxAlarm<'a, A>> {
+        self.armed.get()
+    }
+
+    /// Set the underlying alarm for the armed alarm that expires first.
+    fn schedule(&self) {
+        if self.firing.get() {
+            // The underlying alarm is set once all expired alarms fired.
+            return;
+        }
+        match self.first() {
+            Some(first) => {
+                let dt_reference = first.dt_reference.get();
+                self.set_alarm(dt_reference.reference, dt_reference.dt);
+            }
+            None => self.disarm(),
+        }
+    }
+
+    /// Combine two heaps, returning the root of the result.
+    fn meld(
+        &self,
+        a: &'a VirtualMuxAlarm<'a, A>,
+        b: &'a VirtualMuxAlarm<'a, A>,
+        now: A::Ticks,
+    ) -> &'a VirtualMuxAlarm<'a, A> {
+        let (root, other) = if b.remaining(now) < a.remaining(now) {
+            (b, a)
+        } else {
+            (a, b)
+        };
+        other.sibling.set(root.child.get());
+        if let Some(child) = root.child.get() {
+            child.prev.set(Some(other));
+        }
+        other.prev.set(Some(root));
+        root.child.set(Some(other));
+        root
+    }
+
+    /// Combine a list of sibling heaps into one, meld them in pairs from the
+    /// left and then the pairs from the right.
+    fn merge_pairs(
+        &self,
+        first: Option<&'a VirtualMuxAlarm<'a, A>>,
+        now: A::Ticks,
+    ) -> Option<&'a VirtualMuxAlarm<'a, A>> {
+        // The melded pairs are stacked through their sibling links.
+        let mut pairs = None;
+        let mut next = first;
+        while let Some(a) = next {
+            let b = a.sibling.take();
+            a.prev.set(None);
+            let pair = match b {
+                Some(b) => {
+                    next = b.sibling.take();
+                    b.prev.set(None);
+                    self.meld(a, b, now)
+                }
+                None => {
+                    next = None;
+                    a
+                }
+            };
+            pair.sibling.set(pairs);
+            pairs = Some(pair);
+        }
+
+        let mut root = None;
+        while let Some(pair) = pairs {
+            pairs = pair.sibling.take();
+            root = Some(match root {
+                Some(root) => self.meld(root, pair, now),
+                None => pair,
+            });
+        }
+        root
+    }
+
+    fn insert(&self, valarm: &'a VirtualMuxAlarm<'a, A>, now: A::Ticks) {
+        valarm.child.set(None);
+        valarm.sibling.set(None);
+        valarm.prev.set(None);
+        self.armed.set(Some(match self.armed.get() {
+            Some(root) => self.meld(root, valarm, now),
+            None => valarm,
+        }));
+    }
+
+    fn remove(&self, valarm: &'a VirtualMuxAlarm<'a, A>, now: A::Ticks) {
+        let children = self.merge_pairs(valarm.child.take(), now);
+        match valarm.prev.take() {
+            // Only the root has no parent or previous sibling.
+            None => self.armed.set(children),
+            Some(prev) => {
+                let sibling = valarm.sibling.take();
+                if prev
+                    .child
+                    .get()
+                    .is_some_and(|child| core::ptr::eq(child, valarm))
+                {
+                    prev.child.set(sibling);
+                } else {
+                    prev.sibling.set(sibling);
+                }
+                if let Some(sibling) = sibling {
+                    sibling.prev.set(Some(prev));
+                }
+                if let (Some(root), Some(children)) = (self.armed.get(), children) {
+                    self.armed.set(Some(self.meld(root, children, now)));
+                }
+            }
+        }
+    }
 }
 
 impl<'a, A: Alarm<'a>> time::AlarmClient for MuxAlarm<'a, A> {
     /// When the underlying alarm has fired, we have to multiplex this event back to the virtual
     /// alarms that should now fire.
     fn alarm(&self) {
-        // Check whether to fire each alarm. At this level, alarms are one-shot,
-        // so a repeating client will set it again in the alarm() callback.
         self.firing.set(true);
-        self.virtual_alarms
-            .iter()
-            .filter(|cur| {
-                let dt_ref = cur.dt_reference.get();
-                // It is very important to get the current now time as the reference could have been
-                // set from now in the previous for_each iteration. We rely on the reference always
-                // being in the past when compared to now.
-                let now = self.alarm.now();
-                cur.armed.get() && !now.within_range(dt_ref.reference, dt_ref.reference_plus_dt())
-            })
-            .for_each(|cur| {
-                let dt_ref = cur.dt_reference.get();
-                if dt_ref.extended {
-                    // The first part of the extended alarm just fired, leave alarm armed with
-                    // remaining time.
-                    cur.dt_reference.set(TickDtReference {
-                        reference: dt_ref.reference_plus_dt(),
-                        dt: A::Ticks::half_max_value(),
-                        extended: false,
-                    });
-                } else {
-                    // Alarm fully expired, disarm and fire callback
-                    cur.armed.set(false);
-                    self.enabled.set(self.enabled.get() - 1);
-                    //debug!("  Virtualizer: {:?} outside {:?}-{:?}, fire!", now, cur.reference.get(), cur.reference.get().wrapping_add(cur.dt.get()));
-                    cur.alarm();
-                }
-            });
-        self.firing.set(false);
-        // Find the soonest alarm client (if any) and set the "next" underlying
-        // alarm based on it.  This needs to happen after firing all expired
-        // alarms since those may have reset new alarms.
-        let now = self.alarm.now();
-        let next = self
-            .virtual_alarms
-            .iter()
-            .filter(|cur| cur.armed.get())
-            .min_by_key(|cur| {
-                let when = cur.dt_reference.get();
-                // If the alarm has already expired, then it should be
-                // considered as the earliest possible (0 ticks), so it
-                // will trigger as soon as possible. This can happen
-                // if the alarm expired *after* it was examined in the
-                // above loop.
-                if !now.within_range(when.reference, when.reference_plus_dt()) {
-                    A::Ticks::from(0u32)
-                } else {
-                    when.reference_plus_dt().wrapping_sub(now)
-                }
-            });
 
-        // Set the alarm.
-        if let Some(valrm) = next {
-            let dt_reference = valrm.dt_reference.get();
-            self.set_alarm(dt_reference.reference, dt_reference.dt);
-        } else {
-            self.disarm();
+        // Take the expired alarms off the heap, in the order they expired.
+        // It is very important to get the current now time for each alarm,
+        // so alarms that expire while we take them off also fire.
+        let mut to_fire: Option<&'a VirtualMuxAlarm<'a, A>> = None;
+        let mut last: Option<&'a VirtualMuxAlarm<'a, A>> = None;
+        while let Some(first) = self.first() {
+            let now = self.alarm.now();
+            if first.remaining(now) != A::Ticks::from(0u32) {
+                break;
+            }
+            self.remove(first, now);
+            first.state.set(State::Firing);
+            first.next_firing.set(None);
+            match last {
+                Some(last) => last.next_firing.set(Some(first)),
+                None => to_fire = Some(first),
+            }
+            last = Some(first);
         }
+
+        // Fire them. At this level, alarms are one-shot, so a repeating
+        // client will set it again in the alarm() callback; that alarm only
+        // fires again the next time the underlying alarm fires.
+        let mut next = to_fire;
+        while let Some(cur) = next {
+            next = cur.next_firing.take();
+            if cur.state.get() != State::Firing {
+                // An earlier client disarmed or set this alarm again.
+                continue;
+            }
+            let dt_ref = cur.dt_reference.get();
+            if dt_ref.extended {
+                // The first part of the extended alarm just fired, leave alarm armed with
+                // remaining time.
+                cur.dt_reference.set(TickDtReference {
+                    reference: dt_ref.reference_plus_dt(),
+                    dt: A::Ticks::half_max_value(),
+                    extended: false,
+                });
+                cur.state.set(State::Armed);
+                self.insert(cur, self.alarm.now());
+            } else {
+                // Alarm fully expired, disarm and fire callback
+                cur.state.set(State::Idle);
+                cur.alarm();
+            }
+        }
+        self.firing.set(false);
+
+        // Set the underlying alarm for the soonest alarm (if any). If it
+        // already expired, the underlying alarm fires as soon as possible.
+        self.schedule();
     }
 }
 
@@ -581,4 +711,107 @@ mod tests {
         alarm.run_for_ticks(Ticks32::from(750));
         assert_eq!(client.count(), v_alarms.len());
     }
+
+    struct OrderClient<'a> {
+        sequence: &'a Cell<usize>,
+        fired: Cell<Option<usize>>,
+    }
+
+    impl<'a> OrderClient<'a> {
+        fn new(sequence: &'a Cell<usize>) -> Self {
+            Self {
+                sequence,
+                fired: Cell::new(None),
+            }
+        }
+    }
+
+    impl AlarmClient for OrderClient<'_> {
+        fn alarm(&self) {
+            self.fired.set(Some(self.sequence.get()));
+            self.sequence.set(self.sequence.get() + 1);
+        }
+    }
+
+    #[test]
+    fn test_many_alarms_fire_in_order() {
+        let alarm = FakeAlarm::new();
+        let mux = MuxAlarm::new(&alarm);
+        alarm.set_alarm_client(&mux);
+
+        const N: usize = 16;
+        let sequence = Cell::new(0);
+        let v_alarms: [VirtualMuxAlarm<FakeAlarm>; N] =
+            core::array::from_fn(|_| VirtualMuxAlarm::new(&mux));
+        let clients: [OrderClient; N] = core::array::from_fn(|_| OrderClient::new(&sequence));
+
+        // Arm the alarms in a scrambled order, 100 ticks apart.
+        let now = alarm.now();
+        for i in 0..N {
+            let slot = (i * 7) % N;
+            v_alarms[slot].setup();
+            v_alarms[slot].set_alarm_client(&clients[slot]);
+            v_alarms[slot].set_alarm(now, Ticks32::from(100 * (slot as u32 + 1)));
+        }
+        // Disarm one that is not the first to expire, and move another one
+        // from the end to the front.
+        assert!(v_alarms[5].disarm().is_ok());
+        v_alarms[N - 1].set_alarm(now, Ticks32::from(50));
+
+        alarm.run_for_ticks(Ticks32::from(100 * (N as u32 + 1)));
+
+        assert_eq!(sequence.get(), N - 1);
+        assert_eq!(clients[5].fired.get(), None);
+        assert_eq!(clients[N - 1].fired.get(), Some(0));
+        let mut expected = 1;
+        for (i, client) in clients.iter().enumerate().take(N - 1) {
+            if i == 5 {
+                continue;
+            }
+            assert_eq!(client.fired.get(), Some(expected));
+            expected += 1;
+        }
+        assert!(!alarm.is_armed());
+    }
+
+    struct DisarmClient<'a> {
+        other: &'a VirtualMuxAlarm<'a, FakeAlarm<'a>>,
+        count: Cell<usize>,
+    }
+
+    impl AlarmClient for DisarmClient<'_> {
+        fn alarm(&self) {
+            self.count.set(self.count.get() + 1);
+            let _ = self.other.disarm();
+        }
+    }
+
+    #[test]
+    fn test_disarm_alarm_expired_at_the_same_time() {
+        let alarm = FakeAlarm::new();
+        let mux = MuxAlarm::new(&alarm);
+        alarm.set_alarm_client(&mux);
+
+        let v_alarms = &[VirtualMuxAlarm::new(&mux), VirtualMuxAlarm::new(&mux)];
+        v_alarms[0].setup();
+        v_alarms[1].setup();
+        let first = DisarmClient {
+            other: &v_alarms[1],
+            count: Cell::new(0),
+        };
+        let second = ClientCounter::new();
+        v_alarms[0].set_alarm_client(&first);
+        v_alarms[1].set_alarm_client(&second);
+
+        // Both alarms expired when the underlying alarm fires, but the first
+        // one disarms the second one.
+        let now = alarm.now();
+        v_alarms[0].set_alarm(now, Ticks32::from(10));
+        v_alarms[1].set_alarm(now, Ticks32::from(11));
+        alarm.run_for_ticks(Ticks32::from(100));
+
+        assert_eq!(first.count.get(), 1);
+        assert_eq!(second.count(), 0);
+        assert!(!v_alarms[1].is_armed());
+    }
 }
@@ -29,6 +29,19 @@
 //!     capsules_core::virtual_flash::FlashUser<'static, sam4l::flashcalw::FLASHCALW>,
 //!     capsules_core::virtual_flash::FlashUser::new(mux_flash));
 //! ```
+//!
+//! Scheduling
+//! ----------
+//!
+//! Each request is for a single page, and the mux picks the next request
+//! when the flash finishes one, so a user writing many pages is preempted at
+//! page boundaries. The mux serves the pending request of the user with the
+//! highest priority, set with [`FlashUser::set_priority`], and users of the
+//! same priority take turns. A user whose page operations must not be
+//! interleaved with those of others, like a read-modify-write of a page, can
+//! turn off preemption with [`FlashUser::set_preemptible`]. Its next request,
+//! if it makes one from the callback of the previous one, is then served
+//! before all others.
 
 use core::cell::Cell;
 
@@ -47,6 +60,11 @@ pub struct MuxFlash<'a, F: hil::flash::Flash + 'static> {
     flash: &'a F,
     users: List<'a, FlashUser<'a, F>>,
     inflight: OptionalCell<&'a FlashUser<'a, F>>,
+    /// User the last request was served for.
+    last: OptionalCell<&'a FlashUser<'a, F>>,
+    /// Whether a completion is being reported, during which the next request
+    /// is not issued yet.
+    completing: Cell<bool>,
 }
 
 impl<F: hil::flash::Flash> hil::flash::Client<F> for MuxFlash<'_, F> {
@@ -55,9 +73,11 @@ impl<F: hil::flash::Flash> hil::flash::Client<F> for MuxFlash<'_, F> {
         pagebuffer: &'static mut F::Page,
         result: Result<(), hil::flash::Error>,
     ) {
+        self.completing.set(true);
         self.inflight.take().map(move |user| {
             user.read_complete(pagebuffer, result);
         });
+        self.completing.set(false);
         self.do_next_op();
     }
 
@@ -66,16 +86,20 @@ impl<F: hil::flash::Flash> hil::flash::Client<F> for MuxFlash<'_, F> {
         pagebuffer: &'static mut F::Page,
         result: Result<(), hil::flash::Error>,
     ) {
+        self.completing.set(true);
         self.inflight.take().map(move |user| {
             user.write_complete(pagebuffer, result);
         });
+        self.completing.set(false);
         self.do_next_op();
     }
 
     fn erase_complete(&self, result: Result<(), hil::flash::Error>) {
+        self.completing.set(true);
         self.inflight.take().map(move |user| {
             user.erase_complete(result);
         });
+        self.completing.set(false);
         self.do_next_op();
     }
 }
@@ -86,17 +110,46 @@ impl<'a, F: hil::flash::Flash> MuxFlash<'a, F> {
             flash,
             users: List::new(),
             inflight: OptionalCell::empty(),
+            last: OptionalCell::empty(),
+            completing: Cell::new(false),
+        }
+    }
+
+    /// The user whose pending request is served next.
+    ///
+    /// This is the last user served if it is not preemptible and made
+    /// another request, and otherwise the user with the highest priority.
+    /// Of the users with the same priority, the first one after the last
+    /// user served in the list is picked.
+    fn next_user(&self) -> Option<&'a FlashUser<'a, F>> {
+        let last = self.last.get();
+        if let Some(last) = last {
+            if !last.preemptible.get() && last.operation.get() != Op::Idle {
+                return Some(last);
+            }
+        }
+
+        let mut next: Option<(&'a FlashUser<'a, F>, (u8, bool))> = None;
+        let mut after_last = false;
+        for node in self.users.iter() {
+            if node.operation.get() != Op::Idle {
+                let rank = (node.priority.get(), after_last);
+                if next.map_or(true, |(_, best)| rank > best) {
+                    next = Some((node, rank));
+                }
+            }
+            if last.is_some_and(|last| core::ptr::eq(node, last)) {
+                after_last = true;
+            }
         }
+        next.map(|(node, _)| node)
     }
 
-    /// Scan the list of users and find the first user that has a pending
-    /// request, then issue that request to the flash hardware.
+    /// Find the user whose request is served next, then issue that request
+    /// to the flash hardware.
     fn do_next_op(&self) {
-        if self.inflight.is_none() {
-            let mnode = self
-                .users
-                .iter()
-                .find(|node| node.operation.get() != Op::Idle);
+        if self.inflight.is_none() && !self.completing.get() {
+            let mnode = self.next_user();
             mnode.map(|node| {
                 node.buffer.take().map_or_else(
                     || {
@@ -129,6 +182,7 @@ impl<'a, F: hil::flash::Flash> MuxFlash<'a, F> {
                 );
                 node.operation.set(Op::Idle);
                 self.inflight.set(node);
+                self.last.set(node);
             });
         }
     }
@@ -152,6 +206,8 @@ pub struct FlashUser<'a, F: hil::flash::Flash + 'static> {
     mux: &'a MuxFlash<'a, F>,
     buffer: TakeCell<'static, F::Page>,
     operation: Cell<Op>,
+    priority: Cell<u8>,
+    preemptible: Cell<bool>,
     next: ListLink<'a, FlashUser<'a, F>>,
     client: OptionalCell<&'a dyn hil::flash::Client<FlashUser<'a, F>>>,
 }
@@ -162,10 +218,24 @@ impl<'a, F: hil::flash::Flash> FlashUser<'a, F> {
             mux,
             buffer: TakeCell::empty(),
             operation: Cell::new(Op::Idle),
+            priority: Cell::new(0),
+            preemptible: Cell::new(true),
             next: ListLink::empty(),
             client: OptionalCell::empty(),
         }
     }
+
+    /// Set the priority of the requests of this user. Users with a higher
+    /// priority are served first. The default is 0.
+    pub fn set_priority(&self, priority: u8) {
+        self.priority.set(priority);
+    }
+
+    /// Set whether the requests of other users can be served between two
+    /// requests of this user. The default is `true`.
+    pub fn set_preemptible(&self, preemptible: bool) {
+        self.preemptible.set(preemptible);
+    }
 }
 
 impl<'a, F: hil::flash::Flash, C: hil::flash::Client<Self>> hil::flash::HasClient<'a, C>
@@ -242,3 +312,157 @@ impl<F: hil::flash::Flash> hil::flash::Flash for FlashUser<'_, F> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kernel::hil::flash::{Client, Flash as _, HasClient as _};
+
+    /// Flash that records the request it was given, which the tests complete.
+    struct FakeFlash {
+        issued: Cell<Op>,
+        buffer: TakeCell<'static, [u8; 4]>,
+    }
+
+    impl hil::flash::Flash for FakeFlash {
+        type Page = [u8; 4];
+
+        fn read_page(
+            &self,
+            page_number: usize,
+            buf: &'static mut Self::Page,
+        ) -> Result<(), (ErrorCode, &'static mut Self::Page)> {
+            self.issued.set(Op::Read(page_number));
+            self.buffer.replace(buf);
+            Ok(())
+        }
+
+        fn write_page(
+            &self,
+            page_number: usize,
+            buf: &'static mut Self::Page,
+        ) -> Result<(), (ErrorCode, &'static mut Self::Page)> {
+            self.issued.set(Op::Write(page_number));
+            self.buffer.replace(buf);
+            Ok(())
+        }
+
+        fn erase_page(&self, page_number: usize) -> Result<(), ErrorCode> {
+            self.issued.set(Op::Erase(page_number));
+            Ok(())
+        }
+    }
+
+    /// Writes consecutive pages, each from the callback of the previous.
+    struct Writer {
+        user: OptionalCell<&'static FlashUser<'static, FakeFlash>>,
+        next_page: Cell<usize>,
+        remaining: Cell<usize>,
+    }
+
+    impl Client<FlashUser<'static, FakeFlash>> for Writer {
+        fn read_complete(&self, _: &'static mut [u8; 4], _: Result<(), hil::flash::Error>) {}
+
+        fn write_complete(&self, buffer: &'static mut [u8; 4], _: Result<(), hil::flash::Error>) {
+            if self.remaining.get() > 0 {
+                self.remaining.set(self.remaining.get() - 1);
+                self.next_page.set(self.next_page.get() + 1);
+                self.user
+                    .map(|user| user.write_page(self.next_page.get(), buffer));
+            }
+        }
+
+        fn erase_complete(&self, _: Result<(), hil::flash::Error>) {}
+    }
+
+    fn leak<T>(value: T) -> &'static T {
+        extern crate std;
+        std::boxed::Box::leak(std::boxed::Box::new(value))
+    }
+
+    fn leak_page() -> &'static mut [u8; 4] {
+        extern crate std;
+        std::boxed::Box::leak(std::boxed::Box::new([0; 4]))
+    }
+
+    fn setup<const N: usize>() -> (
+        &'static FakeFlash,
+        &'static MuxFlash<'static, FakeFlash>,
+        [(&'static FlashUser<'static, FakeFlash>, &'static Writer); N],
+    ) {
+        let flash = leak(FakeFlash {
+            issued: Cell::new(Op::Idle),
+            buffer: TakeCell::empty(),
+        });
+        let mux = leak(MuxFlash::new(flash));
+        let users = [(); N].map(|()| {
+            let user = leak(FlashUser::new(mux));
+            let writer = leak(Writer {
+                user: OptionalCell::new(user),
+                next_page: Cell::new(0),
+                remaining: Cell::new(0),
+            });
+            user.set_client(writer);
+            (user, writer)
+        });
+        (flash, mux, users)
+    }
+
+    /// Start writing `count` pages from `first` with `user`.
+    fn write(user: &FlashUser<'static, FakeFlash>, writer: &Writer, first: usize, count: usize) {
+        writer.next_page.set(first);
+        writer.remaining.set(count - 1);
+        assert!(user.write_page(first, leak_page()).is_ok());
+    }
+
+    /// Complete the write in progress, and return the request issued next.
+    fn complete(flash: &FakeFlash, mux: &MuxFlash<'static, FakeFlash>) -> Op {
+        flash.issued.set(Op::Idle);
+        let buffer = flash.buffer.take().unwrap();
+        mux.write_complete(buffer, Ok(()));
+        flash.issued.get()
+    }
+
+    #[test]
+    fn higher_priority_preempts_at_page_boundaries() {
+        let (flash, mux, [(bulk, bulk_writer), (state, state_writer)]) = setup();
+        state.set_priority(1);
+
+        write(bulk, bulk_writer, 0, 3);
+        assert!(flash.issued.get() == Op::Write(0));
+        write(state, state_writer, 100, 1);
+
+        assert!(complete(flash, mux) == Op::Write(100));
+        assert!(complete(flash, mux) == Op::Write(1));
+        assert!(complete(flash, mux) == Op::Write(2));
+        assert!(complete(flash, mux) == Op::Idle);
+    }
+
+    #[test]
+    fn users_of_the_same_priority_take_turns() {
+        let (flash, mux, [(first, first_writer), (second, second_writer)]) = setup();
+
+        write(first, first_writer, 0, 2);
+        write(second, second_writer, 10, 2);
+        assert!(flash.issued.get() == Op::Write(0));
+
+        assert!(complete(flash, mux) == Op::Write(10));
+        assert!(complete(flash, mux) == Op::Write(1));
+        assert!(complete(flash, mux) == Op::Write(11));
+        assert!(complete(flash, mux) == Op::Idle);
+    }
+
+    #[test]
+    fn non_preemptible_user_keeps_the_flash() {
+        let (flash, mux, [(bulk, bulk_writer), (state, state_writer)]) = setup();
+        bulk.set_preemptible(false);
+        state.set_priority(1);
+
+        write(bulk, bulk_writer, 0, 2);
+        write(state, state_writer, 100, 1);
+
+        assert!(complete(flash, mux) == Op::Write(1));
+        assert!(complete(flash, mux) == Op::Write(100));
+        assert!(complete(flash, mux) == Op::Idle);
+    }
+}
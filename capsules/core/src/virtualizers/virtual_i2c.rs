@@ -6,6 +6,15 @@
 //!
 //! `MuxI2C` provides shared access to a single I2C Master Bus for multiple
 //! users. `I2CDevice` provides access to a specific I2C address.
+//!
+//! When the bus is free, the pending device with the highest priority is
+//! served next (see [`arbitration`](super::arbitration)). I2C transactions
+//! are never split, so a device waits at most for the transaction in flight.
+//!
+//! A device that does not acknowledge several transactions in a row, for
+//! example because it was unplugged, is marked offline and its requests fail
+//! immediately with `AddressNak` (see [`health`](super::health)), so it does
+//! not hold up the bus.
 
 use core::cell::Cell;
 
@@ -13,6 +22,10 @@ use kernel::collections::list::{List, ListLink, ListNode};
 use kernel::deferred_call::{DeferredCall, DeferredCallClient};
 use kernel::hil::i2c::{self, Error, I2CClient, I2CHwMasterClient, NoSMBus};
 use kernel::utilities::cells::{OptionalCell, TakeCell};
+
+use super::arbitration::{self, Arbitration, StarvationStats};
+use super::health::DeviceHealth;
+
 // `NoSMBus` provides a placeholder for `SMBusMaster` in case the board doesn't have a SMBus
 pub struct MuxI2C<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a> = NoSMBus> {
     i2c: &'a I,
@@ -75,10 +88,11 @@ impl<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a>> MuxI2C<'a, I, S> {
             // Nothing is currently in flight
 
             // Try to do the next I2C operation
-            let mnode = self
-                .i2c_devices
-                .iter()
-                .find(|node| node.operation.get() != Op::Idle);
+            let mnode = arbitration::select(
+                || self.i2c_devices.iter(),
+                |node| node.operation.get() != Op::Idle,
+                |node| &node.arbitration,
+            );
             mnode.map(|node| {
                 node.buffer.take().map(|buf| {
                     match node.operation.get() {
@@ -120,10 +134,11 @@ impl<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a>> MuxI2C<'a, I, S> {
 
             if self.i2c_inflight.is_none() && self.smbus.is_some() {
                 // No I2C operation in flight, try SMBus next
-                let mnode = self
-                    .smbus_devices
-                    .iter()
-                    .find(|node| node.operation.get() != Op::Idle);
+                let mnode = arbitration::select(
+                    || self.smbus_devices.iter(),
+                    |node| node.operation.get() != Op::Idle,
+                    |node| &node.arbitration,
+                );
                 mnode.map(|node| {
                     node.buffer.take().map(|buf| match node.operation.get() {
                         Op::Write(len) => {
@@ -194,6 +209,16 @@ impl<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a>> DeferredCallClient for
     }
 }
 
+/// Update the health of a device with the result of a transaction. Only
+/// missing acknowledgements are the fault of the device.
+fn record_health(health: &DeviceHealth, status: Result<(), Error>) {
+    match status {
+        Ok(()) => health.succeeded(),
+        Err(Error::AddressNak | Error::DataNak) => health.failed(),
+        Err(_) => {}
+    }
+}
+
 #[derive(Copy, Clone, PartialEq)]
 enum Op {
     Idle,
@@ -209,6 +234,8 @@ pub struct I2CDevice<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a> = NoSMBu
     enabled: Cell<bool>,
     buffer: TakeCell<'static, [u8]>,
     operation: Cell<Op>,
+    arbitration: Arbitration,
+    health: DeviceHealth,
     next: ListLink<'a, I2CDevice<'a, I, S>>,
     client: OptionalCell<&'a dyn I2CClient>,
 }
@@ -221,6 +248,8 @@ impl<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a>> I2CDevice<'a, I, S> {
             enabled: Cell::new(false),
             buffer: TakeCell::empty(),
             operation: Cell::new(Op::Idle),
+            arbitration: Arbitration::new(),
+            health: DeviceHealth::new(),
             next: ListLink::empty(),
             client: OptionalCell::empty(),
         }
@@ -230,10 +259,50 @@ impl<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a>> I2CDevice<'a, I, S> {
         self.mux.i2c_devices.push_head(self);
         self.client.set(client);
     }
+
+    /// Set the priority of this device on the bus. Devices with a higher
+    /// priority are served first. The default priority is 0.
+    pub fn set_priority(&self, priority: u8) {
+        self.arbitration.set_priority(priority);
+    }
+
+    /// How often this device had to wait for other devices of the bus.
+    pub fn starvation_stats(&self) -> StarvationStats {
+        self.arbitration.starvation_stats()
+    }
+
+    /// Set the number of consecutive unacknowledged transactions after which
+    /// the device is offline. A threshold of 0 keeps the device online.
+    pub fn set_failure_threshold(&self, threshold: u8) {
+        self.health.set_failure_threshold(threshold);
+    }
+
+    /// Whether the device answers, see [`health`](super::health).
+    pub fn is_online(&self) -> bool {
+        self.health.is_online()
+    }
+
+    fn request(
+        &self,
+        data: &'static mut [u8],
+        operation: Op,
+    ) -> Result<(), (Error, &'static mut [u8])> {
+        if self.operation.get() != Op::Idle {
+            Err((Error::ArbitrationLost, data))
+        } else if !self.health.admit() {
+            Err((Error::AddressNak, data))
+        } else {
+            self.buffer.replace(data);
+            self.operation.set(operation);
+            self.mux.do_next_op();
+            Ok(())
+        }
+    }
 }
 
 impl<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a>> I2CClient for I2CDevice<'a, I, S> {
     fn command_complete(&self, buffer: &'static mut [u8], status: Result<(), Error>) {
+        record_health(&self.health, status);
         self.client.map(move |client| {
             client.command_complete(buffer, status);
         });
@@ -269,25 +338,11 @@ impl<'a, I: i2c::I2CMaster<'a>> i2c::I2CDevice for I2CDevice<'a, I> {
         write_len: usize,
         read_len: usize,
     ) -> Result<(), (Error, &'static mut [u8])> {
-        if self.operation.get() == Op::Idle {
-            self.buffer.replace(data);
-            self.operation.set(Op::WriteRead(write_len, read_len));
-            self.mux.do_next_op();
-            Ok(())
-        } else {
-            Err((Error::ArbitrationLost, data))
-        }
+        self.request(data, Op::WriteRead(write_len, read_len))
     }
 
     fn write(&self, data: &'static mut [u8], len: usize) -> Result<(), (Error, &'static mut [u8])> {
-        if self.operation.get() == Op::Idle {
-            self.buffer.replace(data);
-            self.operation.set(Op::Write(len));
-            self.mux.do_next_op();
-            Ok(())
-        } else {
-            Err((Error::ArbitrationLost, data))
-        }
+        self.request(data, Op::Write(len))
     }
 
     fn read(
@@ -295,14 +350,7 @@ impl<'a, I: i2c::I2CMaster<'a>> i2c::I2CDevice for I2CDevice<'a, I> {
         buffer: &'static mut [u8],
         len: usize,
     ) -> Result<(), (Error, &'static mut [u8])> {
-        if self.operation.get() == Op::Idle {
-            self.buffer.replace(buffer);
-            self.operation.set(Op::Read(len));
-            self.mux.do_next_op();
-            Ok(())
-        } else {
-            Err((Error::ArbitrationLost, buffer))
-        }
+        self.request(buffer, Op::Read(len))
     }
 }
 
@@ -312,6 +360,8 @@ pub struct SMBusDevice<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a>> {
     enabled: Cell<bool>,
     buffer: TakeCell<'static, [u8]>,
     operation: Cell<Op>,
+    arbitration: Arbitration,
+    health: DeviceHealth,
     next: ListLink<'a, SMBusDevice<'a, I, S>>,
     client: OptionalCell<&'a dyn I2CClient>,
 }
@@ -328,6 +378,8 @@ impl<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a>> SMBusDevice<'a, I, S> {
             enabled: Cell::new(false),
             buffer: TakeCell::empty(),
             operation: Cell::new(Op::Idle),
+            arbitration: Arbitration::new(),
+            health: DeviceHealth::new(),
             next: ListLink::empty(),
             client: OptionalCell::empty(),
         }
@@ -337,10 +389,50 @@ impl<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a>> SMBusDevice<'a, I, S> {
         self.mux.smbus_devices.push_head(self);
         self.client.set(client);
     }
+
+    /// Set the priority of this device on the bus. Devices with a higher
+    /// priority are served first. The default priority is 0.
+    pub fn set_priority(&self, priority: u8) {
+        self.arbitration.set_priority(priority);
+    }
+
+    /// How often this device had to wait for other devices of the bus.
+    pub fn starvation_stats(&self) -> StarvationStats {
+        self.arbitration.starvation_stats()
+    }
+
+    /// Set the number of consecutive unacknowledged transactions after which
+    /// the device is offline. A threshold of 0 keeps the device online.
+    pub fn set_failure_threshold(&self, threshold: u8) {
+        self.health.set_failure_threshold(threshold);
+    }
+
+    /// Whether the device answers, see [`health`](super::health).
+    pub fn is_online(&self) -> bool {
+        self.health.is_online()
+    }
+
+    fn request(
+        &self,
+        data: &'static mut [u8],
+        operation: Op,
+    ) -> Result<(), (Error, &'static mut [u8])> {
+        if self.operation.get() != Op::Idle {
+            Err((Error::ArbitrationLost, data))
+        } else if !self.health.admit() {
+            Err((Error::AddressNak, data))
+        } else {
+            self.buffer.replace(data);
+            self.operation.set(operation);
+            self.mux.do_next_op();
+            Ok(())
+        }
+    }
 }
 
 impl<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a>> I2CClient for SMBusDevice<'a, I, S> {
     fn command_complete(&self, buffer: &'static mut [u8], status: Result<(), Error>) {
+        record_health(&self.health, status);
         self.client.map(move |client| {
             client.command_complete(buffer, status);
         });
@@ -376,25 +468,11 @@ impl<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a>> i2c::I2CDevice for SMBu
         write_len: usize,
         read_len: usize,
     ) -> Result<(), (Error, &'static mut [u8])> {
-        if self.operation.get() == Op::Idle {
-            self.buffer.replace(data);
-            self.operation.set(Op::WriteRead(write_len, read_len));
-            self.mux.do_next_op();
-            Ok(())
-        } else {
-            Err((Error::ArbitrationLost, data))
-        }
+        self.request(data, Op::WriteRead(write_len, read_len))
     }
 
     fn write(&self, data: &'static mut [u8], len: usize) -> Result<(), (Error, &'static mut [u8])> {
-        if self.operation.get() == Op::Idle {
-            self.buffer.replace(data);
-            self.operation.set(Op::Write(len));
-            self.mux.do_next_op();
-            Ok(())
-        } else {
-            Err((Error::ArbitrationLost, data))
-        }
+        self.request(data, Op::Write(len))
     }
 
     fn read(
@@ -402,14 +480,7 @@ impl<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a>> i2c::I2CDevice for SMBu
         buffer: &'static mut [u8],
         len: usize,
     ) -> Result<(), (Error, &'static mut [u8])> {
-        if self.operation.get() == Op::Idle {
-            self.buffer.replace(buffer);
-            self.operation.set(Op::Read(len));
-            self.mux.do_next_op();
-            Ok(())
-        } else {
-            Err((Error::ArbitrationLost, buffer))
-        }
+        self.request(buffer, Op::Read(len))
     }
 }
 
@@ -422,14 +493,7 @@ impl<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a>> i2c::SMBusDevice
         write_len: usize,
         read_len: usize,
     ) -> Result<(), (Error, &'static mut [u8])> {
-        if self.operation.get() == Op::Idle {
-            self.buffer.replace(data);
-            self.operation.set(Op::WriteRead(write_len, read_len));
-            self.mux.do_next_op();
-            Ok(())
-        } else {
-            Err((Error::ArbitrationLost, data))
-        }
+        self.request(data, Op::WriteRead(write_len, read_len))
     }
 
     fn smbus_write(
@@ -437,14 +501,7 @@ impl<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a>> i2c::SMBusDevice
         data: &'static mut [u8],
         len: usize,
     ) -> Result<(), (Error, &'static mut [u8])> {
-        if self.operation.get() == Op::Idle {
-            self.buffer.replace(data);
-            self.operation.set(Op::Write(len));
-            self.mux.do_next_op();
-            Ok(())
-        } else {
-            Err((Error::ArbitrationLost, data))
-        }
+        self.request(data, Op::Write(len))
     }
 
     fn smbus_read(
@@ -452,13 +509,6 @@ impl<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a>> i2c::SMBusDevice
         buffer: &'static mut [u8],
         len: usize,
     ) -> Result<(), (Error, &'static mut [u8])> {
-        if self.operation.get() == Op::Idle {
-            self.buffer.replace(buffer);
-            self.operation.set(Op::Read(len));
-            self.mux.do_next_op();
-            Ok(())
-        } else {
-            Err((Error::ArbitrationLost, buffer))
-        }
+        self.request(buffer, Op::Read(len))
     }
 }
@@ -0,0 +1,447 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Virtualize the slave mode of an I2C peripheral.
+//!
+//! `MuxI2CSlave` shares the slave mode of a peripheral between several users,
+//! each of which responds on its own address as an `I2CSlaveDevice`. For
+//! instance, the `I2CMasterSlaveDriver` can respond on one address on behalf
+//! of userspace, while a capsule in the kernel responds on another one.
+//!
+//! The peripheral must implement [`I2CSlaveMultiAddress`], and the number of
+//! devices that have an address is limited by the number of addresses it can
+//! respond on at once. The buffers the devices provide with `write_receive()`
+//! and `read_send()` are kept by the mux until a master addresses the device,
+//! and only then passed to the peripheral. Until then, the peripheral
+//! stretches the clock.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let mux_i2c_slave = static_init!(
+//!     MuxI2CSlave<'static, nrf52840::i2c::TWI<'static>>,
+//!     MuxI2CSlave::new(&base_peripherals.twi1)
+//! );
+//! hil::i2c::I2CSlave::set_slave_client(&base_peripherals.twi1, mux_i2c_slave);
+//! let i2c_slave = static_init!(
+//!     I2CSlaveDevice<'static, nrf52840::i2c::TWI<'static>>,
+//!     I2CSlaveDevice::new(mux_i2c_slave)
+//! );
+//! i2c_slave.setup();
+//! ```
+
+use core::cell::Cell;
+
+use kernel::collections::list::{List, ListLink, ListNode};
+use kernel::hil::i2c::{
+    Error, I2CHwSlaveClient, I2CSlave, I2CSlaveMultiAddress, SlaveTransmissionType,
+};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+
+pub struct MuxI2CSlave<'a, S: I2CSlaveMultiAddress<'a>> {
+    i2c: &'a S,
+    devices: List<'a, I2CSlaveDevice<'a, S>>,
+    /// Number of address slots of the peripheral in use.
+    slots: Cell<usize>,
+    /// Device that the current transaction is addressed to.
+    inflight: OptionalCell<&'a I2CSlaveDevice<'a, S>>,
+    /// Transfer the device of the current transaction was asked a buffer for.
+    expected: Cell<Option<SlaveTransmissionType>>,
+}
+
+impl<'a, S: I2CSlaveMultiAddress<'a>> MuxI2CSlave<'a, S> {
+    pub const fn new(i2c: &'a S) -> Self {
+        Self {
+            i2c,
+            devices: List::new(),
+            slots: Cell::new(0),
+            inflight: OptionalCell::empty(),
+            expected: Cell::new(None),
+        }
+    }
+
+    /// Disable the peripheral once no device is enabled.
+    fn disable(&self) {
+        if !self.devices.iter().any(|device| device.enabled.get()) {
+            self.i2c.disable();
+        }
+    }
+
+    fn set_address(&self, device: &I2CSlaveDevice<'a, S>, addr: u8) -> Result<(), Error> {
+        if self
+            .devices
+            .iter()
+            .any(|other| !core::ptr::eq(other, device) && other.address.get() == Some(addr))
+        {
+            return Err(Error::Busy);
+        }
+        let slot = match device.slot.get() {
+            Some(slot) => slot,
+            None if self.slots.get() < self.i2c.address_count() => self.slots.get(),
+            None => return Err(Error::NotSupported),
+        };
+        self.i2c.set_address_at(slot, addr)?;
+        if device.slot.get().is_none() {
+            self.slots.set(slot + 1);
+            device.slot.set(Some(slot));
+        }
+        device.address.set(Some(addr));
+        Ok(())
+    }
+
+    /// The device the current transaction is addressed to.
+    fn addressed_device(&self) -> Option<&'a I2CSlaveDevice<'a, S>> {
+        let addr = self.i2c.matching_address()?;
+        self.devices
+            .iter()
+            .find(|device| device.address.get() == Some(addr))
+    }
+
+    /// Pass the buffer of `device` for `transfer` to the peripheral, or ask
+    /// the device for one if it has none.
+    fn expect(&self, transfer: SlaveTransmissionType) {
+        let Some(device) = self.addressed_device() else {
+            return;
+        };
+        self.inflight.set(device);
+        let (buffer, len) = device.buffer(transfer);
+        match buffer.take() {
+            Some(data) => {
+                if let Err((_, data)) = self.start(transfer, data, len.get()) {
+                    buffer.replace(data);
+                }
+            }
+            None => {
+                self.expected.set(Some(transfer));
+                device.client.map(|client| match transfer {
+                    SlaveTransmissionType::Write => client.write_expected(),
+                    SlaveTransmissionType::Read => client.read_expected(),
+                });
+            }
+        }
+    }
+
+    fn start(
+        &self,
+        transfer: SlaveTransmissionType,
+        data: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (Error, &'static mut [u8])> {
+        match transfer {
+            SlaveTransmissionType::Write => self.i2c.write_receive(data, len),
+            SlaveTransmissionType::Read => self.i2c.read_send(data, len),
+        }
+    }
+
+    /// Take the buffer `device` provides for `transfer`, or pass it to the
+    /// peripheral right away if the device is waited for.
+    fn provide(
+        &self,
+        device: &I2CSlaveDevice<'a, S>,
+        transfer: SlaveTransmissionType,
+        data: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (Error, &'static mut [u8])> {
+        let (buffer, buffer_len) = device.buffer(transfer);
+        let waited_for = self
+            .inflight
+            .get()
+            .is_some_and(|inflight| core::ptr::eq(inflight, device))
+            && self.expected.get() == Some(transfer);
+        if waited_for {
+            self.expected.set(None);
+            self.start(transfer, data, len)
+        } else if buffer.is_some() {
+            Err((Error::Busy, data))
+        } else {
+            buffer.replace(data);
+            buffer_len.set(len);
+            Ok(())
+        }
+    }
+}
+
+impl<'a, S: I2CSlaveMultiAddress<'a>> I2CHwSlaveClient for MuxI2CSlave<'a, S> {
+    fn command_complete(
+        &self,
+        buffer: &'static mut [u8],
+        length: usize,
+        transmission_type: SlaveTransmissionType,
+    ) {
+        self.expected.set(None);
+        let device = self.inflight.take().or_else(|| self.addressed_device());
+        device.map(|device| {
+            device.client.map(|client| {
+                client.command_complete(buffer, length, transmission_type);
+            });
+        });
+    }
+
+    fn read_expected(&self) {
+        self.expect(SlaveTransmissionType::Read);
+    }
+
+    fn write_expected(&self) {
+        self.expect(SlaveTransmissionType::Write);
+    }
+}
+
+pub struct I2CSlaveDevice<'a, S: I2CSlaveMultiAddress<'a>> {
+    mux: &'a MuxI2CSlave<'a, S>,
+    address: Cell<Option<u8>>,
+    slot: Cell<Option<usize>>,
+    enabled: Cell<bool>,
+    write_buffer: TakeCell<'static, [u8]>,
+    write_len: Cell<usize>,
+    read_buffer: TakeCell<'static, [u8]>,
+    read_len: Cell<usize>,
+    next: ListLink<'a, I2CSlaveDevice<'a, S>>,
+    client: OptionalCell<&'a dyn I2CHwSlaveClient>,
+}
+
+impl<'a, S: I2CSlaveMultiAddress<'a>> I2CSlaveDevice<'a, S> {
+    pub fn new(mux: &'a MuxI2CSlave<'a, S>) -> Self {
+        Self {
+            mux,
+            address: Cell::new(None),
+            slot: Cell::new(None),
+            enabled: Cell::new(false),
+            write_buffer: TakeCell::empty(),
+            write_len: Cell::new(0),
+            read_buffer: TakeCell::empty(),
+            read_len: Cell::new(0),
+            next: ListLink::empty(),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn setup(&'a self) {
+        self.mux.devices.push_head(self);
+    }
+
+    /// The buffer for `transfer` and the length to use of it.
+    fn buffer(&self, transfer: SlaveTransmissionType) -> (&TakeCell<'static, [u8]>, &Cell<usize>) {
+        match transfer {
+            SlaveTransmissionType::Write => (&self.write_buffer, &self.write_len),
+            SlaveTransmissionType::Read => (&self.read_buffer, &self.read_len),
+        }
+    }
+}
+
+impl<'a, S: I2CSlaveMultiAddress<'a>> ListNode<'a, I2CSlaveDevice<'a, S>>
+    for I2CSlaveDevice<'a, S>
+{
+    fn next(&'a self) -> &'a ListLink<'a, I2CSlaveDevice<'a, S>> {
+        &self.next
+    }
+}
+
+impl<'a, S: I2CSlaveMultiAddress<'a>> I2CSlave<'a> for I2CSlaveDevice<'a, S> {
+    fn set_slave_client(&self, client: &'a dyn I2CHwSlaveClient) {
+        self.client.set(client);
+    }
+
+    /// Enables the peripheral every time, as the master mode of a peripheral
+    /// in both modes may have been enabled in the meantime.
+    fn enable(&self) {
+        self.enabled.set(true);
+        self.mux.i2c.enable();
+    }
+
+    fn disable(&self) {
+        self.enabled.set(false);
+        self.mux.disable();
+    }
+
+    /// Returns `Busy` if another device responds on `addr`, and
+    /// `NotSupported` if the peripheral cannot respond on another address.
+    fn set_address(&self, addr: u8) -> Result<(), Error> {
+        self.mux.set_address(self, addr)
+    }
+
+    fn write_receive(
+        &self,
+        data: &'static mut [u8],
+        max_len: usize,
+    ) -> Result<(), (Error, &'static mut [u8])> {
+        self.mux
+            .provide(self, SlaveTransmissionType::Write, data, max_len)
+    }
+
+    fn read_send(
+        &self,
+        data: &'static mut [u8],
+        max_len: usize,
+    ) -> Result<(), (Error, &'static mut [u8])> {
+        self.mux
+            .provide(self, SlaveTransmissionType::Read, data, max_len)
+    }
+
+    fn listen(&self) {
+        self.mux.i2c.listen();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Slave hardware with two addresses that records the buffers it is
+    /// given.
+    struct FakeSlave {
+        addresses: [Cell<Option<u8>>; 2],
+        matching: Cell<Option<u8>>,
+        buffer: TakeCell<'static, [u8]>,
+    }
+
+    impl FakeSlave {
+        fn new() -> Self {
+            Self {
+                addresses: [Cell::new(None), Cell::new(None)],
+                matching: Cell::new(None),
+                buffer: TakeCell::empty(),
+            }
+        }
+    }
+
+    impl<'a> I2CSlave<'a> for FakeSlave {
+        fn set_slave_client(&self, _client: &'a dyn I2CHwSlaveClient) {}
+        fn enable(&self) {}
+        fn disable(&self) {}
+        fn set_address(&self, addr: u8) -> Result<(), Error> {
+            self.set_address_at(0, addr)
+        }
+        fn write_receive(
+            &self,
+            data: &'static mut [u8],
+            _max_len: usize,
+        ) -> Result<(), (Error, &'static mut [u8])> {
+            self.buffer.replace(data);
+            Ok(())
+        }
+        fn read_send(
+            &self,
+            data: &'static mut [u8],
+            _max_len: usize,
+        ) -> Result<(), (Error, &'static mut [u8])> {
+            self.buffer.replace(data);
+            Ok(())
+        }
+        fn listen(&self) {}
+    }
+
+    impl I2CSlaveMultiAddress<'_> for FakeSlave {
+        fn address_count(&self) -> usize {
+            2
+        }
+        fn set_address_at(&self, index: usize, addr: u8) -> Result<(), Error> {
+            self.addresses
+                .get(index)
+                .ok_or(Error::NotSupported)?
+                .set(Some(addr));
+            Ok(())
+        }
+        fn matching_address(&self) -> Option<u8> {
+            self.matching.get()
+        }
+    }
+
+    /// Records the completed transfers and requests of a device.
+    struct Client {
+        completed: Cell<Option<(usize, u8)>>,
+        write_expected: Cell<bool>,
+    }
+
+    impl I2CHwSlaveClient for Client {
+        fn command_complete(
+            &self,
+            buffer: &'static mut [u8],
+            length: usize,
+            _: SlaveTransmissionType,
+        ) {
+            self.completed.set(Some((length, buffer[0])));
+        }
+        fn read_expected(&self) {}
+        fn write_expected(&self) {
+            self.write_expected.set(true);
+        }
+    }
+
+    fn leak<T>(value: T) -> &'static T {
+        extern crate std;
+        std::boxed::Box::leak(std::boxed::Box::new(value))
+    }
+
+    fn leak_buf(byte: u8) -> &'static mut [u8] {
+        extern crate std;
+        std::boxed::Box::leak(std::boxed::Box::new([byte; 4]))
+    }
+
+    fn setup() -> (
+        &'static FakeSlave,
+        &'static MuxI2CSlave<'static, FakeSlave>,
+        [(&'static I2CSlaveDevice<'static, FakeSlave>, &'static Client); 2],
+    ) {
+        let slave = leak(FakeSlave::new());
+        let mux = leak(MuxI2CSlave::new(slave));
+        let devices = [0x10, 0x20].map(|addr| {
+            let device = leak(I2CSlaveDevice::new(mux));
+            device.setup();
+            let client = leak(Client {
+                completed: Cell::new(None),
+                write_expected: Cell::new(false),
+            });
+            device.set_slave_client(client);
+            assert_eq!(device.set_address(addr), Ok(()));
+            (device, client)
+        });
+        (slave, mux, devices)
+    }
+
+    #[test]
+    fn routes_by_address() {
+        let (slave, mux, [(first, first_client), (second, second_client)]) = setup();
+        assert_eq!(slave.addresses[0].get(), Some(0x10));
+        assert_eq!(slave.addresses[1].get(), Some(0x20));
+
+        assert!(first.write_receive(leak_buf(1), 4).is_ok());
+        assert!(second.write_receive(leak_buf(2), 4).is_ok());
+        assert!(slave.buffer.is_none());
+
+        // A write to the second device uses its buffer.
+        slave.matching.set(Some(0x20));
+        mux.write_expected();
+        let buffer = slave.buffer.take().unwrap();
+        assert_eq!(buffer[0], 2);
+        mux.command_complete(buffer, 3, SlaveTransmissionType::Write);
+        assert_eq!(second_client.completed.get(), Some((3, 2)));
+        assert_eq!(first_client.completed.get(), None);
+    }
+
+    #[test]
+    fn asks_the_addressed_device_for_a_buffer() {
+        let (slave, mux, [(first, first_client), (_, second_client)]) = setup();
+
+        slave.matching.set(Some(0x10));
+        mux.write_expected();
+        assert!(first_client.write_expected.get());
+        assert!(!second_client.write_expected.get());
+
+        // The buffer is passed on right away, as the master waits for it.
+        assert!(first.write_receive(leak_buf(7), 4).is_ok());
+        assert_eq!(slave.buffer.take().map(|buffer| buffer[0]), Some(7));
+    }
+
+    #[test]
+    fn limits_addresses() {
+        let (_, mux, [(first, _), _]) = setup();
+        assert_eq!(first.set_address(0x20), Err(Error::Busy));
+        let third = leak(I2CSlaveDevice::new(mux));
+        third.setup();
+        assert_eq!(third.set_address(0x30), Err(Error::NotSupported));
+        // A device with an address keeps its slot.
+        assert_eq!(first.set_address(0x11), Ok(()));
+    }
+}
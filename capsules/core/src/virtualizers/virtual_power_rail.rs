@@ -0,0 +1,410 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Share a power rail between its users.
+//!
+//! `MuxPowerRail` counts the users of a rail that need it on. The rail is
+//! turned on when the first user asks for power, and turned off once no user
+//! needs it anymore. Each user has a `VirtualPowerRail`, which also implements
+//! the `PowerRail` HIL:
+//!
+//! - `power_on` completes once the rail is on and settled. If the rail is
+//!   already on for another user, it completes right away.
+//! - `power_off` releases the rail for this user: it completes right away,
+//!   and the rail stays on while other users need it.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let mux_rail = static_init!(
+//!     capsules_core::virtualizers::virtual_power_rail::MuxPowerRail<'static>,
+//!     capsules_core::virtualizers::virtual_power_rail::MuxPowerRail::new(sensor_rail));
+//! sensor_rail.set_client(mux_rail);
+//! mux_rail.register();
+//!
+//! let temperature_rail = static_init!(
+//!     capsules_core::virtualizers::virtual_power_rail::VirtualPowerRail<'static>,
+//!     capsules_core::virtualizers::virtual_power_rail::VirtualPowerRail::new(mux_rail));
+//! temperature_rail.setup();
+//! ```
+
+use core::cell::Cell;
+
+use kernel::collections::list::{List, ListLink, ListNode};
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::power_rail::{PowerRail, PowerRailClient};
+use kernel::utilities::cells::OptionalCell;
+use kernel::ErrorCode;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum RailState {
+    Off,
+    TurningOn,
+    On,
+    TurningOff,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum UserState {
+    Off,
+    /// The user asked for power, and waits for the rail to be on.
+    PoweringOn,
+    On,
+}
+
+/// A callback to a user, delivered from a deferred call.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Callback {
+    PowerOn(Result<(), ErrorCode>),
+    PowerOff,
+}
+
+pub struct MuxPowerRail<'a> {
+    rail: &'a dyn PowerRail<'a>,
+    users: List<'a, VirtualPowerRail<'a>>,
+    state: Cell<RailState>,
+    deferred_call: DeferredCall,
+}
+
+impl<'a> MuxPowerRail<'a> {
+    pub fn new(rail: &'a dyn PowerRail<'a>) -> Self {
+        Self {
+            rail,
+            users: List::new(),
+            state: Cell::new(if rail.is_on() {
+                RailState::On
+            } else {
+                RailState::Off
+            }),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    /// The number of users that need the rail on, including the ones waiting
+    /// for it.
+    pub fn users_powered(&self) -> usize {
+        self.users
+            .iter()
+            .filter(|user| user.state.get() != UserState::Off)
+            .count()
+    }
+
+    /// Turn the rail on or off as the users need it, and complete the
+    /// requests that wait for the rail.
+    fn update(&self) {
+        let needed = self.users_powered() > 0;
+        match self.state.get() {
+            RailState::Off if needed => match self.rail.power_on() {
+                Ok(()) => self.state.set(RailState::TurningOn),
+                Err(ErrorCode::ALREADY) => {
+                    self.state.set(RailState::On);
+                    self.update();
+                }
+                Err(e) => self.complete_waiting(Err(e)),
+            },
+            RailState::On if needed => self.complete_waiting(Ok(())),
+            RailState::On => match self.rail.power_off() {
+                Ok(()) => self.state.set(RailState::TurningOff),
+                Err(ErrorCode::ALREADY) => self.state.set(RailState::Off),
+                // The rail stays on, and is turned off with the next user
+                // that releases it.
+                Err(_) => {}
+            },
+            RailState::Off | RailState::TurningOn | RailState::TurningOff => {}
+        }
+    }
+
+    fn complete_waiting(&self, result: Result<(), ErrorCode>) {
+        for user in self.users.iter() {
+            if user.state.get() == UserState::PoweringOn {
+                user.state.set(if result.is_ok() {
+                    UserState::On
+                } else {
+                    UserState::Off
+                });
+                user.pending.set(Some(Callback::PowerOn(result)));
+                self.deferred_call.set();
+            }
+        }
+    }
+}
+
+impl PowerRailClient for MuxPowerRail<'_> {
+    fn power_on_done(&self, result: Result<(), ErrorCode>) {
+        match result {
+            Ok(()) => {
+                self.state.set(RailState::On);
+                self.update();
+            }
+            Err(e) => {
+                self.state.set(RailState::Off);
+                self.complete_waiting(Err(e));
+            }
+        }
+    }
+
+    fn power_off_done(&self, result: Result<(), ErrorCode>) {
+        self.state.set(if result.is_ok() {
+            RailState::Off
+        } else {
+            RailState::On
+        });
+        // A user may have asked for power while the rail was turning off.
+        if result.is_ok() {
+            self.update();
+        }
+    }
+}
+
+impl DeferredCallClient for MuxPowerRail<'_> {
+    fn handle_deferred_call(&self) {
+        for user in self.users.iter() {
+            user.pending.take().map(|callback| {
+                user.client.map(|client| match callback {
+                    Callback::PowerOn(result) => client.power_on_done(result),
+                    Callback::PowerOff => client.power_off_done(Ok(())),
+                });
+            });
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+pub struct VirtualPowerRail<'a> {
+    mux: &'a MuxPowerRail<'a>,
+    next: ListLink<'a, VirtualPowerRail<'a>>,
+    client: OptionalCell<&'a dyn PowerRailClient>,
+    state: Cell<UserState>,
+    pending: Cell<Option<Callback>>,
+}
+
+impl<'a> VirtualPowerRail<'a> {
+    pub fn new(mux: &'a MuxPowerRail<'a>) -> Self {
+        Self {
+            mux,
+            next: ListLink::empty(),
+            client: OptionalCell::empty(),
+            state: Cell::new(UserState::Off),
+            pending: Cell::new(None),
+        }
+    }
+
+    pub fn setup(&'a self) {
+        self.mux.users.push_head(self);
+    }
+}
+
+impl<'a> ListNode<'a, VirtualPowerRail<'a>> for VirtualPowerRail<'a> {
+    fn next(&'a self) -> &'a ListLink<'a, VirtualPowerRail<'a>> {
+        &self.next
+    }
+}
+
+impl<'a> PowerRail<'a> for VirtualPowerRail<'a> {
+    fn set_client(&self, client: &'a dyn PowerRailClient) {
+        self.client.set(client);
+    }
+
+    fn name(&self) -> &'static str {
+        self.mux.rail.name()
+    }
+
+    fn power_on(&self) -> Result<(), ErrorCode> {
+        if self.pending.get().is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        match self.state.get() {
+            UserState::On => Err(ErrorCode::ALREADY),
+            UserState::PoweringOn => Err(ErrorCode::BUSY),
+            UserState::Off => {
+                self.state.set(UserState::PoweringOn);
+                self.mux.update();
+                Ok(())
+            }
+        }
+    }
+
+    fn power_off(&self) -> Result<(), ErrorCode> {
+        if self.pending.get().is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        match self.state.get() {
+            UserState::Off => Err(ErrorCode::ALREADY),
+            UserState::PoweringOn => Err(ErrorCode::BUSY),
+            UserState::On => {
+                self.state.set(UserState::Off);
+                self.pending.set(Some(Callback::PowerOff));
+                self.mux.deferred_call.set();
+                self.mux.update();
+                Ok(())
+            }
+        }
+    }
+
+    fn is_on(&self) -> bool {
+        self.state.get() == UserState::On
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A rail that completes when the test tells it to.
+    struct FakeRail<'a> {
+        on: Cell<bool>,
+        busy: Cell<bool>,
+        client: OptionalCell<&'a dyn PowerRailClient>,
+    }
+
+    impl FakeRail<'_> {
+        fn new() -> Self {
+            Self {
+                on: Cell::new(false),
+                busy: Cell::new(false),
+                client: OptionalCell::empty(),
+            }
+        }
+
+        fn settle(&self) {
+            self.busy.set(false);
+            self.client.map(|client| {
+                if self.on.get() {
+                    client.power_on_done(Ok(()))
+                } else {
+                    client.power_off_done(Ok(()))
+                }
+            });
+        }
+    }
+
+    impl<'a> PowerRail<'a> for FakeRail<'a> {
+        fn set_client(&self, client: &'a dyn PowerRailClient) {
+            self.client.set(client);
+        }
+
+        fn name(&self) -> &'static str {
+            "fake"
+        }
+
+        fn power_on(&self) -> Result<(), ErrorCode> {
+            assert!(!self.busy.get() && !self.on.get());
+            self.on.set(true);
+            self.busy.set(true);
+            Ok(())
+        }
+
+        fn power_off(&self) -> Result<(), ErrorCode> {
+            assert!(!self.busy.get() && self.on.get());
+            self.on.set(false);
+            self.busy.set(true);
+            Ok(())
+        }
+
+        fn is_on(&self) -> bool {
+            self.on.get() && !self.busy.get()
+        }
+    }
+
+    /// Counts the callbacks of a user.
+    struct Client {
+        powered_on: Cell<usize>,
+        powered_off: Cell<usize>,
+    }
+
+    impl Client {
+        fn new() -> Self {
+            Self {
+                powered_on: Cell::new(0),
+                powered_off: Cell::new(0),
+            }
+        }
+    }
+
+    impl PowerRailClient for Client {
+        fn power_on_done(&self, result: Result<(), ErrorCode>) {
+            assert_eq!(result, Ok(()));
+            self.powered_on.set(self.powered_on.get() + 1);
+        }
+
+        fn power_off_done(&self, result: Result<(), ErrorCode>) {
+            assert_eq!(result, Ok(()));
+            self.powered_off.set(self.powered_off.get() + 1);
+        }
+    }
+
+    #[test]
+    fn test_rail_is_on_while_a_user_needs_it() {
+        let rail = FakeRail::new();
+        let mux = MuxPowerRail::new(&rail);
+        rail.set_client(&mux);
+        let (first, second) = (VirtualPowerRail::new(&mux), VirtualPowerRail::new(&mux));
+        first.setup();
+        second.setup();
+        let (first_client, second_client) = (Client::new(), Client::new());
+        first.set_client(&first_client);
+        second.set_client(&second_client);
+
+        // Both users wait for the rail to settle.
+        assert_eq!(first.power_on(), Ok(()));
+        assert_eq!(second.power_on(), Ok(()));
+        assert_eq!(first.power_on(), Err(ErrorCode::BUSY));
+        rail.settle();
+        mux.handle_deferred_call();
+        assert_eq!(first_client.powered_on.get(), 1);
+        assert_eq!(second_client.powered_on.get(), 1);
+        assert!(first.is_on() && second.is_on());
+
+        // The rail stays on for the second user.
+        assert_eq!(first.power_off(), Ok(()));
+        mux.handle_deferred_call();
+        assert_eq!(first_client.powered_off.get(), 1);
+        assert!(rail.is_on());
+        assert_eq!(mux.users_powered(), 1);
+
+        // A user asking for power again gets it right away.
+        assert_eq!(first.power_on(), Ok(()));
+        mux.handle_deferred_call();
+        assert_eq!(first_client.powered_on.get(), 2);
+
+        assert_eq!(first.power_off(), Ok(()));
+        assert_eq!(second.power_off(), Ok(()));
+        mux.handle_deferred_call();
+        rail.settle();
+        assert!(!rail.is_on());
+        assert_eq!(second.power_off(), Err(ErrorCode::ALREADY));
+    }
+
+    #[test]
+    fn test_rail_turns_on_again_after_turning_off() {
+        let rail = FakeRail::new();
+        let mux = MuxPowerRail::new(&rail);
+        rail.set_client(&mux);
+        let user = VirtualPowerRail::new(&mux);
+        user.setup();
+        let client = Client::new();
+        user.set_client(&client);
+
+        assert_eq!(user.power_on(), Ok(()));
+        rail.settle();
+        mux.handle_deferred_call();
+        assert_eq!(user.power_off(), Ok(()));
+        mux.handle_deferred_call();
+
+        // The rail is turning off when the user asks for power again.
+        assert_eq!(user.power_on(), Ok(()));
+        rail.settle();
+        assert!(rail.busy.get() && rail.on.get());
+        rail.settle();
+        mux.handle_deferred_call();
+        assert_eq!(client.powered_on.get(), 2);
+        assert!(user.is_on());
+    }
+}
@@ -5,7 +5,9 @@
 //! Virtualize a PWM interface.
 //!
 //! `MuxPwm` provides shared access to a single PWM interface for multiple
-//! users. `PwmPinUser` provides access to a specific PWM pin.
+//! users. `PwmPinUser` provides access to a specific PWM pin. The pins of
+//! several users run at the same time if the PWM has enough channels, for
+//! example to drive several servos.
 //!
 //! Usage
 //! -----
@@ -24,6 +26,8 @@
 //! virtual_pwm_buzzer.add_to_mux();
 //! ```
 
+use core::cell::Cell;
+
 use kernel::collections::list::{List, ListLink, ListNode};
 use kernel::hil;
 use kernel::utilities::cells::OptionalCell;
@@ -32,70 +36,68 @@ use kernel::ErrorCode;
 pub struct MuxPwm<'a, P: hil::pwm::Pwm> {
     pwm: &'a P,
     devices: List<'a, PwmPinUser<'a, P>>,
-    inflight: OptionalCell<&'a PwmPinUser<'a, P>>,
+    /// How many pins the PWM can drive at the same time.
+    channels: usize,
 }
 
 impl<'a, P: hil::pwm::Pwm> MuxPwm<'a, P> {
+    /// Share a PWM that drives one pin at a time. A user that starts its pin
+    /// waits until the user whose pin is running stops it.
     pub const fn new(pwm: &'a P) -> MuxPwm<'a, P> {
+        MuxPwm::new_with_channels(pwm, 1)
+    }
+
+    /// Share a PWM whose channels drive up to `channels` pins at the same
+    /// time, such as the slices of the RP2040. Users start their pins
+    /// directly while fewer than `channels` pins are running.
+    pub const fn new_with_channels(pwm: &'a P, channels: usize) -> MuxPwm<'a, P> {
         MuxPwm {
             pwm,
             devices: List::new(),
-            inflight: OptionalCell::empty(),
+            channels,
         }
     }
 
-    /// If we are not currently doing anything, scan the list of devices for
-    /// one with an outstanding operation and run that.
+    /// Run the outstanding operations of the users whose pin is running, and
+    /// then start the pins of waiting users while channels are free.
     fn do_next_op(&self) {
-        if self.inflight.is_none() {
-            let mnode = self.devices.iter().find(|node| node.operation.is_some());
-            mnode.map(|node| {
-                let started = node.operation.take().is_some_and(|operation| {
-                    match operation {
-                        Operation::Simple {
-                            frequency_hz,
-                            duty_cycle,
-                        } => {
-                            let _ = self.pwm.start(&node.pin, frequency_hz, duty_cycle);
-                            true
-                        }
-                        Operation::Stop => {
-                            // Can't stop if nothing is running
-                            false
-                        }
-                    }
-                });
-                if started {
-                    self.inflight.set(node);
-                } else {
-                    // Keep looking for something to do.
-                    self.do_next_op();
+        for node in self.devices.iter().filter(|node| node.running.get()) {
+            node.operation.take().map(|operation| match operation {
+                Operation::Simple {
+                    frequency_hz,
+                    duty_cycle,
+                } => {
+                    // Changed some parameter.
+                    let _ = self.pwm.start(&node.pin, frequency_hz, duty_cycle);
+                }
+                Operation::Stop => {
+                    let _ = self.pwm.stop(&node.pin);
+                    node.running.set(false);
                 }
-            });
-        } else {
-            // We are running so we do whatever the inflight user wants, if
-            // there is some command there.
-            self.inflight.map(|node| {
-                node.operation.take().map(|operation| {
-                    match operation {
-                        Operation::Simple {
-                            frequency_hz,
-                            duty_cycle,
-                        } => {
-                            // Changed some parameter.
-                            let _ = self.pwm.start(&node.pin, frequency_hz, duty_cycle);
-                        }
-                        Operation::Stop => {
-                            // Ok we got a stop.
-                            let _ = self.pwm.stop(&node.pin);
-                            self.inflight.clear();
-                        }
-                    }
-                    // Recurse in case there is more to do.
-                    self.do_next_op();
-                });
             });
         }
+
+        let mut running = self
+            .devices
+            .iter()
+            .filter(|node| node.running.get())
+            .count();
+        for node in self.devices.iter().filter(|node| !node.running.get()) {
+            match node.operation.get() {
+                Some(Operation::Simple {
+                    frequency_hz,
+                    duty_cycle,
+                }) if running < self.channels => {
+                    node.operation.clear();
+                    let _ = self.pwm.start(&node.pin, frequency_hz, duty_cycle);
+                    node.running.set(true);
+                    running += 1;
+                }
+                // Can't stop if nothing is running.
+                Some(Operation::Stop) => node.operation.clear(),
+                _ => {}
+            }
+        }
     }
 }
 
@@ -112,6 +114,8 @@ pub struct PwmPinUser<'a, P: hil::pwm::Pwm> {
     mux: &'a MuxPwm<'a, P>,
     pin: P::Pin,
     operation: OptionalCell<Operation>,
+    /// Whether the pin is started on the PWM.
+    running: Cell<bool>,
     next: ListLink<'a, PwmPinUser<'a, P>>,
 }
 
@@ -121,6 +125,7 @@ impl<'a, P: hil::pwm::Pwm> PwmPinUser<'a, P> {
             mux,
             pin,
             operation: OptionalCell::empty(),
+            running: Cell::new(false),
             next: ListLink::empty(),
         }
     }
@@ -160,3 +165,97 @@ impl<P: hil::pwm::Pwm> hil::pwm::PwmPin for PwmPinUser<'_, P> {
         self.mux.pwm.get_maximum_duty_cycle()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use kernel::hil::pwm::PwmPin;
+
+    /// A PWM that records the duty cycle of each of its four pins, or `None`
+    /// if the pin is stopped.
+    struct FakePwm {
+        pins: [Cell<Option<usize>>; 4],
+    }
+
+    impl FakePwm {
+        fn new() -> Self {
+            Self {
+                pins: [const { Cell::new(None) }; 4],
+            }
+        }
+
+        fn duty_cycles(&self) -> [Option<usize>; 4] {
+            [0, 1, 2, 3].map(|pin| self.pins[pin].get())
+        }
+    }
+
+    impl hil::pwm::Pwm for FakePwm {
+        type Pin = usize;
+
+        fn start(
+            &self,
+            pin: &usize,
+            _frequency_hz: usize,
+            duty_cycle: usize,
+        ) -> Result<(), ErrorCode> {
+            self.pins[*pin].set(Some(duty_cycle));
+            Ok(())
+        }
+
+        fn stop(&self, pin: &usize) -> Result<(), ErrorCode> {
+            self.pins[*pin].set(None);
+            Ok(())
+        }
+
+        fn get_maximum_frequency_hz(&self) -> usize {
+            1000
+        }
+
+        fn get_maximum_duty_cycle(&self) -> usize {
+            1000
+        }
+    }
+
+    #[test]
+    fn one_channel_waits_for_stop() {
+        let pwm = FakePwm::new();
+        let mux = MuxPwm::new(&pwm);
+        let first = PwmPinUser::new(&mux, 0);
+        let second = PwmPinUser::new(&mux, 1);
+        first.add_to_mux();
+        second.add_to_mux();
+
+        first.start(50, 10).unwrap();
+        second.start(50, 20).unwrap();
+        assert_eq!(pwm.duty_cycles(), [Some(10), None, None, None]);
+
+        first.start(50, 15).unwrap();
+        assert_eq!(pwm.duty_cycles(), [Some(15), None, None, None]);
+
+        first.stop().unwrap();
+        assert_eq!(pwm.duty_cycles(), [None, Some(20), None, None]);
+    }
+
+    #[test]
+    fn channels_run_at_the_same_time() {
+        let pwm = FakePwm::new();
+        let mux = MuxPwm::new_with_channels(&pwm, 2);
+        let users = [0, 1, 2].map(|pin| PwmPinUser::new(&mux, pin));
+        for user in users.iter() {
+            user.add_to_mux();
+        }
+
+        users[0].start(50, 10).unwrap();
+        users[1].start(50, 20).unwrap();
+        users[2].start(50, 30).unwrap();
+        assert_eq!(pwm.duty_cycles(), [Some(10), Some(20), None, None]);
+
+        users[1].stop().unwrap();
+        assert_eq!(pwm.duty_cycles(), [Some(10), None, Some(30), None]);
+
+        // Stopping a pin that is not running does nothing.
+        users[1].stop().unwrap();
+        users[0].start(50, 40).unwrap();
+        assert_eq!(pwm.duty_cycles(), [Some(40), None, Some(30), None]);
+    }
+}
@@ -3,6 +3,17 @@
 // Copyright Tock Contributors 2022.
 
 //! Virtualize a SPI master bus to enable multiple users of the SPI bus.
+//!
+//! When the bus is free, the pending device with the highest priority is
+//! served next (see [`arbitration`](super::arbitration)), so that a
+//! latency-sensitive device such as a radio can be given precedence over a
+//! flash chip with [`VirtualSpiMasterDevice::set_priority`].
+//!
+//! A device can also be given preemption points with
+//! [`VirtualSpiMasterDevice::set_max_chunk_len`]: its long transactions are
+//! then split into chunks, and other devices can be served between two chunks.
+//! The chip select is released between chunks, so this is only possible for
+//! peripherals that accept it, for example when streaming data to a display.
 
 use core::cell::Cell;
 use kernel::collections::list::{List, ListLink, ListNode};
@@ -13,6 +24,8 @@ use kernel::utilities::cells::{MapCell, OptionalCell};
 use kernel::utilities::leasable_buffer::SubSliceMut;
 use kernel::ErrorCode;
 
+use super::arbitration::{self, Arbitration, StarvationStats};
+
 /// The Mux struct manages multiple Spi clients. Each client may have
 /// at most one outstanding Spi request.
 pub struct MuxSpiMaster<'a, Spi: hil::spi::SpiMaster<'a>> {
@@ -30,6 +43,17 @@ impl<'a, Spi: hil::spi::SpiMaster<'a>> hil::spi::SpiMasterClient for MuxSpiMaste
         status: Result<usize, ErrorCode>,
     ) {
         let dev = self.inflight.take();
+        if let Some(device) = dev.filter(|device| device.next_chunk_pending(status)) {
+            // The transaction continues with its next chunk, which is
+            // scheduled like any other operation.
+            device.txbuffer.replace(write_buffer);
+            if let Some(buffer) = read_buffer {
+                device.rxbuffer.replace(buffer);
+            }
+            device.operation.set(Op::ReadWriteBytes);
+            self.do_next_op();
+            return;
+        }
         // Need to do next op before signaling so we get some kind of
         // sharing. Otherwise a call to read_write in the callback
         // can allow this client to never relinquish the device.
@@ -53,10 +77,11 @@ impl<'a, Spi: hil::spi::SpiMaster<'a>> MuxSpiMaster<'a, Spi> {
 
     fn do_next_op(&self) {
         if self.inflight.is_none() {
-            let mnode = self
-                .devices
-                .iter()
-                .find(|node| node.operation.get() != Op::Idle);
+            let mnode = arbitration::select(
+                || self.devices.iter(),
+                |node| node.operation.get() != Op::Idle,
+                |node| &node.arbitration,
+            );
             mnode.map(|node| {
                 let configuration = node.configuration.get();
                 let cs = configuration.chip_select;
@@ -70,16 +95,20 @@ impl<'a, Spi: hil::spi::SpiMaster<'a>> MuxSpiMaster<'a, Spi> {
                         // Only async operations want to block by setting
                         // the devices as inflight.
                         self.inflight.set(node);
-                        node.txbuffer.take().map(|txbuffer| {
+                        node.txbuffer.take().map(|mut txbuffer| {
+                            let mut rxbuffer = node.rxbuffer.take();
+                            node.slice_chunk(&mut txbuffer, rxbuffer.as_mut());
                             let rresult = self.spi.set_rate(configuration.rate);
                             let polresult = self.spi.set_polarity(configuration.polarity);
                             let phaseresult = self.spi.set_phase(configuration.phase);
                             if rresult.is_err() || polresult.is_err() || phaseresult.is_err() {
                                 node.txbuffer.replace(txbuffer);
+                                if let Some(buffer) = rxbuffer {
+                                    node.rxbuffer.replace(buffer);
+                                }
                                 node.operation.set(Op::ReadWriteDone(Err(ErrorCode::INVAL)));
                                 self.do_next_op_async();
                             } else {
-                                let rxbuffer = node.rxbuffer.take();
                                 if let Err((e, write_buffer, read_buffer)) =
                                     self.spi.read_write_bytes(txbuffer, rxbuffer)
                                 {
@@ -147,6 +176,26 @@ enum Op {
     ReadWriteDone(Result<usize, ErrorCode>),
 }
 
+// Progress of a transaction which is split into chunks. The windows are the
+// bounds of the buffers passed by the client, within the whole buffers.
+#[derive(Copy, Clone)]
+struct Chunks {
+    tx_window: (usize, usize),
+    rx_window: Option<(usize, usize)>,
+    len: usize,
+    done: usize,
+}
+
+// Bounds of the accessible part of `buffer` within the whole buffer. This
+// resets `buffer`.
+fn window(buffer: &mut SubSliceMut<'static, u8>) -> (usize, usize) {
+    let start = buffer.as_ptr() as usize;
+    let len = buffer.len();
+    buffer.reset();
+    let offset = start - buffer.as_ptr() as usize;
+    (offset, offset + len)
+}
+
 // Structure used to store the SPI configuration of a client/virtual device,
 // so it can restored on each operation.
 struct SpiConfiguration<'a, Spi: hil::spi::SpiMaster<'a>> {
@@ -172,6 +221,9 @@ pub struct VirtualSpiMasterDevice<'a, Spi: hil::spi::SpiMaster<'a>> {
     txbuffer: MapCell<SubSliceMut<'static, u8>>,
     rxbuffer: MapCell<SubSliceMut<'static, u8>>,
     operation: Cell<Op>,
+    arbitration: Arbitration,
+    max_chunk_len: Cell<Option<usize>>,
+    chunks: Cell<Option<Chunks>>,
     next: ListLink<'a, VirtualSpiMasterDevice<'a, Spi>>,
     client: OptionalCell<&'a dyn hil::spi::SpiMasterClient>,
 }
@@ -192,6 +244,9 @@ impl<'a, Spi: hil::spi::SpiMaster<'a>> VirtualSpiMasterDevice<'a, Spi> {
             txbuffer: MapCell::empty(),
             rxbuffer: MapCell::empty(),
             operation: Cell::new(Op::Idle),
+            arbitration: Arbitration::new(),
+            max_chunk_len: Cell::new(None),
+            chunks: Cell::new(None),
             next: ListLink::empty(),
             client: OptionalCell::empty(),
         }
@@ -201,6 +256,93 @@ impl<'a, Spi: hil::spi::SpiMaster<'a>> VirtualSpiMasterDevice<'a, Spi> {
     pub fn setup(&'a self) {
         self.mux.devices.push_head(self);
     }
+
+    /// Set the priority of this device on the bus. Devices with a higher
+    /// priority are served first. The default priority is 0.
+    pub fn set_priority(&self, priority: u8) {
+        self.arbitration.set_priority(priority);
+    }
+
+    /// Split the transactions of this device that are longer than `len`
+    /// bytes into chunks of at most `len` bytes, so that other devices can be
+    /// served between two chunks. `None`, the default, never splits
+    /// transactions.
+    ///
+    /// The chip select is released between two chunks, so this must only be
+    /// used for peripherals that accept it. The client still gets a single
+    /// callback per transaction.
+    pub fn set_max_chunk_len(&self, len: Option<usize>) -> Result<(), ErrorCode> {
+        if len == Some(0) {
+            Err(ErrorCode::INVAL)
+        } else if self.operation.get() != Op::Idle {
+            Err(ErrorCode::BUSY)
+        } else {
+            self.max_chunk_len.set(len);
+            Ok(())
+        }
+    }
+
+    /// How often this device had to wait for other devices of the bus.
+    pub fn starvation_stats(&self) -> StarvationStats {
+        self.arbitration.starvation_stats()
+    }
+
+    // Slice the buffers to the next chunk of the transaction, if the
+    // transaction is split into chunks.
+    fn slice_chunk(
+        &self,
+        txbuffer: &mut SubSliceMut<'static, u8>,
+        mut rxbuffer: Option<&mut SubSliceMut<'static, u8>>,
+    ) {
+        let chunks = match self.chunks.get() {
+            Some(chunks) => chunks,
+            None => {
+                let Some(max_chunk_len) = self.max_chunk_len.get() else {
+                    return;
+                };
+                let len = rxbuffer
+                    .as_ref()
+                    .map_or(txbuffer.len(), |rx| txbuffer.len().min(rx.len()));
+                if len <= max_chunk_len {
+                    return;
+                }
+                Chunks {
+                    tx_window: window(txbuffer),
+                    rx_window: rxbuffer.as_mut().map(|rx| window(rx)),
+                    len,
+                    done: 0,
+                }
+            }
+        };
+        self.chunks.set(Some(chunks));
+
+        let chunk_len = self
+            .max_chunk_len
+            .get()
+            .unwrap_or(chunks.len)
+            .min(chunks.len - chunks.done);
+        let tx_start = chunks.tx_window.0 + chunks.done;
+        txbuffer.reset();
+        txbuffer.slice(tx_start..tx_start + chunk_len);
+        if let (Some(rx), Some((rx_start, _))) = (rxbuffer, chunks.rx_window) {
+            let rx_start = rx_start + chunks.done;
+            rx.reset();
+            rx.slice(rx_start..rx_start + chunk_len);
+        }
+    }
+
+    // Record the completion of a chunk, and return whether the transaction
+    // has more chunks.
+    fn next_chunk_pending(&self, status: Result<usize, ErrorCode>) -> bool {
+        match (self.chunks.get(), status) {
+            (Some(mut chunks), Ok(len)) if chunks.done + len < chunks.len && len > 0 => {
+                chunks.done += len;
+                self.chunks.set(Some(chunks));
+                true
+            }
+            _ => false,
+        }
+    }
 }
 
 impl<'a, Spi: hil::spi::SpiMaster<'a>> hil::spi::SpiMasterClient
@@ -208,10 +350,21 @@ impl<'a, Spi: hil::spi::SpiMaster<'a>> hil::spi::SpiMasterClient
 {
     fn read_write_done(
         &self,
-        write_buffer: SubSliceMut<'static, u8>,
-        read_buffer: Option<SubSliceMut<'static, u8>>,
-        status: Result<usize, ErrorCode>,
+        mut write_buffer: SubSliceMut<'static, u8>,
+        mut read_buffer: Option<SubSliceMut<'static, u8>>,
+        mut status: Result<usize, ErrorCode>,
     ) {
+        if let Some(chunks) = self.chunks.take() {
+            // Give the buffers back with the bounds the client passed them
+            // with, and report the length of the whole transaction.
+            write_buffer.reset();
+            write_buffer.slice(chunks.tx_window.0..chunks.tx_window.1);
+            if let (Some(rx), Some((start, end))) = (read_buffer.as_mut(), chunks.rx_window) {
+                rx.reset();
+                rx.slice(start..end);
+            }
+            status = status.map(|len| chunks.done + len);
+        }
         self.client.map(move |client| {
             client.read_write_done(write_buffer, read_buffer, status);
         });
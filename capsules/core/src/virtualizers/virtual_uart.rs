@@ -14,6 +14,13 @@
 //! `MuxUart` provides shared access to a single UART bus for multiple users.
 //! `UartDevice` provides access for a single client.
 //!
+//! Any device can reconfigure the UART at runtime, for example to change its
+//! baud rate, which changes it for all devices. The mux applies the new
+//! configuration once the transmission in flight is finished and the
+//! reception in progress is stopped, and holds back new transmissions until
+//! then. Stopping the reception gives the bytes received so far to the
+//! devices, whose receptions then continue with the new configuration.
+//!
 //! Usage
 //! -----
 //!
@@ -58,7 +65,9 @@ pub const RX_BUF_LEN: usize = 64;
 
 pub struct MuxUart<'a> {
     uart: &'a dyn uart::Uart<'a>,
-    speed: u32,
+    parameters: Cell<uart::Parameters>,
+    /// Configuration waiting for the UART to be idle.
+    pending_parameters: OptionalCell<uart::Parameters>,
     devices: List<'a, UartDevice<'a>>,
     inflight: OptionalCell<&'a UartDevice<'a>>,
     buffer: TakeCell<'static, [u8]>,
@@ -178,6 +187,14 @@ impl uart::ReceiveClient for MuxUart<'_> {
         // Clear the flag that we are in this handler.
         self.completing_read.set(false);
 
+        // The reception may have been stopped to reconfigure the UART. Apply
+        // the configuration before receiving again, and send the
+        // transmissions held back for it.
+        if self.pending_parameters.is_some() {
+            let _ = self.try_reconfigure();
+            self.do_next_op();
+        }
+
         // If either our outstanding receive was longer than the number of bytes
         // we just received, or if a new receive has been started, we start the
         // underlying UART receive again.
@@ -214,7 +231,14 @@ impl<'a> MuxUart<'a> {
     pub fn new(uart: &'a dyn uart::Uart<'a>, buffer: &'static mut [u8], speed: u32) -> MuxUart<'a> {
         MuxUart {
             uart,
-            speed,
+            parameters: Cell::new(uart::Parameters {
+                baud_rate: speed,
+                width: uart::Width::Eight,
+                stop_bits: uart::StopBits::One,
+                parity: uart::Parity::None,
+                hw_flow_control: false,
+            }),
+            pending_parameters: OptionalCell::empty(),
             devices: List::new(),
             inflight: OptionalCell::empty(),
             buffer: TakeCell::new(buffer),
@@ -224,16 +248,72 @@ impl<'a> MuxUart<'a> {
     }
 
     pub fn initialize(&self) {
-        let _ = self.uart.configure(uart::Parameters {
-            baud_rate: self.speed,
-            width: uart::Width::Eight,
-            stop_bits: uart::StopBits::One,
-            parity: uart::Parity::None,
-            hw_flow_control: false,
-        });
+        let _ = self.uart.configure(self.parameters.get());
+    }
+
+    /// The configuration of the UART. A reconfiguration that waits for the
+    /// UART to be idle is not included.
+    pub fn parameters(&self) -> uart::Parameters {
+        self.parameters.get()
+    }
+
+    /// Reconfigure the UART for all devices.
+    ///
+    /// If the UART is idle, the configuration is applied now and the result
+    /// is that of the UART. Otherwise, it returns `Ok(())` and the
+    /// configuration is applied once the transmission in flight is finished
+    /// and the reception in progress is stopped. If the UART rejects it then,
+    /// it keeps the previous configuration.
+    ///
+    /// ### Return values
+    ///
+    /// - `Err(INVAL)`: The baud rate is 0.
+    /// - `Err(BUSY)`: Another reconfiguration is waiting for the UART.
+    pub fn reconfigure(&self, params: uart::Parameters) -> Result<(), ErrorCode> {
+        if params.baud_rate == 0 {
+            return Err(ErrorCode::INVAL);
+        }
+        if self.pending_parameters.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.pending_parameters.set(params);
+        self.try_reconfigure()
+    }
+
+    /// Apply the pending configuration if the UART is idle. Otherwise, stop
+    /// the reception so that `received_buffer()` applies it, or wait for the
+    /// transmission in flight to finish.
+    fn try_reconfigure(&self) -> Result<(), ErrorCode> {
+        if self.inflight.is_some() {
+            return Ok(());
+        }
+        if self.buffer.is_none() {
+            // A reception is in progress. If we are in the midst of
+            // completing it, `received_buffer()` applies the configuration
+            // when done.
+            if !self.completing_read.get() {
+                let _ = self.uart.receive_abort();
+            }
+            return Ok(());
+        }
+        match self.pending_parameters.take() {
+            Some(params) => {
+                self.uart.configure(params)?;
+                self.parameters.set(params);
+                Ok(())
+            }
+            None => Ok(()),
+        }
     }
 
     fn do_next_op(&self) {
+        if self.pending_parameters.is_some() {
+            // Hold back transmissions until the UART is reconfigured.
+            let _ = self.try_reconfigure();
+            if self.pending_parameters.is_some() {
+                return;
+            }
+        }
         if self.inflight.is_none() {
             let mnode = self.devices.iter().find(|node| node.operation.is_some());
             mnode.map(|node| {
@@ -410,6 +490,14 @@ impl uart::ReceiveClient for UartDevice<'_> {
     }
 }
 
+impl uart::Configure for UartDevice<'_> {
+    /// Reconfigure the shared UART, for all devices of the mux, with
+    /// [`MuxUart::reconfigure`].
+    fn configure(&self, params: uart::Parameters) -> Result<(), ErrorCode> {
+        self.mux.reconfigure(params)
+    }
+}
+
 impl<'a> ListNode<'a, UartDevice<'a>> for UartDevice<'a> {
     fn next(&'a self) -> &'a ListLink<'a, UartDevice<'a>> {
         &self.next
@@ -0,0 +1,341 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Install new applications at runtime.
+//!
+//! This capsule lets a process install a new TBF binary without reflashing
+//! the board. The process is responsible for getting the binary (for example
+//! by receiving it over the console or a USB CDC link) and passes it to the
+//! kernel in chunks. The kernel writes the binary into the app flash region,
+//! validates it and starts it as a new process.
+//!
+//! Installing an application can replace any other application, so the board
+//! chooses which processes may do it with an [`InstallPolicy`], for example
+//! [`ShortIdInstallPolicy`].
+//!
+//! Only one installation may be in progress at a time. The process that
+//! calls `setup` owns the installation until it calls `load` or `abort`, or
+//! until it exits.
+//!
+//! The capsule also implements `DynamicProcessLoading` for kernel users, like
+//! the `receive` command of the process console, which installs a binary sent
+//! over the console. Their requests are refused with `BUSY` while a process
+//! installs a binary, and the other way around.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let allowed = static_init!(
+//!     [kernel::process::ShortId; 1],
+//!     [kernel::process::ShortId::Fixed(core::num::NonZeroU32::new(0x1234).unwrap())]
+//! );
+//! let policy = static_init!(
+//!     capsules_extra::app_loader::ShortIdInstallPolicy,
+//!     capsules_extra::app_loader::ShortIdInstallPolicy::new(allowed)
+//! );
+//! let app_loader = components::app_loader::AppLoaderComponent::new(
+//!     board_kernel,
+//!     capsules_extra::app_loader::DRIVER_NUM,
+//!     dynamic_loader,
+//!     policy,
+//! )
+//! .finalize(components::app_loader_component_static!());
+//! ```
+
+use core::cell::Cell;
+use core::cmp;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::process::{
+    DynamicProcessLoading, DynamicProcessLoadingClient, ProcessLoadError, ShortId,
+};
+use kernel::processbuffer::ReadableProcessBuffer;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::AppLoader as usize;
+
+/// Size of the buffer used to pass data to the loader.
+pub const BUF_LEN: usize = 512;
+
+/// IDs for subscribed upcalls.
+mod upcall {
+    /// Write of a chunk of the binary finished. Called with the status and the
+    /// number of bytes written.
+    pub const WRITE_DONE: usize = 0;
+    /// Loading the binary as a new process finished. Called with the status.
+    pub const LOAD_DONE: usize = 1;
+    /// Number of upcalls.
+    pub const COUNT: u8 = 2;
+}
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    /// Chunk of the binary to write.
+    pub const BUFFER: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Which processes may install new applications.
+pub trait InstallPolicy {
+    /// Whether the process `processid` may install new applications.
+    fn may_install(&self, processid: ProcessId) -> bool;
+}
+
+/// Lets only the applications with one of the listed `ShortId`s install new
+/// applications.
+pub struct ShortIdInstallPolicy {
+    allowed: &'static [ShortId],
+}
+
+impl ShortIdInstallPolicy {
+    pub fn new(allowed: &'static [ShortId]) -> Self {
+        Self { allowed }
+    }
+}
+
+impl InstallPolicy for ShortIdInstallPolicy {
+    fn may_install(&self, processid: ProcessId) -> bool {
+        let short_id = processid.short_app_id();
+        // A locally unique `ShortId` does not identify an application.
+        short_id != ShortId::LocallyUnique && self.allowed.contains(&short_id)
+    }
+}
+
+#[derive(Default)]
+pub struct App;
+
+pub struct AppLoader<'a> {
+    loader: &'a dyn DynamicProcessLoading<'a>,
+    policy: &'a dyn InstallPolicy,
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<0>,
+    >,
+    current_process: OptionalCell<ProcessId>,
+    buffer: TakeCell<'static, [u8]>,
+    /// Whether a kernel user of the loader is installing a binary.
+    kernel_installing: Cell<bool>,
+    /// Client of the loader for installations by kernel users.
+    client: OptionalCell<&'a dyn DynamicProcessLoadingClient>,
+}
+
+impl<'a> AppLoader<'a> {
+    pub fn new(
+        loader: &'a dyn DynamicProcessLoading<'a>,
+        policy: &'a dyn InstallPolicy,
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<0>,
+        >,
+        buffer: &'static mut [u8],
+    ) -> AppLoader<'a> {
+        AppLoader {
+            loader,
+            policy,
+            apps: grant,
+            current_process: OptionalCell::empty(),
+            buffer: TakeCell::new(buffer),
+            kernel_installing: Cell::new(false),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    /// The process installing a binary, if it still exists. If it exited,
+    /// its partial installation is dropped.
+    fn installing_process(&self) -> Option<ProcessId> {
+        let owner = self.current_process.get()?;
+        if self.apps.enter(owner, |_, _| {}).is_ok() {
+            Some(owner)
+        } else {
+            let _ = self.loader.abort();
+            self.current_process.clear();
+            None
+        }
+    }
+
+    /// Check that `processid` may use the installation in progress, claiming
+    /// it if nobody owns it.
+    fn claim(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        if !self.policy.may_install(processid) {
+            return Err(ErrorCode::NOSUPPORT);
+        }
+        match self.installing_process() {
+            Some(owner) if owner == processid => Ok(()),
+            Some(_) => Err(ErrorCode::BUSY),
+            None if self.kernel_installing.get() => Err(ErrorCode::BUSY),
+            None => {
+                self.current_process.set(processid);
+                Ok(())
+            }
+        }
+    }
+
+    fn write(&self, offset: usize, length: usize, processid: ProcessId) -> Result<(), ErrorCode> {
+        self.apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readonly_processbuffer(ro_allow::BUFFER)
+                    .and_then(|buffer| {
+                        buffer.enter(|app_buffer| {
+                            self.buffer.map_or(Err(ErrorCode::RESERVE), |buffer| {
+                                let length =
+                                    cmp::min(cmp::min(length, buffer.len()), app_buffer.len());
+                                app_buffer[..length].copy_to_slice(&mut buffer[..length]);
+                                self.loader.write(&buffer[..length], offset)
+                            })
+                        })
+                    })
+                    .unwrap_or(Err(ErrorCode::RESERVE))
+            })
+            .unwrap_or_else(|err| Err(err.into()))
+    }
+}
+
+impl<'a> DynamicProcessLoading<'a> for AppLoader<'a> {
+    fn set_client(&self, client: &'a dyn DynamicProcessLoadingClient) {
+        self.client.set(client);
+    }
+
+    fn setup(&self, app_length: usize) -> Result<usize, ErrorCode> {
+        if self.installing_process().is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        let address = self.loader.setup(app_length)?;
+        self.kernel_installing.set(true);
+        Ok(address)
+    }
+
+    fn write(&self, data: &[u8], offset: usize) -> Result<(), ErrorCode> {
+        if self.installing_process().is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.loader.write(data, offset)
+    }
+
+    fn load(&self) -> Result<(), ErrorCode> {
+        if self.installing_process().is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.loader.load()
+    }
+
+    fn abort(&self) -> Result<(), ErrorCode> {
+        if self.installing_process().is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.loader
+            .abort()
+            .inspect(|()| self.kernel_installing.set(false))
+    }
+}
+
+impl DynamicProcessLoadingClient for AppLoader<'_> {
+    fn write_done(&self, result: Result<(), ErrorCode>, length: usize) {
+        if self.kernel_installing.get() {
+            self.client.map(|client| client.write_done(result, length));
+            return;
+        }
+        self.current_process.map(|processid| {
+            let _ = self.apps.enter(processid, |_, kernel_data| {
+                kernel_data
+                    .schedule_upcall(
+                        upcall::WRITE_DONE,
+                        (kernel::errorcode::into_statuscode(result), length, 0),
+                    )
+                    .ok();
+            });
+        });
+    }
+
+    fn load_done(&self, result: Result<(), ProcessLoadError>) {
+        // The installation is over either way.
+        if self.kernel_installing.take() {
+            self.client.map(|client| client.load_done(result));
+            return;
+        }
+        self.current_process.take().map(|processid| {
+            let _ = self.apps.enter(processid, |_, kernel_data| {
+                let status = kernel::errorcode::into_statuscode(result.map_err(|e| match e {
+                    ProcessLoadError::NotEnoughMemory => ErrorCode::NOMEM,
+                    ProcessLoadError::NoProcessSlot => ErrorCode::NOMEM,
+                    ProcessLoadError::NotLoaded => ErrorCode::ALREADY,
+                    _ => ErrorCode::INVAL,
+                }));
+                kernel_data
+                    .schedule_upcall(upcall::LOAD_DONE, (status, 0, 0))
+                    .ok();
+            });
+        });
+    }
+}
+
+impl SyscallDriver for AppLoader<'_> {
+    /// Install a new application.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    ///
+    /// The other commands return `NOSUPPORT` unless the board's install
+    /// policy lets the process install applications.
+    ///
+    /// - `1`: Reserve space for a new binary of `arg1` bytes. Returns the
+    ///   flash address the binary will be stored at.
+    /// - `2`: Write `arg2` bytes from the allowed buffer at offset `arg1` in
+    ///   the new binary. At most 512 bytes are written at a time; the number
+    ///   of bytes actually written is passed to the write done upcall.
+    /// - `3`: Validate the binary and start it as a new process.
+    /// - `4`: Abort the installation in progress.
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        arg2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        if command_num == 0 {
+            return CommandReturn::success();
+        }
+        if command_num > 4 {
+            return CommandReturn::failure(ErrorCode::NOSUPPORT);
+        }
+        if let Err(e) = self.claim(processid) {
+            return CommandReturn::failure(e);
+        }
+
+        let res = match command_num {
+            1 => self.loader.setup(arg1).map(|address| address as u32),
+            2 => self.write(arg1, arg2, processid).map(|()| 0),
+            3 => self.loader.load().map(|()| 0),
+            _ => self.loader.abort().map(|()| 0),
+        };
+
+        // Release ownership if nothing is left in progress.
+        if command_num == 4 || (command_num == 1 && res.is_err()) {
+            self.current_process.clear();
+        }
+
+        match (command_num, res) {
+            (1, Ok(address)) => CommandReturn::success_u32(address),
+            (_, Ok(_)) => CommandReturn::success(),
+            (_, Err(e)) => CommandReturn::failure(e),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
@@ -0,0 +1,221 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Leveled log records from processes, written to the kernel debug output.
+//!
+//! Instead of printing raw text with the console driver, a process can emit
+//! log records with a level. Each record is written as one line of the kernel
+//! debug output, with the level and the identity of the process, so that the
+//! records of all processes and of the kernel interleave line by line:
+//!
+//! ```text
+//! [WARN] sensor_app(2): temperature out of range
+//! ```
+//!
+//! Records that are less severe than the level of the process are dropped.
+//! The level of each process defaults to the one given to the capsule, and
+//! can be changed by the board with [`AppLog::set_process_level`].
+//!
+//! The system call interface is documented in
+//! doc/syscalls/0000a_app_log.md.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let app_log = components::app_log::AppLogComponent::new(
+//!     board_kernel,
+//!     capsules_extra::app_log::DRIVER_NUM,
+//!     capsules_extra::app_log::Level::Info,
+//! )
+//! .finalize(components::app_log_component_static!());
+//! ```
+
+use core::cell::Cell;
+
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::debug;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::processbuffer::ReadableProcessBuffer;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, Kernel, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::AppLog as usize;
+
+/// Longest message of a record, in bytes. Longer messages are truncated.
+pub const MAX_MESSAGE_LEN: usize = 128;
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    /// Message of the record.
+    pub const MESSAGE: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Level of a log record, from the most to the least severe.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error = 0,
+    Warn = 1,
+    Info = 2,
+    Debug = 3,
+    Trace = 4,
+}
+
+impl Level {
+    fn from_usize(level: usize) -> Option<Level> {
+        match level {
+            0 => Some(Level::Error),
+            1 => Some(Level::Warn),
+            2 => Some(Level::Info),
+            3 => Some(Level::Debug),
+            4 => Some(Level::Trace),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct App {
+    /// Level of the process, if it is not the default one.
+    level: Option<Level>,
+}
+
+pub struct AppLog<C: ProcessManagementCapability> {
+    kernel: &'static Kernel,
+    apps: Grant<App, UpcallCount<0>, AllowRoCount<{ ro_allow::COUNT }>, AllowRwCount<0>>,
+    default_level: Cell<Level>,
+    capability: C,
+}
+
+impl<C: ProcessManagementCapability> AppLog<C> {
+    pub fn new(
+        kernel: &'static Kernel,
+        apps: Grant<App, UpcallCount<0>, AllowRoCount<{ ro_allow::COUNT }>, AllowRwCount<0>>,
+        default_level: Level,
+        capability: C,
+    ) -> Self {
+        Self {
+            kernel,
+            apps,
+            default_level: Cell::new(default_level),
+            capability,
+        }
+    }
+
+    /// Set the level of the processes that do not have their own.
+    pub fn set_default_level(&self, level: Level) {
+        self.default_level.set(level);
+    }
+
+    /// Set the level of `processid`, or make it use the default level if
+    /// `level` is `None`.
+    pub fn set_process_level(
+        &self,
+        processid: ProcessId,
+        level: Option<Level>,
+    ) -> Result<(), ErrorCode> {
+        self.apps
+            .enter(processid, |app, _| app.level = level)
+            .map_err(ErrorCode::from)
+    }
+
+    fn log(&self, processid: ProcessId, level: Level, len: usize) -> Result<(), ErrorCode> {
+        let name = self.kernel.process_map_or_external(
+            "?",
+            processid,
+            |process| process.get_process_name(),
+            &self.capability,
+        );
+        self.apps
+            .enter(processid, |app, kernel_data| {
+                if level > app.level.unwrap_or(self.default_level.get()) {
+                    return Ok(());
+                }
+
+                let mut message = [0; MAX_MESSAGE_LEN];
+                let len = kernel_data
+                    .get_readonly_processbuffer(ro_allow::MESSAGE)
+                    .and_then(|buffer| {
+                        buffer.enter(|buffer| {
+                            let len = len.min(buffer.len()).min(MAX_MESSAGE_LEN);
+                            buffer[..len].copy_to_slice(&mut message[..len]);
+                            len
+                        })
+                    })
+                    .map_err(ErrorCode::from)?;
+
+                // Only print the valid UTF-8 prefix of the message.
+                let message = match core::str::from_utf8(&message[..len]) {
+                    Ok(message) => message,
+                    Err(err) => core::str::from_utf8(&message[..err.valid_up_to()]).unwrap_or(""),
+                };
+                debug!(
+                    "[{}] {}({}): {}",
+                    level.name(),
+                    name,
+                    processid.id(),
+                    message
+                );
+                Ok(())
+            })
+            .unwrap_or_else(|err| Err(err.into()))
+    }
+}
+
+impl<C: ProcessManagementCapability> SyscallDriver for AppLog<C> {
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Emit a record of level `arg1` (0 is error, 1 warning, 2 info, 3
+    ///   debug and 4 trace), with the first `arg2` bytes of the read-only
+    ///   buffer as message.
+    /// - `2`: Get the level of the process. Records less severe than it are
+    ///   dropped.
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        arg2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => match Level::from_usize(arg1) {
+                Some(level) => self.log(processid, level, arg2).into(),
+                None => CommandReturn::failure(ErrorCode::INVAL),
+            },
+
+            2 => self
+                .apps
+                .enter(processid, |app, _| {
+                    let level = app.level.unwrap_or(self.default_level.get());
+                    CommandReturn::success_u32(level as u32)
+                })
+                .unwrap_or_else(|err| CommandReturn::failure(err.into())),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
@@ -0,0 +1,219 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Watchdog for processes.
+//!
+//! A process starts its watchdog with an interval, and must then pet it
+//! within each interval. If it does not, the process is considered stuck:
+//! the capsule makes it fault, which restarts it if its fault policy allows
+//! it, and logs the event on the debug console.
+//!
+//! Faulting clears the grant of the process, so the watchdog of a restarted
+//! process is stopped until the process starts it again.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let app_watchdog = components::app_watchdog::AppWatchdogComponent::new(
+//!     board_kernel,
+//!     capsules_extra::app_watchdog::DRIVER_NUM,
+//!     mux_alarm,
+//! )
+//! .finalize(components::app_watchdog_component_static!(
+//!     nrf52840::rtc::Rtc<'static>
+//! ));
+//! ```
+//!
+//! Syscall Interface
+//! -----------------
+//!
+//! ### Command
+//!
+//! #### `command_num`
+//!
+//! - `0`: Driver existence check.
+//! - `1`: Start the watchdog, or change its interval, with an interval of
+//!   `arg1` milliseconds. The interval starts now. Returns `INVAL` if `arg1`
+//!   is 0.
+//! - `2`: Pet the watchdog, which starts a new interval. Returns `OFF` if the
+//!   watchdog is not started.
+//! - `3`: Stop the watchdog.
+
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::debug;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks, Ticks};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, Kernel, ProcessId};
+
+use capsules_core::driver;
+
+/// Syscall driver number.
+pub const DRIVER_NUM: usize = driver::NUM::AppWatchdog as usize;
+
+kernel::driver_api!(
+    DRIVER_API,
+    DRIVER_NUM,
+    name: "app_watchdog",
+    commands: [
+        0 => "exists()",
+        1 => "start(interval_ms)",
+        2 => "pet()",
+        3 => "stop()",
+    ],
+    subscribes: [],
+);
+
+/// Watchdog of a process, in ticks of the alarm.
+#[derive(Default)]
+pub struct App {
+    /// When the current interval started, and its length, if the watchdog
+    /// is started.
+    interval: Option<(u32, u32)>,
+}
+
+pub struct AppWatchdog<'a, A: Alarm<'a>, C: ProcessManagementCapability> {
+    alarm: &'a A,
+    kernel: &'static Kernel,
+    capability: C,
+    apps: Grant<App, UpcallCount<0>, AllowRoCount<0>, AllowRwCount<0>>,
+}
+
+impl<'a, A: Alarm<'a>, C: ProcessManagementCapability> AppWatchdog<'a, A, C> {
+    pub fn new(
+        alarm: &'a A,
+        kernel: &'static Kernel,
+        capability: C,
+        grant: Grant<App, UpcallCount<0>, AllowRoCount<0>, AllowRwCount<0>>,
+    ) -> Self {
+        Self {
+            alarm,
+            kernel,
+            capability,
+            apps: grant,
+        }
+    }
+
+    /// Whether the interval starting at `reference` for `dt` ticks is over
+    /// at `now`.
+    fn expired(now: A::Ticks, reference: u32, dt: u32) -> bool {
+        let reference = A::Ticks::from(reference);
+        !now.within_range(reference, reference.wrapping_add(A::Ticks::from(dt)))
+    }
+
+    /// Set the alarm for the end of the first interval to end, or disarm it
+    /// if no watchdog is started.
+    fn reset_alarm(&self) {
+        let now = self.alarm.now();
+        let mut earliest: Option<(A::Ticks, A::Ticks, A::Ticks)> = None;
+        for app in self.apps.iter() {
+            app.enter(|app, _| {
+                if let Some((reference, dt)) = app.interval {
+                    let expired = Self::expired(now, reference, dt);
+                    let reference = A::Ticks::from(reference);
+                    let dt = A::Ticks::from(dt);
+                    let remaining = if expired {
+                        A::Ticks::from(0)
+                    } else {
+                        reference.wrapping_add(dt).wrapping_sub(now)
+                    };
+                    if earliest.map_or(true, |(_, _, earliest)| remaining < earliest) {
+                        earliest = Some((reference, dt, remaining));
+                    }
+                }
+            });
+        }
+        match earliest {
+            Some((reference, dt, _)) => self.alarm.set_alarm(reference, dt),
+            None => {
+                let _ = self.alarm.disarm();
+            }
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>, C: ProcessManagementCapability> AlarmClient for AppWatchdog<'a, A, C> {
+    fn alarm(&self) {
+        let now = self.alarm.now();
+        for app in self.apps.iter() {
+            let processid = app.processid();
+            let expired = app.enter(|app, _| match app.interval {
+                Some((reference, dt)) => Self::expired(now, reference, dt),
+                None => false,
+            });
+            if expired {
+                self.kernel.process_map_or_external(
+                    (),
+                    processid,
+                    |process| {
+                        debug!(
+                            "app_watchdog: {} was not petted in time, faulting it",
+                            process.get_process_name()
+                        )
+                    },
+                    &self.capability,
+                );
+                let _ = self.kernel.hardfault_app(processid, &self.capability);
+            }
+        }
+        self.reset_alarm();
+    }
+}
+
+impl<'a, A: Alarm<'a>, C: ProcessManagementCapability> SyscallDriver for AppWatchdog<'a, A, C> {
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        _arg2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        let now = self.alarm.now().into_u32();
+        let result = match command_num {
+            0 => return CommandReturn::success(),
+            1 => {
+                if arg1 == 0 {
+                    return CommandReturn::failure(ErrorCode::INVAL);
+                }
+                let dt = self.alarm.ticks_from_ms(arg1 as u32).into_u32();
+                self.apps
+                    .enter(processid, |app, _| {
+                        app.interval = Some((now, dt));
+                        Ok(())
+                    })
+                    .unwrap_or_else(|err| Err(err.into()))
+            }
+            2 => self
+                .apps
+                .enter(processid, |app, _| match app.interval {
+                    Some((_, dt)) => {
+                        app.interval = Some((now, dt));
+                        Ok(())
+                    }
+                    None => Err(ErrorCode::OFF),
+                })
+                .unwrap_or_else(|err| Err(err.into())),
+            3 => self
+                .apps
+                .enter(processid, |app, _| {
+                    app.interval = None;
+                    Ok(())
+                })
+                .unwrap_or_else(|err| Err(err.into())),
+            _ => return CommandReturn::failure(ErrorCode::NOSUPPORT),
+        };
+        match result {
+            Ok(()) => {
+                self.reset_alarm();
+                CommandReturn::success()
+            }
+            Err(err) => CommandReturn::failure(err),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
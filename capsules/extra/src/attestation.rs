@@ -0,0 +1,527 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Signed attestation reports of the software running on the device.
+//!
+//! A backend service that must decide whether to trust a device asks it for
+//! an attestation report, with a fresh nonce to prevent replays. The report
+//! describes the kernel and the loaded applications and is signed with a
+//! device key, which the signing engine (for example a key store or a secure
+//! element) holds. The service verifies the signature with the public key of
+//! the device and compares the measurements with the software it expects.
+//!
+//! The report contains, with integers in little endian:
+//!
+//! | Offset | Length | Content                                              |
+//! |--------|--------|------------------------------------------------------|
+//! | 0      | 4      | Magic value `"TATR"`                                 |
+//! | 4      | 1      | Format version, currently 1                          |
+//! | 5      | 1      | Kernel integrity status, see [`KernelStatus`]        |
+//! | 6      | 2      | Number of applications                               |
+//! | 8      | 4      | Boot count                                           |
+//! | 12     | 32     | Nonce, padded with zeros                             |
+//! | 44     | 32     | Stored kernel hash, zeros if not provisioned         |
+//! | 76     | 44 × n | One entry per application                            |
+//! | ...    | 64     | Signature of the SHA-256 hash of the bytes before it |
+//!
+//! Each application entry contains:
+//!
+//! | Offset | Length | Content                                              |
+//! |--------|--------|------------------------------------------------------|
+//! | 0      | 4      | ShortId, 0 if the application has no fixed ShortId   |
+//! | 4      | 4      | Number of restarts of the process                    |
+//! | 8      | 1      | Format of the accepted credential, 0xff if none      |
+//! | 9      | 3      | Reserved, zeros                                      |
+//! | 12     | 32     | SHA-256 hash of the credential, zeros if none        |
+//!
+//! The kernel status tells whether the kernel image matches the stored hash
+//! (see `capsules_system::kernel_integrity`), so a device whose kernel was
+//! modified reports it, unless the attacker also controls the signing key.
+//!
+//! Only one report is generated at a time.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let attestation = components::attestation::AttestationComponent::new(
+//!     board_kernel,
+//!     capsules_extra::attestation::DRIVER_NUM,
+//!     sha,
+//!     signer,
+//!     integrity,
+//!     boot_count,
+//! )
+//! .finalize(components::attestation_component_static!(Sha, Signer, 4));
+//! ```
+
+use core::cell::Cell;
+
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::digest::{ClientData, ClientHash, DigestDataHash, Sha256};
+use kernel::hil::public_key_crypto::signature::{ClientSign, SignatureSign};
+use kernel::platform::self_test::{KernelIntegrity, KernelIntegrityStatus};
+use kernel::process::ShortId;
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::{SubSlice, SubSliceMut};
+use kernel::{ErrorCode, Kernel, ProcessId};
+
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::Attestation as usize;
+
+pub const HASH_LEN: usize = 32;
+pub const SIGNATURE_LEN: usize = 64;
+pub const NONCE_LEN: usize = 32;
+pub const HEADER_LEN: usize = 76;
+pub const APP_ENTRY_LEN: usize = 44;
+
+const MAGIC: &[u8; 4] = b"TATR";
+const VERSION: u8 = 1;
+
+/// Credential format of applications without an accepted credential.
+const NO_CREDENTIAL: u8 = 0xff;
+
+/// Length of a report with `apps` applications.
+pub const fn report_len(apps: usize) -> usize {
+    HEADER_LEN + apps * APP_ENTRY_LEN + SIGNATURE_LEN
+}
+
+/// Kernel integrity status, as encoded in the report.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum KernelStatus {
+    Unchecked = 0,
+    Checking = 1,
+    NotProvisioned = 2,
+    Verified = 3,
+    Mismatch = 4,
+    Error = 5,
+}
+
+impl From<KernelIntegrityStatus> for KernelStatus {
+    fn from(status: KernelIntegrityStatus) -> Self {
+        match status {
+            KernelIntegrityStatus::Unchecked => KernelStatus::Unchecked,
+            KernelIntegrityStatus::Checking => KernelStatus::Checking,
+            KernelIntegrityStatus::NotProvisioned => KernelStatus::NotProvisioned,
+            KernelIntegrityStatus::Verified => KernelStatus::Verified,
+            KernelIntegrityStatus::Mismatch => KernelStatus::Mismatch,
+            KernelIntegrityStatus::Error(_) => KernelStatus::Error,
+        }
+    }
+}
+
+/// IDs for subscribed upcalls.
+mod upcall {
+    /// Report generated. Arguments are the status code and the length of the
+    /// report.
+    pub const DONE: usize = 0;
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for read-only allow buffers.
+mod ro_allow {
+    /// Nonce included in the report.
+    pub const NONCE: usize = 0;
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for read-write allow buffers.
+mod rw_allow {
+    /// Buffer the report is written to.
+    pub const REPORT: usize = 0;
+    pub const COUNT: u8 = 1;
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Idle,
+    /// Hashing the credential of the application at this index.
+    HashingCredential(usize),
+    HashingReport,
+    Signing,
+}
+
+#[derive(Default)]
+pub struct App;
+
+pub struct Attestation<
+    'a,
+    D: DigestDataHash<'a, HASH_LEN> + Sha256,
+    S: SignatureSign<'a, HASH_LEN, SIGNATURE_LEN>,
+    C: ProcessManagementCapability,
+> {
+    kernel: &'static Kernel,
+    capability: C,
+    digest: &'a D,
+    signer: &'a S,
+    integrity: &'a dyn KernelIntegrity,
+    boot_count: u32,
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+    /// Process the report is generated for.
+    current: OptionalCell<ProcessId>,
+    state: Cell<State>,
+    report: TakeCell<'static, [u8]>,
+    /// Number of applications in the report being generated.
+    app_count: Cell<usize>,
+    hash: TakeCell<'static, [u8; HASH_LEN]>,
+    signature: TakeCell<'static, [u8; SIGNATURE_LEN]>,
+}
+
+impl<
+        'a,
+        D: DigestDataHash<'a, HASH_LEN> + Sha256,
+        S: SignatureSign<'a, HASH_LEN, SIGNATURE_LEN>,
+        C: ProcessManagementCapability,
+    > Attestation<'a, D, S, C>
+{
+    /// Create the driver. The report buffer bounds the number of applications
+    /// a report can describe, see [`report_len`].
+    pub fn new(
+        kernel: &'static Kernel,
+        capability: C,
+        digest: &'a D,
+        signer: &'a S,
+        integrity: &'a dyn KernelIntegrity,
+        boot_count: u32,
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+        report: &'static mut [u8],
+        hash: &'static mut [u8; HASH_LEN],
+        signature: &'static mut [u8; SIGNATURE_LEN],
+    ) -> Self {
+        Self {
+            kernel,
+            capability,
+            digest,
+            signer,
+            integrity,
+            boot_count,
+            apps: grant,
+            current: OptionalCell::empty(),
+            state: Cell::new(State::Idle),
+            report: TakeCell::new(report),
+            app_count: Cell::new(0),
+            hash: TakeCell::new(hash),
+            signature: TakeCell::new(signature),
+        }
+    }
+
+    fn count_apps(&self) -> usize {
+        let mut count = 0;
+        self.kernel
+            .process_each_capability(&self.capability, |_| count += 1);
+        count
+    }
+
+    /// The accepted credential of the application at `index`.
+    fn credential(&self, index: usize) -> Option<&'static [u8]> {
+        let mut i = 0;
+        let mut credential = None;
+        self.kernel
+            .process_each_capability(&self.capability, |process| {
+                if i == index {
+                    credential = process
+                        .get_credential()
+                        .map(|accepted| accepted.credential.data());
+                }
+                i += 1;
+            });
+        credential
+    }
+
+    /// Write the header and the application entries, without the credential
+    /// hashes, into `report`. Returns the number of applications.
+    fn fill_report(&self, report: &mut [u8], nonce: &[u8]) -> Result<usize, ErrorCode> {
+        let app_count = self.count_apps();
+        let len = report_len(app_count);
+        if report.len() < len || app_count > u16::MAX as usize {
+            return Err(ErrorCode::SIZE);
+        }
+        report[..len].fill(0);
+
+        report[0..4].copy_from_slice(MAGIC);
+        report[4] = VERSION;
+        report[5] = KernelStatus::from(self.integrity.integrity_status()) as u8;
+        report[6..8].copy_from_slice(&(app_count as u16).to_le_bytes());
+        report[8..12].copy_from_slice(&self.boot_count.to_le_bytes());
+        let nonce_len = nonce.len().min(NONCE_LEN);
+        report[12..12 + nonce_len].copy_from_slice(&nonce[..nonce_len]);
+        if let Some(hash) = self
+            .integrity
+            .kernel_hash()
+            .filter(|hash| hash.len() == HASH_LEN)
+        {
+            report[44..76].copy_from_slice(hash);
+        }
+
+        let mut entries = report[HEADER_LEN..].chunks_exact_mut(APP_ENTRY_LEN);
+        self.kernel
+            .process_each_capability(&self.capability, |process| {
+                // The report holds exactly `app_count` entries, more
+                // processes can only appear if one is loaded meanwhile.
+                if let Some(entry) = entries.next() {
+                    let short_id = match process.short_app_id() {
+                        ShortId::LocallyUnique => 0,
+                        ShortId::Fixed(id) => id.get(),
+                    };
+                    entry[0..4].copy_from_slice(&short_id.to_le_bytes());
+                    entry[4..8]
+                        .copy_from_slice(&(process.get_restart_count() as u32).to_le_bytes());
+                    entry[8] = process
+                        .get_credential()
+                        .map_or(NO_CREDENTIAL, |accepted| accepted.credential.format() as u8);
+                }
+            });
+        Ok(app_count)
+    }
+
+    fn start(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        if self.current.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        let report = self.report.take().ok_or(ErrorCode::BUSY)?;
+        let result = self
+            .apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readonly_processbuffer(ro_allow::NONCE)
+                    .and_then(|nonce| {
+                        nonce.enter(|nonce| {
+                            let mut copy = [0; NONCE_LEN];
+                            for (a, b) in copy.iter_mut().zip(nonce.iter()) {
+                                *a = b.get();
+                            }
+                            copy
+                        })
+                    })
+                    .unwrap_or([0; NONCE_LEN])
+            })
+            .map_err(ErrorCode::from)
+            .and_then(|nonce| self.fill_report(report, &nonce))
+            .and_then(|app_count| {
+                self.digest.set_mode_sha256()?;
+                Ok(app_count)
+            });
+        self.report.replace(report);
+        self.app_count.set(result?);
+        self.current.set(processid);
+        self.hash_credential(0);
+        Ok(())
+    }
+
+    /// Hash the next credential from the application at `index`, or the
+    /// report once all credentials are hashed.
+    fn hash_credential(&self, index: usize) {
+        for index in index..self.app_count.get() {
+            if let Some(credential) = self.credential(index) {
+                self.state.set(State::HashingCredential(index));
+                self.digest.clear_data();
+                if let Err((err, _)) = self.digest.add_data(SubSlice::new(credential)) {
+                    self.finish(Err(err));
+                }
+                return;
+            }
+        }
+        self.hash_report();
+    }
+
+    fn hash_report(&self) {
+        let body_len = report_len(self.app_count.get()) - SIGNATURE_LEN;
+        self.state.set(State::HashingReport);
+        self.digest.clear_data();
+        if let Some(report) = self.report.take() {
+            let mut report = SubSliceMut::new(report);
+            report.slice(..body_len);
+            if let Err((err, mut report)) = self.digest.add_mut_data(report) {
+                report.reset();
+                self.report.replace(report.take());
+                self.finish(Err(err));
+            }
+        }
+    }
+
+    fn run_digest(&self) {
+        if let Some(hash) = self.hash.take() {
+            if let Err((err, hash)) = self.digest.run(hash) {
+                self.hash.replace(hash);
+                self.finish(Err(err));
+            }
+        }
+    }
+
+    /// Copy the report to the process, or report the error, and get ready
+    /// for the next report.
+    fn finish(&self, result: Result<(), ErrorCode>) {
+        self.state.set(State::Idle);
+        let len = report_len(self.app_count.get());
+        self.current.take().map(|processid| {
+            let _ = self.apps.enter(processid, |_, kernel_data| {
+                let result = result.and_then(|()| {
+                    self.report.map_or(Err(ErrorCode::FAIL), |report| {
+                        kernel_data
+                            .get_readwrite_processbuffer(rw_allow::REPORT)
+                            .and_then(|buffer| {
+                                buffer.mut_enter(|buffer| {
+                                    if buffer.len() < len {
+                                        Err(ErrorCode::SIZE)
+                                    } else {
+                                        buffer[..len].copy_from_slice(&report[..len]);
+                                        Ok(())
+                                    }
+                                })
+                            })
+                            .unwrap_or(Err(ErrorCode::NOMEM))
+                    })
+                });
+                let len = if result.is_ok() { len } else { 0 };
+                kernel_data
+                    .schedule_upcall(
+                        upcall::DONE,
+                        (kernel::errorcode::into_statuscode(result), len, 0),
+                    )
+                    .ok();
+            });
+        });
+    }
+}
+
+impl<
+        'a,
+        D: DigestDataHash<'a, HASH_LEN> + Sha256,
+        S: SignatureSign<'a, HASH_LEN, SIGNATURE_LEN>,
+        C: ProcessManagementCapability,
+    > ClientData<HASH_LEN> for Attestation<'a, D, S, C>
+{
+    fn add_data_done(&self, result: Result<(), ErrorCode>, _data: SubSlice<'static, u8>) {
+        match result {
+            Ok(()) => self.run_digest(),
+            Err(err) => self.finish(Err(err)),
+        }
+    }
+
+    fn add_mut_data_done(&self, result: Result<(), ErrorCode>, mut data: SubSliceMut<'static, u8>) {
+        data.reset();
+        self.report.replace(data.take());
+        match result {
+            Ok(()) => self.run_digest(),
+            Err(err) => self.finish(Err(err)),
+        }
+    }
+}
+
+impl<
+        'a,
+        D: DigestDataHash<'a, HASH_LEN> + Sha256,
+        S: SignatureSign<'a, HASH_LEN, SIGNATURE_LEN>,
+        C: ProcessManagementCapability,
+    > ClientHash<HASH_LEN> for Attestation<'a, D, S, C>
+{
+    fn hash_done(&self, result: Result<(), ErrorCode>, digest: &'static mut [u8; HASH_LEN]) {
+        if let Err(err) = result {
+            self.hash.replace(digest);
+            self.finish(Err(err));
+            return;
+        }
+        match self.state.get() {
+            State::HashingCredential(index) => {
+                self.report.map(|report| {
+                    let start = HEADER_LEN + index * APP_ENTRY_LEN + 12;
+                    report[start..start + HASH_LEN].copy_from_slice(digest);
+                });
+                self.hash.replace(digest);
+                self.hash_credential(index + 1);
+            }
+            State::HashingReport => match self.signature.take() {
+                Some(signature) => {
+                    self.state.set(State::Signing);
+                    if let Err((err, digest, signature)) = self.signer.sign(digest, signature) {
+                        self.hash.replace(digest);
+                        self.signature.replace(signature);
+                        self.finish(Err(err));
+                    }
+                }
+                None => {
+                    self.hash.replace(digest);
+                    self.finish(Err(ErrorCode::FAIL));
+                }
+            },
+            State::Idle | State::Signing => {
+                self.hash.replace(digest);
+            }
+        }
+    }
+}
+
+impl<
+        'a,
+        D: DigestDataHash<'a, HASH_LEN> + Sha256,
+        S: SignatureSign<'a, HASH_LEN, SIGNATURE_LEN>,
+        C: ProcessManagementCapability,
+    > ClientSign<HASH_LEN, SIGNATURE_LEN> for Attestation<'a, D, S, C>
+{
+    fn signing_done(
+        &self,
+        result: Result<(), ErrorCode>,
+        hash: &'static mut [u8; HASH_LEN],
+        signature: &'static mut [u8; SIGNATURE_LEN],
+    ) {
+        if result.is_ok() {
+            let start = report_len(self.app_count.get()) - SIGNATURE_LEN;
+            self.report.map(|report| {
+                report[start..start + SIGNATURE_LEN].copy_from_slice(signature);
+            });
+        }
+        self.hash.replace(hash);
+        self.signature.replace(signature);
+        self.finish(result);
+    }
+}
+
+impl<
+        'a,
+        D: DigestDataHash<'a, HASH_LEN> + Sha256,
+        S: SignatureSign<'a, HASH_LEN, SIGNATURE_LEN>,
+        C: ProcessManagementCapability,
+    > SyscallDriver for Attestation<'a, D, S, C>
+{
+    /// Generate attestation reports.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Generate a report with the nonce in the nonce buffer into the
+    ///   report buffer. The upcall is called when the report is ready.
+    /// - `2`: Return the length of a report for the loaded applications.
+    fn command(
+        &self,
+        command_num: usize,
+        _: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+            1 => self.start(processid).into(),
+            2 => CommandReturn::success_u32(report_len(self.count_apps()) as u32),
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
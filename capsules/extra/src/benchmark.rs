@@ -0,0 +1,320 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Performance benchmarks of the kernel.
+//!
+//! This capsule measures how long common kernel paths take, so that changes
+//! to the kernel loop or an architecture crate that slow them down are caught
+//! by running the benchmarks in an emulator, before they are noticed on
+//! hardware. Times are measured with a cycle counter, so on an emulator they
+//! approximate the number of cycles.
+//!
+//! Each benchmark collects samples in a series:
+//!
+//! - `syscall`: a process marks the series in a loop. Each sample is the time
+//!   between two marks of the process, one system call round trip.
+//! - `ipc_round_trip`: an IPC client marks the series each time its service
+//!   answered. Each sample is the time between two marks of the process, one
+//!   IPC round trip.
+//! - `context_switch`: processes take turns marking the series. Each mark
+//!   wakes the other processes with an upcall, and each sample is the time
+//!   between marks of two different processes.
+//! - `alarm_jitter`: the kernel sets periodic alarms, and each sample is how
+//!   late an alarm fired, in microseconds.
+//!
+//! The report prints one line per series with [`debug!`], starting with
+//! `bench: ` and followed by a JSON object, for example:
+//!
+//! ```text
+//! bench: {"name":"syscall","unit":"cycles","samples":1000,"min":412,"max":530,"mean":420}
+//! ```
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let benchmark = components::benchmark::BenchmarkComponent::new(
+//!     board_kernel,
+//!     capsules_extra::benchmark::DRIVER_NUM,
+//!     mux_alarm,
+//!     cycle_counter,
+//! )
+//! .finalize(components::benchmark_component_static!(
+//!     qemu_rv32_virt_chip::chip::QemuRv32VirtClint,
+//!     rv32i::cycle_counter::McycleCounter
+//! ));
+//! let _ = benchmark.start_alarm_jitter(100, 10);
+//! ```
+
+use core::cell::Cell;
+
+use capsules_core::driver;
+use kernel::debug;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::hw_debug::CycleCounter;
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks, Ticks};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+pub const DRIVER_NUM: usize = driver::NUM::Benchmark as usize;
+
+/// Ids for subscribe upcalls
+mod upcall {
+    /// Another process marked the context switch series.
+    pub const SWITCH: usize = 0;
+    /// The number of upcalls the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// A series of samples.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Series {
+    Syscall = 0,
+    IpcRoundTrip = 1,
+    ContextSwitch = 2,
+    AlarmJitter = 3,
+}
+
+/// Number of series.
+pub const NUM_SERIES: usize = 4;
+
+impl Series {
+    const ALL: [Series; NUM_SERIES] = [
+        Series::Syscall,
+        Series::IpcRoundTrip,
+        Series::ContextSwitch,
+        Series::AlarmJitter,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Series::Syscall => "syscall",
+            Series::IpcRoundTrip => "ipc_round_trip",
+            Series::ContextSwitch => "context_switch",
+            Series::AlarmJitter => "alarm_jitter",
+        }
+    }
+
+    fn unit(self) -> &'static str {
+        match self {
+            Series::AlarmJitter => "us",
+            _ => "cycles",
+        }
+    }
+}
+
+/// Summary of the samples of a series.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub samples: u32,
+    pub min: u64,
+    pub max: u64,
+    pub total: u64,
+}
+
+impl Stats {
+    fn add(&mut self, sample: u64) {
+        if self.samples == 0 || sample < self.min {
+            self.min = sample;
+        }
+        self.max = self.max.max(sample);
+        self.total = self.total.saturating_add(sample);
+        self.samples += 1;
+    }
+
+    pub fn mean(&self) -> u64 {
+        self.total / u64::from(self.samples.max(1))
+    }
+}
+
+/// Periodic alarms of the alarm jitter benchmark.
+#[derive(Clone, Copy)]
+struct JitterRun<T: Ticks> {
+    /// When the pending alarm should fire.
+    expected: T,
+    period: T,
+    remaining: u32,
+}
+
+#[derive(Default)]
+pub struct App;
+
+pub struct Benchmark<'a, A: Alarm<'a>, C: CycleCounter> {
+    alarm: &'a A,
+    counter: &'a C,
+    apps: Grant<App, UpcallCount<{ upcall::COUNT }>, AllowRoCount<0>, AllowRwCount<0>>,
+    stats: [Cell<Stats>; NUM_SERIES],
+    /// Time of the last mark of each series, and the process that made it.
+    last_mark: [Cell<Option<(u64, ProcessId)>>; NUM_SERIES],
+    jitter: Cell<Option<JitterRun<A::Ticks>>>,
+}
+
+impl<'a, A: Alarm<'a>, C: CycleCounter> Benchmark<'a, A, C> {
+    pub fn new(
+        alarm: &'a A,
+        counter: &'a C,
+        grant: Grant<App, UpcallCount<{ upcall::COUNT }>, AllowRoCount<0>, AllowRwCount<0>>,
+    ) -> Self {
+        counter.reset();
+        counter.start();
+        Self {
+            alarm,
+            counter,
+            apps: grant,
+            stats: [const {
+                Cell::new(Stats {
+                    samples: 0,
+                    min: 0,
+                    max: 0,
+                    total: 0,
+                })
+            }; NUM_SERIES],
+            last_mark: [const { Cell::new(None) }; NUM_SERIES],
+            jitter: Cell::new(None),
+        }
+    }
+
+    /// The samples collected in `series` since the last report.
+    pub fn stats(&self, series: Series) -> Stats {
+        self.stats[series as usize].get()
+    }
+
+    fn add_sample(&self, series: Series, sample: u64) {
+        let mut stats = self.stats[series as usize].get();
+        stats.add(sample);
+        self.stats[series as usize].set(stats);
+    }
+
+    /// Print the series that have samples, and start them again.
+    pub fn report(&self) {
+        for series in Series::ALL {
+            let stats = self.stats[series as usize].take();
+            if stats.samples == 0 {
+                continue;
+            }
+            debug!(
+                "bench: {{\"name\":\"{}\",\"unit\":\"{}\",\"samples\":{},\"min\":{},\"max\":{},\"mean\":{}}}",
+                series.name(),
+                series.unit(),
+                stats.samples,
+                stats.min,
+                stats.max,
+                stats.mean()
+            );
+        }
+        for last_mark in self.last_mark.iter() {
+            last_mark.set(None);
+        }
+    }
+
+    /// Set `count` alarms, `period_ms` apart, and report how late they fire.
+    pub fn start_alarm_jitter(&self, count: u32, period_ms: u32) -> Result<(), ErrorCode> {
+        if self.jitter.get().is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        if count == 0 || period_ms == 0 {
+            return Err(ErrorCode::INVAL);
+        }
+        let now = self.alarm.now();
+        let period = self.alarm.ticks_from_ms(period_ms);
+        self.jitter.set(Some(JitterRun {
+            expected: now.wrapping_add(period),
+            period,
+            remaining: count,
+        }));
+        self.alarm.set_alarm(now, period);
+        Ok(())
+    }
+
+    /// Mark `series` for `processid`.
+    fn mark(&self, series: Series, processid: ProcessId) -> Result<(), ErrorCode> {
+        let now = self.counter.count();
+        let last_mark = &self.last_mark[series as usize];
+        match (series, last_mark.get()) {
+            (Series::Syscall | Series::IpcRoundTrip, Some((time, owner))) if owner == processid => {
+                self.add_sample(series, now.wrapping_sub(time));
+            }
+            (Series::ContextSwitch, Some((time, owner))) if owner != processid => {
+                self.add_sample(series, now.wrapping_sub(time));
+            }
+            (Series::AlarmJitter, _) => return Err(ErrorCode::INVAL),
+            _ => {}
+        }
+        if series == Series::ContextSwitch {
+            for app in self.apps.iter() {
+                if app.processid() != processid {
+                    app.enter(|_, kernel_data| {
+                        kernel_data.schedule_upcall(upcall::SWITCH, (0, 0, 0)).ok();
+                    });
+                }
+            }
+        }
+        // Read the counter again, so the time spent here is not counted.
+        last_mark.set(Some((self.counter.count(), processid)));
+        Ok(())
+    }
+}
+
+impl<'a, A: Alarm<'a>, C: CycleCounter> AlarmClient for Benchmark<'a, A, C> {
+    fn alarm(&self) {
+        let Some(mut run) = self.jitter.get() else {
+            return;
+        };
+        let now = self.alarm.now();
+        let late = now.wrapping_sub(run.expected);
+        self.add_sample(Series::AlarmJitter, u64::from(self.alarm.ticks_to_us(late)));
+
+        run.remaining -= 1;
+        if run.remaining == 0 {
+            self.jitter.set(None);
+            self.report();
+        } else {
+            // Alarms follow the schedule, so a late alarm does not delay the
+            // next ones.
+            self.alarm.set_alarm(run.expected, run.period);
+            run.expected = run.expected.wrapping_add(run.period);
+            self.jitter.set(Some(run));
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>, C: CycleCounter> SyscallDriver for Benchmark<'a, A, C> {
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Mark the series `arg1`: 0 for system calls, 1 for IPC round
+    ///   trips and 2 for context switches.
+    /// - `2`: Start the alarm jitter benchmark, with `arg1` alarms `arg2`
+    ///   milliseconds apart. The series is reported once the last alarm
+    ///   fired.
+    /// - `3`: Report the series that have samples, and start them again.
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        arg2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+            1 => match Series::ALL.get(arg1) {
+                Some(series) => self.mark(*series, processid).into(),
+                None => CommandReturn::failure(ErrorCode::INVAL),
+            },
+            2 => self.start_alarm_jitter(arg1 as u32, arg2 as u32).into(),
+            3 => {
+                self.report();
+                CommandReturn::success()
+            }
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
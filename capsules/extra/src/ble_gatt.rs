@@ -0,0 +1,1044 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Bluetooth Low Energy peripheral with a read-only GATT server.
+//!
+//! The peripheral advertises that it is connectable on the three advertising
+//! channels, and accepts the first connection request of a central. It then
+//! follows the connection as the link layer of the peripheral:
+//!
+//! - Connection events are timed with an alarm from the anchor point of the
+//!   last packet received, with a window widened by
+//!   [`WINDOW_WIDENING_US`] for the drift of the clocks and the latency of
+//!   the kernel, and hop channels with channel selection algorithm #1.
+//! - One packet is exchanged in each event. Packets are acknowledged and
+//!   retransmitted with the sequence numbers of the link layer.
+//! - Connection updates and channel maps are applied at their instant, the
+//!   version and the features are exchanged, and unknown control procedures
+//!   are answered with `LL_UNKNOWN_RSP`.
+//! - The connection ends when the central terminates it or after the
+//!   supervision timeout, and the peripheral advertises again.
+//!
+//! The L2CAP channels are not fragmented, so the ATT MTU is the default of
+//! 23 bytes. Pairing is not supported, and signaling commands are rejected.
+//! The GATT server answers the requests a central needs to discover the
+//! services and characteristics and to read them from the attributes of the
+//! board. Writes are not permitted.
+//!
+//! Attributes are listed in the order of their handles, which start at 1,
+//! with the declarations of the services and of the characteristics.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! use capsules_extra::ble_gatt::{self, Attribute};
+//!
+//! static DEVICE_NAME: &[u8] = b"Tock";
+//! static ATTRIBUTES: [Attribute; 3] = [
+//!     // Generic Access service
+//!     Attribute::new(ble_gatt::PRIMARY_SERVICE_UUID, &[0x00, 0x18]),
+//!     Attribute::new(
+//!         ble_gatt::CHARACTERISTIC_UUID,
+//!         &ble_gatt::characteristic(ble_gatt::PROPERTY_READ, 3, 0x2a00),
+//!     ),
+//!     // Device Name
+//!     Attribute::new(0x2a00, DEVICE_NAME),
+//! ];
+//!
+//! let ble_peripheral = components::ble_gatt::BlePeripheralComponent::new(
+//!     &base_peripherals.ble_radio,
+//!     mux_alarm,
+//!     [0x01, 0x02, 0x03, 0x04, 0x05, 0xc6],
+//!     DEVICE_NAME,
+//!     &ATTRIBUTES,
+//! )
+//! .finalize(components::ble_peripheral_component_static!(
+//!     nrf52840::ble_radio::Radio,
+//!     nrf52840::rtc::Rtc<'static>
+//! ));
+//! ble_peripheral.start();
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::ble_advertising::RadioChannel;
+use kernel::hil::ble_connection::{BleConnectionRadio, ConnectionClient};
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks, Ticks};
+use kernel::utilities::cells::TakeCell;
+
+/// Length of the radio buffer, which holds an advertisement.
+pub const BUFFER_LEN: usize = 2 + 6 + 31;
+
+/// Time between the advertising events.
+pub const ADVERTISING_INTERVAL_MS: u32 = 100;
+/// Time the peripheral listens for a connection request after it starts
+/// advertising on a channel.
+pub const ADVERTISING_LISTEN_US: u32 = 2000;
+/// Time the peripheral listens before and after the expected anchor point.
+pub const WINDOW_WIDENING_US: u32 = 1000;
+
+/// UUID of the declaration of a primary service.
+pub const PRIMARY_SERVICE_UUID: u16 = 0x2800;
+/// UUID of the declaration of a characteristic.
+pub const CHARACTERISTIC_UUID: u16 = 0x2803;
+/// Characteristic property: the value can be read.
+pub const PROPERTY_READ: u8 = 0x02;
+
+/// Value of the declaration of a characteristic of type `uuid` whose value
+/// is the attribute at `value_handle`.
+pub const fn characteristic(properties: u8, value_handle: u16, uuid: u16) -> [u8; 5] {
+    let handle = value_handle.to_le_bytes();
+    let uuid = uuid.to_le_bytes();
+    [properties, handle[0], handle[1], uuid[0], uuid[1]]
+}
+
+/// Attribute of the GATT server.
+pub struct Attribute<'a> {
+    uuid: u16,
+    value: &'a [u8],
+}
+
+impl<'a> Attribute<'a> {
+    pub const fn new(uuid: u16, value: &'a [u8]) -> Attribute<'a> {
+        Attribute { uuid, value }
+    }
+}
+
+const ADVERTISING_CHANNELS: [RadioChannel; 3] = [
+    RadioChannel::AdvertisingChannel37,
+    RadioChannel::AdvertisingChannel38,
+    RadioChannel::AdvertisingChannel39,
+];
+
+// BLUETOOTH SPECIFICATION Version 4.2 [Vol 6, Part B], section 2.3 Advertising Channel PDU
+const ADV_IND: u8 = 0x00;
+const CONNECT_IND: u8 = 0x05;
+/// Random address of the advertiser (TxAdd) or of the target (RxAdd).
+const TX_ADD: u8 = 0x40;
+const RX_ADD: u8 = 0x80;
+const CONNECT_IND_LEN: usize = 34;
+
+// BLUETOOTH SPECIFICATION Version 4.2 [Vol 6, Part B], section 2.4 Data Channel PDU
+const MAX_PAYLOAD: usize = 27;
+const LLID_CONTINUATION: u8 = 0x01;
+const LLID_START: u8 = 0x02;
+const LLID_CONTROL: u8 = 0x03;
+const NESN: u8 = 0x04;
+const SN: u8 = 0x08;
+
+// BLUETOOTH SPECIFICATION Version 4.2 [Vol 6, Part B], section 2.4.2 LL Control PDU
+mod ll {
+    pub const CONNECTION_UPDATE_IND: u8 = 0x00;
+    pub const CHANNEL_MAP_IND: u8 = 0x01;
+    pub const TERMINATE_IND: u8 = 0x02;
+    pub const ENC_REQ: u8 = 0x03;
+    pub const UNKNOWN_RSP: u8 = 0x07;
+    pub const FEATURE_REQ: u8 = 0x08;
+    pub const FEATURE_RSP: u8 = 0x09;
+    pub const VERSION_IND: u8 = 0x0c;
+    pub const REJECT_IND: u8 = 0x0d;
+    pub const REJECT_EXT_IND: u8 = 0x11;
+    pub const PING_REQ: u8 = 0x12;
+    pub const PING_RSP: u8 = 0x13;
+    pub const LENGTH_REQ: u8 = 0x14;
+    pub const LENGTH_RSP: u8 = 0x15;
+    /// Version 4.2 of the specification.
+    pub const VERSION: u8 = 0x08;
+    /// Error code of unsupported features.
+    pub const UNSUPPORTED_REMOTE_FEATURE: u8 = 0x1a;
+}
+
+// BLUETOOTH SPECIFICATION Version 4.2 [Vol 3, Part A], section 2.1 Channel Identifiers
+const CID_ATT: u16 = 0x0004;
+const CID_SIGNALING: u16 = 0x0005;
+const CID_SMP: u16 = 0x0006;
+const SIGNALING_COMMAND_REJECT: u8 = 0x01;
+const SIGNALING_CONNECTION_PARAMETER_UPDATE_RSP: u8 = 0x13;
+const SIGNALING_LE_CREDIT_BASED_CONNECTION_RSP: u8 = 0x15;
+const SMP_PAIRING_REQUEST: u8 = 0x01;
+const SMP_PAIRING_FAILED: u8 = 0x05;
+const SMP_PAIRING_NOT_SUPPORTED: u8 = 0x05;
+
+// BLUETOOTH SPECIFICATION Version 4.2 [Vol 3, Part F], section 3.4 Attribute Protocol PDUs
+mod att {
+    pub const MTU: usize = 23;
+    pub const ERROR_RSP: u8 = 0x01;
+    pub const EXCHANGE_MTU_REQ: u8 = 0x02;
+    pub const EXCHANGE_MTU_RSP: u8 = 0x03;
+    pub const FIND_INFORMATION_REQ: u8 = 0x04;
+    pub const FIND_INFORMATION_RSP: u8 = 0x05;
+    pub const FIND_BY_TYPE_VALUE_REQ: u8 = 0x06;
+    pub const FIND_BY_TYPE_VALUE_RSP: u8 = 0x07;
+    pub const READ_BY_TYPE_REQ: u8 = 0x08;
+    pub const READ_BY_TYPE_RSP: u8 = 0x09;
+    pub const READ_REQ: u8 = 0x0a;
+    pub const READ_RSP: u8 = 0x0b;
+    pub const READ_BLOB_REQ: u8 = 0x0c;
+    pub const READ_BLOB_RSP: u8 = 0x0d;
+    pub const READ_BY_GROUP_TYPE_REQ: u8 = 0x10;
+    pub const READ_BY_GROUP_TYPE_RSP: u8 = 0x11;
+    pub const WRITE_REQ: u8 = 0x12;
+    pub const HANDLE_VALUE_CFM: u8 = 0x1e;
+    /// Commands are not answered.
+    pub const COMMAND: u8 = 0x40;
+
+    pub const INVALID_HANDLE: u8 = 0x01;
+    pub const WRITE_NOT_PERMITTED: u8 = 0x03;
+    pub const INVALID_PDU: u8 = 0x04;
+    pub const REQUEST_NOT_SUPPORTED: u8 = 0x06;
+    pub const INVALID_OFFSET: u8 = 0x07;
+    pub const ATTRIBUTE_NOT_FOUND: u8 = 0x0a;
+    pub const UNSUPPORTED_GROUP_TYPE: u8 = 0x10;
+}
+
+/// Data channel PDU waiting to be transmitted.
+#[derive(Clone, Copy)]
+struct Pdu {
+    llid: u8,
+    len: u8,
+    data: [u8; MAX_PAYLOAD],
+}
+
+impl Pdu {
+    fn new(llid: u8, payload: &[u8]) -> Pdu {
+        let len = payload.len().min(MAX_PAYLOAD);
+        let mut data = [0; MAX_PAYLOAD];
+        data[..len].copy_from_slice(&payload[..len]);
+        Pdu {
+            llid,
+            len: len as u8,
+            data,
+        }
+    }
+}
+
+const QUEUE_LEN: usize = 2;
+
+#[derive(Clone, Copy)]
+struct ConnectionUpdate {
+    window_size_us: u32,
+    window_offset_us: u32,
+    interval_us: u32,
+    timeout_us: u32,
+    instant: u16,
+}
+
+#[derive(Clone, Copy)]
+struct Connection {
+    access_address: u32,
+    crc_init: u32,
+    interval_us: u32,
+    timeout_us: u32,
+    channel_map: [u8; 5],
+    hop: u8,
+    unmapped_channel: u8,
+    event_counter: u16,
+    sn: bool,
+    nesn: bool,
+    /// Whether a packet of the central was received.
+    established: bool,
+    terminated: bool,
+    update: Option<ConnectionUpdate>,
+    channel_map_update: Option<([u8; 5], u16)>,
+    /// Whether the version was sent to the central.
+    version_sent: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    /// Advertising on the channel with this index in `ADVERTISING_CHANNELS`.
+    Advertising(usize),
+    /// Waiting for the next advertising event.
+    AdvertisingWait,
+    /// Waiting for the next connection event.
+    ConnectionWait,
+    /// Listening for the central in a connection event.
+    ConnectionListen,
+    /// Answering the central in a connection event.
+    ConnectionResponse,
+}
+
+pub struct BlePeripheral<'a, R: BleConnectionRadio<'a>, A: Alarm<'a>> {
+    radio: &'a R,
+    alarm: &'a A,
+    /// Static random device address, in the order it is transmitted.
+    address: [u8; 6],
+    name: &'a [u8],
+    attributes: &'a [Attribute<'a>],
+    buffer: TakeCell<'static, [u8]>,
+    state: Cell<State>,
+    connection: Cell<Connection>,
+    /// Anchor point of the last event a packet was received in, or the start
+    /// of the transmit window.
+    anchor: Cell<A::Ticks>,
+    events_since_anchor: Cell<u32>,
+    /// Size of the transmit window, which the first packet of the central
+    /// may start anywhere in.
+    window_us: Cell<u32>,
+    /// Time of the last packet received, for the supervision timeout.
+    last_received: Cell<A::Ticks>,
+    /// Time the connection must be established within.
+    establish_us: Cell<u32>,
+    /// PDU transmitted that is not acknowledged yet.
+    outgoing: Cell<Option<Pdu>>,
+    queue: Cell<[Option<Pdu>; QUEUE_LEN]>,
+}
+
+impl<'a, R: BleConnectionRadio<'a>, A: Alarm<'a>> BlePeripheral<'a, R, A> {
+    pub fn new(
+        radio: &'a R,
+        alarm: &'a A,
+        address: [u8; 6],
+        name: &'a [u8],
+        attributes: &'a [Attribute<'a>],
+        buffer: &'static mut [u8],
+    ) -> BlePeripheral<'a, R, A> {
+        let mut address = address;
+        // The two most significant bits of a static random address are set.
+        address[5] |= 0xc0;
+        BlePeripheral {
+            radio,
+            alarm,
+            address,
+            name,
+            attributes,
+            buffer: TakeCell::new(buffer),
+            state: Cell::new(State::Idle),
+            connection: Cell::new(Connection {
+                access_address: 0,
+                crc_init: 0,
+                interval_us: 0,
+                timeout_us: 0,
+                channel_map: [0; 5],
+                hop: 0,
+                unmapped_channel: 0,
+                event_counter: 0,
+                sn: false,
+                nesn: false,
+                established: false,
+                terminated: false,
+                update: None,
+                channel_map_update: None,
+                version_sent: false,
+            }),
+            anchor: Cell::new(A::Ticks::from(0)),
+            events_since_anchor: Cell::new(0),
+            window_us: Cell::new(0),
+            last_received: Cell::new(A::Ticks::from(0)),
+            establish_us: Cell::new(0),
+            outgoing: Cell::new(None),
+            queue: Cell::new([None; QUEUE_LEN]),
+        }
+    }
+
+    /// Start advertising, and accept a connection.
+    pub fn start(&self) {
+        if self.state.get() == State::Idle {
+            self.advertise(0);
+        }
+    }
+
+    /// Whether a central is connected.
+    pub fn is_connected(&self) -> bool {
+        matches!(
+            self.state.get(),
+            State::ConnectionWait | State::ConnectionListen | State::ConnectionResponse
+        )
+    }
+
+    fn advertise(&self, channel: usize) {
+        self.buffer.take().map(|buf| {
+            // Flags: LE General Discoverable Mode, BR/EDR not supported, and
+            // the complete local name as far as it fits.
+            let name_len = self.name.len().min(BUFFER_LEN - 2 - 6 - 3 - 2);
+            let data_len = 3 + 2 + name_len;
+            buf[0] = ADV_IND | TX_ADD;
+            buf[1] = (6 + data_len) as u8;
+            buf[2..8].copy_from_slice(&self.address);
+            buf[8..11].copy_from_slice(&[0x02, 0x01, 0x06]);
+            buf[11] = name_len as u8 + 1;
+            buf[12] = 0x09;
+            buf[13..13 + name_len].copy_from_slice(&self.name[..name_len]);
+
+            self.state.set(State::Advertising(channel));
+            self.radio
+                .advertise_connectable(buf, 2 + 6 + data_len, ADVERTISING_CHANNELS[channel]);
+            self.alarm.set_alarm(
+                self.alarm.now(),
+                self.alarm.ticks_from_us(ADVERTISING_LISTEN_US),
+            );
+        });
+    }
+
+    /// Advertise on the next channel, or wait for the next advertising event.
+    fn next_advertisement(&self, channel: usize) {
+        if channel + 1 < ADVERTISING_CHANNELS.len() {
+            self.advertise(channel + 1);
+        } else {
+            self.state.set(State::AdvertisingWait);
+            self.alarm.set_alarm(
+                self.alarm.now(),
+                self.alarm.ticks_from_ms(ADVERTISING_INTERVAL_MS),
+            );
+        }
+    }
+
+    /// Start the connection requested in the CONNECT_IND `request`, or
+    /// return `false` if it is not a valid request to this peripheral.
+    fn connect(&self, request: &[u8]) -> bool {
+        // BLUETOOTH SPECIFICATION Version 4.2 [Vol 6, Part B], section 2.3.3.1 CONNECT_REQ
+        if request.len() < 2 + CONNECT_IND_LEN
+            || request[0] & 0x0f != CONNECT_IND
+            || request[0] & RX_ADD == 0
+            || request[1] as usize != CONNECT_IND_LEN
+            || request[8..14] != self.address[..]
+        {
+            return false;
+        }
+        let ll_data = &request[14..];
+        let u16_at = |i: usize| u16::from_le_bytes([ll_data[i], ll_data[i + 1]]) as u32;
+        let interval_us = u16_at(10) * 1250;
+        let mut channel_map = [0; 5];
+        channel_map.copy_from_slice(&ll_data[16..21]);
+        channel_map[4] &= 0x1f;
+        let hop = ll_data[21] & 0x1f;
+        let used_channels: u32 = channel_map.iter().map(|byte| byte.count_ones()).sum();
+        if !(6 * 1250..=3200 * 1250).contains(&interval_us)
+            || !(5..=16).contains(&hop)
+            || used_channels < 2
+        {
+            return false;
+        }
+
+        let window_size_us = ll_data[7] as u32 * 1250;
+        let window_offset_us = u16_at(8) * 1250;
+        self.connection.set(Connection {
+            access_address: u32::from_le_bytes([ll_data[0], ll_data[1], ll_data[2], ll_data[3]]),
+            crc_init: u32::from_le_bytes([ll_data[4], ll_data[5], ll_data[6], 0]),
+            interval_us,
+            timeout_us: u16_at(14) * 10_000,
+            channel_map,
+            hop,
+            unmapped_channel: 0,
+            event_counter: 0,
+            sn: false,
+            nesn: false,
+            established: false,
+            terminated: false,
+            update: None,
+            channel_map_update: None,
+            version_sent: false,
+        });
+        self.outgoing.set(None);
+        self.queue.set([None; QUEUE_LEN]);
+
+        // The transmit window starts 1.25 ms and the offset after the end of
+        // the request, which was just received.
+        let now = self.alarm.now();
+        self.last_received.set(now);
+        self.anchor
+            .set(now.wrapping_add(self.alarm.ticks_from_us(1250 + window_offset_us)));
+        self.events_since_anchor.set(0);
+        self.window_us.set(window_size_us);
+        self.establish_us
+            .set(1250 + window_offset_us + window_size_us + 6 * interval_us);
+        self.schedule_event();
+        true
+    }
+
+    fn disconnect(&self) {
+        let _ = self.alarm.disarm();
+        self.state.set(State::Idle);
+        self.start();
+    }
+
+    /// Wait for the next connection event.
+    fn schedule_event(&self) {
+        let connection = self.connection.get();
+        self.state.set(State::ConnectionWait);
+        let reference = self
+            .anchor
+            .get()
+            .wrapping_sub(self.alarm.ticks_from_us(WINDOW_WIDENING_US));
+        self.alarm.set_alarm(
+            reference,
+            self.alarm
+                .ticks_from_us(self.events_since_anchor.get() * connection.interval_us),
+        );
+    }
+
+    /// Select the channel of the next connection event with channel
+    /// selection algorithm #1.
+    fn next_channel(&self, connection: &mut Connection) -> RadioChannel {
+        // BLUETOOTH SPECIFICATION Version 4.2 [Vol 6, Part B], section 4.5.8.2 Channel Selection
+        let unmapped = (connection.unmapped_channel + connection.hop) % 37;
+        connection.unmapped_channel = unmapped;
+        let used =
+            |channel: u8| connection.channel_map[channel as usize / 8] & (1 << (channel % 8)) != 0;
+        let channel = if used(unmapped) {
+            unmapped
+        } else {
+            let used_channels: u32 = connection
+                .channel_map
+                .iter()
+                .map(|byte| byte.count_ones())
+                .sum();
+            let remapping_index = unmapped as u32 % used_channels;
+            (0..37)
+                .filter(|channel| used(*channel))
+                .nth(remapping_index as usize)
+                .unwrap_or(0)
+        };
+        RadioChannel::from_channel_index(channel as u32).unwrap_or(RadioChannel::DataChannel0)
+    }
+
+    fn start_event(&self) {
+        let mut connection = self.connection.get();
+        if let Some((channel_map, instant)) = connection.channel_map_update {
+            if connection.event_counter == instant {
+                connection.channel_map = channel_map;
+                connection.channel_map_update = None;
+            }
+        }
+        let channel = self.next_channel(&mut connection);
+        self.connection.set(connection);
+
+        self.buffer.take().map(|response| {
+            self.fill_response(response);
+            self.state.set(State::ConnectionListen);
+            self.radio.connection_event(
+                channel,
+                connection.access_address,
+                connection.crc_init,
+                response,
+            );
+            self.alarm.set_alarm(
+                self.anchor.get(),
+                self.alarm.ticks_from_us(
+                    self.events_since_anchor.get() * connection.interval_us
+                        + self.window_us.get()
+                        + WINDOW_WIDENING_US,
+                ),
+            );
+        });
+    }
+
+    /// Move on to the next connection event after the current one.
+    fn end_event(&self) {
+        let mut connection = self.connection.get();
+        let limit_us = if connection.established {
+            connection.timeout_us
+        } else {
+            self.establish_us.get()
+        };
+        let elapsed = self.alarm.now().wrapping_sub(self.last_received.get());
+        if connection.terminated
+            || elapsed.into_u32() > self.alarm.ticks_from_us(limit_us).into_u32()
+        {
+            self.disconnect();
+            return;
+        }
+
+        connection.event_counter = connection.event_counter.wrapping_add(1);
+        self.events_since_anchor
+            .set(self.events_since_anchor.get() + 1);
+        if let Some(update) = connection.update {
+            if connection.event_counter == update.instant {
+                // The event at the instant is replaced by the transmit window
+                // of the new parameters.
+                let anchor = self.anchor.get().wrapping_add(self.alarm.ticks_from_us(
+                    self.events_since_anchor.get() * connection.interval_us
+                        + update.window_offset_us,
+                ));
+                self.anchor.set(anchor);
+                self.events_since_anchor.set(0);
+                self.window_us.set(update.window_size_us);
+                connection.interval_us = update.interval_us;
+                connection.timeout_us = update.timeout_us;
+                connection.update = None;
+            }
+        }
+        self.connection.set(connection);
+        self.schedule_event();
+    }
+
+    /// Write the packet to transmit in the connection event to `response`.
+    fn fill_response(&self, response: &mut [u8]) {
+        let connection = self.connection.get();
+        if self.outgoing.get().is_none() {
+            let mut queue = self.queue.get();
+            self.outgoing.set(queue[0]);
+            queue.rotate_left(1);
+            queue[QUEUE_LEN - 1] = None;
+            self.queue.set(queue);
+        }
+        let pdu = self
+            .outgoing
+            .get()
+            .unwrap_or(Pdu::new(LLID_CONTINUATION, &[]));
+        let mut header = pdu.llid;
+        if connection.nesn {
+            header |= NESN;
+        }
+        if connection.sn {
+            header |= SN;
+        }
+        response[0] = header;
+        response[1] = pdu.len;
+        response[2..2 + pdu.len as usize].copy_from_slice(&pdu.data[..pdu.len as usize]);
+    }
+
+    fn send(&self, llid: u8, payload: &[u8]) {
+        let mut queue = self.queue.get();
+        if let Some(slot) = queue.iter_mut().find(|slot| slot.is_none()) {
+            *slot = Some(Pdu::new(llid, payload));
+            self.queue.set(queue);
+        }
+    }
+
+    fn send_l2cap(&self, cid: u16, data: &[u8]) {
+        let mut payload = [0; MAX_PAYLOAD];
+        let len = data.len().min(MAX_PAYLOAD - 4);
+        payload[0..2].copy_from_slice(&(len as u16).to_le_bytes());
+        payload[2..4].copy_from_slice(&cid.to_le_bytes());
+        payload[4..4 + len].copy_from_slice(&data[..len]);
+        self.send(LLID_START, &payload[..4 + len]);
+    }
+
+    fn receive_control(&self, payload: &[u8]) {
+        let Some(&opcode) = payload.first() else {
+            return;
+        };
+        let mut connection = self.connection.get();
+        let u16_at = |i: usize| {
+            payload
+                .get(i..i + 2)
+                .map_or(0, |bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+        };
+        match opcode {
+            ll::CONNECTION_UPDATE_IND if payload.len() >= 12 => {
+                connection.update = Some(ConnectionUpdate {
+                    window_size_us: payload[1] as u32 * 1250,
+                    window_offset_us: u16_at(2) as u32 * 1250,
+                    interval_us: u16_at(4) as u32 * 1250,
+                    timeout_us: u16_at(8) as u32 * 10_000,
+                    instant: u16_at(10),
+                });
+            }
+            ll::CHANNEL_MAP_IND if payload.len() >= 8 => {
+                let mut channel_map = [0; 5];
+                channel_map.copy_from_slice(&payload[1..6]);
+                channel_map[4] &= 0x1f;
+                if channel_map
+                    .iter()
+                    .map(|byte| byte.count_ones())
+                    .sum::<u32>()
+                    >= 2
+                {
+                    connection.channel_map_update = Some((channel_map, u16_at(6)));
+                }
+            }
+            ll::TERMINATE_IND => connection.terminated = true,
+            ll::ENC_REQ => self.send(
+                LLID_CONTROL,
+                &[ll::REJECT_IND, ll::UNSUPPORTED_REMOTE_FEATURE],
+            ),
+            ll::FEATURE_REQ => self.send(LLID_CONTROL, &[ll::FEATURE_RSP, 0, 0, 0, 0, 0, 0, 0, 0]),
+            ll::VERSION_IND => {
+                if !connection.version_sent {
+                    connection.version_sent = true;
+                    self.send(
+                        LLID_CONTROL,
+                        &[ll::VERSION_IND, ll::VERSION, 0xff, 0xff, 0, 0],
+                    );
+                }
+            }
+            ll::PING_REQ => self.send(LLID_CONTROL, &[ll::PING_RSP]),
+            ll::LENGTH_REQ => {
+                // Only the lengths of version 4.2 are supported: 27 bytes in
+                // 328 µs.
+                let octets = (MAX_PAYLOAD as u16).to_le_bytes();
+                let time = 328u16.to_le_bytes();
+                self.send(
+                    LLID_CONTROL,
+                    &[
+                        ll::LENGTH_RSP,
+                        octets[0],
+                        octets[1],
+                        time[0],
+                        time[1],
+                        octets[0],
+                        octets[1],
+                        time[0],
+                        time[1],
+                    ],
+                );
+            }
+            ll::UNKNOWN_RSP
+            | ll::FEATURE_RSP
+            | ll::REJECT_IND
+            | ll::REJECT_EXT_IND
+            | ll::PING_RSP
+            | ll::LENGTH_RSP => {}
+            _ => self.send(LLID_CONTROL, &[ll::UNKNOWN_RSP, opcode]),
+        }
+        self.connection.set(connection);
+    }
+
+    fn receive_l2cap(&self, payload: &[u8]) {
+        if payload.len() < 4 {
+            return;
+        }
+        let len = u16::from_le_bytes([payload[0], payload[1]]) as usize;
+        let cid = u16::from_le_bytes([payload[2], payload[3]]);
+        let data = &payload[4..payload.len().min(4 + len)];
+        match cid {
+            CID_ATT => self.receive_att(data),
+            CID_SIGNALING => {
+                if let [code, identifier, ..] = *data {
+                    if !matches!(
+                        code,
+                        SIGNALING_COMMAND_REJECT
+                            | SIGNALING_CONNECTION_PARAMETER_UPDATE_RSP
+                            | SIGNALING_LE_CREDIT_BASED_CONNECTION_RSP
+                    ) {
+                        // Command not understood.
+                        self.send_l2cap(
+                            CID_SIGNALING,
+                            &[SIGNALING_COMMAND_REJECT, identifier, 2, 0, 0, 0],
+                        );
+                    }
+                }
+            }
+            CID_SMP => {
+                if data.first() == Some(&SMP_PAIRING_REQUEST) {
+                    self.send_l2cap(CID_SMP, &[SMP_PAIRING_FAILED, SMP_PAIRING_NOT_SUPPORTED]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn attribute(&self, handle: u16) -> Option<&Attribute<'a>> {
+        (handle as usize)
+            .checked_sub(1)
+            .and_then(|index| self.attributes.get(index))
+    }
+
+    /// Attributes with handles from `start` to `end`, with their handles.
+    fn attributes_in(
+        &self,
+        start: u16,
+        end: u16,
+    ) -> impl Iterator<Item = (u16, &Attribute<'a>)> + '_ {
+        self.attributes
+            .iter()
+            .zip(1..=u16::MAX)
+            .map(|(attribute, handle)| (handle, attribute))
+            .filter(move |(handle, _)| (start..=end).contains(handle))
+    }
+
+    /// Handle of the last attribute of the service declared at `handle`.
+    fn group_end(&self, handle: u16) -> u16 {
+        self.attributes_in(handle + 1, u16::MAX)
+            .find(|(_, attribute)| attribute.uuid == PRIMARY_SERVICE_UUID)
+            .map_or(0xffff, |(next, _)| next - 1)
+    }
+
+    fn att_error(&self, request: u8, handle: u16, error: u8) {
+        let handle = handle.to_le_bytes();
+        self.send_l2cap(
+            CID_ATT,
+            &[att::ERROR_RSP, request, handle[0], handle[1], error],
+        );
+    }
+
+    fn receive_att(&self, request: &[u8]) {
+        let Some(&opcode) = request.first() else {
+            return;
+        };
+        let u16_at = |i: usize| {
+            request
+                .get(i..i + 2)
+                .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+        };
+        let mut response = [0; att::MTU];
+        let mut len = 1;
+        // Append `bytes` to the response if they fit.
+        let push = |response: &mut [u8; att::MTU], len: &mut usize, bytes: &[u8]| {
+            if *len + bytes.len() > att::MTU {
+                return false;
+            }
+            response[*len..*len + bytes.len()].copy_from_slice(bytes);
+            *len += bytes.len();
+            true
+        };
+
+        // Requests on a range of handles.
+        let range = match (u16_at(1), u16_at(3)) {
+            (Some(start), Some(end)) => Some((start, end)),
+            _ => None,
+        };
+        if let (
+            att::FIND_INFORMATION_REQ
+            | att::FIND_BY_TYPE_VALUE_REQ
+            | att::READ_BY_TYPE_REQ
+            | att::READ_BY_GROUP_TYPE_REQ,
+            Some((start, end)),
+        ) = (opcode, range)
+        {
+            if start == 0 || start > end {
+                self.att_error(opcode, start, att::INVALID_HANDLE);
+                return;
+            }
+        }
+
+        match (opcode, range) {
+            (att::EXCHANGE_MTU_REQ, _) => {
+                response[0] = att::EXCHANGE_MTU_RSP;
+                push(&mut response, &mut len, &(att::MTU as u16).to_le_bytes());
+            }
+            (att::FIND_INFORMATION_REQ, Some((start, end))) => {
+                // Format of 16 bit UUIDs.
+                response[0] = att::FIND_INFORMATION_RSP;
+                push(&mut response, &mut len, &[0x01]);
+                for (handle, attribute) in self.attributes_in(start, end) {
+                    let handle = handle.to_le_bytes();
+                    let uuid = attribute.uuid.to_le_bytes();
+                    if !push(
+                        &mut response,
+                        &mut len,
+                        &[handle[0], handle[1], uuid[0], uuid[1]],
+                    ) {
+                        break;
+                    }
+                }
+            }
+            (att::FIND_BY_TYPE_VALUE_REQ, Some((start, end))) => {
+                response[0] = att::FIND_BY_TYPE_VALUE_RSP;
+                let uuid = u16_at(5).unwrap_or(0);
+                let value = request.get(7..).unwrap_or(&[]);
+                for (handle, _) in self
+                    .attributes_in(start, end)
+                    .filter(|(_, attribute)| attribute.uuid == uuid && attribute.value == value)
+                {
+                    let found = handle.to_le_bytes();
+                    let group_end = if uuid == PRIMARY_SERVICE_UUID {
+                        self.group_end(handle)
+                    } else {
+                        handle
+                    }
+                    .to_le_bytes();
+                    if !push(
+                        &mut response,
+                        &mut len,
+                        &[found[0], found[1], group_end[0], group_end[1]],
+                    ) {
+                        break;
+                    }
+                }
+            }
+            (att::READ_BY_TYPE_REQ | att::READ_BY_GROUP_TYPE_REQ, Some((start, end))) => {
+                let group = opcode == att::READ_BY_GROUP_TYPE_REQ;
+                // Only 16 bit UUIDs are used by the attributes.
+                let uuid = if request.len() == 7 { u16_at(5) } else { None };
+                if group && uuid != Some(PRIMARY_SERVICE_UUID) {
+                    self.att_error(opcode, start, att::UNSUPPORTED_GROUP_TYPE);
+                    return;
+                }
+                response[0] = if group {
+                    att::READ_BY_GROUP_TYPE_RSP
+                } else {
+                    att::READ_BY_TYPE_RSP
+                };
+                // Entries all have the length of the first one.
+                let header_len = if group { 4 } else { 2 };
+                let mut entry_len = None;
+                for (handle, attribute) in self
+                    .attributes_in(start, end)
+                    .filter(|(_, attribute)| Some(attribute.uuid) == uuid)
+                {
+                    let value_len = attribute.value.len().min(att::MTU - 2 - header_len);
+                    let value_len = *entry_len.get_or_insert(value_len);
+                    if len == 1 {
+                        push(&mut response, &mut len, &[(header_len + value_len) as u8]);
+                    }
+                    if attribute.value.len().min(att::MTU - 2 - header_len) != value_len
+                        || len + header_len + value_len > att::MTU
+                    {
+                        break;
+                    }
+                    push(&mut response, &mut len, &handle.to_le_bytes());
+                    if group {
+                        push(
+                            &mut response,
+                            &mut len,
+                            &self.group_end(handle).to_le_bytes(),
+                        );
+                    }
+                    push(&mut response, &mut len, &attribute.value[..value_len]);
+                }
+                if len == 1 {
+                    self.att_error(opcode, start, att::ATTRIBUTE_NOT_FOUND);
+                    return;
+                }
+            }
+            (att::READ_REQ | att::READ_BLOB_REQ, _) => {
+                let handle = u16_at(1).unwrap_or(0);
+                let Some(attribute) = self.attribute(handle) else {
+                    self.att_error(opcode, handle, att::INVALID_HANDLE);
+                    return;
+                };
+                let offset = if opcode == att::READ_BLOB_REQ {
+                    u16_at(3).unwrap_or(0) as usize
+                } else {
+                    0
+                };
+                if offset > attribute.value.len() {
+                    self.att_error(opcode, handle, att::INVALID_OFFSET);
+                    return;
+                }
+                response[0] = if opcode == att::READ_BLOB_REQ {
+                    att::READ_BLOB_RSP
+                } else {
+                    att::READ_RSP
+                };
+                let value = &attribute.value[offset..];
+                let value_len = value.len().min(att::MTU - 1);
+                push(&mut response, &mut len, &value[..value_len]);
+            }
+            (att::WRITE_REQ, _) => {
+                let handle = u16_at(1).unwrap_or(0);
+                let error = if self.attribute(handle).is_some() {
+                    att::WRITE_NOT_PERMITTED
+                } else {
+                    att::INVALID_HANDLE
+                };
+                self.att_error(opcode, handle, error);
+                return;
+            }
+            (
+                att::FIND_INFORMATION_REQ
+                | att::FIND_BY_TYPE_VALUE_REQ
+                | att::READ_BY_TYPE_REQ
+                | att::READ_BY_GROUP_TYPE_REQ,
+                None,
+            ) => {
+                self.att_error(opcode, 0, att::INVALID_PDU);
+                return;
+            }
+            _ => {
+                // Responses, which have odd opcodes, confirmations and
+                // commands are not answered.
+                if opcode & 0x01 == 0
+                    && opcode & att::COMMAND == 0
+                    && opcode != att::HANDLE_VALUE_CFM
+                {
+                    self.att_error(opcode, 0, att::REQUEST_NOT_SUPPORTED);
+                }
+                return;
+            }
+        }
+
+        let found = match opcode {
+            att::FIND_INFORMATION_REQ => len > 2,
+            att::FIND_BY_TYPE_VALUE_REQ => len > 1,
+            _ => true,
+        };
+        if !found {
+            self.att_error(opcode, u16_at(1).unwrap_or(0), att::ATTRIBUTE_NOT_FOUND);
+        } else {
+            self.send_l2cap(CID_ATT, &response[..len]);
+        }
+    }
+}
+
+impl<'a, R: BleConnectionRadio<'a>, A: Alarm<'a>> ConnectionClient for BlePeripheral<'a, R, A> {
+    fn advertisement_done(&self, buf: &'static mut [u8], request: Option<&[u8]>) {
+        self.buffer.replace(buf);
+        if let State::Advertising(channel) = self.state.get() {
+            if !request.is_some_and(|request| self.connect(request)) {
+                self.next_advertisement(channel);
+            }
+        }
+    }
+
+    fn packet_received(&self, packet: &[u8], crc_valid: bool, response: &mut [u8]) {
+        if self.state.get() != State::ConnectionListen {
+            return;
+        }
+        self.state.set(State::ConnectionResponse);
+        let _ = self.alarm.disarm();
+        if !crc_valid || packet.len() < 2 {
+            return;
+        }
+
+        // The anchor point is the start of the packet.
+        let now = self.alarm.now();
+        let air_time_us = (1 + 4 + packet.len() as u32 + 3) * 8;
+        self.anchor
+            .set(now.wrapping_sub(self.alarm.ticks_from_us(air_time_us)));
+        self.events_since_anchor.set(0);
+        self.window_us.set(0);
+        self.last_received.set(now);
+
+        let mut connection = self.connection.get();
+        connection.established = true;
+        let header = packet[0];
+        if (header & NESN != 0) != connection.sn {
+            // The central acknowledged the last packet.
+            connection.sn = !connection.sn;
+            self.outgoing.set(None);
+        }
+        let new = (header & SN != 0) == connection.nesn;
+        if new {
+            connection.nesn = !connection.nesn;
+        }
+        self.connection.set(connection);
+
+        if new {
+            let payload = &packet[2..];
+            match header & 0x03 {
+                LLID_CONTROL => self.receive_control(payload),
+                LLID_START => self.receive_l2cap(payload),
+                _ => {}
+            }
+        }
+        self.fill_response(response);
+    }
+
+    fn connection_event_done(&self, response: &'static mut [u8]) {
+        self.buffer.replace(response);
+        if self.state.get() == State::ConnectionResponse {
+            self.end_event();
+        }
+    }
+}
+
+impl<'a, R: BleConnectionRadio<'a>, A: Alarm<'a>> AlarmClient for BlePeripheral<'a, R, A> {
+    fn alarm(&self) {
+        match self.state.get() {
+            State::Advertising(channel) => {
+                // A packet being received ends with `advertisement_done`.
+                if let Some(buf) = self.radio.stop() {
+                    self.buffer.replace(buf);
+                    self.next_advertisement(channel);
+                }
+            }
+            State::AdvertisingWait => self.advertise(0),
+            State::ConnectionWait => self.start_event(),
+            State::ConnectionListen => {
+                // No packet was received in this event, and one being
+                // received ends with `connection_event_done`.
+                if let Some(buf) = self.radio.stop() {
+                    self.buffer.replace(buf);
+                    self.end_event();
+                }
+            }
+            State::Idle | State::ConnectionResponse => {}
+        }
+    }
+}
@@ -28,6 +28,16 @@
 //! - if it's greater the 0, the message will be copied to the RW buffer
 //!   but no upcall will be done
 //!
+//! Several processes can use the capsule at the same time:
+//! - The peripheral is configured, enabled and disabled by a single process
+//!   at a time, the one that configured it first. It stays reserved to that
+//!   process until it disables the peripheral.
+//! - Any process can send messages. They are sent one after the other, in
+//!   the order of the processes.
+//! - Any process can receive messages. Each of them has a filter, and only
+//!   receives the messages whose identifier matches it. The hardware receives
+//!   while at least one process does.
+//!
 //! Usage
 //! -----
 //!
@@ -107,8 +117,16 @@ pub struct CanCapsule<'a, Can: can::Can> {
         AllowRoCount<{ ro_allow::COUNT }>,
         AllowRwCount<{ rw_allow::COUNT }>,
     >,
+    // Process that configures the peripheral.
     processid: OptionalCell<ProcessId>,
 
+    // Process whose message is being sent.
+    tx_processid: OptionalCell<ProcessId>,
+
+    // Process that stopped receiving last, which is notified once the
+    // hardware stopped.
+    rx_stopping_processid: OptionalCell<ProcessId>,
+
     // Variable used to store the current state of the CAN peripheral
     // during an `enable` or `disable` command.
     peripheral_state: OptionalCell<can::State>,
@@ -117,6 +135,26 @@ pub struct CanCapsule<'a, Can: can::Can> {
 #[derive(Default)]
 pub struct App {
     lost_messages: u32,
+    // Whether the process receives messages.
+    receiving: bool,
+    // Identifier and mask of the receive filter of the process. A message
+    // matches if its identifier has the same value as the filter identifier
+    // for all bits set in the mask. All messages match when there is no
+    // filter.
+    filter: Option<(u32, u32)>,
+    // Message waiting to be sent, while the message of another process is.
+    pending_tx: Option<(can::Id, usize)>,
+}
+
+impl App {
+    fn accepts(&self, id: can::Id) -> bool {
+        let id = match id {
+            can::Id::Standard(id) => id as u32,
+            can::Id::Extended(id) => id,
+        };
+        self.filter
+            .map_or(true, |(filter_id, mask)| id & mask == filter_id & mask)
+    }
 }
 
 impl<'a, Can: can::Can> CanCapsule<'a, Can> {
@@ -138,19 +176,87 @@ impl<'a, Can: can::Can> CanCapsule<'a, Can> {
             processes: grant,
             peripheral_state: OptionalCell::empty(),
             processid: OptionalCell::empty(),
+            tx_processid: OptionalCell::empty(),
+            rx_stopping_processid: OptionalCell::empty(),
         }
     }
 
     fn schedule_callback(&self, callback_number: usize, data: (usize, usize, usize)) {
         self.processid.map(|processid| {
-            let _ = self.processes.enter(processid, |_app, kernel_data| {
-                kernel_data
-                    .schedule_upcall(callback_number, (data.0, data.1, data.2))
-                    .ok();
-            });
+            self.schedule_process_callback(processid, callback_number, data);
         });
     }
 
+    fn schedule_process_callback(
+        &self,
+        processid: ProcessId,
+        callback_number: usize,
+        data: (usize, usize, usize),
+    ) {
+        let _ = self.processes.enter(processid, |_app, kernel_data| {
+            kernel_data
+                .schedule_upcall(callback_number, (data.0, data.1, data.2))
+                .ok();
+        });
+    }
+
+    /// Send the message of `processid` now if no other message is being
+    /// sent, otherwise after the messages that are waiting.
+    fn send_or_queue(&self, processid: ProcessId, id: can::Id, length: usize) -> CommandReturn {
+        let res = self
+            .processes
+            .enter(processid, |app, _| {
+                if app.pending_tx.is_some() || self.tx_processid.contains(&processid) {
+                    Err(ErrorCode::BUSY)
+                } else if self.tx_processid.is_some() {
+                    app.pending_tx = Some((id, length));
+                    Ok(false)
+                } else {
+                    Ok(true)
+                }
+            })
+            .unwrap_or_else(|err| Err(err.into()));
+        match res {
+            Ok(true) => match self.process_send_command(processid, id, length) {
+                Ok(()) => {
+                    self.tx_processid.set(processid);
+                    CommandReturn::success()
+                }
+                Err(err) => CommandReturn::failure(err),
+            },
+            Ok(false) => CommandReturn::success(),
+            Err(err) => CommandReturn::failure(err),
+        }
+    }
+
+    /// Send the next message that is waiting, if any.
+    fn send_next_pending(&self) {
+        for cntr in self.processes.iter() {
+            let processid = cntr.processid();
+            let pending = cntr.enter(|app, _| app.pending_tx.take());
+            if let Some((id, length)) = pending {
+                match self.process_send_command(processid, id, length) {
+                    Ok(()) => {
+                        self.tx_processid.set(processid);
+                        break;
+                    }
+                    Err(err) => self.schedule_process_callback(
+                        processid,
+                        up_calls::UPCALL_TRANSMISSION_ERROR,
+                        (error_upcalls::ERROR_TX, err as usize, 0),
+                    ),
+                }
+            }
+        }
+    }
+
+    /// Whether a process other than `processid` receives messages.
+    fn others_receiving(&self, processid: ProcessId) -> bool {
+        self.processes
+            .iter()
+            .any(|cntr| cntr.processid() != processid && cntr.enter(|app, _| app.receiving))
+    }
+
     /// This function makes a copy of the buffer in the grant and sends it
     /// to the low-level hardware, in order for it to be sent on the bus.
     pub fn process_send_command(
@@ -213,13 +319,14 @@ impl<Can: can::Can> SyscallDriver for CanCapsule<'_, Can> {
             return CommandReturn::success();
         }
 
-        // Check to see if the process or no process at all
-        // owns the capsule. Only one application can use the
-        // capsule at a time.
-        if !self.is_valid_process(processid) {
-            return CommandReturn::failure(ErrorCode::RESERVE);
-        } else {
-            self.processid.set(processid);
+        // The configuration of the peripheral is owned by a single process.
+        // Check to see if the process or no process at all owns it.
+        if matches!(command_num, 1..=4 | 9) {
+            if !self.is_valid_process(processid) {
+                return CommandReturn::failure(ErrorCode::RESERVE);
+            } else {
+                self.processid.set(processid);
+            }
         }
 
         match command_num {
@@ -255,38 +362,49 @@ impl<Can: can::Can> SyscallDriver for CanCapsule<'_, Can> {
             },
 
             // Send a message with a 16-bit identifier
-            5 => {
-                let id = can::Id::Standard(arg1 as u16);
-                self.processid
-                    .map_or(
-                        CommandReturn::failure(ErrorCode::BUSY),
-                        |processid| match self.process_send_command(processid, id, arg2) {
-                            Ok(()) => CommandReturn::success(),
-                            Err(err) => CommandReturn::failure(err),
-                        },
-                    )
-            }
+            5 => self.send_or_queue(processid, can::Id::Standard(arg1 as u16), arg2),
 
             // Send a message with a 32-bit identifier
-            6 => {
-                let id = can::Id::Extended(arg1 as u32);
-                self.processid
-                    .map_or(
-                        CommandReturn::failure(ErrorCode::BUSY),
-                        |processid| match self.process_send_command(processid, id, arg2) {
-                            Ok(()) => CommandReturn::success(),
-                            Err(err) => CommandReturn::failure(err),
-                        },
-                    )
-            }
+            6 => self.send_or_queue(processid, can::Id::Extended(arg1 as u32), arg2),
 
             // Start receiving messages
             7 => {
+                let receiving = self
+                    .processes
+                    .enter(processid, |app, _| app.receiving)
+                    .unwrap_or(false);
+                if receiving {
+                    return CommandReturn::failure(ErrorCode::ALREADY);
+                }
+                if self.others_receiving(processid) {
+                    // The hardware is already receiving, the process only
+                    // needs a large enough buffer.
+                    return self
+                        .processes
+                        .enter(processid, |app, kernel| {
+                            let res = kernel
+                                .get_readwrite_processbuffer(rw_allow::RW_ALLOW_BUFFER)
+                                .map_or(Err(ErrorCode::NOMEM), |buffer| {
+                                    if buffer.len()
+                                        >= 2 * can::STANDARD_CAN_PACKET_SIZE + size_of::<u32>()
+                                    {
+                                        Ok(())
+                                    } else {
+                                        Err(ErrorCode::SIZE)
+                                    }
+                                });
+                            if res.is_ok() {
+                                app.receiving = true;
+                            }
+                            CommandReturn::from(res)
+                        })
+                        .unwrap_or_else(|err| err.into());
+                }
                 self.can_rx
                     .take()
-                    .map_or(CommandReturn::failure(ErrorCode::NOMEM), |dest_buffer| {
+                    .map_or(CommandReturn::failure(ErrorCode::BUSY), |dest_buffer| {
                         self.processes
-                            .enter(processid, |_, kernel| {
+                            .enter(processid, |app, kernel| {
                                 match kernel.get_readwrite_processbuffer(0).map_or_else(
                                     |err| err.into(),
                                     |buffer_ref| {
@@ -307,10 +425,19 @@ impl<Can: can::Can> SyscallDriver for CanCapsule<'_, Can> {
                                     },
                                 ) {
                                     Ok(()) => match self.can.start_receive_process(dest_buffer) {
-                                        Ok(()) => CommandReturn::success(),
-                                        Err((err, _)) => CommandReturn::failure(err),
+                                        Ok(()) => {
+                                            app.receiving = true;
+                                            CommandReturn::success()
+                                        }
+                                        Err((err, buf)) => {
+                                            self.can_rx.replace(buf);
+                                            CommandReturn::failure(err)
+                                        }
                                     },
-                                    Err(err) => CommandReturn::failure(err),
+                                    Err(err) => {
+                                        self.can_rx.replace(dest_buffer);
+                                        CommandReturn::failure(err)
+                                    }
                                 }
                             })
                             .unwrap_or_else(|err| err.into())
@@ -318,10 +445,38 @@ impl<Can: can::Can> SyscallDriver for CanCapsule<'_, Can> {
             }
 
             // Stop receiving messages
-            8 => match self.can.stop_receive() {
-                Ok(()) => CommandReturn::success(),
-                Err(err) => CommandReturn::failure(err),
-            },
+            8 => {
+                let receiving = self
+                    .processes
+                    .enter(processid, |app, _| {
+                        core::mem::replace(&mut app.receiving, false)
+                    })
+                    .unwrap_or(false);
+                if !receiving {
+                    CommandReturn::failure(ErrorCode::ALREADY)
+                } else if self.others_receiving(processid) {
+                    // The hardware keeps receiving for the other processes.
+                    self.schedule_process_callback(
+                        processid,
+                        up_calls::UPCALL_RECEIVED_STOPPED,
+                        (0, 0, 0),
+                    );
+                    CommandReturn::success()
+                } else {
+                    match self.can.stop_receive() {
+                        Ok(()) => {
+                            self.rx_stopping_processid.set(processid);
+                            CommandReturn::success()
+                        }
+                        Err(err) => {
+                            let _ = self
+                                .processes
+                                .enter(processid, |app, _| app.receiving = true);
+                            CommandReturn::failure(err)
+                        }
+                    }
+                }
+            }
 
             // Set the timing parameters
             9 => {
@@ -337,6 +492,24 @@ impl<Can: can::Can> SyscallDriver for CanCapsule<'_, Can> {
                 }
             }
 
+            // Set the receive filter of the process
+            10 => self
+                .processes
+                .enter(processid, |app, _| {
+                    app.filter = Some((arg1 as u32, arg2 as u32));
+                    CommandReturn::success()
+                })
+                .unwrap_or_else(|err| err.into()),
+
+            // Remove the receive filter of the process
+            11 => self
+                .processes
+                .enter(processid, |app, _| {
+                    app.filter = None;
+                    CommandReturn::success()
+                })
+                .unwrap_or_else(|err| err.into()),
+
             _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
         }
     }
@@ -420,15 +593,23 @@ impl<Can: can::Can> can::TransmitClient<{ can::STANDARD_CAN_PACKET_SIZE }> for C
         buffer: &'static mut [u8; can::STANDARD_CAN_PACKET_SIZE],
     ) {
         self.can_tx.replace(buffer);
-        match status {
-            Ok(()) => self.schedule_callback(up_calls::UPCALL_MESSAGE_SENT, (0, 0, 0)),
-            Err(err) => {
-                self.schedule_callback(
-                    up_calls::UPCALL_TRANSMISSION_ERROR,
-                    (error_upcalls::ERROR_TX, err as usize, 0),
-                );
+        if let Some(processid) = self.tx_processid.take() {
+            match status {
+                Ok(()) => self.schedule_process_callback(
+                    processid,
+                    up_calls::UPCALL_MESSAGE_SENT,
+                    (0, 0, 0),
+                ),
+                Err(err) => {
+                    self.schedule_process_callback(
+                        processid,
+                        up_calls::UPCALL_TRANSMISSION_ERROR,
+                        (error_upcalls::ERROR_TX, err as usize, 0),
+                    );
+                }
             }
         }
+        self.send_next_pending();
     }
 }
 
@@ -444,10 +625,14 @@ impl<Can: can::Can> can::ReceiveClient<{ can::STANDARD_CAN_PACKET_SIZE }> for Ca
     ) {
         match status {
             Ok(()) => {
-                let res: Result<(bool, u32), ErrorCode> =
-                    self.processid.map_or(Err(ErrorCode::NOMEM), |processid| {
-                        self.processes
-                            .enter(processid, |app_data, kernel_data| {
+                for cntr in self.processes.iter() {
+                    let processid = cntr.processid();
+                    let res: Option<Result<(bool, u32), ErrorCode>> =
+                        cntr.enter(|app_data, kernel_data| {
+                            if !app_data.receiving || !app_data.accepts(id) {
+                                return None;
+                            }
+                            Some(
                                 kernel_data
                                     .get_readwrite_processbuffer(rw_allow::RW_ALLOW_BUFFER)
                                     .map_or_else(
@@ -463,41 +648,54 @@ impl<Can: can::Can> can::ReceiveClient<{ can::STANDARD_CAN_PACKET_SIZE }> for Ca
                                                 })
                                                 .unwrap_or_else(|err| Err(err.into()))
                                         },
-                                    )
-                            })
-                            .unwrap_or_else(|err| Err(err.into()))
-                    });
-
-                match res {
-                    Err(err) => self.schedule_callback(
-                        up_calls::UPCALL_TRANSMISSION_ERROR,
-                        (error_upcalls::ERROR_RX, err as usize, 0),
-                    ),
-                    Ok((_first_chunk, new_offset)) => self.schedule_callback(
-                        up_calls::UPCALL_MESSAGE_RECEIVED,
-                        (
-                            0,
-                            new_offset as usize,
-                            match id {
-                                can::Id::Standard(u16) => u16 as usize,
-                                can::Id::Extended(u32) => u32 as usize,
-                            },
+                                    ),
+                            )
+                        });
+
+                    match res {
+                        None => {}
+                        Some(Err(err)) => self.schedule_process_callback(
+                            processid,
+                            up_calls::UPCALL_TRANSMISSION_ERROR,
+                            (error_upcalls::ERROR_RX, err as usize, 0),
                         ),
-                    ),
+                        Some(Ok((_first_chunk, new_offset))) => self.schedule_process_callback(
+                            processid,
+                            up_calls::UPCALL_MESSAGE_RECEIVED,
+                            (
+                                0,
+                                new_offset as usize,
+                                match id {
+                                    can::Id::Standard(u16) => u16 as usize,
+                                    can::Id::Extended(u32) => u32 as usize,
+                                },
+                            ),
+                        ),
+                    }
                 }
             }
             Err(err) => {
                 let kernel_err: ErrorCode = err.into();
-                self.schedule_callback(
-                    up_calls::UPCALL_TRANSMISSION_ERROR,
-                    (error_upcalls::ERROR_RX, kernel_err.into(), 0),
-                )
+                for cntr in self.processes.iter() {
+                    cntr.enter(|app_data, kernel_data| {
+                        if app_data.receiving {
+                            kernel_data
+                                .schedule_upcall(
+                                    up_calls::UPCALL_TRANSMISSION_ERROR,
+                                    (error_upcalls::ERROR_RX, kernel_err.into(), 0),
+                                )
+                                .ok();
+                        }
+                    });
+                }
             }
         };
     }
 
     fn stopped(&self, buffer: &'static mut [u8; can::STANDARD_CAN_PACKET_SIZE]) {
         self.can_rx.replace(buffer);
-        self.schedule_callback(up_calls::UPCALL_RECEIVED_STOPPED, (0, 0, 0));
+        self.rx_stopping_processid.take().map(|processid| {
+            self.schedule_process_callback(processid, up_calls::UPCALL_RECEIVED_STOPPED, (0, 0, 0))
+        });
     }
 }
@@ -0,0 +1,575 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Typed configuration records in the KV store.
+//!
+//! Applications keep their settings, such as network identifiers, sampling
+//! intervals or feature flags, in configuration records identified by a 32
+//! bit record number. Each record has a type, which the service checks when
+//! the record is written, and a schema version chosen by the writer, which
+//! readers use to tell which layout the record has.
+//!
+//! Records are stored in the KV store, under the key `"cfg:"` followed by the
+//! record number (little endian), with a value starting with a 4 byte record
+//! header:
+//!
+//! | Offset | Length | Content                          |
+//! |--------|--------|----------------------------------|
+//! | 0      | 1      | Type, see [`ConfigType`]         |
+//! | 1      | 1      | Reserved, 0                      |
+//! | 2      | 2      | Schema version, little endian    |
+//! | 4      | ...    | Value                            |
+//!
+//! Access goes through the storage permissions of the processes, as for the
+//! KV driver: a process can read a record written by a process whose write ID
+//! it is allowed to read, and overwrite a record it is allowed to modify.
+//!
+//! A process can watch up to [`MAX_WATCHES`] records. When a record is
+//! written or deleted, the watching processes that can read the record are
+//! notified, so they can apply the new settings without a reboot.
+//!
+//! ```rust,ignore
+//! +===============+
+//! ||  Userspace  ||
+//! +===============+
+//!
+//! -----Syscall Interface-----
+//!
+//! +------------------------------+
+//! |  Config service (this file)  |
+//! +------------------------------+
+//!
+//!    hil::kv::KVPermissions
+//!
+//! +------------------------------+
+//! | Virtualizer                  |
+//! +------------------------------+
+//! ```
+
+use capsules_core::driver;
+/// Syscall driver number.
+pub const DRIVER_NUM: usize = driver::NUM::ConfigService as usize;
+
+use core::cmp;
+use kernel::errorcode;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::kv;
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::{ErrorCode, ProcessId};
+
+/// Prefix of the KV keys of configuration records.
+const KEY_PREFIX: &[u8; 4] = b"cfg:";
+/// Length of the KV keys of configuration records.
+pub const KEY_LEN: usize = 8;
+/// Length of the record header before the value.
+pub const RECORD_HEADER_LEN: usize = 4;
+/// Number of records each process can watch.
+pub const MAX_WATCHES: usize = 4;
+/// Type reported in change notifications of deleted records.
+const DELETED: usize = 0xff;
+
+/// IDs for subscribed upcalls.
+mod upcall {
+    /// Operation done. Arguments are the status code and, for reads, the
+    /// length of the value and the type and schema version of the record.
+    pub const DONE: usize = 0;
+    /// A watched record changed. Arguments are the record number, and the
+    /// type and schema version of the new record.
+    pub const CHANGED: usize = 1;
+    pub const COUNT: u8 = 2;
+}
+
+/// Ids for read-only allow buffers.
+mod ro_allow {
+    /// Value to write.
+    pub const VALUE: usize = 0;
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for read-write allow buffers.
+mod rw_allow {
+    /// Value read.
+    pub const VALUE: usize = 0;
+    pub const COUNT: u8 = 1;
+}
+
+/// Type of a configuration record.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ConfigType {
+    /// Any sequence of bytes, for example a network name.
+    Bytes = 0,
+    /// A 32 bit unsigned integer, little endian, for example an interval.
+    U32 = 1,
+    /// A flag, one byte which is 0 or 1.
+    Bool = 2,
+}
+
+impl ConfigType {
+    fn from_u8(value: u8) -> Option<ConfigType> {
+        match value {
+            0 => Some(ConfigType::Bytes),
+            1 => Some(ConfigType::U32),
+            2 => Some(ConfigType::Bool),
+            _ => None,
+        }
+    }
+
+    /// Whether `value` is a valid value of this type.
+    fn accepts(self, value: &[u8]) -> bool {
+        match self {
+            ConfigType::Bytes => true,
+            ConfigType::U32 => value.len() == 4,
+            ConfigType::Bool => value.len() == 1 && value[0] <= 1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Op {
+    Read(u32),
+    Write {
+        record: u32,
+        config_type: ConfigType,
+        schema: u16,
+    },
+    Delete(u32),
+}
+
+impl Op {
+    fn record(self) -> u32 {
+        match self {
+            Op::Read(record) | Op::Delete(record) => record,
+            Op::Write { record, .. } => record,
+        }
+    }
+}
+
+/// Contents of the grant for each app.
+#[derive(Default)]
+pub struct App {
+    /// Operation requested and not complete yet.
+    op: Option<Op>,
+    watches: [Option<u32>; MAX_WATCHES],
+}
+
+/// Capsule that keeps typed configuration records in a key-value store.
+pub struct ConfigService<'a, V: kv::KVPermissions<'a>> {
+    /// Underlying k-v store implementation.
+    kv: &'a V,
+    /// Grant storage for each app.
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+    /// App whose operation is running.
+    processid: OptionalCell<ProcessId>,
+    /// Key buffer, at least `KEY_LEN` bytes long.
+    key_buffer: TakeCell<'static, [u8]>,
+    /// Value buffer, holding the KV header, the record header and the value.
+    value_buffer: TakeCell<'static, [u8]>,
+}
+
+impl<'a, V: kv::KVPermissions<'a>> ConfigService<'a, V> {
+    pub fn new(
+        kv: &'a V,
+        key_buffer: &'static mut [u8],
+        value_buffer: &'static mut [u8],
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+    ) -> ConfigService<'a, V> {
+        ConfigService {
+            kv,
+            apps: grant,
+            processid: OptionalCell::empty(),
+            key_buffer: TakeCell::new(key_buffer),
+            value_buffer: TakeCell::new(value_buffer),
+        }
+    }
+
+    /// Start the pending operation of the app in `processid`.
+    fn run(&self) -> Result<(), ErrorCode> {
+        let processid = self.processid.get().ok_or(ErrorCode::FAIL)?;
+        let perms = processid
+            .get_storage_permissions()
+            .ok_or(ErrorCode::INVAL)?;
+        let op = self
+            .apps
+            .enter(processid, |app, _| app.op)?
+            .ok_or(ErrorCode::FAIL)?;
+
+        let key_buf = self.key_buffer.take().ok_or(ErrorCode::BUSY)?;
+        if key_buf.len() < KEY_LEN {
+            self.key_buffer.replace(key_buf);
+            return Err(ErrorCode::SIZE);
+        }
+        key_buf[..KEY_PREFIX.len()].copy_from_slice(KEY_PREFIX);
+        key_buf[KEY_PREFIX.len()..KEY_LEN].copy_from_slice(&op.record().to_le_bytes());
+        let mut key = SubSliceMut::new(key_buf);
+        key.slice(..KEY_LEN);
+
+        if let Op::Delete(_) = op {
+            return self.kv.delete(key, perms).map_err(|(key, e)| {
+                self.key_buffer.replace(key.take());
+                e
+            });
+        }
+
+        let Some(value_buf) = self.value_buffer.take() else {
+            self.key_buffer.replace(key.take());
+            return Err(ErrorCode::BUSY);
+        };
+        let mut value_len = value_buf.len();
+
+        if let Op::Write {
+            config_type,
+            schema,
+            ..
+        } = op
+        {
+            // Leave room for the KV header before the record.
+            let header = self.kv.header_size();
+            let start = header + RECORD_HEADER_LEN;
+            let copied = self
+                .apps
+                .enter(processid, |_, kernel_data| {
+                    kernel_data
+                        .get_readonly_processbuffer(ro_allow::VALUE)
+                        .and_then(|buffer| {
+                            buffer.enter(|appslice| {
+                                if value_buf.len() < start + appslice.len() {
+                                    Err(ErrorCode::SIZE)
+                                } else {
+                                    appslice.copy_to_slice(
+                                        &mut value_buf[start..start + appslice.len()],
+                                    );
+                                    Ok(appslice.len())
+                                }
+                            })
+                        })
+                        .unwrap_or(Err(ErrorCode::RESERVE))
+                })
+                .unwrap_or_else(|err| Err(err.into()))
+                .and_then(|len| {
+                    if config_type.accepts(&value_buf[start..start + len]) {
+                        Ok(len)
+                    } else {
+                        Err(ErrorCode::INVAL)
+                    }
+                });
+            match copied {
+                Ok(len) => value_len = start + len,
+                Err(e) => {
+                    self.key_buffer.replace(key.take());
+                    self.value_buffer.replace(value_buf);
+                    return Err(e);
+                }
+            }
+            value_buf[header] = config_type as u8;
+            value_buf[header + 1] = 0;
+            value_buf[header + 2..start].copy_from_slice(&schema.to_le_bytes());
+        }
+
+        let mut value = SubSliceMut::new(value_buf);
+        value.slice(..value_len);
+        match op {
+            Op::Write { .. } => self.kv.set(key, value, perms),
+            _ => self.kv.get(key, value, perms),
+        }
+        .map_err(|(key, value, e)| {
+            self.key_buffer.replace(key.take());
+            self.value_buffer.replace(value.take());
+            e
+        })
+    }
+
+    /// Signal the end of the running operation to its app, and start the next
+    /// pending operation.
+    fn complete(&self, result: Result<(), ErrorCode>, len: usize, description: usize) {
+        self.processid.take().map(|processid| {
+            self.apps.enter(processid, |app, kernel_data| {
+                app.op = None;
+                kernel_data
+                    .schedule_upcall(
+                        upcall::DONE,
+                        (errorcode::into_statuscode(result), len, description),
+                    )
+                    .ok();
+            })
+        });
+        self.check_queue();
+    }
+
+    fn check_queue(&self) {
+        // If an app is already running let it complete.
+        if self.processid.is_some() {
+            return;
+        }
+
+        for appiter in self.apps.iter() {
+            let processid = appiter.processid();
+            if !appiter.enter(|app, _| app.op.is_some()) {
+                continue;
+            }
+            self.processid.set(processid);
+            match self.run() {
+                Ok(()) => break,
+                // The operation cannot run, so it fails and the next app
+                // gets its turn.
+                Err(e) => {
+                    self.processid.clear();
+                    let _ = self.apps.enter(processid, |app, kernel_data| {
+                        app.op = None;
+                        kernel_data
+                            .schedule_upcall(
+                                upcall::DONE,
+                                (errorcode::into_statuscode(Err(e)), 0, 0),
+                            )
+                            .ok();
+                    });
+                }
+            }
+        }
+    }
+
+    /// Notify the apps watching the record changed by the running operation,
+    /// other than the app that changed it, if they can read what it writes.
+    fn notify_change(&self) {
+        let Some(writer) = self.processid.get() else {
+            return;
+        };
+        let (record, description) = match self.apps.enter(writer, |app, _| app.op) {
+            Ok(Some(Op::Write {
+                record,
+                config_type,
+                schema,
+            })) => (record, config_type as usize | (schema as usize) << 8),
+            Ok(Some(Op::Delete(record))) => (record, DELETED),
+            _ => return,
+        };
+        let Some(writer_id) = writer
+            .get_storage_permissions()
+            .and_then(|perms| perms.get_write_id())
+        else {
+            return;
+        };
+
+        for appiter in self.apps.iter() {
+            let processid = appiter.processid();
+            let can_read = processid
+                .get_storage_permissions()
+                .is_some_and(|perms| perms.check_read_permission(writer_id));
+            if processid == writer || !can_read {
+                continue;
+            }
+            appiter.enter(|app, kernel_data| {
+                if app.watches.contains(&Some(record)) {
+                    kernel_data
+                        .schedule_upcall(upcall::CHANGED, (record as usize, description, 0))
+                        .ok();
+                }
+            });
+        }
+    }
+
+    fn request(&self, processid: ProcessId, op: Op) -> Result<(), ErrorCode> {
+        self.apps.enter(processid, |app, _| {
+            if app.op.is_some() {
+                Err(ErrorCode::BUSY)
+            } else {
+                app.op = Some(op);
+                Ok(())
+            }
+        })??;
+
+        if self.processid.is_none() {
+            // Nothing is using the KV store, so we can handle this request.
+            self.processid.set(processid);
+            if let Err(e) = self.run() {
+                self.processid.clear();
+                let _ = self.apps.enter(processid, |app, _| app.op = None);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    fn watch(&self, processid: ProcessId, record: u32) -> Result<(), ErrorCode> {
+        self.apps.enter(processid, |app, _| {
+            if app.watches.contains(&Some(record)) {
+                return Err(ErrorCode::ALREADY);
+            }
+            let free = app
+                .watches
+                .iter_mut()
+                .find(|watch| watch.is_none())
+                .ok_or(ErrorCode::NOMEM)?;
+            *free = Some(record);
+            Ok(())
+        })?
+    }
+
+    fn unwatch(&self, processid: ProcessId, record: u32) -> Result<(), ErrorCode> {
+        self.apps.enter(processid, |app, _| {
+            let watch = app
+                .watches
+                .iter_mut()
+                .find(|watch| **watch == Some(record))
+                .ok_or(ErrorCode::INVAL)?;
+            *watch = None;
+            Ok(())
+        })?
+    }
+}
+
+impl<'a, V: kv::KVPermissions<'a>> kv::KVClient for ConfigService<'a, V> {
+    fn get_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.key_buffer.replace(key.take());
+
+        // The KV header is already removed from `value`.
+        let mut len = 0;
+        let mut description = 0;
+        let result = result.and_then(|()| {
+            let record = &value[..];
+            if record.len() < RECORD_HEADER_LEN {
+                return Err(ErrorCode::FAIL);
+            }
+            let schema = u16::from_le_bytes([record[2], record[3]]);
+            description = record[0] as usize | (schema as usize) << 8;
+            let record = &record[RECORD_HEADER_LEN..];
+            len = record.len();
+            self.processid.map_or(Err(ErrorCode::FAIL), |processid| {
+                self.apps
+                    .enter(processid, |_, kernel_data| {
+                        kernel_data
+                            .get_readwrite_processbuffer(rw_allow::VALUE)
+                            .and_then(|buffer| {
+                                buffer.mut_enter(|appslice| {
+                                    let copy_len = cmp::min(record.len(), appslice.len());
+                                    appslice[..copy_len].copy_from_slice(&record[..copy_len]);
+                                    if copy_len < record.len() {
+                                        Err(ErrorCode::SIZE)
+                                    } else {
+                                        Ok(())
+                                    }
+                                })
+                            })
+                            .unwrap_or(Err(ErrorCode::RESERVE))
+                    })
+                    .unwrap_or_else(|err| Err(err.into()))
+            })
+        });
+
+        self.value_buffer.replace(value.take());
+        self.complete(result, len, description);
+    }
+
+    fn set_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.key_buffer.replace(key.take());
+        self.value_buffer.replace(value.take());
+        if result.is_ok() {
+            self.notify_change();
+        }
+        self.complete(result, 0, 0);
+    }
+
+    fn add_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.key_buffer.replace(key.take());
+        self.value_buffer.replace(value.take());
+        self.complete(result, 0, 0);
+    }
+
+    fn update_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.key_buffer.replace(key.take());
+        self.value_buffer.replace(value.take());
+        self.complete(result, 0, 0);
+    }
+
+    fn delete_complete(&self, result: Result<(), ErrorCode>, key: SubSliceMut<'static, u8>) {
+        self.key_buffer.replace(key.take());
+        if result.is_ok() {
+            self.notify_change();
+        }
+        self.complete(result, 0, 0);
+    }
+
+    fn garbage_collection_complete(&self, _result: Result<(), ErrorCode>) {}
+}
+
+impl<'a, V: kv::KVPermissions<'a>> SyscallDriver for ConfigService<'a, V> {
+    /// Read, write and watch configuration records.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Read record `arg1` into the read-write buffer.
+    /// - `2`: Write the read-only buffer to record `arg1`, with the type in
+    ///   bits 0 to 7 of `arg2` and the schema version in bits 8 to 23.
+    /// - `3`: Delete record `arg1`.
+    /// - `4`: Watch record `arg1`.
+    /// - `5`: Stop watching record `arg1`.
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        arg2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        let record = arg1 as u32;
+        match command_num {
+            0 => CommandReturn::success(),
+            1 => self.request(processid, Op::Read(record)).into(),
+            2 => match ConfigType::from_u8(arg2 as u8) {
+                Some(config_type) => self
+                    .request(
+                        processid,
+                        Op::Write {
+                            record,
+                            config_type,
+                            schema: (arg2 >> 8) as u16,
+                        },
+                    )
+                    .into(),
+                None => CommandReturn::failure(ErrorCode::INVAL),
+            },
+            3 => self.request(processid, Op::Delete(record)).into(),
+            4 => self.watch(processid, record).into(),
+            5 => self.unwatch(processid, record).into(),
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
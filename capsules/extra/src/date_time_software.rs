@@ -0,0 +1,499 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Date and time kept in software, for boards without an RTC.
+//!
+//! The time is counted with an alarm, and implements the date time HIL, so
+//! the date time driver gives it to processes as it does for an RTC.
+//!
+//! The alarm stops when the board resets. So that the time does not start
+//! over at every boot, it is stored in the KV store when it is set and every
+//! [`STORE_INTERVAL_S`] seconds, and loaded again with
+//! [`SoftwareDateTime::load`]. After a reset, the time goes on from the last
+//! stored time, so it is late by up to the store interval plus the time the
+//! board was off, until it is set again. Until the time is set or loaded,
+//! reading it fails with `OFF`.
+//!
+//! The alarm drifts with its clock source. A known drift, measured for
+//! example by comparing with a time source over a few days, is corrected
+//! with [`SoftwareDateTime::set_drift_ppm`].
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let software_date_time = components::date_time_software::SoftwareDateTimeComponent::new(
+//!     mux_alarm,
+//!     date_time_kv,
+//! )
+//! .finalize(components::date_time_software_component_static!(
+//!     nrf52840::rtc::Rtc<'static>,
+//!     capsules_extra::virtual_kv::VirtualKVPermissions<...>
+//! ));
+//! let date_time = components::date_time::DateTimeComponent::new(
+//!     board_kernel,
+//!     capsules_extra::date_time::DRIVER_NUM,
+//!     software_date_time,
+//! )
+//! .finalize(components::date_time_component_static!(...));
+//! let _ = software_date_time.load();
+//! ```
+
+use core::cell::Cell;
+
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::date_time::{DateTime, DateTimeClient, DateTimeValues, DayOfWeek, Month};
+use kernel::hil::kv;
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks, Frequency, Ticks, Time};
+use kernel::storage_permissions::StoragePermissions;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::ErrorCode;
+
+/// KV key of the stored time.
+const KEY: &[u8; KEY_LEN] = b"datetime";
+/// Length of the KV key of the stored time.
+pub const KEY_LEN: usize = 8;
+/// Length of a stored time: the seconds since 1970 as a little endian `u64`.
+pub const VALUE_LEN: usize = 8;
+
+/// Longest time between two times the count of the alarm is read.
+const UPDATE_INTERVAL_S: u32 = 60;
+/// Time between two times the time is stored.
+pub const STORE_INTERVAL_S: u64 = 3600;
+
+const SECONDS_PER_DAY: u64 = 86400;
+/// Days from 0000-03-01 to 1970-01-01, in the proleptic Gregorian calendar.
+const DAYS_TO_1970: u64 = 719468;
+/// Days in 400 years.
+const DAYS_PER_ERA: u64 = 146097;
+
+fn is_leap_year(year: u16) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+fn month_number(month: Month) -> u8 {
+    match month {
+        Month::January => 1,
+        Month::February => 2,
+        Month::March => 3,
+        Month::April => 4,
+        Month::May => 5,
+        Month::June => 6,
+        Month::July => 7,
+        Month::August => 8,
+        Month::September => 9,
+        Month::October => 10,
+        Month::November => 11,
+        Month::December => 12,
+    }
+}
+
+fn month_from_number(month: u8) -> Month {
+    match month {
+        1 => Month::January,
+        2 => Month::February,
+        3 => Month::March,
+        4 => Month::April,
+        5 => Month::May,
+        6 => Month::June,
+        7 => Month::July,
+        8 => Month::August,
+        9 => Month::September,
+        10 => Month::October,
+        11 => Month::November,
+        _ => Month::December,
+    }
+}
+
+/// Seconds since 1970-01-01 00:00:00 of `date_time`, or `None` if it is not
+/// a valid date and time from 1970.
+fn to_seconds(date_time: &DateTimeValues) -> Option<u64> {
+    let month = month_number(date_time.month);
+    if date_time.year < 1970
+        || date_time.day == 0
+        || date_time.day > days_in_month(date_time.year, month)
+        || date_time.hour >= 24
+        || date_time.minute >= 60
+        || date_time.seconds >= 60
+    {
+        return None;
+    }
+
+    // Count years from March, so that the leap day is the last day of a
+    // year.
+    let year = u64::from(date_time.year) - u64::from(month <= 2);
+    let year_of_era = year % 400;
+    let shifted_month = (u64::from(month) + 9) % 12;
+    let day_of_year = (153 * shifted_month + 2) / 5 + u64::from(date_time.day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = (year / 400) * DAYS_PER_ERA + day_of_era - DAYS_TO_1970;
+
+    Some(
+        days * SECONDS_PER_DAY
+            + u64::from(date_time.hour) * 3600
+            + u64::from(date_time.minute) * 60
+            + u64::from(date_time.seconds),
+    )
+}
+
+/// Date and time `seconds` after 1970-01-01 00:00:00.
+fn from_seconds(seconds: u64) -> DateTimeValues {
+    let days = seconds / SECONDS_PER_DAY;
+    let time = seconds % SECONDS_PER_DAY;
+
+    let shifted_days = days + DAYS_TO_1970;
+    let day_of_era = shifted_days % DAYS_PER_ERA;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = (shifted_month + 2) % 12 + 1;
+    let year = (shifted_days / DAYS_PER_ERA) * 400 + year_of_era + u64::from(month <= 2);
+
+    // 1970-01-01 was a Thursday.
+    let day_of_week = match (days + 4) % 7 {
+        0 => DayOfWeek::Sunday,
+        1 => DayOfWeek::Monday,
+        2 => DayOfWeek::Tuesday,
+        3 => DayOfWeek::Wednesday,
+        4 => DayOfWeek::Thursday,
+        5 => DayOfWeek::Friday,
+        _ => DayOfWeek::Saturday,
+    };
+
+    DateTimeValues {
+        year: year as u16,
+        month: month_from_number(month as u8),
+        day: day as u8,
+        day_of_week,
+        hour: (time / 3600) as u8,
+        minute: (time / 60 % 60) as u8,
+        seconds: (time % 60) as u8,
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Op {
+    Load,
+    Store,
+}
+
+pub struct SoftwareDateTime<'a, A: Alarm<'a>, V: kv::KVPermissions<'a>> {
+    alarm: &'a A,
+    kv: &'a V,
+    /// Permissions of the kernel for the stored time.
+    permissions: StoragePermissions,
+    client: OptionalCell<&'a dyn DateTimeClient>,
+    deferred_call: DeferredCall,
+    /// Seconds since 1970-01-01 00:00:00 at `reference`, if the time is
+    /// known.
+    seconds: OptionalCell<u64>,
+    /// Count of the alarm at `seconds`.
+    reference: Cell<A::Ticks>,
+    /// Time when the time was last stored.
+    stored_seconds: Cell<u64>,
+    /// How much faster the time goes than the alarm counts, in parts per
+    /// million.
+    drift_ppm: Cell<i32>,
+    /// Drift not corrected yet, in microseconds.
+    drift_us: Cell<i64>,
+    get_pending: Cell<bool>,
+    set_pending: Cell<bool>,
+    /// Whether the time must be stored once the running KV operation is
+    /// done.
+    store_pending: Cell<bool>,
+    op: OptionalCell<Op>,
+    key_buffer: TakeCell<'static, [u8]>,
+    value_buffer: TakeCell<'static, [u8]>,
+}
+
+impl<'a, A: Alarm<'a>, V: kv::KVPermissions<'a>> SoftwareDateTime<'a, A, V> {
+    pub fn new(
+        alarm: &'a A,
+        kv: &'a V,
+        permissions: StoragePermissions,
+        key_buffer: &'static mut [u8],
+        value_buffer: &'static mut [u8],
+    ) -> Self {
+        Self {
+            alarm,
+            kv,
+            permissions,
+            client: OptionalCell::empty(),
+            deferred_call: DeferredCall::new(),
+            seconds: OptionalCell::empty(),
+            reference: Cell::new(A::Ticks::from(0)),
+            stored_seconds: Cell::new(0),
+            drift_ppm: Cell::new(0),
+            drift_us: Cell::new(0),
+            get_pending: Cell::new(false),
+            set_pending: Cell::new(false),
+            store_pending: Cell::new(false),
+            op: OptionalCell::empty(),
+            key_buffer: TakeCell::new(key_buffer),
+            value_buffer: TakeCell::new(value_buffer),
+        }
+    }
+
+    /// Load the stored time, unless the time was set in the meantime.
+    pub fn load(&self) -> Result<(), ErrorCode> {
+        self.start(Op::Load)
+    }
+
+    /// Correct a drift of the alarm: `ppm` is positive if the alarm is slow,
+    /// and negative if it is fast, in parts per million.
+    pub fn set_drift_ppm(&self, ppm: i32) {
+        self.update();
+        self.drift_ppm.set(ppm);
+    }
+
+    /// The drift correction in use, in parts per million.
+    pub fn drift_ppm(&self) -> i32 {
+        self.drift_ppm.get()
+    }
+
+    /// The current time in seconds since 1970-01-01 00:00:00, if it is known.
+    pub fn now_seconds(&self) -> Option<u64> {
+        self.update();
+        self.seconds.get()
+    }
+
+    /// Add the whole seconds the alarm counted since `reference` to the time.
+    fn update(&self) {
+        let elapsed = self
+            .alarm
+            .now()
+            .wrapping_sub(self.reference.get())
+            .into_u32();
+        let whole = elapsed / <A as Time>::Frequency::frequency();
+        if whole == 0 {
+            return;
+        }
+        self.reference.set(
+            self.reference
+                .get()
+                .wrapping_add(A::Ticks::from(whole * <A as Time>::Frequency::frequency())),
+        );
+
+        let drift_us = self.drift_us.get() + i64::from(whole) * i64::from(self.drift_ppm.get());
+        self.drift_us.set(drift_us % 1_000_000);
+        let correction = drift_us / 1_000_000;
+        if let Some(seconds) = self.seconds.get() {
+            self.seconds.set(
+                seconds
+                    .saturating_add(u64::from(whole))
+                    .saturating_add_signed(correction),
+            );
+        }
+    }
+
+    /// Use `seconds` as the current time, and count from it.
+    fn set_seconds(&self, seconds: u64) {
+        self.reference.set(self.alarm.now());
+        self.drift_us.set(0);
+        self.seconds.set(seconds);
+        self.stored_seconds.set(seconds);
+        if !self.alarm.is_armed() {
+            self.arm();
+        }
+    }
+
+    /// Arm the alarm to read its count before it wraps around.
+    fn arm(&self) {
+        let interval = self.alarm.ticks_from_seconds(UPDATE_INTERVAL_S);
+        let half_max = A::Ticks::half_max_value();
+        let interval = if interval > half_max {
+            half_max
+        } else {
+            interval
+        };
+        self.alarm.set_alarm(self.alarm.now(), interval);
+    }
+
+    /// Store the time, now or once the running KV operation is done.
+    fn store(&self) {
+        if self.op.is_some() {
+            self.store_pending.set(true);
+        } else {
+            self.store_pending.set(false);
+            let _ = self.start(Op::Store);
+        }
+    }
+
+    /// Start `op` on the KV store.
+    fn start(&self, op: Op) -> Result<(), ErrorCode> {
+        if self.op.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        let key_buf = self.key_buffer.take().ok_or(ErrorCode::BUSY)?;
+        let Some(value_buf) = self.value_buffer.take() else {
+            self.key_buffer.replace(key_buf);
+            return Err(ErrorCode::BUSY);
+        };
+        let header = self.kv.header_size();
+        if key_buf.len() < KEY_LEN || value_buf.len() < header + VALUE_LEN {
+            self.key_buffer.replace(key_buf);
+            self.value_buffer.replace(value_buf);
+            return Err(ErrorCode::SIZE);
+        }
+        key_buf[..KEY_LEN].copy_from_slice(KEY);
+        let mut key = SubSliceMut::new(key_buf);
+        key.slice(..KEY_LEN);
+        let mut value = SubSliceMut::new(value_buf);
+
+        let result = if op == Op::Store {
+            let seconds = self.now_seconds().ok_or(ErrorCode::OFF);
+            match seconds {
+                Ok(seconds) => {
+                    value[header..header + VALUE_LEN].copy_from_slice(&seconds.to_le_bytes());
+                    value.slice(..header + VALUE_LEN);
+                    self.stored_seconds.set(seconds);
+                    self.kv.set(key, value, self.permissions)
+                }
+                Err(e) => Err((key, value, e)),
+            }
+        } else {
+            self.kv.get(key, value, self.permissions)
+        };
+        match result {
+            Ok(()) => {
+                self.op.set(op);
+                Ok(())
+            }
+            Err((key, value, e)) => {
+                self.key_buffer.replace(key.take());
+                self.value_buffer.replace(value.take());
+                Err(e)
+            }
+        }
+    }
+
+    /// Return the buffers of the finished KV operation, and store the time if
+    /// that waited for it.
+    fn complete(&self, key: SubSliceMut<'static, u8>, value: SubSliceMut<'static, u8>) {
+        self.key_buffer.replace(key.take());
+        self.value_buffer.replace(value.take());
+        self.op.clear();
+        if self.store_pending.get() {
+            self.store();
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>, V: kv::KVPermissions<'a>> DateTime<'a> for SoftwareDateTime<'a, A, V> {
+    fn get_date_time(&self) -> Result<(), ErrorCode> {
+        if self.get_pending.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.get_pending.set(true);
+        self.deferred_call.set();
+        Ok(())
+    }
+
+    fn set_date_time(&self, date_time: DateTimeValues) -> Result<(), ErrorCode> {
+        if self.set_pending.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        let seconds = to_seconds(&date_time).ok_or(ErrorCode::INVAL)?;
+        self.set_seconds(seconds);
+        self.store();
+        self.set_pending.set(true);
+        self.deferred_call.set();
+        Ok(())
+    }
+
+    fn set_client(&self, client: &'a dyn DateTimeClient) {
+        self.client.set(client);
+    }
+}
+
+impl<'a, A: Alarm<'a>, V: kv::KVPermissions<'a>> DeferredCallClient for SoftwareDateTime<'a, A, V> {
+    fn handle_deferred_call(&self) {
+        if self.set_pending.take() {
+            self.client.map(|client| client.set_date_time_done(Ok(())));
+        }
+        if self.get_pending.take() {
+            let date_time = self.now_seconds().map(from_seconds).ok_or(ErrorCode::OFF);
+            self.client
+                .map(|client| client.get_date_time_done(date_time));
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+impl<'a, A: Alarm<'a>, V: kv::KVPermissions<'a>> AlarmClient for SoftwareDateTime<'a, A, V> {
+    fn alarm(&self) {
+        if let Some(seconds) = self.now_seconds() {
+            if seconds.saturating_sub(self.stored_seconds.get()) >= STORE_INTERVAL_S {
+                self.store();
+            }
+            self.arm();
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>, V: kv::KVPermissions<'a>> kv::KVClient for SoftwareDateTime<'a, A, V> {
+    fn get_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        // The KV header is already removed from `value`.
+        if result.is_ok() && self.seconds.is_none() && value.len() >= VALUE_LEN {
+            let mut bytes = [0; VALUE_LEN];
+            bytes.copy_from_slice(&value[..VALUE_LEN]);
+            self.set_seconds(u64::from_le_bytes(bytes));
+        }
+        self.complete(key, value);
+    }
+
+    fn set_complete(
+        &self,
+        _result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.complete(key, value);
+    }
+
+    fn add_complete(
+        &self,
+        _result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.complete(key, value);
+    }
+
+    fn update_complete(
+        &self,
+        _result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.complete(key, value);
+    }
+
+    fn delete_complete(&self, _result: Result<(), ErrorCode>, key: SubSliceMut<'static, u8>) {
+        self.key_buffer.replace(key.take());
+        self.op.clear();
+    }
+
+    fn garbage_collection_complete(&self, _result: Result<(), ErrorCode>) {}
+}
@@ -0,0 +1,135 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Routes kernel `debug!()` output to one of several transports.
+//!
+//! Boards with several consoles, for example a UART for people, USB CDC for
+//! host tooling and RTT for CI, can choose at runtime which one gets the
+//! kernel debug output. `DebugRouter` sits between the `DebugWriter` and the
+//! transports and forwards each transmission to the selected [`Route`].
+//!
+//! Changing the route while a transmission is in progress takes effect with
+//! the next transmission. While the route is [`Route::Off`], debug output is
+//! dropped.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let router = components::debug_router::DebugRouterComponent::new([uart_mux, cdc_mux])
+//!     .finalize(components::debug_router_component_static!(2));
+//! components::debug_writer::DebugWriterTransmitComponent::new(router)
+//!     .finalize(components::debug_writer_transmit_component_static!());
+//!
+//! // Send debug output over USB from now on.
+//! router.set_route(capsules_extra::debug_router::Route::Output(1));
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::uart;
+use kernel::utilities::cells::OptionalCell;
+use kernel::ErrorCode;
+
+/// Where debug output goes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Route {
+    /// The output with this index.
+    Output(usize),
+    /// Nowhere: debug output is dropped.
+    Off,
+}
+
+pub struct DebugRouter<'a> {
+    outputs: &'a [&'a dyn uart::Transmit<'a>],
+    route: Cell<Route>,
+    /// Output of the transmission in progress.
+    in_progress: OptionalCell<usize>,
+    client: OptionalCell<&'a dyn uart::TransmitClient>,
+}
+
+impl<'a> DebugRouter<'a> {
+    /// Create a router sending debug output to the first output.
+    pub fn new(outputs: &'a [&'a dyn uart::Transmit<'a>]) -> DebugRouter<'a> {
+        DebugRouter {
+            outputs,
+            route: Cell::new(Route::Output(0)),
+            in_progress: OptionalCell::empty(),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    /// Select where debug output goes from the next transmission on.
+    ///
+    /// Returns `Err(ErrorCode::INVAL)` if the output does not exist.
+    pub fn set_route(&self, route: Route) -> Result<(), ErrorCode> {
+        if let Route::Output(index) = route {
+            if index >= self.outputs.len() {
+                return Err(ErrorCode::INVAL);
+            }
+        }
+        self.route.set(route);
+        Ok(())
+    }
+
+    /// Where debug output goes.
+    pub fn route(&self) -> Route {
+        self.route.get()
+    }
+
+    /// Number of outputs.
+    pub fn outputs(&self) -> usize {
+        self.outputs.len()
+    }
+}
+
+impl<'a> uart::Transmit<'a> for DebugRouter<'a> {
+    fn set_transmit_client(&self, client: &'a dyn uart::TransmitClient) {
+        self.client.set(client);
+    }
+
+    fn transmit_buffer(
+        &self,
+        tx_buffer: &'static mut [u8],
+        tx_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.in_progress.is_some() {
+            return Err((ErrorCode::BUSY, tx_buffer));
+        }
+        let index = match self.route.get() {
+            Route::Output(index) => index,
+            Route::Off => return Err((ErrorCode::OFF, tx_buffer)),
+        };
+        let Some(output) = self.outputs.get(index) else {
+            return Err((ErrorCode::FAIL, tx_buffer));
+        };
+        output.transmit_buffer(tx_buffer, tx_len)?;
+        self.in_progress.set(index);
+        Ok(())
+    }
+
+    fn transmit_word(&self, _word: u32) -> Result<(), ErrorCode> {
+        Err(ErrorCode::FAIL)
+    }
+
+    fn transmit_abort(&self) -> Result<(), ErrorCode> {
+        self.in_progress
+            .get()
+            .and_then(|index| self.outputs.get(index))
+            .map_or(Ok(()), |output| output.transmit_abort())
+    }
+}
+
+impl uart::TransmitClient for DebugRouter<'_> {
+    fn transmitted_buffer(
+        &self,
+        tx_buffer: &'static mut [u8],
+        tx_len: usize,
+        rval: Result<(), ErrorCode>,
+    ) {
+        self.in_progress.clear();
+        self.client
+            .map(move |client| client.transmitted_buffer(tx_buffer, tx_len, rval));
+    }
+}
@@ -0,0 +1,369 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Driver for the Maxim DS3231 real time clock, using the I2C bus.
+//!
+//! <https://www.analog.com/media/en/technical-documentation/data-sheets/DS3231.pdf>
+//!
+//! > The DS3231 is a low-cost, extremely accurate I2C real-time clock (RTC)
+//! > with an integrated temperature-compensated crystal oscillator (TCXO) and
+//! > crystal. The device incorporates a battery input, and maintains accurate
+//! > timekeeping when main power to the device is interrupted.
+//!
+//! Driver Semantics
+//! ----------------
+//!
+//! The driver implements the [`DateTime`] HIL, for example for the date-time
+//! syscall driver, and the [`DateTimeAlarm`] HIL with the first alarm of the
+//! RTC. The alarm compares the day of the month, the hour, the minute and the
+//! seconds.
+//!
+//! The INT/SQW output of the RTC must be connected to an interrupt pin. It
+//! stays low until the driver handles the alarm, so the alarm wakes the chip
+//! from any sleep mode in which GPIO interrupts wake it, even when the
+//! internal RTC of the chip is stopped.
+//!
+//! If the oscillator of the RTC stopped, for example because the backup
+//! battery ran out, reading the date and time fails with `FAIL` until it is
+//! set again.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let ds3231 = components::ds3231::Ds3231Component::new(mux_i2c, 0x68, rtc_int_pin)
+//!     .finalize(components::ds3231_component_static!(nrf52840::i2c::TWI));
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::date_time::{
+    DateTime, DateTimeAlarm, DateTimeAlarmClient, DateTimeClient, DateTimeValues,
+};
+use kernel::hil::gpio;
+use kernel::hil::i2c::{self, I2CClient, I2CDevice};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+use crate::i2c_rtc;
+
+/// Size of the buffer of the driver.
+pub const BUFFER_LEN: usize = 16;
+
+// Registers.
+const SECONDS: u8 = 0x00;
+const ALARM1_SECONDS: u8 = 0x07;
+const CONTROL: u8 = 0x0E;
+
+const HOURS_12: u8 = 1 << 6;
+const HOURS_PM: u8 = 1 << 5;
+const MONTH_CENTURY: u8 = 1 << 7;
+const CONTROL_INTCN: u8 = 1 << 2;
+const CONTROL_A1IE: u8 = 1 << 0;
+const STATUS_OSF: u8 = 1 << 7;
+const STATUS_A1F: u8 = 1 << 0;
+
+/// What the control and status registers are updated for.
+#[derive(Clone, Copy, PartialEq)]
+enum Operation {
+    SetDateTime,
+    SetAlarm,
+    DisarmAlarm,
+    AlarmFired,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    ReadDateTime,
+    WriteDateTime,
+    WriteAlarm,
+    ReadControl(Operation),
+    WriteControl(Operation),
+}
+
+pub struct Ds3231<'a, I: I2CDevice> {
+    i2c: &'a I,
+    interrupt_pin: &'a dyn gpio::InterruptPin<'a>,
+    buffer: TakeCell<'static, [u8]>,
+    state: Cell<State>,
+    /// The alarm fired while another operation was in progress.
+    alarm_pending: Cell<bool>,
+    client: OptionalCell<&'a dyn DateTimeClient>,
+    alarm_client: OptionalCell<&'a dyn DateTimeAlarmClient>,
+}
+
+impl<'a, I: I2CDevice> Ds3231<'a, I> {
+    pub fn new(
+        i2c: &'a I,
+        interrupt_pin: &'a dyn gpio::InterruptPin<'a>,
+        buffer: &'static mut [u8],
+    ) -> Self {
+        Self {
+            i2c,
+            interrupt_pin,
+            buffer: TakeCell::new(buffer),
+            state: Cell::new(State::Idle),
+            alarm_pending: Cell::new(false),
+            client: OptionalCell::empty(),
+            alarm_client: OptionalCell::empty(),
+        }
+    }
+
+    /// Write the first `write_len` bytes of the buffer, filled by `fill`, and
+    /// then read `read_len` bytes if it is not zero.
+    fn start(
+        &self,
+        state: State,
+        write_len: usize,
+        read_len: usize,
+        fill: impl FnOnce(&mut [u8]),
+    ) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        let buffer = self.buffer.take().ok_or(ErrorCode::BUSY)?;
+        fill(buffer);
+        self.i2c.enable();
+        let result = if read_len == 0 {
+            self.i2c.write(buffer, write_len)
+        } else {
+            self.i2c.write_read(buffer, write_len, read_len)
+        };
+        match result {
+            Ok(()) => {
+                self.state.set(state);
+                Ok(())
+            }
+            Err((error, buffer)) => {
+                self.buffer.replace(buffer);
+                self.i2c.disable();
+                Err(error.into())
+            }
+        }
+    }
+
+    /// Continue an operation from `command_complete`.
+    fn next(
+        &self,
+        buffer: &'static mut [u8],
+        state: State,
+        write_len: usize,
+        read_len: usize,
+    ) -> Result<(), ErrorCode> {
+        let result = if read_len == 0 {
+            self.i2c.write(buffer, write_len)
+        } else {
+            self.i2c.write_read(buffer, write_len, read_len)
+        };
+        match result {
+            Ok(()) => {
+                self.state.set(state);
+                Ok(())
+            }
+            Err((error, buffer)) => {
+                self.buffer.replace(buffer);
+                Err(error.into())
+            }
+        }
+    }
+
+    fn read_control(&self, operation: Operation) -> Result<(), ErrorCode> {
+        self.start(State::ReadControl(operation), 1, 2, |buffer| {
+            buffer[0] = CONTROL;
+        })
+    }
+
+    fn done(&self, operation: Operation, result: Result<(), ErrorCode>) {
+        self.state.set(State::Idle);
+        self.i2c.disable();
+        match operation {
+            Operation::SetDateTime => self.client.map(|client| client.set_date_time_done(result)),
+            Operation::SetAlarm => {
+                if result.is_err() {
+                    self.interrupt_pin.disable_interrupts();
+                }
+                self.alarm_client
+                    .map(|client| client.set_alarm_done(result))
+            }
+            Operation::DisarmAlarm => self
+                .alarm_client
+                .map(|client| client.disarm_alarm_done(result)),
+            Operation::AlarmFired => self.alarm_client.map(|client| client.alarm()),
+        };
+        if self.alarm_pending.take() {
+            let _ = self.read_control(Operation::AlarmFired);
+        }
+    }
+
+    fn decode_date_time(buffer: &[u8]) -> Result<DateTimeValues, ErrorCode> {
+        if buffer[15] & STATUS_OSF != 0 {
+            return Err(ErrorCode::FAIL);
+        }
+        let century = if buffer[5] & MONTH_CENTURY != 0 {
+            100
+        } else {
+            0
+        };
+        Ok(DateTimeValues {
+            year: 2000 + century + i2c_rtc::from_bcd(buffer[6]) as u16,
+            month: i2c_rtc::month_from_number(i2c_rtc::from_bcd(buffer[5] & 0x1F))?,
+            day: i2c_rtc::from_bcd(buffer[4]),
+            day_of_week: i2c_rtc::day_of_week_from_number(buffer[3].wrapping_sub(1))?,
+            hour: i2c_rtc::hours_from_bcd(buffer[2], buffer[2] & HOURS_12 != 0, HOURS_PM),
+            minute: i2c_rtc::from_bcd(buffer[1]),
+            seconds: i2c_rtc::from_bcd(buffer[0] & 0x7F),
+        })
+    }
+}
+
+impl<'a, I: I2CDevice> DateTime<'a> for Ds3231<'a, I> {
+    fn get_date_time(&self) -> Result<(), ErrorCode> {
+        self.start(State::ReadDateTime, 1, BUFFER_LEN, |buffer| {
+            buffer[0] = SECONDS;
+        })
+    }
+
+    fn set_date_time(&self, date_time: DateTimeValues) -> Result<(), ErrorCode> {
+        i2c_rtc::check_date_time(&date_time, 2199)?;
+        let century = if date_time.year >= 2100 {
+            MONTH_CENTURY
+        } else {
+            0
+        };
+        self.start(State::WriteDateTime, 8, 0, |buffer| {
+            buffer[0] = SECONDS;
+            buffer[1] = i2c_rtc::to_bcd(date_time.seconds);
+            buffer[2] = i2c_rtc::to_bcd(date_time.minute);
+            buffer[3] = i2c_rtc::to_bcd(date_time.hour);
+            buffer[4] = i2c_rtc::day_of_week_number(date_time.day_of_week) + 1;
+            buffer[5] = i2c_rtc::to_bcd(date_time.day);
+            buffer[6] = i2c_rtc::to_bcd(i2c_rtc::month_number(date_time.month)) | century;
+            buffer[7] = i2c_rtc::to_bcd((date_time.year % 100) as u8);
+        })
+    }
+
+    fn set_client(&self, client: &'a dyn DateTimeClient) {
+        self.client.set(client);
+    }
+}
+
+impl<'a, I: I2CDevice> DateTimeAlarm<'a> for Ds3231<'a, I> {
+    fn set_alarm(&self, date_time: DateTimeValues) -> Result<(), ErrorCode> {
+        i2c_rtc::check_date_time(
+            &DateTimeValues {
+                year: 2000,
+                ..date_time
+            },
+            2000,
+        )?;
+        // The alarm mask bits are all clear, so that the day of the month,
+        // hour, minute and seconds are compared.
+        self.start(State::WriteAlarm, 5, 0, |buffer| {
+            buffer[0] = ALARM1_SECONDS;
+            buffer[1] = i2c_rtc::to_bcd(date_time.seconds);
+            buffer[2] = i2c_rtc::to_bcd(date_time.minute);
+            buffer[3] = i2c_rtc::to_bcd(date_time.hour);
+            buffer[4] = i2c_rtc::to_bcd(date_time.day);
+        })?;
+        self.interrupt_pin.make_input();
+        self.interrupt_pin
+            .set_floating_state(gpio::FloatingState::PullUp);
+        self.interrupt_pin
+            .enable_interrupts(gpio::InterruptEdge::FallingEdge);
+        Ok(())
+    }
+
+    fn disarm_alarm(&self) -> Result<(), ErrorCode> {
+        self.read_control(Operation::DisarmAlarm)?;
+        self.interrupt_pin.disable_interrupts();
+        Ok(())
+    }
+
+    fn set_alarm_client(&self, client: &'a dyn DateTimeAlarmClient) {
+        self.alarm_client.set(client);
+    }
+}
+
+impl<I: I2CDevice> gpio::Client for Ds3231<'_, I> {
+    fn fired(&self) {
+        // The alarm fires once.
+        self.interrupt_pin.disable_interrupts();
+        if self.read_control(Operation::AlarmFired).is_err() {
+            self.alarm_pending.set(true);
+        }
+    }
+}
+
+impl<I: I2CDevice> I2CClient for Ds3231<'_, I> {
+    fn command_complete(&self, buffer: &'static mut [u8], status: Result<(), i2c::Error>) {
+        let state = self.state.get();
+        if let Err(error) = status {
+            self.buffer.replace(buffer);
+            match state {
+                State::ReadDateTime => {
+                    self.state.set(State::Idle);
+                    self.i2c.disable();
+                    self.client
+                        .map(|client| client.get_date_time_done(Err(error.into())));
+                }
+                State::WriteDateTime => self.done(Operation::SetDateTime, Err(error.into())),
+                State::WriteAlarm => self.done(Operation::SetAlarm, Err(error.into())),
+                State::ReadControl(operation) | State::WriteControl(operation) => {
+                    self.done(operation, Err(error.into()))
+                }
+                State::Idle => {}
+            }
+            return;
+        }
+
+        match state {
+            State::ReadDateTime => {
+                let date_time = Self::decode_date_time(buffer);
+                self.buffer.replace(buffer);
+                self.state.set(State::Idle);
+                self.i2c.disable();
+                self.client
+                    .map(|client| client.get_date_time_done(date_time));
+            }
+            State::WriteDateTime | State::WriteAlarm => {
+                let operation = if state == State::WriteDateTime {
+                    Operation::SetDateTime
+                } else {
+                    Operation::SetAlarm
+                };
+                buffer[0] = CONTROL;
+                if let Err(e) = self.next(buffer, State::ReadControl(operation), 1, 2) {
+                    self.done(operation, Err(e));
+                }
+            }
+            State::ReadControl(operation) => {
+                let (control, status) = (buffer[0], buffer[1]);
+                let (control, status) = match operation {
+                    // Setting the time clears the oscillator stop flag.
+                    Operation::SetDateTime => (control, status & !STATUS_OSF),
+                    Operation::SetAlarm => {
+                        (control | CONTROL_INTCN | CONTROL_A1IE, status & !STATUS_A1F)
+                    }
+                    Operation::DisarmAlarm | Operation::AlarmFired => {
+                        (control & !CONTROL_A1IE, status & !STATUS_A1F)
+                    }
+                };
+                buffer[0] = CONTROL;
+                buffer[1] = control;
+                buffer[2] = status;
+                if let Err(e) = self.next(buffer, State::WriteControl(operation), 3, 0) {
+                    self.done(operation, Err(e));
+                }
+            }
+            State::WriteControl(operation) => {
+                self.buffer.replace(buffer);
+                self.done(operation, Ok(()));
+            }
+            State::Idle => {
+                self.buffer.replace(buffer);
+            }
+        }
+    }
+}
@@ -0,0 +1,277 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Per process energy accounting.
+//!
+//! `EnergyAccountant` receives the execution events of processes from the
+//! kernel and the peripheral usage reported by capsules (see
+//! [`kernel::energy`]), times them with an alarm, and estimates the energy
+//! each process used from an [`EnergyModel`] of the board. The estimates are
+//! kept in the grant region of each process.
+//!
+//! If the board has a power meter, the accountant also measures the energy
+//! the whole system uses, once per second. Measured energy is not split
+//! between processes.
+//!
+//! Processes can query their own energy use and budget with this driver.
+//! Budgets are informational: processes that exceed their budget are not
+//! stopped.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let energy = static_init!(
+//!     capsules_extra::energy::EnergyAccountant<'static, VirtualMuxAlarm<'static, Rtc>>,
+//!     capsules_extra::energy::EnergyAccountant::new(
+//!         energy_alarm,
+//!         &ENERGY_MODEL,
+//!         Some(1_000_000),
+//!         board_kernel.create_grant(capsules_extra::energy::DRIVER_NUM, &grant_cap)
+//!     )
+//! );
+//! energy_alarm.set_alarm_client(energy);
+//! board_kernel.set_energy_monitor(energy, &process_management_cap);
+//! ```
+//!
+//! Syscall Interface
+//! -----------------
+//!
+//! ### Command
+//!
+//! #### `command_num`
+//!
+//! - `0`: Driver existence check.
+//! - `1`: Get the estimated energy the process used, in microjoules.
+//! - `2`: Get the time the process ran, in microseconds.
+//! - `3`: Get the part of the energy budget of the process that is left, in
+//!   microjoules. Returns `NOSUPPORT` if processes have no budget.
+//! - `4`: Get the energy budget of the process, in microjoules. Returns
+//!   `NOSUPPORT` if processes have no budget.
+
+use core::cell::Cell;
+
+use kernel::energy::{EnergyMonitor, EnergyStatistics, PeripheralEnergy, ProcessEnergy};
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::power_meter::{PowerMeter, PowerMeterClient};
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks, Ticks};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::OptionalCell;
+use kernel::{ErrorCode, ProcessId};
+
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::Energy as usize;
+
+/// Number of peripherals that can be active at the same time.
+pub const MAX_ACTIVE_PERIPHERALS: usize = 8;
+
+/// Interval between power measurements, in milliseconds.
+const MEASUREMENT_INTERVAL_MS: u32 = 1000;
+
+/// Power a peripheral draws while it is active.
+pub struct PeripheralPower {
+    /// Driver number of the capsule that uses the peripheral.
+    pub driver_num: usize,
+    pub power_uw: u32,
+}
+
+/// Power model of a board, used to estimate the energy of processes.
+pub struct EnergyModel {
+    /// Power the CPU draws while it runs a process.
+    pub cpu_active_uw: u32,
+    pub peripherals: &'static [PeripheralPower],
+}
+
+impl EnergyModel {
+    fn peripheral_power_uw(&self, driver_num: usize) -> u32 {
+        self.peripherals
+            .iter()
+            .find(|peripheral| peripheral.driver_num == driver_num)
+            .map_or(0, |peripheral| peripheral.power_uw)
+    }
+}
+
+/// Energy `power_uw` microwatts use in `time_us` microseconds, in nanojoules.
+fn energy_nj(power_uw: u32, time_us: u32) -> u64 {
+    power_uw as u64 * time_us as u64 / 1000
+}
+
+#[derive(Default)]
+pub struct App {
+    cpu_time_us: u64,
+    cpu_energy_nj: u64,
+    peripheral_energy_nj: u64,
+}
+
+pub struct EnergyAccountant<'a, A: Alarm<'a>> {
+    alarm: &'a A,
+    model: &'static EnergyModel,
+    budget_uj: Option<u64>,
+    /// Process that is running, and when it started.
+    running: OptionalCell<(ProcessId, A::Ticks)>,
+    /// Active peripherals, with the process they are active for, and when
+    /// they started.
+    active: [OptionalCell<(ProcessId, usize, A::Ticks)>; MAX_ACTIVE_PERIPHERALS],
+    meter: OptionalCell<&'a dyn PowerMeter<'a>>,
+    /// Time of the last power measurement.
+    last_measurement: OptionalCell<A::Ticks>,
+    measured_energy_nj: Cell<u64>,
+    apps: Grant<App, UpcallCount<0>, AllowRoCount<0>, AllowRwCount<0>>,
+}
+
+impl<'a, A: Alarm<'a>> EnergyAccountant<'a, A> {
+    /// `budget_uj` is the energy budget of every process, if any.
+    pub fn new(
+        alarm: &'a A,
+        model: &'static EnergyModel,
+        budget_uj: Option<u64>,
+        grant: Grant<App, UpcallCount<0>, AllowRoCount<0>, AllowRwCount<0>>,
+    ) -> EnergyAccountant<'a, A> {
+        EnergyAccountant {
+            alarm,
+            model,
+            budget_uj,
+            running: OptionalCell::empty(),
+            active: [const { OptionalCell::empty() }; MAX_ACTIVE_PERIPHERALS],
+            meter: OptionalCell::empty(),
+            last_measurement: OptionalCell::empty(),
+            measured_energy_nj: Cell::new(0),
+            apps: grant,
+        }
+    }
+
+    /// Measure the energy of the whole system with `meter`.
+    pub fn set_power_meter(&self, meter: &'a dyn PowerMeter<'a>) {
+        self.meter.set(meter);
+        self.last_measurement.set(self.alarm.now());
+        self.alarm.set_alarm(
+            self.alarm.now(),
+            self.alarm.ticks_from_ms(MEASUREMENT_INTERVAL_MS),
+        );
+    }
+
+    /// Microseconds since `start`.
+    fn elapsed_us(&self, start: A::Ticks) -> u32 {
+        self.alarm.ticks_to_us(self.alarm.now().wrapping_sub(start))
+    }
+
+    fn process_energy_of(&self, app: &App) -> ProcessEnergy {
+        ProcessEnergy {
+            cpu_time_us: app.cpu_time_us,
+            cpu_energy_uj: app.cpu_energy_nj / 1000,
+            peripheral_energy_uj: app.peripheral_energy_nj / 1000,
+            budget_uj: self.budget_uj,
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>> EnergyMonitor for EnergyAccountant<'a, A> {
+    fn process_started(&self, processid: ProcessId) {
+        self.running.set((processid, self.alarm.now()));
+    }
+
+    fn process_stopped(&self, processid: ProcessId) {
+        if let Some((running, start)) = self.running.take() {
+            if running == processid {
+                let time_us = self.elapsed_us(start);
+                let _ = self.apps.enter(processid, |app, _| {
+                    app.cpu_time_us += time_us as u64;
+                    app.cpu_energy_nj += energy_nj(self.model.cpu_active_uw, time_us);
+                });
+            }
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>> PeripheralEnergy for EnergyAccountant<'a, A> {
+    fn peripheral_started(&self, processid: ProcessId, driver_num: usize) {
+        // Peripherals that do not fit in the table are not accounted.
+        if let Some(slot) = self.active.iter().find(|slot| slot.is_none()) {
+            slot.set((processid, driver_num, self.alarm.now()));
+        }
+    }
+
+    fn peripheral_stopped(&self, processid: ProcessId, driver_num: usize) {
+        let slot = self.active.iter().find(|slot| {
+            slot.get()
+                .is_some_and(|(id, num, _)| id == processid && num == driver_num)
+        });
+        if let Some((_, _, start)) = slot.and_then(|slot| slot.take()) {
+            let time_us = self.elapsed_us(start);
+            let _ = self.apps.enter(processid, |app, _| {
+                app.peripheral_energy_nj +=
+                    energy_nj(self.model.peripheral_power_uw(driver_num), time_us);
+            });
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>> AlarmClient for EnergyAccountant<'a, A> {
+    fn alarm(&self) {
+        self.meter.map(|meter| {
+            let _ = meter.measure_power();
+        });
+        self.alarm.set_alarm(
+            self.alarm.get_alarm(),
+            self.alarm.ticks_from_ms(MEASUREMENT_INTERVAL_MS),
+        );
+    }
+}
+
+impl<'a, A: Alarm<'a>> PowerMeterClient for EnergyAccountant<'a, A> {
+    fn power_measured(&self, power_uw: Result<u32, ErrorCode>) {
+        let now = self.alarm.now();
+        if let (Ok(power_uw), Some(last)) = (power_uw, self.last_measurement.get()) {
+            // Assume the power was constant since the last measurement.
+            let time_us = self.alarm.ticks_to_us(now.wrapping_sub(last));
+            self.measured_energy_nj
+                .set(self.measured_energy_nj.get() + energy_nj(power_uw, time_us));
+        }
+        self.last_measurement.set(now);
+    }
+}
+
+impl<'a, A: Alarm<'a>> EnergyStatistics for EnergyAccountant<'a, A> {
+    fn process_energy(&self, processid: ProcessId) -> Option<ProcessEnergy> {
+        self.apps
+            .enter(processid, |app, _| self.process_energy_of(app))
+            .ok()
+    }
+
+    fn measured_energy_uj(&self) -> Option<u64> {
+        self.meter.map(|_| self.measured_energy_nj.get() / 1000)
+    }
+}
+
+impl<'a, A: Alarm<'a>> SyscallDriver for EnergyAccountant<'a, A> {
+    fn command(
+        &self,
+        command_num: usize,
+        _: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        if command_num == 0 {
+            return CommandReturn::success();
+        }
+        let energy = match self.process_energy(processid) {
+            Some(energy) => energy,
+            None => return CommandReturn::failure(ErrorCode::NOMEM),
+        };
+        let value = match command_num {
+            1 => Some(energy.energy_uj()),
+            2 => Some(energy.cpu_time_us),
+            3 => energy.remaining_budget_uj(),
+            4 => energy.budget_uj,
+            _ => None,
+        };
+        value.map_or(CommandReturn::failure(ErrorCode::NOSUPPORT), |value| {
+            CommandReturn::success_u64(value)
+        })
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
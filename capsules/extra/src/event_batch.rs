@@ -0,0 +1,470 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Batch input events for low-power human interface devices.
+//!
+//! Waking a process for every button press or touch costs more energy than
+//! handling the interrupt itself. `EventBatch` timestamps the events of
+//! buttons and analog comparators, such as a low-power comparator watching a
+//! capacitive touch pad, and queues them. The process that owns the queue is
+//! only woken once a batch is ready: when the queue holds `threshold` events,
+//! or `window_ms` after the first event of the batch.
+//!
+//! The queue is a [`RetainedEvents`], which boards place in the `.retained`
+//! section of RAM. It is not zeroed at boot, so that events survive a reset
+//! or a System OFF with RAM retention, and the event that woke the chip can be
+//! queued with [`EventBatch::record_wake`]. Each event carries the number of
+//! the boot it happened in, as timestamps restart at each boot.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! #[link_section = ".retained"]
+//! static mut HMI_EVENTS: RetainedEvents<32> = RetainedEvents::new();
+//!
+//! let event_batch = components::event_batch::EventBatchComponent::new(
+//!     board_kernel,
+//!     capsules_extra::event_batch::DRIVER_NUM,
+//!     mux_alarm,
+//!     buttons,
+//!     unsafe { &mut *addr_of_mut!(HMI_EVENTS) },
+//! )
+//! .finalize(components::event_batch_component_static!(nrf52840::rtc::Rtc<'static>, 32));
+//! if base_peripherals.pwr_clk.wake_source() == Some(WakeSource::Lpcomp) {
+//!     event_batch.record_wake(TOUCH_SOURCE);
+//! }
+//! ```
+//!
+//! Syscall Interface
+//! -----------------
+//!
+//! ### Command
+//!
+//! - `0`: Driver existence check.
+//! - `1`: Take the queue if no other process has it, and deliver batches
+//!   after `arg1` milliseconds or once `arg2` events are queued. A window of
+//!   0 delivers each event right away. Returns `RESERVE` if another process
+//!   has the queue.
+//! - `2`: Move the oldest queued events to read-write allow buffer 0, as many
+//!   as fit, and return their number. Each event is 8 bytes: the timestamp in
+//!   milliseconds (u32), the boot number (u16), the source (u8) and the kind
+//!   (u8), little endian.
+//! - `3`: Return the number of queued events and the number of events dropped
+//!   because the queue was full.
+//! - `4`: Give up the queue.
+//!
+//! ### Subscribe
+//!
+//! - `0`: A batch is ready. The upcall gets the number of queued events and
+//!   the number of events dropped.
+
+use core::cell::Cell;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::analog_comparator;
+use kernel::hil::gpio::{self, ActivationMode, ActivationState, InterruptEdge};
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks};
+use kernel::processbuffer::WriteableProcessBuffer;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{MapCell, OptionalCell};
+use kernel::{ErrorCode, ProcessId};
+
+use capsules_core::driver;
+/// Syscall driver number.
+pub const DRIVER_NUM: usize = driver::NUM::EventBatch as usize;
+
+kernel::driver_api!(
+    DRIVER_API,
+    DRIVER_NUM,
+    name: "event_batch",
+    commands: [
+        0 => "exists()",
+        1 => "take(window_ms, threshold)",
+        2 => "read() -> u32",
+        3 => "count() -> (u32, u32)",
+        4 => "release()",
+    ],
+    subscribes: [0 => "batch_ready(count, dropped)"],
+);
+
+/// Ids for subscribe upcalls
+mod upcall {
+    /// A batch of events is ready.
+    pub const BATCH_READY: usize = 0;
+    /// The number of upcalls the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// Buffer the events are read into.
+    pub const EVENTS: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Size of an event in the buffer of a process.
+pub const EVENT_LEN: usize = 8;
+
+/// What happened to the source of an event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventKind {
+    /// A button was pressed.
+    Pressed = 0,
+    /// A button was released.
+    Released = 1,
+    /// A comparator input crossed its reference.
+    Crossed = 2,
+    /// The source woke the chip from deep sleep.
+    Wake = 3,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Event {
+    /// Milliseconds since the boot the event happened in.
+    pub timestamp_ms: u32,
+    /// Number of the boot, counted from the creation of the queue.
+    pub boot: u16,
+    /// Index of the button, or the number of buttons plus the channel of the
+    /// comparator. Wake events have the source given by the board.
+    pub source: u8,
+    /// An [`EventKind`].
+    pub kind: u8,
+}
+
+impl Event {
+    const EMPTY: Event = Event {
+        timestamp_ms: 0,
+        boot: 0,
+        source: 0,
+        kind: 0,
+    };
+
+    fn to_bytes(self) -> [u8; EVENT_LEN] {
+        let mut bytes = [0; EVENT_LEN];
+        bytes[0..4].copy_from_slice(&self.timestamp_ms.to_le_bytes());
+        bytes[4..6].copy_from_slice(&self.boot.to_le_bytes());
+        bytes[6] = self.source;
+        bytes[7] = self.kind;
+        bytes
+    }
+}
+
+const RETAINED_MAGIC: u32 = 0x4556_4254;
+
+/// Queue of events that keeps its contents across resets, when it is placed
+/// in memory that is not zeroed at boot.
+///
+/// Any contents are valid for its fields, so the queue can be placed in
+/// uninitialized memory. At boot, the header is checked, and the queue is
+/// emptied if it does not hold a queue.
+#[repr(C)]
+pub struct RetainedEvents<const N: usize> {
+    magic: u32,
+    boot: u16,
+    head: u16,
+    len: u16,
+    dropped: u16,
+    /// Check of the fields above, which changes with the queue.
+    check: u32,
+    events: [Event; N],
+}
+
+impl<const N: usize> RetainedEvents<N> {
+    pub const fn new() -> Self {
+        Self {
+            magic: 0,
+            boot: 0,
+            head: 0,
+            len: 0,
+            dropped: 0,
+            check: 0,
+            events: [Event::EMPTY; N],
+        }
+    }
+
+    fn compute_check(&self) -> u32 {
+        self.magic
+            ^ ((self.boot as u32) << 16 | self.head as u32).rotate_left(7)
+            ^ ((self.len as u32) << 16 | self.dropped as u32).rotate_left(13)
+            ^ N as u32
+    }
+
+    fn seal(&mut self) {
+        self.check = self.compute_check();
+    }
+
+    /// Keep the events of the last boot if the queue is valid, or empty it.
+    /// Returns whether events were kept.
+    fn restore(&mut self) -> bool {
+        let valid = self.magic == RETAINED_MAGIC
+            && self.check == self.compute_check()
+            && (self.head as usize) < N.max(1)
+            && (self.len as usize) <= N;
+        if valid {
+            self.boot = self.boot.wrapping_add(1);
+        } else {
+            self.magic = RETAINED_MAGIC;
+            self.boot = 0;
+            self.head = 0;
+            self.len = 0;
+            self.dropped = 0;
+        }
+        self.seal();
+        valid
+    }
+
+    fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Queue `event`, dropping the oldest event if the queue is full.
+    fn push(&mut self, event: Event) {
+        if N == 0 {
+            self.dropped = self.dropped.saturating_add(1);
+        } else {
+            if self.len() == N {
+                self.pop();
+                self.dropped = self.dropped.saturating_add(1);
+            }
+            self.events[(self.head as usize + self.len()) % N] = event;
+            self.len += 1;
+        }
+        self.seal();
+    }
+
+    fn pop(&mut self) -> Option<Event> {
+        if self.len == 0 {
+            return None;
+        }
+        let event = self.events[self.head as usize];
+        self.head = ((self.head as usize + 1) % N) as u16;
+        self.len -= 1;
+        self.seal();
+        Some(event)
+    }
+}
+
+#[derive(Default)]
+pub struct App;
+
+pub struct EventBatch<'a, A: Alarm<'a>, const N: usize> {
+    alarm: &'a A,
+    buttons: &'a [(&'a dyn gpio::InterruptValuePin<'a>, ActivationMode)],
+    events: MapCell<&'static mut RetainedEvents<N>>,
+    /// Process that owns the queue.
+    owner: OptionalCell<ProcessId>,
+    window_ms: Cell<u32>,
+    threshold: Cell<usize>,
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<0>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+}
+
+impl<'a, A: Alarm<'a>, const N: usize> EventBatch<'a, A, N> {
+    pub fn new(
+        alarm: &'a A,
+        buttons: &'a [(&'a dyn gpio::InterruptValuePin<'a>, ActivationMode)],
+        events: &'static mut RetainedEvents<N>,
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<0>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+    ) -> Self {
+        events.restore();
+        for (i, &(pin, _)) in buttons.iter().enumerate() {
+            pin.make_input();
+            pin.set_value(i as u32);
+        }
+        Self {
+            alarm,
+            buttons,
+            events: MapCell::new(events),
+            owner: OptionalCell::empty(),
+            window_ms: Cell::new(0),
+            threshold: Cell::new(N),
+            apps: grant,
+        }
+    }
+
+    /// Start watching the buttons.
+    pub fn start(&self) {
+        for &(pin, _) in self.buttons.iter() {
+            let _ = pin.enable_interrupts(InterruptEdge::EitherEdge);
+        }
+    }
+
+    /// Queue that `source` woke the chip. Boards call this at boot with the
+    /// wake source reported by the chip.
+    pub fn record_wake(&self, source: u8) {
+        self.record(source, EventKind::Wake);
+    }
+
+    /// The number of queued events.
+    pub fn queued(&self) -> usize {
+        self.events.map_or(0, |events| events.len())
+    }
+
+    fn record(&self, source: u8, kind: EventKind) {
+        let boot = self.events.map_or(0, |events| events.boot);
+        let event = Event {
+            timestamp_ms: self.alarm.ticks_to_ms(self.alarm.now()),
+            boot,
+            source,
+            kind: kind as u8,
+        };
+        self.events.map(|events| events.push(event));
+        self.schedule();
+    }
+
+    /// Deliver the batch now if it is full, or arm the window.
+    fn schedule(&self) {
+        if self.owner.is_none() || self.queued() == 0 {
+            return;
+        }
+        if self.window_ms.get() == 0 || self.queued() >= self.threshold.get() {
+            let _ = self.alarm.disarm();
+            self.deliver();
+        } else if !self.alarm.is_armed() {
+            let window = self.alarm.ticks_from_ms(self.window_ms.get());
+            self.alarm.set_alarm(self.alarm.now(), window);
+        }
+    }
+
+    fn deliver(&self) {
+        let (queued, dropped) = self
+            .events
+            .map_or((0, 0), |events| (events.len(), events.dropped as usize));
+        self.owner.map(|owner| {
+            let _ = self.apps.enter(owner, |_, kernel_data| {
+                kernel_data
+                    .schedule_upcall(upcall::BATCH_READY, (queued, dropped, 0))
+                    .ok();
+            });
+        });
+    }
+
+    fn owns(&self, processid: ProcessId) -> bool {
+        self.owner.contains(&processid)
+    }
+
+    fn take(&self, processid: ProcessId, window_ms: u32, threshold: usize) -> CommandReturn {
+        if !self.owns(processid) {
+            // The queue is free once its owner exited.
+            let owner_alive = self
+                .owner
+                .get()
+                .is_some_and(|owner| self.apps.enter(owner, |_, _| ()).is_ok());
+            if owner_alive {
+                return CommandReturn::failure(ErrorCode::RESERVE);
+            }
+            self.owner.set(processid);
+        }
+        self.window_ms.set(window_ms);
+        self.threshold
+            .set(if threshold == 0 { N } else { threshold.min(N) });
+        // Events queued without an owner, or before the boot, are delivered
+        // as a batch too.
+        self.schedule();
+        CommandReturn::success()
+    }
+
+    fn read(&self, processid: ProcessId) -> CommandReturn {
+        self.apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readwrite_processbuffer(rw_allow::EVENTS)
+                    .and_then(|buffer| {
+                        buffer.mut_enter(|buffer| {
+                            let mut count = 0;
+                            self.events.map(|events| {
+                                for chunk in buffer.chunks(EVENT_LEN) {
+                                    if chunk.len() < EVENT_LEN {
+                                        break;
+                                    }
+                                    match events.pop() {
+                                        Some(event) => chunk.copy_from_slice(&event.to_bytes()),
+                                        None => break,
+                                    }
+                                    count += 1;
+                                }
+                                if events.len() == 0 {
+                                    events.dropped = 0;
+                                    events.seal();
+                                }
+                            });
+                            count
+                        })
+                    })
+                    .unwrap_or(0)
+            })
+            .map_or_else(
+                |err| CommandReturn::failure(err.into()),
+                |count| CommandReturn::success_u32(count as u32),
+            )
+    }
+}
+
+impl<'a, A: Alarm<'a>, const N: usize> gpio::ClientWithValue for EventBatch<'a, A, N> {
+    fn fired(&self, value: u32) {
+        if let Some(&(pin, mode)) = self.buttons.get(value as usize) {
+            let kind = match pin.read_activation(mode) {
+                ActivationState::Active => EventKind::Pressed,
+                ActivationState::Inactive => EventKind::Released,
+            };
+            self.record(value as u8, kind);
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>, const N: usize> analog_comparator::Client for EventBatch<'a, A, N> {
+    fn fired(&self, channel: usize) {
+        self.record((self.buttons.len() + channel) as u8, EventKind::Crossed);
+    }
+}
+
+impl<'a, A: Alarm<'a>, const N: usize> AlarmClient for EventBatch<'a, A, N> {
+    fn alarm(&self) {
+        self.deliver();
+    }
+}
+
+impl<'a, A: Alarm<'a>, const N: usize> SyscallDriver for EventBatch<'a, A, N> {
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        arg2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+            1 => self.take(processid, arg1 as u32, arg2),
+            2 | 3 | 4 if !self.owns(processid) => CommandReturn::failure(ErrorCode::RESERVE),
+            2 => self.read(processid),
+            3 => self
+                .events
+                .map_or(CommandReturn::success_u32_u32(0, 0), |events| {
+                    CommandReturn::success_u32_u32(events.len() as u32, events.dropped as u32)
+                }),
+            4 => {
+                self.owner.clear();
+                let _ = self.alarm.disarm();
+                CommandReturn::success()
+            }
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
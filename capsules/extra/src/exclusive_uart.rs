@@ -0,0 +1,444 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Gives applications exclusive use of hardware UARTs.
+//!
+//! Unlike the console, which multiplexes one UART between all processes, this
+//! driver hands whole UARTs to single applications, for example an app driving
+//! a modem or a GPS receiver at a high baud rate. The board assigns each UART
+//! to an application by its `ShortId`, with an [`ExclusiveUartPort`], and
+//! gives the ports to [`ExclusiveUart`], the userspace driver. Applications
+//! refer to a port by its index in that list.
+//!
+//! The first process of the application to use a port owns it, and can
+//! configure the UART, transmit and receive. The port is released when the
+//! process exits: the pending operations are aborted and the UART gets back
+//! its default configuration when the port notices, either on the next UART
+//! event or when the next process of the application uses the port.
+//!
+//! Setup
+//! -----
+//!
+//! ```rust,ignore
+//! let gps = components::exclusive_uart::ExclusiveUartPortComponent::new(
+//!     &base_peripherals.uarte0,
+//!     kernel::process::ShortId::Fixed(core::num::NonZeroU32::new(0x1234).unwrap()),
+//!     kernel::hil::uart::Parameters {
+//!         baud_rate: 9600,
+//!         width: kernel::hil::uart::Width::Eight,
+//!         parity: kernel::hil::uart::Parity::None,
+//!         stop_bits: kernel::hil::uart::StopBits::One,
+//!         hw_flow_control: false,
+//!     },
+//! )
+//! .finalize(components::exclusive_uart_port_component_static!());
+//! let ports = static_init!([&'static ExclusiveUartPort<'static>; 1], [gps]);
+//! let exclusive_uart = components::exclusive_uart::ExclusiveUartComponent::new(
+//!     board_kernel,
+//!     capsules_extra::exclusive_uart::DRIVER_NUM,
+//!     ports,
+//! )
+//! .finalize(components::exclusive_uart_component_static!());
+//! ```
+
+use core::cell::Cell;
+use core::cmp;
+
+use capsules_core::console::decode_word_format;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, GrantKernelData, UpcallCount};
+use kernel::hil::uart;
+use kernel::process::ShortId;
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::Uart as usize;
+
+/// Maximum number of ports of a driver. Each port has its own allow buffers.
+pub const MAX_PORTS: usize = 4;
+
+/// Default size for the transmit and receive buffers of a port.
+pub const DEFAULT_BUF_SIZE: usize = 256;
+
+/// IDs for subscribed upcalls.
+mod upcall {
+    /// Transmission done.
+    pub const TX_DONE: usize = 0;
+    /// Reception done.
+    pub const RX_DONE: usize = 1;
+    /// Number of upcalls.
+    pub const COUNT: u8 = 2;
+}
+
+/// Ids for read-only allow buffers, one per port.
+mod ro_allow {
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = super::MAX_PORTS as u8;
+}
+
+/// Ids for read-write allow buffers, one per port.
+mod rw_allow {
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = super::MAX_PORTS as u8;
+}
+
+/// A hardware UART reserved for one application.
+pub struct ExclusiveUartPort<'a> {
+    uart: &'a dyn uart::Uart<'a>,
+    /// Application the UART is reserved for.
+    owner: ShortId,
+    /// Configuration of the UART when no process owns it.
+    default_parameters: uart::Parameters,
+    parameters: Cell<uart::Parameters>,
+    /// Process using the UART.
+    process: OptionalCell<ProcessId>,
+    /// Process that started the transmission in progress.
+    tx_process: OptionalCell<ProcessId>,
+    /// Process that started the reception in progress.
+    rx_process: OptionalCell<ProcessId>,
+    tx_buffer: TakeCell<'static, [u8]>,
+    rx_buffer: TakeCell<'static, [u8]>,
+    driver: OptionalCell<&'a ExclusiveUart<'a>>,
+    /// Index of the port in the driver.
+    index: Cell<usize>,
+}
+
+impl<'a> ExclusiveUartPort<'a> {
+    pub fn new(
+        uart: &'a dyn uart::Uart<'a>,
+        owner: ShortId,
+        default_parameters: uart::Parameters,
+        tx_buffer: &'static mut [u8],
+        rx_buffer: &'static mut [u8],
+    ) -> ExclusiveUartPort<'a> {
+        ExclusiveUartPort {
+            uart,
+            owner,
+            default_parameters,
+            parameters: Cell::new(default_parameters),
+            process: OptionalCell::empty(),
+            tx_process: OptionalCell::empty(),
+            rx_process: OptionalCell::empty(),
+            tx_buffer: TakeCell::new(tx_buffer),
+            rx_buffer: TakeCell::new(rx_buffer),
+            driver: OptionalCell::empty(),
+            index: Cell::new(0),
+        }
+    }
+
+    /// Apply the default configuration to the UART.
+    pub fn initialize(&self) -> Result<(), ErrorCode> {
+        self.uart.configure(self.default_parameters)
+    }
+
+    fn set_driver(&self, driver: &'a ExclusiveUart<'a>, index: usize) {
+        self.driver.set(driver);
+        self.index.set(index);
+    }
+
+    /// Stop the operations of the process that owned the port, and restore
+    /// the default configuration.
+    fn release(&self) {
+        self.process.clear();
+        let _ = self.uart.transmit_abort();
+        let _ = self.uart.receive_abort();
+        self.parameters.set(self.default_parameters);
+        let _ = self.uart.configure(self.default_parameters);
+    }
+
+    fn configure(&self, parameters: uart::Parameters) -> Result<(), ErrorCode> {
+        self.uart.configure(parameters)?;
+        self.parameters.set(parameters);
+        Ok(())
+    }
+}
+
+impl uart::TransmitClient for ExclusiveUartPort<'_> {
+    fn transmitted_buffer(
+        &self,
+        tx_buffer: &'static mut [u8],
+        tx_len: usize,
+        rval: Result<(), ErrorCode>,
+    ) {
+        self.tx_buffer.replace(tx_buffer);
+        let processid = self.tx_process.take();
+        self.driver
+            .map(|driver| driver.transmitted(self, processid, tx_len, rval));
+    }
+}
+
+impl uart::ReceiveClient for ExclusiveUartPort<'_> {
+    fn received_buffer(
+        &self,
+        rx_buffer: &'static mut [u8],
+        rx_len: usize,
+        rval: Result<(), ErrorCode>,
+        _error: uart::Error,
+    ) {
+        let processid = self.rx_process.take();
+        self.driver
+            .map(|driver| driver.received(self, processid, &rx_buffer[..rx_len], rval));
+        self.rx_buffer.replace(rx_buffer);
+    }
+}
+
+/// Empty per-process state: the ports keep track of their owner.
+#[derive(Default)]
+pub struct App {}
+
+/// Userspace driver for the UARTs reserved for applications.
+pub struct ExclusiveUart<'a> {
+    ports: &'a [&'a ExclusiveUartPort<'a>],
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+}
+
+impl<'a> ExclusiveUart<'a> {
+    /// Create the driver. Only the first [`MAX_PORTS`] ports can be used.
+    pub fn new(
+        ports: &'a [&'a ExclusiveUartPort<'a>],
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+    ) -> ExclusiveUart<'a> {
+        ExclusiveUart { ports, apps: grant }
+    }
+
+    /// Connect the ports to the driver.
+    pub fn initialize(&'a self) {
+        for (index, port) in self.ports.iter().enumerate() {
+            port.set_driver(self, index);
+        }
+    }
+
+    /// The port at `index`, if `processid` may use it. The first process of
+    /// the owning application to use the port, or the first one after the
+    /// previous owner exited, gets the port.
+    fn port(
+        &self,
+        index: usize,
+        processid: ProcessId,
+    ) -> Result<&'a ExclusiveUartPort<'a>, ErrorCode> {
+        let port = *self
+            .ports
+            .get(index)
+            .filter(|_| index < MAX_PORTS)
+            .ok_or(ErrorCode::INVAL)?;
+        if processid.short_app_id() != port.owner {
+            return Err(ErrorCode::NOSUPPORT);
+        }
+        match port.process.get() {
+            Some(owner) if owner == processid => {}
+            Some(owner) if self.apps.enter(owner, |_, _| ()).is_ok() => {
+                return Err(ErrorCode::BUSY);
+            }
+            // The previous owner exited.
+            Some(_) => {
+                port.release();
+                port.process.set(processid);
+            }
+            None => port.process.set(processid),
+        }
+        Ok(port)
+    }
+
+    fn transmit(&self, port: &ExclusiveUartPort, len: usize) -> Result<(), ErrorCode> {
+        let Some(processid) = port.process.get() else {
+            return Err(ErrorCode::FAIL);
+        };
+        let buffer = port.tx_buffer.take().ok_or(ErrorCode::BUSY)?;
+        let copied = self
+            .apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readonly_processbuffer(port.index.get())
+                    .and_then(|data| {
+                        data.enter(|data| {
+                            let len = cmp::min(len, data.len());
+                            if len > buffer.len() {
+                                Err(ErrorCode::SIZE)
+                            } else {
+                                data[..len].copy_to_slice(&mut buffer[..len]);
+                                Ok(len)
+                            }
+                        })
+                    })
+                    .unwrap_or(Err(ErrorCode::RESERVE))
+            })
+            .unwrap_or_else(|err| Err(err.into()));
+        match copied {
+            Ok(len) => match port.uart.transmit_buffer(buffer, len) {
+                Ok(()) => {
+                    port.tx_process.set(processid);
+                    Ok(())
+                }
+                Err((err, buffer)) => {
+                    port.tx_buffer.replace(buffer);
+                    Err(err)
+                }
+            },
+            Err(err) => {
+                port.tx_buffer.replace(buffer);
+                Err(err)
+            }
+        }
+    }
+
+    fn receive(&self, port: &ExclusiveUartPort, len: usize) -> Result<(), ErrorCode> {
+        let Some(processid) = port.process.get() else {
+            return Err(ErrorCode::FAIL);
+        };
+        let buffer = port.rx_buffer.take().ok_or(ErrorCode::BUSY)?;
+        if len == 0 || len > buffer.len() {
+            port.rx_buffer.replace(buffer);
+            return Err(if len == 0 {
+                ErrorCode::INVAL
+            } else {
+                ErrorCode::SIZE
+            });
+        }
+        match port.uart.receive_buffer(buffer, len) {
+            Ok(()) => {
+                port.rx_process.set(processid);
+                Ok(())
+            }
+            Err((err, buffer)) => {
+                port.rx_buffer.replace(buffer);
+                Err(err)
+            }
+        }
+    }
+
+    fn transmitted(
+        &self,
+        port: &ExclusiveUartPort,
+        processid: Option<ProcessId>,
+        len: usize,
+        rval: Result<(), ErrorCode>,
+    ) {
+        self.notify(port, processid, upcall::TX_DONE, |_| (rval, len));
+    }
+
+    fn received(
+        &self,
+        port: &ExclusiveUartPort,
+        processid: Option<ProcessId>,
+        data: &[u8],
+        rval: Result<(), ErrorCode>,
+    ) {
+        self.notify(port, processid, upcall::RX_DONE, |kernel_data| {
+            let copied = kernel_data
+                .get_readwrite_processbuffer(port.index.get())
+                .and_then(|buffer| {
+                    buffer.mut_enter(|buffer| {
+                        let len = cmp::min(data.len(), buffer.len());
+                        buffer[..len].copy_from_slice(&data[..len]);
+                        if len < data.len() {
+                            Err(ErrorCode::SIZE)
+                        } else {
+                            Ok(())
+                        }
+                    })
+                })
+                .unwrap_or(Err(ErrorCode::RESERVE));
+            (rval.and(copied), data.len())
+        });
+    }
+
+    /// Signal the end of an operation of `port` to the process that started
+    /// it, if it still owns the port, or release the port if the owner exited.
+    fn notify(
+        &self,
+        port: &ExclusiveUartPort,
+        processid: Option<ProcessId>,
+        upcall_num: usize,
+        result: impl FnOnce(&GrantKernelData) -> (Result<(), ErrorCode>, usize),
+    ) {
+        let Some(processid) = processid.filter(|p| port.process.contains(p)) else {
+            return;
+        };
+        let entered = self.apps.enter(processid, |_, kernel_data| {
+            let (rval, len) = result(kernel_data);
+            kernel_data
+                .schedule_upcall(
+                    upcall_num,
+                    (
+                        kernel::errorcode::into_statuscode(rval),
+                        len,
+                        port.index.get(),
+                    ),
+                )
+                .ok();
+        });
+        if entered.is_err() {
+            port.release();
+        }
+    }
+}
+
+impl SyscallDriver for ExclusiveUart<'_> {
+    /// Use the UARTs reserved for the application.
+    ///
+    /// `arg1` is the index of the port for all commands but 0.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Set the baud rate to `arg2`.
+    /// - `2`: Set the word format: bits 0 to 3 of `arg2` are the width in
+    ///   bits, bits 4 and 5 the parity (0 none, 1 odd, 2 even), bit 6 selects
+    ///   two stop bits and bit 7 enables hardware flow control.
+    /// - `3`: Transmit `arg2` bytes of the read-only buffer of the port.
+    /// - `4`: Receive `arg2` bytes into the read-write buffer of the port.
+    /// - `5`: Abort the reception, which completes with the bytes received.
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        arg2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        if command_num == 0 {
+            return CommandReturn::success();
+        }
+        let port = match self.port(arg1, processid) {
+            Ok(port) => port,
+            Err(err) => return CommandReturn::failure(err),
+        };
+        match command_num {
+            1 => {
+                let parameters = uart::Parameters {
+                    baud_rate: arg2 as u32,
+                    ..port.parameters.get()
+                };
+                port.configure(parameters).into()
+            }
+            2 => decode_word_format(port.parameters.get(), arg2)
+                .and_then(|parameters| port.configure(parameters))
+                .into(),
+            3 => self.transmit(port, arg2).into(),
+            4 => self.receive(port, arg2).into(),
+            5 => match port.uart.receive_abort() {
+                // The reception completes with an upcall.
+                Ok(()) | Err(ErrorCode::BUSY) => CommandReturn::success(),
+                Err(err) => CommandReturn::failure(err),
+            },
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
@@ -0,0 +1,119 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Low-latency output pins for bit-banged protocols in userspace.
+//!
+//! The GPIO driver configures and reads pins one at a time and keeps per
+//! process state for interrupts. This driver only drives a fixed set of output
+//! pins chosen by the board, so commands need no grant and no configuration:
+//! each command sets, clears or toggles any of the pins in a single system
+//! call, using a bitmask of pin numbers.
+//!
+//! Any process can use the driver. Boards that expose the pins only to
+//! approved applications restrict access to the driver with TBF permission
+//! headers and a syscall filter such as
+//! [`TbfHeaderFilterDefaultAllow`](kernel::platform::TbfHeaderFilterDefaultAllow).
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let pins = static_init!(
+//!     [&'static nrf52840::gpio::GPIOPin; 2],
+//!     [&nrf52840_peripherals.gpio_port[Pin::P1_01], &nrf52840_peripherals.gpio_port[Pin::P1_02]]
+//! );
+//! let fast_gpio = static_init!(
+//!     capsules_extra::fast_gpio::FastGpio<'static, nrf52840::gpio::GPIOPin>,
+//!     capsules_extra::fast_gpio::FastGpio::new(pins)
+//! );
+//! ```
+
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::FastGpio as usize;
+
+use kernel::hil::gpio;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, ProcessId};
+
+/// Largest number of pins, one per bit of a command argument.
+pub const MAX_PINS: usize = 32;
+
+pub struct FastGpio<'a, P: gpio::Output + gpio::Configure> {
+    pins: &'a [&'a P],
+}
+
+impl<'a, P: gpio::Output + gpio::Configure> FastGpio<'a, P> {
+    /// Drive `pins` as outputs. Only the first [`MAX_PINS`] pins are used.
+    pub fn new(pins: &'a [&'a P]) -> Self {
+        let pins = &pins[..pins.len().min(MAX_PINS)];
+        for pin in pins {
+            pin.make_output();
+        }
+        Self { pins }
+    }
+
+    /// Call `f` on each pin whose bit is set in `mask`.
+    fn for_each(&self, mask: usize, f: impl Fn(&P)) {
+        for (i, pin) in self.pins.iter().enumerate() {
+            if mask & (1 << i) != 0 {
+                f(pin);
+            }
+        }
+    }
+
+    fn check_mask(&self, mask: usize) -> Result<(), ErrorCode> {
+        if mask >> self.pins.len() == 0 {
+            Ok(())
+        } else {
+            Err(ErrorCode::INVAL)
+        }
+    }
+}
+
+impl<P: gpio::Output + gpio::Configure> SyscallDriver for FastGpio<'_, P> {
+    /// Drive the output pins.
+    ///
+    /// Bit `n` of a mask selects pin `n`. Masks that select pins the board
+    /// did not provide fail with `INVAL` without changing any pin.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check. Returns the number of pins.
+    /// - `1`: Set the pins in `mask`.
+    /// - `2`: Clear the pins in `mask`.
+    /// - `3`: Toggle the pins in `mask`.
+    /// - `4`: Write the pins in `mask` (argument 1) to the corresponding bit
+    ///   of `values` (argument 2).
+    fn command(
+        &self,
+        command_num: usize,
+        mask: usize,
+        values: usize,
+        _: ProcessId,
+    ) -> CommandReturn {
+        if command_num == 0 {
+            return CommandReturn::success_u32(self.pins.len() as u32);
+        }
+        if let Err(err) = self.check_mask(mask) {
+            return CommandReturn::failure(err);
+        }
+        match command_num {
+            1 => self.for_each(mask, |pin| pin.set()),
+            2 => self.for_each(mask, |pin| pin.clear()),
+            3 => self.for_each(mask, |pin| {
+                pin.toggle();
+            }),
+            4 => {
+                self.for_each(mask & values, |pin| pin.set());
+                self.for_each(mask & !values, |pin| pin.clear());
+            }
+            _ => return CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+        CommandReturn::success()
+    }
+
+    fn allocate_grant(&self, _: ProcessId) -> Result<(), kernel::process::Error> {
+        Ok(())
+    }
+}
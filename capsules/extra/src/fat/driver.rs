@@ -0,0 +1,390 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! FAT filesystem userspace interface.
+//!
+//! Gives processes access to the files in the root directory of the FAT
+//! volume of a block storage device, such as an SD card. Any process can open files, and each open file
+//! belongs to the process that opened it. Files of processes that exited are
+//! closed the next time a file is opened.
+//!
+//! One operation can be in progress at a time, across all processes. Reads
+//! and writes transfer at most `KERNEL_BUFFER_LENGTH` bytes each.
+//!
+//! Directory entries are written into the list buffer as the name of the
+//! entry (`NAME.EXT`, padded with zeros to 12 bytes), followed by its size
+//! as a little endian `u32`, and a byte that is 1 for directories and 0 for
+//! files.
+
+use core::cmp;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::block_storage::BlockStorage;
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::{ErrorCode, ProcessId};
+
+use super::{FatClient, FatFs, FileInfo, MAX_OPEN_FILES};
+
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::FatFs as usize;
+
+/// Size of the buffer reads and writes go through.
+pub const KERNEL_BUFFER_LENGTH: usize = 512;
+
+/// Length of a directory entry in the list buffer.
+const LIST_ENTRY_LEN: usize = 17;
+
+/// Maximum length of an 8.3 file name.
+const MAX_NAME_LEN: usize = 12;
+
+/// IDs for subscribed upcalls.
+mod upcall {
+    /// The volume was mounted. The first argument is the result as a
+    /// `StatusCode`.
+    pub const MOUNTED: usize = 0;
+    /// A file was opened. The arguments are the result as a `StatusCode`,
+    /// the handle of the file, and its size.
+    pub const OPENED: usize = 1;
+    /// A read finished. The arguments are the result as a `StatusCode`, and
+    /// the number of bytes read, which is 0 at the end of the file.
+    pub const READ: usize = 2;
+    /// A write finished. The arguments are the result as a `StatusCode`, and
+    /// the number of bytes written.
+    pub const WRITTEN: usize = 3;
+    /// A directory entry was listed. The arguments are the result as a
+    /// `StatusCode`, the cursor of the next entry, and 1 if an entry was
+    /// written to the list buffer, or 0 at the end of the directory.
+    pub const LISTED: usize = 4;
+    /// Number of upcalls.
+    pub const COUNT: u8 = 5;
+}
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    /// Data to write.
+    pub const WRITE: usize = 0;
+    /// Name of the file to open.
+    pub const NAME: usize = 1;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 2;
+}
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// Read data is copied into it.
+    pub const READ: usize = 0;
+    /// Listed directory entries are written into it.
+    pub const LIST: usize = 1;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 2;
+}
+
+#[derive(Default)]
+pub struct App;
+
+pub struct FatDriver<'a, B: BlockStorage<'a>> {
+    fs: &'a FatFs<'a, B>,
+    kernel_buf: TakeCell<'static, [u8]>,
+    /// Process that opened each of the files.
+    owners: [OptionalCell<ProcessId>; MAX_OPEN_FILES],
+    /// Process whose operation is in progress.
+    current_process: OptionalCell<ProcessId>,
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+}
+
+impl<'a, B: BlockStorage<'a>> FatDriver<'a, B> {
+    pub fn new(
+        fs: &'a FatFs<'a, B>,
+        kernel_buf: &'static mut [u8; KERNEL_BUFFER_LENGTH],
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+    ) -> FatDriver<'a, B> {
+        FatDriver {
+            fs,
+            kernel_buf: TakeCell::new(kernel_buf),
+            owners: core::array::from_fn(|_| OptionalCell::empty()),
+            current_process: OptionalCell::empty(),
+            apps: grant,
+        }
+    }
+
+    fn check_owner(&self, handle: usize, processid: ProcessId) -> Result<(), ErrorCode> {
+        match self.owners.get(handle) {
+            Some(owner) if owner.contains(&processid) => Ok(()),
+            _ => Err(ErrorCode::INVAL),
+        }
+    }
+
+    /// Close the files of processes that no longer exist.
+    fn close_orphaned_files(&self) {
+        for (handle, owner) in self.owners.iter().enumerate() {
+            if owner
+                .get()
+                .is_some_and(|owner| self.apps.enter(owner, |_, _| {}).is_err())
+                && self.fs.close(handle).is_ok()
+            {
+                owner.clear();
+            }
+        }
+    }
+
+    fn open(&self, flags: u32, processid: ProcessId) -> Result<(), ErrorCode> {
+        let mut name = [0; MAX_NAME_LEN];
+        let len = self
+            .apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readonly_processbuffer(ro_allow::NAME)
+                    .and_then(|buffer| {
+                        buffer.enter(|buffer| {
+                            let len = buffer
+                                .iter()
+                                .position(|c| c.get() == 0)
+                                .unwrap_or(buffer.len());
+                            if len > MAX_NAME_LEN {
+                                return Err(ErrorCode::INVAL);
+                            }
+                            buffer[..len].copy_to_slice(&mut name[..len]);
+                            Ok(len)
+                        })
+                    })
+                    .unwrap_or(Err(ErrorCode::RESERVE))
+            })
+            .unwrap_or_else(|err| Err(err.into()))?;
+        self.close_orphaned_files();
+        self.fs.open(&name[..len], flags)
+    }
+
+    fn write(&self, handle: usize, len: usize, processid: ProcessId) -> Result<(), ErrorCode> {
+        self.check_owner(handle, processid)?;
+        let buffer = self.kernel_buf.take().ok_or(ErrorCode::BUSY)?;
+        let len = self
+            .apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readonly_processbuffer(ro_allow::WRITE)
+                    .and_then(|write| {
+                        write.enter(|data| {
+                            let len = cmp::min(cmp::min(len, data.len()), buffer.len());
+                            data[..len].copy_to_slice(&mut buffer[..len]);
+                            len
+                        })
+                    })
+                    .map_err(ErrorCode::from)
+            })
+            .unwrap_or_else(|err| Err(err.into()));
+        match len {
+            Ok(len) => self.fs.write(handle, buffer, len).map_err(|(e, buffer)| {
+                self.kernel_buf.replace(buffer);
+                e
+            }),
+            Err(e) => {
+                self.kernel_buf.replace(buffer);
+                Err(e)
+            }
+        }
+    }
+
+    fn read(&self, handle: usize, len: usize, processid: ProcessId) -> Result<(), ErrorCode> {
+        self.check_owner(handle, processid)?;
+        let buffer = self.kernel_buf.take().ok_or(ErrorCode::BUSY)?;
+        self.fs.read(handle, buffer, len).map_err(|(e, buffer)| {
+            self.kernel_buf.replace(buffer);
+            e
+        })
+    }
+
+    fn schedule_upcall(&self, upcall: usize, data: (usize, usize, usize)) {
+        self.current_process.take().map(|processid| {
+            let _ = self.apps.enter(processid, |_, kernel_data| {
+                kernel_data.schedule_upcall(upcall, data).ok();
+            });
+        });
+    }
+}
+
+impl<'a, B: BlockStorage<'a>> FatClient for FatDriver<'a, B> {
+    fn mounted(&self, result: Result<(), ErrorCode>) {
+        self.owners.iter().for_each(|owner| owner.clear());
+        self.schedule_upcall(
+            upcall::MOUNTED,
+            (kernel::errorcode::into_statuscode(result), 0, 0),
+        );
+    }
+
+    fn opened(&self, result: Result<usize, ErrorCode>) {
+        let (handle, size) = match result {
+            Ok(handle) => {
+                self.current_process
+                    .map(|processid| self.owners[handle].set(processid));
+                (handle, self.fs.size(handle).unwrap_or(0) as usize)
+            }
+            Err(_) => (0, 0),
+        };
+        self.schedule_upcall(
+            upcall::OPENED,
+            (
+                kernel::errorcode::into_statuscode(result.map(|_| ())),
+                handle,
+                size,
+            ),
+        );
+    }
+
+    fn read_done(&self, buffer: &'static mut [u8], result: Result<usize, ErrorCode>) {
+        let result = result.map(|len| {
+            self.current_process
+                .map(|processid| {
+                    self.apps
+                        .enter(processid, |_, kernel_data| {
+                            kernel_data
+                                .get_readwrite_processbuffer(rw_allow::READ)
+                                .and_then(|read| {
+                                    read.mut_enter(|dest| {
+                                        let len = cmp::min(dest.len(), len);
+                                        dest[..len].copy_from_slice(&buffer[..len]);
+                                        len
+                                    })
+                                })
+                                .unwrap_or(0)
+                        })
+                        .unwrap_or(0)
+                })
+                .unwrap_or(0)
+        });
+        self.kernel_buf.replace(buffer);
+        self.schedule_upcall(
+            upcall::READ,
+            (
+                kernel::errorcode::into_statuscode(result.map(|_| ())),
+                result.unwrap_or(0),
+                0,
+            ),
+        );
+    }
+
+    fn write_done(&self, buffer: &'static mut [u8], result: Result<usize, ErrorCode>) {
+        self.kernel_buf.replace(buffer);
+        self.schedule_upcall(
+            upcall::WRITTEN,
+            (
+                kernel::errorcode::into_statuscode(result.map(|_| ())),
+                result.unwrap_or(0),
+                0,
+            ),
+        );
+    }
+
+    fn listed(&self, result: Result<Option<(FileInfo, u32)>, ErrorCode>) {
+        if let Ok(Some((info, _))) = result {
+            self.current_process.map(|processid| {
+                let _ = self.apps.enter(processid, |_, kernel_data| {
+                    kernel_data
+                        .get_readwrite_processbuffer(rw_allow::LIST)
+                        .and_then(|list| {
+                            list.mut_enter(|dest| {
+                                if dest.len() >= LIST_ENTRY_LEN {
+                                    let mut entry = [0; LIST_ENTRY_LEN];
+                                    entry[..info.name_len]
+                                        .copy_from_slice(&info.name[..info.name_len]);
+                                    entry[12..16].copy_from_slice(&info.size.to_le_bytes());
+                                    entry[16] = info.directory as u8;
+                                    dest[..LIST_ENTRY_LEN].copy_from_slice(&entry);
+                                }
+                            })
+                        })
+                        .ok();
+                });
+            });
+        }
+        let (next, found) = match result {
+            Ok(Some((_, next))) => (next as usize, 1),
+            _ => (0, 0),
+        };
+        self.schedule_upcall(
+            upcall::LISTED,
+            (
+                kernel::errorcode::into_statuscode(result.map(|_| ())),
+                next,
+                found,
+            ),
+        );
+    }
+}
+
+impl<'a, B: BlockStorage<'a>> SyscallDriver for FatDriver<'a, B> {
+    /// FAT filesystem control.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Mount the volume, initializing the card if needed. Returns
+    ///   ALREADY if it is mounted.
+    /// - `2`: Open the file whose name is in the name buffer. `arg1` holds
+    ///   the open flags: 0x1 creates the file if it does not exist, and 0x2
+    ///   appends to it.
+    /// - `3`: Read up to `arg2` bytes from file `arg1` into the read buffer.
+    /// - `4`: Write the first `arg2` bytes of the write buffer to file `arg1`.
+    /// - `5`: Close file `arg1`.
+    /// - `6`: List the directory entry at cursor `arg1`, where the first
+    ///   entry is at cursor 0.
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        arg2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        if command_num == 0 {
+            return CommandReturn::success();
+        }
+        if command_num == 5 {
+            return self
+                .check_owner(arg1, processid)
+                .and_then(|()| self.fs.close(arg1))
+                .map(|()| self.owners[arg1].clear())
+                .into();
+        }
+
+        if self.current_process.get().is_some_and(|current| {
+            current != processid && self.apps.enter(current, |_, _| {}).is_ok()
+        }) {
+            return CommandReturn::failure(ErrorCode::BUSY);
+        }
+
+        let result = match command_num {
+            1 => {
+                if self.fs.is_mounted() {
+                    Err(ErrorCode::ALREADY)
+                } else {
+                    self.fs.mount()
+                }
+            }
+            2 => self.open(arg1 as u32, processid),
+            3 => self.read(arg1, arg2, processid),
+            4 => self.write(arg1, arg2, processid),
+            6 => self.fs.list(arg1 as u32),
+            _ => Err(ErrorCode::NOSUPPORT),
+        };
+        if result.is_ok() {
+            self.current_process.set(processid);
+        }
+        result.into()
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
@@ -0,0 +1,1288 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! FAT16 and FAT32 filesystem on block storage, such as an SD card.
+//!
+//! [`FatFs`] mounts the FAT volume of a block storage device with 512 byte
+//! blocks (either a volume on the whole device, or the first partition of its
+//! MBR) and provides access to the
+//! files in its root directory. Files can be created, read, and written, and
+//! the root directory can be listed. Only 8.3 file names are supported: long
+//! file name entries are skipped, and files are looked up by their short
+//! name. Subdirectories are listed, but cannot be opened.
+//!
+//! All operations go through a single 512 byte sector buffer, and FAT and
+//! directory updates are written through to the card right away, so that the
+//! volume stays consistent if the card is removed between operations. Writes
+//! update all copies of the FAT, but not the free cluster count of the FAT32
+//! FSInfo sector, which is only a hint.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let storage = static_init!(
+//!     capsules_extra::sdcard::SDCardBlockStorage<'static, VirtualMuxAlarm<'static, Rtc>>,
+//!     capsules_extra::sdcard::SDCardBlockStorage::new(sdcard)
+//! );
+//! sdcard.set_client(storage);
+//! let fat = static_init!(
+//!     capsules_extra::fat::FatFs<'static, SDCardBlockStorage<'static, VirtualMuxAlarm<'static, Rtc>>>,
+//!     capsules_extra::fat::FatFs::new(storage, sector_buffer)
+//! );
+//! storage.set_client(fat);
+//! fat.register();
+//! fat.set_client(client);
+//! fat.mount();
+//! ```
+
+use core::cell::Cell;
+use core::cmp;
+
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::block_storage::{BlockStorage, BlockStorageClient};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+pub mod driver;
+
+/// Size of a sector, and of the sector buffer.
+pub const SECTOR_SIZE: usize = 512;
+
+/// Number of files that can be open at the same time.
+pub const MAX_OPEN_FILES: usize = 4;
+
+/// Flags for [`FatFs::open`].
+pub mod open_flags {
+    /// Create the file if it does not exist.
+    pub const CREATE: u32 = 0x1;
+    /// Start at the end of the file, so that writes append to it.
+    pub const APPEND: u32 = 0x2;
+}
+
+/// Length of a directory entry.
+const DIR_ENTRY_LEN: usize = 32;
+/// Directory entries in a sector.
+const DIR_ENTRIES_PER_SECTOR: u32 = (SECTOR_SIZE / DIR_ENTRY_LEN) as u32;
+
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_ARCHIVE: u8 = 0x20;
+const ATTR_LONG_NAME: u8 = 0x0F;
+
+/// First name byte of deleted directory entries.
+const ENTRY_DELETED: u8 = 0xE5;
+/// First name byte of the entry that ends the directory.
+const ENTRY_END: u8 = 0x00;
+
+/// A file or directory in the root directory.
+#[derive(Copy, Clone, Debug)]
+pub struct FileInfo {
+    /// Name in `NAME.EXT` form. Only the first `name_len` bytes are valid.
+    pub name: [u8; 12],
+    pub name_len: usize,
+    pub size: u32,
+    pub directory: bool,
+}
+
+pub trait FatClient {
+    /// The volume was mounted, or could not be.
+    fn mounted(&self, result: Result<(), ErrorCode>);
+
+    /// A file was opened, and can be accessed with the returned handle.
+    /// Fails with FAIL if the file does not exist, and NOMEM if the file
+    /// could not be created because the root directory is full.
+    fn opened(&self, result: Result<usize, ErrorCode>);
+
+    /// A read finished. Returns the number of bytes read into `buffer`, which
+    /// is 0 at the end of the file.
+    fn read_done(&self, buffer: &'static mut [u8], result: Result<usize, ErrorCode>);
+
+    /// A write finished. Returns the number of bytes written, which is less
+    /// than requested if the volume is full.
+    fn write_done(&self, buffer: &'static mut [u8], result: Result<usize, ErrorCode>);
+
+    /// The next entry of the root directory was found, along with the cursor
+    /// for the entry after it. `None` marks the end of the directory.
+    fn listed(&self, result: Result<Option<(FileInfo, u32)>, ErrorCode>);
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum FatType {
+    Fat16,
+    Fat32,
+}
+
+/// Layout of a mounted volume. Sector numbers are absolute.
+#[derive(Copy, Clone, Debug)]
+struct Volume {
+    fat_type: FatType,
+    sectors_per_cluster: u32,
+    fat_start: u32,
+    fat_size: u32,
+    num_fats: u32,
+    /// Root directory region, FAT16 only.
+    root_dir_start: u32,
+    root_dir_sectors: u32,
+    /// First cluster of the root directory, FAT32 only.
+    root_cluster: u32,
+    data_start: u32,
+    cluster_count: u32,
+}
+
+impl Volume {
+    /// Parse the boot sector of a volume starting at `start`.
+    fn parse(sector: &[u8], start: u32) -> Result<Volume, ErrorCode> {
+        if sector[510..512] != [0x55, 0xAA] || read_u16(sector, 11) as usize != SECTOR_SIZE {
+            return Err(ErrorCode::NOSUPPORT);
+        }
+        let sectors_per_cluster = sector[13] as u64;
+        let reserved = read_u16(sector, 14) as u64;
+        let num_fats = sector[16] as u64;
+        let root_entries = read_u16(sector, 17) as u64;
+        let total_sectors = match read_u16(sector, 19) {
+            0 => read_u32(sector, 32),
+            total => total as u32,
+        } as u64;
+        let fat_size = match read_u16(sector, 22) {
+            0 => read_u32(sector, 36),
+            size => size as u32,
+        } as u64;
+
+        let root_dir_sectors = (root_entries * DIR_ENTRY_LEN as u64).div_ceil(SECTOR_SIZE as u64);
+        let metadata_sectors = reserved + num_fats * fat_size + root_dir_sectors;
+        if sectors_per_cluster == 0 || num_fats == 0 || total_sectors <= metadata_sectors {
+            return Err(ErrorCode::NOSUPPORT);
+        }
+        let cluster_count = (total_sectors - metadata_sectors) / sectors_per_cluster;
+        let fat_type = match cluster_count {
+            // FAT12
+            0..4085 => return Err(ErrorCode::NOSUPPORT),
+            4085..65525 => FatType::Fat16,
+            _ => FatType::Fat32,
+        };
+        let start = start as u64;
+        let to_u32 = |value: u64| u32::try_from(value).map_err(|_| ErrorCode::NOSUPPORT);
+        Ok(Volume {
+            fat_type,
+            sectors_per_cluster: sectors_per_cluster as u32,
+            fat_start: to_u32(start + reserved)?,
+            fat_size: fat_size as u32,
+            num_fats: num_fats as u32,
+            root_dir_start: to_u32(start + reserved + num_fats * fat_size)?,
+            root_dir_sectors: root_dir_sectors as u32,
+            root_cluster: match fat_type {
+                FatType::Fat16 => 0,
+                FatType::Fat32 => read_u32(sector, 44),
+            },
+            data_start: to_u32(start + metadata_sectors)?,
+            cluster_count: to_u32(cluster_count)?,
+        })
+    }
+
+    fn cluster_bytes(&self) -> u32 {
+        self.sectors_per_cluster * SECTOR_SIZE as u32
+    }
+
+    fn cluster_sector(&self, cluster: u32) -> u32 {
+        self.data_start + (cluster - 2) * self.sectors_per_cluster
+    }
+
+    /// Whether `cluster` is a data cluster of the volume.
+    fn is_valid_cluster(&self, cluster: u32) -> bool {
+        cluster >= 2 && cluster < self.cluster_count + 2
+    }
+
+    fn end_of_chain(&self) -> u32 {
+        match self.fat_type {
+            FatType::Fat16 => 0xFFFF,
+            FatType::Fat32 => 0x0FFF_FFFF,
+        }
+    }
+
+    fn fat_entry_len(&self) -> u32 {
+        match self.fat_type {
+            FatType::Fat16 => 2,
+            FatType::Fat32 => 4,
+        }
+    }
+
+    /// Sector of the first FAT holding the entry of `cluster`, and the offset
+    /// of the entry in it.
+    fn fat_location(&self, cluster: u32) -> (u32, usize) {
+        let offset = cluster * self.fat_entry_len();
+        (
+            self.fat_start + offset / SECTOR_SIZE as u32,
+            (offset % SECTOR_SIZE as u32) as usize,
+        )
+    }
+
+    fn read_fat_entry(&self, sector: &[u8], offset: usize) -> u32 {
+        match self.fat_type {
+            FatType::Fat16 => read_u16(sector, offset) as u32,
+            FatType::Fat32 => read_u32(sector, offset) & 0x0FFF_FFFF,
+        }
+    }
+
+    fn root(&self) -> DirPos {
+        DirPos {
+            cluster: self.root_cluster,
+            sector: 0,
+            entry: 0,
+        }
+    }
+
+    /// Sector holding the directory entry at `pos`.
+    fn dir_sector(&self, pos: DirPos) -> u32 {
+        if pos.cluster == 0 {
+            self.root_dir_start + pos.sector
+        } else {
+            self.cluster_sector(pos.cluster) + pos.sector
+        }
+    }
+}
+
+/// Position in a directory. Cluster 0 is the FAT16 root directory region.
+#[derive(Copy, Clone, Debug)]
+struct DirPos {
+    cluster: u32,
+    sector: u32,
+    entry: u32,
+}
+
+/// Location of a directory entry on the card.
+#[derive(Copy, Clone, Debug)]
+struct EntryLoc {
+    sector: u32,
+    offset: usize,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct File {
+    entry: EntryLoc,
+    first_cluster: u32,
+    size: u32,
+    position: u32,
+    /// Cluster that holds `position` (or the last cluster, if `position` is
+    /// at its end), and its index in the cluster chain.
+    cluster: u32,
+    cluster_index: u32,
+}
+
+/// Operation in progress. Each step of an operation accesses at most one
+/// sector, and records its progress here before the next sector is read or
+/// written.
+#[derive(Copy, Clone, Debug)]
+enum Op {
+    Idle,
+    /// Waiting for the card to initialize.
+    Init,
+    Mount {
+        boot_sector: Option<u32>,
+    },
+    Open {
+        name: [u8; 11],
+        flags: u32,
+        handle: usize,
+        pos: DirPos,
+        free: Option<EntryLoc>,
+    },
+    Create {
+        name: [u8; 11],
+        handle: usize,
+        entry: EntryLoc,
+    },
+    Read {
+        handle: usize,
+        len: usize,
+        done: usize,
+    },
+    Write {
+        handle: usize,
+        len: usize,
+        done: usize,
+        /// Cluster that was allocated, and still has to be linked to the file.
+        allocated: Option<u32>,
+    },
+    List {
+        pos: DirPos,
+        index: u32,
+        /// Entries left to skip to get to the requested cursor.
+        skip: u32,
+    },
+    /// The operation finished, and waits for its last write.
+    Done(Completion),
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Completion {
+    Mounted,
+    Opened(usize),
+    Read(usize),
+    Written(usize),
+    Listed(Option<(FileInfo, u32)>),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Io {
+    Idle,
+    Read(u32),
+    /// Writing copy `copy` of `copies` copies of `sector`, which are
+    /// `stride` sectors apart.
+    Write {
+        sector: u32,
+        copy: u32,
+        copies: u32,
+        stride: u32,
+    },
+}
+
+/// Why a step could not finish.
+enum Stall {
+    /// A sector is being read or written. The step is retried afterwards.
+    Io,
+    Error(ErrorCode),
+}
+
+impl From<ErrorCode> for Stall {
+    fn from(e: ErrorCode) -> Stall {
+        Stall::Error(e)
+    }
+}
+
+type StepResult<T> = Result<T, Stall>;
+
+pub struct FatFs<'a, B: BlockStorage<'a>> {
+    storage: &'a B,
+    sector: TakeCell<'static, [u8]>,
+    /// Sector held by the sector buffer.
+    cached_sector: Cell<Option<u32>>,
+    io: Cell<Io>,
+    volume: Cell<Option<Volume>>,
+    files: [Cell<Option<File>>; MAX_OPEN_FILES],
+    op: Cell<Op>,
+    /// Buffer of the read or write in progress.
+    data: TakeCell<'static, [u8]>,
+    /// Next cluster to check when allocating, and how many clusters were
+    /// checked without finding a free one.
+    alloc_hint: Cell<u32>,
+    alloc_checked: Cell<u32>,
+    deferred_call: DeferredCall,
+    client: OptionalCell<&'a dyn FatClient>,
+}
+
+impl<'a, B: BlockStorage<'a>> FatFs<'a, B> {
+    /// `sector_buffer` must be at least `SECTOR_SIZE` bytes long.
+    pub fn new(storage: &'a B, sector_buffer: &'static mut [u8]) -> FatFs<'a, B> {
+        FatFs {
+            storage,
+            sector: TakeCell::new(sector_buffer),
+            cached_sector: Cell::new(None),
+            io: Cell::new(Io::Idle),
+            volume: Cell::new(None),
+            files: [const { Cell::new(None) }; MAX_OPEN_FILES],
+            op: Cell::new(Op::Idle),
+            data: TakeCell::empty(),
+            alloc_hint: Cell::new(2),
+            alloc_checked: Cell::new(0),
+            deferred_call: DeferredCall::new(),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn FatClient) {
+        self.client.set(client);
+    }
+
+    pub fn is_mounted(&self) -> bool {
+        self.volume.get().is_some()
+    }
+
+    /// Mount the volume, initializing the device first if needed. Any open
+    /// files are closed.
+    pub fn mount(&self) -> Result<(), ErrorCode> {
+        self.check_idle()?;
+        if self.storage.block_size() != SECTOR_SIZE {
+            return Err(ErrorCode::NOSUPPORT);
+        }
+        self.unmount();
+        if self.storage.is_ready() {
+            self.start(Op::Mount { boot_sector: None });
+        } else {
+            self.storage.initialize()?;
+            self.op.set(Op::Init);
+        }
+        Ok(())
+    }
+
+    /// Open the file `name` in the root directory.
+    ///
+    /// `flags` is a combination of [`open_flags`].
+    pub fn open(&self, name: &[u8], flags: u32) -> Result<(), ErrorCode> {
+        self.check_idle()?;
+        let volume = self.volume.get().ok_or(ErrorCode::RESERVE)?;
+        let name = short_name(name).ok_or(ErrorCode::INVAL)?;
+        let handle = self
+            .files
+            .iter()
+            .position(|file| file.get().is_none())
+            .ok_or(ErrorCode::NOMEM)?;
+        self.start(Op::Open {
+            name,
+            flags,
+            handle,
+            pos: volume.root(),
+            free: None,
+        });
+        Ok(())
+    }
+
+    pub fn close(&self, handle: usize) -> Result<(), ErrorCode> {
+        self.file(handle)?;
+        match self.op.get() {
+            Op::Read { handle: h, .. } | Op::Write { handle: h, .. } if h == handle => {
+                Err(ErrorCode::BUSY)
+            }
+            _ => {
+                self.files[handle].set(None);
+                Ok(())
+            }
+        }
+    }
+
+    /// Size of the open file `handle`.
+    pub fn size(&self, handle: usize) -> Result<u32, ErrorCode> {
+        self.file(handle).map(|file| file.size)
+    }
+
+    /// Read up to `len` bytes from the current position of `handle`.
+    pub fn read(
+        &self,
+        handle: usize,
+        buffer: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if let Err(e) = self.check_idle().and(self.file(handle).map(|_| ())) {
+            return Err((e, buffer));
+        }
+        let len = cmp::min(len, buffer.len());
+        self.data.replace(buffer);
+        self.start(Op::Read {
+            handle,
+            len,
+            done: 0,
+        });
+        Ok(())
+    }
+
+    /// Write `len` bytes of `buffer` at the current position of `handle`.
+    pub fn write(
+        &self,
+        handle: usize,
+        buffer: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if let Err(e) = self.check_idle().and(self.file(handle).map(|_| ())) {
+            return Err((e, buffer));
+        }
+        let len = cmp::min(len, buffer.len());
+        self.data.replace(buffer);
+        self.start(Op::Write {
+            handle,
+            len,
+            done: 0,
+            allocated: None,
+        });
+        Ok(())
+    }
+
+    /// Find the next entry of the root directory, starting at `cursor`.
+    ///
+    /// The cursor of the first entry is 0, and every listed entry comes with
+    /// the cursor of the next one.
+    pub fn list(&self, cursor: u32) -> Result<(), ErrorCode> {
+        self.check_idle()?;
+        let volume = self.volume.get().ok_or(ErrorCode::RESERVE)?;
+        self.start(Op::List {
+            pos: volume.root(),
+            index: 0,
+            skip: cursor,
+        });
+        Ok(())
+    }
+
+    fn check_idle(&self) -> Result<(), ErrorCode> {
+        match self.op.get() {
+            Op::Idle => Ok(()),
+            _ => Err(ErrorCode::BUSY),
+        }
+    }
+
+    fn file(&self, handle: usize) -> Result<File, ErrorCode> {
+        self.files
+            .get(handle)
+            .and_then(|file| file.get())
+            .ok_or(ErrorCode::INVAL)
+    }
+
+    fn unmount(&self) {
+        self.volume.set(None);
+        self.cached_sector.set(None);
+        self.files.iter().for_each(|file| file.set(None));
+        self.alloc_hint.set(2);
+        self.alloc_checked.set(0);
+    }
+
+    /// Start `op` from a deferred call, so that callbacks never happen
+    /// within the call that started the operation.
+    fn start(&self, op: Op) {
+        self.op.set(op);
+        self.deferred_call.set();
+    }
+
+    /// Get a value out of `sector`, reading it first if it is not cached.
+    fn cached<T, F: FnOnce(&[u8]) -> T>(&self, sector: u32, f: F) -> StepResult<T> {
+        if self.cached_sector.get() == Some(sector) {
+            if let Some(value) = self.sector.map(|buffer| f(buffer)) {
+                return Ok(value);
+            }
+        }
+        self.start_read(sector)?;
+        Err(Stall::Io)
+    }
+
+    /// Modify `sector` and write it back `copies` times, `stride` sectors
+    /// apart. Reads the sector first if it is not cached.
+    fn modify<F: FnOnce(&mut [u8])>(
+        &self,
+        sector: u32,
+        copies: u32,
+        stride: u32,
+        f: F,
+    ) -> StepResult<()> {
+        if self.cached_sector.get() != Some(sector) {
+            self.start_read(sector)?;
+            return Err(Stall::Io);
+        }
+        self.sector.map(f);
+        self.start_write(Io::Write {
+            sector,
+            copy: 0,
+            copies,
+            stride,
+        })?;
+        Ok(())
+    }
+
+    /// Write `sector` without reading it first. The part of the sector `f`
+    /// does not fill in is zeroed.
+    fn overwrite<F: FnOnce(&mut [u8])>(&self, sector: u32, f: F) -> StepResult<()> {
+        self.sector.map(|buffer| {
+            buffer.fill(0);
+            f(buffer);
+        });
+        self.cached_sector.set(Some(sector));
+        self.start_write(Io::Write {
+            sector,
+            copy: 0,
+            copies: 1,
+            stride: 0,
+        })?;
+        Ok(())
+    }
+
+    fn start_read(&self, sector: u32) -> Result<(), ErrorCode> {
+        let buffer = self.sector.take().ok_or(ErrorCode::NOMEM)?;
+        self.cached_sector.set(None);
+        self.io.set(Io::Read(sector));
+        self.storage
+            .read(buffer, sector as u64, 1)
+            .map_err(|(e, buffer)| {
+                self.io.set(Io::Idle);
+                self.sector.replace(buffer);
+                e
+            })
+    }
+
+    fn start_write(&self, io: Io) -> Result<(), ErrorCode> {
+        let sector = match io {
+            Io::Write {
+                sector,
+                copy,
+                stride,
+                ..
+            } => sector + copy * stride,
+            _ => return Err(ErrorCode::FAIL),
+        };
+        let buffer = self.sector.take().ok_or(ErrorCode::NOMEM)?;
+        self.io.set(io);
+        self.storage
+            .write(buffer, sector as u64, 1)
+            .map_err(|(e, buffer)| {
+                self.io.set(Io::Idle);
+                self.cached_sector.set(None);
+                self.sector.replace(buffer);
+                e
+            })
+    }
+
+    fn fat_entry(&self, volume: &Volume, cluster: u32) -> StepResult<u32> {
+        let (sector, offset) = volume.fat_location(cluster);
+        self.cached(sector, |buffer| volume.read_fat_entry(buffer, offset))
+    }
+
+    fn set_fat_entry(&self, volume: &Volume, cluster: u32, value: u32) -> StepResult<()> {
+        let (sector, offset) = volume.fat_location(cluster);
+        self.modify(sector, volume.num_fats, volume.fat_size, |buffer| {
+            match volume.fat_type {
+                FatType::Fat16 => write_u16(buffer, offset, value as u16),
+                FatType::Fat32 => {
+                    // The top four bits are reserved, and must be preserved.
+                    let reserved = read_u32(buffer, offset) & 0xF000_0000;
+                    write_u32(buffer, offset, reserved | (value & 0x0FFF_FFFF))
+                }
+            }
+        })
+    }
+
+    /// Find a free cluster, scanning one FAT sector per step.
+    fn find_free_cluster(&self, volume: &Volume) -> StepResult<u32> {
+        let end = volume.cluster_count + 2;
+        let per_sector = SECTOR_SIZE as u32 / volume.fat_entry_len();
+        loop {
+            let start = match self.alloc_hint.get() {
+                hint if volume.is_valid_cluster(hint) => hint,
+                _ => 2,
+            };
+            let sector_end = cmp::min((start / per_sector + 1) * per_sector, end);
+            let (sector, _) = volume.fat_location(start);
+            let found = self.cached(sector, |buffer| {
+                (start..sector_end).find(|cluster| {
+                    volume.read_fat_entry(buffer, volume.fat_location(*cluster).1) == 0
+                })
+            })?;
+            match found {
+                Some(cluster) => {
+                    // The cluster is only taken once it is marked in the
+                    // FAT, so the search can find it again until then.
+                    self.alloc_hint.set(cluster);
+                    self.alloc_checked.set(0);
+                    return Ok(cluster);
+                }
+                None => {
+                    self.alloc_hint.set(sector_end);
+                    self.alloc_checked
+                        .set(self.alloc_checked.get() + (sector_end - start));
+                    if self.alloc_checked.get() >= volume.cluster_count {
+                        self.alloc_checked.set(0);
+                        return Err(ErrorCode::NOMEM.into());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Move `pos` to its next directory entry, if it is at the end of a
+    /// sector or cluster. Returns `None` at the end of the directory.
+    ///
+    /// This follows the cluster chain of the directory, so it is done in a
+    /// step of its own, before the next entry is read.
+    fn resolve_dir_pos(&self, volume: &Volume, pos: DirPos) -> StepResult<Option<DirPos>> {
+        if pos.entry < DIR_ENTRIES_PER_SECTOR {
+            return Ok(Some(pos));
+        }
+        let next = DirPos {
+            cluster: pos.cluster,
+            sector: pos.sector + 1,
+            entry: 0,
+        };
+        if next.cluster == 0 {
+            return Ok((next.sector < volume.root_dir_sectors).then_some(next));
+        }
+        if next.sector < volume.sectors_per_cluster {
+            return Ok(Some(next));
+        }
+        let cluster = self.fat_entry(volume, pos.cluster)?;
+        Ok(volume.is_valid_cluster(cluster).then_some(DirPos {
+            cluster,
+            sector: 0,
+            entry: 0,
+        }))
+    }
+
+    fn dir_entry(
+        &self,
+        volume: &Volume,
+        pos: DirPos,
+    ) -> StepResult<(EntryLoc, [u8; DIR_ENTRY_LEN])> {
+        let loc = EntryLoc {
+            sector: volume.dir_sector(pos),
+            offset: pos.entry as usize * DIR_ENTRY_LEN,
+        };
+        let entry = self.cached(loc.sector, |buffer| {
+            let mut entry = [0; DIR_ENTRY_LEN];
+            entry.copy_from_slice(&buffer[loc.offset..loc.offset + DIR_ENTRY_LEN]);
+            entry
+        })?;
+        Ok((loc, entry))
+    }
+
+    /// Run the current operation until it waits for the card, or finishes.
+    fn run(&self) {
+        loop {
+            if self.io.get() != Io::Idle {
+                return;
+            }
+            let op = self.op.get();
+            match self.step(op) {
+                Ok(None) => {}
+                Ok(Some(completion)) => {
+                    if self.io.get() != Io::Idle {
+                        self.op.set(Op::Done(completion));
+                    } else {
+                        self.op.set(Op::Idle);
+                        self.complete(completion);
+                    }
+                    return;
+                }
+                Err(Stall::Io) => return,
+                Err(Stall::Error(e)) => {
+                    self.op.set(Op::Idle);
+                    self.fail(op, e);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Advance `op` by one step. Returns the completion once it finished.
+    fn step(&self, op: Op) -> StepResult<Option<Completion>> {
+        if let Op::Done(completion) = op {
+            return Ok(Some(completion));
+        }
+        if let Op::Mount { boot_sector } = op {
+            return self.mount_step(boot_sector);
+        }
+        let volume = match (op, self.volume.get()) {
+            (Op::Idle | Op::Init, _) => return Err(Stall::Io),
+            (_, Some(volume)) => volume,
+            (_, None) => return Err(ErrorCode::RESERVE.into()),
+        };
+
+        match op {
+            Op::Open {
+                name,
+                flags,
+                handle,
+                pos,
+                free,
+            } => {
+                let not_found = |free: Option<EntryLoc>| {
+                    if flags & open_flags::CREATE == 0 {
+                        return Err(ErrorCode::FAIL.into());
+                    }
+                    let entry = free.ok_or(ErrorCode::NOMEM)?;
+                    self.op.set(Op::Create {
+                        name,
+                        handle,
+                        entry,
+                    });
+                    Ok(None)
+                };
+                if pos.entry >= DIR_ENTRIES_PER_SECTOR {
+                    return match self.resolve_dir_pos(&volume, pos)? {
+                        Some(pos) => {
+                            self.op.set(Op::Open {
+                                name,
+                                flags,
+                                handle,
+                                pos,
+                                free,
+                            });
+                            Ok(None)
+                        }
+                        None => not_found(free),
+                    };
+                }
+
+                let (loc, entry) = self.dir_entry(&volume, pos)?;
+                match entry[0] {
+                    ENTRY_END => return not_found(free.or(Some(loc))),
+                    _ if entry[0..11] == name && entry[11] & ATTR_VOLUME_ID == 0 => {
+                        if entry[11] & ATTR_DIRECTORY != 0 {
+                            return Err(ErrorCode::INVAL.into());
+                        }
+                        let first_cluster = entry_cluster(&entry);
+                        let size = read_u32(&entry, 28);
+                        self.files[handle].set(Some(File {
+                            entry: loc,
+                            first_cluster,
+                            size,
+                            position: if flags & open_flags::APPEND != 0 {
+                                size
+                            } else {
+                                0
+                            },
+                            cluster: first_cluster,
+                            cluster_index: 0,
+                        }));
+                        return Ok(Some(Completion::Opened(handle)));
+                    }
+                    _ => {}
+                }
+                self.op.set(Op::Open {
+                    name,
+                    flags,
+                    handle,
+                    pos: DirPos {
+                        entry: pos.entry + 1,
+                        ..pos
+                    },
+                    free: free.or((entry[0] == ENTRY_DELETED).then_some(loc)),
+                });
+                Ok(None)
+            }
+
+            Op::Create {
+                name,
+                handle,
+                entry,
+            } => {
+                self.modify(entry.sector, 1, 0, |buffer| {
+                    let dir_entry = &mut buffer[entry.offset..entry.offset + DIR_ENTRY_LEN];
+                    dir_entry.fill(0);
+                    dir_entry[0..11].copy_from_slice(&name);
+                    dir_entry[11] = ATTR_ARCHIVE;
+                })?;
+                self.files[handle].set(Some(File {
+                    entry,
+                    first_cluster: 0,
+                    size: 0,
+                    position: 0,
+                    cluster: 0,
+                    cluster_index: 0,
+                }));
+                Ok(Some(Completion::Opened(handle)))
+            }
+
+            Op::Read { handle, len, done } => {
+                let mut file = self.file(handle)?;
+                if done >= len
+                    || file.position >= file.size
+                    || !volume.is_valid_cluster(file.cluster)
+                {
+                    return Ok(Some(Completion::Read(done)));
+                }
+                let cluster_bytes = volume.cluster_bytes();
+                if file.position / cluster_bytes > file.cluster_index {
+                    let next = self.fat_entry(&volume, file.cluster)?;
+                    if !volume.is_valid_cluster(next) {
+                        // The cluster chain is shorter than the file.
+                        return Ok(Some(Completion::Read(done)));
+                    }
+                    file.cluster = next;
+                    file.cluster_index += 1;
+                    self.files[handle].set(Some(file));
+                    return Ok(None);
+                }
+
+                let sector = volume.cluster_sector(file.cluster)
+                    + (file.position % cluster_bytes) / SECTOR_SIZE as u32;
+                let offset = file.position as usize % SECTOR_SIZE;
+                let count = cmp::min(
+                    cmp::min(SECTOR_SIZE - offset, len - done),
+                    (file.size - file.position) as usize,
+                );
+                self.cached(sector, |buffer| {
+                    self.data.map(|data| {
+                        data[done..done + count].copy_from_slice(&buffer[offset..offset + count])
+                    });
+                })?;
+                file.position += count as u32;
+                self.files[handle].set(Some(file));
+                self.op.set(Op::Read {
+                    handle,
+                    len,
+                    done: done + count,
+                });
+                Ok(None)
+            }
+
+            Op::Write {
+                handle,
+                len,
+                done,
+                allocated,
+            } => {
+                let mut file = self.file(handle)?;
+                if let Some(cluster) = allocated {
+                    if file.first_cluster == 0 {
+                        file.first_cluster = cluster;
+                        file.cluster_index = 0;
+                    } else {
+                        self.set_fat_entry(&volume, file.cluster, cluster)?;
+                        file.cluster_index += 1;
+                    }
+                    file.cluster = cluster;
+                    self.files[handle].set(Some(file));
+                    self.op.set(Op::Write {
+                        handle,
+                        len,
+                        done,
+                        allocated: None,
+                    });
+                    return Ok(None);
+                }
+
+                if done >= len {
+                    if done > 0 {
+                        self.modify(file.entry.sector, 1, 0, |buffer| {
+                            let entry = &mut buffer[file.entry.offset..];
+                            write_u16(entry, 20, (file.first_cluster >> 16) as u16);
+                            write_u16(entry, 26, file.first_cluster as u16);
+                            write_u32(entry, 28, file.size);
+                        })?;
+                    }
+                    return Ok(Some(Completion::Written(done)));
+                }
+
+                let cluster_bytes = volume.cluster_bytes();
+                let needs_cluster = if file.first_cluster == 0 {
+                    true
+                } else if file.position / cluster_bytes > file.cluster_index {
+                    let next = self.fat_entry(&volume, file.cluster)?;
+                    if volume.is_valid_cluster(next) {
+                        file.cluster = next;
+                        file.cluster_index += 1;
+                        self.files[handle].set(Some(file));
+                        return Ok(None);
+                    }
+                    true
+                } else {
+                    false
+                };
+                if needs_cluster {
+                    let cluster = match self.find_free_cluster(&volume) {
+                        Ok(cluster) => cluster,
+                        Err(Stall::Error(ErrorCode::NOMEM)) if done > 0 => {
+                            // The volume is full, finish with what was
+                            // written so far.
+                            self.op.set(Op::Write {
+                                handle,
+                                len: done,
+                                done,
+                                allocated: None,
+                            });
+                            return Ok(None);
+                        }
+                        Err(e) => return Err(e),
+                    };
+                    self.set_fat_entry(&volume, cluster, volume.end_of_chain())?;
+                    self.op.set(Op::Write {
+                        handle,
+                        len,
+                        done,
+                        allocated: Some(cluster),
+                    });
+                    return Ok(None);
+                }
+
+                let sector = volume.cluster_sector(file.cluster)
+                    + (file.position % cluster_bytes) / SECTOR_SIZE as u32;
+                let offset = file.position as usize % SECTOR_SIZE;
+                let count = cmp::min(SECTOR_SIZE - offset, len - done);
+                let fill = |buffer: &mut [u8]| {
+                    self.data.map(|data| {
+                        buffer[offset..offset + count].copy_from_slice(&data[done..done + count])
+                    });
+                };
+                // Sectors that are overwritten completely, or hold no data of
+                // the file yet, do not need to be read first.
+                if offset == 0 && (count == SECTOR_SIZE || file.position >= file.size) {
+                    self.overwrite(sector, fill)?;
+                } else {
+                    self.modify(sector, 1, 0, fill)?;
+                }
+                file.position += count as u32;
+                file.size = cmp::max(file.size, file.position);
+                self.files[handle].set(Some(file));
+                self.op.set(Op::Write {
+                    handle,
+                    len,
+                    done: done + count,
+                    allocated: None,
+                });
+                Ok(None)
+            }
+
+            Op::List { pos, index, skip } => {
+                if pos.entry >= DIR_ENTRIES_PER_SECTOR {
+                    return match self.resolve_dir_pos(&volume, pos)? {
+                        Some(pos) => {
+                            self.op.set(Op::List { pos, index, skip });
+                            Ok(None)
+                        }
+                        None => Ok(Some(Completion::Listed(None))),
+                    };
+                }
+
+                let (_, entry) = self.dir_entry(&volume, pos)?;
+                if entry[0] == ENTRY_END {
+                    return Ok(Some(Completion::Listed(None)));
+                }
+                let visible = entry[0] != ENTRY_DELETED
+                    && entry[11] != ATTR_LONG_NAME
+                    && entry[11] & ATTR_VOLUME_ID == 0;
+                if visible && skip == 0 {
+                    return Ok(Some(Completion::Listed(Some((
+                        file_info(&entry),
+                        index + 1,
+                    )))));
+                }
+                self.op.set(Op::List {
+                    pos: DirPos {
+                        entry: pos.entry + 1,
+                        ..pos
+                    },
+                    index: index + 1,
+                    skip: skip.saturating_sub(1),
+                });
+                Ok(None)
+            }
+
+            Op::Idle | Op::Init | Op::Mount { .. } | Op::Done(_) => Err(Stall::Io),
+        }
+    }
+
+    fn mount_step(&self, boot_sector: Option<u32>) -> StepResult<Option<Completion>> {
+        match boot_sector {
+            None => {
+                let boot_sector = self.cached(0, |sector| {
+                    if sector[510..512] != [0x55, 0xAA] {
+                        return Err(ErrorCode::NOSUPPORT);
+                    }
+                    let jump = sector[0] == 0xEB || sector[0] == 0xE9;
+                    if jump && read_u16(sector, 11) as usize == SECTOR_SIZE {
+                        // The volume covers the whole card.
+                        return Ok(0);
+                    }
+                    // Use the first partition of the MBR.
+                    match sector[450] {
+                        0x04 | 0x06 | 0x0B | 0x0C | 0x0E => Ok(read_u32(sector, 454)),
+                        _ => Err(ErrorCode::NOSUPPORT),
+                    }
+                })??;
+                self.op.set(Op::Mount {
+                    boot_sector: Some(boot_sector),
+                });
+                Ok(None)
+            }
+            Some(start) => {
+                let volume = self.cached(start, |sector| Volume::parse(sector, start))??;
+                self.volume.set(Some(volume));
+                Ok(Some(Completion::Mounted))
+            }
+        }
+    }
+
+    fn complete(&self, completion: Completion) {
+        self.client.map(|client| match completion {
+            Completion::Mounted => client.mounted(Ok(())),
+            Completion::Opened(handle) => client.opened(Ok(handle)),
+            Completion::Read(len) => {
+                self.data
+                    .take()
+                    .map(|buffer| client.read_done(buffer, Ok(len)));
+            }
+            Completion::Written(len) => {
+                self.data
+                    .take()
+                    .map(|buffer| client.write_done(buffer, Ok(len)));
+            }
+            Completion::Listed(entry) => client.listed(Ok(entry)),
+        });
+    }
+
+    /// A sector could not be read or written, which fails the operation.
+    fn io_failed(&self, e: ErrorCode) {
+        self.cached_sector.set(None);
+        let op = self.op.replace(Op::Idle);
+        self.fail(op, e);
+    }
+
+    fn fail(&self, op: Op, e: ErrorCode) {
+        self.client.map(|client| match op {
+            Op::Init | Op::Mount { .. } | Op::Done(Completion::Mounted) => client.mounted(Err(e)),
+            Op::Open { .. } | Op::Create { .. } => client.opened(Err(e)),
+            Op::Done(Completion::Opened(handle)) => {
+                // The directory entry of the new file could not be written.
+                self.files[handle].set(None);
+                client.opened(Err(e));
+            }
+            Op::Read { .. } | Op::Done(Completion::Read(_)) => {
+                self.data
+                    .take()
+                    .map(|buffer| client.read_done(buffer, Err(e)));
+            }
+            Op::Write { .. } | Op::Done(Completion::Written(_)) => {
+                self.data
+                    .take()
+                    .map(|buffer| client.write_done(buffer, Err(e)));
+            }
+            Op::List { .. } | Op::Done(Completion::Listed(_)) => client.listed(Err(e)),
+            Op::Idle => {}
+        });
+    }
+}
+
+impl<'a, B: BlockStorage<'a>> BlockStorageClient for FatFs<'a, B> {
+    fn initialize_done(&self, result: Result<(), ErrorCode>) {
+        if let Op::Init = self.op.get() {
+            match result {
+                Ok(()) => {
+                    self.op.set(Op::Mount { boot_sector: None });
+                    self.run();
+                }
+                Err(e) => {
+                    let op = self.op.replace(Op::Idle);
+                    self.fail(op, e);
+                }
+            }
+        }
+    }
+
+    fn read_done(&self, buffer: &'static mut [u8], result: Result<(), ErrorCode>) {
+        self.sector.replace(buffer);
+        let io = self.io.replace(Io::Idle);
+        if let Err(e) = result {
+            self.io_failed(e);
+            return;
+        }
+        if let Io::Read(sector) = io {
+            self.cached_sector.set(Some(sector));
+        }
+        self.run();
+    }
+
+    fn write_done(&self, buffer: &'static mut [u8], result: Result<(), ErrorCode>) {
+        self.sector.replace(buffer);
+        let io = self.io.replace(Io::Idle);
+        if let Err(e) = result {
+            self.io_failed(e);
+            return;
+        }
+        if let Io::Write {
+            sector,
+            copy,
+            copies,
+            stride,
+        } = io
+        {
+            if copy + 1 < copies {
+                // Write the next copy of the FAT sector.
+                let next = Io::Write {
+                    sector,
+                    copy: copy + 1,
+                    copies,
+                    stride,
+                };
+                if let Err(e) = self.start_write(next) {
+                    let op = self.op.replace(Op::Idle);
+                    self.fail(op, e);
+                }
+                return;
+            }
+        }
+        self.run();
+    }
+
+    fn media_changed(&self, present: bool) {
+        if !present {
+            self.unmount();
+        }
+    }
+}
+
+impl<'a, B: BlockStorage<'a>> DeferredCallClient for FatFs<'a, B> {
+    fn handle_deferred_call(&self) {
+        self.run();
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+fn read_u16(buffer: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buffer[offset], buffer[offset + 1]])
+}
+
+fn read_u32(buffer: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        buffer[offset],
+        buffer[offset + 1],
+        buffer[offset + 2],
+        buffer[offset + 3],
+    ])
+}
+
+fn write_u16(buffer: &mut [u8], offset: usize, value: u16) {
+    buffer[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn write_u32(buffer: &mut [u8], offset: usize, value: u32) {
+    buffer[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// First cluster of a directory entry.
+fn entry_cluster(entry: &[u8]) -> u32 {
+    ((read_u16(entry, 20) as u32) << 16) | read_u16(entry, 26) as u32
+}
+
+fn file_info(entry: &[u8]) -> FileInfo {
+    let mut info = FileInfo {
+        name: [0; 12],
+        name_len: 0,
+        size: read_u32(entry, 28),
+        directory: entry[11] & ATTR_DIRECTORY != 0,
+    };
+    let base = entry[0..8].iter().take_while(|c| **c != b' ');
+    let extension = entry[8..11].iter().take_while(|c| **c != b' ');
+    let mut push = |c: u8| {
+        info.name[info.name_len] = c;
+        info.name_len += 1;
+    };
+    base.for_each(|c| push(*c));
+    let mut extension = extension.peekable();
+    if extension.peek().is_some() {
+        push(b'.');
+        extension.for_each(|c| push(*c));
+    }
+    info
+}
+
+/// Convert `name` to the padded upper case form of 8.3 names in directory
+/// entries.
+fn short_name(name: &[u8]) -> Option<[u8; 11]> {
+    let (base, extension) = match name.iter().rposition(|c| *c == b'.') {
+        Some(dot) => (&name[..dot], &name[dot + 1..]),
+        None => (name, &name[name.len()..]),
+    };
+    if base.is_empty() || base.len() > 8 || extension.len() > 3 {
+        return None;
+    }
+    let mut short = [b' '; 11];
+    let (short_base, short_extension) = short.split_at_mut(8);
+    for (dest, c) in short_base
+        .iter_mut()
+        .zip(base)
+        .chain(short_extension.iter_mut().zip(extension))
+    {
+        if !c.is_ascii_graphic() || b"\"*+,./:;<=>?[\\]|".contains(c) {
+            return None;
+        }
+        *dest = c.to_ascii_uppercase();
+    }
+    Some(short)
+}
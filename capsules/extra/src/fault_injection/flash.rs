@@ -0,0 +1,149 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Fault injection for flash.
+//!
+//! Faults are injected into reads, writes and erases. A [`Fault::Error`]
+//! returns the error as is: flash drivers return `FAIL` and `BUSY` for
+//! failed and refused operations.
+
+use core::cell::Cell;
+
+use kernel::hil::flash::{Client, Error, Flash, HasClient};
+use kernel::hil::time::{Alarm, AlarmClient};
+use kernel::utilities::cells::{MapCell, OptionalCell};
+use kernel::utilities::fault_injection::{Fault, FaultSite};
+use kernel::ErrorCode;
+
+enum Completed<P: 'static> {
+    Read(&'static mut P, Result<(), Error>),
+    Write(&'static mut P, Result<(), Error>),
+    Erase(Result<(), Error>),
+}
+
+pub struct FlashFaults<'a, F: Flash + 'static, A: Alarm<'a>> {
+    flash: &'a F,
+    alarm: &'a A,
+    site: FaultSite,
+    client: OptionalCell<&'a dyn Client<FlashFaults<'a, F, A>>>,
+    /// Fault injected into the callback of the pending operation.
+    fault: Cell<Option<Fault>>,
+    delayed: MapCell<Completed<F::Page>>,
+}
+
+impl<'a, F: Flash + 'static, A: Alarm<'a>> FlashFaults<'a, F, A> {
+    /// Wrap `flash`, whose fault site is called `name`. `alarm` delays
+    /// callbacks.
+    pub fn new(flash: &'a F, alarm: &'a A, name: &'static str) -> Self {
+        Self {
+            flash,
+            alarm,
+            site: FaultSite::new(name),
+            client: OptionalCell::empty(),
+            fault: Cell::new(None),
+            delayed: MapCell::empty(),
+        }
+    }
+
+    /// Receive the callbacks of the alarm. The board also sets this wrapper
+    /// as the client of the flash.
+    pub fn setup(&'a self) {
+        self.alarm.set_alarm_client(self);
+    }
+
+    pub fn fault_site(&self) -> &FaultSite {
+        &self.site
+    }
+
+    fn complete(&self, completed: Completed<F::Page>) {
+        match self.fault.take() {
+            Some(Fault::Drop) => {}
+            Some(Fault::Delay(ms)) => {
+                self.delayed.put(completed);
+                super::delay(self.alarm, ms);
+            }
+            _ => self.deliver(completed),
+        }
+    }
+
+    fn deliver(&self, completed: Completed<F::Page>) {
+        self.client.map(move |client| match completed {
+            Completed::Read(buffer, result) => client.read_complete(buffer, result),
+            Completed::Write(buffer, result) => client.write_complete(buffer, result),
+            Completed::Erase(result) => client.erase_complete(result),
+        });
+    }
+}
+
+impl<'a, F: Flash + 'static, A: Alarm<'a>, C: Client<Self>> HasClient<'a, C>
+    for FlashFaults<'a, F, A>
+{
+    fn set_client(&'a self, client: &'a C) {
+        self.client.set(client);
+    }
+}
+
+impl<'a, F: Flash + 'static, A: Alarm<'a>> Flash for FlashFaults<'a, F, A> {
+    type Page = F::Page;
+
+    fn read_page(
+        &self,
+        page_number: usize,
+        buf: &'static mut Self::Page,
+    ) -> Result<(), (ErrorCode, &'static mut Self::Page)> {
+        let fault = self.site.next();
+        if let Some(Fault::Error(error)) = fault {
+            return Err((error, buf));
+        }
+        self.flash.read_page(page_number, buf)?;
+        self.fault.set(fault);
+        Ok(())
+    }
+
+    fn write_page(
+        &self,
+        page_number: usize,
+        buf: &'static mut Self::Page,
+    ) -> Result<(), (ErrorCode, &'static mut Self::Page)> {
+        let fault = self.site.next();
+        if let Some(Fault::Error(error)) = fault {
+            return Err((error, buf));
+        }
+        self.flash.write_page(page_number, buf)?;
+        self.fault.set(fault);
+        Ok(())
+    }
+
+    fn erase_page(&self, page_number: usize) -> Result<(), ErrorCode> {
+        let fault = self.site.next();
+        if let Some(Fault::Error(error)) = fault {
+            return Err(error);
+        }
+        self.flash.erase_page(page_number)?;
+        self.fault.set(fault);
+        Ok(())
+    }
+}
+
+impl<'a, F: Flash + 'static, A: Alarm<'a>> Client<F> for FlashFaults<'a, F, A> {
+    fn read_complete(&self, read_buffer: &'static mut F::Page, result: Result<(), Error>) {
+        self.complete(Completed::Read(read_buffer, result));
+    }
+
+    fn write_complete(&self, write_buffer: &'static mut F::Page, result: Result<(), Error>) {
+        self.complete(Completed::Write(write_buffer, result));
+    }
+
+    fn erase_complete(&self, result: Result<(), Error>) {
+        self.complete(Completed::Erase(result));
+    }
+}
+
+impl<'a, F: Flash + 'static, A: Alarm<'a>> AlarmClient for FlashFaults<'a, F, A> {
+    fn alarm(&self) {
+        if let Some(completed) = self.delayed.take() {
+            self.deliver(completed);
+        }
+    }
+}
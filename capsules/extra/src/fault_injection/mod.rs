@@ -0,0 +1,66 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Wrappers of HIL implementations that inject faults.
+//!
+//! Each wrapper implements the same HIL as the implementation it wraps, and
+//! injects the faults of its [`FaultSite`] into the operations that start a
+//! transfer:
+//!
+//! - [`Fault::Error`]: the operation returns the error, with its buffer, and
+//!   does not reach the hardware.
+//! - [`Fault::Delay`]: the operation reaches the hardware, and its completion
+//!   callback is delivered that many milliseconds late.
+//! - [`Fault::Drop`]: the operation reaches the hardware, and its completion
+//!   callback is never delivered. The buffer of the operation is lost, as it
+//!   would be with a hardware that hangs.
+//!
+//! The other operations, like configuration, are only forwarded. Faults are
+//! only injected with the `fault_injection` feature of the kernel crate, see
+//! `kernel::utilities::fault_injection`.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let uart_alarm = static_init!(
+//!     VirtualMuxAlarm<'static, nrf52840::rtc::Rtc>,
+//!     VirtualMuxAlarm::new(mux_alarm)
+//! );
+//! uart_alarm.setup();
+//! let uart_faults = static_init!(
+//!     capsules_extra::fault_injection::uart::UartFaults<
+//!         'static,
+//!         nrf52840::uart::Uarte,
+//!         VirtualMuxAlarm<'static, nrf52840::rtc::Rtc>,
+//!     >,
+//!     capsules_extra::fault_injection::uart::UartFaults::new(
+//!         &base_peripherals.uarte0,
+//!         uart_alarm,
+//!         "uart0"
+//!     )
+//! );
+//! uart_faults.setup();
+//! // Capsules use `uart_faults` instead of `uarte0`.
+//! ```
+//!
+//! [`FaultSite`]: kernel::utilities::fault_injection::FaultSite
+//! [`Fault::Error`]: kernel::utilities::fault_injection::Fault::Error
+//! [`Fault::Delay`]: kernel::utilities::fault_injection::Fault::Delay
+//! [`Fault::Drop`]: kernel::utilities::fault_injection::Fault::Drop
+
+use kernel::hil::time::{Alarm, ConvertTicks};
+
+pub mod flash;
+pub mod radio;
+pub mod spi;
+pub mod uart;
+
+/// Fire `alarm` in `ms` milliseconds, to deliver a delayed callback.
+///
+/// A wrapper delivers all its delayed callbacks when its alarm fires, so a
+/// second delay started before the first one expired extends it.
+fn delay<'a, A: Alarm<'a>>(alarm: &A, ms: u32) {
+    alarm.set_alarm(alarm.now(), alarm.ticks_from_ms(ms));
+}
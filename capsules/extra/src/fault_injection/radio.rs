@@ -0,0 +1,195 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Fault injection for IEEE 802.15.4 radios.
+//!
+//! Faults are injected into `transmit()`. Received frames, power and
+//! configuration callbacks are only forwarded.
+
+use core::cell::Cell;
+
+use kernel::hil::radio::{
+    ConfigClient, PowerClient, Radio, RadioChannel, RadioConfig, RadioData, RxClient, TxClient,
+};
+use kernel::hil::time::{Alarm, AlarmClient};
+use kernel::utilities::cells::{MapCell, OptionalCell};
+use kernel::utilities::fault_injection::{Fault, FaultSite};
+use kernel::ErrorCode;
+
+struct Sent {
+    buffer: &'static mut [u8],
+    acked: bool,
+    result: Result<(), ErrorCode>,
+}
+
+pub struct RadioFaults<'a, R: Radio<'a>, A: Alarm<'a>> {
+    radio: &'a R,
+    alarm: &'a A,
+    site: FaultSite,
+    tx_client: OptionalCell<&'a dyn TxClient>,
+    /// Fault injected into the callback of the pending transmission.
+    fault: Cell<Option<Fault>>,
+    delayed: MapCell<Sent>,
+}
+
+impl<'a, R: Radio<'a>, A: Alarm<'a>> RadioFaults<'a, R, A> {
+    /// Wrap `radio`, whose fault site is called `name`. `alarm` delays
+    /// callbacks.
+    pub fn new(radio: &'a R, alarm: &'a A, name: &'static str) -> Self {
+        Self {
+            radio,
+            alarm,
+            site: FaultSite::new(name),
+            tx_client: OptionalCell::empty(),
+            fault: Cell::new(None),
+            delayed: MapCell::empty(),
+        }
+    }
+
+    /// Receive the transmit callbacks of the radio and the callbacks of the
+    /// alarm.
+    pub fn setup(&'a self) {
+        self.radio.set_transmit_client(self);
+        self.alarm.set_alarm_client(self);
+    }
+
+    pub fn fault_site(&self) -> &FaultSite {
+        &self.site
+    }
+}
+
+impl<'a, R: Radio<'a>, A: Alarm<'a>> RadioConfig<'a> for RadioFaults<'a, R, A> {
+    fn initialize(&self) -> Result<(), ErrorCode> {
+        self.radio.initialize()
+    }
+
+    fn reset(&self) -> Result<(), ErrorCode> {
+        self.radio.reset()
+    }
+
+    fn start(&self) -> Result<(), ErrorCode> {
+        self.radio.start()
+    }
+
+    fn stop(&self) -> Result<(), ErrorCode> {
+        self.radio.stop()
+    }
+
+    fn is_on(&self) -> bool {
+        self.radio.is_on()
+    }
+
+    fn busy(&self) -> bool {
+        self.radio.busy()
+    }
+
+    fn set_power_client(&self, client: &'a dyn PowerClient) {
+        self.radio.set_power_client(client);
+    }
+
+    fn config_commit(&self) {
+        self.radio.config_commit();
+    }
+
+    fn set_config_client(&self, client: &'a dyn ConfigClient) {
+        self.radio.set_config_client(client);
+    }
+
+    fn get_address(&self) -> u16 {
+        self.radio.get_address()
+    }
+
+    fn get_address_long(&self) -> [u8; 8] {
+        self.radio.get_address_long()
+    }
+
+    fn get_pan(&self) -> u16 {
+        self.radio.get_pan()
+    }
+
+    fn get_tx_power(&self) -> i8 {
+        self.radio.get_tx_power()
+    }
+
+    fn get_channel(&self) -> u8 {
+        self.radio.get_channel()
+    }
+
+    fn set_address(&self, addr: u16) {
+        self.radio.set_address(addr);
+    }
+
+    fn set_address_long(&self, addr: [u8; 8]) {
+        self.radio.set_address_long(addr);
+    }
+
+    fn set_pan(&self, id: u16) {
+        self.radio.set_pan(id);
+    }
+
+    fn set_tx_power(&self, power: i8) -> Result<(), ErrorCode> {
+        self.radio.set_tx_power(power)
+    }
+
+    fn set_channel(&self, chan: RadioChannel) {
+        self.radio.set_channel(chan);
+    }
+}
+
+impl<'a, R: Radio<'a>, A: Alarm<'a>> RadioData<'a> for RadioFaults<'a, R, A> {
+    fn set_transmit_client(&self, client: &'a dyn TxClient) {
+        self.tx_client.set(client);
+    }
+
+    fn set_receive_client(&self, client: &'a dyn RxClient) {
+        self.radio.set_receive_client(client);
+    }
+
+    fn set_receive_buffer(&self, receive_buffer: &'static mut [u8]) {
+        self.radio.set_receive_buffer(receive_buffer);
+    }
+
+    fn transmit(
+        &self,
+        buf: &'static mut [u8],
+        frame_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        let fault = self.site.next();
+        if let Some(Fault::Error(error)) = fault {
+            return Err((error, buf));
+        }
+        self.radio.transmit(buf, frame_len)?;
+        self.fault.set(fault);
+        Ok(())
+    }
+}
+
+impl<'a, R: Radio<'a>, A: Alarm<'a>> TxClient for RadioFaults<'a, R, A> {
+    fn send_done(&self, buf: &'static mut [u8], acked: bool, result: Result<(), ErrorCode>) {
+        match self.fault.take() {
+            Some(Fault::Drop) => {}
+            Some(Fault::Delay(ms)) => {
+                self.delayed.put(Sent {
+                    buffer: buf,
+                    acked,
+                    result,
+                });
+                super::delay(self.alarm, ms);
+            }
+            _ => {
+                self.tx_client
+                    .map(move |client| client.send_done(buf, acked, result));
+            }
+        }
+    }
+}
+
+impl<'a, R: Radio<'a>, A: Alarm<'a>> AlarmClient for RadioFaults<'a, R, A> {
+    fn alarm(&self) {
+        if let Some(sent) = self.delayed.take() {
+            self.tx_client
+                .map(move |client| client.send_done(sent.buffer, sent.acked, sent.result));
+        }
+    }
+}
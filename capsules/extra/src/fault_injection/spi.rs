@@ -0,0 +1,148 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Fault injection for SPI devices.
+//!
+//! Faults are injected into `read_write_bytes()` of one chip select, so
+//! other devices on the same bus are not affected.
+
+use core::cell::Cell;
+
+use kernel::hil::spi::{ClockPhase, ClockPolarity, SpiMasterClient, SpiMasterDevice};
+use kernel::hil::time::{Alarm, AlarmClient};
+use kernel::utilities::cells::{MapCell, OptionalCell};
+use kernel::utilities::fault_injection::{Fault, FaultSite};
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::ErrorCode;
+
+struct Transferred {
+    write_buffer: SubSliceMut<'static, u8>,
+    read_buffer: Option<SubSliceMut<'static, u8>>,
+    status: Result<usize, ErrorCode>,
+}
+
+pub struct SpiFaults<'a, S: SpiMasterDevice<'a>, A: Alarm<'a>> {
+    spi: &'a S,
+    alarm: &'a A,
+    site: FaultSite,
+    client: OptionalCell<&'a dyn SpiMasterClient>,
+    /// Fault injected into the callback of the pending transfer.
+    fault: Cell<Option<Fault>>,
+    delayed: MapCell<Transferred>,
+}
+
+impl<'a, S: SpiMasterDevice<'a>, A: Alarm<'a>> SpiFaults<'a, S, A> {
+    /// Wrap `spi`, whose fault site is called `name`. `alarm` delays
+    /// callbacks.
+    pub fn new(spi: &'a S, alarm: &'a A, name: &'static str) -> Self {
+        Self {
+            spi,
+            alarm,
+            site: FaultSite::new(name),
+            client: OptionalCell::empty(),
+            fault: Cell::new(None),
+            delayed: MapCell::empty(),
+        }
+    }
+
+    /// Receive the callbacks of the SPI device and of the alarm.
+    pub fn setup(&'a self) {
+        self.spi.set_client(self);
+        self.alarm.set_alarm_client(self);
+    }
+
+    pub fn fault_site(&self) -> &FaultSite {
+        &self.site
+    }
+}
+
+impl<'a, S: SpiMasterDevice<'a>, A: Alarm<'a>> SpiMasterDevice<'a> for SpiFaults<'a, S, A> {
+    fn set_client(&self, client: &'a dyn SpiMasterClient) {
+        self.client.set(client);
+    }
+
+    fn configure(&self, cpol: ClockPolarity, cpal: ClockPhase, rate: u32) -> Result<(), ErrorCode> {
+        self.spi.configure(cpol, cpal, rate)
+    }
+
+    fn read_write_bytes(
+        &self,
+        write_buffer: SubSliceMut<'static, u8>,
+        read_buffer: Option<SubSliceMut<'static, u8>>,
+    ) -> Result<
+        (),
+        (
+            ErrorCode,
+            SubSliceMut<'static, u8>,
+            Option<SubSliceMut<'static, u8>>,
+        ),
+    > {
+        let fault = self.site.next();
+        if let Some(Fault::Error(error)) = fault {
+            return Err((error, write_buffer, read_buffer));
+        }
+        self.spi.read_write_bytes(write_buffer, read_buffer)?;
+        self.fault.set(fault);
+        Ok(())
+    }
+
+    fn set_rate(&self, rate: u32) -> Result<(), ErrorCode> {
+        self.spi.set_rate(rate)
+    }
+
+    fn get_rate(&self) -> u32 {
+        self.spi.get_rate()
+    }
+
+    fn set_polarity(&self, polarity: ClockPolarity) -> Result<(), ErrorCode> {
+        self.spi.set_polarity(polarity)
+    }
+
+    fn get_polarity(&self) -> ClockPolarity {
+        self.spi.get_polarity()
+    }
+
+    fn set_phase(&self, phase: ClockPhase) -> Result<(), ErrorCode> {
+        self.spi.set_phase(phase)
+    }
+
+    fn get_phase(&self) -> ClockPhase {
+        self.spi.get_phase()
+    }
+}
+
+impl<'a, S: SpiMasterDevice<'a>, A: Alarm<'a>> SpiMasterClient for SpiFaults<'a, S, A> {
+    fn read_write_done(
+        &self,
+        write_buffer: SubSliceMut<'static, u8>,
+        read_buffer: Option<SubSliceMut<'static, u8>>,
+        status: Result<usize, ErrorCode>,
+    ) {
+        match self.fault.take() {
+            Some(Fault::Drop) => {}
+            Some(Fault::Delay(ms)) => {
+                self.delayed.put(Transferred {
+                    write_buffer,
+                    read_buffer,
+                    status,
+                });
+                super::delay(self.alarm, ms);
+            }
+            _ => {
+                self.client
+                    .map(move |client| client.read_write_done(write_buffer, read_buffer, status));
+            }
+        }
+    }
+}
+
+impl<'a, S: SpiMasterDevice<'a>, A: Alarm<'a>> AlarmClient for SpiFaults<'a, S, A> {
+    fn alarm(&self) {
+        if let Some(transfer) = self.delayed.take() {
+            self.client.map(move |client| {
+                client.read_write_done(transfer.write_buffer, transfer.read_buffer, transfer.status)
+            });
+        }
+    }
+}
@@ -0,0 +1,210 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Fault injection for UARTs.
+//!
+//! Faults are injected into `transmit_buffer()` and `receive_buffer()`. Word
+//! transfers are only forwarded.
+
+use core::cell::Cell;
+
+use kernel::hil::time::{Alarm, AlarmClient};
+use kernel::hil::uart::{
+    Configure, Error, Parameters, Receive, ReceiveClient, Transmit, TransmitClient, Uart,
+};
+use kernel::utilities::cells::{MapCell, OptionalCell};
+use kernel::utilities::fault_injection::{Fault, FaultSite};
+use kernel::ErrorCode;
+
+struct Transmitted {
+    buffer: &'static mut [u8],
+    len: usize,
+    rval: Result<(), ErrorCode>,
+}
+
+struct Received {
+    buffer: &'static mut [u8],
+    len: usize,
+    rval: Result<(), ErrorCode>,
+    error: Error,
+}
+
+pub struct UartFaults<'a, U: Uart<'a>, A: Alarm<'a>> {
+    uart: &'a U,
+    alarm: &'a A,
+    site: FaultSite,
+    tx_client: OptionalCell<&'a dyn TransmitClient>,
+    rx_client: OptionalCell<&'a dyn ReceiveClient>,
+    /// Fault injected into the callback of the pending transmission.
+    tx_fault: Cell<Option<Fault>>,
+    /// Fault injected into the callback of the pending reception.
+    rx_fault: Cell<Option<Fault>>,
+    delayed_tx: MapCell<Transmitted>,
+    delayed_rx: MapCell<Received>,
+}
+
+impl<'a, U: Uart<'a>, A: Alarm<'a>> UartFaults<'a, U, A> {
+    /// Wrap `uart`, whose fault site is called `name`. `alarm` delays
+    /// callbacks.
+    pub fn new(uart: &'a U, alarm: &'a A, name: &'static str) -> Self {
+        Self {
+            uart,
+            alarm,
+            site: FaultSite::new(name),
+            tx_client: OptionalCell::empty(),
+            rx_client: OptionalCell::empty(),
+            tx_fault: Cell::new(None),
+            rx_fault: Cell::new(None),
+            delayed_tx: MapCell::empty(),
+            delayed_rx: MapCell::empty(),
+        }
+    }
+
+    /// Receive the callbacks of the UART and of the alarm.
+    pub fn setup(&'a self) {
+        self.uart.set_transmit_client(self);
+        self.uart.set_receive_client(self);
+        self.alarm.set_alarm_client(self);
+    }
+
+    pub fn fault_site(&self) -> &FaultSite {
+        &self.site
+    }
+}
+
+impl<'a, U: Uart<'a>, A: Alarm<'a>> Configure for UartFaults<'a, U, A> {
+    fn configure(&self, params: Parameters) -> Result<(), ErrorCode> {
+        self.uart.configure(params)
+    }
+}
+
+impl<'a, U: Uart<'a>, A: Alarm<'a>> Transmit<'a> for UartFaults<'a, U, A> {
+    fn set_transmit_client(&self, client: &'a dyn TransmitClient) {
+        self.tx_client.set(client);
+    }
+
+    fn transmit_buffer(
+        &self,
+        tx_buffer: &'static mut [u8],
+        tx_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        let fault = self.site.next();
+        if let Some(Fault::Error(error)) = fault {
+            return Err((error, tx_buffer));
+        }
+        self.uart.transmit_buffer(tx_buffer, tx_len)?;
+        self.tx_fault.set(fault);
+        Ok(())
+    }
+
+    fn transmit_word(&self, word: u32) -> Result<(), ErrorCode> {
+        self.uart.transmit_word(word)
+    }
+
+    fn transmit_abort(&self) -> Result<(), ErrorCode> {
+        self.uart.transmit_abort()
+    }
+}
+
+impl<'a, U: Uart<'a>, A: Alarm<'a>> Receive<'a> for UartFaults<'a, U, A> {
+    fn set_receive_client(&self, client: &'a dyn ReceiveClient) {
+        self.rx_client.set(client);
+    }
+
+    fn receive_buffer(
+        &self,
+        rx_buffer: &'static mut [u8],
+        rx_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        let fault = self.site.next();
+        if let Some(Fault::Error(error)) = fault {
+            return Err((error, rx_buffer));
+        }
+        self.uart.receive_buffer(rx_buffer, rx_len)?;
+        self.rx_fault.set(fault);
+        Ok(())
+    }
+
+    fn receive_word(&self) -> Result<(), ErrorCode> {
+        self.uart.receive_word()
+    }
+
+    fn receive_abort(&self) -> Result<(), ErrorCode> {
+        self.uart.receive_abort()
+    }
+}
+
+impl<'a, U: Uart<'a>, A: Alarm<'a>> TransmitClient for UartFaults<'a, U, A> {
+    fn transmitted_word(&self, rval: Result<(), ErrorCode>) {
+        self.tx_client.map(|client| client.transmitted_word(rval));
+    }
+
+    fn transmitted_buffer(
+        &self,
+        tx_buffer: &'static mut [u8],
+        tx_len: usize,
+        rval: Result<(), ErrorCode>,
+    ) {
+        match self.tx_fault.take() {
+            Some(Fault::Drop) => {}
+            Some(Fault::Delay(ms)) => {
+                self.delayed_tx.put(Transmitted {
+                    buffer: tx_buffer,
+                    len: tx_len,
+                    rval,
+                });
+                super::delay(self.alarm, ms);
+            }
+            _ => {
+                self.tx_client
+                    .map(move |client| client.transmitted_buffer(tx_buffer, tx_len, rval));
+            }
+        }
+    }
+}
+
+impl<'a, U: Uart<'a>, A: Alarm<'a>> ReceiveClient for UartFaults<'a, U, A> {
+    fn received_word(&self, word: u32, rval: Result<(), ErrorCode>, error: Error) {
+        self.rx_client
+            .map(|client| client.received_word(word, rval, error));
+    }
+
+    fn received_buffer(
+        &self,
+        rx_buffer: &'static mut [u8],
+        rx_len: usize,
+        rval: Result<(), ErrorCode>,
+        error: Error,
+    ) {
+        match self.rx_fault.take() {
+            Some(Fault::Drop) => {}
+            Some(Fault::Delay(ms)) => {
+                self.delayed_rx.put(Received {
+                    buffer: rx_buffer,
+                    len: rx_len,
+                    rval,
+                    error,
+                });
+                super::delay(self.alarm, ms);
+            }
+            _ => {
+                self.rx_client
+                    .map(move |client| client.received_buffer(rx_buffer, rx_len, rval, error));
+            }
+        }
+    }
+}
+
+impl<'a, U: Uart<'a>, A: Alarm<'a>> AlarmClient for UartFaults<'a, U, A> {
+    fn alarm(&self) {
+        if let Some(tx) = self.delayed_tx.take() {
+            self.tx_client
+                .map(move |client| client.transmitted_buffer(tx.buffer, tx.len, tx.rval));
+        }
+        if let Some(rx) = self.delayed_rx.take() {
+            self.rx_client
+                .map(move |client| client.received_buffer(rx.buffer, rx.len, rx.rval, rx.error));
+        }
+    }
+}
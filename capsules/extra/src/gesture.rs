@@ -0,0 +1,318 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Gesture recognition for touch panels.
+//!
+//! Most touch panels only report where they are touched. This capsule sits
+//! between the touch panel and the touch driver, recognizes taps, long
+//! presses and swipes from the touch events of the panel, and delivers them
+//! to processes and to a gesture client, such as the touch driver, so apps
+//! do not each recognize gestures on their own.
+//!
+//! A gesture starts when the panel is pressed, and follows that touch:
+//!
+//! - A release that is followed by a press within [`DEBOUNCE_MS`] is a
+//!   bounce of the contact, and the gesture goes on.
+//! - A touch that stays within half the swipe distance of where it started
+//!   for [`LONG_PRESS_MS`] is a long press, which is reported while the panel
+//!   is still pressed.
+//! - A touch released at least the swipe distance away from where it started
+//!   is a swipe, in the direction it moved the most in.
+//! - A touch that stays within half the swipe distance of where it started,
+//!   and is released before it is a long press, is a tap.
+//!
+//! Positions and directions are in the coordinates of the panel.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let gesture = components::gesture::GestureComponent::new(
+//!     board_kernel,
+//!     capsules_extra::gesture::DRIVER_NUM,
+//!     touch_panel,
+//!     mux_alarm,
+//!     40,
+//! )
+//! .finalize(components::gesture_component_static!(nrf52840::rtc::Rtc<'static>));
+//! let touch = components::touch::TouchComponent::new(
+//!     board_kernel,
+//!     capsules_extra::touch::DRIVER_NUM,
+//!     gesture,
+//!     Some(gesture),
+//!     Some(screen),
+//! )
+//! .finalize(components::touch_component_static!());
+//! ```
+
+use core::cell::Cell;
+
+use crate::touch::gesture_to_number;
+use capsules_core::driver;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil;
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks};
+use kernel::hil::touch::{GestureClient, GestureEvent, TouchClient, TouchEvent, TouchStatus};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::OptionalCell;
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+pub const DRIVER_NUM: usize = driver::NUM::Gesture as usize;
+
+/// Longest time between a release and a press that continues the gesture.
+pub const DEBOUNCE_MS: u32 = 30;
+/// Time a touch must stay in place to be a long press.
+pub const LONG_PRESS_MS: u32 = 600;
+
+/// IDs for subscribed upcalls.
+mod upcall {
+    /// Gesture recognized. Arguments are the gesture, and the x and y
+    /// position where it started.
+    pub const GESTURE: usize = 0;
+    pub const COUNT: u8 = 1;
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    /// The panel is pressed.
+    Pressed,
+    /// The panel was released, and the gesture ends unless it is pressed
+    /// again within [`DEBOUNCE_MS`].
+    Released,
+}
+
+#[derive(Default)]
+pub struct App {
+    enabled: bool,
+}
+
+pub struct GestureRecognizer<'a, A: Alarm<'a>> {
+    touch: &'a dyn hil::touch::Touch<'a>,
+    alarm: &'a A,
+    /// Distance a touch must move to be a swipe.
+    swipe_distance: u16,
+    apps: Grant<App, UpcallCount<{ upcall::COUNT }>, AllowRoCount<0>, AllowRwCount<0>>,
+    client: OptionalCell<&'a dyn TouchClient>,
+    gesture_client: OptionalCell<&'a dyn GestureClient>,
+    /// Whether the client enabled the panel.
+    enabled: Cell<bool>,
+    state: Cell<State>,
+    /// Touch the gesture follows.
+    id: Cell<usize>,
+    start: Cell<(u16, u16)>,
+    position: Cell<(u16, u16)>,
+    pressed_at: Cell<A::Ticks>,
+    /// Whether the touch moved further than half the swipe distance, which
+    /// rules out taps and long presses.
+    moved: Cell<bool>,
+    long_pressed: Cell<bool>,
+}
+
+impl<'a, A: Alarm<'a>> GestureRecognizer<'a, A> {
+    pub fn new(
+        touch: &'a dyn hil::touch::Touch<'a>,
+        alarm: &'a A,
+        swipe_distance: u16,
+        grant: Grant<App, UpcallCount<{ upcall::COUNT }>, AllowRoCount<0>, AllowRwCount<0>>,
+    ) -> Self {
+        Self {
+            touch,
+            alarm,
+            swipe_distance,
+            apps: grant,
+            client: OptionalCell::empty(),
+            gesture_client: OptionalCell::empty(),
+            enabled: Cell::new(false),
+            state: Cell::new(State::Idle),
+            id: Cell::new(0),
+            start: Cell::new((0, 0)),
+            position: Cell::new((0, 0)),
+            pressed_at: Cell::new(A::Ticks::from(0)),
+            moved: Cell::new(false),
+            long_pressed: Cell::new(false),
+        }
+    }
+
+    /// Whether a process receives gestures.
+    fn processes_enabled(&self) -> bool {
+        self.apps.iter().any(|app| app.enter(|app, _| app.enabled))
+    }
+
+    /// Turn gestures on or off for `processid`.
+    fn set_enabled(&self, processid: ProcessId, enabled: bool) -> Result<(), ErrorCode> {
+        self.apps.enter(processid, |app, _| app.enabled = enabled)?;
+        if enabled {
+            self.touch.enable()
+        } else if self.enabled.get() || self.processes_enabled() {
+            Ok(())
+        } else {
+            self.touch.disable()
+        }
+    }
+
+    fn report(&self, gesture: GestureEvent) {
+        let (x, y) = self.start.get();
+        for app in self.apps.iter() {
+            app.enter(|app, kernel_data| {
+                if app.enabled {
+                    kernel_data
+                        .schedule_upcall(
+                            upcall::GESTURE,
+                            (gesture_to_number(&gesture), x as usize, y as usize),
+                        )
+                        .ok();
+                }
+            });
+        }
+        self.gesture_client
+            .map(|client| client.gesture_event(gesture));
+    }
+
+    /// Follow the touch to `(x, y)`.
+    fn track(&self, x: u16, y: u16) {
+        self.position.set((x, y));
+        let (start_x, start_y) = self.start.get();
+        let distance = x.abs_diff(start_x).max(y.abs_diff(start_y));
+        if distance > self.swipe_distance / 2 {
+            self.moved.set(true);
+        }
+    }
+
+    /// Report the gesture of the touch that was released.
+    fn end(&self) {
+        self.state.set(State::Idle);
+        let (start_x, start_y) = self.start.get();
+        let (x, y) = self.position.get();
+        let dx = x as i32 - start_x as i32;
+        let dy = y as i32 - start_y as i32;
+        let gesture = if dx.unsigned_abs().max(dy.unsigned_abs()) >= self.swipe_distance as u32 {
+            Some(match (dx.abs() >= dy.abs(), dx > 0, dy > 0) {
+                (true, true, _) => GestureEvent::SwipeRight,
+                (true, false, _) => GestureEvent::SwipeLeft,
+                (false, _, true) => GestureEvent::SwipeDown,
+                (false, _, false) => GestureEvent::SwipeUp,
+            })
+        } else if !self.moved.get() && !self.long_pressed.get() {
+            Some(GestureEvent::Tap)
+        } else {
+            None
+        };
+        if let Some(gesture) = gesture {
+            self.report(gesture);
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>> hil::touch::Touch<'a> for GestureRecognizer<'a, A> {
+    fn enable(&self) -> Result<(), ErrorCode> {
+        self.enabled.set(true);
+        self.touch.enable()
+    }
+
+    fn disable(&self) -> Result<(), ErrorCode> {
+        self.enabled.set(false);
+        if self.processes_enabled() {
+            // Keep the panel on for the processes.
+            Ok(())
+        } else {
+            self.touch.disable()
+        }
+    }
+
+    fn set_client(&self, client: &'a dyn TouchClient) {
+        self.client.set(client);
+    }
+}
+
+impl<'a, A: Alarm<'a>> hil::touch::Gesture<'a> for GestureRecognizer<'a, A> {
+    fn set_client(&self, gesture_client: &'a dyn GestureClient) {
+        self.gesture_client.set(gesture_client);
+    }
+}
+
+impl<'a, A: Alarm<'a>> TouchClient for GestureRecognizer<'a, A> {
+    fn touch_event(&self, event: TouchEvent) {
+        if self.enabled.get() {
+            self.client.map(|client| client.touch_event(event));
+        }
+
+        match (self.state.get(), event.status) {
+            (State::Idle, TouchStatus::Pressed) => {
+                let now = self.alarm.now();
+                self.state.set(State::Pressed);
+                self.id.set(event.id);
+                self.start.set((event.x, event.y));
+                self.position.set((event.x, event.y));
+                self.pressed_at.set(now);
+                self.moved.set(false);
+                self.long_pressed.set(false);
+                self.alarm
+                    .set_alarm(now, self.alarm.ticks_from_ms(LONG_PRESS_MS));
+            }
+            (State::Released, TouchStatus::Pressed) => {
+                // The release was a bounce.
+                self.state.set(State::Pressed);
+                self.id.set(event.id);
+                self.track(event.x, event.y);
+                self.alarm.set_alarm(
+                    self.pressed_at.get(),
+                    self.alarm.ticks_from_ms(LONG_PRESS_MS),
+                );
+            }
+            (State::Pressed, TouchStatus::Moved) if event.id == self.id.get() => {
+                self.track(event.x, event.y);
+            }
+            (State::Pressed, TouchStatus::Released) if event.id == self.id.get() => {
+                self.state.set(State::Released);
+                self.track(event.x, event.y);
+                self.alarm
+                    .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(DEBOUNCE_MS));
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>> AlarmClient for GestureRecognizer<'a, A> {
+    fn alarm(&self) {
+        match self.state.get() {
+            State::Pressed => {
+                if !self.moved.get() && !self.long_pressed.get() {
+                    self.long_pressed.set(true);
+                    self.report(GestureEvent::LongPress);
+                }
+            }
+            State::Released => self.end(),
+            State::Idle => {}
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>> SyscallDriver for GestureRecognizer<'a, A> {
+    fn command(
+        &self,
+        command_num: usize,
+        _arg1: usize,
+        _arg2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            // Start receiving gestures
+            1 => self.set_enabled(processid, true).into(),
+
+            // Stop receiving gestures
+            2 => self.set_enabled(processid, false).into(),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
@@ -0,0 +1,147 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Power rail switched by a GPIO pin, such as the enable pin of a load switch,
+//! a charge pump or a regulator.
+//!
+//! Turning the rail on sets the pin active, and completes after the settle
+//! time of the rail, measured with an alarm. Turning it off sets the pin
+//! inactive, and completes from a deferred call.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let sensor_rail = static_init!(
+//!     capsules_extra::gpio_power_rail::GpioPowerRail<'static, VirtualMuxAlarm<'static, Rtc>>,
+//!     capsules_extra::gpio_power_rail::GpioPowerRail::new(
+//!         "sensors",
+//!         &nrf52840_peripherals.gpio_port[SENSORS_ENABLE],
+//!         kernel::hil::gpio::ActivationMode::ActiveHigh,
+//!         virtual_alarm,
+//!         5,
+//!     ));
+//! virtual_alarm.set_alarm_client(sensor_rail);
+//! sensor_rail.register();
+//! ```
+
+use core::cell::Cell;
+
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::gpio::{ActivationMode, ActivationState, Pin};
+use kernel::hil::power_rail::{PowerRail, PowerRailClient};
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks};
+use kernel::utilities::cells::OptionalCell;
+use kernel::ErrorCode;
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Off,
+    Settling,
+    On,
+    TurningOff,
+}
+
+pub struct GpioPowerRail<'a, A: Alarm<'a>> {
+    name: &'static str,
+    pin: &'a dyn Pin,
+    mode: ActivationMode,
+    alarm: &'a A,
+    /// Time for the rail to be usable after the pin is set active.
+    settle_ms: u32,
+    state: Cell<State>,
+    client: OptionalCell<&'a dyn PowerRailClient>,
+    deferred_call: DeferredCall,
+}
+
+impl<'a, A: Alarm<'a>> GpioPowerRail<'a, A> {
+    /// The rail starts off.
+    pub fn new(
+        name: &'static str,
+        pin: &'a dyn Pin,
+        mode: ActivationMode,
+        alarm: &'a A,
+        settle_ms: u32,
+    ) -> Self {
+        pin.make_output();
+        pin.write_activation(ActivationState::Inactive, mode);
+        Self {
+            name,
+            pin,
+            mode,
+            alarm,
+            settle_ms,
+            state: Cell::new(State::Off),
+            client: OptionalCell::empty(),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>> PowerRail<'a> for GpioPowerRail<'a, A> {
+    fn set_client(&self, client: &'a dyn PowerRailClient) {
+        self.client.set(client);
+    }
+
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn power_on(&self) -> Result<(), ErrorCode> {
+        match self.state.get() {
+            State::Off => {
+                self.pin
+                    .write_activation(ActivationState::Active, self.mode);
+                self.state.set(State::Settling);
+                self.alarm
+                    .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(self.settle_ms));
+                Ok(())
+            }
+            State::On => Err(ErrorCode::ALREADY),
+            State::Settling | State::TurningOff => Err(ErrorCode::BUSY),
+        }
+    }
+
+    fn power_off(&self) -> Result<(), ErrorCode> {
+        match self.state.get() {
+            State::On => {
+                self.pin
+                    .write_activation(ActivationState::Inactive, self.mode);
+                self.state.set(State::TurningOff);
+                self.deferred_call.set();
+                Ok(())
+            }
+            State::Off => Err(ErrorCode::ALREADY),
+            State::Settling | State::TurningOff => Err(ErrorCode::BUSY),
+        }
+    }
+
+    fn is_on(&self) -> bool {
+        self.state.get() == State::On
+    }
+}
+
+impl<'a, A: Alarm<'a>> AlarmClient for GpioPowerRail<'a, A> {
+    fn alarm(&self) {
+        if self.state.get() == State::Settling {
+            self.state.set(State::On);
+            self.client.map(|client| client.power_on_done(Ok(())));
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>> DeferredCallClient for GpioPowerRail<'a, A> {
+    fn handle_deferred_call(&self) {
+        if self.state.get() == State::TurningOff {
+            self.state.set(State::Off);
+            self.client.map(|client| client.power_off_done(Ok(())));
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
@@ -0,0 +1,319 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Forward peripheral operations to a process on the host.
+//!
+//! When running Tock in an emulator such as QEMU there are often no GPIO
+//! pins, screens or sensors to test applications with. The host bridge
+//! provides stand-in implementations of these HILs that forward every
+//! operation over a serial channel (for example a UART connected to a socket
+//! with `-serial tcp::4444,server`) to a process on the host, which can show
+//! the state of the virtual peripherals and inject input and sensor values.
+//!
+//! The bridge itself ([`HostBridge`]) only frames and multiplexes messages.
+//! Virtual peripherals register with it as [`HostBridgeDevice`]s:
+//!
+//! - [`pin::HostBridgePin`]: a GPIO pin (`hil::gpio::Pin` and
+//!   `hil::gpio::Interrupt`).
+//! - [`screen::HostBridgeScreen`]: a screen (`hil::screen::Screen`).
+//! - [`sensor::HostBridgeSensor`]: a temperature and humidity sensor.
+//!
+//! Protocol
+//! --------
+//!
+//! Messages in both directions use the same framing:
+//!
+//! ```text
+//! +------+------+----+--------------+---------------+
+//! | 0x02 | kind | id | length (u16) | payload ...   |
+//! +------+------+----+--------------+---------------+
+//! ```
+//!
+//! `id` is the identifier the device was created with, and all multi-byte
+//! values are little endian. Bytes received outside of a frame are ignored,
+//! so the channel can be shared with plain text output. The kinds and their
+//! payloads are documented with the [`kind`] constants.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let bridge = static_init!(
+//!     HostBridge<'static>,
+//!     HostBridge::new(bridge_uart, tx_buffer, rx_buffer, frame_buffer)
+//! );
+//! bridge_uart.set_transmit_client(bridge);
+//! bridge_uart.set_receive_client(bridge);
+//!
+//! let led_pin = static_init!(HostBridgePin<'static>, HostBridgePin::new(bridge, 0));
+//! bridge.add_device(led_pin);
+//! bridge.start();
+//! ```
+
+use core::cell::Cell;
+use core::cmp;
+
+use kernel::collections::list::{List, ListLink, ListNode};
+use kernel::hil::uart;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+pub mod pin;
+pub mod screen;
+pub mod sensor;
+
+/// Recommended size of the transmit buffer.
+pub const TX_BUF_LEN: usize = 256;
+/// Recommended size of the frame buffer for received messages. Longer
+/// messages from the host are dropped.
+pub const FRAME_BUF_LEN: usize = 16;
+
+/// First byte of every frame.
+const FRAME_START: u8 = 0x02;
+/// Length of the frame header.
+const HEADER_LEN: usize = 5;
+
+/// Kinds of messages exchanged with the host.
+pub mod kind {
+    /// State of a GPIO pin.
+    ///
+    /// To the host: `[level, configuration]`, where `configuration` is 0 for
+    /// low power, 1 for input, 2 for output, 3 for input and output and 4
+    /// for any other configuration.
+    ///
+    /// From the host: `[level]`, the level applied to the pin's input.
+    pub const GPIO: u8 = 0x01;
+    /// Screen write frame, to the host: `[x, y, width, height]`, each a u16.
+    pub const SCREEN_FRAME: u8 = 0x02;
+    /// Pixel data for the current write frame, to the host. The first frame
+    /// of a new write starts with a reset flag byte of 1, continued writes
+    /// with 0, followed by the pixel data.
+    pub const SCREEN_DATA: u8 = 0x03;
+    /// Screen settings, to the host: `[power, invert, brightness (u16)]`.
+    pub const SCREEN_CONTROL: u8 = 0x04;
+    /// Request for a new sensor value, to the host: `[channel]`, where the
+    /// channel is 0 for temperature and 1 for humidity.
+    pub const SENSOR_REQUEST: u8 = 0x05;
+    /// Sensor value, from the host: `[channel, value (i32)]`, with
+    /// temperatures in hundredths of degrees centigrade and humidity in
+    /// hundredths of percent.
+    pub const SENSOR_VALUE: u8 = 0x06;
+}
+
+/// A virtual peripheral connected to the host through a [`HostBridge`].
+pub trait HostBridgeDevice<'a>: 'a {
+    /// Identifier of this device in frames.
+    fn id(&self) -> u8;
+
+    fn next_device(&'a self) -> &'a ListLink<'a, dyn HostBridgeDevice<'a>>;
+
+    /// Write the payload of the next message for the host into `payload`.
+    ///
+    /// Returns the kind and length of the message, or `None` if the device
+    /// has nothing to send.
+    fn transmit(&self, payload: &mut [u8]) -> Option<(u8, usize)>;
+
+    /// The message returned by the last call to `transmit()` was sent.
+    fn transmit_done(&self, result: Result<(), ErrorCode>);
+
+    /// A message of `message_kind` addressed to this device was received.
+    fn received(&self, message_kind: u8, payload: &[u8]);
+}
+
+impl<'a> ListNode<'a, dyn HostBridgeDevice<'a>> for dyn HostBridgeDevice<'a> {
+    fn next(&'a self) -> &'a ListLink<'a, dyn HostBridgeDevice<'a>> {
+        self.next_device()
+    }
+}
+
+/// Multiplexes virtual peripherals over a serial channel to the host.
+pub struct HostBridge<'a> {
+    uart: &'a dyn uart::UartData<'a>,
+    devices: List<'a, dyn HostBridgeDevice<'a>>,
+    tx_buffer: TakeCell<'static, [u8]>,
+    rx_buffer: TakeCell<'static, [u8]>,
+    /// Frame being received.
+    frame: TakeCell<'static, [u8]>,
+    frame_len: Cell<usize>,
+    /// Device whose message is being transmitted.
+    tx_device: OptionalCell<&'a dyn HostBridgeDevice<'a>>,
+    /// Identifier of the device that transmitted last, so that devices take
+    /// turns.
+    last_tx_id: OptionalCell<u8>,
+}
+
+impl<'a> HostBridge<'a> {
+    /// Create a host bridge.
+    ///
+    /// Messages to the host are limited to the length of `tx_buffer` minus the
+    /// five byte frame header, and messages from the host to the length of
+    /// `frame_buffer`. `rx_buffer` must be at least one byte long.
+    pub fn new(
+        uart: &'a dyn uart::UartData<'a>,
+        tx_buffer: &'static mut [u8],
+        rx_buffer: &'static mut [u8],
+        frame_buffer: &'static mut [u8],
+    ) -> HostBridge<'a> {
+        HostBridge {
+            uart,
+            devices: List::new(),
+            tx_buffer: TakeCell::new(tx_buffer),
+            rx_buffer: TakeCell::new(rx_buffer),
+            frame: TakeCell::new(frame_buffer),
+            frame_len: Cell::new(0),
+            tx_device: OptionalCell::empty(),
+            last_tx_id: OptionalCell::empty(),
+        }
+    }
+
+    pub fn add_device(&self, device: &'a dyn HostBridgeDevice<'a>) {
+        self.devices.push_tail(device);
+    }
+
+    /// Start receiving messages from the host and send the initial state of
+    /// all devices.
+    pub fn start(&self) -> Result<(), ErrorCode> {
+        self.receive_next()?;
+        self.transmit_pending();
+        Ok(())
+    }
+
+    fn receive_next(&self) -> Result<(), ErrorCode> {
+        self.rx_buffer
+            .take()
+            .map_or(Err(ErrorCode::BUSY), |buffer| {
+                self.uart.receive_buffer(buffer, 1).map_err(|(e, buffer)| {
+                    self.rx_buffer.replace(buffer);
+                    e
+                })
+            })
+    }
+
+    /// Find the next device with a message for the host, starting after the
+    /// device that transmitted last.
+    fn next_transmitter(
+        &self,
+        payload: &mut [u8],
+    ) -> Option<(&'a dyn HostBridgeDevice<'a>, u8, usize)> {
+        let start = self
+            .last_tx_id
+            .get()
+            .and_then(|id| self.devices.iter().position(|device| device.id() == id))
+            .map_or(0, |position| position + 1);
+
+        self.devices
+            .iter()
+            .skip(start)
+            .chain(self.devices.iter().take(start))
+            .find_map(|device| {
+                device
+                    .transmit(payload)
+                    .map(|(kind, length)| (device, kind, length))
+            })
+    }
+    /// Send the next pending message to the host, if the channel is free.
+    ///
+    /// Devices call this whenever they have a new message to send.
+    pub fn transmit_pending(&self) {
+        if self.tx_device.is_some() {
+            return;
+        }
+        self.tx_buffer.take().map(|buffer| {
+            if buffer.len() <= HEADER_LEN {
+                self.tx_buffer.replace(buffer);
+                return;
+            }
+            let payload_len = buffer.len() - HEADER_LEN;
+            match self.next_transmitter(&mut buffer[HEADER_LEN..]) {
+                Some((device, kind, length)) => {
+                    let length = cmp::min(length, payload_len);
+                    buffer[0] = FRAME_START;
+                    buffer[1] = kind;
+                    buffer[2] = device.id();
+                    buffer[3..5].copy_from_slice(&(length as u16).to_le_bytes());
+
+                    self.tx_device.set(device);
+                    self.last_tx_id.set(device.id());
+                    if let Err((e, buffer)) = self.uart.transmit_buffer(buffer, HEADER_LEN + length)
+                    {
+                        self.tx_buffer.replace(buffer);
+                        self.tx_device.clear();
+                        device.transmit_done(Err(e));
+                    }
+                }
+                None => {
+                    self.tx_buffer.replace(buffer);
+                }
+            }
+        });
+    }
+
+    /// Add a received byte to the current frame, and dispatch the frame once
+    /// complete.
+    fn receive_byte(&self, byte: u8) {
+        self.frame.map(|frame| {
+            let len = self.frame_len.get();
+            if len == 0 && byte != FRAME_START {
+                // Not part of a frame.
+                return;
+            }
+            if len >= frame.len() {
+                // Frame too long for the buffer, drop it.
+                self.frame_len.set(0);
+                return;
+            }
+
+            frame[len] = byte;
+            let len = len + 1;
+            self.frame_len.set(len);
+
+            if len >= HEADER_LEN {
+                let payload_len = u16::from_le_bytes([frame[3], frame[4]]) as usize;
+                if HEADER_LEN + payload_len > frame.len() {
+                    self.frame_len.set(0);
+                } else if len == HEADER_LEN + payload_len {
+                    self.frame_len.set(0);
+                    let (kind, id) = (frame[1], frame[2]);
+                    self.devices
+                        .iter()
+                        .find(|device| device.id() == id)
+                        .map(|device| device.received(kind, &frame[HEADER_LEN..len]));
+                }
+            }
+        });
+    }
+}
+
+impl uart::TransmitClient for HostBridge<'_> {
+    fn transmitted_buffer(
+        &self,
+        tx_buffer: &'static mut [u8],
+        _tx_len: usize,
+        rval: Result<(), ErrorCode>,
+    ) {
+        self.tx_buffer.replace(tx_buffer);
+        self.tx_device
+            .take()
+            .map(|device| device.transmit_done(rval));
+        self.transmit_pending();
+    }
+}
+
+impl uart::ReceiveClient for HostBridge<'_> {
+    fn received_buffer(
+        &self,
+        rx_buffer: &'static mut [u8],
+        rx_len: usize,
+        rval: Result<(), ErrorCode>,
+        _error: uart::Error,
+    ) {
+        if rval.is_ok() {
+            rx_buffer[..rx_len]
+                .iter()
+                .for_each(|byte| self.receive_byte(*byte));
+        }
+        self.rx_buffer.replace(rx_buffer);
+        let _ = self.receive_next();
+    }
+}
@@ -0,0 +1,217 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! GPIO pin forwarded to the host.
+//!
+//! The output level and configuration of the pin are sent to the host every
+//! time they change. The host drives the input level of the pin, which also
+//! generates interrupts.
+
+use core::cell::Cell;
+
+use kernel::collections::list::ListLink;
+use kernel::hil::gpio;
+use kernel::utilities::cells::OptionalCell;
+use kernel::ErrorCode;
+
+use super::{kind, HostBridge, HostBridgeDevice};
+
+pub struct HostBridgePin<'a> {
+    bridge: &'a HostBridge<'a>,
+    id: u8,
+    next: ListLink<'a, dyn HostBridgeDevice<'a>>,
+    configuration: Cell<gpio::Configuration>,
+    floating_state: Cell<gpio::FloatingState>,
+    /// Level driven by the kernel.
+    output: Cell<bool>,
+    /// Level driven by the host.
+    input: Cell<bool>,
+    /// Whether the state changed since it was last sent to the host.
+    changed: Cell<bool>,
+    interrupt_edge: OptionalCell<gpio::InterruptEdge>,
+    client: OptionalCell<&'a dyn gpio::Client>,
+}
+
+impl<'a> HostBridgePin<'a> {
+    pub fn new(bridge: &'a HostBridge<'a>, id: u8) -> HostBridgePin<'a> {
+        HostBridgePin {
+            bridge,
+            id,
+            next: ListLink::empty(),
+            configuration: Cell::new(gpio::Configuration::LowPower),
+            floating_state: Cell::new(gpio::FloatingState::PullNone),
+            output: Cell::new(false),
+            input: Cell::new(false),
+            // Send the initial state when the bridge starts.
+            changed: Cell::new(true),
+            interrupt_edge: OptionalCell::empty(),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    fn set_configuration(&self, configuration: gpio::Configuration) -> gpio::Configuration {
+        self.configuration.set(configuration);
+        self.state_changed();
+        configuration
+    }
+
+    fn set_output(&self, level: bool) {
+        if gpio::Configure::is_output(self) {
+            self.output.set(level);
+            self.state_changed();
+        }
+    }
+
+    fn state_changed(&self) {
+        self.changed.set(true);
+        self.bridge.transmit_pending();
+    }
+}
+
+impl gpio::Configure for HostBridgePin<'_> {
+    fn configuration(&self) -> gpio::Configuration {
+        self.configuration.get()
+    }
+
+    fn make_output(&self) -> gpio::Configuration {
+        match self.configuration.get() {
+            gpio::Configuration::Input | gpio::Configuration::InputOutput => {
+                self.set_configuration(gpio::Configuration::InputOutput)
+            }
+            _ => self.set_configuration(gpio::Configuration::Output),
+        }
+    }
+
+    fn disable_output(&self) -> gpio::Configuration {
+        match self.configuration.get() {
+            gpio::Configuration::InputOutput => self.set_configuration(gpio::Configuration::Input),
+            gpio::Configuration::Output => self.set_configuration(gpio::Configuration::LowPower),
+            configuration => configuration,
+        }
+    }
+
+    fn make_input(&self) -> gpio::Configuration {
+        match self.configuration.get() {
+            gpio::Configuration::Output | gpio::Configuration::InputOutput => {
+                self.set_configuration(gpio::Configuration::InputOutput)
+            }
+            _ => self.set_configuration(gpio::Configuration::Input),
+        }
+    }
+
+    fn disable_input(&self) -> gpio::Configuration {
+        match self.configuration.get() {
+            gpio::Configuration::InputOutput => self.set_configuration(gpio::Configuration::Output),
+            gpio::Configuration::Input => self.set_configuration(gpio::Configuration::LowPower),
+            configuration => configuration,
+        }
+    }
+
+    fn deactivate_to_low_power(&self) {
+        self.set_configuration(gpio::Configuration::LowPower);
+    }
+
+    fn set_floating_state(&self, state: gpio::FloatingState) {
+        self.floating_state.set(state);
+    }
+
+    fn floating_state(&self) -> gpio::FloatingState {
+        self.floating_state.get()
+    }
+}
+
+impl gpio::Output for HostBridgePin<'_> {
+    fn set(&self) {
+        self.set_output(true);
+    }
+
+    fn clear(&self) {
+        self.set_output(false);
+    }
+
+    fn toggle(&self) -> bool {
+        self.set_output(!self.output.get());
+        self.output.get()
+    }
+}
+
+impl gpio::Input for HostBridgePin<'_> {
+    fn read(&self) -> bool {
+        match self.configuration.get() {
+            gpio::Configuration::Input | gpio::Configuration::InputOutput => self.input.get(),
+            gpio::Configuration::Output => self.output.get(),
+            _ => false,
+        }
+    }
+}
+
+impl<'a> gpio::Interrupt<'a> for HostBridgePin<'a> {
+    fn set_client(&self, client: &'a dyn gpio::Client) {
+        self.client.set(client);
+    }
+
+    fn enable_interrupts(&self, mode: gpio::InterruptEdge) {
+        self.interrupt_edge.set(mode);
+    }
+
+    fn disable_interrupts(&self) {
+        self.interrupt_edge.clear();
+    }
+
+    fn is_pending(&self) -> bool {
+        false
+    }
+}
+
+impl<'a> HostBridgeDevice<'a> for HostBridgePin<'a> {
+    fn id(&self) -> u8 {
+        self.id
+    }
+
+    fn next_device(&'a self) -> &'a ListLink<'a, dyn HostBridgeDevice<'a>> {
+        &self.next
+    }
+
+    fn transmit(&self, payload: &mut [u8]) -> Option<(u8, usize)> {
+        if !self.changed.get() || payload.len() < 2 {
+            return None;
+        }
+        self.changed.set(false);
+        payload[0] = self.output.get() as u8;
+        payload[1] = match self.configuration.get() {
+            gpio::Configuration::LowPower => 0,
+            gpio::Configuration::Input => 1,
+            gpio::Configuration::Output => 2,
+            gpio::Configuration::InputOutput => 3,
+            _ => 4,
+        };
+        Some((kind::GPIO, 2))
+    }
+
+    fn transmit_done(&self, result: Result<(), ErrorCode>) {
+        if result.is_err() {
+            // Try again with the next message.
+            self.changed.set(true);
+        }
+    }
+
+    fn received(&self, message_kind: u8, payload: &[u8]) {
+        if message_kind != kind::GPIO || payload.is_empty() {
+            return;
+        }
+        let level = payload[0] != 0;
+        let previous = self.input.replace(level);
+        if level == previous || !gpio::Configure::is_input(self) {
+            return;
+        }
+        let fire = self.interrupt_edge.map_or(false, |edge| match edge {
+            gpio::InterruptEdge::RisingEdge => level,
+            gpio::InterruptEdge::FallingEdge => !level,
+            gpio::InterruptEdge::EitherEdge => true,
+        });
+        if fire {
+            self.client.map(|client| client.fired());
+        }
+    }
+}
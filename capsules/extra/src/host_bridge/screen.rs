@@ -0,0 +1,241 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Screen forwarded to the host.
+//!
+//! The screen has a fixed resolution and pixel format. Write frames, pixel
+//! data and settings are sent to the host, which is expected to keep the frame
+//! buffer and display it. Operations complete once the corresponding messages
+//! have been sent.
+
+use core::cell::Cell;
+use core::cmp;
+
+use kernel::collections::list::ListLink;
+use kernel::hil::screen::{self, ScreenClient, ScreenPixelFormat, ScreenRotation};
+use kernel::utilities::cells::{MapCell, OptionalCell};
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::ErrorCode;
+
+use super::{kind, HostBridge, HostBridgeDevice};
+
+/// Operation in progress.
+#[derive(Clone, Copy, PartialEq)]
+enum Operation {
+    /// Send the write frame.
+    SetWriteFrame,
+    /// Send the screen settings.
+    Control,
+    /// Send the settings after the power was changed.
+    Power,
+    /// Send pixel data from the write buffer, starting at `offset`.
+    Write { offset: usize, reset: bool },
+}
+
+pub struct HostBridgeScreen<'a> {
+    bridge: &'a HostBridge<'a>,
+    id: u8,
+    next: ListLink<'a, dyn HostBridgeDevice<'a>>,
+    resolution: (usize, usize),
+    pixel_format: ScreenPixelFormat,
+    frame: Cell<(usize, usize, usize, usize)>,
+    power: Cell<bool>,
+    invert: Cell<bool>,
+    brightness: Cell<u16>,
+    operation: OptionalCell<Operation>,
+    /// Whether the message for the operation is being sent.
+    sending: Cell<bool>,
+    buffer: MapCell<SubSliceMut<'static, u8>>,
+    client: OptionalCell<&'a dyn ScreenClient>,
+}
+
+impl<'a> HostBridgeScreen<'a> {
+    pub fn new(
+        bridge: &'a HostBridge<'a>,
+        id: u8,
+        resolution: (usize, usize),
+        pixel_format: ScreenPixelFormat,
+    ) -> HostBridgeScreen<'a> {
+        HostBridgeScreen {
+            bridge,
+            id,
+            next: ListLink::empty(),
+            resolution,
+            pixel_format,
+            frame: Cell::new((0, 0, resolution.0, resolution.1)),
+            power: Cell::new(false),
+            invert: Cell::new(false),
+            brightness: Cell::new(0),
+            operation: OptionalCell::empty(),
+            sending: Cell::new(false),
+            buffer: MapCell::empty(),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    fn start(&self, operation: Operation) -> Result<(), ErrorCode> {
+        if self.operation.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.operation.set(operation);
+        self.bridge.transmit_pending();
+        Ok(())
+    }
+
+    fn finish(&self, operation: Operation, result: Result<(), ErrorCode>) {
+        self.operation.clear();
+        self.client.map(|client| match operation {
+            Operation::Write { .. } => {
+                self.buffer
+                    .take()
+                    .map(|buffer| client.write_complete(buffer, result));
+            }
+            Operation::Power => client.screen_is_ready(),
+            _ => client.command_complete(result),
+        });
+    }
+}
+
+impl<'a> screen::Screen<'a> for HostBridgeScreen<'a> {
+    fn set_client(&self, client: &'a dyn ScreenClient) {
+        self.client.set(client);
+    }
+
+    fn get_resolution(&self) -> (usize, usize) {
+        self.resolution
+    }
+
+    fn get_pixel_format(&self) -> ScreenPixelFormat {
+        self.pixel_format
+    }
+
+    fn get_rotation(&self) -> ScreenRotation {
+        ScreenRotation::Normal
+    }
+
+    fn set_write_frame(
+        &self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) -> Result<(), ErrorCode> {
+        if x + width > self.resolution.0 || y + height > self.resolution.1 {
+            return Err(ErrorCode::INVAL);
+        }
+        self.start(Operation::SetWriteFrame)?;
+        self.frame.set((x, y, width, height));
+        Ok(())
+    }
+
+    fn write(
+        &self,
+        buffer: SubSliceMut<'static, u8>,
+        continue_write: bool,
+    ) -> Result<(), ErrorCode> {
+        let (_, _, width, height) = self.frame.get();
+        let frame_len = (width * height * self.pixel_format.get_bits_per_pixel()).div_ceil(8);
+        if buffer.len() > frame_len {
+            return Err(ErrorCode::SIZE);
+        }
+        self.start(Operation::Write {
+            offset: 0,
+            reset: !continue_write,
+        })?;
+        self.buffer.replace(buffer);
+        Ok(())
+    }
+
+    fn set_brightness(&self, brightness: u16) -> Result<(), ErrorCode> {
+        self.start(Operation::Control)?;
+        self.brightness.set(brightness);
+        Ok(())
+    }
+
+    fn set_power(&self, enabled: bool) -> Result<(), ErrorCode> {
+        self.start(Operation::Power)?;
+        self.power.set(enabled);
+        if enabled && self.brightness.get() == 0 {
+            self.brightness.set(u16::MAX);
+        }
+        Ok(())
+    }
+
+    fn set_invert(&self, enabled: bool) -> Result<(), ErrorCode> {
+        self.start(Operation::Control)?;
+        self.invert.set(enabled);
+        Ok(())
+    }
+}
+
+impl<'a> HostBridgeDevice<'a> for HostBridgeScreen<'a> {
+    fn id(&self) -> u8 {
+        self.id
+    }
+
+    fn next_device(&'a self) -> &'a ListLink<'a, dyn HostBridgeDevice<'a>> {
+        &self.next
+    }
+
+    fn transmit(&self, payload: &mut [u8]) -> Option<(u8, usize)> {
+        if self.sending.get() {
+            return None;
+        }
+        let message = match self.operation.get()? {
+            Operation::SetWriteFrame => {
+                let (x, y, width, height) = self.frame.get();
+                if payload.len() < 8 {
+                    return None;
+                }
+                for (i, value) in [x, y, width, height].iter().enumerate() {
+                    payload[2 * i..2 * i + 2].copy_from_slice(&(*value as u16).to_le_bytes());
+                }
+                (kind::SCREEN_FRAME, 8)
+            }
+            Operation::Control | Operation::Power => {
+                if payload.len() < 4 {
+                    return None;
+                }
+                payload[0] = self.power.get() as u8;
+                payload[1] = self.invert.get() as u8;
+                payload[2..4].copy_from_slice(&self.brightness.get().to_le_bytes());
+                (kind::SCREEN_CONTROL, 4)
+            }
+            Operation::Write { offset, reset } => {
+                if payload.len() < 2 {
+                    return None;
+                }
+                let length = self.buffer.map_or(0, |buffer| {
+                    let data = &buffer.as_slice()[offset..];
+                    let length = cmp::min(data.len(), payload.len() - 1);
+                    payload[1..1 + length].copy_from_slice(&data[..length]);
+                    length
+                });
+                payload[0] = reset as u8;
+                self.operation.set(Operation::Write {
+                    offset: offset + length,
+                    reset: false,
+                });
+                (kind::SCREEN_DATA, 1 + length)
+            }
+        };
+        self.sending.set(true);
+        Some(message)
+    }
+
+    fn transmit_done(&self, result: Result<(), ErrorCode>) {
+        self.sending.set(false);
+        self.operation.get().map(|operation| match operation {
+            Operation::Write { offset, .. } => {
+                let remaining = self.buffer.map_or(0, |buffer| buffer.len() - offset);
+                if result.is_err() || remaining == 0 {
+                    self.finish(operation, result);
+                }
+            }
+            _ => self.finish(operation, result),
+        });
+    }
+
+    fn received(&self, _message_kind: u8, _payload: &[u8]) {}
+}
@@ -0,0 +1,149 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Temperature and humidity sensor forwarded to the host.
+//!
+//! Every reading sends a request to the host, and completes when the host
+//! replies with a value. If the host never replies, the reading never
+//! completes.
+
+use core::cell::Cell;
+
+use kernel::collections::list::ListLink;
+use kernel::hil::sensors;
+use kernel::utilities::cells::OptionalCell;
+use kernel::ErrorCode;
+
+use super::{kind, HostBridge, HostBridgeDevice};
+
+/// Channel of temperature readings in messages.
+const CHANNEL_TEMPERATURE: u8 = 0;
+/// Channel of humidity readings in messages.
+const CHANNEL_HUMIDITY: u8 = 1;
+
+pub struct HostBridgeSensor<'a> {
+    bridge: &'a HostBridge<'a>,
+    id: u8,
+    next: ListLink<'a, dyn HostBridgeDevice<'a>>,
+    /// Temperature reading requested from the host, and whether the request
+    /// has been sent.
+    temperature_request: Cell<Option<bool>>,
+    /// Humidity reading requested from the host, and whether the request has
+    /// been sent.
+    humidity_request: Cell<Option<bool>>,
+    /// Channel of the request being sent.
+    sending: OptionalCell<u8>,
+    temperature_client: OptionalCell<&'a dyn sensors::TemperatureClient>,
+    humidity_client: OptionalCell<&'a dyn sensors::HumidityClient>,
+}
+
+impl<'a> HostBridgeSensor<'a> {
+    pub fn new(bridge: &'a HostBridge<'a>, id: u8) -> HostBridgeSensor<'a> {
+        HostBridgeSensor {
+            bridge,
+            id,
+            next: ListLink::empty(),
+            temperature_request: Cell::new(None),
+            humidity_request: Cell::new(None),
+            sending: OptionalCell::empty(),
+            temperature_client: OptionalCell::empty(),
+            humidity_client: OptionalCell::empty(),
+        }
+    }
+
+    fn request(&self, request: &Cell<Option<bool>>) -> Result<(), ErrorCode> {
+        if request.get().is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        request.set(Some(false));
+        self.bridge.transmit_pending();
+        Ok(())
+    }
+}
+
+impl<'a> sensors::TemperatureDriver<'a> for HostBridgeSensor<'a> {
+    fn set_client(&self, client: &'a dyn sensors::TemperatureClient) {
+        self.temperature_client.set(client);
+    }
+
+    fn read_temperature(&self) -> Result<(), ErrorCode> {
+        self.request(&self.temperature_request)
+    }
+}
+
+impl<'a> sensors::HumidityDriver<'a> for HostBridgeSensor<'a> {
+    fn set_client(&self, client: &'a dyn sensors::HumidityClient) {
+        self.humidity_client.set(client);
+    }
+
+    fn read_humidity(&self) -> Result<(), ErrorCode> {
+        self.request(&self.humidity_request)
+    }
+}
+
+impl<'a> HostBridgeDevice<'a> for HostBridgeSensor<'a> {
+    fn id(&self) -> u8 {
+        self.id
+    }
+
+    fn next_device(&'a self) -> &'a ListLink<'a, dyn HostBridgeDevice<'a>> {
+        &self.next
+    }
+
+    fn transmit(&self, payload: &mut [u8]) -> Option<(u8, usize)> {
+        if payload.is_empty() {
+            return None;
+        }
+        let (request, channel) = if self.temperature_request.get() == Some(false) {
+            (&self.temperature_request, CHANNEL_TEMPERATURE)
+        } else if self.humidity_request.get() == Some(false) {
+            (&self.humidity_request, CHANNEL_HUMIDITY)
+        } else {
+            return None;
+        };
+        request.set(Some(true));
+        self.sending.set(channel);
+        payload[0] = channel;
+        Some((kind::SENSOR_REQUEST, 1))
+    }
+
+    fn transmit_done(&self, result: Result<(), ErrorCode>) {
+        let channel = self.sending.take();
+        if let Err(e) = result {
+            // The request will not be answered.
+            match channel {
+                Some(CHANNEL_TEMPERATURE) => {
+                    self.temperature_request.set(None);
+                    self.temperature_client
+                        .map(|client| client.callback(Err(e)));
+                }
+                Some(CHANNEL_HUMIDITY) => {
+                    // The humidity HIL cannot report errors, retry instead.
+                    self.humidity_request.set(Some(false));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn received(&self, message_kind: u8, payload: &[u8]) {
+        if message_kind != kind::SENSOR_VALUE || payload.len() < 5 {
+            return;
+        }
+        let value = i32::from_le_bytes([payload[1], payload[2], payload[3], payload[4]]);
+        match payload[0] {
+            CHANNEL_TEMPERATURE if self.temperature_request.get().is_some() => {
+                self.temperature_request.set(None);
+                self.temperature_client
+                    .map(|client| client.callback(Ok(value)));
+            }
+            CHANNEL_HUMIDITY if self.humidity_request.get().is_some() => {
+                self.humidity_request.set(None);
+                self.humidity_client
+                    .map(|client| client.callback(value.max(0) as usize));
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,114 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Shared code for the external RTC chips on I2C, the
+//! [DS3231](crate::ds3231) and the [PCF8523](crate::pcf8523).
+//!
+//! Both store the date and time as BCD registers, with a two-digit year.
+//! Years are counted from 2000.
+
+use kernel::hil::date_time::{DateTimeValues, DayOfWeek, Month};
+use kernel::ErrorCode;
+
+pub fn to_bcd(value: u8) -> u8 {
+    ((value / 10) << 4) | (value % 10)
+}
+
+pub fn from_bcd(value: u8) -> u8 {
+    (value >> 4) * 10 + (value & 0x0F)
+}
+
+/// Decode an hours register in BCD. In 12-hour mode, `pm_bit` is set after
+/// noon.
+pub fn hours_from_bcd(value: u8, twelve_hour: bool, pm_bit: u8) -> u8 {
+    if twelve_hour {
+        let hours = from_bcd(value & 0x1F) % 12;
+        if value & pm_bit != 0 {
+            hours + 12
+        } else {
+            hours
+        }
+    } else {
+        from_bcd(value & 0x3F)
+    }
+}
+
+/// Check that `date_time` is a valid time, in a year from 2000 to
+/// `last_year`.
+pub fn check_date_time(date_time: &DateTimeValues, last_year: u16) -> Result<(), ErrorCode> {
+    if date_time.year < 2000
+        || date_time.year > last_year
+        || date_time.day == 0
+        || date_time.day > 31
+        || date_time.hour > 23
+        || date_time.minute > 59
+        || date_time.seconds > 59
+    {
+        Err(ErrorCode::INVAL)
+    } else {
+        Ok(())
+    }
+}
+
+/// The month, from 1 for January.
+pub fn month_number(month: Month) -> u8 {
+    match month {
+        Month::January => 1,
+        Month::February => 2,
+        Month::March => 3,
+        Month::April => 4,
+        Month::May => 5,
+        Month::June => 6,
+        Month::July => 7,
+        Month::August => 8,
+        Month::September => 9,
+        Month::October => 10,
+        Month::November => 11,
+        Month::December => 12,
+    }
+}
+
+pub fn month_from_number(month: u8) -> Result<Month, ErrorCode> {
+    match month {
+        1 => Ok(Month::January),
+        2 => Ok(Month::February),
+        3 => Ok(Month::March),
+        4 => Ok(Month::April),
+        5 => Ok(Month::May),
+        6 => Ok(Month::June),
+        7 => Ok(Month::July),
+        8 => Ok(Month::August),
+        9 => Ok(Month::September),
+        10 => Ok(Month::October),
+        11 => Ok(Month::November),
+        12 => Ok(Month::December),
+        _ => Err(ErrorCode::FAIL),
+    }
+}
+
+/// The day of the week, from 0 for Sunday.
+pub fn day_of_week_number(day: DayOfWeek) -> u8 {
+    match day {
+        DayOfWeek::Sunday => 0,
+        DayOfWeek::Monday => 1,
+        DayOfWeek::Tuesday => 2,
+        DayOfWeek::Wednesday => 3,
+        DayOfWeek::Thursday => 4,
+        DayOfWeek::Friday => 5,
+        DayOfWeek::Saturday => 6,
+    }
+}
+
+pub fn day_of_week_from_number(day: u8) -> Result<DayOfWeek, ErrorCode> {
+    match day {
+        0 => Ok(DayOfWeek::Sunday),
+        1 => Ok(DayOfWeek::Monday),
+        2 => Ok(DayOfWeek::Tuesday),
+        3 => Ok(DayOfWeek::Wednesday),
+        4 => Ok(DayOfWeek::Thursday),
+        5 => Ok(DayOfWeek::Friday),
+        6 => Ok(DayOfWeek::Saturday),
+        _ => Err(ErrorCode::FAIL),
+    }
+}
@@ -304,6 +304,20 @@ impl<'a, M: device::MacDevice<'a>> RadioDriver<'a, M> {
         self.backup_device_procedure.set(device_procedure);
     }
 
+    /// Add a key provisioned by the board, for example a network key that all
+    /// nodes share, returning its index. Userspace sees it as any other key.
+    ///
+    /// Returns `Err(ErrorCode::NOMEM)` if the key list is full.
+    pub fn provision_key(
+        &self,
+        level: SecurityLevel,
+        key_id: KeyId,
+        key: [u8; 16],
+    ) -> Result<usize, ErrorCode> {
+        self.add_key(KeyDescriptor { level, key_id, key })
+            .ok_or(ErrorCode::NOMEM)
+    }
+
     // Neighbor management functions
 
     /// Add a new neighbor to the end of the list if there is still space
@@ -134,6 +134,11 @@ impl Frame {
         self.buf
     }
 
+    /// The MAC frame, without the MIC and the FCS
+    pub fn mac_frame(&self) -> &[u8] {
+        &self.buf[radio::PSDU_OFFSET..radio::PSDU_OFFSET + self.info.unsecured_length()]
+    }
+
     /// Calculates how much more data this frame can hold
     pub fn remaining_data_capacity(&self) -> usize {
         self.buf.len() - self.info.secured_length()
@@ -31,11 +31,21 @@
 //!     capsules::ieee802154::virtual_mac::MacUser::new(mux_mac));
 //! mux_mac.add_user(virtual_mac);
 //! ```
+//!
+//! The mux can also report the source address and link quality of every
+//! frame it receives to a
+//! [`LinkQualityObserver`](kernel::hil::link_quality::LinkQualityObserver),
+//! such as a neighbor table, with [`MuxMac::set_link_observer`], and the
+//! frames it passes to a
+//! [`PacketObserver`](crate::packet_capture::PacketObserver), such as a
+//! packet capture, with [`MuxMac::set_packet_observer`].
 
 use crate::ieee802154::{device, framer};
 use crate::net::ieee802154::{Header, KeyId, MacAddress, PanID, SecurityLevel};
+use crate::packet_capture::{Direction, PacketObserver};
 
 use kernel::collections::list::{List, ListLink, ListNode};
+use kernel::hil::link_quality::LinkQualityObserver;
 use kernel::utilities::cells::{MapCell, OptionalCell};
 use kernel::ErrorCode;
 
@@ -46,6 +56,8 @@ pub struct MuxMac<'a, M: device::MacDevice<'a>> {
     mac: &'a M,
     users: List<'a, MacUser<'a, M>>,
     inflight: OptionalCell<&'a MacUser<'a, M>>,
+    link_observer: OptionalCell<&'a dyn LinkQualityObserver>,
+    packet_observer: OptionalCell<&'a dyn PacketObserver>,
 }
 
 impl<'a, M: device::MacDevice<'a>> device::TxClient for MuxMac<'a, M> {
@@ -66,6 +78,20 @@ impl<'a, M: device::MacDevice<'a>> device::RxClient for MuxMac<'a, M> {
         data_offset: usize,
         data_len: usize,
     ) {
+        // The radio HIL does not report the RSSI of frames.
+        if let Some(source) = header.src_addr {
+            self.link_observer.map(|observer| match source {
+                MacAddress::Short(address) => {
+                    observer.frame_received(&address.to_be_bytes(), None, Some(lqi))
+                }
+                MacAddress::Long(address) => observer.frame_received(&address, None, Some(lqi)),
+            });
+        }
+        self.packet_observer.map(|observer| {
+            if let Some(frame) = buf.get(..data_offset + data_len) {
+                observer.packet(Direction::Received, frame);
+            }
+        });
         for user in self.users.iter() {
             user.receive(buf, header, lqi, data_offset, data_len);
         }
@@ -78,9 +104,26 @@ impl<'a, M: device::MacDevice<'a>> MuxMac<'a, M> {
             mac,
             users: List::new(),
             inflight: OptionalCell::empty(),
+            link_observer: OptionalCell::empty(),
+            packet_observer: OptionalCell::empty(),
         }
     }
 
+    /// Report the frames received to `observer`.
+    pub fn set_link_observer(&self, observer: &'a dyn LinkQualityObserver) {
+        self.link_observer.set(observer);
+    }
+
+    /// Report the frames received, and the frames handed to the MAC device
+    /// for transmission, to `observer`.
+    ///
+    /// Frames are reported as the stack sees them: received frames after
+    /// they are unsecured, and transmitted frames before they are secured.
+    /// Secured frames thus carry their payload in the clear, and no MIC.
+    pub fn set_packet_observer(&self, observer: &'a dyn PacketObserver) {
+        self.packet_observer.set(observer);
+    }
+
     /// Registers a MAC user with this MAC mux device. Each MAC user should only
     /// be registered once.
     pub fn add_user(&self, user: &'a MacUser<'a, M>) {
@@ -109,11 +152,18 @@ impl<'a, M: device::MacDevice<'a>> MuxMac<'a, M> {
         })
     }
 
+    /// Reports `frame` to the packet observer, as it is transmitted.
+    fn observe_transmit(&self, frame: &framer::Frame) {
+        self.packet_observer
+            .map(|observer| observer.packet(Direction::Transmitted, frame.mac_frame()));
+    }
+
     /// Performs a non-idle operation on a `MacUser` asynchronously: that is, if the
     /// transmission operation results in immediate failure, then return the
     /// buffer to the `MacUser` via its transmit client.
     fn perform_op_async(&self, node: &'a MacUser<'a, M>, op: Op) {
         if let Op::Transmit(frame) = op {
+            self.observe_transmit(&frame);
             match self.mac.transmit(frame) {
                 // If Err, the transmission failed,
                 // otherwise it succeeded.
@@ -135,6 +185,7 @@ impl<'a, M: device::MacDevice<'a>> MuxMac<'a, M> {
         op: Op,
     ) -> Option<Result<(), (ErrorCode, &'static mut [u8])>> {
         if let Op::Transmit(frame) = op {
+            self.observe_transmit(&frame);
             let result = self.mac.transmit(frame);
             if result.is_ok() {
                 self.inflight.set(node);
@@ -18,9 +18,15 @@ pub mod analog_comparator;
 pub mod analog_sensor;
 pub mod apds9960;
 pub mod app_flash_driver;
+pub mod app_loader;
+pub mod app_log;
+pub mod app_watchdog;
 pub mod at24c_eeprom;
 pub mod atecc508a;
+pub mod attestation;
+pub mod benchmark;
 pub mod ble_advertising_driver;
+pub mod ble_gatt;
 pub mod bme280;
 pub mod bmm150;
 pub mod bmp280;
@@ -30,25 +36,39 @@ pub mod buzzer_pwm;
 pub mod can;
 pub mod ccs811;
 pub mod chirp_i2c_moisture;
+pub mod config_service;
 pub mod crc;
 pub mod cycle_count;
 pub mod dac;
 pub mod date_time;
+pub mod date_time_software;
 pub mod debug_process_restart;
+pub mod debug_router;
 pub mod dfrobot_rainfall_sensor;
 pub mod distance;
+pub mod ds3231;
+pub mod energy;
 pub mod eui64;
+pub mod event_batch;
+pub mod exclusive_uart;
+pub mod fast_gpio;
+pub mod fat;
+pub mod fault_injection;
 pub mod fm25cl;
 pub mod ft6x06;
 pub mod fxos8700cq;
+pub mod gesture;
 pub mod gpio_async;
+pub mod gpio_power_rail;
 pub mod hc_sr04;
 pub mod hd44780;
 pub mod hmac;
 pub mod hmac_sha256;
+pub mod host_bridge;
 pub mod hs3003;
 pub mod hts221;
 pub mod humidity;
+pub mod i2c_rtc;
 pub mod ieee802154;
 pub mod isl29035;
 pub mod kv_driver;
@@ -69,12 +89,19 @@ pub mod mcp230xx;
 pub mod mlx90614;
 pub mod moisture;
 pub mod mx25r6435f;
+pub mod neighbor_table;
 pub mod ninedof;
 pub mod nonvolatile_storage_driver;
 pub mod nonvolatile_to_pages;
 pub mod nrf51822_serialization;
+pub mod packet_capture;
 pub mod panic_button;
 pub mod pca9544a;
+pub mod pcf8523;
+pub mod pcm_audio;
+pub mod peripherals;
+pub mod pipe;
+pub mod power_gated_sensor;
 pub mod pressure;
 pub mod proximity;
 pub mod public_key_crypto;
@@ -100,14 +127,18 @@ pub mod sound_pressure;
 pub mod ssd1306;
 pub mod st77xx;
 pub mod symmetric_encryption;
+pub mod system_events;
 pub mod temperature;
 pub mod temperature_rp2040;
 pub mod temperature_stm;
 pub mod text_screen;
+pub mod thermal;
 pub mod tickv;
 pub mod tickv_kv_store;
 pub mod touch;
+pub mod touch_calibration;
 pub mod tsl2561;
 pub mod usb;
 pub mod usb_hid_driver;
 pub mod virtual_kv;
+pub mod wear_leveling;
@@ -54,7 +54,6 @@
 //! mx25r6435f_virtual_alarm.set_client(mx25r6435f);
 //! ```
 
-use core::cell::Cell;
 use core::ops::{Index, IndexMut};
 use kernel::debug;
 use kernel::hil;
@@ -62,6 +61,7 @@ use kernel::hil::time::ConvertTicks;
 use kernel::utilities::cells::TakeCell;
 use kernel::utilities::cells::{MapCell, OptionalCell};
 use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::utilities::state_machine::{StateCell, StateMachine};
 use kernel::ErrorCode;
 
 pub const TX_BUF_LEN: usize = PAGE_SIZE as usize + 4;
@@ -127,13 +127,13 @@ enum Opcodes {
     RDSR = 0x05, // Read Status Register
 }
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 enum Operation {
     Erase,
     Write { sector_index: u32 },
 }
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 enum State {
     Idle,
 
@@ -174,6 +174,108 @@ enum State {
     ReadId,
 }
 
+impl StateMachine for State {
+    fn can_transition(&self, next: &Self) -> bool {
+        match (*self, *next) {
+            (
+                State::Idle,
+                State::ReadId
+                | State::ReadSector { page_index: 0, .. }
+                | State::EraseSectorWriteEnable { .. },
+            ) => true,
+            (State::ReadId, State::Idle) => true,
+
+            // Sectors are read one page at a time.
+            (State::ReadSector { .. }, State::Idle) => true,
+            (
+                State::ReadSector {
+                    sector_index,
+                    page_index,
+                },
+                State::ReadSector {
+                    sector_index: next_sector,
+                    page_index: next_page,
+                },
+            ) => next_sector == sector_index && next_page == page_index + 1,
+
+            // Erases, also the one that starts a write.
+            (
+                State::EraseSectorWriteEnable { operation, .. },
+                State::EraseSectorErase {
+                    operation: next_operation,
+                },
+            )
+            | (
+                State::EraseSectorErase { operation },
+                State::EraseSectorCheckDone {
+                    operation: next_operation,
+                },
+            ) => operation == next_operation,
+            (
+                State::EraseSectorCheckDone {
+                    operation: Operation::Erase,
+                },
+                State::EraseSectorDone,
+            ) => true,
+            (
+                State::EraseSectorCheckDone {
+                    operation: Operation::Write { sector_index },
+                },
+                State::WriteSectorWriteEnable {
+                    sector_index: next_sector,
+                    page_index: 0,
+                },
+            ) => next_sector == sector_index,
+            (State::EraseSectorDone, State::Idle) => true,
+
+            // Writes, one page at a time.
+            (State::WriteSectorWriteEnable { .. }, State::Idle) => true,
+            (
+                State::WriteSectorWriteEnable {
+                    sector_index,
+                    page_index,
+                },
+                State::WriteSectorWrite {
+                    sector_index: next_sector,
+                    page_index: next_page,
+                },
+            )
+            | (
+                State::WriteSectorCheckDone {
+                    sector_index,
+                    page_index,
+                },
+                State::WriteSectorWaitDone {
+                    sector_index: next_sector,
+                    page_index: next_page,
+                },
+            )
+            | (
+                State::WriteSectorWaitDone {
+                    sector_index,
+                    page_index,
+                },
+                State::WriteSectorWriteEnable {
+                    sector_index: next_sector,
+                    page_index: next_page,
+                },
+            ) => next_sector == sector_index && next_page == page_index,
+            (
+                State::WriteSectorWrite {
+                    sector_index,
+                    page_index,
+                },
+                State::WriteSectorCheckDone {
+                    sector_index: next_sector,
+                    page_index: next_page,
+                },
+            ) => next_sector == sector_index && next_page == page_index + 1,
+
+            _ => false,
+        }
+    }
+}
+
 pub struct MX25R6435F<
     'a,
     S: hil::spi::SpiMasterDevice<'a> + 'a,
@@ -182,7 +284,7 @@ pub struct MX25R6435F<
 > {
     spi: &'a S,
     alarm: &'a A,
-    state: Cell<State>,
+    state: StateCell<State>,
     write_protect_pin: Option<&'a P>,
     hold_pin: Option<&'a P>,
     txbuffer: MapCell<SubSliceMut<'static, u8>>,
@@ -209,7 +311,7 @@ impl<
         MX25R6435F {
             spi,
             alarm,
-            state: Cell::new(State::Idle),
+            state: StateCell::new(State::Idle),
             write_protect_pin,
             hold_pin,
             txbuffer: MapCell::new(txbuffer.into()),
@@ -231,33 +333,55 @@ impl<
         )
     }
 
+    /// Start an operation in `state`, which `start` kicks off. If `start`
+    /// fails, the driver goes back to idle.
+    fn start_operation(
+        &self,
+        state: State,
+        start: impl FnOnce() -> Result<(), ErrorCode>,
+    ) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        self.state.transition(state).or(Err(ErrorCode::FAIL))?;
+        start().inspect_err(|_| self.state.reset(State::Idle))
+    }
+
+    /// Move to the next step of the current operation. The callbacks below
+    /// follow the transitions of `State`, so this only fails if they
+    /// disagree, which `StateMachine::illegal_transition` reports.
+    fn next_state(&self, next: State) {
+        let _ = self.state.transition(next);
+    }
+
     /// Requests the readout of a 24-bit identification number.
     /// This command will cause a debug print when succeeded.
     pub fn read_identification(&self) -> Result<(), ErrorCode> {
-        self.configure_spi()?;
+        self.start_operation(State::ReadId, || {
+            self.configure_spi()?;
 
-        self.txbuffer
-            .take()
-            .map_or(Err(ErrorCode::RESERVE), |mut txbuffer| {
-                self.rxbuffer
-                    .take()
-                    .map_or(Err(ErrorCode::RESERVE), move |rxbuffer| {
-                        txbuffer.reset();
-                        txbuffer[0] = Opcodes::RDID as u8;
-                        txbuffer.slice(0..4);
-
-                        self.state.set(State::ReadId);
-                        if let Err((err, txbuffer, rxbuffer)) =
-                            self.spi.read_write_bytes(txbuffer, Some(rxbuffer))
-                        {
-                            self.txbuffer.replace(txbuffer);
-                            self.rxbuffer.replace(rxbuffer.unwrap());
-                            Err(err)
-                        } else {
-                            Ok(())
-                        }
-                    })
-            })
+            self.txbuffer
+                .take()
+                .map_or(Err(ErrorCode::RESERVE), |mut txbuffer| {
+                    self.rxbuffer
+                        .take()
+                        .map_or(Err(ErrorCode::RESERVE), move |rxbuffer| {
+                            txbuffer.reset();
+                            txbuffer[0] = Opcodes::RDID as u8;
+                            txbuffer.slice(0..4);
+
+                            if let Err((err, txbuffer, rxbuffer)) =
+                                self.spi.read_write_bytes(txbuffer, Some(rxbuffer))
+                            {
+                                self.txbuffer.replace(txbuffer);
+                                self.rxbuffer.replace(rxbuffer.unwrap());
+                                Err(err)
+                            } else {
+                                Ok(())
+                            }
+                        })
+                })
+        })
     }
 
     fn enable_write(&self) -> Result<(), ErrorCode> {
@@ -280,12 +404,16 @@ impl<
     }
 
     fn erase_sector(&self, sector_index: u32) -> Result<(), ErrorCode> {
-        self.configure_spi()?;
-        self.state.set(State::EraseSectorWriteEnable {
-            sector_index,
-            operation: Operation::Erase,
-        });
-        self.enable_write()
+        self.start_operation(
+            State::EraseSectorWriteEnable {
+                sector_index,
+                operation: Operation::Erase,
+            },
+            || {
+                self.configure_spi()?;
+                self.enable_write()
+            },
+        )
     }
 
     fn read_sector(
@@ -293,53 +421,52 @@ impl<
         sector_index: u32,
         sector: &'static mut Mx25r6435fSector,
     ) -> Result<(), (ErrorCode, &'static mut Mx25r6435fSector)> {
-        match self.configure_spi() {
-            Ok(()) => {
-                let retval =
-                    self.txbuffer
-                        .take()
-                        .map_or(Err(ErrorCode::RESERVE), |mut txbuffer| {
-                            self.rxbuffer.take().map_or(
-                                Err(ErrorCode::RESERVE),
-                                move |mut rxbuffer| {
-                                    // Setup the read instruction
-                                    txbuffer.reset();
-                                    txbuffer[0] = Opcodes::READ as u8;
-                                    txbuffer[1] = ((sector_index * SECTOR_SIZE) >> 16) as u8;
-                                    txbuffer[2] = ((sector_index * SECTOR_SIZE) >> 8) as u8;
-                                    txbuffer[3] = ((sector_index * SECTOR_SIZE) >> 0) as u8;
-                                    txbuffer.slice(0..(PAGE_SIZE as usize + 4));
-
-                                    rxbuffer.reset();
-                                    rxbuffer.slice(0..(PAGE_SIZE as usize + 4));
-
-                                    // Call the SPI driver to kick things off.
-                                    self.state.set(State::ReadSector {
-                                        sector_index,
-                                        page_index: 0,
-                                    });
-                                    if let Err((err, txbuffer, rxbuffer)) =
-                                        self.spi.read_write_bytes(txbuffer, Some(rxbuffer))
-                                    {
-                                        self.txbuffer.replace(txbuffer);
-                                        self.rxbuffer.replace(rxbuffer.unwrap());
-                                        Err(err)
-                                    } else {
-                                        Ok(())
-                                    }
-                                },
-                            )
-                        });
+        let retval = self.start_operation(
+            State::ReadSector {
+                sector_index,
+                page_index: 0,
+            },
+            || {
+                self.configure_spi()?;
 
-                match retval {
-                    Ok(()) => {
-                        self.client_sector.replace(sector);
-                        Ok(())
-                    }
-                    Err(ecode) => Err((ecode, sector)),
-                }
+                self.txbuffer
+                    .take()
+                    .map_or(Err(ErrorCode::RESERVE), |mut txbuffer| {
+                        self.rxbuffer
+                            .take()
+                            .map_or(Err(ErrorCode::RESERVE), move |mut rxbuffer| {
+                                // Setup the read instruction
+                                txbuffer.reset();
+                                txbuffer[0] = Opcodes::READ as u8;
+                                txbuffer[1] = ((sector_index * SECTOR_SIZE) >> 16) as u8;
+                                txbuffer[2] = ((sector_index * SECTOR_SIZE) >> 8) as u8;
+                                txbuffer[3] = ((sector_index * SECTOR_SIZE) >> 0) as u8;
+                                txbuffer.slice(0..(PAGE_SIZE as usize + 4));
+
+                                rxbuffer.reset();
+                                rxbuffer.slice(0..(PAGE_SIZE as usize + 4));
+
+                                // Call the SPI driver to kick things off.
+                                if let Err((err, txbuffer, rxbuffer)) =
+                                    self.spi.read_write_bytes(txbuffer, Some(rxbuffer))
+                                {
+                                    self.txbuffer.replace(txbuffer);
+                                    self.rxbuffer.replace(rxbuffer.unwrap());
+                                    Err(err)
+                                } else {
+                                    Ok(())
+                                }
+                            })
+                    })
+            },
+        );
+
+        match retval {
+            Ok(()) => {
+                self.client_sector.replace(sector);
+                Ok(())
             }
-            Err(error) => Err((error, sector)),
+            Err(ecode) => Err((ecode, sector)),
         }
     }
 
@@ -348,23 +475,23 @@ impl<
         sector_index: u32,
         sector: &'static mut Mx25r6435fSector,
     ) -> Result<(), (ErrorCode, &'static mut Mx25r6435fSector)> {
-        match self.configure_spi() {
+        let retval = self.start_operation(
+            State::EraseSectorWriteEnable {
+                sector_index,
+                operation: Operation::Write { sector_index },
+            },
+            || {
+                self.configure_spi()?;
+                self.enable_write()
+            },
+        );
+
+        match retval {
             Ok(()) => {
-                self.state.set(State::EraseSectorWriteEnable {
-                    sector_index,
-                    operation: Operation::Write { sector_index },
-                });
-                let retval = self.enable_write();
-
-                match retval {
-                    Ok(()) => {
-                        self.client_sector.replace(sector);
-                        Ok(())
-                    }
-                    Err(ecode) => Err((ecode, sector)),
-                }
+                self.client_sector.replace(sector);
+                Ok(())
             }
-            Err(error) => Err((error, sector)),
+            Err(ecode) => Err((ecode, sector)),
         }
     }
 }
@@ -384,6 +511,7 @@ impl<
     ) {
         match self.state.get() {
             State::ReadId => {
+                self.next_state(State::Idle);
                 self.txbuffer.replace(write_buffer);
                 read_buffer.map(|read_buffer| {
                     debug!(
@@ -407,7 +535,7 @@ impl<
 
                         if (page_index + 1) * PAGE_SIZE == SECTOR_SIZE {
                             // Done reading
-                            self.state.set(State::Idle);
+                            self.next_state(State::Idle);
                             self.txbuffer.replace(write_buffer);
                             self.rxbuffer.replace(read_buffer);
 
@@ -429,7 +557,7 @@ impl<
                             write_buffer[3] = (address >> 0) as u8;
                             write_buffer.slice(0..(PAGE_SIZE as usize + 4));
 
-                            self.state.set(State::ReadSector {
+                            self.next_state(State::ReadSector {
                                 sector_index,
                                 page_index: page_index + 1,
                             });
@@ -445,7 +573,7 @@ impl<
                 sector_index,
                 operation,
             } => {
-                self.state.set(State::EraseSectorErase { operation });
+                self.next_state(State::EraseSectorErase { operation });
 
                 write_buffer.reset();
                 write_buffer[0] = Opcodes::SE as u8;
@@ -458,7 +586,7 @@ impl<
                 let _ = self.spi.read_write_bytes(write_buffer, None);
             }
             State::EraseSectorErase { operation } => {
-                self.state.set(State::EraseSectorCheckDone { operation });
+                self.next_state(State::EraseSectorCheckDone { operation });
                 self.txbuffer.replace(write_buffer);
                 // Datasheet says erase takes 58 ms on average. So we wait that
                 // long.
@@ -482,7 +610,7 @@ impl<
                                 page_index: 0,
                             },
                         };
-                        self.state.set(next_state);
+                        self.next_state(next_state);
                         self.rxbuffer.replace(read_buffer);
                         self.read_write_done(write_buffer, None, read_write_status);
                     }
@@ -490,7 +618,7 @@ impl<
             }
             State::EraseSectorDone => {
                 // No need to disable write, chip does it automatically.
-                self.state.set(State::Idle);
+                self.next_state(State::Idle);
                 self.txbuffer.replace(write_buffer);
                 self.client.map(|client| {
                     client.erase_complete(
@@ -508,7 +636,7 @@ impl<
                 // sector's worth of data, one page at a time.
                 if page_index * PAGE_SIZE == SECTOR_SIZE {
                     // No need to disable writes since it happens automatically.
-                    self.state.set(State::Idle);
+                    self.next_state(State::Idle);
                     self.txbuffer.replace(write_buffer);
                     self.client.map(|client| {
                         self.client_sector.take().map(|sector| {
@@ -521,7 +649,7 @@ impl<
                         });
                     });
                 } else {
-                    self.state.set(State::WriteSectorWrite {
+                    self.next_state(State::WriteSectorWrite {
                         sector_index,
                         page_index,
                     });
@@ -537,7 +665,7 @@ impl<
                 page_index,
             } => {
                 // Continue writing page by page.
-                self.state.set(State::WriteSectorCheckDone {
+                self.next_state(State::WriteSectorCheckDone {
                     sector_index,
                     page_index: page_index + 1,
                 });
@@ -562,7 +690,7 @@ impl<
                 sector_index,
                 page_index,
             } => {
-                self.state.set(State::WriteSectorWaitDone {
+                self.next_state(State::WriteSectorWaitDone {
                     sector_index,
                     page_index,
                 });
@@ -587,7 +715,7 @@ impl<
                         let _ = self.spi.read_write_bytes(write_buffer, Some(read_buffer));
                     } else {
                         // Write has finished, so go back to writing.
-                        self.state.set(State::WriteSectorWriteEnable {
+                        self.next_state(State::WriteSectorWriteEnable {
                             sector_index,
                             page_index,
                         });
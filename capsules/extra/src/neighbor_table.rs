@@ -0,0 +1,370 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Table of the link quality of radio neighbors.
+//!
+//! `NeighborTableService` records the signal strength and link quality of the
+//! frames reported by the receive paths of radio stacks, per source address,
+//! so that link diagnostics do not need a sniffer. Neighbors that have not
+//! been heard from for `max_age_s` seconds leave the table. When the table is
+//! full, a new neighbor replaces the one heard from least recently.
+//!
+//! The table is read by kernel code through the
+//! [`NeighborTable`](kernel::hil::link_quality::NeighborTable) trait, for
+//! example by the `neighbors` command of the process console, and by
+//! processes through the syscall interface.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let neighbor_table = components::neighbor_table::NeighborTableComponent::new(
+//!     board_kernel,
+//!     capsules_extra::neighbor_table::DRIVER_NUM,
+//!     mux_alarm,
+//!     300,
+//! )
+//! .finalize(components::neighbor_table_component_static!(nrf52840::rtc::Rtc<'static>, 16));
+//! mux_mac.set_link_observer(neighbor_table);
+//! pconsole.set_neighbor_table(neighbor_table);
+//! ```
+//!
+//! Syscall Interface
+//! -----------------
+//!
+//! ### Command
+//!
+//! - `0`: Driver existence check.
+//! - `1`: Return the number of neighbors.
+//! - `2`: Copy neighbor number `arg1` to read-write allow buffer 0, and return
+//!   its length. A neighbor is 26 bytes, little endian: the address length
+//!   (u8) and the address, padded to 8 bytes, the number of frames received
+//!   (u32), the seconds since the last frame (u32), flags (u8, bit 0 if the
+//!   RSSI is measured and bit 1 if the LQI is), then the last, average,
+//!   minimum and maximum RSSI in dBm (4 i8) and LQI (4 u8). Returns `INVAL`
+//!   if there is no neighbor `arg1`, and `SIZE` if the buffer is too short.
+
+use core::cell::Cell;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::link_quality::{
+    LinkAddress, LinkQualityObserver, LinkSummary, Neighbor, NeighborTable,
+};
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks};
+use kernel::processbuffer::WriteableProcessBuffer;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, ProcessId};
+
+use capsules_core::driver;
+/// Syscall driver number.
+pub const DRIVER_NUM: usize = driver::NUM::NeighborTable as usize;
+
+kernel::driver_api!(
+    DRIVER_API,
+    DRIVER_NUM,
+    name: "neighbor_table",
+    commands: [
+        0 => "exists()",
+        1 => "count() -> u32",
+        2 => "read(index) -> u32",
+    ],
+);
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// Buffer a neighbor is read into.
+    pub const NEIGHBOR: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Size of a neighbor in the buffer of a process.
+pub const NEIGHBOR_LEN: usize = 26;
+
+/// Ages are counted in steps of this many milliseconds.
+const AGING_INTERVAL_MS: u32 = 1000;
+
+/// Running statistics of a measurement.
+#[derive(Clone, Copy)]
+struct Running {
+    last: i16,
+    /// Exponential moving average, in sixteenths.
+    average_x16: i32,
+    min: i16,
+    max: i16,
+}
+
+impl Running {
+    fn new(value: i16) -> Self {
+        Self {
+            last: value,
+            average_x16: value as i32 * 16,
+            min: value,
+            max: value,
+        }
+    }
+
+    fn update(running: Option<Self>, value: Option<i16>) -> Option<Self> {
+        match (running, value) {
+            (Some(mut running), Some(value)) => {
+                running.last = value;
+                // Each frame has a weight of 1/8.
+                running.average_x16 += (value as i32 * 16 - running.average_x16) / 8;
+                running.min = running.min.min(value);
+                running.max = running.max.max(value);
+                Some(running)
+            }
+            (None, Some(value)) => Some(Self::new(value)),
+            (running, None) => running,
+        }
+    }
+
+    fn summary(&self) -> LinkSummary<i16> {
+        LinkSummary {
+            last: self.last,
+            average: ((self.average_x16 + 8).div_euclid(16)) as i16,
+            min: self.min,
+            max: self.max,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Entry {
+    address: LinkAddress,
+    frames: u32,
+    age_s: u32,
+    rssi: Option<Running>,
+    lqi: Option<Running>,
+}
+
+impl Entry {
+    fn neighbor(&self) -> Neighbor {
+        Neighbor {
+            address: self.address,
+            frames: self.frames,
+            age_s: self.age_s,
+            rssi: self.rssi.map(|rssi| {
+                let s = rssi.summary();
+                LinkSummary {
+                    last: s.last as i8,
+                    average: s.average as i8,
+                    min: s.min as i8,
+                    max: s.max as i8,
+                }
+            }),
+            lqi: self.lqi.map(|lqi| {
+                let s = lqi.summary();
+                LinkSummary {
+                    last: s.last as u8,
+                    average: s.average as u8,
+                    min: s.min as u8,
+                    max: s.max as u8,
+                }
+            }),
+        }
+    }
+
+    fn to_bytes(self) -> [u8; NEIGHBOR_LEN] {
+        let neighbor = self.neighbor();
+        let mut bytes = [0; NEIGHBOR_LEN];
+        let address = neighbor.address.as_slice();
+        bytes[0] = address.len() as u8;
+        bytes[1..1 + address.len()].copy_from_slice(address);
+        bytes[9..13].copy_from_slice(&neighbor.frames.to_le_bytes());
+        bytes[13..17].copy_from_slice(&neighbor.age_s.to_le_bytes());
+        if let Some(rssi) = neighbor.rssi {
+            bytes[17] |= 1 << 0;
+            bytes[18..22].copy_from_slice(&[
+                rssi.last as u8,
+                rssi.average as u8,
+                rssi.min as u8,
+                rssi.max as u8,
+            ]);
+        }
+        if let Some(lqi) = neighbor.lqi {
+            bytes[17] |= 1 << 1;
+            bytes[22..26].copy_from_slice(&[lqi.last, lqi.average, lqi.min, lqi.max]);
+        }
+        bytes
+    }
+}
+
+pub struct NeighborTableService<'a, A: Alarm<'a>, const N: usize> {
+    alarm: &'a A,
+    /// Seconds without frames after which a neighbor leaves the table.
+    max_age_s: u32,
+    entries: [Cell<Option<Entry>>; N],
+    apps: Grant<(), UpcallCount<0>, AllowRoCount<0>, AllowRwCount<{ rw_allow::COUNT }>>,
+}
+
+impl<'a, A: Alarm<'a>, const N: usize> NeighborTableService<'a, A, N> {
+    pub fn new(
+        alarm: &'a A,
+        max_age_s: u32,
+        grant: Grant<(), UpcallCount<0>, AllowRoCount<0>, AllowRwCount<{ rw_allow::COUNT }>>,
+    ) -> Self {
+        Self {
+            alarm,
+            max_age_s,
+            entries: [const { Cell::new(None) }; N],
+            apps: grant,
+        }
+    }
+
+    /// Forget all neighbors.
+    pub fn clear(&self) {
+        for entry in self.entries.iter() {
+            entry.set(None);
+        }
+        let _ = self.alarm.disarm();
+    }
+
+    /// The `index`th neighbor in the table.
+    fn entry(&self, index: usize) -> Option<Entry> {
+        self.entries
+            .iter()
+            .filter_map(|entry| entry.get())
+            .nth(index)
+    }
+
+    /// The slot for `address`: its entry, a free slot, or the entry heard
+    /// from least recently.
+    fn slot(&self, address: &LinkAddress) -> Option<&Cell<Option<Entry>>> {
+        self.entries
+            .iter()
+            .find(|entry| entry.get().is_some_and(|entry| entry.address == *address))
+            .or_else(|| self.entries.iter().find(|entry| entry.get().is_none()))
+            .or_else(|| {
+                self.entries
+                    .iter()
+                    .max_by_key(|entry| entry.get().map_or(0, |entry| entry.age_s))
+            })
+    }
+
+    fn read(&self, index: usize, processid: ProcessId) -> CommandReturn {
+        let entry = match self.entry(index) {
+            Some(entry) => entry,
+            None => return CommandReturn::failure(ErrorCode::INVAL),
+        };
+        self.apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readwrite_processbuffer(rw_allow::NEIGHBOR)
+                    .map_err(ErrorCode::from)
+                    .and_then(|buffer| {
+                        buffer
+                            .mut_enter(|buffer| {
+                                if buffer.len() < NEIGHBOR_LEN {
+                                    return Err(ErrorCode::SIZE);
+                                }
+                                buffer[..NEIGHBOR_LEN].copy_from_slice(&entry.to_bytes());
+                                Ok(())
+                            })
+                            .unwrap_or(Err(ErrorCode::NOMEM))
+                    })
+            })
+            .map_err(ErrorCode::from)
+            .and_then(|result| result)
+            .map_or_else(CommandReturn::failure, |()| {
+                CommandReturn::success_u32(NEIGHBOR_LEN as u32)
+            })
+    }
+}
+
+impl<'a, A: Alarm<'a>, const N: usize> LinkQualityObserver for NeighborTableService<'a, A, N> {
+    fn frame_received(&self, source: &[u8], rssi: Option<i8>, lqi: Option<u8>) {
+        let address = match LinkAddress::new(source) {
+            Some(address) => address,
+            None => return,
+        };
+        let slot = match self.slot(&address) {
+            Some(slot) => slot,
+            // The table has no slots.
+            None => return,
+        };
+        let entry = match slot.get() {
+            Some(entry) if entry.address == address => Entry {
+                frames: entry.frames.saturating_add(1),
+                age_s: 0,
+                rssi: Running::update(entry.rssi, rssi.map(i16::from)),
+                lqi: Running::update(entry.lqi, lqi.map(i16::from)),
+                ..entry
+            },
+            _ => Entry {
+                address,
+                frames: 1,
+                age_s: 0,
+                rssi: rssi.map(|rssi| Running::new(rssi.into())),
+                lqi: lqi.map(|lqi| Running::new(lqi.into())),
+            },
+        };
+        slot.set(Some(entry));
+
+        if !self.alarm.is_armed() {
+            self.alarm.set_alarm(
+                self.alarm.now(),
+                self.alarm.ticks_from_ms(AGING_INTERVAL_MS),
+            );
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>, const N: usize> NeighborTable for NeighborTableService<'a, A, N> {
+    fn num_neighbors(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|entry| entry.get().is_some())
+            .count()
+    }
+
+    fn neighbor(&self, index: usize) -> Option<Neighbor> {
+        self.entry(index).map(|entry| entry.neighbor())
+    }
+}
+
+impl<'a, A: Alarm<'a>, const N: usize> AlarmClient for NeighborTableService<'a, A, N> {
+    fn alarm(&self) {
+        let mut remaining = false;
+        for slot in self.entries.iter() {
+            if let Some(mut entry) = slot.get() {
+                entry.age_s = entry.age_s.saturating_add(AGING_INTERVAL_MS / 1000);
+                if entry.age_s >= self.max_age_s {
+                    slot.set(None);
+                } else {
+                    slot.set(Some(entry));
+                    remaining = true;
+                }
+            }
+        }
+        // Only count while there are neighbors to age.
+        if remaining {
+            self.alarm.set_alarm(
+                self.alarm.get_alarm(),
+                self.alarm.ticks_from_ms(AGING_INTERVAL_MS),
+            );
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>, const N: usize> SyscallDriver for NeighborTableService<'a, A, N> {
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        _arg2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+            1 => CommandReturn::success_u32(self.num_neighbors() as u32),
+            2 => self.read(arg1, processid),
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
@@ -0,0 +1,141 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Address Resolution Protocol (RFC 826) for IPv4 over Ethernet.
+//!
+//! The cache holds the MAC addresses of a few neighbors. Entries expire
+//! after a few minutes, and once the cache is full, resolving a new neighbor
+//! replaces the oldest entry.
+
+use core::cell::Cell;
+
+use crate::net::ipv4::stack::EthernetAddr;
+use crate::net::ipv4::Ipv4Addr;
+
+/// Length of an ARP packet for IPv4 over Ethernet.
+pub const ARP_PACKET_LEN: usize = 28;
+
+/// Number of neighbors in the cache.
+const ARP_CACHE_LEN: usize = 4;
+
+/// Time after which a cache entry expires, in seconds.
+const ARP_ENTRY_LIFETIME_S: u16 = 300;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArpOperation {
+    Request = 1,
+    Reply = 2,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ArpPacket {
+    pub operation: ArpOperation,
+    pub sender_mac: EthernetAddr,
+    pub sender_ip: Ipv4Addr,
+    pub target_mac: EthernetAddr,
+    pub target_ip: Ipv4Addr,
+}
+
+impl ArpPacket {
+    /// Parse an ARP packet for IPv4 over Ethernet.
+    pub fn decode(buf: &[u8]) -> Option<ArpPacket> {
+        if buf.len() < ARP_PACKET_LEN || buf[0..6] != [0, 1, 0x08, 0x00, 6, 4] {
+            return None;
+        }
+        let operation = match u16::from_be_bytes([buf[6], buf[7]]) {
+            1 => ArpOperation::Request,
+            2 => ArpOperation::Reply,
+            _ => return None,
+        };
+        let mut packet = ArpPacket {
+            operation,
+            sender_mac: [0; 6],
+            sender_ip: Ipv4Addr::UNSPECIFIED,
+            target_mac: [0; 6],
+            target_ip: Ipv4Addr::UNSPECIFIED,
+        };
+        packet.sender_mac.copy_from_slice(&buf[8..14]);
+        packet.sender_ip.0.copy_from_slice(&buf[14..18]);
+        packet.target_mac.copy_from_slice(&buf[18..24]);
+        packet.target_ip.0.copy_from_slice(&buf[24..28]);
+        Some(packet)
+    }
+
+    /// Write the packet into `buf`, which must hold at least
+    /// `ARP_PACKET_LEN` bytes. Returns the length of the packet.
+    pub fn encode(&self, buf: &mut [u8]) -> usize {
+        // Ethernet hardware, IPv4 protocol, and the length of their addresses
+        buf[0..6].copy_from_slice(&[0, 1, 0x08, 0x00, 6, 4]);
+        buf[6..8].copy_from_slice(&(self.operation as u16).to_be_bytes());
+        buf[8..14].copy_from_slice(&self.sender_mac);
+        buf[14..18].copy_from_slice(&self.sender_ip.0);
+        buf[18..24].copy_from_slice(&self.target_mac);
+        buf[24..28].copy_from_slice(&self.target_ip.0);
+        ARP_PACKET_LEN
+    }
+}
+
+#[derive(Clone, Copy)]
+struct ArpEntry {
+    ip: Ipv4Addr,
+    mac: EthernetAddr,
+    /// Time since the entry was last confirmed, in seconds.
+    age: u16,
+}
+
+pub struct ArpCache {
+    entries: [Cell<Option<ArpEntry>>; ARP_CACHE_LEN],
+}
+
+impl ArpCache {
+    pub fn new() -> ArpCache {
+        ArpCache {
+            entries: Default::default(),
+        }
+    }
+
+    pub fn lookup(&self, ip: Ipv4Addr) -> Option<EthernetAddr> {
+        self.entries
+            .iter()
+            .find_map(|entry| entry.get().filter(|entry| entry.ip == ip))
+            .map(|entry| entry.mac)
+    }
+
+    /// Whether the cache has an entry for `ip`.
+    pub fn contains(&self, ip: Ipv4Addr) -> bool {
+        self.lookup(ip).is_some()
+    }
+
+    /// Add or refresh the entry for `ip`.
+    pub fn insert(&self, ip: Ipv4Addr, mac: EthernetAddr) {
+        let slot = self
+            .entries
+            .iter()
+            .find(|entry| entry.get().is_some_and(|entry| entry.ip == ip))
+            .or_else(|| self.entries.iter().find(|entry| entry.get().is_none()))
+            .unwrap_or_else(|| {
+                // Replace the oldest entry.
+                self.entries
+                    .iter()
+                    .max_by_key(|entry| entry.get().map_or(0, |entry| entry.age))
+                    .unwrap()
+            });
+        slot.set(Some(ArpEntry { ip, mac, age: 0 }));
+    }
+
+    /// Remove all entries.
+    pub fn clear(&self) {
+        self.entries.iter().for_each(|entry| entry.set(None));
+    }
+
+    /// Age the entries by a second, expiring the old ones.
+    pub fn tick(&self) {
+        for entry in self.entries.iter() {
+            entry.set(entry.get().and_then(|mut e| {
+                e.age += 1;
+                (e.age < ARP_ENTRY_LIFETIME_S).then_some(e)
+            }));
+        }
+    }
+}
@@ -0,0 +1,374 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! DHCP client (RFC 2131).
+//!
+//! The client discovers a server, requests the address it offers, and
+//! renews the lease once half of it has passed. It does not send any I/O
+//! itself: the stack passes it the replies received on the client port, and
+//! calls `tick()` every second. Both return the [action](DhcpAction) the
+//! stack has to take, in particular when to send the message built by
+//! `encode()`.
+//!
+//! Renewals are broadcast like the initial request, so that they do not
+//! depend on a route to the server. A lease that is not renewed by the time
+//! it expires is dropped, and the client starts over.
+
+use core::cell::Cell;
+use core::cmp;
+
+use crate::net::ipv4::stack::EthernetAddr;
+use crate::net::ipv4::{Ipv4Addr, Ipv4Config};
+
+pub const DHCP_SERVER_PORT: u16 = 67;
+pub const DHCP_CLIENT_PORT: u16 = 68;
+
+/// Length of the messages sent: the length of a BOOTP message with its
+/// full 312 byte vendor area. Some servers, such as the one of QEMU's user
+/// networking, drop shorter messages.
+pub const DHCP_MESSAGE_LEN: usize = 548;
+
+/// Offset of the options, after the fixed fields and the magic cookie.
+const OPTIONS_OFFSET: usize = 240;
+const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+
+/// Time between retransmissions while the client has no address, in seconds.
+const RETRANSMIT_S: u32 = 4;
+/// Number of requests sent for an offer before the client starts over.
+const MAX_REQUESTS: u8 = 4;
+/// Lease time assumed when the server does not give one, in seconds.
+const DEFAULT_LEASE_S: u32 = 3600;
+
+mod option {
+    pub const PAD: u8 = 0;
+    pub const SUBNET_MASK: u8 = 1;
+    pub const ROUTER: u8 = 3;
+    pub const REQUESTED_ADDRESS: u8 = 50;
+    pub const LEASE_TIME: u8 = 51;
+    pub const MESSAGE_TYPE: u8 = 53;
+    pub const SERVER_ID: u8 = 54;
+    pub const PARAMETER_REQUEST_LIST: u8 = 55;
+    pub const RENEWAL_TIME: u8 = 58;
+    pub const END: u8 = 255;
+}
+
+mod message_type {
+    pub const DISCOVER: u8 = 1;
+    pub const OFFER: u8 = 2;
+    pub const REQUEST: u8 = 3;
+    pub const ACK: u8 = 5;
+    pub const NAK: u8 = 6;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DhcpState {
+    /// The client is not running.
+    Stopped,
+    /// Waiting to send a discover.
+    Init,
+    /// Discover sent, waiting for an offer.
+    Selecting,
+    /// Request sent for an offer, waiting for the acknowledgment.
+    Requesting,
+    /// The interface has an address.
+    Bound,
+    /// Request sent to extend the lease, waiting for the acknowledgment.
+    Renewing,
+}
+
+/// What the stack has to do after passing an event to the client.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DhcpAction {
+    Nothing,
+    /// Broadcast the message built by `encode()`.
+    Send,
+    /// Use this address configuration.
+    Configure(Ipv4Config),
+    /// Drop the address of the interface.
+    Deconfigure,
+}
+
+pub struct DhcpClient {
+    mac: EthernetAddr,
+    state: Cell<DhcpState>,
+    /// Transaction id of the current exchange.
+    xid: Cell<u32>,
+    /// Transactions started so far, to derive the next transaction id.
+    transactions: Cell<u32>,
+    /// Time until the next retransmission, or until the lease must be
+    /// renewed when bound, in seconds.
+    timer: Cell<u32>,
+    /// Time until the lease expires, in seconds.
+    lease_remaining: Cell<u32>,
+    requests: Cell<u8>,
+    /// Configuration offered, or leased.
+    offer: Cell<Option<Ipv4Config>>,
+    server: Cell<Ipv4Addr>,
+}
+
+impl DhcpClient {
+    pub fn new(mac: EthernetAddr) -> DhcpClient {
+        DhcpClient {
+            mac,
+            state: Cell::new(DhcpState::Stopped),
+            xid: Cell::new(0),
+            transactions: Cell::new(0),
+            timer: Cell::new(0),
+            lease_remaining: Cell::new(0),
+            requests: Cell::new(0),
+            offer: Cell::new(None),
+            server: Cell::new(Ipv4Addr::UNSPECIFIED),
+        }
+    }
+
+    pub fn state(&self) -> DhcpState {
+        self.state.get()
+    }
+
+    /// Start looking for a server on the next tick.
+    pub fn start(&self) {
+        self.state.set(DhcpState::Init);
+        self.timer.set(0);
+    }
+
+    /// Stop the client. The stack is responsible for dropping the address.
+    pub fn stop(&self) {
+        self.state.set(DhcpState::Stopped);
+        self.offer.set(None);
+    }
+
+    fn new_transaction(&self) {
+        // The MAC address keeps clients on the same network apart, the
+        // counter keeps transactions of this client apart.
+        let transactions = self.transactions.get().wrapping_add(1);
+        self.transactions.set(transactions);
+        let mac = u32::from_be_bytes([self.mac[2], self.mac[3], self.mac[4], self.mac[5]]);
+        self.xid.set(mac ^ transactions.wrapping_mul(0x9e37_79b9));
+    }
+
+    /// Advance the timers by a second.
+    pub fn tick(&self) -> DhcpAction {
+        let state = self.state.get();
+        if state == DhcpState::Stopped {
+            return DhcpAction::Nothing;
+        }
+
+        if matches!(state, DhcpState::Bound | DhcpState::Renewing) {
+            let lease_remaining = self.lease_remaining.get().saturating_sub(1);
+            self.lease_remaining.set(lease_remaining);
+            if lease_remaining == 0 {
+                self.start();
+                self.offer.set(None);
+                return DhcpAction::Deconfigure;
+            }
+        }
+
+        let timer = self.timer.get().saturating_sub(1);
+        self.timer.set(timer);
+        if timer > 0 {
+            return DhcpAction::Nothing;
+        }
+
+        match state {
+            DhcpState::Stopped => DhcpAction::Nothing,
+            DhcpState::Init => {
+                self.new_transaction();
+                self.state.set(DhcpState::Selecting);
+                self.timer.set(RETRANSMIT_S);
+                DhcpAction::Send
+            }
+            DhcpState::Selecting => {
+                self.timer.set(RETRANSMIT_S);
+                DhcpAction::Send
+            }
+            DhcpState::Requesting => {
+                if self.requests.get() >= MAX_REQUESTS {
+                    self.start();
+                    return DhcpAction::Nothing;
+                }
+                self.requests.set(self.requests.get() + 1);
+                self.timer.set(RETRANSMIT_S);
+                DhcpAction::Send
+            }
+            DhcpState::Bound | DhcpState::Renewing => {
+                if state == DhcpState::Bound {
+                    self.new_transaction();
+                    self.state.set(DhcpState::Renewing);
+                }
+                // Retransmit at half of the remaining lease, but not more
+                // often than while looking for an address.
+                self.timer
+                    .set(cmp::max(self.lease_remaining.get() / 2, RETRANSMIT_S));
+                DhcpAction::Send
+            }
+        }
+    }
+
+    /// Build the message to send in the current state into `buf`, which
+    /// must hold at least `DHCP_MESSAGE_LEN` bytes. Returns the length of
+    /// the message.
+    pub fn encode(&self, buf: &mut [u8]) -> usize {
+        let buf = &mut buf[..DHCP_MESSAGE_LEN];
+        buf.fill(0);
+        buf[0] = 1; // BOOTREQUEST
+        buf[1] = 1; // Ethernet
+        buf[2] = 6; // Length of the hardware address
+        buf[4..8].copy_from_slice(&self.xid.get().to_be_bytes());
+        // Ask for broadcast replies, as we cannot receive unicast to an
+        // address we do not have yet.
+        buf[10] = 0x80;
+        buf[28..34].copy_from_slice(&self.mac);
+        buf[236..OPTIONS_OFFSET].copy_from_slice(&MAGIC_COOKIE);
+
+        let mut options = OptionWriter {
+            buf,
+            offset: OPTIONS_OFFSET,
+        };
+        let offer = self.offer.get();
+        match (self.state.get(), offer) {
+            (DhcpState::Requesting, Some(offer)) => {
+                options.write(option::MESSAGE_TYPE, &[message_type::REQUEST]);
+                options.write(option::REQUESTED_ADDRESS, &offer.address.0);
+                options.write(option::SERVER_ID, &self.server.get().0);
+            }
+            (DhcpState::Renewing, Some(lease)) => {
+                options.buf[12..16].copy_from_slice(&lease.address.0);
+                options.write(option::MESSAGE_TYPE, &[message_type::REQUEST]);
+            }
+            _ => options.write(option::MESSAGE_TYPE, &[message_type::DISCOVER]),
+        }
+        options.write(
+            option::PARAMETER_REQUEST_LIST,
+            &[option::SUBNET_MASK, option::ROUTER],
+        );
+        options.buf[options.offset] = option::END;
+        DHCP_MESSAGE_LEN
+    }
+
+    /// Process a message received on the client port.
+    pub fn receive(&self, msg: &[u8]) -> DhcpAction {
+        let reply = match DhcpReply::decode(msg) {
+            Some(reply) => reply,
+            None => return DhcpAction::Nothing,
+        };
+        if reply.xid != self.xid.get() || reply.chaddr != self.mac {
+            return DhcpAction::Nothing;
+        }
+
+        match (self.state.get(), reply.message_type) {
+            (DhcpState::Selecting, message_type::OFFER) => {
+                self.offer.set(Some(reply.config));
+                self.server.set(reply.server);
+                self.state.set(DhcpState::Requesting);
+                self.requests.set(1);
+                self.timer.set(RETRANSMIT_S);
+                DhcpAction::Send
+            }
+            (DhcpState::Requesting | DhcpState::Renewing, message_type::ACK) => {
+                let lease = reply.lease_time.unwrap_or(DEFAULT_LEASE_S).max(2);
+                self.offer.set(Some(reply.config));
+                self.lease_remaining.set(lease);
+                self.timer
+                    .set(reply.renewal_time.unwrap_or(lease / 2).max(1));
+                self.state.set(DhcpState::Bound);
+                DhcpAction::Configure(reply.config)
+            }
+            (DhcpState::Requesting | DhcpState::Renewing, message_type::NAK) => {
+                let was_bound = self.state.get() == DhcpState::Renewing;
+                self.start();
+                self.offer.set(None);
+                if was_bound {
+                    DhcpAction::Deconfigure
+                } else {
+                    DhcpAction::Nothing
+                }
+            }
+            _ => DhcpAction::Nothing,
+        }
+    }
+}
+
+struct OptionWriter<'b> {
+    buf: &'b mut [u8],
+    offset: usize,
+}
+
+impl OptionWriter<'_> {
+    fn write(&mut self, code: u8, value: &[u8]) {
+        let end = self.offset + 2 + value.len();
+        self.buf[self.offset] = code;
+        self.buf[self.offset + 1] = value.len() as u8;
+        self.buf[self.offset + 2..end].copy_from_slice(value);
+        self.offset = end;
+    }
+}
+
+/// The fields of a server reply the client uses.
+struct DhcpReply {
+    xid: u32,
+    chaddr: EthernetAddr,
+    message_type: u8,
+    config: Ipv4Config,
+    server: Ipv4Addr,
+    lease_time: Option<u32>,
+    renewal_time: Option<u32>,
+}
+
+impl DhcpReply {
+    fn decode(msg: &[u8]) -> Option<DhcpReply> {
+        // BOOTREPLY, over Ethernet
+        if msg.len() < OPTIONS_OFFSET || msg[0] != 2 || msg[1] != 1 || msg[2] != 6 {
+            return None;
+        }
+        if msg[236..OPTIONS_OFFSET] != MAGIC_COOKIE {
+            return None;
+        }
+        let mut reply = DhcpReply {
+            xid: u32::from_be_bytes([msg[4], msg[5], msg[6], msg[7]]),
+            chaddr: [0; 6],
+            message_type: 0,
+            config: Ipv4Config {
+                address: Ipv4Addr::UNSPECIFIED,
+                // Classless default, for servers that do not send a mask
+                netmask: Ipv4Addr::new(255, 255, 255, 0),
+                gateway: None,
+            },
+            server: Ipv4Addr::UNSPECIFIED,
+            lease_time: None,
+            renewal_time: None,
+        };
+        reply.chaddr.copy_from_slice(&msg[28..34]);
+        reply.config.address.0.copy_from_slice(&msg[16..20]);
+
+        let mut options = &msg[OPTIONS_OFFSET..];
+        while let Some((&code, rest)) = options.split_first() {
+            match code {
+                option::PAD => {
+                    options = rest;
+                    continue;
+                }
+                option::END => break,
+                _ => {}
+            }
+            let (&len, rest) = rest.split_first()?;
+            let value = rest.get(..len as usize)?;
+            options = &rest[len as usize..];
+
+            let addr = value
+                .get(..4)
+                .map(|addr| Ipv4Addr([addr[0], addr[1], addr[2], addr[3]]));
+            let seconds = addr.map(|addr| addr.to_u32());
+            match code {
+                option::MESSAGE_TYPE => reply.message_type = *value.first()?,
+                option::SUBNET_MASK => reply.config.netmask = addr?,
+                option::ROUTER => reply.config.gateway = addr,
+                option::SERVER_ID => reply.server = addr?,
+                option::LEASE_TIME => reply.lease_time = seconds,
+                option::RENEWAL_TIME => reply.renewal_time = seconds,
+                _ => {}
+            }
+        }
+        Some(reply)
+    }
+}
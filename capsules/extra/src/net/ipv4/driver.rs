@@ -0,0 +1,262 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! UDP over IPv4 userspace interface.
+//!
+//! Gives processes access to a fixed pool of UDP sockets of an
+//! [Ipv4Stack](crate::net::ipv4::stack::Ipv4Stack). A process claims a
+//! socket from the pool by binding it to a port, and keeps it until it
+//! releases it (command 4) or exits. Each process can hold one socket at a
+//! time.
+//!
+//! Addresses are passed as numbers, with the first byte of the address most
+//! significant: 192.168.1.2 is `0xc0a80102`.
+
+use crate::net::ipv4::stack::Ipv4Stack;
+use crate::net::ipv4::udp::{UdpSocket, UdpSocketClient};
+use crate::net::ipv4::Ipv4Addr;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::time;
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::OptionalCell;
+use kernel::{ErrorCode, ProcessId};
+
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::Ipv4Udp as usize;
+
+/// IDs for subscribed upcalls.
+mod upcall {
+    /// A datagram was received and copied into the read buffer. The
+    /// arguments are the length of the datagram, which can be more than was
+    /// copied, and the address and port it came from.
+    pub const RECEIVED: usize = 0;
+    /// The datagram passed to send was sent, or failed to be. The first
+    /// argument is the result as a `StatusCode`.
+    pub const SENT: usize = 1;
+    /// Number of upcalls.
+    pub const COUNT: u8 = 2;
+}
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    /// Write buffer. Contains the payload of the datagram to send.
+    pub const WRITE: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// Read buffer. Received datagrams are copied into it.
+    pub const READ: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+#[derive(Default)]
+pub struct App;
+
+pub struct Ipv4UdpDriver<'a, A: time::Alarm<'a>, const NUM_SOCKETS: usize> {
+    stack: &'a Ipv4Stack<'a, A>,
+    sockets: &'a [UdpSocket<'a>; NUM_SOCKETS],
+    /// Process that holds each of the sockets.
+    owners: [OptionalCell<ProcessId>; NUM_SOCKETS],
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+}
+
+impl<'a, A: time::Alarm<'a>, const NUM_SOCKETS: usize> Ipv4UdpDriver<'a, A, NUM_SOCKETS> {
+    /// Sockets must be created with their index in `sockets` as their id,
+    /// and added to `stack`.
+    pub fn new(
+        stack: &'a Ipv4Stack<'a, A>,
+        sockets: &'a [UdpSocket<'a>; NUM_SOCKETS],
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+    ) -> Ipv4UdpDriver<'a, A, NUM_SOCKETS> {
+        Ipv4UdpDriver {
+            stack,
+            sockets,
+            owners: core::array::from_fn(|_| OptionalCell::empty()),
+            apps: grant,
+        }
+    }
+
+    /// Index of the socket held by `processid`.
+    fn socket_of(&self, processid: ProcessId) -> Option<usize> {
+        self.owners
+            .iter()
+            .position(|owner| owner.contains(&processid))
+    }
+
+    /// Claim a free socket for `processid`.
+    fn claim_socket(&self, processid: ProcessId) -> Result<usize, ErrorCode> {
+        // Sockets of processes that no longer exist are free as well.
+        let index = self
+            .owners
+            .iter()
+            .position(|owner| {
+                owner.map_or(true, |owner| self.apps.enter(owner, |_, _| {}).is_err())
+            })
+            .ok_or(ErrorCode::NOMEM)?;
+        self.stack.unbind(&self.sockets[index]);
+        self.owners[index].set(processid);
+        Ok(index)
+    }
+
+    fn release_socket(&self, index: usize) {
+        self.stack.unbind(&self.sockets[index]);
+        self.owners[index].clear();
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, const NUM_SOCKETS: usize> UdpSocketClient
+    for Ipv4UdpDriver<'a, A, NUM_SOCKETS>
+{
+    fn received(&self, socket: usize, src_addr: Ipv4Addr, src_port: u16, payload: &[u8]) {
+        if let Some(owner) = self.owners.get(socket).and_then(|owner| owner.get()) {
+            let _ = self.apps.enter(owner, |_, kernel_data| {
+                kernel_data
+                    .get_readwrite_processbuffer(rw_allow::READ)
+                    .and_then(|read| {
+                        read.mut_enter(|dest| {
+                            let length = core::cmp::min(dest.len(), payload.len());
+                            dest[..length].copy_from_slice(&payload[..length]);
+                        })
+                    })
+                    .ok();
+                kernel_data
+                    .schedule_upcall(
+                        upcall::RECEIVED,
+                        (payload.len(), src_addr.to_u32() as usize, src_port as usize),
+                    )
+                    .ok();
+            });
+        }
+    }
+
+    fn sent(&self, socket: usize, result: Result<(), ErrorCode>) {
+        if let Some(owner) = self.owners.get(socket).and_then(|owner| owner.get()) {
+            let _ = self.apps.enter(owner, |_, kernel_data| {
+                kernel_data
+                    .schedule_upcall(
+                        upcall::SENT,
+                        (kernel::errorcode::into_statuscode(result), 0, 0),
+                    )
+                    .ok();
+            });
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, const NUM_SOCKETS: usize> SyscallDriver
+    for Ipv4UdpDriver<'a, A, NUM_SOCKETS>
+{
+    /// UDP over IPv4 control.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Get the address configuration of the interface. Returns the
+    ///   address and the netmask, or `OFF` while the interface has no
+    ///   address.
+    /// - `2`: Claim a socket and bind it to the port in `arg1`.
+    /// - `3`: Send the contents of the write buffer to the address in `arg1`
+    ///   and the port in `arg2`. `sent` is signaled once the datagram was
+    ///   sent.
+    /// - `4`: Release the socket.
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        arg2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => match self.stack.config() {
+                Some(config) => {
+                    CommandReturn::success_u32_u32(config.address.to_u32(), config.netmask.to_u32())
+                }
+                None => CommandReturn::failure(ErrorCode::OFF),
+            },
+
+            2 => {
+                if arg1 > u16::MAX as usize {
+                    return CommandReturn::failure(ErrorCode::INVAL);
+                }
+                if self.socket_of(processid).is_some() {
+                    return CommandReturn::failure(ErrorCode::ALREADY);
+                }
+                let index = match self.claim_socket(processid) {
+                    Ok(index) => index,
+                    Err(e) => return CommandReturn::failure(e),
+                };
+                let result = self.stack.bind(&self.sockets[index], arg1 as u16);
+                if result.is_err() {
+                    self.owners[index].clear();
+                }
+                result.into()
+            }
+
+            3 => {
+                let index = match self.socket_of(processid) {
+                    Some(index) => index,
+                    None => return CommandReturn::failure(ErrorCode::RESERVE),
+                };
+                if arg1 > u32::MAX as usize || arg2 > u16::MAX as usize {
+                    return CommandReturn::failure(ErrorCode::INVAL);
+                }
+                let dst = Ipv4Addr::from_u32(arg1 as u32);
+                self.apps
+                    .enter(processid, |_, kernel_data| {
+                        kernel_data
+                            .get_readonly_processbuffer(ro_allow::WRITE)
+                            .and_then(|write| {
+                                write.enter(|data| {
+                                    self.stack.send_to(
+                                        &self.sockets[index],
+                                        dst,
+                                        arg2 as u16,
+                                        |payload| {
+                                            let length = core::cmp::min(payload.len(), data.len());
+                                            data[..length].copy_to_slice(&mut payload[..length]);
+                                            length
+                                        },
+                                    )
+                                })
+                            })
+                            .unwrap_or(Err(ErrorCode::RESERVE))
+                    })
+                    .unwrap_or_else(|err| Err(err.into()))
+                    .into()
+            }
+
+            4 => match self.socket_of(processid) {
+                Some(index) => {
+                    self.release_socket(index);
+                    CommandReturn::success()
+                }
+                None => CommandReturn::failure(ErrorCode::RESERVE),
+            },
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
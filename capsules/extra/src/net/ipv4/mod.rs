@@ -0,0 +1,105 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! IPv4 over Ethernet.
+//!
+//! [Ipv4Stack](stack::Ipv4Stack) runs a single IPv4 interface on any
+//! `EthernetAdapterDatapath`. It resolves the addresses of its neighbors with
+//! ARP, gets its own address from a DHCP server unless it is given a static
+//! configuration, answers pings, and carries UDP datagrams for
+//! [sockets](udp::UdpSocket). The [driver](driver::Ipv4UdpDriver) gives
+//! processes access to a pool of these sockets.
+//!
+//! Fragmented datagrams and IP options are not supported: fragments are
+//! dropped, and datagrams are limited to the MTU of the adapter.
+
+pub mod arp;
+pub mod dhcp;
+pub mod driver;
+pub mod stack;
+pub mod udp;
+
+pub use driver::{Ipv4UdpDriver, DRIVER_NUM};
+
+/// An IPv4 address.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Ipv4Addr(pub [u8; 4]);
+
+impl Ipv4Addr {
+    pub const UNSPECIFIED: Ipv4Addr = Ipv4Addr([0; 4]);
+    pub const BROADCAST: Ipv4Addr = Ipv4Addr([255; 4]);
+
+    pub const fn new(a: u8, b: u8, c: u8, d: u8) -> Ipv4Addr {
+        Ipv4Addr([a, b, c, d])
+    }
+
+    /// The address as a number, with the first byte most significant.
+    pub fn to_u32(self) -> u32 {
+        u32::from_be_bytes(self.0)
+    }
+
+    pub fn from_u32(addr: u32) -> Ipv4Addr {
+        Ipv4Addr(addr.to_be_bytes())
+    }
+
+    pub fn is_unspecified(&self) -> bool {
+        *self == Ipv4Addr::UNSPECIFIED
+    }
+}
+
+/// Address configuration of an interface.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Ipv4Config {
+    pub address: Ipv4Addr,
+    pub netmask: Ipv4Addr,
+    /// Router for destinations outside of the subnet.
+    pub gateway: Option<Ipv4Addr>,
+}
+
+impl Ipv4Config {
+    /// Whether `addr` is on the subnet of the interface.
+    pub fn is_local(&self, addr: Ipv4Addr) -> bool {
+        let mask = self.netmask.to_u32();
+        addr.to_u32() & mask == self.address.to_u32() & mask
+    }
+
+    /// Broadcast address of the subnet.
+    pub fn broadcast(&self) -> Ipv4Addr {
+        Ipv4Addr::from_u32(self.address.to_u32() | !self.netmask.to_u32())
+    }
+}
+
+/// Client notified when the address configuration of an interface changes.
+pub trait Ipv4ConfigClient {
+    /// The interface was configured, or lost its address (for example
+    /// because its DHCP lease expired) if `config` is `None`.
+    fn config_changed(&self, config: Option<Ipv4Config>);
+}
+
+/// Protocol numbers of the payloads carried over IPv4.
+pub mod ip4_proto {
+    pub const ICMP: u8 = 1;
+    pub const UDP: u8 = 17;
+}
+
+/// Adds `data` to the one's complement sum `sum` of the internet checksum
+/// (RFC 1071).
+pub(crate) fn checksum_add(sum: u32, data: &[u8]) -> u32 {
+    let mut chunks = data.chunks_exact(2);
+    let mut sum = chunks.by_ref().fold(sum, |sum, word| {
+        sum + u16::from_be_bytes([word[0], word[1]]) as u32
+    });
+    if let [last] = chunks.remainder() {
+        sum += (*last as u32) << 8;
+    }
+    sum
+}
+
+/// Folds the sum of `checksum_add` into the checksum.
+pub(crate) fn checksum_finish(mut sum: u32) -> u16 {
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
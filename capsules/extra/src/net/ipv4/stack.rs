@@ -0,0 +1,659 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! IPv4 interface on an Ethernet adapter.
+//!
+//! The stack transmits one frame at a time, from two buffers:
+//!
+//! - The control buffer holds the frames the stack sends on its own: ARP
+//!   requests and replies, echo replies and DHCP messages. A control frame
+//!   that is generated while the buffer is in use is dropped, and recovered
+//!   from by the retransmissions of its peer or of the DHCP client.
+//! - The data buffer holds the datagram of a socket. It stays there until
+//!   the MAC address of its next hop is resolved, so sockets take turns: a
+//!   socket sending while a datagram is pending gets `BUSY`.
+//!
+//! A periodic alarm ages the ARP cache, retries ARP requests and drives the
+//! DHCP client.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let ipv4_stack = static_init!(
+//!     Ipv4Stack<'static, VirtualMuxAlarm<'static, Timer>>,
+//!     Ipv4Stack::new(ethernet_adapter, alarm, MAC_ADDR, control_buffer, data_buffer)
+//! );
+//! ethernet_adapter.set_client(ipv4_stack);
+//! alarm.set_alarm_client(ipv4_stack);
+//! // Without a static configuration, the stack asks a DHCP server.
+//! ipv4_stack.start();
+//! ```
+
+use core::cell::Cell;
+use core::cmp;
+
+use crate::net::ipv4::arp::{ArpCache, ArpOperation, ArpPacket};
+use crate::net::ipv4::dhcp::{DhcpAction, DhcpClient, DhcpState};
+use crate::net::ipv4::dhcp::{DHCP_CLIENT_PORT, DHCP_SERVER_PORT};
+use crate::net::ipv4::udp::{UdpSocket, UDP_HDR_LEN};
+use crate::net::ipv4::{checksum_add, checksum_finish, ip4_proto};
+use crate::net::ipv4::{Ipv4Addr, Ipv4Config, Ipv4ConfigClient};
+
+use kernel::collections::list::List;
+use kernel::hil::ethernet::{EthernetAdapterDatapath, EthernetAdapterDatapathClient};
+use kernel::hil::time::{self, ConvertTicks};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+/// An Ethernet MAC address.
+pub type EthernetAddr = [u8; 6];
+
+pub const ETHERNET_BROADCAST: EthernetAddr = [0xff; 6];
+
+const ETHERNET_HDR_LEN: usize = 14;
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_ARP: u16 = 0x0806;
+
+/// Length of an IPv4 header without options.
+pub const IPV4_HDR_LEN: usize = 20;
+const IPV4_DEFAULT_TTL: u8 = 64;
+
+/// Offset of the payload of UDP datagrams in frames.
+pub const UDP_PAYLOAD_OFFSET: usize = ETHERNET_HDR_LEN + IPV4_HDR_LEN + UDP_HDR_LEN;
+
+/// Length of a frame with the payload of 1500 bytes of Ethernet. Frame
+/// buffers of this length carry datagrams up to the MTU.
+pub const FRAME_BUF_LEN: usize = 1514;
+
+/// Period of the timer of the stack, in ms.
+pub const TICK_MS: u32 = 1000;
+
+/// Number of ARP requests sent for a datagram before it is dropped.
+const ARP_ATTEMPTS: u8 = 3;
+
+/// Transmission identifiers, telling which buffer a sent frame was in.
+mod frame {
+    pub const CONTROL: usize = 0;
+    pub const DATA: usize = 1;
+}
+
+const ICMP_ECHO_REPLY: u8 = 0;
+const ICMP_ECHO_REQUEST: u8 = 8;
+
+/// Datagram in the data buffer.
+#[derive(Clone, Copy)]
+struct PendingDatagram {
+    /// Id of the socket that sent it.
+    socket: usize,
+    /// Address whose MAC address the datagram is sent to, or `None` for
+    /// broadcasts.
+    next_hop: Option<Ipv4Addr>,
+    len: usize,
+    /// Whether the destination MAC address is in the frame.
+    resolved: bool,
+    arp_attempts: u8,
+}
+
+pub struct Ipv4Stack<'a, A: time::Alarm<'a>> {
+    adapter: &'a dyn EthernetAdapterDatapath<'a>,
+    alarm: &'a A,
+    mac: EthernetAddr,
+    config: Cell<Option<Ipv4Config>>,
+    config_client: OptionalCell<&'a dyn Ipv4ConfigClient>,
+    dhcp: DhcpClient,
+    arp: ArpCache,
+    sockets: List<'a, UdpSocket<'a>>,
+    control_buffer: TakeCell<'static, [u8]>,
+    /// Length of the frame in the control buffer waiting to be sent, or 0.
+    control_len: Cell<usize>,
+    data_buffer: TakeCell<'static, [u8]>,
+    datagram: Cell<Option<PendingDatagram>>,
+    transmitting: Cell<bool>,
+    /// Identification field of the next IPv4 header.
+    ip_id: Cell<u16>,
+}
+
+impl<'a, A: time::Alarm<'a>> Ipv4Stack<'a, A> {
+    /// The buffers should be `FRAME_BUF_LEN` long. DHCP needs a control
+    /// buffer of at least 590 bytes, which is also the limit for the pings
+    /// answered.
+    pub fn new(
+        adapter: &'a dyn EthernetAdapterDatapath<'a>,
+        alarm: &'a A,
+        mac: EthernetAddr,
+        control_buffer: &'static mut [u8],
+        data_buffer: &'static mut [u8],
+    ) -> Ipv4Stack<'a, A> {
+        Ipv4Stack {
+            adapter,
+            alarm,
+            mac,
+            config: Cell::new(None),
+            config_client: OptionalCell::empty(),
+            dhcp: DhcpClient::new(mac),
+            arp: ArpCache::new(),
+            sockets: List::new(),
+            control_buffer: TakeCell::new(control_buffer),
+            control_len: Cell::new(0),
+            data_buffer: TakeCell::new(data_buffer),
+            datagram: Cell::new(None),
+            transmitting: Cell::new(false),
+            ip_id: Cell::new(0),
+        }
+    }
+
+    pub fn set_config_client(&self, client: &'a dyn Ipv4ConfigClient) {
+        self.config_client.set(client);
+    }
+
+    pub fn add_socket(&self, socket: &'a UdpSocket<'a>) {
+        self.sockets.push_tail(socket);
+    }
+
+    pub fn mac_address(&self) -> EthernetAddr {
+        self.mac
+    }
+
+    /// Current address configuration, if the interface has one.
+    pub fn config(&self) -> Option<Ipv4Config> {
+        self.config.get()
+    }
+
+    /// Use a static address configuration, rather than DHCP.
+    pub fn set_static_config(&self, config: Ipv4Config) {
+        self.dhcp.stop();
+        self.set_config(Some(config));
+    }
+
+    /// Start receiving, and, without a static configuration, ask a DHCP
+    /// server for an address.
+    pub fn start(&self) {
+        self.adapter.enable_receive();
+        if self.config.get().is_none() {
+            self.dhcp.start();
+        }
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(TICK_MS));
+    }
+
+    /// Bind `socket` to `port`.
+    pub fn bind(&self, socket: &UdpSocket, port: u16) -> Result<(), ErrorCode> {
+        if port == 0 {
+            return Err(ErrorCode::INVAL);
+        }
+        if socket.local_port().is_some() {
+            return Err(ErrorCode::ALREADY);
+        }
+        let dhcp_port = port == DHCP_CLIENT_PORT && self.dhcp.state() != DhcpState::Stopped;
+        if dhcp_port || self.sockets.iter().any(|s| s.local_port() == Some(port)) {
+            return Err(ErrorCode::BUSY);
+        }
+        socket.set_port(port);
+        Ok(())
+    }
+
+    pub fn unbind(&self, socket: &UdpSocket) {
+        socket.set_port(0);
+    }
+
+    /// Send a datagram from the port of `socket` to `dst_port` of `dst`.
+    ///
+    /// `fill` is passed the payload space of the datagram, and returns how
+    /// many bytes it wrote into it. `sent()` is called on the client of the
+    /// socket when the datagram was sent. Returns `BUSY` while another
+    /// datagram is pending, `OFF` while the interface has no address, and
+    /// `INVAL` if the socket is not bound or there is no route to `dst`.
+    pub fn send_to<F>(
+        &self,
+        socket: &UdpSocket,
+        dst: Ipv4Addr,
+        dst_port: u16,
+        fill: F,
+    ) -> Result<(), ErrorCode>
+    where
+        F: FnOnce(&mut [u8]) -> usize,
+    {
+        let src_port = socket.local_port().ok_or(ErrorCode::INVAL)?;
+        let config = self.config.get().ok_or(ErrorCode::OFF)?;
+        if self.datagram.get().is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        let next_hop = if dst == Ipv4Addr::BROADCAST || dst == config.broadcast() {
+            None
+        } else if config.is_local(dst) {
+            Some(dst)
+        } else {
+            Some(config.gateway.ok_or(ErrorCode::INVAL)?)
+        };
+
+        let len = self
+            .data_buffer
+            .map(|frame| {
+                let payload = &mut frame[UDP_PAYLOAD_OFFSET..];
+                let payload_len = cmp::min(fill(payload), payload.len());
+                self.encode_udp(frame, config.address, dst, src_port, dst_port, payload_len)
+            })
+            .ok_or(ErrorCode::BUSY)?;
+        self.datagram.set(Some(PendingDatagram {
+            socket: socket.id(),
+            next_hop,
+            len,
+            resolved: false,
+            arp_attempts: 0,
+        }));
+        self.transmit_pending();
+        Ok(())
+    }
+
+    fn set_config(&self, config: Option<Ipv4Config>) {
+        if self.config.get() != config {
+            self.config.set(config);
+            self.arp.clear();
+            self.config_client
+                .map(|client| client.config_changed(config));
+        }
+    }
+
+    fn encode_ethernet(&self, frame: &mut [u8], dst: EthernetAddr, ethertype: u16) {
+        frame[0..6].copy_from_slice(&dst);
+        frame[6..12].copy_from_slice(&self.mac);
+        frame[12..14].copy_from_slice(&ethertype.to_be_bytes());
+    }
+
+    /// Write an IPv4 header for `payload_len` bytes into `header`.
+    fn encode_ipv4(
+        &self,
+        header: &mut [u8],
+        src: Ipv4Addr,
+        dst: Ipv4Addr,
+        protocol: u8,
+        payload_len: usize,
+    ) {
+        let id = self.ip_id.get();
+        self.ip_id.set(id.wrapping_add(1));
+
+        header[0] = 0x45; // Version 4, no options
+        header[1] = 0;
+        header[2..4].copy_from_slice(&((IPV4_HDR_LEN + payload_len) as u16).to_be_bytes());
+        header[4..6].copy_from_slice(&id.to_be_bytes());
+        header[6..8].copy_from_slice(&[0x40, 0]); // Don't fragment
+        header[8] = IPV4_DEFAULT_TTL;
+        header[9] = protocol;
+        header[10..12].copy_from_slice(&[0, 0]);
+        header[12..16].copy_from_slice(&src.0);
+        header[16..20].copy_from_slice(&dst.0);
+        let checksum = checksum_finish(checksum_add(0, &header[..IPV4_HDR_LEN]));
+        header[10..12].copy_from_slice(&checksum.to_be_bytes());
+    }
+
+    /// Write the headers of a UDP datagram whose payload is already at
+    /// `UDP_PAYLOAD_OFFSET` of `frame`, except for the destination MAC
+    /// address. Returns the length of the frame.
+    fn encode_udp(
+        &self,
+        frame: &mut [u8],
+        src: Ipv4Addr,
+        dst: Ipv4Addr,
+        src_port: u16,
+        dst_port: u16,
+        payload_len: usize,
+    ) -> usize {
+        let udp_len = UDP_HDR_LEN + payload_len;
+        self.encode_ethernet(frame, ETHERNET_BROADCAST, ETHERTYPE_IPV4);
+        self.encode_ipv4(
+            &mut frame[ETHERNET_HDR_LEN..],
+            src,
+            dst,
+            ip4_proto::UDP,
+            udp_len,
+        );
+        let udp = &mut frame[ETHERNET_HDR_LEN + IPV4_HDR_LEN..][..udp_len];
+        udp[0..2].copy_from_slice(&src_port.to_be_bytes());
+        udp[2..4].copy_from_slice(&dst_port.to_be_bytes());
+        udp[4..6].copy_from_slice(&(udp_len as u16).to_be_bytes());
+        udp[6..8].copy_from_slice(&[0, 0]);
+        let checksum = match udp_checksum(src, dst, udp) {
+            // Zero means that there is no checksum.
+            0 => 0xffff,
+            checksum => checksum,
+        };
+        udp[6..8].copy_from_slice(&checksum.to_be_bytes());
+        ETHERNET_HDR_LEN + IPV4_HDR_LEN + udp_len
+    }
+
+    /// Build a frame in the control buffer with `encode`, which returns the
+    /// length of the frame or `None` to drop it. Frames are dropped as well
+    /// while the buffer is in use.
+    fn queue_control<F>(&self, encode: F)
+    where
+        F: FnOnce(&mut [u8]) -> Option<usize>,
+    {
+        if self.control_len.get() != 0 {
+            return;
+        }
+        self.control_buffer.map(|frame| {
+            if let Some(len) = encode(frame) {
+                self.control_len.set(len);
+            }
+        });
+    }
+
+    fn queue_arp(&self, operation: ArpOperation, target_mac: EthernetAddr, target_ip: Ipv4Addr) {
+        let sender_ip = match self.config.get() {
+            Some(config) => config.address,
+            None => return,
+        };
+        let packet = ArpPacket {
+            operation,
+            sender_mac: self.mac,
+            sender_ip,
+            // Requests leave the target hardware address open.
+            target_mac: match operation {
+                ArpOperation::Request => [0; 6],
+                ArpOperation::Reply => target_mac,
+            },
+            target_ip,
+        };
+        self.queue_control(|frame| {
+            self.encode_ethernet(frame, target_mac, ETHERTYPE_ARP);
+            Some(ETHERNET_HDR_LEN + packet.encode(&mut frame[ETHERNET_HDR_LEN..]))
+        });
+    }
+
+    fn handle_dhcp_action(&self, action: DhcpAction) {
+        match action {
+            DhcpAction::Nothing => {}
+            DhcpAction::Send => {
+                // Renewals come from the address being renewed.
+                let src = self
+                    .config
+                    .get()
+                    .map_or(Ipv4Addr::UNSPECIFIED, |config| config.address);
+                self.queue_control(|frame| {
+                    let payload = frame.get_mut(UDP_PAYLOAD_OFFSET..)?;
+                    let len = self.dhcp.encode(payload);
+                    Some(self.encode_udp(
+                        frame,
+                        src,
+                        Ipv4Addr::BROADCAST,
+                        DHCP_CLIENT_PORT,
+                        DHCP_SERVER_PORT,
+                        len,
+                    ))
+                });
+            }
+            DhcpAction::Configure(config) => self.set_config(Some(config)),
+            DhcpAction::Deconfigure => self.set_config(None),
+        }
+    }
+
+    /// Fill the destination MAC address of the pending datagram in, or ask
+    /// for it.
+    fn resolve_datagram(&self) {
+        let mut datagram = match self.datagram.get() {
+            Some(datagram) if !datagram.resolved => datagram,
+            _ => return,
+        };
+        let mac = match datagram.next_hop {
+            None => Some(ETHERNET_BROADCAST),
+            Some(next_hop) => self.arp.lookup(next_hop),
+        };
+        match (mac, datagram.next_hop) {
+            (Some(mac), _) => {
+                self.data_buffer
+                    .map(|frame| frame[0..6].copy_from_slice(&mac));
+                datagram.resolved = true;
+            }
+            (None, Some(next_hop)) if datagram.arp_attempts == 0 => {
+                self.queue_arp(ArpOperation::Request, ETHERNET_BROADCAST, next_hop);
+                datagram.arp_attempts = 1;
+            }
+            (None, _) => {}
+        }
+        self.datagram.set(Some(datagram));
+    }
+
+    fn finish_datagram(&self, result: Result<(), ErrorCode>) {
+        if let Some(datagram) = self.datagram.take() {
+            self.sockets
+                .iter()
+                .find(|socket| socket.id() == datagram.socket)
+                .map(|socket| socket.sent(result));
+        }
+    }
+
+    /// Send the next frame, unless one is in flight. Control frames go
+    /// first.
+    fn transmit_pending(&self) {
+        if self.transmitting.get() {
+            return;
+        }
+        self.resolve_datagram();
+
+        let control_len = self.control_len.get();
+        if control_len != 0 {
+            if let Some(frame) = self.control_buffer.take() {
+                self.control_len.set(0);
+                self.transmit(frame, control_len, frame::CONTROL);
+                return;
+            }
+        }
+        if let Some(datagram) = self.datagram.get().filter(|datagram| datagram.resolved) {
+            if let Some(frame) = self.data_buffer.take() {
+                self.transmit(frame, datagram.len, frame::DATA);
+            }
+        }
+    }
+
+    fn transmit(&self, frame: &'static mut [u8], len: usize, id: usize) {
+        match self.adapter.transmit_frame(frame, len as u16, id) {
+            Ok(()) => self.transmitting.set(true),
+            Err((err, frame)) => {
+                // Control frames are dropped, their senders retransmit.
+                if id == frame::CONTROL {
+                    self.control_buffer.replace(frame);
+                } else {
+                    self.data_buffer.replace(frame);
+                    self.finish_datagram(Err(err));
+                }
+            }
+        }
+    }
+
+    fn receive_arp(&self, packet: &[u8]) {
+        let packet = match ArpPacket::decode(packet) {
+            Some(packet) => packet,
+            None => return,
+        };
+        let address = match self.config.get() {
+            Some(config) => config.address,
+            None => return,
+        };
+        if packet.sender_ip.is_unspecified() {
+            return;
+        }
+        if packet.target_ip == address {
+            self.arp.insert(packet.sender_ip, packet.sender_mac);
+            if packet.operation == ArpOperation::Request {
+                self.queue_arp(ArpOperation::Reply, packet.sender_mac, packet.sender_ip);
+            }
+        } else if self.arp.contains(packet.sender_ip) {
+            self.arp.insert(packet.sender_ip, packet.sender_mac);
+        }
+    }
+
+    fn receive_ipv4(&self, src_mac: EthernetAddr, packet: &[u8]) {
+        if packet.len() < IPV4_HDR_LEN || packet[0] >> 4 != 4 {
+            return;
+        }
+        let header_len = (packet[0] & 0xf) as usize * 4;
+        let total_len = u16::from_be_bytes([packet[2], packet[3]]) as usize;
+        if header_len < IPV4_HDR_LEN || total_len < header_len || total_len > packet.len() {
+            return;
+        }
+        if checksum_finish(checksum_add(0, &packet[..header_len])) != 0 {
+            return;
+        }
+        // Drop fragments: more fragments, or a fragment offset.
+        if u16::from_be_bytes([packet[6], packet[7]]) & 0x3fff != 0 {
+            return;
+        }
+
+        let mut src = Ipv4Addr::UNSPECIFIED;
+        let mut dst = Ipv4Addr::UNSPECIFIED;
+        src.0.copy_from_slice(&packet[12..16]);
+        dst.0.copy_from_slice(&packet[16..20]);
+        let payload = &packet[header_len..total_len];
+
+        let config = self.config.get();
+        let unicast = config.is_some_and(|config| dst == config.address);
+        let broadcast =
+            dst == Ipv4Addr::BROADCAST || config.is_some_and(|config| dst == config.broadcast());
+        match packet[9] {
+            ip4_proto::ICMP if unicast => self.receive_icmp(src_mac, src, dst, payload),
+            ip4_proto::UDP => self.receive_udp(src, dst, unicast || broadcast, payload),
+            _ => {}
+        }
+    }
+
+    fn receive_icmp(&self, src_mac: EthernetAddr, src: Ipv4Addr, dst: Ipv4Addr, message: &[u8]) {
+        if message.len() < 8 || message[0] != ICMP_ECHO_REQUEST || message[1] != 0 {
+            return;
+        }
+        if checksum_finish(checksum_add(0, message)) != 0 {
+            return;
+        }
+        self.queue_control(|frame| {
+            let len = ETHERNET_HDR_LEN + IPV4_HDR_LEN + message.len();
+            let reply = frame.get_mut(ETHERNET_HDR_LEN + IPV4_HDR_LEN..len)?;
+            reply.copy_from_slice(message);
+            reply[0] = ICMP_ECHO_REPLY;
+            reply[2..4].copy_from_slice(&[0, 0]);
+            let checksum = checksum_finish(checksum_add(0, reply));
+            reply[2..4].copy_from_slice(&checksum.to_be_bytes());
+
+            // Answer the MAC address the request came from, there is no
+            // need to resolve it.
+            self.encode_ethernet(frame, src_mac, ETHERTYPE_IPV4);
+            self.encode_ipv4(
+                &mut frame[ETHERNET_HDR_LEN..],
+                dst,
+                src,
+                ip4_proto::ICMP,
+                message.len(),
+            );
+            Some(len)
+        });
+    }
+
+    fn receive_udp(&self, src: Ipv4Addr, dst: Ipv4Addr, for_us: bool, datagram: &[u8]) {
+        if datagram.len() < UDP_HDR_LEN {
+            return;
+        }
+        let udp_len = u16::from_be_bytes([datagram[4], datagram[5]]) as usize;
+        if udp_len < UDP_HDR_LEN || udp_len > datagram.len() {
+            return;
+        }
+        let datagram = &datagram[..udp_len];
+        let has_checksum = datagram[6..8] != [0, 0];
+        if has_checksum && udp_checksum(src, dst, datagram) != 0 {
+            return;
+        }
+        let src_port = u16::from_be_bytes([datagram[0], datagram[1]]);
+        let dst_port = u16::from_be_bytes([datagram[2], datagram[3]]);
+        let payload = &datagram[UDP_HDR_LEN..];
+
+        // Replies of DHCP servers can be sent to the address being offered,
+        // which the interface does not have yet.
+        if dst_port == DHCP_CLIENT_PORT && self.dhcp.state() != DhcpState::Stopped {
+            if src_port == DHCP_SERVER_PORT {
+                self.handle_dhcp_action(self.dhcp.receive(payload));
+            }
+            return;
+        }
+        if !for_us {
+            return;
+        }
+        if let Some(socket) = self
+            .sockets
+            .iter()
+            .find(|socket| socket.local_port() == Some(dst_port))
+        {
+            socket.received(src, src_port, payload);
+        }
+    }
+}
+
+/// Checksum of a UDP datagram, including the IPv4 pseudo-header. This is 0
+/// for received datagrams with a valid checksum.
+fn udp_checksum(src: Ipv4Addr, dst: Ipv4Addr, datagram: &[u8]) -> u16 {
+    let sum = checksum_add(0, &src.0);
+    let sum = checksum_add(sum, &dst.0);
+    let sum = checksum_add(sum, &[0, ip4_proto::UDP]);
+    let sum = checksum_add(sum, &(datagram.len() as u16).to_be_bytes());
+    checksum_finish(checksum_add(sum, datagram))
+}
+
+impl<'a, A: time::Alarm<'a>> EthernetAdapterDatapathClient for Ipv4Stack<'a, A> {
+    fn transmit_frame_done(
+        &self,
+        err: Result<(), ErrorCode>,
+        frame_buffer: &'static mut [u8],
+        _len: u16,
+        transmission_identifier: usize,
+        _timestamp: Option<u64>,
+    ) {
+        self.transmitting.set(false);
+        if transmission_identifier == frame::CONTROL {
+            self.control_buffer.replace(frame_buffer);
+        } else {
+            self.data_buffer.replace(frame_buffer);
+            self.finish_datagram(err);
+        }
+        self.transmit_pending();
+    }
+
+    fn received_frame(&self, frame: &[u8], _timestamp: Option<u64>) {
+        if frame.len() < ETHERNET_HDR_LEN {
+            return;
+        }
+        if frame[0..6] != self.mac && frame[0..6] != ETHERNET_BROADCAST {
+            return;
+        }
+        let mut src_mac = [0; 6];
+        src_mac.copy_from_slice(&frame[6..12]);
+        let payload = &frame[ETHERNET_HDR_LEN..];
+        match u16::from_be_bytes([frame[12], frame[13]]) {
+            ETHERTYPE_ARP => self.receive_arp(payload),
+            ETHERTYPE_IPV4 => self.receive_ipv4(src_mac, payload),
+            _ => {}
+        }
+        self.transmit_pending();
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> time::AlarmClient for Ipv4Stack<'a, A> {
+    fn alarm(&self) {
+        self.arp.tick();
+
+        if let Some(mut datagram) = self.datagram.get() {
+            if let (false, Some(next_hop)) = (datagram.resolved, datagram.next_hop) {
+                if datagram.arp_attempts >= ARP_ATTEMPTS {
+                    self.finish_datagram(Err(ErrorCode::FAIL));
+                } else {
+                    self.queue_arp(ArpOperation::Request, ETHERNET_BROADCAST, next_hop);
+                    datagram.arp_attempts += 1;
+                    self.datagram.set(Some(datagram));
+                }
+            }
+        }
+
+        self.handle_dhcp_action(self.dhcp.tick());
+        self.transmit_pending();
+
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(TICK_MS));
+    }
+}
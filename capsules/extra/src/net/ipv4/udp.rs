@@ -0,0 +1,95 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! UDP sockets of the IPv4 stack.
+//!
+//! A socket receives the datagrams sent to the port it is bound to, and
+//! sends datagrams from that port. Sockets are bound, and send, through the
+//! [stack](crate::net::ipv4::stack::Ipv4Stack) they were added to.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let socket = static_init!(UdpSocket<'static>, UdpSocket::new(0));
+//! ipv4_stack.add_socket(socket);
+//! socket.set_client(client);
+//! ipv4_stack.bind(socket, 5683)?;
+//! ipv4_stack.send_to(socket, Ipv4Addr::new(192, 168, 1, 2), 5683, |buf| {
+//!     buf[..4].copy_from_slice(b"ping");
+//!     4
+//! })?;
+//! ```
+
+use core::cell::Cell;
+
+use crate::net::ipv4::Ipv4Addr;
+
+use kernel::collections::list::{ListLink, ListNode};
+use kernel::utilities::cells::OptionalCell;
+use kernel::ErrorCode;
+
+/// Length of a UDP header.
+pub const UDP_HDR_LEN: usize = 8;
+
+/// Client of a UDP socket. Every callback carries the id the socket was
+/// created with, so that one client can serve several sockets.
+pub trait UdpSocketClient {
+    /// A datagram was received from port `src_port` of `src_addr`.
+    fn received(&self, socket: usize, src_addr: Ipv4Addr, src_port: u16, payload: &[u8]);
+
+    /// The datagram passed to `send_to` was sent, or failed to be (for
+    /// example because the destination did not answer ARP requests).
+    fn sent(&self, socket: usize, result: Result<(), ErrorCode>);
+}
+
+pub struct UdpSocket<'a> {
+    id: usize,
+    /// Local port, or 0 while unbound.
+    port: Cell<u16>,
+    client: OptionalCell<&'a dyn UdpSocketClient>,
+    next: ListLink<'a, UdpSocket<'a>>,
+}
+
+impl<'a> ListNode<'a, UdpSocket<'a>> for UdpSocket<'a> {
+    fn next(&'a self) -> &'a ListLink<'a, UdpSocket<'a>> {
+        &self.next
+    }
+}
+
+impl<'a> UdpSocket<'a> {
+    pub fn new(id: usize) -> UdpSocket<'a> {
+        UdpSocket {
+            id,
+            port: Cell::new(0),
+            client: OptionalCell::empty(),
+            next: ListLink::empty(),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn UdpSocketClient) {
+        self.client.set(client);
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn local_port(&self) -> Option<u16> {
+        Some(self.port.get()).filter(|&port| port != 0)
+    }
+
+    pub(crate) fn set_port(&self, port: u16) {
+        self.port.set(port);
+    }
+
+    pub(crate) fn received(&self, src_addr: Ipv4Addr, src_port: u16, payload: &[u8]) {
+        self.client
+            .map(|client| client.received(self.id, src_addr, src_port, payload));
+    }
+
+    pub(crate) fn sent(&self, result: Result<(), ErrorCode>) {
+        self.client.map(|client| client.sent(self.id, result));
+    }
+}
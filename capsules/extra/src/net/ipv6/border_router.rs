@@ -0,0 +1,473 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! IPv6 border router between a 6LoWPAN mesh and an Ethernet link.
+//!
+//! The border router forwards IPv6 datagrams between the 802.15.4 interface,
+//! where it is a user of the virtual MAC with its own 6LoWPAN state, and an
+//! Ethernet adapter:
+//!
+//! - Datagrams received from the mesh whose destination is not in the mesh
+//!   prefix, link-local or multicast are sent on Ethernet, to the gateway if
+//!   one is set and otherwise to the destination itself. Other datagrams are
+//!   passed to the local client, such as the IPv6 receiver of the node.
+//! - Datagrams received from Ethernet whose destination is a unicast address
+//!   in the mesh prefix are sent in the mesh, to the MAC address in the
+//!   interface identifier of the destination.
+//!
+//! The hop limit of forwarded datagrams is decremented, and datagrams whose
+//! hop limit runs out are dropped.
+//!
+//! The border router also proxies neighbor discovery on Ethernet, so the
+//! hosts there reach the mesh without routes: it answers neighbor
+//! solicitations for the addresses in the mesh prefix with its own MAC
+//! address. It learns the MAC addresses of the Ethernet neighbors from the
+//! datagrams they send and their advertisements, and solicits the ones it
+//! does not know, in which case the datagram is dropped and the sender
+//! retransmits.
+//!
+//! One datagram is sent on each interface at a time, and datagrams that
+//! arrive while the interface is busy are dropped.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let border_router = components::border_router::BorderRouterComponent::new(
+//!     mux_mac,
+//!     mux_alarm,
+//!     src_mac_from_serial_num,
+//!     ethernet,
+//!     [0x02, 0x00, 0x00, 0x00, 0x00, 0x01],
+//!     [0xfd, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01],
+//! )
+//! .finalize(components::border_router_component_static!(
+//!     nrf52840::rtc::Rtc<'static>,
+//!     capsules_extra::ieee802154::framer::Framer<'static, AwakeMac, AesCcm>,
+//! ));
+//! ```
+
+use core::cell::Cell;
+
+use crate::ieee802154::device::{MacDevice, TxClient};
+use crate::net::ieee802154::MacAddress;
+use crate::net::ipv6::ip_utils::{compute_ipv6_ph_sum, compute_sum, ip6_nh, IPAddr};
+use crate::net::ipv6::IP6Header;
+use crate::net::sixlowpan::sixlowpan_state::{SixlowpanRxClient, TxState};
+use crate::net::thread::thread_utils::mac_from_ipv6;
+use kernel::hil::ethernet::{EthernetAdapterDatapath, EthernetAdapterDatapathClient};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+/// Number of Ethernet neighbors whose MAC address is kept.
+pub const NEIGHBOR_CACHE_LEN: usize = 8;
+
+const ETHERNET_HEADER_LEN: usize = 14;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const IP6_HEADER_LEN: usize = 40;
+
+// RFC 4861, section 4 Message Formats
+const NEIGHBOR_SOLICITATION: u8 = 135;
+const NEIGHBOR_ADVERTISEMENT: u8 = 136;
+const SOURCE_LINK_LAYER_ADDRESS: u8 = 1;
+const TARGET_LINK_LAYER_ADDRESS: u8 = 2;
+const ADVERTISEMENT_ROUTER: u8 = 0x80;
+const ADVERTISEMENT_SOLICITED: u8 = 0x40;
+/// Length of a solicitation or an advertisement with a link-layer address.
+const NEIGHBOR_MESSAGE_LEN: usize = 32;
+
+#[derive(Clone, Copy)]
+struct Neighbor {
+    addr: IPAddr,
+    mac: [u8; 6],
+}
+
+pub struct BorderRouter<'a> {
+    mac: &'a dyn MacDevice<'a>,
+    sixlowpan: TxState<'a>,
+    src_mac_addr: MacAddress,
+    ethernet: &'a dyn EthernetAdapterDatapath<'a>,
+    ethernet_mac: [u8; 6],
+    /// /64 prefix of the mesh.
+    prefix: [u8; 8],
+    gateway: Cell<Option<IPAddr>>,
+    neighbors: Cell<[Option<Neighbor>; NEIGHBOR_CACHE_LEN]>,
+    next_neighbor: Cell<usize>,
+    local_client: OptionalCell<&'a dyn SixlowpanRxClient>,
+    /// Datagram being sent in the mesh.
+    datagram: TakeCell<'static, [u8]>,
+    datagram_len: Cell<usize>,
+    sending: Cell<bool>,
+    frag_buf: TakeCell<'static, [u8]>,
+    ethernet_buf: TakeCell<'static, [u8]>,
+}
+
+impl<'a> BorderRouter<'a> {
+    pub fn new(
+        mac: &'a dyn MacDevice<'a>,
+        sixlowpan: TxState<'a>,
+        src_mac_addr: MacAddress,
+        ethernet: &'a dyn EthernetAdapterDatapath<'a>,
+        ethernet_mac: [u8; 6],
+        prefix: [u8; 8],
+        datagram: &'static mut [u8],
+        frag_buf: &'static mut [u8],
+        ethernet_buf: &'static mut [u8],
+    ) -> BorderRouter<'a> {
+        BorderRouter {
+            mac,
+            sixlowpan,
+            src_mac_addr,
+            ethernet,
+            ethernet_mac,
+            prefix,
+            gateway: Cell::new(None),
+            neighbors: Cell::new([None; NEIGHBOR_CACHE_LEN]),
+            next_neighbor: Cell::new(0),
+            local_client: OptionalCell::empty(),
+            datagram: TakeCell::new(datagram),
+            datagram_len: Cell::new(0),
+            sending: Cell::new(false),
+            frag_buf: TakeCell::new(frag_buf),
+            ethernet_buf: TakeCell::new(ethernet_buf),
+        }
+    }
+
+    /// Set the client that receives the datagrams from the mesh that are not
+    /// forwarded.
+    pub fn set_local_client(&self, client: &'a dyn SixlowpanRxClient) {
+        self.local_client.set(client);
+    }
+
+    /// Send the datagrams that leave the mesh to the router at `gateway` on
+    /// Ethernet, or to their destinations if it is `None`.
+    pub fn set_gateway(&self, gateway: Option<IPAddr>) {
+        self.gateway.set(gateway);
+    }
+
+    fn in_mesh(&self, addr: &IPAddr) -> bool {
+        addr.0[..8] == self.prefix
+    }
+
+    /// Link-local address of the router on Ethernet, from its MAC address.
+    fn link_local(&self) -> IPAddr {
+        let mac = self.ethernet_mac;
+        let mut addr = IPAddr::new();
+        addr.set_unicast_link_local();
+        addr.0[8..].copy_from_slice(&[
+            mac[0] ^ 0x02,
+            mac[1],
+            mac[2],
+            0xff,
+            0xfe,
+            mac[3],
+            mac[4],
+            mac[5],
+        ]);
+        addr
+    }
+
+    fn learn(&self, addr: IPAddr, mac: [u8; 6]) {
+        if addr.is_unspecified() || addr.is_multicast() || mac[0] & 0x01 != 0 {
+            return;
+        }
+        let mut neighbors = self.neighbors.get();
+        let slot = match neighbors
+            .iter()
+            .position(|neighbor| neighbor.is_some_and(|neighbor| neighbor.addr == addr))
+        {
+            Some(slot) => slot,
+            None => {
+                let slot = self.next_neighbor.get();
+                self.next_neighbor.set((slot + 1) % NEIGHBOR_CACHE_LEN);
+                slot
+            }
+        };
+        neighbors[slot] = Some(Neighbor { addr, mac });
+        self.neighbors.set(neighbors);
+    }
+
+    fn lookup(&self, addr: IPAddr) -> Option<[u8; 6]> {
+        self.neighbors
+            .get()
+            .iter()
+            .flatten()
+            .find(|neighbor| neighbor.addr == addr)
+            .map(|neighbor| neighbor.mac)
+    }
+
+    /// Send an Ethernet frame to `dst_mac` whose IPv6 payload of `len` bytes
+    /// is written by `fill`.
+    fn send_ethernet(&self, dst_mac: [u8; 6], len: usize, fill: impl FnOnce(&mut [u8])) {
+        let Some(buf) = self.ethernet_buf.take() else {
+            return;
+        };
+        if ETHERNET_HEADER_LEN + len > buf.len() {
+            self.ethernet_buf.replace(buf);
+            return;
+        }
+        buf[0..6].copy_from_slice(&dst_mac);
+        buf[6..12].copy_from_slice(&self.ethernet_mac);
+        buf[12..14].copy_from_slice(&ETHERTYPE_IPV6.to_be_bytes());
+        fill(&mut buf[ETHERNET_HEADER_LEN..ETHERNET_HEADER_LEN + len]);
+        if let Err((_, buf)) =
+            self.ethernet
+                .transmit_frame(buf, (ETHERNET_HEADER_LEN + len) as u16, 0)
+        {
+            self.ethernet_buf.replace(buf);
+        }
+    }
+
+    /// Send a neighbor discovery message of `icmp_type` to `dst`, with the
+    /// MAC address of the router as the link-layer address `option`.
+    fn send_neighbor_message(
+        &self,
+        dst: IPAddr,
+        dst_mac: [u8; 6],
+        icmp_type: u8,
+        flags: u8,
+        target: IPAddr,
+        option: u8,
+    ) {
+        let mut header = IP6Header::new();
+        header.src_addr = self.link_local();
+        header.dst_addr = dst;
+        header.set_next_header(ip6_nh::ICMP);
+        header.set_hop_limit(255);
+        header.set_payload_len(NEIGHBOR_MESSAGE_LEN as u16);
+
+        self.send_ethernet(dst_mac, IP6_HEADER_LEN + NEIGHBOR_MESSAGE_LEN, |buf| {
+            let _ = header.encode(buf);
+            let message = &mut buf[IP6_HEADER_LEN..];
+            message.fill(0);
+            message[0] = icmp_type;
+            message[4] = flags;
+            message[8..24].copy_from_slice(&target.0);
+            message[24] = option;
+            message[25] = 1;
+            message[26..32].copy_from_slice(&self.ethernet_mac);
+
+            let mut sum =
+                compute_ipv6_ph_sum(&header) + compute_sum(message, NEIGHBOR_MESSAGE_LEN as u16);
+            while sum > 0xffff {
+                sum = (sum >> 16) + (sum & 0xffff);
+            }
+            message[2..4].copy_from_slice(&(!(sum as u16)).to_be_bytes());
+        });
+    }
+
+    /// Ask the Ethernet neighbor at `target` for its MAC address.
+    fn solicit(&self, target: IPAddr) {
+        // Solicited-node multicast address.
+        let mut dst = IPAddr([0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01, 0xff, 0, 0, 0]);
+        dst.0[13..].copy_from_slice(&target.0[13..]);
+        let dst_mac = [0x33, 0x33, 0xff, target.0[13], target.0[14], target.0[15]];
+        self.send_neighbor_message(
+            dst,
+            dst_mac,
+            NEIGHBOR_SOLICITATION,
+            0,
+            target,
+            SOURCE_LINK_LAYER_ADDRESS,
+        );
+    }
+
+    fn forward_to_ethernet(&self, datagram: &[u8]) {
+        let hop_limit = datagram[7];
+        if hop_limit <= 1 {
+            return;
+        }
+        let mut dst = IPAddr::new();
+        dst.0.copy_from_slice(&datagram[24..40]);
+        let next_hop = self.gateway.get().unwrap_or(dst);
+        match self.lookup(next_hop) {
+            Some(dst_mac) => self.send_ethernet(dst_mac, datagram.len(), |buf| {
+                buf.copy_from_slice(datagram);
+                buf[7] = hop_limit - 1;
+            }),
+            None => self.solicit(next_hop),
+        }
+    }
+
+    fn forward_to_mesh(&self, datagram: &[u8], dst: IPAddr) {
+        let hop_limit = datagram[7];
+        if hop_limit <= 1 || self.sending.get() {
+            return;
+        }
+        let copied = self.datagram.map_or(false, |buf| {
+            if datagram.len() > buf.len() {
+                return false;
+            }
+            buf[..datagram.len()].copy_from_slice(datagram);
+            buf[7] = hop_limit - 1;
+            true
+        });
+        if !copied
+            || self
+                .sixlowpan
+                .init(
+                    self.src_mac_addr,
+                    MacAddress::Long(mac_from_ipv6(dst)),
+                    self.mac.get_pan(),
+                    None,
+                )
+                .is_err()
+        {
+            return;
+        }
+        self.datagram_len.set(datagram.len());
+        self.sending.set(true);
+        self.send_next_fragment();
+    }
+
+    fn send_next_fragment(&self) {
+        let Some(frag_buf) = self.frag_buf.take() else {
+            return;
+        };
+        let result = self.datagram.map(|datagram| {
+            self.sixlowpan.next_datagram_fragment(
+                &datagram[..self.datagram_len.get()],
+                frag_buf,
+                self.mac,
+            )
+        });
+        match result {
+            Some(Ok((false, frame))) => {
+                if let Err((_, buf)) = self.mac.transmit(frame) {
+                    self.frag_buf.replace(buf);
+                    self.end_send(Err(ErrorCode::FAIL));
+                }
+            }
+            Some(Ok((true, frame))) => {
+                self.frag_buf.replace(frame.into_buf());
+                self.end_send(Ok(()));
+            }
+            Some(Err((result, buf))) => {
+                self.frag_buf.replace(buf);
+                self.end_send(result);
+            }
+            None => {}
+        }
+    }
+
+    fn end_send(&self, result: Result<(), ErrorCode>) {
+        if result.is_err() {
+            self.sixlowpan.abort();
+        }
+        self.sending.set(false);
+    }
+}
+
+impl SixlowpanRxClient for BorderRouter<'_> {
+    fn receive(&self, buf: &[u8], len: usize, result: Result<(), ErrorCode>) {
+        if result.is_ok() && len <= buf.len() {
+            if let Some((_, header)) = IP6Header::decode(&buf[..len]).done() {
+                let dst = header.get_dst_addr();
+                if !dst.is_unspecified()
+                    && !dst.is_multicast()
+                    && !dst.is_unicast_link_local()
+                    && !self.in_mesh(&dst)
+                {
+                    self.forward_to_ethernet(&buf[..len]);
+                    return;
+                }
+            }
+        }
+        self.local_client
+            .map(|client| client.receive(buf, len, result));
+    }
+}
+
+impl TxClient for BorderRouter<'_> {
+    fn send_done(&self, spi_buf: &'static mut [u8], _acked: bool, result: Result<(), ErrorCode>) {
+        self.frag_buf.replace(spi_buf);
+        if result.is_err() {
+            self.end_send(result);
+        } else {
+            self.send_next_fragment();
+        }
+    }
+}
+
+impl EthernetAdapterDatapathClient for BorderRouter<'_> {
+    fn transmit_frame_done(
+        &self,
+        _err: Result<(), ErrorCode>,
+        frame_buffer: &'static mut [u8],
+        _len: u16,
+        _transmission_identifier: usize,
+        _timestamp: Option<u64>,
+    ) {
+        self.ethernet_buf.replace(frame_buffer);
+    }
+
+    fn received_frame(&self, frame: &[u8], _timestamp: Option<u64>) {
+        if frame.len() < ETHERNET_HEADER_LEN + IP6_HEADER_LEN
+            || u16::from_be_bytes([frame[12], frame[13]]) != ETHERTYPE_IPV6
+            || (frame[0] & 0x01 == 0 && frame[0..6] != self.ethernet_mac)
+        {
+            return;
+        }
+        let mut src_mac = [0; 6];
+        src_mac.copy_from_slice(&frame[6..12]);
+        let Some((_, header)) = IP6Header::decode(&frame[ETHERNET_HEADER_LEN..]).done() else {
+            return;
+        };
+        let len = IP6_HEADER_LEN + header.get_payload_len() as usize;
+        let Some(datagram) = frame.get(ETHERNET_HEADER_LEN..ETHERNET_HEADER_LEN + len) else {
+            return;
+        };
+        let src = header.get_src_addr();
+        let dst = header.get_dst_addr();
+        self.learn(src, src_mac);
+
+        if header.get_next_header() == ip6_nh::ICMP && len >= IP6_HEADER_LEN + 24 {
+            let message = &datagram[IP6_HEADER_LEN..];
+            let mut target = IPAddr::new();
+            target.0.copy_from_slice(&message[8..24]);
+            match message[0] {
+                NEIGHBOR_SOLICITATION => {
+                    if self.in_mesh(&target) || target == self.link_local() {
+                        let (dst, dst_mac, flags) = if src.is_unspecified() {
+                            // All nodes.
+                            let mut all_nodes = IPAddr::new();
+                            all_nodes.0[0] = 0xff;
+                            all_nodes.0[1] = 0x02;
+                            all_nodes.0[15] = 0x01;
+                            (all_nodes, [0x33, 0x33, 0, 0, 0, 0x01], ADVERTISEMENT_ROUTER)
+                        } else {
+                            (src, src_mac, ADVERTISEMENT_ROUTER | ADVERTISEMENT_SOLICITED)
+                        };
+                        self.send_neighbor_message(
+                            dst,
+                            dst_mac,
+                            NEIGHBOR_ADVERTISEMENT,
+                            flags,
+                            target,
+                            TARGET_LINK_LAYER_ADDRESS,
+                        );
+                    }
+                    return;
+                }
+                NEIGHBOR_ADVERTISEMENT => {
+                    let mac = message
+                        .get(24..32)
+                        .filter(|option| option[0] == TARGET_LINK_LAYER_ADDRESS && option[1] == 1)
+                        .map_or(src_mac, |option| {
+                            let mut mac = [0; 6];
+                            mac.copy_from_slice(&option[2..8]);
+                            mac
+                        });
+                    self.learn(target, mac);
+                    return;
+                }
+                _ => {}
+            }
+        }
+
+        if self.in_mesh(&dst) && !dst.is_multicast() {
+            self.forward_to_mesh(datagram, dst);
+        }
+    }
+}
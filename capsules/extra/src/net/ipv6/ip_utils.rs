@@ -186,6 +186,39 @@ pub fn compute_udp_checksum(
     sum as u16 //Return result as u16 in host byte order */
 }
 
+/// Computes the checksum of a TCP segment, consisting of an encoded TCP header
+/// `header` (including any options) and `payload`, sent with the given IPv6
+/// header (RFC 9293, section 3.1).
+///
+/// The checksum field in `header` is included in the sum, so for a segment
+/// with a correct checksum this returns 0. The result is in host byte order.
+pub fn compute_tcp_checksum(ip6_header: &IP6Header, header: &[u8], payload: &[u8]) -> u16 {
+    let tcp_length = (header.len() + payload.len()) as u32;
+    let mut sum: u32 = 0;
+
+    // Pseudo-header: addresses, upper-layer packet length and next header
+    for i in (0..16).step_by(2) {
+        sum += (ip6_header.src_addr.0[i] as u32) << 8 | ip6_header.src_addr.0[i + 1] as u32;
+        sum += (ip6_header.dst_addr.0[i] as u32) << 8 | ip6_header.dst_addr.0[i + 1] as u32;
+    }
+    sum += tcp_length >> 16;
+    sum += tcp_length & 0xffff;
+    sum += ip6_nh::TCP as u32;
+
+    // Headers are always a multiple of four bytes long, so the payload starts
+    // on a 16 bit boundary
+    for chunk in header.chunks(2).chain(payload.chunks(2)) {
+        let msb = (chunk[0] as u32) << 8;
+        let lsb = chunk.get(1).map_or(0, |b| *b as u32);
+        sum += msb + lsb;
+    }
+
+    while sum > 0xffff {
+        sum = (sum >> 16) + (sum & 0xffff);
+    }
+    !sum as u16
+}
+
 pub fn compute_icmp_checksum(
     ipv6_header: &IP6Header,
     icmp_header: &ICMP6Header,
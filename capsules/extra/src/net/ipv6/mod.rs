@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 // Copyright Tock Contributors 2022.
 
+pub mod border_router;
 pub mod ip_utils;
 pub mod ipv6_recv;
 pub mod ipv6_send;
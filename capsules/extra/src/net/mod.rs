@@ -2,7 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 // Copyright Tock Contributors 2022.
 
-//! Modules for IPv6 over 6LoWPAN stack
+//! Modules for the IPv6 over 6LoWPAN and IPv4 over Ethernet stacks
 
 pub mod frag_utils;
 pub mod sixlowpan;
@@ -11,6 +11,7 @@ pub mod util;
 pub mod stream;
 pub mod icmpv6;
 pub mod ieee802154;
+pub mod ipv4;
 pub mod ipv6;
 pub mod network_capabilities;
 pub mod tcp;
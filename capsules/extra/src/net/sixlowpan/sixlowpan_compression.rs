@@ -194,11 +194,68 @@ pub fn compress<'a>(
     src_mac_addr: MacAddress,
     dst_mac_addr: MacAddress,
     buf: &mut [u8],
+) -> Result<(usize, usize), ()> {
+    let udp_header = match ip6_packet.payload.header {
+        TransportHeader::UDP(udp_header) => Some(udp_header),
+        _ => None,
+    };
+    // Return an error, as there is a conflict between IPv6 next
+    // header and actual IPv6 payload
+    if ip6_packet.header.next_header == ip6_nh::UDP && udp_header.is_none() {
+        return Err(());
+    }
+    compress_header(
+        ctx_store,
+        &ip6_packet.header,
+        udp_header,
+        src_mac_addr,
+        dst_mac_addr,
+        buf,
+    )
+}
+
+/// Compresses the IPv6 header of a serialized IPv6 datagram into a 6loWPAN
+/// header
+///
+/// This is the same as `compress`, for datagrams that are not built from an
+/// `IP6Packet`, such as the ones a router forwards. The UDP header is
+/// compressed if the datagram carries UDP, and the other next headers are
+/// not.
+pub fn compress_datagram(
+    ctx_store: &dyn ContextStore,
+    datagram: &[u8],
+    src_mac_addr: MacAddress,
+    dst_mac_addr: MacAddress,
+    buf: &mut [u8],
+) -> Result<(usize, usize), ()> {
+    let (offset, ip6_header) = IP6Header::decode(datagram).done().ok_or(())?;
+    let udp_header = if ip6_header.next_header == ip6_nh::UDP {
+        let (_, udp_header) = UDPHeader::decode(&datagram[offset..]).done().ok_or(())?;
+        Some(udp_header)
+    } else {
+        None
+    };
+    compress_header(
+        ctx_store,
+        &ip6_header,
+        udp_header,
+        src_mac_addr,
+        dst_mac_addr,
+        buf,
+    )
+}
+
+fn compress_header(
+    ctx_store: &dyn ContextStore,
+    ip6_header: &IP6Header,
+    udp_header: Option<UDPHeader>,
+    src_mac_addr: MacAddress,
+    dst_mac_addr: MacAddress,
+    buf: &mut [u8],
 ) -> Result<(usize, usize), ()> {
     // Note that consumed should be constant, and equal sizeof(IP6Header)
-    //let (mut consumed, ip6_header) = IP6Header::decode(ip6_datagram).done().ok_or(())?;
     let mut consumed = 40; // TODO
-    let ip6_header = ip6_packet.header;
+    let ip6_header = *ip6_header;
 
     //let mut next_headers: &[u8] = &ip6_datagram[consumed..];
 
@@ -235,7 +292,7 @@ pub fn compress<'a>(
     // Next Header
 
     //let (mut is_nhc, mut nh_len): (bool, u8) = is_ip6_nh_compressible(ip6_packet)?;
-    let is_nhc = ip6_header.next_header == ip6_nh::UDP;
+    let is_nhc = ip6_header.next_header == ip6_nh::UDP && udp_header.is_some();
     compress_nh(&ip6_header, is_nhc, buf, &mut written);
 
     // Hop Limit
@@ -267,27 +324,20 @@ pub fn compress<'a>(
     // At each iteration, next_headers begins at the first byte of the
     // current uncompressed next header.
     // Since we aren't recursing, we only handle UDP
-    if is_nhc {
-        match ip6_packet.payload.header {
-            TransportHeader::UDP(udp_header) => {
-                let mut nhc_header = nhc::DISPATCH_UDP;
+    if let Some(udp_header) = udp_header.filter(|_| is_nhc) {
+        let mut nhc_header = nhc::DISPATCH_UDP;
 
-                // Leave a space for the UDP LoWPAN_NHC byte
-                let udp_nh_offset = written;
-                written += 1;
+        // Leave a space for the UDP LoWPAN_NHC byte
+        let udp_nh_offset = written;
+        written += 1;
 
-                // Compress ports and checksum
-                nhc_header |= compress_udp_ports(&udp_header, buf, &mut written);
-                nhc_header |= compress_udp_checksum(&udp_header, buf, &mut written);
+        // Compress ports and checksum
+        nhc_header |= compress_udp_ports(&udp_header, buf, &mut written);
+        nhc_header |= compress_udp_checksum(&udp_header, buf, &mut written);
 
-                // Write the UDP LoWPAN_NHC byte
-                buf[udp_nh_offset] = nhc_header;
-                consumed += 8;
-            }
-            // Return an error, as there is a conflict between IPv6 next
-            // header and actual IPv6 payload
-            _ => return Err(()),
-        }
+        // Write the UDP LoWPAN_NHC byte
+        buf[udp_nh_offset] = nhc_header;
+        consumed += 8;
     }
     Ok((consumed, written))
 }
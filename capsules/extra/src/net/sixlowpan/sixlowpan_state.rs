@@ -247,6 +247,7 @@ use kernel::hil::radio;
 use kernel::hil::time;
 use kernel::hil::time::{Frequency, Ticks};
 use kernel::utilities::cells::{MapCell, TakeCell};
+use kernel::utilities::poison::{PoisonCell, Poisonable};
 use kernel::ErrorCode;
 
 // Reassembly timeout in seconds
@@ -456,6 +457,87 @@ impl<'a> TxState<'a> {
         }
     }
 
+    /// Gets the next 6LoWPAN fragment of an IPv6 `datagram` that is already
+    /// serialized, such as one that is forwarded. This works like
+    /// `next_fragment`, and the whole `datagram` is sent.
+    pub fn next_datagram_fragment(
+        &self,
+        datagram: &[u8],
+        frag_buf: &'static mut [u8],
+        radio: &dyn MacDevice,
+    ) -> Result<(bool, Frame), (Result<(), ErrorCode>, &'static mut [u8])> {
+        let mut frame = radio
+            .prepare_data_frame(
+                frag_buf,
+                self.dst_pan.get(),
+                self.dst_mac_addr.get(),
+                self.src_pan.get(),
+                self.src_mac_addr.get(),
+                self.security.get(),
+            )
+            .map_err(|frame| (Err(ErrorCode::FAIL), frame))?;
+
+        let (start, remaining_capacity) = if !self.busy.get() {
+            self.busy.set(true);
+            self.dgram_size.set(datagram.len() as u16);
+            self.dgram_tag.set(self.sixlowpan.next_dgram_tag());
+
+            let mut lowpan_packet = [0_u8; radio::MAX_FRAME_SIZE];
+            let (consumed, written) = match sixlowpan_compression::compress_datagram(
+                self.sixlowpan.get_ctx_store(),
+                datagram,
+                self.src_mac_addr.get(),
+                self.dst_mac_addr.get(),
+                &mut lowpan_packet,
+            ) {
+                Err(()) => {
+                    self.end_transmit();
+                    return Err((Err(ErrorCode::FAIL), frame.into_buf()));
+                }
+                Ok(result) => result,
+            };
+
+            // TODO: This -2 is added to account for the FCS; this should be
+            // changed in the MAC code
+            let mut remaining_capacity = frame.remaining_data_capacity() - 2;
+            if written + datagram.len() - consumed > remaining_capacity {
+                remaining_capacity -= self.write_frag_hdr(&mut frame, true);
+            }
+            if written > remaining_capacity {
+                self.end_transmit();
+                return Err((Err(ErrorCode::SIZE), frame.into_buf()));
+            }
+            let _ = frame.append_payload(&lowpan_packet[0..written]);
+            (consumed, remaining_capacity - written)
+        } else if self.is_transmit_done() {
+            self.end_transmit();
+            return Ok((true, frame));
+        } else {
+            if self.dgram_size.get() as usize != datagram.len() {
+                return Err((Err(ErrorCode::NOMEM), frame.into_buf()));
+            }
+            let remaining_capacity =
+                frame.remaining_data_capacity() - self.write_frag_hdr(&mut frame, false);
+            (self.dgram_offset.get(), remaining_capacity)
+        };
+
+        // Round down to a multiple of 8 if this is not the last fragment
+        let remaining_payload = datagram.len() - start;
+        let payload_len = if remaining_payload > remaining_capacity {
+            remaining_capacity & !0b111
+        } else {
+            remaining_payload
+        };
+        let _ = frame.append_payload(&datagram[start..start + payload_len]);
+        self.dgram_offset.set(start + payload_len);
+        Ok((false, frame))
+    }
+
+    /// Stops sending the current packet, after a fragment could not be sent.
+    pub fn abort(&self) {
+        self.end_transmit();
+    }
+
     fn is_transmit_done(&self) -> bool {
         self.dgram_size.get() as usize <= self.dgram_offset.get()
     }
@@ -695,7 +777,7 @@ impl<'a> RxState<'a> {
     fn is_busy(&self, frequency: u32, current_time: u32) -> bool {
         let expired = current_time >= (self.start_time.get() + FRAG_TIMEOUT * frequency);
         if expired {
-            self.end_receive(None, Err(ErrorCode::FAIL));
+            let _ = self.end_receive(None, Err(ErrorCode::FAIL));
         }
         self.busy.get()
     }
@@ -763,15 +845,16 @@ impl<'a> RxState<'a> {
         }
     }
 
+    /// Returns `Err(ErrorCode::FAIL)` if the packet is missing.
     fn end_receive(
         &self,
         client: Option<&'a dyn SixlowpanRxClient>,
         result: Result<(), ErrorCode>,
-    ) {
+    ) -> Result<(), ErrorCode> {
         self.busy.set(false);
         self.bitmap.map(|bitmap| bitmap.clear());
         self.start_time.set(0);
-        client.map(move |client| {
+        client.map_or(Ok(()), move |client| {
             // Since packet is borrowed from the upper layer, failing to return it
             // in the callback represents a significant error that should never
             // occur - all other calls to `packet.take()` replace the packet,
@@ -780,8 +863,8 @@ impl<'a> RxState<'a> {
                 .map(|packet| {
                     client.receive(packet, self.dgram_size.get() as usize, result);
                 })
-                .unwrap(); // Unwrap fail = Error: `packet` is None in call to end_receive.
-        });
+                .ok_or(ErrorCode::FAIL)
+        })
     }
 }
 
@@ -805,6 +888,10 @@ pub struct Sixlowpan<'a, A: time::Alarm<'a>, C: ContextStore> {
 
     // Receive state
     rx_states: List<'a, RxState<'a>>,
+
+    /// Set if an `RxState` lost its packet buffer, after which frames are
+    /// dropped.
+    poison: PoisonCell,
 }
 
 // This function is called after receiving a frame
@@ -817,6 +904,10 @@ impl<'a, A: time::Alarm<'a>, C: ContextStore> RxClient for Sixlowpan<'a, A, C> {
         data_offset: usize,
         data_len: usize,
     ) {
+        if self.poison.is_poisoned() {
+            return;
+        }
+
         // We return if retcode is not valid, as it does not make sense to issue
         // a callback for an invalid frame reception
         // TODO: Handle the case where the addresses are None/elided - they
@@ -832,7 +923,18 @@ impl<'a, A: time::Alarm<'a>, C: ContextStore> RxClient for Sixlowpan<'a, A, C> {
         );
         // Reception completed if rx_state is not None. Note that this can
         // also occur for some fail states (e.g. dropping an invalid packet)
-        rx_state.map(|state| state.end_receive(self.rx_client.get(), returncode));
+        if let Some(state) = rx_state {
+            if state.end_receive(self.rx_client.get(), returncode).is_err() {
+                self.poison
+                    .poison("received packet without a packet buffer");
+            }
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, C: ContextStore> Poisonable for Sixlowpan<'a, A, C> {
+    fn poison_cell(&self) -> &PoisonCell {
+        &self.poison
     }
 }
 
@@ -889,6 +991,7 @@ impl<'a, A: time::Alarm<'a>, C: ContextStore> Sixlowpan<'a, A, C> {
             rx_client: Cell::new(None),
 
             rx_states: List::new(),
+            poison: PoisonCell::new("sixlowpan"),
         }
     }
 
@@ -942,7 +1045,11 @@ impl<'a, A: time::Alarm<'a>, C: ContextStore> Sixlowpan<'a, A, C> {
             // The packet buffer should *always* be there; in particular,
             // since this state is not busy, it must have the packet buffer.
             // Otherwise, we are in an inconsistent state and can fail.
-            let packet = state.packet.take().unwrap();
+            let Some(packet) = state.packet.take() else {
+                self.poison
+                    .poison("idle receive state without a packet buffer");
+                return (None, Err(ErrorCode::FAIL));
+            };
 
             // Filter non 6LoWPAN packets and return
             if !is_lowpan(payload) {
@@ -1045,7 +1152,7 @@ impl<'a, A: time::Alarm<'a>, C: ContextStore> Sixlowpan<'a, A, C> {
     // to expire all pending state.
     fn discard_all_state(&self) {
         for rx_state in self.rx_states.iter() {
-            rx_state.end_receive(None, Err(ErrorCode::FAIL));
+            let _ = rx_state.end_receive(None, Err(ErrorCode::FAIL));
         }
         unimplemented!();
         // TODO: Need to get buffer back from Mac layer on disassociation
@@ -0,0 +1,336 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! TCP userspace interface.
+//!
+//! Gives processes access to a fixed pool of kernel TCP sockets. A process
+//! claims a socket from the pool by listening or connecting, and keeps it
+//! until it releases it (command 6) or exits. Each process can hold one
+//! socket at a time.
+//!
+//! When an accepted connection completes its handshake, the address and
+//! port of the peer are written into the config buffer, in the same format
+//! that is used to pass the destination to `connect`.
+
+use crate::net::ipv6::ip_utils::IPAddr;
+use crate::net::tcp::tcp_socket::{TcpClient, TcpSocket};
+use crate::net::util::host_slice_to_u16;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::time;
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::OptionalCell;
+use kernel::{ErrorCode, ProcessId};
+
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::Tcp as usize;
+
+/// Length of an endpoint in the config buffer: a 16 byte address followed by
+/// the port in host byte order.
+const ENDPOINT_LEN: usize = 18;
+
+/// IDs for subscribed upcalls.
+mod upcall {
+    /// The connection was established, or failed to be. The first argument
+    /// is the result as a `StatusCode`.
+    pub const CONNECTED: usize = 0;
+    /// Data was received. The first argument is the number of bytes that can
+    /// be read.
+    pub const RECEIVED: usize = 1;
+    /// Data was acknowledged by the peer. The first argument is the number of
+    /// bytes that were freed in the transmit buffer.
+    pub const SENT: usize = 2;
+    /// The connection closed. The first argument is the result as a
+    /// `StatusCode`: success for an orderly close, and an error if the
+    /// connection was reset or timed out.
+    pub const CLOSED: usize = 3;
+    /// Number of upcalls.
+    pub const COUNT: u8 = 4;
+}
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    /// Write buffer. Contains the data to send.
+    pub const WRITE: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// Read buffer. Received data is copied into it.
+    pub const READ: usize = 0;
+    /// Config buffer. Contains the endpoint to connect to, and the endpoint
+    /// of the peer of accepted connections.
+    pub const CFG: usize = 1;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 2;
+}
+
+#[derive(Default)]
+pub struct App;
+
+pub struct TcpDriver<'a, A: time::Alarm<'a>, const NUM_SOCKETS: usize> {
+    sockets: &'a [TcpSocket<'a, A>; NUM_SOCKETS],
+    /// Process that holds each of the sockets.
+    owners: [OptionalCell<ProcessId>; NUM_SOCKETS],
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+}
+
+impl<'a, A: time::Alarm<'a>, const NUM_SOCKETS: usize> TcpDriver<'a, A, NUM_SOCKETS> {
+    /// Sockets must be created with their index in `sockets` as their id.
+    pub fn new(
+        sockets: &'a [TcpSocket<'a, A>; NUM_SOCKETS],
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+    ) -> TcpDriver<'a, A, NUM_SOCKETS> {
+        TcpDriver {
+            sockets,
+            owners: core::array::from_fn(|_| OptionalCell::empty()),
+            apps: grant,
+        }
+    }
+
+    /// Index of the socket held by `processid`.
+    fn socket_of(&self, processid: ProcessId) -> Option<usize> {
+        self.owners
+            .iter()
+            .position(|owner| owner.contains(&processid))
+    }
+
+    /// Return the socket held by `processid`, or claim a free one.
+    fn claim_socket(&self, processid: ProcessId) -> Result<usize, ErrorCode> {
+        if let Some(index) = self.socket_of(processid) {
+            return Ok(index);
+        }
+        // Sockets of processes that no longer exist are free as well.
+        let index = self
+            .owners
+            .iter()
+            .position(|owner| {
+                owner.map_or(true, |owner| self.apps.enter(owner, |_, _| {}).is_err())
+            })
+            .ok_or(ErrorCode::NOMEM)?;
+        self.sockets[index].abort();
+        self.owners[index].set(processid);
+        Ok(index)
+    }
+
+    fn release_socket(&self, index: usize) {
+        self.sockets[index].abort();
+        self.owners[index].clear();
+    }
+
+    fn parse_endpoint(buf: &[u8; ENDPOINT_LEN]) -> (IPAddr, u16) {
+        let mut addr = IPAddr::new();
+        addr.0.copy_from_slice(&buf[0..16]);
+        (addr, host_slice_to_u16(&buf[16..ENDPOINT_LEN]))
+    }
+
+    fn schedule_upcall(&self, socket: usize, upcall: usize, data: usize) {
+        if let Some(owner) = self.owners.get(socket).and_then(|owner| owner.get()) {
+            let _ = self.apps.enter(owner, |_, kernel_data| {
+                kernel_data.schedule_upcall(upcall, (data, 0, 0)).ok();
+            });
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, const NUM_SOCKETS: usize> TcpClient for TcpDriver<'a, A, NUM_SOCKETS> {
+    fn connected(&self, socket: usize, result: Result<(), ErrorCode>) {
+        if result.is_ok() {
+            let (addr, port) = self.sockets[socket].remote_endpoint();
+            if let Some(owner) = self.owners[socket].get() {
+                let _ = self.apps.enter(owner, |_, kernel_data| {
+                    kernel_data
+                        .get_readwrite_processbuffer(rw_allow::CFG)
+                        .and_then(|cfg| {
+                            cfg.mut_enter(|cfg| {
+                                if cfg.len() >= ENDPOINT_LEN {
+                                    cfg[0..16].copy_from_slice(&addr.0);
+                                    cfg[16..ENDPOINT_LEN].copy_from_slice(&port.to_ne_bytes());
+                                }
+                            })
+                        })
+                        .ok();
+                });
+            }
+        }
+        self.schedule_upcall(
+            socket,
+            upcall::CONNECTED,
+            kernel::errorcode::into_statuscode(result),
+        );
+    }
+
+    fn received(&self, socket: usize, available: usize) {
+        self.schedule_upcall(socket, upcall::RECEIVED, available);
+    }
+
+    fn sent(&self, socket: usize, acked: usize) {
+        self.schedule_upcall(socket, upcall::SENT, acked);
+    }
+
+    fn closed(&self, socket: usize, result: Result<(), ErrorCode>) {
+        self.schedule_upcall(
+            socket,
+            upcall::CLOSED,
+            kernel::errorcode::into_statuscode(result),
+        );
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, const NUM_SOCKETS: usize> SyscallDriver
+    for TcpDriver<'a, A, NUM_SOCKETS>
+{
+    /// TCP control.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Listen for a connection on the port in `arg1`.
+    /// - `2`: Connect to the endpoint in the config buffer.
+    /// - `3`: Send the contents of the write buffer. Returns the number of
+    ///   bytes queued, which can be less than the length of the buffer if the
+    ///   transmit buffer of the socket is full.
+    /// - `4`: Copy received data into the read buffer. Returns the number of
+    ///   bytes copied.
+    /// - `5`: Close the connection. `closed` is signaled once both sides
+    ///   closed it.
+    /// - `6`: Reset the connection (if any) and release the socket.
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => {
+                if arg1 > u16::MAX as usize {
+                    return CommandReturn::failure(ErrorCode::INVAL);
+                }
+                let index = match self.claim_socket(processid) {
+                    Ok(index) => index,
+                    Err(e) => return CommandReturn::failure(e),
+                };
+                self.sockets[index].listen(arg1 as u16).into()
+            }
+
+            2 => {
+                let endpoint = self
+                    .apps
+                    .enter(processid, |_, kernel_data| {
+                        kernel_data
+                            .get_readwrite_processbuffer(rw_allow::CFG)
+                            .and_then(|cfg| {
+                                cfg.enter(|cfg| {
+                                    if cfg.len() < ENDPOINT_LEN {
+                                        return None;
+                                    }
+                                    let mut endpoint = [0; ENDPOINT_LEN];
+                                    cfg[..ENDPOINT_LEN].copy_to_slice(&mut endpoint);
+                                    Some(Self::parse_endpoint(&endpoint))
+                                })
+                            })
+                            .unwrap_or(None)
+                    })
+                    .unwrap_or(None);
+                let (addr, port) = match endpoint {
+                    Some(endpoint) => endpoint,
+                    None => return CommandReturn::failure(ErrorCode::INVAL),
+                };
+                let index = match self.claim_socket(processid) {
+                    Ok(index) => index,
+                    Err(e) => return CommandReturn::failure(e),
+                };
+                self.sockets[index].connect(addr, port).into()
+            }
+
+            3 => {
+                let index = match self.socket_of(processid) {
+                    Some(index) => index,
+                    None => return CommandReturn::failure(ErrorCode::RESERVE),
+                };
+                let result = self
+                    .apps
+                    .enter(processid, |_, kernel_data| {
+                        kernel_data
+                            .get_readonly_processbuffer(ro_allow::WRITE)
+                            .and_then(|write| {
+                                write.enter(|data| {
+                                    self.sockets[index].write(|free| {
+                                        let length = core::cmp::min(free.len(), data.len());
+                                        data[..length].copy_to_slice(&mut free[..length]);
+                                        length
+                                    })
+                                })
+                            })
+                            .unwrap_or(Err(ErrorCode::RESERVE))
+                    })
+                    .unwrap_or_else(|err| Err(err.into()));
+                match result {
+                    Ok(length) => CommandReturn::success_u32(length as u32),
+                    Err(e) => CommandReturn::failure(e),
+                }
+            }
+
+            4 => {
+                let index = match self.socket_of(processid) {
+                    Some(index) => index,
+                    None => return CommandReturn::failure(ErrorCode::RESERVE),
+                };
+                self.apps
+                    .enter(processid, |_, kernel_data| {
+                        kernel_data
+                            .get_readwrite_processbuffer(rw_allow::READ)
+                            .and_then(|read| {
+                                read.mut_enter(|dest| {
+                                    self.sockets[index].read(|data| {
+                                        let length = core::cmp::min(dest.len(), data.len());
+                                        dest[..length].copy_from_slice(&data[..length]);
+                                        length
+                                    })
+                                })
+                            })
+                            .map_or(CommandReturn::failure(ErrorCode::RESERVE), |length| {
+                                CommandReturn::success_u32(length as u32)
+                            })
+                    })
+                    .unwrap_or_else(|err| CommandReturn::failure(err.into()))
+            }
+
+            5 => match self.socket_of(processid) {
+                Some(index) => self.sockets[index].close().into(),
+                None => CommandReturn::failure(ErrorCode::RESERVE),
+            },
+
+            6 => match self.socket_of(processid) {
+                Some(index) => {
+                    self.release_socket(index);
+                    CommandReturn::success()
+                }
+                None => CommandReturn::failure(ErrorCode::RESERVE),
+            },
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
@@ -0,0 +1,129 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! In-kernel structure for tracking bound TCP ports.
+//!
+//! This follows the same model as the UDP port table (`udp_port_table.rs`):
+//! a fixed size table stores the ports in use, and binding a port returns a
+//! `TcpPortBinding` which acts as proof that the holder is bound to the port.
+//! Bindings can only be created within this file, and unbinding consumes
+//! them.
+//!
+//! TCP sockets bind a port when they start listening or connecting, and
+//! unbind it once the connection is closed. Outgoing connections are bound
+//! to an ephemeral port, which is allocated from the dynamic port range
+//! (RFC 6335) by this table.
+//!
+//! Unlike UDP, all TCP sockets (including those used by the userspace
+//! driver) are kernel objects which bind ports through this table, so
+//! no separate query of userspace bindings is needed.
+
+use core::cell::Cell;
+use core::fmt;
+
+use kernel::capabilities::CreatePortTableCapability;
+use kernel::utilities::cells::TakeCell;
+use kernel::ErrorCode;
+
+/// Sets the maximum number of TCP ports that can be bound at the same time.
+/// Every socket binds at most one port, so this should be at least the
+/// number of TCP sockets.
+pub const MAX_NUM_BOUND_PORTS: usize = 8;
+
+/// First port of the dynamic port range, used for ephemeral ports.
+const EPHEMERAL_PORT_START: u16 = 49152;
+
+/// An opaque descriptor that proves that the holder is bound to a TCP port.
+#[derive(Debug)]
+pub struct TcpPortBinding {
+    idx: usize,
+    port: u16,
+}
+
+impl TcpPortBinding {
+    fn new(idx: usize, port: u16) -> TcpPortBinding {
+        TcpPortBinding { idx, port }
+    }
+
+    pub fn get_port(&self) -> u16 {
+        self.port
+    }
+}
+
+/// Table of bound TCP ports.
+pub struct TcpPortManager {
+    port_array: TakeCell<'static, [Option<u16>]>,
+    /// Next ephemeral port to try.
+    next_ephemeral: Cell<u16>,
+}
+
+impl fmt::Debug for TcpPortManager {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[TCP Port Table]")
+    }
+}
+
+impl TcpPortManager {
+    // Require capability so that the port table is only created by kernel
+    pub fn new(
+        _cap: &dyn CreatePortTableCapability,
+        used_ports: &'static mut [Option<u16>],
+    ) -> TcpPortManager {
+        TcpPortManager {
+            port_array: TakeCell::new(used_ports),
+            next_ephemeral: Cell::new(EPHEMERAL_PORT_START),
+        }
+    }
+
+    /// Check if a given port is already bound.
+    pub fn is_bound(&self, port: u16) -> bool {
+        self.port_array.map_or(false, |table| {
+            table.iter().any(|entry| *entry == Some(port))
+        })
+    }
+
+    /// Bind to `port`.
+    ///
+    /// Returns INVAL for port 0, BUSY if the port is already bound, and NOMEM
+    /// if no slots are left in the table.
+    pub fn bind(&self, port: u16) -> Result<TcpPortBinding, ErrorCode> {
+        if port == 0 {
+            return Err(ErrorCode::INVAL);
+        }
+        if self.is_bound(port) {
+            return Err(ErrorCode::BUSY);
+        }
+        self.port_array.map_or(Err(ErrorCode::NOSUPPORT), |table| {
+            let idx = table
+                .iter()
+                .position(|entry| entry.is_none())
+                .ok_or(ErrorCode::NOMEM)?;
+            table[idx] = Some(port);
+            Ok(TcpPortBinding::new(idx, port))
+        })
+    }
+
+    /// Bind to the next free ephemeral port.
+    pub fn bind_ephemeral(&self) -> Result<TcpPortBinding, ErrorCode> {
+        // At most `MAX_NUM_BOUND_PORTS` ports are bound, so one of the next
+        // `MAX_NUM_BOUND_PORTS + 1` ephemeral ports must be free.
+        for _ in 0..=MAX_NUM_BOUND_PORTS {
+            let port = self.next_ephemeral.get();
+            self.next_ephemeral
+                .set(port.checked_add(1).unwrap_or(EPHEMERAL_PORT_START));
+            match self.bind(port) {
+                Err(ErrorCode::BUSY) => continue,
+                result => return result,
+            }
+        }
+        Err(ErrorCode::NOMEM)
+    }
+
+    /// Release the port of `binding`.
+    pub fn unbind(&self, binding: TcpPortBinding) {
+        self.port_array.map(|table| {
+            table[binding.idx] = None;
+        });
+    }
+}
@@ -0,0 +1,923 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Definition and implementation of TCP sockets.
+//!
+//! A [TcpSocket](struct.TcpSocket.html) holds the state of a single TCP
+//! connection (RFC 9293), and the [MuxTcp](struct.MuxTcp.html) multiplexes
+//! all sockets over one IPv6 sender and receiver. The mux demultiplexes
+//! received segments to the socket they belong to, transmits the segments
+//! sockets have pending one at a time, and drives the retransmission
+//! timers of all sockets from a single periodic alarm.
+//!
+//! This is a minimal implementation meant for low-bandwidth links:
+//!
+//! - Each socket has a fixed transmit and receive buffer. Data written to
+//!   the socket stays in the transmit buffer until the peer acknowledges it,
+//!   and the free space of the receive buffer is advertised as the window.
+//! - Only segments that arrive in order are accepted. Anything else is
+//!   answered with a duplicate acknowledgment, so the peer retransmits.
+//! - Retransmission is go-back-N: when the retransmission timer expires,
+//!   everything that was not acknowledged is sent again. The timeout starts
+//!   at about one second and doubles with every retransmission.
+//! - When the peer closes the connection, the socket closes its side as
+//!   soon as all buffered data has been sent.
+//! - Options (including MSS), urgent data, congestion control and
+//!   simultaneous open are not supported.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let mux = static_init!(
+//!     MuxTcp<'static, VirtualMuxAlarm<'static, Rtc>>,
+//!     MuxTcp::new(ip_send, alarm, port_table, tx_buffer, net_cap)
+//! );
+//! ip_send.set_client(mux);
+//! ip_receive.set_client(mux);
+//! alarm.set_alarm_client(mux);
+//!
+//! let socket = static_init!(
+//!     TcpSocket<'static, VirtualMuxAlarm<'static, Rtc>>,
+//!     TcpSocket::new(0, mux, socket_tx_buffer, socket_rx_buffer)
+//! );
+//! mux.add_socket(socket);
+//! socket.set_client(client);
+//! socket.listen(80);
+//! ```
+
+use core::cell::Cell;
+use core::cmp;
+
+use crate::net::ipv6::ip_utils::{ip6_nh, IPAddr};
+use crate::net::ipv6::ipv6_recv::IP6RecvClient;
+use crate::net::ipv6::ipv6_send::{IP6SendClient, IP6Sender};
+use crate::net::ipv6::{IP6Header, TransportHeader};
+use crate::net::network_capabilities::NetworkCapability;
+use crate::net::tcp::tcp_port_table::{TcpPortBinding, TcpPortManager};
+use crate::net::tcp::{tcp_flags, TCPHeader};
+
+use kernel::collections::list::{List, ListLink, ListNode};
+use kernel::hil::time::{self, ConvertTicks, Ticks};
+use kernel::utilities::cells::{MapCell, OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::ErrorCode;
+
+/// Period of the timer that drives the retransmission timers, in ms.
+pub const TICK_MS: u32 = 250;
+
+/// Initial retransmission timeout, in ticks.
+const INITIAL_RTO_TICKS: u16 = 4;
+/// Upper bound for the retransmission timeout, in ticks.
+const MAX_RTO_TICKS: u16 = 120;
+/// Number of retransmissions after which the connection is dropped.
+const MAX_RETRANSMISSIONS: u8 = 6;
+/// Time spent in TIME-WAIT, in ticks. This is much shorter than the 2 MSL
+/// recommended by RFC 9293, as sockets are a scarce resource here.
+const TIME_WAIT_TICKS: u16 = 8;
+
+/// States of a TCP connection, see RFC 9293 section 3.3.2.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TcpState {
+    Closed,
+    Listen,
+    SynSent,
+    SynReceived,
+    Established,
+    FinWait1,
+    FinWait2,
+    CloseWait,
+    Closing,
+    LastAck,
+    TimeWait,
+}
+
+/// Client of a TCP socket. Every callback carries the id the socket was
+/// created with, so that one client can serve several sockets.
+pub trait TcpClient {
+    /// The socket connected to its peer, or failed to (for example because
+    /// the peer refused the connection).
+    fn connected(&self, socket: usize, result: Result<(), ErrorCode>);
+
+    /// New data was received, and `available` bytes can now be read.
+    fn received(&self, socket: usize, available: usize);
+
+    /// The peer acknowledged `acked` bytes, which freed up space in the
+    /// transmit buffer.
+    fn sent(&self, socket: usize, acked: usize);
+
+    /// The connection closed. `result` is `Ok` for an orderly close by
+    /// either side, and an error if the connection was reset or timed out.
+    fn closed(&self, socket: usize, result: Result<(), ErrorCode>);
+}
+
+pub struct TcpSocket<'a, A: time::Alarm<'a>> {
+    id: usize,
+    mux: &'a MuxTcp<'a, A>,
+    next: ListLink<'a, TcpSocket<'a, A>>,
+    client: OptionalCell<&'a dyn TcpClient>,
+
+    state: Cell<TcpState>,
+    /// Whether the connection was opened with `listen()`.
+    passive_open: Cell<bool>,
+    binding: MapCell<TcpPortBinding>,
+    remote_addr: Cell<IPAddr>,
+    remote_port: Cell<u16>,
+
+    // Send sequence variables.
+    iss: Cell<u32>,
+    snd_una: Cell<u32>,
+    snd_nxt: Cell<u32>,
+    snd_wnd: Cell<u16>,
+    // Receive sequence variables.
+    rcv_nxt: Cell<u32>,
+
+    /// Data that the peer did not acknowledge yet, starting at `snd_una`.
+    tx_buffer: TakeCell<'static, [u8]>,
+    tx_len: Cell<usize>,
+    /// Received data that the client did not read yet.
+    rx_buffer: TakeCell<'static, [u8]>,
+    rx_len: Cell<usize>,
+
+    /// The local side was closed, so a FIN follows the buffered data.
+    fin_queued: Cell<bool>,
+    /// The FIN is in flight, or was acknowledged.
+    fin_sent: Cell<bool>,
+    /// An acknowledgment (or window update) needs to be sent.
+    ack_pending: Cell<bool>,
+
+    /// Ticks until the timer expires, or 0 if the timer is stopped.
+    timer: Cell<u16>,
+    rto: Cell<u16>,
+    retransmissions: Cell<u8>,
+}
+
+impl<'a, A: time::Alarm<'a>> ListNode<'a, TcpSocket<'a, A>> for TcpSocket<'a, A> {
+    fn next(&'a self) -> &'a ListLink<'a, TcpSocket<'a, A>> {
+        &self.next
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> TcpSocket<'a, A> {
+    pub fn new(
+        id: usize,
+        mux: &'a MuxTcp<'a, A>,
+        tx_buffer: &'static mut [u8],
+        rx_buffer: &'static mut [u8],
+    ) -> TcpSocket<'a, A> {
+        TcpSocket {
+            id,
+            mux,
+            next: ListLink::empty(),
+            client: OptionalCell::empty(),
+            state: Cell::new(TcpState::Closed),
+            passive_open: Cell::new(false),
+            binding: MapCell::empty(),
+            remote_addr: Cell::new(IPAddr::new()),
+            remote_port: Cell::new(0),
+            iss: Cell::new(0),
+            snd_una: Cell::new(0),
+            snd_nxt: Cell::new(0),
+            snd_wnd: Cell::new(0),
+            rcv_nxt: Cell::new(0),
+            tx_buffer: TakeCell::new(tx_buffer),
+            tx_len: Cell::new(0),
+            rx_buffer: TakeCell::new(rx_buffer),
+            rx_len: Cell::new(0),
+            fin_queued: Cell::new(false),
+            fin_sent: Cell::new(false),
+            ack_pending: Cell::new(false),
+            timer: Cell::new(0),
+            rto: Cell::new(INITIAL_RTO_TICKS),
+            retransmissions: Cell::new(0),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn TcpClient) {
+        self.client.set(client);
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn state(&self) -> TcpState {
+        self.state.get()
+    }
+
+    /// Local port of the socket, if it is bound.
+    pub fn local_port(&self) -> Option<u16> {
+        self.binding.map(|binding| binding.get_port())
+    }
+
+    /// Address and port of the peer. Only meaningful once the socket started
+    /// connecting, or accepted a connection.
+    pub fn remote_endpoint(&self) -> (IPAddr, u16) {
+        (self.remote_addr.get(), self.remote_port.get())
+    }
+
+    /// Number of bytes that can be read.
+    pub fn available(&self) -> usize {
+        self.rx_len.get()
+    }
+
+    /// Wait for a connection on `port`.
+    ///
+    /// The socket accepts the first connection attempt, and calls
+    /// `connected()` once the handshake completes.
+    pub fn listen(&self, port: u16) -> Result<(), ErrorCode> {
+        if self.state.get() != TcpState::Closed {
+            return Err(ErrorCode::ALREADY);
+        }
+        let binding = self.mux.port_table.bind(port)?;
+        self.reset_connection();
+        self.binding.replace(binding);
+        self.passive_open.set(true);
+        self.state.set(TcpState::Listen);
+        Ok(())
+    }
+
+    /// Open a connection to `port` on `addr`, from an ephemeral local port.
+    ///
+    /// Calls `connected()` once the handshake completes or fails.
+    pub fn connect(&self, addr: IPAddr, port: u16) -> Result<(), ErrorCode> {
+        if self.state.get() != TcpState::Closed {
+            return Err(ErrorCode::ALREADY);
+        }
+        if port == 0 {
+            return Err(ErrorCode::INVAL);
+        }
+        let binding = self.mux.port_table.bind_ephemeral()?;
+        self.reset_connection();
+        self.binding.replace(binding);
+        self.passive_open.set(false);
+        self.remote_addr.set(addr);
+        self.remote_port.set(port);
+        self.start_handshake();
+        self.state.set(TcpState::SynSent);
+        self.mux.transmit_pending();
+        Ok(())
+    }
+
+    /// Queue data for transmission.
+    ///
+    /// `fill` is passed the free space of the transmit buffer, and returns
+    /// how many bytes it wrote into it. Returns the number of bytes queued,
+    /// or BUSY if the transmit buffer is full.
+    pub fn write<F>(&self, fill: F) -> Result<usize, ErrorCode>
+    where
+        F: FnOnce(&mut [u8]) -> usize,
+    {
+        if self.state.get() != TcpState::Established || self.fin_queued.get() {
+            return Err(ErrorCode::INVAL);
+        }
+        let tx_len = self.tx_len.get();
+        let written = self.tx_buffer.map_or(Err(ErrorCode::NOMEM), |buffer| {
+            if tx_len >= buffer.len() {
+                return Err(ErrorCode::BUSY);
+            }
+            let free = &mut buffer[tx_len..];
+            let written = cmp::min(fill(free), free.len());
+            Ok(written)
+        })?;
+        self.tx_len.set(tx_len + written);
+        self.mux.transmit_pending();
+        Ok(written)
+    }
+
+    /// Read received data.
+    ///
+    /// `drain` is passed the received data, and returns how many bytes it
+    /// consumed. Returns the number of bytes consumed.
+    pub fn read<F>(&self, drain: F) -> usize
+    where
+        F: FnOnce(&[u8]) -> usize,
+    {
+        let rx_len = self.rx_len.get();
+        let consumed = self.rx_buffer.map_or(0, |buffer| {
+            let consumed = cmp::min(drain(&buffer[..rx_len]), rx_len);
+            buffer.copy_within(consumed..rx_len, 0);
+            consumed
+        });
+        self.rx_len.set(rx_len - consumed);
+        if consumed > 0 && self.is_synchronized() {
+            // Let the peer know that the window opened up.
+            self.ack_pending.set(true);
+            self.mux.transmit_pending();
+        }
+        consumed
+    }
+
+    /// Close the local side of the connection.
+    ///
+    /// Data that was already written is still delivered, and `closed()` is
+    /// called once both sides closed the connection. Sockets that are not
+    /// connected yet are closed immediately, without a callback.
+    pub fn close(&self) -> Result<(), ErrorCode> {
+        match self.state.get() {
+            TcpState::Closed => Err(ErrorCode::ALREADY),
+            TcpState::Listen | TcpState::SynSent => {
+                self.finish();
+                Ok(())
+            }
+            TcpState::SynReceived | TcpState::Established | TcpState::CloseWait => {
+                self.fin_queued.set(true);
+                self.mux.transmit_pending();
+                Ok(())
+            }
+            _ => Err(ErrorCode::ALREADY),
+        }
+    }
+
+    /// Reset the connection, discarding any buffered data.
+    ///
+    /// The socket is closed immediately, without a callback.
+    pub fn abort(&self) {
+        if self.is_synchronized() || self.state.get() == TcpState::SynReceived {
+            let mut header = TCPHeader::new();
+            header.set_src_port(self.local_port().unwrap_or(0));
+            header.set_dst_port(self.remote_port.get());
+            header.set_seq_num(self.snd_nxt.get());
+            header.set_ack_num(self.rcv_nxt.get());
+            header.set_flags(tcp_flags::RST | tcp_flags::ACK);
+            self.mux.queue_reset(self.remote_addr.get(), header);
+        }
+        if self.state.get() != TcpState::Closed {
+            self.finish();
+        }
+        self.rx_len.set(0);
+    }
+
+    /// States in which the connection is synchronized, i.e. both sides
+    /// agreed on the initial sequence numbers.
+    fn is_synchronized(&self) -> bool {
+        !matches!(
+            self.state.get(),
+            TcpState::Closed | TcpState::Listen | TcpState::SynSent | TcpState::SynReceived
+        )
+    }
+
+    /// Whether the socket has to be served by the alarm of the mux.
+    fn timer_running(&self) -> bool {
+        self.timer.get() > 0
+    }
+
+    /// Whether the segment belongs to the connection of this socket.
+    fn is_connection(&self, src_addr: IPAddr, header: &TCPHeader) -> bool {
+        !matches!(self.state.get(), TcpState::Closed | TcpState::Listen)
+            && self.local_port() == Some(header.get_dst_port())
+            && self.remote_port.get() == header.get_src_port()
+            && self.remote_addr.get() == src_addr
+    }
+
+    fn is_listening(&self, port: u16) -> bool {
+        self.state.get() == TcpState::Listen && self.local_port() == Some(port)
+    }
+
+    fn reset_connection(&self) {
+        self.tx_len.set(0);
+        self.rx_len.set(0);
+        self.fin_queued.set(false);
+        self.fin_sent.set(false);
+        self.ack_pending.set(false);
+        self.timer.set(0);
+        self.rto.set(INITIAL_RTO_TICKS);
+        self.retransmissions.set(0);
+    }
+
+    fn start_handshake(&self) {
+        let iss = self.mux.next_iss();
+        self.iss.set(iss);
+        self.snd_una.set(iss);
+        self.snd_nxt.set(iss);
+        self.timer.set(0);
+        self.rto.set(INITIAL_RTO_TICKS);
+        self.retransmissions.set(0);
+    }
+
+    /// Move to CLOSED and release the port. The client is not notified.
+    fn finish(&self) {
+        self.state.set(TcpState::Closed);
+        self.timer.set(0);
+        self.tx_len.set(0);
+        self.ack_pending.set(false);
+        self.binding
+            .take()
+            .map(|binding| self.mux.port_table.unbind(binding));
+    }
+
+    /// Close the connection with `result`, and notify the client.
+    fn close_connection(&self, result: Result<(), ErrorCode>) {
+        match self.state.get() {
+            TcpState::SynReceived if self.passive_open.get() => {
+                // The client never saw this connection, so wait for the next
+                // connection attempt instead.
+                self.state.set(TcpState::Listen);
+                self.timer.set(0);
+                self.ack_pending.set(false);
+            }
+            TcpState::SynSent | TcpState::SynReceived => {
+                self.finish();
+                self.client.map(|client| client.connected(self.id, result));
+            }
+            _ => {
+                self.finish();
+                self.client.map(|client| client.closed(self.id, result));
+            }
+        }
+    }
+
+    /// Write the next segment this socket has to send into `payload`.
+    ///
+    /// Returns the header and payload length of the segment, or `None` if
+    /// there is nothing to send.
+    fn next_segment(&self, payload: &mut [u8]) -> Option<(TCPHeader, usize)> {
+        let state = self.state.get();
+        let seq = self.snd_nxt.get();
+        let mut flags = tcp_flags::ACK;
+        let mut len = 0;
+
+        match state {
+            TcpState::Closed | TcpState::Listen => return None,
+            TcpState::SynSent | TcpState::SynReceived => {
+                if seq != self.iss.get() {
+                    // The SYN is in flight.
+                    return None;
+                }
+                flags = if state == TcpState::SynSent {
+                    tcp_flags::SYN
+                } else {
+                    tcp_flags::SYN | tcp_flags::ACK
+                };
+                self.snd_nxt.set(seq.wrapping_add(1));
+            }
+            _ => {
+                let tx_len = self.tx_len.get();
+                let in_flight = cmp::min(seq.wrapping_sub(self.snd_una.get()) as usize, tx_len);
+                let unsent = tx_len - in_flight;
+                // Probe a closed window with a single byte, so that the peer
+                // is asked again once the probe times out.
+                let window = match self.snd_wnd.get() {
+                    0 if in_flight == 0 => 1,
+                    window => window as usize,
+                };
+                len = cmp::min(
+                    cmp::min(unsent, window.saturating_sub(in_flight)),
+                    payload.len(),
+                );
+                if len > 0 {
+                    self.tx_buffer.map(|buffer| {
+                        payload[..len].copy_from_slice(&buffer[in_flight..in_flight + len]);
+                    });
+                    flags |= tcp_flags::PSH;
+                }
+                if self.fin_queued.get() && !self.fin_sent.get() && in_flight + len == tx_len {
+                    flags |= tcp_flags::FIN;
+                    self.fin_sent.set(true);
+                    match state {
+                        TcpState::Established => self.state.set(TcpState::FinWait1),
+                        TcpState::CloseWait => self.state.set(TcpState::LastAck),
+                        _ => {}
+                    }
+                }
+                if len == 0 && flags & tcp_flags::FIN == 0 && !self.ack_pending.get() {
+                    return None;
+                }
+                let fin = (flags & tcp_flags::FIN != 0) as u32;
+                self.snd_nxt.set(seq.wrapping_add(len as u32 + fin));
+            }
+        }
+
+        if (len > 0 || flags & (tcp_flags::SYN | tcp_flags::FIN) != 0) && !self.timer_running() {
+            self.timer.set(self.rto.get());
+        }
+        self.ack_pending.set(false);
+
+        let mut header = TCPHeader::new();
+        header.set_src_port(self.local_port().unwrap_or(0));
+        header.set_dst_port(self.remote_port.get());
+        header.set_seq_num(seq);
+        if flags & tcp_flags::ACK != 0 {
+            header.set_ack_num(self.rcv_nxt.get());
+        }
+        header.set_flags(flags);
+        let window = self
+            .rx_buffer
+            .map_or(0, |buffer| buffer.len() - self.rx_len.get());
+        header.set_window(cmp::min(window, u16::MAX as usize) as u16);
+        Some((header, len))
+    }
+
+    /// Handle a segment that was demultiplexed to this socket.
+    ///
+    /// Returns true if the segment should be answered with a reset.
+    fn segment_arrived(&self, src_addr: IPAddr, header: &TCPHeader, payload: &[u8]) -> bool {
+        let seq = header.get_seq_num();
+        let ack = header.get_ack_num();
+        let has = |flag| header.has_flags(flag);
+
+        match self.state.get() {
+            TcpState::Closed => return !has(tcp_flags::RST),
+            TcpState::Listen => {
+                if has(tcp_flags::RST) {
+                    return false;
+                }
+                if has(tcp_flags::ACK) {
+                    return true;
+                }
+                if has(tcp_flags::SYN) {
+                    self.remote_addr.set(src_addr);
+                    self.remote_port.set(header.get_src_port());
+                    self.rcv_nxt.set(seq.wrapping_add(1));
+                    self.snd_wnd.set(header.get_window());
+                    self.start_handshake();
+                    self.state.set(TcpState::SynReceived);
+                }
+                return false;
+            }
+            TcpState::SynSent => {
+                if has(tcp_flags::ACK) && ack != self.iss.get().wrapping_add(1) {
+                    return !has(tcp_flags::RST);
+                }
+                if has(tcp_flags::RST) {
+                    if has(tcp_flags::ACK) {
+                        // Connection refused.
+                        self.close_connection(Err(ErrorCode::FAIL));
+                    }
+                    return false;
+                }
+                if has(tcp_flags::SYN) && has(tcp_flags::ACK) {
+                    self.rcv_nxt.set(seq.wrapping_add(1));
+                    self.snd_una.set(ack);
+                    self.snd_wnd.set(header.get_window());
+                    self.timer.set(0);
+                    self.rto.set(INITIAL_RTO_TICKS);
+                    self.retransmissions.set(0);
+                    self.ack_pending.set(true);
+                    self.state.set(TcpState::Established);
+                    self.client.map(|client| client.connected(self.id, Ok(())));
+                }
+                return false;
+            }
+            _ => {}
+        }
+
+        if seq != self.rcv_nxt.get() {
+            // Out of order or duplicate. Acknowledge again in case our last
+            // acknowledgment was lost.
+            if !has(tcp_flags::RST) {
+                self.ack_pending.set(true);
+            }
+            return false;
+        }
+        if has(tcp_flags::RST) {
+            self.close_connection(Err(ErrorCode::CANCEL));
+            return false;
+        }
+        if has(tcp_flags::SYN) {
+            self.close_connection(Err(ErrorCode::FAIL));
+            return true;
+        }
+        if !has(tcp_flags::ACK) {
+            return false;
+        }
+
+        let in_flight = self.snd_nxt.get().wrapping_sub(self.snd_una.get());
+        let acked = ack.wrapping_sub(self.snd_una.get());
+        if acked > in_flight {
+            if self.state.get() == TcpState::SynReceived {
+                return true;
+            }
+            // Acknowledges data that was never sent.
+            self.ack_pending.set(true);
+            return false;
+        }
+
+        let mut data_acked = acked as usize;
+        if self.state.get() == TcpState::SynReceived {
+            if acked == 0 {
+                return true;
+            }
+            // The SYN takes up one sequence number.
+            data_acked -= 1;
+            self.state.set(TcpState::Established);
+            self.client.map(|client| client.connected(self.id, Ok(())));
+        }
+        let fin_acked = self.fin_sent.get() && ack == self.snd_nxt.get();
+        if acked > 0 && fin_acked {
+            data_acked -= 1;
+        }
+        self.snd_wnd.set(header.get_window());
+        if acked > 0 {
+            let tx_len = self.tx_len.get();
+            let data_acked = cmp::min(data_acked, tx_len);
+            self.tx_buffer
+                .map(|buffer| buffer.copy_within(data_acked..tx_len, 0));
+            self.tx_len.set(tx_len - data_acked);
+            self.snd_una.set(ack);
+            self.rto.set(INITIAL_RTO_TICKS);
+            self.retransmissions.set(0);
+            self.timer.set(if in_flight == acked {
+                0
+            } else {
+                self.rto.get()
+            });
+            if data_acked > 0 {
+                self.client.map(|client| client.sent(self.id, data_acked));
+            }
+        }
+        if fin_acked {
+            match self.state.get() {
+                TcpState::FinWait1 => self.state.set(TcpState::FinWait2),
+                TcpState::Closing => self.enter_time_wait(),
+                TcpState::LastAck => {
+                    self.close_connection(Ok(()));
+                    return false;
+                }
+                _ => {}
+            }
+        }
+
+        if !payload.is_empty() {
+            if !matches!(
+                self.state.get(),
+                TcpState::Established | TcpState::FinWait1 | TcpState::FinWait2
+            ) {
+                return false;
+            }
+            let rx_len = self.rx_len.get();
+            let copied = self.rx_buffer.map_or(0, |buffer| {
+                let copied = cmp::min(payload.len(), buffer.len() - rx_len);
+                buffer[rx_len..rx_len + copied].copy_from_slice(&payload[..copied]);
+                copied
+            });
+            self.ack_pending.set(true);
+            if copied > 0 {
+                self.rx_len.set(rx_len + copied);
+                self.rcv_nxt
+                    .set(self.rcv_nxt.get().wrapping_add(copied as u32));
+                self.client
+                    .map(|client| client.received(self.id, rx_len + copied));
+            }
+            if copied < payload.len() {
+                // The rest of the segment (including any FIN) has to be
+                // retransmitted by the peer.
+                return false;
+            }
+        }
+
+        if has(tcp_flags::FIN) {
+            self.rcv_nxt.set(self.rcv_nxt.get().wrapping_add(1));
+            self.ack_pending.set(true);
+            match self.state.get() {
+                TcpState::Established => {
+                    // Close our side as well once the buffered data is sent.
+                    self.state.set(TcpState::CloseWait);
+                    self.fin_queued.set(true);
+                }
+                TcpState::FinWait1 => self.state.set(TcpState::Closing),
+                TcpState::FinWait2 => self.enter_time_wait(),
+                _ => {}
+            }
+        }
+        false
+    }
+
+    fn enter_time_wait(&self) {
+        self.state.set(TcpState::TimeWait);
+        self.timer.set(TIME_WAIT_TICKS);
+    }
+
+    /// Advance the timer of this socket by one tick.
+    fn tick(&self) {
+        match self.timer.get() {
+            0 => return,
+            1 => self.timer.set(0),
+            ticks => {
+                self.timer.set(ticks - 1);
+                return;
+            }
+        }
+
+        if self.state.get() == TcpState::TimeWait {
+            self.close_connection(Ok(()));
+            return;
+        }
+        // Window probes are repeated for as long as the peer keeps its
+        // window closed.
+        let probing = self.is_synchronized() && self.snd_wnd.get() == 0;
+        if !probing {
+            if self.retransmissions.get() >= MAX_RETRANSMISSIONS {
+                self.close_connection(Err(ErrorCode::NOACK));
+                return;
+            }
+            self.retransmissions.set(self.retransmissions.get() + 1);
+        }
+        self.rto.set(cmp::min(self.rto.get() * 2, MAX_RTO_TICKS));
+        // Go back to the first unacknowledged byte, and send everything from
+        // there again. The timer restarts when the segment is sent.
+        self.snd_nxt.set(self.snd_una.get());
+        self.fin_sent.set(false);
+    }
+}
+
+/// Multiplexes TCP sockets over an IPv6 sender and receiver.
+pub struct MuxTcp<'a, A: time::Alarm<'a>> {
+    ip_sender: &'a dyn IP6Sender<'a>,
+    alarm: &'a A,
+    port_table: &'static TcpPortManager,
+    sockets: List<'a, TcpSocket<'a, A>>,
+    tx_buffer: MapCell<SubSliceMut<'static, u8>>,
+    sending: Cell<bool>,
+    /// Id of the socket that transmitted last, so that sockets take turns.
+    last_sender: OptionalCell<usize>,
+    /// Reset to send, and its destination.
+    reset: OptionalCell<(IPAddr, TCPHeader)>,
+    iss: Cell<u32>,
+    alarm_armed: Cell<bool>,
+    net_cap: &'static NetworkCapability,
+}
+
+impl<'a, A: time::Alarm<'a>> MuxTcp<'a, A> {
+    /// Segments are limited to the size of `tx_buffer`, which should leave
+    /// room for the IPv6 and TCP headers within the IPv6 minimum MTU.
+    pub fn new(
+        ip_sender: &'a dyn IP6Sender<'a>,
+        alarm: &'a A,
+        port_table: &'static TcpPortManager,
+        tx_buffer: &'static mut [u8],
+        net_cap: &'static NetworkCapability,
+    ) -> MuxTcp<'a, A> {
+        MuxTcp {
+            ip_sender,
+            alarm,
+            port_table,
+            sockets: List::new(),
+            tx_buffer: MapCell::new(SubSliceMut::new(tx_buffer)),
+            sending: Cell::new(false),
+            last_sender: OptionalCell::empty(),
+            reset: OptionalCell::empty(),
+            iss: Cell::new(0),
+            alarm_armed: Cell::new(false),
+            net_cap,
+        }
+    }
+
+    pub fn add_socket(&self, socket: &'a TcpSocket<'a, A>) {
+        self.sockets.push_tail(socket);
+    }
+
+    /// Pick the initial sequence number for a new connection.
+    fn next_iss(&self) -> u32 {
+        // RFC 9293 asks for a clock-driven ISN, mixing in a per-connection
+        // offset keeps consecutive connections apart.
+        let iss = self
+            .alarm
+            .now()
+            .into_u32()
+            .wrapping_add(self.iss.get().wrapping_add(64000));
+        self.iss.set(iss);
+        iss
+    }
+
+    fn queue_reset(&self, dst: IPAddr, header: TCPHeader) {
+        self.reset.set((dst, header));
+        self.transmit_pending();
+    }
+
+    /// Answer `header`, which was received from `src_addr` with a payload
+    /// of `payload_len` bytes, with a reset.
+    fn reset_segment(&self, src_addr: IPAddr, header: &TCPHeader, payload_len: usize) {
+        let mut reset = TCPHeader::new();
+        reset.set_src_port(header.get_dst_port());
+        reset.set_dst_port(header.get_src_port());
+        if header.has_flags(tcp_flags::ACK) {
+            reset.set_seq_num(header.get_ack_num());
+            reset.set_flags(tcp_flags::RST);
+        } else {
+            let syn_fin = header.get_flags() & (tcp_flags::SYN | tcp_flags::FIN);
+            let seg_len = payload_len as u32 + syn_fin.count_ones();
+            reset.set_ack_num(header.get_seq_num().wrapping_add(seg_len));
+            reset.set_flags(tcp_flags::RST | tcp_flags::ACK);
+        }
+        self.queue_reset(src_addr, reset);
+    }
+
+    /// Send pending segments until one is in flight.
+    ///
+    /// Sockets call this whenever they have something new to send.
+    fn transmit_pending(&self) {
+        while !self.sending.get() && self.transmit_next() {}
+        self.start_alarm();
+    }
+
+    /// Send the next pending segment. Returns false if there was nothing to
+    /// send, or the segment could not be sent.
+    fn transmit_next(&self) -> bool {
+        self.tx_buffer.take().is_some_and(|mut buf| {
+            buf.reset();
+            let segment = match self.reset.take() {
+                Some((dst, header)) => Some((dst, header, 0)),
+                None => self.next_transmitter(buf.as_slice()),
+            };
+            let sent = match segment {
+                Some((dst, header, len)) => {
+                    buf.slice(0..len);
+                    self.sending.set(true);
+                    let result = self.ip_sender.send_to(
+                        dst,
+                        TransportHeader::TCP(header),
+                        &buf,
+                        self.net_cap,
+                    );
+                    if result.is_err() {
+                        // Lost segments are retransmitted when the socket's
+                        // timer expires.
+                        self.sending.set(false);
+                    }
+                    result.is_ok()
+                }
+                None => false,
+            };
+            self.tx_buffer.replace(buf);
+            sent
+        })
+    }
+
+    /// Find the next socket with a segment to send, starting after the socket
+    /// that transmitted last.
+    fn next_transmitter(&self, payload: &mut [u8]) -> Option<(IPAddr, TCPHeader, usize)> {
+        let start = self
+            .last_sender
+            .get()
+            .and_then(|id| self.sockets.iter().position(|socket| socket.id == id))
+            .map_or(0, |position| position + 1);
+
+        self.sockets
+            .iter()
+            .skip(start)
+            .chain(self.sockets.iter().take(start))
+            .find_map(|socket| {
+                socket.next_segment(payload).map(|(header, len)| {
+                    self.last_sender.set(socket.id);
+                    (socket.remote_addr.get(), header, len)
+                })
+            })
+    }
+
+    fn start_alarm(&self) {
+        if !self.alarm_armed.get() && self.sockets.iter().any(|socket| socket.timer_running()) {
+            self.alarm_armed.set(true);
+            self.alarm
+                .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(TICK_MS));
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> IP6RecvClient for MuxTcp<'a, A> {
+    fn receive(&self, ip_header: IP6Header, payload: &[u8]) {
+        if ip_header.get_next_header() != ip6_nh::TCP {
+            return;
+        }
+        let (offset, header) = match TCPHeader::decode(payload).done() {
+            Some(decoded) => decoded,
+            None => return,
+        };
+        let data = &payload[offset..];
+        let src_addr = ip_header.get_src_addr();
+
+        let socket = self
+            .sockets
+            .iter()
+            .find(|socket| socket.is_connection(src_addr, &header))
+            .or_else(|| {
+                self.sockets
+                    .iter()
+                    .find(|socket| socket.is_listening(header.get_dst_port()))
+            });
+        let reset = match socket {
+            Some(socket) => socket.segment_arrived(src_addr, &header, data),
+            None => !header.has_flags(tcp_flags::RST),
+        };
+        if reset {
+            self.reset_segment(src_addr, &header, data.len());
+        }
+        self.transmit_pending();
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> IP6SendClient for MuxTcp<'a, A> {
+    fn send_done(&self, _result: Result<(), ErrorCode>) {
+        self.sending.set(false);
+        self.transmit_pending();
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> time::AlarmClient for MuxTcp<'a, A> {
+    fn alarm(&self) {
+        self.alarm_armed.set(false);
+        self.sockets.iter().for_each(|socket| socket.tick());
+        self.transmit_pending();
+    }
+}
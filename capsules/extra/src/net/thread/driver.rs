@@ -63,6 +63,7 @@ use kernel::processbuffer::ReadableProcessBuffer;
 use kernel::syscall::{CommandReturn, SyscallDriver};
 use kernel::utilities::cells::MapCell;
 use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::utilities::poison::{PoisonCell, Poisonable};
 use kernel::{ErrorCode, ProcessId};
 
 const SECURITY_SUITE_ENCRYP: u8 = 0;
@@ -138,6 +139,10 @@ pub struct ThreadNetworkDriver<'a, A: time::Alarm<'a>> {
 
     /// Length of the message passed to the crypto engine
     crypto_sizelock: MapCell<usize>,
+
+    /// Set if the state machine diverged, after which the driver refuses
+    /// all operations.
+    poison: PoisonCell,
 }
 
 // Note: For now, we initialize the Thread state as empty.
@@ -177,6 +182,7 @@ impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
             frame_count: Cell::new(5),
             networkkey: MapCell::empty(),
             crypto_sizelock: MapCell::empty(),
+            poison: PoisonCell::new("thread"),
         }
     }
 
@@ -189,9 +195,12 @@ impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
         // UNCOMMENT TO DEBUG THREAD //
         // kernel::debug!("[Thread] Sending parent request...");
 
-        // Panicking on unwrap indicates the state was taken without replacement
+        // The state is only missing if it was taken without replacement
         // (unreachable with proper state machine implementation)
-        let curr_state = self.state.take().unwrap();
+        let Some(curr_state) = self.state.take() else {
+            self.poison.poison("parent request without a state");
+            return;
+        };
 
         match curr_state {
             ThreadState::Detached => {
@@ -473,6 +482,7 @@ impl<'a, A: time::Alarm<'a>> SyscallDriver for ThreadNetworkDriver<'a, A> {
         match command_num {
             0 => CommandReturn::success(),
 
+            1 if self.poison.is_poisoned() => CommandReturn::failure(ErrorCode::FAIL),
             1 => self
                 .apps
                 .enter(processid, |_, kernel_data| {
@@ -530,21 +540,31 @@ impl<'a, A: time::Alarm<'a>> UDPSendClient for ThreadNetworkDriver<'a, A> {
     fn send_done(&self, _result: Result<(), ErrorCode>, mut dgram: SubSliceMut<'static, u8>) {
         // TODO: handle result from send done and respond accordingly
 
-        // Panicking on unwrap indicates the state was taken without replacement
+        // The state is only missing if it was taken without replacement
         // (unreachable with proper state machine implementation)
-        let curr_state = self.state.take().unwrap();
+        let Some(curr_state) = self.state.take() else {
+            self.poison.poison("send done without a state");
+            dgram.reset();
+            self.send_buffer.replace(dgram);
+            return;
+        };
 
         // Advance state machine
         let next_state = match curr_state {
             ThreadState::SendUpdate(dst_ip, dst_mac) => ThreadState::SEDActive(dst_ip, dst_mac),
-            ThreadState::SendUDPMsg => unimplemented!(),
             ThreadState::SendChildIdReq(_) => ThreadState::WaitingChildRsp,
             ThreadState::SendParentReq => {
                 // UNCOMMENT TO DEBUG THREAD //
                 // kernel::debug!("[Thread] Completed sending parent request to multicast IP");
                 ThreadState::WaitingParentRsp
             }
-            _ => panic!("Thread state machine diverged"),
+            // Sending UDP messages is not implemented, so no other state
+            // sends.
+            _ => {
+                self.poison
+                    .poison("send done in a state that does not send");
+                curr_state
+            }
         };
 
         self.frame_count.set(self.frame_count.get() + 1);
@@ -561,17 +581,11 @@ impl<'a, A: time::Alarm<'a>> time::AlarmClient for ThreadNetworkDriver<'a, A> {
     // for implementing timeouts/timing for sending heartbeat messages to the parent
     // node
     fn alarm(&self) {
-        match self.state.take().unwrap() {
-            // TODO: Implement retries as defined in the thread spec (when timeouts occur)
-            ThreadState::Detached => unimplemented!("[Thread ALARM] Detached"),
-            ThreadState::SendParentReq => unimplemented!("[Thread ALARM] Send Parent Req"),
-            ThreadState::SendChildIdReq(_) => unimplemented!("[Thread ALARM] Send Child ID Req"),
-            ThreadState::SEDActive(_ipaddr, _mac) => {
-                // TODO: SEND HEARTBEAT to parent node
-                unimplemented!("[Thread ALARM] Send Heartbeat")
-            }
-            _ => panic!(""),
-        }
+        // TODO: Implement retries as defined in the thread spec (when
+        // timeouts occur) while joining, and SEND HEARTBEAT to the parent
+        // node once in SEDActive. Until then the alarm is never set.
+        self.poison
+            .poison("alarm fired, but Thread timeouts are not implemented");
     }
 }
 
@@ -584,6 +598,10 @@ impl<'a, A: time::Alarm<'a>> UDPRecvClient for ThreadNetworkDriver<'a, A> {
         _dst_port: u16,
         payload: &[u8],
     ) {
+        if self.poison.is_poisoned() {
+            return;
+        }
+
         if payload[0] != SECURITY_SUITE_ENCRYP {
             // Tock's current implementation of Thread ignores all messages that do not possess MLE encryption. This
             // is due to the Thread spec stating "Except for when specifically indicated, incoming
@@ -644,8 +662,12 @@ impl<'a, A: time::Alarm<'a>> CCMClient for ThreadNetworkDriver<'a, A> {
     fn crypt_done(&self, buf: &'static mut [u8], _res: Result<(), ErrorCode>, _tag_is_valid: bool) {
         // TODO: check validity of result/tag and handle accordingly
 
-        // Obtain the length of the payload from the sizelock
-        let buf_len = self.crypto_sizelock.take().unwrap();
+        // Obtain the length of the payload from the sizelock, which is only
+        // empty if no crypto operation was started.
+        let Some(buf_len) = self.crypto_sizelock.take() else {
+            self.poison.poison("crypt done without a crypto operation");
+            return;
+        };
 
         // The auth data contains the src_addr || dest_addr || aux_sec_header;
         // Recover src/dst addr from the auth data
@@ -661,12 +683,14 @@ impl<'a, A: time::Alarm<'a>> CCMClient for ThreadNetworkDriver<'a, A> {
         buf.copy_within(auth_addr_offset.., SECURITY_SUITE_LEN);
 
         // Recover the length of the mic from the security information encoded in the aux_sec_header
-        let mic_len = ieee802154::Security::decode(&buf[SECURITY_SUITE_LEN..])
-            .done()
-            .unwrap()
-            .1
-            .level
-            .mic_len();
+        // (which was checked or written by `perform_crypt_op`, so it decodes)
+        let Some((_, security)) = ieee802154::Security::decode(&buf[SECURITY_SUITE_LEN..]).done()
+        else {
+            self.poison
+                .poison("crypto operation returned a malformed security header");
+            return;
+        };
+        let mic_len = security.level.mic_len();
 
         // We hard code the security suite to `0` for now as all messages are
         // assumed to be encrypted for the current implementation
@@ -678,9 +702,12 @@ impl<'a, A: time::Alarm<'a>> CCMClient for ThreadNetworkDriver<'a, A> {
         // We create a new subslice that we will slice accordingly depending on if we are sending/receiving
         let mut assembled_subslice = SubSliceMut::new(buf);
 
-        // Panicking on unwrap indicates the state was taken without replacement
+        // The state is only missing if it was taken without replacement
         // (unreachable with proper state machine implementation)
-        let curr_state = self.state.take().unwrap();
+        let Some(curr_state) = self.state.take() else {
+            self.poison.poison("crypt done without a state");
+            return;
+        };
 
         match curr_state {
             ThreadState::SendParentReq | ThreadState::SendChildIdReq(_) => {
@@ -741,3 +768,9 @@ impl<'a, A: time::Alarm<'a>> CCMClient for ThreadNetworkDriver<'a, A> {
         };
     }
 }
+
+impl<'a, A: time::Alarm<'a>> Poisonable for ThreadNetworkDriver<'a, A> {
+    fn poison_cell(&self) -> &PoisonCell {
+        &self.poison
+    }
+}
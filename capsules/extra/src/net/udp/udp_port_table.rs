@@ -41,12 +41,12 @@ use crate::net::network_capabilities::{NetworkCapability, UdpVisibilityCapabilit
 use core::fmt;
 
 use kernel::capabilities::{CreatePortTableCapability, UdpDriverCapability};
-use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::collections::ordered_map::OrderedMap;
+use kernel::utilities::cells::{MapCell, OptionalCell, TakeCell};
 use kernel::ErrorCode;
 
 // Sets the maximum number of UDP ports that can be bound by capsules. Reducing this number
-// can save a small amount of memory, and slightly reduces the overhead of iterating through the
-// table to check whether a port is already bound. Note: if this numberis changed,
+// can save a small amount of memory. Note: if this numberis changed,
 // port_table_test2 in udp_lowpan_test.rs will fail since it tests the capacity of
 // the port table -- therefore that test should be modified when MAX_NUM_BOUND_PORTS
 // is.
@@ -82,6 +82,8 @@ pub struct UdpSocket {
 /// Maps bound ports to userspace port bindings.
 pub struct UdpPortManager {
     port_array: TakeCell<'static, [Option<SocketBindingEntry>]>,
+    /// Ports bound by capsules, mapped to the index of their socket.
+    bound_ports: MapCell<OrderedMap<'static, u16, usize>>,
     user_ports: OptionalCell<&'static dyn PortQuery>,
     udp_vis: &'static UdpVisibilityCapability,
 }
@@ -150,10 +152,12 @@ impl UdpPortManager {
     pub fn new(
         _cap: &dyn CreatePortTableCapability,
         used_kernel_ports: &'static mut [Option<SocketBindingEntry>],
+        bound_kernel_ports: &'static mut [Option<(u16, usize)>],
         udp_vis: &'static UdpVisibilityCapability,
     ) -> UdpPortManager {
         UdpPortManager {
             port_array: TakeCell::new(used_kernel_ports),
+            bound_ports: MapCell::new(OrderedMap::new(bound_kernel_ports)),
             user_ports: OptionalCell::empty(),
             udp_vis,
         }
@@ -218,25 +222,9 @@ impl UdpPortManager {
         if user_bound {
             return Ok(true);
         };
-        let ret = self
-            .port_array
-            .map(|table| {
-                let mut port_exists = false;
-                for i in 0..MAX_NUM_BOUND_PORTS {
-                    match table[i] {
-                        Some(SocketBindingEntry::Port(p)) => {
-                            if p == port {
-                                port_exists = true;
-                                break;
-                            }
-                        }
-                        _ => (),
-                    }
-                }
-                port_exists
-            })
-            .unwrap();
-        Ok(ret)
+        Ok(self
+            .bound_ports
+            .map_or(false, |ports| ports.contains_key(&port)))
     }
 
     /// Called by capsules that have already reserved a socket to attempt to bind to
@@ -254,6 +242,11 @@ impl UdpPortManager {
                 Ok(bound) => {
                     if bound {
                         Err(socket)
+                    } else if self
+                        .bound_ports
+                        .map_or(true, |ports| ports.insert(port, socket.idx).is_err())
+                    {
+                        Err(socket)
                     } else {
                         self.port_array
                             .map(|table| {
@@ -287,6 +280,8 @@ impl UdpPortManager {
             return Err((sender_binding, receiver_binding));
         }
         let idx = sender_binding.idx;
+        self.bound_ports
+            .map(|ports| ports.remove(&sender_binding.port));
         self.port_array.map(|table| {
             table[idx] = Some(SocketBindingEntry::Unbound);
         });
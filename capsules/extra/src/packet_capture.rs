@@ -0,0 +1,550 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Capture of the frames of a network stack.
+//!
+//! Debugging a network stack usually needs an external sniffer. This capsule
+//! records the frames a stack sends and receives instead, with timestamps,
+//! into a ring buffer in the kernel. Processes read the recorded frames with
+//! the syscall driver, and the capture can also be streamed in the pcap
+//! format over a UART, for Wireshark or `tcpdump` on the host.
+//!
+//! The capture is fed by a [`PacketObserver`] hook of a stack:
+//!
+//! - The 802.15.4 MAC mux reports the frames it passes with
+//!   [`MuxMac::set_packet_observer`](crate::ieee802154::virtual_mac::MuxMac::set_packet_observer).
+//! - [`EthernetCapture`] is interposed between an Ethernet adapter and its
+//!   client, such as the IPv4 stack.
+//!
+//! When the ring buffer is full, the oldest frames are dropped to make room.
+//! Frames longer than the snapshot length are truncated. Timestamps count
+//! the microseconds since the capture first started.
+//!
+//! Records
+//! -------
+//!
+//! Each frame in the ring buffer, and in the buffer of a process that reads
+//! them, is a [`RECORD_HEADER_LEN`] byte header followed by the captured
+//! bytes of the frame. The header holds, in little endian:
+//!
+//! - the timestamp, in microseconds (8 bytes),
+//! - the length of the frame (2 bytes),
+//! - the number of bytes of the frame that were captured (2 bytes),
+//! - the direction, 0 for received and 1 for transmitted (1 byte).
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let packet_capture = components::packet_capture::PacketCaptureComponent::new(
+//!     board_kernel,
+//!     capsules_extra::packet_capture::DRIVER_NUM,
+//!     &base_peripherals.rtc,
+//!     capsules_extra::packet_capture::LinkType::Ieee802154,
+//!     128,
+//! )
+//! .finalize(components::packet_capture_component_static!(
+//!     nrf52840::rtc::Rtc<'static>,
+//!     2048
+//! ));
+//! mux_mac.set_packet_observer(packet_capture);
+//! ```
+
+use core::cell::Cell;
+use core::cmp;
+
+use capsules_core::driver;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::ethernet::{EthernetAdapterDatapath, EthernetAdapterDatapathClient};
+use kernel::hil::time::{Frequency, Ticks, Time};
+use kernel::hil::uart;
+use kernel::processbuffer::WriteableProcessBuffer;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+pub const DRIVER_NUM: usize = driver::NUM::PacketCapture as usize;
+
+/// Length of the header of the records in the ring buffer.
+pub const RECORD_HEADER_LEN: usize = 13;
+
+/// Length of the global header of a pcap stream.
+pub const PCAP_HEADER_LEN: usize = 24;
+/// Length of the header of each frame in a pcap stream.
+pub const PCAP_RECORD_HEADER_LEN: usize = 16;
+
+/// IDs for subscribed upcalls.
+mod upcall {
+    /// Frames were captured into the empty ring buffer. The argument is the
+    /// number of bytes of records in the ring buffer.
+    pub const CAPTURED: usize = 0;
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for read-write allow buffers.
+mod rw_allow {
+    /// Buffer the records are read into.
+    pub const RECORDS: usize = 0;
+    pub const COUNT: u8 = 1;
+}
+
+/// Whether a frame was received or transmitted.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Direction {
+    Received = 0,
+    Transmitted = 1,
+}
+
+/// Link layer of the captured frames, which tells tools how to decode them.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LinkType {
+    /// Ethernet frames, without the frame check sequence.
+    Ethernet,
+    /// 802.15.4 MAC frames, without the frame check sequence.
+    Ieee802154,
+}
+
+impl LinkType {
+    /// The pcap `LINKTYPE_` number of the link layer.
+    pub fn pcap_number(&self) -> u32 {
+        match self {
+            LinkType::Ethernet => 1,
+            LinkType::Ieee802154 => 230,
+        }
+    }
+}
+
+/// Receives the frames a network stack sends and receives.
+pub trait PacketObserver {
+    /// `frame` was received or transmitted. The observer only borrows the
+    /// frame, and has to copy out what it keeps.
+    fn packet(&self, direction: Direction, frame: &[u8]);
+}
+
+#[derive(Default)]
+pub struct App {}
+
+pub struct PacketCapture<'a, T: Time> {
+    time: &'a T,
+    link_type: LinkType,
+    /// Number of bytes of a frame that are captured.
+    snap_len: u16,
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<0>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+    ring: TakeCell<'static, [u8]>,
+    /// Offset of the oldest record in the ring buffer.
+    ring_start: Cell<usize>,
+    /// Number of bytes of records in the ring buffer.
+    ring_used: Cell<usize>,
+    /// Number of frames dropped since the records were last read.
+    dropped: Cell<u32>,
+    capturing: Cell<bool>,
+    /// Whether the capture started before, and the time base is set.
+    started: Cell<bool>,
+    last_now: Cell<T::Ticks>,
+    /// Ticks since the capture first started.
+    ticks: Cell<u64>,
+    uart: OptionalCell<&'a dyn uart::Transmit<'a>>,
+    uart_buffer: TakeCell<'static, [u8]>,
+    /// Whether the global header of the pcap stream is yet to be sent.
+    pcap_header_pending: Cell<bool>,
+}
+
+impl<'a, T: Time> PacketCapture<'a, T> {
+    pub fn new(
+        time: &'a T,
+        link_type: LinkType,
+        snap_len: u16,
+        ring: &'static mut [u8],
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<0>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+    ) -> Self {
+        Self {
+            time,
+            link_type,
+            snap_len,
+            apps: grant,
+            ring: TakeCell::new(ring),
+            ring_start: Cell::new(0),
+            ring_used: Cell::new(0),
+            dropped: Cell::new(0),
+            capturing: Cell::new(false),
+            started: Cell::new(false),
+            last_now: Cell::new(T::Ticks::from(0)),
+            ticks: Cell::new(0),
+            uart: OptionalCell::empty(),
+            uart_buffer: TakeCell::empty(),
+            pcap_header_pending: Cell::new(false),
+        }
+    }
+
+    /// Stream the capture in the pcap format over `uart`.
+    ///
+    /// Frames are sent from `buffer`, so they are truncated to the length of
+    /// `buffer` less [`PCAP_RECORD_HEADER_LEN`]. Frames sent over the UART
+    /// are removed from the ring buffer, and processes do not read them.
+    /// Must be called before the capture starts.
+    pub fn set_pcap_uart(&self, uart: &'a dyn uart::Transmit<'a>, buffer: &'static mut [u8]) {
+        self.uart.set(uart);
+        self.uart_buffer.replace(buffer);
+    }
+
+    /// Start recording frames.
+    pub fn start(&self) {
+        if !self.started.get() {
+            self.started.set(true);
+            self.last_now.set(self.time.now());
+            self.pcap_header_pending.set(self.uart.is_some());
+        }
+        self.capturing.set(true);
+        self.stream();
+    }
+
+    /// Stop recording frames. The frames recorded stay in the ring buffer.
+    pub fn stop(&self) {
+        self.capturing.set(false);
+    }
+
+    /// Microseconds since the capture first started.
+    fn timestamp_us(&self) -> u64 {
+        let now = self.time.now();
+        let elapsed = now.wrapping_sub(self.last_now.get()).into_u32();
+        self.last_now.set(now);
+        let ticks = self.ticks.get() + elapsed as u64;
+        self.ticks.set(ticks);
+
+        let frequency = T::Frequency::frequency() as u64;
+        (ticks / frequency) * 1_000_000 + (ticks % frequency) * 1_000_000 / frequency
+    }
+
+    /// Header of the oldest record in `ring`.
+    fn oldest_header(&self, ring: &[u8]) -> [u8; RECORD_HEADER_LEN] {
+        let mut header = [0; RECORD_HEADER_LEN];
+        ring_read(ring, self.ring_start.get(), &mut header);
+        header
+    }
+
+    /// Remove the oldest record, of `len` bytes, from `ring`.
+    fn remove_oldest(&self, ring: &[u8], len: usize) {
+        self.ring_start
+            .set((self.ring_start.get() + len) % ring.len());
+        self.ring_used.set(self.ring_used.get() - len);
+    }
+
+    /// Copy as many of the oldest records as fit into the buffer of
+    /// `processid`, and remove them. Returns the number of bytes copied
+    /// and the number of frames dropped since the last read.
+    fn read_records(&self, processid: ProcessId) -> Result<(u32, u32), ErrorCode> {
+        self.apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readwrite_processbuffer(rw_allow::RECORDS)
+                    .and_then(|buffer| {
+                        buffer.mut_enter(|dest| {
+                            self.ring.map_or(0, |ring| {
+                                let mut copied = 0;
+                                while self.ring_used.get() > 0 {
+                                    let header = self.oldest_header(ring);
+                                    let len = record_len(&header);
+                                    if copied + len > dest.len() {
+                                        break;
+                                    }
+                                    let start = self.ring_start.get();
+                                    let first = cmp::min(len, ring.len() - start);
+                                    dest[copied..copied + first]
+                                        .copy_from_slice(&ring[start..start + first]);
+                                    dest[copied + first..copied + len]
+                                        .copy_from_slice(&ring[..len - first]);
+                                    copied += len;
+                                    self.remove_oldest(ring, len);
+                                }
+                                copied
+                            })
+                        })
+                    })
+                    .map_err(ErrorCode::from)
+            })
+            .map_err(ErrorCode::from)
+            .and_then(|copied| copied)
+            .map(|copied| (copied as u32, self.dropped.replace(0)))
+    }
+
+    /// Send the next part of the pcap stream, if the UART is idle.
+    fn stream(&self) {
+        self.uart.map(|uart| {
+            self.uart_buffer.take().map(|buffer| {
+                let len = if self.pcap_header_pending.get() {
+                    self.pcap_header_pending.set(false);
+                    self.write_pcap_header(buffer)
+                } else {
+                    self.ring
+                        .map_or(0, |ring| self.write_pcap_record(ring, buffer))
+                };
+                if len == 0 {
+                    self.uart_buffer.replace(buffer);
+                } else if let Err((_, buffer)) = uart.transmit_buffer(buffer, len) {
+                    self.uart_buffer.replace(buffer);
+                }
+            });
+        });
+    }
+
+    /// Write the global header of the pcap stream into `buffer`.
+    fn write_pcap_header(&self, buffer: &mut [u8]) -> usize {
+        let snap_len = cmp::min(
+            self.snap_len as usize,
+            buffer.len().saturating_sub(PCAP_RECORD_HEADER_LEN),
+        );
+        if buffer.len() < PCAP_HEADER_LEN {
+            return 0;
+        }
+        buffer[0..4].copy_from_slice(&0xa1b2c3d4u32.to_le_bytes());
+        // Version 2.4.
+        buffer[4..6].copy_from_slice(&2u16.to_le_bytes());
+        buffer[6..8].copy_from_slice(&4u16.to_le_bytes());
+        // Timestamps are in UTC, and their accuracy is not given.
+        buffer[8..16].fill(0);
+        buffer[16..20].copy_from_slice(&(snap_len as u32).to_le_bytes());
+        buffer[20..24].copy_from_slice(&self.link_type.pcap_number().to_le_bytes());
+        PCAP_HEADER_LEN
+    }
+
+    /// Move the oldest record of `ring` into `buffer` as a pcap record.
+    fn write_pcap_record(&self, ring: &[u8], buffer: &mut [u8]) -> usize {
+        if self.ring_used.get() == 0 || buffer.len() < PCAP_RECORD_HEADER_LEN {
+            return 0;
+        }
+        let header = self.oldest_header(ring);
+        let timestamp = u64::from_le_bytes([
+            header[0], header[1], header[2], header[3], header[4], header[5], header[6], header[7],
+        ]);
+        let original = u16::from_le_bytes([header[8], header[9]]) as usize;
+        let captured = u16::from_le_bytes([header[10], header[11]]) as usize;
+        let included = cmp::min(captured, buffer.len() - PCAP_RECORD_HEADER_LEN);
+
+        buffer[0..4].copy_from_slice(&((timestamp / 1_000_000) as u32).to_le_bytes());
+        buffer[4..8].copy_from_slice(&((timestamp % 1_000_000) as u32).to_le_bytes());
+        buffer[8..12].copy_from_slice(&(included as u32).to_le_bytes());
+        buffer[12..16].copy_from_slice(&(original as u32).to_le_bytes());
+        ring_read(
+            ring,
+            (self.ring_start.get() + RECORD_HEADER_LEN) % ring.len(),
+            &mut buffer[PCAP_RECORD_HEADER_LEN..PCAP_RECORD_HEADER_LEN + included],
+        );
+        self.remove_oldest(ring, RECORD_HEADER_LEN + captured);
+        PCAP_RECORD_HEADER_LEN + included
+    }
+}
+
+/// Length of the record starting with `header`.
+fn record_len(header: &[u8; RECORD_HEADER_LEN]) -> usize {
+    RECORD_HEADER_LEN + u16::from_le_bytes([header[10], header[11]]) as usize
+}
+
+/// Copy `ring`, from `offset` and wrapping around its end, into `out`.
+fn ring_read(ring: &[u8], offset: usize, out: &mut [u8]) {
+    let first = cmp::min(out.len(), ring.len() - offset);
+    out[..first].copy_from_slice(&ring[offset..offset + first]);
+    let rest = out.len() - first;
+    out[first..].copy_from_slice(&ring[..rest]);
+}
+
+/// Copy `data` into `ring`, from `offset` and wrapping around its end.
+/// Returns the offset after `data`.
+fn ring_write(ring: &mut [u8], offset: usize, data: &[u8]) -> usize {
+    let first = cmp::min(data.len(), ring.len() - offset);
+    ring[offset..offset + first].copy_from_slice(&data[..first]);
+    let rest = data.len() - first;
+    ring[..rest].copy_from_slice(&data[first..]);
+    (offset + data.len()) % ring.len()
+}
+
+impl<T: Time> PacketObserver for PacketCapture<'_, T> {
+    fn packet(&self, direction: Direction, frame: &[u8]) {
+        if !self.capturing.get() {
+            return;
+        }
+        let timestamp = self.timestamp_us();
+        let was_empty = self.ring_used.get() == 0;
+
+        self.ring.map(|ring| {
+            if ring.len() <= RECORD_HEADER_LEN {
+                return;
+            }
+            let captured = cmp::min(
+                cmp::min(frame.len(), self.snap_len as usize),
+                ring.len() - RECORD_HEADER_LEN,
+            );
+            let len = RECORD_HEADER_LEN + captured;
+            while ring.len() - self.ring_used.get() < len {
+                let oldest = record_len(&self.oldest_header(ring));
+                self.remove_oldest(ring, oldest);
+                self.dropped.set(self.dropped.get().saturating_add(1));
+            }
+
+            let mut header = [0; RECORD_HEADER_LEN];
+            header[0..8].copy_from_slice(&timestamp.to_le_bytes());
+            header[8..10].copy_from_slice(&(cmp::min(frame.len(), 0xffff) as u16).to_le_bytes());
+            header[10..12].copy_from_slice(&(captured as u16).to_le_bytes());
+            header[12] = direction as u8;
+
+            let end = (self.ring_start.get() + self.ring_used.get()) % ring.len();
+            let end = ring_write(ring, end, &header);
+            ring_write(ring, end, &frame[..captured]);
+            self.ring_used.set(self.ring_used.get() + len);
+        });
+
+        if was_empty && self.ring_used.get() > 0 {
+            let used = self.ring_used.get();
+            self.apps.each(|_, _, kernel_data| {
+                kernel_data
+                    .schedule_upcall(upcall::CAPTURED, (used, 0, 0))
+                    .ok();
+            });
+        }
+        self.stream();
+    }
+}
+
+impl<T: Time> uart::TransmitClient for PacketCapture<'_, T> {
+    fn transmitted_buffer(
+        &self,
+        tx_buffer: &'static mut [u8],
+        _tx_len: usize,
+        _rval: Result<(), ErrorCode>,
+    ) {
+        self.uart_buffer.replace(tx_buffer);
+        self.stream();
+    }
+}
+
+impl<T: Time> SyscallDriver for PacketCapture<'_, T> {
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Start capturing frames.
+    /// - `2`: Stop capturing frames.
+    /// - `3`: Read the oldest records that fit into the allowed buffer.
+    ///   Returns the number of bytes read and the number of frames dropped
+    ///   since the last read.
+    /// - `4`: Return the pcap link type of the frames.
+    fn command(
+        &self,
+        command_num: usize,
+        _arg1: usize,
+        _arg2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => {
+                self.start();
+                CommandReturn::success()
+            }
+
+            2 => {
+                self.stop();
+                CommandReturn::success()
+            }
+
+            3 => match self.read_records(processid) {
+                Ok((copied, dropped)) => CommandReturn::success_u32_u32(copied, dropped),
+                Err(e) => CommandReturn::failure(e),
+            },
+
+            4 => CommandReturn::success_u32(self.link_type.pcap_number()),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+/// Interposes between an Ethernet adapter and its client, and reports the
+/// frames received and sent to a [`PacketObserver`].
+///
+/// Transmitted frames are reported once the adapter sent them.
+pub struct EthernetCapture<'a> {
+    adapter: &'a dyn EthernetAdapterDatapath<'a>,
+    observer: &'a dyn PacketObserver,
+    client: OptionalCell<&'a dyn EthernetAdapterDatapathClient>,
+}
+
+impl<'a> EthernetCapture<'a> {
+    pub fn new(
+        adapter: &'a dyn EthernetAdapterDatapath<'a>,
+        observer: &'a dyn PacketObserver,
+    ) -> Self {
+        Self {
+            adapter,
+            observer,
+            client: OptionalCell::empty(),
+        }
+    }
+}
+
+impl<'a> EthernetAdapterDatapath<'a> for EthernetCapture<'a> {
+    fn set_client(&self, client: &'a dyn EthernetAdapterDatapathClient) {
+        self.client.set(client);
+    }
+
+    fn enable_receive(&self) {
+        self.adapter.enable_receive();
+    }
+
+    fn disable_receive(&self) {
+        self.adapter.disable_receive();
+    }
+
+    fn transmit_frame(
+        &self,
+        frame_buffer: &'static mut [u8],
+        len: u16,
+        transmission_identifier: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        self.adapter
+            .transmit_frame(frame_buffer, len, transmission_identifier)
+    }
+}
+
+impl EthernetAdapterDatapathClient for EthernetCapture<'_> {
+    fn transmit_frame_done(
+        &self,
+        err: Result<(), ErrorCode>,
+        frame_buffer: &'static mut [u8],
+        len: u16,
+        transmission_identifier: usize,
+        timestamp: Option<u64>,
+    ) {
+        if err.is_ok() {
+            let len = cmp::min(len as usize, frame_buffer.len());
+            self.observer
+                .packet(Direction::Transmitted, &frame_buffer[..len]);
+        }
+        self.client.map(|client| {
+            client.transmit_frame_done(err, frame_buffer, len, transmission_identifier, timestamp)
+        });
+    }
+
+    fn received_frame(&self, frame: &[u8], timestamp: Option<u64>) {
+        self.observer.packet(Direction::Received, frame);
+        self.client
+            .map(|client| client.received_frame(frame, timestamp));
+    }
+}
@@ -0,0 +1,359 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Driver for the NXP PCF8523 real time clock, using the I2C bus.
+//!
+//! <https://www.nxp.com/docs/en/data-sheet/PCF8523.pdf>
+//!
+//! > The PCF8523 is a CMOS Real-Time Clock (RTC) and calendar optimized for
+//! > low power consumption. [...] It has a backup battery switch-over
+//! > circuit, which detects power failures and automatically switches to the
+//! > battery supply when a power failure occurs.
+//!
+//! Driver Semantics
+//! ----------------
+//!
+//! The driver implements the [`DateTime`] HIL, for example for the date-time
+//! syscall driver, and the [`DateTimeAlarm`] HIL. The alarm compares the day
+//! of the month, the hour and the minute: it fires at the start of the
+//! minute.
+//!
+//! Setting the date and time also enables the switch-over to the backup
+//! battery, which is disabled when the RTC is first powered.
+//!
+//! The INT1 output of the RTC must be connected to an interrupt pin. It
+//! stays low until the driver handles the alarm, so the alarm wakes the chip
+//! from any sleep mode in which GPIO interrupts wake it, even when the
+//! internal RTC of the chip is stopped.
+//!
+//! If the oscillator of the RTC stopped, for example because the backup
+//! battery ran out, reading the date and time fails with `FAIL` until it is
+//! set again.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let pcf8523 = components::pcf8523::Pcf8523Component::new(mux_i2c, 0x68, rtc_int_pin)
+//!     .finalize(components::pcf8523_component_static!(nrf52840::i2c::TWI));
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::date_time::{
+    DateTime, DateTimeAlarm, DateTimeAlarmClient, DateTimeClient, DateTimeValues,
+};
+use kernel::hil::gpio;
+use kernel::hil::i2c::{self, I2CClient, I2CDevice};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+use crate::i2c_rtc;
+
+/// Size of the buffer of the driver.
+pub const BUFFER_LEN: usize = 10;
+
+// Registers.
+const CONTROL_1: u8 = 0x00;
+const CONTROL_3: u8 = 0x02;
+const MINUTE_ALARM: u8 = 0x0A;
+
+const CONTROL_1_12_24: u8 = 1 << 3;
+const CONTROL_1_AIE: u8 = 1 << 1;
+const CONTROL_2_AF: u8 = 1 << 3;
+const SECONDS_OS: u8 = 1 << 7;
+const HOURS_PM: u8 = 1 << 5;
+/// Disables the comparison of an alarm register.
+const ALARM_DISABLE: u8 = 1 << 7;
+
+/// What the control registers are updated for.
+#[derive(Clone, Copy, PartialEq)]
+enum Operation {
+    SetDateTime,
+    SetAlarm,
+    DisarmAlarm,
+    AlarmFired,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    ReadDateTime,
+    WriteDateTime,
+    WriteAlarm,
+    ReadControl(Operation),
+    WriteControl(Operation),
+}
+
+pub struct Pcf8523<'a, I: I2CDevice> {
+    i2c: &'a I,
+    interrupt_pin: &'a dyn gpio::InterruptPin<'a>,
+    buffer: TakeCell<'static, [u8]>,
+    state: Cell<State>,
+    /// The alarm fired while another operation was in progress.
+    alarm_pending: Cell<bool>,
+    client: OptionalCell<&'a dyn DateTimeClient>,
+    alarm_client: OptionalCell<&'a dyn DateTimeAlarmClient>,
+}
+
+impl<'a, I: I2CDevice> Pcf8523<'a, I> {
+    pub fn new(
+        i2c: &'a I,
+        interrupt_pin: &'a dyn gpio::InterruptPin<'a>,
+        buffer: &'static mut [u8],
+    ) -> Self {
+        Self {
+            i2c,
+            interrupt_pin,
+            buffer: TakeCell::new(buffer),
+            state: Cell::new(State::Idle),
+            alarm_pending: Cell::new(false),
+            client: OptionalCell::empty(),
+            alarm_client: OptionalCell::empty(),
+        }
+    }
+
+    /// Write the first `write_len` bytes of the buffer, filled by `fill`, and
+    /// then read `read_len` bytes if it is not zero.
+    fn start(
+        &self,
+        state: State,
+        write_len: usize,
+        read_len: usize,
+        fill: impl FnOnce(&mut [u8]),
+    ) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        let buffer = self.buffer.take().ok_or(ErrorCode::BUSY)?;
+        fill(buffer);
+        self.i2c.enable();
+        let result = if read_len == 0 {
+            self.i2c.write(buffer, write_len)
+        } else {
+            self.i2c.write_read(buffer, write_len, read_len)
+        };
+        match result {
+            Ok(()) => {
+                self.state.set(state);
+                Ok(())
+            }
+            Err((error, buffer)) => {
+                self.buffer.replace(buffer);
+                self.i2c.disable();
+                Err(error.into())
+            }
+        }
+    }
+
+    /// Continue an operation from `command_complete`.
+    fn next(
+        &self,
+        buffer: &'static mut [u8],
+        state: State,
+        write_len: usize,
+        read_len: usize,
+    ) -> Result<(), ErrorCode> {
+        let result = if read_len == 0 {
+            self.i2c.write(buffer, write_len)
+        } else {
+            self.i2c.write_read(buffer, write_len, read_len)
+        };
+        match result {
+            Ok(()) => {
+                self.state.set(state);
+                Ok(())
+            }
+            Err((error, buffer)) => {
+                self.buffer.replace(buffer);
+                Err(error.into())
+            }
+        }
+    }
+
+    fn read_control(&self, operation: Operation) -> Result<(), ErrorCode> {
+        self.start(State::ReadControl(operation), 1, 2, |buffer| {
+            buffer[0] = CONTROL_1;
+        })
+    }
+
+    fn done(&self, operation: Operation, result: Result<(), ErrorCode>) {
+        self.state.set(State::Idle);
+        self.i2c.disable();
+        match operation {
+            Operation::SetDateTime => self.client.map(|client| client.set_date_time_done(result)),
+            Operation::SetAlarm => {
+                if result.is_err() {
+                    self.interrupt_pin.disable_interrupts();
+                }
+                self.alarm_client
+                    .map(|client| client.set_alarm_done(result))
+            }
+            Operation::DisarmAlarm => self
+                .alarm_client
+                .map(|client| client.disarm_alarm_done(result)),
+            Operation::AlarmFired => self.alarm_client.map(|client| client.alarm()),
+        };
+        if self.alarm_pending.take() {
+            let _ = self.read_control(Operation::AlarmFired);
+        }
+    }
+
+    fn decode_date_time(buffer: &[u8]) -> Result<DateTimeValues, ErrorCode> {
+        if buffer[3] & SECONDS_OS != 0 {
+            return Err(ErrorCode::FAIL);
+        }
+        Ok(DateTimeValues {
+            year: 2000 + i2c_rtc::from_bcd(buffer[9]) as u16,
+            month: i2c_rtc::month_from_number(i2c_rtc::from_bcd(buffer[8] & 0x1F))?,
+            day: i2c_rtc::from_bcd(buffer[6] & 0x3F),
+            day_of_week: i2c_rtc::day_of_week_from_number(buffer[7] & 0x07)?,
+            hour: i2c_rtc::hours_from_bcd(buffer[5], buffer[0] & CONTROL_1_12_24 != 0, HOURS_PM),
+            minute: i2c_rtc::from_bcd(buffer[4] & 0x7F),
+            seconds: i2c_rtc::from_bcd(buffer[3] & 0x7F),
+        })
+    }
+}
+
+impl<'a, I: I2CDevice> DateTime<'a> for Pcf8523<'a, I> {
+    fn get_date_time(&self) -> Result<(), ErrorCode> {
+        self.start(State::ReadDateTime, 1, BUFFER_LEN, |buffer| {
+            buffer[0] = CONTROL_1;
+        })
+    }
+
+    fn set_date_time(&self, date_time: DateTimeValues) -> Result<(), ErrorCode> {
+        i2c_rtc::check_date_time(&date_time, 2099)?;
+        // Writing the seconds clears the oscillator stop flag. Clearing
+        // control register 3 enables the battery switch-over.
+        self.start(State::WriteDateTime, 9, 0, |buffer| {
+            buffer[0] = CONTROL_3;
+            buffer[1] = 0;
+            buffer[2] = i2c_rtc::to_bcd(date_time.seconds);
+            buffer[3] = i2c_rtc::to_bcd(date_time.minute);
+            buffer[4] = i2c_rtc::to_bcd(date_time.hour);
+            buffer[5] = i2c_rtc::to_bcd(date_time.day);
+            buffer[6] = i2c_rtc::day_of_week_number(date_time.day_of_week);
+            buffer[7] = i2c_rtc::to_bcd(i2c_rtc::month_number(date_time.month));
+            buffer[8] = i2c_rtc::to_bcd((date_time.year % 100) as u8);
+        })
+    }
+
+    fn set_client(&self, client: &'a dyn DateTimeClient) {
+        self.client.set(client);
+    }
+}
+
+impl<'a, I: I2CDevice> DateTimeAlarm<'a> for Pcf8523<'a, I> {
+    fn set_alarm(&self, date_time: DateTimeValues) -> Result<(), ErrorCode> {
+        i2c_rtc::check_date_time(
+            &DateTimeValues {
+                year: 2000,
+                ..date_time
+            },
+            2000,
+        )?;
+        // The day of the week is not compared.
+        self.start(State::WriteAlarm, 5, 0, |buffer| {
+            buffer[0] = MINUTE_ALARM;
+            buffer[1] = i2c_rtc::to_bcd(date_time.minute);
+            buffer[2] = i2c_rtc::to_bcd(date_time.hour);
+            buffer[3] = i2c_rtc::to_bcd(date_time.day);
+            buffer[4] = ALARM_DISABLE;
+        })?;
+        self.interrupt_pin.make_input();
+        self.interrupt_pin
+            .set_floating_state(gpio::FloatingState::PullUp);
+        self.interrupt_pin
+            .enable_interrupts(gpio::InterruptEdge::FallingEdge);
+        Ok(())
+    }
+
+    fn disarm_alarm(&self) -> Result<(), ErrorCode> {
+        self.read_control(Operation::DisarmAlarm)?;
+        self.interrupt_pin.disable_interrupts();
+        Ok(())
+    }
+
+    fn set_alarm_client(&self, client: &'a dyn DateTimeAlarmClient) {
+        self.alarm_client.set(client);
+    }
+}
+
+impl<I: I2CDevice> gpio::Client for Pcf8523<'_, I> {
+    fn fired(&self) {
+        // The alarm fires once.
+        self.interrupt_pin.disable_interrupts();
+        if self.read_control(Operation::AlarmFired).is_err() {
+            self.alarm_pending.set(true);
+        }
+    }
+}
+
+impl<I: I2CDevice> I2CClient for Pcf8523<'_, I> {
+    fn command_complete(&self, buffer: &'static mut [u8], status: Result<(), i2c::Error>) {
+        let state = self.state.get();
+        if let Err(error) = status {
+            self.buffer.replace(buffer);
+            match state {
+                State::ReadDateTime => {
+                    self.state.set(State::Idle);
+                    self.i2c.disable();
+                    self.client
+                        .map(|client| client.get_date_time_done(Err(error.into())));
+                }
+                State::WriteDateTime => self.done(Operation::SetDateTime, Err(error.into())),
+                State::WriteAlarm => self.done(Operation::SetAlarm, Err(error.into())),
+                State::ReadControl(operation) | State::WriteControl(operation) => {
+                    self.done(operation, Err(error.into()))
+                }
+                State::Idle => {}
+            }
+            return;
+        }
+
+        match state {
+            State::ReadDateTime => {
+                let date_time = Self::decode_date_time(buffer);
+                self.buffer.replace(buffer);
+                self.state.set(State::Idle);
+                self.i2c.disable();
+                self.client
+                    .map(|client| client.get_date_time_done(date_time));
+            }
+            State::WriteDateTime => {
+                self.buffer.replace(buffer);
+                self.done(Operation::SetDateTime, Ok(()));
+            }
+            State::WriteAlarm => {
+                buffer[0] = CONTROL_1;
+                if let Err(e) = self.next(buffer, State::ReadControl(Operation::SetAlarm), 1, 2) {
+                    self.done(Operation::SetAlarm, Err(e));
+                }
+            }
+            State::ReadControl(operation) => {
+                let (control_1, control_2) = (buffer[0], buffer[1]);
+                // The flags of control register 2 are cleared by writing 0,
+                // and kept by writing 1.
+                let control_1 = match operation {
+                    Operation::SetAlarm => control_1 | CONTROL_1_AIE,
+                    _ => control_1 & !CONTROL_1_AIE,
+                };
+                buffer[0] = CONTROL_1;
+                buffer[1] = control_1;
+                buffer[2] = control_2 & !CONTROL_2_AF;
+                if let Err(e) = self.next(buffer, State::WriteControl(operation), 3, 0) {
+                    self.done(operation, Err(e));
+                }
+            }
+            State::WriteControl(operation) => {
+                self.buffer.replace(buffer);
+                self.done(operation, Ok(()));
+            }
+            State::Idle => {
+                self.buffer.replace(buffer);
+            }
+        }
+    }
+}
@@ -0,0 +1,385 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Plays PCM audio from userspace through a PWM pin or a DAC.
+//!
+//! This provides basic audio output on boards without I2S. Each sample is
+//! output from an alarm callback, so the sample rate is limited by the
+//! frequency of the alarm and the interrupt load, and the quality is that
+//! of the output: a PWM pin needs a low pass filter, and its duty cycle
+//! resolution limits the number of bits of each sample that are played.
+//!
+//! A process shares two sample buffers, and queues them in turn while the
+//! other one plays. The capsule notifies the process when a buffer finished
+//! playing, so it can be refilled and queued again. Playback stops when the
+//! next buffer is not queued in time. Only one process plays at a time.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let audio = static_init!(
+//!     capsules_extra::pcm_audio::PcmAudio<'static, VirtualMuxAlarm<'static, Rtc>>,
+//!     capsules_extra::pcm_audio::PcmAudio::new(
+//!         audio_alarm,
+//!         capsules_extra::pcm_audio::AudioOutput::Pwm {
+//!             pin: audio_pwm_pin,
+//!             carrier_hz: 62_500,
+//!         },
+//!         board_kernel.create_grant(capsules_extra::pcm_audio::DRIVER_NUM, &grant_cap),
+//!     )
+//! );
+//! audio_alarm.set_alarm_client(audio);
+//! ```
+//!
+//! Syscall Interface
+//! -----------------
+//!
+//! ### Allow
+//!
+//! - Read-only allow `0` and `1`: the two sample buffers. Samples are
+//!   either unsigned 8 bit, or signed 16 bit little endian.
+//!
+//! ### Command
+//!
+//! - `0`: Driver existence check.
+//! - `1`: Start playing, at `data1` samples per second, with the sample
+//!   format `data2` (`0`: unsigned 8 bit, `1`: signed 16 bit). Buffer `0`
+//!   must be queued; it plays first. Returns `BUSY` if another process is
+//!   playing.
+//! - `2`: Queue buffer `data1` to play the first `data2` bytes of it (all of
+//!   it if `data2` is `0`). Returns `BUSY` if the buffer is already queued.
+//! - `3`: Stop playing and unqueue both buffers.
+//!
+//! ### Subscribe
+//!
+//! - `0`: Called when a buffer finished playing. The first argument is the
+//!   number of the buffer, the second is `1` if playback stopped because the
+//!   next buffer was not queued, and `0` otherwise.
+
+use core::cell::Cell;
+use core::cmp;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::dac::DacChannel;
+use kernel::hil::pwm::PwmPin;
+use kernel::hil::time::{Alarm, AlarmClient, Frequency};
+use kernel::processbuffer::ReadableProcessBuffer;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::OptionalCell;
+use kernel::{ErrorCode, ProcessId};
+
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::Audio as usize;
+
+/// Highest sample rate processes can request.
+pub const MAX_SAMPLE_RATE_HZ: usize = 22_050;
+
+/// Number of samples copied from the process buffer at a time.
+const CHUNK_LEN: usize = 32;
+
+const NUM_BUFFERS: usize = 2;
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = super::NUM_BUFFERS as u8;
+}
+
+/// Where samples are played.
+pub enum AudioOutput<'a> {
+    /// The duty cycle of a PWM pin running at `carrier_hz`. The carrier
+    /// should be well above the highest sample rate, and filtered out.
+    Pwm {
+        pin: &'a dyn PwmPin,
+        carrier_hz: usize,
+    },
+    /// A DAC channel that takes values of `bits` bits.
+    Dac {
+        channel: &'a dyn DacChannel,
+        bits: u8,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum SampleFormat {
+    Unsigned8,
+    Signed16,
+}
+
+impl SampleFormat {
+    fn sample_len(self) -> usize {
+        match self {
+            SampleFormat::Unsigned8 => 1,
+            SampleFormat::Signed16 => 2,
+        }
+    }
+
+    /// Decode the sample in `bytes` to an unsigned 16 bit value.
+    fn decode(self, bytes: &[u8]) -> u16 {
+        match self {
+            SampleFormat::Unsigned8 => (bytes[0] as u16) << 8,
+            SampleFormat::Signed16 => {
+                (i16::from_le_bytes([bytes[0], bytes[1]]) as u16).wrapping_add(0x8000)
+            }
+        }
+    }
+}
+
+/// Value of the output when no sample is played.
+const SILENCE: u16 = 0x8000;
+
+#[derive(Default)]
+pub struct App {
+    /// Number of bytes to play of each queued buffer.
+    queued: [Option<usize>; NUM_BUFFERS],
+}
+
+pub struct PcmAudio<'a, A: Alarm<'a>> {
+    alarm: &'a A,
+    output: AudioOutput<'a>,
+    apps: Grant<App, UpcallCount<1>, AllowRoCount<{ ro_allow::COUNT }>, AllowRwCount<0>>,
+    /// Process that is playing.
+    playing: OptionalCell<ProcessId>,
+    rate: Cell<usize>,
+    format: Cell<SampleFormat>,
+    /// Buffer that is playing, and the offset of the next sample in it.
+    buffer: Cell<usize>,
+    offset: Cell<usize>,
+    /// Samples copied from the buffer, and the index of the next one to play.
+    chunk: [Cell<u16>; CHUNK_LEN],
+    chunk_len: Cell<usize>,
+    chunk_index: Cell<usize>,
+    /// Index of the next sample within the current second, used to compute
+    /// the time of each sample without accumulating rounding errors.
+    sample_index: Cell<usize>,
+}
+
+impl<'a, A: Alarm<'a>> PcmAudio<'a, A> {
+    pub fn new(
+        alarm: &'a A,
+        output: AudioOutput<'a>,
+        grant: Grant<App, UpcallCount<1>, AllowRoCount<{ ro_allow::COUNT }>, AllowRwCount<0>>,
+    ) -> PcmAudio<'a, A> {
+        PcmAudio {
+            alarm,
+            output,
+            apps: grant,
+            playing: OptionalCell::empty(),
+            rate: Cell::new(0),
+            format: Cell::new(SampleFormat::Unsigned8),
+            buffer: Cell::new(0),
+            offset: Cell::new(0),
+            chunk: [const { Cell::new(SILENCE) }; CHUNK_LEN],
+            chunk_len: Cell::new(0),
+            chunk_index: Cell::new(0),
+            sample_index: Cell::new(0),
+        }
+    }
+
+    fn output_sample(&self, sample: u16) {
+        let _ = match self.output {
+            AudioOutput::Pwm { pin, carrier_hz } => {
+                let duty_cycle = sample as usize * pin.get_maximum_duty_cycle() / 0x10000;
+                pin.start(carrier_hz, duty_cycle)
+            }
+            AudioOutput::Dac { channel, bits } => {
+                channel.set_value((sample >> (16 - cmp::min(bits, 16))) as usize)
+            }
+        };
+    }
+
+    fn start(&self, processid: ProcessId, rate: usize, format: usize) -> Result<(), ErrorCode> {
+        if self
+            .playing
+            .get()
+            .is_some_and(|playing| playing != processid)
+        {
+            return Err(ErrorCode::BUSY);
+        }
+        let format = match format {
+            0 => SampleFormat::Unsigned8,
+            1 => SampleFormat::Signed16,
+            _ => return Err(ErrorCode::INVAL),
+        };
+        let max_rate = cmp::min(MAX_SAMPLE_RATE_HZ, A::Frequency::frequency() as usize / 2);
+        if rate == 0 || rate > max_rate {
+            return Err(ErrorCode::INVAL);
+        }
+        let queued = self
+            .apps
+            .enter(processid, |app, _| app.queued[0].is_some())
+            .map_err(ErrorCode::from)?;
+        if !queued {
+            return Err(ErrorCode::INVAL);
+        }
+
+        let _ = self.alarm.disarm();
+        self.playing.set(processid);
+        self.rate.set(rate);
+        self.format.set(format);
+        self.buffer.set(0);
+        self.offset.set(0);
+        self.chunk_len.set(0);
+        self.chunk_index.set(0);
+        self.sample_index.set(0);
+        self.alarm.set_alarm(self.alarm.now(), A::Ticks::from(0));
+        Ok(())
+    }
+
+    fn stop(&self) {
+        let _ = self.alarm.disarm();
+        if let Some(processid) = self.playing.take() {
+            let _ = self.apps.enter(processid, |app, _| {
+                app.queued = [None; NUM_BUFFERS];
+            });
+        }
+        match self.output {
+            AudioOutput::Pwm { pin, .. } => {
+                let _ = pin.stop();
+            }
+            AudioOutput::Dac { .. } => self.output_sample(SILENCE),
+        }
+    }
+
+    /// Copy the next samples of the playing buffer. Returns `false` if the
+    /// buffer has no samples left.
+    fn fill_chunk(&self, processid: ProcessId) -> bool {
+        let format = self.format.get();
+        let sample_len = format.sample_len();
+        let buffer = self.buffer.get();
+        let offset = self.offset.get();
+        let copied = self
+            .apps
+            .enter(processid, |app, kernel_data| {
+                let queued_len = app.queued[buffer].unwrap_or(0);
+                kernel_data
+                    .get_readonly_processbuffer(buffer)
+                    .and_then(|samples| {
+                        samples.enter(|samples| {
+                            let end = cmp::min(queued_len, samples.len());
+                            let available = end.saturating_sub(offset) / sample_len;
+                            let count = cmp::min(available, CHUNK_LEN);
+                            let mut bytes = [0; 2];
+                            for (i, cell) in self.chunk.iter().take(count).enumerate() {
+                                let start = offset + i * sample_len;
+                                samples[start..start + sample_len]
+                                    .copy_to_slice(&mut bytes[..sample_len]);
+                                cell.set(format.decode(&bytes));
+                            }
+                            count
+                        })
+                    })
+                    .unwrap_or(0)
+            })
+            .unwrap_or(0);
+        self.offset.set(offset + copied * sample_len);
+        self.chunk_len.set(copied);
+        self.chunk_index.set(0);
+        copied > 0
+    }
+
+    /// Next sample to play, or `None` if playback ended.
+    fn next_sample(&self, processid: ProcessId) -> Option<u16> {
+        if self.chunk_index.get() == self.chunk_len.get() && !self.fill_chunk(processid) {
+            // The buffer finished playing: notify the process, and switch to
+            // the other buffer.
+            let finished = self.buffer.get();
+            let next = (finished + 1) % NUM_BUFFERS;
+            self.buffer.set(next);
+            self.offset.set(0);
+            let next_queued = self
+                .apps
+                .enter(processid, |app, kernel_data| {
+                    app.queued[finished] = None;
+                    let next_queued = app.queued[next].is_some();
+                    let _ = kernel_data.schedule_upcall(0, (finished, !next_queued as usize, 0));
+                    next_queued
+                })
+                .unwrap_or(false);
+            if !next_queued || !self.fill_chunk(processid) {
+                return None;
+            }
+        }
+        let index = self.chunk_index.get();
+        self.chunk_index.set(index + 1);
+        Some(self.chunk[index].get())
+    }
+
+    /// Ticks from the start of the current second to sample `index`.
+    fn sample_ticks(&self, index: usize) -> u32 {
+        (index as u64 * A::Frequency::frequency() as u64 / self.rate.get() as u64) as u32
+    }
+}
+
+impl<'a, A: Alarm<'a>> AlarmClient for PcmAudio<'a, A> {
+    fn alarm(&self) {
+        let Some(processid) = self.playing.get() else {
+            return;
+        };
+        match self.next_sample(processid) {
+            Some(sample) => {
+                self.output_sample(sample);
+                let index = self.sample_index.get();
+                let dt = self.sample_ticks(index + 1) - self.sample_ticks(index);
+                self.sample_index.set((index + 1) % self.rate.get());
+                self.alarm
+                    .set_alarm(self.alarm.get_alarm(), A::Ticks::from(dt));
+            }
+            None => self.stop(),
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>> SyscallDriver for PcmAudio<'a, A> {
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => self.start(processid, data1, data2).into(),
+
+            2 => {
+                if data1 >= NUM_BUFFERS {
+                    return CommandReturn::failure(ErrorCode::INVAL);
+                }
+                self.apps
+                    .enter(processid, |app, _| {
+                        if app.queued[data1].is_some() {
+                            Err(ErrorCode::BUSY)
+                        } else {
+                            app.queued[data1] = Some(if data2 == 0 { usize::MAX } else { data2 });
+                            Ok(())
+                        }
+                    })
+                    .unwrap_or_else(|err| Err(err.into()))
+                    .into()
+            }
+
+            3 => {
+                if self.playing.contains(&processid) {
+                    self.stop();
+                } else {
+                    let _ = self.apps.enter(processid, |app, _| {
+                        app.queued = [None; NUM_BUFFERS];
+                    });
+                }
+                CommandReturn::success()
+            }
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
@@ -0,0 +1,77 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Provides the list of the optional subsystems of the board to userspace.
+//!
+//! The board declares its subsystems with
+//! [`kernel::platform::peripherals`]. Portable applications can then check
+//! whether the board has a screen or a radio before they use it.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! static PERIPHERALS: [Peripheral; 1] =
+//!     [Peripheral::screen(capsules_extra::screen::DRIVER_NUM, 240, 240)];
+//! let peripherals = static_init!(
+//!     capsules_extra::peripherals::PeripheralsDriver,
+//!     capsules_extra::peripherals::PeripheralsDriver::new(Peripherals::new(&PERIPHERALS))
+//! );
+//! ```
+
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::Peripherals as usize;
+
+use kernel::platform::peripherals::{PeripheralKind, Peripherals};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, ProcessId};
+
+pub struct PeripheralsDriver {
+    peripherals: Peripherals<'static>,
+}
+
+impl PeripheralsDriver {
+    pub fn new(peripherals: Peripherals<'static>) -> PeripheralsDriver {
+        PeripheralsDriver { peripherals }
+    }
+}
+
+impl SyscallDriver for PeripheralsDriver {
+    /// Query the optional subsystems of the board.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Number of subsystems of kind `arg1`. Returns 0 for unknown
+    ///   kinds, so that applications keep working on older kernels.
+    /// - `2`: Describe the subsystem number `arg2` of kind `arg1`. Returns the
+    ///   driver number of the subsystem and its two parameters, or `INVAL` if
+    ///   the board has no such subsystem.
+    fn command(&self, command_num: usize, kind: usize, n: usize, _: ProcessId) -> CommandReturn {
+        let kind = u32::try_from(kind)
+            .ok()
+            .and_then(|kind| PeripheralKind::try_from(kind).ok());
+        match command_num {
+            0 => CommandReturn::success(),
+            1 => CommandReturn::success_u32(
+                kind.map_or(0, |kind| self.peripherals.count(kind)) as u32
+            ),
+            2 => kind.and_then(|kind| self.peripherals.get(kind, n)).map_or(
+                CommandReturn::failure(ErrorCode::INVAL),
+                |peripheral| {
+                    CommandReturn::success_u32_u32_u32(
+                        peripheral.driver_num as u32,
+                        peripheral.parameters[0],
+                        peripheral.parameters[1],
+                    )
+                },
+            ),
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, _: ProcessId) -> Result<(), kernel::process::Error> {
+        Ok(())
+    }
+}
@@ -0,0 +1,426 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Named pipes between processes.
+//!
+//! A pipe is a unidirectional byte stream with a name chosen by the board.
+//! One process opens the write end of a pipe and another one its read end,
+//! and they then exchange bytes with the same commands, allows and upcalls as
+//! the console, so an application that writes to the console can write to a
+//! pipe instead, for example to feed a logger application.
+//!
+//! Each pipe buffers the bytes written and not read yet. When the buffer is
+//! full, a write waits until the reader makes room, so a fast writer is slowed
+//! down to the pace of the reader. A read completes as soon as bytes are
+//! available, with at most the requested number of bytes. Once the writer
+//! closes its end, or exits, the reader reads the remaining bytes and then
+//! gets reads of 0 bytes.
+//!
+//! A process can hold the read end of one pipe and the write end of another,
+//! to filter a stream.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let pipes = components::pipe::PipeComponent::new(
+//!     board_kernel,
+//!     capsules_extra::pipe::DRIVER_NUM,
+//!     ["sensor", "log"],
+//! )
+//! .finalize(components::pipe_component_static!(2, 128));
+//! ```
+
+use core::cell::Cell;
+use core::cmp;
+
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::Pipe as usize;
+
+use kernel::collections::queue::Queue;
+use kernel::collections::ring_buffer::RingBuffer;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{MapCell, OptionalCell};
+use kernel::{ErrorCode, ProcessId};
+
+/// IDs for subscribed upcalls.
+mod upcall {
+    /// Write completed, as for the console.
+    pub const WRITE_DONE: usize = 1;
+    /// Read completed, as for the console.
+    pub const READ_DONE: usize = 2;
+    pub const COUNT: u8 = 3;
+}
+
+/// Ids for read-only allow buffers.
+mod ro_allow {
+    /// Bytes to write, as for the console.
+    pub const WRITE: usize = 1;
+    /// Name of the pipe to open.
+    pub const NAME: usize = 2;
+    pub const COUNT: u8 = 3;
+}
+
+/// Ids for read-write allow buffers.
+mod rw_allow {
+    /// Buffer for the bytes read, as for the console.
+    pub const READ: usize = 1;
+    pub const COUNT: u8 = 2;
+}
+
+/// A pipe and the bytes written to it and not read yet.
+pub struct Pipe<'a> {
+    name: &'static str,
+    buffer: MapCell<RingBuffer<'a, u8>>,
+    reader: OptionalCell<ProcessId>,
+    writer: OptionalCell<ProcessId>,
+    /// Whether the last writer closed the pipe. Readers get the end of the
+    /// stream once the buffer is empty.
+    writer_closed: Cell<bool>,
+}
+
+impl<'a> Pipe<'a> {
+    /// Create the pipe `name`, which holds one byte less than `buffer`.
+    pub fn new(name: &'static str, buffer: &'a mut [u8]) -> Self {
+        Self {
+            name,
+            buffer: MapCell::new(RingBuffer::new(buffer)),
+            reader: OptionalCell::empty(),
+            writer: OptionalCell::empty(),
+            writer_closed: Cell::new(false),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum End {
+    Read,
+    Write,
+}
+
+#[derive(Default)]
+pub struct App {
+    /// Index of the pipe whose read end the process holds.
+    reader: Option<usize>,
+    /// Index of the pipe whose write end the process holds.
+    writer: Option<usize>,
+    /// Length of the pending write, 0 if there is none.
+    write_len: usize,
+    /// Number of bytes of the pending write already in the pipe.
+    written: usize,
+    /// Length of the pending read, 0 if there is none.
+    read_len: usize,
+}
+
+pub struct PipeDriver<'a> {
+    pipes: &'a [Pipe<'a>],
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+}
+
+impl<'a> PipeDriver<'a> {
+    pub fn new(
+        pipes: &'a [Pipe<'a>],
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+    ) -> Self {
+        Self { pipes, apps: grant }
+    }
+
+    /// Whether `processid` still exists. Must not be called while the grant
+    /// of `processid` is entered.
+    fn is_alive(&self, processid: ProcessId) -> bool {
+        self.apps.enter(processid, |_, _| ()).is_ok()
+    }
+
+    /// Release the ends of `pipe` held by processes that exited.
+    fn release_dead_ends(&self, pipe: &Pipe) {
+        if pipe.writer.get().is_some_and(|pid| !self.is_alive(pid)) {
+            pipe.writer.clear();
+            pipe.writer_closed.set(true);
+        }
+        if pipe.reader.get().is_some_and(|pid| !self.is_alive(pid)) {
+            pipe.reader.clear();
+        }
+    }
+
+    fn open(&self, processid: ProcessId, end: End) -> Result<usize, ErrorCode> {
+        let index = self
+            .apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readonly_processbuffer(ro_allow::NAME)
+                    .and_then(|name| {
+                        name.enter(|name| {
+                            self.pipes.iter().position(|pipe| {
+                                pipe.name.len() == name.len()
+                                    && pipe
+                                        .name
+                                        .bytes()
+                                        .zip(name.iter())
+                                        .all(|(a, b)| a == b.get())
+                            })
+                        })
+                    })
+                    .unwrap_or(None)
+            })?
+            .ok_or(ErrorCode::INVAL)?;
+
+        let pipe = &self.pipes[index];
+        self.release_dead_ends(pipe);
+        let owner = match end {
+            End::Read => &pipe.reader,
+            End::Write => &pipe.writer,
+        };
+        if owner.get().is_some_and(|owner| owner != processid) {
+            return Err(ErrorCode::BUSY);
+        }
+        self.apps.enter(processid, |app, _| {
+            let held = match end {
+                End::Read => &mut app.reader,
+                End::Write => &mut app.writer,
+            };
+            if held.is_some() {
+                return Err(ErrorCode::ALREADY);
+            }
+            *held = Some(index);
+            Ok(())
+        })??;
+        owner.set(processid);
+        if end == End::Write {
+            pipe.writer_closed.set(false);
+        }
+        Ok(index)
+    }
+
+    fn close(&self, processid: ProcessId, end: End) -> Result<usize, ErrorCode> {
+        let index = self
+            .apps
+            .enter(processid, |app, _| match end {
+                End::Read => {
+                    app.read_len = 0;
+                    app.reader.take()
+                }
+                End::Write => {
+                    app.write_len = 0;
+                    app.writer.take()
+                }
+            })?
+            .ok_or(ErrorCode::RESERVE)?;
+
+        let pipe = &self.pipes[index];
+        match end {
+            End::Read => pipe.reader.clear(),
+            End::Write => {
+                pipe.writer.clear();
+                pipe.writer_closed.set(true);
+            }
+        }
+        Ok(index)
+    }
+
+    /// Move bytes from the pending write of the writer of the pipe `index`
+    /// into the pipe, and from the pipe into the pending read of its reader.
+    fn pump(&self, index: usize) {
+        let pipe = &self.pipes[index];
+        self.release_dead_ends(pipe);
+
+        // Reading makes room for more writes, so repeat until nothing moves.
+        loop {
+            let mut progress = false;
+
+            pipe.writer.map(|writer| {
+                let _ = self.apps.enter(writer, |app, kernel_data| {
+                    if app.write_len == 0 {
+                        return;
+                    }
+                    let available = kernel_data
+                        .get_readonly_processbuffer(ro_allow::WRITE)
+                        .and_then(|write| {
+                            write.enter(|data| {
+                                let end = cmp::min(app.write_len, data.len());
+                                pipe.buffer.map(|ring| {
+                                    for byte in data.iter().take(end).skip(app.written) {
+                                        if !ring.enqueue(byte.get()) {
+                                            break;
+                                        }
+                                        app.written += 1;
+                                        progress = true;
+                                    }
+                                });
+                                end
+                            })
+                        })
+                        .unwrap_or(0);
+                    // The write is done once all bytes are in the pipe, or
+                    // if the process revoked its buffer.
+                    if app.written >= available {
+                        let written = app.written;
+                        app.write_len = 0;
+                        kernel_data
+                            .schedule_upcall(upcall::WRITE_DONE, (written, 0, 0))
+                            .ok();
+                    }
+                });
+            });
+
+            pipe.reader.map(|reader| {
+                let _ = self.apps.enter(reader, |app, kernel_data| {
+                    let buffered = pipe.buffer.map_or(0, |ring| ring.len());
+                    if app.read_len == 0 || (buffered == 0 && !pipe.writer_closed.get()) {
+                        return;
+                    }
+                    let (result, count) = kernel_data
+                        .get_readwrite_processbuffer(rw_allow::READ)
+                        .and_then(|read| {
+                            read.mut_enter(|dest| {
+                                let mut count = 0;
+                                pipe.buffer.map(|ring| {
+                                    for byte in dest.iter().take(app.read_len) {
+                                        match ring.dequeue() {
+                                            Some(value) => byte.set(value),
+                                            None => break,
+                                        }
+                                        count += 1;
+                                    }
+                                });
+                                count
+                            })
+                        })
+                        .map_or((Err(ErrorCode::NOMEM), 0), |count| (Ok(()), count));
+                    progress |= count > 0;
+                    app.read_len = 0;
+                    kernel_data
+                        .schedule_upcall(
+                            upcall::READ_DONE,
+                            (kernel::errorcode::into_statuscode(result), count, 0),
+                        )
+                        .ok();
+                });
+            });
+
+            if !progress {
+                break;
+            }
+        }
+    }
+}
+
+impl SyscallDriver for PipeDriver<'_> {
+    /// Open, close, write and read pipes.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Write `arg1` bytes of the write buffer to the pipe the process
+    ///   opened for writing, as the console `putstr`.
+    /// - `2`: Read at most `arg1` bytes into the read buffer from the pipe the
+    ///   process opened for reading.
+    /// - `3`: Abort the pending read.
+    /// - `4`: Open the read end (`arg1` = 0) or the write end (`arg1` = 1) of
+    ///   the pipe named by the name buffer. Returns the index of the pipe.
+    /// - `5`: Close the read end (`arg1` = 0) or the write end (`arg1` = 1).
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        let end = match arg1 {
+            0 => Ok(End::Read),
+            1 => Ok(End::Write),
+            _ => Err(ErrorCode::INVAL),
+        };
+        let result = match command_num {
+            0 => return CommandReturn::success(),
+            1 => self
+                .apps
+                .enter(processid, |app, _| {
+                    let index = app.writer.ok_or(ErrorCode::RESERVE)?;
+                    if app.write_len != 0 {
+                        return Err(ErrorCode::BUSY);
+                    }
+                    if arg1 == 0 {
+                        return Err(ErrorCode::SIZE);
+                    }
+                    app.write_len = arg1;
+                    app.written = 0;
+                    Ok(index)
+                })
+                .map_err(ErrorCode::from)
+                .and_then(|r| r),
+            2 => self
+                .apps
+                .enter(processid, |app, _| {
+                    let index = app.reader.ok_or(ErrorCode::RESERVE)?;
+                    if app.read_len != 0 {
+                        return Err(ErrorCode::BUSY);
+                    }
+                    if arg1 == 0 {
+                        return Err(ErrorCode::SIZE);
+                    }
+                    app.read_len = arg1;
+                    Ok(index)
+                })
+                .map_err(ErrorCode::from)
+                .and_then(|r| r),
+            3 => {
+                return self
+                    .apps
+                    .enter(processid, |app, kernel_data| {
+                        if app.read_len != 0 {
+                            app.read_len = 0;
+                            kernel_data
+                                .schedule_upcall(
+                                    upcall::READ_DONE,
+                                    (
+                                        kernel::errorcode::into_statuscode(Err(ErrorCode::CANCEL)),
+                                        0,
+                                        0,
+                                    ),
+                                )
+                                .ok();
+                        }
+                    })
+                    .map_or_else(
+                        |err| CommandReturn::failure(err.into()),
+                        |()| CommandReturn::success(),
+                    );
+            }
+            4 => {
+                return end.and_then(|end| self.open(processid, end)).map_or_else(
+                    CommandReturn::failure,
+                    |index| {
+                        self.pump(index);
+                        CommandReturn::success_u32(index as u32)
+                    },
+                );
+            }
+            5 => end.and_then(|end| self.close(processid, end)),
+            _ => Err(ErrorCode::NOSUPPORT),
+        };
+        match result {
+            Ok(index) => {
+                self.pump(index);
+                CommandReturn::success()
+            }
+            Err(err) => CommandReturn::failure(err),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
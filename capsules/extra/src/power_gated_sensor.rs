@@ -0,0 +1,232 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Power a sensor only around its measurements.
+//!
+//! `PowerGatedSensor` wraps a temperature and/or humidity sensor whose supply
+//! is a switchable power rail. Each read turns the rail on, waits for it to
+//! settle, reads the sensor, and turns the rail off once no read is pending.
+//! Reads of both values share one power-up.
+//!
+//! The rail is usually a `VirtualPowerRail`, so that it stays on while other
+//! devices on the same rail need it. Only sensors that need no configuration
+//! after power-up can be gated this way, and the settle time of the rail must
+//! include the start-up time of the sensor.
+//!
+//! The humidity HIL cannot report errors: if the rail fails to turn on, a
+//! pending humidity read gets no callback.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let gated_hs3003 = static_init!(
+//!     capsules_extra::power_gated_sensor::PowerGatedSensor<'static>,
+//!     capsules_extra::power_gated_sensor::PowerGatedSensor::new(sensor_rail));
+//! sensor_rail.set_client(gated_hs3003);
+//! gated_hs3003.set_temperature_sensor(hs3003);
+//! gated_hs3003.set_humidity_sensor(hs3003);
+//! kernel::hil::sensors::TemperatureDriver::set_client(hs3003, gated_hs3003);
+//! kernel::hil::sensors::HumidityDriver::set_client(hs3003, gated_hs3003);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::power_rail::{PowerRail, PowerRailClient};
+use kernel::hil::sensors::{HumidityClient, HumidityDriver, TemperatureClient, TemperatureDriver};
+use kernel::utilities::cells::OptionalCell;
+use kernel::ErrorCode;
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Off,
+    PoweringOn,
+    On,
+    PoweringOff,
+}
+
+pub struct PowerGatedSensor<'a> {
+    rail: &'a dyn PowerRail<'a>,
+    temperature: OptionalCell<&'a dyn TemperatureDriver<'a>>,
+    humidity: OptionalCell<&'a dyn HumidityDriver<'a>>,
+    state: Cell<State>,
+    pending_temperature: Cell<bool>,
+    pending_humidity: Cell<bool>,
+    temperature_client: OptionalCell<&'a dyn TemperatureClient>,
+    humidity_client: OptionalCell<&'a dyn HumidityClient>,
+}
+
+impl<'a> PowerGatedSensor<'a> {
+    pub fn new(rail: &'a dyn PowerRail<'a>) -> Self {
+        Self {
+            rail,
+            temperature: OptionalCell::empty(),
+            humidity: OptionalCell::empty(),
+            state: Cell::new(State::Off),
+            pending_temperature: Cell::new(false),
+            pending_humidity: Cell::new(false),
+            temperature_client: OptionalCell::empty(),
+            humidity_client: OptionalCell::empty(),
+        }
+    }
+
+    /// Gate the temperature reads of `sensor`. The board must also set this
+    /// wrapper as the temperature client of `sensor`.
+    pub fn set_temperature_sensor(&self, sensor: &'a dyn TemperatureDriver<'a>) {
+        self.temperature.set(sensor);
+    }
+
+    /// Gate the humidity reads of `sensor`. The board must also set this
+    /// wrapper as the humidity client of `sensor`.
+    pub fn set_humidity_sensor(&self, sensor: &'a dyn HumidityDriver<'a>) {
+        self.humidity.set(sensor);
+    }
+
+    fn start_temperature(&self) -> Result<(), ErrorCode> {
+        self.temperature
+            .map_or(Err(ErrorCode::NODEVICE), |sensor| sensor.read_temperature())
+    }
+
+    fn start_humidity(&self) -> Result<(), ErrorCode> {
+        self.humidity
+            .map_or(Err(ErrorCode::NODEVICE), |sensor| sensor.read_humidity())
+    }
+
+    fn pending(&self) -> bool {
+        self.pending_temperature.get() || self.pending_humidity.get()
+    }
+
+    /// Start a read with `start` once the rail is on. `pending` is the flag of
+    /// the read, which is set while it is pending.
+    fn request(
+        &self,
+        pending: &Cell<bool>,
+        start: fn(&Self) -> Result<(), ErrorCode>,
+    ) -> Result<(), ErrorCode> {
+        if pending.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        match self.state.get() {
+            State::On => start(self)?,
+            State::Off => match self.rail.power_on() {
+                Ok(()) => self.state.set(State::PoweringOn),
+                Err(ErrorCode::ALREADY) => {
+                    self.state.set(State::On);
+                    let result = start(self);
+                    if result.is_err() {
+                        self.release();
+                    }
+                    result?;
+                }
+                Err(e) => return Err(e),
+            },
+            // The read starts once the rail is on.
+            State::PoweringOn | State::PoweringOff => {}
+        }
+        pending.set(true);
+        Ok(())
+    }
+
+    fn start_pending(&self) {
+        if self.pending_temperature.get() {
+            if let Err(e) = self.start_temperature() {
+                self.pending_temperature.set(false);
+                self.temperature_client
+                    .map(|client| client.callback(Err(e)));
+            }
+        }
+        if self.pending_humidity.get() && self.start_humidity().is_err() {
+            self.pending_humidity.set(false);
+        }
+        self.release();
+    }
+
+    /// Turn the rail off once no read is pending.
+    fn release(&self) {
+        if self.state.get() == State::On && !self.pending() {
+            match self.rail.power_off() {
+                Ok(()) => self.state.set(State::PoweringOff),
+                Err(_) => self.state.set(State::Off),
+            }
+        }
+    }
+}
+
+impl<'a> TemperatureDriver<'a> for PowerGatedSensor<'a> {
+    fn set_client(&self, client: &'a dyn TemperatureClient) {
+        self.temperature_client.set(client);
+    }
+
+    fn read_temperature(&self) -> Result<(), ErrorCode> {
+        if self.temperature.is_none() {
+            return Err(ErrorCode::NODEVICE);
+        }
+        self.request(&self.pending_temperature, Self::start_temperature)
+    }
+}
+
+impl<'a> HumidityDriver<'a> for PowerGatedSensor<'a> {
+    fn set_client(&self, client: &'a dyn HumidityClient) {
+        self.humidity_client.set(client);
+    }
+
+    fn read_humidity(&self) -> Result<(), ErrorCode> {
+        if self.humidity.is_none() {
+            return Err(ErrorCode::NODEVICE);
+        }
+        self.request(&self.pending_humidity, Self::start_humidity)
+    }
+}
+
+impl TemperatureClient for PowerGatedSensor<'_> {
+    fn callback(&self, value: Result<i32, ErrorCode>) {
+        self.pending_temperature.set(false);
+        self.temperature_client.map(|client| client.callback(value));
+        self.release();
+    }
+}
+
+impl HumidityClient for PowerGatedSensor<'_> {
+    fn callback(&self, value: usize) {
+        self.pending_humidity.set(false);
+        self.humidity_client.map(|client| client.callback(value));
+        self.release();
+    }
+}
+
+impl PowerRailClient for PowerGatedSensor<'_> {
+    fn power_on_done(&self, result: Result<(), ErrorCode>) {
+        match result {
+            Ok(()) => {
+                self.state.set(State::On);
+                self.start_pending();
+            }
+            Err(e) => {
+                self.state.set(State::Off);
+                self.pending_humidity.set(false);
+                if self.pending_temperature.take() {
+                    self.temperature_client
+                        .map(|client| client.callback(Err(e)));
+                }
+            }
+        }
+    }
+
+    fn power_off_done(&self, _result: Result<(), ErrorCode>) {
+        self.state.set(State::Off);
+        // A read was requested while the rail was turning off.
+        if self.pending() {
+            match self.rail.power_on() {
+                Ok(()) => self.state.set(State::PoweringOn),
+                Err(ErrorCode::ALREADY) => {
+                    self.state.set(State::On);
+                    self.start_pending();
+                }
+                Err(e) => self.power_on_done(Err(e)),
+            }
+        }
+    }
+}
@@ -0,0 +1,926 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Software ECDSA signature verification and signing on the NIST P-256 curve.
+//!
+//! The public key is the `x` and `y` coordinates of the public point, and a
+//! signature is the `r` and `s` values, each concatenated and 32 bytes big
+//! endian. This is the format of P-256 keys and signatures in TBF credential
+//! footers. The private key is the 32 bytes big endian scalar.
+//!
+//! The hash given to `verify` and `sign` is used as the digest of the signed
+//! message, so for ECDSA with SHA-256 it is the SHA-256 hash of the message.
+//!
+//! Verification runs to completion in a deferred call. It does not run in
+//! constant time, which is not needed as it only handles public values.
+//!
+//! Signing also runs to completion in a deferred call, for chips without a
+//! crypto accelerator. The nonce is derived from the private key and the hash
+//! as in RFC 6979, with HMAC-SHA256, so signing does not need a random number
+//! generator and the same hash always gives the same signature. Signing runs
+//! in constant time: the scalar multiplication is a Montgomery ladder with
+//! complete addition formulas, and the field arithmetic selects its results
+//! instead of branching on them. This assumes that the multiply instructions of
+//! the core take the same time for all operands, which is not the case for
+//! some cores, like the Cortex-M3.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let verifier = static_init!(
+//!     EcdsaP256SoftwareVerifier<'static>,
+//!     EcdsaP256SoftwareVerifier::new(&PUBLIC_KEY)
+//! );
+//! kernel::deferred_call::DeferredCallClient::register(verifier);
+//!
+//! let signer = static_init!(
+//!     EcdsaP256SoftwareSigner<'static>,
+//!     EcdsaP256SoftwareSigner::new(&PRIVATE_KEY)
+//! );
+//! kernel::deferred_call::DeferredCallClient::register(signer);
+//! ```
+
+use crate::sha256::Sha256Hasher;
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::public_key_crypto::signature::{
+    ClientSign, ClientVerify, SignatureSign, SignatureVerify,
+};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+/// A 256 bit number, as 32 bit limbs with the least significant limb first.
+type U256 = [u32; 8];
+
+const ONE: U256 = [1, 0, 0, 0, 0, 0, 0, 0];
+
+/// A modulus and the constants for Montgomery multiplication with it.
+struct Modulus {
+    m: U256,
+    /// `-m^-1 mod 2^32`.
+    m_inv: u32,
+    /// `2^512 mod m`, to convert numbers into the Montgomery domain.
+    r2: U256,
+}
+
+/// The prime of the field the curve is defined over.
+const P: Modulus = Modulus {
+    m: [
+        0xffffffff, 0xffffffff, 0xffffffff, 0x00000000, 0x00000000, 0x00000000, 0x00000001,
+        0xffffffff,
+    ],
+    m_inv: 0x00000001,
+    r2: [
+        0x00000003, 0x00000000, 0xffffffff, 0xfffffffb, 0xfffffffe, 0xffffffff, 0xfffffffd,
+        0x00000004,
+    ],
+};
+
+/// The order of the base point.
+const N: Modulus = Modulus {
+    m: [
+        0xfc632551, 0xf3b9cac2, 0xa7179e84, 0xbce6faad, 0xffffffff, 0xffffffff, 0x00000000,
+        0xffffffff,
+    ],
+    m_inv: 0xee00bc4f,
+    r2: [
+        0xbe79eea2, 0x83244c95, 0x49bd6fa6, 0x4699799c, 0x2b6bec59, 0x2845b239, 0xf3d95620,
+        0x66e12d94,
+    ],
+};
+
+/// The `b` coefficient of the curve `y^2 = x^3 - 3x + b`.
+const B: U256 = [
+    0x27d2604b, 0x3bce3c3e, 0xcc53b0f6, 0x651d06b0, 0x769886bc, 0xb3ebbd55, 0xaa3a93e7, 0x5ac635d8,
+];
+
+/// Coordinates of the base point.
+const GX: U256 = [
+    0xd898c296, 0xf4a13945, 0x2deb33a0, 0x77037d81, 0x63a440f2, 0xf8bce6e5, 0xe12c4247, 0x6b17d1f2,
+];
+const GY: U256 = [
+    0x37bf51f5, 0xcbb64068, 0x6b315ece, 0x2bce3357, 0x7c0f9e16, 0x8ee7eb4a, 0xfe1a7f9b, 0x4fe342e2,
+];
+
+fn from_be_bytes(bytes: &[u8]) -> U256 {
+    let mut a = [0; 8];
+    for (i, limb) in a.iter_mut().enumerate() {
+        let offset = 28 - 4 * i;
+        *limb = u32::from_be_bytes([
+            bytes[offset],
+            bytes[offset + 1],
+            bytes[offset + 2],
+            bytes[offset + 3],
+        ]);
+    }
+    a
+}
+
+fn to_be_bytes(a: &U256, bytes: &mut [u8]) {
+    for (i, limb) in a.iter().enumerate() {
+        let offset = 28 - 4 * i;
+        bytes[offset..offset + 4].copy_from_slice(&limb.to_be_bytes());
+    }
+}
+
+fn is_zero(a: &U256) -> bool {
+    a.iter().all(|&limb| limb == 0)
+}
+
+fn less_than(a: &U256, b: &U256) -> bool {
+    for i in (0..8).rev() {
+        if a[i] != b[i] {
+            return a[i] < b[i];
+        }
+    }
+    false
+}
+
+fn bit(a: &U256, index: usize) -> bool {
+    (a[index / 32] >> (index % 32)) & 1 == 1
+}
+
+/// `b` if `choice` is set, otherwise `a`, without branching on `choice`.
+fn select(a: &U256, b: &U256, choice: bool) -> U256 {
+    let mask = (choice as u32).wrapping_neg();
+    let mut r = [0; 8];
+    for i in 0..8 {
+        r[i] = a[i] ^ (mask & (a[i] ^ b[i]));
+    }
+    r
+}
+
+/// `a + b`, and whether it overflowed.
+fn add(a: &U256, b: &U256) -> (U256, bool) {
+    let mut r = [0; 8];
+    let mut carry = 0u64;
+    for i in 0..8 {
+        let sum = a[i] as u64 + b[i] as u64 + carry;
+        r[i] = sum as u32;
+        carry = sum >> 32;
+    }
+    (r, carry != 0)
+}
+
+/// `a - b`, and whether it underflowed.
+fn sub(a: &U256, b: &U256) -> (U256, bool) {
+    let mut r = [0; 8];
+    let mut borrow = 0u64;
+    for i in 0..8 {
+        let difference = (a[i] as u64).wrapping_sub(b[i] as u64 + borrow);
+        r[i] = difference as u32;
+        borrow = (difference >> 63) & 1;
+    }
+    (r, borrow != 0)
+}
+
+/// The arithmetic modulo `m` selects its results instead of branching on
+/// them, so that its timing does not depend on the values of the operands.
+/// The conditions are combined with `&` rather than `&&` for the same reason.
+#[allow(clippy::needless_bitwise_bool)]
+impl Modulus {
+    /// Reduce any 256 bit number. All moduli here are more than 2^255, so at
+    /// most one subtraction is needed.
+    fn reduce(&self, a: &U256) -> U256 {
+        let (difference, underflow) = sub(a, &self.m);
+        select(&difference, a, underflow)
+    }
+
+    fn add(&self, a: &U256, b: &U256) -> U256 {
+        let (sum, overflow) = add(a, b);
+        let (difference, underflow) = sub(&sum, &self.m);
+        select(&difference, &sum, underflow & !overflow)
+    }
+
+    fn sub(&self, a: &U256, b: &U256) -> U256 {
+        let (difference, underflow) = sub(a, b);
+        select(&difference, &add(&difference, &self.m).0, underflow)
+    }
+
+    /// Montgomery multiplication, `a * b * 2^-256 mod m`.
+    fn mul(&self, a: &U256, b: &U256) -> U256 {
+        let mut t = [0u32; 10];
+        for i in 0..8 {
+            let mut carry = 0u64;
+            for j in 0..8 {
+                let sum = t[j] as u64 + a[j] as u64 * b[i] as u64 + carry;
+                t[j] = sum as u32;
+                carry = sum >> 32;
+            }
+            let sum = t[8] as u64 + carry;
+            t[8] = sum as u32;
+            t[9] = (sum >> 32) as u32;
+
+            // Add a multiple of m that makes the lowest limb zero, and shift
+            // it out.
+            let q = t[0].wrapping_mul(self.m_inv);
+            let mut carry = (t[0] as u64 + q as u64 * self.m[0] as u64) >> 32;
+            for j in 1..8 {
+                let sum = t[j] as u64 + q as u64 * self.m[j] as u64 + carry;
+                t[j - 1] = sum as u32;
+                carry = sum >> 32;
+            }
+            let sum = t[8] as u64 + carry;
+            t[7] = sum as u32;
+            t[8] = t[9] + (sum >> 32) as u32;
+        }
+
+        let mut r = [0; 8];
+        r.copy_from_slice(&t[..8]);
+        let (difference, underflow) = sub(&r, &self.m);
+        select(&difference, &r, (t[8] == 0) & underflow)
+    }
+
+    fn to_montgomery(&self, a: &U256) -> U256 {
+        self.mul(a, &self.r2)
+    }
+
+    fn out_of_montgomery(&self, a: &U256) -> U256 {
+        self.mul(a, &ONE)
+    }
+
+    /// Inverse of `a`, both in the Montgomery domain, as `a^(m - 2)`. `m` is
+    /// prime for both moduli. The exponent is public, so branching on its
+    /// bits does not leak anything about `a`.
+    fn invert(&self, a: &U256) -> U256 {
+        let exponent = sub(&self.m, &[2, 0, 0, 0, 0, 0, 0, 0]).0;
+        let mut r = self.to_montgomery(&ONE);
+        for i in (0..256).rev() {
+            r = self.mul(&r, &r);
+            if bit(&exponent, i) {
+                r = self.mul(&r, a);
+            }
+        }
+        r
+    }
+}
+
+/// `1` in the Montgomery domain of `P`, `2^256 mod p`.
+const MONTGOMERY_ONE: U256 = [
+    0x00000001, 0x00000000, 0x00000000, 0xffffffff, 0xffffffff, 0xffffffff, 0xfffffffe, 0x00000000,
+];
+
+/// `B` in the Montgomery domain of `P`.
+const MONTGOMERY_B: U256 = [
+    0x29c4bddf, 0xd89cdf62, 0x78843090, 0xacf005cd, 0xf7212ed6, 0xe5a220ab, 0x04874834, 0xdc30061d,
+];
+
+/// A point in projective coordinates, in the Montgomery domain. The point at
+/// infinity has `z == 0`.
+#[derive(Clone, Copy)]
+struct Point {
+    x: U256,
+    y: U256,
+    z: U256,
+}
+
+impl Point {
+    const INFINITY: Point = Point {
+        x: [0; 8],
+        y: MONTGOMERY_ONE,
+        z: [0; 8],
+    };
+
+    fn from_affine(x: &U256, y: &U256) -> Point {
+        Point {
+            x: P.to_montgomery(x),
+            y: P.to_montgomery(y),
+            z: MONTGOMERY_ONE,
+        }
+    }
+
+    fn is_infinity(&self) -> bool {
+        is_zero(&self.z)
+    }
+
+    /// Swap `first` and `second` if `choice` is set, without branching on
+    /// `choice`.
+    fn swap(first: &mut Point, second: &mut Point, choice: bool) {
+        let old_first = *first;
+        first.x = select(&first.x, &second.x, choice);
+        first.y = select(&first.y, &second.y, choice);
+        first.z = select(&first.z, &second.z, choice);
+        second.x = select(&second.x, &old_first.x, choice);
+        second.y = select(&second.y, &old_first.y, choice);
+        second.z = select(&second.z, &old_first.z, choice);
+    }
+
+    /// The complete addition formula for `a = -3` of Renes, Costello and
+    /// Batina, "Complete addition formulas for prime order elliptic curves",
+    /// algorithm 4. It is correct for all points, including the point at
+    /// infinity and `self == other`, so it does not branch on them.
+    fn add(&self, other: &Point) -> Point {
+        let (x1, y1, z1) = (&self.x, &self.y, &self.z);
+        let (x2, y2, z2) = (&other.x, &other.y, &other.z);
+
+        let t0 = P.mul(x1, x2);
+        let t1 = P.mul(y1, y2);
+        let t2 = P.mul(z1, z2);
+        let t3 = P.mul(&P.add(x1, y1), &P.add(x2, y2));
+        let t3 = P.sub(&t3, &P.add(&t0, &t1));
+        let t4 = P.mul(&P.add(y1, z1), &P.add(y2, z2));
+        let t4 = P.sub(&t4, &P.add(&t1, &t2));
+        let x3 = P.mul(&P.add(x1, z1), &P.add(x2, z2));
+        let y3 = P.sub(&x3, &P.add(&t0, &t2));
+        let z3 = P.mul(&MONTGOMERY_B, &t2);
+        let x3 = P.sub(&y3, &z3);
+        let x3 = P.add(&x3, &P.add(&x3, &x3));
+        let z3 = P.sub(&t1, &x3);
+        let x3 = P.add(&t1, &x3);
+        let y3 = P.mul(&MONTGOMERY_B, &y3);
+        let t2 = P.add(&t2, &P.add(&t2, &t2));
+        let y3 = P.sub(&P.sub(&y3, &t2), &t0);
+        let y3 = P.add(&y3, &P.add(&y3, &y3));
+        let t0 = P.sub(&P.add(&t0, &P.add(&t0, &t0)), &t2);
+        let t1 = P.mul(&t4, &y3);
+        let t2 = P.mul(&t0, &y3);
+        let y3 = P.add(&P.mul(&x3, &z3), &t2);
+        let x3 = P.sub(&P.mul(&t3, &x3), &t1);
+        let z3 = P.add(&P.mul(&t4, &z3), &P.mul(&t3, &t0));
+
+        Point {
+            x: x3,
+            y: y3,
+            z: z3,
+        }
+    }
+
+    fn double(&self) -> Point {
+        self.add(self)
+    }
+
+    /// `k * self`, with a Montgomery ladder. Every bit of `k` takes one
+    /// addition and one doubling, and the points are swapped with `select`,
+    /// so the timing does not depend on `k`.
+    fn mul(&self, k: &U256) -> Point {
+        let mut r0 = Point::INFINITY;
+        let mut r1 = *self;
+        for i in (0..256).rev() {
+            // r1 - r0 == self, so keep adding r0 and r1 and doubling the one
+            // the bit picks.
+            let set = bit(k, i);
+            Point::swap(&mut r0, &mut r1, set);
+            r1 = r0.add(&r1);
+            r0 = r0.double();
+            Point::swap(&mut r0, &mut r1, set);
+        }
+        r0
+    }
+
+    /// The affine x coordinate, outside of the Montgomery domain.
+    fn affine_x(&self) -> U256 {
+        P.out_of_montgomery(&P.mul(&self.x, &P.invert(&self.z)))
+    }
+
+    /// The affine x and y coordinates, outside of the Montgomery domain.
+    fn affine(&self) -> (U256, U256) {
+        let z_inv = P.invert(&self.z);
+        (
+            P.out_of_montgomery(&P.mul(&self.x, &z_inv)),
+            P.out_of_montgomery(&P.mul(&self.y, &z_inv)),
+        )
+    }
+}
+
+/// Whether the affine point `(x, y)` is on the curve.
+fn on_curve(x: &U256, y: &U256) -> bool {
+    if !less_than(x, &P.m) || !less_than(y, &P.m) {
+        return false;
+    }
+    let x = P.to_montgomery(x);
+    let y = P.to_montgomery(y);
+    let x3 = P.mul(&P.mul(&x, &x), &x);
+    let x3_3x = P.sub(&x3, &P.add(&P.add(&x, &x), &x));
+    P.mul(&y, &y) == P.add(&x3_3x, &P.to_montgomery(&B))
+}
+
+/// Check the signature `r || s` of `hash` against the public key `x || y`.
+///
+/// Returns `None` if the public key is not a point on the curve.
+fn verify_signature(public_key: &[u8; 64], hash: &[u8; 32], signature: &[u8; 64]) -> Option<bool> {
+    let qx = from_be_bytes(&public_key[..32]);
+    let qy = from_be_bytes(&public_key[32..]);
+    if !on_curve(&qx, &qy) {
+        return None;
+    }
+
+    let r = from_be_bytes(&signature[..32]);
+    let s = from_be_bytes(&signature[32..]);
+    if is_zero(&r) || is_zero(&s) || !less_than(&r, &N.m) || !less_than(&s, &N.m) {
+        return Some(false);
+    }
+    let digest = N.reduce(&from_be_bytes(hash));
+
+    // u1 = digest / s and u2 = r / s.
+    let s_inv = N.invert(&N.to_montgomery(&s));
+    let u1 = N.out_of_montgomery(&N.mul(&N.to_montgomery(&digest), &s_inv));
+    let u2 = N.out_of_montgomery(&N.mul(&N.to_montgomery(&r), &s_inv));
+
+    // u1 * G + u2 * Q, with both multiplications sharing the doublings.
+    let base = Point::from_affine(&GX, &GY);
+    let public = Point::from_affine(&qx, &qy);
+    let base_public = base.add(&public);
+    let mut sum = Point::INFINITY;
+    for i in (0..256).rev() {
+        sum = sum.double();
+        match (bit(&u1, i), bit(&u2, i)) {
+            (true, true) => sum = sum.add(&base_public),
+            (true, false) => sum = sum.add(&base),
+            (false, true) => sum = sum.add(&public),
+            (false, false) => {}
+        }
+    }
+    if sum.is_infinity() {
+        return Some(false);
+    }
+
+    Some(N.reduce(&sum.affine_x()) == r)
+}
+
+/// HMAC-SHA256 with `key` of the concatenation of `parts`.
+fn hmac_sha256(key: &[u8], parts: &[&[u8]]) -> [u8; 32] {
+    // Keys longer than a block are replaced by their hash.
+    let hashed_key;
+    let key = if key.len() > 64 {
+        let mut sha = Sha256Hasher::new();
+        sha.update(key);
+        hashed_key = sha.finish();
+        &hashed_key[..]
+    } else {
+        key
+    };
+    let mut pad = [0x36; 64];
+    for (pad, key) in pad.iter_mut().zip(key) {
+        *pad ^= key;
+    }
+    let mut inner = Sha256Hasher::new();
+    inner.update(&pad);
+    for part in parts {
+        inner.update(part);
+    }
+    let inner = inner.finish();
+
+    for pad in pad.iter_mut() {
+        *pad ^= 0x36 ^ 0x5c;
+    }
+    let mut outer = Sha256Hasher::new();
+    outer.update(&pad);
+    outer.update(&inner);
+    outer.finish()
+}
+
+/// Whether `private_key` is a valid scalar, in `[1, n - 1]`.
+fn valid_private_key(private_key: &[u8; 32]) -> bool {
+    let d = from_be_bytes(private_key);
+    !is_zero(&d) && less_than(&d, &N.m)
+}
+
+/// The public key `x || y` of a valid `private_key`.
+fn public_key(private_key: &[u8; 32]) -> [u8; 64] {
+    let (x, y) = Point::from_affine(&GX, &GY)
+        .mul(&from_be_bytes(private_key))
+        .affine();
+    let mut public_key = [0; 64];
+    to_be_bytes(&x, &mut public_key[..32]);
+    to_be_bytes(&y, &mut public_key[32..]);
+    public_key
+}
+
+/// The signature `(r, s)` of `digest` with the private key `d` and `nonce`,
+/// or `None` if `r` or `s` is zero.
+fn sign_with_nonce(d: &U256, digest: &U256, nonce: &U256) -> Option<(U256, U256)> {
+    let point = Point::from_affine(&GX, &GY).mul(nonce);
+    if point.is_infinity() {
+        return None;
+    }
+    let r = N.reduce(&point.affine_x());
+    if is_zero(&r) {
+        return None;
+    }
+
+    // s = (digest + r * d) / nonce.
+    let rd = N.mul(&N.to_montgomery(&r), &N.to_montgomery(d));
+    let sum = N.add(&N.to_montgomery(digest), &rd);
+    let nonce_inv = N.invert(&N.to_montgomery(nonce));
+    let s = N.out_of_montgomery(&N.mul(&sum, &nonce_inv));
+    if is_zero(&s) {
+        None
+    } else {
+        Some((r, s))
+    }
+}
+
+/// Nonces derived from a private key and a digest as in RFC 6979, section
+/// 3.2, with HMAC-SHA256.
+struct NonceGenerator {
+    k: [u8; 32],
+    v: [u8; 32],
+    /// Whether a nonce was already returned, so the next one must be
+    /// derived again.
+    started: bool,
+}
+
+impl NonceGenerator {
+    fn new(private_key: &[u8; 32], digest: &U256) -> NonceGenerator {
+        let mut digest_bytes = [0; 32];
+        to_be_bytes(digest, &mut digest_bytes);
+
+        let mut v = [0x01; 32];
+        let mut k = [0x00; 32];
+        k = hmac_sha256(&k, &[&v, &[0x00], private_key, &digest_bytes]);
+        v = hmac_sha256(&k, &[&v]);
+        k = hmac_sha256(&k, &[&v, &[0x01], private_key, &digest_bytes]);
+        v = hmac_sha256(&k, &[&v]);
+        NonceGenerator {
+            k,
+            v,
+            started: false,
+        }
+    }
+
+    /// The next nonce in `[1, n - 1]`, to use if the previous one gave a
+    /// signature with `r` or `s` zero.
+    fn next_nonce(&mut self) -> U256 {
+        loop {
+            if self.started {
+                self.k = hmac_sha256(&self.k, &[&self.v, &[0x00]]);
+                self.v = hmac_sha256(&self.k, &[&self.v]);
+            }
+            self.started = true;
+            self.v = hmac_sha256(&self.k, &[&self.v]);
+            let nonce = from_be_bytes(&self.v);
+            if !is_zero(&nonce) && less_than(&nonce, &N.m) {
+                return nonce;
+            }
+        }
+    }
+}
+
+/// Write the signature `r || s` of `hash` with a valid `private_key` to
+/// `signature`, with the nonce derived as in RFC 6979.
+fn sign_hash(private_key: &[u8; 32], hash: &[u8; 32], signature: &mut [u8; 64]) {
+    let d = from_be_bytes(private_key);
+    let digest = N.reduce(&from_be_bytes(hash));
+    let mut nonces = NonceGenerator::new(private_key, &digest);
+    loop {
+        if let Some((r, s)) = sign_with_nonce(&d, &digest, &nonces.next_nonce()) {
+            to_be_bytes(&r, &mut signature[..32]);
+            to_be_bytes(&s, &mut signature[32..]);
+            return;
+        }
+    }
+}
+
+pub struct EcdsaP256SoftwareVerifier<'a> {
+    public_key: &'a [u8; 64],
+    client: OptionalCell<&'a dyn ClientVerify<32, 64>>,
+    hash: TakeCell<'static, [u8; 32]>,
+    signature: TakeCell<'static, [u8; 64]>,
+    deferred_call: DeferredCall,
+}
+
+impl<'a> EcdsaP256SoftwareVerifier<'a> {
+    /// `public_key` is the `x` and `y` coordinates of the public point.
+    pub fn new(public_key: &'a [u8; 64]) -> EcdsaP256SoftwareVerifier<'a> {
+        EcdsaP256SoftwareVerifier {
+            public_key,
+            client: OptionalCell::empty(),
+            hash: TakeCell::empty(),
+            signature: TakeCell::empty(),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+}
+
+impl<'a> SignatureVerify<'a, 32, 64> for EcdsaP256SoftwareVerifier<'a> {
+    fn set_verify_client(&self, client: &'a dyn ClientVerify<32, 64>) {
+        self.client.set(client);
+    }
+
+    fn verify(
+        &self,
+        hash: &'static mut [u8; 32],
+        signature: &'static mut [u8; 64],
+    ) -> Result<(), (ErrorCode, &'static mut [u8; 32], &'static mut [u8; 64])> {
+        if self.hash.is_some() {
+            return Err((ErrorCode::BUSY, hash, signature));
+        }
+        self.hash.replace(hash);
+        self.signature.replace(signature);
+        self.deferred_call.set();
+        Ok(())
+    }
+}
+
+impl DeferredCallClient for EcdsaP256SoftwareVerifier<'_> {
+    fn handle_deferred_call(&self) {
+        if let (Some(hash), Some(signature)) = (self.hash.take(), self.signature.take()) {
+            let result = verify_signature(self.public_key, hash, signature).ok_or(ErrorCode::FAIL);
+            self.client
+                .map(|client| client.verification_done(result, hash, signature));
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+pub struct EcdsaP256SoftwareSigner<'a> {
+    private_key: &'a [u8; 32],
+    client: OptionalCell<&'a dyn ClientSign<32, 64>>,
+    hash: TakeCell<'static, [u8; 32]>,
+    signature: TakeCell<'static, [u8; 64]>,
+    deferred_call: DeferredCall,
+}
+
+impl<'a> EcdsaP256SoftwareSigner<'a> {
+    /// `private_key` is the private scalar, 32 bytes big endian.
+    pub fn new(private_key: &'a [u8; 32]) -> EcdsaP256SoftwareSigner<'a> {
+        EcdsaP256SoftwareSigner {
+            private_key,
+            client: OptionalCell::empty(),
+            hash: TakeCell::empty(),
+            signature: TakeCell::empty(),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    /// The public key matching the private key, as the `x` and `y`
+    /// coordinates of the public point, for example to print it so that it
+    /// can be given to the verifiers of the signatures.
+    ///
+    /// This runs synchronously, and fails with `NOSUPPORT` if the private key
+    /// is not valid.
+    pub fn public_key(&self) -> Result<[u8; 64], ErrorCode> {
+        if !valid_private_key(self.private_key) {
+            return Err(ErrorCode::NOSUPPORT);
+        }
+        Ok(public_key(self.private_key))
+    }
+}
+
+impl<'a> SignatureSign<'a, 32, 64> for EcdsaP256SoftwareSigner<'a> {
+    fn set_sign_client(&self, client: &'a dyn ClientSign<32, 64>) {
+        self.client.set(client);
+    }
+
+    fn sign(
+        &self,
+        hash: &'static mut [u8; 32],
+        signature: &'static mut [u8; 64],
+    ) -> Result<(), (ErrorCode, &'static mut [u8; 32], &'static mut [u8; 64])> {
+        if self.hash.is_some() {
+            return Err((ErrorCode::BUSY, hash, signature));
+        }
+        if !valid_private_key(self.private_key) {
+            return Err((ErrorCode::NOSUPPORT, hash, signature));
+        }
+        self.hash.replace(hash);
+        self.signature.replace(signature);
+        self.deferred_call.set();
+        Ok(())
+    }
+}
+
+impl DeferredCallClient for EcdsaP256SoftwareSigner<'_> {
+    fn handle_deferred_call(&self) {
+        if let (Some(hash), Some(signature)) = (self.hash.take(), self.signature.take()) {
+            sign_hash(self.private_key, hash, signature);
+            self.client
+                .map(|client| client.signing_done(Ok(()), hash, signature));
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parse `s`, leaving the bytes after its end zero.
+    fn hex<const L: usize>(s: &str) -> [u8; L] {
+        let mut bytes = [0; L];
+        for (byte, digits) in bytes.iter_mut().zip(s.as_bytes().chunks(2)) {
+            *byte = u8::from_str_radix(core::str::from_utf8(digits).unwrap(), 16).unwrap();
+        }
+        bytes
+    }
+
+    /// Public key of RFC 6979, appendix A.2.5.
+    const PUBLIC_KEY: &str = "60FED4BA255A9D31C961EB74C6356D68C049B8923B61FA6CE669622E60F29FB6\
+                              7903FE1008B8BC99A41AE9E95628BC64F2F1B20C2D7E9F5177A3C294D4462299";
+    /// SHA-256 of "sample".
+    const SAMPLE_HASH: &str = "AF2BDBE1AA9B6EC1E2ADE1D694F41FC71A831D0268E9891562113D8A62ADD1BF";
+    /// Signature of "sample" with SHA-256 of RFC 6979, appendix A.2.5.
+    const SAMPLE_SIGNATURE: &str = "EFD48B2AACB6A8FD1140DD9CD45E81D69D2C877B56AAF991C34D0EA84EAF3716\
+                                    F7CB1C942D657C41D436C7A1B6E29F65F3E900DBB9AFF4064DC4AB2F843ACDA8";
+    /// The order of the base point, big endian.
+    const ORDER: &str = "FFFFFFFF00000000FFFFFFFFFFFFFFFFBCE6FAADA7179E84F3B9CAC2FC632551";
+    /// Private key of RFC 6979, appendix A.2.5.
+    const PRIVATE_KEY: &str = "C9AFA9D845BA75166B5C215767B1D6934E50C3DB36E89B127B8A622B120F6721";
+    /// SHA-256 of "test".
+    const TEST_HASH: &str = "9F86D081884C7D659A2FEAA0C55AD015A3BF4F1B2B0B822CD15D6C15B0F00A08";
+
+    #[test]
+    fn verify_valid_signature() {
+        assert_eq!(
+            verify_signature(&hex(PUBLIC_KEY), &hex(SAMPLE_HASH), &hex(SAMPLE_SIGNATURE)),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn reject_flipped_bits() {
+        let public_key = hex(PUBLIC_KEY);
+        for index in [0, 31, 32, 63] {
+            let mut signature: [u8; 64] = hex(SAMPLE_SIGNATURE);
+            signature[index] ^= 0x01;
+            assert_eq!(
+                verify_signature(&public_key, &hex(SAMPLE_HASH), &signature),
+                Some(false)
+            );
+        }
+        let mut hash: [u8; 32] = hex(SAMPLE_HASH);
+        hash[31] ^= 0x80;
+        assert_eq!(
+            verify_signature(&public_key, &hash, &hex(SAMPLE_SIGNATURE)),
+            Some(false)
+        );
+    }
+
+    #[test]
+    fn reject_out_of_range_signature() {
+        let public_key = hex(PUBLIC_KEY);
+        let valid: [u8; 64] = hex(SAMPLE_SIGNATURE);
+        let order: [u8; 32] = hex(ORDER);
+        let mut order_plus_one = order;
+        order_plus_one[31] += 1;
+        for value in [[0; 32], order, order_plus_one, [0xff; 32]] {
+            for half in [0..32, 32..64] {
+                let mut signature = valid;
+                signature[half].copy_from_slice(&value);
+                assert_eq!(
+                    verify_signature(&public_key, &hex(SAMPLE_HASH), &signature),
+                    Some(false)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn reject_public_key_off_curve() {
+        let mut public_key: [u8; 64] = hex(PUBLIC_KEY);
+        public_key[63] ^= 0x01;
+        assert_eq!(
+            verify_signature(&public_key, &hex(SAMPLE_HASH), &hex(SAMPLE_SIGNATURE)),
+            None
+        );
+        // Coordinates not below the field prime.
+        assert_eq!(
+            verify_signature(&[0xff; 64], &hex(SAMPLE_HASH), &hex(SAMPLE_SIGNATURE)),
+            None
+        );
+    }
+
+    /// Test cases 1 to 7 of RFC 4231.
+    #[test]
+    fn hmac_sha256_rfc4231() {
+        let key4: [u8; 25] = core::array::from_fn(|i| i as u8 + 1);
+        let cases: [(&[u8], &[u8], &str); 7] = [
+            (
+                &[0x0b; 20],
+                b"Hi There",
+                "B0344C61D8DB38535CA8AFCEAF0BF12B881DC200C9833DA726E9376C2E32CFF7",
+            ),
+            (
+                b"Jefe",
+                b"what do ya want for nothing?",
+                "5BDCC146BF60754E6A042426089575C75A003F089D2739839DEC58B964EC3843",
+            ),
+            (
+                &[0xaa; 20],
+                &[0xdd; 50],
+                "773EA91E36800E46854DB8EBD09181A72959098B3EF8C122D9635514CED565FE",
+            ),
+            (
+                &key4,
+                &[0xcd; 50],
+                "82558A389A443C0EA4CC819899F2083A85F0FAA3E578F8077A2E3FF46729665B",
+            ),
+            // Truncated to 128 bits in the RFC.
+            (
+                &[0x0c; 20],
+                b"Test With Truncation",
+                "A3B6167473100EE06E0C796C2955552B",
+            ),
+            (
+                &[0xaa; 131],
+                b"Test Using Larger Than Block-Size Key - Hash Key First",
+                "60E431591EE0B67F0D8A26AACBF5B77F8E0BC6213728C5140546040F0EE37F54",
+            ),
+            (
+                &[0xaa; 131],
+                b"This is a test using a larger than block-size key and a larger than \
+                  block-size data. The key needs to be hashed before being used by the \
+                  HMAC algorithm.",
+                "9B09FFA71B942FCB27635FBCD5B0E944BFDC63644F0713938A7F51535C3A35E2",
+            ),
+        ];
+        for (key, data, mac) in cases {
+            // Split the data to cover the concatenation of parts.
+            let (first, second) = data.split_at(data.len() / 3);
+            let computed = hmac_sha256(key, &[first, second]);
+            let expected: [u8; 32] = hex(mac);
+            let len = mac.len() / 2;
+            assert_eq!(computed[..len], expected[..len]);
+        }
+    }
+
+    /// Nonces and signatures with SHA-256 of RFC 6979, appendix A.2.5.
+    #[test]
+    fn sign_rfc6979() {
+        let private_key = hex(PRIVATE_KEY);
+        let d = from_be_bytes(&private_key);
+        for (hash, nonce, signature) in [
+            (
+                SAMPLE_HASH,
+                "A6E3C57DD01ABE90086538398355DD4C3B17AA873382B0F24D6129493D8AAD60",
+                SAMPLE_SIGNATURE,
+            ),
+            (
+                TEST_HASH,
+                "D16B6AE827F17175E040871A1C7EC3500192C4C92677336EC2537ACAEE0008E0",
+                "F1ABB023518351CD71D881567B1EA663ED3EFCF6C5132B354F28D3B0B7D38367\
+                 019F4113742A2B14BD25926B49C649155F267E60D3814B4C0CC84250E46F0083",
+            ),
+        ] {
+            let hash: [u8; 32] = hex(hash);
+            let digest = N.reduce(&from_be_bytes(&hash));
+            let k = NonceGenerator::new(&private_key, &digest).next_nonce();
+            assert_eq!(k, from_be_bytes(&hex::<32>(nonce)));
+
+            let (r, s) = sign_with_nonce(&d, &digest, &k).unwrap();
+            let expected: [u8; 64] = hex(signature);
+            assert_eq!(r, from_be_bytes(&expected[..32]));
+            assert_eq!(s, from_be_bytes(&expected[32..]));
+
+            let mut computed = [0; 64];
+            sign_hash(&private_key, &hash, &mut computed);
+            assert_eq!(computed, expected);
+            assert_eq!(
+                verify_signature(&hex(PUBLIC_KEY), &hash, &computed),
+                Some(true)
+            );
+        }
+    }
+
+    #[test]
+    fn montgomery_constants() {
+        assert_eq!(MONTGOMERY_ONE, P.to_montgomery(&ONE));
+        assert_eq!(MONTGOMERY_B, P.to_montgomery(&B));
+    }
+
+    #[test]
+    fn complete_addition() {
+        let g = Point::from_affine(&GX, &GY);
+        let g2 = g.double();
+        assert!(Point::INFINITY.double().is_infinity());
+        assert!(Point::INFINITY.add(&Point::INFINITY).is_infinity());
+        assert_eq!(Point::INFINITY.add(&g).affine(), (GX, GY));
+        assert_eq!(g.add(&Point::INFINITY).affine(), (GX, GY));
+        assert_eq!(g.add(&g).affine(), g2.affine());
+        assert_eq!(g2.add(&g).affine(), g.add(&g2).affine());
+
+        // G + (-G) is the point at infinity.
+        let minus_g = Point::from_affine(&GX, &P.sub(&P.m, &GY));
+        assert!(g.add(&minus_g).is_infinity());
+    }
+
+    #[test]
+    fn ladder_multiplication() {
+        let g = Point::from_affine(&GX, &GY);
+        let mut sum = Point::INFINITY;
+        for k in 1..8u32 {
+            sum = sum.add(&g);
+            assert_eq!(g.mul(&[k, 0, 0, 0, 0, 0, 0, 0]).affine(), sum.affine());
+        }
+        assert!(g.mul(&[0; 8]).is_infinity());
+        assert!(g.mul(&N.m).is_infinity());
+        // (n - 1) * G is -G.
+        let n_minus_one = sub(&N.m, &ONE).0;
+        assert_eq!(g.mul(&n_minus_one).affine(), (GX, P.sub(&P.m, &GY)));
+    }
+
+    #[test]
+    fn public_key_of_private_key() {
+        assert_eq!(public_key(&hex(PRIVATE_KEY)), hex(PUBLIC_KEY));
+        assert!(!valid_private_key(&[0; 32]));
+        assert!(!valid_private_key(&hex(ORDER)));
+        assert!(valid_private_key(&hex(PRIVATE_KEY)));
+    }
+}
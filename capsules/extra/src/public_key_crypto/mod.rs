@@ -4,4 +4,5 @@
 
 //! Provides capsules for asymmetric encryption
 
+pub mod ecdsa_p256;
 pub mod rsa_keys;
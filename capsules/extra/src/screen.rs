@@ -13,6 +13,16 @@
 //! let screen =
 //!     components::screen::ScreenComponent::new(board_kernel, tft).finalize();
 //! ```
+//!
+//! Frame Streaming
+//! ---------------
+//!
+//! Animations submit whole frames with the frame command instead of writes.
+//! A process shares two frame buffers and submits them in turn: while one is
+//! written to the screen, the next one waits, and the process draws into the
+//! other. When the screen has finished writing a frame, the process receives
+//! the frame upcall for its buffer and the next frame starts, so frames are
+//! paced by the screen and the process never has to poll.
 
 use core::cell::Cell;
 
@@ -32,8 +42,20 @@ pub const DRIVER_NUM: usize = driver::NUM::Screen as usize;
 /// Ids for read-only allow buffers
 mod ro_allow {
     pub const SHARED: usize = 0;
+    /// Second frame buffer for frame streaming. The first one is `SHARED`.
+    pub const FRAME: usize = 1;
     /// The number of allow buffers the kernel stores for this grant
-    pub const COUNT: u8 = 1;
+    pub const COUNT: u8 = 2;
+}
+
+/// Ids for subscribe upcalls
+mod upcall {
+    /// A command finished.
+    pub const COMMAND: usize = 0;
+    /// A frame was written to the screen and its buffer can be reused.
+    pub const FRAME: usize = 1;
+    /// The number of upcalls the kernel stores for this grant
+    pub const COUNT: u8 = 2;
 }
 
 fn screen_rotation_from(screen_rotation: usize) -> Option<ScreenRotation> {
@@ -76,9 +98,26 @@ enum ScreenCommand {
         height: usize,
     },
     Write(usize),
+    /// Write the frame of `len` bytes in the allow buffer `buffer`.
+    Frame {
+        buffer: usize,
+        len: usize,
+    },
     Fill,
 }
 
+impl ScreenCommand {
+    /// The allow buffer the command writes to the screen, and the length of
+    /// the data requested.
+    fn write_source(&self) -> Option<(usize, usize)> {
+        match *self {
+            ScreenCommand::Write(len) => Some((ro_allow::SHARED, len)),
+            ScreenCommand::Frame { buffer, len } => Some((buffer, len)),
+            _ => None,
+        }
+    }
+}
+
 fn pixels_in_bytes(pixels: usize, bits_per_pixel: usize) -> usize {
     let bytes = pixels * bits_per_pixel / 8;
     if pixels * bits_per_pixel % 8 != 0 {
@@ -93,6 +132,8 @@ pub struct App {
     write_position: usize,
     write_len: usize,
     command: ScreenCommand,
+    /// Frame submitted while the previous one is written, as `(buffer, len)`.
+    next_frame: Option<(usize, usize)>,
     width: usize,
     height: usize,
 }
@@ -102,6 +143,7 @@ impl Default for App {
         App {
             pending_command: false,
             command: ScreenCommand::Nop,
+            next_frame: None,
             width: 0,
             height: 0,
             write_len: 0,
@@ -113,7 +155,12 @@ impl Default for App {
 pub struct Screen<'a> {
     screen: &'a dyn hil::screen::Screen<'a>,
     screen_setup: Option<&'a dyn hil::screen::ScreenSetup<'a>>,
-    apps: Grant<App, UpcallCount<1>, AllowRoCount<{ ro_allow::COUNT }>, AllowRwCount<0>>,
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<0>,
+    >,
     current_process: OptionalCell<ProcessId>,
     pixel_format: Cell<ScreenPixelFormat>,
     buffer: TakeCell<'static, [u8]>,
@@ -124,7 +171,12 @@ impl<'a> Screen<'a> {
         screen: &'a dyn hil::screen::Screen<'a>,
         screen_setup: Option<&'a dyn hil::screen::ScreenSetup<'a>>,
         buffer: &'static mut [u8],
-        grant: Grant<App, UpcallCount<1>, AllowRoCount<{ ro_allow::COUNT }>, AllowRwCount<0>>,
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<0>,
+        >,
     ) -> Screen<'a> {
         Screen {
             screen,
@@ -170,6 +222,36 @@ impl<'a> Screen<'a> {
         }
     }
 
+    /// Submit a frame. If the process is already streaming frames, the frame
+    /// starts once the screen finished the current one.
+    fn enqueue_frame(&self, buffer: usize, len: usize, process_id: ProcessId) -> CommandReturn {
+        if buffer != ro_allow::SHARED && buffer != ro_allow::FRAME {
+            return CommandReturn::failure(ErrorCode::INVAL);
+        }
+        let queued = self
+            .apps
+            .enter(process_id, |app, _| match app.command {
+                ScreenCommand::Frame { .. } if app.pending_command => {
+                    if app.next_frame.is_some() {
+                        Err(ErrorCode::BUSY)
+                    } else {
+                        app.next_frame = Some((buffer, len));
+                        Ok(true)
+                    }
+                }
+                _ => {
+                    app.next_frame = None;
+                    Ok(false)
+                }
+            })
+            .unwrap_or_else(|err| Err(err.into()));
+        match queued {
+            Ok(true) => CommandReturn::success(),
+            Ok(false) => self.enqueue_command(ScreenCommand::Frame { buffer, len }, process_id),
+            Err(e) => CommandReturn::failure(e),
+        }
+    }
+
     fn is_len_multiple_color_depth(&self, len: usize) -> bool {
         let depth = pixels_in_bytes(1, self.screen.get_pixel_format().get_bits_per_pixel());
         (len % depth) == 0
@@ -240,12 +322,13 @@ impl<'a> Screen<'a> {
                 }
             }
 
-            ScreenCommand::Write(data_len) => {
+            ScreenCommand::Write(_) | ScreenCommand::Frame { .. } => {
+                let (source, data_len) = command.write_source().unwrap_or((ro_allow::SHARED, 0));
                 match self
                     .apps
                     .enter(process_id, |app, kernel_data| {
                         let len = kernel_data
-                            .get_readonly_processbuffer(ro_allow::SHARED)
+                            .get_readonly_processbuffer(source)
                             .map_or(0, |shared| shared.len())
                             .min(data_len);
                         // Ensure we have a buffer that is the correct size
@@ -299,7 +382,21 @@ impl<'a> Screen<'a> {
         self.current_process.take().map(|process_id| {
             let _ = self.apps.enter(process_id, |app, upcalls| {
                 app.pending_command = false;
-                upcalls.schedule_upcall(0, (data1, data2, data3)).ok();
+                if let ScreenCommand::Frame { buffer, .. } = app.command {
+                    upcalls
+                        .schedule_upcall(upcall::FRAME, (data1, buffer, 0))
+                        .ok();
+                    // The next frame starts like any other pending command.
+                    if let Some((buffer, len)) = app.next_frame.take() {
+                        app.command = ScreenCommand::Frame { buffer, len };
+                        app.write_position = 0;
+                        app.pending_command = true;
+                    }
+                } else {
+                    upcalls
+                        .schedule_upcall(upcall::COMMAND, (data1, data2, data3))
+                        .ok();
+                }
             });
         });
     }
@@ -348,9 +445,13 @@ impl<'a> Screen<'a> {
                         let initial_pos = chunk_number * buffer_size;
                         let mut pos = initial_pos;
                         match app.command {
-                            ScreenCommand::Write(_) => {
+                            ScreenCommand::Write(_) | ScreenCommand::Frame { .. } => {
+                                let source = app
+                                    .command
+                                    .write_source()
+                                    .map_or(ro_allow::SHARED, |(source, _)| source);
                                 let res = kernel_data
-                                    .get_readonly_processbuffer(ro_allow::SHARED)
+                                    .get_readonly_processbuffer(source)
                                     .and_then(|shared| {
                                         shared.enter(|s| {
                                             let mut count = 0;
@@ -566,6 +667,8 @@ impl SyscallDriver for Screen<'_> {
             ),
             // Write
             200 => self.enqueue_command(ScreenCommand::Write(data1), process_id),
+            // Submit frame
+            201 => self.enqueue_frame(data1, data2, process_id),
             // Fill
             300 => self.enqueue_command(ScreenCommand::Fill, process_id),
 
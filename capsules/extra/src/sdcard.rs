@@ -234,6 +234,8 @@ enum SDCardType {
 const SUCCESS_STATUS: u8 = 0x00;
 const INITIALIZING_STATUS: u8 = 0x01;
 const DATA_TOKEN: u8 = 0xFE;
+/// Size of the blocks of the card, which is set during initialization.
+const BLOCK_SIZE: usize = 512;
 
 /// Callback functions from SDCard
 pub trait SDCardClient {
@@ -1268,6 +1270,11 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
         self.is_initialized.get()
     }
 
+    /// Take back the buffer of a read or write that failed with an error
+    pub fn reclaim_buffer(&self) -> Option<&'static mut [u8]> {
+        self.client_buffer.take()
+    }
+
     /// watches SD card detect pin for changes, sends callback on change
     pub fn detect_changes(&self) {
         self.detect_pin.get().map(|pin| {
@@ -1307,57 +1314,8 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
         sector: u32,
         count: u32,
     ) -> Result<(), ErrorCode> {
-        // only if initialized and installed
-        if self.is_installed() {
-            if self.is_initialized() {
-                self.txbuffer
-                    .take()
-                    .map_or(Err(ErrorCode::NOMEM), |txbuffer| {
-                        self.rxbuffer
-                            .take()
-                            .map_or(Err(ErrorCode::NOMEM), move |rxbuffer| {
-                                // save the user buffer for later
-                                self.client_buffer.replace(buffer);
-                                self.client_offset.set(0);
-
-                                // convert block address to byte address for non-block
-                                //  access cards
-                                let mut address = sector;
-                                if self.card_type.get() != SDCardType::SDv2BlockAddressable {
-                                    address *= 512;
-                                }
-
-                                self.state.set(SpiState::StartReadBlocks { count });
-                                if count == 1 {
-                                    self.send_command(
-                                        SDCmd::CMD17_ReadSingle,
-                                        address,
-                                        txbuffer,
-                                        rxbuffer,
-                                        10,
-                                    );
-                                } else {
-                                    self.send_command(
-                                        SDCmd::CMD18_ReadMultiple,
-                                        address,
-                                        txbuffer,
-                                        rxbuffer,
-                                        10,
-                                    );
-                                }
-
-                                // command started successfully
-                                Ok(())
-                            })
-                    })
-            } else {
-                // sd card not initialized
-                Err(ErrorCode::RESERVE)
-            }
-        } else {
-            // sd card not installed
-            Err(ErrorCode::UNINSTALLED)
-        }
+        self.start_transfer(buffer, sector, count, false)
+            .map_err(|(e, _)| e)
     }
 
     pub fn write_blocks(
@@ -1366,52 +1324,71 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
         sector: u32,
         count: u32,
     ) -> Result<(), ErrorCode> {
+        self.start_transfer(buffer, sector, count, true)
+            .map_err(|(e, _)| e)
+    }
+
+    /// Start reading or writing blocks, passing back `buffer` if the
+    /// transfer could not be started.
+    fn start_transfer(
+        &self,
+        buffer: &'static mut [u8],
+        sector: u32,
+        count: u32,
+        write: bool,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
         // only if initialized and installed
-        if self.is_installed() {
-            if self.is_initialized() {
-                self.txbuffer
-                    .take()
-                    .map_or(Err(ErrorCode::NOMEM), |txbuffer| {
-                        self.rxbuffer
-                            .take()
-                            .map_or(Err(ErrorCode::NOMEM), move |rxbuffer| {
-                                // save the user buffer for later
-                                self.client_buffer.replace(buffer);
-                                self.client_offset.set(0);
-
-                                // convert block address to byte address for non-block
-                                //  access cards
-                                let mut address = sector;
-                                if self.card_type.get() != SDCardType::SDv2BlockAddressable {
-                                    address *= 512;
-                                }
+        if !self.is_installed() {
+            // sd card not installed
+            return Err((ErrorCode::UNINSTALLED, buffer));
+        }
+        if !self.is_initialized() {
+            // sd card not initialized
+            return Err((ErrorCode::RESERVE, buffer));
+        }
+        if write && count != 1 {
+            // can't write multiple blocks yet
+            return Err((ErrorCode::NOSUPPORT, buffer));
+        }
+        let Some(txbuffer) = self.txbuffer.take() else {
+            return Err((ErrorCode::NOMEM, buffer));
+        };
+        let Some(rxbuffer) = self.rxbuffer.take() else {
+            self.txbuffer.replace(txbuffer);
+            return Err((ErrorCode::NOMEM, buffer));
+        };
+
+        // save the user buffer for later
+        self.client_buffer.replace(buffer);
+        self.client_offset.set(0);
+
+        // convert block address to byte address for non-block
+        //  access cards
+        let mut address = sector;
+        if self.card_type.get() != SDCardType::SDv2BlockAddressable {
+            address *= 512;
+        }
 
-                                self.state.set(SpiState::StartWriteBlocks { count });
-                                if count == 1 {
-                                    self.send_command(
-                                        SDCmd::CMD24_WriteSingle,
-                                        address,
-                                        txbuffer,
-                                        rxbuffer,
-                                        10,
-                                    );
-
-                                    // command started successfully
-                                    Ok(())
-                                } else {
-                                    // can't write multiple blocks yet
-                                    Err(ErrorCode::NOSUPPORT)
-                                }
-                            })
-                    })
+        let cmd = if write {
+            self.state.set(SpiState::StartWriteBlocks { count });
+            SDCmd::CMD24_WriteSingle
+        } else {
+            self.state.set(SpiState::StartReadBlocks { count });
+            if count == 1 {
+                SDCmd::CMD17_ReadSingle
             } else {
-                // sd card not initialized
-                Err(ErrorCode::RESERVE)
+                SDCmd::CMD18_ReadMultiple
             }
-        } else {
-            // sd card not installed
-            Err(ErrorCode::UNINSTALLED)
-        }
+        };
+        self.send_command(cmd, address, txbuffer, rxbuffer, 10);
+
+        // command started successfully
+        Ok(())
+    }
+
+    /// Whether a transaction with the card is in progress.
+    fn busy(&self) -> bool {
+        self.txbuffer.is_none() || self.rxbuffer.is_none()
     }
 }
 
@@ -1466,6 +1443,161 @@ impl<'a, A: hil::time::Alarm<'a>> hil::gpio::Client for SDCard<'a, A> {
     }
 }
 
+/// Operation in progress on an SDCardBlockStorage.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BlockOperation {
+    Idle,
+    Initialize,
+    Read,
+    Write,
+}
+
+/// Block storage interface to an SD card.
+///
+/// This lets capsules written against the `BlockStorage` HIL, such as the
+/// FAT filesystem, use an SD card. It becomes the client of the SDCard, so
+/// it cannot be used together with SDCardDriver.
+pub struct SDCardBlockStorage<'a, A: hil::time::Alarm<'a>> {
+    sdcard: &'a SDCard<'a, A>,
+    num_blocks: Cell<Option<u64>>,
+    operation: Cell<BlockOperation>,
+    client: OptionalCell<&'a dyn hil::block_storage::BlockStorageClient>,
+}
+
+impl<'a, A: hil::time::Alarm<'a>> SDCardBlockStorage<'a, A> {
+    pub fn new(sdcard: &'a SDCard<'a, A>) -> SDCardBlockStorage<'a, A> {
+        SDCardBlockStorage {
+            sdcard,
+            num_blocks: Cell::new(None),
+            operation: Cell::new(BlockOperation::Idle),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    fn check_access(&self, buffer: &[u8], block: u64, count: u32) -> Result<u32, ErrorCode> {
+        if self.operation.get() != BlockOperation::Idle || self.sdcard.busy() {
+            return Err(ErrorCode::BUSY);
+        }
+        let num_blocks = self.num_blocks.get().ok_or(ErrorCode::RESERVE)?;
+        if count == 0
+            || block + count as u64 > num_blocks
+            || buffer.len() < count as usize * BLOCK_SIZE
+        {
+            return Err(ErrorCode::INVAL);
+        }
+        u32::try_from(block).or(Err(ErrorCode::INVAL))
+    }
+
+    /// Hand the buffer of the failed operation back to the client.
+    fn fail_operation(&self, error: ErrorCode) {
+        let operation = self.operation.replace(BlockOperation::Idle);
+        self.client.map(|client| match operation {
+            BlockOperation::Idle => {}
+            BlockOperation::Initialize => client.initialize_done(Err(error)),
+            BlockOperation::Read => {
+                if let Some(buffer) = self.sdcard.reclaim_buffer() {
+                    client.read_done(buffer, Err(error));
+                }
+            }
+            BlockOperation::Write => {
+                if let Some(buffer) = self.sdcard.reclaim_buffer() {
+                    client.write_done(buffer, Err(error));
+                }
+            }
+        });
+    }
+}
+
+impl<'a, A: hil::time::Alarm<'a>> hil::block_storage::BlockStorage<'a>
+    for SDCardBlockStorage<'a, A>
+{
+    fn set_client(&self, client: &'a dyn hil::block_storage::BlockStorageClient) {
+        self.client.set(client);
+    }
+
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn num_blocks(&self) -> Option<u64> {
+        self.num_blocks.get()
+    }
+
+    fn is_ready(&self) -> bool {
+        self.num_blocks.get().is_some() && self.sdcard.is_initialized()
+    }
+
+    fn initialize(&self) -> Result<(), ErrorCode> {
+        if self.operation.get() != BlockOperation::Idle || self.sdcard.busy() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.num_blocks.set(None);
+        self.sdcard.initialize()?;
+        self.operation.set(BlockOperation::Initialize);
+        Ok(())
+    }
+
+    fn read(
+        &self,
+        buffer: &'static mut [u8],
+        block: u64,
+        count: u32,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        let sector = match self.check_access(buffer, block, count) {
+            Ok(sector) => sector,
+            Err(e) => return Err((e, buffer)),
+        };
+        self.sdcard.start_transfer(buffer, sector, count, false)?;
+        self.operation.set(BlockOperation::Read);
+        Ok(())
+    }
+
+    fn write(
+        &self,
+        buffer: &'static mut [u8],
+        block: u64,
+        count: u32,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        let sector = match self.check_access(buffer, block, count) {
+            Ok(sector) => sector,
+            Err(e) => return Err((e, buffer)),
+        };
+        self.sdcard.start_transfer(buffer, sector, count, true)?;
+        self.operation.set(BlockOperation::Write);
+        Ok(())
+    }
+}
+
+impl<'a, A: hil::time::Alarm<'a>> SDCardClient for SDCardBlockStorage<'a, A> {
+    fn card_detection_changed(&self, installed: bool) {
+        self.num_blocks.set(None);
+        self.operation.set(BlockOperation::Idle);
+        self.client.map(|client| client.media_changed(installed));
+    }
+
+    fn init_done(&self, block_size: u32, total_size: u64) {
+        self.num_blocks.set(Some(total_size / block_size as u64));
+        self.operation.set(BlockOperation::Idle);
+        self.client.map(|client| client.initialize_done(Ok(())));
+    }
+
+    fn read_done(&self, data: &'static mut [u8], _len: usize) {
+        self.operation.set(BlockOperation::Idle);
+        self.client
+            .map(move |client| client.read_done(data, Ok(())));
+    }
+
+    fn write_done(&self, buffer: &'static mut [u8]) {
+        self.operation.set(BlockOperation::Idle);
+        self.client
+            .map(move |client| client.write_done(buffer, Ok(())));
+    }
+
+    fn error(&self, _error: u32) {
+        self.fail_operation(ErrorCode::FAIL);
+    }
+}
+
 /// Application driver for SD Card capsule.
 ///
 /// This is used if the SDCard is going to be attached directly to userspace
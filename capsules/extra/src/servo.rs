@@ -4,6 +4,10 @@
 
 //! This provides virtualized userspace access to a servomotor.
 //!
+//! Any process can set the angle of a servo, unless a process claimed the
+//! servo, in which case only that process can until it releases it. This
+//! lets several applications each drive their own servos.
+//!
 //! Usage
 //! -----
 //!
@@ -31,12 +35,17 @@
 //! );
 //! let servo = static_init!(
 //! capsules_extra::servo::Servo<'static, 2>,
-//! capsules_extra::servo::Servo::new(multi_servo)
+//! capsules_extra::servo::Servo::new(
+//!     multi_servo,
+//!     board_kernel.create_grant(capsules_extra::servo::DRIVER_NUM, &grant_cap),
+//! )
 //! );
 //! ```
 
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
 use kernel::hil;
 use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::OptionalCell;
 use kernel::{ErrorCode, ProcessId};
 
 /// Syscall driver number.
@@ -46,11 +55,30 @@ pub const DRIVER_NUM: usize = driver::NUM::Servo as usize;
 pub struct Servo<'a, const SERVO_COUNT: usize> {
     /// The service capsule servo.
     servo: &'a [&'a dyn hil::servo::Servo<'a>; SERVO_COUNT],
+    /// The process that claimed each servo.
+    owners: [OptionalCell<ProcessId>; SERVO_COUNT],
+    apps: Grant<(), UpcallCount<0>, AllowRoCount<0>, AllowRwCount<0>>,
 }
 
 impl<'a, const SERVO_COUNT: usize> Servo<'a, SERVO_COUNT> {
-    pub fn new(servo: &'a [&'a dyn hil::servo::Servo<'a>; SERVO_COUNT]) -> Self {
-        Self { servo }
+    pub fn new(
+        servo: &'a [&'a dyn hil::servo::Servo<'a>; SERVO_COUNT],
+        grant: Grant<(), UpcallCount<0>, AllowRoCount<0>, AllowRwCount<0>>,
+    ) -> Self {
+        Self {
+            servo,
+            owners: [const { OptionalCell::empty() }; SERVO_COUNT],
+            apps: grant,
+        }
+    }
+
+    /// Whether `processid` may drive the servo at `servo_index`, which is
+    /// the case if the servo is not claimed by another process that still
+    /// exists.
+    fn may_drive(&self, servo_index: usize, processid: ProcessId) -> bool {
+        self.owners[servo_index].map_or(true, |owner| {
+            owner == processid || self.apps.enter(owner, |_, _| {}).is_err()
+        })
     }
 }
 /// Provide an interface for userland.
@@ -63,14 +91,19 @@ impl<const SERVO_COUNT: usize> SyscallDriver for Servo<'_, SERVO_COUNT> {
     /// - `1`: Returns an u32 representing the number of available servomotors.
     /// - `2`: Changing the angle immediatelly.`servo_index` receives the index
     /// corresponding to the servo whose angle we want to adjust
-    /// `angle` is used to receive a value between 0 and 180.
+    /// `angle` is used to receive a value between 0 and 180. Returns
+    /// `RESERVE` if another process claimed the servo.
     /// - `3`: Returning the current angle for a specific index.
+    /// - `4`: Claim the servo at `servo_index`, so that other processes cannot
+    /// change its angle. Returns `RESERVE` if another process claimed it.
+    /// - `5`: Release the servo at `servo_index`. Returns `RESERVE` if another
+    /// process claimed it.
     fn command(
         &self,
         command_num: usize,
         servo_index: usize,
         angle: usize,
-        _processid: ProcessId,
+        processid: ProcessId,
     ) -> CommandReturn {
         match command_num {
             // Check whether the driver exists.
@@ -81,6 +114,8 @@ impl<const SERVO_COUNT: usize> SyscallDriver for Servo<'_, SERVO_COUNT> {
             2 => {
                 if servo_index >= SERVO_COUNT {
                     CommandReturn::failure(ErrorCode::NODEVICE)
+                } else if !self.may_drive(servo_index, processid) {
+                    CommandReturn::failure(ErrorCode::RESERVE)
                 } else {
                     match angle.try_into() {
                         Ok(angle) => match self.servo[servo_index].set_angle(angle) {
@@ -102,11 +137,26 @@ impl<const SERVO_COUNT: usize> SyscallDriver for Servo<'_, SERVO_COUNT> {
                     }
                 }
             }
+            // Claim or release the servo.
+            4 | 5 => {
+                if servo_index >= SERVO_COUNT {
+                    CommandReturn::failure(ErrorCode::NODEVICE)
+                } else if !self.may_drive(servo_index, processid) {
+                    CommandReturn::failure(ErrorCode::RESERVE)
+                } else {
+                    if command_num == 4 {
+                        self.owners[servo_index].set(processid);
+                    } else {
+                        self.owners[servo_index].clear();
+                    }
+                    CommandReturn::success()
+                }
+            }
             _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
         }
     }
 
-    fn allocate_grant(&self, _process_id: ProcessId) -> Result<(), kernel::process::Error> {
-        Ok(())
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
     }
 }
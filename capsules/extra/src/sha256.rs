@@ -48,6 +48,132 @@ const ROUND_CONSTANTS: [u32; NUM_ROUND_CONSTANTS] = [
     0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
 ];
 
+/// Hash values before the first block.
+const INITIAL_HASH_VALUES: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+fn right_rotate(x: u32, rotate: u32) -> u32 {
+    (x >> rotate) | (x << (32 - rotate))
+}
+
+/// Update `hash_values` with a 64 byte `block`.
+fn compress_block(hash_values: &mut [u32; 8], block: &[u8]) {
+    // This is clearly inefficient (copy a u8 array into a u32
+    // array), but it's better than using unsafe.  This
+    // implementation is not intended to be high performance.
+    let mut message_schedule: [u32; 64] = [0; 64];
+    for i in 0..16 {
+        let val: u32 = (block[i * 4 + 0] as u32) << 24
+            | (block[i * 4 + 1] as u32) << 16
+            | (block[i * 4 + 2] as u32) << 8
+            | (block[i * 4 + 3] as u32);
+        message_schedule[i] = val;
+    }
+
+    // Message schedule
+    for i in 16..64 {
+        let mut s0 = right_rotate(message_schedule[i - 15], 7);
+        s0 ^= right_rotate(message_schedule[i - 15], 18);
+        s0 ^= message_schedule[i - 15] >> 3;
+        let mut s1 = right_rotate(message_schedule[i - 2], 17);
+        s1 ^= right_rotate(message_schedule[i - 2], 19);
+        s1 ^= message_schedule[i - 2] >> 10;
+        message_schedule[i] = message_schedule[i - 16]
+            .wrapping_add(s0)
+            .wrapping_add(message_schedule[i - 7])
+            .wrapping_add(s1);
+    }
+
+    // Compression
+    let mut hashes = *hash_values;
+    for i in 0..64 {
+        let s1 =
+            right_rotate(hashes[4], 6) ^ right_rotate(hashes[4], 11) ^ right_rotate(hashes[4], 25);
+        let ch = (hashes[4] & hashes[5]) ^ ((!hashes[4]) & hashes[6]);
+        let constant = ROUND_CONSTANTS[i];
+        let temp1 = hashes[7]
+            .wrapping_add(s1)
+            .wrapping_add(ch)
+            .wrapping_add(constant)
+            .wrapping_add(message_schedule[i]);
+        let s0 =
+            right_rotate(hashes[0], 2) ^ right_rotate(hashes[0], 13) ^ right_rotate(hashes[0], 22);
+        let maj = (hashes[0] & hashes[1]) ^ (hashes[0] & hashes[2]) ^ (hashes[1] & hashes[2]);
+        let temp2 = s0.wrapping_add(maj);
+
+        hashes[7] = hashes[6];
+        hashes[6] = hashes[5];
+        hashes[5] = hashes[4];
+        hashes[4] = hashes[3].wrapping_add(temp1);
+        hashes[3] = hashes[2];
+        hashes[2] = hashes[1];
+        hashes[1] = hashes[0];
+        hashes[0] = temp1.wrapping_add(temp2);
+    }
+
+    for i in 0..8 {
+        hash_values[i] = hash_values[i].wrapping_add(hashes[i]);
+    }
+}
+
+/// SHA-256 computed synchronously, for capsules that need a hash in the
+/// middle of a computation, like the HMAC of the ECDSA nonce derivation.
+pub struct Sha256Hasher {
+    hash_values: [u32; 8],
+    block: [u8; SHA_BLOCK_LEN_BYTES],
+    buffered: usize,
+    total: usize,
+}
+
+impl Sha256Hasher {
+    pub fn new() -> Sha256Hasher {
+        Sha256Hasher {
+            hash_values: INITIAL_HASH_VALUES,
+            block: [0; SHA_BLOCK_LEN_BYTES],
+            buffered: 0,
+            total: 0,
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.block[self.buffered] = byte;
+            self.buffered += 1;
+            if self.buffered == SHA_BLOCK_LEN_BYTES {
+                compress_block(&mut self.hash_values, &self.block);
+                self.buffered = 0;
+            }
+        }
+        self.total += data.len();
+    }
+
+    pub fn finish(mut self) -> [u8; SHA_256_OUTPUT_LEN_BYTES] {
+        let length = (self.total as u64 * 8).to_be_bytes();
+        self.block[self.buffered] = 0x80;
+        self.block[self.buffered + 1..].fill(0);
+        if self.buffered >= SHA_BLOCK_LEN_BYTES - 8 {
+            // No room for the length in this block.
+            compress_block(&mut self.hash_values, &self.block);
+            self.block.fill(0);
+        }
+        self.block[SHA_BLOCK_LEN_BYTES - 8..].copy_from_slice(&length);
+        compress_block(&mut self.hash_values, &self.block);
+
+        let mut hash = [0; SHA_256_OUTPUT_LEN_BYTES];
+        for (bytes, value) in hash.chunks_exact_mut(4).zip(self.hash_values) {
+            bytes.copy_from_slice(&value.to_be_bytes());
+        }
+        hash
+    }
+}
+
+impl Default for Sha256Hasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub struct Sha256Software<'a> {
     state: Cell<State>,
 
@@ -106,10 +232,7 @@ impl Sha256Software<'_> {
                 b[i] = 0;
             }
         });
-        self.hash_values.set([
-            0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
-            0x5be0cd19,
-        ]);
+        self.hash_values.set(INITIAL_HASH_VALUES);
     }
 
     // Complete the hash and produce a final hash result.
@@ -227,73 +350,16 @@ impl Sha256Software<'_> {
         }
     }
 
-    fn right_rotate(&self, x: u32, rotate: u32) -> u32 {
-        (x >> rotate) | (x << (32 - rotate))
-    }
-
     // Note: slice MUST be >= 64 bytes long
     fn compute_buffer(&self, buffer: &[u8]) {
-        // This is clearly inefficient (copy a u8 array into a u32
-        // array), but it's better than using unsafe.  This
-        // implementation is not intended to be high performance.
-        let mut message_schedule: [u32; 64] = [0; 64];
-        for i in 0..16 {
-            let val: u32 = (buffer[i * 4 + 0] as u32) << 24
-                | (buffer[i * 4 + 1] as u32) << 16
-                | (buffer[i * 4 + 2] as u32) << 8
-                | (buffer[i * 4 + 3] as u32);
-            message_schedule[i] = val;
-        }
-        self.perform_sha(&mut message_schedule);
+        let mut hash_values = self.hash_values.get();
+        compress_block(&mut hash_values, buffer);
+        self.hash_values.set(hash_values);
     }
 
     fn compute_block(&self, data: &[u8; 64]) {
         self.compute_buffer(data);
     }
-
-    fn perform_sha(&self, message_schedule: &mut [u32; 64]) {
-        // Message schedule
-        for i in 16..64 {
-            let mut s0 = self.right_rotate(message_schedule[i - 15], 7);
-            s0 ^= self.right_rotate(message_schedule[i - 15], 18);
-            s0 ^= message_schedule[i - 15] >> 3;
-            let mut s1 = self.right_rotate(message_schedule[i - 2], 17);
-            s1 ^= self.right_rotate(message_schedule[i - 2], 19);
-            s1 ^= message_schedule[i - 2] >> 10;
-            message_schedule[i] = message_schedule[i - 16] + s0 + message_schedule[i - 7] + s1;
-        }
-
-        // Compression
-        let mut hashes = self.hash_values.get();
-        for i in 0..64 {
-            let s1 = self.right_rotate(hashes[4], 6)
-                ^ self.right_rotate(hashes[4], 11)
-                ^ self.right_rotate(hashes[4], 25);
-            let ch = (hashes[4] & hashes[5]) ^ ((!hashes[4]) & hashes[6]);
-            let constant = ROUND_CONSTANTS[i];
-            let temp1 = hashes[7] + s1 + ch + constant + message_schedule[i];
-            let s0 = self.right_rotate(hashes[0], 2)
-                ^ self.right_rotate(hashes[0], 13)
-                ^ self.right_rotate(hashes[0], 22);
-            let maj = (hashes[0] & hashes[1]) ^ (hashes[0] & hashes[2]) ^ (hashes[1] & hashes[2]);
-            let temp2 = s0 + maj;
-
-            hashes[7] = hashes[6];
-            hashes[6] = hashes[5];
-            hashes[5] = hashes[4];
-            hashes[4] = hashes[3].wrapping_add(temp1);
-            hashes[3] = hashes[2];
-            hashes[2] = hashes[1];
-            hashes[1] = hashes[0];
-            hashes[0] = temp1.wrapping_add(temp2);
-        }
-
-        let mut new_hashes = self.hash_values.get();
-        for i in 0..8 {
-            new_hashes[i] = new_hashes[i].wrapping_add(hashes[i]);
-        }
-        self.hash_values.set(new_hashes);
-    }
 }
 
 impl<'a> DigestData<'a, 32> for Sha256Software<'a> {
@@ -504,3 +570,39 @@ impl<'a> DigestDataVerify<'a, 32> for Sha256Software<'a> {
         unimplemented!()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> [u8; 32] {
+        let mut bytes = [0; 32];
+        for (byte, digits) in bytes.iter_mut().zip(s.as_bytes().chunks(2)) {
+            *byte = u8::from_str_radix(core::str::from_utf8(digits).unwrap(), 16).unwrap();
+        }
+        bytes
+    }
+
+    fn sha256(data: &[u8]) -> [u8; 32] {
+        let mut hasher = Sha256Hasher::new();
+        hasher.update(data);
+        hasher.finish()
+    }
+
+    #[test]
+    fn hasher_known_answers() {
+        assert_eq!(
+            sha256(b""),
+            hex("E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855")
+        );
+        assert_eq!(
+            sha256(b"abc"),
+            hex("BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD")
+        );
+        // 56 bytes, so the length goes in a block of its own.
+        assert_eq!(
+            sha256(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            hex("248D6A61D20638B8E5C026930C3E6039A33CE45964FF2167F6ECEDD419DB06C1")
+        );
+    }
+}
@@ -0,0 +1,551 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Software implementation of AES-128 and AES-256.
+//!
+//! Implements the ECB, CBC and CTR modes of the `AES128` HIL for chips
+//! without an AES peripheral, so that AES consumers such as the CCM and GCM
+//! virtualizers can be used on them. Keys of `AES128_KEY_SIZE` bytes select
+//! AES-128, and keys of `AES256_KEY_SIZE` bytes select AES-256. The cipher
+//! follows the description in FIPS-197 and works on bytes, using lookup tables for the S-box. It is not
+//! hardened against timing side channels.
+//!
+//! A request is processed all at once in a deferred call, after which the
+//! client's `crypt_done` is called.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let aes = static_init!(Aes128Software<'static>, Aes128Software::new());
+//! kernel::deferred_call::DeferredCallClient::register(aes);
+//!
+//! let ccm_mux = static_init!(
+//!     MuxAES128CCM<'static, Aes128Software<'static>>,
+//!     MuxAES128CCM::new(aes)
+//! );
+//! kernel::deferred_call::DeferredCallClient::register(ccm_mux);
+//! aes.set_client(ccm_mux);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::symmetric_encryption::{
+    AES128Ctr, Client, AES128, AES128CBC, AES128ECB, AES128_BLOCK_SIZE, AES128_KEY_SIZE,
+    AES256_KEY_SIZE,
+};
+use kernel::utilities::cells::{MapCell, OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+/// Number of rounds of AES-256. AES-128 has 10.
+const MAX_ROUNDS: usize = 14;
+
+/// Length of the longest expanded key: one block per round, plus the initial
+/// one.
+const ROUND_KEYS_LEN: usize = (MAX_ROUNDS + 1) * AES128_BLOCK_SIZE;
+
+const SBOX: [u8; 256] = [
+    0x63, 0x7c, 0x77, 0x7b, 0xf2, 0x6b, 0x6f, 0xc5, 0x30, 0x01, 0x67, 0x2b, 0xfe, 0xd7, 0xab, 0x76,
+    0xca, 0x82, 0xc9, 0x7d, 0xfa, 0x59, 0x47, 0xf0, 0xad, 0xd4, 0xa2, 0xaf, 0x9c, 0xa4, 0x72, 0xc0,
+    0xb7, 0xfd, 0x93, 0x26, 0x36, 0x3f, 0xf7, 0xcc, 0x34, 0xa5, 0xe5, 0xf1, 0x71, 0xd8, 0x31, 0x15,
+    0x04, 0xc7, 0x23, 0xc3, 0x18, 0x96, 0x05, 0x9a, 0x07, 0x12, 0x80, 0xe2, 0xeb, 0x27, 0xb2, 0x75,
+    0x09, 0x83, 0x2c, 0x1a, 0x1b, 0x6e, 0x5a, 0xa0, 0x52, 0x3b, 0xd6, 0xb3, 0x29, 0xe3, 0x2f, 0x84,
+    0x53, 0xd1, 0x00, 0xed, 0x20, 0xfc, 0xb1, 0x5b, 0x6a, 0xcb, 0xbe, 0x39, 0x4a, 0x4c, 0x58, 0xcf,
+    0xd0, 0xef, 0xaa, 0xfb, 0x43, 0x4d, 0x33, 0x85, 0x45, 0xf9, 0x02, 0x7f, 0x50, 0x3c, 0x9f, 0xa8,
+    0x51, 0xa3, 0x40, 0x8f, 0x92, 0x9d, 0x38, 0xf5, 0xbc, 0xb6, 0xda, 0x21, 0x10, 0xff, 0xf3, 0xd2,
+    0xcd, 0x0c, 0x13, 0xec, 0x5f, 0x97, 0x44, 0x17, 0xc4, 0xa7, 0x7e, 0x3d, 0x64, 0x5d, 0x19, 0x73,
+    0x60, 0x81, 0x4f, 0xdc, 0x22, 0x2a, 0x90, 0x88, 0x46, 0xee, 0xb8, 0x14, 0xde, 0x5e, 0x0b, 0xdb,
+    0xe0, 0x32, 0x3a, 0x0a, 0x49, 0x06, 0x24, 0x5c, 0xc2, 0xd3, 0xac, 0x62, 0x91, 0x95, 0xe4, 0x79,
+    0xe7, 0xc8, 0x37, 0x6d, 0x8d, 0xd5, 0x4e, 0xa9, 0x6c, 0x56, 0xf4, 0xea, 0x65, 0x7a, 0xae, 0x08,
+    0xba, 0x78, 0x25, 0x2e, 0x1c, 0xa6, 0xb4, 0xc6, 0xe8, 0xdd, 0x74, 0x1f, 0x4b, 0xbd, 0x8b, 0x8a,
+    0x70, 0x3e, 0xb5, 0x66, 0x48, 0x03, 0xf6, 0x0e, 0x61, 0x35, 0x57, 0xb9, 0x86, 0xc1, 0x1d, 0x9e,
+    0xe1, 0xf8, 0x98, 0x11, 0x69, 0xd9, 0x8e, 0x94, 0x9b, 0x1e, 0x87, 0xe9, 0xce, 0x55, 0x28, 0xdf,
+    0x8c, 0xa1, 0x89, 0x0d, 0xbf, 0xe6, 0x42, 0x68, 0x41, 0x99, 0x2d, 0x0f, 0xb0, 0x54, 0xbb, 0x16,
+];
+
+const INV_SBOX: [u8; 256] = [
+    0x52, 0x09, 0x6a, 0xd5, 0x30, 0x36, 0xa5, 0x38, 0xbf, 0x40, 0xa3, 0x9e, 0x81, 0xf3, 0xd7, 0xfb,
+    0x7c, 0xe3, 0x39, 0x82, 0x9b, 0x2f, 0xff, 0x87, 0x34, 0x8e, 0x43, 0x44, 0xc4, 0xde, 0xe9, 0xcb,
+    0x54, 0x7b, 0x94, 0x32, 0xa6, 0xc2, 0x23, 0x3d, 0xee, 0x4c, 0x95, 0x0b, 0x42, 0xfa, 0xc3, 0x4e,
+    0x08, 0x2e, 0xa1, 0x66, 0x28, 0xd9, 0x24, 0xb2, 0x76, 0x5b, 0xa2, 0x49, 0x6d, 0x8b, 0xd1, 0x25,
+    0x72, 0xf8, 0xf6, 0x64, 0x86, 0x68, 0x98, 0x16, 0xd4, 0xa4, 0x5c, 0xcc, 0x5d, 0x65, 0xb6, 0x92,
+    0x6c, 0x70, 0x48, 0x50, 0xfd, 0xed, 0xb9, 0xda, 0x5e, 0x15, 0x46, 0x57, 0xa7, 0x8d, 0x9d, 0x84,
+    0x90, 0xd8, 0xab, 0x00, 0x8c, 0xbc, 0xd3, 0x0a, 0xf7, 0xe4, 0x58, 0x05, 0xb8, 0xb3, 0x45, 0x06,
+    0xd0, 0x2c, 0x1e, 0x8f, 0xca, 0x3f, 0x0f, 0x02, 0xc1, 0xaf, 0xbd, 0x03, 0x01, 0x13, 0x8a, 0x6b,
+    0x3a, 0x91, 0x11, 0x41, 0x4f, 0x67, 0xdc, 0xea, 0x97, 0xf2, 0xcf, 0xce, 0xf0, 0xb4, 0xe6, 0x73,
+    0x96, 0xac, 0x74, 0x22, 0xe7, 0xad, 0x35, 0x85, 0xe2, 0xf9, 0x37, 0xe8, 0x1c, 0x75, 0xdf, 0x6e,
+    0x47, 0xf1, 0x1a, 0x71, 0x1d, 0x29, 0xc5, 0x89, 0x6f, 0xb7, 0x62, 0x0e, 0xaa, 0x18, 0xbe, 0x1b,
+    0xfc, 0x56, 0x3e, 0x4b, 0xc6, 0xd2, 0x79, 0x20, 0x9a, 0xdb, 0xc0, 0xfe, 0x78, 0xcd, 0x5a, 0xf4,
+    0x1f, 0xdd, 0xa8, 0x33, 0x88, 0x07, 0xc7, 0x31, 0xb1, 0x12, 0x10, 0x59, 0x27, 0x80, 0xec, 0x5f,
+    0x60, 0x51, 0x7f, 0xa9, 0x19, 0xb5, 0x4a, 0x0d, 0x2d, 0xe5, 0x7a, 0x9f, 0x93, 0xc9, 0x9c, 0xef,
+    0xa0, 0xe0, 0x3b, 0x4d, 0xae, 0x2a, 0xf5, 0xb0, 0xc8, 0xeb, 0xbb, 0x3c, 0x83, 0x53, 0x99, 0x61,
+    0x17, 0x2b, 0x04, 0x7e, 0xba, 0x77, 0xd6, 0x26, 0xe1, 0x69, 0x14, 0x63, 0x55, 0x21, 0x0c, 0x7d,
+];
+
+/// Round constants of the key expansion.
+const RCON: [u8; 10] = [0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40, 0x80, 0x1b, 0x36];
+
+type Block = [u8; AES128_BLOCK_SIZE];
+
+/// Multiply by x in GF(2^8).
+fn xtime(b: u8) -> u8 {
+    (b << 1) ^ if b & 0x80 != 0 { 0x1b } else { 0 }
+}
+
+/// Multiply `a` and `b` in GF(2^8).
+fn gmul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        a = xtime(a);
+        b >>= 1;
+    }
+    product
+}
+
+/// Expand a 16 or 32 byte `key`, and return the number of rounds.
+fn expand_key(key: &[u8], round_keys: &mut [u8; ROUND_KEYS_LEN]) -> usize {
+    let rounds = key.len() / 4 + 6;
+    round_keys[..key.len()].copy_from_slice(key);
+    for i in (key.len()..(rounds + 1) * AES128_BLOCK_SIZE).step_by(4) {
+        let mut word = [0; 4];
+        word.copy_from_slice(&round_keys[i - 4..i]);
+        if i % key.len() == 0 {
+            word = [
+                SBOX[word[1] as usize] ^ RCON[i / key.len() - 1],
+                SBOX[word[2] as usize],
+                SBOX[word[3] as usize],
+                SBOX[word[0] as usize],
+            ];
+        } else if key.len() == AES256_KEY_SIZE && i % key.len() == 16 {
+            // AES-256 also substitutes the word in the middle of each key
+            // length.
+            word = word.map(|byte| SBOX[byte as usize]);
+        }
+        for j in 0..4 {
+            round_keys[i + j] = round_keys[i + j - key.len()] ^ word[j];
+        }
+    }
+    rounds
+}
+
+fn add_round_key(state: &mut Block, round_keys: &[u8; ROUND_KEYS_LEN], round: usize) {
+    let round_key = &round_keys[round * AES128_BLOCK_SIZE..(round + 1) * AES128_BLOCK_SIZE];
+    state
+        .iter_mut()
+        .zip(round_key)
+        .for_each(|(byte, key)| *byte ^= key);
+}
+
+/// The state is stored column by column, so byte `r` of column `c` is at
+/// `4 * c + r`. Row `r` is rotated left by `r` when encrypting, and right
+/// when decrypting.
+fn shift_rows(state: &mut Block, inverse: bool) {
+    let prior = *state;
+    for c in 0..4 {
+        for r in 1..4 {
+            let from = (if inverse { c + 4 - r } else { c + r }) % 4;
+            state[4 * c + r] = prior[4 * from + r];
+        }
+    }
+}
+
+fn mix_columns(state: &mut Block, inverse: bool) {
+    let coefficients = if inverse {
+        [0x0e, 0x0b, 0x0d, 0x09]
+    } else {
+        [0x02, 0x03, 0x01, 0x01]
+    };
+    for column in state.chunks_mut(4) {
+        let prior = [column[0], column[1], column[2], column[3]];
+        for r in 0..4 {
+            column[r] = (0..4).fold(0, |acc, i| acc ^ gmul(prior[(r + i) % 4], coefficients[i]));
+        }
+    }
+}
+
+fn encrypt_block(block: &mut Block, round_keys: &[u8; ROUND_KEYS_LEN], rounds: usize) {
+    add_round_key(block, round_keys, 0);
+    for round in 1..=rounds {
+        block
+            .iter_mut()
+            .for_each(|byte| *byte = SBOX[*byte as usize]);
+        shift_rows(block, false);
+        if round != rounds {
+            mix_columns(block, false);
+        }
+        add_round_key(block, round_keys, round);
+    }
+}
+
+fn decrypt_block(block: &mut Block, round_keys: &[u8; ROUND_KEYS_LEN], rounds: usize) {
+    add_round_key(block, round_keys, rounds);
+    for round in (0..rounds).rev() {
+        shift_rows(block, true);
+        block
+            .iter_mut()
+            .for_each(|byte| *byte = INV_SBOX[*byte as usize]);
+        add_round_key(block, round_keys, round);
+        if round != 0 {
+            mix_columns(block, true);
+        }
+    }
+}
+
+fn xor_block(block: &mut Block, other: &Block) {
+    block
+        .iter_mut()
+        .zip(other)
+        .for_each(|(byte, other)| *byte ^= other);
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Ecb,
+    Cbc,
+    Ctr,
+}
+
+pub struct Aes128Software<'a> {
+    client: OptionalCell<&'a dyn Client<'a>>,
+    round_keys: MapCell<[u8; ROUND_KEYS_LEN]>,
+    /// Number of rounds for the size of the key.
+    rounds: Cell<usize>,
+    iv: Cell<Block>,
+    /// Previous ciphertext block in CBC mode, or the counter in CTR mode.
+    chain: Cell<Block>,
+    mode: Cell<Mode>,
+    encrypting: Cell<bool>,
+
+    source: TakeCell<'static, [u8]>,
+    dest: TakeCell<'static, [u8]>,
+    start_index: Cell<usize>,
+    stop_index: Cell<usize>,
+
+    deferred_call: DeferredCall,
+}
+
+impl Aes128Software<'_> {
+    pub fn new() -> Self {
+        Self {
+            client: OptionalCell::empty(),
+            round_keys: MapCell::new([0; ROUND_KEYS_LEN]),
+            rounds: Cell::new(10),
+            iv: Cell::new([0; AES128_BLOCK_SIZE]),
+            chain: Cell::new([0; AES128_BLOCK_SIZE]),
+            mode: Cell::new(Mode::Ecb),
+            encrypting: Cell::new(true),
+
+            source: TakeCell::empty(),
+            dest: TakeCell::empty(),
+            start_index: Cell::new(0),
+            stop_index: Cell::new(0),
+
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    fn busy(&self) -> bool {
+        self.dest.is_some()
+    }
+
+    fn set_mode(&self, mode: Mode, encrypting: bool) -> Result<(), ErrorCode> {
+        if self.busy() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.mode.set(mode);
+        self.encrypting.set(encrypting);
+        Ok(())
+    }
+
+    /// Transform one block in the configured mode.
+    fn crypt_block(&self, block: &mut Block, round_keys: &[u8; ROUND_KEYS_LEN]) {
+        let mut chain = self.chain.get();
+        let rounds = self.rounds.get();
+        match (self.mode.get(), self.encrypting.get()) {
+            (Mode::Ecb, true) => encrypt_block(block, round_keys, rounds),
+            (Mode::Ecb, false) => decrypt_block(block, round_keys, rounds),
+            (Mode::Cbc, true) => {
+                xor_block(block, &chain);
+                encrypt_block(block, round_keys, rounds);
+                chain = *block;
+            }
+            (Mode::Cbc, false) => {
+                let input = *block;
+                decrypt_block(block, round_keys, rounds);
+                xor_block(block, &chain);
+                chain = input;
+            }
+            (Mode::Ctr, _) => {
+                let mut keystream = chain;
+                encrypt_block(&mut keystream, round_keys, rounds);
+                xor_block(block, &keystream);
+                // The counter is the whole block, incremented as a big
+                // endian number.
+                chain = (u128::from_be_bytes(chain).wrapping_add(1)).to_be_bytes();
+            }
+        }
+        self.chain.set(chain);
+    }
+}
+
+impl<'a> AES128<'a> for Aes128Software<'a> {
+    fn enable(&self) {}
+
+    fn disable(&self) {}
+
+    fn set_client(&'a self, client: &'a dyn Client<'a>) {
+        self.client.set(client);
+    }
+
+    fn set_key(&self, key: &[u8]) -> Result<(), ErrorCode> {
+        if key.len() != AES128_KEY_SIZE && key.len() != AES256_KEY_SIZE {
+            return Err(ErrorCode::INVAL);
+        }
+        self.round_keys
+            .map(|round_keys| self.rounds.set(expand_key(key, round_keys)))
+            .ok_or(ErrorCode::FAIL)
+    }
+
+    fn set_iv(&self, iv: &[u8]) -> Result<(), ErrorCode> {
+        let iv: Block = iv.try_into().or(Err(ErrorCode::INVAL))?;
+        self.iv.set(iv);
+        self.chain.set(iv);
+        Ok(())
+    }
+
+    fn start_message(&self) {
+        if !self.busy() {
+            self.chain.set(self.iv.get());
+        }
+    }
+
+    fn crypt(
+        &self,
+        source: Option<&'static mut [u8]>,
+        dest: &'static mut [u8],
+        start_index: usize,
+        stop_index: usize,
+    ) -> Option<(
+        Result<(), ErrorCode>,
+        Option<&'static mut [u8]>,
+        &'static mut [u8],
+    )> {
+        if self.busy() {
+            return Some((Err(ErrorCode::BUSY), source, dest));
+        }
+        if start_index > stop_index
+            || stop_index > dest.len()
+            || (stop_index - start_index) % AES128_BLOCK_SIZE != 0
+            || source
+                .as_ref()
+                .is_some_and(|source| source.len() != stop_index - start_index)
+        {
+            return Some((Err(ErrorCode::INVAL), source, dest));
+        }
+
+        self.source.put(source);
+        self.dest.replace(dest);
+        self.start_index.set(start_index);
+        self.stop_index.set(stop_index);
+        self.deferred_call.set();
+        None
+    }
+}
+
+impl AES128ECB for Aes128Software<'_> {
+    fn set_mode_aes128ecb(&self, encrypting: bool) -> Result<(), ErrorCode> {
+        self.set_mode(Mode::Ecb, encrypting)
+    }
+}
+
+impl AES128CBC for Aes128Software<'_> {
+    fn set_mode_aes128cbc(&self, encrypting: bool) -> Result<(), ErrorCode> {
+        self.set_mode(Mode::Cbc, encrypting)
+    }
+}
+
+impl AES128Ctr for Aes128Software<'_> {
+    fn set_mode_aes128ctr(&self, encrypting: bool) -> Result<(), ErrorCode> {
+        self.set_mode(Mode::Ctr, encrypting)
+    }
+}
+
+impl DeferredCallClient for Aes128Software<'_> {
+    fn handle_deferred_call(&self) {
+        let Some(dest) = self.dest.take() else {
+            return;
+        };
+        let mut source = self.source.take();
+        let start = self.start_index.get();
+        let stop = self.stop_index.get();
+
+        self.round_keys.map(|round_keys| {
+            for offset in (start..stop).step_by(AES128_BLOCK_SIZE) {
+                let output = &mut dest[offset..offset + AES128_BLOCK_SIZE];
+                let mut block = [0; AES128_BLOCK_SIZE];
+                match source.as_ref() {
+                    Some(source) => block.copy_from_slice(
+                        &source[offset - start..offset - start + AES128_BLOCK_SIZE],
+                    ),
+                    None => block.copy_from_slice(output),
+                }
+                self.crypt_block(&mut block, round_keys);
+                output.copy_from_slice(&block);
+            }
+        });
+
+        self.client
+            .map(move |client| client.crypt_done(source.take(), dest));
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parse `s`, which has a whole number of blocks.
+    fn hex<const L: usize>(s: &str) -> [u8; L] {
+        let mut bytes = [0; L];
+        assert_eq!(s.len(), 2 * L);
+        for (byte, digits) in bytes.iter_mut().zip(s.as_bytes().chunks(2)) {
+            *byte = u8::from_str_radix(core::str::from_utf8(digits).unwrap(), 16).unwrap();
+        }
+        bytes
+    }
+
+    /// Transform `data` in `mode` as a single message, in place.
+    fn crypt(mode: Mode, encrypting: bool, key: &[u8], iv: &Block, data: &mut [u8]) {
+        let aes = Aes128Software::new();
+        aes.set_key(key).unwrap();
+        aes.set_mode(mode, encrypting).unwrap();
+        aes.set_iv(iv).unwrap();
+        aes.start_message();
+        aes.round_keys.map(|round_keys| {
+            for chunk in data.chunks_mut(AES128_BLOCK_SIZE) {
+                let mut block: Block = (&*chunk).try_into().unwrap();
+                aes.crypt_block(&mut block, round_keys);
+                chunk.copy_from_slice(&block);
+            }
+        });
+    }
+
+    /// Check that `mode` turns `plaintext` into `ciphertext` and back.
+    fn check(mode: Mode, key: &[u8], iv: &Block, plaintext: &[u8], ciphertext: &[u8]) {
+        let mut data = [0; 64];
+        let data = &mut data[..plaintext.len()];
+        data.copy_from_slice(plaintext);
+        crypt(mode, true, key, iv, data);
+        assert_eq!(data, ciphertext);
+        crypt(mode, false, key, iv, data);
+        assert_eq!(data, plaintext);
+    }
+
+    /// The example vectors of FIPS-197, appendix C.
+    #[test]
+    fn fips197_known_answers() {
+        let plaintext: [u8; 16] = hex("00112233445566778899aabbccddeeff");
+        let key: [u8; 32] = hex("000102030405060708090a0b0c0d0e0f\
+                                 101112131415161718191a1b1c1d1e1f");
+        check(
+            Mode::Ecb,
+            &key[..AES128_KEY_SIZE],
+            &[0; 16],
+            &plaintext,
+            &hex::<16>("69c4e0d86a7b0430d8cdb78070b4c55a"),
+        );
+        check(
+            Mode::Ecb,
+            &key,
+            &[0; 16],
+            &plaintext,
+            &hex::<16>("8ea2b7ca516745bfeafc49904b496089"),
+        );
+    }
+
+    /// Plaintext of the examples of SP 800-38A, appendix F.
+    const SP800_38A_PLAINTEXT: &str = "6bc1bee22e409f96e93d7e117393172a\
+                                       ae2d8a571e03ac9c9eb76fac45af8e51\
+                                       30c81c46a35ce411e5fbc1191a0a52ef\
+                                       f69f2445df4f9b17ad2b417be66c3710";
+    const SP800_38A_KEY_128: &str = "2b7e151628aed2a6abf7158809cf4f3c";
+    const SP800_38A_KEY_256: &str = "603deb1015ca71be2b73aef0857d7781\
+                                     1f352c073b6108d72d9810a30914dff4";
+    const SP800_38A_IV: &str = "000102030405060708090a0b0c0d0e0f";
+    const SP800_38A_COUNTER: &str = "f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff";
+
+    /// The examples of SP 800-38A, appendix F, for `mode` with AES-128 and
+    /// AES-256.
+    fn check_sp800_38a(mode: Mode, iv: &str, ciphertext_128: &str, ciphertext_256: &str) {
+        let plaintext: [u8; 64] = hex(SP800_38A_PLAINTEXT);
+        let iv: Block = hex(iv);
+        let key_128: [u8; 16] = hex(SP800_38A_KEY_128);
+        let key_256: [u8; 32] = hex(SP800_38A_KEY_256);
+        check(mode, &key_128, &iv, &plaintext, &hex::<64>(ciphertext_128));
+        check(mode, &key_256, &iv, &plaintext, &hex::<64>(ciphertext_256));
+    }
+
+    #[test]
+    fn sp800_38a_ecb() {
+        check_sp800_38a(
+            Mode::Ecb,
+            SP800_38A_IV,
+            "3ad77bb40d7a3660a89ecaf32466ef97\
+             f5d3d58503b9699de785895a96fdbaaf\
+             43b1cd7f598ece23881b00e3ed030688\
+             7b0c785e27e8ad3f8223207104725dd4",
+            "f3eed1bdb5d2a03c064b5a7e3db181f8\
+             591ccb10d410ed26dc5ba74a31362870\
+             b6ed21b99ca6f4f9f153e7b1beafed1d\
+             23304b7a39f9f3ff067d8d8f9e24ecc7",
+        );
+    }
+
+    #[test]
+    fn sp800_38a_cbc() {
+        check_sp800_38a(
+            Mode::Cbc,
+            SP800_38A_IV,
+            "7649abac8119b246cee98e9b12e9197d\
+             5086cb9b507219ee95db113a917678b2\
+             73bed6b8e3c1743b7116e69e22229516\
+             3ff1caa1681fac09120eca307586e1a7",
+            "f58c4c04d6e5f1ba779eabfb5f7bfbd6\
+             9cfc4e967edb808d679f777bc6702c7d\
+             39f23369a9d9bacfa530e26304231461\
+             b2eb05e2c39be9fcda6c19078c6a9d1b",
+        );
+    }
+
+    #[test]
+    fn sp800_38a_ctr() {
+        check_sp800_38a(
+            Mode::Ctr,
+            SP800_38A_COUNTER,
+            "874d6191b620e3261bef6864990db6ce\
+             9806f66b7970fdff8617187bb9fffdff\
+             5ae4df3edbd5d35e5b4f09020db03eab\
+             1e031dda2fbe03d1792170a0f3009cee",
+            "601ec313775789a5b7a7f504bbf3d228\
+             f443e3ca4d62b59aca84e990cacaf5c5\
+             2b0930daa23de94ce87017ba2d84988d\
+             dfc9c58db67aada613c2dd08457941a6",
+        );
+    }
+
+    #[test]
+    fn reject_other_key_sizes() {
+        let aes = Aes128Software::new();
+        for len in [0, 15, 17, 24, 31, 33] {
+            assert_eq!(aes.set_key(&[0; 33][..len]), Err(ErrorCode::INVAL));
+        }
+    }
+}
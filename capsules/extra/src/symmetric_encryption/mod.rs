@@ -3,3 +3,4 @@
 // Copyright Tock Contributors 2022.
 
 pub mod aes;
+pub mod aes_software;
@@ -0,0 +1,294 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Lifecycle and system event notifications for processes.
+//!
+//! Processes subscribe once to the events they care about, such as a request
+//! to pause or terminate, a low battery or an impending shutdown, and receive
+//! them through a single upcall no matter which part of the kernel raised
+//! them. The kernel raises events through the [`SystemEventNotifier`] trait,
+//! so a battery monitor or a storage driver only depends on the trait and
+//! not on this capsule.
+//!
+//! Delivery follows these rules, documented for processes in
+//! doc/syscalls/10005_system_events.md:
+//!
+//! - Events a process did not subscribe to are dropped.
+//! - Each process has at most one upcall of this driver queued at a time.
+//!   The next event is only delivered once the process acknowledges the
+//!   previous one, so a process that is slow to handle events cannot fill
+//!   its upcall queue.
+//! - Events that cannot be delivered yet stay pending. An event raised again
+//!   while it is pending is delivered once, with the latest argument.
+//! - Pending events are delivered in the order of their number.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let system_events = components::system_events::SystemEventsComponent::new(
+//!     board_kernel,
+//!     capsules_extra::system_events::DRIVER_NUM,
+//! )
+//! .finalize(components::system_events_component_static!());
+//!
+//! // In a battery monitor:
+//! system_events.notify(SystemEvent::LowBattery, percent);
+//! ```
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, GrantKernelData, UpcallCount};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::OptionalCell;
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::SystemEvents as usize;
+
+/// Ids for subscribe upcalls
+mod upcall {
+    /// An event was delivered.
+    pub const EVENT: usize = 0;
+    /// The number of upcalls the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Number of event types.
+pub const NUM_EVENTS: usize = 6;
+
+/// An event delivered to processes. The number of each event is the bit of
+/// the event in subscription masks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SystemEvent {
+    /// The kernel asks the process to pause. The argument is unused.
+    PauseRequest = 0,
+    /// The kernel asks the paused process to resume. The argument is unused.
+    ResumeRequest = 1,
+    /// The kernel asks the process to save its state and exit. The argument
+    /// is the number of milliseconds before the process is terminated, or 0
+    /// if the kernel waits for the acknowledgement.
+    TerminateRequest = 2,
+    /// The battery is low. The argument is the remaining charge in percent.
+    LowBattery = 3,
+    /// The system shuts down. The argument is the number of milliseconds
+    /// before it does.
+    Shutdown = 4,
+    /// A storage is full. The argument is the driver number of the storage.
+    StorageFull = 5,
+}
+
+impl SystemEvent {
+    fn from_usize(event: usize) -> Option<SystemEvent> {
+        match event {
+            0 => Some(SystemEvent::PauseRequest),
+            1 => Some(SystemEvent::ResumeRequest),
+            2 => Some(SystemEvent::TerminateRequest),
+            3 => Some(SystemEvent::LowBattery),
+            4 => Some(SystemEvent::Shutdown),
+            5 => Some(SystemEvent::StorageFull),
+            _ => None,
+        }
+    }
+
+    fn mask(self) -> u32 {
+        1 << self as u32
+    }
+}
+
+/// Mask of all events.
+const ALL_EVENTS: u32 = (1 << NUM_EVENTS) - 1;
+
+/// Raise events for processes.
+pub trait SystemEventNotifier {
+    /// Raise `event` for every process that subscribed to it.
+    fn notify(&self, event: SystemEvent, arg: u32);
+
+    /// Raise `event` for `processid` only.
+    ///
+    /// Returns `Err(ErrorCode::OFF)` if the process did not subscribe to
+    /// `event`.
+    fn notify_process(
+        &self,
+        processid: ProcessId,
+        event: SystemEvent,
+        arg: u32,
+    ) -> Result<(), ErrorCode>;
+}
+
+/// Told when a process acknowledges an event, for example to terminate the
+/// process once it acknowledged a [`SystemEvent::TerminateRequest`].
+pub trait SystemEventClient {
+    fn acknowledged(&self, processid: ProcessId, event: SystemEvent);
+}
+
+#[derive(Default)]
+pub struct App {
+    /// Events the process subscribed to.
+    subscribed: u32,
+    /// Events raised and not delivered yet.
+    pending: u32,
+    /// Argument of each pending event.
+    args: [u32; NUM_EVENTS],
+    /// Event whose upcall is queued and not acknowledged yet.
+    delivered: Option<SystemEvent>,
+}
+
+pub struct SystemEvents<'a> {
+    apps: Grant<App, UpcallCount<{ upcall::COUNT }>, AllowRoCount<0>, AllowRwCount<0>>,
+    client: OptionalCell<&'a dyn SystemEventClient>,
+}
+
+impl<'a> SystemEvents<'a> {
+    pub fn new(
+        apps: Grant<App, UpcallCount<{ upcall::COUNT }>, AllowRoCount<0>, AllowRwCount<0>>,
+    ) -> Self {
+        Self {
+            apps,
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn SystemEventClient) {
+        self.client.set(client);
+    }
+
+    /// Mark `event` pending for `app` if it subscribed to it, and deliver it
+    /// if no other event is waiting for an acknowledgement.
+    fn raise(
+        app: &mut App,
+        kernel_data: &GrantKernelData,
+        event: SystemEvent,
+        arg: u32,
+    ) -> Result<(), ErrorCode> {
+        if app.subscribed & event.mask() == 0 {
+            return Err(ErrorCode::OFF);
+        }
+        app.pending |= event.mask();
+        app.args[event as usize] = arg;
+        Self::deliver(app, kernel_data);
+        Ok(())
+    }
+
+    /// Deliver the first pending event, unless an upcall is already queued.
+    fn deliver(app: &mut App, kernel_data: &GrantKernelData) {
+        if app.delivered.is_some() {
+            return;
+        }
+        let pending = app.pending & app.subscribed;
+        let Some(event) = SystemEvent::from_usize(pending.trailing_zeros() as usize) else {
+            return;
+        };
+        let remaining = pending & !event.mask();
+        // If the upcall queue of the process is full, the event stays
+        // pending and is delivered with the next event or command.
+        if kernel_data
+            .schedule_upcall(
+                upcall::EVENT,
+                (
+                    event as usize,
+                    app.args[event as usize] as usize,
+                    remaining as usize,
+                ),
+            )
+            .is_ok()
+        {
+            app.pending &= !event.mask();
+            app.delivered = Some(event);
+        }
+    }
+}
+
+impl SystemEventNotifier for SystemEvents<'_> {
+    fn notify(&self, event: SystemEvent, arg: u32) {
+        self.apps.each(|_, app, kernel_data| {
+            let _ = Self::raise(app, kernel_data, event, arg);
+        });
+    }
+
+    fn notify_process(
+        &self,
+        processid: ProcessId,
+        event: SystemEvent,
+        arg: u32,
+    ) -> Result<(), ErrorCode> {
+        self.apps
+            .enter(processid, |app, kernel_data| {
+                Self::raise(app, kernel_data, event, arg)
+            })
+            .unwrap_or_else(|err| Err(err.into()))
+    }
+}
+
+impl SyscallDriver for SystemEvents<'_> {
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Subscribe to the events whose bits are set in `arg1`, and
+    ///   unsubscribe from the others. Pending events the process unsubscribes
+    ///   from are dropped.
+    /// - `2`: Acknowledge the last event delivered, which lets the next
+    ///   pending event be delivered.
+    /// - `3`: Get the mask of pending events.
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        _arg2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => {
+                if arg1 & !(ALL_EVENTS as usize) != 0 {
+                    return CommandReturn::failure(ErrorCode::INVAL);
+                }
+                self.apps
+                    .enter(processid, |app, kernel_data| {
+                        app.subscribed = arg1 as u32;
+                        app.pending &= app.subscribed;
+                        Self::deliver(app, kernel_data);
+                        CommandReturn::success()
+                    })
+                    .unwrap_or_else(|err| CommandReturn::failure(err.into()))
+            }
+
+            2 => {
+                let acknowledged = self.apps.enter(processid, |app, kernel_data| {
+                    let event = app.delivered.take();
+                    Self::deliver(app, kernel_data);
+                    event
+                });
+                match acknowledged {
+                    Ok(Some(event)) => {
+                        // Outside of the grant, so that the client can raise
+                        // events for the process.
+                        self.client
+                            .map(|client| client.acknowledged(processid, event));
+                        CommandReturn::success()
+                    }
+                    Ok(None) => CommandReturn::failure(ErrorCode::ALREADY),
+                    Err(err) => CommandReturn::failure(err.into()),
+                }
+            }
+
+            3 => self
+                .apps
+                .enter(processid, |app, kernel_data| {
+                    // Retry an event that did not fit in the upcall queue.
+                    Self::deliver(app, kernel_data);
+                    CommandReturn::success_u32(app.pending)
+                })
+                .unwrap_or_else(|err| CommandReturn::failure(err.into())),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
@@ -57,9 +57,11 @@
 
 use core::cell::Cell;
 
+use kernel::energy::PeripheralEnergy;
 use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
 use kernel::hil;
 use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::OptionalCell;
 use kernel::{ErrorCode, ProcessId};
 
 /// Syscall driver number.
@@ -75,6 +77,9 @@ pub struct TemperatureSensor<'a, T: hil::sensors::TemperatureDriver<'a>> {
     driver: &'a T,
     apps: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<0>>,
     busy: Cell<bool>,
+    /// Process that started the read in progress, which it is charged to.
+    reader: OptionalCell<ProcessId>,
+    energy: OptionalCell<&'a dyn PeripheralEnergy>,
 }
 
 impl<'a, T: hil::sensors::TemperatureDriver<'a>> TemperatureSensor<'a, T> {
@@ -86,9 +91,16 @@ impl<'a, T: hil::sensors::TemperatureDriver<'a>> TemperatureSensor<'a, T> {
             driver,
             apps: grant,
             busy: Cell::new(false),
+            reader: OptionalCell::empty(),
+            energy: OptionalCell::empty(),
         }
     }
 
+    /// Report the time the sensor is busy reading to `energy`.
+    pub fn set_energy_accounting(&self, energy: &'a dyn PeripheralEnergy) {
+        self.energy.set(energy);
+    }
+
     fn enqueue_command(&self, processid: ProcessId) -> CommandReturn {
         self.apps
             .enter(processid, |app, _| {
@@ -100,7 +112,12 @@ impl<'a, T: hil::sensors::TemperatureDriver<'a>> TemperatureSensor<'a, T> {
                 if !self.busy.get() {
                     self.busy.set(true);
                     match self.driver.read_temperature() {
-                        Ok(()) => CommandReturn::success(),
+                        Ok(()) => {
+                            self.reader.set(processid);
+                            self.energy
+                                .map(|energy| energy.peripheral_started(processid, DRIVER_NUM));
+                            CommandReturn::success()
+                        }
                         Err(e) => CommandReturn::failure(e),
                     }
                 } else {
@@ -120,6 +137,10 @@ impl<'a, T: hil::sensors::TemperatureDriver<'a>> hil::sensors::TemperatureClient
         // We completed the operation so we clear the busy flag in case we get
         // another measurement request.
         self.busy.set(false);
+        if let Some(reader) = self.reader.take() {
+            self.energy
+                .map(|energy| energy.peripheral_stopped(reader, DRIVER_NUM));
+        }
 
         // Return the temperature reading to any waiting client.
         if let Ok(temp_val) = temp_val {
@@ -0,0 +1,424 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Thermal monitoring and throttling.
+//!
+//! `ThermalManager` samples a temperature sensor, usually the die temperature
+//! sensor of the chip, periodically, and classifies the temperature in
+//! [`ThermalLevel`]s with thresholds set by the board. When the level changes,
+//! it calls the [`ThermalPolicy`] of the board, which can for example reduce
+//! the clock, turn the radio off or pause processes with
+//! [`PauseProcessesPolicy`], and notifies the processes that subscribed.
+//!
+//! A level is only left once the temperature is `hysteresis` below its
+//! threshold, so that a temperature close to a threshold does not make the
+//! policy toggle.
+//!
+//! The manager also is a [`TemperatureDriver`] for one client, so that the
+//! sensor can still be used by the temperature driver of processes:
+//!
+//! ```text
+//! sensor -> ThermalManager -> temperature driver
+//! ```
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let thermal = components::thermal::ThermalManagerComponent::new(
+//!     board_kernel,
+//!     capsules_extra::thermal::DRIVER_NUM,
+//!     mux_alarm,
+//!     &base_peripherals.temp,
+//!     THERMAL_THRESHOLDS,
+//!     1000,
+//!     Some(thermal_policy),
+//! )
+//! .finalize(components::thermal_manager_component_static!(
+//!     nrf52840::rtc::Rtc<'static>,
+//!     nrf52840::temperature::Temp<'static>
+//! ));
+//! ```
+//!
+//! Syscall Interface
+//! -----------------
+//!
+//! ### Command
+//!
+//! #### `command_num`
+//!
+//! - `0`: Driver existence check.
+//! - `1`: Get the current thermal level: 0 for normal, 1 for warm, 2 for hot
+//!   and 3 for critical.
+//! - `2`: Get the last temperature sampled, in hundredths of degrees Celsius.
+//!   Returns `BUSY` until the first sample.
+//! - `3`: Subscribe to level changes if `arg1` is 1, or unsubscribe if it is
+//!   0.
+//!
+//! ### Subscribe
+//!
+//! - `0`: The thermal level changed. The upcall gets the new level and the
+//!   temperature that changed it.
+
+use core::cell::Cell;
+
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::sensors::{TemperatureClient, TemperatureDriver};
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks};
+use kernel::process::State;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::OptionalCell;
+use kernel::{ErrorCode, Kernel, ProcessId};
+
+use capsules_core::driver;
+
+/// Syscall driver number.
+pub const DRIVER_NUM: usize = driver::NUM::Thermal as usize;
+
+kernel::driver_api!(
+    DRIVER_API,
+    DRIVER_NUM,
+    name: "thermal",
+    commands: [
+        0 => "exists()",
+        1 => "level() -> u32",
+        2 => "temperature() -> i32",
+        3 => "notify(enable)",
+    ],
+    subscribes: [0 => "level_changed(level, temperature)"],
+);
+
+/// Ids for subscribe upcalls
+mod upcall {
+    /// The thermal level changed.
+    pub const LEVEL_CHANGED: usize = 0;
+    /// The number of upcalls the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// How hot the system is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ThermalLevel {
+    Normal = 0,
+    Warm = 1,
+    Hot = 2,
+    Critical = 3,
+}
+
+impl ThermalLevel {
+    const ALL: [ThermalLevel; 4] = [
+        ThermalLevel::Normal,
+        ThermalLevel::Warm,
+        ThermalLevel::Hot,
+        ThermalLevel::Critical,
+    ];
+}
+
+/// Temperatures at which the levels start, in hundredths of degrees Celsius.
+#[derive(Clone, Copy, Debug)]
+pub struct ThermalThresholds {
+    pub warm: i32,
+    pub hot: i32,
+    pub critical: i32,
+    /// How much cooler than the threshold of a level the temperature must be
+    /// to leave the level.
+    pub hysteresis: i32,
+}
+
+impl ThermalThresholds {
+    fn start(&self, level: ThermalLevel) -> i32 {
+        match level {
+            ThermalLevel::Normal => i32::MIN,
+            ThermalLevel::Warm => self.warm,
+            ThermalLevel::Hot => self.hot,
+            ThermalLevel::Critical => self.critical,
+        }
+    }
+
+    /// The level at `temperature`, coming from level `current`.
+    fn level(&self, current: ThermalLevel, temperature: i32) -> ThermalLevel {
+        let level = ThermalLevel::ALL
+            .into_iter()
+            .rev()
+            .find(|level| temperature >= self.start(*level))
+            .unwrap_or(ThermalLevel::Normal);
+        if level >= current {
+            return level;
+        }
+        // Only leave the current level once it is `hysteresis` cooler.
+        ThermalLevel::ALL
+            .into_iter()
+            .rev()
+            .filter(|level| *level <= current)
+            .find(|level| temperature >= self.start(*level).saturating_sub(self.hysteresis))
+            .unwrap_or(ThermalLevel::Normal)
+    }
+}
+
+/// What the board does when the thermal level changes.
+pub trait ThermalPolicy {
+    /// The system moved from level `from` to level `to`, at `temperature`
+    /// hundredths of degrees Celsius.
+    fn level_changed(&self, from: ThermalLevel, to: ThermalLevel, temperature: i32);
+}
+
+#[derive(Default)]
+pub struct App {
+    subscribed: bool,
+}
+
+pub struct ThermalManager<'a, A: Alarm<'a>, T: TemperatureDriver<'a>> {
+    alarm: &'a A,
+    sensor: &'a T,
+    thresholds: ThermalThresholds,
+    interval_ms: u32,
+    level: Cell<ThermalLevel>,
+    /// Last temperature sampled.
+    temperature: OptionalCell<i32>,
+    policy: OptionalCell<&'a dyn ThermalPolicy>,
+    /// Whether the sensor is reading.
+    reading: Cell<bool>,
+    /// Client of the sensor through this capsule, and whether it waits for a
+    /// reading.
+    client: OptionalCell<&'a dyn TemperatureClient>,
+    client_reading: Cell<bool>,
+    apps: Grant<App, UpcallCount<{ upcall::COUNT }>, AllowRoCount<0>, AllowRwCount<0>>,
+}
+
+impl<'a, A: Alarm<'a>, T: TemperatureDriver<'a>> ThermalManager<'a, A, T> {
+    /// Sample `sensor` every `interval_ms`.
+    pub fn new(
+        alarm: &'a A,
+        sensor: &'a T,
+        thresholds: ThermalThresholds,
+        interval_ms: u32,
+        grant: Grant<App, UpcallCount<{ upcall::COUNT }>, AllowRoCount<0>, AllowRwCount<0>>,
+    ) -> Self {
+        Self {
+            alarm,
+            sensor,
+            thresholds,
+            interval_ms,
+            level: Cell::new(ThermalLevel::Normal),
+            temperature: OptionalCell::empty(),
+            policy: OptionalCell::empty(),
+            reading: Cell::new(false),
+            client: OptionalCell::empty(),
+            client_reading: Cell::new(false),
+            apps: grant,
+        }
+    }
+
+    pub fn set_policy(&self, policy: &'a dyn ThermalPolicy) {
+        self.policy.set(policy);
+    }
+
+    /// Take the first sample now, and then every interval.
+    pub fn start(&self) {
+        self.sample();
+    }
+
+    pub fn level(&self) -> ThermalLevel {
+        self.level.get()
+    }
+
+    /// The last temperature sampled, in hundredths of degrees Celsius.
+    pub fn temperature(&self) -> Option<i32> {
+        self.temperature.get()
+    }
+
+    fn sample(&self) {
+        if !self.reading.get() {
+            match self.sensor.read_temperature() {
+                Ok(()) => self.reading.set(true),
+                // Try again at the next interval.
+                Err(_) => self.schedule_sample(),
+            }
+        }
+    }
+
+    fn schedule_sample(&self) {
+        let interval = self.alarm.ticks_from_ms(self.interval_ms);
+        self.alarm.set_alarm(self.alarm.now(), interval);
+    }
+
+    fn update_level(&self, temperature: i32) {
+        let from = self.level.get();
+        let to = self.thresholds.level(from, temperature);
+        if to == from {
+            return;
+        }
+        self.level.set(to);
+        self.policy
+            .map(|policy| policy.level_changed(from, to, temperature));
+        for app in self.apps.iter() {
+            app.enter(|app, kernel_data| {
+                if app.subscribed {
+                    kernel_data
+                        .schedule_upcall(
+                            upcall::LEVEL_CHANGED,
+                            (to as usize, temperature as usize, 0),
+                        )
+                        .ok();
+                }
+            });
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>, T: TemperatureDriver<'a>> AlarmClient for ThermalManager<'a, A, T> {
+    fn alarm(&self) {
+        self.sample();
+    }
+}
+
+impl<'a, A: Alarm<'a>, T: TemperatureDriver<'a>> TemperatureClient for ThermalManager<'a, A, T> {
+    fn callback(&self, value: Result<i32, ErrorCode>) {
+        self.reading.set(false);
+        if let Ok(temperature) = value {
+            self.temperature.set(temperature);
+            self.update_level(temperature);
+        }
+        if self.client_reading.take() {
+            self.client.map(|client| client.callback(value));
+        }
+        if !self.alarm.is_armed() {
+            self.schedule_sample();
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>, T: TemperatureDriver<'a>> TemperatureDriver<'a>
+    for ThermalManager<'a, A, T>
+{
+    fn set_client(&self, client: &'a dyn TemperatureClient) {
+        self.client.set(client);
+    }
+
+    /// Read the sensor for the client, sharing the reading with the next
+    /// sample if the sensor is already reading.
+    fn read_temperature(&self) -> Result<(), ErrorCode> {
+        if self.client_reading.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        if !self.reading.get() {
+            self.sensor.read_temperature()?;
+            self.reading.set(true);
+        }
+        self.client_reading.set(true);
+        Ok(())
+    }
+}
+
+impl<'a, A: Alarm<'a>, T: TemperatureDriver<'a>> SyscallDriver for ThermalManager<'a, A, T> {
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        _arg2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+            1 => CommandReturn::success_u32(self.level.get() as u32),
+            2 => self
+                .temperature
+                .get()
+                .map_or(CommandReturn::failure(ErrorCode::BUSY), |temperature| {
+                    CommandReturn::success_u32(temperature as u32)
+                }),
+            3 => match arg1 {
+                0 | 1 => self
+                    .apps
+                    .enter(processid, |app, _| app.subscribed = arg1 == 1)
+                    .map_or_else(
+                        |err| CommandReturn::failure(err.into()),
+                        |()| CommandReturn::success(),
+                    ),
+                _ => CommandReturn::failure(ErrorCode::INVAL),
+            },
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+/// Policy that stops processes while the system is at or above a level, and
+/// resumes them once it cools down.
+///
+/// Only the processes it stopped itself are resumed, so processes stopped
+/// from the process console stay stopped.
+pub struct PauseProcessesPolicy<C: ProcessManagementCapability, const N: usize> {
+    kernel: &'static Kernel,
+    capability: C,
+    level: ThermalLevel,
+    /// Names of the processes to stop, or `None` for all of them.
+    names: Option<&'static [&'static str]>,
+    paused: [OptionalCell<ProcessId>; N],
+}
+
+impl<C: ProcessManagementCapability, const N: usize> PauseProcessesPolicy<C, N> {
+    /// Stop the processes in `names`, or all processes if `None`, at `level`
+    /// and above. At most `N` processes are stopped.
+    pub fn new(
+        kernel: &'static Kernel,
+        capability: C,
+        level: ThermalLevel,
+        names: Option<&'static [&'static str]>,
+    ) -> Self {
+        Self {
+            kernel,
+            capability,
+            level,
+            names,
+            paused: [const { OptionalCell::empty() }; N],
+        }
+    }
+
+    fn pause(&self) {
+        self.kernel
+            .process_each_capability(&self.capability, |process| {
+                let selected = self
+                    .names
+                    .map_or(true, |names| names.contains(&process.get_process_name()));
+                let running = matches!(
+                    process.get_state(),
+                    State::Running | State::Yielded | State::YieldedFor(_)
+                );
+                if selected && running {
+                    if let Some(slot) = self.paused.iter().find(|slot| slot.is_none()) {
+                        process.stop();
+                        slot.set(process.processid());
+                    }
+                }
+            });
+    }
+
+    fn resume(&self) {
+        for slot in self.paused.iter() {
+            slot.take().map(|processid| {
+                self.kernel.process_map_or_external(
+                    (),
+                    processid,
+                    |process| process.resume(),
+                    &self.capability,
+                )
+            });
+        }
+    }
+}
+
+impl<C: ProcessManagementCapability, const N: usize> ThermalPolicy for PauseProcessesPolicy<C, N> {
+    fn level_changed(&self, from: ThermalLevel, to: ThermalLevel, _temperature: i32) {
+        if from < self.level && to >= self.level {
+            self.pause();
+        } else if from >= self.level && to < self.level {
+            self.resume();
+        }
+    }
+}
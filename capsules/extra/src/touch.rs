@@ -44,7 +44,7 @@ mod rw_allow {
     pub const COUNT: u8 = 3;
 }
 
-fn touch_status_to_number(status: &TouchStatus) -> usize {
+pub(crate) fn touch_status_to_number(status: &TouchStatus) -> usize {
     match status {
         TouchStatus::Released => 0,
         TouchStatus::Pressed => 1,
@@ -53,6 +53,19 @@ fn touch_status_to_number(status: &TouchStatus) -> usize {
     }
 }
 
+pub(crate) fn gesture_to_number(gesture: &GestureEvent) -> usize {
+    match gesture {
+        GestureEvent::SwipeUp => 1,
+        GestureEvent::SwipeDown => 2,
+        GestureEvent::SwipeLeft => 3,
+        GestureEvent::SwipeRight => 4,
+        GestureEvent::ZoomIn => 5,
+        GestureEvent::ZoomOut => 6,
+        GestureEvent::Tap => 7,
+        GestureEvent::LongPress => 8,
+    }
+}
+
 pub struct App {
     ack: bool,
     dropped_events: usize,
@@ -313,15 +326,9 @@ impl hil::touch::GestureClient for Touch<'_> {
     fn gesture_event(&self, event: GestureEvent) {
         for app in self.apps.iter() {
             app.enter(|_app, kernel_data| {
-                let gesture_id = match event {
-                    GestureEvent::SwipeUp => 1,
-                    GestureEvent::SwipeDown => 2,
-                    GestureEvent::SwipeLeft => 3,
-                    GestureEvent::SwipeRight => 4,
-                    GestureEvent::ZoomIn => 5,
-                    GestureEvent::ZoomOut => 6,
-                };
-                kernel_data.schedule_upcall(1, (gesture_id, 0, 0)).ok();
+                kernel_data
+                    .schedule_upcall(1, (gesture_to_number(&event), 0, 0))
+                    .ok();
             });
         }
     }
@@ -0,0 +1,500 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Calibration of touch panels.
+//!
+//! Resistive touch panels report positions that are offset, scaled and
+//! sometimes skewed compared to the screen under them. This capsule sits
+//! between the touch panel and the touch driver and maps each position the
+//! panel reports to screen coordinates with an affine transform, so every
+//! process gets accurate coordinates without calibrating on its own.
+//!
+//! The transform is computed by a calibration app, which turns on raw mode,
+//! draws targets on the screen and records the raw positions the panel
+//! reports for them. The app gives the transform to this capsule, which
+//! stores it in the KV store and loads it again at boot.
+//!
+//! The transform maps panel positions to screen coordinates in the normal
+//! rotation of the screen. The touch driver applies the screen rotation
+//! afterwards, so the screen can be rotated without calibrating again.
+//!
+//! The transform is six 16.16 fixed point coefficients `a` to `f`:
+//!
+//! ```text
+//! x_screen = a * x_panel + b * y_panel + c
+//! y_screen = d * x_panel + e * y_panel + f
+//! ```
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let calibration = components::touch_calibration::TouchCalibrationComponent::new(
+//!     board_kernel,
+//!     capsules_extra::touch_calibration::DRIVER_NUM,
+//!     touch_panel,
+//!     Some(screen),
+//!     calibration_kv,
+//! )
+//! .finalize(components::touch_calibration_component_static!(
+//!     capsules_extra::virtual_kv::VirtualKVPermissions<...>
+//! ));
+//! let touch = components::touch::TouchComponent::new(
+//!     board_kernel,
+//!     capsules_extra::touch::DRIVER_NUM,
+//!     calibration,
+//!     None,
+//!     Some(screen),
+//! )
+//! .finalize(components::touch_component_static!());
+//! let _ = calibration.load();
+//! ```
+
+use core::cell::Cell;
+
+use crate::touch::touch_status_to_number;
+use capsules_core::driver;
+use kernel::errorcode;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil;
+use kernel::hil::kv;
+use kernel::hil::screen::ScreenRotation;
+use kernel::hil::touch::{TouchClient, TouchEvent};
+use kernel::processbuffer::ReadableProcessBuffer;
+use kernel::storage_permissions::StoragePermissions;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+pub const DRIVER_NUM: usize = driver::NUM::TouchCalibration as usize;
+
+/// KV key of the stored calibration.
+const KEY: &[u8; KEY_LEN] = b"touchcal";
+/// Length of the KV key of the stored calibration.
+pub const KEY_LEN: usize = 8;
+/// Length of a stored calibration: six little endian `i32`.
+pub const CALIBRATION_LEN: usize = 24;
+
+/// IDs for subscribed upcalls.
+mod upcall {
+    /// Store or reset done. The argument is the status code.
+    pub const DONE: usize = 0;
+    /// Raw touch event. Arguments are the touch status, and the raw x and y
+    /// position as `x << 16 | y`.
+    pub const RAW: usize = 1;
+    pub const COUNT: u8 = 2;
+}
+
+/// Ids for read-only allow buffers.
+mod ro_allow {
+    /// Calibration to store.
+    pub const CALIBRATION: usize = 0;
+    pub const COUNT: u8 = 1;
+}
+
+/// An affine transform from panel positions to screen coordinates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Calibration {
+    /// Coefficients `a` to `f`, in 16.16 fixed point.
+    pub coefficients: [i32; 6],
+}
+
+impl Calibration {
+    /// Leaves positions unchanged.
+    pub const IDENTITY: Calibration = Calibration {
+        coefficients: [1 << 16, 0, 0, 0, 1 << 16, 0],
+    };
+
+    fn from_bytes(bytes: &[u8]) -> Option<Calibration> {
+        if bytes.len() < CALIBRATION_LEN {
+            return None;
+        }
+        let mut coefficients = [0; 6];
+        for (coefficient, word) in coefficients.iter_mut().zip(bytes.chunks_exact(4)) {
+            *coefficient = i32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        Some(Calibration { coefficients })
+    }
+
+    fn to_bytes(self, bytes: &mut [u8]) {
+        for (word, coefficient) in bytes.chunks_exact_mut(4).zip(self.coefficients) {
+            word.copy_from_slice(&coefficient.to_le_bytes());
+        }
+    }
+
+    /// Map a panel position to screen coordinates, clamped to `0..=max_x`
+    /// and `0..=max_y`.
+    pub fn apply(&self, x: u16, y: u16, max_x: u16, max_y: u16) -> (u16, u16) {
+        let k = self.coefficients.map(i64::from);
+        let (x, y) = (i64::from(x), i64::from(y));
+        let map = |value: i64, max: u16| (value >> 16).clamp(0, i64::from(max)) as u16;
+        (
+            map(k[0] * x + k[1] * y + k[2], max_x),
+            map(k[3] * x + k[4] * y + k[5], max_y),
+        )
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Op {
+    Load,
+    Store,
+    Reset,
+}
+
+#[derive(Default)]
+pub struct App;
+
+pub struct TouchCalibration<'a, V: kv::KVPermissions<'a>> {
+    touch: &'a dyn hil::touch::Touch<'a>,
+    /// Screen under the touch panel, to clamp positions to its resolution.
+    screen: Option<&'a dyn hil::screen::Screen<'a>>,
+    kv: &'a V,
+    /// Permissions of the kernel for the stored calibration.
+    permissions: StoragePermissions,
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<0>,
+    >,
+    client: OptionalCell<&'a dyn TouchClient>,
+    calibration: Cell<Calibration>,
+    /// Whether the client enabled the panel.
+    enabled: Cell<bool>,
+    /// Process calibrating the panel, which receives raw events instead of
+    /// the client.
+    raw: OptionalCell<ProcessId>,
+    /// Running KV operation, and the process that requested it.
+    op: OptionalCell<(Op, Option<ProcessId>)>,
+    key_buffer: TakeCell<'static, [u8]>,
+    value_buffer: TakeCell<'static, [u8]>,
+}
+
+impl<'a, V: kv::KVPermissions<'a>> TouchCalibration<'a, V> {
+    pub fn new(
+        touch: &'a dyn hil::touch::Touch<'a>,
+        screen: Option<&'a dyn hil::screen::Screen<'a>>,
+        kv: &'a V,
+        permissions: StoragePermissions,
+        key_buffer: &'static mut [u8],
+        value_buffer: &'static mut [u8],
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<0>,
+        >,
+    ) -> Self {
+        Self {
+            touch,
+            screen,
+            kv,
+            permissions,
+            apps: grant,
+            client: OptionalCell::empty(),
+            calibration: Cell::new(Calibration::IDENTITY),
+            enabled: Cell::new(false),
+            raw: OptionalCell::empty(),
+            op: OptionalCell::empty(),
+            key_buffer: TakeCell::new(key_buffer),
+            value_buffer: TakeCell::new(value_buffer),
+        }
+    }
+
+    /// Load the stored calibration. Until it is loaded, and if none is
+    /// stored, positions are left unchanged.
+    pub fn load(&self) -> Result<(), ErrorCode> {
+        self.start(Op::Load, None)
+    }
+
+    /// The calibration in use.
+    pub fn calibration(&self) -> Calibration {
+        self.calibration.get()
+    }
+
+    /// Start `op` on the KV store.
+    fn start(&self, op: Op, processid: Option<ProcessId>) -> Result<(), ErrorCode> {
+        if self.op.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        let key_buf = self.key_buffer.take().ok_or(ErrorCode::BUSY)?;
+        key_buf[..KEY_LEN].copy_from_slice(KEY);
+        let mut key = SubSliceMut::new(key_buf);
+        key.slice(..KEY_LEN);
+
+        let result = if op == Op::Reset {
+            self.kv.delete(key, self.permissions).map_err(|(key, e)| {
+                self.key_buffer.replace(key.take());
+                e
+            })
+        } else {
+            let Some(value_buf) = self.value_buffer.take() else {
+                self.key_buffer.replace(key.take());
+                return Err(ErrorCode::BUSY);
+            };
+            let header = self.kv.header_size();
+            if value_buf.len() < header + CALIBRATION_LEN {
+                self.key_buffer.replace(key.take());
+                self.value_buffer.replace(value_buf);
+                return Err(ErrorCode::SIZE);
+            }
+            let mut value = SubSliceMut::new(value_buf);
+            let result = if op == Op::Store {
+                self.calibration
+                    .get()
+                    .to_bytes(&mut value[header..header + CALIBRATION_LEN]);
+                value.slice(..header + CALIBRATION_LEN);
+                self.kv.set(key, value, self.permissions)
+            } else {
+                self.kv.get(key, value, self.permissions)
+            };
+            result.map_err(|(key, value, e)| {
+                self.key_buffer.replace(key.take());
+                self.value_buffer.replace(value.take());
+                e
+            })
+        };
+        if result.is_ok() {
+            self.op.set((op, processid));
+        }
+        result
+    }
+
+    /// Signal the end of the running operation to the process that requested
+    /// it.
+    fn complete(&self, result: Result<(), ErrorCode>) {
+        if let Some((_, Some(processid))) = self.op.take() {
+            let _ = self.apps.enter(processid, |_, kernel_data| {
+                kernel_data
+                    .schedule_upcall(upcall::DONE, (errorcode::into_statuscode(result), 0, 0))
+                    .ok();
+            });
+        }
+    }
+
+    /// Use the calibration in the read-only buffer of `processid` and store
+    /// it.
+    fn store(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        let calibration = self
+            .apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readonly_processbuffer(ro_allow::CALIBRATION)
+                    .and_then(|buffer| {
+                        buffer.enter(|data| {
+                            let mut bytes = [0; CALIBRATION_LEN];
+                            if data.len() < CALIBRATION_LEN {
+                                return None;
+                            }
+                            data[..CALIBRATION_LEN].copy_to_slice(&mut bytes);
+                            Calibration::from_bytes(&bytes)
+                        })
+                    })
+                    .unwrap_or(None)
+            })?
+            .ok_or(ErrorCode::SIZE)?;
+        if self.op.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.calibration.set(calibration);
+        self.start(Op::Store, Some(processid))
+    }
+
+    /// Turn raw mode on or off for `processid`.
+    fn set_raw(&self, processid: ProcessId, raw: bool) -> Result<(), ErrorCode> {
+        match self.raw.get() {
+            Some(owner) if owner != processid => return Err(ErrorCode::BUSY),
+            _ => {}
+        }
+        if raw {
+            self.raw.set(processid);
+            self.touch.enable()
+        } else {
+            self.raw.clear();
+            if self.enabled.get() {
+                Ok(())
+            } else {
+                self.touch.disable()
+            }
+        }
+    }
+
+    /// Largest coordinates on the screen in its normal rotation.
+    fn max_position(&self) -> (u16, u16) {
+        self.screen.map_or((u16::MAX, u16::MAX), |screen| {
+            let (width, height) = screen.get_resolution();
+            let (width, height) = match screen.get_rotation() {
+                ScreenRotation::Rotated90 | ScreenRotation::Rotated270 => (height, width),
+                _ => (width, height),
+            };
+            (
+                width.saturating_sub(1) as u16,
+                height.saturating_sub(1) as u16,
+            )
+        })
+    }
+}
+
+impl<'a, V: kv::KVPermissions<'a>> hil::touch::Touch<'a> for TouchCalibration<'a, V> {
+    fn enable(&self) -> Result<(), ErrorCode> {
+        self.enabled.set(true);
+        self.touch.enable()
+    }
+
+    fn disable(&self) -> Result<(), ErrorCode> {
+        self.enabled.set(false);
+        if self.raw.is_some() {
+            // Keep the panel on for the calibration.
+            Ok(())
+        } else {
+            self.touch.disable()
+        }
+    }
+
+    fn set_client(&self, client: &'a dyn TouchClient) {
+        self.client.set(client);
+    }
+}
+
+impl<'a, V: kv::KVPermissions<'a>> TouchClient for TouchCalibration<'a, V> {
+    fn touch_event(&self, mut event: TouchEvent) {
+        if let Some(processid) = self.raw.get() {
+            let delivered = self.apps.enter(processid, |_, kernel_data| {
+                kernel_data
+                    .schedule_upcall(
+                        upcall::RAW,
+                        (
+                            touch_status_to_number(&event.status),
+                            (event.x as usize) << 16 | event.y as usize,
+                            0,
+                        ),
+                    )
+                    .ok();
+            });
+            if delivered.is_ok() {
+                return;
+            }
+            // The calibrating process is gone.
+            let _ = self.set_raw(processid, false);
+        }
+
+        let (max_x, max_y) = self.max_position();
+        (event.x, event.y) = self.calibration.get().apply(event.x, event.y, max_x, max_y);
+        if self.enabled.get() {
+            self.client.map(|client| client.touch_event(event));
+        }
+    }
+}
+
+impl<'a, V: kv::KVPermissions<'a>> kv::KVClient for TouchCalibration<'a, V> {
+    fn get_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.key_buffer.replace(key.take());
+        // The KV header is already removed from `value`.
+        if let Some(calibration) = result
+            .ok()
+            .and_then(|()| Calibration::from_bytes(&value[..]))
+        {
+            self.calibration.set(calibration);
+        }
+        self.value_buffer.replace(value.take());
+        self.complete(result);
+    }
+
+    fn set_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.key_buffer.replace(key.take());
+        self.value_buffer.replace(value.take());
+        self.complete(result);
+    }
+
+    fn add_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.key_buffer.replace(key.take());
+        self.value_buffer.replace(value.take());
+        self.complete(result);
+    }
+
+    fn update_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.key_buffer.replace(key.take());
+        self.value_buffer.replace(value.take());
+        self.complete(result);
+    }
+
+    fn delete_complete(&self, result: Result<(), ErrorCode>, key: SubSliceMut<'static, u8>) {
+        self.key_buffer.replace(key.take());
+        // Nothing stored is as good as deleted.
+        self.complete(result.or_else(|e| match e {
+            ErrorCode::NOSUPPORT => Ok(()),
+            e => Err(e),
+        }));
+    }
+
+    fn garbage_collection_complete(&self, _result: Result<(), ErrorCode>) {}
+}
+
+impl<'a, V: kv::KVPermissions<'a>> SyscallDriver for TouchCalibration<'a, V> {
+    /// Calibrate the touch panel.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Get coefficient `arg1` of the calibration in use, from 0 for
+    ///   `a` to 5 for `f`.
+    /// - `2`: Use the calibration in the read-only buffer and store it.
+    /// - `3`: Go back to positions unchanged and delete the stored
+    ///   calibration.
+    /// - `4`: Turn raw mode on if `arg1` is not 0, and off otherwise. In raw
+    ///   mode, the process receives the raw positions of the panel and other
+    ///   processes do not receive touch events.
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        _arg2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+            1 => match self.calibration.get().coefficients.get(arg1) {
+                Some(coefficient) => CommandReturn::success_u32(*coefficient as u32),
+                None => CommandReturn::failure(ErrorCode::INVAL),
+            },
+            2 => self.store(processid).into(),
+            3 => {
+                if self.op.is_some() {
+                    return CommandReturn::failure(ErrorCode::BUSY);
+                }
+                self.calibration.set(Calibration::IDENTITY);
+                self.start(Op::Reset, Some(processid)).into()
+            }
+            4 => self.set_raw(processid, arg1 != 0).into(),
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
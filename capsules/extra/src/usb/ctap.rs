@@ -25,6 +25,7 @@ use kernel::hil;
 use kernel::hil::usb::TransferType;
 use kernel::utilities::cells::OptionalCell;
 use kernel::utilities::cells::TakeCell;
+use kernel::utilities::poison::{PoisonCell, Poisonable};
 use kernel::ErrorCode;
 
 /// Use 1 Interrupt transfer IN/OUT endpoint
@@ -96,6 +97,10 @@ pub struct CtapHid<'a, U: 'a> {
     /// A holder for the buffer to receive bytes into. We use this as a flag as
     /// well, if we have a buffer then we are actively doing a receive.
     recv_buffer: TakeCell<'static, [u8; 64]>,
+
+    /// Set if the USB controller used an endpoint with a transfer type
+    /// CTAP does not declare, after which transfers are refused.
+    poison: PoisonCell,
 }
 
 impl<'a, U: hil::usb::UsbController<'a>> CtapHid<'a, U> {
@@ -167,6 +172,7 @@ impl<'a, U: hil::usb::UsbController<'a>> CtapHid<'a, U> {
             client: OptionalCell::empty(),
             send_buffer: TakeCell::empty(),
             recv_buffer: TakeCell::empty(),
+            poison: PoisonCell::new("ctap"),
         }
     }
 
@@ -185,6 +191,9 @@ impl<'a, U: hil::usb::UsbController<'a>> hil::usb_hid::UsbHid<'a, [u8; 64]> for
         &'a self,
         send: &'static mut [u8; 64],
     ) -> Result<usize, (ErrorCode, &'static mut [u8; 64])> {
+        if let Err(err) = self.poison.check() {
+            return Err((err, send));
+        }
         let len = send.len();
 
         self.send_buffer.replace(send);
@@ -204,6 +213,9 @@ impl<'a, U: hil::usb::UsbController<'a>> hil::usb_hid::UsbHid<'a, [u8; 64]> for
         &'a self,
         recv: &'static mut [u8; 64],
     ) -> Result<(), (ErrorCode, &'static mut [u8; 64])> {
+        if let Err(err) = self.poison.check() {
+            return Err((err, recv));
+        }
         self.recv_buffer.replace(recv);
         self.controller().endpoint_resume_out(ENDPOINT_NUM);
         Ok(())
@@ -217,6 +229,12 @@ impl<'a, U: hil::usb::UsbController<'a>> hil::usb_hid::UsbHid<'a, [u8; 64]> for
     }
 }
 
+impl<'a, U: hil::usb::UsbController<'a>> Poisonable for CtapHid<'a, U> {
+    fn poison_cell(&self) -> &PoisonCell {
+        &self.poison
+    }
+}
+
 impl<'a, U: hil::usb::UsbController<'a>> hil::usb::Client<'a> for CtapHid<'a, U> {
     fn enable(&'a self) {
         // Set up the default control endpoint
@@ -296,7 +314,9 @@ impl<'a, U: hil::usb::UsbController<'a>> hil::usb::Client<'a> for CtapHid<'a, U>
                     })
             }
             TransferType::Bulk | TransferType::Control | TransferType::Isochronous => {
-                panic!("Transfer protocol not supported by CTAP v2");
+                self.poison
+                    .poison("transfer protocol not supported by CTAP v2");
+                hil::usb::InResult::Error
             }
         }
     }
@@ -337,7 +357,9 @@ impl<'a, U: hil::usb::UsbController<'a>> hil::usb::Client<'a> for CtapHid<'a, U>
                     })
             }
             TransferType::Bulk | TransferType::Control | TransferType::Isochronous => {
-                panic!("Transfer protocol not supported by CTAP v2");
+                self.poison
+                    .poison("transfer protocol not supported by CTAP v2");
+                hil::usb::OutResult::Error
             }
         }
     }
@@ -0,0 +1,820 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Mass Storage Class device for USB
+//!
+//! This capsule exposes a region of flash to the host as a removable drive,
+//! using the Bulk-Only Transport and a subset of the SCSI commands. It works
+//! with any `hil::flash::Flash` implementation.
+//!
+//! The drive has a single logical unit. Its blocks are the pages of the
+//! flash, so the block size the host sees is the page size of the flash
+//! (4096 bytes for the nRF52 and the MX25R6435F). Each block written by the
+//! host is written with one `write_page` call, which must erase the page
+//! first. A write that does not cover whole blocks only writes the complete
+//! blocks.
+//!
+//! Supported commands are TEST UNIT READY, REQUEST SENSE, INQUIRY,
+//! MODE SENSE (6 and 10), START STOP UNIT, PREVENT ALLOW MEDIUM REMOVAL,
+//! READ FORMAT CAPACITIES, READ CAPACITY (10), READ (10), WRITE (10),
+//! VERIFY (10) and SYNCHRONIZE CACHE (10). Other commands fail with the
+//! ILLEGAL REQUEST sense key.
+//!
+//! Timing
+//! ------
+//!
+//! While a page is being written, OUT packets from the host are delayed
+//! (NAKed). The packet that was received when the controller was told to
+//! delay is kept in the endpoint buffer and consumed when the write
+//! finishes.
+
+use core::cell::Cell;
+use core::cmp;
+
+use super::descriptors;
+use super::descriptors::Buffer64;
+use super::descriptors::EndpointAddress;
+use super::descriptors::EndpointDescriptor;
+use super::descriptors::InterfaceDescriptor;
+use super::descriptors::Recipient;
+use super::descriptors::RequestType;
+use super::descriptors::TransferDirection;
+use super::usbc_client_ctrl::ClientCtrl;
+
+use kernel::hil;
+use kernel::hil::usb::TransferType;
+use kernel::utilities::cells::{OptionalCell, TakeCell, VolatileCell};
+use kernel::ErrorCode;
+
+/// Identifying number for the endpoint when transferring data from us to the
+/// host.
+const ENDPOINT_IN_NUM: usize = 1;
+/// Identifying number for the endpoint when transferring data from the host to
+/// us.
+const ENDPOINT_OUT_NUM: usize = 2;
+
+const N_ENDPOINTS: usize = 2;
+
+/// Size of the packets on the bulk endpoints.
+const PACKET_SIZE: usize = 64;
+
+static LANGUAGES: &[u16; 1] = &[
+    0x0409, // English (United States)
+];
+
+/// Class specific control requests of the Bulk-Only Transport.
+const REQUEST_GET_MAX_LUN: u8 = 0xfe;
+const REQUEST_RESET: u8 = 0xff;
+
+/// Command Block Wrapper, sent by the host to start a command.
+const CBW_SIGNATURE: u32 = 0x43425355;
+const CBW_LEN: usize = 31;
+/// Command Status Wrapper, sent to the host at the end of a command.
+const CSW_SIGNATURE: u32 = 0x53425355;
+const CSW_LEN: usize = 13;
+
+const CSW_STATUS_PASSED: u8 = 0;
+const CSW_STATUS_FAILED: u8 = 1;
+
+/// SCSI operation codes.
+mod opcode {
+    pub const TEST_UNIT_READY: u8 = 0x00;
+    pub const REQUEST_SENSE: u8 = 0x03;
+    pub const INQUIRY: u8 = 0x12;
+    pub const MODE_SENSE_6: u8 = 0x1a;
+    pub const START_STOP_UNIT: u8 = 0x1b;
+    pub const PREVENT_ALLOW_MEDIUM_REMOVAL: u8 = 0x1e;
+    pub const READ_FORMAT_CAPACITIES: u8 = 0x23;
+    pub const READ_CAPACITY_10: u8 = 0x25;
+    pub const READ_10: u8 = 0x28;
+    pub const WRITE_10: u8 = 0x2a;
+    pub const VERIFY_10: u8 = 0x2f;
+    pub const SYNCHRONIZE_CACHE_10: u8 = 0x35;
+    pub const MODE_SENSE_10: u8 = 0x5a;
+}
+
+/// SCSI sense data: sense key and additional sense code.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Sense(u8, u8);
+
+impl Sense {
+    const NONE: Sense = Sense(0x00, 0x00);
+    const UNRECOVERED_READ_ERROR: Sense = Sense(0x03, 0x11);
+    const WRITE_ERROR: Sense = Sense(0x03, 0x0c);
+    const INVALID_COMMAND: Sense = Sense(0x05, 0x20);
+    const LBA_OUT_OF_RANGE: Sense = Sense(0x05, 0x21);
+}
+
+/// Length of the longest response to a command (INQUIRY).
+const RESPONSE_LEN: usize = 36;
+
+/// Vendor, product and revision reported by INQUIRY.
+const INQUIRY_IDENTIFICATION: &[u8; 28] = b"Tock    Flash Storage   0001";
+
+/// States of a command.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    /// Waiting for a Command Block Wrapper.
+    Command,
+    /// Sending the response of the command in `response`.
+    Response,
+    /// Sending `count` pages of flash, starting at `page`. `offset` is the
+    /// offset of the next byte to send in the page buffer, or `None` while
+    /// the page is being read.
+    Read {
+        page: usize,
+        count: usize,
+        offset: Option<usize>,
+    },
+    /// Receiving `count` pages of flash, starting at `page`. `offset` is the
+    /// offset of the next byte to receive in the page buffer, or `None` while
+    /// the page is being written.
+    Write {
+        page: usize,
+        count: usize,
+        offset: Option<usize>,
+    },
+    /// Sending zeros, or discarding the data received, until the end of the
+    /// data the host expects.
+    Pad,
+    /// Sending the Command Status Wrapper.
+    Status,
+}
+
+pub struct MassStorage<'a, U: 'a, F: hil::flash::Flash + 'static> {
+    /// Helper USB client library for handling many USB operations.
+    client_ctrl: ClientCtrl<'a, 'static, U>,
+
+    /// 64 byte buffers for each endpoint.
+    buffers: [Buffer64; N_ENDPOINTS],
+
+    /// The host sent a GET_MAX_LUN request, that must be answered.
+    get_max_lun: Cell<bool>,
+
+    flash: &'a F,
+    page_buffer: TakeCell<'static, F::Page>,
+    page_size: usize,
+    /// Region of the flash exposed to the host.
+    first_page: usize,
+    num_pages: usize,
+
+    state: Cell<State>,
+    /// Tag of the current command, returned in its status.
+    tag: Cell<u32>,
+    /// Number of bytes the host expects to transfer for the current command.
+    data_len: Cell<u32>,
+    /// Number of bytes left to transfer for the current command.
+    remaining: Cell<u32>,
+    /// Number of bytes of the transfer that were meaningful.
+    transferred: Cell<u32>,
+    /// The host expects data from us for the current command.
+    data_in: Cell<bool>,
+    status: Cell<u8>,
+    /// Sense data of the last command that failed, for REQUEST SENSE.
+    sense: Cell<Sense>,
+
+    response: [Cell<u8>; RESPONSE_LEN],
+    response_len: Cell<usize>,
+
+    /// Size of the OUT packet that was delayed while a page was written.
+    delayed_out: OptionalCell<usize>,
+}
+
+impl<'a, U: hil::usb::UsbController<'a>, F: hil::flash::Flash> MassStorage<'a, U, F> {
+    /// Expose `num_pages` pages of `flash`, starting at `first_page`.
+    pub fn new(
+        controller: &'a U,
+        max_ctrl_packet_size: u8,
+        vendor_id: u16,
+        product_id: u16,
+        strings: &'static [&'static str; 3],
+        flash: &'a F,
+        page_buffer: &'static mut F::Page,
+        first_page: usize,
+        num_pages: usize,
+    ) -> Self {
+        let interfaces: &mut [InterfaceDescriptor] = &mut [InterfaceDescriptor {
+            interface_number: 0,
+            interface_class: 0x08,    // Mass storage
+            interface_subclass: 0x06, // SCSI transparent command set
+            interface_protocol: 0x50, // Bulk-only transport
+            ..InterfaceDescriptor::default()
+        }];
+
+        let endpoints: &[&[EndpointDescriptor]] = &[&[
+            EndpointDescriptor {
+                endpoint_address: EndpointAddress::new_const(
+                    ENDPOINT_IN_NUM,
+                    TransferDirection::DeviceToHost,
+                ),
+                transfer_type: TransferType::Bulk,
+                max_packet_size: PACKET_SIZE as u16,
+                interval: 0,
+            },
+            EndpointDescriptor {
+                endpoint_address: EndpointAddress::new_const(
+                    ENDPOINT_OUT_NUM,
+                    TransferDirection::HostToDevice,
+                ),
+                transfer_type: TransferType::Bulk,
+                max_packet_size: PACKET_SIZE as u16,
+                interval: 0,
+            },
+        ]];
+
+        let (device_descriptor_buffer, other_descriptor_buffer) =
+            descriptors::create_descriptor_buffers(
+                descriptors::DeviceDescriptor {
+                    vendor_id,
+                    product_id,
+                    manufacturer_string: 1,
+                    product_string: 2,
+                    serial_number_string: 3,
+                    max_packet_size_ep0: max_ctrl_packet_size,
+                    ..descriptors::DeviceDescriptor::default()
+                },
+                descriptors::ConfigurationDescriptor::default(),
+                interfaces,
+                endpoints,
+                None, // No HID descriptor
+                None, // No CDC descriptor
+            );
+
+        let page_size = page_buffer.as_mut().len();
+
+        MassStorage {
+            client_ctrl: ClientCtrl::new(
+                controller,
+                device_descriptor_buffer,
+                other_descriptor_buffer,
+                None, // No HID descriptor
+                None, // No report descriptor
+                LANGUAGES,
+                strings,
+            ),
+            buffers: Default::default(),
+            get_max_lun: Cell::new(false),
+            flash,
+            page_buffer: TakeCell::new(page_buffer),
+            page_size,
+            first_page,
+            num_pages,
+            state: Cell::new(State::Command),
+            tag: Cell::new(0),
+            data_len: Cell::new(0),
+            remaining: Cell::new(0),
+            transferred: Cell::new(0),
+            data_in: Cell::new(false),
+            status: Cell::new(CSW_STATUS_PASSED),
+            sense: Cell::new(Sense::NONE),
+            response: [const { Cell::new(0) }; RESPONSE_LEN],
+            response_len: Cell::new(0),
+            delayed_out: OptionalCell::empty(),
+        }
+    }
+
+    #[inline]
+    fn controller(&self) -> &'a U {
+        self.client_ctrl.controller()
+    }
+
+    #[inline]
+    fn buffer(&self, i: usize) -> &[VolatileCell<u8>; 64] {
+        &self.buffers[i - 1].buf
+    }
+
+    /// Abort the current command, for example after a reset.
+    fn reset(&self) {
+        self.state.set(State::Command);
+        self.delayed_out.clear();
+    }
+
+    /// Set the response of a successful command.
+    fn respond(&self, response: &[u8]) {
+        for (cell, byte) in self.response.iter().zip(response) {
+            cell.set(*byte);
+        }
+        self.response_len
+            .set(cmp::min(response.len(), RESPONSE_LEN));
+    }
+
+    fn fail(&self, sense: Sense) {
+        self.status.set(CSW_STATUS_FAILED);
+        self.sense.set(sense);
+    }
+
+    /// State after the meaningful data of the command was transferred.
+    fn end_of_data(&self) -> State {
+        if self.remaining.get() > 0 {
+            State::Pad
+        } else {
+            State::Status
+        }
+    }
+
+    /// Handle a Command Block Wrapper received from the host.
+    fn command(&self, cbw: &[VolatileCell<u8>]) {
+        let get_u32 = |offset: usize| {
+            u32::from_le_bytes([
+                cbw[offset].get(),
+                cbw[offset + 1].get(),
+                cbw[offset + 2].get(),
+                cbw[offset + 3].get(),
+            ])
+        };
+        if get_u32(0) != CBW_SIGNATURE {
+            // Not a command. Wait for the next one.
+            return;
+        }
+        self.tag.set(get_u32(4));
+        self.data_len.set(get_u32(8));
+        self.remaining.set(get_u32(8));
+        self.transferred.set(0);
+        self.data_in.set(cbw[12].get() & 0x80 != 0);
+        self.status.set(CSW_STATUS_PASSED);
+        self.response_len.set(0);
+
+        // The command block starts at offset 15.
+        let cb = |i: usize| cbw[15 + i].get();
+        let lba = u32::from_be_bytes([cb(2), cb(3), cb(4), cb(5)]) as usize;
+        let blocks = u16::from_be_bytes([cb(7), cb(8)]) as usize;
+        let last_block = self.num_pages.saturating_sub(1) as u32;
+        let block_size = self.page_size as u32;
+
+        let mut state = None;
+        match cb(0) {
+            opcode::TEST_UNIT_READY
+            | opcode::START_STOP_UNIT
+            | opcode::PREVENT_ALLOW_MEDIUM_REMOVAL
+            | opcode::VERIFY_10
+            | opcode::SYNCHRONIZE_CACHE_10 => {}
+            opcode::REQUEST_SENSE => {
+                let Sense(key, asc) = self.sense.replace(Sense::NONE);
+                // Fixed format sense data, with 10 additional bytes.
+                self.respond(&[0x70, 0, key, 0, 0, 0, 0, 10, 0, 0, 0, 0, asc, 0, 0, 0, 0, 0]);
+            }
+            opcode::INQUIRY => {
+                let mut inquiry = [0; RESPONSE_LEN];
+                inquiry[1] = 0x80; // Removable
+                inquiry[2] = 0x04; // SPC-2
+                inquiry[3] = 0x02; // Response data format
+                inquiry[4] = (RESPONSE_LEN - 5) as u8; // Additional length
+                inquiry[8..].copy_from_slice(INQUIRY_IDENTIFICATION);
+                self.respond(&inquiry);
+            }
+            opcode::MODE_SENSE_6 => self.respond(&[3, 0, 0, 0]),
+            opcode::MODE_SENSE_10 => self.respond(&[0, 6, 0, 0, 0, 0, 0, 0]),
+            opcode::READ_FORMAT_CAPACITIES => {
+                let blocks = (self.num_pages as u32).to_be_bytes();
+                let size = block_size.to_be_bytes();
+                self.respond(&[
+                    0, 0, 0, 8, // Capacity list header
+                    blocks[0], blocks[1], blocks[2], blocks[3], 0x02, // Formatted media
+                    size[1], size[2], size[3],
+                ]);
+            }
+            opcode::READ_CAPACITY_10 => {
+                let last = last_block.to_be_bytes();
+                let size = block_size.to_be_bytes();
+                self.respond(&[
+                    last[0], last[1], last[2], last[3], size[0], size[1], size[2], size[3],
+                ]);
+            }
+            opcode::READ_10 | opcode::WRITE_10 if lba + blocks > self.num_pages => {
+                self.fail(Sense::LBA_OUT_OF_RANGE);
+            }
+            opcode::READ_10 if blocks > 0 => match self.read_page(lba) {
+                Ok(()) => {
+                    state = Some(State::Read {
+                        page: lba,
+                        count: blocks,
+                        offset: None,
+                    })
+                }
+                Err(_) => self.fail(Sense::UNRECOVERED_READ_ERROR),
+            },
+            opcode::WRITE_10 if blocks > 0 => {
+                state = Some(State::Write {
+                    page: lba,
+                    count: blocks,
+                    offset: Some(0),
+                });
+            }
+            opcode::READ_10 | opcode::WRITE_10 => {}
+            _ => self.fail(Sense::INVALID_COMMAND),
+        }
+
+        let state = state.unwrap_or(if self.remaining.get() == 0 {
+            State::Status
+        } else if self.response_len.get() > 0 && self.data_in.get() {
+            State::Response
+        } else {
+            State::Pad
+        });
+        self.state.set(state);
+        if self.data_in.get() || state == State::Status {
+            self.controller().endpoint_resume_in(ENDPOINT_IN_NUM);
+        }
+    }
+
+    /// Start reading page `page` of the region, to send it to the host.
+    fn read_page(&self, page: usize) -> Result<(), ErrorCode> {
+        self.page_buffer
+            .take()
+            .map_or(Err(ErrorCode::BUSY), |buffer| {
+                self.flash
+                    .read_page(self.first_page + page, buffer)
+                    .map_err(|(error, buffer)| {
+                        self.page_buffer.replace(buffer);
+                        error
+                    })
+            })
+    }
+
+    /// Consume an OUT packet of `packet_bytes` bytes of a WRITE command.
+    fn receive_write_data(&self, packet_bytes: usize) {
+        let State::Write {
+            page,
+            count,
+            offset: Some(offset),
+        } = self.state.get()
+        else {
+            return;
+        };
+        let received = cmp::min(packet_bytes, self.remaining.get() as usize);
+        let copied = cmp::min(received, self.page_size - offset);
+        self.page_buffer.map(|buffer| {
+            let packet = self.buffer(ENDPOINT_OUT_NUM);
+            for (byte, cell) in buffer.as_mut()[offset..offset + copied]
+                .iter_mut()
+                .zip(packet.iter())
+            {
+                *byte = cell.get();
+            }
+        });
+        self.remaining.set(self.remaining.get() - received as u32);
+        self.transferred.set(self.transferred.get() + copied as u32);
+
+        let offset = offset + copied;
+        if offset == self.page_size {
+            self.state.set(State::Write {
+                page,
+                count,
+                offset: None,
+            });
+            self.write_page(page);
+        } else if self.remaining.get() == 0 {
+            // The host sent less data than the command covers. The partial
+            // block is not written.
+            self.state.set(State::Status);
+            self.controller().endpoint_resume_in(ENDPOINT_IN_NUM);
+        } else {
+            self.state.set(State::Write {
+                page,
+                count,
+                offset: Some(offset),
+            });
+        }
+    }
+
+    /// Start writing the received page `page` of the region.
+    fn write_page(&self, page: usize) {
+        let result = self
+            .page_buffer
+            .take()
+            .map_or(Err(ErrorCode::BUSY), |buffer| {
+                self.flash
+                    .write_page(self.first_page + page, buffer)
+                    .map_err(|(error, buffer)| {
+                        self.page_buffer.replace(buffer);
+                        error
+                    })
+            });
+        if result.is_err() {
+            self.write_failed();
+        }
+    }
+
+    fn write_failed(&self) {
+        self.fail(Sense::WRITE_ERROR);
+        self.state.set(self.end_of_data());
+        if self.state.get() == State::Status {
+            self.controller().endpoint_resume_in(ENDPOINT_IN_NUM);
+        }
+        self.resume_delayed_out();
+    }
+
+    /// Consume the OUT packet that was delayed, if any, and accept the next
+    /// ones.
+    fn resume_delayed_out(&self) {
+        if let Some(packet_bytes) = self.delayed_out.take() {
+            let _ = self.packet_out_bulk(packet_bytes);
+            self.controller().endpoint_resume_out(ENDPOINT_OUT_NUM);
+        }
+    }
+
+    /// Handle the IN packets of the data and status phases.
+    fn packet_in_bulk(&self) -> hil::usb::InResult {
+        let packet = self.buffer(ENDPOINT_IN_NUM);
+        let remaining = self.remaining.get() as usize;
+        match self.state.get() {
+            State::Response => {
+                let len = cmp::min(self.response_len.get(), remaining);
+                for (cell, byte) in packet.iter().zip(self.response.iter()).take(len) {
+                    cell.set(byte.get());
+                }
+                self.remaining.set((remaining - len) as u32);
+                self.transferred.set(len as u32);
+                // A short packet ends the data phase, so the remaining data
+                // is only padded if the response fills whole packets.
+                self.state.set(if len % PACKET_SIZE == 0 {
+                    self.end_of_data()
+                } else {
+                    State::Status
+                });
+                hil::usb::InResult::Packet(len)
+            }
+            State::Read {
+                page,
+                count,
+                offset: Some(offset),
+            } => {
+                let len = cmp::min(cmp::min(PACKET_SIZE, self.page_size - offset), remaining);
+                self.page_buffer.map(|buffer| {
+                    for (cell, byte) in packet.iter().zip(&buffer.as_mut()[offset..offset + len]) {
+                        cell.set(*byte);
+                    }
+                });
+                self.remaining.set((remaining - len) as u32);
+                self.transferred.set(self.transferred.get() + len as u32);
+
+                let offset = offset + len;
+                if remaining == len {
+                    self.state.set(State::Status);
+                } else if offset < self.page_size {
+                    self.state.set(State::Read {
+                        page,
+                        count,
+                        offset: Some(offset),
+                    });
+                } else if count > 1 {
+                    self.state.set(State::Read {
+                        page: page + 1,
+                        count: count - 1,
+                        offset: None,
+                    });
+                    if self.read_page(page + 1).is_err() {
+                        self.fail(Sense::UNRECOVERED_READ_ERROR);
+                        self.state.set(State::Pad);
+                    }
+                } else {
+                    self.state.set(State::Pad);
+                }
+                hil::usb::InResult::Packet(len)
+            }
+            State::Pad if self.data_in.get() => {
+                let len = cmp::min(PACKET_SIZE, remaining);
+                for cell in packet.iter().take(len) {
+                    cell.set(0);
+                }
+                self.remaining.set((remaining - len) as u32);
+                if remaining == len {
+                    self.state.set(State::Status);
+                }
+                hil::usb::InResult::Packet(len)
+            }
+            State::Status => {
+                let residue = self.data_len.get() - self.transferred.get();
+                let fields = CSW_SIGNATURE
+                    .to_le_bytes()
+                    .into_iter()
+                    .chain(self.tag.get().to_le_bytes())
+                    .chain(residue.to_le_bytes())
+                    .chain([self.status.get()]);
+                for (cell, byte) in packet.iter().zip(fields) {
+                    cell.set(byte);
+                }
+                self.state.set(State::Command);
+                hil::usb::InResult::Packet(CSW_LEN)
+            }
+            _ => hil::usb::InResult::Delay,
+        }
+    }
+
+    /// Handle the OUT packets of the command and data phases.
+    fn packet_out_bulk(&self, packet_bytes: usize) -> hil::usb::OutResult {
+        match self.state.get() {
+            State::Command => {
+                if packet_bytes == CBW_LEN {
+                    self.command(&self.buffer(ENDPOINT_OUT_NUM)[..CBW_LEN]);
+                }
+                hil::usb::OutResult::Ok
+            }
+            State::Write { offset: None, .. } => {
+                // A page is being written. Keep the packet in the endpoint
+                // buffer until the write finishes.
+                self.delayed_out.set(packet_bytes);
+                hil::usb::OutResult::Delay
+            }
+            State::Write { .. } => {
+                self.receive_write_data(packet_bytes);
+                hil::usb::OutResult::Ok
+            }
+            State::Pad if !self.data_in.get() => {
+                let remaining = self.remaining.get() as usize;
+                let received = cmp::min(packet_bytes, remaining);
+                self.remaining.set((remaining - received) as u32);
+                if remaining == received {
+                    self.state.set(State::Status);
+                    self.controller().endpoint_resume_in(ENDPOINT_IN_NUM);
+                }
+                hil::usb::OutResult::Ok
+            }
+            _ => hil::usb::OutResult::Ok,
+        }
+    }
+}
+
+impl<'a, U: hil::usb::UsbController<'a>, F: hil::flash::Flash> hil::usb::Client<'a>
+    for MassStorage<'a, U, F>
+{
+    fn enable(&'a self) {
+        // Set up the default control endpoint
+        self.client_ctrl.enable();
+
+        // Setup buffers for IN and OUT data transfer.
+        self.controller()
+            .endpoint_set_in_buffer(ENDPOINT_IN_NUM, self.buffer(ENDPOINT_IN_NUM));
+        self.controller()
+            .endpoint_in_enable(TransferType::Bulk, ENDPOINT_IN_NUM);
+
+        self.controller()
+            .endpoint_set_out_buffer(ENDPOINT_OUT_NUM, self.buffer(ENDPOINT_OUT_NUM));
+        self.controller()
+            .endpoint_out_enable(TransferType::Bulk, ENDPOINT_OUT_NUM);
+    }
+
+    fn attach(&'a self) {
+        self.client_ctrl.attach();
+    }
+
+    fn bus_reset(&'a self) {
+        self.reset();
+    }
+
+    /// Handle a Control Setup transaction.
+    fn ctrl_setup(&'a self, endpoint: usize) -> hil::usb::CtrlSetupResult {
+        if let Some(setup_data) = descriptors::SetupData::get(&self.client_ctrl.ctrl_buffer.buf) {
+            if matches!(setup_data.request_type.request_type(), RequestType::Class)
+                && matches!(setup_data.request_type.recipient(), Recipient::Interface)
+            {
+                match setup_data.request_code {
+                    REQUEST_GET_MAX_LUN => {
+                        self.get_max_lun.set(true);
+                        return hil::usb::CtrlSetupResult::Ok;
+                    }
+                    REQUEST_RESET => self.reset(),
+                    _ => {}
+                }
+            }
+        }
+
+        self.client_ctrl.ctrl_setup(endpoint)
+    }
+
+    /// Handle a Control In transaction
+    fn ctrl_in(&'a self, endpoint: usize) -> hil::usb::CtrlInResult {
+        if self.get_max_lun.take() {
+            // There is a single logical unit, number 0.
+            self.client_ctrl.ctrl_buffer.buf[0].set(0);
+            hil::usb::CtrlInResult::Packet(1, true)
+        } else {
+            self.client_ctrl.ctrl_in(endpoint)
+        }
+    }
+
+    /// Handle a Control Out transaction
+    fn ctrl_out(&'a self, endpoint: usize, packet_bytes: u32) -> hil::usb::CtrlOutResult {
+        self.client_ctrl.ctrl_out(endpoint, packet_bytes)
+    }
+
+    fn ctrl_status(&'a self, endpoint: usize) {
+        self.client_ctrl.ctrl_status(endpoint)
+    }
+
+    /// Handle the completion of a Control transfer
+    fn ctrl_status_complete(&'a self, endpoint: usize) {
+        self.client_ctrl.ctrl_status_complete(endpoint)
+    }
+
+    /// Handle a Bulk/Interrupt IN transaction.
+    fn packet_in(&'a self, transfer_type: TransferType, _endpoint: usize) -> hil::usb::InResult {
+        match transfer_type {
+            TransferType::Bulk => self.packet_in_bulk(),
+            TransferType::Control | TransferType::Isochronous | TransferType::Interrupt => {
+                // Nothing to do for mass storage.
+                hil::usb::InResult::Delay
+            }
+        }
+    }
+
+    /// Handle a Bulk/Interrupt OUT transaction
+    fn packet_out(
+        &'a self,
+        transfer_type: TransferType,
+        _endpoint: usize,
+        packet_bytes: u32,
+    ) -> hil::usb::OutResult {
+        match transfer_type {
+            TransferType::Bulk => self.packet_out_bulk(packet_bytes as usize),
+            TransferType::Control | TransferType::Isochronous | TransferType::Interrupt => {
+                // Nothing to do for mass storage.
+                hil::usb::OutResult::Ok
+            }
+        }
+    }
+
+    fn packet_transmitted(&'a self, endpoint: usize) {
+        if endpoint != ENDPOINT_IN_NUM {
+            return;
+        }
+        // Check if more to send.
+        let more = match self.state.get() {
+            State::Response | State::Status => true,
+            State::Read { offset, .. } => offset.is_some(),
+            State::Pad => self.data_in.get(),
+            State::Command | State::Write { .. } => false,
+        };
+        if more {
+            self.controller().endpoint_resume_in(ENDPOINT_IN_NUM);
+        }
+    }
+}
+
+impl<'a, U: hil::usb::UsbController<'a>, F: hil::flash::Flash> hil::flash::Client<F>
+    for MassStorage<'a, U, F>
+{
+    fn read_complete(
+        &self,
+        read_buffer: &'static mut F::Page,
+        result: Result<(), hil::flash::Error>,
+    ) {
+        self.page_buffer.replace(read_buffer);
+        // The command may have been aborted by a reset.
+        if let State::Read {
+            page,
+            count,
+            offset: None,
+        } = self.state.get()
+        {
+            if result.is_ok() {
+                self.state.set(State::Read {
+                    page,
+                    count,
+                    offset: Some(0),
+                });
+            } else {
+                self.fail(Sense::UNRECOVERED_READ_ERROR);
+                self.state.set(State::Pad);
+            }
+            self.controller().endpoint_resume_in(ENDPOINT_IN_NUM);
+        }
+    }
+
+    fn write_complete(
+        &self,
+        write_buffer: &'static mut F::Page,
+        result: Result<(), hil::flash::Error>,
+    ) {
+        self.page_buffer.replace(write_buffer);
+        let State::Write {
+            page,
+            count,
+            offset: None,
+        } = self.state.get()
+        else {
+            return;
+        };
+        if result.is_err() {
+            self.write_failed();
+            return;
+        }
+        if self.remaining.get() == 0 {
+            self.state.set(State::Status);
+            self.controller().endpoint_resume_in(ENDPOINT_IN_NUM);
+        } else if count > 1 {
+            self.state.set(State::Write {
+                page: page + 1,
+                count: count - 1,
+                offset: Some(0),
+            });
+        } else {
+            // The host sends more data than the command covers.
+            self.state.set(State::Pad);
+        }
+        self.resume_delayed_out();
+    }
+
+    fn erase_complete(&self, _result: Result<(), hil::flash::Error>) {}
+}
@@ -6,6 +6,7 @@ pub mod cdc;
 pub mod ctap;
 pub mod descriptors;
 pub mod keyboard_hid;
+pub mod mass_storage;
 pub mod usb_user;
 pub mod usbc_client;
 pub mod usbc_client_ctrl;
@@ -0,0 +1,654 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Wear leveling for flash.
+//!
+//! `WearLevelingFlash` presents a region of a flash as a smaller flash with
+//! pages of the same size, and moves these logical pages between the pages
+//! of the region so that the region wears evenly, even if some logical pages
+//! are erased much more often than others. It can be placed between a flash
+//! and any user of the flash HIL, such as TicKV.
+//!
+//! The region holds `PAGES` data pages, and `METADATA_PAGES` pages at its
+//! start for the table of which logical page each data page holds and how
+//! often each data page was erased. One of the data pages is always spare,
+//! so `PAGES - 1` logical pages are available.
+//!
+//! Logical pages are erased in place, until their data page was erased
+//! `SWAP_THRESHOLD` times more often than the least erased data page. The
+//! logical page then trades places with the contents of that page: these are
+//! copied to the spare page, which was usually erased often itself, and the
+//! least erased page is erased and holds the logical page from then on. The
+//! table is written alternately to the two metadata pages after every step
+//! of a swap, so the metadata pages wear more slowly than the data pages,
+//! and a swap that is interrupted by a reset leaves the table consistent.
+//! Erase counts are only written with a swap, so erases since the last swap
+//! are not counted across resets.
+//!
+//! The region is formatted the first time it is used, which discards its
+//! contents.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let wear_leveling = static_init!(
+//!     capsules_extra::wear_leveling::WearLevelingFlash<'static, Mx25r6435f, 30>,
+//!     capsules_extra::wear_leveling::WearLevelingFlash::new(mx25r6435f, 0, page_buffer)
+//! );
+//! mx25r6435f.set_client(wear_leveling);
+//! wear_leveling.init()?;
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+/// Number of pages at the start of the region that hold the table.
+pub const METADATA_PAGES: usize = 2;
+
+/// Difference in erase counts at which a logical page is moved to the least
+/// erased data page.
+pub const SWAP_THRESHOLD: u32 = 32;
+
+/// Marks valid tables: "WLF1".
+const MAGIC: u32 = 0x574c_4631;
+/// Owner of the spare data page.
+const FREE: u16 = u16::MAX;
+/// Length of the table header: magic, sequence number and number of pages.
+const HEADER_LEN: usize = 10;
+/// Length of the table entry of a data page: its owner and erase count.
+const ENTRY_LEN: usize = 6;
+
+/// Step of the operation in progress.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    Uninitialized,
+    /// Reading the given metadata page.
+    Load(usize),
+    Idle,
+    Read,
+    Write,
+    /// Erasing a logical page in place.
+    Erase,
+    /// Erasing the spare page, as it is the least erased one, to hold the
+    /// logical page being erased.
+    EraseFree,
+    /// Steps of a swap: erasing the spare page, copying the least erased page
+    /// to it, and erasing the least erased page.
+    EraseSpare,
+    ReadCold,
+    WriteSpare,
+    EraseCold,
+    /// Writing the table to a metadata page, which is erased first.
+    EraseMetadata(Then),
+    WriteMetadata(Then),
+}
+
+/// What follows writing the table.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Then {
+    /// The region was formatted.
+    Ready,
+    /// The contents of the least erased page were moved.
+    EraseCold,
+    /// The erase of a logical page finished.
+    EraseDone,
+}
+
+/// Operation requested while the table was loading.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Pending {
+    Read(usize),
+    Write(usize),
+    Erase(usize),
+}
+
+pub struct WearLevelingFlash<'a, F: hil::flash::Flash + 'static, const PAGES: usize> {
+    flash: &'a F,
+    /// First page of the region.
+    start_page: usize,
+    client: OptionalCell<&'a dyn hil::flash::Client<WearLevelingFlash<'a, F, PAGES>>>,
+    state: Cell<State>,
+    ready: Cell<bool>,
+    /// Whether a valid table was found while loading.
+    found: Cell<bool>,
+    pending: Cell<Option<Pending>>,
+    client_buffer: TakeCell<'static, F::Page>,
+    /// Buffer for the table and for copying pages.
+    buffer: TakeCell<'static, F::Page>,
+
+    /// Logical page that each data page holds, or `FREE`.
+    owners: [Cell<u16>; PAGES],
+    erase_counts: [Cell<u32>; PAGES],
+    /// Sequence number of the table, and the metadata page it is in.
+    sequence: Cell<u32>,
+    metadata_page: Cell<usize>,
+
+    /// Logical page being erased, and the data pages of a swap.
+    logical: Cell<u16>,
+    hot: Cell<usize>,
+    cold: Cell<usize>,
+}
+
+impl<'a, F: hil::flash::Flash, const PAGES: usize> WearLevelingFlash<'a, F, PAGES> {
+    /// Use the `PAGES + METADATA_PAGES` pages of `flash` starting at page
+    /// `start_page`.
+    pub fn new(
+        flash: &'a F,
+        start_page: usize,
+        buffer: &'static mut F::Page,
+    ) -> WearLevelingFlash<'a, F, PAGES> {
+        WearLevelingFlash {
+            flash,
+            start_page,
+            client: OptionalCell::empty(),
+            state: Cell::new(State::Uninitialized),
+            ready: Cell::new(false),
+            found: Cell::new(false),
+            pending: Cell::new(None),
+            client_buffer: TakeCell::empty(),
+            buffer: TakeCell::new(buffer),
+            owners: [const { Cell::new(FREE) }; PAGES],
+            erase_counts: [const { Cell::new(0) }; PAGES],
+            sequence: Cell::new(0),
+            metadata_page: Cell::new(0),
+            logical: Cell::new(0),
+            hot: Cell::new(0),
+            cold: Cell::new(0),
+        }
+    }
+
+    /// Load the table, or format the region if it has none. Operations that
+    /// are requested before the table is loaded start once it is.
+    ///
+    /// Fails with `SIZE` if the table of `PAGES` pages does not fit into a
+    /// page.
+    pub fn init(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Uninitialized {
+            return Err(ErrorCode::ALREADY);
+        }
+        let buffer = self.buffer.take().ok_or(ErrorCode::FAIL)?;
+        if PAGES < 2 || PAGES >= FREE as usize || buffer.as_mut().len() < Self::table_len() {
+            self.buffer.replace(buffer);
+            return Err(ErrorCode::SIZE);
+        }
+        self.load(0, buffer)
+    }
+
+    /// Number of logical pages.
+    pub fn num_pages(&self) -> usize {
+        PAGES - 1
+    }
+
+    fn table_len() -> usize {
+        HEADER_LEN + ENTRY_LEN * PAGES + 4
+    }
+
+    fn data_page(&self, index: usize) -> usize {
+        self.start_page + METADATA_PAGES + index
+    }
+
+    fn lookup(&self, logical: usize) -> usize {
+        self.owners
+            .iter()
+            .position(|owner| owner.get() as usize == logical)
+            .unwrap_or(0)
+    }
+
+    fn spare(&self) -> usize {
+        self.owners
+            .iter()
+            .position(|owner| owner.get() == FREE)
+            .unwrap_or(0)
+    }
+
+    fn load(&self, metadata: usize, buffer: &'static mut F::Page) -> Result<(), ErrorCode> {
+        self.state.set(State::Load(metadata));
+        self.flash
+            .read_page(self.start_page + metadata, buffer)
+            .map_err(|(e, buffer)| {
+                self.buffer.replace(buffer);
+                self.state.set(State::Uninitialized);
+                e
+            })
+    }
+
+    /// Check the table in `buffer`, returning its sequence number if it is
+    /// valid.
+    fn check_table(buffer: &[u8]) -> Option<u32> {
+        let len = Self::table_len();
+        let read_u32 = |offset: usize| {
+            u32::from_le_bytes([
+                buffer[offset],
+                buffer[offset + 1],
+                buffer[offset + 2],
+                buffer[offset + 3],
+            ])
+        };
+        if read_u32(0) != MAGIC
+            || u16::from_le_bytes([buffer[8], buffer[9]]) as usize != PAGES
+            || read_u32(len - 4) != checksum(&buffer[..len - 4])
+        {
+            return None;
+        }
+        // Every logical page must be held by exactly one data page.
+        let owner = |index: usize| {
+            let offset = HEADER_LEN + ENTRY_LEN * index;
+            u16::from_le_bytes([buffer[offset], buffer[offset + 1]])
+        };
+        for logical in 0..PAGES as u16 - 1 {
+            if (0..PAGES).filter(|&i| owner(i) == logical).count() != 1 {
+                return None;
+            }
+        }
+        if (0..PAGES).filter(|&i| owner(i) == FREE).count() != 1 {
+            return None;
+        }
+        Some(read_u32(4))
+    }
+
+    fn read_table(&self, buffer: &[u8]) {
+        for (index, (owner, count)) in self.owners.iter().zip(&self.erase_counts).enumerate() {
+            let offset = HEADER_LEN + ENTRY_LEN * index;
+            owner.set(u16::from_le_bytes([buffer[offset], buffer[offset + 1]]));
+            count.set(u32::from_le_bytes([
+                buffer[offset + 2],
+                buffer[offset + 3],
+                buffer[offset + 4],
+                buffer[offset + 5],
+            ]));
+        }
+    }
+
+    fn write_table(&self, buffer: &mut [u8]) {
+        buffer[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+        buffer[4..8].copy_from_slice(&self.sequence.get().to_le_bytes());
+        buffer[8..10].copy_from_slice(&(PAGES as u16).to_le_bytes());
+        for (index, (owner, count)) in self.owners.iter().zip(&self.erase_counts).enumerate() {
+            let offset = HEADER_LEN + ENTRY_LEN * index;
+            buffer[offset..offset + 2].copy_from_slice(&owner.get().to_le_bytes());
+            buffer[offset + 2..offset + 6].copy_from_slice(&count.get().to_le_bytes());
+        }
+        let len = Self::table_len();
+        let sum = checksum(&buffer[..len - 4]);
+        buffer[len - 4..len].copy_from_slice(&sum.to_le_bytes());
+        buffer[len..].fill(0xFF);
+    }
+
+    /// Both metadata pages were read.
+    fn loaded(&self) {
+        if self.found.get() {
+            self.start_pending();
+            return;
+        }
+        // Neither page holds a table: format the region, with logical page
+        // `i` in data page `i` and the last data page spare.
+        for (index, (owner, count)) in self.owners.iter().zip(&self.erase_counts).enumerate() {
+            owner.set(if index == PAGES - 1 {
+                FREE
+            } else {
+                index as u16
+            });
+            count.set(0);
+        }
+        self.sequence.set(0);
+        self.metadata_page.set(METADATA_PAGES - 1);
+        if let Err(e) = self.persist(Then::Ready) {
+            self.finish(Err(e));
+        }
+    }
+
+    /// Write the table to the other metadata page.
+    fn persist(&self, then: Then) -> Result<(), ErrorCode> {
+        self.sequence.set(self.sequence.get().wrapping_add(1));
+        self.state.set(State::EraseMetadata(then));
+        let page = (self.metadata_page.get() + 1) % METADATA_PAGES;
+        self.flash.erase_page(self.start_page + page)
+    }
+
+    fn start_pending(&self) {
+        self.ready.set(true);
+        self.state.set(State::Idle);
+        let error = Err(hil::flash::Error::FlashError);
+        match self.pending.take() {
+            None => {}
+            Some(Pending::Erase(page)) => {
+                if self.start_erase(page).is_err() {
+                    self.client.map(|client| client.erase_complete(error));
+                }
+            }
+            Some(Pending::Read(page)) => {
+                if let Some(Err((_, buffer))) = self
+                    .client_buffer
+                    .take()
+                    .map(|buffer| self.start_read(page, buffer))
+                {
+                    self.client
+                        .map(move |client| client.read_complete(buffer, error));
+                }
+            }
+            Some(Pending::Write(page)) => {
+                if let Some(Err((_, buffer))) = self
+                    .client_buffer
+                    .take()
+                    .map(|buffer| self.start_write(page, buffer))
+                {
+                    self.client
+                        .map(move |client| client.write_complete(buffer, error));
+                }
+            }
+        }
+    }
+
+    fn start_read(
+        &self,
+        page: usize,
+        buffer: &'static mut F::Page,
+    ) -> Result<(), (ErrorCode, &'static mut F::Page)> {
+        self.state.set(State::Read);
+        self.flash
+            .read_page(self.data_page(self.lookup(page)), buffer)
+            .inspect_err(|_| self.state.set(State::Idle))
+    }
+
+    fn start_write(
+        &self,
+        page: usize,
+        buffer: &'static mut F::Page,
+    ) -> Result<(), (ErrorCode, &'static mut F::Page)> {
+        self.state.set(State::Write);
+        self.flash
+            .write_page(self.data_page(self.lookup(page)), buffer)
+            .inspect_err(|_| self.state.set(State::Idle))
+    }
+
+    fn start_erase(&self, page: usize) -> Result<(), ErrorCode> {
+        let hot = self.lookup(page);
+        let cold = (0..PAGES)
+            .filter(|&index| index != hot)
+            .min_by_key(|&index| self.erase_counts[index].get())
+            .unwrap_or(0);
+        self.logical.set(page as u16);
+        self.hot.set(hot);
+        self.cold.set(cold);
+
+        let (state, erase) = if self.erase_counts[hot].get()
+            < self.erase_counts[cold].get().saturating_add(SWAP_THRESHOLD)
+        {
+            (State::Erase, hot)
+        } else if self.owners[cold].get() == FREE {
+            (State::EraseFree, cold)
+        } else {
+            (State::EraseSpare, self.spare())
+        };
+        self.state.set(state);
+        self.flash
+            .erase_page(self.data_page(erase))
+            .inspect_err(|_| self.state.set(State::Idle))
+    }
+
+    fn count_erase(&self, index: usize) {
+        let count = &self.erase_counts[index];
+        count.set(count.get().saturating_add(1));
+    }
+
+    /// Finish the erase of a logical page, or the formatting of the region.
+    fn finish(&self, result: Result<(), ErrorCode>) {
+        if !self.ready.get() {
+            // The region can be used even if the table could not be
+            // written.
+            self.start_pending();
+            return;
+        }
+        self.state.set(State::Idle);
+        let result = result.or(Err(hil::flash::Error::FlashError));
+        self.client.map(|client| client.erase_complete(result));
+    }
+}
+
+/// Checksum of the table.
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |sum: u32, byte| {
+        sum.wrapping_mul(31).wrapping_add(*byte as u32)
+    })
+}
+
+impl<'a, F: hil::flash::Flash, C: hil::flash::Client<Self>, const PAGES: usize>
+    hil::flash::HasClient<'a, C> for WearLevelingFlash<'a, F, PAGES>
+{
+    fn set_client(&'a self, client: &'a C) {
+        self.client.set(client);
+    }
+}
+
+impl<F: hil::flash::Flash, const PAGES: usize> hil::flash::Flash
+    for WearLevelingFlash<'_, F, PAGES>
+{
+    type Page = F::Page;
+
+    fn read_page(
+        &self,
+        page_number: usize,
+        buf: &'static mut Self::Page,
+    ) -> Result<(), (ErrorCode, &'static mut Self::Page)> {
+        if page_number >= PAGES - 1 {
+            Err((ErrorCode::INVAL, buf))
+        } else if !self.ready.get() && self.pending.get().is_none() {
+            self.client_buffer.replace(buf);
+            self.pending.set(Some(Pending::Read(page_number)));
+            Ok(())
+        } else if self.state.get() != State::Idle {
+            Err((ErrorCode::BUSY, buf))
+        } else {
+            self.start_read(page_number, buf)
+        }
+    }
+
+    fn write_page(
+        &self,
+        page_number: usize,
+        buf: &'static mut Self::Page,
+    ) -> Result<(), (ErrorCode, &'static mut Self::Page)> {
+        if page_number >= PAGES - 1 {
+            Err((ErrorCode::INVAL, buf))
+        } else if !self.ready.get() && self.pending.get().is_none() {
+            self.client_buffer.replace(buf);
+            self.pending.set(Some(Pending::Write(page_number)));
+            Ok(())
+        } else if self.state.get() != State::Idle {
+            Err((ErrorCode::BUSY, buf))
+        } else {
+            self.start_write(page_number, buf)
+        }
+    }
+
+    fn erase_page(&self, page_number: usize) -> Result<(), ErrorCode> {
+        if page_number >= PAGES - 1 {
+            Err(ErrorCode::INVAL)
+        } else if !self.ready.get() && self.pending.get().is_none() {
+            self.pending.set(Some(Pending::Erase(page_number)));
+            Ok(())
+        } else if self.state.get() != State::Idle {
+            Err(ErrorCode::BUSY)
+        } else {
+            self.start_erase(page_number)
+        }
+    }
+}
+
+impl<F: hil::flash::Flash, const PAGES: usize> hil::flash::Client<F>
+    for WearLevelingFlash<'_, F, PAGES>
+{
+    fn read_complete(
+        &self,
+        read_buffer: &'static mut F::Page,
+        result: Result<(), hil::flash::Error>,
+    ) {
+        match self.state.get() {
+            State::Read => {
+                self.state.set(State::Idle);
+                self.client
+                    .map(move |client| client.read_complete(read_buffer, result));
+            }
+            State::Load(metadata) => {
+                if result.is_ok() {
+                    if let Some(sequence) = Self::check_table(read_buffer.as_mut()) {
+                        // Use the newer of the two tables.
+                        if !self.found.get()
+                            || sequence.wrapping_sub(self.sequence.get()) < u32::MAX / 2
+                        {
+                            self.read_table(read_buffer.as_mut());
+                            self.sequence.set(sequence);
+                            self.metadata_page.set(metadata);
+                            self.found.set(true);
+                        }
+                    }
+                }
+                if metadata + 1 < METADATA_PAGES {
+                    if self.load(metadata + 1, read_buffer).is_err() {
+                        self.loaded();
+                    }
+                } else {
+                    self.buffer.replace(read_buffer);
+                    self.loaded();
+                }
+            }
+            State::ReadCold => {
+                if let Err(e) = result {
+                    self.buffer.replace(read_buffer);
+                    self.state.set(State::Idle);
+                    self.client.map(|client| client.erase_complete(Err(e)));
+                    return;
+                }
+                self.state.set(State::WriteSpare);
+                if let Err((e, buffer)) = self
+                    .flash
+                    .write_page(self.data_page(self.spare()), read_buffer)
+                {
+                    self.buffer.replace(buffer);
+                    self.finish(Err(e));
+                }
+            }
+            _ => {
+                self.buffer.replace(read_buffer);
+            }
+        }
+    }
+
+    fn write_complete(
+        &self,
+        write_buffer: &'static mut F::Page,
+        result: Result<(), hil::flash::Error>,
+    ) {
+        match self.state.get() {
+            State::Write => {
+                self.state.set(State::Idle);
+                self.client
+                    .map(move |client| client.write_complete(write_buffer, result));
+            }
+            State::WriteSpare => {
+                self.buffer.replace(write_buffer);
+                if result.is_err() {
+                    self.finish(Err(ErrorCode::FAIL));
+                    return;
+                }
+                // The contents of the least erased page are in the spare
+                // page now, which frees the least erased page.
+                let spare = self.spare();
+                let cold = self.cold.get();
+                self.owners[spare].set(self.owners[cold].get());
+                self.owners[cold].set(FREE);
+                if let Err(e) = self.persist(Then::EraseCold) {
+                    self.finish(Err(e));
+                }
+            }
+            State::WriteMetadata(then) => {
+                self.buffer.replace(write_buffer);
+                if result.is_err() {
+                    self.finish(Err(ErrorCode::FAIL));
+                    return;
+                }
+                self.metadata_page
+                    .set((self.metadata_page.get() + 1) % METADATA_PAGES);
+                match then {
+                    Then::Ready => self.start_pending(),
+                    Then::EraseCold => {
+                        self.state.set(State::EraseCold);
+                        if let Err(e) = self.flash.erase_page(self.data_page(self.cold.get())) {
+                            self.finish(Err(e));
+                        }
+                    }
+                    Then::EraseDone => self.finish(Ok(())),
+                }
+            }
+            _ => {
+                self.buffer.replace(write_buffer);
+            }
+        }
+    }
+
+    fn erase_complete(&self, result: Result<(), hil::flash::Error>) {
+        let state = self.state.get();
+        if let State::Erase = state {
+            if result.is_ok() {
+                self.count_erase(self.hot.get());
+            }
+            self.state.set(State::Idle);
+            self.client.map(|client| client.erase_complete(result));
+            return;
+        }
+        if result.is_err() {
+            self.finish(Err(ErrorCode::FAIL));
+            return;
+        }
+        let next = match state {
+            State::EraseFree | State::EraseCold => {
+                // The least erased page holds the logical page from now on,
+                // and its old data page becomes the spare page.
+                let cold = self.cold.get();
+                self.count_erase(cold);
+                self.owners[cold].set(self.logical.get());
+                self.owners[self.hot.get()].set(FREE);
+                self.persist(Then::EraseDone)
+            }
+            State::EraseSpare => {
+                self.count_erase(self.spare());
+                self.state.set(State::ReadCold);
+                match self.buffer.take() {
+                    Some(buffer) => self
+                        .flash
+                        .read_page(self.data_page(self.cold.get()), buffer)
+                        .map_err(|(e, buffer)| {
+                            self.buffer.replace(buffer);
+                            e
+                        }),
+                    None => Err(ErrorCode::NOMEM),
+                }
+            }
+            State::EraseMetadata(then) => {
+                self.state.set(State::WriteMetadata(then));
+                match self.buffer.take() {
+                    Some(buffer) => {
+                        self.write_table(buffer.as_mut());
+                        let page = (self.metadata_page.get() + 1) % METADATA_PAGES;
+                        self.flash
+                            .write_page(self.start_page + page, buffer)
+                            .map_err(|(e, buffer)| {
+                                self.buffer.replace(buffer);
+                                e
+                            })
+                    }
+                    None => Err(ErrorCode::NOMEM),
+                }
+            }
+            _ => Ok(()),
+        };
+        if let Err(e) = next {
+            self.finish(Err(e));
+        }
+    }
+}
@@ -0,0 +1,141 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! System call filter that allows drivers to applications by `ShortId`.
+//!
+//! Boards list which drivers the applications with a fixed `ShortId` may use.
+//! An application listed may only call `Command` and `Subscribe` on the
+//! drivers listed for it whose permission is granted, and other calls fail
+//! with `ErrorCode::NODEVICE`. Permissions can be granted and revoked at
+//! runtime. Allow calls are passed through so that processes can retrieve
+//! their buffers.
+//!
+//! Applications that are not listed, and all system calls that are not
+//! rejected, are checked by an inner filter, such as
+//! [`TbfHeaderFilterDefaultAllow`](kernel::platform::TbfHeaderFilterDefaultAllow)
+//! which applies the permissions in the TBF header of the application.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let permissions = static_init!(
+//!     [AppDriverPermission; 2],
+//!     [
+//!         AppDriverPermission::new(0x1234, capsules_core::led::DRIVER_NUM, true),
+//!         AppDriverPermission::new(0x1234, capsules_core::button::DRIVER_NUM, false),
+//!     ]
+//! );
+//! let filter = static_init!(
+//!     AppDriverPermissions<'static, TbfHeaderFilterDefaultAllow>,
+//!     AppDriverPermissions::new(permissions, TbfHeaderFilterDefaultAllow {})
+//! );
+//! // Later, let the application use the buttons.
+//! filter.set_granted(0x1234, capsules_core::button::DRIVER_NUM, true)?;
+//! ```
+
+use core::cell::Cell;
+
+use kernel::platform::SyscallFilter;
+use kernel::process::{self, ShortId};
+use kernel::syscall;
+use kernel::ErrorCode;
+
+/// Permission of the application with a `ShortId` to use a driver.
+pub struct AppDriverPermission {
+    short_id: u32,
+    driver_num: usize,
+    granted: Cell<bool>,
+}
+
+impl AppDriverPermission {
+    /// Permission of the application with `ShortId` `short_id` to use the
+    /// driver `driver_num`, which is `granted` at first.
+    pub const fn new(short_id: u32, driver_num: usize, granted: bool) -> AppDriverPermission {
+        AppDriverPermission {
+            short_id,
+            driver_num,
+            granted: Cell::new(granted),
+        }
+    }
+}
+
+/// Syscall filter that rejects calls to the drivers an application listed
+/// is not allowed to use.
+pub struct AppDriverPermissions<'a, F: SyscallFilter> {
+    permissions: &'a [AppDriverPermission],
+    filter: F,
+}
+
+impl<'a, F: SyscallFilter> AppDriverPermissions<'a, F> {
+    pub fn new(permissions: &'a [AppDriverPermission], filter: F) -> AppDriverPermissions<'a, F> {
+        AppDriverPermissions {
+            permissions,
+            filter,
+        }
+    }
+
+    /// Grant or revoke the permission of the application with `ShortId`
+    /// `short_id` to use the driver `driver_num`.
+    ///
+    /// Returns `INVAL` if the permission is not listed.
+    pub fn set_granted(
+        &self,
+        short_id: u32,
+        driver_num: usize,
+        granted: bool,
+    ) -> Result<(), ErrorCode> {
+        self.permissions
+            .iter()
+            .find(|permission| {
+                permission.short_id == short_id && permission.driver_num == driver_num
+            })
+            .map(|permission| permission.granted.set(granted))
+            .ok_or(ErrorCode::INVAL)
+    }
+
+    /// Whether the application with `short_id` may use `driver_num`, or
+    /// `None` if the application is not listed.
+    pub fn is_granted(&self, short_id: ShortId, driver_num: usize) -> Option<bool> {
+        let ShortId::Fixed(id) = short_id else {
+            return None;
+        };
+        let mut listed = false;
+        for permission in self
+            .permissions
+            .iter()
+            .filter(|permission| permission.short_id == id.get())
+        {
+            if permission.driver_num == driver_num {
+                return Some(permission.granted.get());
+            }
+            listed = true;
+        }
+        if listed {
+            Some(false)
+        } else {
+            None
+        }
+    }
+}
+
+impl<F: SyscallFilter> SyscallFilter for AppDriverPermissions<'_, F> {
+    fn filter_syscall(
+        &self,
+        process: &dyn process::Process,
+        syscall: &syscall::Syscall,
+    ) -> Result<(), ErrorCode> {
+        let driver_number = match syscall {
+            syscall::Syscall::Command { driver_number, .. }
+            | syscall::Syscall::Subscribe { driver_number, .. } => Some(*driver_number),
+            _ => None,
+        };
+        if let Some(driver_number) = driver_number {
+            if self.is_granted(process.short_app_id(), driver_number) == Some(false) {
+                return Err(ErrorCode::NODEVICE);
+            }
+        }
+        self.filter.filter_syscall(process, syscall)
+    }
+}
@@ -0,0 +1,132 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Boot time instrumentation.
+//!
+//! `BootTimer` measures how long each stage of a board's `start()` takes
+//! with a cycle counter, so the numbers do not depend on an alarm that may
+//! not run yet and are the same from one boot to the next. Boards wrap the
+//! `finalize()` calls they want to measure in [`BootTimer::measure`], or
+//! call [`BootTimer::mark`] at the end of a stage, and print the report
+//! before entering the main loop. When the board gives a budget, the report
+//! says whether boot stayed within it.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let boot = components::boot_timing::BootTimerComponent::new(dwt, 64_000_000, Some(50_000))
+//!     .finalize(components::boot_timer_component_static!(cortexm4::dwt::Dwt, 16));
+//!
+//! let console = boot.measure("console", || {
+//!     components::console::ConsoleComponent::new(board_kernel, DRIVER_NUM, uart_mux)
+//!         .finalize(components::console_component_static!())
+//! });
+//! // ...
+//! boot.report();
+//! ```
+
+use core::cell::Cell;
+
+use kernel::debug;
+use kernel::hil::hw_debug::CycleCounter;
+
+pub struct BootTimer<'a, C: CycleCounter, const N: usize> {
+    counter: &'a C,
+    /// Frequency of the cycle counter.
+    frequency_hz: u32,
+    /// Maximum boot time, in microseconds.
+    budget_us: Option<u32>,
+    /// Cycle count at the end of the last stage.
+    last: Cell<u64>,
+    /// Name and duration in cycles of each stage.
+    stages: [Cell<Option<(&'static str, u64)>>; N],
+    /// Number of stages recorded, including those that did not fit.
+    count: Cell<usize>,
+}
+
+impl<'a, C: CycleCounter, const N: usize> BootTimer<'a, C, N> {
+    /// Start `counter` and measure from now on.
+    pub fn new(counter: &'a C, frequency_hz: u32, budget_us: Option<u32>) -> Self {
+        counter.reset();
+        counter.start();
+        Self {
+            counter,
+            frequency_hz,
+            budget_us,
+            last: Cell::new(0),
+            stages: [const { Cell::new(None) }; N],
+            count: Cell::new(0),
+        }
+    }
+
+    fn cycles_to_us(&self, cycles: u64) -> u64 {
+        cycles * 1_000_000 / u64::from(self.frequency_hz.max(1))
+    }
+
+    fn record(&self, name: &'static str, cycles: u64) {
+        let index = self.count.get();
+        if let Some(stage) = self.stages.get(index) {
+            stage.set(Some((name, cycles)));
+        }
+        self.count.set(index + 1);
+    }
+
+    /// End the stage that started at the end of the last one, naming it
+    /// `name`.
+    pub fn mark(&self, name: &'static str) {
+        let now = self.counter.count();
+        self.record(name, now.wrapping_sub(self.last.get()));
+        self.last.set(now);
+    }
+
+    /// Run `f` as a stage named `name`. Time between stages is only counted
+    /// in the total.
+    pub fn measure<R>(&self, name: &'static str, f: impl FnOnce() -> R) -> R {
+        let start = self.counter.count();
+        let output = f();
+        let now = self.counter.count();
+        self.record(name, now.wrapping_sub(start));
+        self.last.set(now);
+        output
+    }
+
+    /// Time since the timer was created, in microseconds.
+    pub fn elapsed_us(&self) -> u64 {
+        self.cycles_to_us(self.counter.count())
+    }
+
+    /// Iterate over the name and duration in microseconds of each stage.
+    pub fn stages(&self) -> impl Iterator<Item = (&'static str, u64)> + '_ {
+        self.stages
+            .iter()
+            .filter_map(|stage| stage.get())
+            .map(|(name, cycles)| (name, self.cycles_to_us(cycles)))
+    }
+
+    /// Whether boot took longer than the budget so far.
+    pub fn over_budget(&self) -> bool {
+        self.budget_us
+            .is_some_and(|budget| self.elapsed_us() > u64::from(budget))
+    }
+
+    /// Print the duration of each stage and the total with `debug!()`.
+    pub fn report(&self) {
+        for (name, us) in self.stages() {
+            debug!("boot: {:<24} {:>8} us", name, us);
+        }
+        let dropped = self.count.get().saturating_sub(N);
+        if dropped > 0 {
+            debug!("boot: {} more stages not recorded", dropped);
+        }
+        let total = self.elapsed_us();
+        match self.budget_us {
+            Some(budget) if total > u64::from(budget) => {
+                debug!("boot: total {} us, over budget of {} us", total, budget)
+            }
+            Some(budget) => debug!("boot: total {} us, budget {} us", total, budget),
+            None => debug!("boot: total {} us", total),
+        }
+    }
+}
@@ -0,0 +1,80 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Initialization deferred until after the kernel loop starts.
+//!
+//! Some initialization is slow but not needed to start processes, for
+//! example probing an external flash or calibrating a radio or ADC. Instead
+//! of running it in `start()`, boards hand it to `DeferredInit`, which runs
+//! one task each time its alarm fires once the kernel loop is running. The
+//! loop, and so processes, run between the tasks.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let deferred_init = components::deferred_init::DeferredInitComponent::new(
+//!     mux_alarm,
+//!     static_init!([&'static dyn DeferredInitClient; 2], [adc_calibration, flash_probe]),
+//!     10,
+//! )
+//! .finalize(components::deferred_init_component_static!(nrf52840::rtc::Rtc));
+//! deferred_init.start();
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks};
+
+/// Initialization run by [`DeferredInit`].
+pub trait DeferredInitClient {
+    /// Run the initialization. Longer operations should start here and
+    /// finish asynchronously.
+    fn deferred_init(&self);
+}
+
+pub struct DeferredInit<'a, A: Alarm<'a>> {
+    alarm: &'a A,
+    tasks: &'a [&'a dyn DeferredInitClient],
+    /// Time between the start of the loop and the first task, and between
+    /// tasks.
+    interval_ms: u32,
+    /// Index of the next task to run.
+    next: Cell<usize>,
+}
+
+impl<'a, A: Alarm<'a>> DeferredInit<'a, A> {
+    pub fn new(alarm: &'a A, tasks: &'a [&'a dyn DeferredInitClient], interval_ms: u32) -> Self {
+        Self {
+            alarm,
+            tasks,
+            interval_ms,
+            next: Cell::new(0),
+        }
+    }
+
+    /// Run the tasks once the kernel loop runs. Call before entering it.
+    pub fn start(&self) {
+        if self.next.get() < self.tasks.len() {
+            self.alarm
+                .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(self.interval_ms));
+        }
+    }
+
+    /// Whether all tasks ran.
+    pub fn is_done(&self) -> bool {
+        self.next.get() >= self.tasks.len()
+    }
+}
+
+impl<'a, A: Alarm<'a>> AlarmClient for DeferredInit<'a, A> {
+    fn alarm(&self) {
+        let index = self.next.get();
+        if let Some(task) = self.tasks.get(index) {
+            self.next.set(index + 1);
+            task.deferred_init();
+            self.start();
+        }
+    }
+}
@@ -0,0 +1,169 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Boot-time integrity check of the kernel image.
+//!
+//! The kernel attributes reserve space for a SHA-256 hash of the kernel code
+//! and read-only data (see
+//! [`KERNEL_HASH`](kernel::platform::attributes::KERNEL_HASH)), which the
+//! flashing tool writes. `KernelIntegrityChecker` hashes the kernel image with
+//! a digest engine and compares the result with the stored hash. This detects
+//! a corrupted or partially written kernel on boards without secure boot; it
+//! is not a defense against an attacker who can also rewrite the hash.
+//!
+//! A mismatch is recorded with the board's
+//! [`SelfTestLog`](kernel::platform::self_test::SelfTestLog), and the status
+//! is available through
+//! [`KernelIntegrity`](kernel::platform::self_test::KernelIntegrity), which
+//! the process console shows with the `kernel` command. The board decides what
+//! to do about a mismatch.
+
+use core::cell::Cell;
+
+use kernel::hil::digest::{ClientData, ClientVerify, DigestDataVerify, Sha256};
+use kernel::platform::attributes::{Attributes, KERNEL_HASH};
+use kernel::platform::self_test::{
+    KernelIntegrity, KernelIntegrityStatus, SelfTestLog, SelfTestPolicy,
+};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::{SubSlice, SubSliceMut};
+use kernel::ErrorCode;
+
+use crate::deferred_init::DeferredInitClient;
+
+/// Name of the check in the self-test log.
+const NAME: &str = "kernel-integrity";
+
+pub struct KernelIntegrityChecker<'a, D: DigestDataVerify<'a, 32> + Sha256> {
+    digest: &'a D,
+    /// The kernel code and read-only data.
+    image: &'static [u8],
+    /// The kernel attributes region.
+    attributes: &'static [u8],
+    /// Buffer holding the expected hash during verification.
+    compare: TakeCell<'static, [u8; 32]>,
+    status: Cell<KernelIntegrityStatus>,
+    log: OptionalCell<&'a dyn SelfTestLog>,
+}
+
+impl<'a, D: DigestDataVerify<'a, 32> + Sha256> KernelIntegrityChecker<'a, D> {
+    pub fn new(
+        digest: &'a D,
+        image: &'static [u8],
+        attributes: &'static [u8],
+        compare: &'static mut [u8; 32],
+    ) -> Self {
+        Self {
+            digest,
+            image,
+            attributes,
+            compare: TakeCell::new(compare),
+            status: Cell::new(KernelIntegrityStatus::Unchecked),
+            log: OptionalCell::empty(),
+        }
+    }
+
+    /// Set the log the result of the check is recorded with.
+    pub fn set_log(&self, log: &'a dyn SelfTestLog) {
+        self.log.set(log);
+    }
+
+    /// The hash written when the kernel was flashed, if any.
+    fn stored_hash(&self) -> Option<&'static [u8]> {
+        Attributes::new(self.attributes)?
+            .find(|attribute| attribute.attribute_type == KERNEL_HASH)
+            .map(|attribute| attribute.value)
+            .filter(|hash| hash.len() == 32 && hash.iter().any(|b| *b != 0xff))
+    }
+
+    /// Start hashing the kernel image.
+    ///
+    /// Returns `Err(ErrorCode::INVAL)` if no hash is stored in the kernel
+    /// attributes, and `Err(ErrorCode::BUSY)` if the check is running.
+    pub fn start(&self) -> Result<(), ErrorCode> {
+        if self.status.get() == KernelIntegrityStatus::Checking {
+            return Err(ErrorCode::BUSY);
+        }
+        let expected = match self.stored_hash() {
+            Some(expected) => expected,
+            None => {
+                self.status.set(KernelIntegrityStatus::NotProvisioned);
+                return Err(ErrorCode::INVAL);
+            }
+        };
+        self.compare
+            .map(|compare| compare.copy_from_slice(expected))
+            .ok_or(ErrorCode::BUSY)?;
+
+        let result = self.digest.set_mode_sha256().and_then(|()| {
+            self.digest
+                .add_data(SubSlice::new(self.image))
+                .map_err(|(err, _)| err)
+        });
+        match result {
+            Ok(()) => self.status.set(KernelIntegrityStatus::Checking),
+            Err(err) => self.finish(KernelIntegrityStatus::Error(err)),
+        }
+        result
+    }
+
+    fn finish(&self, status: KernelIntegrityStatus) {
+        self.status.set(status);
+        let result = match status {
+            KernelIntegrityStatus::Verified => Ok(()),
+            KernelIntegrityStatus::Error(err) => Err(err),
+            _ => Err(ErrorCode::FAIL),
+        };
+        self.log
+            .map(|log| log.record(NAME, SelfTestPolicy::Continue, result));
+    }
+}
+
+impl<'a, D: DigestDataVerify<'a, 32> + Sha256> ClientData<32> for KernelIntegrityChecker<'a, D> {
+    fn add_data_done(&self, result: Result<(), ErrorCode>, _data: SubSlice<'static, u8>) {
+        let result = result.and_then(|()| {
+            let compare = self.compare.take().ok_or(ErrorCode::FAIL)?;
+            self.digest.verify(compare).map_err(|(err, compare)| {
+                self.compare.replace(compare);
+                err
+            })
+        });
+        if let Err(err) = result {
+            self.finish(KernelIntegrityStatus::Error(err));
+        }
+    }
+
+    fn add_mut_data_done(&self, _result: Result<(), ErrorCode>, _data: SubSliceMut<'static, u8>) {}
+}
+
+impl<'a, D: DigestDataVerify<'a, 32> + Sha256> ClientVerify<32> for KernelIntegrityChecker<'a, D> {
+    fn verification_done(&self, result: Result<bool, ErrorCode>, compare: &'static mut [u8; 32]) {
+        self.compare.replace(compare);
+        self.finish(match result {
+            Ok(true) => KernelIntegrityStatus::Verified,
+            Ok(false) => KernelIntegrityStatus::Mismatch,
+            Err(err) => KernelIntegrityStatus::Error(err),
+        });
+    }
+}
+
+impl<'a, D: DigestDataVerify<'a, 32> + Sha256> KernelIntegrity for KernelIntegrityChecker<'a, D> {
+    fn integrity_status(&self) -> KernelIntegrityStatus {
+        self.status.get()
+    }
+
+    fn kernel_hash(&self) -> Option<&'static [u8]> {
+        self.stored_hash()
+    }
+}
+
+/// The check can be started after the kernel loop starts, so hashing the
+/// kernel does not delay the first processes.
+impl<'a, D: DigestDataVerify<'a, 32> + Sha256> DeferredInitClient
+    for KernelIntegrityChecker<'a, D>
+{
+    fn deferred_init(&self) {
+        let _ = self.start();
+    }
+}
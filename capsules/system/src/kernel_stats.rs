@@ -0,0 +1,204 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Uptime, load averages, and event rates computed from kernel counters.
+//!
+//! `KernelStats` samples the kernel's main loop counters, the number of
+//! runnable processes, and optionally per source interrupt counts once per
+//! second. From these it keeps exponentially decaying averages over one, five
+//! and fifteen minutes, in the same way as the load average on Unix systems.
+//!
+//! The statistics are exposed through the
+//! [`KernelStatistics`](kernel::platform::stats::KernelStatistics) trait, for
+//! example to the `stats` command of the process console.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let stats_alarm = static_init!(VirtualMuxAlarm<'static, Rtc>, VirtualMuxAlarm::new(mux_alarm));
+//! stats_alarm.setup();
+//! let stats = static_init!(
+//!     KernelStats<'static, VirtualMuxAlarm<'static, Rtc>, Capability, 48>,
+//!     KernelStats::new(board_kernel, stats_alarm, Some(interrupt_counter), capability)
+//! );
+//! stats_alarm.set_alarm_client(stats);
+//! stats.start();
+//! ```
+
+use core::cell::Cell;
+
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks};
+use kernel::platform::stats::{InterruptCounts, KernelLoopCounters, KernelStatistics};
+use kernel::Kernel;
+
+/// Number of fractional bits of the fixed point averages.
+const FSHIFT: u32 = 11;
+/// 1.0 in fixed point.
+const FIXED_1: u64 = 1 << FSHIFT;
+/// Decay factors for one sample per second and windows of 1, 5 and 15
+/// minutes, i.e. `FIXED_1 / exp(1 / window_seconds)`.
+const EXP: [u64; 3] = [2014, 2041, 2046];
+
+/// Update the fixed point average `average` with a new `sample`.
+fn decay(average: u64, exp: u64, sample: u64) -> u64 {
+    (average * exp + sample * FIXED_1 * (FIXED_1 - exp)) >> FSHIFT
+}
+
+/// Convert a fixed point average to a value scaled by 100.
+fn to_hundredths(average: u64) -> u32 {
+    ((average * 100) >> FSHIFT) as u32
+}
+
+pub struct KernelStats<
+    'a,
+    A: Alarm<'a>,
+    C: ProcessManagementCapability,
+    const NUM_INTERRUPTS: usize,
+> {
+    kernel: &'static Kernel,
+    alarm: &'a A,
+    interrupts: Option<&'a dyn InterruptCounts>,
+    /// This capsule needs to check which processes are runnable.
+    capability: C,
+    /// Number of samples taken, one per second.
+    uptime: Cell<u32>,
+    /// Loop counters at the last sample.
+    last_counters: Cell<KernelLoopCounters>,
+    /// Context switches during the last sample period.
+    context_switch_rate: Cell<u32>,
+    context_switch_averages: [Cell<u64>; 3],
+    load_averages: [Cell<u64>; 3],
+    /// Interrupt counts at the last sample.
+    last_interrupts: [Cell<u32>; NUM_INTERRUPTS],
+    /// Interrupts serviced during the last sample period.
+    interrupt_rates: [Cell<u32>; NUM_INTERRUPTS],
+}
+
+impl<'a, A: Alarm<'a>, C: ProcessManagementCapability, const NUM_INTERRUPTS: usize>
+    KernelStats<'a, A, C, NUM_INTERRUPTS>
+{
+    pub fn new(
+        kernel: &'static Kernel,
+        alarm: &'a A,
+        interrupts: Option<&'a dyn InterruptCounts>,
+        capability: C,
+    ) -> Self {
+        Self {
+            kernel,
+            alarm,
+            interrupts,
+            capability,
+            uptime: Cell::new(0),
+            last_counters: Cell::new(KernelLoopCounters::default()),
+            context_switch_rate: Cell::new(0),
+            context_switch_averages: [const { Cell::new(0) }; 3],
+            load_averages: [const { Cell::new(0) }; 3],
+            last_interrupts: [const { Cell::new(0) }; NUM_INTERRUPTS],
+            interrupt_rates: [const { Cell::new(0) }; NUM_INTERRUPTS],
+        }
+    }
+
+    /// Start sampling the kernel counters.
+    pub fn start(&self) {
+        self.last_counters.set(self.kernel.loop_counters());
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_seconds(1));
+    }
+
+    fn sample(&self) {
+        let counters = self.kernel.loop_counters();
+        let context_switches = counters
+            .context_switches
+            .wrapping_sub(self.last_counters.get().context_switches);
+        self.last_counters.set(counters);
+        self.context_switch_rate.set(context_switches);
+
+        let mut runnable = 0;
+        self.kernel
+            .process_each_capability(&self.capability, |process| {
+                if process.ready() {
+                    runnable += 1;
+                }
+            });
+
+        for (i, exp) in EXP.iter().enumerate() {
+            let average = &self.context_switch_averages[i];
+            average.set(decay(average.get(), *exp, context_switches as u64));
+            let average = &self.load_averages[i];
+            average.set(decay(average.get(), *exp, runnable));
+        }
+
+        self.interrupts.map(|interrupts| {
+            for (source, (last, rate)) in self
+                .last_interrupts
+                .iter()
+                .zip(self.interrupt_rates.iter())
+                .enumerate()
+            {
+                let count = interrupts.count(source).unwrap_or(0);
+                rate.set(count.wrapping_sub(last.get()));
+                last.set(count);
+            }
+        });
+
+        self.uptime.set(self.uptime.get() + 1);
+    }
+}
+
+impl<'a, A: Alarm<'a>, C: ProcessManagementCapability, const NUM_INTERRUPTS: usize> AlarmClient
+    for KernelStats<'a, A, C, NUM_INTERRUPTS>
+{
+    fn alarm(&self) {
+        self.sample();
+        // Re-arm relative to the previous alarm so samples do not drift.
+        self.alarm
+            .set_alarm(self.alarm.get_alarm(), self.alarm.ticks_from_seconds(1));
+    }
+}
+
+impl<'a, A: Alarm<'a>, C: ProcessManagementCapability, const NUM_INTERRUPTS: usize> KernelStatistics
+    for KernelStats<'a, A, C, NUM_INTERRUPTS>
+{
+    fn uptime_seconds(&self) -> u32 {
+        self.uptime.get()
+    }
+
+    fn loop_counters(&self) -> KernelLoopCounters {
+        self.kernel.loop_counters()
+    }
+
+    fn context_switch_rate(&self) -> u32 {
+        self.context_switch_rate.get()
+    }
+
+    fn context_switch_rate_averages(&self) -> [u32; 3] {
+        self.context_switch_averages
+            .each_ref()
+            .map(|average| to_hundredths(average.get()))
+    }
+
+    fn load_averages(&self) -> [u32; 3] {
+        self.load_averages
+            .each_ref()
+            .map(|average| to_hundredths(average.get()))
+    }
+
+    fn interrupt_sources(&self) -> usize {
+        self.interrupts
+            .map_or(0, |interrupts| interrupts.num_sources())
+            .min(NUM_INTERRUPTS)
+    }
+
+    fn interrupt_count(&self, source: usize) -> Option<(u32, u32)> {
+        if source >= self.interrupt_sources() {
+            return None;
+        }
+        let total = self
+            .interrupts
+            .and_then(|interrupts| interrupts.count(source))?;
+        Some((total, self.interrupt_rates[source].get()))
+    }
+}
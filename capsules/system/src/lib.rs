@@ -5,7 +5,16 @@
 #![forbid(unsafe_code)]
 #![no_std]
 
+pub mod app_driver_permissions;
+pub mod boot_timing;
+pub mod deferred_init;
+pub mod kernel_integrity;
+pub mod kernel_stats;
+pub mod persistent_counters;
 pub mod process_checker;
 pub mod process_policies;
 pub mod process_printer;
+pub mod retained_state;
+pub mod rollback_protection;
 pub mod storage_permissions;
+pub mod suspendable_drivers;
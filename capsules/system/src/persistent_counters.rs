@@ -0,0 +1,245 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Boot and panic counters kept in the KV store.
+//!
+//! At each boot, [`BootCounters::start`] loads the counters from the KV
+//! store, counts the boot, and stores them again. The counters are then
+//! given to tools such as the `status` command of the process console with
+//! the [`PersistentCounters`] trait.
+//!
+//! The KV store cannot be written while the kernel panics, so the counters
+//! are the [`PanicRecorder`] of the kernel: they keep the hash of the panic
+//! message in the panic breadcrumb of the retained registers, and the next
+//! boot counts the panic. The hash is reduced to what the registers hold, so
+//! it is only 8 bits on the nRF52. A panic is lost if the retained registers
+//! are cleared before the next boot, for example by a power loss.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let boot_counters = components::persistent_counters::BootCountersComponent::new(
+//!     counters_kv,
+//!     &base_peripherals.pwr_clk,
+//!     RETAINED_LAYOUT,
+//! )
+//! .finalize(components::boot_counters_component_static!(
+//!     VirtualKVPermissions,
+//!     nrf52840::power::Power<'static>,
+//! ));
+//! let _ = boot_counters.start();
+//! process_console.set_persistent_counters(boot_counters);
+//! ```
+
+use core::cell::Cell;
+
+use crate::retained_state::{RetainedLayout, RetainedState};
+use kernel::hil::kv;
+use kernel::hil::retained::RetainedRegisters;
+use kernel::platform::persistent_counters::{PanicRecorder, PersistentCounters, PersistentCounts};
+use kernel::storage_permissions::StoragePermissions;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::ErrorCode;
+
+/// KV key of the stored counters.
+const KEY: &[u8; KEY_LEN] = b"counters";
+/// Length of the KV key of the stored counters.
+pub const KEY_LEN: usize = 8;
+/// Length of the stored counters: the boot count, the panic count and the
+/// last panic reason, or 0 if there was no panic, as little endian `u32`s.
+pub const VALUE_LEN: usize = 12;
+
+pub struct BootCounters<'a, V: kv::KVPermissions<'a>, R: RetainedRegisters> {
+    kv: &'a V,
+    /// Permissions of the kernel for the stored counters.
+    permissions: StoragePermissions,
+    retained: RetainedState<'a, R>,
+    counts: OptionalCell<PersistentCounts>,
+    /// Reason of the panic that ended the previous boot, until it is
+    /// counted.
+    previous_panic: OptionalCell<u32>,
+    started: Cell<bool>,
+    key_buffer: TakeCell<'static, [u8]>,
+    value_buffer: TakeCell<'static, [u8]>,
+}
+
+impl<'a, V: kv::KVPermissions<'a>, R: RetainedRegisters> BootCounters<'a, V, R> {
+    /// Keep the counters in `kv`, and the reason of a panic in the panic
+    /// breadcrumb of `retained` in `layout`.
+    pub fn new(
+        kv: &'a V,
+        permissions: StoragePermissions,
+        retained: &'a R,
+        layout: RetainedLayout,
+        key_buffer: &'static mut [u8],
+        value_buffer: &'static mut [u8],
+    ) -> Self {
+        Self {
+            kv,
+            permissions,
+            retained: RetainedState::new(retained, layout),
+            counts: OptionalCell::empty(),
+            previous_panic: OptionalCell::empty(),
+            started: Cell::new(false),
+            key_buffer: TakeCell::new(key_buffer),
+            value_buffer: TakeCell::new(value_buffer),
+        }
+    }
+
+    /// Load the counters, count this boot and the panic that ended the
+    /// previous boot, if any, and store them.
+    ///
+    /// Boards call this once during boot.
+    pub fn start(&self) -> Result<(), ErrorCode> {
+        if self.started.get() {
+            return Err(ErrorCode::ALREADY);
+        }
+        let key_buf = self.key_buffer.take().ok_or(ErrorCode::BUSY)?;
+        let Some(value_buf) = self.value_buffer.take() else {
+            self.key_buffer.replace(key_buf);
+            return Err(ErrorCode::BUSY);
+        };
+        if key_buf.len() < KEY_LEN || value_buf.len() < self.kv.header_size() + VALUE_LEN {
+            self.key_buffer.replace(key_buf);
+            self.value_buffer.replace(value_buf);
+            return Err(ErrorCode::SIZE);
+        }
+        if let Ok(Some(reason)) = self.retained.take_panic_breadcrumb() {
+            self.previous_panic.set(reason);
+        }
+
+        key_buf[..KEY_LEN].copy_from_slice(KEY);
+        let mut key = SubSliceMut::new(key_buf);
+        key.slice(..KEY_LEN);
+        match self
+            .kv
+            .get(key, SubSliceMut::new(value_buf), self.permissions)
+        {
+            Ok(()) => {
+                self.started.set(true);
+                Ok(())
+            }
+            Err((key, value, e)) => {
+                self.key_buffer.replace(key.take());
+                self.value_buffer.replace(value.take());
+                Err(e)
+            }
+        }
+    }
+
+    /// Count this boot after the stored counters, if any, and store the
+    /// result.
+    fn count_boot(&self, stored: Option<PersistentCounts>, value_buf: &'static mut [u8]) {
+        let mut counts = stored.unwrap_or_default();
+        counts.boot_count = counts.boot_count.saturating_add(1);
+        if let Some(reason) = self.previous_panic.take() {
+            counts.panic_count = counts.panic_count.saturating_add(1);
+            counts.last_panic = Some(reason);
+        }
+        self.counts.set(counts);
+
+        let header = self.kv.header_size();
+        let value_bytes = &mut value_buf[header..header + VALUE_LEN];
+        value_bytes[0..4].copy_from_slice(&counts.boot_count.to_le_bytes());
+        value_bytes[4..8].copy_from_slice(&counts.panic_count.to_le_bytes());
+        value_bytes[8..12].copy_from_slice(&counts.last_panic.unwrap_or(0).to_le_bytes());
+        let mut value = SubSliceMut::new(value_buf);
+        value.slice(..header + VALUE_LEN);
+
+        let Some(key_buf) = self.key_buffer.take() else {
+            self.value_buffer.replace(value.take());
+            return;
+        };
+        let mut key = SubSliceMut::new(key_buf);
+        key.slice(..KEY_LEN);
+        if let Err((key, value, _)) = self.kv.set(key, value, self.permissions) {
+            self.key_buffer.replace(key.take());
+            self.value_buffer.replace(value.take());
+        }
+    }
+}
+
+impl<'a, V: kv::KVPermissions<'a>, R: RetainedRegisters> PersistentCounters
+    for BootCounters<'a, V, R>
+{
+    fn counts(&self) -> Option<PersistentCounts> {
+        self.counts.get()
+    }
+}
+
+impl<'a, V: kv::KVPermissions<'a>, R: RetainedRegisters> PanicRecorder for BootCounters<'a, V, R> {
+    fn record_panic(&self, reason: u32) {
+        // 0 means that there is no breadcrumb.
+        let max = self.retained.max_value();
+        let reason = if max == u32::MAX {
+            reason.max(1)
+        } else {
+            reason % max + 1
+        };
+        let _ = self.retained.set_panic_breadcrumb(reason);
+    }
+}
+
+impl<'a, V: kv::KVPermissions<'a>, R: RetainedRegisters> kv::KVClient for BootCounters<'a, V, R> {
+    fn get_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        // The KV header is already removed from `value`.
+        let stored = (result.is_ok() && value.len() >= VALUE_LEN).then(|| {
+            let word = |index: usize| {
+                let mut bytes = [0; 4];
+                bytes.copy_from_slice(&value[index * 4..index * 4 + 4]);
+                u32::from_le_bytes(bytes)
+            };
+            PersistentCounts {
+                boot_count: word(0),
+                panic_count: word(1),
+                last_panic: Some(word(2)).filter(|reason| *reason != 0),
+            }
+        });
+        self.key_buffer.replace(key.take());
+        self.count_boot(stored, value.take());
+    }
+
+    fn set_complete(
+        &self,
+        _result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.key_buffer.replace(key.take());
+        self.value_buffer.replace(value.take());
+    }
+
+    fn add_complete(
+        &self,
+        _result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.key_buffer.replace(key.take());
+        self.value_buffer.replace(value.take());
+    }
+
+    fn update_complete(
+        &self,
+        _result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.key_buffer.replace(key.take());
+        self.value_buffer.replace(value.take());
+    }
+
+    fn delete_complete(&self, _result: Result<(), ErrorCode>, key: SubSliceMut<'static, u8>) {
+        self.key_buffer.replace(key.take());
+    }
+
+    fn garbage_collection_complete(&self, _result: Result<(), ErrorCode>) {}
+}
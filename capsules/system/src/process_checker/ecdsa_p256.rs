@@ -0,0 +1,50 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! ECDSA P-256 credential checker for checking process credentials.
+//!
+//! Checks `EcdsaNistP256` credentials, which are an ECDSA signature on the
+//! NIST P-256 curve over the SHA-256 hash of the process binary. The
+//! signature is the `r` and `s` values, each 32 bytes big endian.
+//!
+//! The verifier holds the public key the signatures are checked against, and
+//! can be a software implementation or a hardware accelerator.
+
+use kernel::hil;
+use tock_tbf::types::TbfFooterV2CredentialsType;
+
+use super::signature::AppCheckerSignature;
+
+/// Length of a SHA-256 hash, in bytes.
+pub const HASH_LEN: usize = 32;
+
+/// Length of a P-256 signature, in bytes.
+pub const SIGNATURE_LEN: usize = 64;
+
+/// Checker that accepts processes with a valid ECDSA P-256 signature.
+pub type AppCheckerEcdsaP256<'a, S, H> = AppCheckerSignature<'a, S, H, HASH_LEN, SIGNATURE_LEN>;
+
+impl<
+        'a,
+        S: hil::public_key_crypto::signature::SignatureVerify<'static, HASH_LEN, SIGNATURE_LEN>,
+        H: hil::digest::DigestDataHash<'a, HASH_LEN>,
+    > AppCheckerSignature<'a, S, H, HASH_LEN, SIGNATURE_LEN>
+{
+    /// Create a checker for `EcdsaNistP256` credentials. `hasher` must
+    /// compute SHA-256 hashes.
+    pub fn new_ecdsa_p256(
+        hasher: &'a H,
+        verifier: &'a S,
+        hash_buffer: &'static mut [u8; HASH_LEN],
+        signature_buffer: &'static mut [u8; SIGNATURE_LEN],
+    ) -> AppCheckerEcdsaP256<'a, S, H> {
+        AppCheckerSignature::new(
+            hasher,
+            verifier,
+            hash_buffer,
+            signature_buffer,
+            TbfFooterV2CredentialsType::EcdsaNistP256,
+        )
+    }
+}
@@ -0,0 +1,172 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Typed access to the state kept in retained registers across resets.
+//!
+//! A board assigns its [`RetainedRegisters`] to the values it needs with a
+//! [`RetainedLayout`], and then reads and writes them by name:
+//!
+//! - the bootloader handshake value, which bootloaders such as the Adafruit
+//!   nRF52 bootloader check to stay in their update mode after a reset;
+//! - the boot count, the number of boots since the retained registers were
+//!   last cleared, for example by a power loss;
+//! - the boot flags, which carry requests from one boot to the next, such as
+//!   booting without starting processes;
+//! - the panic breadcrumb, a value written before a panic reset that tells
+//!   the next boot what went wrong.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! const RETAINED_LAYOUT: RetainedLayout = RetainedLayout {
+//!     bootloader_handshake: Some(0),
+//!     ..RetainedLayout::NONE
+//! };
+//!
+//! RetainedState::new(&peripherals.pwr_clk, RETAINED_LAYOUT).set_bootloader_handshake(0x90)?;
+//! ```
+
+use kernel::hil::retained::RetainedRegisters;
+use kernel::ErrorCode;
+
+/// Index of the retained register holding each value, or `None` if the board
+/// does not keep it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetainedLayout {
+    pub bootloader_handshake: Option<usize>,
+    pub boot_count: Option<usize>,
+    pub boot_flags: Option<usize>,
+    pub panic_breadcrumb: Option<usize>,
+}
+
+impl RetainedLayout {
+    /// Layout without any value.
+    pub const NONE: RetainedLayout = RetainedLayout {
+        bootloader_handshake: None,
+        boot_count: None,
+        boot_flags: None,
+        panic_breadcrumb: None,
+    };
+}
+
+/// Flags carried from one boot to the next.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BootFlags(pub u32);
+
+impl BootFlags {
+    /// Boot without starting processes, for example after processes caused
+    /// repeated resets.
+    pub const SAFE_MODE: BootFlags = BootFlags(1 << 0);
+    /// The previous boot ended with a panic.
+    pub const PANICKED: BootFlags = BootFlags(1 << 1);
+
+    pub const fn empty() -> BootFlags {
+        BootFlags(0)
+    }
+
+    pub const fn contains(self, flags: BootFlags) -> bool {
+        self.0 & flags.0 == flags.0
+    }
+
+    pub const fn union(self, flags: BootFlags) -> BootFlags {
+        BootFlags(self.0 | flags.0)
+    }
+
+    pub const fn difference(self, flags: BootFlags) -> BootFlags {
+        BootFlags(self.0 & !flags.0)
+    }
+}
+
+pub struct RetainedState<'a, R: RetainedRegisters> {
+    registers: &'a R,
+    layout: RetainedLayout,
+}
+
+impl<'a, R: RetainedRegisters> RetainedState<'a, R> {
+    pub const fn new(registers: &'a R, layout: RetainedLayout) -> Self {
+        Self { registers, layout }
+    }
+
+    /// Largest value a register holds.
+    pub fn max_value(&self) -> u32 {
+        match self.registers.width() {
+            width if width >= 32 => u32::MAX,
+            width => (1 << width) - 1,
+        }
+    }
+
+    fn read(&self, index: Option<usize>) -> Result<u32, ErrorCode> {
+        index.map_or(Err(ErrorCode::NOSUPPORT), |index| {
+            self.registers.read(index)
+        })
+    }
+
+    fn write(&self, index: Option<usize>, value: u32) -> Result<(), ErrorCode> {
+        index.map_or(Err(ErrorCode::NOSUPPORT), |index| {
+            self.registers.write(index, value)
+        })
+    }
+
+    /// Return the bootloader handshake value. Returns `NOSUPPORT` if the
+    /// layout has no bootloader handshake value.
+    pub fn bootloader_handshake(&self) -> Result<u32, ErrorCode> {
+        self.read(self.layout.bootloader_handshake)
+    }
+
+    /// Set the value that the bootloader checks after the next reset.
+    pub fn set_bootloader_handshake(&self, value: u32) -> Result<(), ErrorCode> {
+        self.write(self.layout.bootloader_handshake, value)
+    }
+
+    /// Count this boot, and return the number of boots including this one.
+    /// The count stops at the largest value the register holds. Returns
+    /// `NOSUPPORT` if the layout has no boot count.
+    ///
+    /// Boards call this once, early during boot.
+    pub fn count_boot(&self) -> Result<u32, ErrorCode> {
+        let count = self
+            .read(self.layout.boot_count)?
+            .saturating_add(1)
+            .min(self.max_value());
+        self.write(self.layout.boot_count, count)?;
+        Ok(count)
+    }
+
+    /// Return the boot flags. Returns `NOSUPPORT` if the layout has no boot
+    /// flags.
+    pub fn boot_flags(&self) -> Result<BootFlags, ErrorCode> {
+        self.read(self.layout.boot_flags).map(BootFlags)
+    }
+
+    pub fn set_boot_flags(&self, flags: BootFlags) -> Result<(), ErrorCode> {
+        self.write(self.layout.boot_flags, flags.0)
+    }
+
+    /// Set `flags` in the boot flags, and keep the other flags.
+    pub fn insert_boot_flags(&self, flags: BootFlags) -> Result<(), ErrorCode> {
+        self.set_boot_flags(self.boot_flags()?.union(flags))
+    }
+
+    /// Clear `flags` in the boot flags, and keep the other flags.
+    pub fn remove_boot_flags(&self, flags: BootFlags) -> Result<(), ErrorCode> {
+        self.set_boot_flags(self.boot_flags()?.difference(flags))
+    }
+
+    /// Record `breadcrumb` for the next boot. The meaning of the value is up
+    /// to the board, 0 means that there is no breadcrumb.
+    pub fn set_panic_breadcrumb(&self, breadcrumb: u32) -> Result<(), ErrorCode> {
+        self.write(self.layout.panic_breadcrumb, breadcrumb)
+    }
+
+    /// Return the breadcrumb recorded by the previous boot, if any, and clear
+    /// it.
+    pub fn take_panic_breadcrumb(&self) -> Result<Option<u32>, ErrorCode> {
+        let breadcrumb = self.read(self.layout.panic_breadcrumb)?;
+        if breadcrumb != 0 {
+            self.write(self.layout.panic_breadcrumb, 0)?;
+        }
+        Ok(Some(breadcrumb).filter(|breadcrumb| *breadcrumb != 0))
+    }
+}
@@ -0,0 +1,150 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Rollback protection with the minimum application versions kept in
+//! nonvolatile storage.
+//!
+//! The minimum version of each application is kept in a small table, with
+//! one 8 byte entry per application: its `ShortId` and its minimum version,
+//! both little endian. Entries with a `ShortId` of 0 or 0xFFFFFFFF (erased
+//! flash) are free.
+//!
+//! The table must be in memory-mapped flash: it is read directly when the
+//! board creates [`NonvolatileRollbackProtection`], before processes are
+//! loaded, and written through a [`NonvolatileStorage`]. Updates are kept in
+//! RAM and written back in the background, so a new minimum is effective
+//! immediately but only survives a reboot once it is written.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let rollback = components::rollback_protection::RollbackProtectionComponent::new(
+//!     nv_to_page,
+//!     ROLLBACK_TABLE_ADDRESS,
+//!     rollback_table,
+//! )
+//! .finalize(components::rollback_protection_component_static!(8));
+//! loader.set_rollback_protection(rollback);
+//! ```
+
+use core::cell::Cell;
+use core::num::NonZeroU32;
+
+use kernel::hil::nonvolatile_storage::{NonvolatileStorage, NonvolatileStorageClient};
+use kernel::process::{RollbackProtection, ShortId};
+use kernel::utilities::cells::TakeCell;
+use kernel::ErrorCode;
+
+/// Length of a table entry in bytes.
+pub const ENTRY_LEN: usize = 8;
+
+pub struct NonvolatileRollbackProtection<'a, const N: usize> {
+    storage: &'a dyn NonvolatileStorage<'a>,
+    /// Address of the table in `storage`.
+    address: usize,
+    /// `ShortId` and minimum version of each application.
+    entries: [Cell<Option<(NonZeroU32, u32)>>; N],
+    /// Buffer to write the table, `N * ENTRY_LEN` bytes long. Taken while a
+    /// write is in progress.
+    buffer: TakeCell<'static, [u8]>,
+    /// Whether the table changed since the last write started.
+    dirty: Cell<bool>,
+}
+
+impl<'a, const N: usize> NonvolatileRollbackProtection<'a, N> {
+    /// Create the rollback protection from the table stored at `address` in
+    /// `storage`, whose current content is `stored`.
+    pub fn new(
+        storage: &'a dyn NonvolatileStorage<'a>,
+        address: usize,
+        stored: &[u8],
+        buffer: &'static mut [u8],
+    ) -> Self {
+        let entries = core::array::from_fn(|i| {
+            let entry = stored.get(i * ENTRY_LEN..(i + 1) * ENTRY_LEN)?;
+            let short_id = u32::from_le_bytes(entry[0..4].try_into().ok()?);
+            let version = u32::from_le_bytes(entry[4..8].try_into().ok()?);
+            if short_id == u32::MAX {
+                return None;
+            }
+            NonZeroU32::new(short_id).map(|short_id| (short_id, version))
+        });
+        Self {
+            storage,
+            address,
+            entries: entries.map(Cell::new),
+            buffer: TakeCell::new(buffer),
+            dirty: Cell::new(false),
+        }
+    }
+
+    /// Start writing the table, unless a write is in progress, in which case
+    /// the table is written again when it finishes.
+    fn write_back(&self) -> Result<(), ErrorCode> {
+        self.dirty.set(true);
+        let buffer = match self.buffer.take() {
+            Some(buffer) => buffer,
+            None => return Ok(()),
+        };
+        let len = N * ENTRY_LEN;
+        if buffer.len() < len {
+            self.buffer.replace(buffer);
+            return Err(ErrorCode::SIZE);
+        }
+        for (entry, chunk) in self.entries.iter().zip(buffer.chunks_exact_mut(ENTRY_LEN)) {
+            match entry.get() {
+                Some((short_id, version)) => {
+                    chunk[0..4].copy_from_slice(&short_id.get().to_le_bytes());
+                    chunk[4..8].copy_from_slice(&version.to_le_bytes());
+                }
+                None => chunk.fill(0xff),
+            }
+        }
+        self.dirty.set(false);
+        // The storage does not return the buffer if the write fails to start,
+        // so later updates then only stay in RAM.
+        self.storage.write(buffer, self.address, len)
+    }
+}
+
+impl<const N: usize> RollbackProtection for NonvolatileRollbackProtection<'_, N> {
+    fn minimum_version(&self, short_id: ShortId) -> u32 {
+        let ShortId::Fixed(short_id) = short_id else {
+            return 0;
+        };
+        self.entries
+            .iter()
+            .find_map(|entry| entry.get().filter(|(id, _)| *id == short_id))
+            .map_or(0, |(_, version)| version)
+    }
+
+    fn raise_minimum_version(&self, short_id: ShortId, version: u32) -> Result<(), ErrorCode> {
+        let ShortId::Fixed(short_id) = short_id else {
+            return Ok(());
+        };
+        let entry = self
+            .entries
+            .iter()
+            .find(|entry| entry.get().is_some_and(|(id, _)| id == short_id))
+            .or_else(|| self.entries.iter().find(|entry| entry.get().is_none()))
+            .ok_or(ErrorCode::NOMEM)?;
+        if entry.get().is_some_and(|(_, minimum)| minimum >= version) {
+            return Ok(());
+        }
+        entry.set(Some((short_id, version)));
+        self.write_back()
+    }
+}
+
+impl<const N: usize> NonvolatileStorageClient for NonvolatileRollbackProtection<'_, N> {
+    fn read_done(&self, _buffer: &'static mut [u8], _length: usize) {}
+
+    fn write_done(&self, buffer: &'static mut [u8], _length: usize) {
+        self.buffer.replace(buffer);
+        if self.dirty.get() {
+            let _ = self.write_back();
+        }
+    }
+}
@@ -0,0 +1,157 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Registry of capsules that can be suspended and resumed at runtime.
+//!
+//! Boards list the capsules they want to be able to disable in the field,
+//! optionally together with an object implementing
+//! [`Suspendable`](kernel::platform::suspend::Suspendable) that masks the
+//! capsule's interrupts. The registry is then used as the board's
+//! `SyscallFilter`: while a capsule is suspended, every `Command` system call
+//! to its driver number fails with `ErrorCode::OFF`. Subscribe and allow calls
+//! are still passed through so that processes can retrieve their buffers and
+//! clean up.
+//!
+//! All other system calls are forwarded to an inner filter, so the registry can
+//! be layered on top of any existing `SyscallFilter` policy.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let drivers = static_init!(
+//!     [SuspendableDriver<'static>; 2],
+//!     [
+//!         SuspendableDriver::new("button", capsules_core::button::DRIVER_NUM, Some(button)),
+//!         SuspendableDriver::new("led", capsules_core::led::DRIVER_NUM, None),
+//!     ]
+//! );
+//! let suspendable = static_init!(
+//!     SuspendableDrivers<'static, ()>,
+//!     SuspendableDrivers::new(drivers, ())
+//! );
+//! process_console.set_suspend_control(suspendable);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::platform::suspend::{SuspendControl, Suspendable};
+use kernel::platform::SyscallFilter;
+use kernel::process;
+use kernel::syscall;
+use kernel::ErrorCode;
+
+/// A single capsule registered as suspendable.
+pub struct SuspendableDriver<'a> {
+    name: &'static str,
+    driver_num: usize,
+    device: Option<&'a dyn Suspendable>,
+    suspended: Cell<bool>,
+}
+
+impl<'a> SuspendableDriver<'a> {
+    /// Register the capsule with driver number `driver_num` under `name`.
+    ///
+    /// If `device` is provided it is notified when the capsule is suspended
+    /// and resumed.
+    pub const fn new(
+        name: &'static str,
+        driver_num: usize,
+        device: Option<&'a dyn Suspendable>,
+    ) -> SuspendableDriver<'a> {
+        SuspendableDriver {
+            name,
+            driver_num,
+            device,
+            suspended: Cell::new(false),
+        }
+    }
+}
+
+/// Syscall filter that rejects commands to suspended capsules.
+pub struct SuspendableDrivers<'a, F: SyscallFilter> {
+    drivers: &'a [SuspendableDriver<'a>],
+    filter: F,
+}
+
+impl<'a, F: SyscallFilter> SuspendableDrivers<'a, F> {
+    pub fn new(drivers: &'a [SuspendableDriver<'a>], filter: F) -> SuspendableDrivers<'a, F> {
+        SuspendableDrivers { drivers, filter }
+    }
+
+    fn find(&self, name: &str) -> Result<&SuspendableDriver<'a>, ErrorCode> {
+        self.drivers
+            .iter()
+            .find(|driver| driver.name == name)
+            .ok_or(ErrorCode::INVAL)
+    }
+
+    /// The filter the system calls that are not rejected are forwarded to.
+    pub fn filter(&self) -> &F {
+        &self.filter
+    }
+
+    /// Check whether the capsule with driver number `driver_num` is
+    /// registered and currently suspended.
+    pub fn driver_suspended(&self, driver_num: usize) -> bool {
+        self.drivers
+            .iter()
+            .any(|driver| driver.driver_num == driver_num && driver.suspended.get())
+    }
+}
+
+impl<F: SyscallFilter> SuspendControl for SuspendableDrivers<'_, F> {
+    fn count(&self) -> usize {
+        self.drivers.len()
+    }
+
+    fn name(&self, index: usize) -> Option<&'static str> {
+        self.drivers.get(index).map(|driver| driver.name)
+    }
+
+    fn driver_num(&self, index: usize) -> Option<usize> {
+        self.drivers.get(index).map(|driver| driver.driver_num)
+    }
+
+    fn is_suspended(&self, index: usize) -> Option<bool> {
+        self.drivers.get(index).map(|driver| driver.suspended.get())
+    }
+
+    fn suspend(&self, name: &str) -> Result<(), ErrorCode> {
+        let driver = self.find(name)?;
+        if driver.suspended.get() {
+            return Err(ErrorCode::ALREADY);
+        }
+        // Stop accepting new operations before masking interrupts so that a
+        // command cannot re-arm the hardware in between.
+        driver.suspended.set(true);
+        driver.device.map(|device| device.suspend());
+        Ok(())
+    }
+
+    fn resume(&self, name: &str) -> Result<(), ErrorCode> {
+        let driver = self.find(name)?;
+        if !driver.suspended.get() {
+            return Err(ErrorCode::ALREADY);
+        }
+        driver.device.map(|device| device.resume());
+        driver.suspended.set(false);
+        Ok(())
+    }
+}
+
+impl<F: SyscallFilter> SyscallFilter for SuspendableDrivers<'_, F> {
+    fn filter_syscall(
+        &self,
+        process: &dyn process::Process,
+        syscall: &syscall::Syscall,
+    ) -> Result<(), ErrorCode> {
+        if let syscall::Syscall::Command { driver_number, .. } = syscall {
+            if self.driver_suspended(*driver_number) {
+                return Err(ErrorCode::OFF);
+            }
+        }
+        self.filter.filter_syscall(process, syscall)
+    }
+}
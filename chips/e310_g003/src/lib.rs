@@ -8,7 +8,7 @@
 #![crate_name = "e310_g003"]
 #![crate_type = "rlib"]
 
-pub use e310x::{chip, clint, gpio, plic, prci, pwm, rtc, uart, watchdog};
+pub use e310x::{chip, clint, gpio, plic, prci, pwm, rtc, spi_flash, uart, watchdog};
 
 pub mod interrupt_service;
 mod interrupts;
@@ -15,5 +15,6 @@ pub mod plic;
 pub mod prci;
 pub mod pwm;
 pub mod rtc;
+pub mod spi_flash;
 pub mod uart;
 pub mod watchdog;
@@ -0,0 +1,15 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! SPI flash controller instantiation.
+
+use kernel::utilities::StaticRef;
+use sifive::spi_flash::SpiFlashRegisters;
+
+/// The QSPI0 controller, which maps the flash the core executes from.
+pub const SPI_FLASH_BASE: StaticRef<SpiFlashRegisters> =
+    unsafe { StaticRef::new(0x1001_4000 as *const SpiFlashRegisters) };
+
+/// Address the flash is mapped at.
+pub const FLASH_MAPPED_ADDRESS: usize = 0x2000_0000;
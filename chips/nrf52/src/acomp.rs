@@ -281,6 +281,10 @@ impl Comparator<'_> {
 
     /// Handles upward crossing events (when VIN+ becomes greater than VIN-)
     pub fn handle_interrupt(&self) {
+        // The LPCOMP shares this interrupt.
+        if !self.registers.enable.matches_all(Enable::ENABLE::Enabled) {
+            return;
+        }
         // HIL only cares about upward crossing interrupts
         // VIN+ crossed VIN-
         if self.registers.events_up.get() == 1 {
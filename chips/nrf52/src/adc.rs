@@ -3,6 +3,11 @@
 // Copyright Tock Contributors 2022.
 
 //! ADC driver for the nRF52. Uses the SAADC peripheral.
+//!
+//! The SAADC samples relative to either its internal 0.6 V reference or a
+//! quarter of VDD (the default), scaled by the gain of the channel. It can
+//! average up to 256 conversions into each sample, and sample the difference
+//! between two analog inputs.
 
 use core::cell::Cell;
 use core::cmp;
@@ -263,6 +268,23 @@ pub enum AdcChannelGain {
     Gain4 = 7,
 }
 
+impl AdcChannelGain {
+    /// Scale the full-scale input voltage `mv` of the ADC by the inverse of
+    /// the gain.
+    fn scale_full_scale(self, mv: usize) -> usize {
+        match self {
+            AdcChannelGain::Gain1_6 => mv * 6,
+            AdcChannelGain::Gain1_5 => mv * 5,
+            AdcChannelGain::Gain1_4 => mv * 4,
+            AdcChannelGain::Gain1_3 => mv * 3,
+            AdcChannelGain::Gain1_2 => mv * 2,
+            AdcChannelGain::Gain1 => mv,
+            AdcChannelGain::Gain2 => mv / 2,
+            AdcChannelGain::Gain4 => mv / 4,
+        }
+    }
+}
+
 #[repr(u8)]
 #[derive(Copy, Clone, Debug)]
 pub enum AdcChannelResistor {
@@ -335,9 +357,19 @@ enum AdcMode {
     HighSpeed,
 }
 
+/// Largest number of conversions averaged into a sample, as a power of two.
+const MAX_OVERSAMPLE_LOG2: usize = 8;
+
+/// Internal reference voltage, in millivolts.
+const INTERNAL_REFERENCE_MV: usize = 600;
+
 pub struct Adc<'a> {
     registers: StaticRef<AdcRegisters>,
     reference: Cell<usize>,
+    reference_selection: Cell<hil::adc::Reference>,
+    oversample_log2: Cell<usize>,
+    gain: Cell<AdcChannelGain>,
+    differential: Cell<bool>,
     mode: Cell<AdcMode>,
     client: OptionalCell<&'a dyn hil::adc::Client>,
     highspeed_client: OptionalCell<&'a dyn hil::adc::HighSpeedClient>,
@@ -353,6 +385,10 @@ impl Adc<'_> {
         Self {
             registers: SAADC_BASE,
             reference: Cell::new(voltage_reference_in_mv),
+            reference_selection: Cell::new(hil::adc::Reference::Supply),
+            oversample_log2: Cell::new(0),
+            gain: Cell::new(AdcChannelGain::Gain1_4),
+            differential: Cell::new(false),
             mode: Cell::new(AdcMode::Idle),
             client: OptionalCell::empty(),
             highspeed_client: OptionalCell::empty(),
@@ -396,6 +432,7 @@ impl Adc<'_> {
                     );
 
                     self.setup_resolution();
+                    self.registers.oversample.set(0);
                     self.setup_sample_count(1);
 
                     // Where to put the reading.
@@ -467,7 +504,11 @@ impl Adc<'_> {
                     let val = unsafe { SAMPLE[0] as i16 };
                     self.client.map(|client| {
                         // shift left to meet the ADC HIL requirement
-                        client.sample_ready(if val < 0 { 0 } else { val << 4 } as u16);
+                        if self.differential.get() {
+                            client.sample_ready((val << 4) as u16);
+                        } else {
+                            client.sample_ready(if val < 0 { 0 } else { val << 4 } as u16);
+                        }
                     });
                 }
             }
@@ -528,27 +569,81 @@ impl Adc<'_> {
         }
     }
 
-    fn setup_channel(&self, channel: &AdcChannelSetup) {
-        // Positive goes to the channel passed in, negative not connected.
+    fn setup_channel(&self, channel: &AdcChannelSetup, negative: Option<&AdcChannelSetup>) {
+        // Positive goes to the channel passed in, negative to the other
+        // channel of a differential pair, or not connected.
         self.registers.ch[0]
             .pselp
             .write(PSEL::PSEL.val(channel.channel as u32));
-        self.registers.ch[0].pseln.write(PSEL::PSEL::NotConnected);
+        match negative {
+            Some(negative) => self.registers.ch[0]
+                .pseln
+                .write(PSEL::PSEL.val(negative.channel as u32)),
+            None => self.registers.ch[0].pseln.write(PSEL::PSEL::NotConnected),
+        }
+        self.gain.set(channel.gain);
+        self.differential.set(negative.is_some());
 
-        // Configure the ADC for a single read.
+        // Configure the ADC for a single read. With oversampling, burst mode
+        // takes all the conversions of a sample with a single SAMPLE task.
         self.registers.ch[0].config.write(
             CONFIG::GAIN.val(channel.gain as u32)
-                + CONFIG::REFSEL::VDD1_4
+                + match self.reference_selection.get() {
+                    hil::adc::Reference::Internal => CONFIG::REFSEL::Internal,
+                    _ => CONFIG::REFSEL::VDD1_4,
+                }
                 + CONFIG::TACQ.val(channel.sampling_time as u32)
                 + CONFIG::RESP.val(channel.resp as u32)
                 + CONFIG::RESN.val(channel.resn as u32)
-                + CONFIG::MODE::SE,
+                + if negative.is_some() {
+                    CONFIG::MODE::Diff
+                } else {
+                    CONFIG::MODE::SE
+                }
+                + if self.oversample_log2.get() > 0 {
+                    CONFIG::BURST::Enable
+                } else {
+                    CONFIG::BURST::Disable
+                },
         );
     }
 
     fn setup_resolution(&self) {
         // Set max resolution (with oversampling).
         self.registers.resolution.write(RESOLUTION::VAL::bit12);
+        self.registers
+            .oversample
+            .set(self.oversample_log2.get() as u32);
+    }
+
+    // Take a single sample of `channel`, or of the difference between
+    // `channel` and `negative`.
+    fn start_single(&self, channel: &AdcChannelSetup, negative: Option<&AdcChannelSetup>) {
+        self.setup_channel(channel, negative);
+        self.setup_resolution();
+
+        // Do one measurement.
+        self.registers
+            .result_maxcnt
+            .write(RESULT_MAXCNT::MAXCNT.val(1));
+        // Where to put the reading.
+        self.registers.result_ptr.set(addr_of!(SAMPLE) as *const _);
+
+        // No automatic sampling, will trigger manually.
+        self.registers.samplerate.write(SAMPLERATE::MODE::Task);
+
+        // Enable the ADC
+        self.registers.enable.write(ENABLE::ENABLE::SET);
+
+        // Enable started, sample end, and stopped interrupts.
+        self.registers
+            .inten
+            .write(INTEN::STARTED::SET + INTEN::END::SET + INTEN::STOPPED::SET);
+
+        self.mode.set(AdcMode::Single);
+
+        // Start the SAADC and wait for the started interrupt.
+        self.registers.tasks_start.write(TASK::TASK::SET);
     }
 
     fn setup_sample_count(&self, count: usize) {
@@ -578,32 +673,7 @@ impl<'a> hil::adc::Adc<'a> for Adc<'a> {
     type Channel = AdcChannelSetup;
 
     fn sample(&self, channel: &Self::Channel) -> Result<(), ErrorCode> {
-        self.setup_channel(channel);
-        self.setup_resolution();
-
-        // Do one measurement.
-        self.registers
-            .result_maxcnt
-            .write(RESULT_MAXCNT::MAXCNT.val(1));
-        // Where to put the reading.
-        self.registers.result_ptr.set(addr_of!(SAMPLE) as *const _);
-
-        // No automatic sampling, will trigger manually.
-        self.registers.samplerate.write(SAMPLERATE::MODE::Task);
-
-        // Enable the ADC
-        self.registers.enable.write(ENABLE::ENABLE::SET);
-
-        // Enable started, sample end, and stopped interrupts.
-        self.registers
-            .inten
-            .write(INTEN::STARTED::SET + INTEN::END::SET + INTEN::STOPPED::SET);
-
-        self.mode.set(AdcMode::Single);
-
-        // Start the SAADC and wait for the started interrupt.
-        self.registers.tasks_start.write(TASK::TASK::SET);
-
+        self.start_single(channel, None);
         Ok(())
     }
 
@@ -625,7 +695,13 @@ impl<'a> hil::adc::Adc<'a> for Adc<'a> {
     }
 
     fn get_voltage_reference_mv(&self) -> Option<usize> {
-        Some(self.reference.get())
+        // The reference is scaled by the gain of the last sampled channel,
+        // which is 1/4 for the VDD/4 reference by default.
+        let reference = match self.reference_selection.get() {
+            hil::adc::Reference::Internal => INTERNAL_REFERENCE_MV,
+            _ => self.reference.get() / 4,
+        };
+        Some(self.gain.get().scale_full_scale(reference))
     }
 
     fn set_client(&self, client: &'a dyn hil::adc::Client) {
@@ -651,7 +727,7 @@ impl<'a> hil::adc::AdcHighSpeed<'a> for Adc<'a> {
             self.next_buffer.replace(buffer2);
             self.next_length.set(length2);
 
-            self.setup_channel(channel);
+            self.setup_channel(channel, None);
             self.setup_resolution();
 
             // Use EasyDMA to save the samples to our buffer.
@@ -711,3 +787,45 @@ impl<'a> hil::adc::AdcHighSpeed<'a> for Adc<'a> {
         self.highspeed_client.set(client);
     }
 }
+
+impl<'a> hil::adc::AdcConfigure<'a> for Adc<'a> {
+    fn set_reference(&self, reference: hil::adc::Reference) -> Result<(), ErrorCode> {
+        match reference {
+            hil::adc::Reference::Internal | hil::adc::Reference::Supply => {
+                self.reference_selection.set(reference);
+                Ok(())
+            }
+            hil::adc::Reference::External => Err(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn get_reference(&self) -> hil::adc::Reference {
+        self.reference_selection.get()
+    }
+
+    fn set_oversampling(&self, samples: usize) -> Result<(), ErrorCode> {
+        if !samples.is_power_of_two() || samples > 1 << MAX_OVERSAMPLE_LOG2 {
+            return Err(ErrorCode::INVAL);
+        }
+        self.oversample_log2.set(samples.trailing_zeros() as usize);
+        Ok(())
+    }
+
+    fn get_oversampling(&self) -> usize {
+        1 << self.oversample_log2.get()
+    }
+}
+
+impl<'a> hil::adc::AdcDifferential<'a> for Adc<'a> {
+    fn sample_differential(
+        &self,
+        positive: &Self::Channel,
+        negative: &Self::Channel,
+    ) -> Result<(), ErrorCode> {
+        if positive == negative {
+            return Err(ErrorCode::INVAL);
+        }
+        self.start_single(positive, Some(negative));
+        Ok(())
+    }
+}
@@ -36,12 +36,22 @@
 //! * Payload - 2 to 255 bytes
 //!
 //! * CRC - 3 bytes
+//!
+//! ### Connections
+//!
+//! For connections the radio answers packets T_IFS after they end with the
+//! shortcuts between its events and tasks. Received packets are written to
+//! `PAYLOAD` and transmitted packets are read from `RESPONSE`. The packet
+//! pointer is latched when the radio starts, so it is switched to the buffer
+//! of the next packet on the READY event of the current one.
 
 use core::cell::Cell;
 use core::ptr::addr_of;
 use core::ptr::addr_of_mut;
 use kernel::hil::ble_advertising;
 use kernel::hil::ble_advertising::RadioChannel;
+use kernel::hil::ble_connection;
+use kernel::platform::power::{SleepConstraint, SleepState};
 use kernel::utilities::cells::OptionalCell;
 use kernel::utilities::cells::TakeCell;
 use kernel::utilities::registers::interfaces::{Readable, Writeable};
@@ -535,12 +545,32 @@ register_bitfields! [u32,
 static mut PAYLOAD: [u8; nrf5x::constants::RADIO_PAYLOAD_LENGTH] =
     [0x00; nrf5x::constants::RADIO_PAYLOAD_LENGTH];
 
+static mut RESPONSE: [u8; nrf5x::constants::RADIO_PAYLOAD_LENGTH] =
+    [0x00; nrf5x::constants::RADIO_PAYLOAD_LENGTH];
+
+// BLUETOOTH SPECIFICATION Version 4.2 [Vol 6, Part B], section 4.1.1 Inter Frame Space
+const T_IFS_US: u32 = 150;
+
+/// Step of a connectable advertisement or of a connection event.
+#[derive(Clone, Copy, PartialEq)]
+enum ConnectionOperation {
+    None,
+    AdvertisingTx,
+    AdvertisingRx,
+    EventRx,
+    EventTx,
+}
+
 pub struct Radio<'a> {
     registers: StaticRef<RadioRegisters>,
     tx_power: Cell<TxPower>,
     rx_client: OptionalCell<&'a dyn ble_advertising::RxClient>,
     tx_client: OptionalCell<&'a dyn ble_advertising::TxClient>,
     buffer: TakeCell<'static, [u8]>,
+    connection_client: OptionalCell<&'a dyn ble_connection::ConnectionClient>,
+    operation: Cell<ConnectionOperation>,
+    /// Whether a packet is being received in the current operation.
+    address_received: Cell<bool>,
 }
 
 impl<'a> Radio<'a> {
@@ -551,6 +581,9 @@ impl<'a> Radio<'a> {
             rx_client: OptionalCell::empty(),
             tx_client: OptionalCell::empty(),
             buffer: TakeCell::empty(),
+            connection_client: OptionalCell::empty(),
+            operation: Cell::new(ConnectionOperation::None),
+            address_received: Cell::new(false),
         }
     }
 
@@ -602,6 +635,14 @@ impl<'a> Radio<'a> {
     pub fn handle_interrupt(&self) {
         self.disable_all_interrupts();
 
+        if self.operation.get() != ConnectionOperation::None {
+            self.handle_connection_interrupt();
+            if self.operation.get() != ConnectionOperation::None {
+                self.enable_connection_interrupts();
+            }
+            return;
+        }
+
         if self.registers.event_ready.is_set(Event::READY) {
             self.registers.event_ready.write(Event::READY::CLEAR);
             self.registers.event_end.write(Event::READY::CLEAR);
@@ -659,6 +700,154 @@ impl<'a> Radio<'a> {
         self.enable_interrupts();
     }
 
+    fn handle_connection_interrupt(&self) {
+        let operation = self.operation.get();
+
+        if self.registers.event_ready.is_set(Event::READY) {
+            self.registers.event_ready.write(Event::READY::CLEAR);
+            match operation {
+                // The radio started, so the pointer is used for the next packet.
+                ConnectionOperation::AdvertisingTx => self.set_dma_ptr(),
+                ConnectionOperation::EventRx => self.set_response_dma_ptr(),
+                // The second packet started, so the radio must stop after it.
+                ConnectionOperation::AdvertisingRx | ConnectionOperation::EventTx => {
+                    self.registers
+                        .shorts
+                        .write(Shortcut::READY_START::SET + Shortcut::END_DISABLE::SET);
+                }
+                ConnectionOperation::None => (),
+            }
+        }
+
+        if self.registers.event_address.is_set(Event::READY) {
+            self.registers.event_address.write(Event::READY::CLEAR);
+            if operation == ConnectionOperation::AdvertisingRx
+                || operation == ConnectionOperation::EventRx
+            {
+                self.address_received.set(true);
+            }
+        }
+
+        if !self.registers.event_end.is_set(Event::READY) {
+            return;
+        }
+        self.registers.event_end.write(Event::READY::CLEAR);
+
+        match operation {
+            ConnectionOperation::AdvertisingTx => {
+                self.operation.set(ConnectionOperation::AdvertisingRx);
+                self.second_packet_started(
+                    nrf5x::constants::RADIO_STATE_RXRU..=nrf5x::constants::RADIO_STATE_RX,
+                );
+            }
+            ConnectionOperation::EventRx => {
+                self.operation.set(ConnectionOperation::EventTx);
+                let crc_valid = self.registers.crcstatus.is_set(Event::READY);
+                self.buffer.map(|response| {
+                    self.connection_client.map(|client| {
+                        client.packet_received(Self::received_packet(), crc_valid, response)
+                    });
+                    // The update is only sent if the radio has not started
+                    // transmitting the response prepared before.
+                    match self.registers.state.get() {
+                        nrf5x::constants::RADIO_STATE_RXDISABLE
+                        | nrf5x::constants::RADIO_STATE_TXRU => {
+                            self.replace_response_buffer(response)
+                        }
+                        _ => (),
+                    }
+                });
+                self.second_packet_started(
+                    nrf5x::constants::RADIO_STATE_TXRU..=nrf5x::constants::RADIO_STATE_TX,
+                );
+            }
+            ConnectionOperation::AdvertisingRx | ConnectionOperation::EventTx => {
+                self.connection_operation_done();
+            }
+            ConnectionOperation::None => (),
+        }
+    }
+
+    /// Handle the start of the second packet of the operation, which runs
+    /// in the `running` states.
+    fn second_packet_started(&self, running: core::ops::RangeInclusive<u32>) {
+        let state = self.registers.state.get();
+        if running.contains(&state) {
+            self.registers
+                .shorts
+                .write(Shortcut::READY_START::SET + Shortcut::END_DISABLE::SET);
+        } else if state == nrf5x::constants::RADIO_STATE_DISABLE {
+            // The second packet ended before the first one was handled.
+            self.connection_operation_done();
+        }
+    }
+
+    fn connection_operation_done(&self) {
+        let operation = self.operation.get();
+        let crc_valid = self.registers.crcstatus.is_set(Event::READY);
+        self.connection_off();
+        self.buffer.take().map(|buf| {
+            self.connection_client.map(|client| match operation {
+                ConnectionOperation::AdvertisingRx => {
+                    client.advertisement_done(buf, crc_valid.then(Self::received_packet))
+                }
+                _ => client.connection_event_done(buf),
+            })
+        });
+    }
+
+    fn received_packet() -> &'static [u8] {
+        // Length is: S0 (1 Byte) + Length (1 Byte) + S1 (0 Bytes) + Payload
+        unsafe {
+            let payload = &*addr_of!(PAYLOAD);
+            &payload[..payload[1] as usize + 2]
+        }
+    }
+
+    fn connection_off(&self) {
+        self.radio_off();
+        self.operation.set(ConnectionOperation::None);
+        self.address_received.set(false);
+    }
+
+    fn enable_connection_interrupts(&self) {
+        self.registers
+            .intenset
+            .write(Interrupt::READY::SET + Interrupt::ADDRESS::SET + Interrupt::END::SET);
+    }
+
+    fn set_response_dma_ptr(&self) {
+        self.registers.packetptr.set(addr_of!(RESPONSE) as u32);
+    }
+
+    fn replace_response_buffer(&self, buf: &[u8]) {
+        let len = buf.len().min(nrf5x::constants::RADIO_PAYLOAD_LENGTH);
+        unsafe {
+            (*addr_of_mut!(RESPONSE))[..len].copy_from_slice(&buf[..len]);
+        }
+    }
+
+    /// Start the first packet of a connection operation, with `second` the
+    /// shortcut to the second packet.
+    fn start_connection_operation(
+        &self,
+        operation: ConnectionOperation,
+        second: kernel::utilities::registers::FieldValue<u32, Shortcut::Register>,
+    ) {
+        self.operation.set(operation);
+        self.address_received.set(false);
+        self.registers
+            .tifs
+            .write(InterFrameSpacing::TIFS.val(T_IFS_US));
+        self.registers
+            .shorts
+            .write(Shortcut::READY_START::SET + Shortcut::END_DISABLE::SET + second);
+        self.registers.event_ready.write(Event::READY::CLEAR);
+        self.registers.event_address.write(Event::READY::CLEAR);
+        self.registers.event_end.write(Event::READY::CLEAR);
+        self.enable_connection_interrupts();
+    }
+
     pub fn enable_interrupts(&self) {
         self.registers.intenset.write(
             Interrupt::READY::SET
@@ -818,6 +1007,72 @@ impl<'a> ble_advertising::BleAdvertisementDriver<'a> for Radio<'a> {
     }
 }
 
+impl<'a> ble_connection::BleConnectionRadio<'a> for Radio<'a> {
+    fn set_connection_client(&self, client: &'a dyn ble_connection::ConnectionClient) {
+        self.connection_client.set(client);
+    }
+
+    fn advertise_connectable(&self, buf: &'static mut [u8], len: usize, channel: RadioChannel) {
+        self.replace_response_buffer(&buf[..len.min(buf.len())]);
+        self.buffer.replace(buf);
+        self.ble_initialize(channel);
+        self.set_response_dma_ptr();
+        self.start_connection_operation(
+            ConnectionOperation::AdvertisingTx,
+            Shortcut::DISABLED_RXEN::SET,
+        );
+        self.registers.task_txen.write(Task::ENABLE::SET);
+    }
+
+    fn connection_event(
+        &self,
+        channel: RadioChannel,
+        access_address: u32,
+        crc_init: u32,
+        response: &'static mut [u8],
+    ) {
+        self.replace_response_buffer(response);
+        self.buffer.replace(response);
+        self.ble_initialize(channel);
+        self.registers.prefix0.set(access_address >> 24);
+        self.registers.base0.set(access_address << 8);
+        self.registers
+            .crcinit
+            .write(CrcInitialValue::CRCINIT.val(crc_init));
+        self.start_connection_operation(ConnectionOperation::EventRx, Shortcut::DISABLED_TXEN::SET);
+        self.registers.task_rxen.write(Task::ENABLE::SET);
+    }
+
+    fn stop(&self) -> Option<&'static mut [u8]> {
+        let receiving = match self.operation.get() {
+            ConnectionOperation::None | ConnectionOperation::EventTx => return None,
+            ConnectionOperation::AdvertisingTx => false,
+            ConnectionOperation::AdvertisingRx | ConnectionOperation::EventRx => {
+                self.address_received.get()
+                    || (self.registers.event_address.is_set(Event::READY)
+                        && self.registers.state.get() == nrf5x::constants::RADIO_STATE_RX)
+            }
+        };
+        if receiving {
+            return None;
+        }
+        self.disable_all_interrupts();
+        self.connection_off();
+        self.buffer.take()
+    }
+}
+
+/// The radio needs the HFXO while it is not disabled.
+impl SleepConstraint for Radio<'_> {
+    fn max_wakeup_latency_us(&self) -> Option<u32> {
+        (!self.registers.state.matches_all(State::STATE::DISABLED)).then_some(0)
+    }
+
+    fn wakes_from(&self, _state: SleepState) -> bool {
+        false
+    }
+}
+
 impl ble_advertising::BleConfig for Radio<'_> {
     // The BLE Advertising Driver validates that the `tx_power` is between -20 to 10 dBm but then
     // underlying chip must validate if the current `tx_power` is supported as well
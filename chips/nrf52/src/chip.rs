@@ -29,11 +29,13 @@ impl<'a, I: InterruptService + 'a> NRF52<'a, I> {
 /// constructed manually in main.rs.
 pub struct Nrf52DefaultPeripherals<'a> {
     pub acomp: crate::acomp::Comparator<'a>,
+    pub lpcomp: crate::lpcomp::Lpcomp<'a>,
     pub ecb: crate::aes::AesECB<'a>,
     pub pwr_clk: crate::power::Power<'a>,
     pub ble_radio: crate::ble_radio::Radio<'a>,
     pub trng: crate::trng::Trng<'a>,
     pub rtc: crate::rtc::Rtc<'a>,
+    pub rtc2: crate::rtc::RtcSchedulerTimer,
     pub temp: crate::temperature::Temp<'a>,
     pub timer0: crate::timer::TimerAlarm<'a>,
     pub timer1: crate::timer::TimerAlarm<'a>,
@@ -42,6 +44,8 @@ pub struct Nrf52DefaultPeripherals<'a> {
     pub spim0: crate::spi::SPIM<'a>,
     pub twi1: crate::i2c::TWI<'a>,
     pub spim2: crate::spi::SPIM<'a>,
+    /// Shares its registers with `spim2`.
+    pub spis2: crate::spis::SPIS<'a>,
     pub adc: crate::adc::Adc<'a>,
     pub nvmc: crate::nvmc::Nvmc,
     pub clock: crate::clock::Clock,
@@ -52,11 +56,13 @@ impl Nrf52DefaultPeripherals<'_> {
     pub fn new() -> Self {
         Self {
             acomp: crate::acomp::Comparator::new(),
+            lpcomp: crate::lpcomp::Lpcomp::new(),
             ecb: crate::aes::AesECB::new(),
             pwr_clk: crate::power::Power::new(),
             ble_radio: crate::ble_radio::Radio::new(),
             trng: crate::trng::Trng::new(),
             rtc: crate::rtc::Rtc::new(),
+            rtc2: crate::rtc::RtcSchedulerTimer::new_rtc2(),
             temp: crate::temperature::Temp::new(),
             timer0: crate::timer::TimerAlarm::new(0),
             timer1: crate::timer::TimerAlarm::new(1),
@@ -65,6 +71,7 @@ impl Nrf52DefaultPeripherals<'_> {
             spim0: crate::spi::SPIM::new(0),
             twi1: crate::i2c::TWI::new_twi1(),
             spim2: crate::spi::SPIM::new(2),
+            spis2: crate::spis::SPIS::new(2),
             // Default to 3.3 V VDD reference.
             adc: crate::adc::Adc::new(3300),
             nvmc: crate::nvmc::Nvmc::new(),
@@ -80,7 +87,12 @@ impl Nrf52DefaultPeripherals<'_> {
 impl kernel::platform::chip::InterruptService for Nrf52DefaultPeripherals<'_> {
     unsafe fn service_interrupt(&self, interrupt: u32) -> bool {
         match interrupt {
-            crate::peripheral_interrupts::COMP => self.acomp.handle_interrupt(),
+            // The comparator and the LPCOMP share this interrupt. Only the
+            // enabled one handles it.
+            crate::peripheral_interrupts::COMP => {
+                self.acomp.handle_interrupt();
+                self.lpcomp.handle_interrupt();
+            }
             crate::peripheral_interrupts::ECB => self.ecb.handle_interrupt(),
             crate::peripheral_interrupts::POWER_CLOCK => self.pwr_clk.handle_interrupt(),
             crate::peripheral_interrupts::RADIO => match self.ble_radio.is_enabled() {
@@ -89,6 +101,7 @@ impl kernel::platform::chip::InterruptService for Nrf52DefaultPeripherals<'_> {
             },
             crate::peripheral_interrupts::RNG => self.trng.handle_interrupt(),
             crate::peripheral_interrupts::RTC1 => self.rtc.handle_interrupt(),
+            crate::peripheral_interrupts::RTC2 => self.rtc2.handle_interrupt(),
             crate::peripheral_interrupts::TEMP => self.temp.handle_interrupt(),
             crate::peripheral_interrupts::TIMER0 => self.timer0.handle_interrupt(),
             crate::peripheral_interrupts::TIMER1 => self.timer1.handle_interrupt(),
@@ -96,7 +109,10 @@ impl kernel::platform::chip::InterruptService for Nrf52DefaultPeripherals<'_> {
             crate::peripheral_interrupts::UART0 => self.uarte0.handle_interrupt(),
             crate::peripheral_interrupts::SPI0_TWI0 => self.spim0.handle_interrupt(),
             crate::peripheral_interrupts::SPI1_TWI1 => self.twi1.handle_interrupt(),
-            crate::peripheral_interrupts::SPIM2_SPIS2_SPI2 => self.spim2.handle_interrupt(),
+            crate::peripheral_interrupts::SPIM2_SPIS2_SPI2 => {
+                self.spim2.handle_interrupt();
+                self.spis2.handle_interrupt();
+            }
             crate::peripheral_interrupts::ADC => self.adc.handle_interrupt(),
             _ => return false,
         }
@@ -0,0 +1,94 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Workarounds for anomalies of the nRF52 family, applied by
+//! [`crate::init`].
+//!
+//! The workarounds are documented in the nRF52832 errata, and have always
+//! been applied on every nRF52 chip Tock supports. They stay so until each
+//! one is checked against the errata of the other parts and revisions. The
+//! checks read back what the workaround wrote.
+
+use kernel::platform::errata::Erratum;
+
+const NRF52832_ERRATA: &str = "nRF52832 Errata v1.2";
+
+fn all_nrf52() -> bool {
+    true
+}
+
+unsafe fn read(address: usize) -> u32 {
+    core::ptr::read_volatile(address as *const u32)
+}
+
+unsafe fn write(address: usize, value: u32) {
+    core::ptr::write_volatile(address as *mut u32, value)
+}
+
+/// Values written by the workaround for errata 57.
+const ERRATA_57_WRITES: [(usize, u32); 4] = [
+    (0x40005610, 0x5),
+    (0x40005688, 0x1),
+    (0x40005618, 0x0),
+    (0x40005614, 0x3f),
+];
+
+pub static ERRATA: [Erratum; 6] = [
+    Erratum {
+        id: "nRF52832-12",
+        summary: "COMP: Reference ladder not correctly calibrated",
+        document: NRF52832_ERRATA,
+        affects: all_nrf52,
+        apply: || unsafe { write(0x40013540, (read(0x10000324) & 0x1f00) >> 8) },
+        check: Some(|| unsafe { read(0x40013540) == (read(0x10000324) & 0x1f00) >> 8 }),
+    },
+    Erratum {
+        id: "nRF52832-16",
+        summary: "System: RAM may be corrupt on wakeup from CPU IDLE",
+        document: NRF52832_ERRATA,
+        affects: all_nrf52,
+        apply: || unsafe { write(0x4007c074, 3131961357) },
+        check: Some(|| unsafe { read(0x4007c074) == 3131961357 }),
+    },
+    Erratum {
+        id: "nRF52832-31",
+        summary: "CLOCK: Calibration values are not correctly loaded from FICR at reset",
+        document: NRF52832_ERRATA,
+        affects: all_nrf52,
+        apply: || unsafe { write(0x4000053c, (read(0x10000244) & 0xe000) >> 13) },
+        check: Some(|| unsafe { read(0x4000053c) == (read(0x10000244) & 0xe000) >> 13 }),
+    },
+    Erratum {
+        id: "nRF52832-37",
+        summary: "RADIO: Encryption engine is slow by default",
+        document: NRF52832_ERRATA,
+        affects: all_nrf52,
+        apply: || unsafe { write(0x400005a0, 0x3) },
+        check: Some(|| unsafe { read(0x400005a0) == 0x3 }),
+    },
+    Erratum {
+        id: "nRF52832-57",
+        summary: "NFCT: NFC Modulation amplitude",
+        document: NRF52832_ERRATA,
+        affects: all_nrf52,
+        apply: || {
+            for (address, value) in ERRATA_57_WRITES {
+                unsafe { write(address, value) };
+            }
+        },
+        check: Some(|| {
+            ERRATA_57_WRITES
+                .iter()
+                .all(|(address, value)| unsafe { read(*address) } == *value)
+        }),
+    },
+    Erratum {
+        id: "nRF52832-108",
+        summary: "RAM: RAM content cannot be trusted upon waking up from System ON Idle or System OFF mode",
+        document: NRF52832_ERRATA,
+        affects: all_nrf52,
+        apply: || unsafe { write(0x40000ee4, read(0x10000258) & 0x4f) },
+        check: Some(|| unsafe { read(0x40000ee4) == read(0x10000258) & 0x4f }),
+    },
+];
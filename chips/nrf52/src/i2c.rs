@@ -403,6 +403,38 @@ impl<'a> hil::i2c::I2CSlave<'a> for TWI<'a> {
     }
 }
 
+/// The TWIS responds on two addresses.
+impl<'a> hil::i2c::I2CSlaveMultiAddress<'a> for TWI<'a> {
+    fn address_count(&self) -> usize {
+        2
+    }
+
+    fn set_address_at(&self, index: usize, addr: u8) -> Result<(), hil::i2c::Error> {
+        match index {
+            0 => hil::i2c::I2CSlave::set_address(self, addr),
+            1 => {
+                self.registers
+                    .address_1
+                    .write(ADDRESS::ADDRESS.val(addr as u32));
+                self.registers.config.modify(CONFIG::ADDRESS1::Enable);
+                Ok(())
+            }
+            _ => Err(hil::i2c::Error::NotSupported),
+        }
+    }
+
+    fn matching_address(&self) -> Option<u8> {
+        if !self.is_slave_enabled() {
+            return None;
+        }
+        let address = match self.registers.match_reg.get() {
+            0 => &self.registers.address_0,
+            _ => &self.registers.address_1,
+        };
+        Some(address.read(ADDRESS::ADDRESS) as u8)
+    }
+}
+
 impl<'a> hil::i2c::I2CMasterSlave<'a> for TWI<'a> {}
 
 // The SPI0_TWI0 and SPI1_TWI1 interrupts are dispatched to the
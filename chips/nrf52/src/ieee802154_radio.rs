@@ -68,6 +68,7 @@ use core::cell::Cell;
 use kernel::deferred_call::{DeferredCall, DeferredCallClient};
 use kernel::hil::radio::{self, PowerClient, RadioChannel, RadioConfig, RadioData};
 use kernel::hil::time::{Alarm, AlarmClient, Time};
+use kernel::platform::power::{SleepConstraint, SleepState};
 use kernel::utilities::cells::{OptionalCell, TakeCell};
 use kernel::utilities::registers::interfaces::{Readable, Writeable};
 use kernel::utilities::registers::{register_bitfields, ReadOnly, ReadWrite, WriteOnly};
@@ -1394,6 +1395,17 @@ impl<'a> kernel::hil::radio::RadioData<'a> for Radio<'a> {
     }
 }
 
+/// The radio needs the HFXO while it is on.
+impl SleepConstraint for Radio<'_> {
+    fn max_wakeup_latency_us(&self) -> Option<u32> {
+        self.radio_is_on().then_some(0)
+    }
+
+    fn wakes_from(&self, _state: SleepState) -> bool {
+        false
+    }
+}
+
 impl DeferredCallClient for Radio<'_> {
     fn handle_deferred_call(&self) {
         // On deferred call we trigger the config or power callbacks. The
@@ -13,14 +13,18 @@ pub mod ble_radio;
 pub mod chip;
 pub mod clock;
 pub mod crt1;
+pub mod errata;
 pub mod ficr;
 pub mod i2c;
 pub mod ieee802154_radio;
+pub mod lpcomp;
 pub mod nvmc;
 pub mod power;
 pub mod ppi;
 pub mod pwm;
 pub mod spi;
+pub mod spis;
+pub mod timer_capture;
 pub mod uart;
 pub mod uicr;
 pub mod usbd;
@@ -0,0 +1,321 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Low-power comparator (LPCOMP), for nrf52.
+//!
+//! The LPCOMP compares an analog input pin (AIN0-AIN7) against a fraction of
+//! VDD, or against an external reference on AIN0 or AIN1. Unlike the
+//! [comparator](crate::acomp), it keeps running in System OFF, and its
+//! detection event wakes the chip from System OFF. This makes it a wake source
+//! for analog signals, such as a capacitive touch pad behind an RC filter.
+//!
+//! The LPCOMP shares its registers and its interrupt with the comparator: only
+//! one of them can be used at a time.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! base_peripherals.lpcomp.configure(
+//!     nrf52::lpcomp::Reference::Vdd4_8,
+//!     nrf52::lpcomp::Detect::Up,
+//!     true,
+//! );
+//! AnalogComparator::start_comparing(&base_peripherals.lpcomp, &nrf52::lpcomp::Input::Ain2)?;
+//! ```
+
+use core::cell::Cell;
+use kernel::hil::analog_comparator;
+use kernel::platform::power::{SleepConstraint, SleepState};
+use kernel::utilities::cells::OptionalCell;
+use kernel::utilities::registers::interfaces::{Readable, Writeable};
+use kernel::utilities::registers::{
+    register_bitfields, register_structs, ReadOnly, ReadWrite, WriteOnly,
+};
+use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
+
+const LPCOMP_BASE: StaticRef<LpcompRegisters> =
+    unsafe { StaticRef::new(0x40013000 as *const LpcompRegisters) };
+
+register_structs! {
+    LpcompRegisters {
+        (0x000 => tasks_start: WriteOnly<u32>),
+        (0x004 => tasks_stop: WriteOnly<u32>),
+        /// Sample the comparator value into RESULT
+        (0x008 => tasks_sample: WriteOnly<u32>),
+        (0x00C => _reserved0),
+        (0x100 => events_ready: ReadWrite<u32>),
+        /// VIN+ crossed VIN- downwards
+        (0x104 => events_down: ReadWrite<u32>),
+        /// VIN+ crossed VIN- upwards
+        (0x108 => events_up: ReadWrite<u32>),
+        /// VIN+ crossed VIN- in either direction
+        (0x10C => events_cross: ReadWrite<u32>),
+        (0x110 => _reserved1),
+        (0x304 => intenset: ReadWrite<u32, Interrupt::Register>),
+        (0x308 => intenclr: ReadWrite<u32, Interrupt::Register>),
+        (0x30C => _reserved2),
+        (0x400 => result: ReadOnly<u32, ComparisonResult::Register>),
+        (0x404 => _reserved3),
+        (0x500 => enable: ReadWrite<u32, Enable::Register>),
+        /// Input pin for VIN+
+        (0x504 => psel: ReadWrite<u32>),
+        /// Reference for VIN-
+        (0x508 => refsel: ReadWrite<u32>),
+        /// External reference pin for VIN-
+        (0x50C => extrefsel: ReadWrite<u32>),
+        (0x510 => _reserved4),
+        /// Event that wakes the chip from System OFF
+        (0x520 => anadetect: ReadWrite<u32, AnaDetect::Register>),
+        (0x524 => _reserved5),
+        /// 50 mV hysteresis
+        (0x538 => hyst: ReadWrite<u32, Hysteresis::Register>),
+        (0x53C => @END),
+    }
+}
+
+register_bitfields! [u32,
+    Interrupt [
+        READY OFFSET(0) NUMBITS(1),
+        DOWN OFFSET(1) NUMBITS(1),
+        UP OFFSET(2) NUMBITS(1),
+        CROSS OFFSET(3) NUMBITS(1)
+    ],
+    ComparisonResult [
+        RESULT OFFSET(0) NUMBITS(1) [
+            Below = 0,
+            Above = 1
+        ]
+    ],
+    Enable [
+        ENABLE OFFSET(0) NUMBITS(2) [
+            Disabled = 0,
+            Enabled = 1
+        ]
+    ],
+    AnaDetect [
+        ANADETECT OFFSET(0) NUMBITS(2) [
+            Cross = 0,
+            Up = 1,
+            Down = 2
+        ]
+    ],
+    Hysteresis [
+        HYST OFFSET(0) NUMBITS(1)
+    ]
+];
+
+/// Analog input pin compared by the LPCOMP.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Input {
+    Ain0 = 0,
+    Ain1 = 1,
+    Ain2 = 2,
+    Ain3 = 3,
+    Ain4 = 4,
+    Ain5 = 5,
+    Ain6 = 6,
+    Ain7 = 7,
+}
+
+/// Reference the input is compared against: a fraction of VDD, or an external
+/// reference pin.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Reference {
+    Vdd1_8,
+    Vdd2_8,
+    Vdd3_8,
+    Vdd4_8,
+    Vdd5_8,
+    Vdd6_8,
+    Vdd7_8,
+    Vdd1_16,
+    Vdd3_16,
+    Vdd5_16,
+    Vdd7_16,
+    Vdd9_16,
+    Vdd11_16,
+    Vdd13_16,
+    Vdd15_16,
+    ExternalAin0,
+    ExternalAin1,
+}
+
+impl Reference {
+    /// Values of the REFSEL and EXTREFSEL registers.
+    fn registers(self) -> (u32, u32) {
+        match self {
+            Reference::Vdd1_8 => (0, 0),
+            Reference::Vdd2_8 => (1, 0),
+            Reference::Vdd3_8 => (2, 0),
+            Reference::Vdd4_8 => (3, 0),
+            Reference::Vdd5_8 => (4, 0),
+            Reference::Vdd6_8 => (5, 0),
+            Reference::Vdd7_8 => (6, 0),
+            Reference::ExternalAin0 => (7, 0),
+            Reference::ExternalAin1 => (7, 1),
+            Reference::Vdd1_16 => (8, 0),
+            Reference::Vdd3_16 => (9, 0),
+            Reference::Vdd5_16 => (10, 0),
+            Reference::Vdd7_16 => (11, 0),
+            Reference::Vdd9_16 => (12, 0),
+            Reference::Vdd11_16 => (13, 0),
+            Reference::Vdd13_16 => (14, 0),
+            Reference::Vdd15_16 => (15, 0),
+        }
+    }
+}
+
+/// Crossing of the reference that interrupts the kernel, and wakes the chip
+/// from System OFF.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Detect {
+    /// The input rises above the reference.
+    Up,
+    /// The input falls below the reference.
+    Down,
+    /// Either way.
+    Cross,
+}
+
+pub struct Lpcomp<'a> {
+    registers: StaticRef<LpcompRegisters>,
+    reference: Cell<Reference>,
+    detect: Cell<Detect>,
+    hysteresis: Cell<bool>,
+    input: Cell<Option<Input>>,
+    client: OptionalCell<&'a dyn analog_comparator::Client>,
+}
+
+impl Lpcomp<'_> {
+    pub const fn new() -> Self {
+        Self {
+            registers: LPCOMP_BASE,
+            reference: Cell::new(Reference::Vdd4_8),
+            detect: Cell::new(Detect::Up),
+            hysteresis: Cell::new(false),
+            input: Cell::new(None),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    /// Set the reference, the crossing that is detected and whether to add
+    /// 50 mV of hysteresis. This applies from the next comparison started.
+    pub fn configure(&self, reference: Reference, detect: Detect, hysteresis: bool) {
+        self.reference.set(reference);
+        self.detect.set(detect);
+        self.hysteresis.set(hysteresis);
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.registers.enable.matches_all(Enable::ENABLE::Enabled)
+    }
+
+    fn enable(&self, input: Input) {
+        if self.is_enabled() && self.input.get() == Some(input) {
+            return;
+        }
+        self.disable();
+
+        let (refsel, extrefsel) = self.reference.get().registers();
+        self.registers.psel.set(input as u32);
+        self.registers.refsel.set(refsel);
+        self.registers.extrefsel.set(extrefsel);
+        self.registers.anadetect.write(match self.detect.get() {
+            Detect::Up => AnaDetect::ANADETECT::Up,
+            Detect::Down => AnaDetect::ANADETECT::Down,
+            Detect::Cross => AnaDetect::ANADETECT::Cross,
+        });
+        self.registers
+            .hyst
+            .write(Hysteresis::HYST.val(self.hysteresis.get() as u32));
+
+        self.registers.enable.write(Enable::ENABLE::Enabled);
+        self.registers.events_ready.set(0);
+        self.registers.tasks_start.set(1);
+        // The LPCOMP is ready within about 140 µs.
+        while self.registers.events_ready.get() == 0 {}
+        self.input.set(Some(input));
+    }
+
+    fn disable(&self) {
+        self.registers.intenclr.set(0xF);
+        if self.is_enabled() {
+            self.registers.tasks_stop.set(1);
+            self.registers.enable.write(Enable::ENABLE::Disabled);
+        }
+        self.input.set(None);
+    }
+
+    pub fn handle_interrupt(&self) {
+        // The comparator shares this interrupt.
+        if !self.is_enabled() {
+            return;
+        }
+        let detected = match self.detect.get() {
+            Detect::Up => &self.registers.events_up,
+            Detect::Down => &self.registers.events_down,
+            Detect::Cross => &self.registers.events_cross,
+        };
+        if detected.get() == 1 {
+            detected.set(0);
+            self.input.get().map(|input| {
+                self.client.map(|client| client.fired(input as usize));
+            });
+        }
+    }
+}
+
+/// The LPCOMP can wake the chip from System OFF while it is enabled.
+impl SleepConstraint for Lpcomp<'_> {
+    fn max_wakeup_latency_us(&self) -> Option<u32> {
+        None
+    }
+
+    fn wakes_from(&self, _state: SleepState) -> bool {
+        self.is_enabled()
+    }
+}
+
+impl<'a> analog_comparator::AnalogComparator<'a> for Lpcomp<'a> {
+    type Channel = Input;
+
+    /// Whether the input is above the reference. This enables the LPCOMP on
+    /// `channel` if it is not comparing it yet.
+    fn comparison(&self, channel: &Self::Channel) -> bool {
+        self.enable(*channel);
+        self.registers.tasks_sample.set(1);
+        self.registers
+            .result
+            .matches_all(ComparisonResult::RESULT::Above)
+    }
+
+    /// Interrupt on the configured crossing of `channel`. The LPCOMP keeps
+    /// comparing in System OFF, and wakes the chip on that crossing.
+    fn start_comparing(&self, channel: &Self::Channel) -> Result<(), ErrorCode> {
+        self.enable(*channel);
+        self.registers.events_up.set(0);
+        self.registers.events_down.set(0);
+        self.registers.events_cross.set(0);
+        self.registers.intenset.write(match self.detect.get() {
+            Detect::Up => Interrupt::UP::SET,
+            Detect::Down => Interrupt::DOWN::SET,
+            Detect::Cross => Interrupt::CROSS::SET,
+        });
+        Ok(())
+    }
+
+    fn stop_comparing(&self, channel: &Self::Channel) -> Result<(), ErrorCode> {
+        if self.input.get() != Some(*channel) {
+            return Err(ErrorCode::INVAL);
+        }
+        self.disable();
+        Ok(())
+    }
+
+    fn set_client(&self, client: &'a dyn analog_comparator::Client) {
+        self.client.set(client);
+    }
+}
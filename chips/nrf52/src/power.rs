@@ -10,6 +10,7 @@ use kernel::utilities::registers::{
     register_bitfields, register_structs, ReadOnly, ReadWrite, WriteOnly,
 };
 use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
 
 const POWER_BASE: StaticRef<PowerRegisters> =
     unsafe { StaticRef::new(0x40000000 as *const PowerRegisters) };
@@ -370,3 +371,95 @@ impl<'a> Power<'a> {
         self.registers.gpregret.write(Byte::VALUE.val(val as u32));
     }
 }
+
+/// What woke the chip from System OFF.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WakeSource {
+    /// A pin reached the level it senses.
+    Gpio,
+    Lpcomp,
+    Nfc,
+    /// The debug interface.
+    Debug,
+    /// VBUS was connected.
+    Vbus,
+}
+
+impl Power<'_> {
+    /// What woke the chip from System OFF, if the last reset was a wake up.
+    /// The reset reason accumulates over resets until
+    /// [`Power::clear_reset_reason`] is called.
+    pub fn wake_source(&self) -> Option<WakeSource> {
+        let reason = self.registers.resetreas.extract();
+        if reason.is_set(ResetReason::OFF) {
+            Some(WakeSource::Gpio)
+        } else if reason.is_set(ResetReason::LPCOMP) {
+            Some(WakeSource::Lpcomp)
+        } else if reason.is_set(ResetReason::NFC) {
+            Some(WakeSource::Nfc)
+        } else if reason.is_set(ResetReason::DIF) {
+            Some(WakeSource::Debug)
+        } else if reason.is_set(ResetReason::VBUS) {
+            Some(WakeSource::Vbus)
+        } else {
+            None
+        }
+    }
+
+    pub fn clear_reset_reason(&self) {
+        // Reset reason bits are cleared by writing 1 to them.
+        self.registers.resetreas.set(0xFFFF_FFFF);
+    }
+
+    /// Keep the sections of RAM bank `bank` set in `sections` powered in
+    /// System OFF, so that they hold their contents after a wake up.
+    pub fn set_ram_retention(&self, bank: usize, sections: u16) {
+        if let Some(ram) = self.registers.ram.get(bank) {
+            ram.powerset.set((sections as u32) << 16);
+        }
+    }
+
+    /// Enter System OFF. The chip only leaves System OFF through a reset from
+    /// one of the [`WakeSource`]s, so this never returns.
+    pub fn system_off(&self) -> ! {
+        self.registers.systemoff.write(Task::ENABLE::SET);
+        // The chip is off once the write completes. In debug interface mode,
+        // System OFF is emulated and the CPU keeps running.
+        loop {
+            // SAFETY: Waiting for an interrupt has no side effect.
+            unsafe { cortexm4f::support::wfi() };
+        }
+    }
+}
+
+/// GPREGRET and GPREGRET2, which retain eight bits each across a soft reset.
+impl kernel::hil::retained::RetainedRegisters for Power<'_> {
+    fn count(&self) -> usize {
+        2
+    }
+
+    fn width(&self) -> usize {
+        8
+    }
+
+    fn read(&self, index: usize) -> Result<u32, ErrorCode> {
+        match index {
+            0 => Ok(self.registers.gpregret.read(Byte::VALUE)),
+            1 => Ok(self.registers.gpregret2.read(Byte::VALUE)),
+            _ => Err(ErrorCode::INVAL),
+        }
+    }
+
+    fn write(&self, index: usize, value: u32) -> Result<(), ErrorCode> {
+        let register = match index {
+            0 => &self.registers.gpregret,
+            1 => &self.registers.gpregret2,
+            _ => return Err(ErrorCode::INVAL),
+        };
+        if value > 0xFF {
+            return Err(ErrorCode::SIZE);
+        }
+        register.write(Byte::VALUE.val(value));
+        Ok(())
+    }
+}
@@ -44,6 +44,15 @@ use kernel::utilities::StaticRef;
 const PPI_BASE: StaticRef<PpiRegisters> =
     unsafe { StaticRef::new(0x4001F000 as *const PpiRegisters) };
 
+/// Number of channels that can be connected to any event and task.
+pub const NUM_PROGRAMMABLE_CHANNELS: usize = 20;
+
+#[repr(C)]
+struct PpiChannel {
+    eep: ReadWrite<u32, EventEndPoint::Register>,
+    tep: ReadWrite<u32, TaskEndPoint::Register>,
+}
+
 #[repr(C)]
 struct PpiRegisters {
     tasks_chg0_en: ReadWrite<u32, Control::Register>,
@@ -62,49 +71,11 @@ struct PpiRegisters {
     chen: ReadWrite<u32, Channel::Register>,
     chenset: ReadWrite<u32, Channel::Register>,
     chenclr: ReadWrite<u32, Channel::Register>,
-    ch0_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch0_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    ch1_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch1_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    ch2_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch2_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    ch3_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch3_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    ch4_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch4_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    ch5_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch5_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    ch6_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch6_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    ch7_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch7_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    ch8_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch8_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    ch9_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch9_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    ch10_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch10_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    ch11_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch11_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    ch12_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch12_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    ch13_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch13_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    ch14_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch14_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    ch15_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch15_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    ch16_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch16_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    ch17_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch17_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    ch18_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch18_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    ch19_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch19_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    _reserved2: [u32; 148],
+    _reserved2: u32,
+    ch: [PpiChannel; NUM_PROGRAMMABLE_CHANNELS],
+    _reserved3: [u32; 148],
     chg: [ReadWrite<u32, Channel::Register>; 6],
-    _reserved3: [u32; 62],
+    _reserved4: [u32; 62],
     fork_tep: [ReadWrite<u32, TaskEndPoint::Register>; 32],
 }
 
@@ -172,4 +143,29 @@ impl Ppi {
     pub fn disable(&self, channels: FieldValue<u32, Channel::Register>) {
         self.registers.chenclr.write(channels);
     }
+
+    /// Connect the event at address `event` to the task at address `task`
+    /// through the programmable `channel`. The channel must be disabled.
+    pub fn connect(&self, channel: usize, event: u32, task: u32) {
+        if let Some(ch) = self.registers.ch.get(channel) {
+            ch.eep.write(EventEndPoint::ADDRESS.val(event));
+            ch.tep.write(TaskEndPoint::ADDRESS.val(task));
+        }
+    }
+
+    /// Also trigger the task at address `task` when the event of the
+    /// programmable `channel` occurs.
+    pub fn fork(&self, channel: usize, task: u32) {
+        if let Some(fork) = self.registers.fork_tep.get(channel) {
+            fork.write(TaskEndPoint::ADDRESS.val(task));
+        }
+    }
+
+    pub fn enable_channel(&self, channel: usize) {
+        self.registers.chenset.set(1 << channel);
+    }
+
+    pub fn disable_channel(&self, channel: usize) {
+        self.registers.chenclr.set(1 << channel);
+    }
 }
@@ -5,7 +5,7 @@
 //! Implementation of SPI for NRF52 using EasyDMA.
 //!
 //! This file only implements support for the three SPI master (`SPIM`)
-//! peripherals, and not SPI slave (`SPIS`).
+//! peripherals. SPI slaves (`SPIS`) are implemented in [`crate::spis`].
 //!
 //! Although `kernel::hil::spi::SpiMaster` is implemented for `SPIM`,
 //! only the functions marked with `x` are fully defined:
@@ -265,6 +265,12 @@ impl<'a> SPIM<'a> {
 
     #[inline(never)]
     pub fn handle_interrupt(&self) {
+        // The SPIS of this instance shares the interrupt, and the events of
+        // the SPIS are at the same addresses as other events of the SPIM.
+        if !self.is_enabled() {
+            return;
+        }
+
         if self.registers.events_end.is_set(EVENT::EVENT) {
             // End of RXD buffer and TXD buffer reached
 
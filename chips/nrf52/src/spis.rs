@@ -0,0 +1,366 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! SPI slave (`SPIS`) with EasyDMA, for nrf52.
+//!
+//! The SPIS exchanges data with an external SPI controller. Its buffers are
+//! shared between the CPU and the SPIS with a hardware semaphore: the CPU
+//! acquires the semaphore to set up the buffers of the next transaction, and
+//! releases it to the SPIS. The controller then selects the peripheral and
+//! clocks the transaction, which ends when it deselects the peripheral. While
+//! the CPU holds the semaphore, the SPIS answers with the default character
+//! and drops what it receives.
+//!
+//! An SPIS shares its registers and its interrupt with the SPIM and TWI of
+//! the same instance: only one of them can be used at a time.
+//!
+//! The SPIS has no event for the chip select becoming active, so
+//! `SpiSlaveClient::chip_selected` is never called.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! base_peripherals.spis2.configure(
+//!     nrf52840::pinmux::Pinmux::new(SPIS_MOSI as u32),
+//!     nrf52840::pinmux::Pinmux::new(SPIS_MISO as u32),
+//!     nrf52840::pinmux::Pinmux::new(SPIS_SCK as u32),
+//!     nrf52840::pinmux::Pinmux::new(SPIS_CSN as u32),
+//! );
+//! let spi_peripheral = components::spi::SpiSyscallPComponent::new(
+//!     board_kernel,
+//!     &base_peripherals.spis2,
+//!     capsules_core::spi_peripheral::DRIVER_NUM,
+//! )
+//! .finalize(components::spi_syscallp_component_static!(nrf52840::spis::SPIS));
+//! ```
+
+use core::cell::Cell;
+use core::cmp;
+use kernel::hil::spi::{ClockPhase, ClockPolarity, SpiSlave, SpiSlaveClient};
+use kernel::utilities::cells::{OptionalCell, TakeCell, VolatileCell};
+use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
+use kernel::utilities::registers::{
+    register_bitfields, register_structs, ReadOnly, ReadWrite, WriteOnly,
+};
+use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
+use nrf5x::pinmux::Pinmux;
+
+const INSTANCES: [StaticRef<SpisRegisters>; 3] = unsafe {
+    [
+        StaticRef::new(0x40003000 as *const SpisRegisters),
+        StaticRef::new(0x40004000 as *const SpisRegisters),
+        StaticRef::new(0x40023000 as *const SpisRegisters),
+    ]
+};
+
+/// Longest transaction EasyDMA supports on all nrf52 chips.
+const MAX_TRANSFER_LEN: usize = 255;
+
+register_structs! {
+    SpisRegisters {
+        (0x000 => _reserved0),
+        /// Acquire the semaphore
+        (0x024 => tasks_acquire: WriteOnly<u32>),
+        /// Release the semaphore to the SPIS
+        (0x028 => tasks_release: WriteOnly<u32>),
+        (0x02C => _reserved1),
+        /// The controller ended a transaction
+        (0x104 => events_end: ReadWrite<u32>),
+        (0x108 => _reserved2),
+        (0x110 => events_endrx: ReadWrite<u32>),
+        (0x114 => _reserved3),
+        /// The CPU acquired the semaphore
+        (0x128 => events_acquired: ReadWrite<u32>),
+        (0x12C => _reserved4),
+        (0x200 => shorts: ReadWrite<u32, Shorts::Register>),
+        (0x204 => _reserved5),
+        (0x304 => intenset: ReadWrite<u32, Interrupt::Register>),
+        (0x308 => intenclr: ReadWrite<u32, Interrupt::Register>),
+        (0x30C => _reserved6),
+        (0x400 => semstat: ReadOnly<u32, Semaphore::Register>),
+        (0x404 => _reserved7),
+        /// Overflow and over-read, write 1 to clear
+        (0x440 => status: ReadWrite<u32, Status::Register>),
+        (0x444 => _reserved8),
+        (0x500 => enable: ReadWrite<u32, Enable::Register>),
+        (0x504 => _reserved9),
+        (0x508 => psel_sck: VolatileCell<Pinmux>),
+        (0x50C => psel_miso: VolatileCell<Pinmux>),
+        (0x510 => psel_mosi: VolatileCell<Pinmux>),
+        (0x514 => psel_csn: VolatileCell<Pinmux>),
+        (0x518 => _reserved10),
+        (0x534 => rxd_ptr: ReadWrite<u32>),
+        (0x538 => rxd_maxcnt: ReadWrite<u32>),
+        /// Number of bytes received in the last transaction
+        (0x53C => rxd_amount: ReadOnly<u32>),
+        (0x540 => _reserved11),
+        (0x544 => txd_ptr: ReadWrite<u32>),
+        (0x548 => txd_maxcnt: ReadWrite<u32>),
+        /// Number of bytes sent in the last transaction
+        (0x54C => txd_amount: ReadOnly<u32>),
+        (0x550 => _reserved12),
+        (0x554 => config: ReadWrite<u32, Config::Register>),
+        (0x558 => _reserved13),
+        /// Character sent while the CPU holds the semaphore
+        (0x55C => def: ReadWrite<u32>),
+        (0x560 => _reserved14),
+        /// Character sent after the transmit buffer
+        (0x5C0 => orc: ReadWrite<u32>),
+        (0x5C4 => @END),
+    }
+}
+
+register_bitfields! [u32,
+    Shorts [
+        /// Give the semaphore back to the CPU at the end of a transaction
+        END_ACQUIRE OFFSET(2) NUMBITS(1)
+    ],
+    Interrupt [
+        END OFFSET(1) NUMBITS(1),
+        ENDRX OFFSET(4) NUMBITS(1),
+        ACQUIRED OFFSET(10) NUMBITS(1)
+    ],
+    Semaphore [
+        SEMSTAT OFFSET(0) NUMBITS(2) [
+            Free = 0,
+            Cpu = 1,
+            Spis = 2,
+            CpuPending = 3
+        ]
+    ],
+    Status [
+        OVERREAD OFFSET(0) NUMBITS(1),
+        OVERFLOW OFFSET(1) NUMBITS(1)
+    ],
+    Enable [
+        ENABLE OFFSET(0) NUMBITS(4) [
+            Disabled = 0,
+            Enabled = 2
+        ]
+    ],
+    Config [
+        ORDER OFFSET(0) NUMBITS(1) [
+            MsbFirst = 0,
+            LsbFirst = 1
+        ],
+        CPHA OFFSET(1) NUMBITS(1) [
+            Leading = 0,
+            Trailing = 1
+        ],
+        CPOL OFFSET(2) NUMBITS(1) [
+            ActiveHigh = 0,
+            ActiveLow = 1
+        ]
+    ]
+];
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    /// No transaction is set up.
+    Idle,
+    /// Waiting for the CPU to acquire the semaphore to set up the buffers.
+    Acquiring,
+    /// The SPIS has the buffers, until the controller ends a transaction.
+    Released,
+}
+
+pub struct SPIS<'a> {
+    registers: StaticRef<SpisRegisters>,
+    client: OptionalCell<&'a dyn SpiSlaveClient>,
+    tx_buf: TakeCell<'static, [u8]>,
+    rx_buf: TakeCell<'static, [u8]>,
+    /// Length of the transaction the buffers were given for.
+    transfer_len: Cell<usize>,
+    state: Cell<State>,
+}
+
+impl<'a> SPIS<'a> {
+    pub const fn new(instance: usize) -> SPIS<'a> {
+        SPIS {
+            registers: INSTANCES[instance],
+            client: OptionalCell::empty(),
+            tx_buf: TakeCell::empty(),
+            rx_buf: TakeCell::empty(),
+            transfer_len: Cell::new(0),
+            state: Cell::new(State::Idle),
+        }
+    }
+
+    /// Set the pins of the SPIS.
+    pub fn configure(&self, mosi: Pinmux, miso: Pinmux, sck: Pinmux, csn: Pinmux) {
+        self.registers.psel_mosi.set(mosi);
+        self.registers.psel_miso.set(miso);
+        self.registers.psel_sck.set(sck);
+        self.registers.psel_csn.set(csn);
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.registers.enable.matches_all(Enable::ENABLE::Enabled)
+    }
+
+    /// Give the buffers to the SPIS for the next transaction. The CPU must
+    /// hold the semaphore.
+    fn release_buffers(&self) {
+        let len = self.transfer_len.get();
+        let (tx_ptr, tx_len) = self.tx_buf.map_or((0, 0), |buf| {
+            (buf.as_ptr() as u32, cmp::min(len, buf.len()))
+        });
+        let (rx_ptr, rx_len) = self.rx_buf.map_or((0, 0), |buf| {
+            (buf.as_mut_ptr() as u32, cmp::min(len, buf.len()))
+        });
+        self.registers.txd_ptr.set(tx_ptr);
+        self.registers.txd_maxcnt.set(tx_len as u32);
+        self.registers.rxd_ptr.set(rx_ptr);
+        self.registers.rxd_maxcnt.set(rx_len as u32);
+        self.state.set(State::Released);
+        self.registers.tasks_release.set(1);
+    }
+
+    pub fn handle_interrupt(&self) {
+        // The SPIM and the TWI of this instance share the interrupt.
+        if !self.is_enabled() {
+            return;
+        }
+
+        if self.registers.events_acquired.get() == 1 {
+            self.registers.events_acquired.set(0);
+            // The CPU also gets the semaphore back at the end of each
+            // transaction, which needs nothing.
+            if self.state.get() == State::Acquiring {
+                self.release_buffers();
+            }
+        }
+
+        if self.registers.events_end.get() == 1 {
+            self.registers.events_end.set(0);
+            self.registers.events_endrx.set(0);
+            self.registers
+                .status
+                .write(Status::OVERFLOW::SET + Status::OVERREAD::SET);
+            if self.state.get() == State::Released {
+                let len = cmp::max(
+                    self.registers.rxd_amount.get(),
+                    self.registers.txd_amount.get(),
+                ) as usize;
+                self.state.set(State::Idle);
+                self.client.map(|client| {
+                    client.read_write_done(self.tx_buf.take(), self.rx_buf.take(), len, Ok(()))
+                });
+            }
+        }
+    }
+}
+
+impl<'a> SpiSlave<'a> for SPIS<'a> {
+    fn init(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        self.registers.enable.write(Enable::ENABLE::Enabled);
+        // Take the semaphore back at the end of each transaction, so the
+        // buffers are not reused until they are given again.
+        self.registers.shorts.write(Shorts::END_ACQUIRE::SET);
+        self.registers.events_end.set(0);
+        self.registers.events_acquired.set(0);
+        self.registers
+            .intenset
+            .write(Interrupt::END::SET + Interrupt::ACQUIRED::SET);
+        Ok(())
+    }
+
+    fn has_client(&self) -> bool {
+        self.client.is_some()
+    }
+
+    fn set_client(&self, client: Option<&'a dyn SpiSlaveClient>) {
+        match client {
+            Some(client) => self.client.set(client),
+            None => {
+                self.client.clear();
+                self.registers
+                    .intenclr
+                    .write(Interrupt::END::SET + Interrupt::ACQUIRED::SET);
+                self.registers.enable.write(Enable::ENABLE::Disabled);
+            }
+        }
+    }
+
+    fn set_write_byte(&self, write_byte: u8) {
+        self.registers.def.set(write_byte as u32);
+        self.registers.orc.set(write_byte as u32);
+    }
+
+    fn read_write_bytes(
+        &self,
+        write_buffer: Option<&'static mut [u8]>,
+        read_buffer: Option<&'static mut [u8]>,
+        len: usize,
+    ) -> Result<
+        (),
+        (
+            ErrorCode,
+            Option<&'static mut [u8]>,
+            Option<&'static mut [u8]>,
+        ),
+    > {
+        if self.state.get() != State::Idle {
+            return Err((ErrorCode::BUSY, write_buffer, read_buffer));
+        }
+        if len == 0 {
+            return Err((ErrorCode::INVAL, write_buffer, read_buffer));
+        }
+        if !self.is_enabled() {
+            return Err((ErrorCode::OFF, write_buffer, read_buffer));
+        }
+        self.transfer_len.set(cmp::min(len, MAX_TRANSFER_LEN));
+        self.tx_buf.put(write_buffer);
+        self.rx_buf.put(read_buffer);
+        if self.registers.semstat.matches_all(Semaphore::SEMSTAT::Cpu) {
+            self.release_buffers();
+        } else {
+            // The buffers are set up when the ACQUIRED event fires.
+            self.state.set(State::Acquiring);
+            self.registers.tasks_acquire.set(1);
+        }
+        Ok(())
+    }
+
+    fn set_polarity(&self, polarity: ClockPolarity) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        self.registers.config.modify(match polarity {
+            ClockPolarity::IdleLow => Config::CPOL::ActiveHigh,
+            ClockPolarity::IdleHigh => Config::CPOL::ActiveLow,
+        });
+        Ok(())
+    }
+
+    fn get_polarity(&self) -> ClockPolarity {
+        match self.registers.config.read(Config::CPOL) {
+            0 => ClockPolarity::IdleLow,
+            _ => ClockPolarity::IdleHigh,
+        }
+    }
+
+    fn set_phase(&self, phase: ClockPhase) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        self.registers.config.modify(match phase {
+            ClockPhase::SampleLeading => Config::CPHA::Leading,
+            ClockPhase::SampleTrailing => Config::CPHA::Trailing,
+        });
+        Ok(())
+    }
+
+    fn get_phase(&self) -> ClockPhase {
+        match self.registers.config.read(Config::CPHA) {
+            0 => ClockPhase::SampleLeading,
+            _ => ClockPhase::SampleTrailing,
+        }
+    }
+}
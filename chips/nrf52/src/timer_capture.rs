@@ -0,0 +1,260 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Capture and compare output channels on a TIMER, nRF52-family
+//!
+//! The channels use capture/compare registers 0 to 2 of the timer. CC3 is
+//! used to read the current value of the timer. Each channel is connected to
+//! its pin with a GPIOTE channel and a PPI channel, so timestamps are taken
+//! and outputs changed by the hardware, without depending on interrupt
+//! latency:
+//!
+//! - Capture: the GPIOTE IN event of the pin triggers the CAPTURE task of the
+//!   timer. The GPIOTE interrupt then reports the captured value.
+//! - Compare output: the COMPARE event of the timer triggers the SET, CLR or
+//!   OUT task of the GPIOTE channel of the pin.
+//!
+//! The timer runs at 1 MHz, and is started when a channel is first used.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let capture_compare = static_init!(
+//!     nrf52::timer_capture::TimerCaptureCompare<'static, 48>,
+//!     nrf52::timer_capture::TimerCaptureCompare::new(
+//!         &base_peripherals.timer2,
+//!         &nrf52840_peripherals.gpio_port,
+//!         &[Pin::P1_01, Pin::P1_02],
+//!         0,
+//!     )
+//! );
+//! base_peripherals.timer2.set_client(capture_compare);
+//! nrf52840_peripherals.gpio_port.set_gpiote_client(capture_compare);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::capture_compare::{
+    Capture, CaptureClient, CompareOutput, CompareOutputClient, Edge, OutputAction,
+};
+use kernel::hil::gpio::InterruptEdge;
+use kernel::hil::time::{Freq1MHz, Ticks, Ticks32, Time};
+use kernel::utilities::cells::OptionalCell;
+use kernel::ErrorCode;
+use nrf5x::gpio::{GpioteClient, Pin, Port};
+use nrf5x::timer::{CompareClient, Timer};
+
+use crate::ppi::{Ppi, NUM_PROGRAMMABLE_CHANNELS};
+
+/// Maximum number of channels, one per capture/compare register of the
+/// timer that is not used to read its value.
+pub const MAX_CHANNELS: usize = 3;
+
+/// Capture/compare register used to read the value of the timer.
+const CC_NOW: usize = 3;
+
+/// Minimum number of ticks in the future an output can be scheduled at.
+const SYNC_TICKS: u32 = 2;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Idle,
+    /// Capturing, with the GPIOTE channel of the pin.
+    Capture(usize),
+    /// Generating an output, with the GPIOTE channel of the pin.
+    Output(usize),
+}
+
+pub struct TimerCaptureCompare<'a, const N: usize> {
+    timer: &'a Timer,
+    port: &'a Port<'a, N>,
+    ppi: Ppi,
+    /// Pin of each channel.
+    pins: &'a [Pin],
+    /// PPI channel of the first channel. The others use the following PPI
+    /// channels.
+    first_ppi_channel: usize,
+    modes: [Cell<Mode>; MAX_CHANNELS],
+    started: Cell<bool>,
+    capture_client: OptionalCell<&'a dyn CaptureClient<Ticks32>>,
+    output_client: OptionalCell<&'a dyn CompareOutputClient>,
+}
+
+impl<'a, const N: usize> TimerCaptureCompare<'a, N> {
+    /// Channel `n` uses `pins[n]`, and PPI channel `first_ppi_channel + n`.
+    /// Only the first `MAX_CHANNELS` pins are used.
+    pub fn new(
+        timer: &'a Timer,
+        port: &'a Port<'a, N>,
+        pins: &'a [Pin],
+        first_ppi_channel: usize,
+    ) -> Self {
+        let channels = pins
+            .len()
+            .min(MAX_CHANNELS)
+            .min(NUM_PROGRAMMABLE_CHANNELS.saturating_sub(first_ppi_channel));
+        TimerCaptureCompare {
+            timer,
+            port,
+            ppi: Ppi::new(),
+            pins: &pins[..channels],
+            first_ppi_channel,
+            modes: [const { Cell::new(Mode::Idle) }; MAX_CHANNELS],
+            started: Cell::new(false),
+            capture_client: OptionalCell::empty(),
+            output_client: OptionalCell::empty(),
+        }
+    }
+
+    fn start(&self) {
+        if !self.started.get() {
+            self.timer.start();
+            self.started.set(true);
+        }
+    }
+
+    fn mode(&self, channel: usize) -> Result<Mode, ErrorCode> {
+        if channel < self.pins.len() {
+            Ok(self.modes[channel].get())
+        } else {
+            Err(ErrorCode::INVAL)
+        }
+    }
+}
+
+impl<const N: usize> Time for TimerCaptureCompare<'_, N> {
+    type Frequency = Freq1MHz;
+    type Ticks = Ticks32;
+
+    fn now(&self) -> Ticks32 {
+        Ticks32::from(self.timer.capture(CC_NOW))
+    }
+}
+
+impl<'a, const N: usize> Capture<'a> for TimerCaptureCompare<'a, N> {
+    fn set_capture_client(&self, client: &'a dyn CaptureClient<Ticks32>) {
+        self.capture_client.set(client);
+    }
+
+    fn capture_channels(&self) -> usize {
+        self.pins.len()
+    }
+
+    fn start_capture(&self, channel: usize, edge: Edge) -> Result<(), ErrorCode> {
+        if self.mode(channel)? != Mode::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        let edge = match edge {
+            Edge::Rising => InterruptEdge::RisingEdge,
+            Edge::Falling => InterruptEdge::FallingEdge,
+            Edge::Both => InterruptEdge::EitherEdge,
+        };
+        let gpiote = self.port.allocate_gpiote_event(self.pins[channel], edge)?;
+        self.start();
+
+        let ppi_channel = self.first_ppi_channel + channel;
+        self.ppi.connect(
+            ppi_channel,
+            self.port.gpiote_event_address(gpiote),
+            self.timer.capture_task_address(channel),
+        );
+        self.ppi.enable_channel(ppi_channel);
+        self.modes[channel].set(Mode::Capture(gpiote));
+        Ok(())
+    }
+
+    fn stop_capture(&self, channel: usize) -> Result<(), ErrorCode> {
+        if let Mode::Capture(gpiote) = self.mode(channel)? {
+            self.ppi.disable_channel(self.first_ppi_channel + channel);
+            self.port.release_gpiote_channel(gpiote);
+            self.modes[channel].set(Mode::Idle);
+        }
+        Ok(())
+    }
+}
+
+impl<'a, const N: usize> CompareOutput<'a> for TimerCaptureCompare<'a, N> {
+    fn set_compare_output_client(&self, client: &'a dyn CompareOutputClient) {
+        self.output_client.set(client);
+    }
+
+    fn compare_channels(&self) -> usize {
+        self.pins.len()
+    }
+
+    fn set_output(
+        &self,
+        channel: usize,
+        reference: Ticks32,
+        dt: Ticks32,
+        action: OutputAction,
+    ) -> Result<(), ErrorCode> {
+        let gpiote = match self.mode(channel)? {
+            Mode::Capture(_) => return Err(ErrorCode::BUSY),
+            Mode::Output(gpiote) => gpiote,
+            // The pin starts low, until the first output changes it.
+            Mode::Idle => self.port.allocate_gpiote_task(self.pins[channel], false)?,
+        };
+        self.modes[channel].set(Mode::Output(gpiote));
+        self.start();
+
+        let ppi_channel = self.first_ppi_channel + channel;
+        self.ppi.disable_channel(ppi_channel);
+        self.ppi.connect(
+            ppi_channel,
+            self.timer.compare_event_address(channel),
+            self.port.gpiote_task_address(gpiote, action),
+        );
+        self.ppi.enable_channel(ppi_channel);
+
+        let mut expire = reference.wrapping_add(dt);
+        let now = self.now();
+        if !now.within_range(reference, expire) || expire.wrapping_sub(now).into_u32() <= SYNC_TICKS
+        {
+            expire = now.wrapping_add(Ticks32::from(SYNC_TICKS));
+        }
+        self.timer.set_compare(channel, expire.into_u32());
+        Ok(())
+    }
+
+    fn cancel_output(&self, channel: usize) -> Result<(), ErrorCode> {
+        if let Mode::Output(gpiote) = self.mode(channel)? {
+            self.timer.disable_compare(channel);
+            self.ppi.disable_channel(self.first_ppi_channel + channel);
+            self.port.release_gpiote_channel(gpiote);
+            self.modes[channel].set(Mode::Idle);
+        }
+        Ok(())
+    }
+}
+
+impl<const N: usize> CompareClient for TimerCaptureCompare<'_, N> {
+    fn compare(&self, bitmask: u8) {
+        for channel in 0..self.pins.len() {
+            if bitmask & (1 << channel) != 0 {
+                if let Mode::Output(_) = self.modes[channel].get() {
+                    // The PPI performed the output. Disconnect the channel so
+                    // the output is not repeated when the timer wraps around.
+                    self.ppi.disable_channel(self.first_ppi_channel + channel);
+                    self.output_client.map(|client| client.output_done(channel));
+                }
+            }
+        }
+    }
+}
+
+impl<const N: usize> GpioteClient for TimerCaptureCompare<'_, N> {
+    fn gpiote_event(&self, gpiote: usize) {
+        let channel = self
+            .modes
+            .iter()
+            .position(|mode| mode.get() == Mode::Capture(gpiote));
+        if let Some(channel) = channel {
+            let timestamp = Ticks32::from(self.timer.cc(channel));
+            self.capture_client
+                .map(|client| client.captured(channel, timestamp));
+        }
+    }
+}
@@ -4,6 +4,18 @@
 
 //! Universal asynchronous receiver/transmitter with EasyDMA (UARTE)
 //!
+//! The UARTE cannot detect an idle line by itself. For
+//! [`uart::ReceiveAdvanced::receive_automatic`], a TIMER and two PPI channels,
+//! given with [`Uarte::set_idle_timer`], restart a one-shot timeout on every
+//! received byte and stop the reception when it expires.
+//!
+//! Transmissions are split in chunks of at most 255 bytes, the largest the
+//! EasyDMA counter of every nRF52 holds. The DMA pointer registers are double
+//! buffered, so the next chunk is loaded as soon as the current one starts,
+//! and started as soon as the current one ends, without a gap on the line.
+//! [`uart::TransmitVectored`] transmits up to [`MAX_TX_PARTS`] parts of a
+//! buffer the same way.
+//!
 //! Author
 //! -------------------
 //!
@@ -19,9 +31,15 @@ use kernel::utilities::registers::{register_bitfields, ReadOnly, ReadWrite, Writ
 use kernel::utilities::StaticRef;
 use kernel::ErrorCode;
 use nrf5x::pinmux;
+use nrf5x::timer::Timer;
+
+use crate::ppi::Ppi;
 
 const UARTE_MAX_BUFFER_SIZE: u32 = 0xff;
 
+/// Maximum number of parts of a [`uart::TransmitVectored`] transmission.
+pub const MAX_TX_PARTS: usize = 4;
+
 static mut BYTE: u8 = 0;
 
 pub const UARTE0_BASE: StaticRef<UarteRegisters> =
@@ -38,7 +56,8 @@ pub struct UarteRegisters {
     _reserved2: [u32; 52],
     event_cts: ReadWrite<u32, Event::Register>,
     event_ncts: ReadWrite<u32, Event::Register>,
-    _reserved3: [u32; 2],
+    event_rxdrdy: ReadWrite<u32, Event::Register>,
+    _reserved3: [u32; 1],
     event_endrx: ReadWrite<u32, Event::Register>,
     _reserved4: [u32; 3],
     event_endtx: ReadWrite<u32, Event::Register>,
@@ -167,12 +186,24 @@ pub struct Uarte<'a> {
     tx_client: OptionalCell<&'a dyn uart::TransmitClient>,
     tx_buffer: kernel::utilities::cells::TakeCell<'static, [u8]>,
     tx_len: Cell<usize>,
-    tx_remaining_bytes: Cell<usize>,
+    /// Start and length of the parts of `tx_buffer` to transmit.
+    tx_parts: [Cell<(usize, usize)>; MAX_TX_PARTS],
+    tx_part_count: Cell<usize>,
+    /// Part and offset in it of the next chunk to load.
+    tx_cursor: Cell<(usize, usize)>,
+    /// Whether the next chunk is loaded in the DMA pointer registers.
+    tx_next_loaded: Cell<bool>,
     rx_client: OptionalCell<&'a dyn uart::ReceiveClient>,
     rx_buffer: kernel::utilities::cells::TakeCell<'static, [u8]>,
     rx_remaining_bytes: Cell<usize>,
     rx_abort_in_progress: Cell<bool>,
-    offset: Cell<usize>,
+    /// Whether the reception in progress ends when the line is idle.
+    rx_automatic: Cell<bool>,
+    rx_offset: Cell<usize>,
+    baud_rate: Cell<u32>,
+    /// Timer and first of the two PPI channels used to detect an idle line.
+    idle_timer: OptionalCell<(&'a Timer, usize)>,
+    ppi: Ppi,
 }
 
 #[derive(Copy, Clone)]
@@ -189,12 +220,19 @@ impl<'a> Uarte<'a> {
             tx_client: OptionalCell::empty(),
             tx_buffer: kernel::utilities::cells::TakeCell::empty(),
             tx_len: Cell::new(0),
-            tx_remaining_bytes: Cell::new(0),
+            tx_parts: [const { Cell::new((0, 0)) }; MAX_TX_PARTS],
+            tx_part_count: Cell::new(0),
+            tx_cursor: Cell::new((0, 0)),
+            tx_next_loaded: Cell::new(false),
             rx_client: OptionalCell::empty(),
             rx_buffer: kernel::utilities::cells::TakeCell::empty(),
             rx_remaining_bytes: Cell::new(0),
             rx_abort_in_progress: Cell::new(false),
-            offset: Cell::new(0),
+            rx_automatic: Cell::new(false),
+            rx_offset: Cell::new(0),
+            baud_rate: Cell::new(115200),
+            idle_timer: OptionalCell::empty(),
+            ppi: Ppi::new(),
         }
     }
 
@@ -235,11 +273,51 @@ impl<'a> Uarte<'a> {
         // some other startup code) may have setup TX interrupts, and there may
         // be one pending. We clear it to be safe.
         self.registers.event_endtx.write(Event::READY::CLEAR);
+        self.registers.event_txstarted.write(Event::READY::CLEAR);
 
         self.enable_uart();
     }
 
+    /// Use `timer` and the PPI channels `first_ppi_channel` and
+    /// `first_ppi_channel + 1` to end receptions started with
+    /// [`uart::ReceiveAdvanced::receive_automatic`] when the line is idle.
+    /// The timer must not be used for anything else.
+    pub fn set_idle_timer(&self, timer: &'a Timer, first_ppi_channel: usize) {
+        self.idle_timer.set((timer, first_ppi_channel));
+    }
+
+    /// Restart the idle timer on every received byte, and stop the reception
+    /// when `bit_periods` pass without one.
+    fn enable_idle_timeout(&self, timer: &Timer, first_ppi_channel: usize, bit_periods: u8) {
+        // The timer counts at 1 MHz.
+        let ticks = (u32::from(bit_periods) * 1_000_000).div_ceil(self.baud_rate.get());
+        timer.set_one_shot(ticks.max(1));
+
+        let rxdrdy = core::ptr::addr_of!(self.registers.event_rxdrdy) as u32;
+        let stoprx = core::ptr::addr_of!(self.registers.task_stoprx) as u32;
+        self.ppi
+            .connect(first_ppi_channel, rxdrdy, timer.clear_task_address());
+        self.ppi.fork(first_ppi_channel, timer.start_task_address());
+        self.ppi.connect(
+            first_ppi_channel + 1,
+            timer.compare_event_address(0),
+            stoprx,
+        );
+        self.ppi.enable_channel(first_ppi_channel);
+        self.ppi.enable_channel(first_ppi_channel + 1);
+    }
+
+    fn disable_idle_timeout(&self) {
+        self.rx_automatic.set(false);
+        self.idle_timer.map(|(timer, first_ppi_channel)| {
+            self.ppi.disable_channel(first_ppi_channel);
+            self.ppi.disable_channel(first_ppi_channel + 1);
+            timer.stop_one_shot();
+        });
+    }
+
     fn set_baud_rate(&self, baud_rate: u32) {
+        self.baud_rate.set(baud_rate);
         match baud_rate {
             1200 => self.registers.baudrate.set(0x0004F000),
             2400 => self.registers.baudrate.set(0x0009D000),
@@ -257,7 +335,11 @@ impl<'a> Uarte<'a> {
             460800 => self.registers.baudrate.set(0x07400000),
             921600 => self.registers.baudrate.set(0x0F000000),
             1000000 => self.registers.baudrate.set(0x10000000),
-            _ => self.registers.baudrate.set(0x01D60000), //setting default to 115200
+            _ => {
+                //setting default to 115200
+                self.registers.baudrate.set(0x01D60000);
+                self.baud_rate.set(115200);
+            }
         }
     }
 
@@ -276,7 +358,9 @@ impl<'a> Uarte<'a> {
     }
 
     fn enable_tx_interrupts(&self) {
-        self.registers.intenset.write(Interrupt::ENDTX::SET);
+        self.registers
+            .intenset
+            .write(Interrupt::ENDTX::SET + Interrupt::TXSTARTED::SET);
     }
 
     fn disable_rx_interrupts(&self) {
@@ -284,40 +368,34 @@ impl<'a> Uarte<'a> {
     }
 
     fn disable_tx_interrupts(&self) {
-        self.registers.intenclr.write(Interrupt::ENDTX::SET);
+        self.registers
+            .intenclr
+            .write(Interrupt::ENDTX::SET + Interrupt::TXSTARTED::SET);
     }
 
     /// UART interrupt handler that listens for both tx_end and rx_end events
     #[inline(never)]
     pub fn handle_interrupt(&self) {
+        // A chunk starts before it ends, so handle TXSTARTED first when both
+        // are pending.
+        if self.registers.event_txstarted.is_set(Event::READY) {
+            self.registers.event_txstarted.write(Event::READY::CLEAR);
+            self.tx_next_loaded.set(self.load_next_tx_chunk());
+        }
+
         if self.tx_ready() {
-            self.disable_tx_interrupts();
             self.registers.event_endtx.write(Event::READY::CLEAR);
-            let tx_bytes = self.registers.txd_amount.get() as usize;
 
-            let rem = match self.tx_remaining_bytes.get().checked_sub(tx_bytes) {
-                None => return,
-                Some(r) => r,
-            };
-
-            // All bytes have been transmitted
-            if rem == 0 {
-                // Signal client write done
+            if self.tx_next_loaded.take() {
+                self.registers.task_starttx.write(Task::ENABLE::SET);
+            } else {
+                // All bytes have been transmitted
+                self.disable_tx_interrupts();
                 self.tx_client.map(|client| {
                     self.tx_buffer.take().map(|tx_buffer| {
                         client.transmitted_buffer(tx_buffer, self.tx_len.get(), Ok(()));
                     });
                 });
-            } else {
-                // Not all bytes have been transmitted then update offset and continue transmitting
-                self.offset.set(self.offset.get() + tx_bytes);
-                self.tx_remaining_bytes.set(rem);
-                self.set_tx_dma_pointer_to_buffer();
-                self.registers
-                    .txd_maxcnt
-                    .write(Counter::COUNTER.val(min(rem as u32, UARTE_MAX_BUFFER_SIZE)));
-                self.registers.task_starttx.write(Task::ENABLE::SET);
-                self.enable_tx_interrupts();
             }
         }
 
@@ -334,11 +412,12 @@ impl<'a> Uarte<'a> {
             // do the receive callback immediately.
             if self.rx_abort_in_progress.get() {
                 self.rx_abort_in_progress.set(false);
+                self.disable_idle_timeout();
                 self.rx_client.map(|client| {
                     self.rx_buffer.take().map(|rx_buffer| {
                         client.received_buffer(
                             rx_buffer,
-                            self.offset.get() + rx_bytes,
+                            self.rx_offset.get() + rx_bytes,
                             Err(ErrorCode::CANCEL),
                             uart::Error::None,
                         );
@@ -352,16 +431,23 @@ impl<'a> Uarte<'a> {
                 // where we are storing in the buffer.
                 self.rx_remaining_bytes
                     .set(self.rx_remaining_bytes.get().saturating_sub(rx_bytes));
-                self.offset.set(self.offset.get() + rx_bytes);
+                self.rx_offset.set(self.rx_offset.get() + rx_bytes);
+
+                // A reception ending before the DMA buffer is full was
+                // stopped by the idle timer.
+                let idle = self.rx_automatic.get()
+                    && rx_bytes < self.registers.rxd_maxcnt.read(Counter::COUNTER) as usize;
 
                 let rem = self.rx_remaining_bytes.get();
-                if rem == 0 {
+                if rem == 0 || idle {
+                    self.disable_idle_timeout();
+
                     // Signal client that the read is done
                     self.rx_client.map(|client| {
                         self.rx_buffer.take().map(|rx_buffer| {
                             client.received_buffer(
                                 rx_buffer,
-                                self.offset.get(),
+                                self.rx_offset.get(),
                                 Ok(()),
                                 uart::Error::None,
                             );
@@ -387,7 +473,6 @@ impl<'a> Uarte<'a> {
     /// Transmit one byte at the time and the client is responsible for polling
     /// This is used by the panic handler
     pub unsafe fn send_byte(&self, byte: u8) {
-        self.tx_remaining_bytes.set(1);
         self.registers.event_endtx.write(Event::READY::CLEAR);
         // precaution: copy value into variable with static lifetime
         BYTE = byte;
@@ -406,33 +491,52 @@ impl<'a> Uarte<'a> {
         self.registers.event_endrx.is_set(Event::READY)
     }
 
-    fn set_tx_dma_pointer_to_buffer(&self) {
+    /// Load the next chunk of the transmission in the DMA pointer registers,
+    /// and return whether there was one.
+    fn load_next_tx_chunk(&self) -> bool {
+        let (part, offset) = self.tx_cursor.get();
+        if part >= self.tx_part_count.get() {
+            return false;
+        }
+        let (start, len) = self.tx_parts[part].get();
+        let chunk = min(len - offset, UARTE_MAX_BUFFER_SIZE as usize);
         self.tx_buffer.map(|tx_buffer| {
             self.registers
                 .txd_ptr
-                .set(tx_buffer[self.offset.get()..].as_ptr() as u32);
+                .set(tx_buffer[start + offset..].as_ptr() as u32);
+        });
+        self.registers
+            .txd_maxcnt
+            .write(Counter::COUNTER.val(chunk as u32));
+        self.tx_cursor.set(if offset + chunk == len {
+            (part + 1, 0)
+        } else {
+            (part, offset + chunk)
         });
+        true
     }
 
     fn set_rx_dma_pointer_to_buffer(&self) {
         self.rx_buffer.map(|rx_buffer| {
             self.registers
                 .rxd_ptr
-                .set(rx_buffer[self.offset.get()..].as_ptr() as u32);
+                .set(rx_buffer[self.rx_offset.get()..].as_ptr() as u32);
         });
     }
 
-    // Helper function used by both transmit_word and transmit_buffer
-    fn setup_buffer_transmit(&self, buf: &'static mut [u8], tx_len: usize) {
-        self.tx_remaining_bytes.set(tx_len);
-        self.tx_len.set(tx_len);
-        self.offset.set(0);
+    // Helper function used by both transmit_buffer and transmit_vectored
+    fn setup_buffer_transmit(&self, buf: &'static mut [u8], parts: &[(usize, usize)]) {
+        for (slot, part) in self.tx_parts.iter().zip(parts) {
+            slot.set(*part);
+        }
+        self.tx_part_count.set(parts.len());
+        self.tx_len.set(parts.iter().map(|&(_, len)| len).sum());
+        self.tx_cursor.set((0, 0));
+        self.tx_next_loaded.set(false);
         self.tx_buffer.replace(buf);
-        self.set_tx_dma_pointer_to_buffer();
 
-        self.registers
-            .txd_maxcnt
-            .write(Counter::COUNTER.val(min(tx_len as u32, UARTE_MAX_BUFFER_SIZE)));
+        self.registers.event_txstarted.write(Event::READY::CLEAR);
+        self.load_next_tx_chunk();
         self.registers.task_starttx.write(Task::ENABLE::SET);
 
         self.enable_tx_interrupts();
@@ -454,7 +558,7 @@ impl<'a> uart::Transmit<'a> for Uarte<'a> {
         } else if self.tx_buffer.is_some() {
             Err((ErrorCode::BUSY, tx_data))
         } else {
-            self.setup_buffer_transmit(tx_data, tx_len);
+            self.setup_buffer_transmit(tx_data, &[(0, tx_len)]);
             Ok(())
         }
     }
@@ -468,6 +572,33 @@ impl<'a> uart::Transmit<'a> for Uarte<'a> {
     }
 }
 
+impl<'a> uart::TransmitVectored<'a> for Uarte<'a> {
+    fn max_parts(&self) -> usize {
+        MAX_TX_PARTS
+    }
+
+    fn transmit_vectored(
+        &self,
+        tx_buffer: &'static mut [u8],
+        parts: &[(usize, usize)],
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        let invalid_part = |&(start, len): &(usize, usize)| {
+            len == 0
+                || start
+                    .checked_add(len)
+                    .map_or(true, |end| end > tx_buffer.len())
+        };
+        if self.tx_buffer.is_some() {
+            Err((ErrorCode::BUSY, tx_buffer))
+        } else if parts.is_empty() || parts.len() > MAX_TX_PARTS || parts.iter().any(invalid_part) {
+            Err((ErrorCode::SIZE, tx_buffer))
+        } else {
+            self.setup_buffer_transmit(tx_buffer, parts);
+            Ok(())
+        }
+    }
+}
+
 impl uart::Configure for Uarte<'_> {
     fn configure(&self, params: uart::Parameters) -> Result<(), ErrorCode> {
         // These could probably be implemented, but are currently ignored, so
@@ -505,7 +636,7 @@ impl<'a> uart::Receive<'a> for Uarte<'a> {
         let truncated_length = core::cmp::min(rx_len, rx_buf.len());
 
         self.rx_remaining_bytes.set(truncated_length);
-        self.offset.set(0);
+        self.rx_offset.set(0);
         self.rx_buffer.replace(rx_buf);
         self.set_rx_dma_pointer_to_buffer();
 
@@ -536,3 +667,28 @@ impl<'a> uart::Receive<'a> for Uarte<'a> {
         }
     }
 }
+
+impl<'a> uart::ReceiveAdvanced<'a> for Uarte<'a> {
+    fn receive_automatic(
+        &self,
+        rx_buffer: &'static mut [u8],
+        rx_len: usize,
+        interbyte_timeout: u8,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        let Some((timer, first_ppi_channel)) = self.idle_timer.get() else {
+            return Err((ErrorCode::NOSUPPORT, rx_buffer));
+        };
+        if self.rx_buffer.is_some() {
+            return Err((ErrorCode::BUSY, rx_buffer));
+        }
+        if rx_len > rx_buffer.len() {
+            return Err((ErrorCode::SIZE, rx_buffer));
+        }
+
+        // The timer only starts with the first byte, so the reception does
+        // not time out before a byte is received.
+        self.enable_idle_timeout(timer, first_ppi_channel, interbyte_timeout);
+        self.rx_automatic.set(true);
+        uart::Receive::receive_buffer(self, rx_buffer, rx_len)
+    }
+}
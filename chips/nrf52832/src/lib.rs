@@ -5,8 +5,8 @@
 #![no_std]
 
 pub use nrf52::{
-    acomp, adc, aes, ble_radio, chip, clock, constants, crt1, ficr, i2c, init, nvmc,
-    peripheral_interrupts as base_interrupts, pinmux, power, ppi, pwm, rtc, spi, temperature,
+    acomp, adc, aes, ble_radio, chip, clock, constants, crt1, errata, ficr, i2c, init, nvmc,
+    peripheral_interrupts as base_interrupts, pinmux, power, ppi, pwm, rtc, spi, spis, temperature,
     timer, trng, uart, uicr,
 };
 pub mod gpio;
@@ -7,9 +7,9 @@
 // FIXME: Move ieee802154_radio to an nrf528xx crate so this can access it.
 
 pub use nrf52::{
-    acomp, adc, aes, ble_radio, chip, clock, constants, crt1, ficr, i2c, ieee802154_radio, init,
-    nvmc, peripheral_interrupts as base_interrupts, pinmux, power, ppi, pwm, rtc, spi, temperature,
-    timer, trng, uart, uicr,
+    acomp, adc, aes, ble_radio, chip, clock, constants, crt1, errata, ficr, i2c, ieee802154_radio,
+    init, nvmc, peripheral_interrupts as base_interrupts, pinmux, power, ppi, pwm, rtc, spi, spis,
+    temperature, timer, trng, uart, uicr,
 };
 pub mod gpio;
 pub mod interrupt_service;
@@ -4,11 +4,12 @@
 
 #![no_std]
 pub use nrf52::{
-    acomp, adc, aes, ble_radio, chip, clock, constants, crt1, ficr, i2c, ieee802154_radio, init,
-    nvmc, peripheral_interrupts as base_interrupts, pinmux, power, ppi, pwm, rtc, spi, temperature,
-    timer, trng, uart, uicr, usbd,
+    acomp, adc, aes, ble_radio, chip, clock, constants, crt1, errata, ficr, i2c, ieee802154_radio,
+    init, nvmc, peripheral_interrupts as base_interrupts, pinmux, power, ppi, pwm, rtc, spi, spis,
+    temperature, timer, trng, uart, uicr, usbd,
 };
 pub mod gpio;
 pub mod interrupt_service;
 
 pub mod peripheral_interrupts;
+pub mod power_manager;
@@ -0,0 +1,198 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Power manager for the nRF52840.
+//!
+//! The nRF52840 sleeps in System ON, where any interrupt wakes it, or enters
+//! System OFF, which only the GPIO sense, the LPCOMP, NFC and VBUS wake it
+//! from, through a reset. This maps onto the sleep states as:
+//!
+//! - [`SleepState::Sleep`]: System ON, with the clocks the board started.
+//! - [`SleepState::DeepSleep`]: System ON, with the external crystal of the
+//!   high frequency clock (HFXO) stopped. This saves the current of the
+//!   crystal, a few hundred microamps, while the chip sleeps. Peripherals that
+//!   need the high frequency clock in the meantime run from the internal RC
+//!   oscillator. The crystal is started again when the chip wakes up, which
+//!   takes up to [`DEEP_SLEEP_LATENCY_US`], before interrupts are handled.
+//!   The chip only enters this state if no alarm expires within the deep
+//!   sleep threshold, as restarting the crystal costs energy too.
+//! - [`SleepState::Off`]: System OFF. Only the RAM sections set with
+//!   [`PowerManager::retain_ram`] keep their contents. The chip only enters
+//!   this state if no alarm is armed, a registered constraint is armed to
+//!   wake it, and the board allows it with [`PowerManager::set_deepest`], as
+//!   the kernel and its processes boot again after the wake up.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let power_manager = static_init!(
+//!     nrf52840::power_manager::PowerManager<'static, nrf52840::rtc::Rtc<'static>>,
+//!     nrf52840::power_manager::PowerManager::new(
+//!         &base_peripherals.pwr_clk,
+//!         &base_peripherals.clock,
+//!         &base_peripherals.rtc,
+//!         5000,
+//!     )
+//! );
+//! let _ = power_manager.register(&nrf52840_peripherals.ieee802154_radio);
+//! board_kernel.set_power_manager(power_manager, &main_loop_capability);
+//! ```
+
+use core::cell::Cell;
+use kernel::hil::time::{Alarm, ConvertTicks, Ticks};
+use kernel::platform::power::{self, SleepConstraint, SleepState};
+use kernel::utilities::cells::OptionalCell;
+use kernel::ErrorCode;
+use nrf52::clock::{Clock, HighClockSource};
+use nrf52::power::Power;
+
+/// Number of constraints a power manager tracks.
+pub const MAX_CONSTRAINTS: usize = 8;
+
+/// Longest time the HFXO takes to start again after a deep sleep.
+pub const DEEP_SLEEP_LATENCY_US: u32 = 1000;
+
+const RAM_START: usize = 0x2000_0000;
+/// RAM banks 0 to 7 hold two sections of 4 KiB each, bank 8 six sections of
+/// 32 KiB each.
+const SMALL_SECTION_SIZE: usize = 0x1000;
+const SMALL_SECTIONS: usize = 16;
+const LARGE_SECTION_SIZE: usize = 0x8000;
+const LARGE_SECTIONS: usize = 6;
+
+/// RAM bank and section that hold `address`, and the size of the section.
+fn ram_section(address: usize) -> Option<(usize, usize, usize)> {
+    let offset = address.checked_sub(RAM_START)?;
+    let small = offset / SMALL_SECTION_SIZE;
+    if small < SMALL_SECTIONS {
+        return Some((small / 2, small % 2, SMALL_SECTION_SIZE));
+    }
+    let large = (offset - SMALL_SECTIONS * SMALL_SECTION_SIZE) / LARGE_SECTION_SIZE;
+    (large < LARGE_SECTIONS).then_some((8, large, LARGE_SECTION_SIZE))
+}
+
+pub struct PowerManager<'a, A: Alarm<'a>> {
+    power: &'a Power<'a>,
+    clock: &'a Clock,
+    alarm: &'a A,
+    deep_sleep_threshold_us: u32,
+    deepest: Cell<SleepState>,
+    constraints: [OptionalCell<&'a dyn SleepConstraint>; MAX_CONSTRAINTS],
+}
+
+impl<'a, A: Alarm<'a>> PowerManager<'a, A> {
+    /// Create a power manager that deep sleeps if the next alarm of `alarm`
+    /// expires in `deep_sleep_threshold_us` or later. The power manager
+    /// enters [`SleepState::DeepSleep`] at most until
+    /// [`PowerManager::set_deepest`] is called.
+    pub fn new(
+        power: &'a Power<'a>,
+        clock: &'a Clock,
+        alarm: &'a A,
+        deep_sleep_threshold_us: u32,
+    ) -> Self {
+        Self {
+            power,
+            clock,
+            alarm,
+            deep_sleep_threshold_us,
+            deepest: Cell::new(SleepState::DeepSleep),
+            constraints: [const { OptionalCell::empty() }; MAX_CONSTRAINTS],
+        }
+    }
+
+    /// Never enter a state deeper than `state`.
+    pub fn set_deepest(&self, state: SleepState) {
+        self.deepest.set(state);
+    }
+
+    /// Retain the RAM from `start` to `start + len` in System OFF. Whole
+    /// sections of RAM are retained, so more RAM than requested may be.
+    pub fn retain_ram(&self, start: usize, len: usize) {
+        let mut address = start;
+        while address < start.saturating_add(len) {
+            let Some((bank, section, size)) = ram_section(address) else {
+                return;
+            };
+            self.power.set_ram_retention(bank, 1 << section);
+            address = (address & !(size - 1)) + size;
+        }
+    }
+
+    /// Time until the next alarm expires, or `None` if no alarm is armed.
+    fn next_alarm_us(&self) -> Option<u32> {
+        self.alarm.is_armed().then(|| {
+            let remaining = self.alarm.get_alarm().wrapping_sub(self.alarm.now());
+            self.alarm.ticks_to_us(remaining)
+        })
+    }
+
+    fn hfxo_running(&self) -> bool {
+        self.clock.high_running() && matches!(self.clock.high_source(), HighClockSource::XTAL)
+    }
+
+    fn deep_sleep(&self, sleep: &dyn Fn()) {
+        // Only stop the crystal if the board started it.
+        if self.hfxo_running() {
+            self.clock.high_stop();
+            sleep();
+            self.clock.high_start();
+            while !self.hfxo_running() {}
+        } else {
+            sleep();
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>> power::PowerManager<'a> for PowerManager<'a, A> {
+    fn register(&self, constraint: &'a dyn SleepConstraint) -> Result<(), ErrorCode> {
+        let slot = self
+            .constraints
+            .iter()
+            .find(|slot| slot.is_none())
+            .ok_or(ErrorCode::NOMEM)?;
+        slot.set(constraint);
+        Ok(())
+    }
+
+    fn sleep_state(&self) -> SleepState {
+        let mut latency_us = None;
+        let mut wakes_from_off = false;
+        for constraint in self.constraints.iter() {
+            constraint.map(|constraint| {
+                if let Some(max) = constraint.max_wakeup_latency_us() {
+                    latency_us = Some(latency_us.map_or(max, |latency: u32| latency.min(max)));
+                }
+                wakes_from_off |= constraint.wakes_from(SleepState::Off);
+            });
+        }
+        let next_alarm_us = self.next_alarm_us();
+
+        if self.deepest.get() >= SleepState::Off
+            && latency_us.is_none()
+            && next_alarm_us.is_none()
+            && wakes_from_off
+        {
+            SleepState::Off
+        } else if self.deepest.get() >= SleepState::DeepSleep
+            && latency_us.map_or(true, |latency| latency >= DEEP_SLEEP_LATENCY_US)
+            && next_alarm_us.map_or(true, |us| us >= self.deep_sleep_threshold_us)
+        {
+            SleepState::DeepSleep
+        } else {
+            SleepState::Sleep
+        }
+    }
+
+    fn sleep(&self, sleep: &dyn Fn()) -> SleepState {
+        let state = self.sleep_state();
+        match state {
+            SleepState::Sleep => sleep(),
+            SleepState::DeepSleep => self.deep_sleep(sleep),
+            SleepState::Off => self.power.system_off(),
+        }
+        state
+    }
+}
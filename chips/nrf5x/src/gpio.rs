@@ -13,10 +13,13 @@ use enum_primitive::cast::FromPrimitive;
 use enum_primitive::enum_from_primitive;
 use kernel::debug;
 use kernel::hil;
+use kernel::hil::capture_compare::OutputAction;
+use kernel::platform::power::{SleepConstraint, SleepState};
 use kernel::utilities::cells::OptionalCell;
 use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
 use kernel::utilities::registers::{register_bitfields, ReadWrite};
 use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
 
 #[cfg(feature = "nrf51")]
 const NUM_GPIOTE: usize = 4;
@@ -392,6 +395,22 @@ impl<'a> GPIOPin<'a> {
         });
     }
 
+    /// Sense the level of the pin, so that it wakes the chip from System OFF
+    /// when it is at `level`. The pin must be an input.
+    ///
+    /// Sensing pins also raise the PORT event of the GPIOTE, which this driver
+    /// does not use.
+    pub fn enable_sense(&self, level: SenseLevel) {
+        self.gpio_registers.pin_cnf[self.pin as usize].modify(match level {
+            SenseLevel::High => PinConfig::SENSE::High,
+            SenseLevel::Low => PinConfig::SENSE::Low,
+        });
+    }
+
+    pub fn disable_sense(&self) {
+        self.gpio_registers.pin_cnf[self.pin as usize].modify(PinConfig::SENSE::Disabled);
+    }
+
     // This sets the specified pin cfg as per the TRM for i2c pin usage.
     pub fn set_i2c_pin_cfg(&self) {
         self.gpio_registers.pin_cnf[self.pin as usize].modify(
@@ -403,6 +422,13 @@ impl<'a> GPIOPin<'a> {
     }
 }
 
+/// Level of a pin that wakes the chip from System OFF.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SenseLevel {
+    High,
+    Low,
+}
+
 impl hil::gpio::Configure for GPIOPin<'_> {
     fn set_floating_state(&self, mode: hil::gpio::FloatingState) {
         let pin_config = match mode {
@@ -545,6 +571,22 @@ impl<'a> hil::gpio::Interrupt<'a> for GPIOPin<'a> {
     }
 }
 
+/// A pin can wake the chip from System OFF while it senses a level, and from
+/// System ON through its interrupt.
+impl SleepConstraint for GPIOPin<'_> {
+    fn max_wakeup_latency_us(&self) -> Option<u32> {
+        None
+    }
+
+    fn wakes_from(&self, state: SleepState) -> bool {
+        match state {
+            SleepState::Off => !self.gpio_registers.pin_cnf[self.pin as usize]
+                .matches_all(PinConfig::SENSE::Disabled),
+            _ => self.client.is_some(),
+        }
+    }
+}
+
 impl GPIOPin<'_> {
     /// Allocate a GPIOTE channel
     /// If the channel couldn't be allocated return error instead
@@ -564,8 +606,15 @@ impl GPIOPin<'_> {
     }
 }
 
+/// Receives the events of GPIOTE channels allocated with
+/// [`Port::allocate_gpiote_event`].
+pub trait GpioteClient {
+    fn gpiote_event(&self, channel: usize);
+}
+
 pub struct Port<'a, const N: usize> {
     pub pins: [GPIOPin<'a>; N],
+    gpiote_client: OptionalCell<&'a dyn GpioteClient>,
 }
 
 impl<'a, const N: usize> Index<Pin> for Port<'a, N> {
@@ -584,7 +633,84 @@ impl<'a, const N: usize> IndexMut<Pin> for Port<'a, N> {
 
 impl<'a, const N: usize> Port<'a, N> {
     pub const fn new(pins: [GPIOPin<'a>; N]) -> Self {
-        Self { pins }
+        Self {
+            pins,
+            gpiote_client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn set_gpiote_client(&self, client: &'a dyn GpioteClient) {
+        self.gpiote_client.set(client);
+    }
+
+    fn gpiote_registers(&self) -> StaticRef<GpioteRegisters> {
+        self.pins[0].gpiote_registers
+    }
+
+    /// Allocate a GPIOTE channel that generates an event on each `edge` of
+    /// `pin`. Other peripherals can use the event through the PPI, and the
+    /// GPIOTE client is notified about it.
+    pub fn allocate_gpiote_event(
+        &self,
+        pin: Pin,
+        edge: hil::gpio::InterruptEdge,
+    ) -> Result<usize, ErrorCode> {
+        let registers = self.gpiote_registers();
+        let channel = self.pins[0].allocate_channel().or(Err(ErrorCode::FAIL))?;
+        let polarity = match edge {
+            hil::gpio::InterruptEdge::EitherEdge => Config::POLARITY::Toggle,
+            hil::gpio::InterruptEdge::RisingEdge => Config::POLARITY::LoToHi,
+            hil::gpio::InterruptEdge::FallingEdge => Config::POLARITY::HiToLo,
+        };
+        registers.event_in[channel].write(EventsIn::EVENT::NotReady);
+        registers.config[channel]
+            .write(Config::MODE::Event + Config::PSEL.val(pin as u32) + polarity);
+        registers.intenset.set(1 << channel);
+        Ok(channel)
+    }
+
+    /// Allocate a GPIOTE channel that drives `pin`, starting at level
+    /// `initial`. Other peripherals can change the level through the PPI
+    /// (see [`Port::gpiote_task_address`]).
+    pub fn allocate_gpiote_task(&self, pin: Pin, initial: bool) -> Result<usize, ErrorCode> {
+        let registers = self.gpiote_registers();
+        let channel = self.pins[0].allocate_channel().or(Err(ErrorCode::FAIL))?;
+        let outinit = if initial {
+            Config::OUTINIT::High
+        } else {
+            Config::OUTINIT::Low
+        };
+        registers.config[channel].write(
+            Config::MODE::Task + Config::PSEL.val(pin as u32) + Config::POLARITY::Toggle + outinit,
+        );
+        Ok(channel)
+    }
+
+    /// Release a channel allocated with `allocate_gpiote_event` or
+    /// `allocate_gpiote_task`.
+    pub fn release_gpiote_channel(&self, channel: usize) {
+        let registers = self.gpiote_registers();
+        registers.intenclr.set(1 << channel);
+        registers.config[channel]
+            .write(Config::MODE::CLEAR + Config::PSEL::CLEAR + Config::POLARITY::CLEAR);
+    }
+
+    /// Address of the event of `channel`, to connect it through the PPI.
+    pub fn gpiote_event_address(&self, channel: usize) -> u32 {
+        core::ptr::addr_of!(self.gpiote_registers().event_in[channel]) as u32
+    }
+
+    /// Address of the task that performs `action` on the pin of `channel`,
+    /// to trigger it through the PPI. Only the nRF52 has tasks to set and
+    /// clear pins.
+    pub fn gpiote_task_address(&self, channel: usize, action: OutputAction) -> u32 {
+        let out = core::ptr::addr_of!(self.gpiote_registers().task_out[channel]) as u32;
+        match action {
+            OutputAction::Toggle => out,
+            // TASKS_SET[n] and TASKS_CLR[n] follow TASKS_OUT[n].
+            OutputAction::Set => out + 0x30,
+            OutputAction::Clear => out + 0x60,
+        }
     }
 
     /// GPIOTE interrupt: check each GPIOTE channel, if any has
@@ -599,7 +725,11 @@ impl<'a, const N: usize> Port<'a, N> {
                 ev.write(EventsIn::EVENT::NotReady);
                 // Get pin number for the event and `trigger` an interrupt manually on that pin
                 let pin = pin_registers.config[i].read(Config::PSEL) as usize;
-                self.pins[pin].handle_interrupt();
+                if self.pins[pin].allocated_channel.get() == Some(i) {
+                    self.pins[pin].handle_interrupt();
+                } else {
+                    self.gpiote_client.map(|client| client.gpiote_event(i));
+                }
             }
         }
     }
@@ -3,17 +3,31 @@
 // Copyright Tock Contributors 2022.
 
 //! RTC driver, nRF5X-family
+//!
+//! RTC1 provides the kernel [`Alarm`]. RTC0 or RTC2 can provide the kernel
+//! [`SchedulerTimer`] with [`RtcSchedulerTimer`]: unlike SysTick, the RTCs
+//! keep running from the low frequency clock when the CPU sleeps with the
+//! high frequency clock off.
 
 use core::cell::Cell;
+use core::num::NonZeroU32;
 use kernel::hil::time::{self, Alarm, Ticks, Time};
+use kernel::platform::scheduler_timer::SchedulerTimer;
 use kernel::utilities::cells::OptionalCell;
 use kernel::utilities::registers::interfaces::{Readable, Writeable};
 use kernel::utilities::registers::{register_bitfields, ReadOnly, ReadWrite, WriteOnly};
 use kernel::utilities::StaticRef;
 use kernel::ErrorCode;
 
+const RTC0_BASE: StaticRef<RtcRegisters> =
+    unsafe { StaticRef::new(0x4000B000 as *const RtcRegisters) };
 const RTC1_BASE: StaticRef<RtcRegisters> =
     unsafe { StaticRef::new(0x40011000 as *const RtcRegisters) };
+const RTC2_BASE: StaticRef<RtcRegisters> =
+    unsafe { StaticRef::new(0x40024000 as *const RtcRegisters) };
+
+/// Frequency of the RTC counters, which run without prescaler.
+const RTC_FREQUENCY: u64 = 32768;
 
 #[repr(C)]
 struct RtcRegisters {
@@ -203,3 +217,97 @@ impl<'a> Alarm<'a> for Rtc<'a> {
         Self::Ticks::from(10)
     }
 }
+
+/// Kernel scheduler timer on a dedicated RTC, using its compare channel 0.
+///
+/// The counter is started with the first timeslice and then keeps running.
+/// It needs the low frequency clock, which boards already start for the
+/// RTC1 alarm. Timeslices have a resolution of one 32.768 kHz tick (about
+/// 30.5 us).
+///
+/// nRF52 boards use it by returning `&base_peripherals.rtc2` from
+/// `KernelResources::scheduler_timer()` instead of a `SysTick`.
+pub struct RtcSchedulerTimer {
+    registers: StaticRef<RtcRegisters>,
+    running: Cell<bool>,
+    /// Length in ticks of the timeslice in progress.
+    duration: Cell<u32>,
+}
+
+impl RtcSchedulerTimer {
+    /// Scheduler timer on RTC0. The SoftDevice uses RTC0, so this is only
+    /// available without it.
+    pub const fn new_rtc0() -> Self {
+        Self::new(RTC0_BASE)
+    }
+
+    /// Scheduler timer on RTC2.
+    pub const fn new_rtc2() -> Self {
+        Self::new(RTC2_BASE)
+    }
+
+    const fn new(registers: StaticRef<RtcRegisters>) -> Self {
+        Self {
+            registers,
+            running: Cell::new(false),
+            duration: Cell::new(0),
+        }
+    }
+
+    pub fn handle_interrupt(&self) {
+        // The timeslice expired. Leave the compare event set so that
+        // `get_remaining_us()` reports it until the next timeslice starts.
+        self.registers.intenclr.write(Inte::COMPARE0::SET);
+    }
+}
+
+impl SchedulerTimer for RtcSchedulerTimer {
+    fn start(&self, us: NonZeroU32) {
+        // CC[0] must be at least two ticks ahead of the counter to trigger.
+        const SYNC_TICS: u32 = 2;
+        let regs = &*self.registers;
+
+        if !self.running.get() {
+            regs.prescaler.write(Prescaler::PRESCALER.val(0));
+            regs.tasks_start.write(Task::ENABLE::SET);
+            self.running.set(true);
+        }
+
+        let tics = ((us.get() as u64 * RTC_FREQUENCY / 1_000_000) as u32).max(SYNC_TICS + 1);
+        let now = regs.counter.read(Counter::VALUE);
+        self.duration.set(tics);
+        regs.events_compare[0].write(Event::READY::CLEAR);
+        regs.cc[0].write(Counter::VALUE.val(now.wrapping_add(tics) & 0xFF_FFFF));
+    }
+
+    fn reset(&self) {
+        let regs = &*self.registers;
+        regs.intenclr.write(Inte::COMPARE0::SET);
+        regs.events_compare[0].write(Event::READY::CLEAR);
+        self.duration.set(0);
+    }
+
+    fn arm(&self) {
+        self.registers.intenset.write(Inte::COMPARE0::SET);
+    }
+
+    fn disarm(&self) {
+        self.registers.intenclr.write(Inte::COMPARE0::SET);
+    }
+
+    fn get_remaining_us(&self) -> Option<NonZeroU32> {
+        let regs = &*self.registers;
+        if regs.events_compare[0].is_set(Event::READY) {
+            return None;
+        }
+        let expire = regs.cc[0].read(Counter::VALUE);
+        let now = regs.counter.read(Counter::VALUE);
+        let remaining = expire.wrapping_sub(now) & 0xFF_FFFF;
+        // Once the counter passed the compare value the difference wraps
+        // around to more than the timeslice.
+        if remaining > self.duration.get() {
+            return None;
+        }
+        NonZeroU32::new((remaining as u64 * 1_000_000 / RTC_FREQUENCY) as u32)
+    }
+}
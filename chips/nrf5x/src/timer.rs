@@ -231,6 +231,84 @@ impl Timer {
         self.client.set(client);
     }
 
+    /// Start the timer as a free running 32 bit counter at 1 MHz.
+    pub fn start(&self) {
+        self.registers.mode.set(0);
+        self.registers.bitmode.write(Bitmode::BITMODE::Bit32);
+        // 16 MHz / 2^4
+        self.registers.prescaler.set(4);
+        self.registers.tasks_start.write(Task::ENABLE::SET);
+    }
+
+    /// Capture the current value of the timer into `cc`, and return it.
+    pub fn capture(&self, cc: usize) -> u32 {
+        self.registers.tasks_capture[cc].write(Task::ENABLE::SET);
+        self.registers.cc[cc].get()
+    }
+
+    /// Value of the capture/compare register `cc`.
+    pub fn cc(&self, cc: usize) -> u32 {
+        self.registers.cc[cc].get()
+    }
+
+    /// Generate the compare event of `cc`, and an interrupt, when the timer
+    /// reaches `value`.
+    pub fn set_compare(&self, cc: usize, value: u32) {
+        self.registers.events_compare[cc].write(Event::READY::CLEAR);
+        self.registers.cc[cc].write(CC::CC.val(value));
+        self.registers.intenset.set(1 << (16 + cc));
+    }
+
+    pub fn disable_compare(&self, cc: usize) {
+        self.registers.intenclr.set(1 << (16 + cc));
+    }
+
+    /// Address of the capture task of `cc`, to trigger it through the PPI.
+    pub fn capture_task_address(&self, cc: usize) -> u32 {
+        core::ptr::addr_of!(self.registers.tasks_capture[cc]) as u32
+    }
+
+    /// Address of the compare event of `cc`, to connect it through the PPI.
+    pub fn compare_event_address(&self, cc: usize) -> u32 {
+        core::ptr::addr_of!(self.registers.events_compare[cc]) as u32
+    }
+
+    /// Configure the timer to count at 1 MHz and to stop and clear itself,
+    /// without an interrupt, `ticks` after it is started. The timer is meant
+    /// to be started and cleared through the PPI, and its compare 0 event to
+    /// trigger a task through the PPI.
+    pub fn set_one_shot(&self, ticks: u32) {
+        self.registers.tasks_stop.write(Task::ENABLE::SET);
+        self.registers.tasks_clear.write(Task::ENABLE::SET);
+        self.registers.mode.set(0);
+        self.registers.bitmode.write(Bitmode::BITMODE::Bit32);
+        // 16 MHz / 2^4
+        self.registers.prescaler.set(4);
+        self.registers.intenclr.write(Inte::COMPARE0::SET);
+        self.registers.events_compare[0].write(Event::READY::CLEAR);
+        self.registers.cc[0].write(CC::CC.val(ticks));
+        self.registers
+            .shorts
+            .write(Shorts::COMPARE0_STOP::EnableShortcut + Shorts::COMPARE0_CLEAR::EnableShortcut);
+    }
+
+    /// Stop a timer configured with [`Timer::set_one_shot`].
+    pub fn stop_one_shot(&self) {
+        self.registers.tasks_stop.write(Task::ENABLE::SET);
+        self.registers.shorts.set(0);
+        self.registers.events_compare[0].write(Event::READY::CLEAR);
+    }
+
+    /// Address of the start task, to trigger it through the PPI.
+    pub fn start_task_address(&self) -> u32 {
+        core::ptr::addr_of!(self.registers.tasks_start) as u32
+    }
+
+    /// Address of the clear task, to trigger it through the PPI.
+    pub fn clear_task_address(&self) -> u32 {
+        core::ptr::addr_of!(self.registers.tasks_clear) as u32
+    }
+
     /// When an interrupt occurs, check if any of the 4 compares have
     /// created an event, and if so, add it to the bitmask of triggered
     /// events that is passed to the client.
@@ -4,18 +4,24 @@
 
 //! High-level setup and interrupt mapping for the chip.
 
+use core::cell::OnceCell;
 use core::fmt::Write;
 use core::ptr::addr_of;
 
 use kernel::debug;
 use kernel::hil::time::Freq10MHz;
-use kernel::platform::chip::{Chip, InterruptService};
+use kernel::platform::chip::{
+    AtomicHartLock, Chip, HartLock, InterruptService, SmpChip, MAX_HARTS,
+};
+use kernel::platform::scheduler_timer::SchedulerTimer;
 
+use kernel::hil::ipi::InterProcessorInterrupt;
 use kernel::utilities::registers::interfaces::{ReadWriteable, Readable};
 
-use rv32i::csr::{mcause, mie::mie, mip::mip, CSR};
+use rv32i::csr::{mcause, mie::mie, mip::mip, mstatus::mstatus, CSR};
 
 use crate::plic::PLIC;
+use crate::smp::{current_hart, ClintIpi, HartSchedulerTimer, CLINT_HART_BASE};
 use sifive::plic::Plic;
 
 use crate::interrupts;
@@ -35,6 +41,11 @@ pub struct QemuRv32VirtChip<'a, I: InterruptService + 'a> {
     plic: &'a Plic,
     timer: &'a QemuRv32VirtClint<'a>,
     plic_interrupt_service: &'a I,
+    ipi: ClintIpi<'a>,
+    kernel_lock: AtomicHartLock,
+    /// PMP of the harts other than hart 0, set when they are started.
+    hart_pmps: [OnceCell<QemuRv32VirtPMP>; MAX_HARTS],
+    hart_timers: [HartSchedulerTimer; MAX_HARTS],
 }
 
 pub struct QemuRv32VirtDefaultPeripherals<'a> {
@@ -90,9 +101,43 @@ impl<'a, I: InterruptService + 'a> QemuRv32VirtChip<'a, I> {
             plic: &*addr_of!(PLIC),
             timer,
             plic_interrupt_service,
+            ipi: ClintIpi::new(CLINT_HART_BASE),
+            kernel_lock: AtomicHartLock::new(),
+            hart_pmps: [const { OnceCell::new() }; MAX_HARTS],
+            hart_timers: core::array::from_fn(|hart| {
+                HartSchedulerTimer::new(CLINT_HART_BASE, hart)
+            }),
         }
     }
 
+    /// Inter-processor interrupts between the harts.
+    pub fn ipi(&self) -> &ClintIpi<'a> {
+        &self.ipi
+    }
+
+    /// Prepare the current hart, which must not be hart 0, to run the kernel
+    /// with [`Kernel::smp_kernel_loop`](kernel::Kernel::smp_kernel_loop).
+    /// `pmp` must have been created on the current hart.
+    pub unsafe fn start_secondary_hart(
+        &self,
+        pmp: rv32i::pmp::kernel_protection_mml_epmp::KernelProtectionMMLEPMP<16, 5>,
+    ) {
+        let hart = current_hart();
+        if hart == 0 || hart >= MAX_HARTS {
+            return;
+        }
+        self.kernel_lock.lock();
+        let _ = self.hart_pmps[hart].set(rv32i::pmp::PMPUserMPU::new(pmp));
+        self.hart_timers[hart].reset();
+        self.ipi.enable_hart(hart);
+        self.kernel_lock.unlock();
+
+        // Only software interrupts wake the hart up. The machine timer
+        // interrupt is enabled while a process with a timeslice is running.
+        CSR.mie.modify(mie::msoft::SET);
+        CSR.mstatus.modify(mstatus::mie::SET);
+    }
+
     pub unsafe fn enable_plic_interrupts(&self) {
         self.plic.disable_all();
         self.plic.clear_all_pending();
@@ -116,7 +161,17 @@ impl<'a, I: InterruptService + 'a> Chip for QemuRv32VirtChip<'a, I> {
     type UserspaceKernelBoundary = rv32i::syscall::SysCall;
 
     fn mpu(&self) -> &Self::MPU {
-        &self.pmp
+        // Each hart has its own PMP. A PMP rewrites a configuration another
+        // hart changed since it last wrote it, since it tracks the
+        // generation of the configuration and not only a dirty flag.
+        match current_hart() {
+            0 => &self.pmp,
+            hart => self
+                .hart_pmps
+                .get(hart)
+                .and_then(OnceCell::get)
+                .unwrap_or(&self.pmp),
+        }
     }
 
     fn userspace_kernel_boundary(&self) -> &rv32i::syscall::SysCall {
@@ -130,13 +185,16 @@ impl<'a, I: InterruptService + 'a> Chip for QemuRv32VirtChip<'a, I> {
             if mip.is_set(mip::mtimer) {
                 self.timer.handle_interrupt();
             }
+            if mip.is_set(mip::msoft) {
+                self.ipi.handle_interrupt();
+            }
             if self.plic.get_saved_interrupts().is_some() {
                 unsafe {
                     self.handle_plic_interrupts();
                 }
             }
 
-            if !mip.any_matching_bits_set(mip::mtimer::SET)
+            if !mip.any_matching_bits_set(mip::mtimer::SET + mip::msoft::SET)
                 && self.plic.get_saved_interrupts().is_none()
             {
                 break;
@@ -145,14 +203,15 @@ impl<'a, I: InterruptService + 'a> Chip for QemuRv32VirtChip<'a, I> {
 
         // Re-enable all MIE interrupts that we care about. Since we looped
         // until we handled them all, we can re-enable all of them.
-        CSR.mie.modify(mie::mext::SET + mie::mtimer::SET);
+        CSR.mie
+            .modify(mie::mext::SET + mie::mtimer::SET + mie::msoft::SET);
     }
 
     fn has_pending_interrupts(&self) -> bool {
         // First check if the global machine timer interrupt is set.
         // We would also need to check for additional global interrupt bits
         // if there were to be used for anything in the future.
-        if CSR.mip.is_set(mip::mtimer) {
+        if CSR.mip.is_set(mip::mtimer) || CSR.mip.is_set(mip::msoft) {
             return true;
         }
 
@@ -179,6 +238,39 @@ impl<'a, I: InterruptService + 'a> Chip for QemuRv32VirtChip<'a, I> {
     }
 }
 
+impl<'a, I: InterruptService + 'a> SmpChip for QemuRv32VirtChip<'a, I> {
+    fn num_harts(&self) -> usize {
+        self.ipi.num_harts()
+    }
+
+    fn current_hart(&self) -> usize {
+        current_hart()
+    }
+
+    fn kernel_lock(&self) -> &dyn HartLock {
+        &self.kernel_lock
+    }
+
+    fn hart_scheduler_timer(&self) -> &dyn SchedulerTimer {
+        match self.hart_timers.get(current_hart()) {
+            Some(timer) => timer,
+            None => &(),
+        }
+    }
+
+    fn notify_hart(&self, hart: usize) {
+        let _ = self.ipi.send(hart);
+    }
+
+    fn wait_for_notification(&self) {
+        CSR.mie.modify(mie::msoft::SET);
+        unsafe {
+            rv32i::support::wfi();
+        }
+        self.ipi.clear();
+    }
+}
+
 fn handle_exception(exception: mcause::Exception) {
     match exception {
         mcause::Exception::UserEnvCall | mcause::Exception::SupervisorEnvCall => (),
@@ -16,4 +16,5 @@ pub mod virtio_mmio;
 pub mod chip;
 pub mod clint;
 pub mod plic;
+pub mod smp;
 pub mod uart;
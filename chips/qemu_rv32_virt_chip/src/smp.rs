@@ -0,0 +1,164 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Per-hart CLINT registers, used to run the kernel on several harts.
+//!
+//! The CLINT of the virt machine has a software interrupt register (MSIP)
+//! and a machine timer compare register (MTIMECMP) for each hart. The MSIP
+//! registers are used for inter-processor interrupts, and the MTIMECMP
+//! registers of the harts other than hart 0 for their scheduler timers. Hart
+//! 0 keeps using [`Clint`](sifive::clint::Clint) for its alarm.
+
+use core::cell::Cell;
+use core::num::NonZeroU32;
+
+use kernel::hil::ipi::{InterProcessorInterrupt, InterProcessorInterruptClient};
+use kernel::hil::time::{Freq10MHz, Frequency, Ticks64};
+use kernel::platform::chip::MAX_HARTS;
+use kernel::platform::scheduler_timer::SchedulerTimer;
+use kernel::utilities::cells::OptionalCell;
+use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
+use kernel::utilities::registers::{register_structs, ReadWrite};
+use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
+use rv32i::csr::{mie::mie, CSR};
+use rv32i::machine_timer::MachineTimer;
+
+register_structs! {
+    pub ClintHartRegisters {
+        (0x0000 => msip: [ReadWrite<u32>; MAX_HARTS]),
+        (0x0020 => _reserved0),
+        /// Low and high words of the MTIMECMP register of each hart.
+        (0x4000 => mtimecmp: [ReadWrite<u32>; 2 * MAX_HARTS]),
+        (0x4040 => _reserved1),
+        (0xBFF8 => mtime_low: ReadWrite<u32>),
+        (0xBFFC => mtime_high: ReadWrite<u32>),
+        (0xC000 => @END),
+    }
+}
+
+pub const CLINT_HART_BASE: StaticRef<ClintHartRegisters> =
+    unsafe { StaticRef::new(0x0200_0000 as *const ClintHartRegisters) };
+
+/// Hart this function is called on.
+pub fn current_hart() -> usize {
+    CSR.mhartid.get()
+}
+
+/// Inter-processor interrupts through the MSIP registers.
+///
+/// Only hart 0 services interrupts, so the client is only called for the
+/// interrupts sent to hart 0. On the other harts, an interrupt only wakes up
+/// the hart.
+pub struct ClintIpi<'a> {
+    registers: StaticRef<ClintHartRegisters>,
+    /// Number of harts that were started.
+    num_harts: Cell<usize>,
+    client: OptionalCell<&'a dyn InterProcessorInterruptClient>,
+}
+
+impl ClintIpi<'_> {
+    pub const fn new(base: StaticRef<ClintHartRegisters>) -> Self {
+        Self {
+            registers: base,
+            num_harts: Cell::new(1),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    /// Allow interrupts to be sent to `hart`, once it is running.
+    pub fn enable_hart(&self, hart: usize) {
+        if hart < MAX_HARTS {
+            self.num_harts.set(self.num_harts.get().max(hart + 1));
+        }
+    }
+
+    /// Clear the pending interrupt of the current hart.
+    pub fn clear(&self) {
+        if let Some(msip) = self.registers.msip.get(current_hart()) {
+            msip.set(0);
+        }
+    }
+
+    pub fn handle_interrupt(&self) {
+        self.clear();
+        self.client.map(|client| client.interrupt_received());
+    }
+}
+
+impl<'a> InterProcessorInterrupt<'a> for ClintIpi<'a> {
+    fn set_client(&self, client: &'a dyn InterProcessorInterruptClient) {
+        self.client.set(client);
+    }
+
+    fn num_harts(&self) -> usize {
+        self.num_harts.get()
+    }
+
+    fn current_hart(&self) -> usize {
+        current_hart()
+    }
+
+    fn send(&self, hart: usize) -> Result<(), ErrorCode> {
+        if hart >= self.num_harts.get() {
+            return Err(ErrorCode::INVAL);
+        }
+        self.registers.msip[hart].set(1);
+        Ok(())
+    }
+}
+
+/// Scheduler timer of a hart, with its MTIMECMP register and the machine
+/// timer interrupt. It must only be used on that hart.
+pub struct HartSchedulerTimer {
+    registers: StaticRef<ClintHartRegisters>,
+    hart: usize,
+}
+
+impl HartSchedulerTimer {
+    pub const fn new(base: StaticRef<ClintHartRegisters>, hart: usize) -> Self {
+        Self {
+            registers: base,
+            hart,
+        }
+    }
+
+    fn mtimer(&self) -> MachineTimer<'_> {
+        MachineTimer::new(
+            &self.registers.mtimecmp[2 * self.hart],
+            &self.registers.mtimecmp[2 * self.hart + 1],
+            &self.registers.mtime_low,
+            &self.registers.mtime_high,
+        )
+    }
+}
+
+impl SchedulerTimer for HartSchedulerTimer {
+    fn start(&self, us: NonZeroU32) {
+        let tics = Freq10MHz::frequency() as u64 * us.get() as u64 / 1_000_000;
+        let mtimer = self.mtimer();
+        mtimer.set_alarm(mtimer.now(), Ticks64::from(tics));
+    }
+
+    fn reset(&self) {
+        self.mtimer().disable_machine_timer();
+    }
+
+    fn arm(&self) {
+        CSR.mie.modify(mie::mtimer::SET);
+    }
+
+    fn disarm(&self) {
+        CSR.mie.modify(mie::mtimer::CLEAR);
+    }
+
+    fn get_remaining_us(&self) -> Option<NonZeroU32> {
+        let mtimer = self.mtimer();
+        let alarm = mtimer.get_alarm().into_u64();
+        let now = mtimer.now().into_u64();
+        let tics_per_us = (Freq10MHz::frequency() / 1_000_000) as u64;
+        let remaining_us = alarm.saturating_sub(now) / tics_per_us;
+        NonZeroU32::new(remaining_us.min(u32::MAX as u64) as u32)
+    }
+}
@@ -123,6 +123,7 @@ pub struct Rp2040DefaultPeripherals<'a> {
     pub adc: adc::Adc<'a>,
     pub clocks: Clocks,
     pub i2c0: i2c::I2c<'a, 'a>,
+    pub i2c1: i2c::I2c<'a, 'a>,
     pub pins: RPPins<'a>,
     pub pio0: Pio,
     pub pio1: Pio,
@@ -146,6 +147,7 @@ impl Rp2040DefaultPeripherals<'_> {
             adc: adc::Adc::new(),
             clocks: Clocks::new(),
             i2c0: i2c::I2c::new_i2c0(),
+            i2c1: i2c::I2c::new_i2c1(),
             pins: RPPins::new(),
             pio0: Pio::new_pio0(),
             pio1: Pio::new_pio1(),
@@ -173,6 +175,7 @@ impl Rp2040DefaultPeripherals<'_> {
         kernel::deferred_call::DeferredCallClient::register(&self.uart1);
         kernel::deferred_call::DeferredCallClient::register(&self.rtc);
         self.i2c0.resolve_dependencies(&self.clocks, &self.resets);
+        self.i2c1.resolve_dependencies(&self.clocks, &self.resets);
         self.usb.set_gpio(self.pins.get_pin(RPGpio::GPIO15));
         self.rtc.set_clocks(&self.clocks);
     }
@@ -225,6 +228,10 @@ impl InterruptService for Rp2040DefaultPeripherals<'_> {
                 self.i2c0.handle_interrupt();
                 true
             }
+            interrupts::I2C1_IRQ => {
+                self.i2c1.handle_interrupt();
+                true
+            }
             interrupts::PWM_IRQ_WRAP => {
                 // As the PWM HIL doesn't provide any support for interrupts, they are
                 // simply ignored.
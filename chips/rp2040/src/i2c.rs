@@ -5,6 +5,7 @@
 use crate::clocks;
 use crate::resets;
 use core::cell::Cell;
+use core::cmp;
 use kernel::debug;
 use kernel::hil;
 use kernel::utilities::cells::{OptionalCell, TakeCell};
@@ -23,8 +24,9 @@ use kernel::utilities::StaticRef;
 // but have been modified to be non-blocking through the use of IRQs instead of polling.
 // A future improvement would be to use DMA instead for even less overhead.
 //
-// Currently this driver only supports master mode.
-// Reads and slave support are part of the pico-sdk and still need to be ported here.
+// The controller is either a master or a slave at any given time. Slave mode
+// answers one byte per RX_FULL or RD_REQ interrupt, and the clock is stretched
+// while the master waits for data the client has not provided yet.
 
 register_structs! {
     I2cRegisters {
@@ -45,6 +47,7 @@ register_structs! {
         (0x3c => ic_tx_tl: ReadWrite<u32, IC_TX_TL::Register>),
         (0x40 => ic_clr_intr: ReadOnly<u32, IC_CLR_INTR::Register>),
         (0x44 => _reserved2), // TODO: there are still some registers to list in this gap
+        (0x50 => ic_clr_rd_req: ReadOnly<u32, IC_CLR_RD_REQ::Register>),
         (0x54 => ic_clr_tx_abrt: ReadOnly<u32, IC_CLR_TX_ABRT::Register>),
         (0x58 => _reserved3), // TODO: there are still some registers to list in this gap
         (0x60 => ic_clr_stop_det: ReadOnly<u32, IC_CLR_STOP_DET::Register>),
@@ -173,6 +176,10 @@ register_bitfields! [u32,
     IC_CLR_INTR [
         CLR_INTR OFFSET(0) NUMBITS(1) [],
     ],
+    /// Clear RD_REQ Interrupt Register
+    IC_CLR_RD_REQ [
+        CLR_RD_REQ OFFSET(0) NUMBITS(1) [],
+    ],
     /// Clear TX_ABRT Interrupt Register
     IC_CLR_TX_ABRT [
         CLR_TX_ABRT OFFSET(0) NUMBITS(1) [],
@@ -241,6 +248,18 @@ enum State {
     WaitingForStop,
 }
 
+#[derive(Clone, Copy, PartialEq)]
+enum SlaveState {
+    /// The controller is not listening as a slave
+    Disabled,
+    /// Waiting for a master to address us
+    Listening,
+    /// A master is writing to us
+    Receiving,
+    /// A master is reading from us
+    Transmitting,
+}
+
 pub struct I2c<'a, 'c> {
     instance_num: u8,
     registers: StaticRef<I2cRegisters>,
@@ -257,6 +276,18 @@ pub struct I2c<'a, 'c> {
     rw_index: Cell<i32>,
 
     abort_reason: OptionalCell<LocalRegisterCopy<u32, IC_TX_ABRT_SOURCE::Register>>,
+
+    slave_client: OptionalCell<&'c dyn hil::i2c::I2CHwSlaveClient>,
+    slave_state: Cell<SlaveState>,
+    slave_addr: Cell<u8>,
+    /// Buffer for the bytes written to us by a master
+    slave_write_buf: TakeCell<'static, [u8]>,
+    slave_write_len: Cell<usize>,
+    slave_write_index: Cell<usize>,
+    /// Buffer with the bytes read from us by a master
+    slave_read_buf: TakeCell<'static, [u8]>,
+    slave_read_len: Cell<usize>,
+    slave_read_index: Cell<usize>,
 }
 
 impl<'a> I2c<'a, '_> {
@@ -277,6 +308,16 @@ impl<'a> I2c<'a, '_> {
             rw_index: Cell::new(0),
 
             abort_reason: OptionalCell::empty(),
+
+            slave_client: OptionalCell::empty(),
+            slave_state: Cell::new(SlaveState::Disabled),
+            slave_addr: Cell::new(0),
+            slave_write_buf: TakeCell::empty(),
+            slave_write_len: Cell::new(0),
+            slave_write_index: Cell::new(0),
+            slave_read_buf: TakeCell::empty(),
+            slave_read_len: Cell::new(0),
+            slave_read_index: Cell::new(0),
         }
     }
 
@@ -323,6 +364,36 @@ impl<'a> I2c<'a, '_> {
         self.registers.ic_enable.modify(IC_ENABLE::ENABLE::SET);
     }
 
+    fn enable_master(&self) {
+        self.disable();
+        self.slave_state.set(SlaveState::Disabled);
+        self.registers.ic_con.modify(
+            IC_CON::MASTER_MODE::SET
+                + IC_CON::IC_SLAVE_DISABLE::SET
+                + IC_CON::RX_FIFO_FULL_HLD_CTRL::CLEAR,
+        );
+        self.registers
+            .ic_intr_mask
+            .write(IC_INTR_MASK::M_STOP_DET::SET);
+        self.enable();
+    }
+
+    fn enable_slave(&self) {
+        self.disable();
+        self.registers
+            .ic_sar
+            .write(IC_SAR::IC_SAR.val(self.slave_addr.get() as u32));
+        // Hold the bus when the RX FIFO is full, rather than dropping bytes
+        self.registers.ic_con.modify(
+            IC_CON::MASTER_MODE::CLEAR
+                + IC_CON::IC_SLAVE_DISABLE::CLEAR
+                + IC_CON::RX_FIFO_FULL_HLD_CTRL::SET,
+        );
+        // Interrupts are enabled once we listen
+        self.registers.ic_intr_mask.set(0);
+        self.enable();
+    }
+
     fn set_baudrate(&self, baudrate: u32) -> u32 {
         assert!(baudrate != 0);
 
@@ -673,7 +744,108 @@ impl<'a> I2c<'a, '_> {
         // because we set hwparam IC_AVOID_RX_FIFO_FLUSH_ON_TX_ABRT to 0.
     }
 
+    fn slave_receive_byte(&self) {
+        if self.slave_write_buf.is_none() {
+            self.slave_client.map(|client| client.write_expected());
+        }
+
+        // Bytes beyond the buffer, or without a buffer at all, are dropped
+        let byte = self.registers.ic_data_cmd.read(IC_DATA_CMD::DAT) as u8;
+        let idx = self.slave_write_index.get();
+        self.slave_write_buf.map(|buf| {
+            if idx < self.slave_write_len.get() {
+                buf[idx] = byte;
+                self.slave_write_index.set(idx + 1);
+            }
+        });
+        self.slave_state.set(SlaveState::Receiving);
+    }
+
+    fn slave_send_byte(&self) {
+        if self.slave_read_buf.is_none() {
+            // The hardware stretches the clock until RD_REQ is cleared, so
+            // stop listening to it until read_send() provides the data.
+            self.registers
+                .ic_intr_mask
+                .modify(IC_INTR_MASK::M_RD_REQ::CLEAR);
+            self.slave_client.map(|client| client.read_expected());
+            return;
+        }
+
+        // Once the buffer is exhausted, the master reads 0xff
+        let idx = self.slave_read_index.get();
+        let byte = self.slave_read_buf.map_or(0xff, |buf| {
+            if idx < self.slave_read_len.get() {
+                self.slave_read_index.set(idx + 1);
+                buf[idx]
+            } else {
+                0xff
+            }
+        });
+        self.registers
+            .ic_data_cmd
+            .write(IC_DATA_CMD::DAT.val(byte as u32));
+        // Reset by read
+        self.registers.ic_clr_rd_req.get();
+        self.slave_state.set(SlaveState::Transmitting);
+    }
+
+    /// Report the end of the current slave transfer, on a STOP or when the
+    /// master restarts in the other direction.
+    fn slave_transfer_done(&self) {
+        let state = self.slave_state.get();
+        self.slave_state.set(SlaveState::Listening);
+        match state {
+            SlaveState::Receiving => self.slave_write_buf.take().map(|buf| {
+                let length = self.slave_write_index.get();
+                self.slave_write_index.set(0);
+                self.slave_client.map(|client| {
+                    client.command_complete(buf, length, hil::i2c::SlaveTransmissionType::Write)
+                });
+            }),
+            SlaveState::Transmitting => self.slave_read_buf.take().map(|buf| {
+                let length = self.slave_read_index.get();
+                self.slave_read_index.set(0);
+                self.slave_client.map(|client| {
+                    client.command_complete(buf, length, hil::i2c::SlaveTransmissionType::Read)
+                });
+            }),
+            SlaveState::Disabled | SlaveState::Listening => None,
+        };
+    }
+
+    fn handle_slave_interrupt(&self) {
+        let status = self.registers.ic_intr_stat.extract();
+
+        if status.is_set(IC_INTR_STAT::R_TX_ABRT) {
+            // The hardware flushed a byte the master did not read. It holds
+            // the TX FIFO in reset until the abort is cleared.
+            self.registers.ic_clr_tx_abrt.get();
+        }
+        if status.is_set(IC_INTR_STAT::R_RX_FULL) {
+            if self.slave_state.get() == SlaveState::Transmitting {
+                self.slave_transfer_done();
+            }
+            self.slave_receive_byte();
+        }
+        if status.is_set(IC_INTR_STAT::R_RD_REQ) {
+            if self.slave_state.get() == SlaveState::Receiving {
+                self.slave_transfer_done();
+            }
+            self.slave_send_byte();
+        }
+        if status.is_set(IC_INTR_STAT::R_STOP_DET) {
+            // Reset by read
+            self.registers.ic_clr_stop_det.get();
+            self.slave_transfer_done();
+        }
+    }
+
     pub fn handle_interrupt(&self) {
+        if self.slave_state.get() != SlaveState::Disabled {
+            self.handle_slave_interrupt();
+            return;
+        }
         match self.state.get() {
             State::Uninitialized => debug!(
                 "Unexpected IRQ for uninitialized I2C device {}",
@@ -694,8 +866,7 @@ impl<'c> hil::i2c::I2CMaster<'c> for I2c<'_, 'c> {
     }
 
     fn enable(&self) {
-        self.enable();
-        // TODO: set as master once we support slave mode too
+        self.enable_master();
     }
 
     fn disable(&self) {
@@ -745,3 +916,82 @@ impl<'c> hil::i2c::I2CMaster<'c> for I2c<'_, 'c> {
         }
     }
 }
+
+impl<'c> hil::i2c::I2CSlave<'c> for I2c<'_, 'c> {
+    fn set_slave_client(&self, client: &'c dyn hil::i2c::I2CHwSlaveClient) {
+        self.slave_client.set(client);
+    }
+
+    fn enable(&self) {
+        self.enable_slave();
+    }
+
+    fn disable(&self) {
+        self.slave_state.set(SlaveState::Disabled);
+        self.registers.ic_intr_mask.set(0);
+        self.disable();
+    }
+
+    fn set_address(&self, addr: u8) -> Result<(), hil::i2c::Error> {
+        if addr > 0x7f {
+            return Err(hil::i2c::Error::NotSupported);
+        }
+        self.slave_addr.set(addr);
+
+        // IC_SAR can only be written while the controller is disabled
+        let enabled = self.registers.ic_enable.is_set(IC_ENABLE::ENABLE);
+        self.disable();
+        self.registers.ic_sar.write(IC_SAR::IC_SAR.val(addr as u32));
+        if enabled {
+            self.enable();
+        }
+        Ok(())
+    }
+
+    fn write_receive(
+        &self,
+        data: &'static mut [u8],
+        max_len: usize,
+    ) -> Result<(), (hil::i2c::Error, &'static mut [u8])> {
+        if self.slave_write_buf.is_some() {
+            return Err((hil::i2c::Error::Busy, data));
+        }
+        self.slave_write_len.set(cmp::min(max_len, data.len()));
+        self.slave_write_index.set(0);
+        self.slave_write_buf.replace(data);
+        Ok(())
+    }
+
+    fn read_send(
+        &self,
+        data: &'static mut [u8],
+        max_len: usize,
+    ) -> Result<(), (hil::i2c::Error, &'static mut [u8])> {
+        if self.slave_read_buf.is_some() {
+            return Err((hil::i2c::Error::Busy, data));
+        }
+        self.slave_read_len.set(cmp::min(max_len, data.len()));
+        self.slave_read_index.set(0);
+        self.slave_read_buf.replace(data);
+
+        // Answer a read the master may already be stretching the clock for
+        if self.slave_state.get() != SlaveState::Disabled {
+            self.registers
+                .ic_intr_mask
+                .modify(IC_INTR_MASK::M_RD_REQ::SET);
+        }
+        Ok(())
+    }
+
+    fn listen(&self) {
+        self.slave_state.set(SlaveState::Listening);
+        self.registers.ic_intr_mask.write(
+            IC_INTR_MASK::M_RX_FULL::SET
+                + IC_INTR_MASK::M_RD_REQ::SET
+                + IC_INTR_MASK::M_TX_ABRT::SET
+                + IC_INTR_MASK::M_STOP_DET::SET,
+        );
+    }
+}
+
+impl<'c> hil::i2c::I2CMasterSlave<'c> for I2c<'_, 'c> {}
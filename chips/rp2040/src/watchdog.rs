@@ -3,9 +3,10 @@
 // Copyright Tock Contributors 2022.
 
 use kernel::utilities::cells::OptionalCell;
-use kernel::utilities::registers::interfaces::{ReadWriteable, Writeable};
+use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
 use kernel::utilities::registers::{register_bitfields, register_structs, ReadWrite};
 use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
 
 use crate::resets;
 
@@ -133,3 +134,37 @@ impl<'a> Watchdog<'a> {
         self.registers.ctrl.write(CTRL::TRIGGER::SET);
     }
 }
+
+/// The scratch registers 0 to 3, which persist through a soft reset. The
+/// bootrom uses the scratch registers 4 to 7 to boot to a given address after
+/// a watchdog reset, so they are not available.
+impl kernel::hil::retained::RetainedRegisters for Watchdog<'_> {
+    fn count(&self) -> usize {
+        4
+    }
+
+    fn width(&self) -> usize {
+        32
+    }
+
+    fn read(&self, index: usize) -> Result<u32, ErrorCode> {
+        match index {
+            0 => Ok(self.registers.scratch0.get()),
+            1 => Ok(self.registers.scratch1.get()),
+            2 => Ok(self.registers.scratch2.get()),
+            3 => Ok(self.registers.scratch3.get()),
+            _ => Err(ErrorCode::INVAL),
+        }
+    }
+
+    fn write(&self, index: usize, value: u32) -> Result<(), ErrorCode> {
+        match index {
+            0 => self.registers.scratch0.set(value),
+            1 => self.registers.scratch1.set(value),
+            2 => self.registers.scratch2.set(value),
+            3 => self.registers.scratch3.set(value),
+            _ => return Err(ErrorCode::INVAL),
+        }
+        Ok(())
+    }
+}
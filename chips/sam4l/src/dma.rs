@@ -234,13 +234,14 @@ impl DMAChannel {
 
     pub fn disable(&self) {
         if self.enabled.get() {
+            // Disable the channel while its clock is still running.
+            self.registers.cr.write(Control::TDIS::SET);
+            self.enabled.set(false);
             let num_enabled = NUM_ENABLED.fetch_sub(1, atomic::Ordering::Relaxed);
             if num_enabled == 1 {
                 pm::disable_clock(pm::Clock::HSB(pm::HSBClock::PDCA));
                 pm::disable_clock(pm::Clock::PBB(pm::PBBClock::PDCA));
             }
-            self.registers.cr.write(Control::TDIS::SET);
-            self.enabled.set(false);
         }
     }
 
@@ -110,6 +110,8 @@ const WDT_REGS: StaticRef<WdtRegisters> = unsafe { StaticRef::new(WDT_BASE.cast_
 
 pub struct Wdt {
     enabled: Cell<bool>,
+    /// Period set by the kernel, in ms.
+    period: Cell<usize>,
 }
 
 #[derive(Copy, Clone)]
@@ -131,9 +133,33 @@ impl Wdt {
     pub const fn new() -> Wdt {
         Wdt {
             enabled: Cell::new(false),
+            period: Cell::new(100),
         }
     }
 
+    /// Arm the watchdog at the very start of board setup, so that a hang
+    /// while the clocks, flash or peripherals are set up resets the chip.
+    ///
+    /// The watchdog runs from the RCSYS oscillator, which needs no setup, and
+    /// fires after at least `timeout_ms`. Nothing tickles it until the kernel
+    /// loop starts and the kernel takes it over with
+    /// [`WatchDog::setup`](kernel::platform::watchdog::WatchDog::setup), so
+    /// `timeout_ms` must be longer than all of board setup, including
+    /// loading processes.
+    pub fn arm_early(&self, timeout_ms: usize) {
+        pm::enable_clock(Clock::PBD(PBDClock::WDT));
+
+        // RCSYS runs at 115 kHz.
+        let scaler = log_base_two_u64(115 * (timeout_ms as u64));
+        let control = Control::CSSEL::RCSYS
+            + Control::CEN::ClockEnable
+            + Control::PSEL.val(scaler)
+            + Control::FCD::DoNotRedoCalibration
+            + Control::DAR::DisableAfterReset
+            + Control::EN::Enable;
+        self.write_cr(control);
+    }
+
     /// WDT Errata: §45.1.3
     ///
     /// When writing any of the PSEL, TBAN, EN, or MODE fields, must insert a
@@ -178,9 +204,16 @@ impl Wdt {
 
     fn start(&self, period: usize) {
         self.enabled.set(true);
+        self.period.set(period);
 
         pm::enable_clock(Clock::PBD(PBDClock::WDT));
 
+        // The configuration can only change while the watchdog is disabled,
+        // for example if it was armed by `arm_early()`.
+        if WDT_REGS.cr.is_set(Control::EN) {
+            self.write_cr(Control::EN::CLEAR);
+        }
+
         // Note: Must use this clock to allow deep sleep. If you leave the
         // default RCSYS, then the watchdog simply will not fire if you enter
         // deep sleep (despite §20.4.1's protestations to the contrary).
@@ -239,4 +272,8 @@ impl kernel::platform::watchdog::WatchDog for Wdt {
     fn suspend(&self) {
         self.stop();
     }
+
+    fn resume(&self) {
+        self.start(self.period.get());
+    }
 }
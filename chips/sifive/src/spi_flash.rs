@@ -0,0 +1,393 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Driver for writing the SPI flash the core executes from.
+//!
+//! The SPI flash controller normally maps the flash into the address space.
+//! To program or erase the flash, the driver leaves that mode, sends the
+//! commands to the flash itself, and maps the flash again. The core cannot
+//! fetch instructions from flash in between, so that code runs from RAM
+//! (section `.ramfunc`) with interrupts disabled. Operations block the core
+//! until the flash is done: erasing a sector takes tens of milliseconds.
+//!
+//! Pages are the 256 byte program pages of the flash, but the flash erases
+//! 4 KiB sectors. Writing the first page of a sector erases the whole sector,
+//! so the pages of a sector must be written in order from the first one, as
+//! when writing a region sequentially. `erase_page` erases the whole sector of
+//! the page. The flash must accept the standard single lane commands.
+
+use core::cell::Cell;
+use core::ops::{Index, IndexMut};
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::registers::{ReadOnly, ReadWrite};
+use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
+
+/// Size of a program page of the flash.
+pub const PAGE_SIZE: usize = 256;
+
+/// Size of an erase sector of the flash.
+pub const SECTOR_SIZE: usize = 4096;
+
+#[repr(C)]
+pub struct SpiFlashRegisters {
+    /// Serial clock divisor
+    sckdiv: ReadWrite<u32>,
+    /// Serial clock mode
+    sckmode: ReadWrite<u32>,
+    _reserved0: [u8; 8],
+    /// Chip select ID
+    csid: ReadWrite<u32>,
+    /// Chip select default
+    csdef: ReadWrite<u32>,
+    /// Chip select mode
+    csmode: ReadWrite<u32>,
+    _reserved1: [u8; 12],
+    /// Delay control 0
+    delay0: ReadWrite<u32>,
+    /// Delay control 1
+    delay1: ReadWrite<u32>,
+    _reserved2: [u8; 16],
+    /// Frame format
+    fmt: ReadWrite<u32>,
+    _reserved3: [u8; 4],
+    /// Tx FIFO data
+    txdata: ReadWrite<u32>,
+    /// Rx FIFO data
+    rxdata: ReadOnly<u32>,
+    /// Tx FIFO watermark
+    txmark: ReadWrite<u32>,
+    /// Rx FIFO watermark
+    rxmark: ReadWrite<u32>,
+    _reserved4: [u8; 8],
+    /// SPI flash interface control
+    fctrl: ReadWrite<u32>,
+    /// SPI flash instruction format
+    ffmt: ReadWrite<u32>,
+    _reserved5: [u8; 8],
+    /// SPI interrupt enable
+    ie: ReadWrite<u32>,
+    /// SPI interrupt pending
+    ip: ReadOnly<u32>,
+}
+
+/// A program page of the flash.
+pub struct SpiFlashPage(pub [u8; PAGE_SIZE]);
+
+impl Default for SpiFlashPage {
+    fn default() -> Self {
+        Self([0; PAGE_SIZE])
+    }
+}
+
+impl Index<usize> for SpiFlashPage {
+    type Output = u8;
+
+    fn index(&self, idx: usize) -> &u8 {
+        &self.0[idx]
+    }
+}
+
+impl IndexMut<usize> for SpiFlashPage {
+    fn index_mut(&mut self, idx: usize) -> &mut u8 {
+        &mut self.0[idx]
+    }
+}
+
+impl AsMut<[u8]> for SpiFlashPage {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Operation {
+    Read,
+    Write,
+    Erase,
+}
+
+pub struct SpiFlash {
+    registers: StaticRef<SpiFlashRegisters>,
+    /// Address the flash is mapped at.
+    mapped_address: usize,
+    /// Size of the flash.
+    size: usize,
+    client: OptionalCell<&'static dyn hil::flash::Client<SpiFlash>>,
+    buffer: TakeCell<'static, SpiFlashPage>,
+    operation: Cell<Option<Operation>>,
+    deferred_call: DeferredCall,
+}
+
+impl SpiFlash {
+    pub fn new(base: StaticRef<SpiFlashRegisters>, mapped_address: usize, size: usize) -> SpiFlash {
+        SpiFlash {
+            registers: base,
+            mapped_address,
+            size,
+            client: OptionalCell::empty(),
+            buffer: TakeCell::empty(),
+            operation: Cell::new(None),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    fn page_address(&self, page_number: usize) -> Option<usize> {
+        page_number
+            .checked_mul(PAGE_SIZE)
+            .filter(|address| *address < self.size)
+    }
+
+    fn start(&self, operation: Operation) -> Result<(), ErrorCode> {
+        if self.operation.get().is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.operation.set(Some(operation));
+        self.deferred_call.set();
+        Ok(())
+    }
+
+    fn read_page(
+        &self,
+        page_number: usize,
+        buf: &'static mut SpiFlashPage,
+    ) -> Result<(), (ErrorCode, &'static mut SpiFlashPage)> {
+        let Some(address) = self.page_address(page_number) else {
+            return Err((ErrorCode::INVAL, buf));
+        };
+        if let Err(e) = self.start(Operation::Read) {
+            return Err((e, buf));
+        }
+        // SAFETY: the flash is mapped, and the page is inside of it.
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                (self.mapped_address + address) as *const u8,
+                buf.0.as_mut_ptr(),
+                PAGE_SIZE,
+            );
+        }
+        self.buffer.replace(buf);
+        Ok(())
+    }
+
+    fn write_page(
+        &self,
+        page_number: usize,
+        buf: &'static mut SpiFlashPage,
+    ) -> Result<(), (ErrorCode, &'static mut SpiFlashPage)> {
+        let Some(address) = self.page_address(page_number) else {
+            return Err((ErrorCode::INVAL, buf));
+        };
+        if let Err(e) = self.start(Operation::Write) {
+            return Err((e, buf));
+        }
+        let registers = &*self.registers as *const SpiFlashRegisters;
+        // SAFETY: interrupts are disabled while the flash is not mapped, and
+        // the code that runs meanwhile is in RAM.
+        unsafe {
+            rv32i::support::atomic(|| {
+                if address % SECTOR_SIZE == 0 {
+                    erase_sector(registers, address);
+                }
+                program_page(registers, address, buf.0.as_ptr());
+            });
+        }
+        self.buffer.replace(buf);
+        Ok(())
+    }
+
+    fn erase_page(&self, page_number: usize) -> Result<(), ErrorCode> {
+        let address = self.page_address(page_number).ok_or(ErrorCode::INVAL)?;
+        self.start(Operation::Erase)?;
+        let registers = &*self.registers as *const SpiFlashRegisters;
+        // SAFETY: interrupts are disabled while the flash is not mapped, and
+        // the code that runs meanwhile is in RAM.
+        unsafe {
+            rv32i::support::atomic(|| {
+                erase_sector(registers, address - address % SECTOR_SIZE);
+            });
+        }
+        Ok(())
+    }
+}
+
+// Flash commands.
+const WRITE_ENABLE: u32 = 0x06;
+const PAGE_PROGRAM: u32 = 0x02;
+const SECTOR_ERASE: u32 = 0x20;
+const READ_STATUS: u32 = 0x05;
+/// Status bit set while the flash is writing.
+const STATUS_BUSY: u32 = 1 << 0;
+
+/// `fmt`: single lane, MSB first, keep received bytes, 8 bit frames.
+const FMT_SINGLE_8_BITS: u32 = 8 << 16;
+const CSMODE_AUTO: u32 = 0;
+const CSMODE_HOLD: u32 = 2;
+/// Set in `txdata` while the Tx FIFO is full, and in `rxdata` while the Rx
+/// FIFO is empty.
+const FIFO_FLAG: u32 = 1 << 31;
+
+// The functions below run while the flash is not mapped. They must not call
+// any function in flash, so they only use volatile accesses and no code that
+// may panic.
+
+/// Send `byte` to the flash and return the byte it sent back.
+#[link_section = ".ramfunc"]
+#[inline(never)]
+unsafe fn transfer(registers: *const SpiFlashRegisters, byte: u32) -> u32 {
+    let txdata = core::ptr::addr_of!((*registers).txdata) as *mut u32;
+    let rxdata = core::ptr::addr_of!((*registers).rxdata) as *const u32;
+    while core::ptr::read_volatile(txdata) & FIFO_FLAG != 0 {}
+    core::ptr::write_volatile(txdata, byte);
+    loop {
+        let received = core::ptr::read_volatile(rxdata);
+        if received & FIFO_FLAG == 0 {
+            return received & 0xFF;
+        }
+    }
+}
+
+/// Leave the memory mapped mode, and empty the Rx FIFO.
+#[link_section = ".ramfunc"]
+#[inline(never)]
+unsafe fn unmap(registers: *const SpiFlashRegisters) {
+    let fctrl = core::ptr::addr_of!((*registers).fctrl) as *mut u32;
+    let fmt = core::ptr::addr_of!((*registers).fmt) as *mut u32;
+    let rxdata = core::ptr::addr_of!((*registers).rxdata) as *const u32;
+    core::ptr::write_volatile(fctrl, 0);
+    core::ptr::write_volatile(fmt, FMT_SINGLE_8_BITS);
+    while core::ptr::read_volatile(rxdata) & FIFO_FLAG == 0 {}
+}
+
+/// Send `command` and the 24 bit `address` while holding the chip select.
+#[link_section = ".ramfunc"]
+#[inline(never)]
+unsafe fn begin_command(registers: *const SpiFlashRegisters, command: u32, address: usize) {
+    let csmode = core::ptr::addr_of!((*registers).csmode) as *mut u32;
+    core::ptr::write_volatile(csmode, CSMODE_HOLD);
+    transfer(registers, command);
+    transfer(registers, (address as u32 >> 16) & 0xFF);
+    transfer(registers, (address as u32 >> 8) & 0xFF);
+    transfer(registers, address as u32 & 0xFF);
+}
+
+/// Release the chip select, which ends the command.
+#[link_section = ".ramfunc"]
+#[inline(never)]
+unsafe fn end_command(registers: *const SpiFlashRegisters) {
+    let csmode = core::ptr::addr_of!((*registers).csmode) as *mut u32;
+    core::ptr::write_volatile(csmode, CSMODE_AUTO);
+}
+
+/// Enable writes, which the flash disables after every program or erase.
+#[link_section = ".ramfunc"]
+#[inline(never)]
+unsafe fn write_enable(registers: *const SpiFlashRegisters) {
+    let csmode = core::ptr::addr_of!((*registers).csmode) as *mut u32;
+    core::ptr::write_volatile(csmode, CSMODE_HOLD);
+    transfer(registers, WRITE_ENABLE);
+    end_command(registers);
+}
+
+/// Wait for the flash to finish writing, and map it again.
+#[link_section = ".ramfunc"]
+#[inline(never)]
+unsafe fn wait_and_map(registers: *const SpiFlashRegisters) {
+    let csmode = core::ptr::addr_of!((*registers).csmode) as *mut u32;
+    let fctrl = core::ptr::addr_of!((*registers).fctrl) as *mut u32;
+    core::ptr::write_volatile(csmode, CSMODE_HOLD);
+    transfer(registers, READ_STATUS);
+    while transfer(registers, 0) & STATUS_BUSY != 0 {}
+    end_command(registers);
+    core::ptr::write_volatile(fctrl, 1);
+}
+
+/// Erase the sector at `address` of the flash.
+#[link_section = ".ramfunc"]
+#[inline(never)]
+unsafe fn erase_sector(registers: *const SpiFlashRegisters, address: usize) {
+    unmap(registers);
+    write_enable(registers);
+    begin_command(registers, SECTOR_ERASE, address);
+    end_command(registers);
+    wait_and_map(registers);
+}
+
+/// Program the page at `address` of the flash with the `PAGE_SIZE` bytes at
+/// `data`.
+#[link_section = ".ramfunc"]
+#[inline(never)]
+unsafe fn program_page(registers: *const SpiFlashRegisters, address: usize, data: *const u8) {
+    unmap(registers);
+    write_enable(registers);
+    begin_command(registers, PAGE_PROGRAM, address);
+    let mut i = 0;
+    while i < PAGE_SIZE {
+        transfer(registers, core::ptr::read_volatile(data.add(i)) as u32);
+        i += 1;
+    }
+    end_command(registers);
+    wait_and_map(registers);
+}
+
+impl<C: hil::flash::Client<Self>> hil::flash::HasClient<'static, C> for SpiFlash {
+    fn set_client(&self, client: &'static C) {
+        self.client.set(client);
+    }
+}
+
+impl hil::flash::Flash for SpiFlash {
+    type Page = SpiFlashPage;
+
+    fn read_page(
+        &self,
+        page_number: usize,
+        buf: &'static mut Self::Page,
+    ) -> Result<(), (ErrorCode, &'static mut Self::Page)> {
+        self.read_page(page_number, buf)
+    }
+
+    fn write_page(
+        &self,
+        page_number: usize,
+        buf: &'static mut Self::Page,
+    ) -> Result<(), (ErrorCode, &'static mut Self::Page)> {
+        self.write_page(page_number, buf)
+    }
+
+    fn erase_page(&self, page_number: usize) -> Result<(), ErrorCode> {
+        self.erase_page(page_number)
+    }
+}
+
+impl DeferredCallClient for SpiFlash {
+    fn handle_deferred_call(&self) {
+        match self.operation.take() {
+            Some(Operation::Read) => {
+                self.client.map(|client| {
+                    self.buffer
+                        .take()
+                        .map(|buffer| client.read_complete(buffer, Ok(())));
+                });
+            }
+            Some(Operation::Write) => {
+                self.client.map(|client| {
+                    self.buffer
+                        .take()
+                        .map(|buffer| client.write_complete(buffer, Ok(())));
+                });
+            }
+            Some(Operation::Erase) => {
+                self.client.map(|client| client.erase_complete(Ok(())));
+            }
+            None => {}
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
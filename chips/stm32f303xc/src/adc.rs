@@ -3,13 +3,17 @@
 // Copyright Tock Contributors 2022.
 
 //! Analog to Digital Converter Peripheral
+//!
+//! The ADC samples relative to the VREF+ pin, which is bonded to VDDA on
+//! small packages. Channel `n` can be sampled differentially against channel
+//! `n + 1`, and oversampling averages consecutive conversions in software.
 
 use crate::rcc;
 use core::cell::Cell;
 use kernel::hil;
 use kernel::platform::chip::ClockInterface;
 use kernel::utilities::cells::OptionalCell;
-use kernel::utilities::registers::interfaces::{ReadWriteable, Readable};
+use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
 use kernel::utilities::registers::{register_bitfields, ReadOnly, ReadWrite};
 use kernel::utilities::StaticRef;
 use kernel::ErrorCode;
@@ -499,6 +503,9 @@ enum ADCStatus {
     Continuous,
 }
 
+/// Largest number of conversions averaged into a sample.
+const MAX_OVERSAMPLING: usize = 256;
+
 pub struct Adc<'a> {
     registers: StaticRef<AdcRegisters>,
     common_registers: StaticRef<AdcCommonRegisters>,
@@ -508,6 +515,11 @@ pub struct Adc<'a> {
     requested: Cell<ADCStatus>,
     requested_channel: Cell<u32>,
     sc_enabled: Cell<bool>,
+    differential_channels: Cell<u32>,
+    differential: Cell<bool>,
+    oversampling: Cell<usize>,
+    conversions: Cell<usize>,
+    sum: Cell<u32>,
 }
 
 impl<'a> Adc<'a> {
@@ -524,6 +536,11 @@ impl<'a> Adc<'a> {
             requested: Cell::new(ADCStatus::Idle),
             requested_channel: Cell::new(0),
             sc_enabled: Cell::new(false),
+            differential_channels: Cell::new(0),
+            differential: Cell::new(false),
+            oversampling: Cell::new(1),
+            conversions: Cell::new(0),
+            sum: Cell::new(0),
         }
     }
 
@@ -563,6 +580,16 @@ impl<'a> Adc<'a> {
         // Wait for calibration
         while self.registers.cr.is_set(CR::ADCAL) {}
 
+        // Calibrate the differential mode as well
+        self.registers.cr.modify(CR::ADCALDIF::SET);
+        self.registers.cr.modify(CR::ADCAL::SET);
+        while self.registers.cr.is_set(CR::ADCAL) {}
+        self.registers.cr.modify(CR::ADCALDIF::CLEAR);
+
+        // The differential channels can only be selected while the ADC is
+        // disabled
+        self.registers.difsel.set(self.differential_channels.get());
+
         // Enable ADC
         self.registers.cr.modify(CR::ADEN::SET);
         // Enable overrun to overwrite old datas
@@ -591,8 +618,20 @@ impl<'a> Adc<'a> {
             // Clear interrupt
             self.registers.ier.modify(IER::EOCIE::CLEAR);
             let data = self.registers.dr.read(DR::RDATA);
-            self.client
-                .map(|client| client.sample_ready((data as u16) << 4));
+            self.sum.set(self.sum.get() + data);
+            self.conversions.set(self.conversions.get() + 1);
+            if self.conversions.get() >= self.oversampling.get() {
+                let data = self.sum.get() / self.conversions.get() as u32;
+                self.sum.set(0);
+                self.conversions.set(0);
+                // Differential samples are offset by half of the range
+                let sample = if self.differential.get() {
+                    ((data as i32 - 2048) << 4) as i16 as u16
+                } else {
+                    (data as u16) << 4
+                };
+                self.client.map(|client| client.sample_ready(sample));
+            }
             if self.status.get() == ADCStatus::Continuous {
                 self.registers.ier.modify(IER::EOCIE::SET);
             }
@@ -603,10 +642,17 @@ impl<'a> Adc<'a> {
             self.registers.ier.modify(IER::EOSIE::CLEAR);
             self.registers.isr.modify(ISR::EOS::SET);
             if self.status.get() == ADCStatus::OneSample {
-                // stop adc
-                self.registers.cr.modify(CR::ADSTP::SET);
-                // set state
-                self.status.set(ADCStatus::Idle);
+                if self.conversions.get() > 0 {
+                    // more conversions to average into the sample
+                    self.registers.ier.modify(IER::EOSIE::SET);
+                    self.registers.ier.modify(IER::EOCIE::SET);
+                    self.registers.cr.modify(CR::ADSTART::SET);
+                } else {
+                    // stop adc
+                    self.registers.cr.modify(CR::ADSTP::SET);
+                    // set state
+                    self.status.set(ADCStatus::Idle);
+                }
             }
         }
         // Check if sampling ended
@@ -650,6 +696,8 @@ impl<'a> Adc<'a> {
         if self.status.get() == ADCStatus::Idle {
             self.requested.set(ADCStatus::Idle);
             self.status.set(ADCStatus::OneSample);
+            self.sum.set(0);
+            self.conversions.set(0);
             self.registers.smpr2.modify(SMPR2::SMP16.val(0b100));
             self.registers.sqr1.modify(SQR1::L.val(0b0000));
             self.registers.sqr1.modify(SQR1::SQ1.val(channel));
@@ -662,6 +710,41 @@ impl<'a> Adc<'a> {
             Err(ErrorCode::BUSY)
         }
     }
+
+    // Sample `channel`, differentially against the next channel if
+    // `differential` is set. The ADC is enabled first if needed, and
+    // re-enabled if the channel must switch between single-ended and
+    // differential mode.
+    fn request_sample(&self, channel: u32, differential: bool) -> Result<(), ErrorCode> {
+        let bit = 1 << channel;
+        let differential_channels = if differential {
+            self.differential_channels.get() | bit
+        } else {
+            self.differential_channels.get() & !bit
+        };
+        match self.status.get() {
+            ADCStatus::Off => {
+                self.requested.set(ADCStatus::OneSample);
+                self.requested_channel.set(channel);
+                self.differential_channels.set(differential_channels);
+                self.differential.set(differential);
+                self.enable();
+                Ok(())
+            }
+            ADCStatus::Idle if differential_channels != self.differential_channels.get() => {
+                // Disable the ADC to change the differential channels
+                self.registers.cr.modify(CR::ADDIS::SET);
+                while self.registers.cr.is_set(CR::ADEN) {}
+                self.status.set(ADCStatus::Off);
+                self.request_sample(channel, differential)
+            }
+            ADCStatus::Idle => {
+                self.differential.set(differential);
+                self.sample_u32(channel)
+            }
+            _ => Err(ErrorCode::BUSY),
+        }
+    }
 }
 
 struct AdcClock<'a>(rcc::PeripheralClock<'a>);
@@ -684,14 +767,7 @@ impl<'a> hil::adc::Adc<'a> for Adc<'a> {
     type Channel = Channel;
 
     fn sample(&self, channel: &Self::Channel) -> Result<(), ErrorCode> {
-        if self.status.get() == ADCStatus::Off {
-            self.requested.set(ADCStatus::OneSample);
-            self.requested_channel.set(*channel as u32);
-            self.enable();
-            Ok(())
-        } else {
-            self.sample_u32(*channel as u32)
-        }
+        self.request_sample(*channel as u32, false)
     }
 
     fn sample_continuous(
@@ -728,6 +804,49 @@ impl<'a> hil::adc::Adc<'a> for Adc<'a> {
     }
 }
 
+impl<'a> hil::adc::AdcConfigure<'a> for Adc<'a> {
+    fn set_reference(&self, reference: hil::adc::Reference) -> Result<(), ErrorCode> {
+        match reference {
+            hil::adc::Reference::External => Ok(()),
+            _ => Err(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn get_reference(&self) -> hil::adc::Reference {
+        hil::adc::Reference::External
+    }
+
+    fn set_oversampling(&self, samples: usize) -> Result<(), ErrorCode> {
+        if !samples.is_power_of_two() || samples > MAX_OVERSAMPLING {
+            Err(ErrorCode::INVAL)
+        } else if self.status.get() != ADCStatus::Idle && self.status.get() != ADCStatus::Off {
+            Err(ErrorCode::BUSY)
+        } else {
+            self.oversampling.set(samples);
+            Ok(())
+        }
+    }
+
+    fn get_oversampling(&self) -> usize {
+        self.oversampling.get()
+    }
+}
+
+impl<'a> hil::adc::AdcDifferential<'a> for Adc<'a> {
+    /// Channels 1 to 14 can be sampled against the next channel.
+    fn sample_differential(
+        &self,
+        positive: &Self::Channel,
+        negative: &Self::Channel,
+    ) -> Result<(), ErrorCode> {
+        let channel = *positive as u32;
+        if channel == 0 || channel > 14 || *negative as u32 != channel + 1 {
+            return Err(ErrorCode::INVAL);
+        }
+        self.request_sample(channel, true)
+    }
+}
+
 /// Not yet supported
 impl<'a> hil::adc::AdcHighSpeed<'a> for Adc<'a> {
     /// Capture buffered samples from the ADC continuously at a given
@@ -14,6 +14,10 @@
 //! + Set time from which real time clock should start counting
 //! + Read current time from the RTC registers
 //!
+//! The driver also implements the retained registers HIL with the 20 backup
+//! registers, which keep their value across resets, and while VBAT is powered.
+//! They can only be written after [`Rtc::enable_clock`].
+//!
 
 use core::cell::Cell;
 use kernel::deferred_call::{DeferredCall, DeferredCallClient};
@@ -21,12 +25,15 @@ use kernel::hil::date_time;
 use kernel::hil::date_time::{DateTimeClient, DateTimeValues, DayOfWeek, Month};
 use kernel::platform::chip::ClockInterface;
 use kernel::utilities::cells::OptionalCell;
-use kernel::utilities::registers::interfaces::{ReadWriteable, Readable};
+use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
 use kernel::utilities::registers::{register_bitfields, ReadWrite};
 use kernel::utilities::StaticRef;
 use kernel::ErrorCode;
 use stm32f4xx::clocks::{phclk, Stm32f4Clocks};
 
+/// Number of backup registers, which are kept in the backup domain.
+const NUM_BACKUP_REGISTERS: usize = 20;
+
 /// Register block to control RTC
 #[repr(C)]
 pub struct RtcRegisters {
@@ -70,7 +77,7 @@ pub struct RtcRegisters {
     rtc_alrmbssr: ReadWrite<u32, RTC_ALRMBSSR::Register>,
 
     /// The application can write or read data to and from these registers
-    rtc_bkpxr: [ReadWrite<u32, RTC_BKPXR::Register>; 19],
+    rtc_bkpxr: [ReadWrite<u32, RTC_BKPXR::Register>; NUM_BACKUP_REGISTERS],
 }
 
 register_bitfields![u32,
@@ -674,3 +681,35 @@ impl<'a> date_time::DateTime<'a> for Rtc<'a> {
         self.client.set(client);
     }
 }
+
+impl kernel::hil::retained::RetainedRegisters for Rtc<'_> {
+    fn count(&self) -> usize {
+        NUM_BACKUP_REGISTERS
+    }
+
+    fn width(&self) -> usize {
+        32
+    }
+
+    fn read(&self, index: usize) -> Result<u32, ErrorCode> {
+        self.registers
+            .rtc_bkpxr
+            .get(index)
+            .map(|register| register.get())
+            .ok_or(ErrorCode::INVAL)
+    }
+
+    fn write(&self, index: usize, value: u32) -> Result<(), ErrorCode> {
+        let register = self
+            .registers
+            .rtc_bkpxr
+            .get(index)
+            .ok_or(ErrorCode::INVAL)?;
+        // Writes need access to the backup domain, enabled with the PWR clock.
+        if !self.pwr_clock.is_enabled() {
+            return Err(ErrorCode::OFF);
+        }
+        register.set(value);
+        Ok(())
+    }
+}
@@ -24,6 +24,13 @@ pub const BRP_MAX_STM32: u32 = 1023;
 pub const TX_MAILBOX_COUNT: usize = 3;
 pub const RX_MAILBOX_COUNT: usize = 2;
 pub const FILTER_COUNT: usize = 56;
+/// Number of filter banks, each of which uses two filter registers.
+pub const FILTER_BANK_COUNT: usize = FILTER_COUNT / 2;
+
+/// Identifier extension bit of a 32-bit filter register.
+const FILTER32_IDE: u32 = 1 << 2;
+/// Identifier extension bit of a 16-bit filter register.
+const FILTER16_IDE: u32 = 1 << 3;
 
 register_structs! {
     pub Registers {
@@ -616,8 +623,10 @@ impl<'a> Can<'a> {
             }
         }
 
-        self.registers.can_firx[(filter_info.number as usize) * 2].modify(CAN_FiRx::FB.val(0));
-        self.registers.can_firx[(filter_info.number as usize) * 2 + 1].modify(CAN_FiRx::FB.val(0));
+        let (first, second) = Self::filter_bits(&filter_info);
+        self.registers.can_firx[(filter_info.number as usize) * 2].modify(CAN_FiRx::FB.val(first));
+        self.registers.can_firx[(filter_info.number as usize) * 2 + 1]
+            .modify(CAN_FiRx::FB.val(second));
 
         // request filter mode to be mask or list
         match filter_info.identifier_mode {
@@ -655,6 +664,59 @@ impl<'a> Can<'a> {
         }
     }
 
+    /// Values of the two registers of a filter bank, as described in RM0090
+    /// Reference Manual, Chapter 32.7.4. 16-bit filters only compare the
+    /// first 14 bits of extended identifiers. The identifier extension bit
+    /// is only compared if the mask is not 0, so that a mask of 0 accepts all
+    /// messages.
+    fn filter_bits(filter_info: &can::FilterParameters) -> (u32, u32) {
+        let compare_ide = filter_info.mask != 0;
+        match filter_info.scale_bits {
+            can::ScaleBits::Bits32 => {
+                let (identifier, mask) = match filter_info.identifier {
+                    can::Id::Standard(id) => {
+                        ((id as u32 & 0x7ff) << 21, (filter_info.mask & 0x7ff) << 21)
+                    }
+                    can::Id::Extended(id) => (
+                        ((id & 0x1fff_ffff) << 3) | FILTER32_IDE,
+                        (filter_info.mask & 0x1fff_ffff) << 3,
+                    ),
+                };
+                let mask = if compare_ide {
+                    mask | FILTER32_IDE
+                } else {
+                    mask
+                };
+                match filter_info.identifier_mode {
+                    can::IdentifierMode::Mask => (identifier, mask),
+                    can::IdentifierMode::List => (identifier, identifier),
+                }
+            }
+            can::ScaleBits::Bits16 => {
+                let (identifier, mask) = match filter_info.identifier {
+                    can::Id::Standard(id) => {
+                        ((id as u32 & 0x7ff) << 5, (filter_info.mask & 0x7ff) << 5)
+                    }
+                    can::Id::Extended(id) => (
+                        ((id >> 18) & 0x7ff) << 5 | FILTER16_IDE | ((id >> 15) & 0x7),
+                        ((filter_info.mask >> 18) & 0x7ff) << 5 | ((filter_info.mask >> 15) & 0x7),
+                    ),
+                };
+                let mask = if compare_ide {
+                    mask | FILTER16_IDE
+                } else {
+                    mask
+                };
+                // Both halves of both registers hold the same filter.
+                let bits = match filter_info.identifier_mode {
+                    can::IdentifierMode::Mask => mask << 16 | identifier,
+                    can::IdentifierMode::List => identifier << 16 | identifier,
+                };
+                (bits, bits)
+            }
+        }
+    }
+
     pub fn enable_filter_config(&self) {
         // activate the filter configuration
         self.registers.can_fmr.modify(CAN_FMR::FINIT::CLEAR);
@@ -1362,6 +1424,8 @@ impl can::Receive<{ can::STANDARD_CAN_PACKET_SIZE }> for Can<'_> {
                         scale_bits: can::ScaleBits::Bits32,
                         identifier_mode: can::IdentifierMode::Mask,
                         fifo_number: 0,
+                        identifier: can::Id::Standard(0),
+                        mask: 0,
                     },
                     true,
                 );
@@ -1371,6 +1435,8 @@ impl can::Receive<{ can::STANDARD_CAN_PACKET_SIZE }> for Can<'_> {
                         scale_bits: can::ScaleBits::Bits32,
                         identifier_mode: can::IdentifierMode::Mask,
                         fifo_number: 1,
+                        identifier: can::Id::Standard(0),
+                        mask: 0,
                     },
                     true,
                 );
@@ -1394,6 +1460,8 @@ impl can::Receive<{ can::STANDARD_CAN_PACKET_SIZE }> for Can<'_> {
                         scale_bits: can::ScaleBits::Bits32,
                         identifier_mode: can::IdentifierMode::Mask,
                         fifo_number: 0,
+                        identifier: can::Id::Standard(0),
+                        mask: 0,
                     },
                     false,
                 );
@@ -1403,6 +1471,8 @@ impl can::Receive<{ can::STANDARD_CAN_PACKET_SIZE }> for Can<'_> {
                         scale_bits: can::ScaleBits::Bits32,
                         identifier_mode: can::IdentifierMode::Mask,
                         fifo_number: 1,
+                        identifier: can::Id::Standard(0),
+                        mask: 0,
                     },
                     false,
                 );
@@ -1426,3 +1496,33 @@ impl can::Receive<{ can::STANDARD_CAN_PACKET_SIZE }> for Can<'_> {
         }
     }
 }
+
+impl can::Filter for Can<'_> {
+    /// Filter banks 0 and 1 accept all messages while receiving, and are
+    /// configured again when receiving starts or stops.
+    fn enable_filter(&self, filter: can::FilterParameters) -> Result<(), kernel::ErrorCode> {
+        if filter.number as usize >= FILTER_BANK_COUNT || filter.fifo_number >= RX_MAILBOX_COUNT {
+            return Err(kernel::ErrorCode::INVAL);
+        }
+        self.config_filter(filter, true);
+        self.enable_filter_config();
+        Ok(())
+    }
+
+    fn disable_filter(&self, number: u32) -> Result<(), kernel::ErrorCode> {
+        if number as usize >= FILTER_BANK_COUNT {
+            return Err(kernel::ErrorCode::INVAL);
+        }
+        let filter_number = 1 << number;
+        self.registers.can_fmr.modify(CAN_FMR::FINIT::SET);
+        self.registers.can_fa1r.modify(
+            CAN_FA1R::FACT.val(self.registers.can_fa1r.read(CAN_FA1R::FACT) & !filter_number),
+        );
+        self.enable_filter_config();
+        Ok(())
+    }
+
+    fn filter_count(&self) -> usize {
+        FILTER_BANK_COUNT
+    }
+}
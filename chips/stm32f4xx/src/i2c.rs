@@ -5,9 +5,10 @@
 use core::cell::Cell;
 
 use kernel::hil;
-use kernel::hil::i2c::{self, Error, I2CHwMasterClient, I2CMaster};
+use kernel::hil::i2c::{self, Error, I2CHwMasterClient};
 use kernel::platform::chip::ClockInterface;
 use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::peripheral_management::ClockUsage;
 use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
 use kernel::utilities::registers::{register_bitfields, ReadWrite};
 use kernel::utilities::StaticRef;
@@ -185,7 +186,9 @@ const I2C1_BASE: StaticRef<I2CRegisters> =
 
 pub struct I2C<'a> {
     registers: StaticRef<I2CRegisters>,
-    clock: I2CClock<'a>,
+    /// Held while the bus is enabled or a transfer is in progress, so the
+    /// peripheral clock is gated while the bus is idle.
+    clock: ClockUsage<I2CClock<'a>>,
 
     // I2C slave support not yet implemented
     master_client: OptionalCell<&'a dyn hil::i2c::I2CHwMasterClient>,
@@ -213,10 +216,10 @@ impl<'a> I2C<'a> {
     pub fn new(clocks: &'a dyn Stm32f4Clocks) -> Self {
         Self {
             registers: I2C1_BASE,
-            clock: I2CClock(phclk::PeripheralClock::new(
+            clock: ClockUsage::new(I2CClock(phclk::PeripheralClock::new(
                 phclk::PeripheralClockType::APB1(phclk::PCLK1::I2C1),
                 clocks,
-            )),
+            ))),
 
             master_client: OptionalCell::empty(),
 
@@ -234,7 +237,9 @@ impl<'a> I2C<'a> {
     }
 
     pub fn set_speed(&self, speed: I2CSpeed, system_clock_in_mhz: usize) {
-        self.disable();
+        // The configuration is kept while the clock is gated.
+        self.clock.acquire();
+        self.registers.cr1.modify(CR1::PE::CLEAR);
         self.registers
             .cr2
             .modify(CR2::FREQ.val(system_clock_in_mhz as u32));
@@ -256,7 +261,8 @@ impl<'a> I2C<'a> {
                     .modify(TRISE::TRISE.val(system_clock_in_mhz as u32 + 1));
             }
         }
-        self.enable();
+        self.registers.cr1.modify(CR1::PE::SET);
+        self.clock.release();
     }
 
     pub fn is_enabled_clock(&self) -> bool {
@@ -373,8 +379,15 @@ impl<'a> I2C<'a> {
     }
 
     fn reset(&self) {
-        self.disable();
-        self.enable();
+        self.registers.cr1.modify(CR1::PE::CLEAR);
+        self.registers.cr1.modify(CR1::PE::SET);
+    }
+
+    /// Start a transfer, holding the clock until it stops.
+    fn start_transfer(&self, status: I2CStatus) {
+        self.clock.acquire();
+        self.reset();
+        self.status.set(status);
     }
 
     fn start_write(&self) {
@@ -391,7 +404,13 @@ impl<'a> I2C<'a> {
             .cr2
             .modify(CR2::ITEVTEN::CLEAR + CR2::ITERREN::CLEAR + CR2::ITBUFEN::CLEAR);
         self.registers.cr1.modify(CR1::ACK::CLEAR);
-        self.status.set(I2CStatus::Idle);
+        if self.status.get() != I2CStatus::Idle {
+            // The stop condition is generated from the peripheral clock, so
+            // wait for it before the clock can be gated.
+            while self.registers.cr1.is_set(CR1::STOP) {}
+            self.status.set(I2CStatus::Idle);
+            self.clock.release();
+        }
     }
 
     fn start_read(&self) {
@@ -409,10 +428,12 @@ impl<'a> i2c::I2CMaster<'a> for I2C<'a> {
         self.master_client.replace(master_client);
     }
     fn enable(&self) {
+        self.clock.acquire();
         self.registers.cr1.modify(CR1::PE::SET);
     }
     fn disable(&self) {
         self.registers.cr1.modify(CR1::PE::CLEAR);
+        self.clock.release();
     }
     fn write_read(
         &self,
@@ -422,8 +443,7 @@ impl<'a> i2c::I2CMaster<'a> for I2C<'a> {
         read_len: usize,
     ) -> Result<(), (Error, &'static mut [u8])> {
         if self.status.get() == I2CStatus::Idle {
-            self.reset();
-            self.status.set(I2CStatus::WritingReading);
+            self.start_transfer(I2CStatus::WritingReading);
             self.slave_address.set(addr);
             self.buffer.replace(data);
             self.tx_len.set(write_len);
@@ -441,8 +461,7 @@ impl<'a> i2c::I2CMaster<'a> for I2C<'a> {
         len: usize,
     ) -> Result<(), (Error, &'static mut [u8])> {
         if self.status.get() == I2CStatus::Idle {
-            self.reset();
-            self.status.set(I2CStatus::Writing);
+            self.start_transfer(I2CStatus::Writing);
             self.slave_address.set(addr);
             self.buffer.replace(data);
             self.tx_len.set(len);
@@ -459,8 +478,7 @@ impl<'a> i2c::I2CMaster<'a> for I2C<'a> {
         len: usize,
     ) -> Result<(), (Error, &'static mut [u8])> {
         if self.status.get() == I2CStatus::Idle {
-            self.reset();
-            self.status.set(I2CStatus::Reading);
+            self.start_transfer(I2CStatus::Reading);
             self.slave_address.set(addr);
             self.buffer.replace(buffer);
             self.rx_len.set(len);
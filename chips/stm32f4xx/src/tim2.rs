@@ -2,14 +2,26 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 // Copyright Tock Contributors 2022.
 
+//! TIM2 general purpose timer
+//!
+//! Channel 1 of the timer implements [`Alarm`]. Channels 2 to 4 are
+//! available as capture or compare output channels 0 to 2 (see
+//! [`kernel::hil::capture_compare`]). Their pins must be configured by the
+//! board in alternate function mode 1 (for example PA1, PA2 and PA3).
+
+use core::cell::Cell;
+
 use cortexm4f::support::atomic;
+use kernel::hil::capture_compare::{
+    Capture, CaptureClient, CompareOutput, CompareOutputClient, Edge, OutputAction,
+};
 use kernel::hil::time::{
     Alarm, AlarmClient, Counter, Freq16KHz, Frequency, OverflowClient, Ticks, Ticks32, Time,
 };
 use kernel::platform::chip::ClockInterface;
 use kernel::utilities::cells::OptionalCell;
 use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
-use kernel::utilities::registers::{register_bitfields, ReadWrite, WriteOnly};
+use kernel::utilities::registers::{register_bitfields, FieldValue, ReadWrite, WriteOnly};
 use kernel::utilities::StaticRef;
 use kernel::ErrorCode;
 
@@ -311,11 +323,24 @@ register_bitfields![u32,
 const TIM2_BASE: StaticRef<Tim2Registers> =
     unsafe { StaticRef::new(0x40000000 as *const Tim2Registers) };
 
+/// Number of channels available for capture and compare output.
+const NUM_CHANNELS: usize = 3;
+
+#[derive(Clone, Copy, PartialEq)]
+enum ChannelMode {
+    Idle,
+    Capture,
+    Output,
+}
+
 pub struct Tim2<'a> {
     registers: StaticRef<Tim2Registers>,
     clock: Tim2Clock<'a>,
     client: OptionalCell<&'a dyn AlarmClient>,
     irqn: u32,
+    channels: [Cell<ChannelMode>; NUM_CHANNELS],
+    capture_client: OptionalCell<&'a dyn CaptureClient<Ticks32>>,
+    output_client: OptionalCell<&'a dyn CompareOutputClient>,
 }
 
 impl<'a> Tim2<'a> {
@@ -328,6 +353,9 @@ impl<'a> Tim2<'a> {
             )),
             client: OptionalCell::empty(),
             irqn: nvic::TIM2,
+            channels: [const { Cell::new(ChannelMode::Idle) }; NUM_CHANNELS],
+            capture_client: OptionalCell::empty(),
+            output_client: OptionalCell::empty(),
         }
     }
 
@@ -344,9 +372,112 @@ impl<'a> Tim2<'a> {
     }
 
     pub fn handle_interrupt(&self) {
-        self.registers.sr.modify(SR::CC1IF::CLEAR);
+        // Channel 1 matches its compare value whenever the counter wraps
+        // around, so only report it when the alarm is armed.
+        if self.registers.sr.is_set(SR::CC1IF) && self.registers.dier.is_set(DIER::CC1IE) {
+            self.registers.sr.modify(SR::CC1IF::CLEAR);
+            self.client.map(|client| client.alarm());
+        }
+
+        for channel in 0..NUM_CHANNELS {
+            let flag = 1 << (channel + 2);
+            if self.registers.sr.get() & flag == 0 || self.registers.dier.get() & flag == 0 {
+                continue;
+            }
+            match self.channels[channel].get() {
+                ChannelMode::Capture => {
+                    // Reading the captured value clears the flag.
+                    let timestamp = Ticks32::from(self.get_ccr(channel));
+                    self.capture_client
+                        .map(|client| client.captured(channel, timestamp));
+                }
+                ChannelMode::Output => {
+                    // The output was changed on the match. Freeze it so it is
+                    // not changed again when the counter wraps around.
+                    self.set_output_mode(channel, 0b000);
+                    self.registers.dier.set(self.registers.dier.get() & !flag);
+                    self.registers.sr.set(!flag);
+                    self.output_client.map(|client| client.output_done(channel));
+                }
+                ChannelMode::Idle => {
+                    self.registers.dier.set(self.registers.dier.get() & !flag);
+                }
+            }
+        }
+    }
+
+    /// Value of the capture/compare register of `channel`.
+    fn get_ccr(&self, channel: usize) -> u32 {
+        match channel {
+            0 => self.registers.ccr2.get(),
+            1 => self.registers.ccr3.get(),
+            _ => self.registers.ccr4.get(),
+        }
+    }
+
+    fn set_ccr(&self, channel: usize, value: u32) {
+        match channel {
+            0 => self.registers.ccr2.set(value),
+            1 => self.registers.ccr3.set(value),
+            _ => self.registers.ccr4.set(value),
+        }
+    }
+
+    /// Write the selection (CCxS) and output compare mode (OCxM) fields of
+    /// the mode register of `channel`.
+    fn set_channel_mode(&self, channel: usize, selection: u32, output_mode: u32) {
+        // Timer channel 2 is in the upper half of CCMR1, channel 3 in the
+        // lower half of CCMR2 and channel 4 in its upper half.
+        let shift = if channel == 1 { 0 } else { 8 };
+        let value = (output_mode << 4) | selection;
+        if channel == 0 {
+            self.registers
+                .ccmr1_output
+                .modify(FieldValue::<u32, CCMR1_Output::Register>::new(
+                    0x73, shift, value,
+                ));
+        } else {
+            self.registers
+                .ccmr2_output
+                .modify(FieldValue::<u32, CCMR2_Output::Register>::new(
+                    0x73, shift, value,
+                ));
+        }
+    }
+
+    fn set_output_mode(&self, channel: usize, output_mode: u32) {
+        self.set_channel_mode(channel, 0b00, output_mode);
+    }
 
-        self.client.map(|client| client.alarm());
+    /// Write the enable (CCxE), polarity (CCxP) and complementary polarity
+    /// (CCxNP) bits of `channel`.
+    fn set_channel_enable(&self, channel: usize, enable: bool, polarity: bool, npolarity: bool) {
+        let value = enable as u32 | (polarity as u32) << 1 | (npolarity as u32) << 3;
+        self.registers
+            .ccer
+            .modify(FieldValue::<u32, CCER::Register>::new(
+                0b1011,
+                4 * (channel + 1),
+                value,
+            ));
+    }
+
+    fn enable_channel_interrupt(&self, channel: usize) {
+        let flag = 1 << (channel + 2);
+        self.registers.sr.set(!flag);
+        self.registers.dier.set(self.registers.dier.get() | flag);
+    }
+
+    fn disable_channel_interrupt(&self, channel: usize) {
+        let flag = 1 << (channel + 2);
+        self.registers.dier.set(self.registers.dier.get() & !flag);
+    }
+
+    fn channel_mode(&self, channel: usize) -> Result<ChannelMode, ErrorCode> {
+        self.channels
+            .get(channel)
+            .map(Cell::get)
+            .ok_or(ErrorCode::INVAL)
     }
 
     // starts the timer
@@ -467,6 +598,95 @@ impl<'a> Alarm<'a> for Tim2<'a> {
     }
 }
 
+impl<'a> Capture<'a> for Tim2<'a> {
+    fn set_capture_client(&self, client: &'a dyn CaptureClient<Ticks32>) {
+        self.capture_client.set(client);
+    }
+
+    fn capture_channels(&self) -> usize {
+        NUM_CHANNELS
+    }
+
+    fn start_capture(&self, channel: usize, edge: Edge) -> Result<(), ErrorCode> {
+        if self.channel_mode(channel)? != ChannelMode::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        self.channels[channel].set(ChannelMode::Capture);
+        // CCxS = 01: capture the input of the channel's own pin.
+        self.set_channel_mode(channel, 0b01, 0b000);
+        let (polarity, npolarity) = match edge {
+            Edge::Rising => (false, false),
+            Edge::Falling => (true, false),
+            Edge::Both => (true, true),
+        };
+        self.set_channel_enable(channel, true, polarity, npolarity);
+        self.enable_channel_interrupt(channel);
+        Ok(())
+    }
+
+    fn stop_capture(&self, channel: usize) -> Result<(), ErrorCode> {
+        if self.channel_mode(channel)? == ChannelMode::Capture {
+            self.disable_channel_interrupt(channel);
+            self.set_channel_enable(channel, false, false, false);
+            self.set_output_mode(channel, 0b000);
+            self.channels[channel].set(ChannelMode::Idle);
+        }
+        Ok(())
+    }
+}
+
+impl<'a> CompareOutput<'a> for Tim2<'a> {
+    fn set_compare_output_client(&self, client: &'a dyn CompareOutputClient) {
+        self.output_client.set(client);
+    }
+
+    fn compare_channels(&self) -> usize {
+        NUM_CHANNELS
+    }
+
+    fn set_output(
+        &self,
+        channel: usize,
+        reference: Ticks32,
+        dt: Ticks32,
+        action: OutputAction,
+    ) -> Result<(), ErrorCode> {
+        if self.channel_mode(channel)? == ChannelMode::Capture {
+            return Err(ErrorCode::BUSY);
+        }
+        self.channels[channel].set(ChannelMode::Output);
+
+        let mut expire = reference.wrapping_add(dt);
+        let now = self.now();
+        if !now.within_range(reference, expire) || expire.wrapping_sub(now) < self.minimum_dt() {
+            expire = now.wrapping_add(self.minimum_dt());
+        }
+
+        // Freeze the output while the compare value changes.
+        self.set_output_mode(channel, 0b000);
+        self.set_ccr(channel, expire.into_u32());
+        let output_mode = match action {
+            OutputAction::Set => 0b001,
+            OutputAction::Clear => 0b010,
+            OutputAction::Toggle => 0b011,
+        };
+        self.set_output_mode(channel, output_mode);
+        self.set_channel_enable(channel, true, false, false);
+        self.enable_channel_interrupt(channel);
+        Ok(())
+    }
+
+    fn cancel_output(&self, channel: usize) -> Result<(), ErrorCode> {
+        if self.channel_mode(channel)? == ChannelMode::Output {
+            self.disable_channel_interrupt(channel);
+            self.set_output_mode(channel, 0b000);
+            self.set_channel_enable(channel, false, false, false);
+            self.channels[channel].set(ChannelMode::Idle);
+        }
+        Ok(())
+    }
+}
+
 struct Tim2Clock<'a>(phclk::PeripheralClock<'a>);
 
 impl ClockInterface for Tim2Clock<'_> {
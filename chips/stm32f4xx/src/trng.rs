@@ -4,11 +4,14 @@
 
 //! True random number generator
 
+use core::cell::Cell;
+
 use crate::clocks::{phclk, Stm32f4Clocks};
 use kernel::hil;
 use kernel::hil::entropy::Continue;
 use kernel::platform::chip::ClockInterface;
 use kernel::utilities::cells::OptionalCell;
+use kernel::utilities::peripheral_management::ClockUsage;
 use kernel::utilities::registers::interfaces::{ReadWriteable, Readable};
 use kernel::utilities::registers::{register_bitfields, ReadOnly, ReadWrite};
 use kernel::utilities::StaticRef;
@@ -53,7 +56,9 @@ register_bitfields![u32,
 
 pub struct Trng<'a> {
     registers: StaticRef<RngRegisters>,
-    clock: RngClock<'a>,
+    /// Held while random numbers are being generated.
+    clock: ClockUsage<RngClock<'a>>,
+    running: Cell<bool>,
     client: OptionalCell<&'a dyn hil::entropy::Client32>,
 }
 
@@ -64,10 +69,11 @@ impl<'a> Trng<'a> {
     ) -> Trng<'a> {
         Trng {
             registers,
-            clock: RngClock(phclk::PeripheralClock::new(
+            clock: ClockUsage::new(RngClock(phclk::PeripheralClock::new(
                 phclk::PeripheralClockType::AHB2(phclk::HCLK2::RNG),
                 clocks,
-            )),
+            ))),
+            running: Cell::new(false),
             client: OptionalCell::empty(),
         }
     }
@@ -96,7 +102,7 @@ impl<'a> Trng<'a> {
             self.registers.cr.modify(Control::RNGEN::SET);
             return;
         } else if self.registers.sr.is_set(Status::CEIS) {
-            self.clock.0.configure_rng_clock();
+            self.clock.clock().0.configure_rng_clock();
             self.registers.sr.modify(Status::CEIS::CLEAR);
             return;
         }
@@ -104,11 +110,18 @@ impl<'a> Trng<'a> {
         self.client.map(|client| {
             let res = client.entropy_available(&mut TrngIter(self), Ok(()));
             if let Continue::Done = res {
-                self.registers.cr.modify(Control::IE::CLEAR);
-                self.registers.cr.modify(Control::RNGEN::CLEAR);
+                self.stop();
             }
         });
     }
+
+    fn stop(&self) {
+        self.registers.cr.modify(Control::RNGEN::CLEAR);
+        self.registers.cr.modify(Control::IE::CLEAR);
+        if self.running.take() {
+            self.clock.release();
+        }
+    }
 }
 
 struct RngClock<'a>(phclk::PeripheralClock<'a>);
@@ -144,6 +157,9 @@ impl Iterator for TrngIter<'_, '_> {
 
 impl<'a> hil::entropy::Entropy32<'a> for Trng<'a> {
     fn get(&self) -> Result<(), ErrorCode> {
+        if !self.running.replace(true) {
+            self.clock.acquire();
+        }
         // Enable interrupts.
         self.registers.cr.modify(Control::IE::SET);
         self.registers.cr.modify(Control::RNGEN::SET);
@@ -152,8 +168,7 @@ impl<'a> hil::entropy::Entropy32<'a> for Trng<'a> {
     }
 
     fn cancel(&self) -> Result<(), ErrorCode> {
-        self.registers.cr.modify(Control::RNGEN::CLEAR);
-        self.registers.cr.modify(Control::IE::CLEAR);
+        self.stop();
 
         Ok(())
     }
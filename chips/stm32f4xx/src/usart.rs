@@ -2,6 +2,14 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 // Copyright Tock Contributors 2022.
 
+//! Universal synchronous asynchronous receiver transmitter (USART)
+//!
+//! Receptions started with [`hil::uart::ReceiveAdvanced::receive_automatic`]
+//! end on the IDLE interrupt, once the line is idle for one frame after a
+//! received byte. The USART cannot wait longer than one frame, so the
+//! `interbyte_timeout` is ignored: at 8N1 reception always ends after 10 idle
+//! bit periods.
+
 use core::cell::Cell;
 use kernel::deferred_call::{DeferredCall, DeferredCallClient};
 use kernel::hil;
@@ -332,6 +340,34 @@ impl<'a, DMA: dma::StreamServer<'a>> Usart<'a, DMA> {
             }
         }
 
+        if self.is_enabled_idle_interrupt() && self.registers.sr.is_set(SR::IDLE) {
+            let _ = self.registers.dr.get(); // clear idle line detected
+            if self.usart_rx_state.get() == USARTStateRX::DMA_Receiving {
+                self.usart_rx_state.set(USARTStateRX::Idle);
+
+                self.disable_rx();
+                self.disable_error_interrupt();
+                self.disable_idle_interrupt();
+
+                // get buffer
+                let (buffer, len) = self.rx_dma.map_or((None, 0), |rx_dma| {
+                    // `abort_transfer` also disables the stream
+                    rx_dma.abort_transfer()
+                });
+
+                let count = self.rx_len.get() - len as usize;
+                self.rx_len.set(0);
+
+                // alert client
+                self.rx_client.map(|client| {
+                    buffer.map(|buf| {
+                        let buf = buf.take();
+                        client.received_buffer(buf, count, Ok(()), hil::uart::Error::None);
+                    })
+                });
+            }
+        }
+
         if self.is_enabled_error_interrupt() && self.registers.sr.is_set(SR::ORE) {
             let _ = self.registers.dr.get(); // clear overrun error
             if self.usart_rx_state.get() == USARTStateRX::DMA_Receiving {
@@ -339,6 +375,7 @@ impl<'a, DMA: dma::StreamServer<'a>> Usart<'a, DMA> {
 
                 self.disable_rx();
                 self.disable_error_interrupt();
+                self.disable_idle_interrupt();
 
                 // get buffer
                 let (buffer, len) = self.rx_dma.map_or((None, 0), |rx_dma| {
@@ -410,6 +447,19 @@ impl<'a, DMA: dma::StreamServer<'a>> Usart<'a, DMA> {
         self.registers.cr3.is_set(CR3::EIE)
     }
 
+    // enable the interrupt for an idle line after a received word
+    fn enable_idle_interrupt(&self) {
+        self.registers.cr1.modify(CR1::IDLEIE::SET);
+    }
+
+    fn disable_idle_interrupt(&self) {
+        self.registers.cr1.modify(CR1::IDLEIE::CLEAR);
+    }
+
+    fn is_enabled_idle_interrupt(&self) -> bool {
+        self.registers.cr1.is_set(CR1::IDLEIE)
+    }
+
     fn abort_tx(&self, rcode: Result<(), ErrorCode>) {
         if matches!(self.usart_tx_state.get(), USARTStateTX::Aborted(_)) {
             return;
@@ -448,6 +498,7 @@ impl<'a, DMA: dma::StreamServer<'a>> Usart<'a, DMA> {
 
         self.disable_rx();
         self.disable_error_interrupt();
+        self.disable_idle_interrupt();
 
         // get buffer
         let (mut buffer, len) = self.rx_dma.map_or((None, 0), |rx_dma| {
@@ -495,6 +546,7 @@ impl<'a, DMA: dma::StreamServer<'a>> Usart<'a, DMA> {
             if self.usart_rx_state.get() == USARTStateRX::DMA_Receiving {
                 self.disable_rx();
                 self.disable_error_interrupt();
+                self.disable_idle_interrupt();
                 self.usart_rx_state.set(USARTStateRX::Idle);
 
                 // get buffer
@@ -744,6 +796,29 @@ impl<'a, DMA: dma::StreamServer<'a>> hil::uart::Receive<'a> for Usart<'a, DMA> {
     }
 }
 
+impl<'a, DMA: dma::StreamServer<'a>> hil::uart::ReceiveAdvanced<'a> for Usart<'a, DMA> {
+    fn receive_automatic(
+        &self,
+        rx_buffer: &'static mut [u8],
+        rx_len: usize,
+        _interbyte_timeout: u8,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.usart_rx_state.get() != USARTStateRX::Idle {
+            return Err((ErrorCode::BUSY, rx_buffer));
+        }
+
+        // Clear an idle line detected before this reception, by reading the
+        // status and then the data register.
+        if self.registers.sr.is_set(SR::IDLE) {
+            let _ = self.registers.dr.get();
+        }
+
+        hil::uart::Receive::receive_buffer(self, rx_buffer, rx_len)?;
+        self.enable_idle_interrupt();
+        Ok(())
+    }
+}
+
 impl<'a> dma::StreamClient<'a, dma::Dma1<'a>> for Usart<'a, dma::Dma1<'a>> {
     fn transfer_done(&self, pid: dma::Dma1Peripheral) {
         self.transfer_done(pid);
@@ -4,6 +4,7 @@
 
 use core::cell::Cell;
 
+use kernel::hil::ethernet::{EthernetAdapterDatapath, EthernetAdapterDatapathClient};
 use kernel::utilities::cells::OptionalCell;
 use kernel::utilities::registers::{register_bitfields, LocalRegisterCopy};
 use kernel::ErrorCode;
@@ -46,9 +47,12 @@ pub struct VirtIONet<'a> {
     rxqueue: &'a SplitVirtqueue<'static, 'static, 2>,
     txqueue: &'a SplitVirtqueue<'static, 'static, 2>,
     tx_header: OptionalCell<&'static mut [u8; 12]>,
+    /// Length and identifier of the frame being transmitted
+    tx_frame_info: Cell<(u16, usize)>,
     rx_header: OptionalCell<&'static mut [u8]>,
     rx_buffer: OptionalCell<&'static mut [u8]>,
-    client: OptionalCell<&'a dyn VirtIONetClient>,
+    rx_enabled: Cell<bool>,
+    client: OptionalCell<&'a dyn EthernetAdapterDatapathClient>,
 }
 
 impl<'a> VirtIONet<'a> {
@@ -68,9 +72,11 @@ impl<'a> VirtIONet<'a> {
             rxqueue,
             txqueue,
             tx_header: OptionalCell::new(tx_header),
+            tx_frame_info: Cell::new((0, 0)),
             client: OptionalCell::empty(),
             rx_header: OptionalCell::new(rx_header),
             rx_buffer: OptionalCell::new(rx_buffer),
+            rx_enabled: Cell::new(false),
         }
     }
 
@@ -78,17 +84,15 @@ impl<'a> VirtIONet<'a> {
         self.id.get()
     }
 
-    pub fn set_client(&self, client: &'a dyn VirtIONetClient) {
-        self.client.set(client);
-    }
-
-    // This is not executed as part of the `device_initialized` hook to avoid
-    // missing any packets if a client has not been registered, and because this
-    // device can be used in a transmit-only fashion before invoking this
-    // function.
-    pub fn enable_rx(&self) {
-        // To start operation, put the receive buffers into the device initially
-        let rx_buffer = self.rx_buffer.take().unwrap();
+    // The receive buffer is not provided as part of the `device_initialized`
+    // hook to avoid missing any packets if a client has not been registered,
+    // and because this device can be used in a transmit-only fashion.
+    fn provide_rx_buffer(&self) {
+        // Nothing to do if the buffer is already with the device
+        let rx_buffer = match self.rx_buffer.take() {
+            Some(rx_buffer) => rx_buffer,
+            None => return,
+        };
         let rx_buffer_len = rx_buffer.len();
 
         let mut buffer_chain = [
@@ -108,37 +112,43 @@ impl<'a> VirtIONet<'a> {
             .provide_buffer_chain(&mut buffer_chain)
             .unwrap();
     }
+}
 
-    pub fn return_rx_buffer(&self, buf: &'static mut [u8]) {
-        assert!(self.rx_buffer.is_none());
-        assert!(self.rx_header.is_some());
-        self.rx_buffer.replace(buf);
+impl<'a> EthernetAdapterDatapath<'a> for VirtIONet<'a> {
+    fn set_client(&self, client: &'a dyn EthernetAdapterDatapathClient) {
+        self.client.set(client);
+    }
 
-        // Re-register the RX buffer with the Virtqueue:
-        self.enable_rx();
+    fn enable_receive(&self) {
+        self.rx_enabled.set(true);
+        self.provide_rx_buffer();
     }
 
-    pub fn send_packet(
+    fn disable_receive(&self) {
+        // The buffer is not handed back to the device once it returns, so
+        // that the device drops incoming frames.
+        self.rx_enabled.set(false);
+    }
+
+    fn transmit_frame(
         &self,
-        packet: &'static mut [u8],
-        packet_len: usize,
-    ) -> Result<(), (&'static mut [u8], ErrorCode)> {
+        frame_buffer: &'static mut [u8],
+        len: u16,
+        transmission_identifier: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if len as usize > frame_buffer.len() {
+            return Err((ErrorCode::SIZE, frame_buffer));
+        }
+
         // Try to get a hold of the header buffer
         //
         // Otherwise, the device is currently busy transmissing a buffer
         //
         // TODO: Implement simultaneous transmissions
-        let mut packet_buf = Some(VirtqueueBuffer {
-            buf: packet,
-            len: packet_len,
-            device_writeable: false,
-        });
-
-        let header_buf = self
-            .tx_header
-            .take()
-            .ok_or(ErrorCode::BUSY)
-            .map_err(|ret| (packet_buf.take().unwrap().buf, ret))?;
+        let header_buf = match self.tx_header.take() {
+            Some(header_buf) => header_buf,
+            None => return Err((ErrorCode::BUSY, frame_buffer)),
+        };
 
         // Write the header
         //
@@ -162,14 +172,22 @@ impl<'a> VirtIONet<'a> {
                 len: 12,
                 device_writeable: false,
             }),
-            packet_buf.take(),
+            Some(VirtqueueBuffer {
+                buf: frame_buffer,
+                len: len as usize,
+                device_writeable: false,
+            }),
         ];
 
+        self.tx_frame_info.set((len, transmission_identifier));
         self.txqueue
             .provide_buffer_chain(&mut buffer_chain)
-            .map_err(move |ret| (buffer_chain[1].take().unwrap().buf, ret))?;
-
-        Ok(())
+            .map_err(move |ret| {
+                if let Some(header) = buffer_chain[0].take() {
+                    self.tx_header.replace(header.buf.try_into().unwrap());
+                }
+                (ret, buffer_chain[1].take().unwrap().buf)
+            })
     }
 }
 
@@ -188,9 +206,17 @@ impl SplitVirtqueueClient<'static> for VirtIONet<'_> {
             self.rx_header.replace(rx_header);
 
             let rx_buffer = buffer_chain[1].take().expect("No rx content buffer").buf;
-            self.client.map(move |client| {
-                client.packet_received(self.id.get(), rx_buffer, bytes_used - 12)
-            });
+            if self.rx_enabled.get() {
+                self.client
+                    .map(|client| client.received_frame(&rx_buffer[..bytes_used - 12], None));
+            }
+            self.rx_buffer.replace(rx_buffer);
+
+            // Re-register the RX buffer with the Virtqueue, unless receiving
+            // was disabled in the meantime
+            if self.rx_enabled.get() {
+                self.provide_rx_buffer();
+            }
         } else if queue_number == self.txqueue.queue_number().unwrap() {
             // Sent a packet
 
@@ -198,8 +224,10 @@ impl SplitVirtqueueClient<'static> for VirtIONet<'_> {
             self.tx_header.replace(header_buf.try_into().unwrap());
 
             let packet_buf = buffer_chain[1].take().expect("No packet buffer").buf;
-            self.client
-                .map(move |client| client.packet_sent(self.id.get(), packet_buf));
+            let (len, transmission_identifier) = self.tx_frame_info.get();
+            self.client.map(move |client| {
+                client.transmit_frame_done(Ok(()), packet_buf, len, transmission_identifier, None)
+            });
         } else {
             panic!("Callback from unknown queue");
         }
@@ -240,8 +268,3 @@ impl VirtIODeviceDriver for VirtIONet<'_> {
         VirtIODeviceType::NetworkCard
     }
 }
-
-pub trait VirtIONetClient {
-    fn packet_sent(&self, id: usize, buffer: &'static mut [u8]);
-    fn packet_received(&self, id: usize, buffer: &'static mut [u8], len: usize);
-}
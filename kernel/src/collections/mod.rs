@@ -5,5 +5,6 @@
 //! Data structures.
 
 pub mod list;
+pub mod ordered_map;
 pub mod queue;
 pub mod ring_buffer;
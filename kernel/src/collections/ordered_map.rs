@@ -0,0 +1,201 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Implementation of an ordered map on a fixed-size slice.
+//!
+//! `OrderedMap` keeps its entries sorted by key in the slice it is created
+//! with, so looking up a key is a binary search rather than a scan of every
+//! entry. Inserting and removing an entry moves the entries after it, which
+//! is cheap for the table sizes used in the kernel and needs no heap.
+//!
+//! ```rust
+//! use kernel::collections::ordered_map::OrderedMap;
+//!
+//! let mut storage = [None; 8];
+//! let mut ports = OrderedMap::new(&mut storage);
+//! ports.insert(53u16, 1usize).unwrap();
+//! ports.insert(7, 0).unwrap();
+//! assert_eq!(ports.get(&53), Some(&1));
+//! assert_eq!(ports.first(), Some((7, 0)));
+//! ```
+
+use core::cmp::Ordering;
+
+pub struct OrderedMap<'a, K: Ord + Copy, V: Copy> {
+    /// The first `len` entries are occupied and sorted by key.
+    entries: &'a mut [Option<(K, V)>],
+    len: usize,
+}
+
+impl<'a, K: Ord + Copy, V: Copy> OrderedMap<'a, K, V> {
+    /// Create an empty map that holds up to `entries.len()` entries. The
+    /// previous content of `entries` is ignored.
+    pub fn new(entries: &'a mut [Option<(K, V)>]) -> OrderedMap<'a, K, V> {
+        entries.fill(None);
+        OrderedMap { entries, len: 0 }
+    }
+
+    /// Returns the number of entries in the map.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the maximum number of entries in the map.
+    pub fn capacity(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len == self.entries.len()
+    }
+
+    /// Returns the index of `key`, or the index where it would be inserted.
+    fn search(&self, key: &K) -> Result<usize, usize> {
+        self.entries[..self.len].binary_search_by(|entry| {
+            entry
+                .as_ref()
+                .map_or(Ordering::Greater, |(k, _)| k.cmp(key))
+        })
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.search(key).is_ok()
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let index = self.search(key).ok()?;
+        self.entries[index].as_ref().map(|(_, v)| v)
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let index = self.search(key).ok()?;
+        self.entries[index].as_mut().map(|(_, v)| v)
+    }
+
+    /// Insert `value` for `key`, returning the value it replaces, if any.
+    ///
+    /// Returns the key and value back if the key is not in the map and the
+    /// map is full.
+    pub fn insert(&mut self, key: K, value: V) -> Result<Option<V>, (K, V)> {
+        match self.search(&key) {
+            Ok(index) => Ok(self.entries[index]
+                .replace((key, value))
+                .map(|(_, old)| old)),
+            Err(_) if self.is_full() => Err((key, value)),
+            Err(index) => {
+                self.entries[index..=self.len].rotate_right(1);
+                self.entries[index] = Some((key, value));
+                self.len += 1;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Remove `key` from the map, returning its value if it was present.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let index = self.search(key).ok()?;
+        let removed = self.entries[index].take();
+        self.entries[index..self.len].rotate_left(1);
+        self.len -= 1;
+        removed.map(|(_, v)| v)
+    }
+
+    /// Remove all entries.
+    pub fn clear(&mut self) {
+        self.entries[..self.len].fill(None);
+        self.len = 0;
+    }
+
+    /// Returns the entry with the smallest key.
+    pub fn first(&self) -> Option<(K, V)> {
+        self.iter().next()
+    }
+
+    /// Returns the entry with the largest key.
+    pub fn last(&self) -> Option<(K, V)> {
+        self.iter().last()
+    }
+
+    /// Returns the entry with the smallest key that is at least `key`.
+    pub fn first_from(&self, key: &K) -> Option<(K, V)> {
+        let index = match self.search(key) {
+            Ok(index) | Err(index) => index,
+        };
+        self.entries[..self.len].get(index).copied().flatten()
+    }
+
+    /// Iterate over the entries in key order.
+    pub fn iter(&self) -> impl Iterator<Item = (K, V)> + '_ {
+        self.entries[..self.len].iter().filter_map(|entry| *entry)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::OrderedMap;
+
+    #[test]
+    fn test_insert_get_remove() {
+        let mut storage = [None; 4];
+        let mut map = OrderedMap::new(&mut storage);
+        assert!(map.is_empty());
+
+        assert_eq!(map.insert(30, 'c'), Ok(None));
+        assert_eq!(map.insert(10, 'a'), Ok(None));
+        assert_eq!(map.insert(20, 'b'), Ok(None));
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.get(&20), Some(&'b'));
+        assert_eq!(map.get(&25), None);
+
+        // Replacing keeps the length.
+        assert_eq!(map.insert(20, 'B'), Ok(Some('b')));
+        assert_eq!(map.len(), 3);
+
+        assert_eq!(map.remove(&10), Some('a'));
+        assert_eq!(map.remove(&10), None);
+        assert!(!map.contains_key(&10));
+        assert_eq!(map.len(), 2);
+    }
+
+    #[test]
+    fn test_key_order() {
+        let mut storage = [None; 8];
+        let mut map = OrderedMap::new(&mut storage);
+        for key in [5u16, 1, 7, 3, 2] {
+            map.insert(key, key * 10).unwrap();
+        }
+
+        let mut keys = map.iter().map(|(k, _)| k);
+        for expected in [1, 2, 3, 5, 7] {
+            assert_eq!(keys.next(), Some(expected));
+        }
+        assert_eq!(keys.next(), None);
+
+        assert_eq!(map.first(), Some((1, 10)));
+        assert_eq!(map.last(), Some((7, 70)));
+        assert_eq!(map.first_from(&4), Some((5, 50)));
+        assert_eq!(map.first_from(&5), Some((5, 50)));
+        assert_eq!(map.first_from(&8), None);
+    }
+
+    #[test]
+    fn test_full() {
+        let mut storage = [None; 2];
+        let mut map = OrderedMap::new(&mut storage);
+        map.insert(1, ()).unwrap();
+        map.insert(2, ()).unwrap();
+        assert!(map.is_full());
+        assert_eq!(map.insert(3, ()), Err((3, ())));
+        // Existing keys can still be replaced.
+        assert_eq!(map.insert(2, ()), Ok(Some(())));
+
+        map.clear();
+        assert!(map.is_empty());
+        assert_eq!(map.insert(3, ()), Ok(None));
+    }
+}
@@ -80,6 +80,21 @@ pub(crate) struct Config {
     // credentials checking, e.g., whether elf2tab and tockloader are generating
     // properly formatted footers.
     pub(crate) debug_process_credentials: bool,
+
+    /// Whether the kernel should measure how long grant closures, deferred
+    /// calls and interrupt bottom halves run for.
+    ///
+    /// If enabled, the times are reported to the timer set with
+    /// `utilities::section_monitor::set_section_timer()`, to find the
+    /// capsules that delay the rest of the kernel.
+    pub(crate) trace_long_sections: bool,
+
+    /// Whether the fault sites of `utilities::fault_injection` inject the
+    /// faults they are configured with.
+    ///
+    /// If disabled, the fault injection wrappers of HIL implementations only
+    /// forward to the hardware, so they can stay in a board for free.
+    pub(crate) fault_injection: bool,
 }
 
 /// A unique instance of `Config` where compile-time configuration options are
@@ -92,4 +107,6 @@ pub(crate) const CONFIG: Config = Config {
     debug_load_processes: cfg!(feature = "debug_load_processes"),
     debug_panics: !cfg!(feature = "no_debug_panics"),
     debug_process_credentials: cfg!(feature = "debug_process_credentials"),
+    trace_long_sections: cfg!(feature = "trace_long_sections"),
+    fault_injection: cfg!(feature = "fault_injection"),
 };
@@ -22,6 +22,9 @@
 //!     .finalize(components::debug_writer_component_static!());
 //! ```
 //!
+//! Boards can also let the `debug-gpio` process console command reassign the
+//! debug GPIOs at runtime, among the pins of a [`DebugGpioRegistry`].
+//!
 //! The debug queue is optional, if not set in the board it is just ignored. You
 //! can add one in the board file as follows:
 //!
@@ -34,13 +37,14 @@
 //! -------
 //!
 //! ```no_run
-//! # use kernel::{debug, debug_enqueue, debug_flush_queue, debug_gpio, debug_verbose};
+//! # use kernel::{debug, debug_enqueue, debug_flush_queue, debug_gpio, debug_pulse, debug_verbose};
 //! # fn main() {
 //! # let i = 42;
 //! debug!("Yes the code gets here with value {}", i);
 //! debug_verbose!("got here"); // Includes message count, file, and line.
 //!
 //! debug_gpio!(0, toggle); // Toggles the first debug GPIO.
+//! debug_pulse!(1, 3); // Three pulses on the second debug GPIO.
 //!
 //! debug_enqueue!("foo"); // Adds some message to the debug queue.
 //! debug_flush_queue!(); // Flushes the queue, writing "foo".
@@ -65,6 +69,7 @@ use crate::collections::queue::Queue;
 use crate::collections::ring_buffer::RingBuffer;
 use crate::hil;
 use crate::platform::chip::Chip;
+use crate::platform::persistent_counters::{panic_reason_hash, PanicRecorder};
 use crate::process::Process;
 use crate::process::ProcessPrinter;
 use crate::processbuffer::ReadableProcessSlice;
@@ -124,6 +129,7 @@ pub unsafe fn panic_print<W: Write + IoWrite, C: Chip, PP: ProcessPrinter>(
     process_printer: &'static Option<&'static PP>,
 ) {
     panic_begin(nop);
+    panic_record(panic_info);
     // Flush debug buffer if needed
     flush(writer);
     panic_banner(writer, panic_info);
@@ -178,6 +184,23 @@ pub unsafe fn panic_begin(nop: &dyn Fn()) {
     }
 }
 
+static mut PANIC_RECORDER: Option<&'static dyn PanicRecorder> = None;
+
+/// Set the recorder that keeps the reason of a panic until the next boot.
+pub unsafe fn set_panic_recorder(recorder: &'static dyn PanicRecorder) {
+    *addr_of_mut!(PANIC_RECORDER) = Some(recorder);
+}
+
+/// Give the hash of the panic message to the panic recorder, if there is one.
+///
+/// This is called by `panic_print()`. Boards with their own panic routine
+/// call it after `panic_begin()`.
+pub unsafe fn panic_record(panic_info: &PanicInfo) {
+    if let Some(recorder) = *addr_of_mut!(PANIC_RECORDER) {
+        recorder.record_panic(panic_reason_hash(panic_info));
+    }
+}
+
 /// Lightweight prints about the current panic and kernel version.
 ///
 /// **NOTE:** The supplied `writer` must be synchronous.
@@ -295,6 +318,120 @@ macro_rules! debug_gpio {
     }};
 }
 
+/// Emit `n` pulses on a debug GPIO, so that a logic analyzer can tell which
+/// point of the code it is watching from the number of pulses.
+///
+/// ```ignore
+/// debug_pulse!(0, 3); // Three pulses on the first debug GPIO.
+/// ```
+#[macro_export]
+macro_rules! debug_pulse {
+    ($i:tt, $n:expr $(,)?) => {{
+        #[allow(unused_unsafe)]
+        unsafe {
+            $crate::debug::DEBUG_GPIOS.$i.map(|g| {
+                for _ in 0..$n {
+                    g.set();
+                    g.clear();
+                }
+            });
+        }
+    }};
+}
+
+/// Number of debug GPIOs.
+pub const NUM_DEBUG_GPIOS: usize = 3;
+
+/// Reassign the debug GPIOs at runtime, for example from the process
+/// console, so that moving a logic analyzer probe does not require
+/// rebuilding the board.
+pub trait DebugGpioControl {
+    /// The name of the `index`th pin that can be assigned, or `None` past the
+    /// last one.
+    fn pin_name(&self, index: usize) -> Option<&'static str>;
+
+    /// The name of the pin assigned to debug GPIO `slot`. Pins the board
+    /// assigned with [`assign_gpios`] that cannot be reassigned are named
+    /// `"board"`.
+    fn assigned(&self, slot: usize) -> Option<&'static str>;
+
+    /// Assign the pin called `name` to debug GPIO `slot`, or no pin if
+    /// `name` is `None`. The pin is configured as an output and cleared.
+    ///
+    /// Returns `Err(ErrorCode::INVAL)` if `slot` or `name` is unknown.
+    fn assign(&self, slot: usize, name: Option<&str>) -> core::result::Result<(), ErrorCode>;
+}
+
+/// The pins a board lets the debug GPIOs be assigned to at runtime.
+pub struct DebugGpioRegistry {
+    pins: &'static [(&'static str, &'static dyn hil::gpio::Pin)],
+}
+
+impl DebugGpioRegistry {
+    /// # Safety
+    ///
+    /// The registry writes the debug GPIOs, and must only be used from the
+    /// main kernel thread.
+    pub unsafe fn new(pins: &'static [(&'static str, &'static dyn hil::gpio::Pin)]) -> Self {
+        Self { pins }
+    }
+
+    /// The pin assigned to debug GPIO `slot`, if any.
+    fn slot(slot: usize) -> Option<&'static dyn hil::gpio::Pin> {
+        // Safety: the debug GPIOs are only used from the main kernel thread.
+        unsafe {
+            match slot {
+                0 => DEBUG_GPIOS.0,
+                1 => DEBUG_GPIOS.1,
+                2 => DEBUG_GPIOS.2,
+                _ => None,
+            }
+        }
+    }
+}
+
+impl DebugGpioControl for DebugGpioRegistry {
+    fn pin_name(&self, index: usize) -> Option<&'static str> {
+        self.pins.get(index).map(|(name, _)| *name)
+    }
+
+    fn assigned(&self, slot: usize) -> Option<&'static str> {
+        Self::slot(slot).map(|assigned| {
+            self.pins
+                .iter()
+                .find(|(_, pin)| core::ptr::addr_eq(*pin, assigned))
+                .map_or("board", |(name, _)| *name)
+        })
+    }
+
+    fn assign(&self, slot: usize, name: Option<&str>) -> core::result::Result<(), ErrorCode> {
+        let pin = match name {
+            Some(name) => Some(
+                self.pins
+                    .iter()
+                    .find(|(pin_name, _)| *pin_name == name)
+                    .map(|(_, pin)| *pin)
+                    .ok_or(ErrorCode::INVAL)?,
+            ),
+            None => None,
+        };
+        pin.map(|pin| {
+            pin.make_output();
+            pin.clear();
+        });
+        // Safety: the debug GPIOs are only used from the main kernel thread.
+        unsafe {
+            match slot {
+                0 => DEBUG_GPIOS.0 = pin,
+                1 => DEBUG_GPIOS.1 = pin,
+                2 => DEBUG_GPIOS.2 = pin,
+                _ => return Err(ErrorCode::INVAL),
+            }
+        }
+        Ok(())
+    }
+}
+
 ///////////////////////////////////////////////////////////////////
 // debug_enqueue! support
 
@@ -54,6 +54,7 @@
 //! ```
 
 use crate::utilities::cells::OptionalCell;
+use crate::utilities::section_monitor;
 use core::cell::Cell;
 use core::marker::Copy;
 use core::marker::PhantomData;
@@ -95,7 +96,11 @@ impl<'a> DynDefCallRef<'a> {
     fn new<T: DeferredCallClient>(x: &'a T) -> Self {
         Self {
             data: core::ptr::from_ref(x) as *const (),
-            callback: |p| unsafe { T::handle_deferred_call(&*p.cast()) },
+            callback: |p| {
+                section_monitor::measure(core::any::type_name::<T>(), || unsafe {
+                    T::handle_deferred_call(&*p.cast())
+                })
+            },
             _lifetime: PhantomData,
         }
     }
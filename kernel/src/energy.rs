@@ -0,0 +1,81 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Accounting of the energy processes use.
+//!
+//! The kernel reports when each process starts and stops executing to the
+//! [`EnergyMonitor`] registered with
+//! [`Kernel::set_energy_monitor`](crate::Kernel::set_energy_monitor). Time the
+//! kernel spends handling the system calls of a process is charged to that
+//! process, in the same way as for timeslices.
+//!
+//! Capsules that drive power hungry peripherals on behalf of a process report
+//! when the peripheral is active through [`PeripheralEnergy`]. Peripherals are
+//! identified by the driver number of the capsule.
+//!
+//! Turning these events into energy estimates, which requires a time source
+//! and a power model of the board, is left to a capsule (see
+//! `capsules_extra::energy`). Tools such as the process console query the
+//! results through [`EnergyStatistics`].
+
+use crate::process::ProcessId;
+
+/// Receives the execution events of processes from the kernel.
+pub trait EnergyMonitor {
+    /// The kernel is about to run `processid`.
+    fn process_started(&self, processid: ProcessId);
+
+    /// `processid` stopped running, because it yielded, was preempted, or
+    /// stopped.
+    fn process_stopped(&self, processid: ProcessId);
+}
+
+/// Receives the peripheral usage of processes from capsules.
+pub trait PeripheralEnergy {
+    /// The peripheral of the driver `driver_num` became active on behalf of
+    /// `processid`.
+    fn peripheral_started(&self, processid: ProcessId, driver_num: usize);
+
+    /// The peripheral of the driver `driver_num` stopped being active on
+    /// behalf of `processid`.
+    fn peripheral_stopped(&self, processid: ProcessId, driver_num: usize);
+}
+
+/// Energy used by a process since it started.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct ProcessEnergy {
+    /// Time the process ran, in microseconds.
+    pub cpu_time_us: u64,
+    /// Estimated energy the CPU used while running the process, in
+    /// microjoules.
+    pub cpu_energy_uj: u64,
+    /// Estimated energy peripherals used on behalf of the process, in
+    /// microjoules.
+    pub peripheral_energy_uj: u64,
+    /// Energy budget of the process in microjoules, if it has one.
+    pub budget_uj: Option<u64>,
+}
+
+impl ProcessEnergy {
+    /// Total estimated energy use, in microjoules.
+    pub fn energy_uj(&self) -> u64 {
+        self.cpu_energy_uj + self.peripheral_energy_uj
+    }
+
+    /// Part of the budget that is left, in microjoules.
+    pub fn remaining_budget_uj(&self) -> Option<u64> {
+        self.budget_uj
+            .map(|budget| budget.saturating_sub(self.energy_uj()))
+    }
+}
+
+/// Energy accounting results.
+pub trait EnergyStatistics {
+    /// Energy used by `processid`, or `None` if it is not accounted.
+    fn process_energy(&self, processid: ProcessId) -> Option<ProcessEnergy>;
+
+    /// Energy the whole system used since accounting started, in
+    /// microjoules, if it is measured by a power meter.
+    fn measured_energy_uj(&self) -> Option<u64>;
+}
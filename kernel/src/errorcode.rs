@@ -41,6 +41,8 @@ pub enum ErrorCode {
     UNINSTALLED = 12,
     /// Packet transmission not acknowledged
     NOACK = 13,
+    /// Buffer is not aligned as the driver requires
+    ALIGN = 14,
 }
 
 impl From<ErrorCode> for usize {
@@ -68,6 +70,7 @@ impl TryFrom<Result<(), ErrorCode>> for ErrorCode {
             Err(ErrorCode::NODEVICE) => Ok(ErrorCode::NODEVICE),
             Err(ErrorCode::UNINSTALLED) => Ok(ErrorCode::UNINSTALLED),
             Err(ErrorCode::NOACK) => Ok(ErrorCode::NOACK),
+            Err(ErrorCode::ALIGN) => Ok(ErrorCode::ALIGN),
         }
     }
 }
@@ -88,6 +91,7 @@ impl From<ErrorCode> for Result<(), ErrorCode> {
             ErrorCode::NODEVICE => Err(ErrorCode::NODEVICE),
             ErrorCode::UNINSTALLED => Err(ErrorCode::UNINSTALLED),
             ErrorCode::NOACK => Err(ErrorCode::NOACK),
+            ErrorCode::ALIGN => Err(ErrorCode::ALIGN),
         }
     }
 }
@@ -139,6 +139,7 @@ use crate::processbuffer::{ReadOnlyProcessBuffer, ReadWriteProcessBuffer};
 use crate::processbuffer::{ReadOnlyProcessBufferRef, ReadWriteProcessBufferRef};
 use crate::upcall::{Upcall, UpcallError, UpcallId};
 use crate::utilities::capability_ptr::CapabilityPtr;
+use crate::utilities::section_monitor;
 use crate::ErrorCode;
 
 /// Tracks how many upcalls a grant instance supports automatically.
@@ -1475,7 +1476,10 @@ impl<'a, T: Default, Upcalls: UpcallSize, AllowROs: AllowRoSize, AllowRWs: Allow
         };
 
         // Call functor and pass back value.
-        Some(fun(&mut grant_data, &kernel_data, &mut allocator))
+        Some(section_monitor::measure(
+            core::any::type_name::<T>(),
+            || fun(&mut grant_data, &kernel_data, &mut allocator),
+        ))
     }
 }
 
@@ -51,6 +51,78 @@ pub trait Client {
     fn sample_ready(&self, sample: u16);
 }
 
+// *** Interfaces for configurable ADCs ***
+
+/// Voltage reference of an ADC.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reference {
+    /// Reference generated inside the chip, independent of the supply
+    /// voltage. Needed to measure the supply voltage, for example of a
+    /// battery.
+    Internal,
+    /// Analog supply voltage of the chip, or a fixed fraction of it.
+    Supply,
+    /// Voltage applied on a reference pin.
+    External,
+}
+
+/// Interface for configuring the voltage reference and the oversampling of an
+/// ADC.
+///
+/// The configuration applies to all the following samples, and can only be
+/// changed while the ADC is not sampling.
+pub trait AdcConfigure<'a>: Adc<'a> {
+    /// Select the voltage reference. [`Adc::get_voltage_reference_mv`]
+    /// returns the voltage of the new reference afterwards.
+    ///
+    /// ### Return values
+    ///
+    /// - `Ok(())`: the reference is selected.
+    /// - `Err(NOSUPPORT)`: the ADC cannot use this reference.
+    /// - `Err(BUSY)`: the ADC is sampling.
+    fn set_reference(&self, reference: Reference) -> Result<(), ErrorCode>;
+
+    /// Return the selected voltage reference.
+    fn get_reference(&self) -> Reference;
+
+    /// Average `samples` conversions into each sample, which reduces the
+    /// noise at the cost of a lower sampling rate. `samples` must be a power
+    /// of two, and 1 disables oversampling. The samples keep the same format.
+    ///
+    /// ### Return values
+    ///
+    /// - `Ok(())`: the oversampling is configured.
+    /// - `Err(INVAL)`: `samples` is not a power of two, or larger than the
+    ///   ADC supports.
+    /// - `Err(BUSY)`: the ADC is sampling.
+    fn set_oversampling(&self, samples: usize) -> Result<(), ErrorCode>;
+
+    /// Return the number of conversions averaged into each sample.
+    fn get_oversampling(&self) -> usize;
+}
+
+/// Interface for sampling the difference between two channels, for example
+/// the output of a bridge such as a strain gauge.
+pub trait AdcDifferential<'a>: Adc<'a> {
+    /// Request a single sample of the voltage of `positive` minus the voltage
+    /// of `negative`.
+    ///
+    /// The sample is reported with [`Client::sample_ready`] as a signed value
+    /// left-justified in the u16, in two's complement: casting it to an `i16`
+    /// gives the difference, where `i16::MAX` is the reference voltage.
+    ///
+    /// ### Return values
+    ///
+    /// - `Ok(())`: the sample will be reported to the client.
+    /// - `Err(INVAL)`: the ADC cannot sample this pair of channels.
+    /// - `Err(BUSY)`: the ADC is sampling.
+    fn sample_differential(
+        &self,
+        positive: &Self::Channel,
+        negative: &Self::Channel,
+    ) -> Result<(), ErrorCode>;
+}
+
 // *** Interfaces for high-speed, buffered ADC sampling ***
 
 /// Interface for continuously sampling at a given frequency on a channel.
@@ -0,0 +1,65 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Interface for the radio of a Bluetooth Low Energy peripheral that accepts
+//! connections.
+//!
+//! The BLE link layer answers packets 150 µs (T_IFS) after they end, which
+//! is too soon for the kernel, so the radio does it. A connectable
+//! advertisement is followed by listening for a connection request on the
+//! same channel, and in a connection event the radio listens for a packet
+//! of the central and sends a response back automatically.
+//!
+//! The response is prepared before the connection event. The client may
+//! update it when the packet of the central is received, and the radio sends
+//! the update if it is in time. The response prepared beforehand must
+//! therefore be correct whatever the central sends, for example by not
+//! acknowledging the packet of the central and retransmitting the last
+//! packet of the peripheral.
+//!
+//! Buffers hold the packets from the header of the PDU: one header byte,
+//! the length of the payload and the payload.
+
+use crate::hil::ble_advertising::RadioChannel;
+
+pub trait BleConnectionRadio<'a> {
+    fn set_connection_client(&self, client: &'a dyn ConnectionClient);
+
+    /// Transmit the connectable advertisement in the first `len` bytes of
+    /// `buf` on `channel`, then listen for a request of a central on the
+    /// same channel until a packet is received or `stop` is called.
+    fn advertise_connectable(&self, buf: &'static mut [u8], len: usize, channel: RadioChannel);
+
+    /// Listen on the data `channel` of the connection with `access_address`
+    /// and `crc_init` for a packet of the central, and transmit `response`
+    /// T_IFS after it, until a packet is received or `stop` is called.
+    fn connection_event(
+        &self,
+        channel: RadioChannel,
+        access_address: u32,
+        crc_init: u32,
+        response: &'static mut [u8],
+    );
+
+    /// Stop listening, unless a packet is being received. Returns the
+    /// buffer of the operation if it was stopped, and `None` if there is no
+    /// operation or its callback is still to come.
+    fn stop(&self) -> Option<&'static mut [u8]>;
+}
+
+pub trait ConnectionClient {
+    /// The connectable advertisement in `buf` was transmitted, and a packet
+    /// was received after it. `request` is the packet if its CRC was
+    /// correct.
+    fn advertisement_done(&self, buf: &'static mut [u8], request: Option<&[u8]>);
+
+    /// The `packet` of the central was received in a connection event.
+    /// `crc_valid` is whether the CRC of the packet was correct. The client
+    /// may update the `response` that is transmitted after it.
+    fn packet_received(&self, packet: &[u8], crc_valid: bool, response: &mut [u8]);
+
+    /// The connection event ended after the `response` was transmitted for
+    /// a packet of the central.
+    fn connection_event_done(&self, response: &'static mut [u8]);
+}
@@ -0,0 +1,72 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Interface for block storage devices, such as SD cards.
+//!
+//! Block storage is read and written in whole blocks, which are addressed by
+//! their index on the device. Devices may have to be initialized before they
+//! can be accessed, and their media may be removable.
+
+use crate::errorcode::ErrorCode;
+
+pub trait BlockStorage<'a> {
+    fn set_client(&self, client: &'a dyn BlockStorageClient);
+
+    /// Size of a block, in bytes.
+    fn block_size(&self) -> usize;
+
+    /// Number of blocks on the device, or `None` while it is not ready.
+    fn num_blocks(&self) -> Option<u64>;
+
+    /// Whether the device is initialized and can be read and written.
+    fn is_ready(&self) -> bool;
+
+    /// Initialize the device, which makes it ready once `initialize_done` is
+    /// called with `Ok(())`. Initializing a ready device initializes it
+    /// again.
+    ///
+    /// Returns `UNINSTALLED` if the media is not present, and `BUSY` if an
+    /// operation is in progress.
+    fn initialize(&self) -> Result<(), ErrorCode>;
+
+    /// Read `count` blocks starting at block `block` into `buffer`, which must
+    /// hold at least `count` blocks.
+    ///
+    /// Returns `RESERVE` if the device is not ready, `UNINSTALLED` if the
+    /// media is not present, `INVAL` if the blocks are not on the device or do
+    /// not fit into `buffer`, and `BUSY` if an operation is in progress.
+    fn read(
+        &self,
+        buffer: &'static mut [u8],
+        block: u64,
+        count: u32,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+
+    /// Write `count` blocks from `buffer` starting at block `block`.
+    ///
+    /// Fails in the same cases as `read`. Devices that cannot write several
+    /// blocks at once return `NOSUPPORT` if `count` is more than 1.
+    fn write(
+        &self,
+        buffer: &'static mut [u8],
+        block: u64,
+        count: u32,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+}
+
+pub trait BlockStorageClient {
+    /// Initialization finished, or failed.
+    fn initialize_done(&self, result: Result<(), ErrorCode>);
+
+    /// A read finished, or failed. The contents of `buffer` are only valid
+    /// when `result` is `Ok(())`.
+    fn read_done(&self, buffer: &'static mut [u8], result: Result<(), ErrorCode>);
+
+    /// A write finished, or failed.
+    fn write_done(&self, buffer: &'static mut [u8], result: Result<(), ErrorCode>);
+
+    /// Removable media was inserted or removed. The device is not ready
+    /// afterwards, and any operation in progress failed.
+    fn media_changed(&self, present: bool);
+}
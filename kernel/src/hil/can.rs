@@ -168,6 +168,13 @@ pub struct FilterParameters {
 
     /// The receive FIFO Id that the filter will be applied to
     pub fifo_number: usize,
+
+    /// The identifier that the messages are compared to
+    pub identifier: Id,
+
+    /// In mask mode, the bits of the identifier that must match. A mask of
+    /// 0 accepts all messages. This value is ignored in list mode.
+    pub mask: u32,
 }
 
 /// This structure defines the parameters for the timing mode
@@ -0,0 +1,123 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Interfaces for the capture and compare channels of hardware timers.
+//!
+//! [`Alarm`](crate::hil::time::Alarm) only uses timers to generate
+//! interrupts. Most timers also have channels that are connected to pins:
+//!
+//! - A capture channel stores the value of the timer when an edge occurs on
+//!   its input pin ([`Capture`]). The timestamp is taken by the hardware, so
+//!   it does not depend on interrupt latency, which allows precise pulse
+//!   width and frequency measurements.
+//!
+//! - A compare channel changes its output pin when the timer reaches a
+//!   value ([`CompareOutput`]), which allows generating pulses of precise
+//!   length.
+//!
+//! The pin of each channel is chip specific, and is configured by the board.
+//! Both interfaces are implemented on top of [`Time`], and timestamps are in
+//! the ticks of that time source.
+
+use crate::hil::time::{Ticks, Time};
+use crate::ErrorCode;
+
+/// Edges of the input pin that a capture channel timestamps.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Edge {
+    Rising,
+    Falling,
+    Both,
+}
+
+/// Change of the output pin of a compare channel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputAction {
+    /// Drive the pin high.
+    Set,
+    /// Drive the pin low.
+    Clear,
+    /// Invert the level of the pin.
+    Toggle,
+}
+
+pub trait Capture<'a>: Time {
+    fn set_capture_client(&self, client: &'a dyn CaptureClient<Self::Ticks>);
+
+    /// Number of capture channels.
+    fn capture_channels(&self) -> usize;
+
+    /// Timestamp every `edge` of the input pin of `channel`, until
+    /// `stop_capture` is called. Each timestamp is returned through
+    /// `captured`.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: The channel is capturing.
+    /// - `INVAL`: `channel` does not exist.
+    /// - `BUSY`: The channel is already capturing or generating an output.
+    /// - `NOSUPPORT`: The channel does not support `edge`.
+    /// - `FAIL`: The hardware resources for the channel are in use.
+    fn start_capture(&self, channel: usize, edge: Edge) -> Result<(), ErrorCode>;
+
+    /// Stop capturing on `channel`. Does nothing if it is not capturing.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: The channel is not capturing.
+    /// - `INVAL`: `channel` does not exist.
+    fn stop_capture(&self, channel: usize) -> Result<(), ErrorCode>;
+}
+
+pub trait CaptureClient<T: Ticks> {
+    /// An edge occurred on the input pin of `channel` at `timestamp`.
+    ///
+    /// If edges are closer together than the interrupt latency, the
+    /// timestamps of some of them may not be reported.
+    fn captured(&self, channel: usize, timestamp: T);
+}
+
+pub trait CompareOutput<'a>: Time {
+    fn set_compare_output_client(&self, client: &'a dyn CompareOutputClient);
+
+    /// Number of compare channels.
+    fn compare_channels(&self) -> usize;
+
+    /// Perform `action` on the output pin of `channel` at time `reference +
+    /// dt`. The change is made by the hardware, at the exact time. If that
+    /// time is already in the past, the change is made as soon as possible.
+    /// `output_done` is called after the change.
+    ///
+    /// An output that was set earlier, and has not happened yet, is
+    /// replaced. After the change, the pin keeps its level until the next
+    /// output on the channel, or until the output is cancelled.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: The output is scheduled.
+    /// - `INVAL`: `channel` does not exist.
+    /// - `BUSY`: The channel is capturing.
+    /// - `FAIL`: The hardware resources for the channel are in use.
+    fn set_output(
+        &self,
+        channel: usize,
+        reference: Self::Ticks,
+        dt: Self::Ticks,
+        action: OutputAction,
+    ) -> Result<(), ErrorCode>;
+
+    /// Cancel the scheduled output of `channel`, if any, and release its
+    /// pin.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: The channel has no output.
+    /// - `INVAL`: `channel` does not exist.
+    fn cancel_output(&self, channel: usize) -> Result<(), ErrorCode>;
+}
+
+pub trait CompareOutputClient {
+    /// The output of `channel` changed.
+    fn output_done(&self, channel: usize);
+}
@@ -75,3 +75,40 @@ pub trait DateTimeClient {
     /// Takes  `Err(ErrorCode)` in case of an error
     fn set_date_time_done(&self, result: Result<(), ErrorCode>);
 }
+
+/// Interface for an alarm at a date and time.
+///
+/// This is meant for RTCs that keep running, and can wake the chip, when the
+/// rest of the board sleeps or loses power. The alarm fires once.
+pub trait DateTimeAlarm<'a> {
+    /// Fire the alarm at `date_time`.
+    ///
+    /// RTCs only compare some fields: typically the day of the month, the
+    /// hour and the minute, and on some RTCs the seconds. The alarm then fires
+    /// the next time these match, so it cannot be set more than a month
+    /// ahead. The fields an RTC does not compare are ignored.
+    ///
+    /// When successful, this function call must be followed by a call to
+    /// `set_alarm_done`.
+    fn set_alarm(&self, date_time: DateTimeValues) -> Result<(), ErrorCode>;
+
+    /// Disarm the alarm.
+    ///
+    /// When successful, this function call must be followed by a call to
+    /// `disarm_alarm_done`.
+    fn disarm_alarm(&self) -> Result<(), ErrorCode>;
+
+    fn set_alarm_client(&self, client: &'a dyn DateTimeAlarmClient);
+}
+
+/// Callback handler for [`DateTimeAlarm`].
+pub trait DateTimeAlarmClient {
+    /// Called when the alarm is set.
+    fn set_alarm_done(&self, result: Result<(), ErrorCode>);
+
+    /// Called when the alarm is disarmed.
+    fn disarm_alarm_done(&self, result: Result<(), ErrorCode>);
+
+    /// Called when the alarm fires.
+    fn alarm(&self);
+}
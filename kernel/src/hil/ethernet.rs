@@ -0,0 +1,58 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Interface for Ethernet network adapters.
+//!
+//! The datapath carries complete Ethernet frames, starting with the
+//! destination MAC address and without the frame check sequence, which is
+//! computed and verified by the adapter.
+
+use crate::ErrorCode;
+
+/// Client of an Ethernet adapter.
+pub trait EthernetAdapterDatapathClient {
+    /// A frame passed to `transmit_frame` was sent, or failed to be.
+    ///
+    /// `len` and `transmission_identifier` are the values passed to
+    /// `transmit_frame`. `timestamp` is the time the frame was sent at, for
+    /// adapters that support timestamping.
+    fn transmit_frame_done(
+        &self,
+        err: Result<(), ErrorCode>,
+        frame_buffer: &'static mut [u8],
+        len: u16,
+        transmission_identifier: usize,
+        timestamp: Option<u64>,
+    );
+
+    /// A frame was received.
+    ///
+    /// The adapter reuses the receive buffer once this returns, so the
+    /// client has to copy out anything it wants to keep.
+    fn received_frame(&self, frame: &[u8], timestamp: Option<u64>);
+}
+
+/// Datapath of an Ethernet adapter.
+pub trait EthernetAdapterDatapath<'a> {
+    fn set_client(&self, client: &'a dyn EthernetAdapterDatapathClient);
+
+    /// Start passing received frames to the client.
+    fn enable_receive(&self);
+
+    /// Stop passing received frames to the client. Frames received while
+    /// disabled are dropped.
+    fn disable_receive(&self);
+
+    /// Send the first `len` bytes of `frame_buffer` as a frame.
+    ///
+    /// `transmission_identifier` is passed back to `transmit_frame_done`, to
+    /// tell transmissions apart. Returns `BUSY` if the adapter cannot queue
+    /// another frame, and `SIZE` if `len` exceeds the buffer or the MTU.
+    fn transmit_frame(
+        &self,
+        frame_buffer: &'static mut [u8],
+        len: u16,
+        transmission_identifier: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+}
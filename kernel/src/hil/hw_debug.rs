@@ -23,7 +23,10 @@ pub trait CycleCounter {
 
     /// Benchmark the number of cycles to run a passed closure.
     /// This function is intended for use debugging in-kernel routines.
-    fn profile_closure<F: FnOnce()>(&self, f: F) -> u64 {
+    fn profile_closure<F: FnOnce()>(&self, f: F) -> u64
+    where
+        Self: Sized,
+    {
         self.reset();
         self.start();
         f();
@@ -62,7 +62,7 @@ impl Display for Error {
 }
 
 /// This specifies what type of transmission just finished from a Master device.
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum SlaveTransmissionType {
     Write,
     Read,
@@ -176,6 +176,26 @@ pub trait I2CSlave<'a> {
     fn listen(&self);
 }
 
+/// Interface for I2C slave hardware that can respond on several addresses at
+/// once, and tells which one a transaction is addressed to.
+///
+/// Address slot 0 is the address set with [`I2CSlave::set_address`].
+pub trait I2CSlaveMultiAddress<'a>: I2CSlave<'a> {
+    /// Number of addresses the hardware can respond on at once.
+    fn address_count(&self) -> usize;
+
+    /// Respond on `addr` in address slot `index`, in addition to the
+    /// addresses of the other slots. Returns `NotSupported` if `index` is not
+    /// below [`I2CSlaveMultiAddress::address_count`].
+    fn set_address_at(&self, index: usize, addr: u8) -> Result<(), Error>;
+
+    /// The address the current transaction is addressed to. This is valid
+    /// from when the slave client is asked for a buffer with `read_expected()`
+    /// or `write_expected()`, or the transfer starts, until
+    /// `command_complete()` is called.
+    fn matching_address(&self) -> Option<u8>;
+}
+
 /// Convenience type for capsules that need hardware that supports both
 /// Master and Slave modes.
 pub trait I2CMasterSlave<'a>: I2CMaster<'a> + I2CSlave<'a> {}
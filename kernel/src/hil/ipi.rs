@@ -0,0 +1,38 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Interface for inter-processor interrupts.
+//!
+//! On chips with multiple cores (harts), an inter-processor interrupt (IPI)
+//! lets code running on one hart interrupt another one, for example to wake it
+//! up from sleep when there is new work for it. Harts are numbered from 0, the
+//! hart that boots the kernel, to `num_harts() - 1`.
+
+use crate::ErrorCode;
+
+pub trait InterProcessorInterrupt<'a> {
+    fn set_client(&self, client: &'a dyn InterProcessorInterruptClient);
+
+    /// Number of harts that can receive an interrupt.
+    fn num_harts(&self) -> usize;
+
+    /// Hart this function is called on.
+    fn current_hart(&self) -> usize;
+
+    /// Interrupt `hart`. It is interrupted once, even if this is called
+    /// several times before it handles the interrupt. A hart can interrupt
+    /// itself.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: The interrupt is pending on `hart`.
+    /// - `INVAL`: `hart` does not exist.
+    fn send(&self, hart: usize) -> Result<(), ErrorCode>;
+}
+
+pub trait InterProcessorInterruptClient {
+    /// Another hart (or this one) sent an interrupt to the current hart. This
+    /// is called on the hart that received the interrupt.
+    fn interrupt_received(&self);
+}
@@ -0,0 +1,104 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Interfaces for the link quality of radio neighbors.
+//!
+//! Receive paths of radio stacks, such as the 802.15.4 MAC or a LoRa PHY,
+//! report each frame they receive, with its signal strength and link quality
+//! when the radio measures them, to a [`LinkQualityObserver`]. A neighbor
+//! table keeps statistics per source address and forgets neighbors that have
+//! not been heard from for a while. Routing layers, syscall drivers and the
+//! process console read it through the [`NeighborTable`] trait.
+
+use core::fmt;
+
+/// Longest link-layer address, in bytes (an IEEE EUI-64).
+pub const MAX_ADDRESS_LEN: usize = 8;
+
+/// Link-layer address of a neighbor, such as a short or extended 802.15.4
+/// address.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LinkAddress {
+    len: u8,
+    bytes: [u8; MAX_ADDRESS_LEN],
+}
+
+impl LinkAddress {
+    /// Returns `None` if `address` is longer than [`MAX_ADDRESS_LEN`].
+    pub fn new(address: &[u8]) -> Option<Self> {
+        let mut bytes = [0; MAX_ADDRESS_LEN];
+        bytes.get_mut(..address.len())?.copy_from_slice(address);
+        Some(Self {
+            len: address.len() as u8,
+            bytes,
+        })
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+}
+
+impl fmt::Display for LinkAddress {
+    /// Hexadecimal bytes separated by colons.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, byte) in self.as_slice().iter().enumerate() {
+            if i > 0 {
+                f.write_str(":")?;
+            }
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Summary of a measurement over the frames received from a neighbor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LinkSummary<T> {
+    /// Measurement of the last frame.
+    pub last: T,
+    /// Moving average that favors recent frames.
+    pub average: T,
+    pub min: T,
+    pub max: T,
+}
+
+/// Statistics about a neighbor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Neighbor {
+    pub address: LinkAddress,
+    /// Frames received from the neighbor since it entered the table.
+    pub frames: u32,
+    /// Seconds since the last frame from the neighbor.
+    pub age_s: u32,
+    /// Received signal strength, in dBm, if the radio measures it.
+    pub rssi: Option<LinkSummary<i8>>,
+    /// Link quality indicator, if the radio measures it. Its scale depends on
+    /// the radio, 255 being the best.
+    pub lqi: Option<LinkSummary<u8>>,
+}
+
+/// Receiver of the frames of a radio stack.
+pub trait LinkQualityObserver {
+    /// A frame was received from the link-layer address `source`.
+    fn frame_received(&self, source: &[u8], rssi: Option<i8>, lqi: Option<u8>);
+}
+
+/// Query recently heard neighbors.
+pub trait NeighborTable {
+    /// Number of neighbors in the table.
+    fn num_neighbors(&self) -> usize;
+
+    /// Neighbor number `index`, in no particular order, or `None` if `index`
+    /// is not less than [`NeighborTable::num_neighbors`]. Indices change as
+    /// neighbors enter and leave the table.
+    fn neighbor(&self, index: usize) -> Option<Neighbor>;
+
+    /// The neighbor with the address `address`, if it is in the table.
+    fn find(&self, address: &[u8]) -> Option<Neighbor> {
+        (0..self.num_neighbors())
+            .filter_map(|index| self.neighbor(index))
+            .find(|neighbor| neighbor.address.as_slice() == address)
+    }
+}
@@ -7,28 +7,37 @@
 pub mod adc;
 pub mod analog_comparator;
 pub mod ble_advertising;
+pub mod ble_connection;
+pub mod block_storage;
 pub mod bus8080;
 pub mod buzzer;
 pub mod can;
+pub mod capture_compare;
 pub mod crc;
 pub mod dac;
 pub mod date_time;
 pub mod digest;
 pub mod eic;
 pub mod entropy;
+pub mod ethernet;
 pub mod flash;
 pub mod gpio;
 pub mod gpio_async;
 pub mod hasher;
 pub mod hw_debug;
 pub mod i2c;
+pub mod ipi;
 pub mod kv;
 pub mod led;
+pub mod link_quality;
 pub mod log;
 pub mod nonvolatile_storage;
+pub mod power_meter;
+pub mod power_rail;
 pub mod public_key_crypto;
 pub mod pwm;
 pub mod radio;
+pub mod retained;
 pub mod rng;
 pub mod screen;
 pub mod sensors;
@@ -0,0 +1,27 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Interface for sources of power measurements, such as current sensors on
+//! the supply rail of a board.
+
+use crate::ErrorCode;
+
+pub trait PowerMeter<'a> {
+    fn set_client(&self, client: &'a dyn PowerMeterClient);
+
+    /// Start a measurement of the power drawn by the system. The result is
+    /// returned through `power_measured`.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: A measurement was started.
+    /// - `BUSY`: A measurement is already in progress.
+    /// - `FAIL`: The measurement could not be started.
+    fn measure_power(&self) -> Result<(), ErrorCode>;
+}
+
+pub trait PowerMeterClient {
+    /// A measurement finished, with the power in microwatts.
+    fn power_measured(&self, power_uw: Result<u32, ErrorCode>);
+}
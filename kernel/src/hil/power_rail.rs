@@ -0,0 +1,52 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Interface for switchable power rails, such as the supply of a sensor
+//! behind a load switch, a charge pump or a regulator of a PMIC.
+//!
+//! A rail that was just turned on is not usable until its voltage settles, so
+//! turning a rail on completes asynchronously. Implementations wait for the
+//! settle time of the rail before calling `power_on_done`.
+//!
+//! A rail is often shared by several devices. Capsules should use rails
+//! through a virtualizer that counts the users that need the rail, so that
+//! the rail is only turned off once none of them do.
+
+use crate::ErrorCode;
+
+pub trait PowerRail<'a> {
+    fn set_client(&self, client: &'a dyn PowerRailClient);
+
+    /// The name of the rail, for debugging.
+    fn name(&self) -> &'static str;
+
+    /// Turn the rail on. `power_on_done` is called once the rail is usable.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: The rail is being turned on.
+    /// - `ALREADY`: The rail is on.
+    /// - `BUSY`: The rail is being turned on or off.
+    fn power_on(&self) -> Result<(), ErrorCode>;
+
+    /// Turn the rail off. `power_off_done` is called once it is off.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: The rail is being turned off.
+    /// - `ALREADY`: The rail is off.
+    /// - `BUSY`: The rail is being turned on or off.
+    fn power_off(&self) -> Result<(), ErrorCode>;
+
+    /// Whether the rail is on and usable.
+    fn is_on(&self) -> bool;
+}
+
+pub trait PowerRailClient {
+    /// The rail is on and settled, or could not be turned on.
+    fn power_on_done(&self, result: Result<(), ErrorCode>);
+
+    /// The rail is off, or could not be turned off.
+    fn power_off_done(&self, result: Result<(), ErrorCode>);
+}
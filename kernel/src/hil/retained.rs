@@ -0,0 +1,45 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Interface for registers which keep their value across resets.
+//!
+//! Many chips have a few registers, or a small RAM, which is not cleared by a
+//! soft reset, and sometimes stays powered by a backup battery. They can hold
+//! small pieces of state between two boots, such as a value asking the
+//! bootloader to stay in its update mode, or a note of why the kernel
+//! panicked.
+//!
+//! Accesses are synchronous: these are plain registers.
+
+use crate::ErrorCode;
+
+/// A set of retained registers.
+pub trait RetainedRegisters {
+    /// Number of retained registers.
+    fn count(&self) -> usize;
+
+    /// Number of bits of each register which are retained, starting from the
+    /// least significant bit. This is at most 32.
+    fn width(&self) -> usize;
+
+    /// Read the register at `index`.
+    ///
+    /// ### Return values
+    ///
+    /// - `Ok(value)`: the value of the register.
+    /// - `Err(INVAL)`: `index` is not less than [`RetainedRegisters::count`].
+    fn read(&self, index: usize) -> Result<u32, ErrorCode>;
+
+    /// Write `value` to the register at `index`.
+    ///
+    /// ### Return values
+    ///
+    /// - `Ok(())`: the register holds `value`.
+    /// - `Err(INVAL)`: `index` is not less than [`RetainedRegisters::count`].
+    /// - `Err(SIZE)`: `value` does not fit in [`RetainedRegisters::width`]
+    ///   bits.
+    /// - `Err(OFF)`: the registers cannot be written, for example because the
+    ///   domain holding them is not powered.
+    fn write(&self, index: usize, value: u32) -> Result<(), ErrorCode>;
+}
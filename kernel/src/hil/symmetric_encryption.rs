@@ -18,6 +18,9 @@ pub trait Client<'a> {
 /// and encryption/decryption inputs must be have a multiple of this length.
 pub const AES128_BLOCK_SIZE: usize = 16;
 pub const AES128_KEY_SIZE: usize = 16;
+/// Key size of AES-256, for implementations of the `AES128` traits that also
+/// accept 256 bit keys.
+pub const AES256_KEY_SIZE: usize = 32;
 
 pub trait AES128<'a> {
     /// Enable the AES hardware.
@@ -31,7 +34,8 @@ pub trait AES128<'a> {
     fn set_client(&'a self, client: &'a dyn Client<'a>);
 
     /// Set the encryption key.
-    /// Returns `INVAL` if length is not `AES128_KEY_SIZE`
+    /// Returns `INVAL` if length is not `AES128_KEY_SIZE`, or
+    /// `AES256_KEY_SIZE` for implementations that support AES-256
     fn set_key(&self, key: &[u8]) -> Result<(), ErrorCode>;
 
     /// Set the IV (or initial counter).
@@ -24,6 +24,8 @@ pub enum GestureEvent {
     SwipeRight,
     ZoomIn,
     ZoomOut,
+    Tap,
+    LongPress,
 }
 
 /// A single touch event's data
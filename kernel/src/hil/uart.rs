@@ -281,6 +281,39 @@ pub trait Receive<'a> {
 
 /// Trait implemented by a UART transmitter to receive callbacks when
 /// operations complete.
+/// Trait for UARTs that transmit several parts of a buffer as one
+/// transmission.
+///
+/// This lets a client send data that is split in its buffer, such as the two
+/// halves of a wrapped ring buffer, without copying it together first.
+/// Implementations transmit the parts back to back, and should not let the
+/// line go idle between them.
+pub trait TransmitVectored<'a>: Transmit<'a> {
+    /// Maximum number of parts of a transmission.
+    fn max_parts(&self) -> usize;
+
+    /// Transmit the parts of `tx_buffer` in `parts`, in order. Each part is
+    /// the start and length of a range of `tx_buffer`.
+    ///
+    /// [`TransmitClient::transmitted_buffer`] is called once all parts are
+    /// transmitted, with the sum of their lengths as `tx_len`.
+    ///
+    /// ### Return values
+    ///
+    /// - `Ok(())`: The transmission started successfully.
+    ///   [`TransmitClient::transmitted_buffer`] will be called.
+    /// - `Err(BUSY)`: the UART is already transmitting and has not made a
+    ///   transmission callback yet.
+    /// - `Err(SIZE)`: a part is empty, or extends past the end of
+    ///   `tx_buffer`, or there are no parts or more than
+    ///   [`TransmitVectored::max_parts`].
+    fn transmit_vectored(
+        &self,
+        tx_buffer: &'static mut [u8],
+        parts: &[(usize, usize)],
+    ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+}
+
 pub trait TransmitClient {
     /// A call to [`Transmit::transmit_word`] completed.
     ///
@@ -397,7 +430,11 @@ pub trait ReceiveAdvanced<'a>: Receive<'a> {
     /// Receive data until `interbyte_timeout` bit periods have passed since the
     /// last byte or buffer is full.
     ///
-    /// This does not timeout until at least one byte has been received.
+    /// This does not timeout until at least one byte has been received. This
+    /// lets protocols that delimit frames with an idle line, such as Modbus
+    /// RTU or NMEA, receive a whole frame without reading it byte by byte.
+    /// Hardware that can only detect a fixed idle time may document that it
+    /// rounds `interbyte_timeout` to it.
     ///
     /// ### Arguments:
     ///
@@ -416,6 +453,8 @@ pub trait ReceiveAdvanced<'a>: Receive<'a> {
     /// - `Err(BUSY)`: the UART is already receiving and has not made a
     ///   reception callback yet.
     /// - `Err(SIZE)`: `rx_len` is larger than the passed slice.
+    /// - `Err(NOSUPPORT)`: the hardware needed to detect an idle line has not
+    ///   been configured.
     fn receive_automatic(
         &self,
         rx_buffer: &'static mut [u8],
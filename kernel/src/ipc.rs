@@ -12,8 +12,10 @@ use crate::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
 use crate::kernel::Kernel;
 use crate::process;
 use crate::process::ProcessId;
-use crate::processbuffer::ReadableProcessBuffer;
+use crate::process_swap::SwapIn;
+use crate::processbuffer::{ReadableProcessBuffer, ReadableProcessSlice};
 use crate::syscall_driver::{CommandReturn, SyscallDriver};
+use crate::utilities::cells::OptionalCell;
 use crate::ErrorCode;
 
 /// Syscall number
@@ -50,6 +52,8 @@ pub struct IPC<const NUM_PROCS: u8> {
         AllowRoCount<{ ro_allow::COUNT }>,
         AllowRwCount<NUM_PROCS>,
     >,
+    /// Brings swapped out services and clients back into memory.
+    swap_in: OptionalCell<&'static dyn SwapIn>,
 }
 
 impl<const NUM_PROCS: u8> IPC<NUM_PROCS> {
@@ -60,6 +64,40 @@ impl<const NUM_PROCS: u8> IPC<NUM_PROCS> {
     ) -> Self {
         Self {
             data: kernel.create_grant(driver_num, capability),
+            swap_in: OptionalCell::empty(),
+        }
+    }
+
+    /// Swap in the processes that are discovered or notified while they are
+    /// swapped out. The command then fails with `BUSY`, and can be retried
+    /// once the process is back.
+    pub fn set_swap_in(&self, swap_in: &'static dyn SwapIn) {
+        self.swap_in.set(swap_in);
+    }
+
+    /// Start to swap in the process named `name`, if it is swapped out.
+    fn swap_in_by_name(&self, name: &ReadableProcessSlice) -> bool {
+        let mut buffer = [0; 32];
+        let Some(buffer) = buffer.get_mut(..name.len()) else {
+            return false;
+        };
+        name.copy_to_slice(buffer);
+        core::str::from_utf8(buffer).is_ok_and(|name| {
+            self.swap_in
+                .map_or(false, |swap_in| swap_in.swap_in_by_name(name))
+        })
+    }
+
+    /// The result of notifying the process in slot `index` of the processes
+    /// array, which does not exist.
+    fn missing_process(&self, index: usize) -> CommandReturn {
+        if self
+            .swap_in
+            .map_or(false, |swap_in| swap_in.swap_in_by_index(index))
+        {
+            CommandReturn::failure(ErrorCode::BUSY)
+        } else {
+            CommandReturn::failure(ErrorCode::INVAL)
         }
     }
 
@@ -116,7 +154,8 @@ impl<const NUM_PROCS: u8> SyscallDriver for IPC<NUM_PROCS> {
     /// In either case, the target_id is the same number as provided in a notify
     /// upcall or as returned by allow.
     ///
-    /// Returns INVAL if the other process doesn't exist.
+    /// Returns INVAL if the other process doesn't exist, and BUSY if it is
+    /// being swapped in.
 
     /// Initiates a service discovery or notifies a client or service.
     ///
@@ -124,7 +163,8 @@ impl<const NUM_PROCS: u8> SyscallDriver for IPC<NUM_PROCS> {
     ///
     /// - `0`: Driver existence check, always returns Ok(())
     /// - `1`: Perform discovery on the package name passed to `allow_readonly`. Returns the
-    ///        service descriptor if the service is found, otherwise returns an error.
+    ///        service descriptor if the service is found, otherwise returns an error. Returns
+    ///        `BUSY` if the service is being swapped in.
     /// - `2`: Notify a service previously discovered to have the service descriptor in
     ///        `target_id`. Returns an error if `target_id` refers to an invalid service or the
     ///        notify fails to enqueue.
@@ -168,7 +208,13 @@ impl<const NUM_PROCS: u8> SyscallDriver for IPC<NUM_PROCS> {
                                                 None
                                             }
                                         })
-                                        .unwrap_or(CommandReturn::failure(ErrorCode::NODEVICE))
+                                        .unwrap_or_else(|| {
+                                            if self.swap_in_by_name(slice) {
+                                                CommandReturn::failure(ErrorCode::BUSY)
+                                            } else {
+                                                CommandReturn::failure(ErrorCode::NODEVICE)
+                                            }
+                                        })
                                 })
                             })
                             .unwrap_or(CommandReturn::failure(ErrorCode::INVAL))
@@ -188,25 +234,29 @@ impl<const NUM_PROCS: u8> SyscallDriver for IPC<NUM_PROCS> {
                             _ => None,
                         });
 
-                other_process.map_or(CommandReturn::failure(ErrorCode::INVAL), |otherapp| {
-                    self.data.kernel.process_map_or(
-                        CommandReturn::failure(ErrorCode::INVAL),
-                        otherapp,
-                        |target| {
-                            let ret = target.enqueue_task(process::Task::IPC((processid, cb_type)));
-                            match ret {
-                                Ok(()) => CommandReturn::success(),
-                                Err(e) => {
-                                    // `enqueue_task` does not provide information on whether the
-                                    // recipient has set a non-null callback. It only reports
-                                    // general failures, such as insufficient memory in the pending
-                                    // tasks queue
-                                    CommandReturn::failure(e)
+                other_process.map_or_else(
+                    || self.missing_process(target_id),
+                    |otherapp| {
+                        self.data.kernel.process_map_or(
+                            CommandReturn::failure(ErrorCode::INVAL),
+                            otherapp,
+                            |target| {
+                                let ret =
+                                    target.enqueue_task(process::Task::IPC((processid, cb_type)));
+                                match ret {
+                                    Ok(()) => CommandReturn::success(),
+                                    Err(e) => {
+                                        // `enqueue_task` does not provide information on whether the
+                                        // recipient has set a non-null callback. It only reports
+                                        // general failures, such as insufficient memory in the pending
+                                        // tasks queue
+                                        CommandReturn::failure(e)
+                                    }
                                 }
-                            }
-                        },
-                    )
-                })
+                            },
+                        )
+                    },
+                )
             }
             3 =>
             /* Client notify */
@@ -221,25 +271,29 @@ impl<const NUM_PROCS: u8> SyscallDriver for IPC<NUM_PROCS> {
                             _ => None,
                         });
 
-                other_process.map_or(CommandReturn::failure(ErrorCode::INVAL), |otherapp| {
-                    self.data.kernel.process_map_or(
-                        CommandReturn::failure(ErrorCode::INVAL),
-                        otherapp,
-                        |target| {
-                            let ret = target.enqueue_task(process::Task::IPC((processid, cb_type)));
-                            match ret {
-                                Ok(()) => CommandReturn::success(),
-                                Err(e) => {
-                                    // `enqueue_task` does not provide information on whether the
-                                    // recipient has set a non-null callback. It only reports
-                                    // general failures, such as insufficient memory in the pending
-                                    // tasks queue
-                                    CommandReturn::failure(e)
+                other_process.map_or_else(
+                    || self.missing_process(target_id),
+                    |otherapp| {
+                        self.data.kernel.process_map_or(
+                            CommandReturn::failure(ErrorCode::INVAL),
+                            otherapp,
+                            |target| {
+                                let ret =
+                                    target.enqueue_task(process::Task::IPC((processid, cb_type)));
+                                match ret {
+                                    Ok(()) => CommandReturn::success(),
+                                    Err(e) => {
+                                        // `enqueue_task` does not provide information on whether the
+                                        // recipient has set a non-null callback. It only reports
+                                        // general failures, such as insufficient memory in the pending
+                                        // tasks queue
+                                        CommandReturn::failure(e)
+                                    }
                                 }
-                            }
-                        },
-                    )
-                })
+                            },
+                        )
+                    },
+                )
             }
             _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
         }
@@ -249,3 +303,61 @@ impl<const NUM_PROCS: u8> SyscallDriver for IPC<NUM_PROCS> {
         self.data.enter(processid, |_, _| {})
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capabilities;
+    use core::cell::Cell;
+
+    /// Swaps in the process in slot 1.
+    struct TestSwapIn(Cell<Option<usize>>);
+
+    impl SwapIn for TestSwapIn {
+        fn swap_in_by_name(&self, _name: &str) -> bool {
+            false
+        }
+
+        fn swap_in_by_index(&self, index: usize) -> bool {
+            self.0.set(Some(index));
+            index == 1
+        }
+    }
+
+    fn leak<T>(value: T) -> &'static T {
+        extern crate std;
+        std::boxed::Box::leak(std::boxed::Box::new(value))
+    }
+
+    #[test]
+    fn notify_swapped_out_process() {
+        let kernel = leak(Kernel::new(&[None, None]));
+        let swap_in = leak(TestSwapIn(Cell::new(None)));
+        let ipc: IPC<2> = IPC::new(
+            kernel,
+            DRIVER_NUM,
+            &crate::create_capability!(capabilities::MemoryAllocationCapability),
+        );
+        let caller = ProcessId::new(kernel, 0, 0);
+
+        // Without swapping, a missing process is an invalid target.
+        assert_eq!(
+            ipc.command(2, 1, 0, caller).get_failure(),
+            Some(ErrorCode::INVAL)
+        );
+
+        ipc.set_swap_in(swap_in);
+        for command in [2, 3] {
+            assert_eq!(
+                ipc.command(command, 1, 0, caller).get_failure(),
+                Some(ErrorCode::BUSY)
+            );
+            assert_eq!(swap_in.0.take(), Some(1));
+            assert_eq!(
+                ipc.command(command, 0, 0, caller).get_failure(),
+                Some(ErrorCode::INVAL)
+            );
+            assert_eq!(swap_in.0.take(), Some(0));
+        }
+    }
+}
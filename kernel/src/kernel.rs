@@ -15,25 +15,34 @@ use crate::capabilities;
 use crate::config;
 use crate::debug;
 use crate::deferred_call::DeferredCall;
+use crate::energy::EnergyMonitor;
 use crate::errorcode::ErrorCode;
 use crate::grant::{AllowRoSize, AllowRwSize, Grant, UpcallSize};
+use crate::hil::hw_debug::CycleCounter;
 use crate::ipc;
 use crate::memop;
-use crate::platform::chip::Chip;
+use crate::platform::chip::{Chip, HartLock, SmpChip, MAX_HARTS};
 use crate::platform::mpu::MPU;
 use crate::platform::platform::ContextSwitchCallback;
 use crate::platform::platform::KernelResources;
 use crate::platform::platform::{ProcessFault, SyscallDriverLookup, SyscallFilter};
+use crate::platform::power::PowerManager;
 use crate::platform::scheduler_timer::SchedulerTimer;
+use crate::platform::stats::KernelLoopCounters;
 use crate::platform::watchdog::WatchDog;
 use crate::process::{self, ProcessId, Task};
+use crate::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
 use crate::scheduler::{Scheduler, SchedulingDecision};
 use crate::syscall::SyscallDriver;
 use crate::syscall::{ContextSwitchReason, SyscallReturn};
 use crate::syscall::{Syscall, YieldCall};
+use crate::syscall::{COMMAND_BATCH_ENTRY_SIZE, MAX_COMMAND_BATCH_LEN};
 use crate::syscall_driver::CommandReturn;
 use crate::upcall::{Upcall, UpcallId};
+use crate::utilities::arch_helpers;
 use crate::utilities::cells::NumericCellExt;
+use crate::utilities::cells::OptionalCell;
+use crate::utilities::counters::WrappingCounter;
 
 /// Threshold in microseconds to consider a process's timeslice to be exhausted.
 /// That is, Tock will skip re-scheduling a process if its remaining timeslice
@@ -59,6 +68,48 @@ pub struct Kernel {
     /// created and the data structures for grants have already been
     /// established.
     grants_finalized: Cell<bool>,
+
+    /// Counters of events in the main loop, used for runtime statistics.
+    kernel_work_count: WrappingCounter,
+    context_switch_count: WrappingCounter,
+    sleep_count: WrappingCounter,
+    mpu_setup_cycles: WrappingCounter,
+
+    /// Optional receiver of the execution events of processes, used for
+    /// energy accounting.
+    energy_monitor: OptionalCell<&'static dyn EnergyMonitor>,
+
+    /// Optional cycle counter that measures the CPU time of processes.
+    cycle_counter: OptionalCell<&'static dyn CycleCounter>,
+
+    /// Optional power manager that puts the chip to sleep in place of
+    /// `Chip::sleep()`.
+    power_manager: OptionalCell<&'static dyn PowerManager<'static>>,
+
+    /// Process each hart is executing, when the kernel runs on several harts
+    /// with `smp_kernel_loop()`.
+    hart_processes: [OptionalCell<ProcessId>; MAX_HARTS],
+
+    /// Process each hart is executing in userspace, with the kernel lock
+    /// released.
+    userspace_processes: [OptionalCell<ProcessId>; MAX_HARTS],
+
+    /// Transition of the process each hart is executing, requested by another
+    /// hart while the process was in userspace.
+    deferred_transitions: [OptionalCell<DeferredTransition>; MAX_HARTS],
+
+    /// Bitmask of the harts that are sleeping in `smp_kernel_loop()`.
+    sleeping_harts: Cell<usize>,
+}
+
+/// A change of the state of a process that executes on another hart, which is
+/// applied once that hart is back in the kernel.
+#[derive(Clone, Copy)]
+pub(crate) enum DeferredTransition {
+    Stop,
+    Fault,
+    Restart(Option<u32>),
+    Terminate(Option<u32>),
 }
 
 /// Represents the different outcomes when trying to allocate a grant region
@@ -81,6 +132,13 @@ fn try_allocate_grant(driver: &dyn SyscallDriver, process: &dyn process::Process
     }
 }
 
+/// Whether an allowed buffer does not start at the `alignment` the driver
+/// requires. Empty buffers, which revoke a previous allow, are never
+/// misaligned.
+fn is_misaligned(address: usize, size: usize, alignment: usize) -> bool {
+    size > 0 && alignment > 1 && address % alignment != 0
+}
+
 impl Kernel {
     /// Create the kernel object that knows about the list of processes.
     ///
@@ -93,6 +151,86 @@ impl Kernel {
             process_identifier_max: Cell::new(0),
             grant_counter: Cell::new(0),
             grants_finalized: Cell::new(false),
+            kernel_work_count: WrappingCounter::new(),
+            context_switch_count: WrappingCounter::new(),
+            sleep_count: WrappingCounter::new(),
+            mpu_setup_cycles: WrappingCounter::new(),
+            energy_monitor: OptionalCell::empty(),
+            cycle_counter: OptionalCell::empty(),
+            power_manager: OptionalCell::empty(),
+            hart_processes: [const { OptionalCell::empty() }; MAX_HARTS],
+            userspace_processes: [const { OptionalCell::empty() }; MAX_HARTS],
+            deferred_transitions: [const { OptionalCell::empty() }; MAX_HARTS],
+            sleeping_harts: Cell::new(0),
+        }
+    }
+
+    /// Report when processes start and stop executing to `monitor`.
+    pub fn set_energy_monitor(
+        &self,
+        monitor: &'static dyn EnergyMonitor,
+        _capability: &dyn capabilities::ProcessManagementCapability,
+    ) {
+        self.energy_monitor.set(monitor);
+    }
+
+    /// Measure the CPU time of each process, reported by
+    /// [`Process::get_stats`](crate::process::Process::get_stats), with
+    /// `counter`. This starts the counter. Other users of the counter must not
+    /// reset or stop it, or the CPU time of the running process is wrong.
+    pub fn set_cycle_counter(
+        &self,
+        counter: &'static dyn CycleCounter,
+        _capability: &dyn capabilities::ProcessManagementCapability,
+    ) {
+        counter.start();
+        self.cycle_counter.set(counter);
+    }
+
+    /// Put the chip to sleep with `manager` when there is no work, so that it
+    /// enters the deepest sleep state allowed, instead of `Chip::sleep()`.
+    pub fn set_power_manager(
+        &self,
+        manager: &'static dyn PowerManager<'static>,
+        _capability: &dyn capabilities::MainLoopCapability,
+    ) {
+        self.power_manager.set(manager);
+    }
+
+    /// Run `f`, which runs `process` and returns how long it ran according to
+    /// the scheduler timer, and charge that time and the cycles it takes to
+    /// `process`.
+    fn account_run<F>(
+        &self,
+        process: &dyn process::Process,
+        f: F,
+    ) -> (process::StoppedExecutingReason, Option<u32>)
+    where
+        F: FnOnce() -> (process::StoppedExecutingReason, Option<u32>),
+    {
+        let start = self.cycle_counter.map(|counter| counter.count());
+        let result = f();
+        // Some counters, such as the DWT of Cortex-M, are only 32 bits wide. A
+        // run is much shorter than their period, so the difference of the low
+        // 32 bits is the duration of the run.
+        let cycles = self
+            .cycle_counter
+            .get()
+            .zip(start)
+            .map_or(0, |(counter, start)| {
+                (counter.count() as u32).wrapping_sub(start as u32)
+            });
+        process.add_run(result.1.unwrap_or(0), cycles as u64);
+        result
+    }
+
+    /// Get the number of times events happened in the main loop since boot.
+    pub fn loop_counters(&self) -> KernelLoopCounters {
+        KernelLoopCounters {
+            kernel_work: self.kernel_work_count.get(),
+            context_switches: self.context_switch_count.get(),
+            sleeps: self.sleep_count.get(),
+            mpu_setup_cycles: self.mpu_setup_cycles.get(),
         }
     }
 
@@ -341,6 +479,24 @@ impl Kernel {
         }
     }
 
+    /// Cause the process `processid` to fault.
+    ///
+    /// This is `hardfault_all_apps()` for a single process: the process
+    /// enters the state as if it had crashed, and is restarted if it is
+    /// configured to be. Returns `INVAL` if the process does not exist.
+    ///
+    /// Only callers with the `ProcessManagementCapability` can call this
+    /// function.
+    pub fn hardfault_app<C: capabilities::ProcessManagementCapability>(
+        &self,
+        processid: ProcessId,
+        _c: &C,
+    ) -> Result<(), ErrorCode> {
+        self.get_process(processid)
+            .map(|process| process.set_fault_state())
+            .ok_or(ErrorCode::INVAL)
+    }
+
     /// Perform one iteration of the core Tock kernel loop.
     ///
     /// This function is responsible for three main operations:
@@ -378,6 +534,7 @@ impl Kernel {
                     // Execute kernel work. This includes handling
                     // interrupts and is how code in the chips/ and capsules
                     // crates is able to execute.
+                    self.kernel_work_count.increment();
                     scheduler.execute_kernel_work(chip);
                 }
                 false => {
@@ -385,8 +542,21 @@ impl Kernel {
                     match scheduler.next() {
                         SchedulingDecision::RunProcess((processid, timeslice_us)) => {
                             self.process_map_or((), processid, |process| {
-                                let (reason, time_executed) =
-                                    self.do_process(resources, chip, process, ipc, timeslice_us);
+                                self.energy_monitor
+                                    .map(|monitor| monitor.process_started(processid));
+                                let (reason, time_executed) = self.account_run(process, || {
+                                    self.do_process(
+                                        resources,
+                                        chip,
+                                        process,
+                                        ipc,
+                                        timeslice_us,
+                                        resources.scheduler_timer(),
+                                        None,
+                                    )
+                                });
+                                self.energy_monitor
+                                    .map(|monitor| monitor.process_stopped(processid));
                                 scheduler.result(reason, time_executed);
                             });
                         }
@@ -408,8 +578,14 @@ impl Kernel {
                                     // from sleep.
                                     if !chip.has_pending_interrupts() && !DeferredCall::has_tasks()
                                     {
+                                        self.sleep_count.increment();
                                         resources.watchdog().suspend();
-                                        chip.sleep();
+                                        match self.power_manager.get() {
+                                            Some(manager) => {
+                                                manager.sleep(&|| chip.sleep());
+                                            }
+                                            None => chip.sleep(),
+                                        }
                                         resources.watchdog().resume();
                                     }
                                 });
@@ -440,6 +616,204 @@ impl Kernel {
         }
     }
 
+    /// Main loop of the OS on chips with multiple harts.
+    ///
+    /// This must be called on every hart that runs the kernel, hart 0 last,
+    /// once the board is set up. The harts share the processes and the
+    /// scheduler, and take turns using them under the kernel lock of the
+    /// chip (see [`SmpChip`]). Hart 0 does all kernel work, and then runs
+    /// processes like the other harts. Harts that have nothing to do sleep
+    /// until another hart wakes them, which happens every time a hart leaves
+    /// the kernel lock after running kernel work or a process, since that
+    /// work may have made a process ready.
+    ///
+    /// The scheduler may choose a process which is already executing on
+    /// another hart. It is then told that process has no work left, and asked
+    /// again, so that it moves on to another process.
+    ///
+    /// The lock is released while processes execute. A process that executes
+    /// on one hart is not stopped, faulted, restarted or terminated from
+    /// another hart under it: the change is deferred until the hart it
+    /// executes on is back in the kernel, which then stops running it and
+    /// applies the change.
+    pub fn smp_kernel_loop<KR: KernelResources<C>, C: SmpChip, const NUM_PROCS: u8>(
+        &self,
+        resources: &KR,
+        chip: &C,
+        ipc: Option<&ipc::IPC<NUM_PROCS>>,
+        _capability: &dyn capabilities::MainLoopCapability,
+    ) -> ! {
+        let hart = chip.current_hart();
+        let lock = chip.kernel_lock();
+        if hart == 0 {
+            lock.lock();
+            resources.watchdog().setup();
+            // Before we begin, verify that deferred calls were soundly setup.
+            DeferredCall::verify_setup();
+            lock.unlock();
+        }
+        loop {
+            lock.lock();
+            let sleep = self.smp_kernel_loop_operation(resources, chip, ipc, hart, lock);
+            if !sleep {
+                self.notify_sleeping_harts(chip, hart);
+            }
+            lock.unlock();
+
+            if sleep {
+                unsafe {
+                    chip.atomic(|| {
+                        lock.lock();
+                        // As in `kernel_loop_operation()`, hart 0 cannot
+                        // sleep if an interrupt became pending after the
+                        // scheduler decided to sleep.
+                        let idle = hart != 0
+                            || (!chip.has_pending_interrupts() && !DeferredCall::has_tasks());
+                        if idle {
+                            self.sleeping_harts
+                                .set(self.sleeping_harts.get() | (1 << hart));
+                        }
+                        lock.unlock();
+
+                        if idle && hart == 0 {
+                            self.sleep_count.increment();
+                            resources.watchdog().suspend();
+                            chip.sleep();
+                            resources.watchdog().resume();
+                        } else if idle {
+                            chip.wait_for_notification();
+                        }
+                    });
+                }
+            }
+        }
+    }
+
+    /// Perform one iteration of `smp_kernel_loop()` on `hart`, with the kernel
+    /// lock held. Returns whether the hart should sleep.
+    fn smp_kernel_loop_operation<KR: KernelResources<C>, C: SmpChip, const NUM_PROCS: u8>(
+        &self,
+        resources: &KR,
+        chip: &C,
+        ipc: Option<&ipc::IPC<NUM_PROCS>>,
+        hart: usize,
+        lock: &dyn HartLock,
+    ) -> bool {
+        let scheduler = resources.scheduler();
+        self.sleeping_harts
+            .set(self.sleeping_harts.get() & !(1 << hart));
+
+        if hart == 0 {
+            resources.watchdog().tickle();
+            unsafe {
+                if scheduler.do_kernel_work_now(chip) {
+                    self.kernel_work_count.increment();
+                    scheduler.execute_kernel_work(chip);
+                    return false;
+                }
+            }
+        }
+
+        // Skip the processes running on other harts, at most once each.
+        for _ in 0..=self.processes.len() {
+            match scheduler.next() {
+                SchedulingDecision::RunProcess((processid, timeslice_us)) => {
+                    if self
+                        .hart_processes
+                        .iter()
+                        .any(|running| running.contains(&processid))
+                    {
+                        scheduler.result(process::StoppedExecutingReason::NoWorkLeft, None);
+                        continue;
+                    }
+
+                    let scheduler_timer = if hart == 0 {
+                        resources.scheduler_timer() as &dyn SchedulerTimer
+                    } else {
+                        chip.hart_scheduler_timer()
+                    };
+                    self.hart_processes[hart].set(processid);
+                    self.process_map_or((), processid, |process| {
+                        self.energy_monitor
+                            .map(|monitor| monitor.process_started(processid));
+                        let (reason, time_executed) = self.account_run(process, || {
+                            self.do_process(
+                                resources,
+                                chip,
+                                process,
+                                ipc,
+                                timeslice_us,
+                                scheduler_timer,
+                                Some((hart, lock)),
+                            )
+                        });
+                        self.apply_deferred_transition(hart, process);
+                        self.energy_monitor
+                            .map(|monitor| monitor.process_stopped(processid));
+                        scheduler.result(reason, time_executed);
+                    });
+                    self.hart_processes[hart].clear();
+                    return false;
+                }
+                SchedulingDecision::TrySleep => return true,
+            }
+        }
+        true
+    }
+
+    /// Defer `transition` of `processid` if it is executing in userspace on
+    /// another hart, which must not have its state changed under it. Returns
+    /// whether the transition was deferred.
+    pub(crate) fn defer_transition(
+        &self,
+        processid: ProcessId,
+        transition: DeferredTransition,
+    ) -> bool {
+        let hart = self
+            .userspace_processes
+            .iter()
+            .position(|executing| executing.contains(&processid));
+        match hart {
+            Some(hart) => {
+                // Stopping the process does not replace a pending fault,
+                // restart or termination.
+                if !matches!(transition, DeferredTransition::Stop)
+                    || self.deferred_transitions[hart].is_none()
+                {
+                    self.deferred_transitions[hart].set(transition);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Apply the transition of `process`, which `hart` executed, that another
+    /// hart requested in the meantime.
+    fn apply_deferred_transition(&self, hart: usize, process: &dyn process::Process) {
+        match self.deferred_transitions[hart].take() {
+            Some(DeferredTransition::Stop) => process.stop(),
+            Some(DeferredTransition::Fault) => process.set_fault_state(),
+            Some(DeferredTransition::Restart(completion_code)) => {
+                process.try_restart(completion_code)
+            }
+            Some(DeferredTransition::Terminate(completion_code)) => {
+                process.terminate(completion_code)
+            }
+            None => {}
+        }
+    }
+
+    /// Wake up the harts other than `hart` that sleep in `smp_kernel_loop()`.
+    fn notify_sleeping_harts<C: SmpChip>(&self, chip: &C, hart: usize) {
+        let sleeping = self.sleeping_harts.get() & !(1 << hart);
+        for other in 0..chip.num_harts().min(MAX_HARTS) {
+            if sleeping & (1 << other) != 0 {
+                chip.notify_hart(other);
+            }
+        }
+    }
+
     /// Transfer control from the kernel to a userspace process.
     ///
     /// This function is called by the main kernel loop to run userspace code.
@@ -471,6 +845,11 @@ impl Kernel {
     /// cooperatively). Notably, time spent in this function by the kernel,
     /// executing system calls or merely setting up the switch to/from
     /// userspace, is charged to the process.
+    ///
+    /// `scheduler_timer` is the scheduler timer of the hart the process runs
+    /// on. If `smp` is given, with that hart and the kernel lock, the lock is
+    /// released while the process executes, so that other harts can run the
+    /// kernel in the meantime.
     fn do_process<KR: KernelResources<C>, C: Chip, const NUM_PROCS: u8>(
         &self,
         resources: &KR,
@@ -478,6 +857,8 @@ impl Kernel {
         process: &dyn process::Process,
         ipc: Option<&crate::ipc::IPC<NUM_PROCS>>,
         timeslice_us: Option<NonZeroU32>,
+        scheduler_timer: &dyn SchedulerTimer,
+        smp: Option<(usize, &dyn HartLock)>,
     ) -> (process::StoppedExecutingReason, Option<u32>) {
         // We must use a dummy scheduler timer if the process should be executed
         // without any timeslice restrictions. Note, a chip may not provide a
@@ -485,7 +866,7 @@ impl Kernel {
         let scheduler_timer: &dyn SchedulerTimer = if timeslice_us.is_none() {
             &() // dummy timer, no preemption
         } else {
-            resources.scheduler_timer()
+            scheduler_timer
         };
 
         // Clear the scheduler timer and then start the counter. This starts the
@@ -508,6 +889,13 @@ impl Kernel {
         // no longer wants to execute this process or if it exceeds its
         // timeslice.
         loop {
+            // Another hart changed the state of the process while it executed.
+            // Stop running it, so that the caller applies the change.
+            if smp.is_some_and(|(hart, _)| self.deferred_transitions[hart].is_some()) {
+                return_reason = process::StoppedExecutingReason::KernelPreemption;
+                break;
+            }
+
             let stop_running = match scheduler_timer.get_remaining_us() {
                 Some(us) => us.get() <= MIN_QUANTA_THRESHOLD_US,
                 None => true,
@@ -548,10 +936,24 @@ impl Kernel {
                     resources
                         .context_switch_callback()
                         .context_switch_hook(process);
+                    self.context_switch_count.increment();
+                    let start = self.cycle_counter.map(|counter| counter.count());
                     process.setup_mpu();
+                    if let (Some(counter), Some(start)) = (self.cycle_counter.get(), start) {
+                        self.mpu_setup_cycles
+                            .add((counter.count() as u32).wrapping_sub(start as u32));
+                    }
                     chip.mpu().enable_app_mpu();
                     scheduler_timer.arm();
+                    if let Some((hart, lock)) = smp {
+                        self.userspace_processes[hart].set(process.processid());
+                        lock.unlock();
+                    }
                     let context_switch_reason = process.switch_to();
+                    if let Some((hart, lock)) = smp {
+                        lock.lock();
+                        self.userspace_processes[hart].clear();
+                    }
                     scheduler_timer.disarm();
                     chip.mpu().disable_app_mpu();
 
@@ -727,6 +1129,108 @@ impl Kernel {
         (return_reason, time_executed_us)
     }
 
+    /// Run the commands of a CommandBatch system call in order, until one
+    /// fails.
+    ///
+    /// The whole batch is checked before any command runs: the driver must
+    /// opt in with [`SyscallDriver::allow_command_batch`], the batch must hold
+    /// between 1 and [`MAX_COMMAND_BATCH_LEN`] commands, and be word-aligned
+    /// in memory the process can write. Each command is then filtered as a
+    /// Command system call would be, and overwritten with its return value.
+    /// Returns the number of commands that succeeded.
+    fn handle_command_batch<KR: KernelResources<C>, C: Chip>(
+        &self,
+        resources: &KR,
+        process: &dyn process::Process,
+        driver: Option<&dyn SyscallDriver>,
+        driver_number: usize,
+        batch_address: *mut u8,
+        batch_len: usize,
+    ) -> SyscallReturn {
+        let Some(driver) = driver else {
+            return SyscallReturn::Failure(ErrorCode::NODEVICE);
+        };
+        if !driver.allow_command_batch() {
+            return SyscallReturn::Failure(ErrorCode::NOSUPPORT);
+        }
+        if batch_len == 0 || batch_len > MAX_COMMAND_BATCH_LEN {
+            return SyscallReturn::Failure(ErrorCode::SIZE);
+        }
+        if batch_address as usize % 4 != 0 {
+            return SyscallReturn::Failure(ErrorCode::ALIGN);
+        }
+        let size = batch_len * COMMAND_BATCH_ENTRY_SIZE;
+        // This ensures that the batch is in the process-accessible memory.
+        let buffer = match process.build_readwrite_process_buffer(batch_address, size) {
+            Ok(buffer) => buffer,
+            Err(err) => return SyscallReturn::Failure(err),
+        };
+
+        let mut batch = [0; MAX_COMMAND_BATCH_LEN * COMMAND_BATCH_ENTRY_SIZE];
+        if buffer
+            .enter(|slice| slice.copy_to_slice(&mut batch[..size]))
+            .is_err()
+        {
+            return SyscallReturn::Failure(ErrorCode::FAIL);
+        }
+
+        let word = |entry: &[u8], index: usize| {
+            let mut bytes = [0; 4];
+            bytes.copy_from_slice(&entry[index * 4..(index + 1) * 4]);
+            u32::from_ne_bytes(bytes)
+        };
+        let mut executed = 0;
+        let mut succeeded = 0;
+        for entry in batch[..size].chunks_exact_mut(COMMAND_BATCH_ENTRY_SIZE) {
+            let command = Syscall::Command {
+                driver_number,
+                subdriver_number: word(entry, 0) as usize,
+                arg0: word(entry, 1) as usize,
+                arg1: word(entry, 2) as usize,
+            };
+            let res = match resources.syscall_filter().filter_syscall(process, &command) {
+                Ok(()) => SyscallReturn::from_command_return(driver.command(
+                    word(entry, 0) as usize,
+                    word(entry, 1) as usize,
+                    word(entry, 2) as usize,
+                    process.processid(),
+                )),
+                Err(err) => SyscallReturn::Failure(err),
+            };
+
+            let mut registers = [0u32; 4];
+            let [a0, a1, a2, a3] = &mut registers;
+            arch_helpers::encode_syscall_return_trd104(
+                &arch_helpers::TRD104SyscallReturn::from_syscall_return(res),
+                a0,
+                a1,
+                a2,
+                a3,
+            );
+            for (bytes, register) in entry.chunks_exact_mut(4).zip(registers) {
+                bytes.copy_from_slice(&register.to_ne_bytes());
+            }
+
+            executed += 1;
+            match res {
+                SyscallReturn::Failure(_)
+                | SyscallReturn::FailureU32(..)
+                | SyscallReturn::FailureU32U32(..)
+                | SyscallReturn::FailureU64(..) => break,
+                _ => succeeded += 1,
+            }
+        }
+
+        // Only the commands that ran are overwritten. This cannot fail unless
+        // a command terminated the process, in which case the results do not
+        // matter anymore.
+        let _ = buffer.mut_enter(|slice| {
+            slice[..executed * COMMAND_BATCH_ENTRY_SIZE]
+                .copy_from_slice(&batch[..executed * COMMAND_BATCH_ENTRY_SIZE]);
+        });
+        SyscallReturn::SuccessU32(succeeded)
+    }
+
     /// Method to invoke a system call on a particular process. Applies the
     /// kernel system call filtering policy (if any). Handles `Yield` and
     /// `Exit`, dispatches `Memop` to `memop::memop`, and dispatches peripheral
@@ -860,7 +1364,8 @@ impl Kernel {
             | Syscall::Command { driver_number, .. }
             | Syscall::ReadWriteAllow { driver_number, .. }
             | Syscall::UserspaceReadableAllow { driver_number, .. }
-            | Syscall::ReadOnlyAllow { driver_number, .. } => {
+            | Syscall::ReadOnlyAllow { driver_number, .. }
+            | Syscall::CommandBatch { driver_number, .. } => {
                 resources
                 .syscall_driver_lookup()
                 .with_driver(driver_number, |driver| match syscall {
@@ -1067,6 +1572,21 @@ impl Kernel {
                         allow_size,
                     } => {
                         let res = match driver {
+                            Some(driver)
+                                if is_misaligned(
+                                    allow_address as usize,
+                                    allow_size,
+                                    driver.allow_readwrite_alignment(subdriver_number),
+                                ) =>
+                            {
+                                // The driver cannot use this buffer, reject it before
+                                // it is stored in the grant.
+                                SyscallReturn::AllowReadWriteFailure(
+                                    ErrorCode::ALIGN,
+                                    allow_address,
+                                    allow_size,
+                                )
+                            }
                             Some(driver) => {
                                 // Try to create an appropriate
                                 // [`ReadWriteProcessBuffer`]. This method will
@@ -1192,6 +1712,21 @@ impl Kernel {
                         allow_size,
                     } => {
                         let res = match driver {
+                            Some(d)
+                                if is_misaligned(
+                                    allow_address as usize,
+                                    allow_size,
+                                    d.allow_userspace_readable_alignment(subdriver_number),
+                                ) =>
+                            {
+                                // The driver cannot use this buffer, reject it before
+                                // it is stored in the grant.
+                                SyscallReturn::UserspaceReadableAllowFailure(
+                                    ErrorCode::ALIGN,
+                                    allow_address,
+                                    allow_size,
+                                )
+                            }
                             Some(d) => {
                                 // Try to create an appropriate
                                 // [`UserspaceReadableProcessBuffer`]. This
@@ -1272,6 +1807,21 @@ impl Kernel {
                         allow_size,
                     } => {
                         let res = match driver {
+                            Some(driver)
+                                if is_misaligned(
+                                    allow_address as usize,
+                                    allow_size,
+                                    driver.allow_readonly_alignment(subdriver_number),
+                                ) =>
+                            {
+                                // The driver cannot use this buffer, reject it before
+                                // it is stored in the grant.
+                                SyscallReturn::AllowReadOnlyFailure(
+                                    ErrorCode::ALIGN,
+                                    allow_address,
+                                    allow_size,
+                                )
+                            }
                             Some(driver) => {
                                 // Try to create an appropriate
                                 // [`ReadOnlyProcessBuffer`]. This method will
@@ -1391,6 +1941,32 @@ impl Kernel {
 
                         process.set_syscall_return_value(res);
                     }
+                    Syscall::CommandBatch {
+                        driver_number,
+                        batch_address,
+                        batch_len,
+                    } => {
+                        let res = self.handle_command_batch(
+                            resources,
+                            process,
+                            driver,
+                            driver_number,
+                            batch_address,
+                            batch_len,
+                        );
+
+                        if config::CONFIG.trace_syscalls {
+                            debug!(
+                                "[{:?}] cmd batch({:#x}, @{:#x}, {}) = {:?}",
+                                process.processid(),
+                                driver_number,
+                                batch_address as usize,
+                                batch_len,
+                                res,
+                            );
+                        }
+                        process.set_syscall_return_value(res);
+                    }
                     Syscall::Yield { .. }
                     | Syscall::Exit { .. }
                     | Syscall::Memop { .. } => {
@@ -1435,3 +2011,82 @@ impl Kernel {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utilities::arch_helpers::{
+        encode_syscall_return_trd104, TRD104SyscallReturn, TRD104SyscallReturnVariant,
+    };
+
+    /// A driver that needs word aligned buffers for read-write allow 0.
+    struct AlignedDriver;
+
+    impl SyscallDriver for AlignedDriver {
+        fn command(&self, _: usize, _: usize, _: usize, _: ProcessId) -> CommandReturn {
+            CommandReturn::failure(ErrorCode::NOSUPPORT)
+        }
+
+        fn allow_readwrite_alignment(&self, allow_num: usize) -> usize {
+            if allow_num == 0 {
+                4
+            } else {
+                1
+            }
+        }
+
+        fn allocate_grant(&self, _: ProcessId) -> Result<(), process::Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn misaligned_allows() {
+        let driver: &dyn SyscallDriver = &AlignedDriver;
+        let alignment = driver.allow_readwrite_alignment(0);
+        assert!(is_misaligned(0x2000_0002, 8, alignment));
+        assert!(!is_misaligned(0x2000_0004, 8, alignment));
+        // An empty buffer revokes the allow, wherever it is.
+        assert!(!is_misaligned(0x2000_0002, 0, alignment));
+        // Other allows, and the other kinds of allow, accept any buffer.
+        assert!(!is_misaligned(
+            0x2000_0001,
+            8,
+            driver.allow_readwrite_alignment(1)
+        ));
+        assert!(!is_misaligned(
+            0x2000_0001,
+            8,
+            driver.allow_readonly_alignment(0)
+        ));
+        assert!(!is_misaligned(
+            0x2000_0001,
+            8,
+            driver.allow_userspace_readable_alignment(0)
+        ));
+    }
+
+    /// The failures for misaligned buffers give error code 14 to the process,
+    /// and the buffer back.
+    #[test]
+    fn align_failure_encoding() {
+        let address = 0x2000_0002 as *mut u8;
+        for failure in [
+            SyscallReturn::AllowReadWriteFailure(ErrorCode::ALIGN, address, 8),
+            SyscallReturn::AllowReadOnlyFailure(ErrorCode::ALIGN, address, 8),
+            SyscallReturn::UserspaceReadableAllowFailure(ErrorCode::ALIGN, address, 8),
+        ] {
+            let (mut a0, mut a1, mut a2, mut a3) = (0, 0, 0, 0);
+            encode_syscall_return_trd104(
+                &TRD104SyscallReturn::from_syscall_return(failure),
+                &mut a0,
+                &mut a1,
+                &mut a2,
+                &mut a3,
+            );
+            assert_eq!(a0, TRD104SyscallReturnVariant::FailureU32U32 as u32);
+            assert_eq!(a1, 14);
+            assert_eq!((a2, a3), (0x2000_0002, 8));
+        }
+    }
+}
@@ -109,6 +109,7 @@ pub mod collections;
 pub mod component;
 pub mod debug;
 pub mod deferred_call;
+pub mod energy;
 pub mod errorcode;
 pub mod grant;
 pub mod hil;
@@ -128,10 +129,13 @@ mod config;
 mod kernel;
 mod memop;
 mod process_binary;
+mod process_checkpoint;
+mod process_install;
 mod process_loading;
 mod process_policies;
 mod process_printer;
 mod process_standard;
+mod process_swap;
 mod syscall_driver;
 
 // Core resources exposed as `kernel::Type`.
@@ -42,6 +42,20 @@ use crate::ErrorCode;
 ///   where the app has put the start of its heap. This is not strictly
 ///   necessary for correct operation, but allows for better debugging if the
 ///   app crashes.
+/// - `12`: Get the stack usage of the app. Returns how many bytes of stack the
+///   app has used at most, and how many bytes are left between the lowest
+///   stack pointer seen and the start of its RAM. Requires the app to have
+///   specified the start of its stack, and returns `FAIL` if the kernel does
+///   not know the stack location.
+/// - `13`: Get the heap usage of the app. Returns the number of bytes between
+///   the start of the heap and the program break, and the number of bytes the
+///   program break can still grow before reaching the grant region. Returns
+///   `FAIL` if the app did not specify the start of its heap.
+/// - `14`: Get the most recent fault of the app, which is kept when the app
+///   is restarted. Returns the number of faults, the stack pointer when the
+///   app faulted (0 if unknown) and the last syscall before the fault, with
+///   the syscall class in bits 0-7 and the driver number in bits 8-31 (0xFF
+///   if unknown). Returns `FAIL` if the app never faulted.
 pub(crate) fn memop(process: &dyn Process, op_type: usize, r1: usize) -> SyscallReturn {
     match op_type {
         // Op Type 0: BRK
@@ -156,6 +170,42 @@ pub(crate) fn memop(process: &dyn Process, op_type: usize, r1: usize) -> Syscall
             SyscallReturn::Success
         }
 
+        // Op Type 12: Stack usage.
+        12 => {
+            let addresses = process.get_addresses();
+            match (addresses.sram_stack_top, addresses.sram_stack_bottom) {
+                (Some(top), Some(bottom)) => SyscallReturn::SuccessU32U32(
+                    top.saturating_sub(bottom) as u32,
+                    bottom.saturating_sub(addresses.sram_start) as u32,
+                ),
+                _ => SyscallReturn::Failure(ErrorCode::FAIL),
+            }
+        }
+
+        // Op Type 13: Heap usage.
+        13 => {
+            let addresses = process.get_addresses();
+            match addresses.sram_heap_start {
+                Some(heap_start) => SyscallReturn::SuccessU32U32(
+                    addresses.sram_app_brk.saturating_sub(heap_start) as u32,
+                    (addresses.sram_grant_start - addresses.sram_app_brk) as u32,
+                ),
+                None => SyscallReturn::Failure(ErrorCode::FAIL),
+            }
+        }
+
+        // Op Type 14: Last fault.
+        14 => match process.get_last_fault() {
+            Some(fault) => SyscallReturn::SuccessU32U32U32(
+                fault.count as u32,
+                fault.stack_pointer.unwrap_or(0) as u32,
+                fault.last_syscall.map_or(0xFF, |syscall| {
+                    (syscall.class() as u32) | ((syscall.driver_number().unwrap_or(0) as u32) << 8)
+                }),
+            ),
+            None => SyscallReturn::Failure(ErrorCode::FAIL),
+        },
+
         _ => SyscallReturn::Failure(ErrorCode::NOSUPPORT),
     }
 }
@@ -0,0 +1,280 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Kernel attributes, which describe the kernel image to host tools.
+//!
+//! The attributes are stored at the end of the kernel flash region by the
+//! linker script (`boards/build_scripts/tock_kernel_layout.ld`), so that tools
+//! like tockloader can find them on a device without knowing how the kernel
+//! was built. They are a list of TLVs which are stored backwards: the end of
+//! the region holds the `TOCK` sentinel and a version, and each TLV ends with
+//! its type and the length of its value, both 16-bit little-endian values.
+//!
+//! The linker script records the kernel flash, app memory and storage
+//! regions. A board adds its name and the kernel version with
+//! [`kernel_attributes`](crate::kernel_attributes):
+//!
+//! ```rust,ignore
+//! kernel::kernel_attributes!(board: "nrf52840dk");
+//! ```
+//!
+//! [`Attributes`] parses the region again, for example to print it on the
+//! process console.
+
+use core::fmt;
+
+/// Start and size of the RAM available for processes.
+pub const APP_MEMORY: u16 = 0x0101;
+/// Start and size of the kernel binary in flash.
+pub const KERNEL_FLASH: u16 = 0x0102;
+/// Alignment constraints of the app memory. See
+/// [`AppMemoryAlignment`](crate::platform::mpu::AppMemoryAlignment).
+pub const APP_MEMORY_ALIGNMENT: u16 = 0x0103;
+/// Start and size of the kernel non-volatile storage region in flash.
+pub const STORAGE: u16 = 0x0104;
+/// Name of the board.
+pub const BOARD_NAME: u16 = 0x0105;
+/// Major and minor version of the kernel, followed by the build version.
+pub const KERNEL_VERSION: u16 = 0x0106;
+/// SHA-256 hash of the kernel code and read-only data, written by the
+/// flashing tool. It is all ones until a tool writes it.
+pub const KERNEL_HASH: u16 = 0x0107;
+
+/// Version of the attributes region format.
+pub const VERSION: u8 = 1;
+
+/// Size of a TLV whose value is `value_len` bytes long. Values are padded to a
+/// multiple of four bytes.
+pub const fn tlv_len(value_len: usize) -> usize {
+    value_len.next_multiple_of(4) + 4
+}
+
+/// Build a TLV of type `attribute_type` whose value is the concatenation of
+/// `parts`, padded with zeros. `N` must be the [`tlv_len`] of the value.
+pub const fn tlv<const N: usize>(attribute_type: u16, parts: &[&[u8]]) -> [u8; N] {
+    let mut tlv = [0; N];
+    let value_len = N - 4;
+    let mut offset = 0;
+    let mut part = 0;
+    while part < parts.len() {
+        let mut i = 0;
+        while i < parts[part].len() {
+            tlv[offset] = parts[part][i];
+            offset += 1;
+            i += 1;
+        }
+        part += 1;
+    }
+    let attribute_type = attribute_type.to_le_bytes();
+    let length = (value_len as u16).to_le_bytes();
+    tlv[value_len] = attribute_type[0];
+    tlv[value_len + 1] = attribute_type[1];
+    tlv[value_len + 2] = length[0];
+    tlv[value_len + 3] = length[1];
+    tlv
+}
+
+/// Record the board name and the kernel version in the kernel attributes.
+///
+/// This must be called once, in the board crate. The build version is the
+/// `TOCK_KERNEL_VERSION` environment variable set by the build system.
+#[macro_export]
+macro_rules! kernel_attributes {
+    (board: $board:expr $(,)?) => {
+        #[used]
+        #[link_section = ".attributes.board_name"]
+        static KERNEL_ATTRIBUTE_BOARD_NAME: [u8; $crate::platform::attributes::tlv_len(
+            $board.len(),
+        )] = $crate::platform::attributes::tlv(
+            $crate::platform::attributes::BOARD_NAME,
+            &[$board.as_bytes()],
+        );
+
+        const KERNEL_ATTRIBUTE_BUILD_VERSION: &str = match option_env!("TOCK_KERNEL_VERSION") {
+            Some(version) => version,
+            None => "unknown",
+        };
+
+        #[used]
+        #[link_section = ".attributes.kernel_version"]
+        static KERNEL_ATTRIBUTE_KERNEL_VERSION: [u8; $crate::platform::attributes::tlv_len(
+            4 + KERNEL_ATTRIBUTE_BUILD_VERSION.len(),
+        )] = $crate::platform::attributes::tlv(
+            $crate::platform::attributes::KERNEL_VERSION,
+            &[
+                &$crate::KERNEL_MAJOR_VERSION.to_le_bytes(),
+                &$crate::KERNEL_MINOR_VERSION.to_le_bytes(),
+                KERNEL_ATTRIBUTE_BUILD_VERSION.as_bytes(),
+            ],
+        );
+    };
+}
+
+/// One kernel attribute.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Attribute<'a> {
+    pub attribute_type: u16,
+    pub value: &'a [u8],
+}
+
+impl Attribute<'_> {
+    fn u32_at(&self, offset: usize) -> Option<u32> {
+        self.value
+            .get(offset..offset + 4)
+            .and_then(|bytes| bytes.try_into().ok())
+            .map(u32::from_le_bytes)
+    }
+
+    fn u16_at(&self, offset: usize) -> Option<u16> {
+        self.value
+            .get(offset..offset + 2)
+            .and_then(|bytes| bytes.try_into().ok())
+            .map(u16::from_le_bytes)
+    }
+
+    // A string value without its padding, or "?" if it is not valid UTF-8.
+    fn str_from(&self, offset: usize) -> &str {
+        let bytes = self.value.get(offset..).unwrap_or(&[]);
+        let len = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+        core::str::from_utf8(&bytes[..len]).unwrap_or("?")
+    }
+}
+
+impl fmt::Display for Attribute<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let region = |f: &mut fmt::Formatter<'_>, name: &str| match (self.u32_at(0), self.u32_at(4))
+        {
+            (Some(start), Some(size)) => write!(f, "{}: {:#010x}, {} bytes", name, start, size),
+            _ => write!(f, "{}: invalid", name),
+        };
+        match self.attribute_type {
+            APP_MEMORY => region(f, "App memory"),
+            KERNEL_FLASH => region(f, "Kernel flash"),
+            STORAGE => region(f, "Storage"),
+            APP_MEMORY_ALIGNMENT => match (self.u32_at(0), self.u32_at(4)) {
+                (Some(alignment), Some(regions)) => write!(
+                    f,
+                    "App memory alignment: {} bytes, {} regions",
+                    alignment, regions
+                ),
+                _ => write!(f, "App memory alignment: invalid"),
+            },
+            BOARD_NAME => write!(f, "Board: {}", self.str_from(0)),
+            KERNEL_VERSION => match (self.u16_at(0), self.u16_at(2)) {
+                (Some(major), Some(minor)) => write!(
+                    f,
+                    "Kernel version: {}.{} (build {})",
+                    major,
+                    minor,
+                    self.str_from(4)
+                ),
+                _ => write!(f, "Kernel version: invalid"),
+            },
+            KERNEL_HASH if self.value.iter().all(|b| *b == 0xff) => {
+                write!(f, "Kernel hash: not provisioned")
+            }
+            KERNEL_HASH => {
+                write!(f, "Kernel hash: ")?;
+                self.value.iter().try_for_each(|b| write!(f, "{:02x}", b))
+            }
+            attribute_type => write!(
+                f,
+                "Unknown attribute {:#06x}: {} bytes",
+                attribute_type,
+                self.value.len()
+            ),
+        }
+    }
+}
+
+/// The attributes in a kernel attributes region.
+#[derive(Clone)]
+pub struct Attributes<'a> {
+    remaining: &'a [u8],
+}
+
+impl<'a> Attributes<'a> {
+    /// Parse the attributes in `region`, which must end with the sentinel.
+    /// Returns `None` if the sentinel or the version do not match.
+    pub fn new(region: &'a [u8]) -> Option<Self> {
+        let len = region.len();
+        if len < 8 || &region[len - 4..] != b"TOCK" || region[len - 5] != VERSION {
+            return None;
+        }
+        Some(Self {
+            remaining: &region[..len - 8],
+        })
+    }
+}
+
+impl<'a> Iterator for Attributes<'a> {
+    type Item = Attribute<'a>;
+
+    fn next(&mut self) -> Option<Attribute<'a>> {
+        let len = self.remaining.len();
+        if len < 4 {
+            return None;
+        }
+        let attribute_type = u16::from_le_bytes([self.remaining[len - 4], self.remaining[len - 3]]);
+        let value_len = u16::from_le_bytes([self.remaining[len - 2], self.remaining[len - 1]]);
+        let value_start = (len - 4).checked_sub(value_len as usize);
+        match value_start {
+            Some(value_start) => {
+                let value = &self.remaining[value_start..len - 4];
+                self.remaining = &self.remaining[..value_start];
+                Some(Attribute {
+                    attribute_type,
+                    value,
+                })
+            }
+            None => {
+                // A corrupted length ends the list.
+                self.remaining = &[];
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tlv_is_padded() {
+        let tlv: [u8; tlv_len(5)] = tlv(BOARD_NAME, &[b"hail", b"!"]);
+        assert_eq!(tlv, *b"hail!\0\0\0\x05\x01\x08\x00");
+    }
+
+    #[test]
+    fn test_attributes_are_parsed_backwards() {
+        let name: [u8; tlv_len(4)] = tlv(BOARD_NAME, &[b"imix"]);
+        let version: [u8; tlv_len(4)] = tlv(KERNEL_VERSION, &[&[2, 0], &[1, 0]]);
+        let mut region = [0; 8 + 8 + 8];
+        region[..8].copy_from_slice(&name);
+        region[8..16].copy_from_slice(&version);
+        region[16..].copy_from_slice(&[0, 0, 0, VERSION, b'T', b'O', b'C', b'K']);
+
+        let mut attributes = Attributes::new(&region).unwrap();
+        let first = attributes.next().unwrap();
+        assert_eq!(first.attribute_type, KERNEL_VERSION);
+        assert_eq!(first.u16_at(0), Some(2));
+        assert_eq!(first.u16_at(2), Some(1));
+        let second = attributes.next().unwrap();
+        assert_eq!(second.attribute_type, BOARD_NAME);
+        assert_eq!(second.str_from(0), "imix");
+        assert_eq!(attributes.next(), None);
+    }
+
+    #[test]
+    fn test_invalid_region() {
+        assert!(Attributes::new(b"KCOT").is_none());
+        assert!(Attributes::new(&[0, 0, 0, 2, b'T', b'O', b'C', b'K']).is_none());
+        // A length longer than the region ends the list.
+        let region = [
+            0, 0, 0x05, 0x01, 0xFF, 0x00, 0, 0, 0, 1, b'T', b'O', b'C', b'K',
+        ];
+        assert_eq!(Attributes::new(&region).unwrap().next(), None);
+    }
+}
@@ -5,6 +5,7 @@
 //! Interfaces for implementing microcontrollers in Tock.
 
 use crate::platform::mpu;
+use crate::platform::scheduler_timer::SchedulerTimer;
 use crate::syscall;
 use core::fmt::Write;
 
@@ -70,6 +71,93 @@ pub trait Chip {
     unsafe fn print_state(&self, writer: &mut dyn Write);
 }
 
+/// Maximum number of harts (cores) the kernel can run on.
+pub const MAX_HARTS: usize = 8;
+
+/// Lock shared by the harts of a chip.
+///
+/// The lock is not reentrant: a hart that holds it must not try to lock it
+/// again. Interrupt handlers must never take it.
+pub trait HartLock {
+    /// Wait until no other hart holds the lock, and take it.
+    fn lock(&self);
+
+    /// Release the lock. It must be held by the current hart.
+    fn unlock(&self);
+}
+
+/// [`HartLock`] implemented with atomic instructions, for chips whose cores
+/// share coherent memory, and support atomic compare-and-swap.
+#[cfg(target_has_atomic = "8")]
+pub struct AtomicHartLock {
+    locked: core::sync::atomic::AtomicBool,
+}
+
+#[cfg(target_has_atomic = "8")]
+impl AtomicHartLock {
+    pub const fn new() -> Self {
+        Self {
+            locked: core::sync::atomic::AtomicBool::new(false),
+        }
+    }
+}
+
+#[cfg(target_has_atomic = "8")]
+impl HartLock for AtomicHartLock {
+    fn lock(&self) {
+        use core::sync::atomic::Ordering;
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+    }
+
+    fn unlock(&self) {
+        self.locked
+            .store(false, core::sync::atomic::Ordering::Release);
+    }
+}
+
+/// Interface for chips with multiple harts (cores) that all run the kernel.
+///
+/// With [`Kernel::smp_kernel_loop`](crate::Kernel::smp_kernel_loop), every
+/// hart runs its own kernel loop, and the harts share the processes and the
+/// scheduler. All Tock kernel state is protected by a single lock, the
+/// kernel lock, which a hart only releases while it executes a process or
+/// sleeps. Interrupts are handled by hart 0, which also does all kernel work
+/// (interrupt bottom halves and deferred calls). The other harts only
+/// execute processes and their system calls.
+pub trait SmpChip: Chip {
+    /// Number of harts that run the kernel.
+    fn num_harts(&self) -> usize;
+
+    /// Hart this function is called on.
+    fn current_hart(&self) -> usize;
+
+    /// The kernel lock.
+    fn kernel_lock(&self) -> &dyn HartLock;
+
+    /// Scheduler timer of the current hart, used for the timeslices of
+    /// processes on harts other than hart 0. Hart 0 uses the scheduler timer
+    /// of the board. Chips without one for each hart can return `&()`, in
+    /// which case processes on the other harts are not preempted.
+    fn hart_scheduler_timer(&self) -> &dyn SchedulerTimer;
+
+    /// Wake up `hart` if it is sleeping in
+    /// [`SmpChip::wait_for_notification`], or [`Chip::sleep`] for hart 0.
+    /// If it is not sleeping, its next wait returns immediately.
+    fn notify_hart(&self, hart: usize);
+
+    /// Sleep until the current hart is notified. This is called on harts
+    /// other than hart 0 when they have nothing to do, with interrupts
+    /// disabled with [`Chip::atomic`], and without the kernel lock. Spurious
+    /// wakeups are allowed.
+    fn wait_for_notification(&self);
+}
+
 /// Interface for handling interrupts on a hardware chip.
 ///
 /// Each board must construct an implementation of this trait to handle specific
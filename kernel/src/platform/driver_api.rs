@@ -0,0 +1,223 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Machine-readable descriptions of syscall drivers, for host tools.
+//!
+//! Userspace libraries and the syscall documentation repeat the commands,
+//! upcalls and allow buffers of each driver by hand, and drift from the
+//! kernel. Instead, a capsule can describe its syscall interface with
+//! [`driver_api`](crate::driver_api), next to its implementation:
+//!
+//! ```rust,ignore
+//! kernel::driver_api!(
+//!     DRIVER_API,
+//!     DRIVER_NUM,
+//!     name: "led",
+//!     commands: [
+//!         0 => "count() -> u32",
+//!         1 => "on(index)",
+//!         2 => "off(index)",
+//!         3 => "toggle(index)",
+//!     ],
+//! );
+//! ```
+//!
+//! A board exports the descriptions of the drivers it includes with
+//! [`export_driver_apis`](crate::export_driver_apis):
+//!
+//! ```rust,ignore
+//! kernel::export_driver_apis!(
+//!     capsules_core::led::DRIVER_API,
+//!     capsules_core::console::DRIVER_API,
+//! );
+//! ```
+//!
+//! The descriptions are collected in the `.driver_api` section of the kernel
+//! ELF. The linker script keeps the section out of the flash image, so it
+//! costs no space on the device. `tools/driver_api.py` prints the
+//! descriptions of a kernel ELF, for example as Markdown or as a C header for
+//! libtock-c.
+//!
+//! Format
+//! ------
+//!
+//! The section is a list of records, each aligned to four bytes:
+//!
+//! ```text
+//! +--------+------------+-------------+---------+
+//! | Length | Driver num | Description | Padding |
+//! +--------+------------+-------------+---------+
+//! ```
+//!
+//! `Length` is the size of the record including its header and `Driver num`
+//! is the driver number, both 32-bit little-endian values. The description is
+//! UTF-8 text padded with zeros, one line per item:
+//!
+//! ```text
+//! name led
+//! command 0 count() -> u32
+//! command 1 on(index)
+//! ```
+//!
+//! Lines start with `name`, `command`, `subscribe`, `allow_ro` or `allow_rw`.
+//! All but `name` are followed by the number of the item. The rest of the line
+//! is a free-form signature.
+
+/// The syscall interface of a driver.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DriverApi {
+    pub driver_num: u32,
+    /// The lines of the description.
+    pub description: &'static str,
+}
+
+/// Size of the record of `api` in the `.driver_api` section.
+pub const fn record_len(api: &DriverApi) -> usize {
+    8 + api.description.len().next_multiple_of(4)
+}
+
+/// The record of `api`. `N` must be its [`record_len`].
+pub const fn record<const N: usize>(api: &DriverApi) -> [u8; N] {
+    let mut record = [0; N];
+    let len = (N as u32).to_le_bytes();
+    let driver_num = api.driver_num.to_le_bytes();
+    let mut i = 0;
+    while i < 4 {
+        record[i] = len[i];
+        record[4 + i] = driver_num[i];
+        i += 1;
+    }
+    let description = api.description.as_bytes();
+    let mut i = 0;
+    while i < description.len() {
+        record[8 + i] = description[i];
+        i += 1;
+    }
+    record
+}
+
+/// Describe the syscall interface of a driver as the constant `$N`.
+///
+/// Each item is its number and a free-form signature. The lists can be left
+/// out, but those present must be in this order.
+#[macro_export]
+macro_rules! driver_api {
+    (
+        $N:ident,
+        $driver_num:expr,
+        name: $name:literal
+        $(, commands: [$($command:literal => $command_sig:literal),* $(,)?])?
+        $(, subscribes: [$($subscribe:literal => $subscribe_sig:literal),* $(,)?])?
+        $(, allow_ro: [$($allow_ro:literal => $allow_ro_sig:literal),* $(,)?])?
+        $(, allow_rw: [$($allow_rw:literal => $allow_rw_sig:literal),* $(,)?])?
+        $(,)?
+    ) => {
+        /// Description of the syscall interface of this driver, for host
+        /// tools.
+        pub const $N: $crate::platform::driver_api::DriverApi =
+            $crate::platform::driver_api::DriverApi {
+                driver_num: $driver_num as u32,
+                description: concat!(
+                    "name ", $name, "\n",
+                    $($("command ", $command, " ", $command_sig, "\n",)*)?
+                    $($("subscribe ", $subscribe, " ", $subscribe_sig, "\n",)*)?
+                    $($("allow_ro ", $allow_ro, " ", $allow_ro_sig, "\n",)*)?
+                    $($("allow_rw ", $allow_rw, " ", $allow_rw_sig, "\n",)*)?
+                ),
+            };
+    };
+}
+
+/// Export the descriptions of the drivers of a board to the `.driver_api`
+/// section of the kernel ELF.
+///
+/// This must be called once, in the board crate.
+#[macro_export]
+macro_rules! export_driver_apis {
+    ($($api:expr),* $(,)?) => {
+        $(
+            const _: () = {
+                const API: $crate::platform::driver_api::DriverApi = $api;
+
+                #[used]
+                #[link_section = ".driver_api"]
+                static RECORD: [u8; $crate::platform::driver_api::record_len(&API)] =
+                    $crate::platform::driver_api::record(&API);
+            };
+        )*
+    };
+}
+
+/// One item of a description.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Item<'a> {
+    Name(&'a str),
+    Command(u32, &'a str),
+    Subscribe(u32, &'a str),
+    AllowRo(u32, &'a str),
+    AllowRw(u32, &'a str),
+}
+
+impl DriverApi {
+    /// The items of the description. Lines that are not understood are
+    /// skipped.
+    pub fn items(&self) -> impl Iterator<Item = Item<'static>> {
+        self.description.lines().filter_map(|line| {
+            let (kind, rest) = line.split_once(' ')?;
+            if kind == "name" {
+                return Some(Item::Name(rest));
+            }
+            let (num, signature) = rest.split_once(' ')?;
+            let num = num.parse().ok()?;
+            match kind {
+                "command" => Some(Item::Command(num, signature)),
+                "subscribe" => Some(Item::Subscribe(num, signature)),
+                "allow_ro" => Some(Item::AllowRo(num, signature)),
+                "allow_rw" => Some(Item::AllowRw(num, signature)),
+                _ => None,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[allow(unreachable_pub)]
+    mod api {
+        crate::driver_api!(
+            TEST_API,
+            0x90000,
+            name: "test",
+            commands: [0 => "exists", 1 => "start(len)"],
+            allow_rw: [0 => "buffer"],
+        );
+    }
+    use api::TEST_API;
+
+    #[test]
+    fn test_description() {
+        assert_eq!(
+            TEST_API.description,
+            "name test\ncommand 0 exists\ncommand 1 start(len)\nallow_rw 0 buffer\n"
+        );
+        let mut items = TEST_API.items();
+        assert_eq!(items.next(), Some(Item::Name("test")));
+        assert_eq!(items.next(), Some(Item::Command(0, "exists")));
+        assert_eq!(items.next(), Some(Item::Command(1, "start(len)")));
+        assert_eq!(items.next(), Some(Item::AllowRw(0, "buffer")));
+        assert_eq!(items.next(), None);
+    }
+
+    #[test]
+    fn test_record_is_padded() {
+        const API: DriverApi = DriverApi {
+            driver_num: 2,
+            description: "name led\n",
+        };
+        let record: [u8; record_len(&API)] = record(&API);
+        assert_eq!(record, *b"\x14\0\0\0\x02\0\0\0name led\n\0\0\0");
+    }
+}
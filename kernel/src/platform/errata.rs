@@ -0,0 +1,141 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Workarounds for chip errata.
+//!
+//! Chip crates describe each workaround they apply at boot as an
+//! [`Erratum`]: where it is documented, which chips it affects, how it is
+//! applied and, if possible, how to check that it is in effect. The chip
+//! applies its table with [`apply_errata`] early in its `init()`, and boards
+//! can list what their image applies with [`log_errata`] once `debug!()`
+//! works.
+//!
+//! ```rust,ignore
+//! pub static ERRATA: [Erratum; 1] = [Erratum {
+//!     id: "nRF52832-37",
+//!     summary: "RADIO: Encryption engine is slow by default",
+//!     document: "nRF52832 Errata v1.2",
+//!     affects: is_nrf52832,
+//!     apply: || unsafe { *(0x400005a0 as *mut u32) = 0x3 },
+//!     check: Some(|| unsafe { *(0x400005a0 as *const u32) == 0x3 }),
+//! }];
+//! ```
+
+use crate::debug;
+
+/// A workaround for a chip erratum.
+pub struct Erratum {
+    /// Identifier of the erratum, with the chip it is documented for, for
+    /// example `"nRF52832-12"`.
+    pub id: &'static str,
+    /// Title of the erratum in the errata document.
+    pub summary: &'static str,
+    /// Errata document, with its version, that describes the erratum.
+    pub document: &'static str,
+    /// Whether the chip, and its revision, is affected.
+    pub affects: fn() -> bool,
+    /// Apply the workaround. Only called if the chip is affected.
+    pub apply: unsafe fn(),
+    /// Check that the workaround is in effect.
+    pub check: Option<fn() -> bool>,
+}
+
+/// Whether an erratum workaround is in effect.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErratumStatus {
+    /// The chip is not affected, so the workaround is not applied.
+    NotAffected,
+    /// The workaround is applied. `checked` says whether it has a check.
+    Applied { checked: bool },
+    /// The workaround is applied, but its check failed.
+    CheckFailed,
+}
+
+impl Erratum {
+    /// Whether the workaround is in effect, assuming the errata were applied
+    /// with [`apply_errata`].
+    pub fn status(&self) -> ErratumStatus {
+        if !(self.affects)() {
+            return ErratumStatus::NotAffected;
+        }
+        match self.check {
+            Some(check) if !check() => ErratumStatus::CheckFailed,
+            Some(_) => ErratumStatus::Applied { checked: true },
+            None => ErratumStatus::Applied { checked: false },
+        }
+    }
+}
+
+/// Apply the workaround of every erratum that affects the chip, in order,
+/// and return how many were applied.
+///
+/// # Safety
+///
+/// The workarounds write to registers directly. This must only be called
+/// once at boot, before the peripherals they change are used.
+pub unsafe fn apply_errata(errata: &[Erratum]) -> usize {
+    errata
+        .iter()
+        .filter(|erratum| (erratum.affects)())
+        .map(|erratum| (erratum.apply)())
+        .count()
+}
+
+/// Print the status of each erratum with `debug!()`.
+pub fn log_errata(errata: &[Erratum]) {
+    for erratum in errata {
+        let status = match erratum.status() {
+            ErratumStatus::NotAffected => continue,
+            ErratumStatus::Applied { checked: true } => "applied, checked",
+            ErratumStatus::Applied { checked: false } => "applied",
+            ErratumStatus::CheckFailed => "applied, CHECK FAILED",
+        };
+        debug!(
+            "errata: {} ({}): {} [{}]",
+            erratum.id, erratum.document, erratum.summary, status
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_errata, Erratum, ErratumStatus};
+    use core::sync::atomic::{AtomicU32, Ordering};
+
+    static REGISTER: AtomicU32 = AtomicU32::new(0);
+
+    fn erratum(affects: fn() -> bool, check: Option<fn() -> bool>) -> Erratum {
+        Erratum {
+            id: "test-1",
+            summary: "test",
+            document: "test errata v1",
+            affects,
+            apply: || {
+                REGISTER.fetch_add(1, Ordering::Relaxed);
+            },
+            check,
+        }
+    }
+
+    #[test]
+    fn test_apply_and_status() {
+        let errata = [
+            erratum(|| true, Some(|| REGISTER.load(Ordering::Relaxed) != 0)),
+            erratum(|| false, None),
+            erratum(|| true, None),
+        ];
+        assert_eq!(errata[0].status(), ErratumStatus::CheckFailed);
+
+        // Only the errata affecting the chip are applied.
+        assert_eq!(unsafe { apply_errata(&errata) }, 2);
+        assert_eq!(REGISTER.load(Ordering::Relaxed), 2);
+
+        assert_eq!(errata[0].status(), ErratumStatus::Applied { checked: true });
+        assert_eq!(errata[1].status(), ErratumStatus::NotAffected);
+        assert_eq!(
+            errata[2].status(),
+            ErratumStatus::Applied { checked: false }
+        );
+    }
+}
@@ -6,9 +6,18 @@
 //!
 //! Implementations of these traits are used by the core kernel.
 
+pub mod attributes;
 pub mod chip;
+pub mod driver_api;
+pub mod errata;
 pub mod mpu;
+pub mod peripherals;
+pub mod persistent_counters;
+pub mod power;
 pub mod scheduler_timer;
+pub mod self_test;
+pub mod stats;
+pub mod suspend;
 pub mod watchdog;
 
 pub(crate) mod platform;
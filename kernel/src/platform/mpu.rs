@@ -66,6 +66,92 @@ impl Display for MpuConfigDefault {
     }
 }
 
+/// Alignment constraints of the app memory blocks that an MPU allocates with
+/// [`MPU::allocate_app_memory_region`].
+///
+/// Host tools which place processes at fixed RAM addresses read these
+/// constraints from the kernel attributes (see
+/// [`app_memory_alignment_attribute`](crate::app_memory_alignment_attribute)),
+/// so that they can choose addresses the MPU can use. The kernel uses them to
+/// explain why it could not place a process at its fixed address.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct AppMemoryAlignment {
+    /// Alignment of the start of every app memory block, in bytes. This is a
+    /// power of two.
+    pub min_alignment: usize,
+
+    /// When not zero, app memory blocks are covered by this number of MPU
+    /// regions which have the same power-of-two size and must be aligned to
+    /// it. The start of a block is then aligned to the smallest power of two,
+    /// at least `min_alignment`, for which `regions` regions hold the block.
+    pub regions: usize,
+}
+
+impl AppMemoryAlignment {
+    /// Type of the kernel attribute TLV that records the constraints.
+    pub const ATTRIBUTE_TYPE: u16 = crate::platform::attributes::APP_MEMORY_ALIGNMENT;
+
+    /// No constraint on the start address of app memory blocks.
+    pub const NONE: AppMemoryAlignment = AppMemoryAlignment {
+        min_alignment: 1,
+        regions: 0,
+    };
+
+    /// Alignment of the start of an app memory block of `size` bytes.
+    pub const fn alignment(&self, size: usize) -> usize {
+        let mut alignment = self.min_alignment;
+        if self.regions != 0 {
+            while alignment.saturating_mul(self.regions) < size && alignment <= usize::MAX / 2 {
+                alignment *= 2;
+            }
+        }
+        alignment
+    }
+
+    /// Kernel attribute TLV recording the constraints: the minimum alignment
+    /// and the number of regions as two 32-bit values, followed by the type
+    /// and the length of the value, in little-endian order.
+    pub const fn attribute(&self) -> [u8; 12] {
+        let min_alignment = (self.min_alignment as u32).to_le_bytes();
+        let regions = (self.regions as u32).to_le_bytes();
+        let tlv_type = Self::ATTRIBUTE_TYPE.to_le_bytes();
+        let length = 8u16.to_le_bytes();
+        [
+            min_alignment[0],
+            min_alignment[1],
+            min_alignment[2],
+            min_alignment[3],
+            regions[0],
+            regions[1],
+            regions[2],
+            regions[3],
+            tlv_type[0],
+            tlv_type[1],
+            length[0],
+            length[1],
+        ]
+    }
+}
+
+/// Record the app memory alignment constraints of an MPU in the kernel
+/// attributes at the end of the kernel flash, for host tools.
+///
+/// This must be called once, in the board crate, with the MPU type of the
+/// chip:
+///
+/// ```rust,ignore
+/// kernel::app_memory_alignment_attribute!(cortexm4::mpu::MPU);
+/// ```
+#[macro_export]
+macro_rules! app_memory_alignment_attribute {
+    ($mpu:ty $(,)?) => {
+        #[used]
+        #[link_section = ".attributes.app_memory_alignment"]
+        static APP_MEMORY_ALIGNMENT_ATTRIBUTE: [u8; 12] =
+            <$mpu as $crate::platform::mpu::MPU>::APP_MEMORY_ALIGNMENT.attribute();
+    };
+}
+
 /// The generic trait that particular memory protection unit implementations
 /// need to implement.
 ///
@@ -94,6 +180,10 @@ pub trait MPU {
     /// current state to help with debugging.
     type MpuConfig: Display;
 
+    /// Alignment constraints of the app memory blocks allocated with
+    /// [`MPU::allocate_app_memory_region`].
+    const APP_MEMORY_ALIGNMENT: AppMemoryAlignment = AppMemoryAlignment::NONE;
+
     /// Enables the MPU for userspace apps.
     ///
     /// This function must enable the permission restrictions on the various
@@ -257,6 +347,10 @@ pub trait MPU {
     /// an allocated region are inaccessible in user mode and accessible in
     /// supervisor mode.
     ///
+    /// This is called on every switch to a process, so implementations should
+    /// avoid writing hardware state that already matches `config`, for instance
+    /// when switching back to the process the MPU is configured for.
+    ///
     /// # Arguments
     ///
     /// - `config`: MPU region configuration
@@ -0,0 +1,192 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Registry of the optional subsystems of a board.
+//!
+//! Boards declare which optional subsystems they have (a screen, radios,
+//! storage, ...) in a static list of [`Peripheral`]s. Capsules can query the
+//! list to adapt to the board, and the `capsules_extra::peripherals` driver
+//! exposes it to userspace, so that portable applications can check whether a
+//! subsystem exists instead of probing drivers and handling `NODEVICE`.
+//!
+//! ```rust,ignore
+//! static PERIPHERALS: [Peripheral; 2] = [
+//!     Peripheral::screen(capsules_extra::screen::DRIVER_NUM, 240, 240),
+//!     Peripheral::new(
+//!         PeripheralKind::BleRadio,
+//!         capsules_extra::ble_advertising_driver::DRIVER_NUM,
+//!     ),
+//! ];
+//! let peripherals = Peripherals::new(&PERIPHERALS);
+//! if peripherals.has(PeripheralKind::Screen) { /* ... */ }
+//! ```
+
+/// Kind of an optional subsystem.
+///
+/// The values are part of the userspace interface and must not change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum PeripheralKind {
+    /// A graphic screen. The parameters are its width and height in pixels.
+    Screen = 0x01,
+    /// A text screen. The parameters are its number of columns and rows.
+    TextScreen = 0x02,
+    /// A touch panel. The first parameter is the number of simultaneous
+    /// touches it reports.
+    Touch = 0x03,
+    /// A buzzer.
+    Buzzer = 0x04,
+    /// An IEEE 802.15.4 radio.
+    Ieee802154Radio = 0x10,
+    /// A Bluetooth Low Energy radio.
+    BleRadio = 0x11,
+    /// A LoRa radio.
+    LoRaRadio = 0x12,
+    /// Non-volatile storage available to userspace. The first parameter is
+    /// its size in bytes.
+    Storage = 0x20,
+}
+
+impl TryFrom<u32> for PeripheralKind {
+    type Error = ();
+
+    fn try_from(value: u32) -> Result<Self, ()> {
+        match value {
+            0x01 => Ok(PeripheralKind::Screen),
+            0x02 => Ok(PeripheralKind::TextScreen),
+            0x03 => Ok(PeripheralKind::Touch),
+            0x04 => Ok(PeripheralKind::Buzzer),
+            0x10 => Ok(PeripheralKind::Ieee802154Radio),
+            0x11 => Ok(PeripheralKind::BleRadio),
+            0x12 => Ok(PeripheralKind::LoRaRadio),
+            0x20 => Ok(PeripheralKind::Storage),
+            _ => Err(()),
+        }
+    }
+}
+
+/// An optional subsystem of the board.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Peripheral {
+    pub kind: PeripheralKind,
+    /// Driver number of the capsule that gives access to the subsystem.
+    pub driver_num: usize,
+    /// Parameters whose meaning depends on the kind, 0 if unused.
+    pub parameters: [u32; 2],
+}
+
+impl Peripheral {
+    /// A subsystem without parameters.
+    pub const fn new(kind: PeripheralKind, driver_num: usize) -> Peripheral {
+        Peripheral {
+            kind,
+            driver_num,
+            parameters: [0, 0],
+        }
+    }
+
+    pub const fn screen(driver_num: usize, width: u32, height: u32) -> Peripheral {
+        Peripheral {
+            kind: PeripheralKind::Screen,
+            driver_num,
+            parameters: [width, height],
+        }
+    }
+
+    pub const fn text_screen(driver_num: usize, columns: u32, rows: u32) -> Peripheral {
+        Peripheral {
+            kind: PeripheralKind::TextScreen,
+            driver_num,
+            parameters: [columns, rows],
+        }
+    }
+
+    pub const fn touch(driver_num: usize, touches: u32) -> Peripheral {
+        Peripheral {
+            kind: PeripheralKind::Touch,
+            driver_num,
+            parameters: [touches, 0],
+        }
+    }
+
+    pub const fn storage(driver_num: usize, size: u32) -> Peripheral {
+        Peripheral {
+            kind: PeripheralKind::Storage,
+            driver_num,
+            parameters: [size, 0],
+        }
+    }
+}
+
+/// The optional subsystems of a board.
+#[derive(Clone, Copy)]
+pub struct Peripherals<'a> {
+    peripherals: &'a [Peripheral],
+}
+
+impl<'a> Peripherals<'a> {
+    pub const fn new(peripherals: &'a [Peripheral]) -> Peripherals<'a> {
+        Peripherals { peripherals }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &'a Peripheral> {
+        self.peripherals.iter()
+    }
+
+    /// Whether the board has at least one subsystem of kind `kind`.
+    pub fn has(&self, kind: PeripheralKind) -> bool {
+        self.iter().any(|peripheral| peripheral.kind == kind)
+    }
+
+    /// Number of subsystems of kind `kind`.
+    pub fn count(&self, kind: PeripheralKind) -> usize {
+        self.iter()
+            .filter(|peripheral| peripheral.kind == kind)
+            .count()
+    }
+
+    /// The `n`th subsystem of kind `kind`, in the order the board declared
+    /// them.
+    pub fn get(&self, kind: PeripheralKind, n: usize) -> Option<&'a Peripheral> {
+        self.iter()
+            .filter(|peripheral| peripheral.kind == kind)
+            .nth(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_by_kind() {
+        let list = [
+            Peripheral::storage(0x50000, 4096),
+            Peripheral::new(PeripheralKind::BleRadio, 0x30000),
+            Peripheral::storage(0x50003, 65536),
+        ];
+        let peripherals = Peripherals::new(&list);
+        assert!(peripherals.has(PeripheralKind::BleRadio));
+        assert!(!peripherals.has(PeripheralKind::Screen));
+        assert_eq!(peripherals.count(PeripheralKind::Storage), 2);
+        assert_eq!(
+            peripherals.get(PeripheralKind::Storage, 1),
+            Some(&Peripheral::storage(0x50003, 65536))
+        );
+        assert_eq!(peripherals.get(PeripheralKind::Storage, 2), None);
+    }
+
+    #[test]
+    fn test_kind_round_trip() {
+        for kind in [
+            PeripheralKind::Screen,
+            PeripheralKind::Touch,
+            PeripheralKind::LoRaRadio,
+            PeripheralKind::Storage,
+        ] {
+            assert_eq!(PeripheralKind::try_from(kind as u32), Ok(kind));
+        }
+        assert_eq!(PeripheralKind::try_from(0), Err(()));
+    }
+}
@@ -0,0 +1,91 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Counters kept across boots, such as the number of boots and panics.
+//!
+//! Knowing how often a device in the field rebooted or panicked, and why it
+//! last panicked, helps finding problems that do not show on the bench. The
+//! counters are kept by the board in persistent storage (see
+//! `capsules_system::persistent_counters`), which tools such as the process
+//! console query through the [`PersistentCounters`] trait.
+//!
+//! Storage is asynchronous, so it cannot be written when the kernel panics.
+//! Instead, the panic routines of [`debug`](crate::debug) give a hash of the
+//! panic message to the [`PanicRecorder`] set with
+//! [`set_panic_recorder`](crate::debug::set_panic_recorder), which keeps it
+//! synchronously, for example in retained registers, until the next boot
+//! counts it.
+
+use core::fmt;
+
+/// Counters kept across boots.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PersistentCounts {
+    /// Number of boots, including this one.
+    pub boot_count: u32,
+    /// Number of boots that ended with a panic.
+    pub panic_count: u32,
+    /// Hash of the message of the last panic, if the kernel panicked.
+    pub last_panic: Option<u32>,
+}
+
+/// Source of the counters kept across boots.
+pub trait PersistentCounters {
+    /// The counters, or `None` if they are not loaded from storage yet.
+    fn counts(&self) -> Option<PersistentCounts>;
+}
+
+/// Keeps the reason of a panic until the next boot.
+pub trait PanicRecorder {
+    /// The kernel is panicking, with a message whose hash is `reason`, as
+    /// computed by [`panic_reason_hash`].
+    ///
+    /// This is called in the panic routines, so it must be synchronous and
+    /// must not panic.
+    fn record_panic(&self, reason: u32);
+}
+
+/// Hashes formatted text with 32-bit FNV-1a.
+struct ReasonHasher(u32);
+
+impl fmt::Write for ReasonHasher {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            self.0 = (self.0 ^ u32::from(byte)).wrapping_mul(0x0100_0193);
+        }
+        Ok(())
+    }
+}
+
+/// Hash of the formatted `reason`, such as the `PanicInfo` of a panic, which
+/// includes its location and message.
+pub fn panic_reason_hash(reason: &dyn fmt::Display) -> u32 {
+    let mut hasher = ReasonHasher(0x811c_9dc5);
+    let _ = fmt::write(&mut hasher, format_args!("{}", reason));
+    hasher.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_is_fnv1a() {
+        assert_eq!(panic_reason_hash(&""), 0x811c_9dc5);
+        assert_eq!(panic_reason_hash(&"a"), 0xe40c_292c);
+        assert_eq!(panic_reason_hash(&"foobar"), 0xbf9c_f968);
+    }
+
+    #[test]
+    fn hash_covers_formatted_text() {
+        assert_eq!(
+            panic_reason_hash(&format_args!("{}:{}", "src/main.rs", 42)),
+            panic_reason_hash(&"src/main.rs:42")
+        );
+        assert_ne!(
+            panic_reason_hash(&"src/main.rs:42"),
+            panic_reason_hash(&"src/main.rs:43")
+        );
+    }
+}
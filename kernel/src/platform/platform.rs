@@ -221,6 +221,18 @@ impl SyscallFilter for TbfHeaderFilterDefaultAllow {
                 CommandPermissions::Mask(_allowed) => Ok(()),
             },
 
+            // A batch is allowed if any commands are. Each command of the batch
+            // is then filtered as a Command system call.
+            syscall::Syscall::CommandBatch {
+                driver_number,
+                batch_address: _,
+                batch_len: _,
+            } => match process.get_command_permissions(*driver_number, 0) {
+                CommandPermissions::NoPermsAtAll => Ok(()),
+                CommandPermissions::NoPermsThisDriver => Err(errorcode::ErrorCode::NODEVICE),
+                CommandPermissions::Mask(_allowed) => Ok(()),
+            },
+
             // Non-filterable system calls
             syscall::Syscall::Yield { .. }
             | syscall::Syscall::Memop { .. }
@@ -0,0 +1,68 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Interfaces for entering low power sleep states.
+//!
+//! When there is no work, the kernel puts the chip to sleep with
+//! [`Chip::sleep`](crate::platform::chip::Chip::sleep). A board can instead
+//! register a [`PowerManager`] with
+//! [`Kernel::set_power_manager`](crate::Kernel::set_power_manager), which then
+//! puts the chip into the deepest [`SleepState`] that is allowed at that time.
+//!
+//! Deeper states save more energy, but take longer to wake up from, and fewer
+//! peripherals can wake the chip from them. Peripherals register as
+//! [`SleepConstraint`]s with the power manager to bound the latency of waking
+//! up, for instance while a radio is receiving, and to report that they are
+//! armed to wake the chip from a state. The manager also considers the next
+//! alarm, as entering a deep state only pays off if the chip sleeps long
+//! enough.
+//!
+//! Power managers are implemented by chips, as the sleep states and how to
+//! enter them are specific to each chip (see `nrf52840::power_manager`).
+
+use crate::ErrorCode;
+
+/// Sleep states, from the shallowest to the deepest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SleepState {
+    /// The regular sleep state of the chip, which any interrupt wakes the
+    /// chip from right away.
+    Sleep,
+    /// A state with some clocks or power domains of the chip off. The chip
+    /// keeps its state, and waking up takes longer.
+    DeepSleep,
+    /// The chip is off, except for the peripherals that can wake it and the
+    /// RAM it retains. Waking up resets the chip.
+    Off,
+}
+
+/// A peripheral that constrains which sleep states the chip can enter.
+pub trait SleepConstraint {
+    /// The longest time, in microseconds, the peripheral can wait for the
+    /// chip to wake up after an event, or `None` if the peripheral does not
+    /// need the chip to wake up quickly now.
+    fn max_wakeup_latency_us(&self) -> Option<u32>;
+
+    /// Whether the peripheral is armed to wake the chip from `state`.
+    fn wakes_from(&self, state: SleepState) -> bool;
+}
+
+/// Puts the chip to sleep in the deepest state allowed.
+pub trait PowerManager<'a> {
+    /// Take `constraint` into account when choosing a sleep state.
+    ///
+    /// Returns `NOMEM` if the manager cannot track more constraints.
+    fn register(&self, constraint: &'a dyn SleepConstraint) -> Result<(), ErrorCode>;
+
+    /// The deepest state the chip would sleep in now.
+    fn sleep_state(&self) -> SleepState;
+
+    /// Put the chip to sleep, in place of `Chip::sleep`, and return the state
+    /// it slept in once it is awake again. `sleep` puts the chip into its
+    /// regular sleep state, and returns once an interrupt is pending.
+    ///
+    /// This is called with interrupts disabled, when there is no work to do.
+    /// It does not return if the chip enters [`SleepState::Off`].
+    fn sleep(&self, sleep: &dyn Fn()) -> SleepState;
+}
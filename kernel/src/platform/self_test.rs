@@ -0,0 +1,336 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Power-on self-tests.
+//!
+//! Boards can check their hardware before loading processes by registering a
+//! list of self-tests, each with a [`SelfTestPolicy`] that says how much a
+//! failure matters, and running them with [`run_self_tests`]. Each result is
+//! recorded with a [`SelfTestLog`], and the returned [`SelfTestReport`] tells
+//! the board whether it can continue.
+//!
+//! This module provides tests for a RAM region ([`RamPatternTest`]), for the
+//! integrity of a flash region such as the kernel image ([`FlashCrcTest`]),
+//! and a wrapper for board specific checks, for example that a peripheral
+//! answers with its expected identifier ([`FnSelfTest`]).
+//!
+//! Checks that need asynchronous hardware, such as hashing the kernel image
+//! with a digest engine, run after the kernel loop started and report their
+//! result with the same [`SelfTestLog`]. The integrity check of the kernel
+//! image exposes its result with [`KernelIntegrity`].
+//!
+//! ```rust,ignore
+//! let ram_test = RamPatternTest::new("ram", &mut *addr_of_mut!(RAM_TEST_REGION));
+//! let sensor_test = FnSelfTest::new("sensor", || check_sensor_id());
+//! let report = run_self_tests(
+//!     &[
+//!         SelfTestEntry::new(&ram_test, SelfTestPolicy::Halt),
+//!         SelfTestEntry::new(&sensor_test, SelfTestPolicy::Degrade),
+//!     ],
+//!     &DebugSelfTestLog,
+//! );
+//! if report.must_halt() {
+//!     panic!("Power-on self-test failed");
+//! }
+//! ```
+
+use core::cell::Cell;
+
+use crate::debug;
+use crate::utilities::cells::TakeCell;
+use crate::utilities::helpers::crc32_posix;
+use crate::ErrorCode;
+
+/// What the board does if a self-test fails.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SelfTestPolicy {
+    /// The failure is only recorded.
+    Continue,
+    /// The board keeps running without the feature the test checks, for
+    /// example by not registering the driver of a broken peripheral.
+    Degrade,
+    /// The board must not run processes.
+    Halt,
+}
+
+/// A self-test of the board hardware.
+///
+/// Self-tests run synchronously before processes are loaded, so they must be
+/// short.
+pub trait SelfTest {
+    /// Name of the test, used when recording its result.
+    fn name(&self) -> &'static str;
+
+    /// Run the test.
+    fn run(&self) -> Result<(), ErrorCode>;
+}
+
+/// A self-test and the policy to apply if it fails.
+#[derive(Clone, Copy)]
+pub struct SelfTestEntry<'a> {
+    pub test: &'a dyn SelfTest,
+    pub policy: SelfTestPolicy,
+}
+
+impl<'a> SelfTestEntry<'a> {
+    pub const fn new(test: &'a dyn SelfTest, policy: SelfTestPolicy) -> Self {
+        Self { test, policy }
+    }
+}
+
+/// Destination of the self-test results.
+pub trait SelfTestLog {
+    fn record(&self, name: &'static str, policy: SelfTestPolicy, result: Result<(), ErrorCode>);
+}
+
+/// Records self-test results on the debug output.
+pub struct DebugSelfTestLog;
+
+impl SelfTestLog for DebugSelfTestLog {
+    fn record(&self, name: &'static str, policy: SelfTestPolicy, result: Result<(), ErrorCode>) {
+        match result {
+            Ok(()) => debug!("Self-test {}: passed", name),
+            Err(e) => debug!("Self-test {}: failed ({:?}), policy {:?}", name, e, policy),
+        }
+    }
+}
+
+/// Result of checking the kernel image against its stored hash.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KernelIntegrityStatus {
+    /// The check has not started.
+    Unchecked,
+    /// The kernel image is being hashed.
+    Checking,
+    /// No hash was written when the kernel was flashed.
+    NotProvisioned,
+    /// The kernel image matches the stored hash.
+    Verified,
+    /// The kernel image does not match the stored hash.
+    Mismatch,
+    /// The kernel image could not be hashed.
+    Error(ErrorCode),
+}
+
+/// Source of the result of the kernel image integrity check, for tools such
+/// as the process console.
+pub trait KernelIntegrity {
+    fn integrity_status(&self) -> KernelIntegrityStatus;
+
+    /// The hash the kernel image is checked against, `None` if no hash was
+    /// written when the kernel was flashed.
+    fn kernel_hash(&self) -> Option<&'static [u8]>;
+}
+
+/// Results of a set of self-tests.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SelfTestReport {
+    pub passed: usize,
+    pub failed: usize,
+    /// Most severe policy of the failed tests, `None` if all tests passed.
+    pub worst: Option<SelfTestPolicy>,
+}
+
+impl SelfTestReport {
+    /// Whether a test with the `Halt` policy failed.
+    pub fn must_halt(&self) -> bool {
+        self.worst == Some(SelfTestPolicy::Halt)
+    }
+
+    /// Whether a test with the `Degrade` or `Halt` policy failed.
+    pub fn is_degraded(&self) -> bool {
+        self.worst >= Some(SelfTestPolicy::Degrade)
+    }
+}
+
+/// Run `tests` in order and record their results with `log`.
+///
+/// All tests run, even after a test with the `Halt` policy failed, so that
+/// the log shows every failure.
+pub fn run_self_tests(tests: &[SelfTestEntry], log: &dyn SelfTestLog) -> SelfTestReport {
+    let mut report = SelfTestReport::default();
+    for entry in tests {
+        let result = entry.test.run();
+        log.record(entry.test.name(), entry.policy, result);
+        match result {
+            Ok(()) => report.passed += 1,
+            Err(_) => {
+                report.failed += 1;
+                report.worst = report.worst.max(Some(entry.policy));
+            }
+        }
+    }
+    report
+}
+
+/// Checks that a RAM region stores bit patterns correctly.
+///
+/// The test writes alternating bit patterns and then the address of each word
+/// to detect stuck and coupled bits, and address lines that are shorted. The
+/// content of the region is lost, so the region must be a buffer set aside for
+/// the test or memory that is initialized afterwards (for example app
+/// memory).
+pub struct RamPatternTest<'a> {
+    name: &'static str,
+    region: TakeCell<'a, [u32]>,
+}
+
+impl<'a> RamPatternTest<'a> {
+    pub fn new(name: &'static str, region: &'a mut [u32]) -> Self {
+        Self {
+            name,
+            region: TakeCell::new(region),
+        }
+    }
+
+    fn check(region: &mut [u32], pattern: impl Fn(usize, *const u32) -> u32) -> bool {
+        for (i, word) in region.iter_mut().enumerate() {
+            let ptr: *mut u32 = word;
+            // SAFETY: `ptr` comes from a mutable reference. The accesses are
+            // volatile so the compiler does not elide the read back.
+            unsafe { ptr.write_volatile(pattern(i, ptr)) };
+        }
+        region.iter_mut().enumerate().all(|(i, word)| {
+            let ptr: *mut u32 = word;
+            // SAFETY: as above.
+            unsafe { ptr.read_volatile() == pattern(i, ptr) }
+        })
+    }
+}
+
+impl SelfTest for RamPatternTest<'_> {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn run(&self) -> Result<(), ErrorCode> {
+        self.region
+            .map_or(Err(ErrorCode::BUSY), |region| {
+                let ok = Self::check(region, |_, _| 0x5555_5555)
+                    && Self::check(region, |_, _| 0xAAAA_AAAA)
+                    && Self::check(region, |_, ptr| ptr as usize as u32)
+                    && Self::check(region, |_, ptr| !(ptr as usize as u32));
+                region.fill(0);
+                Ok(ok)
+            })
+            .and_then(|ok| if ok { Ok(()) } else { Err(ErrorCode::FAIL) })
+    }
+}
+
+/// Checks the CRC32 (POSIX) of a flash region, such as the kernel image,
+/// against its expected value.
+pub struct FlashCrcTest {
+    name: &'static str,
+    region: &'static [u8],
+    expected: u32,
+    /// The CRC computed by the last run.
+    computed: Cell<Option<u32>>,
+}
+
+impl FlashCrcTest {
+    pub const fn new(name: &'static str, region: &'static [u8], expected: u32) -> Self {
+        Self {
+            name,
+            region,
+            expected,
+            computed: Cell::new(None),
+        }
+    }
+
+    /// The CRC the last run computed, which helps finding the expected value
+    /// of a new image.
+    pub fn computed(&self) -> Option<u32> {
+        self.computed.get()
+    }
+}
+
+impl SelfTest for FlashCrcTest {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn run(&self) -> Result<(), ErrorCode> {
+        let crc = crc32_posix(self.region);
+        self.computed.set(Some(crc));
+        if crc == self.expected {
+            Ok(())
+        } else {
+            Err(ErrorCode::FAIL)
+        }
+    }
+}
+
+/// A board specific self-test implemented by a function.
+pub struct FnSelfTest<F: Fn() -> Result<(), ErrorCode>> {
+    name: &'static str,
+    test: F,
+}
+
+impl<F: Fn() -> Result<(), ErrorCode>> FnSelfTest<F> {
+    pub const fn new(name: &'static str, test: F) -> Self {
+        Self { name, test }
+    }
+}
+
+impl<F: Fn() -> Result<(), ErrorCode>> SelfTest for FnSelfTest<F> {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn run(&self) -> Result<(), ErrorCode> {
+        (self.test)()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestLog(Cell<usize>);
+
+    impl SelfTestLog for TestLog {
+        fn record(&self, _: &'static str, _: SelfTestPolicy, _: Result<(), ErrorCode>) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn test_report_policies() {
+        let pass = FnSelfTest::new("pass", || Ok(()));
+        let fail = FnSelfTest::new("fail", || Err(ErrorCode::NODEVICE));
+        let log = TestLog(Cell::new(0));
+
+        let report = run_self_tests(
+            &[
+                SelfTestEntry::new(&fail, SelfTestPolicy::Continue),
+                SelfTestEntry::new(&pass, SelfTestPolicy::Halt),
+                SelfTestEntry::new(&fail, SelfTestPolicy::Degrade),
+            ],
+            &log,
+        );
+        assert_eq!(log.0.get(), 3);
+        assert_eq!((report.passed, report.failed), (1, 2));
+        assert!(report.is_degraded());
+        assert!(!report.must_halt());
+
+        let report = run_self_tests(&[SelfTestEntry::new(&pass, SelfTestPolicy::Halt)], &log);
+        assert_eq!(report.worst, None);
+        assert!(!report.is_degraded());
+    }
+
+    #[test]
+    fn test_ram_and_crc() {
+        let mut region = [0xffff_ffffu32; 16];
+        let ram = RamPatternTest::new("ram", &mut region);
+        assert_eq!(ram.run(), Ok(()));
+
+        static IMAGE: [u8; 9] = *b"123456789";
+        // Check value of the POSIX CRC32 for "123456789".
+        let crc = FlashCrcTest::new("image", &IMAGE, 0x765e_7680);
+        assert_eq!(crc.run(), Ok(()));
+        let crc = FlashCrcTest::new("image", &IMAGE, 0);
+        assert_eq!(crc.run(), Err(ErrorCode::FAIL));
+        assert_eq!(crc.computed(), Some(0x765e_7680));
+    }
+}
@@ -0,0 +1,224 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Lightweight runtime statistics about the kernel.
+//!
+//! The core kernel keeps a few counters of events in its main loop (see
+//! [`Kernel::loop_counters`](crate::Kernel::loop_counters)). Boards that also
+//! want to count interrupts per source can wrap the chip's
+//! [`InterruptService`] in an [`InterruptCounter`].
+//!
+//! Turning these raw counters into rates and averages is left to a capsule
+//! (see `capsules_system::kernel_stats`), which tools such as the process
+//! console query through the [`KernelStatistics`] trait.
+//!
+//! Boards can also register named [`Metric`]s, such as counters kept by
+//! capsules or the last reading of a sensor, in a [`MetricList`] so they can
+//! be inspected at runtime, for example with the `watch` command of the
+//! process console.
+
+use core::cell::Cell;
+
+use crate::platform::chip::InterruptService;
+use crate::utilities::counters::{SaturatingCounter, WrappingCounter};
+
+/// Number of times events happened in the kernel's main loop since boot.
+///
+/// All counters wrap around on overflow.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct KernelLoopCounters {
+    /// Times the kernel handled interrupts and deferred calls.
+    pub kernel_work: u32,
+    /// Times the kernel switched to a process.
+    pub context_switches: u32,
+    /// Times the kernel put the chip to sleep.
+    pub sleeps: u32,
+    /// Cycles spent configuring the MPU for the processes switched to,
+    /// measured with the counter given to
+    /// [`Kernel::set_cycle_counter`](crate::Kernel::set_cycle_counter), or 0
+    /// without one. Divided by `context_switches`, this is the average cost
+    /// of the MPU configuration of a context switch.
+    pub mpu_setup_cycles: u32,
+}
+
+/// Per source interrupt counts.
+pub trait InterruptCounts {
+    /// Number of interrupt sources that are counted.
+    fn num_sources(&self) -> usize;
+
+    /// Number of times interrupt `source` was serviced since boot, or `None`
+    /// if `source` is not counted.
+    fn count(&self, source: usize) -> Option<u32>;
+}
+
+/// `InterruptService` wrapper that counts how many times each interrupt is
+/// serviced.
+///
+/// Interrupts with a number of `NUM_INTERRUPTS` or higher are still passed to
+/// the wrapped service, but are not counted.
+pub struct InterruptCounter<'a, I: InterruptService, const NUM_INTERRUPTS: usize> {
+    service: &'a I,
+    counts: [WrappingCounter; NUM_INTERRUPTS],
+}
+
+impl<'a, I: InterruptService, const NUM_INTERRUPTS: usize> InterruptCounter<'a, I, NUM_INTERRUPTS> {
+    pub fn new(service: &'a I) -> Self {
+        Self {
+            service,
+            counts: [const { WrappingCounter::new() }; NUM_INTERRUPTS],
+        }
+    }
+}
+
+impl<I: InterruptService, const NUM_INTERRUPTS: usize> InterruptService
+    for InterruptCounter<'_, I, NUM_INTERRUPTS>
+{
+    unsafe fn service_interrupt(&self, interrupt: u32) -> bool {
+        let handled = self.service.service_interrupt(interrupt);
+        if handled {
+            if let Some(count) = self.counts.get(interrupt as usize) {
+                count.increment();
+            }
+        }
+        handled
+    }
+}
+
+impl<I: InterruptService, const NUM_INTERRUPTS: usize> InterruptCounts
+    for InterruptCounter<'_, I, NUM_INTERRUPTS>
+{
+    fn num_sources(&self) -> usize {
+        NUM_INTERRUPTS
+    }
+
+    fn count(&self, source: usize) -> Option<u32> {
+        self.counts.get(source).map(|count| count.get())
+    }
+}
+
+/// Statistics computed from the kernel counters over time.
+///
+/// Averages are reported over three sliding windows of one, five and fifteen
+/// minutes, in that order. Fractional values are scaled by 100.
+pub trait KernelStatistics {
+    /// Seconds elapsed since the statistics started being collected.
+    fn uptime_seconds(&self) -> u32;
+
+    /// Raw kernel loop counters since boot.
+    fn loop_counters(&self) -> KernelLoopCounters;
+
+    /// Context switches per second during the last sample period.
+    fn context_switch_rate(&self) -> u32;
+
+    /// Average context switches per second over each window, scaled by 100.
+    fn context_switch_rate_averages(&self) -> [u32; 3];
+
+    /// Average number of runnable processes over each window, scaled by 100.
+    fn load_averages(&self) -> [u32; 3];
+
+    /// Number of counted interrupt sources.
+    fn interrupt_sources(&self) -> usize;
+
+    /// Number of times interrupt `source` was serviced since boot and during
+    /// the last sample period, or `None` if `source` is not counted.
+    fn interrupt_count(&self, source: usize) -> Option<(u32, u32)>;
+}
+
+/// A value that can be inspected at runtime.
+///
+/// Reading a metric must be cheap and must not start any operation: metrics
+/// that come from hardware, such as sensor readings, return the last value
+/// the capsule received.
+pub trait Metric {
+    fn value(&self) -> i64;
+}
+
+impl Metric for WrappingCounter {
+    fn value(&self) -> i64 {
+        self.get().into()
+    }
+}
+
+impl Metric for SaturatingCounter {
+    fn value(&self) -> i64 {
+        self.get().into()
+    }
+}
+
+impl Metric for Cell<u32> {
+    fn value(&self) -> i64 {
+        self.get().into()
+    }
+}
+
+impl Metric for Cell<i32> {
+    fn value(&self) -> i64 {
+        self.get().into()
+    }
+}
+
+/// Named metrics registered by a board.
+pub trait Metrics {
+    /// Number of metrics.
+    fn num_metrics(&self) -> usize;
+
+    /// Name and current value of metric `index`.
+    fn metric(&self, index: usize) -> Option<(&'static str, i64)>;
+
+    /// Index of the metric called `name`.
+    fn find_metric(&self, name: &str) -> Option<usize> {
+        (0..self.num_metrics()).find(|index| {
+            self.metric(*index)
+                .is_some_and(|(metric_name, _)| metric_name == name)
+        })
+    }
+}
+
+/// [`Metrics`] from a list of names and metrics.
+pub struct MetricList<'a> {
+    metrics: &'a [(&'static str, &'a dyn Metric)],
+}
+
+impl<'a> MetricList<'a> {
+    pub const fn new(metrics: &'a [(&'static str, &'a dyn Metric)]) -> Self {
+        Self { metrics }
+    }
+}
+
+impl Metrics for MetricList<'_> {
+    fn num_metrics(&self) -> usize {
+        self.metrics.len()
+    }
+
+    fn metric(&self, index: usize) -> Option<(&'static str, i64)> {
+        self.metrics
+            .get(index)
+            .map(|(name, metric)| (*name, metric.value()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Metric, MetricList, Metrics};
+    use crate::utilities::counters::WrappingCounter;
+    use core::cell::Cell;
+
+    #[test]
+    fn test_metric_list() {
+        let packets = WrappingCounter::new();
+        let temperature = Cell::new(-4i32);
+        let list: [(&'static str, &dyn Metric); 2] =
+            [("packets", &packets), ("temperature", &temperature)];
+        let metrics = MetricList::new(&list);
+
+        packets.increment();
+        temperature.set(21);
+        assert_eq!(metrics.num_metrics(), 2);
+        assert_eq!(metrics.metric(0), Some(("packets", 1)));
+        assert_eq!(metrics.metric(1), Some(("temperature", 21)));
+        assert_eq!(metrics.metric(2), None);
+        assert_eq!(metrics.find_metric("temperature"), Some(1));
+        assert_eq!(metrics.find_metric("pressure"), None);
+    }
+}
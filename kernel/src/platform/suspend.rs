@@ -0,0 +1,61 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Interfaces for suspending and resuming capsules at runtime.
+//!
+//! Boards can mark selected capsules as "suspendable". A suspended capsule no
+//! longer accepts new operations from userspace, and, if the capsule (or the
+//! peripheral beneath it) implements [`Suspendable`], it is asked to mask its
+//! interrupts until it is resumed. This allows a misbehaving driver to be
+//! isolated on a deployed device without reflashing it.
+//!
+//! The registry of suspendable capsules is implemented outside of the core
+//! kernel (see `capsules_system::suspendable_drivers`). Tools such as the
+//! process console interact with it through the [`SuspendControl`] trait.
+
+use crate::ErrorCode;
+
+/// A driver or peripheral that can be quiesced at runtime.
+pub trait Suspendable {
+    /// Stop generating interrupts and stop starting new hardware operations.
+    ///
+    /// Any state needed to later resume must be preserved.
+    fn suspend(&self);
+
+    /// Undo a previous call to [`Suspendable::suspend`], re-enabling any
+    /// interrupts that are still needed.
+    fn resume(&self);
+}
+
+/// Runtime control over a collection of suspendable capsules.
+///
+/// Capsules are identified by their index in the collection or by the name the
+/// board registered them with.
+pub trait SuspendControl {
+    /// Number of capsules registered as suspendable.
+    fn count(&self) -> usize;
+
+    /// Name of the capsule at `index`, or `None` if `index` is out of range.
+    fn name(&self, index: usize) -> Option<&'static str>;
+
+    /// Driver number of the capsule at `index`, or `None` if `index` is out of
+    /// range.
+    fn driver_num(&self, index: usize) -> Option<usize>;
+
+    /// Whether the capsule at `index` is currently suspended, or `None` if
+    /// `index` is out of range.
+    fn is_suspended(&self, index: usize) -> Option<bool>;
+
+    /// Suspend the capsule registered as `name`.
+    ///
+    /// Returns `Err(ErrorCode::INVAL)` if no capsule is registered with this
+    /// name and `Err(ErrorCode::ALREADY)` if it is already suspended.
+    fn suspend(&self, name: &str) -> Result<(), ErrorCode>;
+
+    /// Resume the capsule registered as `name`.
+    ///
+    /// Returns `Err(ErrorCode::INVAL)` if no capsule is registered with this
+    /// name and `Err(ErrorCode::ALREADY)` if it is not suspended.
+    fn resume(&self, name: &str) -> Result<(), ErrorCode>;
+}
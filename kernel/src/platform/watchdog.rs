@@ -9,13 +9,20 @@
 /// This trait is called from the `kernel_loop()` code to setup
 /// and maintain the watchdog timer.
 /// It is up to the specific `Chip` how it will handle watchdog interrupts.
+///
+/// The kernel only sets up the watchdog when the kernel loop starts. To also
+/// catch hangs during board setup, a chip can provide a way to arm its
+/// watchdog early, at the top of the board's `start()`, with a timeout longer
+/// than the whole setup. `setup()` must then take the early watchdog over and
+/// reconfigure it with the period of the kernel.
 pub trait WatchDog {
     /// This function must enable the watchdog timer and configure it to
     /// trigger regulary. The period of the timer is left to the implementation
     /// to decide. The implementation must ensure that it doesn't trigger too
     /// early (when we haven't hung for example) or too late as to not catch
     /// faults.
-    /// After calling this function the watchdog must be running.
+    /// After calling this function the watchdog must be running, with the
+    /// period of this implementation even if the watchdog was armed early.
     fn setup(&self) {}
 
     /// This function must tickle the watchdog to reset the timer.
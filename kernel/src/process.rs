@@ -25,15 +25,26 @@ use tock_tbf::types::CommandPermissions;
 // Export all process related types via `kernel::process::`.
 pub use crate::process_binary::ProcessBinary;
 pub use crate::process_checker::AcceptedCredential;
+pub use crate::process_checker::RollbackProtection;
 pub use crate::process_checker::{ProcessCheckerMachine, ProcessCheckerMachineClient};
+pub use crate::process_checkpoint::{
+    ProcessCheckpoint, ProcessCheckpointClient, ProcessCheckpointer,
+};
+pub use crate::process_install::{ProcessInstall, ProcessInstallClient, ProcessInstaller};
 pub use crate::process_loading::load_processes;
 pub use crate::process_loading::ProcessLoadError;
 pub use crate::process_loading::SequentialProcessLoaderMachine;
-pub use crate::process_loading::{ProcessLoadingAsync, ProcessLoadingAsyncClient};
+pub use crate::process_loading::{
+    DynamicLoader, DynamicProcessLoading, DynamicProcessLoadingClient,
+};
+pub use crate::process_loading::{
+    ProcessLoadingAsync, ProcessLoadingAsyncClient, ProcessReload, ProcessSwapping,
+};
 pub use crate::process_policies::{ProcessFaultPolicy, ProcessStandardStoragePermissionsPolicy};
 pub use crate::process_printer::{ProcessPrinter, ProcessPrinterContext};
 pub use crate::process_standard::ProcessStandard;
 pub use crate::process_standard::{ProcessStandardDebug, ProcessStandardDebugFull};
+pub use crate::process_swap::{ProcessSwap, ProcessSwapClient, SwapIn};
 
 /// Userspace process identifier.
 ///
@@ -166,6 +177,12 @@ impl ProcessId {
         }
     }
 
+    /// Get the slot of this app in the processes array, even if the app is not
+    /// in it, for example because it is swapped out.
+    pub(crate) fn slot_index(&self) -> usize {
+        self.index
+    }
+
     /// Get a `usize` unique identifier for the app this `ProcessId` refers to.
     ///
     /// This function should not generally be used, instead code should just use
@@ -324,6 +341,11 @@ impl BinaryVersion {
     pub fn new(value: NonZeroU32) -> Self {
         Self(value)
     }
+
+    /// Returns the version number.
+    pub fn get(&self) -> NonZeroU32 {
+        self.0
+    }
 }
 
 /// This trait represents a generic process that the Tock scheduler can
@@ -349,6 +371,19 @@ pub trait Process {
     /// Returns how many times this process has been restarted.
     fn get_restart_count(&self) -> usize;
 
+    /// Returns information about the most recent fault of this process. This
+    /// is kept when the process restarts, so a restarted process can find out
+    /// why it was restarted. Returns `None` if the process never faulted.
+    fn get_last_fault(&self) -> Option<FaultRecord>;
+
+    /// Returns the CPU time this process used. This is kept when the process
+    /// restarts.
+    fn get_stats(&self) -> ProcessStats;
+
+    /// Record that this process was run for `time_us` microseconds and
+    /// `cycles` cycles.
+    fn add_run(&self, time_us: u32, cycles: u64);
+
     /// Get the name of the process. Used for IPC.
     fn get_process_name(&self) -> &'static str;
 
@@ -693,6 +728,11 @@ pub trait Process {
     /// the process will not run again).
     fn remove_mpu_region(&self, region: mpu::Region) -> Result<(), ErrorCode>;
 
+    /// Removes the MPU regions added with `add_mpu_region` that overlap the
+    /// memory from `start` to `end`, for example because that memory is about
+    /// to hold another process. Returns the number of removed regions.
+    fn remove_mpu_regions_in(&self, start: usize, end: usize) -> usize;
+
     // grants
 
     /// Allocate memory from the grant region and store the reference in the
@@ -848,6 +888,15 @@ pub trait Process {
     /// binary representation. Returns `ErrorCode::FAIL` on an internal error.
     fn get_stored_state(&self, out: &mut [u8]) -> Result<usize, ErrorCode>;
 
+    /// Replace the stored state of the process with `state`, written by
+    /// [`get_stored_state`](Process::get_stored_state). Once resumed, the
+    /// process continues from the new state, even if it was yielded when it
+    /// was stopped.
+    ///
+    /// Returns `ErrorCode::BUSY` if the process is not stopped, and
+    /// `ErrorCode::INVAL` if `state` is not a valid stored state.
+    fn set_stored_state(&self, state: &[u8]) -> Result<(), ErrorCode>;
+
     /// Print out the full state of the process: its memory map, its context,
     /// and the state of the memory protection unit (MPU).
     fn print_full_process(&self, writer: &mut dyn Write);
@@ -1162,6 +1211,35 @@ pub struct ProcessAddresses {
     pub sram_stack_bottom: Option<usize>,
 }
 
+/// Information the kernel records when a process faults.
+#[derive(Copy, Clone)]
+pub struct FaultRecord {
+    /// How many times the process has faulted since the kernel started.
+    pub count: usize,
+    /// The stack pointer when the process faulted, if the architecture
+    /// reports it.
+    pub stack_pointer: Option<usize>,
+    /// The last syscall the process called before it faulted, if known.
+    pub last_syscall: Option<Syscall>,
+}
+
+/// CPU time a process used since it was loaded.
+///
+/// The time is measured with the scheduler timer, and cycles with the cycle
+/// counter given to [`Kernel::set_cycle_counter`](crate::Kernel::set_cycle_counter).
+/// Time the kernel spends handling the system calls of the process is charged
+/// to the process, as it runs during its timeslices.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+pub struct ProcessStats {
+    /// Microseconds the process ran for. This stays 0 on boards without a
+    /// scheduler timer, and with schedulers that run processes cooperatively.
+    pub cpu_time_us: u64,
+    /// Cycles the process ran for, or 0 without a cycle counter.
+    pub cpu_cycles: u64,
+    /// How many times the process was run.
+    pub runs: u32,
+}
+
 /// Collection of process state related to the size in memory of various process
 /// structures.
 pub struct ProcessSizes {
@@ -34,6 +34,15 @@ pub enum ProcessCheckError {
     /// credentials increments this counter.
     CredentialsRejected(u32),
 
+    /// The binary is older than a version of the same application that
+    /// already ran, see [`RollbackProtection`].
+    VersionRolledBack {
+        /// Version of the binary.
+        version: u32,
+        /// Lowest version allowed to run.
+        minimum: u32,
+    },
+
     /// Error in the kernel implementation.
     InternalError,
 }
@@ -49,6 +58,10 @@ impl fmt::Debug for ProcessCheckError {
                 write!(f, "Credential {} rejected", index)
             }
 
+            ProcessCheckError::VersionRolledBack { version, minimum } => {
+                write!(f, "Version {} older than minimum {}", version, minimum)
+            }
+
             ProcessCheckError::InternalError => write!(f, "Error in kernel. Likely a bug."),
         }
     }
@@ -205,6 +218,32 @@ impl Compress for () {
     }
 }
 
+/// Rollback protection: the lowest binary version allowed to run for each
+/// application.
+///
+/// Once a version of an application ran, an attacker could try to install an
+/// older version with a known vulnerability, which is still correctly signed.
+/// To prevent this, the process loader refuses binaries older than the
+/// minimum version recorded for their [`ShortId`], and raises the minimum each
+/// time it loads a newer binary. Implementations keep the minimums in
+/// nonvolatile storage so that they survive reboots.
+///
+/// Only applications with a fixed `ShortId` are protected, since the
+/// `ShortId` identifies the application across binaries.
+pub trait RollbackProtection {
+    /// The lowest version of `short_id` allowed to run, 0 if none was
+    /// recorded.
+    fn minimum_version(&self, short_id: ShortId) -> u32;
+
+    /// Record that `version` of `short_id` was loaded, so that older versions
+    /// are refused from now on. Lower versions than the current minimum are
+    /// ignored.
+    ///
+    /// Returns `Err(ErrorCode::NOMEM)` if no more applications can be
+    /// recorded.
+    fn raise_minimum_version(&self, short_id: ShortId, version: u32) -> Result<(), ErrorCode>;
+}
+
 pub trait AppIdPolicy: AppUniqueness + Compress {}
 impl<T: AppUniqueness + Compress> AppIdPolicy for T {}
 
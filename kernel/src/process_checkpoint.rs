@@ -0,0 +1,546 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Checkpoint stopped processes to nonvolatile storage, and restore them after
+//! a reboot.
+//!
+//! A checkpoint holds the memory the process can access, from the start of its
+//! RAM to its application break, and its stored state (registers and status
+//! flags). Restoring it into the same process after a reboot lets the process
+//! continue a long computation where it left off, for example on devices that
+//! are powered off between periods of activity.
+//!
+//! Only the process itself is restored, not the kernel state it had: its
+//! grants, allowed buffers and subscribed upcalls are those of the process
+//! after the reboot. A restored process continues as if its last system call
+//! returned, and must set up what it needs from the kernel again. Processes
+//! are matched to checkpoints by their index in the processes array, name,
+//! addresses, binary version and binary identity, so a checkpoint can only be
+//! restored into the same binary loaded at the same place. The identity of a
+//! binary is the start of the credential the credential checker accepted for
+//! it, which is a hash or signature of the binary. Binaries that run without
+//! an accepted credential are identified by a 32 bit hash of the binary
+//! instead, which detects a changed binary but, unlike a credential, can be
+//! forged.
+//!
+//! Usage
+//! -----
+//!
+//! See `components::process_checkpoint`. Then, for example:
+//!
+//! ```rust,ignore
+//! // Before powering off.
+//! process.stop();
+//! checkpointer.checkpoint(process.processid())?;
+//!
+//! // After the reboot, once the process is loaded.
+//! process.stop();
+//! checkpointer.restore(process.processid())?;
+//! // And once restore_done() is called with Ok(()).
+//! process.resume();
+//! ```
+
+use core::cmp;
+
+use crate::capabilities::ProcessManagementCapability;
+use crate::errorcode::ErrorCode;
+use crate::hil::nonvolatile_storage::{NonvolatileStorage, NonvolatileStorageClient};
+use crate::kernel::Kernel;
+use crate::process::{Process, ProcessId, State};
+use crate::utilities::cells::{OptionalCell, TakeCell};
+
+/// Client of [`ProcessCheckpoint`].
+pub trait ProcessCheckpointClient {
+    /// The checkpoint of the process `process_id` was written. On error, its
+    /// slot holds no checkpoint.
+    fn checkpoint_done(&self, process_id: ProcessId, result: Result<(), ErrorCode>);
+
+    /// The process `process_id` was restored from its checkpoint, and
+    /// continues from it once it is resumed. If the restore failed after the
+    /// memory of the process was changed, the process is restarted.
+    fn restore_done(&self, process_id: ProcessId, result: Result<(), ErrorCode>);
+}
+
+/// Saving processes to nonvolatile storage and restoring them later.
+pub trait ProcessCheckpoint<'a> {
+    fn set_client(&self, client: &'a dyn ProcessCheckpointClient);
+
+    /// Start to write a checkpoint of the process `process_id`, replacing its
+    /// previous checkpoint. The process must stay stopped until
+    /// `checkpoint_done` is called.
+    ///
+    /// Returns `Err(ErrorCode::INVAL)` if no such process exists or it is not
+    /// stopped, `Err(ErrorCode::SIZE)` if its memory does not fit in a slot,
+    /// `Err(ErrorCode::NOMEM)` if there is no slot for it, and
+    /// `Err(ErrorCode::BUSY)` if another checkpoint or restore is in progress.
+    fn checkpoint(&self, process_id: ProcessId) -> Result<(), ErrorCode>;
+
+    /// Start to restore the process `process_id`, which must be stopped, from
+    /// its checkpoint. `restore_done` fails with `ErrorCode::NODEVICE` if
+    /// there is no checkpoint for the process.
+    ///
+    /// Returns the same errors as [`checkpoint`](ProcessCheckpoint::checkpoint).
+    fn restore(&self, process_id: ProcessId) -> Result<(), ErrorCode>;
+}
+
+/// Marks a valid checkpoint, and is its format version.
+const MAGIC: u32 = 0x5043_4b32;
+
+/// Space at the start of a slot for the header, which is followed by the
+/// memory of the process.
+const HEADER_LEN: usize = 256;
+
+const MAGIC_OFFSET: usize = 0;
+const FLASH_START_OFFSET: usize = 4;
+const SRAM_START_OFFSET: usize = 8;
+const APP_BRK_OFFSET: usize = 12;
+const STATE_LEN_OFFSET: usize = 16;
+const VERSION_OFFSET: usize = 20;
+const IDENTITY_KIND_OFFSET: usize = 24;
+const IDENTITY_OFFSET: usize = 28;
+const IDENTITY_LEN: usize = 32;
+const NAME_OFFSET: usize = IDENTITY_OFFSET + IDENTITY_LEN;
+const NAME_LEN: usize = 32;
+const STATE_OFFSET: usize = NAME_OFFSET + NAME_LEN;
+
+// The largest stored state, of RV32I processes, is 148 bytes.
+const _: () = assert!(HEADER_LEN - STATE_OFFSET >= 148);
+
+/// Kind of binary identity that is a hash of the binary, for binaries without
+/// an accepted credential. Otherwise the kind is the format of the credential.
+const BINARY_HASH: u32 = u32::MAX;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Phase {
+    /// Marking the slot as holding no checkpoint.
+    Invalidating,
+    /// Writing the memory of the process, `done` bytes so far.
+    Saving { done: usize },
+    /// Writing the header, which makes the checkpoint valid.
+    WritingHeader,
+    /// Reading the header of the checkpoint.
+    ReadingHeader,
+    /// Reading the memory of the process, `done` bytes so far.
+    Restoring { done: usize },
+}
+
+#[derive(Clone, Copy)]
+struct Operation {
+    process_id: ProcessId,
+    slot: usize,
+    /// Start and length of the memory in the checkpoint.
+    start: usize,
+    len: usize,
+    phase: Phase,
+}
+
+/// Writes checkpoints to a region of nonvolatile storage.
+///
+/// The process in slot `i` of the processes array has its checkpoint at
+/// `storage_start + i * slot_size` in the address space of the storage, if
+/// `i` is less than `num_slots`.
+pub struct ProcessCheckpointer<'a> {
+    kernel: &'static Kernel,
+    storage: &'a dyn NonvolatileStorage<'a>,
+    /// Copies memory to and from storage, one chunk at a time.
+    buffer: TakeCell<'static, [u8]>,
+    storage_start: usize,
+    slot_size: usize,
+    num_slots: usize,
+    operation: OptionalCell<Operation>,
+    client: OptionalCell<&'a dyn ProcessCheckpointClient>,
+}
+
+impl<'a> ProcessCheckpointer<'a> {
+    /// Length of the header at the start of each slot, and the minimum length
+    /// of the buffer.
+    pub const HEADER_LEN: usize = HEADER_LEN;
+
+    /// `buffer` must be at least [`HEADER_LEN`](Self::HEADER_LEN) bytes long.
+    pub fn new(
+        kernel: &'static Kernel,
+        storage: &'a dyn NonvolatileStorage<'a>,
+        buffer: &'static mut [u8],
+        storage_start: usize,
+        slot_size: usize,
+        num_slots: usize,
+        _capability_management: &dyn ProcessManagementCapability,
+    ) -> Self {
+        Self {
+            kernel,
+            storage,
+            buffer: TakeCell::new(buffer),
+            storage_start,
+            slot_size,
+            num_slots,
+            operation: OptionalCell::empty(),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    fn slot_address(&self, slot: usize) -> usize {
+        self.storage_start + slot * self.slot_size
+    }
+
+    /// Check that `process_id` is a stopped process with a slot, and return
+    /// the slot.
+    fn stopped_process_slot(&self, process_id: ProcessId) -> Result<usize, ErrorCode> {
+        if self.operation.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        if !self.is_stopped(process_id) {
+            return Err(ErrorCode::INVAL);
+        }
+        process_id
+            .index()
+            .filter(|&slot| slot < self.num_slots)
+            .ok_or(ErrorCode::NOMEM)
+    }
+
+    fn is_stopped(&self, process_id: ProcessId) -> bool {
+        self.kernel.process_map_or(false, process_id, |process| {
+            matches!(process.get_state(), State::Stopped(_))
+        })
+    }
+
+    /// Start the next step of `operation`.
+    fn step(&self, operation: Operation) -> Result<(), ErrorCode> {
+        // The process must stay stopped, as its memory is copied.
+        if !self.is_stopped(operation.process_id) {
+            return Err(ErrorCode::FAIL);
+        }
+        let buffer = self.buffer.take().ok_or(ErrorCode::FAIL)?;
+        let address = self.slot_address(operation.slot);
+        self.operation.set(operation);
+        let result = match operation.phase {
+            Phase::Invalidating => {
+                buffer[..4].copy_from_slice(&[0; 4]);
+                self.storage.write(buffer, address, 4)
+            }
+            Phase::Saving { done } => {
+                let length = cmp::min(buffer.len(), operation.len - done);
+                // SAFETY: the memory is accessible to the process, which is
+                // stopped, so nothing changes it while it is copied.
+                unsafe {
+                    core::ptr::copy_nonoverlapping(
+                        (operation.start + done) as *const u8,
+                        buffer.as_mut_ptr(),
+                        length,
+                    );
+                }
+                self.storage
+                    .write(buffer, address + HEADER_LEN + done, length)
+            }
+            Phase::WritingHeader => match self.fill_header(operation, buffer) {
+                Ok(()) => self.storage.write(buffer, address, HEADER_LEN),
+                Err(e) => {
+                    self.buffer.replace(buffer);
+                    Err(e)
+                }
+            },
+            Phase::ReadingHeader => self.storage.read(buffer, address, HEADER_LEN),
+            Phase::Restoring { done } => {
+                let length = cmp::min(buffer.len(), operation.len - done);
+                self.storage
+                    .read(buffer, address + HEADER_LEN + done, length)
+            }
+        };
+        result.inspect_err(|_| self.operation.clear())
+    }
+
+    fn fill_header(&self, operation: Operation, header: &mut [u8]) -> Result<(), ErrorCode> {
+        header[..HEADER_LEN].fill(0);
+        let state_len =
+            self.kernel
+                .process_map_or(Err(ErrorCode::FAIL), operation.process_id, |process| {
+                    let name = process.get_process_name().as_bytes();
+                    let name_len = cmp::min(name.len(), NAME_LEN);
+                    header[NAME_OFFSET..NAME_OFFSET + name_len].copy_from_slice(&name[..name_len]);
+                    write_u32(
+                        header,
+                        FLASH_START_OFFSET,
+                        process.get_addresses().flash_start as u32,
+                    );
+                    write_u32(header, VERSION_OFFSET, binary_version(process));
+                    let kind = binary_identity(
+                        process,
+                        &mut header[IDENTITY_OFFSET..IDENTITY_OFFSET + IDENTITY_LEN],
+                    );
+                    write_u32(header, IDENTITY_KIND_OFFSET, kind);
+                    process.get_stored_state(&mut header[STATE_OFFSET..HEADER_LEN])
+                })?;
+        write_u32(header, MAGIC_OFFSET, MAGIC);
+        write_u32(header, SRAM_START_OFFSET, operation.start as u32);
+        write_u32(
+            header,
+            APP_BRK_OFFSET,
+            (operation.start + operation.len) as u32,
+        );
+        write_u32(header, STATE_LEN_OFFSET, state_len as u32);
+        Ok(())
+    }
+
+    /// Check the header of the checkpoint against the process, and restore the
+    /// application break and stored state of the process from it. Returns the
+    /// length of the memory in the checkpoint.
+    fn apply_header(&self, operation: Operation, header: &[u8]) -> Result<usize, ErrorCode> {
+        if read_u32(header, MAGIC_OFFSET) != MAGIC {
+            return Err(ErrorCode::NODEVICE);
+        }
+        let app_brk = read_u32(header, APP_BRK_OFFSET) as usize;
+        let state_len = read_u32(header, STATE_LEN_OFFSET) as usize;
+        if read_u32(header, SRAM_START_OFFSET) as usize != operation.start
+            || app_brk < operation.start
+            || app_brk - operation.start > self.slot_size - HEADER_LEN
+            || state_len > HEADER_LEN - STATE_OFFSET
+        {
+            return Err(ErrorCode::INVAL);
+        }
+
+        self.kernel
+            .process_map_or(Err(ErrorCode::FAIL), operation.process_id, |process| {
+                let name = process.get_process_name().as_bytes();
+                let name_len = cmp::min(name.len(), NAME_LEN);
+                let addresses = process.get_addresses();
+                let mut identity = [0; IDENTITY_LEN];
+                let kind = binary_identity(process, &mut identity);
+                if read_u32(header, FLASH_START_OFFSET) != addresses.flash_start as u32
+                    || header[NAME_OFFSET..NAME_OFFSET + name_len] != name[..name_len]
+                    || read_u32(header, VERSION_OFFSET) != binary_version(process)
+                    || read_u32(header, IDENTITY_KIND_OFFSET) != kind
+                    || header[IDENTITY_OFFSET..IDENTITY_OFFSET + IDENTITY_LEN] != identity
+                {
+                    return Err(ErrorCode::INVAL);
+                }
+
+                process
+                    .brk(app_brk as *const u8)
+                    .or(Err(ErrorCode::NOMEM))?;
+                process
+                    .set_stored_state(&header[STATE_OFFSET..STATE_OFFSET + state_len])
+                    .inspect_err(|_| {
+                        let _ = process.brk(addresses.sram_app_brk as *const u8);
+                    })
+            })?;
+        Ok(app_brk - operation.start)
+    }
+
+    fn finish(&self, operation: Operation, result: Result<(), ErrorCode>) {
+        self.operation.clear();
+        match operation.phase {
+            Phase::Invalidating | Phase::Saving { .. } | Phase::WritingHeader => {
+                self.client
+                    .map(|client| client.checkpoint_done(operation.process_id, result));
+            }
+            Phase::ReadingHeader | Phase::Restoring { .. } => {
+                if result.is_err() && matches!(operation.phase, Phase::Restoring { .. }) {
+                    // The memory of the process is partly overwritten.
+                    self.kernel
+                        .process_map_or((), operation.process_id, |process| {
+                            process.try_restart(None)
+                        });
+                }
+                self.client
+                    .map(|client| client.restore_done(operation.process_id, result));
+            }
+        }
+    }
+
+    /// Start the next step of `operation`, and finish it on error.
+    fn continue_with(&self, operation: Operation) {
+        if let Err(e) = self.step(operation) {
+            self.finish(operation, Err(e));
+        }
+    }
+}
+
+/// The version of the binary of `process`, or 0 if it has none.
+fn binary_version(process: &dyn Process) -> u32 {
+    process
+        .binary_version()
+        .map_or(0, |version| version.get().get())
+}
+
+/// Write the identity of the binary of `process` to `identity`, and return
+/// its kind: the start of the accepted credential and its format, or else a
+/// hash of the binary and `BINARY_HASH`.
+fn binary_identity(process: &dyn Process, identity: &mut [u8]) -> u32 {
+    identity.fill(0);
+    if let Some(accepted) = process.get_credential() {
+        let data = accepted.credential.data();
+        let len = cmp::min(data.len(), identity.len());
+        identity[..len].copy_from_slice(&data[..len]);
+        return accepted.credential.format() as u32;
+    }
+
+    let addresses = process.get_addresses();
+    // SAFETY: the binary of a process is in flash, from its start to the end
+    // of the region covered by integrity checks, and stays there.
+    let binary = unsafe {
+        core::slice::from_raw_parts(
+            addresses.flash_start as *const u8,
+            addresses.flash_integrity_end as usize - addresses.flash_start,
+        )
+    };
+    write_u32(identity, 0, fnv1a(binary));
+    BINARY_HASH
+}
+
+/// The 32 bit FNV-1a hash of `data`.
+fn fnv1a(data: &[u8]) -> u32 {
+    data.iter().fold(0x811c_9dc5, |hash, &byte| {
+        (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+fn write_u32(buffer: &mut [u8], offset: usize, value: u32) {
+    buffer[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn read_u32(buffer: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        buffer[offset],
+        buffer[offset + 1],
+        buffer[offset + 2],
+        buffer[offset + 3],
+    ])
+}
+
+impl<'a> ProcessCheckpoint<'a> for ProcessCheckpointer<'a> {
+    fn set_client(&self, client: &'a dyn ProcessCheckpointClient) {
+        self.client.set(client);
+    }
+
+    fn checkpoint(&self, process_id: ProcessId) -> Result<(), ErrorCode> {
+        let slot = self.stopped_process_slot(process_id)?;
+        let addresses = self
+            .kernel
+            .process_map_or(None, process_id, |process| Some(process.get_addresses()))
+            .ok_or(ErrorCode::INVAL)?;
+        let len = addresses.sram_app_brk - addresses.sram_start;
+        if len > self.slot_size.saturating_sub(HEADER_LEN) {
+            return Err(ErrorCode::SIZE);
+        }
+        self.step(Operation {
+            process_id,
+            slot,
+            start: addresses.sram_start,
+            len,
+            phase: Phase::Invalidating,
+        })
+    }
+
+    fn restore(&self, process_id: ProcessId) -> Result<(), ErrorCode> {
+        let slot = self.stopped_process_slot(process_id)?;
+        let start = self
+            .kernel
+            .process_map_or(None, process_id, |process| {
+                Some(process.get_addresses().sram_start)
+            })
+            .ok_or(ErrorCode::INVAL)?;
+        self.step(Operation {
+            process_id,
+            slot,
+            start,
+            len: 0,
+            phase: Phase::ReadingHeader,
+        })
+    }
+}
+
+impl NonvolatileStorageClient for ProcessCheckpointer<'_> {
+    fn read_done(&self, buffer: &'static mut [u8], length: usize) {
+        let Some(operation) = self.operation.get() else {
+            self.buffer.replace(buffer);
+            return;
+        };
+        match operation.phase {
+            Phase::ReadingHeader => {
+                let result = if length < HEADER_LEN {
+                    Err(ErrorCode::FAIL)
+                } else if !self.is_stopped(operation.process_id) {
+                    Err(ErrorCode::FAIL)
+                } else {
+                    self.apply_header(operation, buffer)
+                };
+                self.buffer.replace(buffer);
+                match result {
+                    Ok(len) => self.continue_with(Operation {
+                        len,
+                        phase: Phase::Restoring { done: 0 },
+                        ..operation
+                    }),
+                    Err(e) => self.finish(operation, Err(e)),
+                }
+            }
+            Phase::Restoring { done } => {
+                let length = cmp::min(length, operation.len - done);
+                if self.is_stopped(operation.process_id) {
+                    // SAFETY: the memory is accessible to the process, which
+                    // is stopped, and is written back where it was saved from.
+                    unsafe {
+                        core::ptr::copy_nonoverlapping(
+                            buffer.as_ptr(),
+                            (operation.start + done) as *mut u8,
+                            length,
+                        );
+                    }
+                }
+                self.buffer.replace(buffer);
+                let done = done + length;
+                if length == 0 {
+                    self.finish(operation, Err(ErrorCode::FAIL));
+                } else if done < operation.len {
+                    self.continue_with(Operation {
+                        phase: Phase::Restoring { done },
+                        ..operation
+                    });
+                } else if self.is_stopped(operation.process_id) {
+                    self.finish(operation, Ok(()));
+                } else {
+                    self.finish(operation, Err(ErrorCode::FAIL));
+                }
+            }
+            _ => {
+                self.buffer.replace(buffer);
+            }
+        }
+    }
+
+    fn write_done(&self, buffer: &'static mut [u8], length: usize) {
+        self.buffer.replace(buffer);
+        let Some(operation) = self.operation.get() else {
+            return;
+        };
+        let next = match operation.phase {
+            Phase::Invalidating => Phase::Saving { done: 0 },
+            Phase::Saving { done } if length > 0 && done + length < operation.len => {
+                Phase::Saving {
+                    done: done + length,
+                }
+            }
+            Phase::Saving { .. } if length > 0 => Phase::WritingHeader,
+            Phase::WritingHeader if length == HEADER_LEN => {
+                return self.finish(operation, Ok(()));
+            }
+            _ => return self.finish(operation, Err(ErrorCode::FAIL)),
+        };
+        self.continue_with(Operation {
+            phase: next,
+            ..operation
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fnv1a_known_answers() {
+        assert_eq!(fnv1a(b""), 0x811c_9dc5);
+        assert_eq!(fnv1a(b"a"), 0xe40c_292c);
+        assert_eq!(fnv1a(b"foobar"), 0xbf9c_f968);
+    }
+}
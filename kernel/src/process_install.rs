@@ -0,0 +1,538 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Install process binaries stored outside the app flash region.
+//!
+//! [`ProcessInstaller`] copies a TBF binary from a region of other storage,
+//! such as an external flash chip, into the app flash region with a
+//! [`DynamicProcessLoading`] loader, which then loads it as a new process. This
+//! lets a board update individual applications at runtime, from binaries an
+//! application downloaded into that storage, without reflashing the kernel.
+//!
+//! The installer wraps the dynamic loader. Other users of the loader, like the
+//! `app_loader` capsule, use it through the installer, which refuses their
+//! requests with `BUSY` while it installs a binary, and forwards the loader
+//! events of their installations to them.
+//!
+//! Usage
+//! -----
+//!
+//! See `components::loader::install`. Then, for example:
+//!
+//! ```rust,ignore
+//! // Install the binary at the start of the image region.
+//! installer.install(0)?;
+//! ```
+
+use core::cmp;
+
+use crate::errorcode::ErrorCode;
+use crate::hil::nonvolatile_storage::{NonvolatileStorage, NonvolatileStorageClient};
+use crate::process_loading::{
+    DynamicProcessLoading, DynamicProcessLoadingClient, ProcessLoadError,
+};
+use crate::utilities::cells::{OptionalCell, TakeCell};
+
+/// Length of the part of the TBF header that holds the length of the binary.
+const TBF_HEADER_LENGTHS_LEN: usize = 8;
+
+/// Client of a [`ProcessInstall`].
+pub trait ProcessInstallClient {
+    /// The installation started with [`ProcessInstall::install`] finished.
+    /// On success, the new process is loaded and running.
+    fn install_done(&self, result: Result<(), ErrorCode>);
+}
+
+/// Installing process binaries from storage outside the app flash region.
+pub trait ProcessInstall<'a> {
+    /// Set the client to notify when installations finish.
+    fn set_client(&self, client: &'a dyn ProcessInstallClient);
+
+    /// Start to install the binary at `offset` in the image storage.
+    ///
+    /// Returns `Err(ErrorCode::INVAL)` if `offset` is outside of the image
+    /// storage, and `Err(ErrorCode::BUSY)` if an installation is in progress.
+    fn install(&self, offset: usize) -> Result<(), ErrorCode>;
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    /// Reading the lengths in the TBF header of the binary at `address`.
+    ReadingHeader { address: usize },
+    /// Reading the chunk of the binary that starts at `start`.
+    Reading {
+        address: usize,
+        length: usize,
+        start: usize,
+    },
+    /// Writing the chunk of the binary from `start` to `end`, of which the
+    /// bytes before `written` were written.
+    Writing {
+        address: usize,
+        length: usize,
+        start: usize,
+        written: usize,
+        end: usize,
+    },
+    /// The loader checks and loads the new binary.
+    Loading,
+}
+
+/// Installs process binaries from a region of storage.
+///
+/// The region starts at `storage_start` in the address space of `storage`, and
+/// is `storage_len` bytes long. Binaries can be stored anywhere in it.
+pub struct ProcessInstaller<'a> {
+    /// Loader that writes the binaries to app flash and loads them.
+    loader: &'a dyn DynamicProcessLoading<'a>,
+    /// Storage the binaries are installed from.
+    storage: &'a dyn NonvolatileStorage<'a>,
+    storage_start: usize,
+    storage_len: usize,
+    /// Buffer that chunks of binaries are copied through.
+    buffer: TakeCell<'static, [u8]>,
+    state: OptionalCell<State>,
+    client: OptionalCell<&'a dyn ProcessInstallClient>,
+    /// Client of the loader for installations by other users of the loader.
+    loader_client: OptionalCell<&'a dyn DynamicProcessLoadingClient>,
+}
+
+impl<'a> ProcessInstaller<'a> {
+    /// `buffer` limits the length of each copy from storage to app flash, and
+    /// must be at least 8 bytes long.
+    pub fn new(
+        loader: &'a dyn DynamicProcessLoading<'a>,
+        storage: &'a dyn NonvolatileStorage<'a>,
+        storage_start: usize,
+        storage_len: usize,
+        buffer: &'static mut [u8],
+    ) -> Self {
+        Self {
+            loader,
+            storage,
+            storage_start,
+            storage_len,
+            buffer: TakeCell::new(buffer),
+            state: OptionalCell::empty(),
+            client: OptionalCell::empty(),
+            loader_client: OptionalCell::empty(),
+        }
+    }
+
+    fn is_installing(&self) -> bool {
+        self.state.is_some()
+    }
+
+    /// Read the chunk of the binary that starts at `start`.
+    fn read_chunk(&self, address: usize, length: usize, start: usize) -> Result<(), ErrorCode> {
+        let buffer = self.buffer.take().ok_or(ErrorCode::BUSY)?;
+        let chunk_len = cmp::min(buffer.len(), length - start);
+        self.state.set(State::Reading {
+            address,
+            length,
+            start,
+        });
+        self.storage.read(buffer, address + start, chunk_len)
+    }
+
+    /// Write the rest of the chunk of the binary in the buffer, or continue
+    /// with the next chunk once it is written.
+    fn write_chunk(&self, state: State) -> Result<(), ErrorCode> {
+        match state {
+            State::Writing {
+                start,
+                written,
+                end,
+                ..
+            } if written < end => {
+                self.state.set(state);
+                self.buffer.map_or(Err(ErrorCode::FAIL), |buffer| {
+                    self.loader
+                        .write(&buffer[written - start..end - start], written)
+                })
+            }
+            State::Writing {
+                address,
+                length,
+                written,
+                ..
+            } if written < length => self.read_chunk(address, length, written),
+            _ => {
+                self.state.set(State::Loading);
+                self.loader.load()
+            }
+        }
+    }
+
+    /// End the installation. Unless it succeeded, this releases the space
+    /// reserved in app flash.
+    fn finish(&self, result: Result<(), ErrorCode>) {
+        if result.is_err() {
+            let _ = self.loader.abort();
+        }
+        self.state.clear();
+        self.client.map(|client| client.install_done(result));
+    }
+}
+
+impl<'a> ProcessInstall<'a> for ProcessInstaller<'a> {
+    fn set_client(&self, client: &'a dyn ProcessInstallClient) {
+        self.client.set(client);
+    }
+
+    fn install(&self, offset: usize) -> Result<(), ErrorCode> {
+        if self.is_installing() {
+            return Err(ErrorCode::BUSY);
+        }
+        if offset.saturating_add(TBF_HEADER_LENGTHS_LEN) > self.storage_len {
+            return Err(ErrorCode::INVAL);
+        }
+        let address = self.storage_start + offset;
+        let buffer = self.buffer.take().ok_or(ErrorCode::BUSY)?;
+        self.state.set(State::ReadingHeader { address });
+        self.storage
+            .read(buffer, address, TBF_HEADER_LENGTHS_LEN)
+            .inspect_err(|_| self.state.clear())
+    }
+}
+
+impl NonvolatileStorageClient for ProcessInstaller<'_> {
+    fn read_done(&self, buffer: &'static mut [u8], length: usize) {
+        let result = match self.state.get() {
+            Some(State::ReadingHeader { address }) => {
+                let app_length = buffer
+                    .get(0..TBF_HEADER_LENGTHS_LEN)
+                    .and_then(|header| header.try_into().ok())
+                    .and_then(|header| tock_tbf::parse::parse_tbf_header_lengths(header).ok())
+                    .map(|(_, _, app_length)| app_length as usize);
+                self.buffer.replace(buffer);
+                match app_length {
+                    Some(app_length)
+                        if address + app_length <= self.storage_start + self.storage_len =>
+                    {
+                        self.loader
+                            .setup(app_length)
+                            .and_then(|_| self.read_chunk(address, app_length, 0))
+                    }
+                    _ => Err(ErrorCode::INVAL),
+                }
+            }
+            Some(State::Reading {
+                address,
+                length: app_length,
+                start,
+            }) => {
+                self.buffer.replace(buffer);
+                self.write_chunk(State::Writing {
+                    address,
+                    length: app_length,
+                    start,
+                    written: start,
+                    end: start + length,
+                })
+            }
+            _ => {
+                self.buffer.replace(buffer);
+                Ok(())
+            }
+        };
+        if let Err(e) = result {
+            self.finish(Err(e));
+        }
+    }
+
+    fn write_done(&self, buffer: &'static mut [u8], _length: usize) {
+        self.buffer.replace(buffer);
+    }
+}
+
+impl<'a> DynamicProcessLoading<'a> for ProcessInstaller<'a> {
+    fn set_client(&self, client: &'a dyn DynamicProcessLoadingClient) {
+        self.loader_client.set(client);
+    }
+
+    fn setup(&self, app_length: usize) -> Result<usize, ErrorCode> {
+        if self.is_installing() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.loader.setup(app_length)
+    }
+
+    fn write(&self, data: &[u8], offset: usize) -> Result<(), ErrorCode> {
+        if self.is_installing() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.loader.write(data, offset)
+    }
+
+    fn load(&self) -> Result<(), ErrorCode> {
+        if self.is_installing() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.loader.load()
+    }
+
+    fn abort(&self) -> Result<(), ErrorCode> {
+        if self.is_installing() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.loader.abort()
+    }
+}
+
+impl DynamicProcessLoadingClient for ProcessInstaller<'_> {
+    fn write_done(&self, result: Result<(), ErrorCode>, length: usize) {
+        match self.state.get() {
+            Some(State::Writing {
+                address,
+                length: app_length,
+                start,
+                written,
+                end,
+            }) => {
+                let result = result.and_then(|()| {
+                    self.write_chunk(State::Writing {
+                        address,
+                        length: app_length,
+                        start,
+                        written: written + length,
+                        end,
+                    })
+                });
+                if let Err(e) = result {
+                    self.finish(Err(e));
+                }
+            }
+            Some(_) => {}
+            None => {
+                self.loader_client
+                    .map(|client| client.write_done(result, length));
+            }
+        }
+    }
+
+    fn load_done(&self, result: Result<(), ProcessLoadError>) {
+        match self.state.get() {
+            Some(State::Loading) => {
+                self.finish(result.map_err(|e| match e {
+                    ProcessLoadError::NotEnoughMemory => ErrorCode::NOMEM,
+                    ProcessLoadError::NoProcessSlot => ErrorCode::NOMEM,
+                    ProcessLoadError::NotLoaded => ErrorCode::ALREADY,
+                    _ => ErrorCode::INVAL,
+                }));
+            }
+            Some(_) => {}
+            None => {
+                self.loader_client.map(|client| client.load_done(result));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+
+    /// Storage that completes reads when `complete` is called.
+    struct TestStorage<'a> {
+        data: [u8; 96],
+        pending: TakeCell<'static, [u8]>,
+        read: Cell<(usize, usize)>,
+        client: OptionalCell<&'a dyn NonvolatileStorageClient>,
+    }
+
+    impl TestStorage<'_> {
+        /// Storage with `binary` at the start of the image storage, which
+        /// starts at 16.
+        fn new(binary: &[u8]) -> Self {
+            let mut data = [0xFF; 96];
+            data[16..16 + binary.len()].copy_from_slice(binary);
+            Self {
+                data,
+                pending: TakeCell::empty(),
+                read: Cell::new((0, 0)),
+                client: OptionalCell::empty(),
+            }
+        }
+
+        fn complete(&self) {
+            let (address, length) = self.read.get();
+            let buffer = self.pending.take().unwrap();
+            buffer[..length].copy_from_slice(&self.data[address..address + length]);
+            self.client.map(|client| client.read_done(buffer, length));
+        }
+    }
+
+    impl<'a> NonvolatileStorage<'a> for TestStorage<'a> {
+        fn set_client(&self, client: &'a dyn NonvolatileStorageClient) {
+            self.client.set(client);
+        }
+
+        fn read(
+            &self,
+            buffer: &'static mut [u8],
+            address: usize,
+            length: usize,
+        ) -> Result<(), ErrorCode> {
+            self.pending.replace(buffer);
+            self.read.set((address, length));
+            Ok(())
+        }
+
+        fn write(&self, _: &'static mut [u8], _: usize, _: usize) -> Result<(), ErrorCode> {
+            Err(ErrorCode::NOSUPPORT)
+        }
+    }
+
+    /// Loader that writes at most 3 bytes at a time, and completes writes and
+    /// loads when `complete` is called.
+    struct TestLoader<'a> {
+        image: [Cell<u8>; 64],
+        length: Cell<Option<usize>>,
+        written: Cell<Option<usize>>,
+        loading: Cell<bool>,
+        client: OptionalCell<&'a dyn DynamicProcessLoadingClient>,
+    }
+
+    impl TestLoader<'_> {
+        fn new() -> Self {
+            Self {
+                image: core::array::from_fn(|_| Cell::new(0)),
+                length: Cell::new(None),
+                written: Cell::new(None),
+                loading: Cell::new(false),
+                client: OptionalCell::empty(),
+            }
+        }
+
+        fn complete(&self) {
+            if let Some(written) = self.written.take() {
+                self.client.map(|client| client.write_done(Ok(()), written));
+            } else if self.loading.take() {
+                self.client.map(|client| client.load_done(Ok(())));
+            }
+        }
+    }
+
+    impl<'a> DynamicProcessLoading<'a> for TestLoader<'a> {
+        fn set_client(&self, client: &'a dyn DynamicProcessLoadingClient) {
+            self.client.set(client);
+        }
+
+        fn setup(&self, app_length: usize) -> Result<usize, ErrorCode> {
+            self.length.set(Some(app_length));
+            Ok(0)
+        }
+
+        fn write(&self, data: &[u8], offset: usize) -> Result<(), ErrorCode> {
+            let written = cmp::min(data.len(), 3);
+            for (i, byte) in data[..written].iter().enumerate() {
+                self.image[offset + i].set(*byte);
+            }
+            self.written.set(Some(written));
+            Ok(())
+        }
+
+        fn load(&self) -> Result<(), ErrorCode> {
+            self.loading.set(true);
+            Ok(())
+        }
+
+        fn abort(&self) -> Result<(), ErrorCode> {
+            self.length.set(None);
+            Ok(())
+        }
+    }
+
+    struct TestClient(Cell<Option<Result<(), ErrorCode>>>);
+
+    impl ProcessInstallClient for TestClient {
+        fn install_done(&self, result: Result<(), ErrorCode>) {
+            self.0.set(Some(result));
+        }
+    }
+
+    fn connect<'a>(
+        installer: &'a ProcessInstaller<'a>,
+        storage: &'a TestStorage<'a>,
+        loader: &'a TestLoader<'a>,
+        client: &'a TestClient,
+    ) {
+        NonvolatileStorage::set_client(storage, installer);
+        DynamicProcessLoading::set_client(loader, installer);
+        ProcessInstall::set_client(installer, client);
+    }
+
+    #[test]
+    fn copies_binary_in_chunks() {
+        // Version 2, 16 byte header, 20 byte binary.
+        let mut binary = [0; 20];
+        binary[..8].copy_from_slice(&[2, 0, 16, 0, 20, 0, 0, 0]);
+        for (i, byte) in binary[8..].iter_mut().enumerate() {
+            *byte = i as u8 + 1;
+        }
+        let storage = TestStorage::new(&binary);
+        let loader = TestLoader::new();
+        let client = TestClient(Cell::new(None));
+        static mut BUFFER: [u8; 8] = [0; 8];
+        let buffer = unsafe { &mut *core::ptr::addr_of_mut!(BUFFER) };
+        let installer = ProcessInstaller::new(&loader, &storage, 16, 80, buffer);
+        connect(&installer, &storage, &loader, &client);
+
+        assert_eq!(installer.install(0), Ok(()));
+        assert_eq!(installer.install(0), Err(ErrorCode::BUSY));
+        assert_eq!(
+            DynamicProcessLoading::setup(&installer, 4),
+            Err(ErrorCode::BUSY)
+        );
+        storage.complete();
+        assert_eq!(loader.length.get(), Some(20));
+        // Three chunks of at most 8 bytes, each written 3 bytes at a time.
+        while client.0.get().is_none() {
+            if storage.pending.is_some() {
+                storage.complete();
+            } else {
+                loader.complete();
+            }
+        }
+        assert_eq!(client.0.get(), Some(Ok(())));
+        for (written, byte) in loader.image.iter().zip(binary.iter()) {
+            assert_eq!(written.get(), *byte);
+        }
+        assert!(!installer.is_installing());
+    }
+
+    #[test]
+    fn rejects_invalid_binaries() {
+        let storage = TestStorage::new(&[0xFF; 8]);
+        let loader = TestLoader::new();
+        let client = TestClient(Cell::new(None));
+        static mut BUFFER: [u8; 8] = [0; 8];
+        let buffer = unsafe { &mut *core::ptr::addr_of_mut!(BUFFER) };
+        let installer = ProcessInstaller::new(&loader, &storage, 16, 80, buffer);
+        connect(&installer, &storage, &loader, &client);
+
+        assert_eq!(installer.install(80), Err(ErrorCode::INVAL));
+        assert_eq!(installer.install(0), Ok(()));
+        storage.complete();
+        assert_eq!(client.0.get(), Some(Err(ErrorCode::INVAL)));
+        assert_eq!(loader.length.get(), None);
+    }
+
+    #[test]
+    fn rejects_binaries_larger_than_storage() {
+        let storage = TestStorage::new(&[2, 0, 16, 0, 100, 0, 0, 0]);
+        let loader = TestLoader::new();
+        let client = TestClient(Cell::new(None));
+        static mut BUFFER: [u8; 8] = [0; 8];
+        let buffer = unsafe { &mut *core::ptr::addr_of_mut!(BUFFER) };
+        let installer = ProcessInstaller::new(&loader, &storage, 16, 80, buffer);
+        connect(&installer, &storage, &loader, &client);
+
+        assert_eq!(installer.install(0), Ok(()));
+        storage.complete();
+        assert_eq!(client.0.get(), Some(Err(ErrorCode::INVAL)));
+    }
+}
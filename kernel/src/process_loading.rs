@@ -13,23 +13,28 @@
 //! features a particular board requires.
 
 use core::cell::Cell;
+use core::cmp;
 use core::fmt;
 
 use crate::capabilities::ProcessManagementCapability;
 use crate::config;
 use crate::debug;
 use crate::deferred_call::{DeferredCall, DeferredCallClient};
+use crate::errorcode::ErrorCode;
+use crate::hil::nonvolatile_storage::{NonvolatileStorage, NonvolatileStorageClient};
 use crate::kernel::Kernel;
 use crate::platform::chip::Chip;
-use crate::process::{Process, ShortId};
+use crate::process::{Process, ProcessId, ShortId};
 use crate::process_binary::{ProcessBinary, ProcessBinaryError};
 use crate::process_checker::AcceptedCredential;
-use crate::process_checker::{AppIdPolicy, ProcessCheckError, ProcessCheckerMachine};
+use crate::process_checker::{
+    AppIdPolicy, ProcessCheckError, ProcessCheckerMachine, RollbackProtection,
+};
 use crate::process_policies::ProcessFaultPolicy;
 use crate::process_policies::ProcessStandardStoragePermissionsPolicy;
 use crate::process_standard::ProcessStandard;
 use crate::process_standard::{ProcessStandardDebug, ProcessStandardDebugFull};
-use crate::utilities::cells::{MapCell, OptionalCell};
+use crate::utilities::cells::{MapCell, OptionalCell, TakeCell};
 
 /// Errors that can occur when trying to load and create processes.
 pub enum ProcessLoadError {
@@ -56,6 +61,11 @@ pub enum ProcessLoadError {
         expected_address: u32,
     },
 
+    /// A process specified a fixed memory address that it needs its memory
+    /// range to start at, but the MPU requires the memory range of the process
+    /// to be aligned to `alignment` bytes, which the address is not.
+    MemoryAddressMisaligned { address: u32, alignment: u32 },
+
     /// There is nowhere in the `PROCESSES` array to store this process.
     NoProcessSlot,
 
@@ -65,6 +75,11 @@ pub enum ProcessLoadError {
     /// Process loading failed because checking the process failed.
     CheckError(ProcessCheckError),
 
+    /// The process binary was valid but no process was created from it, for
+    /// example because a process with the same AppID and a newer version is
+    /// already loaded.
+    NotLoaded,
+
     /// Process loading error due (likely) to a bug in the kernel. If you get
     /// this error please open a bug report.
     InternalError,
@@ -94,6 +109,12 @@ impl fmt::Debug for ProcessLoadError {
                 actual_address, expected_address
             ),
 
+            ProcessLoadError::MemoryAddressMisaligned { address, alignment } => write!(
+                f,
+                "App memory address {:#x} is not aligned to {:#x} as required by the MPU",
+                address, alignment
+            ),
+
             ProcessLoadError::NoProcessSlot => {
                 write!(f, "Nowhere to store the loaded process")
             }
@@ -108,6 +129,8 @@ impl fmt::Debug for ProcessLoadError {
                 write!(f, "{:?}", check_error)
             }
 
+            ProcessLoadError::NotLoaded => write!(f, "Process binary was not loaded"),
+
             ProcessLoadError::InternalError => write!(f, "Error in kernel. Likely a bug."),
         }
     }
@@ -453,6 +476,62 @@ pub trait ProcessLoadingAsync<'a> {
     fn start(&self);
 }
 
+/// Replace a loaded process with the process binary currently in flash.
+///
+/// This lets developers iterate on an application without rebooting the
+/// kernel: after a new version of the application is flashed over the old
+/// one, reloading the process picks up the new binary.
+pub trait ProcessReload {
+    /// Terminate the process `process_id`, remove it from the processes
+    /// array, and load the process binary stored at the start of its flash
+    /// region. The new process is checked and created asynchronously, and the
+    /// loader client is notified as for any other process.
+    ///
+    /// Data the process stored in nonvolatile storage is kept, but its RAM is
+    /// not reclaimed: the new process allocates its memory from the unused
+    /// app memory.
+    ///
+    /// Returns `Err(ErrorCode::INVAL)` if no such process exists or there is
+    /// no valid TBF header at the start of its flash region, and
+    /// `Err(ErrorCode::BUSY)` if the loader is running.
+    fn reload_process(&self, process_id: ProcessId) -> Result<(), ErrorCode>;
+}
+
+/// Take loaded processes out of the processes array and put them back, so
+/// that their memory can be used by other processes in the meantime.
+///
+/// This is the part of process swapping (see
+/// [`ProcessSwap`](crate::process::ProcessSwap)) that needs the loader.
+pub trait ProcessSwapping {
+    /// Remove the process `process_id` from the processes array. Its slot in
+    /// the array is reserved until the process is put back, so that the
+    /// process keeps its `ProcessId`.
+    ///
+    /// Returns `None` if no such process exists.
+    fn take_process(&self, process_id: ProcessId) -> Option<&'static dyn Process>;
+
+    /// Put a process taken with [`take_process`](ProcessSwapping::take_process)
+    /// back in its slot of the processes array.
+    ///
+    /// # Safety
+    ///
+    /// The memory of the process must hold what it did when the process was
+    /// taken, and no other process may use it.
+    unsafe fn put_process(&self, process: &'static dyn Process) -> Result<(), ErrorCode>;
+
+    /// Load the process binary with package name `name` from app flash into
+    /// `memory` instead of the unused app memory. The binary is checked and
+    /// the process is created asynchronously, and the loader client is
+    /// notified as for any other process.
+    ///
+    /// Returns `Err(ErrorCode::INVAL)` if there is no such binary, and
+    /// `Err(ErrorCode::BUSY)` if the loader is running.
+    fn load_process_into(&self, name: &str, memory: &'static mut [u8]) -> Result<(), ErrorCode>;
+
+    /// Whether the loader is running.
+    fn is_loading(&self) -> bool;
+}
+
 /// Operating mode of the loader.
 #[derive(Clone, Copy)]
 enum SequentialProcessLoaderMachineState {
@@ -480,8 +559,15 @@ pub struct SequentialProcessLoaderMachine<'a, C: Chip + 'static, D: ProcessStand
     proc_binaries: MapCell<&'static mut [Option<ProcessBinary>]>,
     /// Flash memory region to load processes from.
     flash: Cell<&'static [u8]>,
+    /// The entire app flash region, which reloaded processes are found in.
+    app_flash: &'static [u8],
     /// Memory available to assign to applications.
     app_memory: Cell<&'static mut [u8]>,
+    /// The unused app memory, while a process is loaded into other memory.
+    saved_app_memory: MapCell<&'static mut [u8]>,
+    /// The slots of the processes array reserved for swapped out processes,
+    /// one bit per slot.
+    reserved_slots: Cell<usize>,
     /// Mechanism for generating async callbacks.
     deferred_call: DeferredCall,
     /// Reference to the kernel object for creating Processes.
@@ -490,6 +576,8 @@ pub struct SequentialProcessLoaderMachine<'a, C: Chip + 'static, D: ProcessStand
     chip: &'static C,
     /// The policy to use when determining ShortIds and process uniqueness.
     policy: OptionalCell<&'a dyn AppIdPolicy>,
+    /// The minimum versions of applications, if rollback protection is used.
+    rollback: OptionalCell<&'a dyn RollbackProtection>,
     /// The fault policy to assign to each created Process.
     fault_policy: &'static dyn ProcessFaultPolicy,
     /// The storage permissions policy to assign to each created Process.
@@ -498,7 +586,7 @@ pub struct SequentialProcessLoaderMachine<'a, C: Chip + 'static, D: ProcessStand
     state: OptionalCell<SequentialProcessLoaderMachineState>,
 }
 
-impl<C: Chip, D: ProcessStandardDebug> SequentialProcessLoaderMachine<'_, C, D> {
+impl<'a, C: Chip, D: ProcessStandardDebug> SequentialProcessLoaderMachine<'a, C, D> {
     /// This function is made `pub` so that board files can use it, but loading
     /// processes from slices of flash an memory is fundamentally unsafe.
     /// Therefore, we require the `ProcessManagementCapability` to call this
@@ -525,19 +613,50 @@ impl<C: Chip, D: ProcessStandardDebug> SequentialProcessLoaderMachine<'_, C, D>
             kernel,
             chip,
             flash: Cell::new(flash),
+            app_flash: flash,
             app_memory: Cell::new(app_memory),
+            saved_app_memory: MapCell::empty(),
+            reserved_slots: Cell::new(0),
             policy: OptionalCell::new(policy),
+            rollback: OptionalCell::empty(),
             fault_policy,
             storage_policy,
             state: OptionalCell::empty(),
         }
     }
 
+    /// Refuse binaries older than the minimum version of their application,
+    /// and raise the minimum as newer binaries are loaded.
+    pub fn set_rollback_protection(&self, rollback: &'a dyn RollbackProtection) {
+        self.rollback.set(rollback);
+    }
+
+    /// Check `process_binary` with `short_id` against the rollback
+    /// protection, if any.
+    fn check_version(
+        &self,
+        process_binary: &ProcessBinary,
+        short_id: ShortId,
+    ) -> Result<(), ProcessCheckError> {
+        let version = process_binary.header.get_binary_version();
+        match short_id {
+            ShortId::LocallyUnique => Ok(()),
+            ShortId::Fixed(_) => self.rollback.map_or(Ok(()), |rollback| {
+                let minimum = rollback.minimum_version(short_id);
+                if version < minimum {
+                    Err(ProcessCheckError::VersionRolledBack { version, minimum })
+                } else {
+                    Ok(())
+                }
+            }),
+        }
+    }
+
     /// Find a slot in the `PROCESSES` array to store this process.
     fn find_open_process_slot(&self) -> Option<usize> {
         self.procs.map_or(None, |procs| {
             for (i, p) in procs.iter().enumerate() {
-                if p.is_none() {
+                if p.is_none() && !self.is_reserved_slot(i) {
                     return Some(i);
                 }
             }
@@ -545,6 +664,46 @@ impl<C: Chip, D: ProcessStandardDebug> SequentialProcessLoaderMachine<'_, C, D>
         })
     }
 
+    fn is_reserved_slot(&self, index: usize) -> bool {
+        index < usize::BITS as usize && self.reserved_slots.get() & (1 << index) != 0
+    }
+
+    /// Use the unused app memory again after loading a process into other
+    /// memory.
+    fn restore_app_memory(&self) {
+        if let Some(memory) = self.saved_app_memory.take() {
+            self.app_memory.set(memory);
+        }
+    }
+
+    /// Find the process binary with package name `name` in app flash.
+    fn find_process_binary(&self, name: &str) -> Option<&'static [u8]> {
+        let mut flash = self.app_flash;
+        loop {
+            let header = flash.get(0..8)?.try_into().ok()?;
+            let (version, header_length, app_length) =
+                match tock_tbf::parse::parse_tbf_header_lengths(header) {
+                    Ok(lengths) => lengths,
+                    Err(tock_tbf::types::InitialTbfParseError::InvalidHeader(app_length)) => {
+                        (0, 0, app_length)
+                    }
+                    Err(tock_tbf::types::InitialTbfParseError::UnableToParse) => return None,
+                };
+            if app_length == 0 {
+                return None;
+            }
+            let binary = flash.get(0..app_length as usize)?;
+            let package_name = binary
+                .get(0..header_length as usize)
+                .and_then(|header| tock_tbf::parse::parse_tbf_header(header, version).ok())
+                .and_then(|header| header.get_package_name());
+            if package_name == Some(name) {
+                return Some(binary);
+            }
+            flash = flash.get(binary.len()..)?;
+        }
+    }
+
     /// Find a slot in the `PROCESS_BINARIES` array to store this process.
     fn find_open_process_binary_slot(&self) -> Option<usize> {
         self.proc_binaries.map_or(None, |proc_bins| {
@@ -727,6 +886,21 @@ impl<C: Chip, D: ProcessStandardDebug> SequentialProcessLoaderMachine<'_, C, D>
                         let short_app_id = self.policy.map_or(ShortId::LocallyUnique, |policy| {
                             policy.to_short_id(&process_binary)
                         });
+                        let version = process_binary.header.get_binary_version();
+
+                        if let Err(e) = self.check_version(&process_binary, short_app_id) {
+                            if config::CONFIG.debug_load_processes {
+                                debug!(
+                                    "Loading: Process {} refused: {:?}",
+                                    process_binary.header.get_package_name().unwrap_or(""),
+                                    e
+                                );
+                            }
+                            self.client.map(|client| {
+                                client.process_loaded(Err(ProcessLoadError::CheckError(e)));
+                            });
+                            continue;
+                        }
 
                         // Try to create a `Process` object.
                         let load_result = load_process(
@@ -756,6 +930,19 @@ impl<C: Chip, D: ProcessStandardDebug> SequentialProcessLoaderMachine<'_, C, D>
                                         self.procs.map(|procs| {
                                             procs[index] = proc;
                                         });
+                                        if let ShortId::Fixed(_) = short_app_id {
+                                            self.rollback.map(|rollback| {
+                                                if let Err(e) = rollback
+                                                    .raise_minimum_version(short_app_id, version)
+                                                {
+                                                    debug!(
+                                                        "Loading: Could not record version of {}: {:?}",
+                                                        p.get_process_name(),
+                                                        e
+                                                    );
+                                                }
+                                            });
+                                        }
                                         // Notify the client the process was loaded
                                         // successfully.
                                         self.client.map(|client| {
@@ -791,6 +978,7 @@ impl<C: Chip, D: ProcessStandardDebug> SequentialProcessLoaderMachine<'_, C, D>
             }
         }
         self.proc_binaries.put(proc_binaries);
+        self.restore_app_memory();
 
         // We have iterated all discovered `ProcessBinary`s and loaded what we
         // could so now we can signal that process loading is finished.
@@ -871,6 +1059,131 @@ impl<C: Chip, D: ProcessStandardDebug> SequentialProcessLoaderMachine<'_, C, D>
     }
 }
 
+impl<C: Chip, D: ProcessStandardDebug> SequentialProcessLoaderMachine<'_, C, D> {
+    /// Load the process binary stored in `flash` after the initial process
+    /// loading has finished.
+    ///
+    /// The binary goes through the same credential checking and uniqueness
+    /// checks as binaries found at boot, and its RAM is allocated from
+    /// whatever app memory remains. The client is notified with
+    /// `process_loaded()` and `process_loading_finished()` as for boot-time
+    /// loading.
+    ///
+    /// Returns `Err(ErrorCode::BUSY)` if the loader is still running.
+    pub fn load_new_process_binary(&self, flash: &'static [u8]) -> Result<(), ErrorCode> {
+        if self.state.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.flash.set(flash);
+        self.state
+            .set(SequentialProcessLoaderMachineState::DiscoverProcessBinaries);
+        self.deferred_call.set();
+        Ok(())
+    }
+}
+
+impl<C: Chip, D: ProcessStandardDebug> ProcessReload for SequentialProcessLoaderMachine<'_, C, D> {
+    fn reload_process(&self, process_id: ProcessId) -> Result<(), ErrorCode> {
+        if self.state.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+
+        let index = self
+            .procs
+            .map_or(None, |procs| {
+                procs
+                    .iter()
+                    .position(|p| p.is_some_and(|p| p.processid() == process_id))
+            })
+            .ok_or(ErrorCode::INVAL)?;
+
+        // Find the binary now stored where the process was loaded from. Its
+        // length is read again since the new version may differ in size.
+        let flash_start = self
+            .procs
+            .map_or(None, |procs| {
+                procs[index].map(|p| p.get_addresses().flash_start)
+            })
+            .ok_or(ErrorCode::INVAL)?;
+        let offset = flash_start
+            .checked_sub(self.app_flash.as_ptr() as usize)
+            .ok_or(ErrorCode::INVAL)?;
+        let header = self
+            .app_flash
+            .get(offset..offset + 8)
+            .and_then(|header| header.try_into().ok())
+            .ok_or(ErrorCode::INVAL)?;
+        let binary = match tock_tbf::parse::parse_tbf_header_lengths(header) {
+            Ok((_, _, app_length)) => self
+                .app_flash
+                .get(offset..offset + app_length as usize)
+                .ok_or(ErrorCode::INVAL)?,
+            Err(_) => return Err(ErrorCode::INVAL),
+        };
+
+        // Remove the old process so that it does not block the new one from
+        // loading with the same AppID.
+        self.procs.map(|procs| {
+            if let Some(p) = procs[index].take() {
+                p.terminate(None);
+            }
+        });
+
+        self.load_new_process_binary(binary)
+    }
+}
+
+impl<C: Chip, D: ProcessStandardDebug> ProcessSwapping
+    for SequentialProcessLoaderMachine<'_, C, D>
+{
+    fn take_process(&self, process_id: ProcessId) -> Option<&'static dyn Process> {
+        let index = process_id.index()?;
+        if index >= usize::BITS as usize {
+            return None;
+        }
+        let process = self.procs.map_or(None, |procs| match procs.get(index) {
+            Some(Some(p)) if p.processid() == process_id => procs[index].take(),
+            _ => None,
+        })?;
+        self.reserved_slots
+            .set(self.reserved_slots.get() | (1 << index));
+        Some(process)
+    }
+
+    unsafe fn put_process(&self, process: &'static dyn Process) -> Result<(), ErrorCode> {
+        // The process is not in the array, so its `ProcessId` cannot look its
+        // index up.
+        let index = process.processid().slot_index();
+        if !self.is_reserved_slot(index) {
+            return Err(ErrorCode::INVAL);
+        }
+        self.procs
+            .map_or(Err(ErrorCode::FAIL), |procs| match procs.get_mut(index) {
+                Some(slot @ None) => {
+                    *slot = Some(process);
+                    Ok(())
+                }
+                _ => Err(ErrorCode::BUSY),
+            })?;
+        self.reserved_slots
+            .set(self.reserved_slots.get() & !(1 << index));
+        Ok(())
+    }
+
+    fn load_process_into(&self, name: &str, memory: &'static mut [u8]) -> Result<(), ErrorCode> {
+        if self.state.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        let binary = self.find_process_binary(name).ok_or(ErrorCode::INVAL)?;
+        self.saved_app_memory.put(self.app_memory.replace(memory));
+        self.load_new_process_binary(binary)
+    }
+
+    fn is_loading(&self) -> bool {
+        self.state.is_some()
+    }
+}
+
 impl<'a, C: Chip, D: ProcessStandardDebug> ProcessLoadingAsync<'a>
     for SequentialProcessLoaderMachine<'a, C, D>
 {
@@ -906,6 +1219,7 @@ impl<C: Chip, D: ProcessStandardDebug> DeferredCallClient
                     Err(()) => {
                         // If this failed for some reason, we still need to
                         // signal that process loading has finished.
+                        self.restore_app_memory();
                         self.client.map(|client| {
                             client.process_loading_finished();
                         });
@@ -972,3 +1286,468 @@ impl<C: Chip, D: ProcessStandardDebug> crate::process_checker::ProcessCheckerMac
         self.deferred_call.set();
     }
 }
+
+////////////////////////////////////////////////////////////////////////////////
+// DYNAMIC PROCESS LOADING
+////////////////////////////////////////////////////////////////////////////////
+
+/// Size of the TBF header written to pad the gap between the last existing
+/// process binary and a newly installed one.
+const PADDING_HEADER_LEN: usize = 16;
+
+/// Client for dynamic process loading.
+pub trait DynamicProcessLoadingClient {
+    /// A write started with `DynamicProcessLoading::write()` finished.
+    fn write_done(&self, result: Result<(), ErrorCode>, length: usize);
+
+    /// Loading the new process binary started with
+    /// `DynamicProcessLoading::load()` finished.
+    fn load_done(&self, result: Result<(), ProcessLoadError>);
+}
+
+/// Installing new process binaries at runtime.
+///
+/// A new binary is installed in three steps. First, `setup()` reserves space
+/// in the app flash region for the binary. Then the binary is copied into that
+/// space with one or more calls to `write()`. Finally, `load()` validates the
+/// binary and creates a new process from it which starts running without
+/// rebooting the board. `abort()` can be called at any point before `load()`
+/// to release the reserved space.
+pub trait DynamicProcessLoading<'a> {
+    /// Set the client to notify when writes and loads complete.
+    fn set_client(&self, client: &'a dyn DynamicProcessLoadingClient);
+
+    /// Reserve `app_length` bytes of app flash for a new process binary.
+    ///
+    /// Returns the address in flash the binary will be stored at.
+    fn setup(&self, app_length: usize) -> Result<usize, ErrorCode>;
+
+    /// Copy `data` into the reserved space at `offset` from its start.
+    fn write(&self, data: &[u8], offset: usize) -> Result<(), ErrorCode>;
+
+    /// Validate the written binary and load it as a new process.
+    fn load(&self) -> Result<(), ErrorCode>;
+
+    /// Release the reserved space without loading a process.
+    fn abort(&self) -> Result<(), ErrorCode>;
+}
+
+/// State of an in-progress dynamic binary installation.
+#[derive(Clone, Copy, PartialEq)]
+enum DynamicLoaderState {
+    /// Space is reserved and the binary is being written.
+    Setup,
+    /// A write to flash is in progress.
+    Writing,
+    /// The padding header before the new binary is being written.
+    WritingPadding,
+    /// The binary is being checked and loaded by the process loader.
+    Loading,
+    /// Loading failed and the client has been notified, but the process
+    /// loader has not finished yet.
+    LoadFailed,
+}
+
+/// Location of the binary being installed, relative to the start of the app
+/// flash region.
+#[derive(Clone, Copy)]
+struct NewBinary {
+    /// End of the last process binary already in flash.
+    previous_end: usize,
+    /// Offset the new binary is written to.
+    offset: usize,
+    /// Length of the new binary.
+    length: usize,
+}
+
+/// Find the offset at which to place a new binary of `app_length` bytes in
+/// the app flash region, which starts at address `start` and is `flash_len`
+/// bytes long, after the binaries that end at offset `previous_end`.
+///
+/// `app_length` comes from untrusted callers, so all of the arithmetic is
+/// checked.
+fn place_new_binary(
+    start: usize,
+    flash_len: usize,
+    previous_end: usize,
+    app_length: usize,
+) -> Result<usize, ErrorCode> {
+    if app_length < PADDING_HEADER_LEN {
+        return Err(ErrorCode::INVAL);
+    }
+    if app_length > flash_len {
+        return Err(ErrorCode::NOMEM);
+    }
+
+    // The MPU needs the address of the binary to be aligned, not its offset
+    // in the app flash region, which may start anywhere.
+    let alignment = app_length
+        .checked_next_power_of_two()
+        .ok_or(ErrorCode::NOMEM)?;
+    let mut offset = start
+        .checked_add(previous_end)
+        .and_then(|address| address.checked_next_multiple_of(alignment))
+        .ok_or(ErrorCode::NOMEM)?
+        - start;
+    // A gap must be large enough to hold a padding header.
+    if offset != previous_end && offset - previous_end < PADDING_HEADER_LEN {
+        offset = offset.checked_add(alignment).ok_or(ErrorCode::NOMEM)?;
+    }
+    match offset.checked_add(app_length) {
+        Some(end) if end <= flash_len => Ok(offset),
+        _ => Err(ErrorCode::NOMEM),
+    }
+}
+
+/// Check that writing `length` bytes at `offset` stays within a binary of
+/// `binary_length` bytes.
+fn check_write_range(offset: usize, length: usize, binary_length: usize) -> Result<(), ErrorCode> {
+    match offset.checked_add(length) {
+        None => Err(ErrorCode::INVAL),
+        Some(end) if end > binary_length => Err(ErrorCode::SIZE),
+        Some(_) => Ok(()),
+    }
+}
+
+/// Installs new process binaries into the app flash region at runtime.
+///
+/// `DynamicLoader` writes new binaries to flash through a
+/// `NonvolatileStorage` implementation covering the app flash region, and then
+/// asks a `SequentialProcessLoaderMachine` to check and load them. Binaries are
+/// placed after the last binary already in flash, aligned to their (power of
+/// two) size as required by most MPUs, and any gap is filled with a TBF
+/// padding header so the linked list of binaries stays intact after a reboot.
+///
+/// The dynamic loader must be set as the client of the process loader. The
+/// process loader events for processes loaded at boot are forwarded to the
+/// client set with `set_loader_client()`.
+pub struct DynamicLoader<'a, C: Chip + 'static, D: ProcessStandardDebug + 'static> {
+    /// Loader that checks and creates new processes.
+    loader: &'a SequentialProcessLoaderMachine<'a, C, D>,
+    /// Storage backing the app flash region.
+    storage: &'a dyn NonvolatileStorage<'a>,
+    /// The entire app flash region.
+    app_flash: &'static [u8],
+    /// Buffer used to write to storage.
+    buffer: TakeCell<'static, [u8]>,
+    /// Binary currently being installed.
+    new_binary: OptionalCell<NewBinary>,
+    /// Current installation state.
+    state: OptionalCell<DynamicLoaderState>,
+    /// Whether a process has been loaded from the new binary.
+    loaded: Cell<bool>,
+    /// Client for installation events.
+    client: OptionalCell<&'a dyn DynamicProcessLoadingClient>,
+    /// Client for process loading events not caused by this loader.
+    loader_client: OptionalCell<&'a dyn ProcessLoadingAsyncClient>,
+}
+
+impl<'a, C: Chip, D: ProcessStandardDebug> DynamicLoader<'a, C, D> {
+    /// Create a dynamic loader for `app_flash`, which `storage` must be able
+    /// to write. `buffer` limits the largest write to storage, and must be at
+    /// least 16 bytes long.
+    ///
+    /// Writing to the app flash region can replace running code, so creating a
+    /// dynamic loader requires the `ProcessManagementCapability`.
+    pub fn new(
+        loader: &'a SequentialProcessLoaderMachine<'a, C, D>,
+        storage: &'a dyn NonvolatileStorage<'a>,
+        app_flash: &'static [u8],
+        buffer: &'static mut [u8],
+        _capability_management: &dyn ProcessManagementCapability,
+    ) -> Self {
+        Self {
+            loader,
+            storage,
+            app_flash,
+            buffer: TakeCell::new(buffer),
+            new_binary: OptionalCell::empty(),
+            state: OptionalCell::empty(),
+            loaded: Cell::new(false),
+            client: OptionalCell::empty(),
+            loader_client: OptionalCell::empty(),
+        }
+    }
+
+    /// Set the client to forward process loading events to that were not
+    /// caused by a dynamic installation.
+    pub fn set_loader_client(&self, client: &'a dyn ProcessLoadingAsyncClient) {
+        self.loader_client.set(client);
+    }
+
+    /// Find the offset of the end of the last process binary in flash.
+    fn find_end_of_binaries(&self) -> usize {
+        let mut offset = 0;
+        while let Some(header) = self.app_flash.get(offset..offset + 8) {
+            let header = match header.try_into() {
+                Ok(header) => header,
+                Err(_) => break,
+            };
+            let app_length = match tock_tbf::parse::parse_tbf_header_lengths(header) {
+                Ok((_, _, app_length)) => app_length,
+                Err(tock_tbf::types::InitialTbfParseError::InvalidHeader(app_length)) => app_length,
+                Err(tock_tbf::types::InitialTbfParseError::UnableToParse) => break,
+            };
+            if app_length == 0 {
+                break;
+            }
+            offset += app_length as usize;
+        }
+        offset
+    }
+
+    /// Write `length` bytes from the internal buffer to flash at `offset` in
+    /// the app flash region.
+    fn write_buffer(&self, offset: usize, length: usize) -> Result<(), ErrorCode> {
+        let buffer = self.buffer.take().ok_or(ErrorCode::BUSY)?;
+        let address = self.app_flash.as_ptr() as usize + offset;
+        self.storage.write(buffer, address, length)
+    }
+
+    /// Start loading the new binary with the process loader.
+    fn start_loading(&self, binary: NewBinary) -> Result<(), ErrorCode> {
+        let flash = self
+            .app_flash
+            .get(binary.offset..binary.offset + binary.length)
+            .ok_or(ErrorCode::FAIL)?;
+
+        // Validate the header before handing the binary to the loader so that
+        // a truncated or corrupted upload is reported precisely.
+        let header = flash
+            .get(0..8)
+            .and_then(|h| h.try_into().ok())
+            .ok_or(ErrorCode::INVAL)?;
+        match tock_tbf::parse::parse_tbf_header_lengths(header) {
+            Ok((_, _, app_length)) if app_length as usize == binary.length => {}
+            _ => return Err(ErrorCode::INVAL),
+        }
+
+        self.state.set(DynamicLoaderState::Loading);
+        self.loaded.set(false);
+        self.loader.load_new_process_binary(flash)
+    }
+
+    /// Finish the current installation and notify the client.
+    fn finish_load(&self, result: Result<(), ProcessLoadError>) {
+        self.state.clear();
+        self.new_binary.clear();
+        self.client.map(|client| client.load_done(result));
+    }
+}
+
+impl<'a, C: Chip, D: ProcessStandardDebug> DynamicProcessLoading<'a> for DynamicLoader<'a, C, D> {
+    fn set_client(&self, client: &'a dyn DynamicProcessLoadingClient) {
+        self.client.set(client);
+    }
+
+    fn setup(&self, app_length: usize) -> Result<usize, ErrorCode> {
+        if self.state.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        let start = self.app_flash.as_ptr() as usize;
+        let previous_end = self.find_end_of_binaries();
+        let offset = place_new_binary(start, self.app_flash.len(), previous_end, app_length)?;
+
+        self.new_binary.set(NewBinary {
+            previous_end,
+            offset,
+            length: app_length,
+        });
+        self.state.set(DynamicLoaderState::Setup);
+        Ok(start + offset)
+    }
+
+    fn write(&self, data: &[u8], offset: usize) -> Result<(), ErrorCode> {
+        if self.state.get() != Some(DynamicLoaderState::Setup) {
+            return Err(ErrorCode::BUSY);
+        }
+        let binary = self.new_binary.get().ok_or(ErrorCode::FAIL)?;
+        check_write_range(offset, data.len(), binary.length)?;
+        let length = self.buffer.map_or(0, |buffer| {
+            let length = cmp::min(data.len(), buffer.len());
+            buffer[..length].copy_from_slice(&data[..length]);
+            length
+        });
+        self.state.set(DynamicLoaderState::Writing);
+        self.write_buffer(binary.offset + offset, length)
+            .inspect_err(|_| self.state.set(DynamicLoaderState::Setup))
+    }
+
+    fn load(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != Some(DynamicLoaderState::Setup) {
+            return Err(ErrorCode::BUSY);
+        }
+        let binary = self.new_binary.get().ok_or(ErrorCode::FAIL)?;
+
+        if binary.offset == binary.previous_end {
+            return self.start_loading(binary).inspect_err(|_| {
+                self.state.set(DynamicLoaderState::Setup);
+            });
+        }
+
+        // Fill the gap between the previous binary and the new one with a
+        // padding TBF header (version 2, no flags).
+        let gap = (binary.offset - binary.previous_end) as u32;
+        self.buffer.map(|buffer| {
+            let words = [
+                2 | ((PADDING_HEADER_LEN as u32) << 16),
+                gap,
+                0,
+                2 ^ ((PADDING_HEADER_LEN as u32) << 16) ^ gap,
+            ];
+            for (chunk, word) in buffer.chunks_exact_mut(4).zip(words.iter()) {
+                chunk.copy_from_slice(&word.to_le_bytes());
+            }
+        });
+        self.state.set(DynamicLoaderState::WritingPadding);
+        self.write_buffer(binary.previous_end, PADDING_HEADER_LEN)
+            .inspect_err(|_| self.state.set(DynamicLoaderState::Setup))
+    }
+
+    fn abort(&self) -> Result<(), ErrorCode> {
+        match self.state.get() {
+            Some(DynamicLoaderState::Setup) => {
+                self.state.clear();
+                self.new_binary.clear();
+                Ok(())
+            }
+            Some(_) => Err(ErrorCode::BUSY),
+            None => Err(ErrorCode::INVAL),
+        }
+    }
+}
+
+impl<C: Chip, D: ProcessStandardDebug> NonvolatileStorageClient for DynamicLoader<'_, C, D> {
+    fn read_done(&self, buffer: &'static mut [u8], _length: usize) {
+        self.buffer.replace(buffer);
+    }
+
+    fn write_done(&self, buffer: &'static mut [u8], length: usize) {
+        self.buffer.replace(buffer);
+        match self.state.get() {
+            Some(DynamicLoaderState::Writing) => {
+                self.state.set(DynamicLoaderState::Setup);
+                self.client.map(|client| client.write_done(Ok(()), length));
+            }
+            Some(DynamicLoaderState::WritingPadding) => {
+                let result = self
+                    .new_binary
+                    .get()
+                    .ok_or(ErrorCode::FAIL)
+                    .and_then(|binary| self.start_loading(binary));
+                if result.is_err() {
+                    // The binary is not valid, but it can still be rewritten
+                    // or the installation aborted.
+                    self.state.set(DynamicLoaderState::Setup);
+                    self.client.map(|client| {
+                        client.load_done(Err(ProcessLoadError::BinaryError(
+                            ProcessBinaryError::TbfHeaderNotFound,
+                        )))
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<C: Chip, D: ProcessStandardDebug> ProcessLoadingAsyncClient for DynamicLoader<'_, C, D> {
+    fn process_loaded(&self, result: Result<(), ProcessLoadError>) {
+        match self.state.get() {
+            Some(DynamicLoaderState::Loading) => match result {
+                Ok(()) => self.loaded.set(true),
+                Err(e) => {
+                    self.state.set(DynamicLoaderState::LoadFailed);
+                    self.new_binary.clear();
+                    self.client.map(|client| client.load_done(Err(e)));
+                }
+            },
+            Some(DynamicLoaderState::LoadFailed) => {}
+            _ => {
+                self.loader_client
+                    .map(|client| client.process_loaded(result));
+            }
+        }
+    }
+
+    fn process_loading_finished(&self) {
+        match self.state.get() {
+            Some(DynamicLoaderState::Loading) => {
+                if self.loaded.get() {
+                    self.finish_load(Ok(()));
+                } else {
+                    self.finish_load(Err(ProcessLoadError::NotLoaded));
+                }
+            }
+            Some(DynamicLoaderState::LoadFailed) => {
+                self.state.clear();
+            }
+            _ => {
+                self.loader_client
+                    .map(|client| client.process_loading_finished());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn place_new_binary_aligns_address() {
+        // The region starts at an address that is not aligned to the binary.
+        assert_eq!(place_new_binary(0x3_0800, 0x1_0000, 0, 0x1000), Ok(0x800));
+        // Directly after an aligned previous binary.
+        assert_eq!(
+            place_new_binary(0x4_0000, 0x1_0000, 0x1000, 0x1000),
+            Ok(0x1000)
+        );
+        // A gap too small for a padding header moves to the next alignment.
+        assert_eq!(
+            place_new_binary(0x4_0000, 0x1_0000, 0xFF8, 0x1000),
+            Ok(0x2000)
+        );
+        assert_eq!(
+            place_new_binary(0x4_0000, 0x1_0000, 0xF000, 0x2000),
+            Err(ErrorCode::NOMEM)
+        );
+    }
+
+    #[test]
+    fn place_new_binary_rejects_huge_lengths() {
+        assert_eq!(
+            place_new_binary(0x4_0000, 0x1_0000, 0, 8),
+            Err(ErrorCode::INVAL)
+        );
+        for app_length in [0x1_0001, (usize::MAX >> 1) + 2, usize::MAX] {
+            assert_eq!(
+                place_new_binary(0x4_0000, 0x1_0000, 0, app_length),
+                Err(ErrorCode::NOMEM)
+            );
+        }
+        // Near the top of the address space.
+        assert_eq!(
+            place_new_binary(usize::MAX - 0xFFF, 0x1000, 0x10, 0x1000),
+            Err(ErrorCode::NOMEM)
+        );
+    }
+
+    #[test]
+    fn check_write_range_rejects_overflow() {
+        assert_eq!(check_write_range(0, 0x100, 0x100), Ok(()));
+        assert_eq!(check_write_range(0x80, 0x81, 0x100), Err(ErrorCode::SIZE));
+        assert_eq!(
+            check_write_range(usize::MAX, 1, 0x100),
+            Err(ErrorCode::INVAL)
+        );
+        assert_eq!(
+            check_write_range(usize::MAX, 0x200, 0x100),
+            Err(ErrorCode::INVAL)
+        );
+        assert_eq!(
+            check_write_range(usize::MAX, 0, 0x100),
+            Err(ErrorCode::SIZE)
+        );
+    }
+}
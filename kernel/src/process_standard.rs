@@ -19,13 +19,15 @@ use crate::collections::ring_buffer::RingBuffer;
 use crate::config;
 use crate::debug;
 use crate::errorcode::ErrorCode;
-use crate::kernel::Kernel;
+use crate::kernel::{DeferredTransition, Kernel};
 use crate::platform::chip::Chip;
 use crate::platform::mpu::{self, MPU};
 use crate::process::BinaryVersion;
 use crate::process::ProcessBinary;
 use crate::process::{Error, FunctionCall, FunctionCallSource, Process, Task};
-use crate::process::{FaultAction, ProcessCustomGrantIdentifier, ProcessId};
+use crate::process::{
+    FaultAction, FaultRecord, ProcessCustomGrantIdentifier, ProcessId, ProcessStats,
+};
 use crate::process::{ProcessAddresses, ProcessSizes, ShortId};
 use crate::process::{State, StoppedState};
 use crate::process_checker::AcceptedCredential;
@@ -482,6 +484,12 @@ pub struct ProcessStandard<'a, C: 'static + Chip, D: 'static + ProcessStandardDe
     /// determine if the process should be restarted or not.
     restart_count: Cell<usize>,
 
+    /// The most recent fault of this process, kept across restarts.
+    last_fault: Cell<Option<FaultRecord>>,
+
+    /// The CPU time this process used, kept across restarts.
+    stats: Cell<ProcessStats>,
+
     /// The completion code set by the process when it last exited, restarted,
     /// or was terminated. If the process is has never terminated, then the
     /// `OptionalCell` will be empty (i.e. `None`). If the process has exited,
@@ -602,6 +610,14 @@ impl<C: Chip, D: 'static + ProcessStandardDebug> Process for ProcessStandard<'_,
     }
 
     fn stop(&self) {
+        // A process executing on another hart is stopped once that hart is
+        // back in the kernel.
+        if self
+            .kernel
+            .defer_transition(self.processid(), DeferredTransition::Stop)
+        {
+            return;
+        }
         match self.state.get() {
             State::Running => self.state.set(State::Stopped(StoppedState::Running)),
             State::Yielded => self.state.set(State::Stopped(StoppedState::Yielded)),
@@ -629,6 +645,12 @@ impl<C: Chip, D: 'static + ProcessStandardDebug> Process for ProcessStandard<'_,
     }
 
     fn set_fault_state(&self) {
+        if self
+            .kernel
+            .defer_transition(self.processid(), DeferredTransition::Fault)
+        {
+            return;
+        }
         // Use the per-process fault policy to determine what action the kernel
         // should take since the process faulted.
         let action = self.fault_policy.action(self);
@@ -671,6 +693,12 @@ impl<C: Chip, D: 'static + ProcessStandardDebug> Process for ProcessStandard<'_,
         if self.get_state() == State::Terminated {
             return;
         }
+        if self.kernel.defer_transition(
+            self.processid(),
+            DeferredTransition::Restart(completion_code),
+        ) {
+            return;
+        }
 
         // Terminate the process, freeing its state and removing any
         // pending tasks from the scheduler's queue.
@@ -696,6 +724,12 @@ impl<C: Chip, D: 'static + ProcessStandardDebug> Process for ProcessStandard<'_,
         if !self.is_running() && self.get_state() != State::Faulted {
             return;
         }
+        if self.kernel.defer_transition(
+            self.processid(),
+            DeferredTransition::Terminate(completion_code),
+        ) {
+            return;
+        }
 
         // And remove those tasks
         self.tasks.map(|tasks| {
@@ -718,6 +752,23 @@ impl<C: Chip, D: 'static + ProcessStandardDebug> Process for ProcessStandard<'_,
         self.restart_count.get()
     }
 
+    fn get_last_fault(&self) -> Option<FaultRecord> {
+        self.last_fault.get()
+    }
+
+    fn get_stats(&self) -> ProcessStats {
+        self.stats.get()
+    }
+
+    fn add_run(&self, time_us: u32, cycles: u64) {
+        let stats = self.stats.get();
+        self.stats.set(ProcessStats {
+            cpu_time_us: stats.cpu_time_us.wrapping_add(time_us as u64),
+            cpu_cycles: stats.cpu_cycles.wrapping_add(cycles),
+            runs: stats.runs.wrapping_add(1),
+        });
+    }
+
     fn has_tasks(&self) -> bool {
         self.tasks.map_or(false, |tasks| tasks.has_elements())
     }
@@ -826,6 +877,27 @@ impl<C: Chip, D: 'static + ProcessStandardDebug> Process for ProcessStandard<'_,
         })
     }
 
+    fn remove_mpu_regions_in(&self, start: usize, end: usize) -> usize {
+        self.mpu_config.map_or(0, |config| {
+            let mut removed = 0;
+            for internal_region in self.mpu_regions.iter() {
+                let Some(region) = internal_region.get() else {
+                    continue;
+                };
+                let region_start = region.start_address() as usize;
+                let region_end = region_start.saturating_add(region.size());
+                if region_start < end
+                    && start < region_end
+                    && self.chip.mpu().remove_memory_region(region, config).is_ok()
+                {
+                    internal_region.set(None);
+                    removed += 1;
+                }
+            }
+            removed
+        })
+    }
+
     fn sbrk(&self, increment: isize) -> Result<CapabilityPtr, Error> {
         // Do not modify an inactive process.
         if !self.is_running() {
@@ -1371,6 +1443,14 @@ impl<C: Chip, D: 'static + ProcessStandardDebug> Process for ProcessStandard<'_,
             self.debug.set_new_app_stack_min_pointer(sp);
         }
 
+        if switch_reason == Some(syscall::ContextSwitchReason::Fault) {
+            self.last_fault.set(Some(FaultRecord {
+                count: self.last_fault.get().map_or(0, |fault| fault.count) + 1,
+                stack_pointer: stack_pointer.map(|sp| sp as usize),
+                last_syscall: self.debug.get_last_syscall(),
+            }));
+        }
+
         switch_reason
     }
 
@@ -1526,6 +1606,21 @@ impl<C: Chip, D: 'static + ProcessStandardDebug> Process for ProcessStandard<'_,
             })
             .unwrap_or(Err(ErrorCode::FAIL))
     }
+
+    fn set_stored_state(&self, state: &[u8]) -> Result<(), ErrorCode> {
+        if !matches!(self.state.get(), State::Stopped(_)) {
+            return Err(ErrorCode::BUSY);
+        }
+        self.stored_state
+            .map(|stored_state| {
+                self.chip
+                    .userspace_kernel_boundary()
+                    .load_context(stored_state, state)
+            })
+            .unwrap_or(Err(ErrorCode::FAIL))?;
+        self.state.set(State::Stopped(StoppedState::Running));
+        Ok(())
+    }
 }
 
 impl<C: 'static + Chip, D: 'static + ProcessStandardDebug> ProcessStandard<'_, C, D> {
@@ -1734,13 +1829,21 @@ impl<C: 'static + Chip, D: 'static + ProcessStandardDebug> ProcessStandard<'_, C
             let actual_address = remaining_memory.as_ptr() as u32 + app_memory_start_offset as u32;
             let expected_address = fixed_memory_start;
             if actual_address != expected_address {
-                return Err((
+                // Explain the failure if the address does not meet the
+                // alignment constraints of the MPU for this memory size.
+                let alignment = <C::MPU as MPU>::APP_MEMORY_ALIGNMENT.alignment(allocation_size);
+                let error = if expected_address as usize % alignment != 0 {
+                    ProcessLoadError::MemoryAddressMisaligned {
+                        address: expected_address,
+                        alignment: alignment as u32,
+                    }
+                } else {
                     ProcessLoadError::MemoryAddressMismatch {
                         actual_address,
                         expected_address,
-                    },
-                    remaining_memory,
-                ));
+                    }
+                };
+                return Err((error, remaining_memory));
             }
         }
 
@@ -1911,6 +2014,8 @@ impl<C: 'static + Chip, D: 'static + ProcessStandardDebug> ProcessStandard<'_, C
         process.state = Cell::new(State::Yielded);
         process.fault_policy = fault_policy;
         process.restart_count = Cell::new(0);
+        process.last_fault = Cell::new(None);
+        process.stats = Cell::new(ProcessStats::default());
         process.completion_code = OptionalCell::empty();
 
         process.mpu_config = MapCell::new(mpu_config);
@@ -0,0 +1,1170 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Swap rarely active processes to flash, to run more processes than fit in
+//! RAM.
+//!
+//! **This is experimental.**
+//!
+//! Boards with little RAM, like the HiFive1, can only load a few processes at
+//! once. Most processes spend most of their time waiting, though, and some
+//! only do something every few minutes or when another process asks them to.
+//! [`ProcessSwap`] writes the memory of such a process (its stack, heap,
+//! grants and process structure) to a slot in flash, and removes the process
+//! from the processes array. Its memory can then be used to load a process
+//! that did not fit, with [`ProcessSwap::load_process`].
+//!
+//! A swapped out process is written back to the same addresses when:
+//!
+//! - [`ProcessSwap::swap_in`] is called,
+//! - the delay given to [`ProcessSwap::swap_out`] expires, or
+//! - another process discovers or notifies it with IPC, if IPC is set up with
+//!   [`IPC::set_swap_in`](crate::ipc::IPC::set_swap_in). The IPC command
+//!   then fails with `BUSY`, and can be retried once the process is back.
+//!
+//! If another process uses that memory in the meantime, it is swapped out
+//! first.
+//!
+//! While a process is swapped out, it does not exist for the rest of the
+//! kernel. Capsules cannot enter its grant or schedule its upcalls, and events
+//! for it are lost. It keeps its `ProcessId`, so capsules that hold it find the
+//! process again once it is swapped in. Buffers shared over IPC with or by the
+//! process are unshared when it is swapped out.
+//!
+//! Usage
+//! -----
+//!
+//! See `components::loader::swap`. Then, for example:
+//!
+//! ```rust,ignore
+//! // Swap the sensor process out, and back in ten minutes later.
+//! process_swap.swap_out(sensor_process_id, Some(10 * 60 * 1000))?;
+//! ```
+
+use core::cell::Cell;
+use core::cmp;
+use core::ptr::NonNull;
+
+use crate::capabilities::ProcessManagementCapability;
+use crate::errorcode::ErrorCode;
+use crate::hil::nonvolatile_storage::{NonvolatileStorage, NonvolatileStorageClient};
+use crate::hil::time::{Alarm, AlarmClient, ConvertTicks, Ticks};
+use crate::kernel::Kernel;
+use crate::process::{Process, ProcessId, State};
+use crate::process_loading::ProcessSwapping;
+use crate::utilities::cells::{OptionalCell, TakeCell};
+
+/// Client of [`ProcessSwap`].
+pub trait ProcessSwapClient {
+    /// The memory of the process `process_id` was written to flash, and it
+    /// was removed from the processes array. On error, the process is back
+    /// in the state it was in.
+    fn swapped_out(&self, process_id: ProcessId, result: Result<(), ErrorCode>);
+
+    /// The process `process_id` was restored. On error, it stays swapped
+    /// out.
+    fn swapped_in(&self, process_id: ProcessId, result: Result<(), ErrorCode>);
+}
+
+/// Bring swapped out processes back into memory when they are needed.
+pub trait SwapIn {
+    /// Start to swap in the process named `name`, or to load it if it was not
+    /// loaded for lack of memory. Returns whether the process will exist
+    /// later.
+    fn swap_in_by_name(&self, name: &str) -> bool;
+
+    /// Start to swap in the process that was in slot `index` of the processes
+    /// array. Returns whether the process will exist later.
+    fn swap_in_by_index(&self, index: usize) -> bool;
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ImageState {
+    /// The memory is being written to flash.
+    Saving,
+    /// The memory is in flash, and may be used by another process.
+    Saved,
+    /// The memory is being read back from flash.
+    Restoring,
+}
+
+/// A process swapped out to a slot in flash.
+#[derive(Clone, Copy)]
+struct Image<T: Ticks> {
+    /// The process structure, which is in the swapped memory. It can only be
+    /// used while the memory holds the image.
+    process: NonNull<dyn Process>,
+    process_id: ProcessId,
+    name: &'static str,
+    /// Start of the memory of the process.
+    start: usize,
+    len: usize,
+    /// Whether to resume the process after it is swapped in, because it was
+    /// not stopped before it was swapped out.
+    resume: bool,
+    /// When to swap the process back in, as a reference and a delay.
+    wake: Option<(T, T)>,
+    state: ImageState,
+}
+
+#[derive(Clone, Copy)]
+enum Operation {
+    /// Writing the image in `slot`, `done` bytes so far.
+    SwapOut { slot: usize, done: usize },
+    /// Reading the image in `slot`, `done` bytes so far.
+    SwapIn { slot: usize, done: usize },
+}
+
+/// Swaps processes to slots of a flash region.
+///
+/// Slot `i` starts at `storage_start + i * slot_size` in the address space of
+/// the storage. A process can be swapped out if its memory fits in a slot.
+pub struct ProcessSwap<'a, A: Alarm<'a>, const NUM_SLOTS: usize> {
+    kernel: &'static Kernel,
+    loader: &'a dyn ProcessSwapping,
+    storage: &'a dyn NonvolatileStorage<'a>,
+    alarm: &'a A,
+    /// Copies memory to and from flash, one chunk at a time.
+    buffer: TakeCell<'static, [u8]>,
+    storage_start: usize,
+    slot_size: usize,
+    images: [OptionalCell<Image<A::Ticks>>; NUM_SLOTS],
+    /// The slots to swap in, one bit per slot.
+    requested: Cell<usize>,
+    operation: OptionalCell<Operation>,
+    client: OptionalCell<&'a dyn ProcessSwapClient>,
+}
+
+impl<'a, A: Alarm<'a>, const NUM_SLOTS: usize> ProcessSwap<'a, A, NUM_SLOTS> {
+    pub fn new(
+        kernel: &'static Kernel,
+        loader: &'a dyn ProcessSwapping,
+        storage: &'a dyn NonvolatileStorage<'a>,
+        alarm: &'a A,
+        buffer: &'static mut [u8],
+        storage_start: usize,
+        slot_size: usize,
+        _capability_management: &dyn ProcessManagementCapability,
+    ) -> Self {
+        const {
+            assert!(NUM_SLOTS <= usize::BITS as usize);
+        }
+        Self {
+            kernel,
+            loader,
+            storage,
+            alarm,
+            buffer: TakeCell::new(buffer),
+            storage_start,
+            slot_size,
+            images: [const { OptionalCell::empty() }; NUM_SLOTS],
+            requested: Cell::new(0),
+            operation: OptionalCell::empty(),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn ProcessSwapClient) {
+        self.client.set(client);
+    }
+
+    /// Write the memory of the process `process_id` to flash and remove it
+    /// from the processes array. If `wake_after_ms` is given, the process is
+    /// swapped back in after that many milliseconds.
+    ///
+    /// Returns `Err(ErrorCode::BUSY)` if a process is being swapped,
+    /// `Err(ErrorCode::NOMEM)` if all slots are used, `Err(ErrorCode::SIZE)`
+    /// if the memory of the process does not fit in a slot, and
+    /// `Err(ErrorCode::INVAL)` if no such process exists or it is not
+    /// runnable.
+    pub fn swap_out(
+        &self,
+        process_id: ProcessId,
+        wake_after_ms: Option<u32>,
+    ) -> Result<(), ErrorCode> {
+        if self.operation.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        let wake = wake_after_ms.map(|ms| (self.alarm.now(), self.alarm.ticks_from_ms(ms)));
+        self.start_swap_out(process_id, wake)
+    }
+
+    /// Swap the process `process_id` back in.
+    ///
+    /// Returns `Err(ErrorCode::INVAL)` if the process is not swapped out.
+    pub fn swap_in(&self, process_id: ProcessId) -> Result<(), ErrorCode> {
+        let slot = self
+            .find_slot(|image| image.process_id == process_id)
+            .ok_or(ErrorCode::INVAL)?;
+        self.request(slot);
+        Ok(())
+    }
+
+    /// Whether the process `process_id` is swapped out.
+    pub fn is_swapped_out(&self, process_id: ProcessId) -> bool {
+        self.find_slot(|image| image.process_id == process_id)
+            .is_some()
+    }
+
+    /// The processes that are swapped out, and their names.
+    pub fn swapped_out(&self) -> impl Iterator<Item = (ProcessId, &'static str)> + '_ {
+        self.images
+            .iter()
+            .filter_map(|image| image.get().map(|image| (image.process_id, image.name)))
+    }
+
+    /// Load the process binary named `name`, which was not loaded for lack of
+    /// memory, into the memory of a swapped out process that no other process
+    /// uses. The binary is loaded by the process loader, which notifies its
+    /// client.
+    ///
+    /// Returns `Err(ErrorCode::NOMEM)` if there is no such memory, and the
+    /// errors of the loader otherwise.
+    pub fn load_process(&self, name: &str) -> Result<(), ErrorCode> {
+        if self.operation.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        let slot = self
+            .find_slot(|image| {
+                image.state == ImageState::Saved
+                    && self
+                        .resident_in(image.start, image.start + image.len)
+                        .is_none()
+            })
+            .ok_or(ErrorCode::NOMEM)?;
+        let image = self.images[slot].get().ok_or(ErrorCode::FAIL)?;
+        // SAFETY: the memory was saved to flash, and no process uses it. The
+        // images that use it are written back only once the loader is done,
+        // after the process loaded into it is swapped out.
+        let memory = unsafe { core::slice::from_raw_parts_mut(image.start as *mut u8, image.len) };
+        self.loader.load_process_into(name, memory)
+    }
+
+    fn find_slot(&self, f: impl Fn(&Image<A::Ticks>) -> bool) -> Option<usize> {
+        self.images
+            .iter()
+            .position(|image| image.get().is_some_and(|image| f(&image)))
+    }
+
+    /// A loaded process that uses memory in `start..end`.
+    fn resident_in(&self, start: usize, end: usize) -> Option<ProcessId> {
+        self.kernel.get_process_iter().find_map(|process| {
+            let addresses = process.get_addresses();
+            if addresses.sram_start < end && start < addresses.sram_end {
+                Some(process.processid())
+            } else {
+                None
+            }
+        })
+    }
+
+    fn request(&self, slot: usize) {
+        self.requested.set(self.requested.get() | (1 << slot));
+        self.run();
+    }
+
+    fn start_swap_out(
+        &self,
+        process_id: ProcessId,
+        wake: Option<(A::Ticks, A::Ticks)>,
+    ) -> Result<(), ErrorCode> {
+        let slot = self
+            .images
+            .iter()
+            .position(|image| image.is_none())
+            .ok_or(ErrorCode::NOMEM)?;
+        let process = self
+            .loader
+            .take_process(process_id)
+            .ok_or(ErrorCode::INVAL)?;
+        let resume = match process.get_state() {
+            State::Running | State::Yielded | State::YieldedFor(_) => true,
+            State::Stopped(_) => false,
+            State::Faulted | State::Terminated => {
+                // SAFETY: the memory of the process was not touched.
+                let _ = unsafe { self.loader.put_process(process) };
+                return Err(ErrorCode::INVAL);
+            }
+        };
+        let addresses = process.get_addresses();
+        let len = addresses.sram_end - addresses.sram_start;
+        if len > self.slot_size {
+            // SAFETY: the memory of the process was not touched.
+            let _ = unsafe { self.loader.put_process(process) };
+            return Err(ErrorCode::SIZE);
+        }
+        process.stop();
+        self.revoke_shared_memory(process, addresses.sram_start, addresses.sram_end);
+
+        self.images[slot].set(Image {
+            process: NonNull::from(process),
+            process_id,
+            name: process.get_process_name(),
+            start: addresses.sram_start,
+            len,
+            resume,
+            wake,
+            state: ImageState::Saving,
+        });
+        self.operation.set(Operation::SwapOut { slot, done: 0 });
+        self.write_chunk(slot, 0).inspect_err(|_| {
+            self.operation.clear();
+            self.cancel_swap_out(slot);
+        })
+    }
+
+    /// Remove the MPU regions that give access to the memory of `process`
+    /// (`start..end`) from the other processes, and those that give access to
+    /// the memory of other processes from `process`.
+    ///
+    /// IPC adds such regions for the buffers that clients share with a
+    /// service. Once the memory holds another process, or the service is
+    /// swapped back in while a client was replaced, they would give access to
+    /// memory of an unrelated process. IPC adds the regions again on the next
+    /// notify.
+    fn revoke_shared_memory(&self, process: &dyn Process, start: usize, end: usize) {
+        for other in self.kernel.get_process_iter() {
+            other.remove_mpu_regions_in(start, end);
+        }
+        process.remove_mpu_regions_in(0, usize::MAX);
+    }
+
+    /// Put back the process of the image in `slot`, which is being swapped
+    /// out.
+    fn cancel_swap_out(&self, slot: usize) {
+        if let Some(image) = self.images[slot].take() {
+            // SAFETY: the process was stopped and removed from the processes
+            // array, so its memory was only read.
+            let process = unsafe { &*image.process.as_ptr() };
+            let _ = unsafe { self.loader.put_process(process) };
+            if image.resume {
+                process.resume();
+            }
+        }
+    }
+
+    fn start_swap_in(&self, slot: usize) -> Result<(), ErrorCode> {
+        let image = self.images[slot].get().ok_or(ErrorCode::INVAL)?;
+        if image.state != ImageState::Saved {
+            return Err(ErrorCode::BUSY);
+        }
+        // A process being loaded may be using the memory.
+        if self.loader.is_loading() {
+            return Err(ErrorCode::BUSY);
+        }
+        if let Some(resident) = self.resident_in(image.start, image.start + image.len) {
+            // Swap out the process using the memory first, and try again
+            // after.
+            self.requested.set(self.requested.get() | (1 << slot));
+            return self.start_swap_out(resident, None).inspect_err(|_| {
+                self.requested.set(self.requested.get() & !(1 << slot));
+            });
+        }
+
+        self.images[slot].set(Image {
+            state: ImageState::Restoring,
+            ..image
+        });
+        self.operation.set(Operation::SwapIn { slot, done: 0 });
+        self.read_chunk(slot, 0).inspect_err(|_| {
+            self.operation.clear();
+            self.images[slot].set(image);
+        })
+    }
+
+    fn write_chunk(&self, slot: usize, done: usize) -> Result<(), ErrorCode> {
+        let image = self.images[slot].get().ok_or(ErrorCode::FAIL)?;
+        let buffer = self.buffer.take().ok_or(ErrorCode::NOMEM)?;
+        let length = cmp::min(buffer.len(), image.len - done);
+        // SAFETY: the memory belongs to the process, which is stopped and out
+        // of the processes array, so nothing writes to it.
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                (image.start + done) as *const u8,
+                buffer.as_mut_ptr(),
+                length,
+            );
+        }
+        self.storage
+            .write(buffer, self.slot_address(slot) + done, length)
+    }
+
+    fn read_chunk(&self, slot: usize, done: usize) -> Result<(), ErrorCode> {
+        let image = self.images[slot].get().ok_or(ErrorCode::FAIL)?;
+        let buffer = self.buffer.take().ok_or(ErrorCode::NOMEM)?;
+        let length = cmp::min(buffer.len(), image.len - done);
+        self.storage
+            .read(buffer, self.slot_address(slot) + done, length)
+    }
+
+    fn slot_address(&self, slot: usize) -> usize {
+        self.storage_start + slot * self.slot_size
+    }
+
+    /// Swap in the requested processes and those whose delay expired, one at
+    /// a time, and set the alarm for the next delay.
+    fn run(&self) {
+        if self.operation.is_some() {
+            return;
+        }
+
+        let now = self.alarm.now();
+        for (slot, image) in self.images.iter().enumerate() {
+            if let Some(
+                image @ Image {
+                    wake: Some((reference, dt)),
+                    state: ImageState::Saved,
+                    ..
+                },
+            ) = image.get()
+            {
+                if !now.within_range(reference, reference.wrapping_add(dt)) {
+                    self.images[slot].set(Image {
+                        wake: None,
+                        ..image
+                    });
+                    self.requested.set(self.requested.get() | (1 << slot));
+                }
+            }
+        }
+
+        while self.requested.get() != 0 {
+            let slot = self.requested.get().trailing_zeros() as usize;
+            self.requested.set(self.requested.get() & !(1 << slot));
+            match self.start_swap_in(slot) {
+                Ok(()) => return,
+                Err(e) => {
+                    if let Some(image) = self.images[slot].get() {
+                        self.client
+                            .map(|client| client.swapped_in(image.process_id, Err(e)));
+                    }
+                }
+            }
+        }
+
+        let next = self
+            .images
+            .iter()
+            .filter_map(|image| image.get().and_then(|image| image.wake))
+            .min_by_key(|(reference, dt)| reference.wrapping_add(*dt).wrapping_sub(now));
+        match next {
+            Some((reference, dt)) => self.alarm.set_alarm(reference, dt),
+            None => {
+                let _ = self.alarm.disarm();
+            }
+        }
+    }
+
+    fn swap_out_done(&self, slot: usize, result: Result<(), ErrorCode>) {
+        self.operation.clear();
+        let Some(image) = self.images[slot].get() else {
+            return;
+        };
+        match result {
+            Ok(()) => self.images[slot].set(Image {
+                state: ImageState::Saved,
+                ..image
+            }),
+            Err(_) => self.cancel_swap_out(slot),
+        }
+        self.client
+            .map(|client| client.swapped_out(image.process_id, result));
+        self.run();
+    }
+
+    fn swap_in_done(&self, slot: usize, result: Result<(), ErrorCode>) {
+        self.operation.clear();
+        let Some(image) = self.images[slot].get() else {
+            return;
+        };
+        let result = result.and_then(|()| {
+            // SAFETY: the memory of the process holds the image again, and
+            // the processes using it were swapped out.
+            let process = unsafe { &*image.process.as_ptr() };
+            unsafe { self.loader.put_process(process) }?;
+            if image.resume {
+                process.resume();
+            }
+            Ok(())
+        });
+        match result {
+            Ok(()) => self.images[slot].clear(),
+            // The image is still in flash, and can be read again.
+            Err(_) => self.images[slot].set(Image {
+                state: ImageState::Saved,
+                ..image
+            }),
+        }
+        self.client
+            .map(|client| client.swapped_in(image.process_id, result));
+        self.run();
+    }
+}
+
+impl<'a, A: Alarm<'a>, const NUM_SLOTS: usize> SwapIn for ProcessSwap<'a, A, NUM_SLOTS> {
+    fn swap_in_by_name(&self, name: &str) -> bool {
+        match self.find_slot(|image| image.name == name) {
+            Some(slot) => {
+                self.request(slot);
+                true
+            }
+            None => self.load_process(name).is_ok(),
+        }
+    }
+
+    fn swap_in_by_index(&self, index: usize) -> bool {
+        match self.find_slot(|image| image.process_id.slot_index() == index) {
+            Some(slot) => {
+                self.request(slot);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>, const NUM_SLOTS: usize> AlarmClient for ProcessSwap<'a, A, NUM_SLOTS> {
+    fn alarm(&self) {
+        self.run();
+    }
+}
+
+impl<'a, A: Alarm<'a>, const NUM_SLOTS: usize> NonvolatileStorageClient
+    for ProcessSwap<'a, A, NUM_SLOTS>
+{
+    fn read_done(&self, buffer: &'static mut [u8], length: usize) {
+        let Some(Operation::SwapIn { slot, done }) = self.operation.get() else {
+            self.buffer.replace(buffer);
+            return;
+        };
+        let Some(image) = self.images[slot].get() else {
+            self.buffer.replace(buffer);
+            return self.swap_in_done(slot, Err(ErrorCode::FAIL));
+        };
+        // SAFETY: no process uses the memory, and the image is written back
+        // where it was read from.
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                buffer.as_ptr(),
+                (image.start + done) as *mut u8,
+                cmp::min(length, image.len - done),
+            );
+        }
+        self.buffer.replace(buffer);
+
+        let done = done + length;
+        if done < image.len && length > 0 {
+            self.operation.set(Operation::SwapIn { slot, done });
+            if let Err(e) = self.read_chunk(slot, done) {
+                self.swap_in_done(slot, Err(e));
+            }
+        } else if done < image.len {
+            self.swap_in_done(slot, Err(ErrorCode::FAIL));
+        } else {
+            self.swap_in_done(slot, Ok(()));
+        }
+    }
+
+    fn write_done(&self, buffer: &'static mut [u8], length: usize) {
+        self.buffer.replace(buffer);
+        let Some(Operation::SwapOut { slot, done }) = self.operation.get() else {
+            return;
+        };
+        let Some(image) = self.images[slot].get() else {
+            return self.swap_out_done(slot, Err(ErrorCode::FAIL));
+        };
+        let done = done + length;
+        if done < image.len && length > 0 {
+            self.operation.set(Operation::SwapOut { slot, done });
+            if let Err(e) = self.write_chunk(slot, done) {
+                self.swap_out_done(slot, Err(e));
+            }
+        } else if done < image.len {
+            self.swap_out_done(slot, Err(ErrorCode::FAIL));
+        } else {
+            self.swap_out_done(slot, Ok(()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capabilities;
+    use crate::hil::time::{Freq1KHz, Ticks32, Time};
+    use crate::platform::mpu;
+    use crate::process::{
+        BinaryVersion, Error, FaultRecord, FunctionCall, ProcessAddresses,
+        ProcessCustomGrantIdentifier, ProcessSizes, ProcessStats, ShortId, StoppedState, Task,
+    };
+    use crate::process_checker::AcceptedCredential;
+    use crate::processbuffer::{ReadOnlyProcessBuffer, ReadWriteProcessBuffer};
+    use crate::storage_permissions::StoragePermissions;
+    use crate::syscall::{ContextSwitchReason, Syscall, SyscallReturn};
+    use crate::upcall::UpcallId;
+    use crate::utilities::capability_ptr::CapabilityPtr;
+    use core::fmt::Write;
+    use tock_tbf::types::CommandPermissions;
+
+    const MEMORY_LEN: usize = 40;
+
+    /// A process that owns `MEMORY_LEN` bytes of memory, and records the MPU
+    /// regions it is asked to remove.
+    struct TestProcess {
+        process_id: OptionalCell<ProcessId>,
+        start: usize,
+        state: Cell<State>,
+        removed_regions: Cell<Option<(usize, usize)>>,
+    }
+
+    impl TestProcess {
+        fn new() -> Self {
+            let memory = leak_mut([0u8; MEMORY_LEN]);
+            for (i, byte) in memory.iter_mut().enumerate() {
+                *byte = i as u8;
+            }
+            Self {
+                process_id: OptionalCell::empty(),
+                start: memory.as_mut_ptr() as usize,
+                state: Cell::new(State::Yielded),
+                removed_regions: Cell::new(None),
+            }
+        }
+
+        fn memory(&self) -> &[u8] {
+            // SAFETY: the memory was leaked for this process.
+            unsafe { core::slice::from_raw_parts(self.start as *const u8, MEMORY_LEN) }
+        }
+
+        fn clear_memory(&self) {
+            // SAFETY: the memory was leaked for this process.
+            unsafe { core::ptr::write_bytes(self.start as *mut u8, 0, MEMORY_LEN) };
+        }
+    }
+
+    impl Process for TestProcess {
+        fn processid(&self) -> ProcessId {
+            self.process_id.unwrap_or_panic()
+        }
+        fn short_app_id(&self) -> ShortId {
+            ShortId::LocallyUnique
+        }
+        fn binary_version(&self) -> Option<BinaryVersion> {
+            None
+        }
+        fn get_credential(&self) -> Option<AcceptedCredential> {
+            None
+        }
+        fn get_restart_count(&self) -> usize {
+            0
+        }
+        fn get_last_fault(&self) -> Option<FaultRecord> {
+            None
+        }
+        fn get_stats(&self) -> ProcessStats {
+            unimplemented!()
+        }
+        fn add_run(&self, _time_us: u32, _cycles: u64) {}
+        fn get_process_name(&self) -> &'static str {
+            "test"
+        }
+        fn has_tasks(&self) -> bool {
+            false
+        }
+        fn pending_tasks(&self) -> usize {
+            0
+        }
+        fn enqueue_task(&self, _task: Task) -> Result<(), ErrorCode> {
+            unimplemented!()
+        }
+        fn dequeue_task(&self) -> Option<Task> {
+            None
+        }
+        fn remove_upcall(&self, _upcall_id: UpcallId) -> Option<Task> {
+            None
+        }
+        fn remove_pending_upcalls(&self, _upcall_id: UpcallId) -> usize {
+            0
+        }
+        fn get_state(&self) -> State {
+            self.state.get()
+        }
+        fn ready(&self) -> bool {
+            false
+        }
+        fn is_running(&self) -> bool {
+            false
+        }
+        fn set_yielded_state(&self) {}
+        fn set_yielded_for_state(&self, _upcall_id: UpcallId) {}
+        fn stop(&self) {
+            if self.state.get() == State::Yielded {
+                self.state.set(State::Stopped(StoppedState::Yielded));
+            }
+        }
+        fn resume(&self) {
+            if self.state.get() == State::Stopped(StoppedState::Yielded) {
+                self.state.set(State::Yielded);
+            }
+        }
+        fn set_fault_state(&self) {}
+        fn start(&self, _cap: &dyn capabilities::ProcessStartCapability) {}
+        fn try_restart(&self, _completion_code: Option<u32>) {}
+        fn terminate(&self, _completion_code: Option<u32>) {}
+        fn get_completion_code(&self) -> Option<Option<u32>> {
+            None
+        }
+        fn brk(&self, _new_break: *const u8) -> Result<CapabilityPtr, Error> {
+            unimplemented!()
+        }
+        fn sbrk(&self, _increment: isize) -> Result<CapabilityPtr, Error> {
+            unimplemented!()
+        }
+        fn number_writeable_flash_regions(&self) -> usize {
+            0
+        }
+        fn get_writeable_flash_region(&self, _region_index: usize) -> (usize, usize) {
+            (0, 0)
+        }
+        fn update_stack_start_pointer(&self, _stack_pointer: *const u8) {}
+        fn update_heap_start_pointer(&self, _heap_pointer: *const u8) {}
+        fn build_readwrite_process_buffer(
+            &self,
+            _buf_start_addr: *mut u8,
+            _size: usize,
+        ) -> Result<ReadWriteProcessBuffer, ErrorCode> {
+            unimplemented!()
+        }
+        fn build_readonly_process_buffer(
+            &self,
+            _buf_start_addr: *const u8,
+            _size: usize,
+        ) -> Result<ReadOnlyProcessBuffer, ErrorCode> {
+            unimplemented!()
+        }
+        unsafe fn set_byte(&self, _addr: *mut u8, _value: u8) -> bool {
+            false
+        }
+        fn get_command_permissions(
+            &self,
+            _driver_num: usize,
+            _offset: usize,
+        ) -> CommandPermissions {
+            CommandPermissions::NoPermsAtAll
+        }
+        fn get_storage_permissions(&self) -> StoragePermissions {
+            unimplemented!()
+        }
+        fn setup_mpu(&self) {}
+        fn add_mpu_region(
+            &self,
+            _unallocated_memory_start: *const u8,
+            _unallocated_memory_size: usize,
+            _min_region_size: usize,
+        ) -> Option<mpu::Region> {
+            None
+        }
+        fn remove_mpu_region(&self, _region: mpu::Region) -> Result<(), ErrorCode> {
+            Err(ErrorCode::INVAL)
+        }
+        fn remove_mpu_regions_in(&self, start: usize, end: usize) -> usize {
+            self.removed_regions.set(Some((start, end)));
+            0
+        }
+        fn allocate_grant(
+            &self,
+            _grant_num: usize,
+            _driver_num: usize,
+            _size: usize,
+            _align: usize,
+        ) -> Result<(), ()> {
+            Err(())
+        }
+        fn grant_is_allocated(&self, _grant_num: usize) -> Option<bool> {
+            None
+        }
+        fn allocate_custom_grant(
+            &self,
+            _size: usize,
+            _align: usize,
+        ) -> Result<(ProcessCustomGrantIdentifier, NonNull<u8>), ()> {
+            Err(())
+        }
+        fn enter_grant(&self, _grant_num: usize) -> Result<NonNull<u8>, Error> {
+            Err(Error::InactiveApp)
+        }
+        fn enter_custom_grant(
+            &self,
+            _identifier: ProcessCustomGrantIdentifier,
+        ) -> Result<*mut u8, Error> {
+            Err(Error::InactiveApp)
+        }
+        unsafe fn leave_grant(&self, _grant_num: usize) {}
+        fn grant_allocated_count(&self) -> Option<usize> {
+            None
+        }
+        fn lookup_grant_from_driver_num(&self, _driver_num: usize) -> Result<usize, Error> {
+            Err(Error::InactiveApp)
+        }
+        fn is_valid_upcall_function_pointer(&self, _upcall_fn: *const ()) -> bool {
+            false
+        }
+        fn set_syscall_return_value(&self, _return_value: SyscallReturn) {}
+        fn set_process_function(&self, _callback: FunctionCall) {}
+        fn switch_to(&self) -> Option<ContextSwitchReason> {
+            None
+        }
+        fn get_addresses(&self) -> ProcessAddresses {
+            ProcessAddresses {
+                flash_start: 0,
+                flash_non_protected_start: 0,
+                flash_integrity_end: core::ptr::null(),
+                flash_end: 0,
+                sram_start: self.start,
+                sram_app_brk: self.start + MEMORY_LEN,
+                sram_grant_start: self.start + MEMORY_LEN,
+                sram_end: self.start + MEMORY_LEN,
+                sram_heap_start: None,
+                sram_stack_top: None,
+                sram_stack_bottom: None,
+            }
+        }
+        fn get_sizes(&self) -> ProcessSizes {
+            unimplemented!()
+        }
+        fn get_stored_state(&self, _out: &mut [u8]) -> Result<usize, ErrorCode> {
+            Err(ErrorCode::NOSUPPORT)
+        }
+        fn set_stored_state(&self, _state: &[u8]) -> Result<(), ErrorCode> {
+            Err(ErrorCode::NOSUPPORT)
+        }
+        fn print_full_process(&self, _writer: &mut dyn Write) {}
+        fn debug_syscall_count(&self) -> usize {
+            0
+        }
+        fn debug_dropped_upcall_count(&self) -> usize {
+            0
+        }
+        fn debug_timeslice_expiration_count(&self) -> usize {
+            0
+        }
+        fn debug_timeslice_expired(&self) {}
+        fn debug_syscall_called(&self, _last_syscall: Syscall) {}
+        fn debug_syscall_last(&self) -> Option<Syscall> {
+            None
+        }
+    }
+
+    /// Loader of a single process, which is in the processes array while
+    /// `present` is set.
+    struct TestLoader {
+        process: &'static TestProcess,
+        present: Cell<bool>,
+    }
+
+    impl ProcessSwapping for TestLoader {
+        fn take_process(&self, process_id: ProcessId) -> Option<&'static dyn Process> {
+            if self.present.get() && self.process.processid() == process_id {
+                self.present.set(false);
+                Some(self.process)
+            } else {
+                None
+            }
+        }
+
+        unsafe fn put_process(&self, _process: &'static dyn Process) -> Result<(), ErrorCode> {
+            if self.present.replace(true) {
+                Err(ErrorCode::BUSY)
+            } else {
+                Ok(())
+            }
+        }
+
+        fn load_process_into(
+            &self,
+            _name: &str,
+            _memory: &'static mut [u8],
+        ) -> Result<(), ErrorCode> {
+            Err(ErrorCode::NOSUPPORT)
+        }
+
+        fn is_loading(&self) -> bool {
+            false
+        }
+    }
+
+    /// Storage that completes reads and writes when `complete` is called.
+    struct TestStorage<'a> {
+        data: [Cell<u8>; 2 * MEMORY_LEN],
+        pending: TakeCell<'static, [u8]>,
+        /// Whether the pending operation is a write, its address and length.
+        operation: Cell<(bool, usize, usize)>,
+        client: OptionalCell<&'a dyn NonvolatileStorageClient>,
+    }
+
+    impl TestStorage<'_> {
+        fn new() -> Self {
+            Self {
+                data: core::array::from_fn(|_| Cell::new(0xFF)),
+                pending: TakeCell::empty(),
+                operation: Cell::new((false, 0, 0)),
+                client: OptionalCell::empty(),
+            }
+        }
+
+        /// Complete the pending operation, and return whether there was one.
+        fn complete(&self) -> bool {
+            let Some(buffer) = self.pending.take() else {
+                return false;
+            };
+            let (write, address, length) = self.operation.get();
+            let data = &self.data[address..address + length];
+            if write {
+                for (cell, byte) in data.iter().zip(buffer.iter()) {
+                    cell.set(*byte);
+                }
+                self.client.map(|client| client.write_done(buffer, length));
+            } else {
+                for (byte, cell) in buffer.iter_mut().zip(data.iter()) {
+                    *byte = cell.get();
+                }
+                self.client.map(|client| client.read_done(buffer, length));
+            }
+            true
+        }
+
+        fn complete_all(&self) {
+            while self.complete() {}
+        }
+    }
+
+    impl<'a> NonvolatileStorage<'a> for TestStorage<'a> {
+        fn set_client(&self, client: &'a dyn NonvolatileStorageClient) {
+            self.client.set(client);
+        }
+
+        fn read(
+            &self,
+            buffer: &'static mut [u8],
+            address: usize,
+            length: usize,
+        ) -> Result<(), ErrorCode> {
+            self.pending.replace(buffer);
+            self.operation.set((false, address, length));
+            Ok(())
+        }
+
+        fn write(
+            &self,
+            buffer: &'static mut [u8],
+            address: usize,
+            length: usize,
+        ) -> Result<(), ErrorCode> {
+            self.pending.replace(buffer);
+            self.operation.set((true, address, length));
+            Ok(())
+        }
+    }
+
+    struct TestAlarm {
+        now: Cell<u32>,
+        alarm: Cell<Option<(u32, u32)>>,
+    }
+
+    impl Time for TestAlarm {
+        type Frequency = Freq1KHz;
+        type Ticks = Ticks32;
+
+        fn now(&self) -> Ticks32 {
+            self.now.get().into()
+        }
+    }
+
+    impl<'a> Alarm<'a> for TestAlarm {
+        fn set_alarm_client(&self, _client: &'a dyn AlarmClient) {}
+
+        fn set_alarm(&self, reference: Ticks32, dt: Ticks32) {
+            self.alarm.set(Some((reference.into_u32(), dt.into_u32())));
+        }
+
+        fn get_alarm(&self) -> Ticks32 {
+            self.alarm
+                .get()
+                .map_or(0, |(reference, dt)| reference + dt)
+                .into()
+        }
+
+        fn disarm(&self) -> Result<(), ErrorCode> {
+            self.alarm.set(None);
+            Ok(())
+        }
+
+        fn is_armed(&self) -> bool {
+            self.alarm.get().is_some()
+        }
+
+        fn minimum_dt(&self) -> Ticks32 {
+            1u32.into()
+        }
+    }
+
+    #[derive(Default)]
+    struct TestClient {
+        swapped_out: Cell<Option<Result<(), ErrorCode>>>,
+        swapped_in: Cell<Option<Result<(), ErrorCode>>>,
+    }
+
+    impl ProcessSwapClient for TestClient {
+        fn swapped_out(&self, _process_id: ProcessId, result: Result<(), ErrorCode>) {
+            self.swapped_out.set(Some(result));
+        }
+
+        fn swapped_in(&self, _process_id: ProcessId, result: Result<(), ErrorCode>) {
+            self.swapped_in.set(Some(result));
+        }
+    }
+
+    fn leak<T>(value: T) -> &'static T {
+        extern crate std;
+        std::boxed::Box::leak(std::boxed::Box::new(value))
+    }
+
+    fn leak_mut<T>(value: T) -> &'static mut T {
+        extern crate std;
+        std::boxed::Box::leak(std::boxed::Box::new(value))
+    }
+
+    struct Test {
+        process: &'static TestProcess,
+        other: &'static TestProcess,
+        loader: &'static TestLoader,
+        storage: &'static TestStorage<'static>,
+        alarm: &'static TestAlarm,
+        client: &'static TestClient,
+        swap: &'static ProcessSwap<'static, TestAlarm, 1>,
+    }
+
+    /// A swapped process in slot 0 of the processes array, and another process
+    /// in slot 1. Slot 0 of the storage starts at `MEMORY_LEN`.
+    fn setup() -> Test {
+        let process = leak(TestProcess::new());
+        let other = leak(TestProcess::new());
+        let kernel = leak(Kernel::new(leak([None, Some(other as &dyn Process)])));
+        process.process_id.set(ProcessId::new(kernel, 7, 0));
+        other.process_id.set(ProcessId::new(kernel, 8, 1));
+        let loader = leak(TestLoader {
+            process,
+            present: Cell::new(true),
+        });
+        let storage = leak(TestStorage::new());
+        let alarm = leak(TestAlarm {
+            now: Cell::new(0),
+            alarm: Cell::new(None),
+        });
+        let client = leak(TestClient::default());
+        let swap = leak(ProcessSwap::new(
+            kernel,
+            loader,
+            storage,
+            alarm,
+            leak_mut([0; 16]),
+            MEMORY_LEN,
+            MEMORY_LEN,
+            &crate::create_capability!(capabilities::ProcessManagementCapability),
+        ));
+        storage.set_client(swap);
+        swap.set_client(client);
+        Test {
+            process,
+            other,
+            loader,
+            storage,
+            alarm,
+            client,
+            swap,
+        }
+    }
+
+    #[test]
+    fn swap_out_and_in() {
+        let test = setup();
+        let process_id = test.process.processid();
+
+        assert_eq!(test.swap.swap_out(process_id, None), Ok(()));
+        assert!(!test.loader.present.get());
+        assert_eq!(
+            test.process.get_state(),
+            State::Stopped(StoppedState::Yielded)
+        );
+        assert_eq!(test.swap.swap_out(process_id, None), Err(ErrorCode::BUSY));
+        test.storage.complete_all();
+        assert_eq!(test.client.swapped_out.take(), Some(Ok(())));
+        assert!(test.swap.is_swapped_out(process_id));
+        for (i, cell) in test.storage.data[MEMORY_LEN..].iter().enumerate() {
+            assert_eq!(cell.get(), i as u8);
+        }
+
+        // Another process may use the memory while the process is out.
+        test.process.clear_memory();
+        assert_eq!(test.swap.swap_in(process_id), Ok(()));
+        test.storage.complete_all();
+        assert_eq!(test.client.swapped_in.take(), Some(Ok(())));
+        assert!(test.loader.present.get());
+        assert!(!test.swap.is_swapped_out(process_id));
+        assert_eq!(test.process.get_state(), State::Yielded);
+        for (i, byte) in test.process.memory().iter().enumerate() {
+            assert_eq!(*byte, i as u8);
+        }
+        assert_eq!(test.swap.swap_in(process_id), Err(ErrorCode::INVAL));
+    }
+
+    #[test]
+    fn swap_out_revokes_shared_memory() {
+        let test = setup();
+        let start = test.process.start;
+
+        assert_eq!(test.swap.swap_out(test.process.processid(), None), Ok(()));
+        // The other process loses access to buffers in the swapped memory,
+        // and the swapped process to buffers anywhere.
+        assert_eq!(
+            test.other.removed_regions.get(),
+            Some((start, start + MEMORY_LEN))
+        );
+        assert_eq!(test.process.removed_regions.get(), Some((0, usize::MAX)));
+    }
+
+    #[test]
+    fn swap_in_when_needed() {
+        let test = setup();
+        let process_id = test.process.processid();
+
+        // Woken up by the alarm.
+        assert_eq!(test.swap.swap_out(process_id, Some(100)), Ok(()));
+        test.storage.complete_all();
+        assert_eq!(test.alarm.alarm.get(), Some((0, 100)));
+        test.alarm.now.set(50);
+        test.swap.alarm();
+        assert!(test.storage.pending.is_none());
+        test.alarm.now.set(101);
+        test.swap.alarm();
+        test.storage.complete_all();
+        assert_eq!(test.client.swapped_in.take(), Some(Ok(())));
+        assert!(!test.alarm.is_armed());
+
+        // Notified over IPC by index, or discovered by name.
+        assert_eq!(test.swap.swap_out(process_id, None), Ok(()));
+        test.storage.complete_all();
+        assert!(!test.swap.swap_in_by_index(1));
+        assert!(test.swap.swap_in_by_index(0));
+        test.storage.complete_all();
+        assert_eq!(test.client.swapped_in.take(), Some(Ok(())));
+
+        assert_eq!(test.swap.swap_out(process_id, None), Ok(()));
+        test.storage.complete_all();
+        assert!(test.swap.swap_in_by_name("test"));
+        test.storage.complete_all();
+        assert_eq!(test.client.swapped_in.take(), Some(Ok(())));
+        assert!(test.loader.present.get());
+    }
+}
@@ -13,6 +13,7 @@ use crate::deferred_call::DeferredCall;
 use crate::platform::chip::Chip;
 use crate::process::ProcessId;
 use crate::process::StoppedExecutingReason;
+use crate::utilities::section_monitor;
 
 use core::num::NonZeroU32;
 
@@ -51,7 +52,7 @@ pub trait Scheduler<C: Chip> {
     /// Custom implementations of this function must be very careful, however,
     /// as this function is called in the core kernel loop.
     unsafe fn execute_kernel_work(&self, chip: &C) {
-        chip.service_pending_interrupts();
+        section_monitor::measure("interrupts", || chip.service_pending_interrupts());
         while DeferredCall::has_tasks() && !chip.has_pending_interrupts() {
             DeferredCall::service_next_pending();
         }
@@ -20,6 +20,10 @@
 //!           reduced (i.e., it moves down one queue).
 //! - Rule 5: After some time period S, move all the jobs in the system to the
 //!           topmost queue.
+//!
+//! By default the scheduler has three queues, with timeslices of 10, 20 and
+//! 50 ms, and S is 5 seconds. Both can be configured with
+//! [`MLFQSched::new_with_queues`].
 
 use core::cell::Cell;
 use core::num::NonZeroU32;
@@ -60,49 +64,69 @@ impl<'a> ListNode<'a, MLFQProcessNode<'a>> for MLFQProcessNode<'a> {
     }
 }
 
-pub struct MLFQSched<'a, A: 'static + time::Alarm<'static>> {
+pub struct MLFQSched<'a, A: 'static + time::Alarm<'static>, const NUM_QUEUES: usize = 3> {
     alarm: &'static A,
-    pub processes: [List<'a, MLFQProcessNode<'a>>; 3], // Using Self::NUM_QUEUES causes rustc to crash..
+    pub processes: [List<'a, MLFQProcessNode<'a>>; NUM_QUEUES],
+    /// Timeslice of each queue, from the highest priority one
+    timeslices_us: [u32; NUM_QUEUES],
+    priority_refresh_period_ms: u32,
     next_reset: Cell<A::Ticks>,
     last_reset_check: Cell<A::Ticks>,
     last_timeslice: Cell<u32>,
     last_queue_idx: Cell<usize>,
+    /// When the last process started running, to measure how long it ran on
+    /// boards without a scheduler timer
+    last_start: Cell<A::Ticks>,
 }
 
-impl<'a, A: 'static + time::Alarm<'static>> MLFQSched<'a, A> {
+impl<A: 'static + time::Alarm<'static>> MLFQSched<'_, A> {
     /// How often to restore all processes to max priority
     pub const PRIORITY_REFRESH_PERIOD_MS: u32 = 5000;
-    pub const NUM_QUEUES: usize = 3;
+    /// Default timeslices of the three queues
+    pub const TIMESLICES_US: [u32; 3] = [10000, 20000, 50000];
 
     pub fn new(alarm: &'static A) -> Self {
+        Self::new_with_queues(alarm, Self::TIMESLICES_US, Self::PRIORITY_REFRESH_PERIOD_MS)
+    }
+}
+
+impl<'a, A: 'static + time::Alarm<'static>, const NUM_QUEUES: usize> MLFQSched<'a, A, NUM_QUEUES> {
+    pub const NUM_QUEUES: usize = NUM_QUEUES;
+
+    /// Create a scheduler with one queue for each of `timeslices_us`, from
+    /// the highest priority one. Timeslices must be nonzero, and usually grow
+    /// as priority decreases. All processes are restored to the highest
+    /// priority queue every `priority_refresh_period_ms`.
+    pub fn new_with_queues(
+        alarm: &'static A,
+        timeslices_us: [u32; NUM_QUEUES],
+        priority_refresh_period_ms: u32,
+    ) -> Self {
         Self {
             alarm,
-            processes: [List::new(), List::new(), List::new()],
+            processes: core::array::from_fn(|_| List::new()),
+            timeslices_us: timeslices_us.map(|timeslice| timeslice.max(1)),
+            priority_refresh_period_ms,
             next_reset: Cell::new(A::Ticks::from(0)),
             last_reset_check: Cell::new(A::Ticks::from(0)),
             last_timeslice: Cell::new(0),
             last_queue_idx: Cell::new(0),
+            last_start: Cell::new(A::Ticks::from(0)),
         }
     }
 
     fn get_timeslice_us(&self, queue_idx: usize) -> u32 {
-        match queue_idx {
-            0 => 10000,
-            1 => 20000,
-            2 => 50000,
-            _ => panic!("invalid queue idx"),
-        }
+        self.timeslices_us[queue_idx]
     }
 
     fn redeem_all_procs(&self) {
         for queue in self.processes.iter().skip(1) {
-            match queue.pop_head() {
-                Some(proc) => self.processes[0].push_tail(proc),
-                None => continue,
+            while let Some(proc) = queue.pop_head() {
+                proc.state.us_used_this_queue.set(0);
+                self.processes[0].push_tail(proc);
             }
         }
     }
-
     /// Returns the process at the head of the highest priority queue containing a process
     /// that is ready to execute (as determined by `has_tasks()`)
     /// This method moves that node to the head of its queue.
@@ -131,7 +155,9 @@ impl<'a, A: 'static + time::Alarm<'static>> MLFQSched<'a, A> {
     }
 }
 
-impl<A: 'static + time::Alarm<'static>, C: Chip> Scheduler<C> for MLFQSched<'_, A> {
+impl<A: 'static + time::Alarm<'static>, C: Chip, const NUM_QUEUES: usize> Scheduler<C>
+    for MLFQSched<'_, A, NUM_QUEUES>
+{
     fn next(&self) -> SchedulingDecision {
         let now = self.alarm.now();
         let next_reset = self.next_reset.get();
@@ -142,7 +168,7 @@ impl<A: 'static + time::Alarm<'static>, C: Chip> Scheduler<C> for MLFQSched<'_,
         if !now.within_range(last_reset_check, next_reset) {
             // Promote all processes to highest priority queue
             self.next_reset
-                .set(now.wrapping_add(self.alarm.ticks_from_ms(Self::PRIORITY_REFRESH_PERIOD_MS)));
+                .set(now.wrapping_add(self.alarm.ticks_from_ms(self.priority_refresh_period_ms)));
             self.redeem_all_procs();
         }
         self.last_reset_check.set(now);
@@ -151,34 +177,47 @@ impl<A: 'static + time::Alarm<'static>, C: Chip> Scheduler<C> for MLFQSched<'_,
             return SchedulingDecision::TrySleep;
         }
         let node_ref = node_ref_opt.unwrap();
+        // Processes that used up their allotment are moved down in `result`,
+        // so this is never 0.
         let timeslice = self.get_timeslice_us(queue_idx) - node_ref.state.us_used_this_queue.get();
         let next = node_ref.proc.unwrap().processid();
         self.last_queue_idx.set(queue_idx);
         self.last_timeslice.set(timeslice);
+        self.last_start.set(now);
 
         SchedulingDecision::RunProcess((next, NonZeroU32::new(timeslice)))
     }
 
     fn result(&self, result: StoppedExecutingReason, execution_time_us: Option<u32>) {
-        let execution_time_us = execution_time_us.unwrap(); // should never fail as we never run cooperatively
+        // Without a scheduler timer, the kernel does not measure how long the
+        // process ran, so measure it with the alarm instead.
+        let execution_time_us = execution_time_us.unwrap_or_else(|| {
+            let elapsed = self.alarm.now().wrapping_sub(self.last_start.get());
+            self.alarm.ticks_to_us(elapsed)
+        });
         let queue_idx = self.last_queue_idx.get();
         // Last executed node will always be at head of its queue
         let node_ref = self.processes[queue_idx].head().unwrap();
-        node_ref
+        let used_us = node_ref
             .state
             .us_used_this_queue
-            .set(self.last_timeslice.get() - execution_time_us);
+            .get()
+            .saturating_add(execution_time_us);
 
-        let punish = result == StoppedExecutingReason::TimesliceExpired;
+        // A process that keeps yielding before its timeslice expires is still
+        // moved down once it used up the allotment of its queue.
+        let punish = result == StoppedExecutingReason::TimesliceExpired
+            || used_us >= self.get_timeslice_us(queue_idx);
         if punish {
             node_ref.state.us_used_this_queue.set(0);
-            let next_queue = if queue_idx == Self::NUM_QUEUES - 1 {
+            let next_queue = if queue_idx == NUM_QUEUES - 1 {
                 queue_idx
             } else {
                 queue_idx + 1
             };
             self.processes[next_queue].push_tail(self.processes[queue_idx].pop_head().unwrap());
         } else {
+            node_ref.state.us_used_this_queue.set(used_us);
             self.processes[queue_idx].push_tail(self.processes[queue_idx].pop_head().unwrap());
         }
     }
@@ -92,6 +92,7 @@ pub enum SyscallClass {
     Memop = 5,
     Exit = 6,
     UserspaceReadableAllow = 7,
+    CommandBatch = 8,
 }
 
 /// Enumeration of the yield system calls based on the Yield identifier
@@ -132,6 +133,7 @@ impl TryFrom<u8> for SyscallClass {
             5 => Ok(SyscallClass::Memop),
             6 => Ok(SyscallClass::Exit),
             7 => Ok(SyscallClass::UserspaceReadableAllow),
+            8 => Ok(SyscallClass::CommandBatch),
             i => Err(i),
         }
     }
@@ -220,6 +222,17 @@ pub enum Syscall {
         arg0: usize,
     },
 
+    /// Structure representing an invocation of the CommandBatch system call
+    /// class.
+    CommandBatch {
+        /// The driver identifier.
+        driver_number: usize,
+        /// The address of the array of commands.
+        batch_address: *mut u8,
+        /// The number of commands in the array.
+        batch_len: usize,
+    },
+
     /// Structure representing an invocation of the Exit system call class.
     Exit {
         /// The exit identifier.
@@ -289,10 +302,30 @@ impl Syscall {
                 which: r0,
                 completion_code: r1.into(),
             }),
+            Ok(SyscallClass::CommandBatch) => Some(Syscall::CommandBatch {
+                driver_number: r0,
+                batch_address: r1.as_ptr::<u8>().cast_mut(),
+                batch_len: r2.into(),
+            }),
             Err(_) => None,
         }
     }
 
+    /// Get the class of the syscall.
+    pub fn class(&self) -> SyscallClass {
+        match *self {
+            Syscall::Yield { .. } => SyscallClass::Yield,
+            Syscall::Subscribe { .. } => SyscallClass::Subscribe,
+            Syscall::Command { .. } => SyscallClass::Command,
+            Syscall::ReadWriteAllow { .. } => SyscallClass::ReadWriteAllow,
+            Syscall::UserspaceReadableAllow { .. } => SyscallClass::UserspaceReadableAllow,
+            Syscall::ReadOnlyAllow { .. } => SyscallClass::ReadOnlyAllow,
+            Syscall::Memop { .. } => SyscallClass::Memop,
+            Syscall::Exit { .. } => SyscallClass::Exit,
+            Syscall::CommandBatch { .. } => SyscallClass::CommandBatch,
+        }
+    }
+
     /// Get the `driver_number` for the syscall classes that use driver numbers.
     pub fn driver_number(&self) -> Option<usize> {
         match *self {
@@ -326,6 +359,11 @@ impl Syscall {
                 allow_address: _,
                 allow_size: _,
             } => Some(driver_number),
+            Syscall::CommandBatch {
+                driver_number,
+                batch_address: _,
+                batch_len: _,
+            } => Some(driver_number),
             _ => None,
         }
     }
@@ -369,6 +407,17 @@ impl Syscall {
     }
 }
 
+/// Maximum number of commands in a CommandBatch system call.
+pub const MAX_COMMAND_BATCH_LEN: usize = 8;
+
+/// Size in bytes of each command of a CommandBatch system call.
+///
+/// Each command is the command number, its two arguments and a reserved
+/// word, 32 bits each. The kernel
+/// overwrites each command it runs with its return value, encoded in four
+/// 32-bit words as in TRD104.
+pub const COMMAND_BATCH_ENTRY_SIZE: usize = 16;
+
 // ---------- SYSCALL RETURN VALUES ----------
 
 /// Enumeration of the possible system call return variants.
@@ -711,4 +760,14 @@ pub trait UserspaceKernelBoundary {
     /// Store architecture specific (e.g. CPU registers or status flags) data
     /// for a process. On success returns the number of elements written to out.
     fn store_context(&self, state: &Self::StoredState, out: &mut [u8]) -> Result<usize, ErrorCode>;
+
+    /// Restore architecture specific data for a process from `input`, which
+    /// was written by [`store_context`](UserspaceKernelBoundary::store_context)
+    /// for the same architecture.
+    ///
+    /// The stored state is not trusted: it is checked against the process
+    /// memory whenever the kernel uses it, as for any other stored state.
+    /// Returns `ErrorCode::INVAL` if `input` is not the stored state of this
+    /// architecture.
+    fn load_context(&self, state: &mut Self::StoredState, input: &[u8]) -> Result<(), ErrorCode>;
 }
@@ -288,6 +288,45 @@ pub trait SyscallDriver {
         Err((slice, ErrorCode::NOSUPPORT))
     }
 
+    /// Required alignment, in bytes, of the start of buffers passed with
+    /// read-write allow number `allow_num`.
+    ///
+    /// Drivers that hand process memory directly to DMA hardware return the
+    /// alignment the hardware needs, for example 4 for word alignment or the
+    /// cache line size. The core kernel then rejects misaligned buffers with
+    /// [`ErrorCode::ALIGN`] before they are stored in the grant, so the driver
+    /// only ever sees aligned buffers. This must be a power of two. The
+    /// default of 1 accepts any buffer.
+    fn allow_readwrite_alignment(&self, allow_num: usize) -> usize {
+        1
+    }
+
+    /// Required alignment, in bytes, of the start of buffers passed with
+    /// read-only allow number `allow_num`. See
+    /// [`SyscallDriver::allow_readwrite_alignment`].
+    fn allow_readonly_alignment(&self, allow_num: usize) -> usize {
+        1
+    }
+
+    /// Required alignment, in bytes, of the start of buffers passed with
+    /// userspace readable allow number `allow_num`. See
+    /// [`SyscallDriver::allow_readwrite_alignment`].
+    fn allow_userspace_readable_alignment(&self, allow_num: usize) -> usize {
+        1
+    }
+
+    /// Whether processes can call the commands of this driver in batches.
+    ///
+    /// With the CommandBatch system call, a process runs several commands of
+    /// one driver in a single system call, which saves the cost of a trap for
+    /// each of them. Drivers with short commands that processes call many
+    /// times in a row, such as setting GPIO pins, can opt in by returning
+    /// `true`. The commands are called exactly as if the process had made
+    /// them one by one. The default of `false` refuses batches.
+    fn allow_command_batch(&self) -> bool {
+        false
+    }
+
     /// Request to allocate a capsule's grant for a specific process.
     ///
     /// The core kernel uses this function to instruct a capsule to ensure its
@@ -0,0 +1,231 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Counters and gauges for runtime statistics.
+//!
+//! These types hold a 32-bit value in an atomic, so they can be updated from
+//! an interrupt handler and read from the main loop (for example by the
+//! process console) without ever reading a torn value. They only need shared
+//! references, so they can be placed in `static`s.
+//!
+//! - [`WrappingCounter`] counts events and wraps around on overflow. Rates are
+//!   computed from the difference between two reads, with
+//!   [`WrappingCounter::since`].
+//! - [`SaturatingCounter`] counts events and stops at `u32::MAX`, for counters
+//!   that are read as totals (errors, dropped packets, ...).
+//! - [`Gauge`] holds a current level, such as a queue length, and remembers
+//!   the highest level it reached.
+//!
+//! On targets with atomic read-modify-write instructions, updates are atomic
+//! and any context can update a counter. On other targets (for example
+//! ARMv6-M, or RISC-V without the `A` extension), updates are a load followed
+//! by a store, so each counter must only be updated from a single context
+//! (only from one interrupt handler, or only from the main loop). Reads are
+//! always safe from any context.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! use kernel::utilities::counters::{Gauge, SaturatingCounter, WrappingCounter};
+//!
+//! static DROPPED: SaturatingCounter = SaturatingCounter::new();
+//!
+//! let received = WrappingCounter::new();
+//! let last = received.get();
+//! received.increment();
+//! DROPPED.increment();
+//! assert_eq!(received.since(last), 1);
+//!
+//! let queue = Gauge::new();
+//! queue.increment();
+//! queue.increment();
+//! queue.decrement();
+//! assert_eq!((queue.get(), queue.max()), (1, 2));
+//! ```
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+// Counters do not order other memory accesses, they only need their own
+// updates to be atomic.
+const ORDERING: Ordering = Ordering::Relaxed;
+
+/// Apply `f` to the value of `atomic`.
+fn update(atomic: &AtomicU32, f: impl Fn(u32) -> u32) {
+    #[cfg(target_has_atomic = "32")]
+    let _ = atomic.fetch_update(ORDERING, ORDERING, |value| Some(f(value)));
+    #[cfg(not(target_has_atomic = "32"))]
+    atomic.store(f(atomic.load(ORDERING)), ORDERING);
+}
+
+/// Reset `atomic` to 0 and return its value.
+fn take(atomic: &AtomicU32) -> u32 {
+    #[cfg(target_has_atomic = "32")]
+    return atomic.swap(0, ORDERING);
+    #[cfg(not(target_has_atomic = "32"))]
+    {
+        let value = atomic.load(ORDERING);
+        atomic.store(0, ORDERING);
+        value
+    }
+}
+
+/// A counter of events that wraps around on overflow.
+#[derive(Debug, Default)]
+pub struct WrappingCounter(AtomicU32);
+
+impl WrappingCounter {
+    pub const fn new() -> Self {
+        Self(AtomicU32::new(0))
+    }
+
+    pub fn increment(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, n: u32) {
+        update(&self.0, |value| value.wrapping_add(n));
+    }
+
+    pub fn get(&self) -> u32 {
+        self.0.load(ORDERING)
+    }
+
+    /// Number of events since the counter was `previous`, assuming it wrapped
+    /// around at most once in between.
+    pub fn since(&self, previous: u32) -> u32 {
+        self.get().wrapping_sub(previous)
+    }
+
+    /// Reset the counter to 0 and return its value.
+    pub fn take(&self) -> u32 {
+        take(&self.0)
+    }
+}
+
+/// A counter of events that stops at `u32::MAX`.
+#[derive(Debug, Default)]
+pub struct SaturatingCounter(AtomicU32);
+
+impl SaturatingCounter {
+    pub const fn new() -> Self {
+        Self(AtomicU32::new(0))
+    }
+
+    pub fn increment(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, n: u32) {
+        update(&self.0, |value| value.saturating_add(n));
+    }
+
+    pub fn get(&self) -> u32 {
+        self.0.load(ORDERING)
+    }
+
+    /// Whether the counter reached `u32::MAX`, after which its value is only a
+    /// lower bound.
+    pub fn is_saturated(&self) -> bool {
+        self.get() == u32::MAX
+    }
+
+    /// Reset the counter to 0 and return its value.
+    pub fn take(&self) -> u32 {
+        take(&self.0)
+    }
+}
+
+/// A level that goes up and down, with the highest level it reached.
+#[derive(Debug, Default)]
+pub struct Gauge {
+    value: AtomicU32,
+    max: AtomicU32,
+}
+
+impl Gauge {
+    pub const fn new() -> Self {
+        Self {
+            value: AtomicU32::new(0),
+            max: AtomicU32::new(0),
+        }
+    }
+
+    pub fn set(&self, value: u32) {
+        self.value.store(value, ORDERING);
+        update(&self.max, |max| max.max(value));
+    }
+
+    /// Raise the level by one, up to `u32::MAX`.
+    pub fn increment(&self) {
+        update(&self.value, |value| value.saturating_add(1));
+        let value = self.get();
+        update(&self.max, |max| max.max(value));
+    }
+
+    /// Lower the level by one, down to 0.
+    pub fn decrement(&self) {
+        update(&self.value, |value| value.saturating_sub(1));
+    }
+
+    pub fn get(&self) -> u32 {
+        self.value.load(ORDERING)
+    }
+
+    /// Highest level since the gauge was created or [`Gauge::reset_max`] was
+    /// called.
+    pub fn max(&self) -> u32 {
+        self.max.load(ORDERING)
+    }
+
+    /// Restart tracking the highest level from the current level.
+    pub fn reset_max(&self) {
+        self.max.store(self.get(), ORDERING);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrapping_counter() {
+        let counter = WrappingCounter::new();
+        counter.add(u32::MAX);
+        let last = counter.get();
+        counter.add(3);
+        assert_eq!(counter.get(), 2);
+        assert_eq!(counter.since(last), 3);
+        assert_eq!(counter.take(), 2);
+        assert_eq!(counter.get(), 0);
+    }
+
+    #[test]
+    fn test_saturating_counter() {
+        let counter = SaturatingCounter::new();
+        counter.add(u32::MAX - 1);
+        assert!(!counter.is_saturated());
+        counter.add(2);
+        counter.increment();
+        assert!(counter.is_saturated());
+        assert_eq!(counter.take(), u32::MAX);
+        assert_eq!(counter.get(), 0);
+    }
+
+    #[test]
+    fn test_gauge() {
+        let gauge = Gauge::new();
+        gauge.decrement();
+        assert_eq!(gauge.get(), 0);
+        gauge.set(5);
+        gauge.decrement();
+        gauge.increment();
+        gauge.increment();
+        assert_eq!((gauge.get(), gauge.max()), (6, 6));
+        gauge.set(1);
+        assert_eq!((gauge.get(), gauge.max()), (1, 6));
+        gauge.reset_max();
+        assert_eq!(gauge.max(), 1);
+    }
+}
@@ -0,0 +1,174 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Inject faults into HIL implementations, to exercise capsule error paths.
+//!
+//! Hardware rarely fails on the bench, so the code of capsules that handles a
+//! failed transfer or a callback that never comes is seldom run before it
+//! matters. The fault injection wrappers of `capsules_extra::fault_injection`
+//! sit between a capsule and a HIL implementation and, when told to, make
+//! operations:
+//!
+//! - fail with an error right away, without reaching the hardware,
+//! - complete late, or
+//! - never complete.
+//!
+//! Each wrapper has a [`FaultSite`], which holds the fault to inject and how
+//! often. Boards pass their fault sites to the process console, whose
+//! `inject` command configures them, and tests can configure them directly:
+//!
+//! ```rust,ignore
+//! uart_faults.fault_site().inject(Fault::Error(ErrorCode::FAIL), 3);
+//! ```
+//!
+//! Faults are only injected with the `fault_injection` feature of the kernel
+//! crate. Without it, [`FaultSite::next`] never returns a fault, and the
+//! wrappers only forward to the hardware.
+
+use core::cell::Cell;
+
+use crate::config::CONFIG;
+use crate::ErrorCode;
+
+/// A fault to inject into an operation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    /// The operation fails with this error, without reaching the hardware.
+    Error(ErrorCode),
+    /// The operation completes this many milliseconds late.
+    Delay(u32),
+    /// The operation never completes: its callback is dropped.
+    Drop,
+}
+
+/// Whether the kernel was built with the `fault_injection` feature, so that
+/// fault sites inject their faults.
+pub const fn enabled() -> bool {
+    CONFIG.fault_injection
+}
+
+/// The errors a fault can inject, by name.
+pub const ERRORS: [(&str, ErrorCode); 14] = [
+    ("FAIL", ErrorCode::FAIL),
+    ("BUSY", ErrorCode::BUSY),
+    ("ALREADY", ErrorCode::ALREADY),
+    ("OFF", ErrorCode::OFF),
+    ("RESERVE", ErrorCode::RESERVE),
+    ("INVAL", ErrorCode::INVAL),
+    ("SIZE", ErrorCode::SIZE),
+    ("CANCEL", ErrorCode::CANCEL),
+    ("NOMEM", ErrorCode::NOMEM),
+    ("NOSUPPORT", ErrorCode::NOSUPPORT),
+    ("NODEVICE", ErrorCode::NODEVICE),
+    ("UNINSTALLED", ErrorCode::UNINSTALLED),
+    ("NOACK", ErrorCode::NOACK),
+    ("ALIGN", ErrorCode::ALIGN),
+];
+
+/// The faults to inject into the operations of one HIL implementation.
+pub struct FaultSite {
+    /// Name of the site, for the process console.
+    name: &'static str,
+    fault: Cell<Option<Fault>>,
+    /// The fault is injected into one operation out of `every`.
+    every: Cell<u32>,
+    /// Operations since the last fault.
+    operations: Cell<u32>,
+    injected: Cell<u32>,
+}
+
+impl FaultSite {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            fault: Cell::new(None),
+            every: Cell::new(1),
+            operations: Cell::new(0),
+            injected: Cell::new(0),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Inject `fault` into one operation out of `every`, starting with the
+    /// `every`-th operation from now. `every` is at least 1.
+    pub fn inject(&self, fault: Fault, every: u32) {
+        self.fault.set(Some(fault));
+        self.every.set(every.max(1));
+        self.operations.set(0);
+    }
+
+    /// Stop injecting faults.
+    pub fn clear(&self) {
+        self.fault.set(None);
+    }
+
+    /// The fault injected, and into one operation out of how many.
+    pub fn fault(&self) -> Option<(Fault, u32)> {
+        self.fault.get().map(|fault| (fault, self.every.get()))
+    }
+
+    /// The number of faults injected so far.
+    pub fn injected(&self) -> u32 {
+        self.injected.get()
+    }
+
+    /// Called by the wrapper at the start of each operation. Returns the fault
+    /// to inject into it, if any.
+    #[inline]
+    pub fn next(&self) -> Option<Fault> {
+        if !CONFIG.fault_injection {
+            return None;
+        }
+        self.next_fault()
+    }
+
+    fn next_fault(&self) -> Option<Fault> {
+        let fault = self.fault.get()?;
+        let operations = self.operations.get() + 1;
+        if operations < self.every.get() {
+            self.operations.set(operations);
+            return None;
+        }
+        self.operations.set(0);
+        self.injected.set(self.injected.get().wrapping_add(1));
+        Some(fault)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Fault, FaultSite};
+    use crate::ErrorCode;
+
+    #[test]
+    fn test_fault_every_third_operation() {
+        let site = FaultSite::new("uart");
+        assert_eq!(site.next_fault(), None);
+
+        site.inject(Fault::Error(ErrorCode::FAIL), 3);
+        assert_eq!(site.next_fault(), None);
+        assert_eq!(site.next_fault(), None);
+        assert_eq!(site.next_fault(), Some(Fault::Error(ErrorCode::FAIL)));
+        assert_eq!(site.next_fault(), None);
+        assert_eq!(site.injected(), 1);
+        assert_eq!(site.fault(), Some((Fault::Error(ErrorCode::FAIL), 3)));
+
+        site.clear();
+        assert_eq!(site.next_fault(), None);
+        assert_eq!(site.next_fault(), None);
+        assert_eq!(site.fault(), None);
+    }
+
+    #[test]
+    fn test_fault_every_operation() {
+        let site = FaultSite::new("flash");
+        site.inject(Fault::Drop, 0);
+        assert_eq!(site.next_fault(), Some(Fault::Drop));
+        assert_eq!(site.next_fault(), Some(Fault::Drop));
+        assert_eq!(site.injected(), 2);
+    }
+}
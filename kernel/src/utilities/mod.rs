@@ -8,14 +8,21 @@ pub mod arch_helpers;
 pub mod binary_write;
 pub mod capability_ptr;
 pub mod copy_slice;
+pub mod counters;
+pub mod fault_injection;
 pub mod helpers;
 pub mod leasable_buffer;
 pub mod math;
 pub mod mut_imut_buffer;
 pub mod peripheral_management;
+pub mod poison;
+pub mod section_monitor;
+pub mod sensor_stream;
+pub mod state_machine;
 pub mod static_init;
 pub mod storage_volume;
 pub mod streaming_process_slice;
+pub mod timeout;
 
 mod static_ref;
 pub use self::static_ref::StaticRef;
@@ -146,6 +146,8 @@
 //! }
 //! ```
 
+use core::cell::Cell;
+
 use crate::platform::chip::ClockInterface;
 
 /// A structure encapsulating a peripheral should implement this trait.
@@ -247,3 +249,132 @@ where
             .after_peripheral_access(self.clock, self.registers);
     }
 }
+
+/// A peripheral clock that is gated while nothing uses the peripheral.
+///
+/// Drivers call [`ClockUsage::acquire`] when an operation starts using the
+/// peripheral, for example at the start of a transfer, and
+/// [`ClockUsage::release`] when the operation ends. The clock is enabled for
+/// the first user and disabled after the last one, so independent operations,
+/// or several peripherals sharing a clock, do not turn the clock off under
+/// each other.
+///
+/// `ClockUsage` is itself a [`ClockInterface`] whose `enable` and `disable`
+/// acquire and release the clock, so code written for an always-on clock keeps
+/// working: it holds the clock until it disables it.
+pub struct ClockUsage<C: ClockInterface> {
+    clock: C,
+    users: Cell<usize>,
+}
+
+impl<C: ClockInterface> ClockUsage<C> {
+    pub const fn new(clock: C) -> ClockUsage<C> {
+        ClockUsage {
+            clock,
+            users: Cell::new(0),
+        }
+    }
+
+    /// Start using the peripheral, enabling its clock if it was gated.
+    pub fn acquire(&self) {
+        if self.users.get() == 0 {
+            self.clock.enable();
+        }
+        self.users.set(self.users.get() + 1);
+    }
+
+    /// Stop using the peripheral, gating its clock if nothing else uses it.
+    ///
+    /// Releasing a clock that is not held does nothing.
+    pub fn release(&self) {
+        match self.users.get() {
+            0 => {}
+            1 => {
+                self.users.set(0);
+                self.clock.disable();
+            }
+            users => self.users.set(users - 1),
+        }
+    }
+
+    /// Number of operations using the peripheral.
+    pub fn users(&self) -> usize {
+        self.users.get()
+    }
+
+    /// The underlying clock.
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+}
+
+impl<C: ClockInterface> ClockInterface for ClockUsage<C> {
+    fn is_enabled(&self) -> bool {
+        self.clock.is_enabled()
+    }
+
+    fn enable(&self) {
+        self.acquire();
+    }
+
+    fn disable(&self) {
+        self.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct TestClock {
+        enabled: Cell<bool>,
+        switches: Cell<usize>,
+    }
+
+    impl ClockInterface for TestClock {
+        fn is_enabled(&self) -> bool {
+            self.enabled.get()
+        }
+        fn enable(&self) {
+            self.enabled.set(true);
+            self.switches.set(self.switches.get() + 1);
+        }
+        fn disable(&self) {
+            self.enabled.set(false);
+            self.switches.set(self.switches.get() + 1);
+        }
+    }
+
+    #[test]
+    fn test_gated_after_last_user() {
+        let clock = ClockUsage::new(TestClock::default());
+        assert!(!clock.is_enabled());
+
+        clock.acquire();
+        clock.acquire();
+        assert!(clock.is_enabled());
+        assert_eq!(clock.users(), 2);
+
+        clock.release();
+        assert!(clock.is_enabled());
+        clock.release();
+        assert!(!clock.is_enabled());
+        // One enable and one disable.
+        assert_eq!(clock.clock().switches.get(), 2);
+    }
+
+    #[test]
+    fn test_release_without_users() {
+        let clock = ClockUsage::new(TestClock::default());
+        clock.release();
+        assert_eq!(clock.users(), 0);
+        assert_eq!(clock.clock().switches.get(), 0);
+
+        clock.enable();
+        clock.disable();
+        clock.disable();
+        assert!(!clock.is_enabled());
+        assert_eq!(clock.users(), 0);
+    }
+}
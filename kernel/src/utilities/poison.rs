@@ -0,0 +1,173 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Fault containment for capsules.
+//!
+//! A capsule that finds one of its own invariants broken, for example a
+//! buffer that should have been returned by a lower layer is missing, has no
+//! way to continue correctly. Panicking stops the whole kernel, including the
+//! functions of the board that have nothing to do with that capsule. Instead,
+//! the capsule can poison itself with a [`PoisonCell`]: it records a
+//! diagnostic, and from then on refuses all operations with
+//! `ErrorCode::FAIL`, while the rest of the kernel keeps running.
+//!
+//! What else happens when a capsule is poisoned is up to the board, through a
+//! [`PoisonPolicy`]. Without one, the diagnostic is printed with `debug!()`.
+//! Boards that prefer to start over can use [`ResetPoisonPolicy`].
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! fn send_done(&self, buffer: &'static mut [u8]) {
+//!     let Some(state) = self.state.take() else {
+//!         self.poison.poison("send done without a pending send");
+//!         return;
+//!     };
+//!     // ...
+//! }
+//!
+//! fn send(&self, buffer: &'static mut [u8]) -> Result<(), ErrorCode> {
+//!     self.poison.check()?;
+//!     // ...
+//! }
+//! ```
+//!
+//! And in the board:
+//!
+//! ```rust,ignore
+//! let reset_policy = static_init!(
+//!     kernel::utilities::poison::ResetPoisonPolicy,
+//!     kernel::utilities::poison::ResetPoisonPolicy::new(cortexm4::support::reset)
+//! );
+//! thread_driver.poison_cell().set_policy(reset_policy);
+//! ```
+
+use crate::debug;
+use crate::utilities::cells::OptionalCell;
+use crate::ErrorCode;
+
+/// What the board does when a capsule is poisoned.
+pub trait PoisonPolicy {
+    /// The capsule `capsule` found an invariant violation described by
+    /// `diagnostic`, and refuses all further operations.
+    fn poisoned(&self, capsule: &'static str, diagnostic: &'static str);
+}
+
+/// Reset the chip when a capsule is poisoned.
+pub struct ResetPoisonPolicy {
+    reset: fn() -> !,
+}
+
+impl ResetPoisonPolicy {
+    pub const fn new(reset: fn() -> !) -> Self {
+        Self { reset }
+    }
+}
+
+impl PoisonPolicy for ResetPoisonPolicy {
+    fn poisoned(&self, _capsule: &'static str, _diagnostic: &'static str) {
+        (self.reset)()
+    }
+}
+
+/// Capsules that can be poisoned, so that boards can find their
+/// [`PoisonCell`] to set a policy or read the diagnostic.
+pub trait Poisonable {
+    fn poison_cell(&self) -> &PoisonCell;
+}
+
+/// Whether a capsule is poisoned, and why.
+pub struct PoisonCell {
+    /// Name of the capsule, passed to the policy.
+    name: &'static str,
+    /// The first invariant violation found, if any.
+    diagnostic: OptionalCell<&'static str>,
+    policy: OptionalCell<&'static dyn PoisonPolicy>,
+}
+
+impl PoisonCell {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            diagnostic: OptionalCell::empty(),
+            policy: OptionalCell::empty(),
+        }
+    }
+
+    /// Set what happens when the capsule is poisoned.
+    pub fn set_policy(&self, policy: &'static dyn PoisonPolicy) {
+        self.policy.set(policy);
+    }
+
+    /// Poison the capsule because of the invariant violation described by
+    /// `diagnostic`.
+    ///
+    /// Only the first diagnostic is kept and reported to the policy.
+    pub fn poison(&self, diagnostic: &'static str) {
+        if self.diagnostic.is_some() {
+            return;
+        }
+        self.diagnostic.set(diagnostic);
+        self.policy.map_or_else(
+            || debug!("{} poisoned: {}", self.name, diagnostic),
+            |policy| policy.poisoned(self.name, diagnostic),
+        );
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        self.diagnostic.is_some()
+    }
+
+    /// The invariant violation that poisoned the capsule, if any.
+    pub fn diagnostic(&self) -> Option<&'static str> {
+        self.diagnostic.get()
+    }
+
+    /// `Err(ErrorCode::FAIL)` if the capsule is poisoned, so that operations
+    /// can start with `self.poison.check()?`.
+    pub fn check(&self) -> Result<(), ErrorCode> {
+        if self.is_poisoned() {
+            Err(ErrorCode::FAIL)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PoisonCell, PoisonPolicy};
+    use crate::ErrorCode;
+    use core::sync::atomic::{AtomicU32, Ordering};
+
+    static POISONED: AtomicU32 = AtomicU32::new(0);
+
+    struct CountPolicy;
+
+    impl PoisonPolicy for CountPolicy {
+        fn poisoned(&self, capsule: &'static str, _diagnostic: &'static str) {
+            assert_eq!(capsule, "test");
+            POISONED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    static POLICY: CountPolicy = CountPolicy;
+
+    #[test]
+    fn test_poison() {
+        let cell = PoisonCell::new("test");
+        cell.set_policy(&POLICY);
+        assert_eq!(cell.check(), Ok(()));
+        assert_eq!(cell.diagnostic(), None);
+
+        cell.poison("first");
+        cell.poison("second");
+        assert!(cell.is_poisoned());
+        assert_eq!(cell.check(), Err(ErrorCode::FAIL));
+        // Only the first invariant violation is kept and reported.
+        assert_eq!(cell.diagnostic(), Some("first"));
+        assert_eq!(POISONED.load(Ordering::Relaxed), 1);
+    }
+}
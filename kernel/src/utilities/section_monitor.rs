@@ -0,0 +1,143 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Find the kernel sections that run for too long.
+//!
+//! The kernel loop does not preempt capsules: while a grant closure, an
+//! interrupt bottom half or a deferred call runs, nothing else does. If one of
+//! them takes too long, alarms fire late and UART bytes are missed, and it is
+//! hard to tell which capsule is to blame on a busy system.
+//!
+//! With the `trace_long_sections` feature of the kernel crate, the kernel
+//! measures the time spent in:
+//!
+//! - each `enter()` of a grant, named after the type the capsule stores in
+//!   the grant,
+//! - each deferred call, named after the type of its client, and
+//! - the interrupt bottom halves of the chip, named `interrupts`.
+//!
+//! The times are measured by the [`SectionTimer`] of the board.
+//! [`LongSectionMonitor`] measures them with a cycle counter, and prints each
+//! section that takes longer than a threshold with `debug!()`:
+//!
+//! ```text
+//! long section: capsules_core::console::App took 25344 cycles
+//! ```
+//!
+//! Chips and capsules can also measure their own critical sections with
+//! [`measure`]. Without the feature, [`measure`] only calls the closure.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let monitor = static_init!(
+//!     kernel::utilities::section_monitor::LongSectionMonitor<'static, cortexm4::dwt::Dwt>,
+//!     kernel::utilities::section_monitor::LongSectionMonitor::new(dwt, 6400)
+//! );
+//! kernel::utilities::section_monitor::set_section_timer(monitor);
+//! ```
+
+use core::cell::Cell;
+use core::ptr::addr_of;
+
+use crate::config::CONFIG;
+use crate::debug;
+use crate::hil::hw_debug::CycleCounter;
+
+/// Measures the time spent in kernel sections.
+pub trait SectionTimer {
+    /// The current time.
+    fn now(&self) -> u64;
+
+    /// The section `name` ran for `elapsed`, in the units of
+    /// [`now`](SectionTimer::now).
+    fn section_done(&self, name: &'static str, elapsed: u64);
+}
+
+static mut SECTION_TIMER: Option<&'static dyn SectionTimer> = None;
+
+/// Set the timer of kernel sections.
+///
+/// # Safety
+///
+/// Must be called during board setup, before the kernel loop starts.
+pub unsafe fn set_section_timer(timer: &'static dyn SectionTimer) {
+    SECTION_TIMER = Some(timer);
+}
+
+/// Run `f`, and report how long it ran to the [`SectionTimer`] as the
+/// section `name`.
+#[inline]
+pub fn measure<R, F: FnOnce() -> R>(name: &'static str, f: F) -> R {
+    if !CONFIG.trace_long_sections {
+        return f();
+    }
+    // SAFETY: the timer is only set before the kernel loop starts, and the
+    // kernel is single-threaded.
+    match unsafe { *addr_of!(SECTION_TIMER) } {
+        Some(timer) => {
+            let start = timer.now();
+            let result = f();
+            timer.section_done(name, timer.now().wrapping_sub(start));
+            result
+        }
+        None => f(),
+    }
+}
+
+/// Prints the sections that run for more than a threshold, measured with a
+/// cycle counter.
+pub struct LongSectionMonitor<'a, C: CycleCounter> {
+    counter: &'a C,
+    /// Sections that run for more cycles are printed.
+    threshold: u64,
+    long_sections: Cell<usize>,
+    longest: Cell<Option<(&'static str, u64)>>,
+}
+
+impl<'a, C: CycleCounter> LongSectionMonitor<'a, C> {
+    /// Print the sections that run for more than `threshold` cycles of
+    /// `counter`.
+    pub fn new(counter: &'a C, threshold: u64) -> Self {
+        counter.reset();
+        counter.start();
+        Self {
+            counter,
+            threshold,
+            long_sections: Cell::new(0),
+            longest: Cell::new(None),
+        }
+    }
+
+    /// The number of sections that ran for more than the threshold.
+    pub fn long_sections(&self) -> usize {
+        self.long_sections.get()
+    }
+
+    /// The longest section so far, and how many cycles it ran for.
+    pub fn longest(&self) -> Option<(&'static str, u64)> {
+        self.longest.get()
+    }
+}
+
+impl<C: CycleCounter> SectionTimer for LongSectionMonitor<'_, C> {
+    fn now(&self) -> u64 {
+        self.counter.count()
+    }
+
+    fn section_done(&self, name: &'static str, elapsed: u64) {
+        if self
+            .longest
+            .get()
+            .map_or(true, |(_, cycles)| elapsed > cycles)
+        {
+            self.longest.set(Some((name, elapsed)));
+        }
+        if elapsed > self.threshold {
+            self.long_sections.set(self.long_sections.get() + 1);
+            debug!("long section: {} took {} cycles", name, elapsed);
+        }
+    }
+}
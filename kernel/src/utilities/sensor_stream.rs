@@ -0,0 +1,263 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! A common syscall interface for drivers that stream sensor samples, such as
+//! ADCs, IMUs and microphones, to a process.
+//!
+//! Drivers that implement the interface keep a [`SensorStream`] in the grant
+//! of each process and use the same command numbers ([`command`]), buffer
+//! format and upcall arguments, so userspace libraries can implement the
+//! buffer management once for all of them.
+//!
+//! Buffers
+//! -------
+//!
+//! Samples are written to a read-write allow buffer in the format of a
+//! [`StreamingProcessSlice`]. A process keeps a ring of two or more buffers
+//! and swaps the next one in with `allow` when it is notified that the
+//! current one is filling up, then reads the samples from the buffer it got
+//! back.
+//!
+//! The driver appends samples in records. Each record starts with an
+//! 8 byte header, in native endianness:
+//!
+//! ```text,ignore
+//! 0                       4           6           8
+//! +-----------------------+-----------+-----------+----------...
+//! | sequence number (u32) | count     | flags     | samples
+//! +-----------------------+-----------+-----------+----------...
+//! ```
+//!
+//! - `sequence number`: number of the first sample of the record. Samples are
+//!   numbered from 0 when the stream starts, and the number wraps around.
+//! - `count`: number of samples in the record. The size of a sample depends
+//!   on the driver.
+//! - `flags`: [`flags::OVERFLOW`] is set if samples were dropped before this
+//!   record, because no buffer had space for them. The gap in the sequence
+//!   numbers is the number of samples dropped.
+//!
+//! Upcall
+//! ------
+//!
+//! The driver schedules an upcall when a buffer is half full, when a record
+//! does not fit in the buffer, and when the process flushes the stream. The
+//! arguments are the [`reason`] of the upcall, the sequence number of the next
+//! sample, and the total number of samples that were dropped since the stream
+//! started. As for every [`StreamingProcessSlice`], a process must read the
+//! header of the buffer to know how much data it holds, and not count
+//! upcalls.
+
+use crate::grant::GrantKernelData;
+use crate::processbuffer::{WriteableProcessBuffer, WriteableProcessSlice};
+use crate::utilities::streaming_process_slice::StreamingProcessSlice;
+use crate::ErrorCode;
+
+/// Command numbers of the sensor stream interface.
+pub mod command {
+    /// Start streaming. The meaning of the arguments depends on the driver;
+    /// usually they select the sensor or channel and the sample rate in Hz.
+    pub const START: usize = 200;
+    /// Stop streaming.
+    pub const STOP: usize = 201;
+    /// Schedule an upcall now, even if the buffer is not half full.
+    pub const FLUSH: usize = 202;
+}
+
+/// Flags in the header of a record.
+pub mod flags {
+    /// Samples were dropped before this record.
+    pub const OVERFLOW: u16 = 1 << 0;
+}
+
+/// Reason of an upcall, passed as its first argument.
+pub mod reason {
+    /// The buffer is half full.
+    pub const DATA: usize = 0;
+    /// A record did not fit in the buffer, and was dropped.
+    pub const OVERFLOW: usize = 1;
+    /// The process flushed the stream.
+    pub const FLUSH: usize = 2;
+}
+
+/// Length of the header of a record, in bytes.
+pub const RECORD_HEADER_LEN: usize = 8;
+
+/// State of the stream of a process, kept in the grant of the driver.
+///
+/// `ALLOW_RW` is the read-write allow number of the buffer of the stream,
+/// and `UPCALL` the subscribe number of its upcall.
+#[derive(Default)]
+pub struct SensorStream<const ALLOW_RW: usize, const UPCALL: usize> {
+    running: bool,
+    /// Sequence number of the next sample.
+    sequence: u32,
+    /// Samples dropped since the stream started.
+    dropped: u32,
+    /// Samples were dropped since the last record was written.
+    overflowed: bool,
+    /// The process was notified about the current buffer.
+    notified: bool,
+}
+
+impl<const ALLOW_RW: usize, const UPCALL: usize> SensorStream<ALLOW_RW, UPCALL> {
+    /// Start a new stream, with sample numbers starting from 0.
+    pub fn start(&mut self) {
+        *self = SensorStream {
+            running: true,
+            ..SensorStream::default()
+        };
+    }
+
+    pub fn stop(&mut self) {
+        self.running = false;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Append a record of `count` samples, encoded in `samples`, to the
+    /// buffer of the process, and notify the process if needed.
+    ///
+    /// Samples that do not fit in the buffer are dropped.
+    pub fn push<I: IntoIterator<Item = u8>>(
+        &mut self,
+        kernel_data: &GrantKernelData,
+        count: u16,
+        samples: I,
+    ) {
+        if !self.running {
+            return;
+        }
+        let notify = kernel_data
+            .get_readwrite_processbuffer(ALLOW_RW)
+            .and_then(|buffer| buffer.mut_enter(|slice| self.append(slice, count, samples)))
+            .unwrap_or_else(|_| self.drop_record(count));
+        if let Some(reason) = notify {
+            self.notify(kernel_data, reason);
+        }
+    }
+
+    /// Notify the process now.
+    pub fn flush(&mut self, kernel_data: &GrantKernelData) {
+        self.notify(kernel_data, reason::FLUSH);
+    }
+
+    fn notify(&mut self, kernel_data: &GrantKernelData, reason: usize) {
+        self.notified = true;
+        let _ = kernel_data.schedule_upcall(
+            UPCALL,
+            (reason, self.sequence as usize, self.dropped as usize),
+        );
+    }
+
+    /// Append a record to `slice`. Returns the reason to notify the process,
+    /// if it should be.
+    fn append<I: IntoIterator<Item = u8>>(
+        &mut self,
+        slice: &WriteableProcessSlice,
+        count: u16,
+        samples: I,
+    ) -> Option<usize> {
+        let flags = if self.overflowed { flags::OVERFLOW } else { 0 };
+        let mut header = [0; RECORD_HEADER_LEN];
+        header[0..4].copy_from_slice(&self.sequence.to_ne_bytes());
+        header[4..6].copy_from_slice(&count.to_ne_bytes());
+        header[6..8].copy_from_slice(&flags.to_ne_bytes());
+
+        let capacity = slice.len();
+        match StreamingProcessSlice::new(slice)
+            .append_chunk_from_iter(header.into_iter().chain(samples))
+        {
+            Ok((first, offset)) => {
+                self.sequence = self.sequence.wrapping_add(count as u32);
+                self.overflowed = false;
+                if first {
+                    // The process swapped in a new buffer.
+                    self.notified = false;
+                }
+                (!self.notified && offset as usize >= capacity / 2).then_some(reason::DATA)
+            }
+            Err(ErrorCode::SIZE) | Err(ErrorCode::BUSY) => self.drop_record(count),
+            // The buffer is not a valid streaming buffer. Samples are
+            // dropped without notifying the process, which would otherwise
+            // get an upcall for every record.
+            Err(_) => {
+                let _ = self.drop_record(count);
+                None
+            }
+        }
+    }
+
+    fn drop_record(&mut self, count: u16) -> Option<usize> {
+        self.sequence = self.sequence.wrapping_add(count as u32);
+        self.dropped = self.dropped.wrapping_add(count as u32);
+        self.overflowed = true;
+        (!self.notified).then_some(reason::OVERFLOW)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{flags, reason, SensorStream, RECORD_HEADER_LEN};
+    use crate::processbuffer::WriteableProcessSlice;
+
+    /// Offset of the payload in a streaming buffer.
+    const DATA: usize = 9;
+
+    fn record(buffer: &[u8], offset: usize) -> (u32, u16, u16) {
+        let header = &buffer[DATA + offset..];
+        (
+            u32::from_ne_bytes(header[0..4].try_into().unwrap()),
+            u16::from_ne_bytes(header[4..6].try_into().unwrap()),
+            u16::from_ne_bytes(header[6..8].try_into().unwrap()),
+        )
+    }
+
+    #[test]
+    fn records() {
+        let mut buffer = [0_u8; 64];
+        let mut stream = SensorStream::<0, 0>::default();
+        stream.start();
+
+        let slice: &WriteableProcessSlice = (&mut buffer[..]).into();
+        assert_eq!(stream.append(slice, 2, [1, 2, 3, 4]), None);
+        assert_eq!(stream.append(slice, 1, [5, 6]), None);
+        // The buffer is now more than half full.
+        assert_eq!(stream.append(slice, 2, [7, 8, 9, 10]), Some(reason::DATA));
+        stream.notified = true;
+        assert_eq!(stream.append(slice, 1, [11, 12]), None);
+
+        assert_eq!(record(&buffer, 0), (0, 2, 0));
+        assert_eq!(&buffer[DATA + RECORD_HEADER_LEN..][..4], &[1, 2, 3, 4]);
+        assert_eq!(record(&buffer, 12), (2, 1, 0));
+        assert_eq!(record(&buffer, 22), (3, 2, 0));
+    }
+
+    #[test]
+    fn overflow() {
+        let mut buffer = [0_u8; 32];
+        let mut stream = SensorStream::<0, 0>::default();
+        stream.start();
+
+        let slice: &WriteableProcessSlice = (&mut buffer[..]).into();
+        assert_eq!(stream.append(slice, 4, [0; 8]), Some(reason::DATA));
+        stream.notified = true;
+        // Does not fit, and the process was already notified.
+        assert_eq!(stream.append(slice, 4, [0; 8]), None);
+        assert_eq!(stream.dropped, 4);
+
+        // The process swaps in a new buffer.
+        let mut next = [0_u8; 32];
+        let slice: &WriteableProcessSlice = (&mut next[..]).into();
+        assert_eq!(stream.append(slice, 1, [0; 2]), None);
+        assert!(!stream.notified);
+
+        // Does not fit, and the process is notified about it.
+        assert_eq!(stream.append(slice, 8, [0; 16]), Some(reason::OVERFLOW));
+        assert_eq!(stream.dropped, 12);
+
+        assert_eq!(record(&next, 0), (8, 1, flags::OVERFLOW));
+    }
+}
@@ -0,0 +1,180 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Explicit state machines for split-phase drivers.
+//!
+//! A driver that issues a sequence of split-phase operations (enable writes,
+//! send a command, wait, poll a status register, ...) keeps the step it is
+//! at in a state enum, and moves to the next step in each callback. Nothing
+//! stops it from moving to a state that makes no sense from the current one,
+//! for example starting a new operation while another one is pending, and
+//! such bugs only show up as a hung or corrupted operation much later.
+//!
+//! [`StateMachine`] lets the driver write down which transitions are legal,
+//! and [`StateCell`] holds the current state and only takes legal
+//! transitions. An illegal transition leaves the state unchanged, is
+//! reported to [`StateMachine::illegal_transition`], and returns an error the
+//! driver can pass on, for example as `ErrorCode::BUSY` when a client starts
+//! an operation while the driver is not idle.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! use kernel::utilities::state_machine::{StateCell, StateMachine};
+//!
+//! #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+//! enum State {
+//!     Idle,
+//!     Sending,
+//!     Waiting,
+//! }
+//!
+//! impl StateMachine for State {
+//!     fn can_transition(&self, next: &Self) -> bool {
+//!         matches!(
+//!             (self, next),
+//!             (State::Idle, State::Sending)
+//!                 | (State::Sending, State::Waiting)
+//!                 | (State::Waiting, State::Idle)
+//!         )
+//!     }
+//! }
+//!
+//! let state = StateCell::new(State::Idle);
+//! assert!(state.transition(State::Sending).is_ok());
+//! // A new operation cannot start before this one is done.
+//! assert!(state.transition(State::Sending).is_err());
+//! assert_eq!(state.get(), State::Sending);
+//! ```
+
+use core::cell::Cell;
+use core::fmt::Debug;
+
+use crate::debug;
+
+/// States of a driver and the legal transitions between them.
+pub trait StateMachine: Copy + PartialEq + Debug {
+    /// Whether the driver may move from `self` to `next`.
+    ///
+    /// States that carry data may check it too, for example that a page
+    /// index only grows.
+    fn can_transition(&self, next: &Self) -> bool;
+
+    /// Called when the driver tries to move from `from` to `to` and
+    /// [`can_transition`](StateMachine::can_transition) refuses. The state
+    /// stays `from`.
+    ///
+    /// The default prints the transition with `debug!()`. A driver can
+    /// override it, for example to panic while it is being brought up.
+    fn illegal_transition(from: Self, to: Self) {
+        debug!("illegal state transition: {:?} -> {:?}", from, to);
+    }
+}
+
+/// An illegal transition refused by a [`StateCell`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IllegalTransition<S> {
+    pub from: S,
+    pub to: S,
+}
+
+/// The current state of a [`StateMachine`], which only changes through legal
+/// transitions.
+pub struct StateCell<S: StateMachine> {
+    state: Cell<S>,
+}
+
+impl<S: StateMachine> StateCell<S> {
+    pub const fn new(initial: S) -> Self {
+        Self {
+            state: Cell::new(initial),
+        }
+    }
+
+    /// The current state.
+    pub fn get(&self) -> S {
+        self.state.get()
+    }
+
+    /// Move to `next` if the transition is legal.
+    ///
+    /// Otherwise the state is unchanged, the transition is reported to
+    /// [`StateMachine::illegal_transition`] and returned as an error.
+    pub fn transition(&self, next: S) -> Result<(), IllegalTransition<S>> {
+        let current = self.state.get();
+        if current.can_transition(&next) {
+            self.state.set(next);
+            Ok(())
+        } else {
+            S::illegal_transition(current, next);
+            Err(IllegalTransition {
+                from: current,
+                to: next,
+            })
+        }
+    }
+
+    /// Move to `next` whatever the current state, for example to recover
+    /// after the lower layer failed. Prefer [`transition`](Self::transition).
+    pub fn reset(&self, next: S) {
+        self.state.set(next);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IllegalTransition, StateCell, StateMachine};
+    use core::sync::atomic::{AtomicU32, Ordering};
+
+    static ILLEGAL: AtomicU32 = AtomicU32::new(0);
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    enum State {
+        Idle,
+        Reading { page: u32 },
+        Done,
+    }
+
+    impl StateMachine for State {
+        fn can_transition(&self, next: &Self) -> bool {
+            match (self, next) {
+                (State::Idle, State::Reading { page: 0 }) => true,
+                (State::Reading { page }, State::Reading { page: next }) => *next == page + 1,
+                (State::Reading { .. }, State::Done) => true,
+                (State::Done, State::Idle) => true,
+                _ => false,
+            }
+        }
+
+        fn illegal_transition(_from: Self, _to: Self) {
+            ILLEGAL.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_transitions() {
+        let state = StateCell::new(State::Idle);
+        assert_eq!(state.transition(State::Reading { page: 0 }), Ok(()));
+        assert_eq!(state.transition(State::Reading { page: 1 }), Ok(()));
+
+        // Pages are read in order.
+        assert_eq!(
+            state.transition(State::Reading { page: 3 }),
+            Err(IllegalTransition {
+                from: State::Reading { page: 1 },
+                to: State::Reading { page: 3 },
+            })
+        );
+        assert_eq!(state.get(), State::Reading { page: 1 });
+        assert_eq!(ILLEGAL.load(Ordering::Relaxed), 1);
+
+        assert_eq!(state.transition(State::Done), Ok(()));
+        assert!(state.transition(State::Reading { page: 0 }).is_err());
+        assert_eq!(ILLEGAL.load(Ordering::Relaxed), 2);
+
+        state.reset(State::Idle);
+        assert_eq!(state.get(), State::Idle);
+    }
+}
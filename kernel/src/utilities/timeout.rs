@@ -0,0 +1,240 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Timeouts for split-phase operations.
+//!
+//! A split-phase operation (an I2C transfer, a SPI transaction, a UART
+//! receive, ...) only finishes when the lower layer calls back. If the
+//! hardware or an external device is wedged, the callback never comes and the
+//! capsule waiting for it stalls forever. A [`Timeout`] bounds how long a
+//! capsule waits: the capsule starts it with the operation, marks it complete
+//! in the callback, and is notified through [`TimeoutClient`] if the
+//! operation did not finish in time.
+//!
+//! After a timeout, the late callback may still arrive. [`Timeout::complete`]
+//! returns `false` in that case, so the capsule can tell it apart from the
+//! completion of a new operation. The lower layer usually still holds the
+//! buffer of a timed out operation, so a capsule that gives up on an operation
+//! must wait for that buffer to come back before it starts the next one, or
+//! reset the lower layer if it can.
+//!
+//! The alarm must not be used for anything else, for example a
+//! `VirtualMuxAlarm` dedicated to the timeout.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! fn read_register(&self) -> Result<(), ErrorCode> {
+//!     let buffer = self.buffer.take().ok_or(ErrorCode::BUSY)?;
+//!     self.timeout.start(READ_TIMEOUT_MS)?;
+//!     self.i2c.write_read(buffer, 1, 2).map_err(|(err, buffer)| {
+//!         self.timeout.cancel();
+//!         self.buffer.replace(buffer);
+//!         err.into()
+//!     })
+//! }
+//!
+//! impl I2CClient for Sensor<'_> {
+//!     fn command_complete(&self, buffer: &'static mut [u8], status: Result<(), Error>) {
+//!         let in_time = self.timeout.complete();
+//!         self.buffer.replace(buffer);
+//!         if in_time {
+//!             // Report the reading.
+//!         }
+//!     }
+//! }
+//!
+//! impl TimeoutClient for Sensor<'_> {
+//!     fn timed_out(&self) {
+//!         // Report `ErrorCode::FAIL` to the process waiting for the reading.
+//!     }
+//! }
+//! ```
+
+use core::cell::Cell;
+
+use crate::hil::time::{Alarm, AlarmClient, ConvertTicks};
+use crate::utilities::cells::OptionalCell;
+use crate::ErrorCode;
+
+/// Client notified when an operation did not complete in time.
+pub trait TimeoutClient {
+    /// The operation started with [`Timeout::start`] did not complete before
+    /// the timeout expired.
+    fn timed_out(&self);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    Idle,
+    Pending,
+    Expired,
+}
+
+/// Bounds the duration of one split-phase operation at a time.
+///
+/// `Timeout` must be set as the client of its alarm.
+pub struct Timeout<'a, A: Alarm<'a>> {
+    alarm: &'a A,
+    client: OptionalCell<&'a dyn TimeoutClient>,
+    state: Cell<State>,
+}
+
+impl<'a, A: Alarm<'a>> Timeout<'a, A> {
+    pub fn new(alarm: &'a A) -> Self {
+        Self {
+            alarm,
+            client: OptionalCell::empty(),
+            state: Cell::new(State::Idle),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn TimeoutClient) {
+        self.client.set(client);
+    }
+
+    /// Start timing an operation that must complete within `ms` milliseconds.
+    ///
+    /// Returns `Err(ErrorCode::BUSY)` if an operation is already being timed.
+    pub fn start(&self, ms: u32) -> Result<(), ErrorCode> {
+        if self.state.get() == State::Pending {
+            return Err(ErrorCode::BUSY);
+        }
+        self.state.set(State::Pending);
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(ms));
+        Ok(())
+    }
+
+    /// Mark the operation as complete, from its completion callback.
+    ///
+    /// Returns `true` if the operation completed in time, and `false` if the
+    /// timeout already expired (or no operation was started), in which case
+    /// the client has already been notified with
+    /// [`TimeoutClient::timed_out`].
+    pub fn complete(&self) -> bool {
+        let in_time = self.state.get() == State::Pending;
+        if in_time {
+            let _ = self.alarm.disarm();
+        }
+        self.state.set(State::Idle);
+        in_time
+    }
+
+    /// Stop timing the operation without completing it, for example because
+    /// starting it failed.
+    pub fn cancel(&self) {
+        if self.state.get() == State::Pending {
+            let _ = self.alarm.disarm();
+        }
+        self.state.set(State::Idle);
+    }
+
+    /// Whether an operation is being timed.
+    pub fn is_pending(&self) -> bool {
+        self.state.get() == State::Pending
+    }
+
+    /// Whether the last operation timed out and its completion callback has
+    /// not arrived yet.
+    pub fn has_expired(&self) -> bool {
+        self.state.get() == State::Expired
+    }
+}
+
+impl<'a, A: Alarm<'a>> AlarmClient for Timeout<'a, A> {
+    fn alarm(&self) {
+        if self.state.get() == State::Pending {
+            self.state.set(State::Expired);
+            self.client.map(|client| client.timed_out());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hil::time::{Freq1KHz, Ticks, Ticks32, Time};
+
+    struct TestAlarm {
+        armed: Cell<bool>,
+        dt: Cell<u32>,
+    }
+
+    impl Time for TestAlarm {
+        type Frequency = Freq1KHz;
+        type Ticks = Ticks32;
+
+        fn now(&self) -> Ticks32 {
+            0u32.into()
+        }
+    }
+
+    impl<'a> Alarm<'a> for TestAlarm {
+        fn set_alarm_client(&self, _client: &'a dyn AlarmClient) {}
+
+        fn set_alarm(&self, _reference: Ticks32, dt: Ticks32) {
+            self.armed.set(true);
+            self.dt.set(dt.into_u32());
+        }
+
+        fn get_alarm(&self) -> Ticks32 {
+            self.dt.get().into()
+        }
+
+        fn disarm(&self) -> Result<(), ErrorCode> {
+            self.armed.set(false);
+            Ok(())
+        }
+
+        fn is_armed(&self) -> bool {
+            self.armed.get()
+        }
+
+        fn minimum_dt(&self) -> Ticks32 {
+            1u32.into()
+        }
+    }
+
+    struct TestClient(Cell<usize>);
+
+    impl TimeoutClient for TestClient {
+        fn timed_out(&self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn test_timeout() {
+        let alarm = TestAlarm {
+            armed: Cell::new(false),
+            dt: Cell::new(0),
+        };
+        let client = TestClient(Cell::new(0));
+        let timeout = Timeout::new(&alarm);
+        timeout.set_client(&client);
+
+        // Completed in time.
+        assert_eq!(timeout.start(20), Ok(()));
+        assert_eq!((alarm.is_armed(), alarm.dt.get()), (true, 20));
+        assert_eq!(timeout.start(20), Err(ErrorCode::BUSY));
+        assert!(timeout.complete());
+        assert!(!alarm.is_armed());
+
+        // Timed out, then the late completion arrives.
+        assert_eq!(timeout.start(10), Ok(()));
+        timeout.alarm();
+        assert_eq!(client.0.get(), 1);
+        assert!(timeout.has_expired());
+        assert!(!timeout.complete());
+        assert!(!timeout.has_expired());
+
+        // A stale alarm after cancellation does not notify the client.
+        assert_eq!(timeout.start(10), Ok(()));
+        timeout.cancel();
+        timeout.alarm();
+        assert_eq!(client.0.get(), 1);
+    }
+}
@@ -24,6 +24,7 @@ pub const MEPC: usize = 0x341;
 pub const MCAUSE: usize = 0x342;
 pub const MTVAL: usize = 0x343;
 pub const MIP: usize = 0x344;
+pub const MHARTID: usize = 0xF14;
 pub const MSECCFG: usize = 0x747;
 pub const MSECCFGH: usize = 0x757;
 pub const PMPCFG0: usize = 0x3A0;
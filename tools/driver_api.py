@@ -0,0 +1,134 @@
+#!/usr/bin/env python3
+
+# Licensed under the Apache License, Version 2.0 or the MIT License.
+# SPDX-License-Identifier: Apache-2.0 OR MIT
+# Copyright Tock Contributors 2024.
+
+'''
+Print the syscall driver descriptions of a kernel ELF.
+
+Boards export the descriptions of their drivers with
+`kernel::export_driver_apis!()` into the `.driver_api` section of the kernel
+ELF (see `kernel/src/platform/driver_api.rs` for the format). This script
+prints them as text, as Markdown for documentation, or as a C header with the
+driver, command, subscribe and allow numbers for libtock-c.
+
+Usage:
+
+    tools/driver_api.py [--format text|markdown|c] <kernel.elf>
+'''
+
+import argparse
+import struct
+import sys
+
+
+def read_section(path, name):
+    '''Return the contents of section `name` of the ELF at `path`.'''
+    with open(path, 'rb') as f:
+        elf = f.read()
+    if elf[:4] != b'\x7fELF':
+        sys.exit('{} is not an ELF file'.format(path))
+    is_64 = elf[4] == 2
+    endian = '<' if elf[5] == 1 else '>'
+    if is_64:
+        shoff, = struct.unpack_from(endian + 'Q', elf, 0x28)
+        shentsize, shnum, shstrndx = struct.unpack_from(endian + 'HHH', elf, 0x3a)
+    else:
+        shoff, = struct.unpack_from(endian + 'I', elf, 0x20)
+        shentsize, shnum, shstrndx = struct.unpack_from(endian + 'HHH', elf, 0x2e)
+
+    def header(index):
+        offset = shoff + index * shentsize
+        if is_64:
+            name, _, _, _, data, size = struct.unpack_from(endian + 'IIQQQQ', elf, offset)
+        else:
+            name, _, _, _, data, size = struct.unpack_from(endian + 'IIIIII', elf, offset)
+        return name, data, size
+
+    _, strtab, _ = header(shstrndx)
+    for index in range(shnum):
+        name_offset, data, size = header(index)
+        end = elf.index(b'\0', strtab + name_offset)
+        if elf[strtab + name_offset:end].decode() == name:
+            return elf[data:data + size]
+    sys.exit('{} has no {} section; does the board call '
+             'kernel::export_driver_apis!()?'.format(path, name))
+
+
+def parse_records(section):
+    '''Yield the driver number and the items of each record.'''
+    offset = 0
+    while offset + 8 <= len(section):
+        length, driver_num = struct.unpack_from('<II', section, offset)
+        if length < 8:
+            break
+        text = section[offset + 8:offset + length].rstrip(b'\0').decode()
+        items = []
+        for line in text.splitlines():
+            kind, _, rest = line.partition(' ')
+            if kind == 'name':
+                items.append((kind, None, rest))
+            else:
+                num, _, signature = rest.partition(' ')
+                items.append((kind, int(num), signature))
+        yield driver_num, items
+        offset += length
+
+
+def driver_name(items):
+    return next((sig for kind, _, sig in items if kind == 'name'), 'unknown')
+
+
+def print_text(drivers):
+    for driver_num, items in drivers:
+        print('{:#07x} {}'.format(driver_num, driver_name(items)))
+        for kind, num, signature in items:
+            if kind != 'name':
+                print('    {:<9} {:>2}  {}'.format(kind, num, signature))
+
+
+def print_markdown(drivers):
+    for driver_num, items in drivers:
+        print('## {} ({:#07x})'.format(driver_name(items), driver_num))
+        print()
+        print('| Kind | Number | Signature |')
+        print('|------|--------|-----------|')
+        for kind, num, signature in items:
+            if kind != 'name':
+                print('| {} | {} | `{}` |'.format(kind, num, signature))
+        print()
+
+
+def c_identifier(signature):
+    name = signature.split('(')[0].split(' ')[0]
+    return ''.join(c if c.isalnum() else '_' for c in name).upper()
+
+
+def print_c(drivers):
+    print('// Generated by tools/driver_api.py from a Tock kernel ELF.')
+    print()
+    print('#pragma once')
+    for driver_num, items in drivers:
+        driver = c_identifier(driver_name(items))
+        print()
+        print('#define DRIVER_NUM_{} {:#x}'.format(driver, driver_num))
+        for kind, num, signature in items:
+            if kind != 'name':
+                print('#define {}_{}_{} {}'.format(
+                    driver, kind.upper(), c_identifier(signature), num))
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__.strip().splitlines()[0])
+    parser.add_argument('--format', choices=['text', 'markdown', 'c'], default='text')
+    parser.add_argument('elf', help='kernel ELF file')
+    args = parser.parse_args()
+
+    drivers = sorted(parse_records(read_section(args.elf, '.driver_api')),
+                     key=lambda driver: driver[0])
+    {'text': print_text, 'markdown': print_markdown, 'c': print_c}[args.format](drivers)
+
+
+if __name__ == '__main__':
+    main()